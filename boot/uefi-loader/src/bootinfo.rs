@@ -0,0 +1,267 @@
+//! Boot protocol v2: versioned header plus typed TLV entries
+//!
+//! The handoff to the kernel is a single buffer: a `BootInfoHeader`
+//! followed by tags, each a `BootTag` header and its payload, padded to
+//! 8 bytes and terminated by an `END` tag. The kernel skips tags it does
+//! not know and either side can grow a payload (the tag size tells the
+//! reader how much is there), so loader and kernel only have to agree on
+//! the tag types they both use.
+//!
+//! The tag type numbers and payload layouts below must match the kernel's
+//! `bootinfo` module.
+
+use core::mem::size_of;
+
+/// Header magic ("NOSTBOOT" in ASCII)
+pub const BOOT_INFO_V2_MAGIC: u64 = 0x4E4F5354424F4F54;
+
+/// Protocol version written by this loader
+pub const BOOT_INFO_VERSION: u32 = 2;
+
+/// Size of the handoff buffer
+pub const BOOT_INFO_MAX_SIZE: usize = 8192;
+
+/// Tag types
+pub mod tag {
+    pub const END: u32 = 0;
+    /// `KernelTag`
+    pub const KERNEL: u32 = 1;
+    /// `MemoryMapTag`
+    pub const MEMMAP: u32 = 2;
+    /// `FramebufferTag` for the primary display
+    pub const FRAMEBUFFER: u32 = 3;
+    /// `RsdpTag`
+    pub const RSDP: u32 = 4;
+    /// NUL-terminated UTF-8 command line
+    pub const CMDLINE: u32 = 5;
+    /// `InitrdTag`
+    pub const INITRD: u32 = 6;
+    /// `ModesListTag` followed by `count` BootDisplay entries
+    pub const MODES_LIST: u32 = 7;
+    /// `EfiRuntimeTag`
+    pub const EFI_RUNTIME: u32 = 8;
+    /// `TpmEventLogTag`
+    pub const TPM_EVENT_LOG: u32 = 9;
+    /// `SmbiosTag`
+    pub const SMBIOS: u32 = 10;
+}
+
+/// Handoff header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfoHeader {
+    pub magic: u64,
+    pub version: u32,
+    /// Size of this header; tags start here
+    pub header_size: u32,
+    /// Header plus all tags, including the END tag
+    pub total_size: u32,
+    /// Tags, not counting END
+    pub tag_count: u32,
+    /// Wrapping sum of the tag bytes as little-endian u32 words
+    pub checksum: u32,
+    pub reserved: u32,
+}
+
+/// Tag header; `size` covers the header and payload, not the padding
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootTag {
+    pub tag_type: u32,
+    pub size: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct KernelTag {
+    pub physical_base: u64,
+    pub virtual_base: u64,
+    pub size: u64,
+    pub pml4_physical_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryMapTag {
+    pub addr: u64,
+    pub entry_count: u64,
+    pub entry_size: u64,
+    pub descriptor_version: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferTag {
+    pub addr: u64,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub stride: u32,
+    pub bpp: u32,
+    /// EFI_GRAPHICS_PIXEL_FORMAT
+    pub pixel_format: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RsdpTag {
+    pub addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InitrdTag {
+    pub addr: u64,
+    pub size: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ModesListTag {
+    /// BootDisplay entries that follow
+    pub count: u32,
+    /// BOOT_DISPLAY_* flags
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EfiRuntimeTag {
+    /// Virtual address of the EFI system table (0 if unavailable)
+    pub system_table: u64,
+    /// Virtual address of the runtime services table (0 if unavailable)
+    pub runtime_services: u64,
+    /// Base of the runtime services window (virtual = base + physical)
+    pub virtual_base: u64,
+    /// EFI status of SetVirtualAddressMap (0 on success)
+    pub status: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TpmEventLogTag {
+    /// Copy of the firmware event log in loader data
+    pub addr: u64,
+    pub size: u64,
+    /// EFI_TCG2_FINAL_EVENTS_TABLE (0 if the firmware has none)
+    pub final_events_addr: u64,
+    /// EFI_TCG2_EVENT_LOG_FORMAT
+    pub format: u32,
+    /// TPM_EVENT_LOG_* flags
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SmbiosTag {
+    /// Physical address of the entry point structure
+    pub entry_addr: u64,
+    /// 3 for a 64-bit `_SM3_` entry point, 2 for a 32-bit `_SM_` one
+    pub entry_version: u32,
+    pub reserved: u32,
+}
+
+/// The firmware ran out of log space and dropped events
+pub const TPM_EVENT_LOG_TRUNCATED: u32 = 0x1;
+
+/// The boot.cfg resolution was applied to the primary display
+pub const BOOT_DISPLAY_PREFERRED_MODE: u32 = 0x1;
+
+#[repr(C, align(8))]
+struct BootInfoBuffer([u8; BOOT_INFO_MAX_SIZE]);
+
+/// Handoff buffer that persists after UEFI exit
+static mut BOOT_INFO_BUFFER: BootInfoBuffer = BootInfoBuffer([0; BOOT_INFO_MAX_SIZE]);
+
+/// Appends tags to the static handoff buffer
+///
+/// Allocation-free, so tags can still be added after ExitBootServices.
+pub struct BootInfoBuilder {
+    len: usize,
+    tag_count: u32,
+    dropped: u32,
+}
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+fn buffer() -> &'static mut [u8; BOOT_INFO_MAX_SIZE] {
+    let buffer = &raw mut BOOT_INFO_BUFFER;
+    unsafe { &mut (*buffer).0 }
+}
+
+impl BootInfoBuilder {
+    pub fn new() -> Self {
+        Self {
+            len: size_of::<BootInfoHeader>(),
+            tag_count: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Append a tag whose payload is the concatenation of `parts`
+    ///
+    /// Returns false (and drops the tag) if it does not fit.
+    pub fn add_parts(&mut self, tag_type: u32, parts: &[&[u8]]) -> bool {
+        let payload: usize = parts.iter().map(|p| p.len()).sum();
+        let size = size_of::<BootTag>() + payload;
+        // Always leave room for the END tag
+        if self.len + size.next_multiple_of(8) + size_of::<BootTag>() > BOOT_INFO_MAX_SIZE {
+            self.dropped += 1;
+            return false;
+        }
+
+        let buf = buffer();
+        let header = BootTag { tag_type, size: size as u32 };
+        let mut at = self.len;
+        for part in core::iter::once(as_bytes(&header)).chain(parts.iter().copied()) {
+            buf[at..at + part.len()].copy_from_slice(part);
+            at += part.len();
+        }
+        let end = self.len + size.next_multiple_of(8);
+        buf[at..end].fill(0);
+        self.len = end;
+        if tag_type != tag::END {
+            self.tag_count += 1;
+        }
+        true
+    }
+
+    /// Append a tag with a single fixed-layout payload
+    pub fn add<T: Copy>(&mut self, tag_type: u32, payload: &T) -> bool {
+        self.add_parts(tag_type, &[as_bytes(payload)])
+    }
+
+    /// Tags dropped for lack of space
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Terminate the tag list, fill in the header and return its address
+    pub fn finish(mut self) -> u64 {
+        self.add_parts(tag::END, &[]);
+
+        let buf = buffer();
+        let header_size = size_of::<BootInfoHeader>();
+        let checksum = buf[header_size..self.len]
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .fold(0u32, |sum, word| sum.wrapping_add(u32::from_le_bytes(*word)));
+        let header = BootInfoHeader {
+            magic: BOOT_INFO_V2_MAGIC,
+            version: BOOT_INFO_VERSION,
+            header_size: header_size as u32,
+            total_size: self.len as u32,
+            tag_count: self.tag_count,
+            checksum,
+            reserved: 0,
+        };
+        buf[..header_size].copy_from_slice(as_bytes(&header));
+        buf.as_ptr() as u64
+    }
+}
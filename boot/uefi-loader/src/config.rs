@@ -0,0 +1,185 @@
+//! Boot configuration from the EFI System Partition
+//!
+//! Reads \EFI\nostalgia\boot.cfg: one `key=value` setting per line; lines
+//! starting with `#` are comments. Unknown keys are logged and ignored; a missing
+//! file leaves every setting at its default.
+//!
+//! Settings:
+//! - `resolution`: preferred display mode, `WIDTHxHEIGHT` or `max`
+//!   (default: keep the mode the firmware set up)
+//! - `cmdline`: kernel command line, passed through as-is
+//! - `initrd`: ESP path of an initial ramdisk to load for the kernel
+//! - `efi_runtime`: `off` leaves runtime services at their physical
+//!   addresses and unavailable to the kernel (default: `on`)
+//! - `kernel_base`: virtual base to relocate the kernel to, hex, 2MB
+//!   aligned in the top 2GB (default: the linked address)
+//! - `kernel_phys`: physical address to load the kernel at, hex, 2MB
+//!   aligned; may be above 4GB (default: the linked address, else anywhere)
+
+use log::info;
+use uefi::boot;
+use uefi::cstr16;
+use uefi::fs::FileSystem;
+use uefi::proto::media::fs::SimpleFileSystem;
+
+/// Preferred display resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the firmware's mode
+    Firmware,
+    /// Largest mode with a linear framebuffer
+    Max,
+    /// Exact width x height
+    Exact(u32, u32),
+}
+
+/// Longest command line kept
+pub const MAX_CMDLINE: usize = 256;
+
+/// Longest initrd path kept
+pub const MAX_INITRD_PATH: usize = 128;
+
+/// Loader settings
+#[derive(Debug, Clone, Copy)]
+pub struct BootConfig {
+    pub resolution: Resolution,
+    /// Map runtime services for the kernel
+    pub efi_runtime: bool,
+    /// Kernel virtual base override
+    pub kernel_base: Option<u64>,
+    /// Kernel physical address override
+    pub kernel_phys: Option<u64>,
+    cmdline: [u8; MAX_CMDLINE],
+    cmdline_len: usize,
+    initrd: [u8; MAX_INITRD_PATH],
+    initrd_len: usize,
+}
+
+impl BootConfig {
+    pub const fn default() -> Self {
+        Self {
+            resolution: Resolution::Firmware,
+            efi_runtime: true,
+            kernel_base: None,
+            kernel_phys: None,
+            cmdline: [0; MAX_CMDLINE],
+            cmdline_len: 0,
+            initrd: [0; MAX_INITRD_PATH],
+            initrd_len: 0,
+        }
+    }
+
+    /// Kernel command line (empty if not set)
+    pub fn cmdline(&self) -> &str {
+        core::str::from_utf8(&self.cmdline[..self.cmdline_len]).unwrap_or("")
+    }
+
+    /// Initial ramdisk path, if set
+    pub fn initrd(&self) -> Option<&str> {
+        if self.initrd_len == 0 {
+            return None;
+        }
+        core::str::from_utf8(&self.initrd[..self.initrd_len]).ok()
+    }
+}
+
+/// Copy `value` into `buf`, truncating at a character boundary
+fn store(buf: &mut [u8], value: &str) -> usize {
+    let mut len = value.len().min(buf.len());
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    buf[..len].copy_from_slice(&value.as_bytes()[..len]);
+    len
+}
+
+/// Read boot.cfg, falling back to defaults
+pub fn load() -> BootConfig {
+    let mut config = BootConfig::default();
+
+    let Ok(fs_handle) = boot::get_handle_for_protocol::<SimpleFileSystem>() else {
+        return config;
+    };
+    let Ok(fs) = boot::open_protocol_exclusive::<SimpleFileSystem>(fs_handle) else {
+        return config;
+    };
+    let mut fs = FileSystem::new(fs);
+
+    let Ok(data) = fs.read(cstr16!("\\EFI\\nostalgia\\boot.cfg")) else {
+        info!("  No boot.cfg, using defaults");
+        return config;
+    };
+    let Ok(text) = core::str::from_utf8(&data) else {
+        info!("  boot.cfg is not valid UTF-8, ignoring it");
+        return config;
+    };
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            info!("  boot.cfg: ignoring '{}'", line);
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+
+        if key.eq_ignore_ascii_case("resolution") {
+            match parse_resolution(value) {
+                Some(resolution) => config.resolution = resolution,
+                None => info!("  boot.cfg: bad resolution '{}'", value),
+            }
+        } else if key.eq_ignore_ascii_case("cmdline") {
+            config.cmdline_len = store(&mut config.cmdline, value);
+        } else if key.eq_ignore_ascii_case("efi_runtime") {
+            if value.eq_ignore_ascii_case("on") {
+                config.efi_runtime = true;
+            } else if value.eq_ignore_ascii_case("off") {
+                config.efi_runtime = false;
+            } else {
+                info!("  boot.cfg: bad efi_runtime '{}'", value);
+            }
+        } else if key.eq_ignore_ascii_case("initrd") {
+            config.initrd_len = store(&mut config.initrd, value);
+        } else if key.eq_ignore_ascii_case("kernel_base") {
+            match parse_hex(value) {
+                Some(base) => config.kernel_base = Some(base),
+                None => info!("  boot.cfg: bad kernel_base '{}'", value),
+            }
+        } else if key.eq_ignore_ascii_case("kernel_phys") {
+            match parse_hex(value) {
+                Some(base) => config.kernel_phys = Some(base),
+                None => info!("  boot.cfg: bad kernel_phys '{}'", value),
+            }
+        } else {
+            info!("  boot.cfg: unknown setting '{}'", key);
+        }
+    }
+
+    config
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+fn parse_resolution(value: &str) -> Option<Resolution> {
+    if value.eq_ignore_ascii_case("max") {
+        return Some(Resolution::Max);
+    }
+    if value.eq_ignore_ascii_case("firmware") {
+        return Some(Resolution::Firmware);
+    }
+    let (width, height) = value.split_once(['x', 'X'])?;
+    let width = width.trim().parse().ok()?;
+    let height = height.trim().parse().ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(Resolution::Exact(width, height))
+}
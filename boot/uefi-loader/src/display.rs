@@ -0,0 +1,215 @@
+//! Graphics Output Protocol enumeration
+//!
+//! Walks every GOP instance the firmware exposes, records each one's mode
+//! list and framebuffer, and applies the preferred resolution from boot.cfg
+//! to the primary (first) instance. The table is handed to the kernel in
+//! BootInfo so its display driver can pick a different mode or drive a
+//! second framebuffer later without going back to the firmware.
+
+use log::info;
+use uefi::boot::{self, SearchType};
+use uefi::proto::console::gop::{GraphicsOutput, Mode, ModeInfo, PixelFormat};
+use uefi::Identify;
+
+use crate::config::Resolution;
+use crate::serial_println;
+
+/// GOP instances recorded
+pub const MAX_BOOT_DISPLAYS: usize = 4;
+
+/// Modes recorded per instance
+pub const MAX_BOOT_VIDEO_MODES: usize = 64;
+
+/// `current_mode` value when the active mode is not in the recorded list
+pub const BOOT_MODE_UNKNOWN: u32 = u32::MAX;
+
+/// One GOP mode
+/// Must match the kernel's BootVideoMode structure exactly!
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootVideoMode {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels per scanline
+    pub pixels_per_scanline: u32,
+    /// EFI_GRAPHICS_PIXEL_FORMAT (0 RGB, 1 BGR, 2 bitmask, 3 BLT only)
+    pub pixel_format: u32,
+}
+
+/// One GOP instance
+/// Must match the kernel's BootDisplay structure exactly!
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootDisplay {
+    /// Physical address of the framebuffer (0 for BLT-only modes)
+    pub framebuffer_addr: u64,
+    /// Framebuffer size in bytes
+    pub framebuffer_size: u64,
+    /// The active mode
+    pub active: BootVideoMode,
+    /// Index into `modes` of the active mode
+    pub current_mode: u32,
+    /// Valid entries in `modes`
+    pub mode_count: u32,
+    pub modes: [BootVideoMode; MAX_BOOT_VIDEO_MODES],
+}
+
+const EMPTY_MODE: BootVideoMode = BootVideoMode {
+    width: 0,
+    height: 0,
+    pixels_per_scanline: 0,
+    pixel_format: 0,
+};
+
+const EMPTY_DISPLAY: BootDisplay = BootDisplay {
+    framebuffer_addr: 0,
+    framebuffer_size: 0,
+    active: EMPTY_MODE,
+    current_mode: BOOT_MODE_UNKNOWN,
+    mode_count: 0,
+    modes: [EMPTY_MODE; MAX_BOOT_VIDEO_MODES],
+};
+
+/// Display table that persists after UEFI exit
+static mut BOOT_DISPLAYS: [BootDisplay; MAX_BOOT_DISPLAYS] = [EMPTY_DISPLAY; MAX_BOOT_DISPLAYS];
+
+/// Result of enumeration
+pub struct DisplayEnumeration {
+    /// Instances recorded
+    pub count: u32,
+    /// The preferred resolution was applied to the primary display
+    pub preferred_applied: bool,
+}
+
+impl DisplayEnumeration {
+    /// The primary display, if any
+    pub fn primary(&self) -> Option<&'static BootDisplay> {
+        if self.count == 0 {
+            None
+        } else {
+            let table = &raw const BOOT_DISPLAYS;
+            unsafe { Some(&(*table)[0]) }
+        }
+    }
+
+    /// The recorded displays
+    pub fn displays(&self) -> &'static [BootDisplay] {
+        let table = &raw const BOOT_DISPLAYS;
+        unsafe { &(&*table)[..self.count as usize] }
+    }
+}
+
+/// Enumerate all GOP instances, applying `preferred` to the primary one
+pub fn enumerate(preferred: Resolution) -> DisplayEnumeration {
+    let mut result = DisplayEnumeration {
+        count: 0,
+        preferred_applied: false,
+    };
+
+    let handles = match boot::locate_handle_buffer(SearchType::ByProtocol(&GraphicsOutput::GUID)) {
+        Ok(handles) => handles,
+        Err(_) => return result,
+    };
+
+    for &handle in handles.iter() {
+        if result.count as usize >= MAX_BOOT_DISPLAYS {
+            info!("  More than {} GOP instances, ignoring the rest", MAX_BOOT_DISPLAYS);
+            break;
+        }
+        let Ok(mut gop) = boot::open_protocol_exclusive::<GraphicsOutput>(handle) else {
+            continue;
+        };
+
+        if result.count == 0 && preferred != Resolution::Firmware {
+            result.preferred_applied = apply_preferred(&mut gop, preferred);
+        }
+
+        let table = &raw mut BOOT_DISPLAYS;
+        let display = unsafe { &mut (*table)[result.count as usize] };
+        record_display(&mut gop, display);
+
+        info!("  Display {}: {}x{}, {} modes, framebuffer {:#x}",
+            result.count, display.active.width, display.active.height,
+            display.mode_count, display.framebuffer_addr);
+        serial_println!("  Display {}: {}x{}, {} modes, framebuffer {:#x}",
+            result.count, display.active.width, display.active.height,
+            display.mode_count, display.framebuffer_addr);
+        result.count += 1;
+    }
+
+    result
+}
+
+/// Switch to the preferred mode; returns true if the active mode matches it
+fn apply_preferred(gop: &mut GraphicsOutput, preferred: Resolution) -> bool {
+    let linear = |mode: &Mode| mode.info().pixel_format() != PixelFormat::BltOnly;
+    let area = |mode: &Mode| {
+        let (w, h) = mode.info().resolution();
+        w * h
+    };
+
+    let target = match preferred {
+        Resolution::Firmware => return false,
+        Resolution::Max => gop.modes().filter(linear).max_by_key(area),
+        Resolution::Exact(width, height) => gop.modes().filter(linear).find(|mode| {
+            mode.info().resolution() == (width as usize, height as usize)
+        }),
+    };
+
+    let Some(target) = target else {
+        info!("  Preferred resolution {:?} not offered, keeping firmware mode", preferred);
+        serial_println!("  Preferred resolution {:?} not offered, keeping firmware mode", preferred);
+        return false;
+    };
+
+    let (width, height) = target.info().resolution();
+    if *target.info() == gop.current_mode_info() {
+        return true;
+    }
+    match gop.set_mode(&target) {
+        Ok(()) => {
+            info!("  Switched to preferred mode {}x{}", width, height);
+            serial_println!("  Switched to preferred mode {}x{}", width, height);
+            true
+        }
+        Err(e) => {
+            info!("  Failed to set {}x{}: {:?}", width, height, e.status());
+            serial_println!("  Failed to set {}x{}: {:?}", width, height, e.status());
+            false
+        }
+    }
+}
+
+fn video_mode(info: &ModeInfo) -> BootVideoMode {
+    let (width, height) = info.resolution();
+    BootVideoMode {
+        width: width as u32,
+        height: height as u32,
+        pixels_per_scanline: info.stride() as u32,
+        pixel_format: info.pixel_format() as u32,
+    }
+}
+
+fn record_display(gop: &mut GraphicsOutput, display: &mut BootDisplay) {
+    let current = gop.current_mode_info();
+
+    display.active = video_mode(&current);
+    display.mode_count = 0;
+    display.current_mode = BOOT_MODE_UNKNOWN;
+    for mode in gop.modes().take(MAX_BOOT_VIDEO_MODES) {
+        if *mode.info() == current {
+            display.current_mode = display.mode_count;
+        }
+        display.modes[display.mode_count as usize] = video_mode(mode.info());
+        display.mode_count += 1;
+    }
+
+    if current.pixel_format() == PixelFormat::BltOnly {
+        display.framebuffer_addr = 0;
+        display.framebuffer_size = 0;
+    } else {
+        let mut fb = gop.frame_buffer();
+        display.framebuffer_addr = fb.as_mut_ptr() as u64;
+        display.framebuffer_size = fb.size() as u64;
+    }
+}
@@ -1,17 +1,20 @@
 //! Kernel loading from EFI System Partition
 //!
 //! Loads the kernel binary from \EFI\nostalgia\kernel.bin on the ESP.
-//! The kernel is a flat binary that will be loaded at KERNEL_PHYSICAL_BASE.
+//! The kernel is an ELF image linked at KERNEL_VIRTUAL_BASE, 16 MB
+//! physical. It can be loaded at any 2MB aligned physical
+//! address (including above 4GB) and moved to another virtual base in the
+//! top 2GB; see `reloc` for how the image is fixed up.
 
 use log::info;
 use uefi::boot;
 use uefi::cstr16;
-use uefi::fs::FileSystem;
+use uefi::fs::{FileSystem, PathBuf};
+use uefi::CString16;
 use uefi::mem::memory_map::MemoryType;
 use uefi::proto::media::fs::SimpleFileSystem;
 
-/// Kernel physical load address (16 MB)
-pub const KERNEL_PHYSICAL_BASE: u64 = 0x100_0000;
+use crate::reloc;
 
 /// Kernel virtual address (higher half)
 /// Using -2GB from top of address space
@@ -20,6 +23,18 @@ pub const KERNEL_VIRTUAL_BASE: u64 = 0xFFFF_FFFF_8000_0000;
 /// Maximum kernel size (64 MB)
 pub const MAX_KERNEL_SIZE: usize = 128 * 1024 * 1024;
 
+/// Mapped past the end of the image for BSS, stack and early heap (32 MB)
+pub const KERNEL_MAP_HEADROOM: u64 = 0x200_0000;
+
+/// Where to put the kernel, `None` keeps the linked address
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelPlacement {
+    /// Virtual base, 2MB aligned in the top 2GB
+    pub virtual_base: Option<u64>,
+    /// Physical base, 2MB aligned
+    pub physical_base: Option<u64>,
+}
+
 /// Loaded kernel information
 pub struct LoadedKernel {
     /// Physical address where kernel is loaded
@@ -45,7 +60,7 @@ impl LoadedKernel {
 }
 
 /// Load kernel from the EFI System Partition
-pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
+pub fn load_kernel(placement: KernelPlacement) -> Result<LoadedKernel, &'static str> {
     info!("Loading kernel from ESP...");
 
     // Get the filesystem protocol
@@ -74,42 +89,6 @@ pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
         return Err("Kernel file is empty");
     }
 
-    // Allocate memory at the desired physical address for the kernel
-    // We need 2MB alignment for huge page mapping!
-    // Allocate extra pages to ensure we can find a 2MB-aligned region
-    const PAGE_SIZE: usize = 4096;
-    const LARGE_PAGE_SIZE: usize = 2 * 1024 * 1024; // 2MB
-
-    let pages_needed = kernel_size.div_ceil(PAGE_SIZE);
-    // Allocate extra for 2MB alignment (512 pages = 2MB)
-    let pages_for_alignment = LARGE_PAGE_SIZE / PAGE_SIZE;
-    let total_pages = pages_needed + pages_for_alignment;
-
-    // Try to allocate at specific address (already 2MB aligned)
-    let (kernel_phys, kernel_phys_addr) = boot::allocate_pages(
-        boot::AllocateType::Address(KERNEL_PHYSICAL_BASE),
-        MemoryType::LOADER_DATA,
-        pages_needed,
-    ).map(|ptr| (ptr, ptr.as_ptr() as u64))
-    .or_else(|_| {
-        // Fall back to any address, but align to 2MB
-        info!("Could not allocate at preferred address, using any available");
-        let ptr = boot::allocate_pages(
-            boot::AllocateType::AnyPages,
-            MemoryType::LOADER_DATA,
-            total_pages,
-        ).map_err(|_| "Failed to allocate memory for kernel")?;
-
-        let raw_addr = ptr.as_ptr() as u64;
-        // Align up to 2MB boundary
-        let aligned_addr = (raw_addr + LARGE_PAGE_SIZE as u64 - 1) & !(LARGE_PAGE_SIZE as u64 - 1);
-        info!("Aligned kernel from {:#x} to {:#x}", raw_addr, aligned_addr);
-        Ok((ptr, aligned_addr))
-    })?;
-
-    let _ = kernel_phys; // Keep allocation alive
-    info!("Kernel loaded at physical address: {:#x}", kernel_phys_addr);
-
     // Parse ELF header to get entry point and load segments properly
     let elf_magic = &kernel_data[0..4];
     if elf_magic != [0x7f, b'E', b'L', b'F'] {
@@ -130,9 +109,11 @@ pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
     let e_phentsize = u16::from_le_bytes(kernel_data[54..56].try_into().unwrap()) as usize;
     let e_phnum = u16::from_le_bytes(kernel_data[56..58].try_into().unwrap()) as usize;
 
-    // Find the lowest physical address from LOAD segments
+    // Find the linked physical and virtual range of the LOAD segments
     let mut load_base_virt: u64 = u64::MAX;
     let mut load_base_phys: u64 = u64::MAX;
+    let mut load_end_phys: u64 = 0;
+    let mut load_end_virt: u64 = 0;
 
     for i in 0..e_phnum {
         let ph_start = e_phoff + i * e_phentsize;
@@ -155,13 +136,56 @@ pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
             if p_paddr < load_base_phys && p_paddr > 0 {
                 load_base_phys = p_paddr;
             }
+            load_end_phys = load_end_phys.max(p_paddr + p_memsz);
+            load_end_virt = load_end_virt.max(p_vaddr + p_memsz);
         }
     }
+    if load_base_phys == u64::MAX || load_end_phys <= load_base_phys {
+        return Err("Kernel has no LOAD segments");
+    }
+    let image_size = load_end_phys - load_base_phys;
+
+    // Allocate memory for the whole image (including BSS) at the requested
+    // physical address, else at the linked one, else anywhere.
+    // We need 2MB alignment for huge page mapping!
+    const PAGE_SIZE: usize = 4096;
+    const LARGE_PAGE_SIZE: usize = 2 * 1024 * 1024; // 2MB
 
-    // Load each ELF segment to its proper physical address
-    let mut max_phys_end: u64 = 0;
-    let mut min_phys_start: u64 = u64::MAX;
+    let pages_needed = (image_size as usize).div_ceil(PAGE_SIZE);
+    // Allocate extra for 2MB alignment (512 pages = 2MB)
+    let pages_for_alignment = LARGE_PAGE_SIZE / PAGE_SIZE;
+    let total_pages = pages_needed + pages_for_alignment;
 
+    let preferred = placement.physical_base.unwrap_or(load_base_phys);
+    if !preferred.is_multiple_of(LARGE_PAGE_SIZE as u64) {
+        return Err("Kernel physical base must be 2MB aligned");
+    }
+    let (kernel_phys, kernel_phys_addr) = boot::allocate_pages(
+        boot::AllocateType::Address(preferred),
+        MemoryType::LOADER_DATA,
+        pages_needed,
+    ).map(|ptr| (ptr, ptr.as_ptr() as u64))
+    .or_else(|_| {
+        // Fall back to any address, but align to 2MB
+        info!("Could not allocate at {:#x}, using any available", preferred);
+        let ptr = boot::allocate_pages(
+            boot::AllocateType::AnyPages,
+            MemoryType::LOADER_DATA,
+            total_pages,
+        ).map_err(|_| "Failed to allocate memory for kernel")?;
+
+        let raw_addr = ptr.as_ptr() as u64;
+        // Align up to 2MB boundary
+        let aligned_addr = (raw_addr + LARGE_PAGE_SIZE as u64 - 1) & !(LARGE_PAGE_SIZE as u64 - 1);
+        info!("Aligned kernel from {:#x} to {:#x}", raw_addr, aligned_addr);
+        Ok((ptr, aligned_addr))
+    })?;
+
+    let _ = kernel_phys; // Keep allocation alive
+    info!("Kernel loaded at physical address: {:#x}", kernel_phys_addr);
+
+    // Load each ELF segment at the same offset from the allocation as it
+    // has from the linked physical base
     for i in 0..e_phnum {
         let ph_start = e_phoff + i * e_phentsize;
         let p_type = u32::from_le_bytes(kernel_data[ph_start..ph_start+4].try_into().unwrap());
@@ -169,26 +193,17 @@ pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
         // PT_LOAD = 1
         if p_type == 1 {
             let p_offset = u64::from_le_bytes(kernel_data[ph_start+8..ph_start+16].try_into().unwrap()) as usize;
-            let _p_vaddr = u64::from_le_bytes(kernel_data[ph_start+16..ph_start+24].try_into().unwrap());
             let p_paddr = u64::from_le_bytes(kernel_data[ph_start+24..ph_start+32].try_into().unwrap());
             let p_filesz = u64::from_le_bytes(kernel_data[ph_start+32..ph_start+40].try_into().unwrap()) as usize;
             let p_memsz = u64::from_le_bytes(kernel_data[ph_start+40..ph_start+48].try_into().unwrap());
-
-            // Track physical memory range
-            if p_paddr < min_phys_start && p_paddr > 0 {
-                min_phys_start = p_paddr;
-            }
-            let phys_end = p_paddr + p_memsz;
-            if phys_end > max_phys_end {
-                max_phys_end = phys_end;
-            }
+            let dest_phys = kernel_phys_addr + (p_paddr - load_base_phys);
 
             // Copy segment data from file to physical memory
             if p_filesz > 0 {
                 info!("Loading segment to phys {:#x}, {} bytes from offset {:#x}",
-                    p_paddr, p_filesz, p_offset);
+                    dest_phys, p_filesz, p_offset);
                 unsafe {
-                    let dest = p_paddr as *mut u8;
+                    let dest = dest_phys as *mut u8;
                     let src = &kernel_data[p_offset..p_offset + p_filesz];
                     core::ptr::copy_nonoverlapping(src.as_ptr(), dest, p_filesz);
                 }
@@ -196,7 +211,7 @@ pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
 
             // Zero out any extra memory (BSS)
             if p_memsz as usize > p_filesz {
-                let bss_start = p_paddr + p_filesz as u64;
+                let bss_start = dest_phys + p_filesz as u64;
                 let bss_size = p_memsz as usize - p_filesz;
                 info!("Zeroing BSS at phys {:#x}, {} bytes", bss_start, bss_size);
                 unsafe {
@@ -207,23 +222,60 @@ pub fn load_kernel() -> Result<LoadedKernel, &'static str> {
         }
     }
 
-    // The entry point is the virtual address from the ELF
-    // We need to calculate the physical entry point
-    let entry_phys = e_entry - KERNEL_VIRTUAL_BASE + min_phys_start;
-    info!("Kernel loaded from phys {:#x} to {:#x}", min_phys_start, max_phys_end);
-    info!("Entry point: virt={:#x} phys={:#x}", e_entry, entry_phys);
+    // Move the image to the requested virtual base
+    let virt_base = placement.virtual_base.unwrap_or(load_base_virt);
+    if !virt_base.is_multiple_of(LARGE_PAGE_SIZE as u64) {
+        return Err("Kernel virtual base must be 2MB aligned");
+    }
+    if virt_base < KERNEL_VIRTUAL_BASE || virt_base.checked_add(image_size + KERNEL_MAP_HEADROOM).is_none() {
+        return Err("Kernel virtual base must leave the image in the top 2GB");
+    }
+    reloc::relocate(&kernel_data, kernel_phys_addr, load_base_virt, virt_base, load_end_virt - load_base_virt)?;
+
+    // The entry point is the linked virtual address; the kernel is entered
+    // at its physical alias
+    let entry_offset = e_entry - load_base_virt;
+    info!("Kernel loaded from phys {:#x} to {:#x}", kernel_phys_addr, kernel_phys_addr + image_size);
+    info!("Entry point: virt={:#x} phys={:#x}", virt_base + entry_offset, kernel_phys_addr + entry_offset);
 
     Ok(LoadedKernel {
-        phys_addr: min_phys_start,
-        virt_addr: KERNEL_VIRTUAL_BASE,
-        size: max_phys_end - min_phys_start,
-        entry_offset: entry_phys - min_phys_start,
+        phys_addr: kernel_phys_addr,
+        virt_addr: virt_base,
+        size: image_size,
+        entry_offset,
     })
 }
 
+/// Load an initial ramdisk from the ESP into loader-owned pages
+///
+/// Returns the physical address and size. The pages are LOADER_DATA, which
+/// the kernel does not hand out, so the image survives until it is consumed.
+pub fn load_initrd(path: &str) -> Result<(u64, u64), &'static str> {
+    let path = CString16::try_from(path).map_err(|_| "Invalid initrd path")?;
+
+    let fs_handle = boot::get_handle_for_protocol::<SimpleFileSystem>()
+        .map_err(|_| "Failed to get filesystem handle")?;
+    let fs = boot::open_protocol_exclusive::<SimpleFileSystem>(fs_handle)
+        .map_err(|_| "Failed to open filesystem protocol")?;
+    let mut fs = FileSystem::new(fs);
+
+    let data = fs.read(PathBuf::from(path)).map_err(|_| "Failed to read initrd file")?;
+    if data.is_empty() {
+        return Err("Initrd file is empty");
+    }
+
+    let pages = data.len().div_ceil(4096);
+    let ptr = boot::allocate_pages(boot::AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)
+        .map_err(|_| "Failed to allocate memory for initrd")?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), ptr.as_ptr(), data.len());
+    }
+    Ok((ptr.as_ptr() as u64, data.len() as u64))
+}
+
 /// Load kernel or create a minimal stub if file not found
-pub fn load_kernel_or_stub() -> Result<LoadedKernel, &'static str> {
-    match load_kernel() {
+pub fn load_kernel_or_stub(placement: KernelPlacement) -> Result<LoadedKernel, &'static str> {
+    match load_kernel(placement) {
         Ok(kernel) => Ok(kernel),
         Err(e) => {
             info!("Could not load kernel: {}", e);
@@ -1,18 +1,25 @@
 //! Nostalgia OS UEFI Bootloader
 //!
 //! This bootloader:
-//! 1. Loads the kernel from \EFI\nostalgia\kernel.bin
-//! 2. Sets up 4-level page tables (identity + higher-half mapping)
-//! 3. Acquires the UEFI memory map
-//! 4. Exits UEFI boot services
-//! 5. Jumps to the kernel entry point
+//! 1. Enumerates GOP displays, applying the resolution from boot.cfg
+//! 2. Loads the kernel from \EFI\nostalgia\kernel.bin
+//! 3. Sets up 4-level page tables (identity + higher-half mapping)
+//! 4. Acquires the UEFI memory map
+//! 5. Exits UEFI boot services
+//! 6. Jumps to the kernel entry point
 
 #![no_std]
 #![no_main]
 
+mod bootinfo;
+mod config;
+mod display;
 mod kernel;
 mod paging;
+mod reloc;
+mod runtime;
 mod serial;
+mod tpm;
 
 use core::arch::asm;
 use log::info;
@@ -20,65 +27,9 @@ use uefi::prelude::*;
 use uefi::boot;
 use uefi::mem::memory_map::{MemoryMap, MemoryMapOwned, MemoryType};
 
+use bootinfo::{tag, BootInfoBuilder};
 use paging::PageTables;
 
-/// Boot information passed to the kernel
-/// This structure is passed to kernel_main
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-pub struct BootInfo {
-    /// Magic number for validation
-    pub magic: u64,
-    /// Physical address of the framebuffer
-    pub framebuffer_addr: u64,
-    /// Framebuffer width in pixels
-    pub framebuffer_width: u32,
-    /// Framebuffer height in pixels
-    pub framebuffer_height: u32,
-    /// Framebuffer stride (bytes per row)
-    pub framebuffer_stride: u32,
-    /// Bytes per pixel
-    pub framebuffer_bpp: u32,
-    /// Physical address of the memory map
-    pub memory_map_addr: u64,
-    /// Number of memory map entries
-    pub memory_map_entries: u64,
-    /// Size of each memory map entry
-    pub memory_map_entry_size: u64,
-    /// Physical address of the kernel
-    pub kernel_physical_base: u64,
-    /// Virtual address of the kernel
-    pub kernel_virtual_base: u64,
-    /// Size of the kernel in bytes
-    pub kernel_size: u64,
-    /// Physical address of the PML4 table
-    pub pml4_physical_addr: u64,
-    /// ACPI RSDP address (if found)
-    pub rsdp_addr: u64,
-}
-
-impl BootInfo {
-    pub const MAGIC: u64 = 0x4E4F5354414C4749; // "NOSTALGI" in ASCII
-}
-
-/// Static boot info that persists after UEFI exit
-static mut BOOT_INFO: BootInfo = BootInfo {
-    magic: 0,
-    framebuffer_addr: 0,
-    framebuffer_width: 0,
-    framebuffer_height: 0,
-    framebuffer_stride: 0,
-    framebuffer_bpp: 0,
-    memory_map_addr: 0,
-    memory_map_entries: 0,
-    memory_map_entry_size: 0,
-    kernel_physical_base: 0,
-    kernel_virtual_base: 0,
-    kernel_size: 0,
-    pml4_physical_addr: 0,
-    rsdp_addr: 0,
-};
-
 #[entry]
 fn main() -> Status {
     // Initialize serial port first for early debugging
@@ -99,11 +50,24 @@ fn main() -> Status {
         uefi::system::firmware_revision());
     serial_println!("Firmware initialized");
 
-    // Step 1: Get framebuffer info
+    // Step 1: Enumerate displays
     info!("");
-    info!("[1/5] Getting framebuffer info...");
-    serial_println!("[1/5] Getting framebuffer info...");
-    let (fb_addr, fb_width, fb_height, fb_stride, fb_bpp) = get_framebuffer_info();
+    info!("[1/5] Enumerating displays...");
+    serial_println!("[1/5] Enumerating displays...");
+    let boot_config = config::load();
+    let displays = display::enumerate(boot_config.resolution);
+    let (fb_addr, fb_width, fb_height, fb_stride, fb_bpp) = match displays.primary() {
+        // Assume 32-bit color (BGRA)
+        Some(primary) => (
+            primary.framebuffer_addr,
+            primary.active.width,
+            primary.active.height,
+            primary.active.pixels_per_scanline * 4,
+            32,
+        ),
+        None => (0, 0, 0, 0, 0),
+    };
+    let fb_pixel_format = displays.primary().map_or(0, |primary| primary.active.pixel_format);
     if fb_addr != 0 {
         info!("  Framebuffer: {}x{} @ {:#x}", fb_width, fb_height, fb_addr);
         serial_println!("  Framebuffer: {}x{} @ {:#x}", fb_width, fb_height, fb_addr);
@@ -116,7 +80,11 @@ fn main() -> Status {
     info!("");
     info!("[2/5] Loading kernel...");
     serial_println!("[2/5] Loading kernel...");
-    let loaded_kernel = match kernel::load_kernel_or_stub() {
+    let placement = kernel::KernelPlacement {
+        virtual_base: boot_config.kernel_base,
+        physical_base: boot_config.kernel_phys,
+    };
+    let loaded_kernel = match kernel::load_kernel_or_stub(placement) {
         Ok(k) => k,
         Err(e) => {
             info!("FATAL: {}", e);
@@ -155,20 +123,49 @@ fn main() -> Status {
     // Note: BSS section can be ~20MB+ (uninitialized static data for pools/tables)
     // We need to map: text + data + bss + stack/heap headroom
     // Add 32MB extra to cover BSS and leave room for early heap
+    let kernel_map_size = loaded_kernel.size + kernel::KERNEL_MAP_HEADROOM;
     if let Err(e) = page_tables.map_kernel(
         loaded_kernel.phys_addr,
         loaded_kernel.virt_addr,
-        loaded_kernel.size + 0x200_0000, // Add extra 32MB for BSS/stack/heap
+        kernel_map_size,
     ) {
         info!("FATAL: {}", e);
         serial_println!("FATAL: {}", e);
         loop { unsafe { asm!("hlt") }; }
     }
+
+    // The kernel is entered at its physical alias, so the part of a kernel
+    // loaded above 4GB needs to be identity mapped too
+    let kernel_phys_end = loaded_kernel.phys_addr + kernel_map_size;
+    if kernel_phys_end > 0x1_0000_0000 {
+        let start = loaded_kernel.phys_addr.max(0x1_0000_0000);
+        if let Err(e) = page_tables.map_kernel(start, start, kernel_phys_end - start) {
+            info!("FATAL: {}", e);
+            serial_println!("FATAL: {}", e);
+            loop { unsafe { asm!("hlt") }; }
+        }
+        info!("  Identity mapped kernel at {:#x}", loaded_kernel.phys_addr);
+        serial_println!("  Identity mapped kernel at {:#x}", loaded_kernel.phys_addr);
+    }
     info!("  Mapped kernel: phys={:#x} -> virt={:#x}",
         loaded_kernel.phys_addr, loaded_kernel.virt_addr);
     serial_println!("  Mapped kernel: phys={:#x} -> virt={:#x}",
         loaded_kernel.phys_addr, loaded_kernel.virt_addr);
 
+    // Map runtime services for SetVirtualAddressMap
+    let runtime_mapping = if boot_config.efi_runtime {
+        match runtime::map_runtime_regions(&mut page_tables) {
+            Ok(mapping) => Some(mapping),
+            Err(e) => {
+                info!("  Runtime services not mapped: {}", e);
+                serial_println!("  Runtime services not mapped: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     let pml4_addr = page_tables.pml4_phys_addr();
     info!("  PML4 at {:#x}", pml4_addr);
     serial_println!("  PML4 at {:#x}", pml4_addr);
@@ -185,19 +182,86 @@ fn main() -> Status {
         serial_println!("  RSDP found at {:#x}", rsdp_addr);
     }
 
-    // Fill in boot info (before we exit boot services)
-    unsafe {
-        BOOT_INFO.magic = BootInfo::MAGIC;
-        BOOT_INFO.framebuffer_addr = fb_addr;
-        BOOT_INFO.framebuffer_width = fb_width;
-        BOOT_INFO.framebuffer_height = fb_height;
-        BOOT_INFO.framebuffer_stride = fb_stride;
-        BOOT_INFO.framebuffer_bpp = fb_bpp;
-        BOOT_INFO.kernel_physical_base = loaded_kernel.phys_addr;
-        BOOT_INFO.kernel_virtual_base = loaded_kernel.virt_addr;
-        BOOT_INFO.kernel_size = loaded_kernel.size;
-        BOOT_INFO.pml4_physical_addr = pml4_addr;
-        BOOT_INFO.rsdp_addr = rsdp_addr;
+    // Load the initial ramdisk, if one is configured
+    let initrd = boot_config.initrd().and_then(|path| match kernel::load_initrd(path) {
+        Ok(initrd) => {
+            info!("  Initrd {} at {:#x}, {} bytes", path, initrd.0, initrd.1);
+            serial_println!("  Initrd {} at {:#x}, {} bytes", path, initrd.0, initrd.1);
+            Some(initrd)
+        }
+        Err(e) => {
+            info!("  Initrd {} not loaded: {}", path, e);
+            serial_println!("  Initrd {} not loaded: {}", path, e);
+            None
+        }
+    });
+
+    // Measure what was loaded, then take the event log including those entries
+    let kernel_image = unsafe {
+        core::slice::from_raw_parts(loaded_kernel.phys_addr as *const u8, loaded_kernel.size as usize)
+    };
+    if tpm::measure(tpm::PCR_IMAGES, kernel_image, "kernel.bin") {
+        if let Some((addr, size)) = initrd {
+            let image = unsafe { core::slice::from_raw_parts(addr as *const u8, size as usize) };
+            tpm::measure(tpm::PCR_IMAGES, image, "initrd");
+        }
+        if !boot_config.cmdline().is_empty() {
+            tpm::measure(tpm::PCR_CMDLINE, boot_config.cmdline().as_bytes(), boot_config.cmdline());
+        }
+        info!("  Measured boot components into PCR {} and {}", tpm::PCR_CMDLINE, tpm::PCR_IMAGES);
+        serial_println!("  Measured boot components into PCR {} and {}", tpm::PCR_CMDLINE, tpm::PCR_IMAGES);
+    }
+    let tpm_event_log = tpm::capture_event_log();
+
+    // Fill in boot info tags (before we exit boot services)
+    let mut boot_info = BootInfoBuilder::new();
+    boot_info.add(tag::KERNEL, &bootinfo::KernelTag {
+        physical_base: loaded_kernel.phys_addr,
+        virtual_base: loaded_kernel.virt_addr,
+        size: loaded_kernel.size,
+        pml4_physical_addr: pml4_addr,
+    });
+    if fb_addr != 0 {
+        boot_info.add(tag::FRAMEBUFFER, &bootinfo::FramebufferTag {
+            addr: fb_addr,
+            size: displays.primary().map_or(0, |primary| primary.framebuffer_size),
+            width: fb_width,
+            height: fb_height,
+            stride: fb_stride,
+            bpp: fb_bpp,
+            pixel_format: fb_pixel_format,
+            reserved: 0,
+        });
+    }
+    if rsdp_addr != 0 {
+        boot_info.add(tag::RSDP, &bootinfo::RsdpTag { addr: rsdp_addr });
+    }
+    if let Some(smbios) = find_smbios() {
+        info!("  SMBIOS {} entry point at {:#x}", smbios.entry_version, smbios.entry_addr);
+        serial_println!("  SMBIOS {} entry point at {:#x}", smbios.entry_version, smbios.entry_addr);
+        boot_info.add(tag::SMBIOS, &smbios);
+    }
+    if !boot_config.cmdline().is_empty() {
+        info!("  Command line: {}", boot_config.cmdline());
+        serial_println!("  Command line: {}", boot_config.cmdline());
+        boot_info.add_parts(tag::CMDLINE, &[boot_config.cmdline().as_bytes(), &[0]]);
+    }
+    if let Some((addr, size)) = initrd {
+        boot_info.add(tag::INITRD, &bootinfo::InitrdTag { addr, size });
+    }
+    if displays.count > 0 {
+        let list = bootinfo::ModesListTag {
+            count: displays.count,
+            flags: if displays.preferred_applied { bootinfo::BOOT_DISPLAY_PREFERRED_MODE } else { 0 },
+        };
+        let table = displays.displays();
+        let table_bytes = unsafe {
+            core::slice::from_raw_parts(table.as_ptr() as *const u8, core::mem::size_of_val(table))
+        };
+        boot_info.add_parts(tag::MODES_LIST, &[as_bytes(&list), table_bytes]);
+    }
+    if let Some(log) = &tpm_event_log {
+        boot_info.add(tag::TPM_EVENT_LOG, log);
     }
 
     // Step 5: Exit boot services and jump to kernel
@@ -215,15 +279,27 @@ fn main() -> Status {
     serial_println!("========================================");
 
     // Exit boot services - this is the point of no return!
-    let memory_map = unsafe { boot::exit_boot_services(MemoryType::LOADER_DATA) };
+    let mut memory_map = unsafe { boot::exit_boot_services(MemoryType::LOADER_DATA) };
+
+    // Move runtime services to their virtual window
+    if let Some(mapping) = &runtime_mapping {
+        let efi_runtime = unsafe { runtime::set_virtual_address_map(mapping, &mut memory_map) };
+        boot_info.add(tag::EFI_RUNTIME, &efi_runtime);
+    }
 
-    // Store memory map info
+    // Store memory map info and seal the tag list
     let (mmap_addr, mmap_entries, mmap_entry_size) = get_memory_map_info(&memory_map);
-    unsafe {
-        BOOT_INFO.memory_map_addr = mmap_addr;
-        BOOT_INFO.memory_map_entries = mmap_entries;
-        BOOT_INFO.memory_map_entry_size = mmap_entry_size;
+    boot_info.add(tag::MEMMAP, &bootinfo::MemoryMapTag {
+        addr: mmap_addr,
+        entry_count: mmap_entries,
+        entry_size: mmap_entry_size,
+        descriptor_version: memory_map.meta().desc_version,
+        reserved: 0,
+    });
+    if boot_info.dropped() > 0 {
+        serial_println!("WARNING: {} boot info tags did not fit", boot_info.dropped());
     }
+    let boot_info_addr = boot_info.finish();
 
     // Now we're on our own - no more UEFI services!
     // Switch to our page tables and jump to kernel
@@ -231,36 +307,11 @@ fn main() -> Status {
         jump_to_kernel(
             pml4_addr,
             loaded_kernel.entry_phys(),
-            &raw const BOOT_INFO as u64,
+            boot_info_addr,
         );
     }
 }
 
-/// Get framebuffer information from GOP
-fn get_framebuffer_info() -> (u64, u32, u32, u32, u32) {
-    use uefi::proto::console::gop::GraphicsOutput;
-
-    let gop_handle = match boot::get_handle_for_protocol::<GraphicsOutput>() {
-        Ok(h) => h,
-        Err(_) => return (0, 0, 0, 0, 0),
-    };
-
-    let mut gop = match boot::open_protocol_exclusive::<GraphicsOutput>(gop_handle) {
-        Ok(g) => g,
-        Err(_) => return (0, 0, 0, 0, 0),
-    };
-
-    let mode_info = gop.current_mode_info();
-    let (width, height) = mode_info.resolution();
-    let stride = mode_info.stride() as u32;
-    let fb_addr = gop.frame_buffer().as_mut_ptr() as u64;
-
-    // Assume 32-bit color (BGRA)
-    let bpp = 32;
-
-    (fb_addr, width as u32, height as u32, stride * 4, bpp)
-}
-
 /// Find ACPI RSDP from UEFI configuration tables
 fn find_rsdp() -> u64 {
     use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
@@ -286,6 +337,25 @@ fn find_rsdp() -> u64 {
     })
 }
 
+/// Find the SMBIOS entry point, preferring the 64-bit one
+fn find_smbios() -> Option<bootinfo::SmbiosTag> {
+    use uefi::table::cfg::{SMBIOS3_GUID, SMBIOS_GUID};
+
+    uefi::system::with_config_table(|tables| {
+        [(SMBIOS3_GUID, 3), (SMBIOS_GUID, 2)].iter().find_map(|(guid, version)| {
+            tables.iter().find(|entry| entry.guid == *guid).map(|entry| bootinfo::SmbiosTag {
+                entry_addr: entry.address as u64,
+                entry_version: *version,
+                reserved: 0,
+            })
+        })
+    })
+}
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
 /// Get memory map information
 fn get_memory_map_info(memory_map: &MemoryMapOwned) -> (u64, u64, u64) {
     // Get the raw buffer from the memory map
@@ -230,6 +230,40 @@ impl PageTables {
         Ok(())
     }
 
+    /// Get the table an entry points to, allocating it if empty
+    fn next_table(&mut self, table: *mut PageTable, index: usize) -> Result<*mut PageTable, &'static str> {
+        let table = unsafe { &mut *table };
+        if table.entries[index] == 0 {
+            let next = Self::allocate_table()?;
+            self.track_table(next);
+            table.set_table_entry(index, next as u64);
+            Ok(next)
+        } else {
+            Ok((table.entries[index] & 0x000F_FFFF_FFFF_F000) as *mut PageTable)
+        }
+    }
+
+    /// Map a range with 4KB pages
+    ///
+    /// Maps physical [phys, phys + size) to virtual [virt, virt + size).
+    /// Both addresses must be page aligned; used for regions that are not
+    /// 2MB aligned, such as UEFI runtime services code and data.
+    pub fn map_pages(&mut self, phys: u64, virt: u64, size: u64) -> Result<(), &'static str> {
+        let mut offset = 0u64;
+        while offset < size {
+            let v = virt + offset;
+            let pdpt = self.next_table(self.pml4, pml4_index(v))?;
+            let pd = self.next_table(pdpt, pdpt_index(v))?;
+            if unsafe { (*pd).entries[pd_index(v)] } & flags::HUGE_PAGE != 0 {
+                return Err("4KB mapping overlaps a 2MB page");
+            }
+            let pt = self.next_table(pd, pd_index(v))?;
+            unsafe { (*pt).set_page(pt_index(v), phys + offset, true) };
+            offset += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
     /// Get physical address of PML4 (for loading into CR3)
     pub fn pml4_phys_addr(&self) -> u64 {
         self.pml4 as u64
@@ -0,0 +1,239 @@
+//! Kernel relocation
+//!
+//! The kernel is linked at KERNEL_VIRTUAL_BASE with `--emit-relocs`, so the
+//! ELF file keeps every relocation the linker resolved. To run it at another
+//! virtual base the loader re-applies the absolute ones with the difference
+//! between the two bases:
+//!
+//! - `R_X86_64_64`: 64-bit addresses (vtables, function pointer tables)
+//! - `R_X86_64_32S`: sign-extended 32-bit addresses from `code-model=kernel`,
+//!   which is why the new base must stay in the top 2GB
+//!
+//! PC-relative relocations need nothing since the whole image moves.
+//! `R_X86_64_32` only appears in the Multiboot2 entry stub, which holds
+//! physical addresses and never runs when this loader boots the kernel, so
+//! it is left alone. The linker emits no relocations for the GOT it builds
+//! itself; its entries are adjusted when they point into the image.
+
+use log::info;
+
+use crate::serial_println;
+
+/// Section header types and flags
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+
+/// Special section indices
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xFFF1;
+
+/// Relocation types
+mod r_x86_64 {
+    pub const R_64: u32 = 1;
+    pub const PC32: u32 = 2;
+    pub const PLT32: u32 = 4;
+    pub const GOTPCREL: u32 = 9;
+    pub const R_32: u32 = 10;
+    pub const R_32S: u32 = 11;
+    pub const PC64: u32 = 24;
+    pub const GOTPCRELX: u32 = 41;
+    pub const REX_GOTPCRELX: u32 = 42;
+}
+
+/// Size of an Elf64_Shdr
+const SECTION_HEADER_SIZE: usize = 64;
+/// Size of an Elf64_Rela
+const RELA_SIZE: usize = 24;
+/// Size of an Elf64_Sym
+const SYMBOL_SIZE: usize = 24;
+
+/// Relocation results
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RelocStats {
+    /// Absolute relocations re-applied
+    pub applied: usize,
+    /// Relative and stub relocations left as linked
+    pub skipped: usize,
+    /// GOT entries adjusted
+    pub got_entries: usize,
+}
+
+/// One Elf64_Shdr, only the fields used here
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    name: u32,
+    section_type: u32,
+    flags: u64,
+    addr: u64,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+}
+
+fn read_u16(elf: &[u8], at: usize) -> Result<u16, &'static str> {
+    elf.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or("Kernel ELF truncated")
+}
+
+fn read_u32(elf: &[u8], at: usize) -> Result<u32, &'static str> {
+    elf.get(at..at + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or("Kernel ELF truncated")
+}
+
+fn read_u64(elf: &[u8], at: usize) -> Result<u64, &'static str> {
+    elf.get(at..at + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or("Kernel ELF truncated")
+}
+
+fn section(elf: &[u8], index: usize) -> Result<Section, &'static str> {
+    let shoff = read_u64(elf, 40)? as usize;
+    let shnum = read_u16(elf, 60)? as usize;
+    if index >= shnum {
+        return Err("Bad section index in kernel ELF");
+    }
+    let at = shoff + index * SECTION_HEADER_SIZE;
+    Ok(Section {
+        name: read_u32(elf, at)?,
+        section_type: read_u32(elf, at + 4)?,
+        flags: read_u64(elf, at + 8)?,
+        addr: read_u64(elf, at + 16)?,
+        offset: read_u64(elf, at + 24)? as usize,
+        size: read_u64(elf, at + 32)? as usize,
+        link: read_u32(elf, at + 40)?,
+        info: read_u32(elf, at + 44)?,
+    })
+}
+
+fn section_name<'a>(elf: &'a [u8], section: &Section) -> Result<&'a [u8], &'static str> {
+    let strtab = self::section(elf, read_u16(elf, 62)? as usize)?;
+    let start = strtab.offset + section.name as usize;
+    let names = elf.get(start..strtab.offset + strtab.size).ok_or("Kernel ELF truncated")?;
+    let len = names.iter().position(|&b| b == 0).unwrap_or(names.len());
+    Ok(&names[..len])
+}
+
+/// True if the ELF carries relocation sections for its loaded image
+pub fn has_relocations(elf: &[u8]) -> bool {
+    let Ok(shnum) = read_u16(elf, 60) else {
+        return false;
+    };
+    (0..shnum as usize).any(|i| {
+        section(elf, i).is_ok_and(|s| {
+            s.section_type == SHT_RELA
+                && section(elf, s.info as usize).is_ok_and(|target| target.flags & SHF_ALLOC != 0)
+        })
+    })
+}
+
+/// Re-apply the kernel's relocations for `new_base`
+///
+/// `image` is the physical address the image was loaded at, `link_base`
+/// the virtual address it was linked for and `image_size` its size in
+/// memory.
+pub fn relocate(
+    elf: &[u8],
+    image: u64,
+    link_base: u64,
+    new_base: u64,
+    image_size: u64,
+) -> Result<RelocStats, &'static str> {
+    let mut stats = RelocStats::default();
+    let delta = new_base.wrapping_sub(link_base);
+    if delta == 0 {
+        return Ok(stats);
+    }
+    if !has_relocations(elf) {
+        return Err("Kernel has no relocations (link with --emit-relocs)");
+    }
+
+    let link_end = link_base + image_size;
+    let location = |vaddr: u64, width: u64| -> Result<u64, &'static str> {
+        if vaddr < link_base || vaddr + width > link_end {
+            return Err("Kernel relocation outside the image");
+        }
+        Ok(image + (vaddr - link_base))
+    };
+
+    let shnum = read_u16(elf, 60)? as usize;
+    for index in 0..shnum {
+        let rela = section(elf, index)?;
+        if rela.section_type != SHT_RELA {
+            continue;
+        }
+        // Debug sections are not loaded
+        let target = section(elf, rela.info as usize)?;
+        if target.flags & SHF_ALLOC == 0 || target.section_type == SHT_NOBITS {
+            continue;
+        }
+        let symtab = section(elf, rela.link as usize)?;
+
+        for entry in 0..rela.size / RELA_SIZE {
+            let at = rela.offset + entry * RELA_SIZE;
+            let offset = read_u64(elf, at)?;
+            let r_info = read_u64(elf, at + 8)?;
+            let (r_type, symbol) = (r_info as u32, (r_info >> 32) as usize);
+
+            // Absolute symbols do not move with the image
+            let shndx = read_u16(elf, symtab.offset + symbol * SYMBOL_SIZE + 6)?;
+            if shndx == SHN_UNDEF || shndx == SHN_ABS {
+                stats.skipped += 1;
+                continue;
+            }
+
+            match r_type {
+                r_x86_64::R_64 => {
+                    let ptr = location(offset, 8)? as *mut u64;
+                    unsafe { ptr.write_unaligned(ptr.read_unaligned().wrapping_add(delta)) };
+                    stats.applied += 1;
+                }
+                r_x86_64::R_32S => {
+                    let ptr = location(offset, 4)? as *mut i32;
+                    let value = unsafe { ptr.read_unaligned() } as i64;
+                    let moved = value.wrapping_add(delta as i64);
+                    let moved = i32::try_from(moved).map_err(|_| "Kernel base outside the top 2GB")?;
+                    unsafe { ptr.write_unaligned(moved) };
+                    stats.applied += 1;
+                }
+                r_x86_64::PC32
+                | r_x86_64::PLT32
+                | r_x86_64::PC64
+                | r_x86_64::GOTPCREL
+                | r_x86_64::GOTPCRELX
+                | r_x86_64::REX_GOTPCRELX
+                | r_x86_64::R_32 => stats.skipped += 1,
+                _ => {
+                    serial_println!("  Unsupported kernel relocation type {} at {:#x}", r_type, offset);
+                    return Err("Unsupported kernel relocation type");
+                }
+            }
+        }
+    }
+
+    // GOT entries filled in by the linker
+    for index in 0..shnum {
+        let got = section(elf, index)?;
+        if section_name(elf, &got)? != b".got" {
+            continue;
+        }
+        let base = location(got.addr, got.size as u64)? as *mut u64;
+        for slot in 0..got.size / 8 {
+            let ptr = unsafe { base.add(slot) };
+            let value = unsafe { ptr.read_unaligned() };
+            if value >= link_base && value < link_end {
+                unsafe { ptr.write_unaligned(value.wrapping_add(delta)) };
+                stats.got_entries += 1;
+            }
+        }
+    }
+
+    info!("  Relocated kernel to {:#x}: {} relocations, {} GOT entries",
+        new_base, stats.applied, stats.got_entries);
+    serial_println!("  Relocated kernel to {:#x}: {} relocations, {} GOT entries",
+        new_base, stats.applied, stats.got_entries);
+    Ok(stats)
+}
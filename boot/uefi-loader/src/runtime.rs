@@ -0,0 +1,125 @@
+//! UEFI runtime services virtual mapping
+//!
+//! Runtime services stay callable after ExitBootServices, but only at the
+//! addresses given to SetVirtualAddressMap. Every region with the RUNTIME
+//! attribute is mapped into a dedicated kernel-half window at
+//! `EFI_RUNTIME_VIRTUAL_BASE + physical`, which the kernel inherits with
+//! the rest of the page tables (and shares into every user address space).
+//!
+//! The window is built from the memory map before ExitBootServices, since
+//! page tables can only be allocated while boot services are up; the final
+//! map is then converted after exit.
+
+use core::ptr::NonNull;
+use log::info;
+use uefi::boot;
+use uefi::mem::memory_map::{MemoryAttribute, MemoryMap, MemoryMapMut, MemoryMapOwned, MemoryType};
+use uefi_raw::table::system::SystemTable;
+
+use crate::bootinfo::EfiRuntimeTag;
+use crate::paging::{PageTables, PAGE_SIZE};
+use crate::serial_println;
+
+/// Base of the runtime services window (PML4 slot 508)
+pub const EFI_RUNTIME_VIRTUAL_BASE: u64 = 0xFFFF_FE00_0000_0000;
+
+/// Runtime regions remembered from the pre-exit map
+const MAX_RUNTIME_REGIONS: usize = 64;
+
+static mut MAPPED_REGIONS: [(u64, u64); MAX_RUNTIME_REGIONS] = [(0, 0); MAX_RUNTIME_REGIONS];
+static mut MAPPED_COUNT: usize = 0;
+
+/// Runtime services state carried from before ExitBootServices
+pub struct RuntimeMapping {
+    system_table: Option<NonNull<SystemTable>>,
+}
+
+/// Map every runtime region into the window
+///
+/// Must be called while boot services are still available.
+pub fn map_runtime_regions(page_tables: &mut PageTables) -> Result<RuntimeMapping, &'static str> {
+    let memory_map = boot::memory_map(MemoryType::LOADER_DATA)
+        .map_err(|_| "Failed to get memory map")?;
+
+    let mapped = &raw mut MAPPED_REGIONS;
+    let mut count = 0usize;
+    for desc in memory_map.entries() {
+        if !desc.att.contains(MemoryAttribute::RUNTIME) {
+            continue;
+        }
+        if count >= MAX_RUNTIME_REGIONS {
+            return Err("Too many runtime regions");
+        }
+        let size = desc.page_count * PAGE_SIZE;
+        page_tables.map_pages(desc.phys_start, EFI_RUNTIME_VIRTUAL_BASE + desc.phys_start, size)?;
+        unsafe { (*mapped)[count] = (desc.phys_start, desc.page_count) };
+        count += 1;
+    }
+    unsafe { MAPPED_COUNT = count };
+
+    info!("  Mapped {} runtime services regions at {:#x}", count, EFI_RUNTIME_VIRTUAL_BASE);
+    serial_println!("  Mapped {} runtime services regions at {:#x}", count, EFI_RUNTIME_VIRTUAL_BASE);
+
+    Ok(RuntimeMapping {
+        system_table: uefi::table::system_table_raw(),
+    })
+}
+
+/// Switch runtime services to the window
+///
+/// Called after ExitBootServices with the final memory map, whose runtime
+/// entries get their virtual addresses filled in. Returns the tag for the
+/// kernel; `status` is nonzero if the firmware refused the new map.
+///
+/// # Safety
+/// Boot services must have been exited and nothing may call runtime
+/// services through their physical addresses afterwards.
+pub unsafe fn set_virtual_address_map(mapping: &RuntimeMapping, memory_map: &mut MemoryMapOwned) -> EfiRuntimeTag {
+    let mut tag = EfiRuntimeTag {
+        system_table: 0,
+        runtime_services: 0,
+        virtual_base: EFI_RUNTIME_VIRTUAL_BASE,
+        status: 0,
+    };
+    let Some(st) = mapping.system_table else {
+        tag.status = uefi::Status::NOT_FOUND.0 as u64;
+        return tag;
+    };
+    let rt = (*st.as_ptr()).runtime_services;
+
+    let mapped = &raw const MAPPED_REGIONS;
+    let mapped = &(&*mapped)[..MAPPED_COUNT];
+    let mut unmapped = 0u32;
+    for index in 0..memory_map.len() {
+        let Some(desc) = memory_map.get_mut(index) else { break };
+        if !desc.att.contains(MemoryAttribute::RUNTIME) {
+            continue;
+        }
+        if !mapped.contains(&(desc.phys_start, desc.page_count)) {
+            unmapped += 1;
+        }
+        desc.virt_start = EFI_RUNTIME_VIRTUAL_BASE + desc.phys_start;
+    }
+    if unmapped != 0 {
+        serial_println!("WARNING: {} runtime regions appeared after mapping", unmapped);
+    }
+
+    let meta = memory_map.meta();
+    let map_size = meta.desc_size * memory_map.len();
+    let buffer = memory_map.buffer_mut();
+    let status = ((*rt).set_virtual_address_map)(
+        map_size,
+        meta.desc_size,
+        meta.desc_version,
+        buffer.as_mut_ptr().cast(),
+    );
+    if status.is_error() {
+        serial_println!("WARNING: SetVirtualAddressMap failed: {:?}", status);
+        tag.status = status.0 as u64;
+        return tag;
+    }
+
+    tag.system_table = EFI_RUNTIME_VIRTUAL_BASE + st.as_ptr() as u64;
+    tag.runtime_services = EFI_RUNTIME_VIRTUAL_BASE + rt as u64;
+    tag
+}
@@ -0,0 +1,199 @@
+//! Measured boot through the firmware's TCG2 protocol
+//!
+//! Before handing over, the loader measures what it loaded into the TPM
+//! (kernel image and initrd into PCR 9, the command line into PCR 8) with
+//! HashLogExtendEvent, so the firmware log records them alongside its own
+//! measurements. It then fetches the crypto-agile (TCG 2.0) event log and
+//! passes its location to the kernel, which cannot reach the protocol after
+//! ExitBootServices.
+//!
+//! The log itself stays in the firmware's boot services memory; it is
+//! copied into loader data so it survives the kernel reclaiming that.
+//! Events logged after GetEventLog (including ExitBootServices itself) go
+//! to the EFI_TCG2_FINAL_EVENTS_TABLE, whose address is passed as well.
+
+use log::info;
+use uefi::boot;
+use uefi::mem::memory_map::MemoryType;
+use uefi::proto::tcg::v2::{HashLogExtendEventFlags, PcrEventInputs, Tcg};
+use uefi::proto::tcg::{EventType, PcrIndex};
+use uefi::{guid, Guid};
+
+use crate::bootinfo::TpmEventLogTag;
+use crate::serial_println;
+
+/// EFI_TCG2_FINAL_EVENTS_TABLE_GUID
+const TCG2_FINAL_EVENTS_TABLE_GUID: Guid = guid!("1e2ed096-30e2-4254-bd89-863bbef82325");
+
+/// EFI_TCG2_EVENT_LOG_FORMAT_TCG_2 (crypto-agile)
+const EVENT_LOG_FORMAT_TCG_2: u32 = 0x2;
+
+/// PCR for the command line
+pub const PCR_CMDLINE: u32 = 8;
+/// PCR for the kernel image and initrd
+pub const PCR_IMAGES: u32 = 9;
+
+/// Size of a TCG_PCR_EVENT without its event data
+const PCR_EVENT_HEADER_SIZE: usize = 32;
+
+/// Most digest algorithms understood in a log
+const MAX_ALGORITHMS: usize = 8;
+
+/// Leading fields of EFI_TCG2_PROTOCOL, used for the raw event log call
+#[repr(C)]
+struct Tcg2Raw {
+    get_capability: usize,
+    get_event_log: unsafe extern "efiapi" fn(
+        this: *mut Tcg2Raw,
+        format: u32,
+        location: *mut u64,
+        last_entry: *mut u64,
+        truncated: *mut u8,
+    ) -> uefi::Status,
+}
+
+/// Extend `pcr` with the hash of `data` and log it as an EV_IPL event
+///
+/// Returns false if there is no TCG2 protocol or the firmware refused.
+pub fn measure(pcr: u32, data: &[u8], description: &str) -> bool {
+    let Ok(handle) = boot::get_handle_for_protocol::<Tcg>() else {
+        return false;
+    };
+    let Ok(mut tcg) = boot::open_protocol_exclusive::<Tcg>(handle) else {
+        return false;
+    };
+
+    let mut buffer = [0u8; 128];
+    let Ok(event) = PcrEventInputs::new_in_buffer(
+        &mut buffer,
+        PcrIndex(pcr),
+        EventType::IPL,
+        description.as_bytes(),
+    ) else {
+        return false;
+    };
+    tcg.hash_log_extend_event(HashLogExtendEventFlags::empty(), data, event).is_ok()
+}
+
+/// Read a little-endian u32 at `offset`
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// Digest sizes announced by the Spec ID header event
+struct DigestSizes {
+    algorithms: [(u16, u16); MAX_ALGORITHMS],
+    count: usize,
+}
+
+impl DigestSizes {
+    fn size_of(&self, algorithm: u16) -> Option<usize> {
+        self.algorithms[..self.count]
+            .iter()
+            .find(|(id, _)| *id == algorithm)
+            .map(|(_, size)| *size as usize)
+    }
+}
+
+/// Parse the TCG_PCR_EVENT header; returns its size and the digest sizes
+///
+/// # Safety
+/// `location` must point at a readable event log.
+unsafe fn parse_header(location: u64) -> Option<(usize, DigestSizes)> {
+    let fixed = core::slice::from_raw_parts(location as *const u8, PCR_EVENT_HEADER_SIZE);
+    let event_size = read_u32(fixed, 28)? as usize;
+    let event = core::slice::from_raw_parts(
+        (location as *const u8).add(PCR_EVENT_HEADER_SIZE),
+        event_size,
+    );
+    if !event.starts_with(b"Spec ID Event03") {
+        return None;
+    }
+    let count = read_u32(event, 24)? as usize;
+    if count > MAX_ALGORITHMS {
+        return None;
+    }
+    let mut sizes = DigestSizes { algorithms: [(0, 0); MAX_ALGORITHMS], count };
+    for (index, entry) in sizes.algorithms[..count].iter_mut().enumerate() {
+        let at = 28 + index * 4;
+        let pair = event.get(at..at + 4)?;
+        *entry = (
+            u16::from_le_bytes([pair[0], pair[1]]),
+            u16::from_le_bytes([pair[2], pair[3]]),
+        );
+    }
+    Some((PCR_EVENT_HEADER_SIZE + event_size, sizes))
+}
+
+/// Size of the TCG_PCR_EVENT2 at `at`
+///
+/// # Safety
+/// `at` must point at a complete event in the log.
+unsafe fn event2_size(at: u64, sizes: &DigestSizes) -> Option<usize> {
+    let base = at as *const u8;
+    let read = |offset: usize| -> u32 {
+        core::ptr::read_unaligned(base.add(offset) as *const u32)
+    };
+    let digest_count = read(8) as usize;
+    let mut offset = 12;
+    for _ in 0..digest_count {
+        let algorithm = core::ptr::read_unaligned(base.add(offset) as *const u16);
+        offset += 2 + sizes.size_of(algorithm)?;
+    }
+    let event_size = read(offset) as usize;
+    Some(offset + 4 + event_size)
+}
+
+/// Copy the crypto-agile event log into loader data
+///
+/// Must be called after the last `measure`, while boot services are up.
+pub fn capture_event_log() -> Option<TpmEventLogTag> {
+    let handle = boot::get_handle_for_protocol::<Tcg>().ok()?;
+    let mut tcg = boot::open_protocol_exclusive::<Tcg>(handle).ok()?;
+
+    let mut location = 0u64;
+    let mut last_entry = 0u64;
+    let mut truncated = 0u8;
+    let raw = &mut *tcg as *mut Tcg as *mut Tcg2Raw;
+    let status = unsafe {
+        ((*raw).get_event_log)(raw, EVENT_LOG_FORMAT_TCG_2, &mut location, &mut last_entry, &mut truncated)
+    };
+    if status.is_error() || location == 0 {
+        info!("  TPM event log unavailable: {:?}", status);
+        serial_println!("  TPM event log unavailable: {:?}", status);
+        return None;
+    }
+
+    // The log is contiguous; its end is the end of the last entry
+    let (header_size, sizes) = unsafe { parse_header(location)? };
+    let end = if last_entry == 0 || last_entry == location {
+        location + header_size as u64
+    } else {
+        last_entry + unsafe { event2_size(last_entry, &sizes)? } as u64
+    };
+    let size = (end - location) as usize;
+
+    let pages = size.div_ceil(4096);
+    let copy = boot::allocate_pages(boot::AllocateType::AnyPages, MemoryType::LOADER_DATA, pages).ok()?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(location as *const u8, copy.as_ptr(), size);
+    }
+
+    let final_events = uefi::system::with_config_table(|tables| {
+        tables
+            .iter()
+            .find(|entry| entry.guid == TCG2_FINAL_EVENTS_TABLE_GUID)
+            .map_or(0, |entry| entry.address as u64)
+    });
+
+    info!("  TPM event log: {} bytes{}", size, if truncated != 0 { " (truncated)" } else { "" });
+    serial_println!("  TPM event log: {} bytes{}", size, if truncated != 0 { " (truncated)" } else { "" });
+
+    Some(TpmEventLogTag {
+        addr: copy.as_ptr() as u64,
+        size: size as u64,
+        final_events_addr: final_events,
+        format: EVENT_LOG_FORMAT_TCG_2,
+        flags: if truncated != 0 { crate::bootinfo::TPM_EVENT_LOG_TRUNCATED } else { 0 },
+    })
+}
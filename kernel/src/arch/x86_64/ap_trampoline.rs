@@ -295,8 +295,13 @@ pub fn wait_for_aps(expected_count: u32, timeout_ms: u32) -> bool {
             }
         }
 
-        // Spin-wait with hint
-        core::hint::spin_loop();
+        // Sleep until the next interrupt (the clock tick at the latest);
+        // with interrupts off nothing would wake us, so spin instead
+        if super::interrupts_enabled() {
+            super::halt();
+        } else {
+            core::hint::spin_loop();
+        }
     }
 }
 
@@ -0,0 +1,311 @@
+//! Early-Boot Exception Handlers
+//!
+//! A temporary IDT installed as the first thing in `kernel_main`, before
+//! .bss is zeroed and long before `init_phase0` loads the real GDT and IDT.
+//! Without it a fault in that window uses the loader's (or firmware's) IDT,
+//! which shows up as a silent reboot or hang.
+//!
+//! Each CPU exception (vectors 0-31) gets a small stub that pushes the
+//! vector and a dummy error code where the CPU does not push one, saves
+//! the general-purpose registers and calls `early_exception_dump`, which
+//! writes the full register state to COM1 and halts.
+//!
+//! Everything here lives in .data and writes the UART directly, so it works
+//! before .bss is zeroed and without any lock, allocator or console.
+
+use core::arch::{asm, naked_asm};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::tables::lidt;
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::VirtAddr;
+
+use crate::serial::early_putc;
+
+/// CPU exception vectors covered
+const EARLY_VECTORS: usize = 32;
+
+/// Raw 64-bit interrupt gate
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EarlyGate {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    type_attr: u8,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+impl EarlyGate {
+    const EMPTY: Self = Self {
+        offset_low: 0,
+        selector: 0,
+        ist: 0,
+        type_attr: 0,
+        offset_mid: 0,
+        offset_high: 0,
+        reserved: 0,
+    };
+
+    /// Present, DPL 0, 64-bit interrupt gate
+    const INTERRUPT_GATE: u8 = 0x8E;
+
+    fn new(handler: u64, selector: u16) -> Self {
+        Self {
+            offset_low: handler as u16,
+            selector,
+            ist: 0,
+            type_attr: Self::INTERRUPT_GATE,
+            offset_mid: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+}
+
+/// Register state saved by the stubs, lowest address first
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EarlyExceptionFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    /// CPU error code, or 0 for vectors without one
+    pub error_code: u64,
+    // Pushed by the CPU
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+// Kept in .data: installed before .bss is zeroed
+#[link_section = ".data"]
+static mut EARLY_IDT: [EarlyGate; EARLY_VECTORS] = [EarlyGate::EMPTY; EARLY_VECTORS];
+
+/// Set while a dump is in progress, to catch faults inside the dumper
+#[link_section = ".data"]
+static EARLY_DUMPING: AtomicBool = AtomicBool::new(false);
+
+/// Common stub: save the general-purpose registers and dump them
+#[unsafe(naked)]
+unsafe extern "C" fn early_exception_common() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp",
+        "and rsp, -16",
+        "call {dump}",
+        "2:",
+        "cli",
+        "hlt",
+        "jmp 2b",
+        dump = sym early_exception_dump,
+    )
+}
+
+/// Per-vector stub for exceptions where the CPU pushes no error code
+macro_rules! early_stub {
+    ($name:ident, $vector:expr) => {
+        #[unsafe(naked)]
+        unsafe extern "C" fn $name() {
+            naked_asm!(
+                "push 0",
+                "push {vector}",
+                "jmp {common}",
+                vector = const $vector,
+                common = sym early_exception_common,
+            )
+        }
+    };
+}
+
+/// Per-vector stub for exceptions where the CPU pushes an error code
+macro_rules! early_stub_error {
+    ($name:ident, $vector:expr) => {
+        #[unsafe(naked)]
+        unsafe extern "C" fn $name() {
+            naked_asm!(
+                "push {vector}",
+                "jmp {common}",
+                vector = const $vector,
+                common = sym early_exception_common,
+            )
+        }
+    };
+}
+
+early_stub!(early_stub_0, 0);
+early_stub!(early_stub_1, 1);
+early_stub!(early_stub_2, 2);
+early_stub!(early_stub_3, 3);
+early_stub!(early_stub_4, 4);
+early_stub!(early_stub_5, 5);
+early_stub!(early_stub_6, 6);
+early_stub!(early_stub_7, 7);
+early_stub_error!(early_stub_8, 8);
+early_stub!(early_stub_9, 9);
+early_stub_error!(early_stub_10, 10);
+early_stub_error!(early_stub_11, 11);
+early_stub_error!(early_stub_12, 12);
+early_stub_error!(early_stub_13, 13);
+early_stub_error!(early_stub_14, 14);
+early_stub!(early_stub_15, 15);
+early_stub!(early_stub_16, 16);
+early_stub_error!(early_stub_17, 17);
+early_stub!(early_stub_18, 18);
+early_stub!(early_stub_19, 19);
+early_stub!(early_stub_20, 20);
+early_stub_error!(early_stub_21, 21);
+early_stub!(early_stub_22, 22);
+early_stub!(early_stub_23, 23);
+early_stub!(early_stub_24, 24);
+early_stub!(early_stub_25, 25);
+early_stub!(early_stub_26, 26);
+early_stub!(early_stub_27, 27);
+early_stub!(early_stub_28, 28);
+early_stub_error!(early_stub_29, 29);
+early_stub_error!(early_stub_30, 30);
+early_stub!(early_stub_31, 31);
+
+const EARLY_STUBS: [unsafe extern "C" fn(); EARLY_VECTORS] = [
+    early_stub_0, early_stub_1, early_stub_2, early_stub_3,
+    early_stub_4, early_stub_5, early_stub_6, early_stub_7,
+    early_stub_8, early_stub_9, early_stub_10, early_stub_11,
+    early_stub_12, early_stub_13, early_stub_14, early_stub_15,
+    early_stub_16, early_stub_17, early_stub_18, early_stub_19,
+    early_stub_20, early_stub_21, early_stub_22, early_stub_23,
+    early_stub_24, early_stub_25, early_stub_26, early_stub_27,
+    early_stub_28, early_stub_29, early_stub_30, early_stub_31,
+];
+
+/// Install the early IDT
+///
+/// Uses the loader's code segment; replaced by `idt::init` in Phase 0.
+///
+/// # Safety
+/// Must run with interrupts disabled, on the boot CPU, before `idt::init`.
+pub unsafe fn install() {
+    let selector: u16;
+    asm!("mov {0:x}, cs", out(reg) selector, options(nomem, nostack, preserves_flags));
+
+    let idt = &mut *core::ptr::addr_of_mut!(EARLY_IDT);
+    for (gate, stub) in idt.iter_mut().zip(EARLY_STUBS) {
+        *gate = EarlyGate::new(stub as usize as u64, selector);
+    }
+
+    lidt(&DescriptorTablePointer {
+        limit: (core::mem::size_of::<[EarlyGate; EARLY_VECTORS]>() - 1) as u16,
+        base: VirtAddr::new(idt.as_ptr() as u64),
+    });
+}
+
+/// Exception mnemonic for a vector
+fn exception_name(vector: u64) -> &'static str {
+    match vector {
+        0 => "#DE Divide Error",
+        1 => "#DB Debug",
+        2 => "NMI",
+        3 => "#BP Breakpoint",
+        4 => "#OF Overflow",
+        5 => "#BR Bound Range Exceeded",
+        6 => "#UD Invalid Opcode",
+        7 => "#NM Device Not Available",
+        8 => "#DF Double Fault",
+        10 => "#TS Invalid TSS",
+        11 => "#NP Segment Not Present",
+        12 => "#SS Stack-Segment Fault",
+        13 => "#GP General Protection",
+        14 => "#PF Page Fault",
+        16 => "#MF x87 Floating-Point",
+        17 => "#AC Alignment Check",
+        18 => "#MC Machine Check",
+        19 => "#XM SIMD Floating-Point",
+        20 => "#VE Virtualization",
+        21 => "#CP Control Protection",
+        29 => "#VC VMM Communication",
+        30 => "#SX Security",
+        _ => "Reserved",
+    }
+}
+
+/// Unbuffered, lock-free COM1 writer
+struct EarlyWriter;
+
+impl Write for EarlyWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &c in s.as_bytes() {
+            if c == b'\n' {
+                early_putc(b'\r');
+            }
+            early_putc(c);
+        }
+        Ok(())
+    }
+}
+
+fn read_control_registers() -> [u64; 4] {
+    let (cr0, cr2, cr3, cr4): (u64, u64, u64, u64);
+    unsafe {
+        asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+        asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    }
+    [cr0, cr2, cr3, cr4]
+}
+
+/// Dump the saved state to serial; the common stub halts afterwards
+extern "C" fn early_exception_dump(frame: &EarlyExceptionFrame) {
+    let mut w = EarlyWriter;
+
+    if EARLY_DUMPING.swap(true, Ordering::Relaxed) {
+        let _ = writeln!(w, "\n*** EARLY EXCEPTION {} WHILE DUMPING, RIP={:#018x} ***",
+            frame.vector, frame.rip);
+        return;
+    }
+
+    let [cr0, cr2, cr3, cr4] = read_control_registers();
+    let _ = writeln!(w, "\n*** EARLY BOOT EXCEPTION {} ({}) ***",
+        frame.vector, exception_name(frame.vector));
+    let _ = writeln!(w, "Error code: {:#x}", frame.error_code);
+    let _ = writeln!(w, "RIP={:#018x} CS={:#06x} RFLAGS={:#018x}",
+        frame.rip, frame.cs, frame.rflags);
+    let _ = writeln!(w, "RSP={:#018x} SS={:#06x}", frame.rsp, frame.ss);
+    let _ = writeln!(w, "RAX={:#018x} RBX={:#018x} RCX={:#018x}", frame.rax, frame.rbx, frame.rcx);
+    let _ = writeln!(w, "RDX={:#018x} RSI={:#018x} RDI={:#018x}", frame.rdx, frame.rsi, frame.rdi);
+    let _ = writeln!(w, "RBP={:#018x} R8 ={:#018x} R9 ={:#018x}", frame.rbp, frame.r8, frame.r9);
+    let _ = writeln!(w, "R10={:#018x} R11={:#018x} R12={:#018x}", frame.r10, frame.r11, frame.r12);
+    let _ = writeln!(w, "R13={:#018x} R14={:#018x} R15={:#018x}", frame.r13, frame.r14, frame.r15);
+    let _ = writeln!(w, "CR0={:#018x} CR2={:#018x} CR3={:#018x} CR4={:#018x}", cr0, cr2, cr3, cr4);
+    let _ = writeln!(w, "System halted (before Phase 0 IDT).");
+}
@@ -24,6 +24,7 @@ pub mod vector {
     pub const TIMER: u8 = 32;
     pub const KEYBOARD: u8 = 33;
     pub const MOUSE: u8 = 44;  // IRQ12 = 32 + 12 = 44
+    pub const PIC_BASE: u8 = 32; // IRQn = 32 + n
     // SMP IPIs (high vectors)
     pub const IPI_STOP: u8 = 0xFC;
     pub const IPI_RESCHEDULE: u8 = 0xFD;
@@ -83,6 +84,11 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     // Mouse interrupt (vector 44) - PS/2 mouse
     idt[vector::MOUSE].set_handler_fn(mouse_interrupt_handler);
 
+    // Remaining PIC lines - dispatched to ISRs connected with hal_connect_interrupt
+    for (irq, handler) in DEVICE_IRQ_HANDLERS {
+        idt[vector::PIC_BASE + irq].set_handler_fn(handler);
+    }
+
     // SMP IPI handlers
     idt[vector::IPI_STOP].set_handler_fn(ipi_stop_handler);
     idt[vector::IPI_RESCHEDULE].set_handler_fn(ipi_reschedule_handler);
@@ -279,7 +285,7 @@ extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFram
 
 /// Timer interrupt handler (vector 32)
 /// Called by APIC timer at configured frequency (typically 1000Hz)
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
     // Increment statistics counter
     INTERRUPT_STATS.timer.fetch_add(1, Ordering::Relaxed);
 
@@ -294,13 +300,18 @@ extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFr
     // Send End of Interrupt to APIC
     apic::eoi();
 
+    // Feed back recorded input that has come due
+    crate::hal::replay::replay_tick();
+
     // Process expired timers
     unsafe {
         crate::ke::timer::ki_expire_timers();
     }
 
-    // Call scheduler to handle quantum expiration
+    // Charge the tick, then handle quantum expiration
     unsafe {
+        let user_mode = stack_frame.code_segment.0 & 3 == 3;
+        crate::ke::scheduler::ki_update_run_time(user_mode);
         crate::ke::scheduler::ki_quantum_end();
 
         // Retire any pending DPCs (including timer DPCs)
@@ -314,6 +325,7 @@ extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFr
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
     // Increment statistics counter
     INTERRUPT_STATS.keyboard.fetch_add(1, Ordering::Relaxed);
+    crate::hal::replay::replay_note_interrupt(vector::KEYBOARD);
 
     // Handle the keyboard interrupt
     crate::hal::keyboard::handle_interrupt();
@@ -330,6 +342,7 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
 extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
     // Increment statistics counter
     INTERRUPT_STATS.mouse.fetch_add(1, Ordering::Relaxed);
+    crate::hal::replay::replay_note_interrupt(vector::MOUSE);
 
     // Handle the mouse interrupt
     crate::hal::mouse::handle_interrupt();
@@ -342,6 +355,41 @@ extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFr
     }
 }
 
+/// Dispatch a device IRQ routed through the PIC to its connected ISRs
+fn device_irq(irq: u8) {
+    INTERRUPT_STATS.other_interrupts.fetch_add(1, Ordering::Relaxed);
+    crate::hal::interrupt::hal_dispatch_interrupt(vector::PIC_BASE + irq);
+    unsafe { crate::hal::pic::send_eoi(irq); }
+}
+
+macro_rules! device_irq_handler {
+    ($name:ident, $irq:expr) => {
+        extern "x86-interrupt" fn $name(_stack_frame: InterruptStackFrame) {
+            device_irq($irq);
+        }
+    };
+}
+
+device_irq_handler!(irq3_handler, 3);
+device_irq_handler!(irq4_handler, 4);
+device_irq_handler!(irq5_handler, 5);
+device_irq_handler!(irq6_handler, 6);
+device_irq_handler!(irq7_handler, 7);
+device_irq_handler!(irq8_handler, 8);
+device_irq_handler!(irq9_handler, 9);
+device_irq_handler!(irq10_handler, 10);
+device_irq_handler!(irq11_handler, 11);
+device_irq_handler!(irq13_handler, 13);
+device_irq_handler!(irq14_handler, 14);
+device_irq_handler!(irq15_handler, 15);
+
+/// PIC lines not claimed by the timer, keyboard, cascade or mouse
+const DEVICE_IRQ_HANDLERS: [(u8, extern "x86-interrupt" fn(InterruptStackFrame)); 12] = [
+    (3, irq3_handler), (4, irq4_handler), (5, irq5_handler), (6, irq6_handler),
+    (7, irq7_handler), (8, irq8_handler), (9, irq9_handler), (10, irq10_handler),
+    (11, irq11_handler), (13, irq13_handler), (14, irq14_handler), (15, irq15_handler),
+];
+
 /// IPI STOP handler (vector 0xFC)
 /// Halts this CPU immediately (for shutdown or panic)
 extern "x86-interrupt" fn ipi_stop_handler(_stack_frame: InterruptStackFrame) {
@@ -2,6 +2,7 @@
 //!
 //! This module provides low-level CPU and hardware support for x86_64:
 //!
+//! - Early-boot exception handlers (before GDT/IDT setup)
 //! - GDT (Global Descriptor Table) setup
 //! - IDT (Interrupt Descriptor Table) and interrupt handlers
 //! - Paging (4-level page tables)
@@ -9,6 +10,7 @@
 //! - Context switching
 //! - System call handling (SYSCALL/SYSRET)
 
+pub mod early;
 pub mod gdt;
 pub mod idt;
 pub mod context;
@@ -16,6 +18,7 @@ pub mod io;
 pub mod syscall;
 pub mod percpu;
 pub mod ap_trampoline;
+pub mod multiboot2;
 
 // Re-export key context types for user-mode support
 pub use context::{KTrapFrame, UserContext, ProcessorMode};
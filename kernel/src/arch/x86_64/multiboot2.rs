@@ -0,0 +1,701 @@
+//! Multiboot2 Entry
+//!
+//! An alternate boot path for machines without UEFI: the kernel ELF carries
+//! a Multiboot2 header, so GRUB (or any other Multiboot2 loader) can boot it
+//! directly with `multiboot2 /boot/kernel`. The loader sets up a VBE linear
+//! framebuffer from the header's framebuffer request and enters the 32-bit
+//! stub below in protected mode with paging off.
+//!
+//! ## Startup Sequence:
+//! 1. Stub checks for long mode and builds page tables in `.multiboot2_bss`:
+//!    the first 4GB identity mapped and the kernel at `KERNEL_VIRTUAL_BASE`,
+//!    the same layout the UEFI loader hands over
+//! 2. Stub enables PAE, long mode and paging and far jumps to 64-bit code
+//! 3. `multiboot2_main` translates the Multiboot2 information into a
+//!    version 2 boot info handoff (E820 map as UEFI descriptors, framebuffer,
+//!    RSDP, command line, first module as initrd)
+//! 4. `kernel_main` starts exactly as it would from the UEFI loader
+//!
+//! Everything here runs before .bss is zeroed, so the page tables, stack,
+//! memory map and RSDP copy live outside it.
+
+use core::arch::global_asm;
+use core::mem::size_of;
+
+use crate::bootinfo::{
+    tag, BootInfoHeader, BootTag, FramebufferTag, InitrdTag, KernelTag, MemoryMapTag, RsdpTag,
+    BOOT_INFO_V2_MAGIC,
+};
+use crate::serial::early_puts;
+
+/// Kernel physical load address (from linker.ld); GRUB loads the image
+/// where it was linked, it is never relocated on this path
+pub const KERNEL_PHYSICAL_BASE: u64 = 0x100_0000;
+
+/// Kernel virtual link address (from linker.ld)
+pub const KERNEL_VIRTUAL_BASE: u64 = 0xFFFF_FFFF_8000_0000;
+
+/// Magic in the kernel's Multiboot2 header
+pub const MULTIBOOT2_HEADER_MAGIC: u32 = 0xE852_50D6;
+
+/// Magic the loader passes in EAX
+pub const MULTIBOOT2_BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+/// Framebuffer mode requested from the loader
+const PREFERRED_WIDTH: u32 = 1024;
+const PREFERRED_HEIGHT: u32 = 768;
+
+/// Mapped beyond the image for BSS growth and the early heap (as the UEFI loader does)
+const KERNEL_MAP_HEADROOM: u64 = 0x200_0000;
+
+/// Boot stack size
+const STACK_SIZE: usize = 512 * 1024;
+
+/// Most memory map descriptors produced from the E820 map
+const MAX_MEMORY_DESCRIPTORS: usize = 128;
+
+/// Most modules kept out of the free memory
+const MAX_MODULES: usize = 8;
+
+/// Handoff buffer size
+const HANDOFF_SIZE: usize = 4096;
+
+/// Low memory kept out of the free memory (real-mode IVT, BDA, EBDA, AP trampoline)
+const LOW_MEMORY_RESERVED: u64 = 0x10_0000;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Multiboot2 information tag types
+mod mb2_tag {
+    pub const END: u32 = 0;
+    pub const CMDLINE: u32 = 1;
+    pub const BOOTLOADER_NAME: u32 = 2;
+    pub const MODULE: u32 = 3;
+    pub const BASIC_MEMINFO: u32 = 4;
+    pub const MMAP: u32 = 6;
+    pub const FRAMEBUFFER: u32 = 8;
+    pub const ACPI_OLD: u32 = 14;
+    pub const ACPI_NEW: u32 = 15;
+}
+
+/// E820 range types
+mod e820 {
+    pub const AVAILABLE: u32 = 1;
+    pub const ACPI_RECLAIMABLE: u32 = 3;
+    pub const ACPI_NVS: u32 = 4;
+    pub const BAD: u32 = 5;
+}
+
+/// EFI_MEMORY_TYPE values the kernel's memory map parser understands
+mod efi_type {
+    pub const RESERVED: u32 = 0;
+    pub const LOADER_DATA: u32 = 2;
+    pub const CONVENTIONAL: u32 = 7;
+    pub const UNUSABLE: u32 = 8;
+    pub const ACPI_RECLAIM: u32 = 9;
+    pub const ACPI_NVS: u32 = 10;
+}
+
+/// EFI_MEMORY_WB
+const EFI_MEMORY_WB: u64 = 0x8;
+
+/// Multiboot2 framebuffer type for direct RGB
+const MB2_FRAMEBUFFER_RGB: u8 = 1;
+
+global_asm!(
+    r#"
+    .set MB2_VIRT_OFFSET, {virt_offset}
+    .set MB2_HEADER_MAGIC, {header_magic}
+
+    # Multiboot2 header; must be 8-byte aligned in the first 32KB of the file
+    .section .multiboot2, "a"
+    .balign 8
+multiboot2_header:
+    .long MB2_HEADER_MAGIC
+    .long 0
+    .long multiboot2_header_end - multiboot2_header
+    .long 0x100000000 - (MB2_HEADER_MAGIC + (multiboot2_header_end - multiboot2_header))
+
+    # Information request (optional): cmdline, modules, mmap, framebuffer, ACPI old/new
+    .balign 8
+    .short 1, 1
+    .long 8 + 4 * 6
+    .long 1, 3, 6, 8, 14, 15
+
+    # Entry address (physical) of the 32-bit stub
+    .balign 8
+    .short 3, 0
+    .long 12
+    .long multiboot2_entry32 - MB2_VIRT_OFFSET
+
+    # Preferred framebuffer mode (optional), set up by the loader through VBE
+    .balign 8
+    .short 5, 1
+    .long 20
+    .long {fb_width}, {fb_height}, 32
+
+    .balign 8
+    .short 0, 0
+    .long 8
+multiboot2_header_end:
+
+    .section .text.multiboot2, "ax"
+    .code32
+multiboot2_entry32:
+    cli
+    cld
+    mov edi, eax
+    mov esi, ebx
+    mov esp, offset multiboot2_stack_top - MB2_VIRT_OFFSET
+
+    # Long mode supported? (CPUID 0x80000001 EDX.LM)
+    mov eax, 0x80000000
+    cpuid
+    cmp eax, 0x80000001
+    jb multiboot2_no_long_mode
+    mov eax, 0x80000001
+    cpuid
+    test edx, 1 << 29
+    jz multiboot2_no_long_mode
+
+    # Identity map the first 4GB with 2MB pages
+    mov ebx, offset multiboot2_pd_identity - MB2_VIRT_OFFSET
+    xor ecx, ecx
+multiboot2_identity_loop:
+    mov eax, ecx
+    shl eax, 21
+    or eax, 0x83
+    mov dword ptr [ebx + ecx * 8], eax
+    inc ecx
+    cmp ecx, 2048
+    jb multiboot2_identity_loop
+
+    mov ebx, offset multiboot2_pdpt_low - MB2_VIRT_OFFSET
+    mov eax, offset multiboot2_pd_identity - MB2_VIRT_OFFSET
+    or eax, 3
+    mov dword ptr [ebx], eax
+    add eax, 4096
+    mov dword ptr [ebx + 8], eax
+    add eax, 4096
+    mov dword ptr [ebx + 16], eax
+    add eax, 4096
+    mov dword ptr [ebx + 24], eax
+
+    # Map the kernel image plus headroom at KERNEL_VIRTUAL_BASE (PML4 511, PDPT 510)
+    mov ecx, offset __kernel_end - MB2_VIRT_OFFSET
+    sub ecx, {phys_base}
+    add ecx, {headroom} + 0x1FFFFF
+    shr ecx, 21
+    mov ebx, offset multiboot2_pd_kernel - MB2_VIRT_OFFSET
+    mov eax, {phys_base} + 0x83
+    xor edx, edx
+multiboot2_kernel_loop:
+    mov dword ptr [ebx + edx * 8], eax
+    add eax, 0x200000
+    inc edx
+    cmp edx, ecx
+    jb multiboot2_kernel_loop
+
+    mov eax, offset multiboot2_pd_kernel - MB2_VIRT_OFFSET
+    or eax, 3
+    mov dword ptr [multiboot2_pdpt_kernel - MB2_VIRT_OFFSET + 510 * 8], eax
+
+    mov ebx, offset multiboot2_pml4 - MB2_VIRT_OFFSET
+    mov eax, offset multiboot2_pdpt_low - MB2_VIRT_OFFSET
+    or eax, 3
+    mov dword ptr [ebx], eax
+    mov eax, offset multiboot2_pdpt_kernel - MB2_VIRT_OFFSET
+    or eax, 3
+    mov dword ptr [ebx + 511 * 8], eax
+
+    # PAE, OSFXSR and OSXMMEXCPT (UEFI firmware leaves SSE enabled too)
+    lgdt [multiboot2_gdt_ptr - MB2_VIRT_OFFSET]
+    mov eax, cr4
+    or eax, 0x620
+    mov cr4, eax
+    mov cr3, ebx
+
+    # EFER.LME
+    mov ecx, 0xC0000080
+    rdmsr
+    or eax, 0x100
+    wrmsr
+
+    # CR0.PG and CR0.MP, clear CR0.EM
+    mov eax, cr0
+    and eax, ~0x4
+    or eax, 0x80000002
+    mov cr0, eax
+
+    push 0x08
+    mov eax, offset multiboot2_entry64 - MB2_VIRT_OFFSET
+    push eax
+    retf
+
+multiboot2_no_long_mode:
+    mov esi, offset multiboot2_no_long_mode_msg - MB2_VIRT_OFFSET
+    mov edi, 0xB8000
+multiboot2_msg_loop:
+    lodsb
+    test al, al
+    jz multiboot2_halt32
+    mov ah, 0x4F
+    stosw
+    jmp multiboot2_msg_loop
+multiboot2_halt32:
+    hlt
+    jmp multiboot2_halt32
+
+    .code64
+multiboot2_entry64:
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    xor eax, eax
+    mov fs, ax
+    mov gs, ax
+
+    # Upper halves are undefined after the mode switch
+    mov esp, esp
+    mov edi, edi
+    mov esi, esi
+    xor ebp, ebp
+    call {main}
+multiboot2_halt64:
+    hlt
+    jmp multiboot2_halt64
+
+    .balign 8
+multiboot2_gdt:
+    .quad 0
+    .quad 0x00AF9B000000FFFF
+    .quad 0x00CF93000000FFFF
+multiboot2_gdt_end:
+multiboot2_gdt_ptr:
+    .short multiboot2_gdt_end - multiboot2_gdt - 1
+    .long multiboot2_gdt - MB2_VIRT_OFFSET
+
+multiboot2_no_long_mode_msg:
+    .asciz "Nostalgia OS requires a 64-bit (x86_64) processor"
+
+    # Not in .bss: kernel_main zeroes that while still using these
+    .section .multiboot2_bss, "aw", @nobits
+    .balign 4096
+multiboot2_pml4:
+    .skip 4096
+multiboot2_pdpt_low:
+    .skip 4096
+multiboot2_pdpt_kernel:
+    .skip 4096
+multiboot2_pd_identity:
+    .skip 4096 * 4
+multiboot2_pd_kernel:
+    .skip 4096
+multiboot2_stack:
+    .skip {stack_size}
+multiboot2_stack_top:
+    .text
+"#,
+    virt_offset = const KERNEL_VIRTUAL_BASE - KERNEL_PHYSICAL_BASE,
+    header_magic = const MULTIBOOT2_HEADER_MAGIC,
+    fb_width = const PREFERRED_WIDTH,
+    fb_height = const PREFERRED_HEIGHT,
+    phys_base = const KERNEL_PHYSICAL_BASE,
+    headroom = const KERNEL_MAP_HEADROOM,
+    stack_size = const STACK_SIZE,
+    main = sym multiboot2_main,
+);
+
+extern "C" {
+    static __kernel_end: u8;
+}
+
+/// EFI_MEMORY_DESCRIPTOR, as the UEFI loader's memory map holds them
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct EfiMemoryDescriptor {
+    memory_type: u32,
+    padding: u32,
+    physical_start: u64,
+    virtual_start: u64,
+    number_of_pages: u64,
+    attribute: u64,
+}
+
+impl EfiMemoryDescriptor {
+    const EMPTY: Self = Self {
+        memory_type: 0,
+        padding: 0,
+        physical_start: 0,
+        virtual_start: 0,
+        number_of_pages: 0,
+        attribute: 0,
+    };
+}
+
+#[repr(C, align(8))]
+struct Handoff([u8; HANDOFF_SIZE]);
+
+// In .data rather than .bss: the memory map and RSDP copy are read long
+// after kernel_main has zeroed .bss.
+#[link_section = ".data.multiboot2"]
+static mut MEMORY_MAP: [EfiMemoryDescriptor; MAX_MEMORY_DESCRIPTORS] =
+    [EfiMemoryDescriptor::EMPTY; MAX_MEMORY_DESCRIPTORS];
+#[link_section = ".data.multiboot2"]
+static mut RSDP_COPY: [u8; 64] = [0; 64];
+#[link_section = ".data.multiboot2"]
+static mut HANDOFF: Handoff = Handoff([0; HANDOFF_SIZE]);
+
+/// Physical address of a kernel static (the stub runs at either alias)
+fn physical(addr: u64) -> u64 {
+    if addr >= KERNEL_VIRTUAL_BASE {
+        addr - KERNEL_VIRTUAL_BASE + KERNEL_PHYSICAL_BASE
+    } else {
+        addr
+    }
+}
+
+unsafe fn read<T: Copy>(addr: u64) -> T {
+    core::ptr::read_unaligned(addr as *const T)
+}
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// Appends version 2 tags to `HANDOFF`
+struct HandoffWriter {
+    len: usize,
+    tag_count: u32,
+}
+
+impl HandoffWriter {
+    fn new() -> Self {
+        Self { len: size_of::<BootInfoHeader>(), tag_count: 0 }
+    }
+
+    fn buffer() -> &'static mut [u8; HANDOFF_SIZE] {
+        unsafe { &mut (*core::ptr::addr_of_mut!(HANDOFF)).0 }
+    }
+
+    fn add_parts(&mut self, tag_type: u32, parts: &[&[u8]]) {
+        let payload: usize = parts.iter().map(|p| p.len()).sum();
+        let size = size_of::<BootTag>() + payload;
+        if self.len + size.next_multiple_of(8) + size_of::<BootTag>() > HANDOFF_SIZE {
+            early_puts(b"Multiboot2: boot info tag dropped\n");
+            return;
+        }
+        let buf = Self::buffer();
+        let header = BootTag { tag_type, size: size as u32 };
+        let mut at = self.len;
+        for part in core::iter::once(as_bytes(&header)).chain(parts.iter().copied()) {
+            buf[at..at + part.len()].copy_from_slice(part);
+            at += part.len();
+        }
+        let end = self.len + size.next_multiple_of(8);
+        buf[at..end].fill(0);
+        self.len = end;
+        if tag_type != tag::END {
+            self.tag_count += 1;
+        }
+    }
+
+    fn add<T: Copy>(&mut self, tag_type: u32, payload: &T) {
+        self.add_parts(tag_type, &[as_bytes(payload)]);
+    }
+
+    fn finish(mut self) -> u64 {
+        self.add_parts(tag::END, &[]);
+        let buf = Self::buffer();
+        let header_size = size_of::<BootInfoHeader>();
+        let checksum = buf[header_size..self.len]
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .fold(0u32, |sum, word| sum.wrapping_add(u32::from_le_bytes(*word)));
+        let header = BootInfoHeader {
+            magic: BOOT_INFO_V2_MAGIC,
+            version: 2,
+            header_size: header_size as u32,
+            total_size: self.len as u32,
+            tag_count: self.tag_count,
+            checksum,
+            reserved: 0,
+        };
+        buf[..header_size].copy_from_slice(as_bytes(&header));
+        physical(buf.as_ptr() as u64)
+    }
+}
+
+/// Builds UEFI-style descriptors from E820 ranges
+struct MemoryMapBuilder {
+    count: usize,
+    dropped: usize,
+    /// Ranges handed to the kernel as loader data, page aligned
+    reserved: [(u64, u64); MAX_MODULES + 3],
+    reserved_count: usize,
+}
+
+impl MemoryMapBuilder {
+    fn new() -> Self {
+        Self { count: 0, dropped: 0, reserved: [(0, 0); MAX_MODULES + 3], reserved_count: 0 }
+    }
+
+    /// Keep [start, end) out of the conventional memory
+    fn reserve(&mut self, start: u64, end: u64) {
+        if self.reserved_count < self.reserved.len() && end > start {
+            self.reserved[self.reserved_count] =
+                (start & !(PAGE_SIZE - 1), end.next_multiple_of(PAGE_SIZE));
+            self.reserved_count += 1;
+        }
+    }
+
+    fn push(&mut self, memory_type: u32, start: u64, end: u64) {
+        if end <= start {
+            return;
+        }
+        if self.count == MAX_MEMORY_DESCRIPTORS {
+            self.dropped += 1;
+            return;
+        }
+        let attribute = match memory_type {
+            efi_type::RESERVED | efi_type::UNUSABLE => 0,
+            _ => EFI_MEMORY_WB,
+        };
+        unsafe {
+            (*core::ptr::addr_of_mut!(MEMORY_MAP))[self.count] = EfiMemoryDescriptor {
+                memory_type,
+                padding: 0,
+                physical_start: start,
+                virtual_start: 0,
+                number_of_pages: (end - start) / PAGE_SIZE,
+                attribute,
+            };
+        }
+        self.count += 1;
+    }
+
+    /// Add an E820 range, splitting usable memory around the reserved ranges
+    fn add_range(&mut self, base: u64, length: u64, e820_type: u32) {
+        let memory_type = match e820_type {
+            e820::AVAILABLE => efi_type::CONVENTIONAL,
+            e820::ACPI_RECLAIMABLE => efi_type::ACPI_RECLAIM,
+            e820::ACPI_NVS => efi_type::ACPI_NVS,
+            e820::BAD => efi_type::UNUSABLE,
+            _ => efi_type::RESERVED,
+        };
+        let limit = base.saturating_add(length);
+        if memory_type != efi_type::CONVENTIONAL {
+            self.push(memory_type, base & !(PAGE_SIZE - 1), limit.next_multiple_of(PAGE_SIZE));
+            return;
+        }
+
+        // Usable memory shrinks to whole pages
+        let mut start = base.next_multiple_of(PAGE_SIZE);
+        let end = limit & !(PAGE_SIZE - 1);
+        let reserved = self.reserved;
+        for &(reserved_start, reserved_end) in &reserved[..self.reserved_count] {
+            if start >= end {
+                break;
+            }
+            if reserved_end <= start || reserved_start >= end {
+                continue;
+            }
+            self.push(efi_type::CONVENTIONAL, start, reserved_start);
+            self.push(efi_type::LOADER_DATA, reserved_start.max(start), reserved_end.min(end));
+            start = reserved_end.min(end);
+        }
+        self.push(efi_type::CONVENTIONAL, start, end);
+    }
+}
+
+/// What the Multiboot2 information tags describe
+#[derive(Default)]
+struct Multiboot2Info {
+    cmdline: u64,
+    cmdline_len: usize,
+    modules: [(u64, u64); MAX_MODULES],
+    module_count: usize,
+    mmap: u64,
+    mmap_entries: usize,
+    mmap_entry_size: usize,
+    basic_meminfo: Option<(u32, u32)>,
+    framebuffer: Option<FramebufferTag>,
+    rsdp: u64,
+    rsdp_len: usize,
+    rsdp_new: bool,
+}
+
+/// Walk the Multiboot2 information structure at `info`
+unsafe fn scan(info: u64, total_size: u64) -> Multiboot2Info {
+    let mut mb = Multiboot2Info::default();
+    let mut offset = 8u64;
+    while offset + 8 <= total_size {
+        let at = info + offset;
+        let tag_type: u32 = read(at);
+        let size: u32 = read(at + 4);
+        if tag_type == mb2_tag::END || size < 8 {
+            break;
+        }
+        let payload = at + 8;
+        let payload_len = size as usize - 8;
+
+        match tag_type {
+            mb2_tag::CMDLINE => {
+                mb.cmdline = payload;
+                mb.cmdline_len = payload_len;
+            }
+            mb2_tag::BOOTLOADER_NAME => {
+                early_puts(b"Multiboot2: booted by ");
+                let name = core::slice::from_raw_parts(payload as *const u8, payload_len);
+                let len = name.iter().position(|&b| b == 0).unwrap_or(payload_len);
+                early_puts(&name[..len]);
+                early_puts(b"\n");
+            }
+            mb2_tag::MODULE if payload_len >= 8 && mb.module_count < MAX_MODULES => {
+                let start: u32 = read(payload);
+                let end: u32 = read(payload + 4);
+                mb.modules[mb.module_count] = (start as u64, end as u64);
+                mb.module_count += 1;
+            }
+            mb2_tag::BASIC_MEMINFO if payload_len >= 8 => {
+                mb.basic_meminfo = Some((read(payload), read(payload + 4)));
+            }
+            mb2_tag::MMAP if payload_len >= 8 => {
+                let entry_size: u32 = read(payload);
+                if entry_size >= 24 {
+                    mb.mmap = payload + 8;
+                    mb.mmap_entry_size = entry_size as usize;
+                    mb.mmap_entries = (payload_len - 8) / entry_size as usize;
+                }
+            }
+            mb2_tag::FRAMEBUFFER if payload_len >= 30 => {
+                let addr: u64 = read(payload);
+                let pitch: u32 = read(payload + 8);
+                let width: u32 = read(payload + 12);
+                let height: u32 = read(payload + 16);
+                let bpp: u8 = read(payload + 20);
+                let fb_type: u8 = read(payload + 21);
+                // The console writes 32-bit pixels; EGA text and palette modes are no use
+                if fb_type == MB2_FRAMEBUFFER_RGB && bpp == 32 {
+                    let red_position: u8 = read(payload + 24);
+                    let blue_position: u8 = read(payload + 28);
+                    // EFI_GRAPHICS_PIXEL_FORMAT: RGB, BGR or bit mask
+                    let pixel_format = match (red_position, blue_position) {
+                        (0, 16) => 0,
+                        (16, 0) => 1,
+                        _ => 2,
+                    };
+                    mb.framebuffer = Some(FramebufferTag {
+                        addr,
+                        size: pitch as u64 * height as u64,
+                        width,
+                        height,
+                        stride: pitch,
+                        bpp: 32,
+                        pixel_format,
+                        reserved: 0,
+                    });
+                }
+            }
+            // Both tags carry a copy of the RSDP; prefer the ACPI 2.0 one
+            mb2_tag::ACPI_OLD if !mb.rsdp_new => {
+                mb.rsdp = payload;
+                mb.rsdp_len = payload_len;
+            }
+            mb2_tag::ACPI_NEW => {
+                mb.rsdp = payload;
+                mb.rsdp_len = payload_len;
+                mb.rsdp_new = true;
+            }
+            _ => {}
+        }
+        offset += (size as u64).next_multiple_of(8);
+    }
+    mb
+}
+
+/// Translate the Multiboot2 information at `info` into a boot info handoff
+unsafe fn translate(info: u64) -> u64 {
+    let total_size = read::<u32>(info) as u64;
+    let mb = scan(info, total_size);
+
+    let kernel_end = physical(&raw const __kernel_end as u64);
+
+    // Memory map: E820 ranges, with the kernel, the information structure
+    // and modules marked as loader data like the UEFI loader's allocations
+    let mut map = MemoryMapBuilder::new();
+    map.reserve(0, LOW_MEMORY_RESERVED);
+    map.reserve(KERNEL_PHYSICAL_BASE, kernel_end);
+    map.reserve(info, info + total_size);
+    for &(start, end) in &mb.modules[..mb.module_count] {
+        map.reserve(start, end);
+    }
+    let count = map.reserved_count;
+    map.reserved[..count].sort_unstable_by_key(|r| r.0);
+
+    if mb.mmap_entries > 0 {
+        for i in 0..mb.mmap_entries {
+            let entry = mb.mmap + (i * mb.mmap_entry_size) as u64;
+            map.add_range(read(entry), read(entry + 8), read(entry + 16));
+        }
+    } else if let Some((lower_kb, upper_kb)) = mb.basic_meminfo {
+        early_puts(b"Multiboot2: no memory map, using basic meminfo\n");
+        map.add_range(0, lower_kb as u64 * 1024, e820::AVAILABLE);
+        map.add_range(0x10_0000, upper_kb as u64 * 1024, e820::AVAILABLE);
+    } else {
+        early_puts(b"Multiboot2: no memory information\n");
+    }
+    if map.dropped > 0 {
+        early_puts(b"Multiboot2: memory map truncated\n");
+    }
+
+    let mut handoff = HandoffWriter::new();
+    let cr3: u64;
+    core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    handoff.add(tag::KERNEL, &KernelTag {
+        physical_base: KERNEL_PHYSICAL_BASE,
+        virtual_base: KERNEL_VIRTUAL_BASE,
+        size: kernel_end - KERNEL_PHYSICAL_BASE,
+        pml4_physical_addr: cr3 & 0x000F_FFFF_FFFF_F000,
+    });
+    if let Some(framebuffer) = mb.framebuffer {
+        handoff.add(tag::FRAMEBUFFER, &framebuffer);
+    }
+    if mb.rsdp_len > 0 {
+        let copy = &mut *core::ptr::addr_of_mut!(RSDP_COPY);
+        let len = mb.rsdp_len.min(copy.len());
+        core::ptr::copy_nonoverlapping(mb.rsdp as *const u8, copy.as_mut_ptr(), len);
+        handoff.add(tag::RSDP, &RsdpTag { addr: physical(copy.as_ptr() as u64) });
+    }
+    if mb.cmdline_len > 0 {
+        let cmdline = core::slice::from_raw_parts(mb.cmdline as *const u8, mb.cmdline_len);
+        handoff.add_parts(tag::CMDLINE, &[cmdline]);
+    }
+    if mb.module_count > 0 {
+        let (start, end) = mb.modules[0];
+        handoff.add(tag::INITRD, &InitrdTag { addr: start, size: end - start });
+    }
+    handoff.add(tag::MEMMAP, &MemoryMapTag {
+        addr: physical(core::ptr::addr_of!(MEMORY_MAP) as u64),
+        entry_count: map.count as u64,
+        entry_size: size_of::<EfiMemoryDescriptor>() as u64,
+        descriptor_version: 1,
+        reserved: 0,
+    });
+    handoff.finish()
+}
+
+/// Long-mode entry from the stub with the loader's EAX and EBX
+unsafe extern "C" fn multiboot2_main(magic: u32, info: u32) -> ! {
+    early_puts(b"Multiboot2 entry\n");
+    if magic != MULTIBOOT2_BOOTLOADER_MAGIC {
+        early_puts(b"FATAL: not started by a Multiboot2 loader\n");
+        loop {
+            super::halt();
+        }
+    }
+    let boot_info = translate(info as u64);
+    crate::kernel_main(boot_info as *const u8)
+}
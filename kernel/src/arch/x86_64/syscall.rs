@@ -257,6 +257,9 @@ pub enum SyscallNumber {
     // Power management
     NtSetSystemPowerState = 220,
     NtInitiatePowerAction = 221,
+
+    // Hard errors
+    NtRaiseHardError = 230,
 }
 
 /// Syscall handler function type
@@ -266,6 +269,295 @@ pub type SyscallHandler = fn(usize, usize, usize, usize, usize, usize) -> isize;
 /// Syscall dispatch table
 static mut SYSCALL_TABLE: [Option<SyscallHandler>; MAX_SYSCALLS] = [None; MAX_SYSCALLS];
 
+// ============================================================================
+// User Pointer Arguments
+// ============================================================================
+
+/// How much user memory a system service touches through a pointer argument
+#[derive(Clone, Copy)]
+enum UserExtent {
+    /// A structure of fixed size
+    Bytes(usize),
+    /// A buffer whose byte length is another argument (1-based)
+    LengthArg(u8),
+    /// An array whose element count is another argument (1-based)
+    CountArg(u8, usize),
+}
+
+impl UserExtent {
+    /// Length in bytes for a call's arguments, or None if it overflows
+    fn length(self, args: &[usize; 6]) -> Option<usize> {
+        match self {
+            UserExtent::Bytes(size) => Some(size),
+            UserExtent::LengthArg(arg) => Some(args[arg as usize - 1]),
+            UserExtent::CountArg(arg, size) => args[arg as usize - 1].checked_mul(size),
+        }
+    }
+}
+
+// Sizes of the structures passed by pointer
+const HANDLE: UserExtent = UserExtent::Bytes(core::mem::size_of::<usize>());
+const SIZE_T: UserExtent = UserExtent::Bytes(core::mem::size_of::<usize>());
+const ULONG: UserExtent = UserExtent::Bytes(core::mem::size_of::<u32>());
+const LARGE_INTEGER: UserExtent = UserExtent::Bytes(core::mem::size_of::<i64>());
+const LUID: UserExtent = UserExtent::Bytes(core::mem::size_of::<u64>());
+const CLIENT_ID: UserExtent = UserExtent::Bytes(core::mem::size_of::<ClientIdForProcess>());
+const OBJECT_ATTRIBUTES: UserExtent = UserExtent::Bytes(core::mem::size_of::<ObjectAttributes>());
+const IO_STATUS_BLOCK: UserExtent = UserExtent::Bytes(core::mem::size_of::<crate::io::IoStatusBlock>());
+const CONTEXT: UserExtent = UserExtent::Bytes(core::mem::size_of::<crate::ke::exception::Context>());
+const EXCEPTION_RECORD: UserExtent = UserExtent::Bytes(core::mem::size_of::<crate::ke::exception::ExceptionRecord>());
+const WAIT_STATE_CHANGE: UserExtent = UserExtent::Bytes(core::mem::size_of::<DbgUiWaitStateChange>());
+const UNICODE_STRING: UserExtent = UserExtent::Bytes(16);
+const SECURITY_QOS: UserExtent = UserExtent::Bytes(12);
+const GENERIC_MAPPING: UserExtent = UserExtent::Bytes(16);
+const PORT_VIEW: UserExtent = UserExtent::Bytes(48);
+const REMOTE_PORT_VIEW: UserExtent = UserExtent::Bytes(24);
+/// LPC message: 32-byte header and 224 data bytes
+const PORT_MESSAGE: UserExtent = UserExtent::Bytes(256);
+/// NUL-terminated name, read up to `read_user_path`'s limit
+const USER_PATH: UserExtent = UserExtent::Bytes(260);
+/// NUL-terminated file name pattern (NtQueryDirectoryFile)
+const NAME_PATTERN: UserExtent = UserExtent::Bytes(256);
+/// NUL-terminated symbolic link target
+const LINK_TARGET: UserExtent = UserExtent::Bytes(MAX_SYMLINK_TARGET);
+/// Buffers whose length is not among the six arguments (security
+/// descriptors, TOKEN_* lists, PRIVILEGE_SET, I/O control buffers): the
+/// header the service reads first
+const UNSIZED: UserExtent = UserExtent::Bytes(8);
+/// Addresses the service never reads through (code, regions to query or
+/// unmap)
+const ADDRESS: UserExtent = UserExtent::Bytes(1);
+
+/// Arguments (1-based) of each system service that are user-mode pointers,
+/// with the extent of memory the service touches through each
+///
+/// The dispatcher rejects a call with STATUS_ACCESS_VIOLATION when any of
+/// these ranges wraps or ends past `MM_USER_PROBE_ADDRESS`, before the
+/// handler runs, so no handler can be made to read or write system space
+/// for its caller. NULL passes; handlers still check the pointers they
+/// require. Handles, timeouts passed by value and context values are not
+/// listed, nor are section and completion port handles (they are object
+/// addresses).
+///
+/// A new system service that takes pointers must be listed here.
+const SYSCALL_POINTER_ARGS: &[(SyscallNumber, &[(u8, UserExtent)])] = &[
+    // Process/thread
+    (SyscallNumber::NtCreateThread, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES), (5, CLIENT_ID), (6, UserExtent::Bytes(16))]),
+    (SyscallNumber::NtDelayExecution, &[(2, LARGE_INTEGER)]),
+    (SyscallNumber::NtDebugPrint, &[(1, UserExtent::LengthArg(2))]),
+    (SyscallNumber::NtOpenProcess, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES), (4, CLIENT_ID)]),
+    (SyscallNumber::NtQueryInformationProcess, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtSetInformationProcess, &[(3, UserExtent::LengthArg(4))]),
+    (SyscallNumber::NtOpenThread, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES), (4, CLIENT_ID)]),
+    (SyscallNumber::NtQueryInformationThread, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtSetInformationThread, &[(3, UserExtent::LengthArg(4))]),
+    (SyscallNumber::NtSuspendThread, &[(2, ULONG)]),
+    (SyscallNumber::NtResumeThread, &[(2, ULONG)]),
+    (SyscallNumber::NtAlertResumeThread, &[(2, ULONG)]),
+    (SyscallNumber::NtGetContextThread, &[(2, CONTEXT)]),
+    (SyscallNumber::NtSetContextThread, &[(2, CONTEXT)]),
+    (SyscallNumber::NtQueueApcThread, &[(2, ADDRESS)]),
+    (SyscallNumber::NtRaiseException, &[(1, EXCEPTION_RECORD), (2, CONTEXT)]),
+    (SyscallNumber::NtContinue, &[(1, CONTEXT)]),
+    (SyscallNumber::NtCreateProcess, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtCreateProcessEx, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+
+    // Files
+    (SyscallNumber::NtCreateFile, &[(1, HANDLE), (3, USER_PATH), (4, IO_STATUS_BLOCK)]),
+    (SyscallNumber::NtOpenFile, &[(1, HANDLE), (3, USER_PATH), (4, IO_STATUS_BLOCK)]),
+    (SyscallNumber::NtReadFile, &[(2, UserExtent::LengthArg(3)), (4, SIZE_T)]),
+    (SyscallNumber::NtWriteFile, &[(2, UserExtent::LengthArg(3)), (4, SIZE_T)]),
+    (SyscallNumber::NtQueryInformationFile, &[(2, IO_STATUS_BLOCK), (3, UserExtent::LengthArg(4))]),
+    (SyscallNumber::NtSetInformationFile, &[(2, IO_STATUS_BLOCK), (3, UserExtent::LengthArg(4))]),
+    (SyscallNumber::NtDeleteFile, &[(1, USER_PATH)]),
+    (SyscallNumber::NtQueryDirectoryFile, &[(3, UserExtent::LengthArg(4)), (6, NAME_PATTERN)]),
+    (SyscallNumber::NtLockFile, &[(3, LARGE_INTEGER), (4, LARGE_INTEGER)]),
+    (SyscallNumber::NtDeviceIoControlFile, &[(3, IO_STATUS_BLOCK), (5, UNSIZED), (6, UNSIZED)]),
+    (SyscallNumber::NtFsControlFile, &[(3, IO_STATUS_BLOCK), (5, UNSIZED), (6, UNSIZED)]),
+    (SyscallNumber::NtFlushBuffersFile, &[(2, IO_STATUS_BLOCK)]),
+    (SyscallNumber::NtCancelIoFile, &[(2, IO_STATUS_BLOCK)]),
+
+    // Synchronization
+    (SyscallNumber::NtWaitForSingleObject, &[(3, LARGE_INTEGER)]),
+    (SyscallNumber::NtWaitForMultipleObjects, &[(2, UserExtent::CountArg(1, core::mem::size_of::<usize>())), (5, LARGE_INTEGER)]),
+    (SyscallNumber::NtSignalAndWaitForSingleObject, &[(4, LARGE_INTEGER)]),
+    (SyscallNumber::NtCreateEvent, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtSetEvent, &[(2, ULONG)]),
+    (SyscallNumber::NtResetEvent, &[(2, ULONG)]),
+    (SyscallNumber::NtClearEvent, &[(2, ULONG)]),
+    (SyscallNumber::NtPulseEvent, &[(2, ULONG)]),
+    (SyscallNumber::NtQueryEvent, &[(3, UserExtent::LengthArg(4)), (5, ULONG)]),
+    (SyscallNumber::NtCreateSemaphore, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtReleaseSemaphore, &[(3, ULONG)]),
+    (SyscallNumber::NtQuerySemaphore, &[(3, UserExtent::LengthArg(4)), (5, ULONG)]),
+    (SyscallNumber::NtCreateMutant, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtReleaseMutant, &[(2, ULONG)]),
+    (SyscallNumber::NtQueryMutant, &[(3, UserExtent::LengthArg(4)), (5, ULONG)]),
+    (SyscallNumber::NtCreateTimer, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtOpenTimer, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtSetTimer, &[(2, LARGE_INTEGER), (3, ADDRESS)]),
+    (SyscallNumber::NtCancelTimer, &[(2, ULONG)]),
+    (SyscallNumber::NtQueryTimer, &[(3, UserExtent::LengthArg(4)), (5, ULONG)]),
+
+    // Memory and sections
+    (SyscallNumber::NtAllocateVirtualMemory, &[(2, SIZE_T), (4, SIZE_T)]),
+    (SyscallNumber::NtFreeVirtualMemory, &[(2, SIZE_T), (3, SIZE_T)]),
+    (SyscallNumber::NtProtectVirtualMemory, &[(2, SIZE_T), (3, SIZE_T), (5, ULONG)]),
+    (SyscallNumber::NtQueryVirtualMemory, &[(2, ADDRESS), (4, UserExtent::LengthArg(5)), (6, SIZE_T)]),
+    (SyscallNumber::NtReadVirtualMemory, &[(2, UserExtent::LengthArg(4)), (3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtWriteVirtualMemory, &[(2, UserExtent::LengthArg(4)), (3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtFlushVirtualMemory, &[(2, UserExtent::LengthArg(3)), (4, IO_STATUS_BLOCK)]),
+    (SyscallNumber::NtLockVirtualMemory, &[(2, SIZE_T), (3, SIZE_T)]),
+    (SyscallNumber::NtUnlockVirtualMemory, &[(2, SIZE_T), (3, SIZE_T)]),
+    (SyscallNumber::NtCreateSection, &[(1, HANDLE), (4, LARGE_INTEGER)]),
+    (SyscallNumber::NtOpenSection, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtMapViewOfSection, &[(3, SIZE_T), (6, LARGE_INTEGER)]),
+    (SyscallNumber::NtUnmapViewOfSection, &[(2, ADDRESS)]),
+    (SyscallNumber::NtQuerySection, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtExtendSection, &[(2, LARGE_INTEGER)]),
+
+    // I/O completion
+    (SyscallNumber::NtCreateIoCompletion, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtRemoveIoCompletion, &[(2, SIZE_T), (3, SIZE_T), (4, IO_STATUS_BLOCK), (5, LARGE_INTEGER)]),
+    (SyscallNumber::NtQueryIoCompletion, &[(3, UserExtent::LengthArg(4)), (5, ULONG)]),
+
+    // Registry
+    (SyscallNumber::NtCreateKey, &[(1, HANDLE), (3, USER_PATH), (5, UNICODE_STRING)]),
+    (SyscallNumber::NtOpenKey, &[(1, HANDLE), (3, USER_PATH)]),
+    (SyscallNumber::NtQueryValueKey, &[(2, USER_PATH), (4, UserExtent::LengthArg(5)), (6, SIZE_T)]),
+    (SyscallNumber::NtSetValueKey, &[(2, USER_PATH), (5, UserExtent::LengthArg(6))]),
+    (SyscallNumber::NtDeleteValueKey, &[(2, USER_PATH)]),
+    (SyscallNumber::NtEnumerateKey, &[(4, UserExtent::LengthArg(5)), (6, SIZE_T)]),
+    (SyscallNumber::NtEnumerateValueKey, &[(4, UserExtent::LengthArg(5)), (6, SIZE_T)]),
+    (SyscallNumber::NtQueryKey, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+
+    // LPC
+    (SyscallNumber::NtCreatePort, &[(1, HANDLE), (2, USER_PATH)]),
+    (SyscallNumber::NtConnectPort, &[(1, HANDLE), (2, USER_PATH), (3, SECURITY_QOS), (4, PORT_VIEW), (5, REMOTE_PORT_VIEW)]),
+    (SyscallNumber::NtListenPort, &[(2, PORT_MESSAGE)]),
+    (SyscallNumber::NtAcceptConnectPort, &[(1, HANDLE), (3, PORT_MESSAGE), (5, PORT_VIEW), (6, REMOTE_PORT_VIEW)]),
+    (SyscallNumber::NtRequestPort, &[(2, PORT_MESSAGE)]),
+    (SyscallNumber::NtRequestWaitReplyPort, &[(2, PORT_MESSAGE), (3, PORT_MESSAGE)]),
+    (SyscallNumber::NtReplyPort, &[(2, PORT_MESSAGE)]),
+    (SyscallNumber::NtReplyWaitReceivePort, &[(2, SIZE_T), (3, PORT_MESSAGE), (4, PORT_MESSAGE)]),
+    (SyscallNumber::NtQueryInformationPort, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+
+    // Objects
+    (SyscallNumber::NtDuplicateHandle, &[(4, HANDLE)]),
+    (SyscallNumber::NtQueryObject, &[(3, UserExtent::LengthArg(4)), (5, ULONG)]),
+    (SyscallNumber::NtSetInformationObject, &[(3, UserExtent::LengthArg(4))]),
+    (SyscallNumber::NtCreateSymbolicLinkObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES), (4, LINK_TARGET)]),
+    (SyscallNumber::NtOpenSymbolicLinkObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtQuerySymbolicLinkObject, &[(2, UNICODE_STRING), (3, ULONG)]),
+    (SyscallNumber::NtCreateDirectoryObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtOpenDirectoryObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtQueryDirectoryObject, &[(2, UserExtent::LengthArg(3)), (6, ULONG)]),
+    (SyscallNumber::NtCreateJobObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtOpenJobObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtQueryInformationJobObject, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtSetInformationJobObject, &[(3, UserExtent::LengthArg(4))]),
+
+    // Security
+    (SyscallNumber::NtOpenProcessToken, &[(3, HANDLE)]),
+    (SyscallNumber::NtOpenThreadToken, &[(4, HANDLE)]),
+    (SyscallNumber::NtQueryInformationToken, &[(3, UserExtent::LengthArg(4)), (5, SIZE_T)]),
+    (SyscallNumber::NtSetInformationToken, &[(3, UserExtent::LengthArg(4))]),
+    (SyscallNumber::NtDuplicateToken, &[(3, OBJECT_ATTRIBUTES), (6, HANDLE)]),
+    (SyscallNumber::NtAdjustPrivilegesToken, &[(3, UNSIZED), (5, UserExtent::LengthArg(4)), (6, SIZE_T)]),
+    (SyscallNumber::NtAdjustGroupsToken, &[(3, UNSIZED), (5, UserExtent::LengthArg(4)), (6, SIZE_T)]),
+    (SyscallNumber::NtImpersonateThread, &[(3, SECURITY_QOS)]),
+    (SyscallNumber::NtCreateToken, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES), (5, LUID), (6, LARGE_INTEGER)]),
+    (SyscallNumber::NtFilterToken, &[(3, UNSIZED), (4, UNSIZED), (5, UNSIZED), (6, HANDLE)]),
+    (SyscallNumber::NtAccessCheck, &[(1, UNSIZED), (4, GENERIC_MAPPING), (5, UNSIZED), (6, UserExtent::Bytes(8))]),
+    (SyscallNumber::NtPrivilegeCheck, &[(2, UNSIZED), (3, ULONG)]),
+    (SyscallNumber::NtAccessCheckAndAuditAlarm, &[(1, UNICODE_STRING), (3, UNICODE_STRING), (4, UNICODE_STRING), (5, UNSIZED)]),
+
+    // Debug
+    (SyscallNumber::NtCreateDebugObject, &[(1, HANDLE), (3, OBJECT_ATTRIBUTES)]),
+    (SyscallNumber::NtWaitForDebugEvent, &[(4, WAIT_STATE_CHANGE)]),
+    (SyscallNumber::NtDebugContinue, &[(2, CLIENT_ID)]),
+
+    // System
+    (SyscallNumber::NtQuerySystemInformation, &[(2, UserExtent::LengthArg(3)), (4, ULONG)]),
+    (SyscallNumber::NtSetSystemInformation, &[(2, UserExtent::LengthArg(3))]),
+    (SyscallNumber::NtQuerySystemTime, &[(1, LARGE_INTEGER)]),
+    (SyscallNumber::NtQueryPerformanceCounter, &[(1, LARGE_INTEGER), (2, LARGE_INTEGER)]),
+    (SyscallNumber::NtRaiseHardError, &[(4, UserExtent::CountArg(2, core::mem::size_of::<usize>())), (6, ULONG)]),
+];
+
+/// System services whose arguments are all handles or values
+///
+/// Every registered service is in exactly one of this list and
+/// `SYSCALL_POINTER_ARGS`; the ktest syscall suite fails otherwise, so a
+/// new service cannot be registered without deciding which of its
+/// arguments are pointers.
+const SYSCALL_NO_POINTER_ARGS: &[SyscallNumber] = &[
+    SyscallNumber::NtTerminateProcess,
+    SyscallNumber::NtTerminateThread,
+    SyscallNumber::NtGetCurrentProcessId,
+    SyscallNumber::NtGetCurrentThreadId,
+    SyscallNumber::NtYieldExecution,
+    SyscallNumber::NtClose,
+    SyscallNumber::NtSetIoCompletion,
+    SyscallNumber::NtCloseKey,
+    SyscallNumber::NtDeleteKey,
+    SyscallNumber::NtClosePort,
+    SyscallNumber::NtSuspendProcess,
+    SyscallNumber::NtResumeProcess,
+    SyscallNumber::NtDebugActiveProcess,
+    SyscallNumber::NtRemoveProcessDebug,
+    SyscallNumber::NtAssignProcessToJobObject,
+    SyscallNumber::NtTerminateJobObject,
+    SyscallNumber::NtIsProcessInJob,
+    SyscallNumber::NtTestAlert,
+    SyscallNumber::NtAlertThread,
+    SyscallNumber::NtSetFileCompletionNotificationModes,
+    SyscallNumber::NtSetSystemPowerState,
+    SyscallNumber::NtInitiatePowerAction,
+];
+
+/// Pointer arguments per syscall number, from `SYSCALL_POINTER_ARGS`
+static mut SYSCALL_POINTERS: [&[(u8, UserExtent)]; MAX_SYSCALLS] = [&[]; MAX_SYSCALLS];
+
+fn syscall_pointers(num: usize) -> &'static [(u8, UserExtent)] {
+    if num < MAX_SYSCALLS {
+        unsafe { SYSCALL_POINTERS[num] }
+    } else {
+        &[]
+    }
+}
+
+/// Get the pointer argument mask of a syscall (bit 0 = argument 1)
+pub fn syscall_pointer_mask(num: usize) -> u8 {
+    syscall_pointers(num).iter().fold(0, |mask, &(arg, _)| mask | 1 << (arg - 1))
+}
+
+/// Check whether a syscall is declared to take no pointer arguments
+pub fn syscall_declared_without_pointers(num: usize) -> bool {
+    SYSCALL_NO_POINTER_ARGS.iter().any(|&n| n as usize == num)
+}
+
+/// Check whether a syscall number has a handler
+pub fn syscall_is_registered(num: usize) -> bool {
+    num < MAX_SYSCALLS && unsafe { SYSCALL_TABLE[num].is_some() }
+}
+
+/// Check a call's pointer arguments against the user address range
+///
+/// Each non-NULL pointer is probed over the extent its service touches,
+/// at least one byte, so a buffer that starts in user space and runs
+/// into system space is caught as well as one that starts there.
+/// Returns the (1-based) number of the first argument that fails, or
+/// None when all are NULL or user ranges.
+pub fn syscall_check_pointers(num: usize, args: &[usize; 6]) -> Option<usize> {
+    syscall_pointers(num).iter().find(|&&(arg, extent)| {
+        let address = args[arg as usize - 1];
+        address != 0 && !extent.length(args).is_some_and(|length| {
+            crate::ex::probe::probe_for_read(address, length.max(1), 1).is_ok()
+        })
+    }).map(|&(arg, _)| arg as usize)
+}
+
 // ============================================================================
 // Syscall Tracing
 // ============================================================================
@@ -756,6 +1048,13 @@ unsafe fn init_syscall_table() {
     // Power management syscalls
     register_syscall(SyscallNumber::NtSetSystemPowerState as usize, sys_set_system_power_state);
     register_syscall(SyscallNumber::NtInitiatePowerAction as usize, sys_initiate_power_action);
+
+    // Hard error syscalls
+    register_syscall(SyscallNumber::NtRaiseHardError as usize, sys_raise_hard_error);
+
+    for &(number, pointers) in SYSCALL_POINTER_ARGS {
+        SYSCALL_POINTERS[number as usize] = pointers;
+    }
 }
 
 /// Register a syscall handler
@@ -940,6 +1239,17 @@ extern "C" fn syscall_dispatcher(
     // Get handler from table
     let handler = unsafe { SYSCALL_TABLE[syscall_num] };
 
+    // Reject system-space pointers before the handler sees them
+    let args = [arg1, arg2, arg3, arg4, arg5, arg6];
+    if handler.is_some() && syscall_check_pointers(syscall_num, &args).is_some() {
+        trace_syscall(syscall_num, arg1, arg2, STATUS_ACCESS_VIOLATION);
+        return STATUS_ACCESS_VIOLATION;
+    }
+
+    // Time the call for per-syscall latency statistics
+    let stats = crate::perf::syscall::syscall_stats_enabled();
+    let start = if stats { crate::hal::timer::read_tsc() } else { 0 };
+
     let result = match handler {
         Some(func) => {
             // Call the handler
@@ -951,6 +1261,11 @@ extern "C" fn syscall_dispatcher(
         }
     };
 
+    if stats {
+        let ticks = crate::hal::timer::read_tsc().wrapping_sub(start);
+        crate::perf::syscall::syscall_stats_record(syscall_num, ticks);
+    }
+
     // Record trace entry if tracing is enabled
     trace_syscall(syscall_num, arg1, arg2, result);
 
@@ -1067,6 +1382,19 @@ unsafe fn free_thread_handle(handle: usize) {
     }
 }
 
+/// Thread handle for a kernel caller
+///
+/// Thread IDs outgrow the handle table, so unlike processes this takes
+/// any free slot; release it with `kernel_close_thread_handle`.
+pub unsafe fn kernel_thread_handle(tid: u32) -> Option<usize> {
+    alloc_thread_handle(tid)
+}
+
+/// Release a handle from `kernel_thread_handle`
+pub unsafe fn kernel_close_thread_handle(handle: usize) {
+    free_thread_handle(handle);
+}
+
 /// NtCreateThread - Create a new thread
 ///
 /// Arguments:
@@ -1439,8 +1767,11 @@ fn sys_write_file(
         return STATUS_INVALID_PARAMETER;
     }
 
+    // Standard output/error may be redirected to a file for this process
+    let redirect = if handle == 1 || handle == 2 { stdio_redirect_for_current() } else { None };
+
     // Special case: handle 1 = stdout (serial console)
-    if handle == 1 && length <= 4096 {
+    if handle == 1 && redirect.is_none() && length <= 4096 {
         let slice = unsafe {
             core::slice::from_raw_parts(buffer as *const u8, length)
         };
@@ -1455,7 +1786,7 @@ fn sys_write_file(
     }
 
     // Special case: handle 2 = stderr (serial console)
-    if handle == 2 && length <= 4096 {
+    if handle == 2 && redirect.is_none() && length <= 4096 {
         let slice = unsafe {
             core::slice::from_raw_parts(buffer as *const u8, length)
         };
@@ -1470,7 +1801,7 @@ fn sys_write_file(
     }
 
     // Try to get fs handle
-    let fs_handle = match unsafe { get_fs_handle(handle) } {
+    let fs_handle = match redirect.or_else(|| unsafe { get_fs_handle(handle) }) {
         Some(h) => h,
         None => {
             crate::serial_println!("[SYSCALL] NtWriteFile: invalid handle {}", handle);
@@ -1551,6 +1882,47 @@ unsafe fn free_file_handle(syscall_handle: usize) {
     }
 }
 
+/// Maximum processes with redirected standard output
+const MAX_STDIO_REDIRECTS: usize = 8;
+
+/// Standard output/error redirections: (process ID, fs handle)
+static STDIO_REDIRECTS: Mutex<[(u32, u16); MAX_STDIO_REDIRECTS]> =
+    Mutex::new([(0, 0xFFFF); MAX_STDIO_REDIRECTS]);
+
+/// Redirect a process's standard output and error (handles 1 and 2) to an
+/// open file, or remove the redirection with `None`
+///
+/// # Returns
+/// `false` if the redirection table is full
+pub fn set_stdio_redirect(pid: u32, fs_handle: Option<u16>) -> bool {
+    let mut table = STDIO_REDIRECTS.lock();
+    let slot = table.iter().position(|&(p, h)| p == pid && h != 0xFFFF);
+
+    match (slot, fs_handle) {
+        (Some(i), Some(h)) => table[i].1 = h,
+        (Some(i), None) => table[i] = (0, 0xFFFF),
+        (None, Some(h)) => match table.iter().position(|&(_, h)| h == 0xFFFF) {
+            Some(i) => table[i] = (pid, h),
+            None => return false,
+        },
+        (None, None) => {}
+    }
+    true
+}
+
+/// Redirected standard output of the current process, if any
+fn stdio_redirect_for_current() -> Option<u16> {
+    let process = crate::ps::get_current_process();
+    if process.is_null() {
+        return None;
+    }
+    let pid = unsafe { (*process).unique_process_id };
+    STDIO_REDIRECTS.lock()
+        .iter()
+        .find(|&&(p, h)| p == pid && h != 0xFFFF)
+        .map(|&(_, h)| h)
+}
+
 /// NT file creation disposition values
 pub mod file_disposition {
     pub const FILE_SUPERSEDE: u32 = 0;
@@ -1707,13 +2079,15 @@ fn sys_open_file(
     open_options: usize,
 ) -> isize {
     const STATUS_OBJECT_NAME_NOT_FOUND: isize = 0xC0000034u32 as isize;
+    const STATUS_VERIFY_REQUIRED: isize = 0x80000016u32 as isize;
+    const STATUS_NO_MEDIA_IN_DEVICE: isize = 0xC0000013u32 as isize;
 
     if file_handle_ptr == 0 || object_attributes == 0 {
         return STATUS_INVALID_PARAMETER;
     }
 
     let _ = share_access;
-    let _ = open_options;
+    let options = open_options as u32 & crate::fs::handle_options::VALID_OPTIONS;
 
     let path_result = unsafe { read_user_path(object_attributes, 260) };
 
@@ -1729,7 +2103,7 @@ fn sys_open_file(
 
     crate::serial_println!("[SYSCALL] NtOpenFile(path='{}', access={:#x})", path_str, desired_access);
 
-    match crate::fs::open(path_str, desired_access as u32) {
+    match crate::fs::open_with_options(path_str, desired_access as u32, options) {
         Ok(fs_handle) => {
             let syscall_handle = unsafe { alloc_file_handle(fs_handle) };
             match syscall_handle {
@@ -1749,6 +2123,8 @@ fn sys_open_file(
                 }
             }
         }
+        Err(crate::fs::FsStatus::VerifyRequired) => STATUS_VERIFY_REQUIRED,
+        Err(crate::fs::FsStatus::NoMedia) => STATUS_NO_MEDIA_IN_DEVICE,
         Err(_) => STATUS_OBJECT_NAME_NOT_FOUND,
     }
 }
@@ -3576,6 +3952,8 @@ struct NamedTimerEntry {
     name: [u8; MAX_TIMER_NAME_LEN],
     /// Name length
     name_len: usize,
+    /// Session whose namespace holds the name
+    session_id: u32,
     /// Handle to the timer in sync object pool
     handle: usize,
     /// Is this entry in use
@@ -3587,6 +3965,7 @@ impl NamedTimerEntry {
         Self {
             name: [0; MAX_TIMER_NAME_LEN],
             name_len: 0,
+            session_id: 0,
             handle: 0,
             in_use: false,
         }
@@ -3600,13 +3979,17 @@ static mut NAMED_TIMER_TABLE: [NamedTimerEntry; MAX_NAMED_TIMERS] = {
 };
 
 /// Register a named timer
+///
+/// The name is resolved against the caller's session (see `Global\`).
 unsafe fn register_named_timer(name: &[u8], handle: usize) -> bool {
+    let (session_id, name) = crate::ob::ob_resolve_session_name(name);
     for entry in NAMED_TIMER_TABLE.iter_mut() {
         if !entry.in_use {
             let len = name.len().min(MAX_TIMER_NAME_LEN - 1);
             entry.name[..len].copy_from_slice(&name[..len]);
             entry.name[len] = 0;
             entry.name_len = len;
+            entry.session_id = session_id;
             entry.handle = handle;
             entry.in_use = true;
             return true;
@@ -3615,10 +3998,11 @@ unsafe fn register_named_timer(name: &[u8], handle: usize) -> bool {
     false
 }
 
-/// Find a named timer by name
+/// Find a named timer by name in the caller's session
 unsafe fn find_named_timer(name: &[u8]) -> Option<usize> {
+    let (session_id, name) = crate::ob::ob_resolve_session_name(name);
     for entry in NAMED_TIMER_TABLE.iter() {
-        if entry.in_use && entry.name_len == name.len()
+        if entry.in_use && entry.session_id == session_id && entry.name_len == name.len()
             && &entry.name[..entry.name_len] == name {
                 return Some(entry.handle);
             }
@@ -5313,68 +5697,6 @@ pub mod file_attributes {
     pub const NORMAL: u32 = 0x0080;
 }
 
-/// Windows-style wildcard pattern matching
-///
-/// Supports:
-/// - `*` matches zero or more characters
-/// - `?` matches exactly one character
-/// - Case insensitive matching
-fn wildcard_match(pattern: &str, name: &str) -> bool {
-    let pattern_bytes = pattern.as_bytes();
-    let name_bytes = name.as_bytes();
-
-    wildcard_match_impl(pattern_bytes, name_bytes)
-}
-
-fn wildcard_match_impl(pattern: &[u8], name: &[u8]) -> bool {
-    let mut pi = 0; // pattern index
-    let mut ni = 0; // name index
-    let mut star_pi = usize::MAX; // position after last '*' in pattern
-    let mut star_ni = usize::MAX; // position in name when we matched '*'
-
-    while ni < name.len() {
-        if pi < pattern.len() {
-            let pc = pattern[pi];
-            let nc = name[ni];
-
-            // Convert to lowercase for case-insensitive matching
-            let pc_lower = if (b'A'..=b'Z').contains(&pc) { pc + 32 } else { pc };
-            let nc_lower = if (b'A'..=b'Z').contains(&nc) { nc + 32 } else { nc };
-
-            if pc == b'*' {
-                // Star matches zero or more characters
-                star_pi = pi + 1;
-                star_ni = ni;
-                pi += 1;
-                continue;
-            } else if pc == b'?' || pc_lower == nc_lower {
-                // ? matches any single character, or exact match
-                pi += 1;
-                ni += 1;
-                continue;
-            }
-        }
-
-        // Mismatch - backtrack if we have a star
-        if star_pi != usize::MAX {
-            pi = star_pi;
-            star_ni += 1;
-            ni = star_ni;
-            continue;
-        }
-
-        // No star to backtrack to - pattern doesn't match
-        return false;
-    }
-
-    // Check remaining pattern characters (must all be stars)
-    while pi < pattern.len() && pattern[pi] == b'*' {
-        pi += 1;
-    }
-
-    pi == pattern.len()
-}
-
 /// NtQueryDirectoryFile - Enumerate directory contents
 ///
 /// NT-style directory enumeration. Returns one or more entries per call.
@@ -5486,8 +5808,8 @@ fn sys_query_directory_file(
             break;
         }
 
-        // Check if name matches pattern
-        if !wildcard_match(pattern, name) {
+        // Check if name matches pattern (DOS wildcards, as FsRtlIsNameInExpression)
+        if !crate::rtl::rtl_is_name_in_expression(pattern, name, true) {
             // Move to next entry
             offset = entry.next_offset;
             if offset == 0 {
@@ -6993,6 +7315,23 @@ unsafe fn free_process_handle(handle: usize) {
     }
 }
 
+/// Process handle for a kernel caller, by the `0x5000 + pid` convention
+///
+/// Lets kernel code (the shell, boot tests) name a process without
+/// passing NtOpenProcess buffers from the kernel stack. Binds the slot if
+/// it is free; fails if the PID is past the table or the slot holds
+/// another process.
+pub unsafe fn kernel_process_handle(pid: u32) -> Option<usize> {
+    let idx = pid as usize;
+    if idx >= MAX_PROCESS_HANDLES {
+        return None;
+    }
+    if PROCESS_HANDLE_MAP[idx] == u32::MAX {
+        PROCESS_HANDLE_MAP[idx] = pid;
+    }
+    (PROCESS_HANDLE_MAP[idx] == pid).then_some(PROCESS_HANDLE_BASE + idx)
+}
+
 /// Token handle table
 const MAX_TOKEN_HANDLES: usize = 64;
 const TOKEN_HANDLE_BASE: usize = 0x6000;
@@ -8178,6 +8517,17 @@ pub mod process_access {
     pub const ProcessQueryInformation: u32 = PROCESS_QUERY_INFORMATION;
     pub const ProcessSuspendResume: u32 = PROCESS_SUSPEND_RESUME;
     pub const ProcessAllAccess: u32 = PROCESS_ALL_ACCESS;
+
+    /// Generic rights mapping for process objects
+    pub const PROCESS_GENERIC_MAPPING: crate::se::GenericMapping = crate::se::GenericMapping {
+        generic_read: READ_CONTROL | PROCESS_VM_READ | PROCESS_QUERY_INFORMATION,
+        generic_write: READ_CONTROL | PROCESS_CREATE_PROCESS | PROCESS_CREATE_THREAD |
+                       PROCESS_VM_OPERATION | PROCESS_VM_WRITE | PROCESS_DUP_HANDLE |
+                       PROCESS_TERMINATE | PROCESS_SET_QUOTA | PROCESS_SET_INFORMATION |
+                       PROCESS_SUSPEND_RESUME | PROCESS_SET_LIMITED_INFORMATION,
+        generic_execute: READ_CONTROL | SYNCHRONIZE | PROCESS_QUERY_LIMITED_INFORMATION,
+        generic_all: PROCESS_ALL_ACCESS,
+    };
 }
 
 /// CLIENT_ID structure for NtOpenProcess
@@ -8346,9 +8696,19 @@ fn sys_open_process(
         // Don't fail - just warn for compatibility
     }
 
-    // Access check would go here in a full implementation
-    // Check if the caller has permission to open the process with requested access
-    // For now, we grant the requested access
+    // Process objects have no DACL yet; the check enforces their integrity label
+    let granted = unsafe {
+        crate::ps::ps_access_check_process(
+            process as *mut crate::ps::EProcess,
+            access,
+            &process_access::PROCESS_GENERIC_MAPPING,
+        )
+    };
+    if let Err(reason) = granted {
+        crate::serial_println!("[SYSCALL] NtOpenProcess: access {:#x} to process {} denied ({:?})",
+            access, pid, reason);
+        return STATUS_ACCESS_DENIED;
+    }
 
     // Special handling for protected processes
     unsafe {
@@ -8882,9 +9242,9 @@ fn sys_query_information_process(
                     // Convert ticks to 100-nanosecond intervals for create/exit time
                     (*info).create_time = (p.create_time as i64) * 10000;
                     (*info).exit_time = if p.exit_time > 0 { (p.exit_time as i64) * 10000 } else { 0 };
-                    // Use tracked kernel/user time from EProcess
-                    (*info).kernel_time = p.kernel_time as i64;
-                    (*info).user_time = p.user_time as i64;
+                    // Kernel/user time is charged to the PCB by the clock tick
+                    (*info).kernel_time = p.pcb.kernel_time() as i64;
+                    (*info).user_time = p.pcb.user_time() as i64;
                 } else {
                     (*info).create_time = 0;
                     (*info).exit_time = 0;
@@ -9411,6 +9771,12 @@ fn sys_suspend_process(
 
     crate::serial_println!("[SYSCALL] NtSuspendProcess(pid={})", pid);
 
+    // The Idle and System processes can never be suspended
+    if crate::ps::ps_is_system_process_id(pid) {
+        crate::serial_println!("[SYSCALL] NtSuspendProcess: refusing to suspend PID {}", pid);
+        return STATUS_ACCESS_DENIED;
+    }
+
     // Look up the process
     let process = unsafe {
         ps_lookup_process_by_id(pid) as *mut EProcess
@@ -10510,12 +10876,18 @@ fn sys_suspend_thread(
         return STATUS_INVALID_HANDLE;
     }
 
-    // Get KTHREAD from ETHREAD and suspend it
-    let prev_count = unsafe {
-        let ethread = thread_ptr as *mut crate::ps::EThread;
-        let kthread = (*ethread).get_tcb_mut();
-        (*kthread).suspend()
-    };
+    // Idle and System threads can never be suspended
+    let kthread = unsafe { (*(thread_ptr as *mut crate::ps::EThread)).get_tcb_mut() };
+    unsafe {
+        let process = (*kthread).process;
+        if !process.is_null() && (*process).is_system_critical() {
+            crate::serial_println!("[SYSCALL] NtSuspendThread: refusing to suspend system thread {}", tid);
+            return STATUS_ACCESS_DENIED;
+        }
+    }
+
+    // Suspend the KTHREAD
+    let prev_count = unsafe { (*kthread).suspend() };
 
     if previous_suspend_count != 0 {
         unsafe { *(previous_suspend_count as *mut u32) = prev_count as u32; }
@@ -11215,6 +11587,11 @@ fn sys_set_information_process(
 
             let session_info = unsafe { ptr::read(process_information as *const ProcessSessionInformation) };
 
+            // The target session must exist
+            if crate::ob::ob_get_session(session_info.session_id).is_null() {
+                return STATUS_INVALID_PARAMETER;
+            }
+
             process.session_id = session_info.session_id;
             crate::serial_println!("[SYSCALL] SetInformationProcess: session ID = {}", session_info.session_id);
 
@@ -11280,12 +11657,14 @@ fn sys_set_information_process(
             let io_priority = unsafe { *(process_information as *const u32) };
 
             // Validate I/O priority (0=VeryLow, 1=Low, 2=Normal, 3=High, 4=Critical)
-            if io_priority > 4 {
-                return 0xC000000Du32 as isize; // STATUS_INVALID_PARAMETER
-            }
+            let io_priority = match crate::io::IoPriority::from_raw(io_priority) {
+                Some(p) => p,
+                None => return 0xC000000Du32 as isize, // STATUS_INVALID_PARAMETER
+            };
 
+            // Applies to the process's subsequent block I/O and IRPs
             process.io_priority = io_priority as u8;
-            crate::serial_println!("[SYSCALL] SetInformationProcess: I/O priority = {}", io_priority);
+            crate::serial_println!("[SYSCALL] SetInformationProcess: I/O priority = {}", io_priority.name());
 
             0
         }
@@ -14506,7 +14885,7 @@ fn sys_query_system_information(
                 highest_physical_page_number: stats.total_pages,
                 allocation_granularity: 65536, // 64KB
                 minimum_user_mode_address: 0x10000,
-                maximum_user_mode_address: 0x7FFFFFFEFFFF,
+                maximum_user_mode_address: crate::mm::MM_HIGHEST_USER_ADDRESS as usize,
                 active_processors_affinity_mask: 1, // Single processor
                 number_of_processors: 1,
             };
@@ -14835,6 +15214,37 @@ fn sys_query_system_information(
             0
         }
 
+        // SystemCallCountInformation = 6
+        6 => query_syscall_stats(
+            crate::perf::syscall::syscall_stats_query_counts,
+            system_info, system_info_length, return_length,
+        ),
+
+        // SystemSyscallLatencyInformation (Nostalgia extension)
+        crate::perf::syscall::SYSTEM_SYSCALL_LATENCY_INFORMATION => query_syscall_stats(
+            crate::perf::syscall::syscall_stats_query_latency,
+            system_info, system_info_length, return_length,
+        ),
+
+        // SystemProcessSnapshotInformation (Nostalgia extension)
+        crate::ps::SYSTEM_PROCESS_SNAPSHOT_INFORMATION => {
+            let buffer = unsafe {
+                core::slice::from_raw_parts_mut(system_info as *mut u8, system_info_length)
+            };
+            let required = match crate::ps::ps_query_snapshot(buffer) {
+                Some(required) => required,
+                None => return 0xC000009Au32 as isize, // STATUS_INSUFFICIENT_RESOURCES
+            };
+
+            if return_length != 0 {
+                unsafe { ptr::write(return_length as *mut u32, required as u32); }
+            }
+            if system_info_length < required {
+                return 0xC0000004u32 as isize; // STATUS_INFO_LENGTH_MISMATCH
+            }
+            0
+        }
+
         _ => {
             crate::serial_println!("[SYSCALL] NtQuerySystemInformation: unsupported class {}", info_class);
             0xC0000003u32 as isize // STATUS_INVALID_INFO_CLASS
@@ -14842,6 +15252,31 @@ fn sys_query_system_information(
     }
 }
 
+/// Fill a NtQuerySystemInformation buffer with syscall statistics
+///
+/// `fill` returns the required size and only writes if the buffer is big
+/// enough, so a call racing with new syscalls reports a length mismatch
+/// rather than returning a partial record.
+fn query_syscall_stats(
+    fill: fn(&mut [u8]) -> usize,
+    system_info: usize,
+    system_info_length: usize,
+    return_length: usize,
+) -> isize {
+    let buffer = unsafe {
+        core::slice::from_raw_parts_mut(system_info as *mut u8, system_info_length)
+    };
+    let required = fill(buffer);
+
+    if return_length != 0 {
+        unsafe { core::ptr::write(return_length as *mut u32, required as u32); }
+    }
+    if system_info_length < required {
+        return 0xC0000004u32 as isize; // STATUS_INFO_LENGTH_MISMATCH
+    }
+    0
+}
+
 /// NtQuerySystemTime - Query current system time
 fn sys_query_system_time(
     system_time: usize,
@@ -14860,6 +15295,7 @@ fn sys_query_system_time(
     // Add a base time (Jan 1, 1601 to Jan 1, 2024 in 100ns units, approximately)
     let base_time: i64 = 132_537_600_000_000_000; // Approximate
     let current_time = base_time + (tick_count as i64 * 10000);
+    let current_time = crate::hal::replay::replay_system_time(current_time as u64) as i64;
 
     unsafe {
         core::ptr::write(system_time as *mut i64, current_time);
@@ -14895,6 +15331,7 @@ fn sys_query_performance_counter(
             options(nostack, nomem)
         );
     }
+    let tsc = crate::hal::replay::replay_perf_counter(tsc);
 
     unsafe {
         core::ptr::write(performance_counter as *mut i64, tsc as i64);
@@ -16526,3 +16963,62 @@ fn sys_initiate_power_action(
     // Forward to NtSetSystemPowerState for now
     sys_set_system_power_state(system_action, min_system_state, flags, 0, 0, 0)
 }
+
+// ============================================================================
+// Hard Error Syscalls
+// ============================================================================
+
+/// NtRaiseHardError - Display an error popup and wait for the response
+///
+/// Arguments:
+/// - error_status: NTSTATUS selecting the message
+/// - number_of_parameters: Number of entries in `parameters`
+/// - unicode_string_parameter_mask: Parameters that are UNICODE_STRING pointers
+/// - parameters: Pointer to the parameter array
+/// - valid_response_options: HardErrorResponseOption
+/// - response: Pointer to receive the HardErrorResponse
+fn sys_raise_hard_error(
+    error_status: usize,
+    number_of_parameters: usize,
+    unicode_string_parameter_mask: usize,
+    parameters: usize,
+    valid_response_options: usize,
+    response: usize,
+) -> isize {
+    use crate::ex::MAXIMUM_HARDERROR_PARAMETERS;
+    use crate::mm::address::{probe_for_read, probe_for_write};
+
+    if number_of_parameters > MAXIMUM_HARDERROR_PARAMETERS || response == 0 {
+        return STATUS_INVALID_PARAMETER;
+    }
+    if !probe_for_write(response as u64, 4) {
+        return STATUS_ACCESS_VIOLATION;
+    }
+
+    let mut params = [0usize; MAXIMUM_HARDERROR_PARAMETERS];
+    if number_of_parameters > 0 {
+        if parameters == 0 {
+            return STATUS_INVALID_PARAMETER;
+        }
+        if !probe_for_read(parameters as u64, number_of_parameters * 8) {
+            return STATUS_ACCESS_VIOLATION;
+        }
+        for (i, slot) in params.iter_mut().take(number_of_parameters).enumerate() {
+            *slot = unsafe { core::ptr::read_unaligned((parameters as *const usize).add(i)) };
+        }
+    }
+
+    match crate::ex::nt_raise_hard_error(
+        error_status as i32,
+        number_of_parameters as u32,
+        unicode_string_parameter_mask as u32,
+        &params[..number_of_parameters],
+        valid_response_options as u32,
+    ) {
+        Ok(r) => {
+            unsafe { core::ptr::write_unaligned(response as *mut u32, r as u32) };
+            STATUS_SUCCESS
+        }
+        Err(status) => status as isize,
+    }
+}
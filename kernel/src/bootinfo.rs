@@ -0,0 +1,541 @@
+//! Boot Protocol
+//!
+//! Parses the loader's handoff into the kernel's `BootInfo`.
+//!
+//! # Version 2
+//!
+//! A `BootInfoHeader` followed by tags, each a `BootTag` header and its
+//! payload padded to 8 bytes, terminated by an `END` tag. Unknown tag types
+//! are skipped, payloads longer than the kernel expects are accepted (the
+//! extra bytes belong to a newer loader) and shorter ones are rejected, so
+//! the loader and kernel can be updated independently.
+//!
+//! | Tag | Payload |
+//! |-----|---------|
+//! | KERNEL | physical/virtual base, size, PML4 |
+//! | MEMMAP | UEFI memory map address, entry count and size |
+//! | FRAMEBUFFER | primary framebuffer geometry |
+//! | RSDP | ACPI RSDP address |
+//! | CMDLINE | NUL-terminated UTF-8 command line |
+//! | INITRD | initial ramdisk address and size |
+//! | MODES_LIST | GOP instances and their mode lists |
+//! | EFI_RUNTIME | virtual addresses of the EFI system and runtime services tables |
+//! | TPM_EVENT_LOG | TCG 2.0 event log copy and final events table |
+//! | SMBIOS | SMBIOS entry point address and version |
+//!
+//! # Version 1
+//!
+//! The original fixed `BootInfoV1` structure is still accepted so an older
+//! loader can boot a newer kernel.
+//!
+//! The handoff lives in loader memory that may overlap the kernel's .bss,
+//! so `RawBootInfo::capture` copies it onto the stack before .bss is zeroed
+//! and `parse` moves it into kernel memory afterwards.
+
+use crate::BootInfo;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Version 2 header magic ("NOSTBOOT" in ASCII)
+pub const BOOT_INFO_V2_MAGIC: u64 = 0x4E4F5354424F4F54;
+
+/// Version 1 structure magic ("NOSTALGI" in ASCII)
+pub const BOOT_INFO_V1_MAGIC: u64 = 0x4E4F5354414C4749;
+
+/// Largest handoff accepted
+pub const BOOT_INFO_MAX_SIZE: usize = 8192;
+
+/// Longest command line kept
+pub const MAX_CMDLINE: usize = 256;
+
+/// Tag types
+pub mod tag {
+    pub const END: u32 = 0;
+    pub const KERNEL: u32 = 1;
+    pub const MEMMAP: u32 = 2;
+    pub const FRAMEBUFFER: u32 = 3;
+    pub const RSDP: u32 = 4;
+    pub const CMDLINE: u32 = 5;
+    pub const INITRD: u32 = 6;
+    pub const MODES_LIST: u32 = 7;
+    pub const EFI_RUNTIME: u32 = 8;
+    pub const TPM_EVENT_LOG: u32 = 9;
+    pub const SMBIOS: u32 = 10;
+}
+
+/// Version 2 header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfoHeader {
+    pub magic: u64,
+    pub version: u32,
+    /// Size of this header; tags start here
+    pub header_size: u32,
+    /// Header plus all tags, including the END tag
+    pub total_size: u32,
+    /// Tags, not counting END
+    pub tag_count: u32,
+    /// Wrapping sum of the tag bytes as little-endian u32 words
+    pub checksum: u32,
+    pub reserved: u32,
+}
+
+/// Tag header; `size` covers the header and payload, not the padding
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootTag {
+    pub tag_type: u32,
+    pub size: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct KernelTag {
+    pub physical_base: u64,
+    pub virtual_base: u64,
+    pub size: u64,
+    pub pml4_physical_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryMapTag {
+    pub addr: u64,
+    pub entry_count: u64,
+    pub entry_size: u64,
+    pub descriptor_version: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferTag {
+    pub addr: u64,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub stride: u32,
+    pub bpp: u32,
+    /// EFI_GRAPHICS_PIXEL_FORMAT
+    pub pixel_format: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RsdpTag {
+    pub addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InitrdTag {
+    pub addr: u64,
+    pub size: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ModesListTag {
+    /// BootDisplay entries that follow
+    pub count: u32,
+    /// BOOT_DISPLAY_* flags
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EfiRuntimeTag {
+    pub system_table: u64,
+    pub runtime_services: u64,
+    /// Base of the runtime services window (virtual = base + physical)
+    pub virtual_base: u64,
+    /// EFI status of SetVirtualAddressMap (0 on success)
+    pub status: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TpmEventLogTag {
+    pub addr: u64,
+    pub size: u64,
+    /// EFI_TCG2_FINAL_EVENTS_TABLE (0 if the firmware has none)
+    pub final_events_addr: u64,
+    /// EFI_TCG2_EVENT_LOG_FORMAT
+    pub format: u32,
+    /// TPM_EVENT_LOG_* flags
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SmbiosTag {
+    pub entry_addr: u64,
+    /// 3 for `_SM3_`, 2 for `_SM_`
+    pub entry_version: u32,
+    pub reserved: u32,
+}
+
+/// The firmware ran out of log space and dropped events
+pub const TPM_EVENT_LOG_TRUNCATED: u32 = 0x1;
+
+/// EFI_TCG2_EVENT_LOG_FORMAT_TCG_2 (crypto-agile)
+pub const TPM_EVENT_LOG_FORMAT_TCG_2: u32 = 0x2;
+
+/// Version 1 fixed structure, as written by older loaders
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfoV1 {
+    pub magic: u64,
+    pub framebuffer_addr: u64,
+    pub framebuffer_width: u32,
+    pub framebuffer_height: u32,
+    pub framebuffer_stride: u32,
+    pub framebuffer_bpp: u32,
+    pub memory_map_addr: u64,
+    pub memory_map_entries: u64,
+    pub memory_map_entry_size: u64,
+    pub kernel_physical_base: u64,
+    pub kernel_virtual_base: u64,
+    pub kernel_size: u64,
+    pub pml4_physical_addr: u64,
+    pub rsdp_addr: u64,
+    pub display_count: u32,
+    pub display_flags: u32,
+    pub display_table_addr: u64,
+}
+
+/// Why a handoff was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootInfoError {
+    /// Neither a version 1 nor a version 2 magic
+    BadMagic(u64),
+    /// Version 2 magic with an older version number
+    BadVersion(u32),
+    /// Header sizes inconsistent or larger than `BOOT_INFO_MAX_SIZE`
+    BadHeader,
+    /// Tag bytes do not match the header checksum
+    BadChecksum,
+    /// A tag runs past the end of the handoff
+    TruncatedTag(u32),
+    /// A known tag is shorter than its payload structure
+    ShortTag(u32),
+    /// No KERNEL or MEMMAP tag
+    MissingTag(u32),
+}
+
+/// Loader handoff copied onto the stack
+pub struct RawBootInfo {
+    bytes: [u8; BOOT_INFO_MAX_SIZE],
+    len: usize,
+}
+
+impl RawBootInfo {
+    /// Copy the handoff at `ptr`
+    ///
+    /// # Safety
+    /// `ptr` must point to readable loader memory. Touches no statics, so it
+    /// can run before .bss is zeroed.
+    #[inline(never)]
+    pub unsafe fn capture(ptr: *const u8) -> Self {
+        let mut raw = Self { bytes: [0; BOOT_INFO_MAX_SIZE], len: 0 };
+        let magic = core::ptr::read_volatile(ptr as *const u64);
+        let len = match magic {
+            BOOT_INFO_V2_MAGIC => {
+                let header = core::ptr::read_volatile(ptr as *const BootInfoHeader);
+                (header.total_size as usize).clamp(size_of::<BootInfoHeader>(), BOOT_INFO_MAX_SIZE)
+            }
+            BOOT_INFO_V1_MAGIC => size_of::<BootInfoV1>(),
+            _ => size_of::<u64>(),
+        };
+        for i in 0..len {
+            raw.bytes[i] = core::ptr::read_volatile(ptr.add(i));
+        }
+        raw.len = len;
+        raw
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { core::ptr::read_unaligned(self.bytes.as_ptr().add(offset) as *const T) }
+    }
+}
+
+/// Summary of the parsed handoff
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BootProtocolInfo {
+    pub version: u32,
+    pub total_size: u32,
+    pub tag_count: u32,
+    /// Tags with types this kernel does not know
+    pub unknown_tags: u32,
+}
+
+/// Kernel copy of the version 2 handoff (cmdline and display table point into it)
+static mut BOOT_INFO_BLOB: [u8; BOOT_INFO_MAX_SIZE] = [0; BOOT_INFO_MAX_SIZE];
+static mut CMDLINE_LEN: usize = 0;
+static mut CMDLINE_OFFSET: usize = 0;
+static PROTOCOL_VERSION: AtomicU32 = AtomicU32::new(0);
+static TOTAL_SIZE: AtomicU32 = AtomicU32::new(0);
+static TAG_COUNT: AtomicU32 = AtomicU32::new(0);
+static UNKNOWN_TAGS: AtomicU32 = AtomicU32::new(0);
+
+fn empty_boot_info() -> BootInfo {
+    BootInfo {
+        magic: 0,
+        framebuffer_addr: 0,
+        framebuffer_width: 0,
+        framebuffer_height: 0,
+        framebuffer_stride: 0,
+        framebuffer_bpp: 0,
+        memory_map_addr: 0,
+        memory_map_entries: 0,
+        memory_map_entry_size: 0,
+        kernel_physical_base: 0,
+        kernel_virtual_base: 0,
+        kernel_size: 0,
+        pml4_physical_addr: 0,
+        rsdp_addr: 0,
+        display_count: 0,
+        display_flags: 0,
+        display_table_addr: 0,
+        initrd_addr: 0,
+        initrd_size: 0,
+        efi_system_table: 0,
+        efi_runtime_services: 0,
+        efi_runtime_status: 0,
+        tpm_event_log_addr: 0,
+        tpm_event_log_size: 0,
+        tpm_final_events_addr: 0,
+        tpm_event_log_flags: 0,
+        smbios_entry_addr: 0,
+        smbios_entry_version: 0,
+    }
+}
+
+/// Parse a captured handoff
+///
+/// Must run after .bss is zeroed.
+pub fn parse(raw: &RawBootInfo) -> Result<BootInfo, BootInfoError> {
+    let magic: u64 = raw.read(0);
+    match magic {
+        BOOT_INFO_V2_MAGIC => parse_v2(raw),
+        BOOT_INFO_V1_MAGIC => Ok(parse_v1(raw)),
+        _ => Err(BootInfoError::BadMagic(magic)),
+    }
+}
+
+fn parse_v1(raw: &RawBootInfo) -> BootInfo {
+    let v1: BootInfoV1 = raw.read(0);
+    PROTOCOL_VERSION.store(1, Ordering::Relaxed);
+    TOTAL_SIZE.store(size_of::<BootInfoV1>() as u32, Ordering::Relaxed);
+    BootInfo {
+        magic: BootInfo::MAGIC,
+        framebuffer_addr: v1.framebuffer_addr,
+        framebuffer_width: v1.framebuffer_width,
+        framebuffer_height: v1.framebuffer_height,
+        framebuffer_stride: v1.framebuffer_stride,
+        framebuffer_bpp: v1.framebuffer_bpp,
+        memory_map_addr: v1.memory_map_addr,
+        memory_map_entries: v1.memory_map_entries,
+        memory_map_entry_size: v1.memory_map_entry_size,
+        kernel_physical_base: v1.kernel_physical_base,
+        kernel_virtual_base: v1.kernel_virtual_base,
+        kernel_size: v1.kernel_size,
+        pml4_physical_addr: v1.pml4_physical_addr,
+        rsdp_addr: v1.rsdp_addr,
+        display_count: v1.display_count,
+        display_flags: v1.display_flags,
+        display_table_addr: v1.display_table_addr,
+        initrd_addr: 0,
+        initrd_size: 0,
+        efi_system_table: 0,
+        efi_runtime_services: 0,
+        efi_runtime_status: 0,
+        tpm_event_log_addr: 0,
+        tpm_event_log_size: 0,
+        tpm_final_events_addr: 0,
+        tpm_event_log_flags: 0,
+        smbios_entry_addr: 0,
+        smbios_entry_version: 0,
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes
+        .as_chunks::<4>()
+        .0
+        .iter()
+        .fold(0u32, |sum, word| sum.wrapping_add(u32::from_le_bytes(*word)))
+}
+
+fn parse_v2(raw: &RawBootInfo) -> Result<BootInfo, BootInfoError> {
+    let header: BootInfoHeader = raw.read(0);
+    if header.version < 2 {
+        return Err(BootInfoError::BadVersion(header.version));
+    }
+    let header_size = header.header_size as usize;
+    let total_size = header.total_size as usize;
+    if header_size < size_of::<BootInfoHeader>() || header_size > total_size || total_size > raw.len {
+        return Err(BootInfoError::BadHeader);
+    }
+    if checksum(&raw.bytes[header_size..total_size]) != header.checksum {
+        return Err(BootInfoError::BadChecksum);
+    }
+
+    // Keep the handoff; the cmdline and display table are used in place
+    let blob = unsafe { &mut *core::ptr::addr_of_mut!(BOOT_INFO_BLOB) };
+    blob[..total_size].copy_from_slice(&raw.bytes[..total_size]);
+
+    let mut info = empty_boot_info();
+    let (mut have_kernel, mut have_memmap) = (false, false);
+    let mut tags = 0u32;
+    let mut unknown = 0u32;
+    let mut offset = header_size;
+
+    while offset + size_of::<BootTag>() <= total_size {
+        let tag_header: BootTag = raw.read(offset);
+        let size = tag_header.size as usize;
+        if size < size_of::<BootTag>() || offset + size > total_size {
+            return Err(BootInfoError::TruncatedTag(tag_header.tag_type));
+        }
+        if tag_header.tag_type == tag::END {
+            break;
+        }
+        let payload = offset + size_of::<BootTag>();
+        let payload_len = size - size_of::<BootTag>();
+        let need = |len: usize| {
+            if payload_len < len {
+                Err(BootInfoError::ShortTag(tag_header.tag_type))
+            } else {
+                Ok(())
+            }
+        };
+
+        match tag_header.tag_type {
+            tag::KERNEL => {
+                need(size_of::<KernelTag>())?;
+                let t: KernelTag = raw.read(payload);
+                info.kernel_physical_base = t.physical_base;
+                info.kernel_virtual_base = t.virtual_base;
+                info.kernel_size = t.size;
+                info.pml4_physical_addr = t.pml4_physical_addr;
+                have_kernel = true;
+            }
+            tag::MEMMAP => {
+                need(size_of::<MemoryMapTag>())?;
+                let t: MemoryMapTag = raw.read(payload);
+                info.memory_map_addr = t.addr;
+                info.memory_map_entries = t.entry_count;
+                info.memory_map_entry_size = t.entry_size;
+                have_memmap = true;
+            }
+            tag::FRAMEBUFFER => {
+                need(size_of::<FramebufferTag>())?;
+                let t: FramebufferTag = raw.read(payload);
+                info.framebuffer_addr = t.addr;
+                info.framebuffer_width = t.width;
+                info.framebuffer_height = t.height;
+                info.framebuffer_stride = t.stride;
+                info.framebuffer_bpp = t.bpp;
+            }
+            tag::RSDP => {
+                need(size_of::<RsdpTag>())?;
+                let t: RsdpTag = raw.read(payload);
+                info.rsdp_addr = t.addr;
+            }
+            tag::CMDLINE => {
+                let bytes = &raw.bytes[payload..payload + payload_len];
+                let len = bytes.iter().position(|&b| b == 0).unwrap_or(payload_len).min(MAX_CMDLINE);
+                unsafe {
+                    CMDLINE_OFFSET = payload;
+                    CMDLINE_LEN = len;
+                }
+            }
+            tag::INITRD => {
+                need(size_of::<InitrdTag>())?;
+                let t: InitrdTag = raw.read(payload);
+                info.initrd_addr = t.addr;
+                info.initrd_size = t.size;
+            }
+            tag::MODES_LIST => {
+                need(size_of::<ModesListTag>())?;
+                let t: ModesListTag = raw.read(payload);
+                let entry = size_of::<crate::hal::display::BootDisplay>();
+                let available = (payload_len - size_of::<ModesListTag>()) / entry;
+                info.display_count = t.count.min(available as u32);
+                info.display_flags = t.flags;
+                info.display_table_addr = blob.as_ptr() as u64 + (payload + size_of::<ModesListTag>()) as u64;
+            }
+            tag::EFI_RUNTIME => {
+                need(size_of::<EfiRuntimeTag>())?;
+                let t: EfiRuntimeTag = raw.read(payload);
+                info.efi_system_table = t.system_table;
+                info.efi_runtime_services = t.runtime_services;
+                info.efi_runtime_status = t.status;
+            }
+            tag::TPM_EVENT_LOG => {
+                need(size_of::<TpmEventLogTag>())?;
+                let t: TpmEventLogTag = raw.read(payload);
+                // Only the crypto-agile format is understood
+                if t.format == TPM_EVENT_LOG_FORMAT_TCG_2 {
+                    info.tpm_event_log_addr = t.addr;
+                    info.tpm_event_log_size = t.size;
+                    info.tpm_final_events_addr = t.final_events_addr;
+                    info.tpm_event_log_flags = t.flags as u64;
+                }
+            }
+            tag::SMBIOS => {
+                need(size_of::<SmbiosTag>())?;
+                let t: SmbiosTag = raw.read(payload);
+                info.smbios_entry_addr = t.entry_addr;
+                info.smbios_entry_version = t.entry_version as u64;
+            }
+            _ => unknown += 1,
+        }
+        tags += 1;
+        offset += size.next_multiple_of(8);
+    }
+
+    if !have_kernel {
+        return Err(BootInfoError::MissingTag(tag::KERNEL));
+    }
+    if !have_memmap {
+        return Err(BootInfoError::MissingTag(tag::MEMMAP));
+    }
+
+    PROTOCOL_VERSION.store(header.version, Ordering::Relaxed);
+    TOTAL_SIZE.store(total_size as u32, Ordering::Relaxed);
+    TAG_COUNT.store(tags, Ordering::Relaxed);
+    UNKNOWN_TAGS.store(unknown, Ordering::Relaxed);
+    info.magic = BootInfo::MAGIC;
+    Ok(info)
+}
+
+/// Kernel command line from the loader (empty if none)
+pub fn boot_cmdline() -> &'static str {
+    unsafe {
+        let blob = &*core::ptr::addr_of!(BOOT_INFO_BLOB);
+        core::str::from_utf8(&blob[CMDLINE_OFFSET..CMDLINE_OFFSET + CMDLINE_LEN]).unwrap_or("")
+    }
+}
+
+/// Initial ramdisk from the loader as (physical address, size)
+pub fn boot_initrd() -> Option<(u64, u64)> {
+    let info = crate::boot_info()?;
+    if info.initrd_size == 0 {
+        None
+    } else {
+        Some((info.initrd_addr, info.initrd_size))
+    }
+}
+
+/// Protocol version and tag counts of the parsed handoff
+pub fn boot_protocol_info() -> BootProtocolInfo {
+    BootProtocolInfo {
+        version: PROTOCOL_VERSION.load(Ordering::Relaxed),
+        total_size: TOTAL_SIZE.load(Ordering::Relaxed),
+        tag_count: TAG_COUNT.load(Ordering::Relaxed),
+        unknown_tags: UNKNOWN_TAGS.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,292 @@
+//! Boot Screen
+//!
+//! Graphical screen shown while the kernel initializes, in place of the
+//! scrolling initialization messages: the logo, a progress bar and the
+//! subsystem being started, like NT's boot screen.
+//!
+//! Progress comes from the messages themselves: each `Initializing ...`
+//! line is one step. The messages are kept, and F8 during boot (or the
+//! `/SOS` or `/NOGUIBOOT` boot option) shows them as text instead. A
+//! panic shows them too.
+//!
+//! Before the keyboard driver is up, F8 is read by polling the PS/2
+//! controller between messages; afterwards the keyboard interrupt
+//! handler requests it through `request_messages`.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::framebuffer::{self, FONT_HEIGHT, FONT_WIDTH};
+
+/// "Initializing" messages in a normal boot; the bar holds at 95% past this
+const EXPECTED_STEPS: u32 = 48;
+
+/// Bytes of boot messages kept for F8
+const LOG_SIZE: usize = 16 * 1024;
+
+/// Longest status line
+const MAX_STATUS: usize = 48;
+
+const BACKGROUND: u32 = 0x00000000;
+const TEXT_COLOR: u32 = 0x00C0C0C0;
+const HINT_COLOR: u32 = 0x00707070;
+const BAR_FRAME: u32 = 0x00A0A0A0;
+const BAR_FILL: u32 = 0x003A6EA5;
+
+const BAR_WIDTH: u32 = 256;
+const BAR_HEIGHT: u32 = 12;
+const LOGO_SCALE: u32 = 4;
+
+/// PS/2 set 1 make code of F8
+pub const SCANCODE_F8: u8 = 0x42;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Text console (no framebuffer, boot option, or not started)
+    Text,
+    /// Boot screen up, messages captured
+    Graphical,
+    /// Left for the text console
+    Done,
+}
+
+struct BootScreen {
+    mode: Mode,
+    steps: u32,
+    /// Captured messages, oldest first
+    log: [u8; LOG_SIZE],
+    log_len: usize,
+    /// Start of the message line being captured
+    line_start: usize,
+    bar_x: u32,
+    bar_y: u32,
+    status_y: u32,
+}
+
+static SCREEN: Mutex<BootScreen> = Mutex::new(BootScreen {
+    mode: Mode::Text,
+    steps: 0,
+    log: [0; LOG_SIZE],
+    log_len: 0,
+    line_start: 0,
+    bar_x: 0,
+    bar_y: 0,
+    status_y: 0,
+});
+
+/// Set (by F8 after the keyboard driver starts) to show the messages
+static MESSAGES_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+impl BootScreen {
+    fn log_text(&self) -> &str {
+        let bytes = &self.log[..self.log_len];
+        match core::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => unsafe { core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) },
+        }
+    }
+
+    /// Drop the older half of the log, at a line boundary
+    fn trim_log(&mut self) {
+        let half = self.log_len / 2;
+        let cut = self.log[half..self.log_len]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(half, |i| half + i + 1);
+        self.log.copy_within(cut..self.log_len, 0);
+        self.log_len -= cut;
+        self.line_start = self.line_start.saturating_sub(cut);
+    }
+
+    fn draw(&mut self) {
+        let (width, height) = framebuffer::screen_size();
+        framebuffer::fill_rect(0, 0, width, height, BACKGROUND);
+
+        use crate::win32k::user::desktop_icons::{WINDOWS_LOGO_DATA, WINDOWS_LOGO_HEIGHT, WINDOWS_LOGO_WIDTH};
+        let logo_w = WINDOWS_LOGO_WIDTH as u32 * LOGO_SCALE;
+        let logo_h = WINDOWS_LOGO_HEIGHT as u32 * LOGO_SCALE;
+        let logo_y = (height / 2).saturating_sub(logo_h + 2 * FONT_HEIGHT);
+        framebuffer::draw_image(
+            (width - logo_w) / 2, logo_y,
+            WINDOWS_LOGO_WIDTH as u32, WINDOWS_LOGO_HEIGHT as u32, LOGO_SCALE,
+            &WINDOWS_LOGO_DATA,
+        );
+
+        let title = "Nostalgia OS";
+        let title_y = logo_y + logo_h + FONT_HEIGHT;
+        framebuffer::draw_text(centered(width, title.len()), title_y, title, TEXT_COLOR, BACKGROUND);
+
+        self.bar_x = (width - BAR_WIDTH) / 2;
+        self.bar_y = title_y + 3 * FONT_HEIGHT;
+        self.status_y = self.bar_y + BAR_HEIGHT + FONT_HEIGHT;
+        framebuffer::fill_rect(self.bar_x - 2, self.bar_y - 2, BAR_WIDTH + 4, BAR_HEIGHT + 4, BAR_FRAME);
+        framebuffer::fill_rect(self.bar_x - 1, self.bar_y - 1, BAR_WIDTH + 2, BAR_HEIGHT + 2, BACKGROUND);
+
+        let hint = "Press F8 for boot messages";
+        framebuffer::draw_text(
+            centered(width, hint.len()), height - 2 * FONT_HEIGHT, hint, HINT_COLOR, BACKGROUND);
+        self.draw_progress(self.steps * 100 / EXPECTED_STEPS);
+    }
+
+    /// Fill the bar to `percent`
+    fn draw_progress(&self, percent: u32) {
+        let filled = BAR_WIDTH * percent.min(100) / 100;
+        framebuffer::fill_rect(self.bar_x, self.bar_y, filled, BAR_HEIGHT, BAR_FILL);
+    }
+
+    fn draw_status(&self, subsystem: &str) {
+        let (width, _) = framebuffer::screen_size();
+        framebuffer::fill_rect(0, self.status_y, width, FONT_HEIGHT, BACKGROUND);
+
+        let mut status = [0u8; MAX_STATUS];
+        let mut writer = StatusWriter { buffer: &mut status, len: 0 };
+        let _ = write!(writer, "Starting {}", subsystem);
+        let len = writer.len;
+        if let Ok(text) = core::str::from_utf8(&status[..len]) {
+            framebuffer::draw_text(centered(width, len), self.status_y, text, HINT_COLOR, BACKGROUND);
+        }
+    }
+
+    /// One complete message line
+    fn message(&mut self, line: &str) {
+        if let Some(subsystem) = line.trim().strip_prefix("Initializing ") {
+            self.steps += 1;
+            self.draw_progress((self.steps * 100 / EXPECTED_STEPS).min(95));
+            self.draw_status(subsystem.trim_end_matches('.'));
+        }
+    }
+}
+
+impl Write for BootScreen {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &b in s.as_bytes() {
+            if self.log_len == LOG_SIZE {
+                self.trim_log();
+            }
+            self.log[self.log_len] = b;
+            self.log_len += 1;
+            if b == b'\n' {
+                let (start, end) = (self.line_start, self.log_len - 1);
+                self.line_start = self.log_len;
+                let mut line = [0u8; 128];
+                let n = (end - start).min(line.len());
+                line[..n].copy_from_slice(&self.log[start..start + n]);
+                if let Ok(text) = core::str::from_utf8(&line[..n]) {
+                    self.message(text);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats the status line into a fixed buffer, truncating
+struct StatusWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for StatusWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.len + c.len_utf8() > self.buffer.len() {
+                break;
+            }
+            self.len += c.encode_utf8(&mut self.buffer[self.len..]).len();
+        }
+        Ok(())
+    }
+}
+
+fn centered(width: u32, chars: usize) -> u32 {
+    width.saturating_sub(chars as u32 * FONT_WIDTH) / 2
+}
+
+/// Whether the command line has boot option `name` (`/SOS`, `SOS` or `-sos`)
+fn boot_option(name: &str) -> bool {
+    crate::bootinfo::boot_cmdline()
+        .split_whitespace()
+        .any(|opt| opt.trim_start_matches(['/', '-']).eq_ignore_ascii_case(name))
+}
+
+/// Poll the PS/2 controller for F8 before the keyboard driver owns it
+fn f8_pressed() -> bool {
+    use crate::hal::port::read_port_u8;
+
+    if crate::hal::keyboard::is_initialized() {
+        return false;
+    }
+    let status = read_port_u8(0x64);
+    // Output buffer full, and the byte is from the keyboard, not the mouse
+    if status & 0x01 == 0 || status & 0x20 != 0 {
+        return false;
+    }
+    read_port_u8(0x60) == SCANCODE_F8
+}
+
+/// Show the boot screen, unless booting with /SOS or /NOGUIBOOT
+///
+/// Called once the framebuffer is initialized.
+pub fn init() {
+    let (width, height) = framebuffer::screen_size();
+    if width < 640 || height < 480 || boot_option("SOS") || boot_option("NOGUIBOOT") {
+        return;
+    }
+
+    let mut screen = SCREEN.lock();
+    screen.mode = Mode::Graphical;
+    screen.draw();
+}
+
+/// Take a message while the boot screen is up
+///
+/// Returns false when the text should be printed as usual.
+pub fn capture(args: fmt::Arguments) -> bool {
+    let mut screen = SCREEN.lock();
+    if screen.mode != Mode::Graphical {
+        return false;
+    }
+    let _ = screen.write_fmt(args);
+
+    if MESSAGES_REQUESTED.load(Ordering::Relaxed) || f8_pressed() {
+        leave(&mut screen, true);
+    }
+    true
+}
+
+/// Ask for the boot messages (F8); safe from interrupt handlers
+pub fn request_messages() {
+    MESSAGES_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Switch to the text console and replay the messages so far
+///
+/// Used by the panic handler, so a crash during boot is visible.
+pub fn show_messages() {
+    if let Some(mut screen) = SCREEN.try_lock() {
+        if screen.mode == Mode::Graphical {
+            leave(&mut screen, true);
+        }
+    }
+}
+
+/// Fill the bar and leave the boot screen for the text console
+pub fn finish() {
+    let mut screen = SCREEN.lock();
+    if screen.mode == Mode::Graphical {
+        screen.draw_progress(100);
+        leave(&mut screen, false);
+    }
+}
+
+fn leave(screen: &mut BootScreen, replay: bool) {
+    screen.mode = Mode::Done;
+    if framebuffer::is_disabled() {
+        return;
+    }
+    framebuffer::clear();
+    if replay {
+        framebuffer::write_text(format_args!("{}", screen.log_text()));
+    }
+}
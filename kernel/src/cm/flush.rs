@@ -0,0 +1,586 @@
+//! Hive Persistence
+//!
+//! Non-volatile hives are saved under `<system drive>:\WINDOWS\SYSTEM32\CONFIG`
+//! as flat images: a header followed by one record per key, depth first,
+//! holding the key's path below the hive root and its values. Volatile
+//! keys and values, and the HARDWARE hive, are never written.
+//!
+//! # Crash Consistency
+//! Every flush writes the image twice: first to `<HIVE>.LOG`, synced past
+//! the disk cache, and only then over the primary file. Each copy carries
+//! a sequence number and a CRC of its body, so a crash at any point leaves
+//! at least one intact copy, and the loader takes the newest one that
+//! verifies.
+//!
+//! # Lazy Flush
+//! Modifications only mark the hive dirty. The lazy flusher thread writes
+//! dirty hives back every `LAZY_FLUSH_INTERVAL_MS`; `cm_shutdown_flush`
+//! stops it and writes whatever is left, as the first step of shutdown.
+//!
+//! Images are merged over the boot-time defaults when loaded.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use crate::fs::{FsStatus, SeekWhence};
+use crate::hal::apic;
+use crate::ke::{EventType, KEvent};
+use super::hive::{self, CmHive, hive_flags, cm_enumerate_hives, cm_get_hive};
+use super::key::{CmKeyNode, cm_get_key, cm_get_key_mut, cm_get_key_pool};
+use super::value::{CmKeyValue, CmValueData, RegType, value_flags};
+
+/// Hive directory below the root of the system drive
+const CONFIG_DIRECTORY: &str = "WINDOWS\\SYSTEM32\\CONFIG";
+
+/// Image signature ("NHIV")
+const HIVE_FILE_MAGIC: u32 = 0x5649_484E;
+
+/// Image format version
+const HIVE_FILE_VERSION: u16 = 1;
+
+/// Largest image the loader will read
+const MAX_HIVE_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Delay between the lazy flusher's passes
+pub const LAZY_FLUSH_INTERVAL_MS: u64 = 5_000;
+
+/// Lazy flusher thread priority
+const FLUSHER_PRIORITY: i8 = 7;
+
+/// Hive image header
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct HiveFileHeader {
+    magic: u32,
+    version: u16,
+    header_size: u16,
+    /// Flush sequence; the newer of the primary and the log wins
+    sequence: u32,
+    key_count: u32,
+    data_length: u32,
+    /// CRC32 of the body
+    checksum: u32,
+    /// System time of the flush
+    timestamp: u64,
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<HiveFileHeader>();
+
+/// Outcome of a flush pass
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HiveFlushSummary {
+    /// Hives written
+    pub written: u32,
+    /// Hives that failed to write
+    pub failed: u32,
+    /// First failure
+    pub error: Option<FsStatus>,
+}
+
+/// Serializes flushes between the lazy flusher and explicit callers
+static FLUSH_BUSY: AtomicBool = AtomicBool::new(false);
+
+static FLUSHER_RUNNING: AtomicBool = AtomicBool::new(false);
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static mut WAKE_EVENT: KEvent = KEvent::new();
+
+static FLUSH_COUNT: AtomicU64 = AtomicU64::new(0);
+static FLUSH_FAILURES: AtomicU64 = AtomicU64::new(0);
+static LAST_FLUSH: AtomicU64 = AtomicU64::new(0);
+static HIVES_LOADED: AtomicU32 = AtomicU32::new(0);
+
+// ============================================================================
+// Image Format
+// ============================================================================
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+/// Build the on-disk image of a hive
+unsafe fn build_image(hive: &CmHive, sequence: u32) -> Vec<u8> {
+    let mut body = Vec::new();
+    let mut key_count = 0u32;
+    if let Some(root) = cm_get_key(hive.root_key) {
+        let mut path = String::new();
+        write_key(root, &mut path, &mut body, &mut key_count);
+    }
+
+    let header = HiveFileHeader {
+        magic: HIVE_FILE_MAGIC,
+        version: HIVE_FILE_VERSION,
+        header_size: HEADER_SIZE as u16,
+        sequence,
+        key_count,
+        data_length: body.len() as u32,
+        checksum: crate::rtl::rtl_compute_crc32(0, &body),
+        timestamp: crate::rtl::rtl_get_system_time() as u64,
+    };
+
+    let mut image = Vec::with_capacity(HEADER_SIZE + body.len());
+    image.extend_from_slice(as_bytes(&header));
+    image.extend_from_slice(&body);
+    image
+}
+
+/// Append a key record and the key's non-volatile subtree
+///
+/// Record: path (u16 length + bytes), value count (u8), then per value
+/// its name (u8 length + bytes), type (u32) and data (u16 length + bytes).
+unsafe fn write_key(key: &CmKeyNode, path: &mut String, out: &mut Vec<u8>, key_count: &mut u32) {
+    out.extend_from_slice(&(path.len() as u16).to_le_bytes());
+    out.extend_from_slice(path.as_bytes());
+
+    let is_persistent = |v: &&CmKeyValue| v.flags & value_flags::VALUE_VOLATILE == 0;
+    out.push(key.enumerate_values().iter().filter(is_persistent).count() as u8);
+    for value in key.enumerate_values().iter().filter(is_persistent) {
+        let name = value.name.as_str();
+        let data = value.data.as_bytes();
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(value.value_type as u32).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+    *key_count += 1;
+
+    for &index in key.enumerate_subkeys() {
+        let Some(child) = cm_get_key(index) else { continue };
+        if child.is_volatile() {
+            continue;
+        }
+        let mark = path.len();
+        if !path.is_empty() {
+            path.push('\\');
+        }
+        path.push_str(child.name.as_str());
+        write_key(child, path, out, key_count);
+        path.truncate(mark);
+    }
+}
+
+/// Check an image, returning its header and body
+fn verify_image(image: &[u8]) -> Option<(HiveFileHeader, &[u8])> {
+    if image.len() < HEADER_SIZE {
+        return None;
+    }
+    let header = unsafe { core::ptr::read_unaligned(image.as_ptr() as *const HiveFileHeader) };
+    if header.magic != HIVE_FILE_MAGIC
+        || header.version != HIVE_FILE_VERSION
+        || header.header_size as usize != HEADER_SIZE
+    {
+        return None;
+    }
+    let body = image.get(HEADER_SIZE..HEADER_SIZE + header.data_length as usize)?;
+    if crate::rtl::rtl_compute_crc32(0, body) != header.checksum {
+        return None;
+    }
+    Some((header, body))
+}
+
+/// Cursor over an image body
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.data.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(slice)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let b = self.bytes(2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b = self.bytes(4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn str(&mut self, len: usize) -> Option<&'a str> {
+        core::str::from_utf8(self.bytes(len)?).ok()
+    }
+}
+
+/// Find or create the key at `path` below the hive root
+unsafe fn open_or_create_path(hive: &CmHive, path: &str) -> Option<u32> {
+    let mut current = hive.root_key;
+    for component in path.split('\\').filter(|c| !c.is_empty()) {
+        let existing = cm_get_key(current)?.find_subkey_index(component, cm_get_key_pool());
+        current = match existing {
+            Some(index) => index,
+            None => hive::create_subkey(current, component, hive.hive_index)?,
+        };
+    }
+    Some(current)
+}
+
+/// Merge an image body into a hive
+///
+/// # Returns
+/// The number of keys applied, or `None` if the body is malformed
+unsafe fn apply_image(hive: &CmHive, body: &[u8]) -> Option<u32> {
+    let mut reader = Reader { data: body, pos: 0 };
+    let mut applied = 0u32;
+
+    while reader.pos < body.len() {
+        let path_len = reader.u16()? as usize;
+        let path = reader.str(path_len)?;
+        let value_count = reader.u8()?;
+        let mut key = open_or_create_path(hive, path).and_then(|index| cm_get_key_mut(index));
+
+        for _ in 0..value_count {
+            let name_len = reader.u8()? as usize;
+            let name = reader.str(name_len)?;
+            let value_type = RegType::from_u32(reader.u32()?)?;
+            let data_len = reader.u16()? as usize;
+            let data = reader.bytes(data_len)?;
+
+            if let Some(key) = key.as_deref_mut() {
+                let is_new = key.find_value(name).is_none();
+                if key.add_value(CmKeyValue::new(name, value_type, CmValueData::from_bytes(data))) && is_new {
+                    hive.add_value();
+                }
+            }
+        }
+        if key.is_some() {
+            applied += 1;
+        }
+    }
+
+    Some(applied)
+}
+
+// ============================================================================
+// Files
+// ============================================================================
+
+/// Hive file name: the hive name without a leading dot
+fn hive_file_name(hive: &CmHive) -> &str {
+    hive.name.as_str().trim_start_matches('.')
+}
+
+/// Path of a hive's primary file, if there is a system drive
+pub fn cm_hive_file_path(hive: &CmHive) -> Option<String> {
+    let drive = crate::fs::mount::get_system_drive()?;
+    Some(format!("{}:\\{}\\{}", drive, CONFIG_DIRECTORY, hive_file_name(hive)))
+}
+
+/// Create the hive directory, one component at a time
+fn ensure_config_directory(drive: char) -> Result<(), FsStatus> {
+    let mut path = format!("{}:", drive);
+    for component in CONFIG_DIRECTORY.split('\\') {
+        path.push('\\');
+        path.push_str(component);
+        match crate::fs::mkdir(&path) {
+            Ok(()) | Err(FsStatus::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Replace the contents of `path` and push them past the disk cache
+fn write_file(path: &str, data: &[u8]) -> Result<(), FsStatus> {
+    let handle = match crate::fs::open(path, crate::io::file_access::FILE_GENERIC_WRITE) {
+        Ok(h) => h,
+        Err(FsStatus::NotFound) => crate::fs::create(path, 0)?,
+        Err(e) => return Err(e),
+    };
+    let result = (|| -> Result<(), FsStatus> {
+        crate::fs::truncate(handle, data.len() as u64)?;
+        crate::fs::seek(handle, 0, SeekWhence::Set)?;
+        let mut done = 0;
+        while done < data.len() {
+            match crate::fs::write(handle, &data[done..])? {
+                0 => return Err(FsStatus::IoError),
+                n => done += n,
+            }
+        }
+        crate::fs::sync(handle)
+    })();
+    let _ = crate::fs::close(handle);
+    result
+}
+
+/// Read a whole file
+fn read_file(path: &str) -> Result<Vec<u8>, FsStatus> {
+    let handle = crate::fs::open(path, 0)?;
+    let result = (|| -> Result<Vec<u8>, FsStatus> {
+        let size = crate::fs::fstat(handle)?.size;
+        if size > MAX_HIVE_FILE_SIZE {
+            return Err(FsStatus::InvalidParameter);
+        }
+        let mut data = alloc::vec![0u8; size as usize];
+        let mut done = 0;
+        while done < data.len() {
+            match crate::fs::read(handle, &mut data[done..])? {
+                0 => break,
+                n => done += n,
+            }
+        }
+        data.truncate(done);
+        Ok(data)
+    })();
+    let _ = crate::fs::close(handle);
+    result
+}
+
+// ============================================================================
+// Flush
+// ============================================================================
+
+fn acquire_flush_lock() {
+    while FLUSH_BUSY.swap(true, Ordering::Acquire) {
+        crate::ex::ex_sleep(1);
+    }
+}
+
+fn release_flush_lock() {
+    FLUSH_BUSY.store(false, Ordering::Release);
+}
+
+/// Check if a hive is ever written to disk
+fn is_persistent(hive: &CmHive) -> bool {
+    !hive.is_volatile() && hive.hive_type != hive::CmHiveType::Volatile
+}
+
+/// Write one hive: log first, then the primary (caller holds the flush lock)
+unsafe fn flush_hive_locked(hive: &CmHive, drive: char) -> Result<(), FsStatus> {
+    // Changes made while the image is built re-mark the hive
+    hive.clear_flag(hive_flags::HIVE_DIRTY);
+
+    let sequence = hive.sequence.fetch_add(1, Ordering::SeqCst) + 1;
+    let image = build_image(hive, sequence);
+    let primary = format!("{}:\\{}\\{}", drive, CONFIG_DIRECTORY, hive_file_name(hive));
+    let log = format!("{}.LOG", primary);
+
+    let result = write_file(&log, &image).and_then(|()| write_file(&primary, &image));
+    if result.is_err() {
+        hive.set_flag(hive_flags::HIVE_DIRTY);
+    }
+    result
+}
+
+/// Write hives back to disk
+///
+/// With `all` unset only dirty hives are written.
+pub fn cm_flush_hives(all: bool) -> HiveFlushSummary {
+    let mut summary = HiveFlushSummary::default();
+    let Some(drive) = crate::fs::mount::get_system_drive() else {
+        summary.error = Some(FsStatus::NotMounted);
+        return summary;
+    };
+
+    acquire_flush_lock();
+    if let Err(e) = ensure_config_directory(drive) {
+        release_flush_lock();
+        summary.error = Some(e);
+        return summary;
+    }
+
+    unsafe {
+        for hive in cm_enumerate_hives() {
+            if !is_persistent(hive) || !(all || hive.is_dirty()) {
+                continue;
+            }
+            match flush_hive_locked(hive, drive) {
+                Ok(()) => summary.written += 1,
+                Err(e) => {
+                    crate::serial_println!("[CM] Failed to flush hive {}: {:?}", hive.name.as_str(), e);
+                    summary.failed += 1;
+                    summary.error.get_or_insert(e);
+                }
+            }
+        }
+    }
+    release_flush_lock();
+
+    FLUSH_COUNT.fetch_add(summary.written as u64, Ordering::Relaxed);
+    FLUSH_FAILURES.fetch_add(summary.failed as u64, Ordering::Relaxed);
+    LAST_FLUSH.store(apic::get_tick_count(), Ordering::Relaxed);
+    summary
+}
+
+/// Write one hive back to disk, dirty or not
+pub fn cm_flush_hive(hive_index: u16) -> Result<(), FsStatus> {
+    let hive = unsafe { cm_get_hive(hive_index) }.ok_or(FsStatus::InvalidParameter)?;
+    if !is_persistent(hive) {
+        return Ok(());
+    }
+    let drive = crate::fs::mount::get_system_drive().ok_or(FsStatus::NotMounted)?;
+
+    acquire_flush_lock();
+    let result = ensure_config_directory(drive).and_then(|()| unsafe { flush_hive_locked(hive, drive) });
+    release_flush_lock();
+
+    match result {
+        Ok(()) => FLUSH_COUNT.fetch_add(1, Ordering::Relaxed),
+        Err(_) => FLUSH_FAILURES.fetch_add(1, Ordering::Relaxed),
+    };
+    LAST_FLUSH.store(apic::get_tick_count(), Ordering::Relaxed);
+    result
+}
+
+/// Number of hives with unsaved changes
+pub fn cm_dirty_hive_count() -> u32 {
+    unsafe { cm_enumerate_hives().filter(|h| is_persistent(h) && h.is_dirty()).count() as u32 }
+}
+
+// ============================================================================
+// Load
+// ============================================================================
+
+/// Load saved hive images over the boot-time defaults
+///
+/// For each hive the newer of the primary file and its log is used,
+/// provided it verifies. A hive recovered from its log stays dirty, so
+/// the next flush repairs the primary.
+///
+/// # Returns
+/// The number of hives loaded
+pub fn cm_load_hives() -> u32 {
+    let Some(drive) = crate::fs::mount::get_system_drive() else {
+        crate::serial_println!("[CM] No system drive; registry is not persistent this session");
+        return 0;
+    };
+
+    let mut loaded = 0u32;
+    unsafe {
+        for index in 0..hive::MAX_HIVES as u16 {
+            let Some(hive) = cm_get_hive(index) else { continue };
+            if !is_persistent(hive) {
+                continue;
+            }
+
+            let primary_path = format!("{}:\\{}\\{}", drive, CONFIG_DIRECTORY, hive_file_name(hive));
+            let log_path = format!("{}.LOG", primary_path);
+            let primary = read_file(&primary_path).ok();
+            let log = read_file(&log_path).ok();
+            let primary_image = primary.as_deref().and_then(verify_image);
+            let log_image = log.as_deref().and_then(verify_image);
+
+            let (header, body, from_log) = match (primary_image, log_image) {
+                (Some(p), Some(l)) if l.0.sequence > p.0.sequence => (l.0, l.1, true),
+                (Some(p), _) => (p.0, p.1, false),
+                (None, Some(l)) => (l.0, l.1, true),
+                (None, None) => {
+                    if primary.is_some() || log.is_some() {
+                        crate::serial_println!("[CM] Hive {}: no intact image, using defaults", hive.name.as_str());
+                    }
+                    continue;
+                }
+            };
+
+            match apply_image(hive, body) {
+                Some(keys) => {
+                    hive.sequence.store(header.sequence, Ordering::SeqCst);
+                    if from_log {
+                        crate::serial_println!("[CM] Hive {}: recovered {} keys from {}",
+                            hive.name.as_str(), keys, log_path);
+                    } else {
+                        hive.clear_flag(hive_flags::HIVE_DIRTY);
+                        crate::serial_println!("[CM] Hive {}: loaded {} keys from {}",
+                            hive.name.as_str(), keys, primary_path);
+                    }
+                    loaded += 1;
+                }
+                None => {
+                    crate::serial_println!("[CM] Hive {}: malformed image, using defaults", hive.name.as_str());
+                }
+            }
+        }
+    }
+
+    HIVES_LOADED.store(loaded, Ordering::Relaxed);
+    loaded
+}
+
+// ============================================================================
+// Lazy Flusher
+// ============================================================================
+
+/// Lazy flusher thread
+fn lazy_flush_thread() {
+    while !STOP_REQUESTED.load(Ordering::Acquire) {
+        unsafe {
+            crate::ke::wait::ke_wait_for_single_object(
+                &mut (*core::ptr::addr_of_mut!(WAKE_EVENT)).header as *mut _,
+                Some(LAZY_FLUSH_INTERVAL_MS),
+            );
+        }
+        if !STOP_REQUESTED.load(Ordering::Acquire) && cm_dirty_hive_count() > 0 {
+            cm_flush_hives(false);
+        }
+    }
+
+    FLUSHER_RUNNING.store(false, Ordering::Release);
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+/// Start the lazy flusher
+///
+/// # Returns
+/// `false` if it is already running or the thread could not be created
+pub fn cm_start_lazy_flusher() -> bool {
+    if FLUSHER_RUNNING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    STOP_REQUESTED.store(false, Ordering::Release);
+    unsafe {
+        (*core::ptr::addr_of_mut!(WAKE_EVENT)).init(EventType::Synchronization, false);
+        if crate::ke::init::create_thread(FLUSHER_PRIORITY, lazy_flush_thread).is_none() {
+            FLUSHER_RUNNING.store(false, Ordering::Release);
+            return false;
+        }
+    }
+    true
+}
+
+/// Stop the lazy flusher and write every dirty hive
+///
+/// Waits for a lazy pass already in progress; the shutdown sequence
+/// bounds the wait.
+pub fn cm_shutdown_flush() -> HiveFlushSummary {
+    if FLUSHER_RUNNING.load(Ordering::Acquire) {
+        STOP_REQUESTED.store(true, Ordering::Release);
+        unsafe { (*core::ptr::addr_of!(WAKE_EVENT)).set() };
+    }
+    cm_flush_hives(false)
+}
+
+/// Persistence status
+#[derive(Debug, Clone, Copy)]
+pub struct HiveFlushStatus {
+    pub lazy_flusher_running: bool,
+    pub hives_loaded: u32,
+    pub dirty_hives: u32,
+    /// Hive writes since boot
+    pub flushes: u64,
+    pub failures: u64,
+    /// Tick of the last flush pass (0 = none yet)
+    pub last_flush: u64,
+}
+
+/// Get the persistence status
+pub fn cm_flush_status() -> HiveFlushStatus {
+    HiveFlushStatus {
+        lazy_flusher_running: FLUSHER_RUNNING.load(Ordering::Acquire),
+        hives_loaded: HIVES_LOADED.load(Ordering::Relaxed),
+        dirty_hives: cm_dirty_hive_count(),
+        flushes: FLUSH_COUNT.load(Ordering::Relaxed),
+        failures: FLUSH_FAILURES.load(Ordering::Relaxed),
+        last_flush: LAST_FLUSH.load(Ordering::Relaxed),
+    }
+}
@@ -522,7 +522,7 @@ pub unsafe fn cm_init_hardware_hive_structure() {
 }
 
 /// Helper: Create a subkey under a parent
-unsafe fn create_subkey(parent_key: u32, name: &str, hive_index: u16) -> Option<u32> {
+pub(super) unsafe fn create_subkey(parent_key: u32, name: &str, hive_index: u16) -> Option<u32> {
     let parent = cm_get_key_mut(parent_key)?;
 
     // Allocate new key
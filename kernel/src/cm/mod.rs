@@ -40,6 +40,8 @@ pub mod key;
 pub mod cell;
 pub mod hive;
 pub mod operations;
+pub mod security;
+pub mod flush;
 
 // Re-export value types
 pub use value::{
@@ -164,6 +166,24 @@ pub use operations::{
     MAX_INFO_VALUE_DATA,
 };
 
+// Re-export hive persistence
+pub use flush::{
+    HiveFlushSummary,
+    HiveFlushStatus,
+    LAZY_FLUSH_INTERVAL_MS,
+    cm_hive_file_path,
+    cm_flush_hive,
+    cm_flush_hives,
+    cm_dirty_hive_count,
+    cm_load_hives,
+    cm_start_lazy_flusher,
+    cm_shutdown_flush,
+    cm_flush_status,
+};
+
+// Re-export key security
+pub use security::{KEY_GENERIC_MAPPING, cm_access_check};
+
 /// Initialize the Configuration Manager
 ///
 /// This initializes all registry subsystems and creates the standard hives:
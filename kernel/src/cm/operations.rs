@@ -11,6 +11,9 @@
 //! - `cm_delete_value` - Delete a registry value
 //! - `cm_enumerate_key` - Enumerate subkeys
 //! - `cm_enumerate_value` - Enumerate values
+//!
+//! Opening, creating and deleting keys and setting and deleting values
+//! are access checked against the calling process (see `security`).
 
 extern crate alloc;
 
@@ -21,6 +24,8 @@ use super::key::{
 };
 use super::value::CmKeyValue;
 use super::hive::{cm_get_hive, cm_get_hive_mut, hive_indices};
+use super::security::cm_access_check;
+use crate::se::standard_rights;
 
 /// Registry status codes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -218,8 +223,9 @@ unsafe fn resolve_hklm_path(subpath: &str) -> Option<(u16, u32, &str)> {
 
 /// Open a registry key by path
 pub unsafe fn cm_open_key(path: &str) -> Result<CmKeyHandle, CmStatus> {
-    let (_hive_idx, start_key, subpath) = resolve_root_path(path)
+    let (hive_idx, start_key, subpath) = resolve_root_path(path)
         .ok_or(CmStatus::KeyNotFound)?;
+    cm_access_check(hive_idx, access_rights::KEY_READ)?;
 
     // Walk the path
     let mut current_key = start_key;
@@ -247,6 +253,7 @@ pub unsafe fn cm_create_key(
 
     if subpath.is_empty() {
         // Trying to create root key - just return it
+        cm_access_check(hive_idx, access_rights::KEY_READ)?;
         return Ok((CmKeyHandle::new(start_key), CmDisposition::OpenedExisting));
     }
 
@@ -267,6 +274,7 @@ pub unsafe fn cm_create_key(
             }
             None => {
                 // Create new subkey
+                cm_access_check(hive_idx, access_rights::KEY_CREATE_SUB_KEY)?;
                 let new_key_idx = cm_allocate_key().ok_or(CmStatus::OutOfMemory)?;
 
                 // Initialize the key
@@ -300,6 +308,7 @@ pub unsafe fn cm_create_key(
     let disposition = if created {
         CmDisposition::CreatedNew
     } else {
+        cm_access_check(hive_idx, access_rights::KEY_READ)?;
         CmDisposition::OpenedExisting
     };
 
@@ -324,6 +333,10 @@ pub unsafe fn cm_delete_key(path: &str) -> CmStatus {
         None => return CmStatus::InvalidKey,
     };
 
+    if let Err(e) = cm_access_check(key.hive_index, standard_rights::DELETE) {
+        return e;
+    }
+
     // Can't delete key with subkeys
     if key.subkey_count() > 0 {
         return CmStatus::KeyHasSubkeys;
@@ -383,6 +396,9 @@ pub unsafe fn cm_set_value(
     };
 
     let hive_idx = key.hive_index;
+    if let Err(e) = cm_access_check(hive_idx, access_rights::KEY_SET_VALUE) {
+        return e;
+    }
 
     if key.add_value(value) {
         if let Some(hive) = cm_get_hive_mut(hive_idx) {
@@ -430,6 +446,9 @@ pub unsafe fn cm_delete_value(handle: CmKeyHandle, name: &str) -> CmStatus {
     };
 
     let hive_idx = key.hive_index;
+    if let Err(e) = cm_access_check(hive_idx, access_rights::KEY_SET_VALUE) {
+        return e;
+    }
 
     if key.remove_value(name) {
         if let Some(hive) = cm_get_hive_mut(hive_idx) {
@@ -1220,11 +1239,15 @@ pub unsafe fn cm_flush_key(handle: CmKeyHandle) -> CmStatus {
         None => return CmStatus::InvalidKey,
     };
 
-    // Clear dirty flag
-    key.clear_flag(key_flags::KEY_DIRTY);
-
-    // In a full implementation, this would write to the hive file
-    CmStatus::Success
+    // Flushing a key writes out its whole hive
+    let hive_index = key.hive_index;
+    match super::flush::cm_flush_hive(hive_index) {
+        Ok(()) => {
+            key.clear_flag(key_flags::KEY_DIRTY);
+            CmStatus::Success
+        }
+        Err(_) => CmStatus::IoError,
+    }
 }
 
 /// Notify on key change (placeholder for future implementation)
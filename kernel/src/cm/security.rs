@@ -0,0 +1,55 @@
+//! Registry Key Security
+//!
+//! Keys don't carry their own security descriptors; every key gets the
+//! default descriptor of its hive:
+//!
+//! - SAM and SECURITY: LocalSystem only
+//! - all other hives: LocalSystem and Administrators full control,
+//!   Users read
+//!
+//! Opening a key needs KEY_READ, creating one KEY_CREATE_SUB_KEY, setting
+//! or deleting a value KEY_SET_VALUE and deleting a key DELETE, checked
+//! against the token of the calling process.
+
+use crate::se::{
+    generic_rights, standard_rights, GenericMapping, SimpleSecurityDescriptor,
+    SID_BUILTIN_ADMINISTRATORS, SID_BUILTIN_USERS, SID_LOCAL_SYSTEM,
+};
+use super::hive::hive_indices;
+use super::operations::{access_rights, CmStatus};
+
+/// Generic mapping for registry keys
+pub const KEY_GENERIC_MAPPING: GenericMapping = GenericMapping {
+    generic_read: standard_rights::STANDARD_RIGHTS_READ | access_rights::KEY_READ,
+    generic_write: standard_rights::STANDARD_RIGHTS_WRITE | access_rights::KEY_WRITE,
+    generic_execute: standard_rights::STANDARD_RIGHTS_EXECUTE | access_rights::KEY_EXECUTE,
+    generic_all: standard_rights::STANDARD_RIGHTS_ALL | access_rights::KEY_ALL_ACCESS,
+};
+
+/// Fill in the default security descriptor of a hive's keys
+fn describe_hive(hive_index: u16, sd: &mut SimpleSecurityDescriptor) {
+    sd.set_owner(SID_BUILTIN_ADMINISTRATORS);
+    sd.add_access_allowed(SID_LOCAL_SYSTEM, generic_rights::GENERIC_ALL);
+
+    if hive_index == hive_indices::HIVE_SAM || hive_index == hive_indices::HIVE_SECURITY {
+        return;
+    }
+
+    sd.add_access_allowed(SID_BUILTIN_ADMINISTRATORS, generic_rights::GENERIC_ALL);
+    sd.add_access_allowed(SID_BUILTIN_USERS, generic_rights::GENERIC_READ);
+}
+
+/// Check the calling process's access to a key in a hive
+pub fn cm_access_check(hive_index: u16, desired_access: u32) -> Result<(), CmStatus> {
+    let result = unsafe {
+        crate::ps::ps_access_check_object(desired_access, &KEY_GENERIC_MAPPING, |sd| {
+            describe_hive(hive_index, sd)
+        })
+    };
+
+    result.map_err(|reason| {
+        crate::serial_println!("[CM] Access {:#x} to hive {} denied: {:?}",
+            desired_access, hive_index, reason);
+        CmStatus::AccessDenied
+    })
+}
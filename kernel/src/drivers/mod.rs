@@ -4,3 +4,4 @@
 
 pub mod virtio;
 pub mod serial;
+pub mod tpm;
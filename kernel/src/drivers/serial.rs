@@ -281,9 +281,7 @@ impl SerialPort {
             port_write(self.base_port + regs::DATA, 0xAE);
 
             // Wait a bit for loopback
-            for _ in 0..1000 {
-                core::hint::spin_loop();
-            }
+            crate::hal::timer::ke_stall_execution_processor(10);
 
             if port_read(self.base_port + regs::DATA) != 0xAE {
                 self.state = PortState::Error;
@@ -553,6 +551,26 @@ pub fn init() -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Initialize a single port (1-4) on demand
+///
+/// Succeeds at once if the port is already up; fails if no UART answers.
+pub fn init_port(port_num: u8) -> Result<(), &'static str> {
+    if !(1..=4).contains(&port_num) {
+        return Err("Invalid port number");
+    }
+    let index = (port_num - 1) as usize;
+    let _guard = SERIAL_LOCK.lock();
+    unsafe {
+        if SERIAL_PORTS[index].is_some() {
+            return Ok(());
+        }
+        let mut port = SerialPort::new(port_num);
+        port.init(BaudRate::Baud115200)?;
+        SERIAL_PORTS[index] = Some(port);
+    }
+    Ok(())
+}
+
 /// Get a serial port by number (1-4)
 pub fn get_port(port_num: u8) -> Option<&'static mut SerialPort> {
     if port_num < 1 || port_num > 4 {
@@ -0,0 +1,144 @@
+//! TPM Command Response Buffer Transport
+//!
+//! The CRB interface exchanges whole commands through memory buffers
+//! described by the control area: the command is copied into the command
+//! buffer, `CTRL_START` is set and the TPM clears it when the response is
+//! in the response buffer. Only locality 0 and the plain start method
+//! (no ACPI `_DSM` or SMC doorbell) are supported.
+
+use super::{mmio_read32, mmio_read64, mmio_read8, mmio_write32, mmio_write8, wait_for, TpmError};
+use super::{TIMEOUT_A_MS, TIMEOUT_C_MS, TIMEOUT_D_MS, TPM_HEADER_SIZE};
+
+/// Register offsets (locality 0)
+mod reg {
+    pub const LOC_STATE: u64 = 0x00;
+    pub const LOC_CTRL: u64 = 0x08;
+    pub const LOC_STS: u64 = 0x0C;
+    pub const INTERFACE_ID: u64 = 0x30;
+    pub const CTRL_REQ: u64 = 0x40;
+    pub const CTRL_STS: u64 = 0x44;
+    pub const CTRL_START: u64 = 0x4C;
+    pub const CMD_SIZE: u64 = 0x58;
+    pub const CMD_LADDR: u64 = 0x5C;
+    pub const CMD_HADDR: u64 = 0x60;
+    pub const RSP_SIZE: u64 = 0x64;
+    pub const RSP_ADDR: u64 = 0x68;
+}
+
+/// LOC_CTRL requests
+const LOC_CTRL_REQUEST_ACCESS: u32 = 0x1;
+const LOC_CTRL_RELINQUISH: u32 = 0x2;
+/// LOC_STS.Granted
+const LOC_STS_GRANTED: u32 = 0x1;
+/// LOC_STATE.tpmRegValidSts
+const LOC_STATE_REG_VALID: u8 = 0x80;
+
+/// CTRL_REQ requests
+const CTRL_REQ_CMD_READY: u32 = 0x1;
+const CTRL_REQ_GO_IDLE: u32 = 0x2;
+/// CTRL_STS.tpmSts (fatal error)
+const CTRL_STS_ERROR: u32 = 0x1;
+
+/// CRB interface at `base`
+#[derive(Debug, Clone, Copy)]
+pub struct Crb {
+    base: u64,
+    command: u64,
+    command_size: usize,
+    response: u64,
+    response_size: usize,
+}
+
+impl Crb {
+    /// Check for a CRB interface at `base` and read its buffer layout
+    pub fn probe(base: u64) -> Option<Self> {
+        let state = unsafe { mmio_read8(base + reg::LOC_STATE) };
+        if state == 0xFF || state & LOC_STATE_REG_VALID == 0 {
+            return None;
+        }
+        let (command, command_size, response, response_size) = unsafe {
+            let low = mmio_read32(base + reg::CMD_LADDR) as u64;
+            let high = mmio_read32(base + reg::CMD_HADDR) as u64;
+            (
+                (high << 32) | low,
+                mmio_read32(base + reg::CMD_SIZE) as usize,
+                mmio_read64(base + reg::RSP_ADDR),
+                mmio_read32(base + reg::RSP_SIZE) as usize,
+            )
+        };
+        if command == 0 || response == 0 || command_size < TPM_HEADER_SIZE || response_size < TPM_HEADER_SIZE {
+            return None;
+        }
+        Some(Self { base, command, command_size, response, response_size })
+    }
+
+    /// TPM_CRB_INTF_ID (low half)
+    pub fn interface_id(&self) -> u32 {
+        unsafe { mmio_read32(self.base + reg::INTERFACE_ID) }
+    }
+
+    /// Command and response buffer sizes
+    pub fn buffer_sizes(&self) -> (usize, usize) {
+        (self.command_size, self.response_size)
+    }
+
+    fn request_locality(&self) -> Result<(), TpmError> {
+        unsafe { mmio_write32(self.base + reg::LOC_CTRL, LOC_CTRL_REQUEST_ACCESS) };
+        wait_for(TIMEOUT_A_MS, || {
+            (unsafe { mmio_read32(self.base + reg::LOC_STS) }) & LOC_STS_GRANTED != 0
+        })
+    }
+
+    fn relinquish_locality(&self) {
+        unsafe { mmio_write32(self.base + reg::LOC_CTRL, LOC_CTRL_RELINQUISH) };
+    }
+
+    /// Send `command` and read the response into `response`
+    ///
+    /// Returns the response length.
+    pub fn transmit(&self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        if command.len() > self.command_size {
+            return Err(TpmError::BufferTooSmall);
+        }
+        self.request_locality()?;
+        let result = self.execute(command, response);
+        unsafe { mmio_write32(self.base + reg::CTRL_REQ, CTRL_REQ_GO_IDLE) };
+        self.relinquish_locality();
+        result
+    }
+
+    fn execute(&self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        // Leave idle
+        unsafe { mmio_write32(self.base + reg::CTRL_REQ, CTRL_REQ_CMD_READY) };
+        wait_for(TIMEOUT_C_MS, || {
+            (unsafe { mmio_read32(self.base + reg::CTRL_REQ) }) & CTRL_REQ_CMD_READY == 0
+        })?;
+        if unsafe { mmio_read32(self.base + reg::CTRL_STS) } & CTRL_STS_ERROR != 0 {
+            return Err(TpmError::Interface);
+        }
+
+        for (offset, &byte) in command.iter().enumerate() {
+            unsafe { mmio_write8(self.command + offset as u64, byte) };
+        }
+        unsafe { mmio_write32(self.base + reg::CTRL_START, 1) };
+        wait_for(TIMEOUT_D_MS, || unsafe { mmio_read32(self.base + reg::CTRL_START) } == 0)?;
+
+        if response.len() < TPM_HEADER_SIZE {
+            return Err(TpmError::BufferTooSmall);
+        }
+        for (offset, byte) in response[..TPM_HEADER_SIZE].iter_mut().enumerate() {
+            *byte = unsafe { mmio_read8(self.response + offset as u64) };
+        }
+        let size = u32::from_be_bytes([response[2], response[3], response[4], response[5]]) as usize;
+        if size < TPM_HEADER_SIZE || size > self.response_size {
+            return Err(TpmError::Malformed);
+        }
+        if size > response.len() {
+            return Err(TpmError::BufferTooSmall);
+        }
+        for (offset, byte) in response[TPM_HEADER_SIZE..size].iter_mut().enumerate() {
+            *byte = unsafe { mmio_read8(self.response + (TPM_HEADER_SIZE + offset) as u64) };
+        }
+        Ok(size)
+    }
+}
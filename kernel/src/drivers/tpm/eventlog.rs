@@ -0,0 +1,300 @@
+//! TCG Event Log
+//!
+//! The crypto-agile (TCG 2.0) event log the firmware built during measured
+//! boot, copied by the loader and handed over in the TPM_EVENT_LOG boot
+//! tag. The log starts with a SHA-1 format `TCG_PCR_EVENT` carrying the
+//! "Spec ID Event03" structure, which lists the digest algorithms and
+//! their sizes; every following `TCG_PCR_EVENT2` carries one digest per
+//! algorithm.
+//!
+//! Replaying the log (extending a zero PCR with every event's digest)
+//! must reproduce the TPM's PCR values if the log is complete.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{alg, SHA256_DIGEST_SIZE};
+use crate::rtl::hash::Sha256Context;
+
+/// Size of a TCG_PCR_EVENT without its event data
+const PCR_EVENT_HEADER_SIZE: usize = 32;
+
+/// Digest algorithms tracked per log (and per event)
+pub const MAX_LOG_ALGORITHMS: usize = 4;
+
+/// Event types (TCG PC Client Platform Firmware Profile)
+pub mod event_type {
+    pub const PREBOOT_CERT: u32 = 0x0000_0000;
+    pub const POST_CODE: u32 = 0x0000_0001;
+    pub const NO_ACTION: u32 = 0x0000_0003;
+    pub const SEPARATOR: u32 = 0x0000_0004;
+    pub const ACTION: u32 = 0x0000_0005;
+    pub const EVENT_TAG: u32 = 0x0000_0006;
+    pub const S_CRTM_CONTENTS: u32 = 0x0000_0007;
+    pub const S_CRTM_VERSION: u32 = 0x0000_0008;
+    pub const CPU_MICROCODE: u32 = 0x0000_0009;
+    pub const PLATFORM_CONFIG_FLAGS: u32 = 0x0000_000A;
+    pub const TABLE_OF_DEVICES: u32 = 0x0000_000B;
+    pub const COMPACT_HASH: u32 = 0x0000_000C;
+    pub const IPL: u32 = 0x0000_000D;
+    pub const IPL_PARTITION_DATA: u32 = 0x0000_000E;
+    pub const NONHOST_CODE: u32 = 0x0000_000F;
+    pub const NONHOST_CONFIG: u32 = 0x0000_0010;
+    pub const NONHOST_INFO: u32 = 0x0000_0011;
+    pub const OMIT_BOOT_DEVICE_EVENTS: u32 = 0x0000_0012;
+    pub const EFI_VARIABLE_DRIVER_CONFIG: u32 = 0x8000_0001;
+    pub const EFI_VARIABLE_BOOT: u32 = 0x8000_0002;
+    pub const EFI_BOOT_SERVICES_APPLICATION: u32 = 0x8000_0003;
+    pub const EFI_BOOT_SERVICES_DRIVER: u32 = 0x8000_0004;
+    pub const EFI_RUNTIME_SERVICES_DRIVER: u32 = 0x8000_0005;
+    pub const EFI_GPT_EVENT: u32 = 0x8000_0006;
+    pub const EFI_ACTION: u32 = 0x8000_0007;
+    pub const EFI_PLATFORM_FIRMWARE_BLOB: u32 = 0x8000_0008;
+    pub const EFI_HANDOFF_TABLES: u32 = 0x8000_0009;
+    pub const EFI_PLATFORM_FIRMWARE_BLOB2: u32 = 0x8000_000A;
+    pub const EFI_HANDOFF_TABLES2: u32 = 0x8000_000B;
+    pub const EFI_HCRTM_EVENT: u32 = 0x8000_0010;
+    pub const EFI_VARIABLE_AUTHORITY: u32 = 0x8000_00E0;
+    pub const EFI_SPDM_FIRMWARE_BLOB: u32 = 0x8000_00E1;
+    pub const EFI_SPDM_FIRMWARE_CONFIG: u32 = 0x8000_00E2;
+}
+
+/// Display name of an event type
+pub fn event_type_name(event_type: u32) -> &'static str {
+    use event_type::*;
+    match event_type {
+        PREBOOT_CERT => "PREBOOT_CERT",
+        POST_CODE => "POST_CODE",
+        NO_ACTION => "NO_ACTION",
+        SEPARATOR => "SEPARATOR",
+        ACTION => "ACTION",
+        EVENT_TAG => "EVENT_TAG",
+        S_CRTM_CONTENTS => "S_CRTM_CONTENTS",
+        S_CRTM_VERSION => "S_CRTM_VERSION",
+        CPU_MICROCODE => "CPU_MICROCODE",
+        PLATFORM_CONFIG_FLAGS => "PLATFORM_CONFIG_FLAGS",
+        TABLE_OF_DEVICES => "TABLE_OF_DEVICES",
+        COMPACT_HASH => "COMPACT_HASH",
+        IPL => "IPL",
+        IPL_PARTITION_DATA => "IPL_PARTITION_DATA",
+        NONHOST_CODE => "NONHOST_CODE",
+        NONHOST_CONFIG => "NONHOST_CONFIG",
+        NONHOST_INFO => "NONHOST_INFO",
+        OMIT_BOOT_DEVICE_EVENTS => "OMIT_BOOT_DEVICE_EVENTS",
+        EFI_VARIABLE_DRIVER_CONFIG => "EFI_VARIABLE_DRIVER_CONFIG",
+        EFI_VARIABLE_BOOT => "EFI_VARIABLE_BOOT",
+        EFI_BOOT_SERVICES_APPLICATION => "EFI_BOOT_SERVICES_APPLICATION",
+        EFI_BOOT_SERVICES_DRIVER => "EFI_BOOT_SERVICES_DRIVER",
+        EFI_RUNTIME_SERVICES_DRIVER => "EFI_RUNTIME_SERVICES_DRIVER",
+        EFI_GPT_EVENT => "EFI_GPT_EVENT",
+        EFI_ACTION => "EFI_ACTION",
+        EFI_PLATFORM_FIRMWARE_BLOB => "EFI_PLATFORM_FIRMWARE_BLOB",
+        EFI_HANDOFF_TABLES => "EFI_HANDOFF_TABLES",
+        EFI_PLATFORM_FIRMWARE_BLOB2 => "EFI_PLATFORM_FIRMWARE_BLOB2",
+        EFI_HANDOFF_TABLES2 => "EFI_HANDOFF_TABLES2",
+        EFI_HCRTM_EVENT => "EFI_HCRTM_EVENT",
+        EFI_VARIABLE_AUTHORITY => "EFI_VARIABLE_AUTHORITY",
+        EFI_SPDM_FIRMWARE_BLOB => "EFI_SPDM_FIRMWARE_BLOB",
+        EFI_SPDM_FIRMWARE_CONFIG => "EFI_SPDM_FIRMWARE_CONFIG",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Event log location from the boot info
+static LOG_ADDR: AtomicU64 = AtomicU64::new(0);
+static LOG_SIZE: AtomicU64 = AtomicU64::new(0);
+static LOG_FLAGS: AtomicU64 = AtomicU64::new(0);
+static FINAL_EVENTS_ADDR: AtomicU64 = AtomicU64::new(0);
+
+/// Remember the event log handed over by the loader
+pub fn init(boot_info: &crate::BootInfo) {
+    LOG_ADDR.store(boot_info.tpm_event_log_addr, Ordering::Release);
+    LOG_SIZE.store(boot_info.tpm_event_log_size, Ordering::Release);
+    LOG_FLAGS.store(boot_info.tpm_event_log_flags, Ordering::Release);
+    FINAL_EVENTS_ADDR.store(boot_info.tpm_final_events_addr, Ordering::Release);
+}
+
+/// Physical address of the EFI_TCG2_FINAL_EVENTS_TABLE (0 if none)
+pub fn final_events_table() -> u64 {
+    FINAL_EVENTS_ADDR.load(Ordering::Acquire)
+}
+
+/// A parsed view of the firmware event log
+#[derive(Clone, Copy)]
+pub struct TcgEventLog {
+    data: &'static [u8],
+    /// Offset of the first TCG_PCR_EVENT2
+    first_event: usize,
+    algorithms: [(u16, u16); MAX_LOG_ALGORITHMS],
+    algorithm_count: usize,
+    truncated: bool,
+}
+
+/// One TCG_PCR_EVENT2 entry
+#[derive(Clone, Copy)]
+pub struct TcgEvent<'a> {
+    /// Offset in the log
+    pub offset: usize,
+    pub pcr: u32,
+    pub event_type: u32,
+    digests: [(u16, &'a [u8]); MAX_LOG_ALGORITHMS],
+    digest_count: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> TcgEvent<'a> {
+    /// Digest for `algorithm`, if the event carries one
+    pub fn digest(&self, algorithm: u16) -> Option<&'a [u8]> {
+        self.digests[..self.digest_count]
+            .iter()
+            .find(|(id, _)| *id == algorithm)
+            .map(|(_, digest)| *digest)
+    }
+
+    /// All (algorithm, digest) pairs of the event
+    pub fn digests(&self) -> &[(u16, &'a [u8])] {
+        &self.digests[..self.digest_count]
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// The event log, if the loader passed a crypto-agile one
+pub fn event_log() -> Option<TcgEventLog> {
+    let addr = LOG_ADDR.load(Ordering::Acquire);
+    let size = LOG_SIZE.load(Ordering::Acquire) as usize;
+    if addr == 0 || size < PCR_EVENT_HEADER_SIZE {
+        return None;
+    }
+    // Loader data is identity mapped and never reclaimed
+    let data = unsafe { core::slice::from_raw_parts(addr as *const u8, size) };
+    TcgEventLog::parse(data, LOG_FLAGS.load(Ordering::Acquire) & crate::bootinfo::TPM_EVENT_LOG_TRUNCATED as u64 != 0)
+}
+
+impl TcgEventLog {
+    fn parse(data: &'static [u8], truncated: bool) -> Option<Self> {
+        let header_event_size = read_u32(data, 28)? as usize;
+        let spec = data.get(PCR_EVENT_HEADER_SIZE..PCR_EVENT_HEADER_SIZE + header_event_size)?;
+        if !spec.starts_with(b"Spec ID Event03\0") {
+            return None;
+        }
+        let count = read_u32(spec, 24)? as usize;
+        let mut algorithms = [(0u16, 0u16); MAX_LOG_ALGORITHMS];
+        if count > MAX_LOG_ALGORITHMS {
+            return None;
+        }
+        for (index, entry) in algorithms[..count].iter_mut().enumerate() {
+            *entry = (read_u16(spec, 28 + index * 4)?, read_u16(spec, 30 + index * 4)?);
+        }
+        Some(Self {
+            data,
+            first_event: PCR_EVENT_HEADER_SIZE + header_event_size,
+            algorithms,
+            algorithm_count: count,
+            truncated,
+        })
+    }
+
+    /// Log size in bytes
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The firmware dropped events for lack of space
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// (algorithm, digest size) pairs from the Spec ID event
+    pub fn algorithms(&self) -> &[(u16, u16)] {
+        &self.algorithms[..self.algorithm_count]
+    }
+
+    fn digest_size(&self, algorithm: u16) -> Option<usize> {
+        self.algorithms()
+            .iter()
+            .find(|(id, _)| *id == algorithm)
+            .map(|(_, size)| *size as usize)
+    }
+
+    /// Iterate over the TCG_PCR_EVENT2 entries
+    pub fn events(&self) -> TcgEventIter<'_> {
+        TcgEventIter { log: self, offset: self.first_event }
+    }
+
+    /// Event count (stops at the first malformed entry)
+    pub fn event_count(&self) -> usize {
+        self.events().count()
+    }
+
+    /// Recompute the SHA-256 value of `pcr` from the log
+    ///
+    /// Returns None if the log has no SHA-256 bank.
+    pub fn replay_sha256(&self, pcr: u32) -> Option<[u8; SHA256_DIGEST_SIZE]> {
+        self.digest_size(alg::SHA256)?;
+        let mut value = [0u8; SHA256_DIGEST_SIZE];
+        for event in self.events().filter(|event| event.pcr == pcr) {
+            if event.event_type == event_type::NO_ACTION {
+                // The H-CRTM locality marker sets PCR 0's starting value
+                if pcr == 0 && event.data.len() >= 17 && event.data.starts_with(b"StartupLocality\0") {
+                    value = [0; SHA256_DIGEST_SIZE];
+                    value[SHA256_DIGEST_SIZE - 1] = event.data[16];
+                }
+                continue;
+            }
+            let Some(digest) = event.digest(alg::SHA256) else { continue };
+            let mut ctx = Sha256Context::new();
+            ctx.update(&value);
+            ctx.update(digest);
+            value = ctx.finalize();
+        }
+        Some(value)
+    }
+}
+
+/// Iterator over the events of a log
+pub struct TcgEventIter<'a> {
+    log: &'a TcgEventLog,
+    offset: usize,
+}
+
+impl<'a> Iterator for TcgEventIter<'a> {
+    type Item = TcgEvent<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.log.data;
+        let start = self.offset;
+        let pcr = read_u32(data, start)?;
+        let event_type = read_u32(data, start + 4)?;
+        let count = read_u32(data, start + 8)? as usize;
+        if count > MAX_LOG_ALGORITHMS {
+            return None;
+        }
+
+        let mut digests: [(u16, &'static [u8]); MAX_LOG_ALGORITHMS] = [(0, &[]); MAX_LOG_ALGORITHMS];
+        let mut at = start + 12;
+        for slot in digests[..count].iter_mut() {
+            let algorithm = read_u16(data, at)?;
+            let size = self.log.digest_size(algorithm)?;
+            *slot = (algorithm, data.get(at + 2..at + 2 + size)?);
+            at += 2 + size;
+        }
+        let event_size = read_u32(data, at)? as usize;
+        let event = data.get(at + 4..at + 4 + event_size)?;
+        self.offset = at + 4 + event_size;
+
+        Some(TcgEvent {
+            offset: start,
+            pcr,
+            event_type,
+            digests,
+            digest_count: count,
+            data: event,
+        })
+    }
+}
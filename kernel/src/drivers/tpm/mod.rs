@@ -0,0 +1,819 @@
+//! TPM 2.0 Driver
+//!
+//! Drives a TPM 2.0 through the FIFO (TIS/PTP) or Command Response Buffer
+//! interface at the standard MMIO window, picking the interface from the
+//! ACPI TPM2 table's start method or, without one, from the interface ID
+//! register.
+//!
+//! Provides:
+//! - PCR read and extend for every active bank the kernel can hash
+//!   (SHA-1 and SHA-256)
+//! - `tpm_measure`, which hashes data, extends a PCR and records the
+//!   measurement in the kernel's own measurement list; the driver loader
+//!   uses it for driver images
+//! - TPM2_Quote with an ECC P-256 attestation key created on first use
+//!   under the endorsement hierarchy
+//! - the firmware's TCG event log, handed over by the loader (`eventlog`)
+//!
+//! The loader measures the kernel image and initrd into PCR 9 and the
+//! command line into PCR 8 before exiting boot services; the kernel
+//! continues with driver images in PCR 10.
+//!
+//! PCR reads and quotes for callers outside the kernel require the TCB
+//! privilege. All commands are serialized under `TPM_LOCK`.
+
+pub mod crb;
+pub mod eventlog;
+pub mod tis;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ke::SpinLock;
+use crate::rtl::hash::{sha1, sha256};
+
+/// Standard TPM MMIO window (locality 0)
+pub const TPM_BASE_ADDRESS: u64 = 0xFED4_0000;
+
+/// Command/response header: tag, size, command or response code
+pub const TPM_HEADER_SIZE: usize = 10;
+
+/// Largest response accepted
+pub const TPM_BUFFER_SIZE: usize = 4096;
+
+/// PCRs in a PC Client TPM
+pub const TPM_PCR_COUNT: u32 = 24;
+
+pub const SHA1_DIGEST_SIZE: usize = 20;
+pub const SHA256_DIGEST_SIZE: usize = 32;
+
+/// PCR for the command line (measured by the loader)
+pub const PCR_CMDLINE: u32 = 8;
+/// PCR for the kernel image and initrd (measured by the loader)
+pub const PCR_KERNEL: u32 = 9;
+/// PCR for driver images loaded by the kernel
+pub const PCR_DRIVERS: u32 = 10;
+
+/// Interface timeouts (PC Client PTP defaults)
+const TIMEOUT_A_MS: u32 = 750;
+const TIMEOUT_B_MS: u32 = 2000;
+const TIMEOUT_C_MS: u32 = 200;
+/// Long enough for key generation
+const TIMEOUT_D_MS: u32 = 30_000;
+
+/// TPM_ALG_ID values
+pub mod alg {
+    pub const SHA1: u16 = 0x0004;
+    pub const SHA256: u16 = 0x000B;
+    pub const SHA384: u16 = 0x000C;
+    pub const SHA512: u16 = 0x000D;
+    pub const NULL: u16 = 0x0010;
+    pub const SM3_256: u16 = 0x0012;
+    pub const ECDSA: u16 = 0x0018;
+    pub const ECC: u16 = 0x0023;
+}
+
+/// Display name of a hash algorithm
+pub fn alg_name(algorithm: u16) -> &'static str {
+    match algorithm {
+        alg::SHA1 => "sha1",
+        alg::SHA256 => "sha256",
+        alg::SHA384 => "sha384",
+        alg::SHA512 => "sha512",
+        alg::SM3_256 => "sm3_256",
+        _ => "unknown",
+    }
+}
+
+/// Structure tags
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+
+/// Command codes
+mod cc {
+    pub const CREATE_PRIMARY: u32 = 0x0000_0131;
+    pub const STARTUP: u32 = 0x0000_0144;
+    pub const QUOTE: u32 = 0x0000_0158;
+    pub const FLUSH_CONTEXT: u32 = 0x0000_0165;
+    pub const GET_CAPABILITY: u32 = 0x0000_017A;
+    pub const PCR_READ: u32 = 0x0000_017E;
+    pub const PCR_EXTEND: u32 = 0x0000_0182;
+}
+
+/// Response codes
+const TPM_RC_SUCCESS: u32 = 0x000;
+/// TPM2_Startup after the firmware already started the TPM
+const TPM_RC_INITIALIZE: u32 = 0x100;
+
+/// Handles
+const TPM_RH_ENDORSEMENT: u32 = 0x4000_000B;
+const TPM_RS_PW: u32 = 0x4000_0009;
+
+/// Capabilities and properties
+const TPM_CAP_TPM_PROPERTIES: u32 = 0x0000_0006;
+const TPM_CAP_PCRS: u32 = 0x0000_0005;
+const TPM_PT_MANUFACTURER: u32 = 0x105;
+const TPM_PT_FIRMWARE_VERSION_1: u32 = 0x10B;
+const TPM_PT_FIRMWARE_VERSION_2: u32 = 0x10C;
+
+/// ACPI TPM2 table start methods
+mod start_method {
+    pub const ACPI: u32 = 2;
+    pub const TIS: u32 = 6;
+    pub const CRB: u32 = 7;
+    pub const CRB_ACPI: u32 = 8;
+}
+
+/// Most PCR banks tracked
+pub const MAX_PCR_BANKS: usize = 4;
+
+/// Kernel measurement list entries kept
+pub const MAX_MEASUREMENTS: usize = 64;
+
+/// Description stored per measurement
+pub const MEASUREMENT_DESC_LEN: usize = 40;
+
+/// TPM driver errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpmError {
+    /// No TPM was found
+    NotPresent,
+    /// The TPM sits behind an interface this driver does not drive
+    Unsupported,
+    /// A register did not reach the expected state in time
+    Timeout,
+    /// The interface reported an error
+    Interface,
+    /// The response does not parse
+    Malformed,
+    /// A buffer is too small for the command or response
+    BufferTooSmall,
+    /// Bad PCR index, bank or argument
+    InvalidParameter,
+    /// The caller lacks the TCB privilege
+    AccessDenied,
+    /// The TPM returned a response code
+    Response(u32),
+}
+
+impl core::fmt::Display for TpmError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TpmError::NotPresent => write!(f, "no TPM present"),
+            TpmError::Unsupported => write!(f, "unsupported TPM interface"),
+            TpmError::Timeout => write!(f, "TPM timeout"),
+            TpmError::Interface => write!(f, "TPM interface error"),
+            TpmError::Malformed => write!(f, "malformed TPM response"),
+            TpmError::BufferTooSmall => write!(f, "buffer too small"),
+            TpmError::InvalidParameter => write!(f, "invalid parameter"),
+            TpmError::AccessDenied => write!(f, "access denied"),
+            TpmError::Response(rc) => write!(f, "TPM response code {:#x}", rc),
+        }
+    }
+}
+
+// ============================================================================
+// MMIO and Polling
+// ============================================================================
+
+unsafe fn mmio_read8(addr: u64) -> u8 {
+    core::ptr::read_volatile(addr as *const u8)
+}
+
+unsafe fn mmio_write8(addr: u64, value: u8) {
+    core::ptr::write_volatile(addr as *mut u8, value)
+}
+
+unsafe fn mmio_read32(addr: u64) -> u32 {
+    core::ptr::read_volatile(addr as *const u32)
+}
+
+unsafe fn mmio_write32(addr: u64, value: u32) {
+    core::ptr::write_volatile(addr as *mut u32, value)
+}
+
+unsafe fn mmio_read64(addr: u64) -> u64 {
+    let low = mmio_read32(addr) as u64;
+    let high = mmio_read32(addr + 4) as u64;
+    (high << 32) | low
+}
+
+/// Poll `condition` every 100us until it holds or `timeout_ms` passes
+fn wait_for(timeout_ms: u32, mut condition: impl FnMut() -> bool) -> Result<(), TpmError> {
+    for _ in 0..timeout_ms * 10 {
+        if condition() {
+            return Ok(());
+        }
+        crate::hal::timer::hal_stall_execution(100);
+    }
+    if condition() { Ok(()) } else { Err(TpmError::Timeout) }
+}
+
+// ============================================================================
+// Command Marshalling
+// ============================================================================
+
+/// Big-endian command builder
+struct Command {
+    buf: [u8; 512],
+    len: usize,
+}
+
+impl Command {
+    fn new(tag: u16, code: u32) -> Self {
+        let mut cmd = Self { buf: [0; 512], len: 0 };
+        cmd.u16(tag).u32(0).u32(code);
+        cmd
+    }
+
+    fn bytes(&mut self, data: &[u8]) -> &mut Self {
+        self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        self
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// TPM2B: u16 size and the data
+    fn sized(&mut self, data: &[u8]) -> &mut Self {
+        self.u16(data.len() as u16).bytes(data)
+    }
+
+    /// Authorization area with an empty password session
+    fn password_session(&mut self) -> &mut Self {
+        self.u32(9).u32(TPM_RS_PW).u16(0).u8(0).u16(0)
+    }
+
+    /// TPML_PCR_SELECTION with one bank and a 24-bit PCR mask
+    fn pcr_selection(&mut self, bank: u16, mask: u32) -> &mut Self {
+        self.u32(1).u16(bank).u8(3).bytes(&mask.to_le_bytes()[..3])
+    }
+
+    /// Patch the size field and return the marshalled command
+    fn finish(&mut self) -> &[u8] {
+        let size = (self.len as u32).to_be_bytes();
+        self.buf[2..6].copy_from_slice(&size);
+        &self.buf[..self.len]
+    }
+}
+
+/// Big-endian response reader
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Reader positioned after the response header
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: TPM_HEADER_SIZE }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], TpmError> {
+        let slice = self.data.get(self.pos..self.pos + count).ok_or(TpmError::Malformed)?;
+        self.pos += count;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, TpmError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, TpmError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, TpmError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn sized(&mut self) -> Result<&'a [u8], TpmError> {
+        let size = self.u16()? as usize;
+        self.bytes(size)
+    }
+}
+
+// ============================================================================
+// Device State
+// ============================================================================
+
+/// Transport in use
+#[derive(Debug, Clone, Copy)]
+enum TpmInterface {
+    None,
+    Tis(tis::Tis),
+    Crb(crb::Crb),
+}
+
+/// Driver state, protected by `TPM_LOCK`
+struct TpmDevice {
+    interface: TpmInterface,
+    info: TpmInfo,
+    /// Transient handle of the attestation key (0 until created)
+    quote_key: u32,
+    response: [u8; TPM_BUFFER_SIZE],
+}
+
+/// Identification of the detected TPM
+#[derive(Debug, Clone, Copy)]
+pub struct TpmInfo {
+    /// "TIS" or "CRB"
+    pub interface: &'static str,
+    /// ACPI TPM2 start method (0 without a TPM2 table)
+    pub start_method: u32,
+    /// TPM_DID_VID (TIS only)
+    pub did_vid: u32,
+    /// TPM_PT_MANUFACTURER as four ASCII characters
+    pub manufacturer: [u8; 4],
+    pub firmware_version: u64,
+    /// Active PCR banks
+    pub banks: [u16; MAX_PCR_BANKS],
+    pub bank_count: usize,
+}
+
+impl TpmInfo {
+    const EMPTY: Self = Self {
+        interface: "",
+        start_method: 0,
+        did_vid: 0,
+        manufacturer: [0; 4],
+        firmware_version: 0,
+        banks: [0; MAX_PCR_BANKS],
+        bank_count: 0,
+    };
+
+    /// Active PCR banks
+    pub fn banks(&self) -> &[u16] {
+        &self.banks[..self.bank_count]
+    }
+
+    /// Manufacturer ID as text (trailing NULs and spaces dropped)
+    pub fn manufacturer_str(&self) -> &str {
+        let text = core::str::from_utf8(&self.manufacturer).unwrap_or("????");
+        text.trim_end_matches(['\0', ' '])
+    }
+}
+
+static TPM_LOCK: SpinLock<TpmDevice> = SpinLock::new(TpmDevice {
+    interface: TpmInterface::None,
+    info: TpmInfo::EMPTY,
+    quote_key: 0,
+    response: [0; TPM_BUFFER_SIZE],
+});
+
+static TPM_PRESENT: AtomicBool = AtomicBool::new(false);
+
+impl TpmDevice {
+    /// Send a command and check the response code
+    ///
+    /// Returns the response length.
+    fn execute(&mut self, command: &[u8]) -> Result<usize, TpmError> {
+        let len = match self.interface {
+            TpmInterface::None => return Err(TpmError::NotPresent),
+            TpmInterface::Tis(tis) => tis.transmit(command, &mut self.response)?,
+            TpmInterface::Crb(crb) => crb.transmit(command, &mut self.response)?,
+        };
+        let rc = u32::from_be_bytes([self.response[6], self.response[7], self.response[8], self.response[9]]);
+        if rc != TPM_RC_SUCCESS {
+            return Err(TpmError::Response(rc));
+        }
+        Ok(len)
+    }
+
+    fn startup(&mut self) -> Result<(), TpmError> {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, cc::STARTUP);
+        cmd.u16(0); // TPM_SU_CLEAR
+        match self.execute(cmd.finish()) {
+            Ok(_) | Err(TpmError::Response(TPM_RC_INITIALIZE)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_properties(&mut self) -> Result<(), TpmError> {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, cc::GET_CAPABILITY);
+        cmd.u32(TPM_CAP_TPM_PROPERTIES).u32(TPM_PT_MANUFACTURER).u32(8);
+        let len = self.execute(cmd.finish())?;
+        let mut r = Reader::new(&self.response[..len]);
+        let _more = r.u8()?;
+        if r.u32()? != TPM_CAP_TPM_PROPERTIES {
+            return Err(TpmError::Malformed);
+        }
+        let mut version = [0u32; 2];
+        for _ in 0..r.u32()? {
+            let property = r.u32()?;
+            let value = r.u32()?;
+            match property {
+                TPM_PT_MANUFACTURER => self.info.manufacturer = value.to_be_bytes(),
+                TPM_PT_FIRMWARE_VERSION_1 => version[0] = value,
+                TPM_PT_FIRMWARE_VERSION_2 => version[1] = value,
+                _ => {}
+            }
+        }
+        self.info.firmware_version = ((version[0] as u64) << 32) | version[1] as u64;
+        Ok(())
+    }
+
+    fn read_banks(&mut self) -> Result<(), TpmError> {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, cc::GET_CAPABILITY);
+        cmd.u32(TPM_CAP_PCRS).u32(0).u32(1);
+        let len = self.execute(cmd.finish())?;
+        let mut r = Reader::new(&self.response[..len]);
+        let _more = r.u8()?;
+        if r.u32()? != TPM_CAP_PCRS {
+            return Err(TpmError::Malformed);
+        }
+        let mut banks = [0u16; MAX_PCR_BANKS];
+        let mut count = 0;
+        for _ in 0..r.u32()? {
+            let bank = r.u16()?;
+            let select_size = r.u8()? as usize;
+            let active = r.bytes(select_size)?.iter().any(|&b| b != 0);
+            if active && count < MAX_PCR_BANKS {
+                banks[count] = bank;
+                count += 1;
+            }
+        }
+        self.info.banks = banks;
+        self.info.bank_count = count;
+        Ok(())
+    }
+
+    fn pcr_read(&mut self, pcr: u32, bank: u16, out: &mut [u8]) -> Result<usize, TpmError> {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, cc::PCR_READ);
+        cmd.pcr_selection(bank, 1 << pcr);
+        let len = self.execute(cmd.finish())?;
+        let mut r = Reader::new(&self.response[..len]);
+        let _update_counter = r.u32()?;
+        // Echoed selection: empty if the bank is not allocated
+        let selections = r.u32()?;
+        for _ in 0..selections {
+            r.u16()?;
+            let size = r.u8()? as usize;
+            r.bytes(size)?;
+        }
+        if r.u32()? == 0 {
+            return Err(TpmError::InvalidParameter);
+        }
+        let digest = r.sized()?;
+        let out = out.get_mut(..digest.len()).ok_or(TpmError::BufferTooSmall)?;
+        out.copy_from_slice(digest);
+        Ok(digest.len())
+    }
+
+    /// Extend `pcr` in every active bank we can hash for
+    fn pcr_extend(&mut self, pcr: u32, sha1_digest: &[u8; SHA1_DIGEST_SIZE], sha256_digest: &[u8; SHA256_DIGEST_SIZE]) -> Result<(), TpmError> {
+        let mut cmd = Command::new(TPM_ST_SESSIONS, cc::PCR_EXTEND);
+        cmd.u32(pcr).password_session();
+        let banks = self.info.banks();
+        let count = banks.iter().filter(|&&b| b == alg::SHA1 || b == alg::SHA256).count();
+        if count == 0 {
+            return Err(TpmError::Unsupported);
+        }
+        cmd.u32(count as u32);
+        for &bank in banks {
+            match bank {
+                alg::SHA1 => { cmd.u16(bank).bytes(sha1_digest); }
+                alg::SHA256 => { cmd.u16(bank).bytes(sha256_digest); }
+                _ => {}
+            }
+        }
+        self.execute(cmd.finish()).map(|_| ())
+    }
+
+    /// Create (once) the ECC P-256 restricted signing key used for quotes
+    fn quote_key(&mut self) -> Result<u32, TpmError> {
+        if self.quote_key != 0 {
+            return Ok(self.quote_key);
+        }
+
+        // TPMT_PUBLIC: fixedTPM | fixedParent | sensitiveDataOrigin |
+        // userWithAuth | restricted | sign
+        let mut public = Command { buf: [0; 512], len: 0 };
+        public
+            .u16(alg::ECC)
+            .u16(alg::SHA256)
+            .u32(0x0005_0072)
+            .u16(0) // authPolicy
+            .u16(alg::NULL) // symmetric
+            .u16(alg::ECDSA)
+            .u16(alg::SHA256)
+            .u16(0x0003) // TPM_ECC_NIST_P256
+            .u16(alg::NULL) // kdf
+            .u16(0) // unique.x
+            .u16(0); // unique.y
+
+        let mut cmd = Command::new(TPM_ST_SESSIONS, cc::CREATE_PRIMARY);
+        cmd.u32(TPM_RH_ENDORSEMENT)
+            .password_session()
+            .u16(4).u16(0).u16(0) // inSensitive: empty auth and data
+            .sized(&public.buf[..public.len])
+            .u16(0) // outsideInfo
+            .u32(0); // creationPCR
+        let len = self.execute(cmd.finish())?;
+        let handle = Reader::new(&self.response[..len]).u32()?;
+        self.quote_key = handle;
+        Ok(handle)
+    }
+
+    fn quote(&mut self, pcr_mask: u32, nonce: &[u8], attest: &mut [u8], signature: &mut [u8]) -> Result<(usize, usize), TpmError> {
+        let key = self.quote_key()?;
+        let mut cmd = Command::new(TPM_ST_SESSIONS, cc::QUOTE);
+        cmd.u32(key)
+            .password_session()
+            .sized(nonce)
+            .u16(alg::NULL) // use the key's scheme
+            .pcr_selection(alg::SHA256, pcr_mask);
+        let len = self.execute(cmd.finish())?;
+
+        let mut r = Reader::new(&self.response[..len]);
+        let parameter_size = r.u32()? as usize;
+        let end = r.pos + parameter_size;
+        let quoted = r.sized()?;
+        let sig = self.response.get(r.pos..end.min(len)).ok_or(TpmError::Malformed)?;
+        attest.get_mut(..quoted.len()).ok_or(TpmError::BufferTooSmall)?.copy_from_slice(quoted);
+        signature.get_mut(..sig.len()).ok_or(TpmError::BufferTooSmall)?.copy_from_slice(sig);
+        Ok((quoted.len(), sig.len()))
+    }
+
+    fn flush_quote_key(&mut self) {
+        if self.quote_key == 0 {
+            return;
+        }
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, cc::FLUSH_CONTEXT);
+        cmd.u32(self.quote_key);
+        let _ = self.execute(cmd.finish());
+        self.quote_key = 0;
+    }
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Pick the interface from the ACPI TPM2 table or by probing
+fn detect() -> Result<(TpmInterface, u32), TpmError> {
+    let table = crate::hal::acpi::find_acpi_table(crate::hal::acpi::table_signature(b"TPM2"));
+    if let Some(table) = table {
+        let (control_area, method) = unsafe {
+            (
+                core::ptr::read_unaligned((table + 40) as *const u64),
+                core::ptr::read_unaligned((table + 48) as *const u32),
+            )
+        };
+        return match method {
+            start_method::TIS => tis::Tis::probe(TPM_BASE_ADDRESS)
+                .map(|t| (TpmInterface::Tis(t), method))
+                .ok_or(TpmError::NotPresent),
+            start_method::CRB => crb::Crb::probe(control_area & !0xFFF)
+                .map(|c| (TpmInterface::Crb(c), method))
+                .ok_or(TpmError::NotPresent),
+            start_method::ACPI | start_method::CRB_ACPI => Err(TpmError::Unsupported),
+            _ => Err(TpmError::Unsupported),
+        };
+    }
+
+    // No table: InterfaceType 1 is CRB, anything else answers as a FIFO
+    let interface_id = unsafe { mmio_read32(TPM_BASE_ADDRESS + 0x30) };
+    if interface_id != u32::MAX && interface_id & 0xF == 1 {
+        if let Some(crb) = crb::Crb::probe(TPM_BASE_ADDRESS) {
+            return Ok((TpmInterface::Crb(crb), 0));
+        }
+    }
+    tis::Tis::probe(TPM_BASE_ADDRESS)
+        .map(|t| (TpmInterface::Tis(t), 0))
+        .ok_or(TpmError::NotPresent)
+}
+
+/// Initialize the TPM driver
+///
+/// Called after ACPI so the TPM2 table can be used.
+pub fn init(boot_info: &crate::BootInfo) {
+    eventlog::init(boot_info);
+    if let Some(log) = eventlog::event_log() {
+        crate::serial_println!(
+            "[TPM] Firmware event log: {} bytes, {} events{}",
+            log.size(), log.event_count(),
+            if log.is_truncated() { " (truncated)" } else { "" }
+        );
+    }
+
+    let (interface, method) = match detect() {
+        Ok(found) => found,
+        Err(e) => {
+            crate::serial_println!("[TPM] {}", e);
+            return;
+        }
+    };
+
+    let mut dev = TPM_LOCK.lock();
+    dev.interface = interface;
+    dev.info.start_method = method;
+    dev.info.interface = match interface {
+        TpmInterface::Tis(tis) => {
+            dev.info.did_vid = tis.did_vid();
+            "TIS"
+        }
+        TpmInterface::Crb(_) => "CRB",
+        TpmInterface::None => "",
+    };
+
+    let result = dev.startup()
+        .and_then(|_| dev.read_properties())
+        .and_then(|_| dev.read_banks());
+    if let Err(e) = result {
+        crate::serial_println!("[TPM] {} TPM not usable: {}", dev.info.interface, e);
+        dev.interface = TpmInterface::None;
+        return;
+    }
+    TPM_PRESENT.store(true, Ordering::Release);
+
+    let info = dev.info;
+    drop(dev);
+    crate::serial_println!(
+        "[TPM] TPM 2.0 via {} ({} firmware {:#x}), {} PCR bank(s)",
+        info.interface, info.manufacturer_str(), info.firmware_version, info.bank_count
+    );
+}
+
+// ============================================================================
+// Kernel API
+// ============================================================================
+
+/// Check whether a usable TPM was found
+pub fn tpm_present() -> bool {
+    TPM_PRESENT.load(Ordering::Acquire)
+}
+
+/// Identification of the TPM
+pub fn tpm_info() -> Option<TpmInfo> {
+    if !tpm_present() {
+        return None;
+    }
+    Some(TPM_LOCK.lock().info)
+}
+
+/// Fail unless the caller is the kernel or holds the TCB privilege
+fn require_privilege() -> Result<(), TpmError> {
+    let process = crate::ps::get_current_process();
+    if process.is_null() {
+        return Ok(());
+    }
+    let token = unsafe { (*process).token };
+    // Kernel processes run without a token
+    if token.is_null() || crate::se::access::se_check_tcb_privilege(unsafe { &*token }) {
+        Ok(())
+    } else {
+        Err(TpmError::AccessDenied)
+    }
+}
+
+/// Read a PCR from `bank` into `out`
+///
+/// Requires the TCB privilege. Returns the digest length.
+pub fn tpm_pcr_read(pcr: u32, bank: u16, out: &mut [u8]) -> Result<usize, TpmError> {
+    require_privilege()?;
+    if pcr >= TPM_PCR_COUNT {
+        return Err(TpmError::InvalidParameter);
+    }
+    if !tpm_present() {
+        return Err(TpmError::NotPresent);
+    }
+    TPM_LOCK.lock().pcr_read(pcr, bank, out)
+}
+
+/// Extend `pcr` with the hash of `data` in every supported bank
+///
+/// Requires the TCB privilege. Returns the SHA-256 digest extended.
+pub fn tpm_pcr_extend(pcr: u32, data: &[u8]) -> Result<[u8; SHA256_DIGEST_SIZE], TpmError> {
+    require_privilege()?;
+    if pcr >= TPM_PCR_COUNT {
+        return Err(TpmError::InvalidParameter);
+    }
+    if !tpm_present() {
+        return Err(TpmError::NotPresent);
+    }
+    let digest = sha256(data);
+    TPM_LOCK.lock().pcr_extend(pcr, &sha1(data), &digest)?;
+    Ok(digest)
+}
+
+/// Quote the PCRs in `pcr_mask` (SHA-256 bank) over `nonce`
+///
+/// Requires the TCB privilege. Writes the TPMS_ATTEST and TPMT_SIGNATURE
+/// structures and returns their lengths.
+pub fn tpm_quote(pcr_mask: u32, nonce: &[u8], attest: &mut [u8], signature: &mut [u8]) -> Result<(usize, usize), TpmError> {
+    require_privilege()?;
+    if pcr_mask == 0 || pcr_mask >> TPM_PCR_COUNT != 0 || nonce.len() > 64 {
+        return Err(TpmError::InvalidParameter);
+    }
+    if !tpm_present() {
+        return Err(TpmError::NotPresent);
+    }
+    TPM_LOCK.lock().quote(pcr_mask, nonce, attest, signature)
+}
+
+/// Unload the attestation key (e.g. before shutdown)
+pub fn tpm_release_quote_key() {
+    if tpm_present() {
+        TPM_LOCK.lock().flush_quote_key();
+    }
+}
+
+// ============================================================================
+// Kernel Measurement List
+// ============================================================================
+
+/// One measurement made by the kernel
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    pub pcr: u32,
+    pub digest: [u8; SHA256_DIGEST_SIZE],
+    pub description: [u8; MEASUREMENT_DESC_LEN],
+    pub description_len: usize,
+    /// The TPM accepted the extend
+    pub extended: bool,
+}
+
+impl Measurement {
+    const EMPTY: Self = Self {
+        pcr: 0,
+        digest: [0; SHA256_DIGEST_SIZE],
+        description: [0; MEASUREMENT_DESC_LEN],
+        description_len: 0,
+        extended: false,
+    };
+
+    pub fn description_str(&self) -> &str {
+        core::str::from_utf8(&self.description[..self.description_len]).unwrap_or("?")
+    }
+}
+
+struct MeasurementList {
+    entries: [Measurement; MAX_MEASUREMENTS],
+    count: usize,
+    /// Measurements not kept for lack of space
+    dropped: usize,
+}
+
+static MEASUREMENTS: SpinLock<MeasurementList> = SpinLock::new(MeasurementList {
+    entries: [Measurement::EMPTY; MAX_MEASUREMENTS],
+    count: 0,
+    dropped: 0,
+});
+
+/// Measure `data` into `pcr` and record it in the measurement list
+///
+/// Kernel-internal: no privilege check. The measurement is recorded even
+/// without a TPM so the list still shows what was loaded.
+pub fn tpm_measure(pcr: u32, data: &[u8], description: &str) -> Result<(), TpmError> {
+    let digest = sha256(data);
+    let result = if pcr >= TPM_PCR_COUNT {
+        Err(TpmError::InvalidParameter)
+    } else if tpm_present() {
+        TPM_LOCK.lock().pcr_extend(pcr, &sha1(data), &digest)
+    } else {
+        Err(TpmError::NotPresent)
+    };
+
+    let mut list = MEASUREMENTS.lock();
+    if list.count < MAX_MEASUREMENTS {
+        let len = description.len().min(MEASUREMENT_DESC_LEN);
+        let index = list.count;
+        let entry = &mut list.entries[index];
+        entry.pcr = pcr;
+        entry.digest = digest;
+        entry.description[..len].copy_from_slice(&description.as_bytes()[..len]);
+        entry.description_len = len;
+        entry.extended = result.is_ok();
+        list.count += 1;
+    } else {
+        list.dropped += 1;
+    }
+    result
+}
+
+/// Measure a driver image into PCR 10
+pub fn tpm_measure_driver(name: &str, image: &[u8]) {
+    if let Err(e) = tpm_measure(PCR_DRIVERS, image, name) {
+        if e != TpmError::NotPresent {
+            crate::serial_println!("[TPM] Measuring driver '{}' failed: {}", name, e);
+        }
+    }
+}
+
+/// Snapshot of the kernel measurement list and the dropped count
+pub fn tpm_measurements() -> ([Measurement; MAX_MEASUREMENTS], usize, usize) {
+    let list = MEASUREMENTS.lock();
+    (list.entries, list.count, list.dropped)
+}
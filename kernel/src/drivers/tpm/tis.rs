@@ -0,0 +1,171 @@
+//! TPM Interface Specification (FIFO) Transport
+//!
+//! The TIS/PTP FIFO interface at the standard TPM MMIO window: the command
+//! is written byte-wise (in bursts of `burstCount`) into the data FIFO,
+//! `tpmGo` starts execution and the response is read back the same way
+//! once `dataAvail` is set. Only locality 0 is used.
+
+use super::{mmio_read32, mmio_read8, mmio_write8, wait_for, TpmError};
+use super::{TIMEOUT_A_MS, TIMEOUT_B_MS, TIMEOUT_C_MS, TIMEOUT_D_MS, TPM_HEADER_SIZE};
+
+/// Register offsets (locality 0)
+mod reg {
+    pub const ACCESS: u64 = 0x00;
+    pub const STS: u64 = 0x18;
+    pub const BURST_COUNT: u64 = 0x19;
+    pub const DATA_FIFO: u64 = 0x24;
+    pub const INTERFACE_ID: u64 = 0x30;
+    pub const DID_VID: u64 = 0xF00;
+    pub const RID: u64 = 0xF04;
+}
+
+/// TPM_ACCESS bits
+mod access {
+    pub const REQUEST_USE: u8 = 0x02;
+    pub const ACTIVE_LOCALITY: u8 = 0x20;
+    pub const REG_VALID: u8 = 0x80;
+}
+
+/// TPM_STS bits
+mod sts {
+    pub const RESPONSE_RETRY: u8 = 0x02;
+    pub const EXPECT: u8 = 0x08;
+    pub const DATA_AVAIL: u8 = 0x10;
+    pub const GO: u8 = 0x20;
+    pub const COMMAND_READY: u8 = 0x40;
+    pub const VALID: u8 = 0x80;
+}
+
+/// FIFO interface at `base`
+#[derive(Debug, Clone, Copy)]
+pub struct Tis {
+    base: u64,
+}
+
+impl Tis {
+    /// Check for a FIFO interface at `base`
+    pub fn probe(base: u64) -> Option<Self> {
+        let access = unsafe { mmio_read8(base + reg::ACCESS) };
+        if access == 0xFF || access & access::REG_VALID == 0 {
+            return None;
+        }
+        Some(Self { base })
+    }
+
+    /// Vendor ID (low 16 bits) and device ID
+    pub fn did_vid(&self) -> u32 {
+        unsafe { mmio_read32(self.base + reg::DID_VID) }
+    }
+
+    /// Revision ID
+    pub fn revision(&self) -> u8 {
+        unsafe { mmio_read8(self.base + reg::RID) }
+    }
+
+    /// TPM_INTERFACE_ID (all ones on TIS 1.2 parts)
+    pub fn interface_id(&self) -> u32 {
+        unsafe { mmio_read32(self.base + reg::INTERFACE_ID) }
+    }
+
+    fn status(&self) -> u8 {
+        unsafe { mmio_read8(self.base + reg::STS) }
+    }
+
+    fn set_status(&self, value: u8) {
+        unsafe { mmio_write8(self.base + reg::STS, value) }
+    }
+
+    fn burst_count(&self) -> usize {
+        let low = unsafe { mmio_read8(self.base + reg::BURST_COUNT) };
+        let high = unsafe { mmio_read8(self.base + reg::BURST_COUNT + 1) };
+        u16::from_le_bytes([low, high]) as usize
+    }
+
+    fn request_locality(&self) -> Result<(), TpmError> {
+        let active = || unsafe { mmio_read8(self.base + reg::ACCESS) } & access::ACTIVE_LOCALITY != 0;
+        if active() {
+            return Ok(());
+        }
+        unsafe { mmio_write8(self.base + reg::ACCESS, access::REQUEST_USE) };
+        wait_for(TIMEOUT_A_MS, active)
+    }
+
+    fn wait_burst(&self) -> Result<usize, TpmError> {
+        let mut burst = 0;
+        wait_for(TIMEOUT_D_MS, || {
+            burst = self.burst_count();
+            burst != 0
+        })?;
+        Ok(burst)
+    }
+
+    fn read_fifo(&self, buffer: &mut [u8]) -> Result<(), TpmError> {
+        let mut done = 0;
+        while done < buffer.len() {
+            wait_for(TIMEOUT_C_MS, || {
+                let status = self.status();
+                status & (sts::VALID | sts::DATA_AVAIL) == sts::VALID | sts::DATA_AVAIL
+            })?;
+            let burst = self.wait_burst()?.min(buffer.len() - done);
+            for byte in &mut buffer[done..done + burst] {
+                *byte = unsafe { mmio_read8(self.base + reg::DATA_FIFO) };
+            }
+            done += burst;
+        }
+        Ok(())
+    }
+
+    /// Send `command` and read the response into `response`
+    ///
+    /// Returns the response length.
+    pub fn transmit(&self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        self.request_locality()?;
+
+        // Move to Ready
+        self.set_status(sts::COMMAND_READY);
+        wait_for(TIMEOUT_B_MS, || self.status() & sts::COMMAND_READY != 0)?;
+
+        let mut sent = 0;
+        while sent < command.len() {
+            let burst = self.wait_burst()?.min(command.len() - sent);
+            for &byte in &command[sent..sent + burst] {
+                unsafe { mmio_write8(self.base + reg::DATA_FIFO, byte) };
+            }
+            sent += burst;
+        }
+        wait_for(TIMEOUT_C_MS, || self.status() & sts::VALID != 0)?;
+        if self.status() & sts::EXPECT != 0 {
+            self.set_status(sts::COMMAND_READY);
+            return Err(TpmError::Interface);
+        }
+
+        self.set_status(sts::GO);
+        wait_for(TIMEOUT_D_MS, || {
+            let status = self.status();
+            status & (sts::VALID | sts::DATA_AVAIL) == sts::VALID | sts::DATA_AVAIL
+        })?;
+
+        let result = self.read_response(response);
+        if result.is_err() {
+            self.set_status(sts::RESPONSE_RETRY);
+        }
+        self.set_status(sts::COMMAND_READY);
+        result
+    }
+
+    fn read_response(&self, response: &mut [u8]) -> Result<usize, TpmError> {
+        if response.len() < TPM_HEADER_SIZE {
+            return Err(TpmError::BufferTooSmall);
+        }
+        self.read_fifo(&mut response[..TPM_HEADER_SIZE])?;
+        let size = u32::from_be_bytes([response[2], response[3], response[4], response[5]]) as usize;
+        if size < TPM_HEADER_SIZE {
+            return Err(TpmError::Malformed);
+        }
+        if size > response.len() {
+            return Err(TpmError::BufferTooSmall);
+        }
+        self.read_fifo(&mut response[TPM_HEADER_SIZE..size])?;
+        Ok(size)
+    }
+}
@@ -0,0 +1,515 @@
+//! VirtIO Memory Balloon Driver
+//!
+//! Cooperates with the host (QEMU `-device virtio-balloon`) on guest
+//! memory size. The host sets a target number of pages in the device
+//! configuration; the driver inflates the balloon by taking pages off
+//! the PFN free lists and handing their numbers to the host, which can
+//! then reclaim the backing memory. Deflating returns page numbers to
+//! the host and, once acknowledged, frees the pages back to the PFN
+//! lists.
+//!
+//! A worker thread follows the target. It wakes on configuration change
+//! interrupts and on the memory manager's low-memory event: when memory
+//! runs low and the host allows it (`DEFLATE_ON_OOM`), pages are given
+//! back to the guest even though the target says otherwise. Inflation
+//! never takes available memory below twice the low-memory threshold.
+//!
+//! Only the worker thread touches the virtqueues, one request at a time.
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use super::VirtioTransport;
+use super::virtqueue::{Virtqueue, virtqueue_size_legacy};
+use crate::ke::{EventType, KEvent};
+use crate::ke::dpc::KDpc;
+use crate::mm::PAGE_SIZE;
+use crate::etw::Guid;
+use crate::wmi::{self, WmiBlockLayout, WmiDataBlock, WmiError, WmiField, WmiFieldType};
+
+/// Balloon device feature bits
+pub mod balloon_features {
+    pub const MUST_TELL_HOST: u64 = 1 << 0; // Tell host before reusing deflated pages
+    pub const STATS_VQ: u64 = 1 << 1;       // Memory statistics virtqueue
+    pub const DEFLATE_ON_OOM: u64 = 1 << 2; // Guest may deflate when out of memory
+}
+
+/// Device configuration offsets
+mod balloon_config {
+    pub const NUM_PAGES: u16 = 0;
+    pub const ACTUAL: u16 = 4;
+}
+
+/// Virtqueue indices
+const INFLATE_QUEUE: u16 = 0;
+const DEFLATE_QUEUE: u16 = 1;
+
+/// Largest balloon (the PFN database size)
+pub const MAX_BALLOON_PAGES: usize = 4096;
+
+/// Page numbers per inflate/deflate request
+const PFNS_PER_REQUEST: usize = 256;
+
+/// Pages returned per pass while memory is low
+const OOM_DEFLATE_PAGES: usize = 64;
+
+/// Worker re-reads the target at least this often (polled devices)
+const POLL_MS: u64 = 1000;
+
+/// Wait slice before polling the used ring ourselves
+const WAIT_SLICE_MS: u64 = 10;
+
+/// Give up on a request after this long
+const REQUEST_TIMEOUT_MS: u64 = 5000;
+
+/// Worker thread priority
+const WORKER_PRIORITY: i8 = 7;
+
+/// One of the two page-number queues
+struct PfnQueue {
+    queue: Virtqueue,
+    /// Page holding the PFN array sent with each request
+    pfns: u64,
+}
+
+/// The virtio-balloon device
+struct VirtioBalloonDevice {
+    transport: VirtioTransport,
+    inflate: PfnQueue,
+    deflate: PfnQueue,
+    features: u64,
+}
+
+/// The driven device (first virtio-balloon found)
+static mut DEVICE: Option<VirtioBalloonDevice> = None;
+
+/// Pages currently in the balloon (owned by the host)
+static mut BALLOON_PFNS: [u32; MAX_BALLOON_PAGES] = [0; MAX_BALLOON_PAGES];
+static BALLOON_PAGES: AtomicU32 = AtomicU32::new(0);
+
+/// Last target read from the device
+static TARGET_PAGES: AtomicU32 = AtomicU32::new(0);
+
+/// Signaled on configuration change interrupts
+static mut CONFIG_EVENT: KEvent = KEvent::new();
+
+/// Signaled when a request completes
+static mut QUEUE_EVENT: KEvent = KEvent::new();
+
+/// ISR status bits collected for the DPC
+static PENDING_ISR: AtomicU32 = AtomicU32::new(0);
+
+/// DPC queued by the ISR
+static mut ISR_DPC: KDpc = KDpc::new();
+
+/// Interrupt line (0xFF = polled only)
+static IRQ_LINE: AtomicU32 = AtomicU32::new(0xFF);
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+static PAGES_INFLATED: AtomicU64 = AtomicU64::new(0);
+static PAGES_DEFLATED: AtomicU64 = AtomicU64::new(0);
+static OOM_DEFLATIONS: AtomicU64 = AtomicU64::new(0);
+static CONFIG_CHANGES: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+// ============================================================================
+// Interrupts
+// ============================================================================
+
+/// ISR DPC: signal the events for the collected status bits
+fn isr_dpc(_dpc: *mut KDpc, _context: usize, _arg1: usize, _arg2: usize) {
+    let status = PENDING_ISR.swap(0, Ordering::AcqRel);
+    unsafe {
+        if status & 1 != 0 {
+            (*ptr::addr_of!(QUEUE_EVENT)).set();
+        }
+        if status & 2 != 0 {
+            CONFIG_CHANGES.fetch_add(1, Ordering::Relaxed);
+            (*ptr::addr_of!(CONFIG_EVENT)).set();
+        }
+    }
+}
+
+/// Interrupt service routine
+fn virtio_balloon_isr(_context: *mut u8) -> bool {
+    let transport = unsafe {
+        match (*ptr::addr_of!(DEVICE)).as_ref() {
+            Some(d) => &d.transport,
+            None => return false,
+        }
+    };
+
+    // Reading the ISR status acknowledges the interrupt;
+    // bit 0 = used ring, bit 1 = configuration change
+    let status = transport.read_isr() & 3;
+    if status == 0 {
+        return false;
+    }
+    PENDING_ISR.fetch_or(status as u32, Ordering::AcqRel);
+    unsafe { (*ptr::addr_of_mut!(ISR_DPC)).queue_no_args(); }
+    true
+}
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+/// Milliseconds elapsed since `start` (performance counter ticks)
+fn elapsed_ms(start: u64) -> u64 {
+    let freq = crate::hal::timer::hal_query_performance_frequency();
+    if freq == 0 {
+        return 0;
+    }
+    crate::hal::timer::hal_query_performance_counter().wrapping_sub(start) * 1000 / freq
+}
+
+/// Send `pfns` on an inflate or deflate queue and wait for the host
+///
+/// # Returns
+/// `false` if the request could not be queued or timed out
+fn send_pfns(device: &mut VirtioBalloonDevice, queue_index: u16, pfns: &[u32]) -> bool {
+    let queue = if queue_index == INFLATE_QUEUE { &mut device.inflate } else { &mut device.deflate };
+    unsafe {
+        ptr::copy_nonoverlapping(pfns.as_ptr(), queue.pfns as *mut u32, pfns.len());
+    }
+
+    let buffer = (queue.pfns, (pfns.len() * 4) as u32);
+    let head = match queue.queue.add_buf(&[buffer], &[]) {
+        Some(head) => head,
+        None => return false,
+    };
+    device.transport.queue_notify(queue_index);
+
+    let start = crate::hal::timer::hal_query_performance_counter();
+    loop {
+        if let Some((used, _len)) = queue.queue.poll() {
+            queue.queue.free_chain(used);
+            if used == head {
+                return true;
+            }
+            continue;
+        }
+        if elapsed_ms(start) > REQUEST_TIMEOUT_MS {
+            crate::serial_println!("[BALLOON] Host did not acknowledge request on queue {}", queue_index);
+            return false;
+        }
+        // Polled devices simply time out each slice
+        unsafe {
+            crate::ke::wait::ke_wait_for_single_object(
+                &mut (*ptr::addr_of_mut!(QUEUE_EVENT)).header as *mut _,
+                Some(WAIT_SLICE_MS),
+            );
+        }
+    }
+}
+
+/// Tell the host how many pages the balloon holds
+fn update_actual(device: &VirtioBalloonDevice) {
+    device.transport.write_config_u32(balloon_config::ACTUAL, BALLOON_PAGES.load(Ordering::Acquire));
+}
+
+/// Whether inflating by one more page keeps enough memory for the guest
+fn can_inflate() -> bool {
+    let stats = crate::mm::mm_get_stats();
+    let (low, _high) = crate::mm::mm_memory_thresholds();
+    stats.free_pages + stats.zeroed_pages > low * 2
+}
+
+/// Take up to `wanted` pages from the free lists and give them to the host
+fn inflate(device: &mut VirtioBalloonDevice, wanted: usize) -> usize {
+    let mut batch = [0u32; PFNS_PER_REQUEST];
+    let mut count = 0;
+    while count < wanted.min(PFNS_PER_REQUEST) && can_inflate() {
+        match unsafe { crate::mm::mm_allocate_page() } {
+            Some(pfn) => {
+                batch[count] = pfn as u32;
+                count += 1;
+            }
+            None => break,
+        }
+    }
+    if count == 0 {
+        return 0;
+    }
+
+    if !send_pfns(device, INFLATE_QUEUE, &batch[..count]) {
+        // Never handed over: the pages are still ours
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+        for pfn in &batch[..count] {
+            unsafe { crate::mm::mm_free_page(*pfn as usize); }
+        }
+        return 0;
+    }
+
+    let held = BALLOON_PAGES.load(Ordering::Acquire) as usize;
+    unsafe {
+        (&mut *ptr::addr_of_mut!(BALLOON_PFNS))[held..held + count].copy_from_slice(&batch[..count]);
+    }
+    BALLOON_PAGES.store((held + count) as u32, Ordering::Release);
+    PAGES_INFLATED.fetch_add(count as u64, Ordering::Relaxed);
+    update_actual(device);
+    count
+}
+
+/// Return up to `wanted` pages from the host to the free lists
+fn deflate(device: &mut VirtioBalloonDevice, wanted: usize) -> usize {
+    let held = BALLOON_PAGES.load(Ordering::Acquire) as usize;
+    let count = wanted.min(held).min(PFNS_PER_REQUEST);
+    if count == 0 {
+        return 0;
+    }
+
+    let mut batch = [0u32; PFNS_PER_REQUEST];
+    unsafe {
+        batch[..count].copy_from_slice(&(&*ptr::addr_of!(BALLOON_PFNS))[held - count..held]);
+    }
+
+    // With MUST_TELL_HOST the pages can't be touched before the host
+    // acknowledges; without it waiting is harmless
+    if !send_pfns(device, DEFLATE_QUEUE, &batch[..count]) {
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+        return 0;
+    }
+
+    BALLOON_PAGES.store((held - count) as u32, Ordering::Release);
+    for pfn in &batch[..count] {
+        unsafe { crate::mm::mm_free_page(*pfn as usize); }
+    }
+    PAGES_DEFLATED.fetch_add(count as u64, Ordering::Relaxed);
+    update_actual(device);
+    count
+}
+
+// ============================================================================
+// Worker
+// ============================================================================
+
+/// Move the balloon toward the host's target (or deflate under pressure)
+fn adjust(device: &mut VirtioBalloonDevice) {
+    let target = (device.transport.read_config_u32(balloon_config::NUM_PAGES) as usize)
+        .min(MAX_BALLOON_PAGES);
+    TARGET_PAGES.store(target as u32, Ordering::Release);
+
+    let low_memory = crate::mm::mm_memory_condition() == crate::mm::MemoryCondition::Low;
+    if low_memory && device.features & balloon_features::DEFLATE_ON_OOM != 0 {
+        let returned = deflate(device, OOM_DEFLATE_PAGES);
+        if returned > 0 {
+            OOM_DEFLATIONS.fetch_add(1, Ordering::Relaxed);
+            crate::serial_println!("[BALLOON] Low memory: returned {} pages to the guest", returned);
+        }
+        return;
+    }
+
+    loop {
+        let held = BALLOON_PAGES.load(Ordering::Acquire) as usize;
+        let moved = if held < target {
+            inflate(device, target - held)
+        } else if held > target {
+            deflate(device, held - target)
+        } else {
+            0
+        };
+        if moved == 0 {
+            break;
+        }
+    }
+}
+
+/// Balloon worker thread
+fn balloon_thread() {
+    loop {
+        let device = match unsafe { (*ptr::addr_of_mut!(DEVICE)).as_mut() } {
+            Some(d) => d,
+            None => unsafe { crate::ke::init::exit_thread() },
+        };
+        adjust(device);
+
+        // The low-memory event stays signaled while memory is low, so only
+        // wait on it while there is something to give back
+        let config = unsafe { &mut (*ptr::addr_of_mut!(CONFIG_EVENT)).header as *mut _ };
+        let low_memory = unsafe { &mut (*crate::mm::mm_low_memory_event()).header as *mut _ };
+        let oom_deflate = device.features & balloon_features::DEFLATE_ON_OOM != 0
+            && BALLOON_PAGES.load(Ordering::Acquire) != 0;
+        unsafe {
+            if oom_deflate {
+                crate::ke::wait::ke_wait_for_multiple_objects(
+                    &[config, low_memory],
+                    crate::ke::dispatcher::WaitType::WaitAny,
+                    Some(POLL_MS),
+                );
+            } else {
+                crate::ke::wait::ke_wait_for_single_object(config, Some(POLL_MS));
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Statistics
+// ============================================================================
+
+/// VirtIO balloon driver statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VirtioBalloonStats {
+    /// Device found and initialized
+    pub ready: bool,
+    /// Negotiated feature bits
+    pub features: u64,
+    /// Interrupt line (None = polled)
+    pub irq: Option<u8>,
+    /// Pages the host asked for
+    pub target_pages: u32,
+    /// Pages currently in the balloon
+    pub actual_pages: u32,
+    pub pages_inflated: u64,
+    pub pages_deflated: u64,
+    /// Deflations forced by low guest memory
+    pub oom_deflations: u64,
+    pub config_changes: u64,
+    pub errors: u64,
+}
+
+/// Get driver statistics
+pub fn virtio_balloon_stats() -> VirtioBalloonStats {
+    let features = unsafe { (*ptr::addr_of!(DEVICE)).as_ref().map(|d| d.features).unwrap_or(0) };
+    let irq = IRQ_LINE.load(Ordering::Relaxed);
+    VirtioBalloonStats {
+        ready: READY.load(Ordering::Acquire),
+        features,
+        irq: if irq == 0xFF { None } else { Some(irq as u8) },
+        target_pages: TARGET_PAGES.load(Ordering::Acquire),
+        actual_pages: BALLOON_PAGES.load(Ordering::Acquire),
+        pages_inflated: PAGES_INFLATED.load(Ordering::Relaxed),
+        pages_deflated: PAGES_DEFLATED.load(Ordering::Relaxed),
+        oom_deflations: OOM_DEFLATIONS.load(Ordering::Relaxed),
+        config_changes: CONFIG_CHANGES.load(Ordering::Relaxed),
+        errors: ERRORS.load(Ordering::Relaxed),
+    }
+}
+
+// ============================================================================
+// WMI
+// ============================================================================
+
+/// GUID of the statistics data block
+pub const VIRTIO_BALLOON_STATS_GUID: Guid = Guid::new(
+    0x2B9E4F10, 0x7C3A, 0x4E88, [0xB2, 0x14, 0x5D, 0x90, 0x6A, 0x1F, 0xC3, 0x7E]);
+
+static WMI_LAYOUT: WmiBlockLayout = WmiBlockLayout::new(
+    "VirtioBalloon_Statistics",
+    "VirtIO balloon driver statistics",
+    &[
+        WmiField::new("Ready", WmiFieldType::Boolean, 0),
+        WmiField::new("TargetPages", WmiFieldType::Uint32, 4),
+        WmiField::new("ActualPages", WmiFieldType::Uint32, 8),
+        WmiField::new("PagesInflated", WmiFieldType::Uint64, 16),
+        WmiField::new("PagesDeflated", WmiFieldType::Uint64, 24),
+        WmiField::new("OomDeflations", WmiFieldType::Uint64, 32),
+        WmiField::new("ConfigChanges", WmiFieldType::Uint64, 40),
+        WmiField::new("Errors", WmiFieldType::Uint64, 48),
+    ],
+);
+
+fn wmi_query_stats(_instance: u32, buffer: &mut [u8]) -> Result<usize, WmiError> {
+    let stats = virtio_balloon_stats();
+    let layout = &WMI_LAYOUT;
+    layout.put(buffer, "Ready", stats.ready)?;
+    layout.put(buffer, "TargetPages", stats.target_pages)?;
+    layout.put(buffer, "ActualPages", stats.actual_pages)?;
+    layout.put(buffer, "PagesInflated", stats.pages_inflated)?;
+    layout.put(buffer, "PagesDeflated", stats.pages_deflated)?;
+    layout.put(buffer, "OomDeflations", stats.oom_deflations)?;
+    layout.put(buffer, "ConfigChanges", stats.config_changes)?;
+    layout.put(buffer, "Errors", stats.errors)?;
+    Ok(layout.size())
+}
+
+/// Expose the statistics as the `VirtioBalloon_Statistics` WMI class
+fn register_wmi() {
+    let Some(provider) = wmi::wmi_register_provider("virtio-balloon", 0) else {
+        return;
+    };
+    let block = WmiDataBlock::new(VIRTIO_BALLOON_STATS_GUID, provider)
+        .with_layout(&WMI_LAYOUT)
+        .with_query(wmi_query_stats);
+    if wmi::wmi_register_data_block(block).is_none() {
+        crate::serial_println!("[BALLOON] WMI data block registration failed");
+    }
+}
+
+// ============================================================================
+// Initialization
+// ============================================================================
+
+/// Set up one page-number queue
+fn setup_queue(transport: &VirtioTransport, index: u16) -> Result<PfnQueue, &'static str> {
+    transport.queue_select(index);
+    let queue_size = transport.queue_size();
+    if queue_size == 0 {
+        return Err("Balloon queue size is 0");
+    }
+    let queue_bytes = virtqueue_size_legacy(queue_size);
+    let queue_phys = unsafe { crate::mm::mm_allocate_contiguous_memory(queue_bytes, 0xFFF_FFFF_FFFF) }
+        .ok_or("Cannot allocate virtqueue")?;
+    let queue = unsafe { Virtqueue::new(index, queue_size, queue_phys, queue_phys as *mut u8) };
+    transport.queue_set_pfn((queue_phys / PAGE_SIZE as u64) as u32);
+
+    let pfns = unsafe { crate::mm::mm_allocate_zeroed_page() }.ok_or("Cannot allocate PFN array")?;
+    Ok(PfnQueue { queue, pfns: (pfns * PAGE_SIZE) as u64 })
+}
+
+/// Find the first virtio-balloon device and start the worker thread
+pub fn init() -> Result<(), &'static str> {
+    let devices = crate::hal::pci::find_virtio_balloon_devices();
+    let loc = *devices.first().ok_or("No VirtIO balloon devices found")?;
+    crate::serial_println!(
+        "[BALLOON] Initializing device at {:02X}:{:02X}.{}",
+        loc.bus, loc.device, loc.function
+    );
+
+    let mut transport = VirtioTransport::new(loc).ok_or("Unsupported transport")?;
+    let features = transport.negotiate(
+        balloon_features::MUST_TELL_HOST | balloon_features::DEFLATE_ON_OOM,
+    )?;
+    let inflate = setup_queue(&transport, INFLATE_QUEUE)?;
+    let deflate = setup_queue(&transport, DEFLATE_QUEUE)?;
+
+    unsafe {
+        (*ptr::addr_of_mut!(CONFIG_EVENT)).init(EventType::Synchronization, false);
+        (*ptr::addr_of_mut!(QUEUE_EVENT)).init(EventType::Synchronization, false);
+        (*ptr::addr_of_mut!(ISR_DPC)).init(isr_dpc, 0);
+    }
+
+    transport.driver_ok();
+    transport.write_config_u32(balloon_config::ACTUAL, 0);
+
+    unsafe {
+        *ptr::addr_of_mut!(DEVICE) = Some(VirtioBalloonDevice {
+            transport,
+            inflate,
+            deflate,
+            features,
+        });
+    }
+
+    let line = unsafe { (*ptr::addr_of!(DEVICE)).as_ref() }
+        .and_then(|d| d.transport.connect_interrupt(virtio_balloon_isr));
+    if let Some(line) = line {
+        IRQ_LINE.store(line as u32, Ordering::Release);
+    }
+
+    if unsafe { crate::ke::init::create_thread(WORKER_PRIORITY, balloon_thread) }.is_none() {
+        unsafe { *ptr::addr_of_mut!(DEVICE) = None; }
+        return Err("Cannot create worker thread");
+    }
+    READY.store(true, Ordering::Release);
+    register_wmi();
+
+    crate::serial_println!(
+        "[BALLOON] Ready, features {:#x}, {}",
+        features,
+        if IRQ_LINE.load(Ordering::Relaxed) != 0xFF { "interrupt-driven" } else { "polled" }
+    );
+    Ok(())
+}
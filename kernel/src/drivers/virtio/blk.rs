@@ -0,0 +1,745 @@
+//! VirtIO Block Device Driver
+//!
+//! Presents a virtio-blk disk (QEMU `-drive if=virtio`) as a block
+//! device named `vda`, so the partition scan and file systems use it
+//! the same way as an IDE disk. The driver is a storage port miniport:
+//! the class layer sends SCSI reads and writes, already split to
+//! `MAX_TRANSFER_BYTES`, which become virtio requests.
+//!
+//! Each request is a descriptor chain on queue 0: a header (type and
+//! sector), the data buffer and a one-byte status the device writes.
+//! Up to `MAX_INFLIGHT` requests are outstanding at once, each with its
+//! own physically contiguous bounce buffer.
+//!
+//! Completion is interrupt-driven: the ISR acknowledges the device and
+//! queues a DPC, which drains the used ring and signals each finished
+//! request's event. Before interrupts are enabled (early boot), or if an
+//! interrupt is lost, the waiting thread drains the used ring itself.
+
+extern crate alloc;
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use super::VirtioTransport;
+use super::virtqueue::{Virtqueue, virtqueue_size_legacy};
+use crate::io::block::{BlockStatus, block_flags, SECTOR_SIZE};
+use crate::io::storport::{
+    self, MiniportOps, Srb, SrbStatus, asc, peripheral, scsiop, sense_key,
+    storport_add_target, storport_register_adapter,
+};
+use crate::ke::{EventType, KEvent};
+use crate::ke::dpc::KDpc;
+use crate::ke::spinlock::SpinLock;
+use crate::etw::Guid;
+use crate::wmi::{self, WmiBlockLayout, WmiDataBlock, WmiError, WmiField, WmiFieldType, WmiValue};
+use alloc::string::String;
+
+/// Block device feature bits
+pub mod blk_features {
+    pub const SIZE_MAX: u64 = 1 << 1;  // Maximum segment size in size_max
+    pub const SEG_MAX: u64 = 1 << 2;   // Maximum segments in seg_max
+    pub const GEOMETRY: u64 = 1 << 4;  // Legacy geometry available
+    pub const RO: u64 = 1 << 5;        // Device is read-only
+    pub const BLK_SIZE: u64 = 1 << 6;  // Block size in blk_size
+    pub const FLUSH: u64 = 1 << 9;     // Cache flush command supported
+    pub const CONFIG_WCE: u64 = 1 << 11; // Writeback mode settable in config
+}
+
+/// Request types
+mod req_type {
+    pub const IN: u32 = 0;
+    pub const OUT: u32 = 1;
+    pub const FLUSH: u32 = 4;
+}
+
+/// Request status written by the device
+mod req_status {
+    pub const OK: u8 = 0;
+    pub const UNSUPP: u8 = 2;
+}
+
+/// Device configuration offsets
+mod blk_config {
+    pub const CAPACITY: u16 = 0;
+    pub const GEOMETRY: u16 = 16;
+    pub const WRITEBACK: u16 = 32;
+}
+
+/// Maximum requests outstanding at once
+pub const MAX_INFLIGHT: usize = 4;
+
+/// Largest single transfer (bounce buffer size per request)
+pub const MAX_TRANSFER_BYTES: usize = 64 * 1024;
+
+/// Sectors per transfer
+const MAX_TRANSFER_SECTORS: u32 = (MAX_TRANSFER_BYTES / SECTOR_SIZE) as u32;
+
+/// Offset of the status byte in a request's control page
+const STATUS_OFFSET: u64 = 16;
+
+/// Wait slice before draining the used ring ourselves
+const WAIT_SLICE_MS: u64 = 10;
+
+/// Give up on a request after this long
+const REQUEST_TIMEOUT_MS: u64 = 30_000;
+
+/// Request header (legacy layout)
+#[repr(C)]
+struct VirtioBlkReqHeader {
+    req_type: u32,
+    ioprio: u32,
+    sector: u64,
+}
+
+/// One in-flight request slot
+struct Slot {
+    /// Page holding the header and the status byte
+    control: u64,
+    /// Bounce buffer, MAX_TRANSFER_BYTES contiguous
+    buffer: u64,
+    /// Head descriptor of the submitted chain
+    head: u16,
+    /// Submitted and not yet completed
+    pending: bool,
+    done: AtomicBool,
+    event: KEvent,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            control: 0,
+            buffer: 0,
+            head: 0,
+            pending: false,
+            done: AtomicBool::new(false),
+            event: KEvent::new(),
+        }
+    }
+}
+
+/// The virtio-blk device
+struct VirtioBlkDevice {
+    transport: VirtioTransport,
+    queue: Virtqueue,
+    features: u64,
+    capacity: u64,
+    /// Block device layer index
+    block_index: u8,
+}
+
+/// The driven device (first virtio-blk found)
+static mut DEVICE: Option<VirtioBlkDevice> = None;
+
+/// Protects DEVICE.queue and the slots' head/pending fields
+static QUEUE_LOCK: SpinLock<()> = SpinLock::new(());
+
+static mut SLOTS: [Slot; MAX_INFLIGHT] = [const { Slot::new() }; MAX_INFLIGHT];
+
+/// Bit per slot in use
+static SLOT_BUSY: AtomicU32 = AtomicU32::new(0);
+
+/// Completion DPC queued by the ISR
+static mut COMPLETION_DPC: KDpc = KDpc::new();
+
+/// Interrupt line (0xFF = polled only)
+static IRQ_LINE: AtomicU32 = AtomicU32::new(0xFF);
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_TRANSFERRED: AtomicU64 = AtomicU64::new(0);
+static INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+static POLLED_COMPLETIONS: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+// ============================================================================
+// Completion
+// ============================================================================
+
+/// Drain the used ring, marking finished slots done
+///
+/// # Returns
+/// Bitmask of slots completed
+fn drain_used() -> u32 {
+    let _guard = QUEUE_LOCK.lock();
+    let mut completed = 0u32;
+    unsafe {
+        let device = match (*ptr::addr_of_mut!(DEVICE)).as_mut() {
+            Some(d) => d,
+            None => return 0,
+        };
+        let slots = &mut *ptr::addr_of_mut!(SLOTS);
+        while let Some((head, _len)) = device.queue.poll() {
+            device.queue.free_chain(head);
+            if let Some((i, slot)) = slots
+                .iter_mut()
+                .enumerate()
+                .find(|(_, s)| s.pending && s.head == head)
+            {
+                slot.pending = false;
+                slot.done.store(true, Ordering::Release);
+                completed |= 1 << i;
+            }
+        }
+    }
+    completed
+}
+
+/// Drain the used ring and wake the completed requests' waiters
+fn complete_requests() {
+    let completed = drain_used();
+    for i in 0..MAX_INFLIGHT {
+        if completed & (1 << i) != 0 {
+            unsafe { (*ptr::addr_of_mut!(SLOTS[i])).event.set(); }
+        }
+    }
+}
+
+/// Completion DPC
+fn completion_dpc(_dpc: *mut KDpc, _context: usize, _arg1: usize, _arg2: usize) {
+    complete_requests();
+}
+
+/// Interrupt service routine
+fn virtio_blk_isr(_context: *mut u8) -> bool {
+    let transport = unsafe {
+        match (*ptr::addr_of!(DEVICE)).as_ref() {
+            Some(d) => &d.transport,
+            None => return false,
+        }
+    };
+
+    // Reading the ISR status acknowledges the interrupt; bit 0 = used ring
+    if transport.read_isr() & 1 == 0 {
+        return false;
+    }
+    INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+    unsafe { (*ptr::addr_of_mut!(COMPLETION_DPC)).queue_no_args(); }
+    true
+}
+
+// ============================================================================
+// Request Submission
+// ============================================================================
+
+/// Claim a free request slot, draining completions while all are busy
+fn claim_slot() -> usize {
+    loop {
+        let busy = SLOT_BUSY.load(Ordering::Acquire);
+        let free = (!busy).trailing_zeros() as usize;
+        if free < MAX_INFLIGHT {
+            if SLOT_BUSY
+                .compare_exchange(busy, busy | (1 << free), Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return free;
+            }
+            continue;
+        }
+        complete_requests();
+        if can_wait() {
+            crate::ex::ex_sleep(1);
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+fn release_slot(index: usize) {
+    SLOT_BUSY.fetch_and(!(1 << index), Ordering::AcqRel);
+}
+
+/// Whether the caller may block on the slot's event
+fn can_wait() -> bool {
+    IRQ_LINE.load(Ordering::Relaxed) != 0xFF
+        && crate::arch::x86_64::interrupts_enabled()
+        && !crate::ke::prcb::get_current_thread().is_null()
+}
+
+/// Milliseconds elapsed since `start` (performance counter ticks)
+fn elapsed_ms(start: u64) -> u64 {
+    let freq = crate::hal::timer::hal_query_performance_frequency();
+    if freq == 0 {
+        return 0;
+    }
+    crate::hal::timer::hal_query_performance_counter().wrapping_sub(start) * 1000 / freq
+}
+
+/// Submit one request through the slot's bounce buffer and wait for it
+fn submit(index: usize, kind: u32, sector: u64, bytes: usize) -> BlockStatus {
+    let slot = unsafe { &mut *ptr::addr_of_mut!(SLOTS[index]) };
+    let status_addr = slot.control + STATUS_OFFSET;
+
+    unsafe {
+        ptr::write_volatile(slot.control as *mut VirtioBlkReqHeader, VirtioBlkReqHeader {
+            req_type: kind,
+            ioprio: 0,
+            sector,
+        });
+        ptr::write_volatile(status_addr as *mut u8, 0xFF);
+    }
+    slot.done.store(false, Ordering::Release);
+
+    let header = (slot.control, core::mem::size_of::<VirtioBlkReqHeader>() as u32);
+    let data = (slot.buffer, bytes as u32);
+    let status = (status_addr, 1u32);
+
+    {
+        let _guard = QUEUE_LOCK.lock();
+        let device = match unsafe { (*ptr::addr_of_mut!(DEVICE)).as_mut() } {
+            Some(d) => d,
+            None => return BlockStatus::NotReady,
+        };
+        let head = match kind {
+            req_type::IN => device.queue.add_buf(&[header], &[data, status]),
+            req_type::OUT => device.queue.add_buf(&[header, data], &[status]),
+            _ => device.queue.add_buf(&[header], &[status]),
+        };
+        match head {
+            Some(head) => {
+                slot.head = head;
+                slot.pending = true;
+            }
+            None => return BlockStatus::Busy,
+        }
+        device.transport.queue_notify(0);
+    }
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let start = crate::hal::timer::hal_query_performance_counter();
+    while !slot.done.load(Ordering::Acquire) {
+        if can_wait() {
+            unsafe {
+                crate::ke::wait::ke_wait_for_single_object(
+                    &mut slot.event.header as *mut _,
+                    Some(WAIT_SLICE_MS),
+                );
+            }
+            if slot.done.load(Ordering::Acquire) {
+                break;
+            }
+        }
+
+        // Early boot, or the interrupt was lost
+        if drain_used() & (1 << index) != 0 {
+            POLLED_COMPLETIONS.fetch_add(1, Ordering::Relaxed);
+            break;
+        }
+        if elapsed_ms(start) > REQUEST_TIMEOUT_MS {
+            // The device still owns the buffers; the slot is never released
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+            crate::serial_println!("[VIRTIO-BLK] Request timed out (sector {})", sector);
+            return BlockStatus::Timeout;
+        }
+        core::hint::spin_loop();
+    }
+
+    match unsafe { ptr::read_volatile(status_addr as *const u8) } {
+        req_status::OK => {
+            BYTES_TRANSFERRED.fetch_add(bytes as u64, Ordering::Relaxed);
+            BlockStatus::Success
+        }
+        req_status::UNSUPP => BlockStatus::InvalidParameter,
+        // VIRTIO_BLK_S_IOERR
+        _ => {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+            BlockStatus::IoError
+        }
+    }
+}
+
+/// Transfer up to `MAX_TRANSFER_BYTES` through a slot's bounce buffer
+unsafe fn transfer(lba: u64, count: u32, buf: *mut u8, write: bool) -> BlockStatus {
+    let index = claim_slot();
+    let bounce = SLOTS[index].buffer as *mut u8;
+    let bytes = count as usize * SECTOR_SIZE;
+
+    if write {
+        ptr::copy_nonoverlapping(buf, bounce, bytes);
+    }
+    let kind = if write { req_type::OUT } else { req_type::IN };
+    let result = submit(index, kind, lba, bytes);
+    if result == BlockStatus::Timeout {
+        return result;
+    }
+    if result == BlockStatus::Success && !write {
+        ptr::copy_nonoverlapping(bounce, buf, bytes);
+    }
+
+    release_slot(index);
+    result
+}
+
+/// Flush the device's write cache
+unsafe fn flush() -> BlockStatus {
+    let features = match (*ptr::addr_of!(DEVICE)).as_ref() {
+        Some(d) => d.features,
+        None => return BlockStatus::NotReady,
+    };
+    if features & blk_features::FLUSH == 0 {
+        // Write-through device: nothing to flush
+        return BlockStatus::Success;
+    }
+    let index = claim_slot();
+    let status = submit(index, req_type::FLUSH, 0, 0);
+    if status != BlockStatus::Timeout {
+        release_slot(index);
+    }
+    status
+}
+
+/// Check if the device caches writes
+///
+/// Without FLUSH the device is write-through; with FLUSH but not
+/// CONFIG_WCE it is always writeback.
+fn write_cache_enabled(device: &VirtioBlkDevice) -> bool {
+    if device.features & blk_features::FLUSH == 0 {
+        return false;
+    }
+    device.features & blk_features::CONFIG_WCE == 0
+        || device.transport.read_config_u8(blk_config::WRITEBACK) != 0
+}
+
+/// Switch between writeback and write-through
+unsafe fn set_write_cache(srb: &mut Srb, enable: bool) -> SrbStatus {
+    let device = match (*ptr::addr_of!(DEVICE)).as_ref() {
+        Some(d) => d,
+        None => return SrbStatus::NoDevice,
+    };
+    if write_cache_enabled(device) == enable {
+        return SrbStatus::Success;
+    }
+    if device.features & blk_features::CONFIG_WCE == 0 {
+        return srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_CDB_FIELD, 0);
+    }
+    if !enable {
+        // Nothing cached may be left behind in write-through mode
+        let status = flush();
+        if status != BlockStatus::Success {
+            return complete(srb, status, 0);
+        }
+    }
+    device.transport.write_config_u8(blk_config::WRITEBACK, enable as u8);
+    SrbStatus::Success
+}
+
+/// Complete an SRB from a request status
+fn complete(srb: &mut Srb, status: BlockStatus, bytes: u32) -> SrbStatus {
+    match status {
+        BlockStatus::Success => {
+            srb.transferred = bytes;
+            SrbStatus::Success
+        }
+        // The device still owns the timed-out slot; a retry would only
+        // tie up another one
+        BlockStatus::Timeout => srb.check_condition(sense_key::NOT_READY, asc::LUN_NOT_READY, 0),
+        BlockStatus::NotReady => srb.check_condition(sense_key::NOT_READY, asc::LUN_NOT_READY, 0),
+        BlockStatus::Busy => SrbStatus::Busy,
+        BlockStatus::InvalidParameter => srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_COMMAND, 0),
+        _ => srb.check_condition(sense_key::MEDIUM_ERROR, asc::UNRECOVERED_READ_ERROR, 0),
+    }
+}
+
+// ============================================================================
+// Miniport
+// ============================================================================
+
+/// Execute an SRB on the device (single target)
+unsafe fn blk_start_io(_context: usize, srb: &mut Srb) -> SrbStatus {
+    let (capacity, features, write_cache) = match (*ptr::addr_of!(DEVICE)).as_ref() {
+        Some(d) if srb.target == 0 => (d.capacity, d.features, write_cache_enabled(d)),
+        _ => return SrbStatus::NoDevice,
+    };
+
+    match srb.opcode() {
+        scsiop::TEST_UNIT_READY => {
+            if READY.load(Ordering::Acquire) {
+                SrbStatus::Success
+            } else {
+                srb.check_condition(sense_key::NOT_READY, asc::LUN_NOT_READY, 0)
+            }
+        }
+        scsiop::INQUIRY => {
+            let data = storport::inquiry_data(peripheral::DIRECT_ACCESS, false, "VIRTIO", "Block Device", "1");
+            srb.complete_with(&data)
+        }
+        scsiop::READ_CAPACITY | scsiop::READ_CAPACITY16 => {
+            storport::complete_read_capacity(srb, capacity, SECTOR_SIZE as u32)
+        }
+        scsiop::READ10 | scsiop::READ16 | scsiop::WRITE10 | scsiop::WRITE16 => {
+            let (lba, count) = match srb.rw_range() {
+                Some(range) => range,
+                None => return SrbStatus::InvalidRequest,
+            };
+            let write = matches!(srb.opcode(), scsiop::WRITE10 | scsiop::WRITE16);
+            if lba + count as u64 > capacity {
+                return srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::LBA_OUT_OF_RANGE, 0);
+            }
+            if write && features & blk_features::RO != 0 {
+                return srb.check_condition(sense_key::DATA_PROTECT, asc::WRITE_PROTECTED, 0);
+            }
+            let bytes = count as usize * SECTOR_SIZE;
+            if count > MAX_TRANSFER_SECTORS || bytes > srb.data_length as usize {
+                return SrbStatus::InvalidRequest;
+            }
+            if count == 0 {
+                return SrbStatus::Success;
+            }
+            let mut status = transfer(lba, count, srb.data, write);
+            // No FUA request type; flush behind the write instead
+            if status == BlockStatus::Success && srb.fua() && write_cache {
+                status = flush();
+            }
+            complete(srb, status, bytes as u32)
+        }
+        scsiop::SYNCHRONIZE_CACHE | scsiop::SYNCHRONIZE_CACHE16 => {
+            let status = flush();
+            complete(srb, status, 0)
+        }
+        scsiop::MODE_SENSE6 => storport::complete_caching_mode_sense(srb, write_cache),
+        scsiop::MODE_SELECT6 => match storport::caching_mode_select(srb) {
+            Some(enable) => set_write_cache(srb, enable),
+            None => srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_CDB_FIELD, 0),
+        },
+        _ => srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_COMMAND, 0),
+    }
+}
+
+/// Legacy CHS geometry, if the device offers it
+unsafe fn legacy_geometry(device: &VirtioBlkDevice) -> Option<(u32, u32, u32)> {
+    if device.features & blk_features::GEOMETRY == 0 {
+        return None;
+    }
+    let raw = device.transport.read_config_u32(blk_config::GEOMETRY);
+    Some((raw & 0xFFFF, (raw >> 16) & 0xFF, raw >> 24))
+}
+
+fn blk_miniport() -> MiniportOps {
+    MiniportOps {
+        start_io: blk_start_io,
+        reset_bus: None,
+    }
+}
+
+// ============================================================================
+// Statistics
+// ============================================================================
+
+/// VirtIO block driver statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VirtioBlkStats {
+    /// Device found and initialized
+    pub ready: bool,
+    /// Block device layer index
+    pub block_index: u8,
+    /// Capacity in sectors
+    pub capacity: u64,
+    /// Negotiated feature bits
+    pub features: u64,
+    /// Interrupt line (None = polled)
+    pub irq: Option<u8>,
+    pub requests: u64,
+    pub bytes: u64,
+    pub interrupts: u64,
+    /// Completions found by the waiter rather than the DPC
+    pub polled_completions: u64,
+    pub errors: u64,
+}
+
+/// Get driver statistics
+pub fn virtio_blk_stats() -> VirtioBlkStats {
+    let (block_index, capacity, features) = unsafe {
+        match (*ptr::addr_of!(DEVICE)).as_ref() {
+            Some(d) => (d.block_index, d.capacity, d.features),
+            None => (0, 0, 0),
+        }
+    };
+    let irq = IRQ_LINE.load(Ordering::Relaxed);
+    VirtioBlkStats {
+        ready: READY.load(Ordering::Acquire),
+        block_index,
+        capacity,
+        features,
+        irq: if irq == 0xFF { None } else { Some(irq as u8) },
+        requests: REQUESTS.load(Ordering::Relaxed),
+        bytes: BYTES_TRANSFERRED.load(Ordering::Relaxed),
+        interrupts: INTERRUPTS.load(Ordering::Relaxed),
+        polled_completions: POLLED_COMPLETIONS.load(Ordering::Relaxed),
+        errors: ERRORS.load(Ordering::Relaxed),
+    }
+}
+
+// ============================================================================
+// WMI
+// ============================================================================
+
+/// GUID of the statistics data block
+pub const VIRTIO_BLK_STATS_GUID: Guid = Guid::new(
+    0x6F3C2A71, 0x1B0E, 0x4D52, [0x9A, 0x61, 0x3E, 0x8C, 0x2D, 0x47, 0xB1, 0x05]);
+
+/// Statistics as seen through WMI; the counters can be set (to reset them)
+static WMI_LAYOUT: WmiBlockLayout = WmiBlockLayout::new(
+    "VirtioBlk_Statistics",
+    "VirtIO block driver statistics",
+    &[
+        WmiField::new("Ready", WmiFieldType::Boolean, 0),
+        WmiField::new("BlockIndex", WmiFieldType::Uint8, 1),
+        WmiField::new("Irq", WmiFieldType::Uint8, 2),
+        WmiField::new("Capacity", WmiFieldType::Uint64, 8),
+        WmiField::new("Features", WmiFieldType::Uint64, 16),
+        WmiField::new("Requests", WmiFieldType::Uint64, 24).writable(),
+        WmiField::new("Bytes", WmiFieldType::Uint64, 32).writable(),
+        WmiField::new("Interrupts", WmiFieldType::Uint64, 40).writable(),
+        WmiField::new("PolledCompletions", WmiFieldType::Uint64, 48).writable(),
+        WmiField::new("Errors", WmiFieldType::Uint64, 56).writable(),
+    ],
+);
+
+fn wmi_query_stats(_instance: u32, buffer: &mut [u8]) -> Result<usize, WmiError> {
+    let stats = virtio_blk_stats();
+    let layout = &WMI_LAYOUT;
+    layout.put(buffer, "Ready", stats.ready)?;
+    layout.put(buffer, "BlockIndex", stats.block_index)?;
+    layout.put(buffer, "Irq", stats.irq.unwrap_or(0xFF))?;
+    layout.put(buffer, "Capacity", stats.capacity)?;
+    layout.put(buffer, "Features", stats.features)?;
+    layout.put(buffer, "Requests", stats.requests)?;
+    layout.put(buffer, "Bytes", stats.bytes)?;
+    layout.put(buffer, "Interrupts", stats.interrupts)?;
+    layout.put(buffer, "PolledCompletions", stats.polled_completions)?;
+    layout.put(buffer, "Errors", stats.errors)?;
+    Ok(layout.size())
+}
+
+fn wmi_set_stats(_instance: u32, buffer: &[u8]) -> Result<(), WmiError> {
+    let counter = |name| match WMI_LAYOUT.get(buffer, name) {
+        Some(WmiValue::Unsigned(n)) => Ok(n),
+        _ => Err(WmiError::InvalidParameter),
+    };
+    REQUESTS.store(counter("Requests")?, Ordering::Relaxed);
+    BYTES_TRANSFERRED.store(counter("Bytes")?, Ordering::Relaxed);
+    INTERRUPTS.store(counter("Interrupts")?, Ordering::Relaxed);
+    POLLED_COMPLETIONS.store(counter("PolledCompletions")?, Ordering::Relaxed);
+    ERRORS.store(counter("Errors")?, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Expose the statistics as the `VirtioBlk_Statistics` WMI class
+fn register_wmi() {
+    let Some(provider) = wmi::wmi_register_provider("virtio-blk", 0) else {
+        return;
+    };
+    let block = WmiDataBlock::new(VIRTIO_BLK_STATS_GUID, provider)
+        .with_layout(&WMI_LAYOUT)
+        .with_instance_name(String::from("vda"))
+        .with_query(wmi_query_stats)
+        .with_set(wmi_set_stats);
+    if wmi::wmi_register_data_block(block).is_none() {
+        crate::serial_println!("[VIRTIO-BLK] WMI data block registration failed");
+    }
+}
+
+// ============================================================================
+// Initialization
+// ============================================================================
+
+/// Find and initialize the first virtio-blk device
+///
+/// # Returns
+/// The block device index
+pub fn init() -> Result<u8, &'static str> {
+    let devices = crate::hal::pci::find_virtio_blk_devices();
+    let loc = *devices.first().ok_or("No VirtIO block devices found")?;
+    crate::serial_println!(
+        "[VIRTIO-BLK] Initializing device at {:02X}:{:02X}.{}",
+        loc.bus, loc.device, loc.function
+    );
+
+    let mut transport = VirtioTransport::new(loc).ok_or("Unsupported transport")?;
+    let features = transport.negotiate(
+        blk_features::RO | blk_features::GEOMETRY | blk_features::BLK_SIZE
+            | blk_features::FLUSH | blk_features::CONFIG_WCE,
+    )?;
+    let capacity = transport.read_config_u64(blk_config::CAPACITY);
+
+    // Request queue (queue 0), physically contiguous for the legacy PFN
+    transport.queue_select(0);
+    let queue_size = transport.queue_size();
+    if queue_size == 0 {
+        return Err("Request queue size is 0");
+    }
+    let queue_bytes = virtqueue_size_legacy(queue_size);
+    let queue_phys = unsafe { crate::mm::mm_allocate_contiguous_memory(queue_bytes, 0xFFF_FFFF_FFFF) }
+        .ok_or("Cannot allocate virtqueue")?;
+    let queue = unsafe { Virtqueue::new(0, queue_size, queue_phys, queue_phys as *mut u8) };
+    transport.queue_set_pfn((queue_phys / crate::mm::PAGE_SIZE as u64) as u32);
+
+    // Per-request control pages and bounce buffers
+    unsafe {
+        let slots = &mut *ptr::addr_of_mut!(SLOTS);
+        for slot in slots.iter_mut() {
+            let control = crate::mm::mm_allocate_zeroed_page().ok_or("Cannot allocate request page")?;
+            slot.control = (control * crate::mm::PAGE_SIZE) as u64;
+            slot.buffer = crate::mm::mm_allocate_contiguous_memory(MAX_TRANSFER_BYTES, 0xFFF_FFFF_FFFF)
+                .ok_or("Cannot allocate bounce buffer")?;
+            slot.event.init(EventType::Synchronization, false);
+        }
+        (*ptr::addr_of_mut!(COMPLETION_DPC)).init(completion_dpc, 0);
+    }
+
+    transport.driver_ok();
+
+    unsafe {
+        *ptr::addr_of_mut!(DEVICE) = Some(VirtioBlkDevice {
+            transport,
+            queue,
+            features,
+            capacity,
+            block_index: 0,
+        });
+    }
+
+    let line = unsafe { (*ptr::addr_of!(DEVICE)).as_ref() }
+        .and_then(|d| d.transport.connect_interrupt(virtio_blk_isr));
+    if let Some(line) = line {
+        IRQ_LINE.store(line as u32, Ordering::Release);
+    }
+
+    let mut flags = block_flags::DMA | block_flags::LBA48;
+    if features & blk_features::RO != 0 {
+        flags |= block_flags::READONLY;
+    }
+    READY.store(true, Ordering::Release);
+
+    let adapter = storport_register_adapter("virtio-blk", blk_miniport(), 0, MAX_TRANSFER_BYTES as u32)
+        .ok_or("Storage port adapter table full")?;
+    let index = storport_add_target(adapter, 0, flags).ok_or("Block device table full")?;
+    if let Some(bdev) = crate::io::block::get_block_device_mut(index) {
+        bdev.set_name("vda");
+        bdev.set_model("VirtIO Block Device");
+        if let Some((cylinders, heads, sectors_per_track)) =
+            unsafe { (*ptr::addr_of!(DEVICE)).as_ref().and_then(|d| legacy_geometry(d)) }
+        {
+            bdev.geometry.cylinders = cylinders;
+            bdev.geometry.heads = heads;
+            bdev.geometry.sectors_per_track = sectors_per_track;
+        }
+    }
+    unsafe {
+        if let Some(d) = (*ptr::addr_of_mut!(DEVICE)).as_mut() {
+            d.block_index = index;
+        }
+    }
+    register_wmi();
+
+    crate::serial_println!(
+        "[VIRTIO-BLK] vda: {} MB, queue {}, features {:#x}, {}",
+        capacity * SECTOR_SIZE as u64 / (1024 * 1024),
+        queue_size,
+        features,
+        if IRQ_LINE.load(Ordering::Relaxed) != 0xFF { "interrupt-driven" } else { "polled" }
+    );
+    Ok(index)
+}
@@ -7,6 +7,9 @@ extern crate alloc;
 
 pub mod virtqueue;
 pub mod net;
+pub mod blk;
+pub mod balloon;
+pub mod p9;
 
 use crate::hal::pci::{PciLocation, pci_read_config_u32, config};
 
@@ -89,6 +92,8 @@ pub mod legacy_io {
     pub const QUEUE_NOTIFY: u16 = 16;
     pub const DEVICE_STATUS: u16 = 18;
     pub const ISR_STATUS: u16 = 19;
+    // Device-specific configuration (without MSI-X)
+    pub const DEVICE_CONFIG: u16 = 20;
     // Network-specific (starts at 20)
     pub const NET_MAC: u16 = 20;
     pub const NET_STATUS: u16 = 26;
@@ -211,6 +216,77 @@ impl VirtioTransport {
         }
     }
 
+    /// Read an 8-bit device-specific configuration field
+    pub fn read_config_u8(&self, offset: u16) -> u8 {
+        unsafe {
+            x86_64::instructions::port::Port::new(self.io_base + legacy_io::DEVICE_CONFIG + offset).read()
+        }
+    }
+
+    /// Write an 8-bit device-specific configuration field
+    pub fn write_config_u8(&self, offset: u16, value: u8) {
+        unsafe {
+            x86_64::instructions::port::Port::new(self.io_base + legacy_io::DEVICE_CONFIG + offset).write(value);
+        }
+    }
+
+    /// Read a 32-bit device-specific configuration field
+    pub fn read_config_u32(&self, offset: u16) -> u32 {
+        unsafe {
+            x86_64::instructions::port::Port::new(self.io_base + legacy_io::DEVICE_CONFIG + offset).read()
+        }
+    }
+
+    /// Write a 32-bit device-specific configuration field
+    pub fn write_config_u32(&self, offset: u16, value: u32) {
+        unsafe {
+            x86_64::instructions::port::Port::new(self.io_base + legacy_io::DEVICE_CONFIG + offset).write(value);
+        }
+    }
+
+    /// Read a 64-bit device-specific configuration field
+    pub fn read_config_u64(&self, offset: u16) -> u64 {
+        // Two 32-bit reads are not atomic; re-read until stable
+        loop {
+            let high = self.read_config_u32(offset + 4);
+            let low = self.read_config_u32(offset);
+            if self.read_config_u32(offset + 4) == high {
+                return ((high as u64) << 32) | low as u64;
+            }
+        }
+    }
+
+    /// Read the legacy INTx line assigned by firmware
+    pub fn interrupt_line(&self) -> u8 {
+        crate::hal::pci::pci_read_config_u8(self.location, config::INTERRUPT_LINE)
+    }
+
+    /// Connect the legacy INTx line through the PIC
+    ///
+    /// # Returns
+    /// The line, or None if the device has no usable line (polled only)
+    pub fn connect_interrupt(&self, isr: fn(*mut u8) -> bool) -> Option<u8> {
+        use crate::hal::interrupt::{hal_connect_interrupt, InterruptMode};
+
+        let line = self.interrupt_line();
+        // Lines 0-2 are the timer, keyboard and cascade; 0xFF is "not connected"
+        if !(3..16).contains(&line) || line == crate::hal::pic::irq::MOUSE {
+            return None;
+        }
+        let vector = crate::arch::x86_64::idt::vector::PIC_BASE + line;
+        // PCI INTx may be shared with other devices
+        hal_connect_interrupt(
+            isr,
+            core::ptr::null_mut(),
+            vector,
+            crate::ke::kpcr::irql::DEVICE_LEVEL_BASE + line,
+            InterruptMode::LevelSensitive,
+            true,
+        )?;
+        crate::hal::pic::enable_device_irq(line);
+        Some(line)
+    }
+
     /// Read MAC address (network devices)
     pub fn read_mac(&self) -> [u8; 6] {
         let mut mac = [0u8; 6];
@@ -224,6 +300,15 @@ impl VirtioTransport {
 
     /// Initialize device (perform feature negotiation and setup)
     pub fn init(&mut self) -> Result<(), &'static str> {
+        // Select simple features for now
+        self.negotiate(net_features::MAC).map(|_| ())
+    }
+
+    /// Reset the device and negotiate features
+    ///
+    /// # Returns
+    /// The accepted features (`wanted` masked by what the device offers)
+    pub fn negotiate(&mut self, wanted: u64) -> Result<u64, &'static str> {
         // Reset device
         self.reset();
 
@@ -237,11 +322,10 @@ impl VirtioTransport {
         let features = self.read_device_features();
         crate::serial_println!("[VIRTIO] Device features: {:#010X}", features);
 
-        // Select simple features for now
-        let our_features = net_features::MAC;
+        let our_features = features & wanted;
         self.write_guest_features(our_features);
 
-        Ok(())
+        Ok(our_features)
     }
 
     /// Complete initialization
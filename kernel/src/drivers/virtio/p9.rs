@@ -0,0 +1,334 @@
+//! VirtIO 9P Transport
+//!
+//! Carries 9P messages between the guest and a host shared folder
+//! (QEMU `-device virtio-9p-pci`). Each request is a two-descriptor
+//! chain on queue 0: the T-message the driver wrote and a buffer the
+//! device fills with the R-message.
+//!
+//! The file system client (`fs::p9fs`) issues one request at a time, so
+//! a single pair of physically contiguous message buffers is enough.
+//! Completion is signaled by interrupt when the line is connected;
+//! before interrupts are enabled the caller polls the used ring.
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use super::VirtioTransport;
+use super::virtqueue::{Virtqueue, virtqueue_size_legacy};
+use crate::ke::{EventType, KEvent};
+use crate::ke::dpc::KDpc;
+use crate::ke::spinlock::SpinLock;
+
+/// 9P device feature bits
+pub mod p9_features {
+    pub const MOUNT_TAG: u64 = 1 << 0; // Mount tag in config space
+}
+
+/// Largest message in either direction (offered as msize)
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Longest mount tag kept
+pub const MAX_TAG_LEN: usize = 32;
+
+/// Wait slice before polling the used ring ourselves
+const WAIT_SLICE_MS: u64 = 10;
+
+/// Give up on a request after this long
+const REQUEST_TIMEOUT_MS: u64 = 10_000;
+
+/// Transport errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportError {
+    /// No device initialized
+    NotReady,
+    /// Message larger than the buffers
+    TooLarge,
+    /// Another request is in flight
+    Busy,
+    /// The device did not answer
+    Timeout,
+}
+
+/// The virtio-9p device
+struct Virtio9pDevice {
+    transport: VirtioTransport,
+    queue: Virtqueue,
+    /// T-message buffer (MAX_MESSAGE_SIZE contiguous)
+    request: u64,
+    /// R-message buffer (MAX_MESSAGE_SIZE contiguous)
+    response: u64,
+    tag: [u8; MAX_TAG_LEN],
+    tag_len: usize,
+}
+
+/// The driven device (first virtio-9p found)
+static mut DEVICE: Option<Virtio9pDevice> = None;
+
+/// Protects DEVICE.queue
+static QUEUE_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// A request is in flight
+static IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+
+/// Signaled by the completion DPC
+static mut REPLY_EVENT: KEvent = KEvent::new();
+
+/// Completion DPC queued by the ISR
+static mut COMPLETION_DPC: KDpc = KDpc::new();
+
+/// Interrupt line (0xFF = polled only)
+static IRQ_LINE: AtomicU32 = AtomicU32::new(0xFF);
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+
+// ============================================================================
+// Completion
+// ============================================================================
+
+fn completion_dpc(_dpc: *mut KDpc, _context: usize, _arg1: usize, _arg2: usize) {
+    unsafe { (*ptr::addr_of!(REPLY_EVENT)).set(); }
+}
+
+/// Interrupt service routine
+fn virtio_9p_isr(_context: *mut u8) -> bool {
+    let transport = unsafe {
+        match (*ptr::addr_of!(DEVICE)).as_ref() {
+            Some(d) => &d.transport,
+            None => return false,
+        }
+    };
+
+    // Reading the ISR status acknowledges the interrupt; bit 0 = used ring
+    if transport.read_isr() & 1 == 0 {
+        return false;
+    }
+    unsafe { (*ptr::addr_of_mut!(COMPLETION_DPC)).queue_no_args(); }
+    true
+}
+
+/// Whether the caller may block on the reply event
+fn can_wait() -> bool {
+    IRQ_LINE.load(Ordering::Relaxed) != 0xFF
+        && crate::arch::x86_64::interrupts_enabled()
+        && !crate::ke::prcb::get_current_thread().is_null()
+}
+
+/// Milliseconds elapsed since `start` (performance counter ticks)
+fn elapsed_ms(start: u64) -> u64 {
+    let freq = crate::hal::timer::hal_query_performance_frequency();
+    if freq == 0 {
+        return 0;
+    }
+    crate::hal::timer::hal_query_performance_counter().wrapping_sub(start) * 1000 / freq
+}
+
+/// Poll the used ring for the request with head `head`
+fn reap(head: u16) -> Option<u32> {
+    let _guard = QUEUE_LOCK.lock();
+    let device = unsafe { (*ptr::addr_of_mut!(DEVICE)).as_mut()? };
+    while let Some((used, len)) = device.queue.poll() {
+        device.queue.free_chain(used);
+        if used == head {
+            return Some(len);
+        }
+    }
+    None
+}
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+/// Send a T-message and receive the R-message
+///
+/// # Returns
+/// The number of bytes written to `response`
+pub fn p9_transport_rpc(request: &[u8], response: &mut [u8]) -> Result<usize, TransportError> {
+    if !READY.load(Ordering::Acquire) {
+        return Err(TransportError::NotReady);
+    }
+    if request.len() > MAX_MESSAGE_SIZE {
+        return Err(TransportError::TooLarge);
+    }
+    if IN_FLIGHT.swap(true, Ordering::AcqRel) {
+        return Err(TransportError::Busy);
+    }
+    let result = rpc_locked(request, response);
+    IN_FLIGHT.store(false, Ordering::Release);
+    result
+}
+
+fn rpc_locked(request: &[u8], response: &mut [u8]) -> Result<usize, TransportError> {
+    let reply_room = response.len().min(MAX_MESSAGE_SIZE);
+    let (response_buf, head) = {
+        let _guard = QUEUE_LOCK.lock();
+        let device = unsafe { (*ptr::addr_of_mut!(DEVICE)).as_mut() }.ok_or(TransportError::NotReady)?;
+        unsafe {
+            ptr::copy_nonoverlapping(request.as_ptr(), device.request as *mut u8, request.len());
+        }
+        let out = (device.request, request.len() as u32);
+        let reply = (device.response, reply_room as u32);
+        let head = device.queue.add_buf(&[out], &[reply]).ok_or(TransportError::Busy)?;
+        device.transport.queue_notify(0);
+        (device.response, head)
+    };
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    BYTES_SENT.fetch_add(request.len() as u64, Ordering::Relaxed);
+
+    let start = crate::hal::timer::hal_query_performance_counter();
+    let len = loop {
+        if let Some(len) = reap(head) {
+            break len as usize;
+        }
+        if elapsed_ms(start) > REQUEST_TIMEOUT_MS {
+            // The device still owns the buffers, so no further requests
+            TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+            READY.store(false, Ordering::Release);
+            crate::serial_println!("[VIRTIO-9P] Request timed out, transport disabled");
+            return Err(TransportError::Timeout);
+        }
+        if can_wait() {
+            unsafe {
+                crate::ke::wait::ke_wait_for_single_object(
+                    &mut (*ptr::addr_of_mut!(REPLY_EVENT)).header as *mut _,
+                    Some(WAIT_SLICE_MS),
+                );
+            }
+        } else {
+            core::hint::spin_loop();
+        }
+    };
+
+    let len = len.min(reply_room);
+    unsafe {
+        ptr::copy_nonoverlapping(response_buf as *const u8, response.as_mut_ptr(), len);
+    }
+    BYTES_RECEIVED.fetch_add(len as u64, Ordering::Relaxed);
+    Ok(len)
+}
+
+/// Mount tag the host gave the shared folder
+pub fn p9_mount_tag() -> Option<&'static str> {
+    let device = unsafe { (*ptr::addr_of!(DEVICE)).as_ref()? };
+    core::str::from_utf8(&device.tag[..device.tag_len]).ok()
+}
+
+/// Whether a device is ready for requests
+pub fn p9_transport_ready() -> bool {
+    READY.load(Ordering::Acquire)
+}
+
+// ============================================================================
+// Statistics
+// ============================================================================
+
+/// VirtIO 9P transport statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Virtio9pStats {
+    pub ready: bool,
+    /// Interrupt line (None = polled)
+    pub irq: Option<u8>,
+    pub requests: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub timeouts: u64,
+}
+
+/// Get transport statistics
+pub fn virtio_9p_stats() -> Virtio9pStats {
+    let irq = IRQ_LINE.load(Ordering::Relaxed);
+    Virtio9pStats {
+        ready: READY.load(Ordering::Acquire),
+        irq: if irq == 0xFF { None } else { Some(irq as u8) },
+        requests: REQUESTS.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        bytes_received: BYTES_RECEIVED.load(Ordering::Relaxed),
+        timeouts: TIMEOUTS.load(Ordering::Relaxed),
+    }
+}
+
+// ============================================================================
+// Initialization
+// ============================================================================
+
+/// Find and initialize the first virtio-9p device
+pub fn init() -> Result<(), &'static str> {
+    if READY.load(Ordering::Acquire) {
+        return Ok(());
+    }
+
+    let devices = crate::hal::pci::find_virtio_9p_devices();
+    let loc = *devices.first().ok_or("No VirtIO 9P devices found")?;
+    crate::serial_println!(
+        "[VIRTIO-9P] Initializing device at {:02X}:{:02X}.{}",
+        loc.bus, loc.device, loc.function
+    );
+
+    let mut transport = VirtioTransport::new(loc).ok_or("Unsupported transport")?;
+    let features = transport.negotiate(p9_features::MOUNT_TAG)?;
+
+    let mut tag = [0u8; MAX_TAG_LEN];
+    let mut tag_len = 0;
+    if features & p9_features::MOUNT_TAG != 0 {
+        // struct virtio_9p_config { u16 tag_len; u8 tag[]; }
+        let len = (transport.read_config_u8(0) as usize) | ((transport.read_config_u8(1) as usize) << 8);
+        tag_len = len.min(MAX_TAG_LEN);
+        for (i, byte) in tag.iter_mut().enumerate().take(tag_len) {
+            *byte = transport.read_config_u8(2 + i as u16);
+        }
+    }
+
+    transport.queue_select(0);
+    let queue_size = transport.queue_size();
+    if queue_size == 0 {
+        return Err("Request queue size is 0");
+    }
+    let queue_bytes = virtqueue_size_legacy(queue_size);
+    let queue_phys = unsafe { crate::mm::mm_allocate_contiguous_memory(queue_bytes, 0xFFF_FFFF_FFFF) }
+        .ok_or("Cannot allocate virtqueue")?;
+    let queue = unsafe { Virtqueue::new(0, queue_size, queue_phys, queue_phys as *mut u8) };
+    transport.queue_set_pfn((queue_phys / crate::mm::PAGE_SIZE as u64) as u32);
+
+    let request = unsafe { crate::mm::mm_allocate_contiguous_memory(MAX_MESSAGE_SIZE, 0xFFF_FFFF_FFFF) }
+        .ok_or("Cannot allocate message buffer")?;
+    let response = unsafe { crate::mm::mm_allocate_contiguous_memory(MAX_MESSAGE_SIZE, 0xFFF_FFFF_FFFF) }
+        .ok_or("Cannot allocate message buffer")?;
+
+    unsafe {
+        (*ptr::addr_of_mut!(REPLY_EVENT)).init(EventType::Synchronization, false);
+        (*ptr::addr_of_mut!(COMPLETION_DPC)).init(completion_dpc, 0);
+    }
+
+    transport.driver_ok();
+
+    unsafe {
+        *ptr::addr_of_mut!(DEVICE) = Some(Virtio9pDevice {
+            transport,
+            queue,
+            request,
+            response,
+            tag,
+            tag_len,
+        });
+    }
+
+    let line = unsafe { (*ptr::addr_of!(DEVICE)).as_ref() }
+        .and_then(|d| d.transport.connect_interrupt(virtio_9p_isr));
+    if let Some(line) = line {
+        IRQ_LINE.store(line as u32, Ordering::Release);
+    }
+    READY.store(true, Ordering::Release);
+
+    crate::serial_println!(
+        "[VIRTIO-9P] Mount tag '{}', queue {}, {}",
+        p9_mount_tag().unwrap_or(""),
+        queue_size,
+        if IRQ_LINE.load(Ordering::Relaxed) != 0xFF { "interrupt-driven" } else { "polled" }
+    );
+    Ok(())
+}
@@ -19,11 +19,13 @@ mod buffer;
 mod event;
 mod logger;
 mod provider;
+mod sched;
 
 pub use buffer::*;
 pub use event::*;
 pub use logger::*;
 pub use provider::*;
+pub use sched::*;
 
 use crate::ke::SpinLock;
 use alloc::collections::BTreeMap;
@@ -91,6 +93,17 @@ impl Guid {
     }
 }
 
+impl core::fmt::Display for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let d = &self.data4;
+        write!(
+            f,
+            "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+            self.data1, self.data2, self.data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]
+        )
+    }
+}
+
 impl PartialOrd for Guid {
     fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         Some(self.cmp(other))
@@ -514,6 +527,10 @@ pub struct EtwStatistics {
     pub total_bytes: u64,
     /// Events dropped (buffer full)
     pub events_dropped: u64,
+    /// Scheduler events logged by the kernel logger
+    pub sched_events: u64,
+    /// Scheduler events overwritten in the ring
+    pub sched_events_lost: u64,
 }
 
 /// Get ETW statistics
@@ -526,6 +543,8 @@ pub fn etw_get_statistics() -> EtwStatistics {
     let mut stats = EtwStatistics {
         active_sessions: loggers.len() as u32,
         registered_providers: providers.len() as u32,
+        sched_events: sched_trace_total_events(),
+        sched_events_lost: sched_trace_lost_events(),
         ..Default::default()
     };
 
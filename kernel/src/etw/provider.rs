@@ -222,6 +222,8 @@ pub enum KernelTraceFlag {
     ImageLoad = 0x00000004,
     /// Disk I/O events
     DiskIo = 0x00000100,
+    /// Dispatcher events (ready thread, quantum end, priority changes)
+    Dispatcher = 0x00000800,
     /// Disk file I/O name events
     DiskFileIo = 0x00000200,
     /// Page faults
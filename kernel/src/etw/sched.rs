@@ -0,0 +1,564 @@
+//! Scheduler Trace Events
+//!
+//! Context switch and dispatcher events for the NT Kernel Logger, enabled
+//! with the `ContextSwitch` and `Dispatcher` kernel trace flags. These fire
+//! from the scheduler with interrupts disabled, so instead of going through
+//! a logger session they are written to a fixed ring of fixed-size records:
+//! one atomic increment and a 32-byte store per event.
+//!
+//! # Events
+//!
+//! - **CSwitch**: old/new thread, priorities, the old thread's state and
+//!   wait reason, and whether its quantum had expired
+//! - **ReadyThread**: a thread leaving a wait (or starting) and becoming ready
+//! - **QuantumEnd**: quantum expired, with any priority decay applied
+//! - **PriorityBoost** / **PriorityChange**: dynamic boost and base changes
+//!
+//! # Dump Format
+//!
+//! `sched_trace_dump` writes a `SchedTraceFileHeader` followed by
+//! `event_count` `SchedTraceEvent` records, oldest first, all little-endian.
+//! Timestamps are raw TSC values; the header carries the TSC frequency so
+//! the file can be analyzed offline without the running system.
+
+use super::KernelTraceFlag;
+use crate::ke::thread::{KThread, ThreadState};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+extern crate alloc;
+
+/// Events held in the ring (oldest are overwritten)
+pub const SCHED_TRACE_CAPACITY: usize = 8192;
+
+/// Dump file magic ("NSCHTRC\0")
+pub const SCHED_TRACE_MAGIC: [u8; 8] = *b"NSCHTRC\0";
+
+/// Dump file format version
+pub const SCHED_TRACE_VERSION: u32 = 1;
+
+/// Threads tracked by the context switch summary
+pub const SCHED_SUMMARY_MAX_THREADS: usize = 64;
+
+/// Processors tracked by the summary (run time is measured per CPU)
+const SUMMARY_CPUS: usize = 64;
+
+/// Scheduler event types (CSwitch and ReadyThread use the NT opcodes)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedEventType {
+    CSwitch = 36,
+    ReadyThread = 50,
+    QuantumEnd = 60,
+    PriorityBoost = 61,
+    PriorityChange = 62,
+}
+
+impl SchedEventType {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            36 => Some(Self::CSwitch),
+            50 => Some(Self::ReadyThread),
+            60 => Some(Self::QuantumEnd),
+            61 => Some(Self::PriorityBoost),
+            62 => Some(Self::PriorityChange),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CSwitch => "CSwitch",
+            Self::ReadyThread => "ReadyThread",
+            Self::QuantumEnd => "QuantumEnd",
+            Self::PriorityBoost => "PriorityBoost",
+            Self::PriorityChange => "PriorityChange",
+        }
+    }
+}
+
+/// CSwitch flag: the old thread's quantum had expired
+pub const SCHED_FLAG_QUANTUM_END: u8 = 0x01;
+
+/// CSwitch flag: the old thread is the idle thread
+pub const SCHED_FLAG_OLD_IDLE: u8 = 0x02;
+
+/// CSwitch flag: the new thread is the idle thread
+pub const SCHED_FLAG_NEW_IDLE: u8 = 0x04;
+
+/// One scheduler trace record
+///
+/// For events about a single thread, `new_thread_id` is that thread and
+/// `old_thread_id` is the thread running when the event fired.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedTraceEvent {
+    /// TSC at the time of the event
+    pub timestamp: u64,
+    /// SchedEventType
+    pub event_type: u8,
+    /// Processor number
+    pub cpu: u8,
+    /// Old thread priority (or priority before the change)
+    pub old_priority: i8,
+    /// New thread priority (or priority after the change)
+    pub new_priority: i8,
+    /// Old thread state after the switch (ThreadState)
+    pub old_state: u8,
+    /// Old thread wait reason, if it is waiting
+    pub wait_reason: u8,
+    /// SCHED_FLAG_* bits
+    pub flags: u8,
+    pub reserved: u8,
+    pub old_thread_id: u32,
+    pub new_thread_id: u32,
+    /// Event-specific value (boost amount for PriorityBoost)
+    pub data: u32,
+    pub reserved2: u32,
+}
+
+/// Header of a binary trace dump
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SchedTraceFileHeader {
+    pub magic: [u8; 8],
+    pub version: u32,
+    /// Size of this header
+    pub header_size: u32,
+    /// Size of each event record
+    pub event_size: u32,
+    /// Number of event records following the header
+    pub event_count: u32,
+    /// TSC frequency in Hz
+    pub tsc_frequency: u64,
+    /// Events overwritten before the dump
+    pub events_lost: u64,
+    /// Kernel trace flags that were enabled
+    pub trace_flags: u32,
+    pub reserved: u32,
+}
+
+static mut RING: [SchedTraceEvent; SCHED_TRACE_CAPACITY] = [SchedTraceEvent {
+    timestamp: 0, event_type: 0, cpu: 0, old_priority: 0, new_priority: 0,
+    old_state: 0, wait_reason: 0, flags: 0, reserved: 0,
+    old_thread_id: 0, new_thread_id: 0, data: 0, reserved2: 0,
+}; SCHED_TRACE_CAPACITY];
+
+/// Total events ever written (the next slot is `WRITE_INDEX % capacity`)
+static WRITE_INDEX: AtomicU64 = AtomicU64::new(0);
+
+/// Enabled KernelTraceFlag bits (ContextSwitch and/or Dispatcher)
+static TRACE_FLAGS: AtomicU32 = AtomicU32::new(0);
+
+/// Flags accepted by the scheduler tracer
+pub const SCHED_TRACE_FLAGS_MASK: u32 =
+    KernelTraceFlag::ContextSwitch as u32 | KernelTraceFlag::Dispatcher as u32;
+
+#[inline]
+fn flag_enabled(flag: KernelTraceFlag) -> bool {
+    TRACE_FLAGS.load(Ordering::Relaxed) & flag as u32 != 0
+}
+
+#[inline]
+unsafe fn thread_id(thread: *mut KThread) -> u32 {
+    if thread.is_null() { 0 } else { (*thread).thread_id }
+}
+
+#[inline]
+unsafe fn thread_priority(thread: *mut KThread) -> i8 {
+    if thread.is_null() { 0 } else { (*thread).priority }
+}
+
+fn write_event(mut event: SchedTraceEvent) {
+    event.timestamp = crate::hal::timer::read_tsc();
+    event.cpu = crate::ke::prcb::ke_get_current_processor_number() as u8;
+
+    let index = WRITE_INDEX.fetch_add(1, Ordering::Relaxed) as usize % SCHED_TRACE_CAPACITY;
+    unsafe {
+        core::ptr::write_volatile(core::ptr::addr_of_mut!(RING[index]), event);
+    }
+}
+
+// ============================================================================
+// Event Sources (called by the scheduler)
+// ============================================================================
+
+/// Log a context switch
+///
+/// Called after the old thread's state has been updated but before the
+/// register switch.
+///
+/// # Safety
+/// Thread pointers must be valid or null; called with interrupts disabled
+pub unsafe fn etw_trace_cswitch(
+    old_thread: *mut KThread,
+    new_thread: *mut KThread,
+    idle_thread: *mut KThread,
+    quantum_end: bool,
+) {
+    if !flag_enabled(KernelTraceFlag::ContextSwitch) {
+        return;
+    }
+
+    let mut flags = 0;
+    if quantum_end {
+        flags |= SCHED_FLAG_QUANTUM_END;
+    }
+    if old_thread == idle_thread {
+        flags |= SCHED_FLAG_OLD_IDLE;
+    }
+    if new_thread == idle_thread {
+        flags |= SCHED_FLAG_NEW_IDLE;
+    }
+
+    let (old_state, wait_reason) = if old_thread.is_null() {
+        (ThreadState::Initialized as u8, 0)
+    } else {
+        ((*old_thread).state as u8, (*old_thread).wait_reason)
+    };
+
+    write_event(SchedTraceEvent {
+        event_type: SchedEventType::CSwitch as u8,
+        old_priority: thread_priority(old_thread),
+        new_priority: thread_priority(new_thread),
+        old_state,
+        wait_reason,
+        flags,
+        old_thread_id: thread_id(old_thread),
+        new_thread_id: thread_id(new_thread),
+        ..Default::default()
+    });
+}
+
+/// Log a thread becoming ready after a wait or at start
+///
+/// # Safety
+/// Thread pointers must be valid or null
+pub unsafe fn etw_trace_ready_thread(current: *mut KThread, thread: *mut KThread, previous_state: ThreadState) {
+    if !flag_enabled(KernelTraceFlag::Dispatcher) {
+        return;
+    }
+
+    write_event(SchedTraceEvent {
+        event_type: SchedEventType::ReadyThread as u8,
+        new_priority: thread_priority(thread),
+        old_state: previous_state as u8,
+        wait_reason: if thread.is_null() { 0 } else { (*thread).wait_reason },
+        old_thread_id: thread_id(current),
+        new_thread_id: thread_id(thread),
+        ..Default::default()
+    });
+}
+
+/// Log a quantum expiration with the priority before and after decay
+///
+/// # Safety
+/// Thread pointer must be valid or null
+pub unsafe fn etw_trace_quantum_end(thread: *mut KThread, old_priority: i8) {
+    if !flag_enabled(KernelTraceFlag::Dispatcher) {
+        return;
+    }
+
+    write_event(SchedTraceEvent {
+        event_type: SchedEventType::QuantumEnd as u8,
+        old_priority,
+        new_priority: thread_priority(thread),
+        old_thread_id: thread_id(thread),
+        new_thread_id: thread_id(thread),
+        ..Default::default()
+    });
+}
+
+/// Log a priority boost or base priority change
+///
+/// # Safety
+/// Thread pointers must be valid or null
+pub unsafe fn etw_trace_priority(
+    event_type: SchedEventType,
+    current: *mut KThread,
+    thread: *mut KThread,
+    old_priority: i8,
+    data: u32,
+) {
+    if !flag_enabled(KernelTraceFlag::Dispatcher) {
+        return;
+    }
+
+    write_event(SchedTraceEvent {
+        event_type: event_type as u8,
+        old_priority,
+        new_priority: thread_priority(thread),
+        old_thread_id: thread_id(current),
+        new_thread_id: thread_id(thread),
+        data,
+        ..Default::default()
+    });
+}
+
+// ============================================================================
+// Control
+// ============================================================================
+
+/// Enable scheduler tracing for the given kernel trace flags
+///
+/// Clears previously collected events. Flags outside
+/// `SCHED_TRACE_FLAGS_MASK` are ignored.
+pub fn sched_trace_start(flags: u32) {
+    TRACE_FLAGS.store(0, Ordering::SeqCst);
+    WRITE_INDEX.store(0, Ordering::SeqCst);
+    TRACE_FLAGS.store(flags & SCHED_TRACE_FLAGS_MASK, Ordering::SeqCst);
+}
+
+/// Disable scheduler tracing, keeping the collected events
+pub fn sched_trace_stop() {
+    TRACE_FLAGS.store(0, Ordering::SeqCst);
+}
+
+/// Currently enabled scheduler trace flags
+pub fn sched_trace_flags() -> u32 {
+    TRACE_FLAGS.load(Ordering::Relaxed)
+}
+
+/// Total events written since the trace was started
+pub fn sched_trace_total_events() -> u64 {
+    WRITE_INDEX.load(Ordering::Relaxed)
+}
+
+/// Events overwritten because the ring wrapped
+pub fn sched_trace_lost_events() -> u64 {
+    sched_trace_total_events().saturating_sub(SCHED_TRACE_CAPACITY as u64)
+}
+
+/// Number of events currently held
+pub fn sched_trace_event_count() -> usize {
+    (sched_trace_total_events() as usize).min(SCHED_TRACE_CAPACITY)
+}
+
+/// Get the `n`th held event, oldest first
+///
+/// Events read while tracing is active may be torn; stop first for a
+/// consistent view.
+pub fn sched_trace_event(n: usize) -> Option<SchedTraceEvent> {
+    let total = sched_trace_total_events() as usize;
+    let count = total.min(SCHED_TRACE_CAPACITY);
+    if n >= count {
+        return None;
+    }
+    let index = (total - count + n) % SCHED_TRACE_CAPACITY;
+    Some(unsafe { core::ptr::read_volatile(core::ptr::addr_of!(RING[index])) })
+}
+
+/// Write the held events to a file in the binary dump format
+///
+/// Returns the number of events written.
+pub fn sched_trace_dump(path: &str) -> Result<usize, crate::fs::FsStatus> {
+    let count = sched_trace_event_count();
+    let header = SchedTraceFileHeader {
+        magic: SCHED_TRACE_MAGIC,
+        version: SCHED_TRACE_VERSION,
+        header_size: core::mem::size_of::<SchedTraceFileHeader>() as u32,
+        event_size: core::mem::size_of::<SchedTraceEvent>() as u32,
+        event_count: count as u32,
+        tsc_frequency: crate::hal::timer::hal_query_tsc_frequency(),
+        events_lost: sched_trace_lost_events(),
+        trace_flags: sched_trace_flags(),
+        reserved: 0,
+    };
+
+    let handle = crate::fs::create(path, 0)?;
+    let result = (|| -> Result<usize, crate::fs::FsStatus> {
+        crate::fs::write(handle, as_bytes(&header))?;
+
+        // Batch records to keep the number of writes down
+        const BATCH: usize = 128;
+        let mut batch = [SchedTraceEvent::default(); BATCH];
+        let mut n = 0;
+        while n < count {
+            let len = (count - n).min(BATCH);
+            for (i, slot) in batch[..len].iter_mut().enumerate() {
+                *slot = sched_trace_event(n + i).unwrap_or_default();
+            }
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    batch.as_ptr() as *const u8,
+                    len * core::mem::size_of::<SchedTraceEvent>(),
+                )
+            };
+            crate::fs::write(handle, bytes)?;
+            n += len;
+        }
+        Ok(count)
+    })();
+    let _ = crate::fs::close(handle);
+    result
+}
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+// ============================================================================
+// Analysis
+// ============================================================================
+
+/// Per-thread context switch statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadSwitchStats {
+    pub thread_id: u32,
+    /// Times switched in
+    pub switches: u32,
+    /// Times switched out while still runnable
+    pub preemptions: u32,
+    /// Times switched out with an expired quantum
+    pub quantum_ends: u32,
+    /// Times switched out to wait
+    pub waits: u32,
+    /// Total ticks spent running (between switch in and switch out)
+    pub run_ticks: u64,
+    /// Completed run intervals contributing to `run_ticks`
+    pub runs: u32,
+    /// Wait reason of the most recent wait
+    pub last_wait_reason: u8,
+}
+
+impl ThreadSwitchStats {
+    /// Average run time per interval in ticks
+    pub fn avg_run_ticks(&self) -> u64 {
+        if self.runs == 0 { 0 } else { self.run_ticks / self.runs as u64 }
+    }
+}
+
+/// Summary of a context switch trace
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedTraceSummary {
+    pub events: usize,
+    pub context_switches: u64,
+    pub quantum_ends: u64,
+    pub ready_threads: u64,
+    pub priority_boosts: u64,
+    pub priority_changes: u64,
+    /// Ticks between the first and last event
+    pub span_ticks: u64,
+    /// Threads that did not fit in the stats table
+    pub threads_dropped: u32,
+}
+
+fn stats_for(stats: &mut alloc::vec::Vec<ThreadSwitchStats>, tid: u32) -> Option<&mut ThreadSwitchStats> {
+    if let Some(pos) = stats.iter().position(|s| s.thread_id == tid) {
+        return Some(&mut stats[pos]);
+    }
+    if stats.len() >= SCHED_SUMMARY_MAX_THREADS {
+        return None;
+    }
+    stats.push(ThreadSwitchStats { thread_id: tid, ..Default::default() });
+    stats.last_mut()
+}
+
+/// Summarize the held events
+///
+/// Fills `stats` with one entry per thread seen in CSwitch events (up to
+/// `SCHED_SUMMARY_MAX_THREADS`).
+pub fn sched_trace_summarize(stats: &mut alloc::vec::Vec<ThreadSwitchStats>) -> SchedTraceSummary {
+    let mut summary = SchedTraceSummary::default();
+    let mut running_since = [(0u32, 0u64); SUMMARY_CPUS];
+    let mut first = None;
+    let mut last = 0u64;
+
+    stats.clear();
+    stats.reserve_exact(SCHED_SUMMARY_MAX_THREADS);
+
+    let count = sched_trace_event_count();
+    summary.events = count;
+    for n in 0..count {
+        let Some(event) = sched_trace_event(n) else { break };
+        first.get_or_insert(event.timestamp);
+        last = event.timestamp;
+
+        match SchedEventType::from_u8(event.event_type) {
+            Some(SchedEventType::CSwitch) => {
+                summary.context_switches += 1;
+                let cpu = event.cpu as usize % SUMMARY_CPUS;
+
+                let (since_tid, since) = running_since[cpu];
+                let old_tid = event.old_thread_id;
+                let mut dropped = false;
+                match stats_for(stats, old_tid) {
+                    Some(old) => {
+                        if since_tid == old_tid && since != 0 {
+                            old.run_ticks += event.timestamp.wrapping_sub(since);
+                            old.runs += 1;
+                        }
+                        if event.flags & SCHED_FLAG_QUANTUM_END != 0 {
+                            old.quantum_ends += 1;
+                        }
+                        if event.old_state == ThreadState::Waiting as u8 {
+                            old.waits += 1;
+                            old.last_wait_reason = event.wait_reason;
+                        } else if event.old_state == ThreadState::Ready as u8 {
+                            old.preemptions += 1;
+                        }
+                    }
+                    None => dropped = true,
+                }
+                match stats_for(stats, event.new_thread_id) {
+                    Some(new) => new.switches += 1,
+                    None => dropped = true,
+                }
+                if dropped {
+                    summary.threads_dropped += 1;
+                }
+
+                running_since[cpu] = (event.new_thread_id, event.timestamp);
+            }
+            Some(SchedEventType::ReadyThread) => summary.ready_threads += 1,
+            Some(SchedEventType::QuantumEnd) => summary.quantum_ends += 1,
+            Some(SchedEventType::PriorityBoost) => summary.priority_boosts += 1,
+            Some(SchedEventType::PriorityChange) => summary.priority_changes += 1,
+            None => {}
+        }
+    }
+
+    summary.span_ticks = last.wrapping_sub(first.unwrap_or(last));
+    summary
+}
+
+/// Name of a thread wait reason code
+pub fn wait_reason_name(reason: u8) -> &'static str {
+    match reason {
+        0 => "Executive",
+        1 => "FreePage",
+        2 => "PageIn",
+        3 => "PoolAllocation",
+        4 => "ExecutiveResource",
+        5 => "Suspended",
+        6 => "UserRequest",
+        7 => "EventPairHigh",
+        8 => "EventPairLow",
+        9 => "LpcReceive",
+        10 => "LpcReply",
+        11 => "VirtualMemory",
+        12 => "PageOut",
+        14 => "WrQueue",
+        _ => "Unknown",
+    }
+}
+
+/// Name of a thread state code
+pub fn thread_state_name(state: u8) -> &'static str {
+    match state {
+        0 => "Initialized",
+        1 => "Ready",
+        2 => "Running",
+        3 => "Standby",
+        4 => "Terminated",
+        5 => "Waiting",
+        6 => "Transition",
+        7 => "DeferredReady",
+        8 => "Suspended",
+        _ => "Unknown",
+    }
+}
+
+/// Name of an event type code
+pub fn sched_event_name(event_type: u8) -> &'static str {
+    SchedEventType::from_u8(event_type).map(|t| t.name()).unwrap_or("Unknown")
+}
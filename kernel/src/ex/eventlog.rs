@@ -10,6 +10,7 @@ use alloc::vec::Vec;
 use alloc::collections::VecDeque;
 use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use crate::ke::SpinLock;
+use crate::rtl::pattern::{rtl_is_name_in_expression, RtlRegex};
 
 /// Maximum number of events to retain in memory
 pub const MAX_EVENTS: usize = 1000;
@@ -103,6 +104,8 @@ pub struct EventRecord {
     pub message: String,
     /// Optional data
     pub data: Option<Vec<u8>>,
+    /// Insertion strings for a message-table event (`log_message`)
+    pub strings: Vec<String>,
 }
 
 impl EventRecord {
@@ -120,6 +123,7 @@ impl EventRecord {
             timestamp: crate::hal::apic::get_tick_count(),
             message,
             data: None,
+            strings: Vec::new(),
         }
     }
 
@@ -138,6 +142,48 @@ impl EventRecord {
             timestamp: crate::hal::apic::get_tick_count(),
             message,
             data: Some(data),
+            strings: Vec::new(),
+        }
+    }
+
+    /// Create an event described by the kernel's event message table
+    pub fn from_message_table(
+        event_id: u32,
+        event_type: EventType,
+        source: EventSource,
+        strings: &[&str],
+    ) -> Self {
+        let mut record = Self::new(event_id, event_type, source, String::new());
+        record.strings = strings.iter().map(|s| String::from(*s)).collect();
+        record
+    }
+
+    /// Event text for display
+    ///
+    /// Message-table events are formatted from their insertion strings
+    /// when viewed, as the Event Viewer does; an ID missing from the
+    /// table gets the Event Viewer's "cannot be found" text.
+    pub fn description(&self) -> String {
+        use crate::rtl::message::{rtl_format_kernel_message, KernelMessageTable, MessageArgument};
+
+        if self.strings.is_empty() {
+            return self.message.clone();
+        }
+        let arguments: Vec<MessageArgument> =
+            self.strings.iter().map(|s| MessageArgument::Str(s)).collect();
+        match rtl_format_kernel_message(KernelMessageTable::Event, self.event_id, &arguments) {
+            Some(text) => String::from(text.trim_end()),
+            None => {
+                let mut text = alloc::format!(
+                    "The description for Event ID ( {} ) in Source ( {} ) cannot be found. \
+                     The following information is part of the event: ",
+                    self.event_id,
+                    self.source.name()
+                );
+                text.push_str(&self.strings.join(", "));
+                text.push('.');
+                text
+            }
         }
     }
 }
@@ -230,14 +276,18 @@ pub fn log_event(event: EventRecord) -> u64 {
     }
 
     // Also output to serial for debugging
+    let description = event.description();
     crate::serial_println!(
         "[{}] [{}] Event {}: {}",
         event.source.name(),
         event.event_type.name(),
         event.event_id,
-        event.message
+        description
     );
 
+    // Forward to the remote syslog sink, if one is running
+    crate::net::syslog::sink_submit_event(event.event_type, event.source.name(), event.event_id, &description);
+
     // Store in log
     let mut log = EVENT_LOG.lock();
     if let Some(ref mut log) = *log {
@@ -277,6 +327,11 @@ pub fn log_error(source: EventSource, event_id: u32, message: &str) -> u64 {
     ))
 }
 
+/// Log an event whose text comes from the event message table
+pub fn log_message(source: EventSource, event_type: EventType, event_id: u32, strings: &[&str]) -> u64 {
+    log_event(EventRecord::from_message_table(event_id, event_type, source, strings))
+}
+
 /// Log a formatted info message
 pub fn log_info_fmt(source: EventSource, event_id: u32, message: String) -> u64 {
     log_event(EventRecord::new(
@@ -327,6 +382,21 @@ pub fn get_events_by_source(source: EventSource, count: usize) -> Vec<EventRecor
     }
 }
 
+/// Get events matching a source pattern and a description expression
+///
+/// `source` is matched against the source name with DOS wildcards, so
+/// `*` accepts every source; `text`, when given, must match somewhere in
+/// the event description. Newest first, at most `count`.
+pub fn get_events_matching(source: &str, text: Option<&RtlRegex>, count: usize) -> Vec<EventRecord> {
+    // Descriptions are formatted outside the log lock
+    get_events(MAX_EVENTS)
+        .into_iter()
+        .filter(|e| rtl_is_name_in_expression(source, e.source.name(), true))
+        .filter(|e| text.is_none_or(|regex| regex.is_match(&e.description())))
+        .take(count)
+        .collect()
+}
+
 /// Get events by type
 pub fn get_events_by_type(event_type: EventType, count: usize) -> Vec<EventRecord> {
     let log = EVENT_LOG.lock();
@@ -388,6 +458,7 @@ pub mod kernel_events {
     pub const DRIVER_LOADED: u32 = 10;
     pub const DRIVER_UNLOADED: u32 = 11;
     pub const DRIVER_ERROR: u32 = 12;
+    pub const DEADLOCK_DETECTED: u32 = 20;
 }
 
 /// Memory events (1000-1999)
@@ -396,6 +467,9 @@ pub mod memory_events {
     pub const OUT_OF_MEMORY: u32 = 1002;
     pub const POOL_CORRUPTION: u32 = 1003;
     pub const PAGE_FAULT: u32 = 1004;
+    pub const FRAGMENTATION_HIGH: u32 = 1005;
+    pub const FRAGMENTATION_NORMAL: u32 = 1006;
+    pub const HEAP_COMPACTED: u32 = 1007;
 }
 
 /// Process events (2000-2999)
@@ -6,11 +6,22 @@
 //! - Error port registration for user-mode handlers
 //! - System error handler for unhandled errors
 //! - NtRaiseHardError and ExRaiseHardError APIs
+//! - Console popups: message text, modal prompt and event log entry
+//!
+//! # Popups
+//! A raised error is formatted from its status: a caption and message
+//! text with `%1`..`%5` replaced by the parameters. Parameters whose bit
+//! is set in the string mask are `UNICODE_STRING` pointers and are
+//! captured when the error is raised. The console handler prints the
+//! popup and, when someone can answer it, takes the keyboard until a
+//! button is chosen; otherwise the default (first) button is returned.
+//! Every popup is written to the event log.
 //!
 //! Based on Windows Server 2003 base/ntos/ex/harderr.c
 
 use crate::ke::SpinLock;
 use alloc::collections::VecDeque;
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
@@ -23,6 +34,16 @@ pub const MAXIMUM_HARDERROR_PARAMETERS: usize = 5;
 /// Hard error override flag (in NTSTATUS)
 pub const HARDERROR_OVERRIDE_ERRORMODE: u32 = 0x10000000;
 
+/// Event ID of popup entries in the event log (NT "Application Popup")
+pub const EVENT_APPLICATION_POPUP: u32 = 26;
+
+/// How long a console prompt waits for an answer before taking the
+/// default button
+pub const HARD_ERROR_PROMPT_TIMEOUT_MS: u64 = 120_000;
+
+/// Console width popups are wrapped to
+const POPUP_WIDTH: usize = 72;
+
 /// Hard error response options
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -138,6 +159,8 @@ pub struct HardErrorMessage {
     pub number_of_parameters: u32,
     /// Parameters (up to 5)
     pub parameters: [usize; MAXIMUM_HARDERROR_PARAMETERS],
+    /// Captured text of the string parameters (by parameter index)
+    pub string_parameters: [Option<String>; MAXIMUM_HARDERROR_PARAMETERS],
     /// Error timestamp
     pub error_time: u64,
     /// Response from handler
@@ -162,10 +185,32 @@ impl HardErrorMessage {
             unicode_string_parameter_mask: string_mask,
             number_of_parameters: num_params,
             parameters,
+            string_parameters: Default::default(),
             error_time: crate::hal::rtc::get_system_time(),
             response: HardErrorResponse::ReturnToCaller,
         }
     }
+
+    /// Build a message whose parameters are all strings
+    pub fn with_strings(status: i32, options: HardErrorResponseOption, strings: &[&str]) -> Self {
+        let count = strings.len().min(MAXIMUM_HARDERROR_PARAMETERS);
+        let mut message = Self::new(status, options, (1u32 << count) - 1, count as u32, &[]);
+        for (slot, s) in message.string_parameters.iter_mut().zip(strings) {
+            *slot = Some(String::from(*s));
+        }
+        message
+    }
+
+    /// Text of parameter `index` as it appears in the popup
+    pub fn parameter_text(&self, index: usize) -> String {
+        if index >= (self.number_of_parameters as usize).min(MAXIMUM_HARDERROR_PARAMETERS) {
+            return String::new();
+        }
+        match &self.string_parameters[index] {
+            Some(s) => s.clone(),
+            None => format!("0x{:X}", self.parameters[index]),
+        }
+    }
 }
 
 /// Hard error handler callback type
@@ -194,6 +239,8 @@ struct HardErrorSubsystem {
     too_late_for_errors: bool,
     /// Default error handler
     default_handler: Option<HardErrorHandler>,
+    /// Default handler is the boot-time console handler (replaceable)
+    console_default: bool,
     /// Default error port process ID
     default_error_port_process: u64,
     /// Pending errors queue
@@ -209,6 +256,7 @@ impl HardErrorSubsystem {
             ready_for_errors: false,
             too_late_for_errors: false,
             default_handler: None,
+            console_default: false,
             default_error_port_process: 0,
             pending_errors: VecDeque::new(),
             error_log: VecDeque::new(),
@@ -240,9 +288,18 @@ fn get_harderr_state() -> &'static SpinLock<HardErrorSubsystem> {
 }
 
 /// Initialize hard error subsystem
+///
+/// Popups go to the console until a handler is registered with
+/// `nt_set_default_hard_error_port`.
 pub fn exp_harderr_init() {
+    let mut subsystem = HardErrorSubsystem::new();
+    subsystem.default_handler = Some(exp_console_error_handler);
+    subsystem.console_default = true;
+    subsystem.ready_for_errors = true;
+    subsystem.state = HardErrorState::Started;
+
     unsafe {
-        HARDERR_STATE = Some(SpinLock::new(HardErrorSubsystem::new()));
+        HARDERR_STATE = Some(SpinLock::new(subsystem));
     }
 
     crate::serial_println!("[EX] Hard error subsystem initialized");
@@ -291,20 +348,15 @@ fn exp_system_error_handler(
 }
 
 /// Internal raise hard error implementation
-fn exp_raise_hard_error(
-    error_status: i32,
-    number_of_parameters: u32,
-    unicode_string_parameter_mask: u32,
-    parameters: &[usize],
-    valid_response_options: HardErrorResponseOption,
-) -> Result<HardErrorResponse, i32> {
+fn exp_raise_hard_error(message: HardErrorMessage) -> Result<HardErrorResponse, i32> {
     ERRORS_RAISED.fetch_add(1, Ordering::Relaxed);
 
+    let error_status = message.status;
     let state = get_harderr_state();
     let mut guard = state.lock();
 
     // Check if system is shutting down
-    if valid_response_options == HardErrorResponseOption::ShutdownSystem {
+    if message.valid_response_options == HardErrorResponseOption::ShutdownSystem {
         guard.ready_for_errors = false;
         guard.state = HardErrorState::Shutdown;
     }
@@ -314,8 +366,8 @@ fn exp_raise_hard_error(
         drop(guard);
         exp_system_error_handler(
             error_status,
-            number_of_parameters,
-            parameters,
+            message.number_of_parameters,
+            &message.parameters,
             false,
         );
         return Ok(HardErrorResponse::ReturnToCaller);
@@ -327,16 +379,22 @@ fn exp_raise_hard_error(
         return Ok(HardErrorResponse::NotHandled);
     }
 
+    // Every popup is logged, whoever ends up displaying it
+    let (caption, text) = exp_format_hard_error(&message);
+    let error = PendingHardError {
+        message: message.clone(),
+        process_id: 0, // Would get from PsGetCurrentProcessId
+        thread_id: 0,  // Would get from PsGetCurrentThreadId
+        description: format!("{}: {}", format_error_description(error_status), text),
+    };
+    if guard.error_log.len() >= MAX_ERROR_LOG {
+        guard.error_log.pop_front();
+    }
+    guard.error_log.push_back(error.clone());
+    log_popup(error_status, &caption, &text);
+
     // Check if we have a default handler
     if let Some(handler) = guard.default_handler {
-        let message = HardErrorMessage::new(
-            error_status,
-            valid_response_options,
-            unicode_string_parameter_mask,
-            number_of_parameters,
-            parameters,
-        );
-
         drop(guard);
         let response = handler(&message);
         ERRORS_HANDLED.fetch_add(1, Ordering::Relaxed);
@@ -345,30 +403,9 @@ fn exp_raise_hard_error(
 
     // Queue the error if ready for errors
     if guard.ready_for_errors {
-        let error = PendingHardError {
-            message: HardErrorMessage::new(
-                error_status,
-                valid_response_options,
-                unicode_string_parameter_mask,
-                number_of_parameters,
-                parameters,
-            ),
-            process_id: 0, // Would get from PsGetCurrentProcessId
-            thread_id: 0,  // Would get from PsGetCurrentThreadId
-            description: format_error_description(error_status),
-        };
-
-        // Add to pending queue
         if guard.pending_errors.len() < MAX_PENDING_ERRORS {
-            guard.pending_errors.push_back(error.clone());
+            guard.pending_errors.push_back(error);
         }
-
-        // Add to error log
-        if guard.error_log.len() >= MAX_ERROR_LOG {
-            guard.error_log.pop_front();
-        }
-        guard.error_log.push_back(error);
-
         return Ok(HardErrorResponse::ReturnToCaller);
     }
 
@@ -377,6 +414,18 @@ fn exp_raise_hard_error(
     Ok(HardErrorResponse::ReturnToCaller)
 }
 
+/// Write a popup to the event log
+fn log_popup(status: i32, caption: &str, text: &str) {
+    use super::eventlog::{log_message, EventSource, EventType};
+
+    let event_type = match ntstatus_severity(status) {
+        3 => EventType::Error,
+        2 => EventType::Warning,
+        _ => EventType::Information,
+    };
+    log_message(EventSource::System, event_type, EVENT_APPLICATION_POPUP, &[caption, text]);
+}
+
 /// Format error description from status code
 fn format_error_description(status: i32) -> String {
     // Common NTSTATUS codes
@@ -391,18 +440,94 @@ fn format_error_description(status: i32) -> String {
         0xC0000034 => String::from("STATUS_OBJECT_NAME_NOT_FOUND"),
         0xC000003A => String::from("STATUS_OBJECT_PATH_NOT_FOUND"),
         0xC0000043 => String::from("STATUS_SHARING_VIOLATION"),
+        0xC0000059 => String::from("STATUS_REVISION_MISMATCH"),
         0xC0000061 => String::from("STATUS_PRIVILEGE_NOT_HELD"),
+        0xC000007B => String::from("STATUS_INVALID_IMAGE_FORMAT"),
+        0xC000009A => String::from("STATUS_INSUFFICIENT_RESOURCES"),
         0xC00000BB => String::from("STATUS_NOT_SUPPORTED"),
         0xC00000E5 => String::from("STATUS_INTERNAL_ERROR"),
         0xC0000135 => String::from("STATUS_DLL_NOT_FOUND"),
         0xC0000139 => String::from("STATUS_ENTRYPOINT_NOT_FOUND"),
         0xC0000142 => String::from("STATUS_DLL_INIT_FAILED"),
         0xC0000221 => String::from("STATUS_IMAGE_CHECKSUM_MISMATCH"),
+        0xC000026C => String::from("STATUS_DRIVER_UNABLE_TO_LOAD"),
         _ => alloc::format!("NTSTATUS 0x{:08X}", status as u32),
     }
 }
 
+/// Format a hard error's popup caption and message text
+pub fn exp_format_hard_error(message: &HardErrorMessage) -> (String, String) {
+    use crate::rtl::message::{rtl_format_kernel_message, KernelMessageTable, MessageArgument};
+
+    let status = message.status as u32 & !HARDERROR_OVERRIDE_ERRORMODE;
+    let parameters: Vec<String> = (0..MAXIMUM_HARDERROR_PARAMETERS)
+        .map(|i| message.parameter_text(i))
+        .collect();
+    let arguments: Vec<MessageArgument> = parameters.iter().map(|p| MessageArgument::Str(p)).collect();
+    if let Some(text) = rtl_format_kernel_message(KernelMessageTable::NtStatus, status, &arguments) {
+        // ntstatus texts open with "{Caption}" on a line of its own
+        let text = text.trim_end();
+        if let Some((caption, body)) = text.strip_prefix('{').and_then(|t| t.split_once("}\r\n")) {
+            return (String::from(caption), String::from(body.trim_start()));
+        }
+        return (String::from("System Error"), String::from(text));
+    }
+
+    // No message text: name the status and list the parameters
+    let caption = match ntstatus_severity(message.status) {
+        3 => "System Error",
+        2 => "Warning",
+        _ => "Information",
+    };
+    let mut text = format!("{} (0x{:08X})", format_error_description(message.status), status);
+    let count = (message.number_of_parameters as usize).min(MAXIMUM_HARDERROR_PARAMETERS);
+    for i in 0..count {
+        text.push_str(if i == 0 { ": " } else { ", " });
+        text.push_str(&message.parameter_text(i));
+    }
+    (String::from(caption), text)
+}
+
+/// Copy the string parameters named by the mask out of their
+/// `UNICODE_STRING`s
+///
+/// Strings from user mode are probed first; one that cannot be read is
+/// shown as empty.
+unsafe fn capture_string_parameters(message: &mut HardErrorMessage, from_user: bool) {
+    let count = (message.number_of_parameters as usize).min(MAXIMUM_HARDERROR_PARAMETERS);
+    for i in 0..count {
+        if message.unicode_string_parameter_mask & (1 << i) != 0 && message.string_parameters[i].is_none() {
+            message.string_parameters[i] = Some(capture_unicode_string(message.parameters[i], from_user).unwrap_or_default());
+        }
+    }
+}
+
+/// Read a `UNICODE_STRING` into a `String`
+unsafe fn capture_unicode_string(address: usize, from_user: bool) -> Option<String> {
+    use crate::rtl::string::UnicodeString;
+    use crate::mm::address::probe_for_read;
+
+    if address == 0 {
+        return None;
+    }
+    if from_user && !probe_for_read(address as u64, core::mem::size_of::<UnicodeString>()) {
+        return None;
+    }
+    let string = core::ptr::read_unaligned(address as *const UnicodeString);
+    if string.buffer.is_null() || string.length == 0 {
+        return Some(String::new());
+    }
+    if from_user && !probe_for_read(string.buffer as u64, string.length as usize) {
+        return None;
+    }
+    let wide = core::slice::from_raw_parts(string.buffer as *const u16, string.length as usize / 2);
+    Some(char::decode_utf16(wide.iter().copied()).map(|c| c.unwrap_or('?')).collect())
+}
+
 /// Raise a hard error (kernel mode API)
+///
+/// Parameters flagged in `unicode_string_parameter_mask` must point to
+/// `UNICODE_STRING`s.
 pub fn ex_raise_hard_error(
     error_status: i32,
     number_of_parameters: u32,
@@ -415,16 +540,35 @@ pub fn ex_raise_hard_error(
         return Err(-1073741811); // STATUS_INVALID_PARAMETER
     }
 
-    exp_raise_hard_error(
+    let mut message = HardErrorMessage::new(
         error_status,
-        number_of_parameters,
+        valid_response_options,
         unicode_string_parameter_mask,
+        number_of_parameters,
         parameters,
-        valid_response_options,
-    )
+    );
+    unsafe { capture_string_parameters(&mut message, false) };
+
+    exp_raise_hard_error(message)
+}
+
+/// Raise a hard error whose parameters are strings
+///
+/// The convenient form for kernel components, e.g. the loader naming the
+/// image it could not load.
+pub fn ex_raise_hard_error_with_strings(
+    error_status: i32,
+    strings: &[&str],
+    valid_response_options: HardErrorResponseOption,
+) -> HardErrorResponse {
+    let message = HardErrorMessage::with_strings(error_status, valid_response_options, strings);
+    exp_raise_hard_error(message).unwrap_or(HardErrorResponse::NotHandled)
 }
 
 /// Raise a hard error (NT syscall API)
+///
+/// String parameters are `UNICODE_STRING` pointers in the caller's
+/// address space.
 pub fn nt_raise_hard_error(
     error_status: i32,
     number_of_parameters: u32,
@@ -436,19 +580,26 @@ pub fn nt_raise_hard_error(
     let options = HardErrorResponseOption::try_from(valid_response_options)
         .map_err(|_| -1073741811i32)?; // STATUS_INVALID_PARAMETER
 
+    if number_of_parameters as usize > MAXIMUM_HARDERROR_PARAMETERS {
+        return Err(-1073741811); // STATUS_INVALID_PARAMETER
+    }
+
     // Check shutdown privilege if needed
     if options == HardErrorResponseOption::ShutdownSystem {
         // Would check SeSinglePrivilegeCheck(SeShutdownPrivilege)
         // For now, allow it
     }
 
-    ex_raise_hard_error(
+    let mut message = HardErrorMessage::new(
         error_status,
-        number_of_parameters,
+        options,
         unicode_string_parameter_mask,
+        number_of_parameters,
         parameters,
-        options,
-    )
+    );
+    unsafe { capture_string_parameters(&mut message, true) };
+
+    exp_raise_hard_error(message)
 }
 
 /// Set the default hard error port/handler
@@ -456,12 +607,13 @@ pub fn nt_set_default_hard_error_port(handler: HardErrorHandler) -> Result<(), i
     let state = get_harderr_state();
     let mut guard = state.lock();
 
-    // Can only set once
-    if guard.state == HardErrorState::Started {
+    // Can only set once (the boot-time console handler may be replaced)
+    if guard.state == HardErrorState::Started && !guard.console_default {
         return Err(-1073741823); // STATUS_UNSUCCESSFUL
     }
 
     guard.default_handler = Some(handler);
+    guard.console_default = false;
     guard.ready_for_errors = true;
     guard.state = HardErrorState::Started;
     guard.default_error_port_process = 0; // Would get from PsGetCurrentProcess
@@ -593,50 +745,154 @@ pub fn ex_raise_ari_error(status: i32) -> HardErrorResponse {
         .unwrap_or(HardErrorResponse::NotHandled)
 }
 
-/// Default console hard error handler (for shell)
-pub fn exp_console_error_handler(message: &HardErrorMessage) -> HardErrorResponse {
-    crate::kprintln!("\n*** Hard Error ***");
-    crate::kprintln!("Status: 0x{:08X}", message.status as u32);
-    crate::kprintln!("Description: {}", format_error_description(message.status));
-
-    if message.number_of_parameters > 0 {
-        crate::kprint!("Parameters: ");
-        for i in 0..(message.number_of_parameters as usize).min(MAXIMUM_HARDERROR_PARAMETERS) {
-            crate::kprint!("0x{:X} ", message.parameters[i]);
-        }
-        crate::kprintln!("");
+/// A popup button: label, hot key and the response it gives
+struct PromptButton {
+    label: &'static str,
+    key: u8,
+    response: HardErrorResponse,
+}
+
+const fn button(label: &'static str, key: u8, response: HardErrorResponse) -> PromptButton {
+    PromptButton { label, key, response }
+}
+
+/// Buttons for each response option; the first is the default
+fn prompt_buttons(options: HardErrorResponseOption) -> &'static [PromptButton] {
+    use HardErrorResponse as R;
+    use HardErrorResponseOption as O;
+
+    const OK: PromptButton = button("OK", b'o', R::Ok);
+    const CANCEL: PromptButton = button("Cancel", b'c', R::Cancel);
+    const YES: PromptButton = button("Yes", b'y', R::Yes);
+    const NO: PromptButton = button("No", b'n', R::No);
+    const RETRY: PromptButton = button("Retry", b'r', R::Retry);
+    const ABORT: PromptButton = button("Abort", b'a', R::Abort);
+    const IGNORE: PromptButton = button("Ignore", b'i', R::Ignore);
+    const TRY_AGAIN: PromptButton = button("Try Again", b't', R::TryAgain);
+    const CONTINUE: PromptButton = button("Continue", b'n', R::Continue);
+
+    match options {
+        O::AbortRetryIgnore => &[ABORT, RETRY, IGNORE],
+        O::Ok | O::OkNoWait | O::ShutdownSystem => &[OK],
+        O::OkCancel => &[OK, CANCEL],
+        O::RetryCancel => &[RETRY, CANCEL],
+        O::YesNo => &[YES, NO],
+        O::YesNoCancel => &[YES, NO, CANCEL],
+        O::CancelTryContinue => &[CANCEL, TRY_AGAIN, CONTINUE],
     }
+}
 
-    // Show valid responses based on options
-    match message.valid_response_options {
-        HardErrorResponseOption::Ok => {
-            crate::kprintln!("Press any key to continue...");
-            HardErrorResponse::Ok
-        }
-        HardErrorResponseOption::OkCancel => {
-            crate::kprintln!("[O]k / [C]ancel");
-            HardErrorResponse::Ok
-        }
-        HardErrorResponseOption::YesNo => {
-            crate::kprintln!("[Y]es / [N]o");
-            HardErrorResponse::Yes
-        }
-        HardErrorResponseOption::YesNoCancel => {
-            crate::kprintln!("[Y]es / [N]o / [C]ancel");
-            HardErrorResponse::Yes
+/// Print one popup line on the serial and framebuffer consoles
+fn popup_line(line: &str) {
+    crate::serial_println!("{}", line);
+    crate::kprintln!("{}", line);
+}
+
+/// Print text word-wrapped to the popup width
+fn popup_wrapped(text: &str) {
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > POPUP_WIDTH - 2 {
+            popup_line(&format!("  {}", line));
+            line.clear();
         }
-        HardErrorResponseOption::AbortRetryIgnore => {
-            crate::kprintln!("[A]bort / [R]etry / [I]gnore");
-            HardErrorResponse::Ignore
+        if !line.is_empty() {
+            line.push(' ');
         }
-        HardErrorResponseOption::RetryCancel => {
-            crate::kprintln!("[R]etry / [C]ancel");
-            HardErrorResponse::Cancel
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        popup_line(&format!("  {}", line));
+    }
+}
+
+/// Check if someone at the console can answer a prompt now
+///
+/// Needs a keyboard and a thread that may block; errors raised during
+/// early boot or shutdown are shown but not waited on.
+fn console_is_interactive() -> bool {
+    crate::hal::keyboard::is_initialized()
+        && !crate::ke::prcb::get_current_thread().is_null()
+        && crate::arch::x86_64::interrupts_enabled()
+        && !crate::po::is_shutdown_in_progress()
+}
+
+/// Wait for a button on the keyboard, holding it modally
+///
+/// # Returns
+/// The chosen button, or `None` on timeout
+fn read_prompt_choice(buttons: &[PromptButton]) -> Option<&PromptButton> {
+    use crate::hal::keyboard;
+
+    let deadline = crate::hal::apic::get_tick_count() + HARD_ERROR_PROMPT_TIMEOUT_MS;
+
+    // One prompt at a time
+    while !keyboard::begin_modal_input() {
+        if crate::hal::apic::get_tick_count() >= deadline {
+            return None;
         }
-        HardErrorResponseOption::CancelTryContinue => {
-            crate::kprintln!("[C]ancel / [T]ry Again / Conti[n]ue");
-            HardErrorResponse::Continue
+        super::ex_sleep(10);
+    }
+
+    let mut choice = None;
+    while choice.is_none() {
+        let now = crate::hal::apic::get_tick_count();
+        if now >= deadline {
+            break;
         }
-        _ => HardErrorResponse::ReturnToCaller,
+        let Some(key) = keyboard::read_char_timeout(deadline - now) else { break };
+        choice = match key {
+            b'\r' | b'\n' => buttons.first(),
+            27 => buttons.iter().find(|b| b.response == HardErrorResponse::Cancel)
+                .or(if buttons.len() == 1 { buttons.first() } else { None }),
+            k => buttons.iter().find(|b| b.key == k.to_ascii_lowercase()),
+        };
     }
+
+    keyboard::end_modal_input();
+    choice
+}
+
+/// Default console hard error handler
+///
+/// Prints the popup and, when the console is interactive and the options
+/// call for an answer, waits for one. Otherwise, and on timeout, the
+/// default button's response is returned.
+pub fn exp_console_error_handler(message: &HardErrorMessage) -> HardErrorResponse {
+    let (caption, text) = exp_format_hard_error(message);
+    let buttons = prompt_buttons(message.valid_response_options);
+    let default = &buttons[0];
+
+    let title = format!("--- {} ", caption);
+    let pad = POPUP_WIDTH.saturating_sub(title.len());
+    popup_line("");
+    popup_line(&format!("{}{}", title, "-".repeat(pad)));
+    popup_wrapped(&text);
+
+    let mut choices = String::new();
+    for b in buttons {
+        // Bracket the hot key: "Co[n]tinue"
+        let at = b.label.bytes().position(|c| c.to_ascii_lowercase() == b.key).unwrap_or(0);
+        choices.push_str(&format!("  {}[{}]{}", &b.label[..at], &b.label[at..at + 1], &b.label[at + 1..]));
+    }
+
+    let interactive = message.valid_response_options != HardErrorResponseOption::OkNoWait
+        && console_is_interactive();
+    let response = if interactive {
+        popup_line(&format!("{}   (Enter = {})", choices, default.label));
+        match read_prompt_choice(buttons) {
+            Some(b) => b.response,
+            None => {
+                popup_line(&format!("  No answer; choosing {}", default.label));
+                default.response
+            }
+        }
+    } else {
+        popup_line(&choices);
+        default.response
+    };
+    popup_line(&"-".repeat(POPUP_WIDTH));
+
+    crate::serial_println!("[EX] Hard error 0x{:08X} response: {:?}", message.status as u32, response);
+    response
 }
@@ -9,6 +9,7 @@
 //! - **Fast Mutexes**: Efficient kernel mutexes
 //! - **Rundown Protection**: Safe resource cleanup
 //! - **Worker Threads**: Deferred work execution
+//! - **Green Tasks**: Cooperative tasks for driver state machines
 //!
 //! # Pool Types
 //!
@@ -51,6 +52,7 @@ pub mod timezone;
 pub mod pooltag;
 pub mod ktm;
 pub mod wer;
+pub mod task;
 // pub mod pool;      // Uses mm::pool
 // pub mod timer;     // Uses ke::timer
 
@@ -79,3 +81,4 @@ pub use slist::*;
 pub use interlocked::*;
 pub use timezone::*;
 pub use pooltag::*;
+pub use task::*;
@@ -9,8 +9,8 @@
 //! Based on Windows Server 2003 base/ntos/ex/probe.c
 
 /// Maximum user-mode address
-/// Values above this are kernel addresses
-pub const MM_USER_PROBE_ADDRESS: usize = 0x7FFFFFFF0000;
+/// Values at or above this are kernel addresses
+pub const MM_USER_PROBE_ADDRESS: usize = crate::mm::MM_USER_PROBE_ADDRESS as usize;
 
 /// Page size for probing
 pub const PAGE_SIZE: usize = 4096;
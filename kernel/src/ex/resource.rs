@@ -10,6 +10,9 @@
 //! - Tracks owner thread for exclusive access
 //! - Supports shared-to-exclusive upgrade (with care)
 //! - Provides waiter counts and priority boosting
+//! - Owners must hold off normal kernel APCs (a critical region) so a
+//!   suspend APC cannot park a thread that others are waiting on; the
+//!   RAII guards and `ex_enter_critical_region_and_acquire_*` do this
 //!
 //! # Usage
 //! ```
@@ -33,6 +36,7 @@ use crate::ke::thread::KThread;
 use crate::ke::prcb::get_current_prcb;
 use crate::ke::event::{KEvent, EventType};
 use crate::ke::list::ListEntry;
+use crate::ke::apc::{ke_enter_critical_region, ke_leave_critical_region};
 
 /// Maximum number of shared waiters to track individually
 const MAX_SHARED_WAITERS: usize = 4;
@@ -375,7 +379,7 @@ impl Default for EResource {
     }
 }
 
-/// RAII guard for exclusive resource access
+/// RAII guard for exclusive resource access, inside a critical region
 pub struct EResourceExclusiveGuard<'a> {
     resource: &'a EResource,
 }
@@ -383,15 +387,18 @@ pub struct EResourceExclusiveGuard<'a> {
 impl<'a> EResourceExclusiveGuard<'a> {
     /// Acquire exclusive access and return a guard
     pub fn new(resource: &'a EResource) -> Self {
+        ke_enter_critical_region();
         resource.acquire_exclusive(true);
         Self { resource }
     }
 
     /// Try to acquire, returning None if contended
     pub fn try_new(resource: &'a EResource) -> Option<Self> {
+        ke_enter_critical_region();
         if resource.try_acquire_exclusive() {
             Some(Self { resource })
         } else {
+            ke_leave_critical_region();
             None
         }
     }
@@ -400,10 +407,11 @@ impl<'a> EResourceExclusiveGuard<'a> {
 impl<'a> Drop for EResourceExclusiveGuard<'a> {
     fn drop(&mut self) {
         self.resource.release();
+        ke_leave_critical_region();
     }
 }
 
-/// RAII guard for shared resource access
+/// RAII guard for shared resource access, inside a critical region
 pub struct EResourceSharedGuard<'a> {
     resource: &'a EResource,
 }
@@ -411,15 +419,18 @@ pub struct EResourceSharedGuard<'a> {
 impl<'a> EResourceSharedGuard<'a> {
     /// Acquire shared access and return a guard
     pub fn new(resource: &'a EResource) -> Self {
+        ke_enter_critical_region();
         resource.acquire_shared(true);
         Self { resource }
     }
 
     /// Try to acquire, returning None if contended
     pub fn try_new(resource: &'a EResource) -> Option<Self> {
+        ke_enter_critical_region();
         if resource.try_acquire_shared() {
             Some(Self { resource })
         } else {
+            ke_leave_critical_region();
             None
         }
     }
@@ -428,6 +439,7 @@ impl<'a> EResourceSharedGuard<'a> {
 impl<'a> Drop for EResourceSharedGuard<'a> {
     fn drop(&mut self) {
         self.resource.release();
+        ke_leave_critical_region();
     }
 }
 
@@ -483,6 +495,27 @@ pub fn ex_convert_exclusive_to_shared(resource: &EResource) {
     resource.convert_to_shared();
 }
 
+/// Enter a critical region and acquire exclusive (NT API compatibility)
+///
+/// Waits for the resource; release with
+/// `ex_release_resource_and_leave_critical_region`.
+pub fn ex_enter_critical_region_and_acquire_resource_exclusive(resource: &EResource) {
+    ke_enter_critical_region();
+    resource.acquire_exclusive(true);
+}
+
+/// Enter a critical region and acquire shared (NT API compatibility)
+pub fn ex_enter_critical_region_and_acquire_resource_shared(resource: &EResource) {
+    ke_enter_critical_region();
+    resource.acquire_shared(true);
+}
+
+/// Release a resource and leave the critical region (NT API compatibility)
+pub fn ex_release_resource_and_leave_critical_region(resource: &EResource) {
+    resource.release();
+    ke_leave_critical_region();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
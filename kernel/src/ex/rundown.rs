@@ -143,8 +143,8 @@ impl ExRundownRef {
                 return;
             }
 
-            // Spin wait
-            core::hint::spin_loop();
+            // Sleep until the next poll; holders drop references quickly
+            super::ex_sleep(1);
         }
     }
 
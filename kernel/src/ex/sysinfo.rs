@@ -705,7 +705,7 @@ fn query_basic_information(buffer: &mut [u8]) -> Result<usize, NtStatus> {
         highest_physical_page_number: get_highest_physical_page(),
         allocation_granularity: 65536, // 64KB
         minimum_user_mode_address: 0x10000,
-        maximum_user_mode_address: 0x7FFFFFFEFFFF, // x86_64 user space
+        maximum_user_mode_address: crate::mm::MM_HIGHEST_USER_ADDRESS as usize,
         active_processors_affinity_mask: get_active_processor_mask(),
         number_of_processors: get_number_of_processors(),
     };
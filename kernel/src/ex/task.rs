@@ -0,0 +1,666 @@
+//! Executive Green Tasks
+//!
+//! Cooperative tasks for drivers whose work is a long-running state
+//! machine (USB enumeration, a network handshake): each step runs until
+//! it has to wait, then gives its thread back. Tasks are Rust futures
+//! polled by a small pool of executor threads, so a device needs a task
+//! slot and a boxed future rather than a kernel thread and its stack.
+//!
+//! # Waiting
+//!
+//! A task waits by awaiting one of:
+//! - `TaskSignal::wait` - signaled from any context: IRP completion
+//!   routines (`ex_task_irp_completion`), DPCs, interrupt handlers
+//! - `ex_task_sleep` - a delay in milliseconds
+//! - `ex_task_wait_event` - a KEvent, checked every `EVENT_POLL_MS`
+//! - `ex_task_yield` - let other ready tasks run first
+//!
+//! A task must never block its executor thread (KeWaitForSingleObject,
+//! long spins); everything it waits for goes through an `await`.
+//!
+//! # Usage
+//! ```ignore
+//! static DONE: TaskSignal = TaskSignal::new();
+//!
+//! ex_spawn_task("usbenum", async {
+//!     // The IRP's completion routine is ex_task_irp_completion(&DONE)
+//!     io_call_driver(device, irp);
+//!     DONE.wait().await;
+//!     ex_task_sleep(10).await;
+//! })?;
+//! ```
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use crate::ke::{EventType, KEvent, SpinLock};
+
+/// Maximum live tasks
+pub const MAX_TASKS: usize = 64;
+
+/// Threads polling tasks
+const EXECUTOR_THREADS: usize = 2;
+
+/// Executor thread priority
+const EXECUTOR_PRIORITY: i8 = 8;
+
+/// How often a task waiting on a KEvent checks it
+pub const EVENT_POLL_MS: u64 = 10;
+
+/// Longest an idle executor thread sleeps
+const IDLE_WAIT_MS: u64 = 1000;
+
+type TaskFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Task state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Free,
+    /// Queued to be polled
+    Ready,
+    /// Being polled by an executor thread
+    Running,
+    /// Waiting to be woken
+    Waiting,
+}
+
+/// Identifies a task
+///
+/// Slot index plus a generation, so a late wakeup of a finished task
+/// never reaches the task that reused its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskId(u32);
+
+impl TaskId {
+    fn new(slot: usize, generation: u32) -> Self {
+        Self((generation << 8) | slot as u32)
+    }
+
+    fn slot(self) -> usize {
+        (self.0 & 0xFF) as usize
+    }
+
+    fn generation(self) -> u32 {
+        self.0 >> 8
+    }
+
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+struct TaskSlot {
+    state: TaskState,
+    generation: u32,
+    name: &'static str,
+    /// Taken out while the task is being polled
+    future: Option<TaskFuture>,
+    /// Woken while running: poll again
+    rewake: bool,
+    /// Cancelled while running: drop after the poll
+    cancel: bool,
+    /// Tick at which a waiting task is woken (0 = none)
+    wake_at: u64,
+    polls: u64,
+    spawn_tick: u64,
+}
+
+impl TaskSlot {
+    const fn new() -> Self {
+        Self {
+            state: TaskState::Free,
+            generation: 0,
+            name: "",
+            future: None,
+            rewake: false,
+            cancel: false,
+            wake_at: 0,
+            polls: 0,
+            spawn_tick: 0,
+        }
+    }
+}
+
+struct Executor {
+    tasks: [TaskSlot; MAX_TASKS],
+    /// Ready queue (ring of slot indices); a slot is queued at most once
+    ready: [u8; MAX_TASKS],
+    ready_head: usize,
+    ready_len: usize,
+}
+
+impl Executor {
+    /// Queue a task to be polled
+    fn make_ready(&mut self, slot: usize) {
+        let task = &mut self.tasks[slot];
+        task.state = TaskState::Ready;
+        task.wake_at = 0;
+        let tail = (self.ready_head + self.ready_len) % MAX_TASKS;
+        self.ready[tail] = slot as u8;
+        self.ready_len += 1;
+    }
+
+    fn pop_ready(&mut self) -> Option<usize> {
+        if self.ready_len == 0 {
+            return None;
+        }
+        let slot = self.ready[self.ready_head] as usize;
+        self.ready_head = (self.ready_head + 1) % MAX_TASKS;
+        self.ready_len -= 1;
+        Some(slot)
+    }
+
+    /// Look up a live task
+    fn task(&mut self, id: TaskId) -> Option<&mut TaskSlot> {
+        let task = self.tasks.get_mut(id.slot())?;
+        (task.state != TaskState::Free && task.generation == id.generation()).then_some(task)
+    }
+
+    /// Release a finished or cancelled task's slot; returns its future to drop
+    fn free(&mut self, slot: usize) -> Option<TaskFuture> {
+        let task = &mut self.tasks[slot];
+        task.state = TaskState::Free;
+        task.generation = task.generation.wrapping_add(1) & 0x00FF_FFFF;
+        task.rewake = false;
+        task.cancel = false;
+        task.wake_at = 0;
+        task.future.take()
+    }
+}
+
+static EXECUTOR: SpinLock<Executor> = SpinLock::new(Executor {
+    tasks: [const { TaskSlot::new() }; MAX_TASKS],
+    ready: [0; MAX_TASKS],
+    ready_head: 0,
+    ready_len: 0,
+});
+
+/// Signaled when a task becomes ready
+static mut READY_EVENT: KEvent = KEvent::new();
+
+/// Executor threads started
+static STARTED: SpinLock<bool> = SpinLock::new(false);
+static EXECUTOR_THREAD_COUNT: AtomicU32 = AtomicU32::new(0);
+
+static TASKS_SPAWNED: AtomicU64 = AtomicU64::new(0);
+static TASKS_COMPLETED: AtomicU64 = AtomicU64::new(0);
+static TASKS_CANCELLED: AtomicU64 = AtomicU64::new(0);
+static TASK_POLLS: AtomicU64 = AtomicU64::new(0);
+
+fn now_ms() -> u64 {
+    crate::hal::apic::get_tick_count()
+}
+
+fn signal_ready() {
+    unsafe { (*ptr::addr_of!(READY_EVENT)).set(); }
+}
+
+// ============================================================================
+// Wakers
+// ============================================================================
+
+static WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(waker_clone, waker_wake, waker_wake, waker_drop);
+
+unsafe fn waker_clone(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &WAKER_VTABLE)
+}
+
+unsafe fn waker_wake(data: *const ()) {
+    ex_task_wake(TaskId(data as usize as u32));
+}
+
+unsafe fn waker_drop(_data: *const ()) {}
+
+fn task_waker(id: TaskId) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(id.0 as usize as *const (), &WAKER_VTABLE)) }
+}
+
+/// The task being polled, if the waker is one of ours
+fn current_task(cx: &Context<'_>) -> Option<TaskId> {
+    let waker = cx.waker();
+    ptr::eq(waker.vtable(), &WAKER_VTABLE).then(|| TaskId(waker.data() as usize as u32))
+}
+
+/// Have the executor wake the polling task at `deadline` (a tick)
+fn wake_at(cx: &Context<'_>, deadline: u64) {
+    match current_task(cx) {
+        Some(id) => {
+            if let Some(task) = EXECUTOR.lock().task(id) {
+                task.wake_at = deadline.max(1);
+            }
+        }
+        // Polled outside the executor: ask to be polled again
+        None => cx.waker().wake_by_ref(),
+    }
+}
+
+// ============================================================================
+// Task Management
+// ============================================================================
+
+/// Start the executor threads on first use
+fn start_executor() -> Result<(), &'static str> {
+    let mut started = STARTED.lock();
+    if *started {
+        return Ok(());
+    }
+
+    unsafe { (*ptr::addr_of_mut!(READY_EVENT)).init(EventType::Synchronization, false); }
+    for _ in 0..EXECUTOR_THREADS {
+        if unsafe { crate::ke::init::create_thread(EXECUTOR_PRIORITY, executor_thread) }.is_some() {
+            EXECUTOR_THREAD_COUNT.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    if EXECUTOR_THREAD_COUNT.load(Ordering::Relaxed) == 0 {
+        return Err("Cannot create executor thread");
+    }
+
+    *started = true;
+    crate::serial_println!("[EX] Task executor started ({} threads)", EXECUTOR_THREAD_COUNT.load(Ordering::Relaxed));
+    Ok(())
+}
+
+/// Spawn a task
+///
+/// The future is polled on an executor thread until it completes.
+pub fn ex_spawn_task<F>(name: &'static str, future: F) -> Result<TaskId, &'static str>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    start_executor()?;
+    let future: TaskFuture = Box::pin(future);
+
+    let mut executor = EXECUTOR.lock();
+    let slot = match executor.tasks.iter().position(|t| t.state == TaskState::Free) {
+        Some(slot) => slot,
+        None => {
+            drop(executor);
+            drop(future);
+            return Err("Too many tasks");
+        }
+    };
+
+    let task = &mut executor.tasks[slot];
+    task.name = name;
+    task.future = Some(future);
+    task.polls = 0;
+    task.spawn_tick = now_ms();
+    let id = TaskId::new(slot, task.generation);
+    executor.make_ready(slot);
+    drop(executor);
+
+    TASKS_SPAWNED.fetch_add(1, Ordering::Relaxed);
+    signal_ready();
+    Ok(id)
+}
+
+/// Wake a task so it is polled again
+///
+/// Safe from any context; wakeups of finished tasks are ignored.
+pub fn ex_task_wake(id: TaskId) {
+    let mut executor = EXECUTOR.lock();
+    let state = match executor.task(id) {
+        Some(task) => task.state,
+        None => return,
+    };
+    match state {
+        TaskState::Waiting => {
+            executor.make_ready(id.slot());
+            drop(executor);
+            signal_ready();
+        }
+        TaskState::Running => executor.tasks[id.slot()].rewake = true,
+        _ => {}
+    }
+}
+
+/// Cancel a task, dropping its future
+///
+/// A queued or running task is dropped by the executor thread that next
+/// takes it. Returns false if the task had already finished.
+pub fn ex_task_cancel(id: TaskId) -> bool {
+    let mut executor = EXECUTOR.lock();
+    let waiting = match executor.task(id) {
+        Some(task) if task.state == TaskState::Waiting => true,
+        Some(task) => {
+            task.cancel = true;
+            false
+        }
+        None => return false,
+    };
+    if !waiting {
+        return true;
+    }
+
+    let future = executor.free(id.slot());
+    drop(executor);
+    drop(future);
+    TASKS_CANCELLED.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// Poll one ready task; returns false if none was ready
+fn run_one() -> bool {
+    let mut executor = EXECUTOR.lock();
+    let (slot, id, mut future) = loop {
+        let slot = match executor.pop_ready() {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let task = &mut executor.tasks[slot];
+        if task.cancel {
+            let future = executor.free(slot);
+            TASKS_CANCELLED.fetch_add(1, Ordering::Relaxed);
+            drop(executor);
+            drop(future);
+            executor = EXECUTOR.lock();
+            continue;
+        }
+        task.state = TaskState::Running;
+        match task.future.take() {
+            Some(future) => break (slot, TaskId::new(slot, task.generation), future),
+            None => continue,
+        }
+    };
+    drop(executor);
+
+    let waker = task_waker(id);
+    let done = future.as_mut().poll(&mut Context::from_waker(&waker)).is_ready();
+    TASK_POLLS.fetch_add(1, Ordering::Relaxed);
+
+    let mut executor = EXECUTOR.lock();
+    let task = &mut executor.tasks[slot];
+    task.polls += 1;
+    if done || task.cancel {
+        let cancelled = !done;
+        executor.free(slot);
+        drop(executor);
+        drop(future);
+        if cancelled {
+            TASKS_CANCELLED.fetch_add(1, Ordering::Relaxed);
+        } else {
+            TASKS_COMPLETED.fetch_add(1, Ordering::Relaxed);
+        }
+    } else {
+        task.future = Some(future);
+        if core::mem::take(&mut task.rewake) {
+            executor.make_ready(slot);
+        } else {
+            task.state = TaskState::Waiting;
+        }
+    }
+    true
+}
+
+/// Wake tasks whose deadline has passed
+///
+/// Returns the milliseconds until the next deadline.
+fn expire_deadlines() -> u64 {
+    let now = now_ms();
+    let mut next = IDLE_WAIT_MS;
+    let mut woke = false;
+
+    let mut executor = EXECUTOR.lock();
+    for slot in 0..MAX_TASKS {
+        let task = &executor.tasks[slot];
+        if task.state != TaskState::Waiting || task.wake_at == 0 {
+            continue;
+        }
+        if now >= task.wake_at {
+            executor.make_ready(slot);
+            woke = true;
+        } else {
+            next = next.min(task.wake_at - now);
+        }
+    }
+    drop(executor);
+
+    if woke { 0 } else { next }
+}
+
+fn executor_thread() {
+    loop {
+        while run_one() {}
+
+        let timeout = expire_deadlines();
+        if timeout == 0 {
+            continue;
+        }
+        unsafe {
+            let ready = &mut (*ptr::addr_of_mut!(READY_EVENT)).header as *mut _;
+            crate::ke::wait::ke_wait_for_single_object(ready, Some(timeout));
+        }
+    }
+}
+
+// ============================================================================
+// Wait Primitives
+// ============================================================================
+
+/// A wakeup a task can await, set from any context
+///
+/// Auto-resetting: a completed `wait` consumes the signal. A signal set
+/// before the wait starts is not lost.
+pub struct TaskSignal {
+    signaled: AtomicBool,
+    waiter: SpinLock<Option<Waker>>,
+}
+
+impl TaskSignal {
+    pub const fn new() -> Self {
+        Self {
+            signaled: AtomicBool::new(false),
+            waiter: SpinLock::new(None),
+        }
+    }
+
+    /// Signal, waking the waiting task
+    pub fn signal(&self) {
+        self.signaled.store(true, Ordering::Release);
+        let waiter = self.waiter.lock().take();
+        if let Some(waker) = waiter {
+            waker.wake();
+        }
+    }
+
+    /// Clear a pending signal
+    pub fn reset(&self) {
+        self.signaled.store(false, Ordering::Release);
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        self.signaled.load(Ordering::Acquire)
+    }
+
+    /// Wait for the signal
+    pub fn wait(&self) -> SignalWait<'_> {
+        SignalWait { signal: self }
+    }
+}
+
+impl Default for TaskSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by `TaskSignal::wait`
+pub struct SignalWait<'a> {
+    signal: &'a TaskSignal,
+}
+
+impl Future for SignalWait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let signal = self.signal;
+        if signal.signaled.swap(false, Ordering::AcqRel) {
+            return Poll::Ready(());
+        }
+        *signal.waiter.lock() = Some(cx.waker().clone());
+
+        // A signal between the check and the registration found no waiter
+        if signal.signaled.swap(false, Ordering::AcqRel) {
+            signal.waiter.lock().take();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+/// IRP completion routine that signals the `TaskSignal` given as context
+///
+/// Returns STATUS_MORE_PROCESSING_REQUIRED, so the IRP stays with the
+/// task: after the wait it reads the IRP's status and frees it.
+pub extern "C" fn ex_task_irp_completion(
+    _device: *mut crate::io::DeviceObject,
+    _irp: *mut crate::io::Irp,
+    context: *mut u8,
+) -> i32 {
+    if !context.is_null() {
+        unsafe { (*(context as *const TaskSignal)).signal() };
+    }
+    -1073741802 // STATUS_MORE_PROCESSING_REQUIRED
+}
+
+/// Future returned by `ex_task_sleep`
+pub struct TaskSleep {
+    duration_ms: u64,
+    deadline: u64,
+}
+
+impl Future for TaskSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let now = now_ms();
+        if self.deadline == 0 {
+            self.deadline = now + self.duration_ms;
+        }
+        if now >= self.deadline {
+            return Poll::Ready(());
+        }
+        wake_at(cx, self.deadline);
+        Poll::Pending
+    }
+}
+
+/// Sleep for `ms` milliseconds
+pub fn ex_task_sleep(ms: u64) -> TaskSleep {
+    TaskSleep { duration_ms: ms, deadline: 0 }
+}
+
+/// Future returned by `ex_task_wait_event`
+pub struct EventWait<'a> {
+    event: &'a KEvent,
+}
+
+impl Future for EventWait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Satisfies the wait like KeWaitForSingleObject: resets a
+        // synchronization event
+        if unsafe { self.event.try_wait() } {
+            return Poll::Ready(());
+        }
+        wake_at(cx, now_ms() + EVENT_POLL_MS);
+        Poll::Pending
+    }
+}
+
+/// Wait for a KEvent to be signaled
+pub fn ex_task_wait_event(event: &KEvent) -> EventWait<'_> {
+    EventWait { event }
+}
+
+/// Future returned by `ex_task_yield`
+pub struct TaskYield {
+    yielded: bool,
+}
+
+impl Future for TaskYield {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Let other ready tasks run before continuing
+pub fn ex_task_yield() -> TaskYield {
+    TaskYield { yielded: false }
+}
+
+// ============================================================================
+// Inspection
+// ============================================================================
+
+/// Executor statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskStats {
+    pub threads: u32,
+    pub live: u32,
+    pub ready: u32,
+    pub spawned: u64,
+    pub completed: u64,
+    pub cancelled: u64,
+    pub polls: u64,
+}
+
+/// A task's state for display
+#[derive(Debug, Clone, Copy)]
+pub struct TaskSnapshot {
+    pub id: TaskId,
+    pub name: &'static str,
+    pub state: TaskState,
+    pub polls: u64,
+    /// Milliseconds since spawned
+    pub age_ms: u64,
+    /// Milliseconds until a timed wakeup
+    pub wake_in_ms: Option<u64>,
+}
+
+pub fn ex_get_task_stats() -> TaskStats {
+    let executor = EXECUTOR.lock();
+    TaskStats {
+        threads: EXECUTOR_THREAD_COUNT.load(Ordering::Relaxed),
+        live: executor.tasks.iter().filter(|t| t.state != TaskState::Free).count() as u32,
+        ready: executor.ready_len as u32,
+        spawned: TASKS_SPAWNED.load(Ordering::Relaxed),
+        completed: TASKS_COMPLETED.load(Ordering::Relaxed),
+        cancelled: TASKS_CANCELLED.load(Ordering::Relaxed),
+        polls: TASK_POLLS.load(Ordering::Relaxed),
+    }
+}
+
+pub fn ex_get_task_snapshots() -> Vec<TaskSnapshot> {
+    let mut snapshots = Vec::with_capacity(MAX_TASKS);
+    let now = now_ms();
+    let executor = EXECUTOR.lock();
+    for (slot, task) in executor.tasks.iter().enumerate() {
+        if task.state == TaskState::Free {
+            continue;
+        }
+        snapshots.push(TaskSnapshot {
+            id: TaskId::new(slot, task.generation),
+            name: task.name,
+            state: task.state,
+            polls: task.polls,
+            age_ms: now.saturating_sub(task.spawn_tick),
+            wake_in_ms: (task.wake_at != 0).then(|| task.wake_at.saturating_sub(now)),
+        });
+    }
+    snapshots
+}
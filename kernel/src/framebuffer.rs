@@ -1,7 +1,14 @@
 //! Framebuffer output for early kernel messages
 //!
 //! Provides basic text output to the UEFI framebuffer before proper
-//! display drivers are loaded.
+//! display drivers are loaded, and the few drawing primitives the boot
+//! screen (`bootscreen`) needs.
+//!
+//! Once the memory manager is up, `enable_double_buffer` moves drawing
+//! to a back buffer in RAM; changed rows are copied to the framebuffer
+//! after each print or drawing call. Scrolling then reads from RAM
+//! instead of (slow, uncached) video memory, and a partly drawn boot
+//! screen is never visible.
 
 use core::fmt::{self, Write};
 use core::ptr;
@@ -10,10 +17,10 @@ use spin::Mutex;
 use crate::BootInfo;
 
 /// Font width in pixels
-const FONT_WIDTH: u32 = 8;
+pub const FONT_WIDTH: u32 = 8;
 
 /// Font height in pixels
-const FONT_HEIGHT: u32 = 16;
+pub const FONT_HEIGHT: u32 = 16;
 
 /// Basic 8x16 font (CP437-style)
 /// Each character is 16 bytes, one byte per row
@@ -37,6 +44,11 @@ struct FramebufferWriter {
     fg_color: u32,
     /// Background color
     bg_color: u32,
+    /// Back buffer, same stride as `buffer` (null = draw directly)
+    back: *mut u32,
+    /// Rows changed in the back buffer since the last present (top..bottom)
+    dirty_top: u32,
+    dirty_bottom: u32,
 }
 
 unsafe impl Send for FramebufferWriter {}
@@ -53,6 +65,9 @@ impl FramebufferWriter {
             cursor_y: 0,
             fg_color: 0x00FFFFFF, // White
             bg_color: 0x00000080, // Dark blue
+            back: ptr::null_mut(),
+            dirty_top: 0,
+            dirty_bottom: 0,
         }
     }
 
@@ -75,20 +90,73 @@ impl FramebufferWriter {
         !self.buffer.is_null() && self.width > 0 && self.height > 0
     }
 
-    /// Clear the screen
-    fn clear(&mut self) {
-        if !self.is_initialized() {
+    /// Buffer that drawing goes to
+    fn surface(&self) -> *mut u32 {
+        if self.back.is_null() { self.buffer } else { self.back }
+    }
+
+    /// Write one pixel; the caller marks the rows dirty
+    #[inline]
+    fn put_pixel(&mut self, x: u32, y: u32, color: u32) {
+        if x < self.width && y < self.height {
+            unsafe {
+                ptr::write_volatile(self.surface().add((y * self.stride + x) as usize), color);
+            }
+        }
+    }
+
+    #[inline]
+    fn get_pixel(&self, x: u32, y: u32) -> u32 {
+        unsafe { ptr::read_volatile(self.surface().add((y * self.stride + x) as usize)) }
+    }
+
+    /// Note rows `top..bottom` as changed
+    fn mark_dirty(&mut self, top: u32, bottom: u32) {
+        let bottom = bottom.min(self.height);
+        if top >= bottom {
             return;
         }
+        if self.dirty_top >= self.dirty_bottom {
+            self.dirty_top = top;
+            self.dirty_bottom = bottom;
+        } else {
+            self.dirty_top = self.dirty_top.min(top);
+            self.dirty_bottom = self.dirty_bottom.max(bottom);
+        }
+    }
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                unsafe {
-                    let offset = (y * self.stride + x) as isize;
-                    ptr::write_volatile(self.buffer.offset(offset), self.bg_color);
-                }
+    /// Copy the changed rows of the back buffer to the framebuffer
+    fn present(&mut self) {
+        if self.back.is_null() || self.dirty_top >= self.dirty_bottom {
+            return;
+        }
+        for y in self.dirty_top..self.dirty_bottom {
+            let offset = (y * self.stride) as usize;
+            unsafe {
+                ptr::copy_nonoverlapping(self.back.add(offset), self.buffer.add(offset), self.width as usize);
+            }
+        }
+        self.dirty_top = 0;
+        self.dirty_bottom = 0;
+    }
+
+    /// Fill a rectangle, clipped to the screen
+    fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: u32) {
+        if !self.is_initialized() {
+            return;
+        }
+        for row in y..y.saturating_add(height).min(self.height) {
+            for col in x..x.saturating_add(width).min(self.width) {
+                self.put_pixel(col, row, color);
             }
         }
+        self.mark_dirty(y, y.saturating_add(height));
+    }
+
+    /// Clear the screen
+    fn clear(&mut self) {
+        let (width, height, color) = (self.width, self.height, self.bg_color);
+        self.fill_rect(0, 0, width, height, color);
         self.cursor_x = 0;
         self.cursor_y = 0;
     }
@@ -111,28 +179,18 @@ impl FramebufferWriter {
 
         // Copy each line up
         let line_height = FONT_HEIGHT;
-        let bytes_per_line = self.stride;
 
         for y in 0..(self.height - line_height) {
             for x in 0..self.width {
-                unsafe {
-                    let src_offset = ((y + line_height) * bytes_per_line + x) as isize;
-                    let dst_offset = (y * bytes_per_line + x) as isize;
-                    let pixel = ptr::read_volatile(self.buffer.offset(src_offset));
-                    ptr::write_volatile(self.buffer.offset(dst_offset), pixel);
-                }
+                let pixel = self.get_pixel(x, y + line_height);
+                self.put_pixel(x, y, pixel);
             }
         }
+        self.mark_dirty(0, self.height);
 
         // Clear the last line
-        for y in (self.height - line_height)..self.height {
-            for x in 0..self.width {
-                unsafe {
-                    let offset = (y * bytes_per_line + x) as isize;
-                    ptr::write_volatile(self.buffer.offset(offset), self.bg_color);
-                }
-            }
-        }
+        let (width, height, color) = (self.width, self.height, self.bg_color);
+        self.fill_rect(0, height - line_height, width, line_height, color);
     }
 
     /// Put a character at the current cursor position
@@ -182,6 +240,12 @@ impl FramebufferWriter {
 
     /// Draw a character at the current cursor position
     fn draw_char(&mut self, c: char) {
+        let (x, y, fg, bg) = (self.cursor_x * FONT_WIDTH, self.cursor_y * FONT_HEIGHT, self.fg_color, self.bg_color);
+        self.draw_glyph(x, y, c, fg, bg);
+    }
+
+    /// Draw a character at a pixel position
+    fn draw_glyph(&mut self, screen_x: u32, screen_y: u32, c: char, fg: u32, bg: u32) {
         let char_index = if c.is_ascii() { c as usize } else { '?' as usize };
 
         // Get font data for this character (16 bytes)
@@ -192,30 +256,13 @@ impl FramebufferWriter {
 
         let char_data = &FONT[font_offset..font_offset + 16];
 
-        let screen_x = self.cursor_x * FONT_WIDTH;
-        let screen_y = self.cursor_y * FONT_HEIGHT;
-
         for (row, &byte) in char_data.iter().enumerate() {
             for col in 0..8 {
-                let pixel_x = screen_x + col;
-                let pixel_y = screen_y + row as u32;
-
-                if pixel_x >= self.width || pixel_y >= self.height {
-                    continue;
-                }
-
-                let color = if (byte >> (7 - col)) & 1 != 0 {
-                    self.fg_color
-                } else {
-                    self.bg_color
-                };
-
-                unsafe {
-                    let offset = (pixel_y * self.stride + pixel_x) as isize;
-                    ptr::write_volatile(self.buffer.offset(offset), color);
-                }
+                let color = if (byte >> (7 - col)) & 1 != 0 { fg } else { bg };
+                self.put_pixel(screen_x + col, screen_y + row as u32, color);
             }
         }
+        self.mark_dirty(screen_y, screen_y + FONT_HEIGHT);
     }
 }
 
@@ -239,6 +286,115 @@ pub fn init(boot_info: &BootInfo) {
     WRITER.lock().init(boot_info);
 }
 
+/// Move drawing to a back buffer in RAM (needs the memory manager)
+///
+/// The buffer is a physically contiguous, identity-mapped block; it is
+/// too large for pool.
+pub fn enable_double_buffer() {
+    let mut writer = WRITER.lock();
+    if !writer.is_initialized() || !writer.back.is_null() {
+        return;
+    }
+
+    let pixels = (writer.stride * writer.height) as usize;
+    let Some(back) = (unsafe { crate::mm::mm_allocate_contiguous_memory(pixels * 4, 0xFFF_FFFF_FFFF) }) else {
+        crate::serial_println!("[FB] No memory for a {} KB back buffer", pixels * 4 / 1024);
+        return;
+    };
+    let back = back as *mut u32;
+    unsafe {
+        ptr::copy_nonoverlapping(writer.buffer, back, pixels);
+    }
+    writer.back = back;
+}
+
+/// Address and size of the back buffer, for pinning
+///
+/// `None` until `enable_double_buffer` has set one up.
+pub fn back_buffer_range() -> Option<(u64, usize)> {
+    let writer = WRITER.lock();
+    if writer.back.is_null() {
+        return None;
+    }
+    Some((writer.back as u64, (writer.stride * writer.height) as usize * 4))
+}
+
+/// Screen size in pixels, or (0, 0) without a framebuffer
+pub fn screen_size() -> (u32, u32) {
+    let writer = WRITER.lock();
+    if writer.is_initialized() { (writer.width, writer.height) } else { (0, 0) }
+}
+
+/// Fill a rectangle
+pub fn fill_rect(x: u32, y: u32, width: u32, height: u32, color: u32) {
+    if is_disabled() {
+        return;
+    }
+    let mut writer = WRITER.lock();
+    writer.fill_rect(x, y, width, height, color);
+    writer.present();
+}
+
+/// Draw text at a pixel position (no wrapping)
+pub fn draw_text(x: u32, y: u32, text: &str, fg: u32, bg: u32) {
+    if is_disabled() {
+        return;
+    }
+    let mut writer = WRITER.lock();
+    if !writer.is_initialized() {
+        return;
+    }
+    for (i, c) in text.chars().enumerate() {
+        writer.draw_glyph(x + i as u32 * FONT_WIDTH, y, c, fg, bg);
+    }
+    writer.present();
+}
+
+/// Draw a 32-bit BGRA image, each pixel scaled to `scale` x `scale`,
+/// alpha-blended over what is on screen
+pub fn draw_image(x: u32, y: u32, width: u32, height: u32, scale: u32, bgra: &[u8]) {
+    if is_disabled() || bgra.len() < (width * height * 4) as usize {
+        return;
+    }
+    let mut writer = WRITER.lock();
+    if !writer.is_initialized() {
+        return;
+    }
+    for row in 0..height {
+        for col in 0..width {
+            let i = ((row * width + col) * 4) as usize;
+            let alpha = bgra[i + 3] as u32;
+            if alpha == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let (px, py) = (x + col * scale + dx, y + row * scale + dy);
+                    if px >= writer.width || py >= writer.height {
+                        continue;
+                    }
+                    let under = writer.get_pixel(px, py);
+                    let blend = |shift: u32, src: u8| {
+                        let dst = (under >> shift) & 0xFF;
+                        ((src as u32 * alpha + dst * (255 - alpha)) / 255) << shift
+                    };
+                    let color = blend(0, bgra[i]) | blend(8, bgra[i + 1]) | blend(16, bgra[i + 2]);
+                    writer.put_pixel(px, py, color);
+                }
+            }
+        }
+    }
+    writer.mark_dirty(y, y + height * scale);
+    writer.present();
+}
+
+/// Clear the text console and home the cursor
+pub fn clear() {
+    let mut writer = WRITER.lock();
+    writer.clear();
+    writer.present();
+}
+
 /// Disable framebuffer text output (for graphical desktop)
 pub fn disable() {
     DISABLED.store(true, core::sync::atomic::Ordering::Release);
@@ -261,8 +417,19 @@ pub fn _print(args: fmt::Arguments) {
     if DISABLED.load(core::sync::atomic::Ordering::Acquire) {
         return;
     }
+    // The boot screen keeps the text until it is asked for
+    if crate::bootscreen::capture(args) {
+        return;
+    }
+    write_text(args);
+}
+
+/// Write to the text console, bypassing the boot screen
+pub fn write_text(args: fmt::Arguments) {
     use core::fmt::Write;
-    WRITER.lock().write_fmt(args).unwrap();
+    let mut writer = WRITER.lock();
+    writer.write_fmt(args).unwrap();
+    writer.present();
 }
 
 /// Print macro
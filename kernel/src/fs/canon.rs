@@ -0,0 +1,273 @@
+//! Path Canonicalization
+//!
+//! Every path handed to the high-level file operations passes through
+//! here before mount resolution:
+//!
+//! 1. `/` becomes `\` and repeated separators collapse
+//! 2. `\??\`, `\DosDevices\` and `\GLOBAL??\` prefixes are stripped
+//! 3. Drive letters defined as DOS device aliases (`subst X: C:\PROJECTS`)
+//!    are replaced by their targets, following chained aliases
+//! 4. `.` and `..` components are resolved, never climbing above the root
+//!
+//! The result is either `X:\...` on a mounted drive letter or an NT device
+//! path such as `\Device\HarddiskVolume1\...`.
+
+use super::path::MAX_PATH;
+use super::vfs::FsStatus;
+
+/// Maximum chained alias expansions (guards against alias loops)
+pub const MAX_ALIAS_DEPTH: usize = 8;
+
+/// Maximum path components tracked while resolving `..`
+const MAX_COMPONENTS: usize = 64;
+
+/// Prefixes naming the DOS device directory
+const DOS_DEVICE_PREFIXES: [&[u8]; 3] = [b"\\??\\", b"\\DosDevices\\", b"\\GLOBAL??\\"];
+
+/// Check for a leading `X:`
+fn has_drive(path: &[u8]) -> bool {
+    path.len() >= 2 && path[1] == b':' && path[0].is_ascii_alphabetic()
+}
+
+/// Append `src` to `buf`, converting separators and collapsing repeats
+fn append_normalized(buf: &mut [u8; MAX_PATH], len: &mut usize, src: &[u8]) -> Result<(), FsStatus> {
+    for &c in src {
+        let c = if c == b'/' { b'\\' } else { c };
+        if c == b'\\' && *len > 0 && buf[*len - 1] == b'\\' {
+            continue;
+        }
+        if *len >= MAX_PATH {
+            return Err(FsStatus::InvalidParameter);
+        }
+        buf[*len] = c;
+        *len += 1;
+    }
+    Ok(())
+}
+
+/// Copy a path into `buf` with separators normalized and DOS device
+/// directory prefixes removed
+fn load(buf: &mut [u8; MAX_PATH], src: &[u8]) -> Result<usize, FsStatus> {
+    let mut len = 0;
+    append_normalized(buf, &mut len, src)?;
+
+    for prefix in DOS_DEVICE_PREFIXES {
+        if len > prefix.len() && buf[..prefix.len()].eq_ignore_ascii_case(prefix) {
+            buf.copy_within(prefix.len()..len, 0);
+            len -= prefix.len();
+            break;
+        }
+    }
+    Ok(len)
+}
+
+/// Replace aliased drive letters with their targets
+fn expand_aliases(buf: &mut [u8; MAX_PATH], mut len: usize) -> Result<usize, FsStatus> {
+    for _ in 0..MAX_ALIAS_DEPTH {
+        if !has_drive(&buf[..len]) {
+            return Ok(len);
+        }
+        let target = match crate::ob::ob_query_dos_device(buf[0] as char) {
+            Some(t) => t,
+            None => return Ok(len),
+        };
+
+        let mut next = [0u8; MAX_PATH];
+        let mut next_len = load(&mut next, target.as_bytes())?;
+        let rest = &buf[2..len];
+        if !rest.is_empty() && rest[0] != b'\\' {
+            append_normalized(&mut next, &mut next_len, b"\\")?;
+        }
+        append_normalized(&mut next, &mut next_len, rest)?;
+
+        *buf = next;
+        len = next_len;
+    }
+
+    // Still aliased after MAX_ALIAS_DEPTH expansions: an alias loop
+    if has_drive(&buf[..len]) && crate::ob::ob_query_dos_device(buf[0] as char).is_some() {
+        return Err(FsStatus::InvalidParameter);
+    }
+    Ok(len)
+}
+
+/// Canonicalize a path into `out`
+///
+/// # Returns
+/// The canonical path, borrowed from `out`
+pub fn canonicalize_path<'a>(path: &str, out: &'a mut [u8; MAX_PATH]) -> Result<&'a str, FsStatus> {
+    let mut work = [0u8; MAX_PATH];
+    let len = load(&mut work, path.as_bytes())?;
+    let len = expand_aliases(&mut work, len)?;
+    let work = &work[..len];
+
+    // Root: "X:\", "\" or nothing for relative paths
+    let drive = has_drive(work);
+    let rest = if drive { &work[2..] } else { work };
+
+    let mut out_len = 0;
+    if drive {
+        out[0] = work[0].to_ascii_uppercase();
+        out[1] = b':';
+        out[2] = b'\\';
+        out_len = 3;
+    } else if rest.first() == Some(&b'\\') {
+        out[0] = b'\\';
+        out_len = 1;
+    }
+    let root_len = out_len;
+
+    // Offsets in `out` where each kept component starts
+    let mut starts = [0usize; MAX_COMPONENTS];
+    let mut depth = 0;
+
+    for component in rest.split(|&c| c == b'\\') {
+        match component {
+            b"" | b"." => {}
+            b".." => {
+                if depth > 0 {
+                    depth -= 1;
+                    out_len = starts[depth];
+                }
+            }
+            _ => {
+                if depth >= MAX_COMPONENTS {
+                    return Err(FsStatus::InvalidParameter);
+                }
+                let separator = usize::from(out_len > root_len);
+                if out_len + separator + component.len() > MAX_PATH {
+                    return Err(FsStatus::InvalidParameter);
+                }
+                starts[depth] = out_len;
+                depth += 1;
+                if separator == 1 {
+                    out[out_len] = b'\\';
+                    out_len += 1;
+                }
+                out[out_len..out_len + component.len()].copy_from_slice(component);
+                out_len += component.len();
+            }
+        }
+    }
+
+    core::str::from_utf8(&out[..out_len]).map_err(|_| FsStatus::InvalidParameter)
+}
+
+/// Load DOS device aliases persisted in the registry
+///
+/// Values under the Session Manager `DOS Devices` key map a drive name
+/// (`X:`) to its target and become global aliases.
+pub fn load_persistent_aliases() {
+    use crate::cm;
+
+    let key = match unsafe { cm::cm_open_key(DOS_DEVICES_KEY) } {
+        Ok(k) => k,
+        Err(_) => return,
+    };
+
+    let mut loaded = 0;
+    let mut index = 0;
+    while let Ok(value) = unsafe { cm::cm_enumerate_value(key, index) } {
+        index += 1;
+        let name = value.name.as_str().as_bytes();
+        let target = match value.get_string() {
+            Some(t) => t,
+            None => continue,
+        };
+        if name.len() == 2 && has_drive(name)
+            && crate::ob::ob_define_dos_device(None, name[0] as char, target) == 0
+        {
+            loaded += 1;
+        }
+    }
+
+    if loaded > 0 {
+        crate::serial_println!("[FS] Loaded {} persistent DOS device aliases", loaded);
+    }
+}
+
+/// Registry key holding persistent DOS device aliases
+pub const DOS_DEVICES_KEY: &str =
+    "MACHINE\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\DOS Devices";
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use super::*;
+    use crate::ob;
+    use alloc::format;
+    use alloc::string::String;
+
+    fn canon(path: &str) -> Result<String, FsStatus> {
+        let mut out = [0u8; MAX_PATH];
+        canonicalize_path(path, &mut out).map(String::from)
+    }
+
+    #[test]
+    fn test_separators() {
+        assert_eq!(canon("C:/WINDOWS//system32").unwrap(), "C:\\WINDOWS\\system32");
+        assert_eq!(canon("c:\\\\a\\\\\\b\\").unwrap(), "C:\\a\\b");
+        assert_eq!(canon("C:").unwrap(), "C:\\");
+    }
+
+    #[test]
+    fn test_dot_components() {
+        assert_eq!(canon("C:\\a\\.\\b\\..\\c").unwrap(), "C:\\a\\c");
+        assert_eq!(canon("C:\\a\\b\\..\\..").unwrap(), "C:\\");
+        // `..` never climbs above the root
+        assert_eq!(canon("C:\\..\\..\\x").unwrap(), "C:\\x");
+        assert_eq!(canon("\\Device\\..\\..\\y").unwrap(), "\\y");
+        assert_eq!(canon("a\\..\\..\\b").unwrap(), "b");
+        assert_eq!(canon("C:\\...\\x").unwrap(), "C:\\...\\x");
+    }
+
+    #[test]
+    fn test_dos_device_prefixes() {
+        assert_eq!(canon("\\??\\C:\\dir").unwrap(), "C:\\dir");
+        assert_eq!(canon("\\DosDevices\\d:\\x").unwrap(), "D:\\x");
+        assert_eq!(canon("\\global??\\C:\\x").unwrap(), "C:\\x");
+        assert_eq!(canon("//??/C:/x").unwrap(), "C:\\x");
+        assert_eq!(canon("\\Device\\HarddiskVolume1\\x").unwrap(), "\\Device\\HarddiskVolume1\\x");
+    }
+
+    #[test]
+    fn test_limits() {
+        let long = format!("C:\\{}", "a".repeat(MAX_PATH));
+        assert_eq!(canon(&long), Err(FsStatus::InvalidParameter));
+
+        let exact = format!("C:\\{}", "a".repeat(MAX_PATH - 3));
+        assert_eq!(canon(&exact).unwrap().len(), MAX_PATH);
+
+        let deep = "\\x".repeat(MAX_COMPONENTS);
+        assert!(canon(&deep).is_ok());
+        let too_deep = "\\x".repeat(MAX_COMPONENTS + 1);
+        assert_eq!(canon(&too_deep), Err(FsStatus::InvalidParameter));
+        // Resolved components do not count
+        let folded = format!("{}\\..\\y", "\\x".repeat(MAX_COMPONENTS));
+        assert!(canon(&folded).unwrap().ends_with("\\x\\y"));
+    }
+
+    #[test]
+    fn test_aliases() {
+        assert_eq!(ob::ob_define_dos_device(None, 'J', "C:\\PROJECTS"), 0);
+        assert_eq!(ob::ob_define_dos_device(None, 'K', "\\??\\J:\\SUB/"), 0);
+        assert_eq!(ob::ob_define_dos_device(None, 'L', "M:"), 0);
+        assert_eq!(ob::ob_define_dos_device(None, 'M', "L:"), 0);
+
+        assert_eq!(canon("j:\\src\\..\\main.c").unwrap(), "C:\\PROJECTS\\main.c");
+        assert_eq!(canon("J:file.c").unwrap(), "C:\\PROJECTS\\file.c");
+        assert_eq!(canon("J:").unwrap(), "C:\\PROJECTS");
+        // Chained aliases expand through
+        assert_eq!(canon("K:\\x").unwrap(), "C:\\PROJECTS\\SUB\\x");
+        // `..` stops at the target's root, not the alias
+        assert_eq!(canon("K:\\..\\..\\..\\y").unwrap(), "C:\\y");
+        // An alias loop is an error
+        assert_eq!(canon("L:\\x"), Err(FsStatus::InvalidParameter));
+
+        for letter in ['J', 'K', 'L', 'M'] {
+            ob::ob_delete_dos_device(None, letter);
+        }
+        assert_eq!(canon("j:\\src").unwrap(), "J:\\src");
+    }
+}
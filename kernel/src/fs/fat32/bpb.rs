@@ -381,6 +381,14 @@ pub mod cluster_values {
     }
 }
 
+/// Volume state bits kept in the high nibble of FAT entry 1
+pub mod volume_flags {
+    /// Set while the volume is cleanly dismounted
+    pub const CLEAN_SHUTDOWN: u32 = 0x08000000;
+    /// Cleared after a disk I/O error
+    pub const NO_HARD_ERRORS: u32 = 0x04000000;
+}
+
 /// Initialize BPB subsystem
 pub fn init() {
     crate::serial_println!("[FS] FAT32 BPB subsystem initialized");
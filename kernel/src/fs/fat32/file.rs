@@ -2,19 +2,28 @@
 //!
 //! Implements file system operations for FAT32:
 //! - Reading/writing files
+//! - Non-cached (sector-aligned) reads and writes
 //! - Directory traversal
 //! - File creation/deletion
 //! - Cluster chain management
 
+extern crate alloc;
+
 use core::sync::atomic::{AtomicU32, Ordering};
 use crate::ke::SpinLock;
-use super::bpb::{Fat32BootSector, FsInfo, cluster_values};
+use alloc::format;
+use crate::ex::eventlog::{log_message, EventSource, EventType};
+use super::bpb::{Fat32BootSector, FsInfo, cluster_values, volume_flags};
 use super::dir::{FatDirEntry, file_attr, entry_status, DIR_ENTRY_SIZE};
 use crate::fs::vfs::{FsStatus, FileInfo, FileType, DirEntry, FsOps, FsInfo as VfsFsInfo, FsType};
+use crate::fs::vfs::{FsControl, FsExtent, FsRange, FsVolumeData};
 
 /// Maximum mounted FAT32 file systems
 pub const MAX_FAT32_MOUNTS: usize = 4;
 
+/// Event logged when a volume was not shut down cleanly
+const EVENT_DIRTY_VOLUME: u32 = 702;
+
 /// Sector buffer size
 pub const SECTOR_SIZE: usize = 512;
 
@@ -53,8 +62,16 @@ pub struct Fat32Mount {
     pub read_sector: Option<unsafe fn(device: *mut u8, sector: u64, buf: &mut [u8]) -> bool>,
     /// Device write function
     pub write_sector: Option<unsafe fn(device: *mut u8, sector: u64, buf: &[u8]) -> bool>,
+    /// Device write function that bypasses the drive's write cache
+    pub write_sector_fua: Option<unsafe fn(device: *mut u8, sector: u64, buf: &[u8]) -> bool>,
+    /// Device cache flush function
+    pub flush_device: Option<unsafe fn(device: *mut u8) -> bool>,
     /// Device pointer
     pub device: *mut u8,
+    /// The volume was still marked dirty when it was mounted
+    pub dirty_at_mount: bool,
+    /// Marked dirty (FSCTL_MARK_VOLUME_DIRTY): left dirty at dismount
+    pub marked_dirty: bool,
 }
 
 impl Fat32Mount {
@@ -76,7 +93,11 @@ impl Fat32Mount {
             next_free: AtomicU32::new(2),
             read_sector: None,
             write_sector: None,
+            write_sector_fua: None,
+            flush_device: None,
             device: core::ptr::null_mut(),
+            dirty_at_mount: false,
+            marked_dirty: false,
         }
     }
 
@@ -556,12 +577,125 @@ pub unsafe fn fat32_mount(fs_index: u16, device: *mut u8) -> FsStatus {
     FsStatus::Success
 }
 
+/// Drain the device write cache (caller holds FAT32_LOCK)
+unsafe fn flush_barrier(mount: &Fat32Mount) -> bool {
+    match mount.flush_device {
+        Some(flush) => flush(mount.device),
+        None => true,
+    }
+}
+
+/// Write the free cluster hints back to FSInfo (caller holds FAT32_LOCK)
+///
+/// The next mount trusts these counts, so the sector goes out with forced
+/// unit access rather than sitting in the drive's cache.
+unsafe fn write_fs_info(mount: &Fat32Mount) -> bool {
+    let (Some(read_fn), Some(write_fn)) = (mount.read_sector, mount.write_sector_fua.or(mount.write_sector)) else {
+        return false;
+    };
+    let sector = mount.boot_sector.ext_bpb.fs_info_sector as u64;
+    if sector == 0 || !read_fn(mount.device, sector, &mut SECTOR_BUFFER) {
+        return false;
+    }
+
+    let fsinfo = &mut *(SECTOR_BUFFER.as_mut_ptr() as *mut FsInfo);
+    if !fsinfo.is_valid() {
+        return false;
+    }
+    fsinfo.free_count = mount.free_clusters.load(Ordering::SeqCst);
+    fsinfo.next_free = mount.next_free.load(Ordering::SeqCst);
+    write_fn(mount.device, sector, &SECTOR_BUFFER)
+}
+
+/// Read the volume state bits from FAT entry 1 (caller holds FAT32_LOCK)
+unsafe fn read_volume_flags(mount: &Fat32Mount) -> Option<u32> {
+    let read_fn = mount.read_sector?;
+    if !read_fn(mount.device, mount.fat_start as u64, &mut SECTOR_BUFFER) {
+        return None;
+    }
+    Some(u32::from_le_bytes([SECTOR_BUFFER[4], SECTOR_BUFFER[5], SECTOR_BUFFER[6], SECTOR_BUFFER[7]]))
+}
+
+/// Set or clear the clean shutdown bit (caller holds FAT32_LOCK)
+///
+/// Marking the volume clean goes out with forced unit access: it has to
+/// land after everything written before it, never ahead of it.
+unsafe fn set_volume_clean(mount: &Fat32Mount, clean: bool) -> bool {
+    let (Some(read_fn), Some(write_fn)) = (mount.read_sector, mount.write_sector_fua.or(mount.write_sector)) else {
+        return false;
+    };
+    let sector = mount.fat_start as u64;
+    if !read_fn(mount.device, sector, &mut SECTOR_BUFFER) {
+        return false;
+    }
+
+    let entry = u32::from_le_bytes([SECTOR_BUFFER[4], SECTOR_BUFFER[5], SECTOR_BUFFER[6], SECTOR_BUFFER[7]]);
+    let updated = if clean {
+        entry | volume_flags::CLEAN_SHUTDOWN
+    } else {
+        entry & !volume_flags::CLEAN_SHUTDOWN
+    };
+    if updated == entry {
+        return true;
+    }
+    SECTOR_BUFFER[4..8].copy_from_slice(&updated.to_le_bytes());
+    write_fn(mount.device, sector, &SECTOR_BUFFER)
+}
+
+/// Write back everything a clean dismount needs (caller holds FAT32_LOCK)
+///
+/// File sizes, then the FSInfo hints, then a cache flush, and only then
+/// the clean shutdown bit followed by a second flush. A volume marked
+/// dirty keeps its dirty bit so that it is checked at the next mount.
+unsafe fn flush_for_dismount(mount: &Fat32Mount) -> bool {
+    let mut ok = true;
+    for file in OPEN_FILES.iter_mut() {
+        if file.in_use && file.fs_index == mount.fs_index && !flush_open_file(mount, file) {
+            ok = false;
+        }
+    }
+    ok &= write_fs_info(mount) || mount.boot_sector.ext_bpb.fs_info_sector == 0;
+    ok &= flush_barrier(mount);
+    if mount.marked_dirty {
+        return ok;
+    }
+    ok && set_volume_clean(mount, true) && flush_barrier(mount)
+}
+
+/// Flush every mounted volume for power-off and mark it clean
+///
+/// A volume that fails any step keeps its dirty bit, so the next mount
+/// knows not to trust it.
+///
+/// # Returns
+/// (volumes marked clean, volumes that failed)
+pub unsafe fn fat32_flush_volumes() -> (u32, u32) {
+    let _guard = FAT32_LOCK.lock();
+
+    let (mut flushed, mut failed) = (0u32, 0u32);
+    for mount in FAT32_MOUNTS.iter() {
+        if !mount.mounted {
+            continue;
+        }
+        if flush_for_dismount(mount) {
+            flushed += 1;
+        } else {
+            crate::serial_println!("[FAT32] fs_index={}: flush failed, volume left dirty", mount.fs_index);
+            failed += 1;
+        }
+    }
+    (flushed, failed)
+}
+
 /// Unmount a FAT32 file system
 pub unsafe fn fat32_unmount(fs_index: u16) -> FsStatus {
     let _guard = FAT32_LOCK.lock();
 
     for mount in FAT32_MOUNTS.iter_mut() {
         if mount.mounted && mount.fs_index == fs_index {
+            if !flush_for_dismount(mount) {
+                crate::serial_println!("[FAT32] fs_index={}: metadata not flushed at unmount", fs_index);
+            }
             mount.mounted = false;
             *mount = Fat32Mount::empty();
             return FsStatus::Success;
@@ -571,6 +705,22 @@ pub unsafe fn fat32_unmount(fs_index: u16) -> FsStatus {
     FsStatus::NotMounted
 }
 
+/// Drop the mount of a volume, by volume number
+///
+/// Unlike `fat32_unmount` nothing is written back: the media may be gone.
+pub unsafe fn fat32_detach_volume(volume_number: u8) -> FsStatus {
+    let _guard = FAT32_LOCK.lock();
+
+    for mount in FAT32_MOUNTS.iter_mut() {
+        if mount.mounted && mount.device == volume_number as *mut u8 {
+            *mount = Fat32Mount::empty();
+            return FsStatus::Success;
+        }
+    }
+
+    FsStatus::NotMounted
+}
+
 /// Get FAT32 file system info
 pub unsafe fn fat32_statfs(fs_index: u16) -> VfsFsInfo {
     let _guard = FAT32_LOCK.lock();
@@ -930,6 +1080,114 @@ pub unsafe fn fat32_write(
     Err(FsStatus::NotMounted)
 }
 
+/// Check that a non-cached transfer covers whole sectors
+fn check_sector_aligned(mount: &Fat32Mount, offset: u64, length: usize) -> Result<(), FsStatus> {
+    let sector = mount.bytes_per_sector as u64;
+    if !offset.is_multiple_of(sector) || !(length as u64).is_multiple_of(sector) {
+        return Err(FsStatus::InvalidParameter);
+    }
+    Ok(())
+}
+
+/// Read file data without intermediate buffering
+///
+/// `offset` and the buffer length must be sector multiples. Sectors are
+/// read straight into the caller's buffer; the last one may run past the
+/// end of the file, but only bytes within the file are counted.
+pub unsafe fn fat32_read_direct(
+    fs_index: u16,
+    node_id: u64,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<usize, FsStatus> {
+    let _guard = FAT32_LOCK.lock();
+
+    let mount = FAT32_MOUNTS.iter()
+        .find(|m| m.mounted && m.fs_index == fs_index)
+        .ok_or(FsStatus::NotMounted)?;
+    check_sector_aligned(mount, offset, buf.len())?;
+    let read_fn = mount.read_sector.ok_or(FsStatus::IoError)?;
+
+    let start_cluster = node_id as u32;
+    let file_size = find_open_file(fs_index, start_cluster).map_or(u64::MAX, |f| f.file_size as u64);
+    if offset >= file_size {
+        return Ok(0);
+    }
+    let in_file = (file_size - offset).min(buf.len() as u64) as usize;
+
+    let sector_size = mount.bytes_per_sector as usize;
+    let mut done = 0;
+    while done < in_file {
+        let position = offset + done as u64;
+        let cluster = match get_cluster_at_offset(mount, start_cluster, position) {
+            Some(c) => c,
+            None => break,
+        };
+        let offset_in_cluster = (position % mount.cluster_size as u64) as usize;
+        let mut sector = mount.cluster_to_sector(cluster) as u64 + (offset_in_cluster / sector_size) as u64;
+
+        // The rest of this cluster, in whole sectors
+        let run_end = done + (mount.cluster_size as usize - offset_in_cluster).min(buf.len() - done);
+        while done < run_end && done < in_file {
+            if !read_fn(mount.device, sector, &mut buf[done..done + sector_size]) {
+                return Err(FsStatus::IoError);
+            }
+            sector += 1;
+            done += sector_size;
+        }
+    }
+
+    Ok(done.min(in_file))
+}
+
+/// Write file data without intermediate buffering
+///
+/// `offset` and the buffer length must be sector multiples. Whole sectors
+/// go straight from the caller's buffer to the disk with no read of the
+/// old contents; like `fat32_write`, the write stops at the end of the
+/// clusters allocated to the file.
+pub unsafe fn fat32_write_direct(
+    fs_index: u16,
+    node_id: u64,
+    offset: u64,
+    buf: &[u8],
+) -> Result<usize, FsStatus> {
+    let _guard = FAT32_LOCK.lock();
+
+    let mount = FAT32_MOUNTS.iter()
+        .find(|m| m.mounted && m.fs_index == fs_index)
+        .ok_or(FsStatus::NotMounted)?;
+    check_sector_aligned(mount, offset, buf.len())?;
+    let write_fn = mount.write_sector.ok_or(FsStatus::IoError)?;
+
+    let start_cluster = node_id as u32;
+    let sector_size = mount.bytes_per_sector as usize;
+    let mut done = 0;
+    while done < buf.len() {
+        let position = offset + done as u64;
+        let cluster = match get_cluster_at_offset(mount, start_cluster, position) {
+            Some(c) => c,
+            None => break,
+        };
+        let offset_in_cluster = (position % mount.cluster_size as u64) as usize;
+        let mut sector = mount.cluster_to_sector(cluster) as u64 + (offset_in_cluster / sector_size) as u64;
+
+        let run_end = done + (mount.cluster_size as usize - offset_in_cluster).min(buf.len() - done);
+        while done < run_end {
+            if !write_fn(mount.device, sector, &buf[done..done + sector_size]) {
+                return Err(FsStatus::IoError);
+            }
+            sector += 1;
+            done += sector_size;
+        }
+    }
+
+    if done > 0 {
+        update_open_file_size(fs_index, start_cluster, (offset + done as u64) as u32);
+    }
+    Ok(done)
+}
+
 /// Create a new file
 pub unsafe fn fat32_create(
     fs_index: u16,
@@ -1039,8 +1297,9 @@ pub unsafe fn fat32_sync(fs_index: u16, node_id: u64) -> FsStatus {
     // Find the mount to get access to write functions
     for mount in FAT32_MOUNTS.iter() {
         if mount.mounted && mount.fs_index == fs_index {
-            // Flush the file metadata (size) to disk
-            if flush_open_file(mount, file) {
+            // Flush the file metadata (size) to disk, then past the
+            // drive's write cache
+            if flush_open_file(mount, file) && flush_barrier(mount) {
                 crate::serial_println!("[FAT32] Synced file (cluster={}, size={})",
                     first_cluster, file.file_size);
                 return FsStatus::Success;
@@ -1599,11 +1858,14 @@ pub unsafe fn fat32_truncate(fs_index: u16, node_id: u64, new_size: u64) -> FsSt
                     );
                 }
             } else {
-                // Extending the file
-                let current_clusters = if current_size == 0 {
+                // Extending the file; a new empty file already owns the
+                // cluster fat32_create gave it
+                let allocated = read_dir_entry(mount, file.dir_cluster, file.entry_index)
+                    .is_some_and(|entry| entry.first_cluster() >= 2);
+                let current_clusters = if !allocated {
                     0
                 } else {
-                    current_size.div_ceil(cluster_size) as u32
+                    current_size.div_ceil(cluster_size).max(1) as u32
                 };
                 let new_clusters = new_size.div_ceil(cluster_size) as u32;
                 let clusters_to_add = new_clusters - current_clusters;
@@ -1729,6 +1991,115 @@ pub unsafe fn fat32_getsize(fs_index: u16, node_id: u64) -> Result<u64, FsStatus
     }
 }
 
+// ============================================================================
+// File System Controls
+// ============================================================================
+
+/// Answer a file system control request
+///
+/// FAT has no sparse files and no valid data length separate from the file
+/// size: every byte up to the size is allocated and valid.
+pub unsafe fn fat32_fsctl(fs_index: u16, node_id: u64, request: &mut FsControl) -> FsStatus {
+    let _guard = FAT32_LOCK.lock();
+
+    let Some(mount) = FAT32_MOUNTS.iter_mut().find(|m| m.mounted && m.fs_index == fs_index) else {
+        return FsStatus::NotMounted;
+    };
+
+    match request {
+        FsControl::IsVolumeDirty { dirty } => {
+            *dirty = mount.dirty_at_mount || mount.marked_dirty;
+            FsStatus::Success
+        }
+        FsControl::MarkVolumeDirty => {
+            mount.marked_dirty = true;
+            FsStatus::Success
+        }
+        FsControl::GetVolumeData { data } => {
+            *data = FsVolumeData {
+                bytes_per_sector: mount.bytes_per_sector,
+                bytes_per_cluster: mount.cluster_size,
+                total_clusters: mount.total_clusters as u64,
+                free_clusters: mount.free_clusters.load(Ordering::SeqCst) as u64,
+            };
+            FsStatus::Success
+        }
+        FsControl::GetRetrievalPointers { start_vcn, extents, count, more } => {
+            if node_id == 0 {
+                return FsStatus::InvalidParameter;
+            }
+            *count = 0;
+            *more = false;
+            let mut vcn = 0u64;
+            let mut cluster = node_id as u32;
+            loop {
+                if vcn >= *start_vcn {
+                    let contiguous = *count > 0 && {
+                        let last = &extents[*count - 1];
+                        last.lcn + last.clusters == cluster as u64
+                    };
+                    if contiguous {
+                        extents[*count - 1].clusters += 1;
+                    } else if *count == extents.len() {
+                        *more = true;
+                        break;
+                    } else {
+                        extents[*count] = FsExtent { vcn, lcn: cluster as u64, clusters: 1 };
+                        *count += 1;
+                    }
+                }
+                match read_fat_entry(mount, cluster) {
+                    Some(next) if !cluster_values::is_eoc(next) && next >= 2 => cluster = next,
+                    Some(_) => break,
+                    None => return FsStatus::IoError,
+                }
+                vcn += 1;
+                if vcn > mount.total_clusters as u64 {
+                    // A chain longer than the volume loops
+                    return FsStatus::InvalidFileSystem;
+                }
+            }
+            FsStatus::Success
+        }
+        FsControl::QueryAllocatedRanges { ranges, count, more } => {
+            let Some(file) = find_open_file(fs_index, node_id as u32) else {
+                return FsStatus::InvalidHandle;
+            };
+            *count = 0;
+            *more = false;
+            if file.file_size > 0 {
+                match ranges.first_mut() {
+                    Some(range) => {
+                        *range = FsRange { offset: 0, length: file.file_size as u64 };
+                        *count = 1;
+                    }
+                    None => *more = true,
+                }
+            }
+            FsStatus::Success
+        }
+        FsControl::QueryFileData { sparse, valid_data_length, allocation_size } => {
+            let Some(file) = find_open_file(fs_index, node_id as u32) else {
+                return FsStatus::InvalidHandle;
+            };
+            let mut clusters = 0u64;
+            let mut cluster = file.first_cluster;
+            while cluster >= 2 && !cluster_values::is_eoc(cluster) && clusters <= mount.total_clusters as u64 {
+                clusters += 1;
+                match read_fat_entry(mount, cluster) {
+                    Some(next) => cluster = next,
+                    None => return FsStatus::IoError,
+                }
+            }
+            *sparse = false;
+            *valid_data_length = file.file_size as u64;
+            *allocation_size = clusters * mount.cluster_size as u64;
+            FsStatus::Success
+        }
+        FsControl::SetSparse { .. } => FsStatus::NotSupported,
+    }
+}
+
 /// Create a FAT32 operations structure
 pub fn fat32_ops() -> FsOps {
     FsOps {
@@ -1749,6 +2120,9 @@ pub fn fat32_ops() -> FsOps {
         rename: Some(fat32_rename),
         getsize: Some(fat32_getsize),
         sync: Some(fat32_sync),
+        read_direct: Some(fat32_read_direct),
+        write_direct: Some(fat32_write_direct),
+        fsctl: Some(fat32_fsctl),
     }
 }
 
@@ -1772,6 +2146,8 @@ pub unsafe fn mount_volume(
     device: *mut u8,
     read_fn: unsafe fn(*mut u8, u64, &mut [u8]) -> bool,
     write_fn: unsafe fn(*mut u8, u64, &[u8]) -> bool,
+    write_fua_fn: Option<unsafe fn(*mut u8, u64, &[u8]) -> bool>,
+    flush_fn: Option<unsafe fn(*mut u8) -> bool>,
 ) -> FsStatus {
     let _guard = FAT32_LOCK.lock();
 
@@ -1836,6 +2212,8 @@ pub unsafe fn mount_volume(
     mount.total_clusters = total_clusters;
     mount.read_sector = Some(read_fn);
     mount.write_sector = Some(write_fn);
+    mount.write_sector_fua = write_fua_fn;
+    mount.flush_device = flush_fn;
 
     // Try to read FSInfo sector for free cluster info
     let fsinfo_sector = bs.ext_bpb.fs_info_sector as u64;
@@ -1847,6 +2225,17 @@ pub unsafe fn mount_volume(
         }
     }
 
+    // Still dirty means the last session never dismounted the volume;
+    // then mark it dirty until this session does
+    mount.dirty_at_mount = read_volume_flags(mount)
+        .is_some_and(|flags| flags & volume_flags::CLEAN_SHUTDOWN == 0);
+    if mount.dirty_at_mount {
+        crate::serial_println!("[FAT32] fs_index={} was not shut down cleanly", fs_index);
+        let volume = format!("fs_index {}", fs_index);
+        log_message(EventSource::FileSystem, EventType::Warning, EVENT_DIRTY_VOLUME, &[&volume]);
+    }
+    set_volume_clean(mount, false);
+
     crate::serial_println!(
         "[FAT32] Mounted fs_index={} clusters={} cluster_size={}",
         fs_index,
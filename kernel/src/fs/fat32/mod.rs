@@ -18,12 +18,16 @@ pub mod file;
 
 // Re-export commonly used items
 pub use bpb::{Fat32BootSector, BiosParameterBlock, Fat32ExtendedBpb, FatType, FsInfo};
-pub use bpb::cluster_values;
+pub use bpb::{cluster_values, volume_flags};
 pub use dir::{FatDirEntry, LfnDirEntry, file_attr, entry_status, lfn_checksum};
 pub use dir::{DIR_ENTRY_SIZE, MAX_LFN_LENGTH, LFN_CHARS_PER_ENTRY};
-pub use file::{Fat32Mount, fat32_ops, fat32_mount_count, mount_volume, get_mount};
+pub use file::{Fat32Mount, fat32_ops, fat32_mount_count, fat32_flush_volumes, mount_volume, get_mount};
 
-use crate::fs::vfs::{vfs_register_fs, FsType};
+use crate::fs::vfs::{vfs_register_driver, FsDriver, FsStatus, FsType};
+use crate::io::disk::{
+    get_volume_flush_callback, get_volume_read_callback, get_volume_write_callback,
+    get_volume_write_fua_callback, partition_type,
+};
 use core::sync::atomic::{AtomicU16, Ordering};
 
 /// FAT32 file system driver name
@@ -42,11 +46,82 @@ pub fn vfs_index() -> Option<u16> {
     }
 }
 
+/// Check a volume's boot sector for FAT32
+pub fn is_fat32_boot_sector(partition: u8, boot_sector: &[u8]) -> bool {
+    // Check partition type first; could still be FAT if the type is unset
+    if !partition_type::is_fat(partition) && partition != partition_type::EMPTY {
+        return false;
+    }
+    if boot_sector.len() < core::mem::size_of::<Fat32BootSector>() {
+        return false;
+    }
+
+    let bs = unsafe { core::ptr::read_unaligned(boot_sector.as_ptr() as *const Fat32BootSector) };
+
+    // Check jump instruction (0xEB or 0xE9)
+    if bs.jump[0] != 0xEB && bs.jump[0] != 0xE9 {
+        return false;
+    }
+
+    // Check bytes per sector (must be power of 2, 512-4096)
+    let bps = bs.bpb.bytes_per_sector;
+    if !(512..=4096).contains(&bps) || (bps & (bps - 1)) != 0 {
+        return false;
+    }
+
+    // Check sectors per cluster (must be power of 2)
+    let spc = bs.bpb.sectors_per_cluster;
+    if spc == 0 || (spc & (spc - 1)) != 0 {
+        return false;
+    }
+
+    // FAT32 has 0 root entries and non-zero sectors per FAT32
+    if bs.bpb.root_entry_count != 0 {
+        return false;  // FAT12/16
+    }
+    if bs.ext_bpb.sectors_per_fat_32 == 0 {
+        return false;  // Not FAT32
+    }
+
+    // Check signature
+    bs.signature == [0x55, 0xAA]
+}
+
+/// VFS recognizer
+unsafe fn recognize(_volume_number: u8, partition: u8, boot_sector: &[u8]) -> bool {
+    is_fat32_boot_sector(partition, boot_sector)
+}
+
+/// VFS attach: mount the volume with the FAT32 driver, keyed by volume number
+unsafe fn attach(fs_index: u16, volume_number: u8, _boot_sector: &[u8]) -> FsStatus {
+    let (Some(read_cb), Some(write_cb)) = (
+        get_volume_read_callback(volume_number),
+        get_volume_write_callback(volume_number),
+    ) else {
+        return FsStatus::IoError;
+    };
+    mount_volume(
+        fs_index,
+        volume_number as *mut u8,
+        read_cb,
+        write_cb,
+        get_volume_write_fua_callback(volume_number),
+        get_volume_flush_callback(volume_number),
+    )
+}
+
+/// VFS detach
+unsafe fn detach(_fs_index: u16, volume_number: u8) -> FsStatus {
+    file::fat32_detach_volume(volume_number)
+}
+
 /// Register FAT32 with VFS
 pub fn register() {
     unsafe {
-        let ops = file::fat32_ops();
-        if let Some(idx) = vfs_register_fs(FAT32_NAME, FsType::Fat32, ops) {
+        let driver = FsDriver::new(FAT32_NAME, FsType::Fat32, file::fat32_ops())
+            .with_recognizer(recognize, attach)
+            .with_detach(detach);
+        if let Some(idx) = vfs_register_driver(driver) {
             FAT32_VFS_INDEX.store(idx, Ordering::Relaxed);
             crate::serial_println!("[FS] FAT32 driver registered with VFS (index={})", idx);
         } else {
@@ -4,7 +4,10 @@
 //! - Virtual File System (VFS) abstraction layer
 //! - FAT32 file system driver
 //! - Mount point management
-//! - Path utilities
+//! - Path utilities and canonicalization (DOS device aliases)
+//! - Directory change watches
+//! - Host shared folders over virtio-9p
+//! - Default path-based file security, checked on open, create and delete
 //!
 //! # Architecture
 //! ```text
@@ -46,11 +49,19 @@
 //!   - Directory traversal
 //!   - File reading
 //!
+//! # Driver Registration
+//! Drivers register an `FsDriver` (name, recognizer, dispatch table) with
+//! `vfs::vfs_register_driver`. Volume mounting offers each volume's boot
+//! sector to the registered recognizers and attaches it to the first
+//! driver that accepts it, so new file systems need no changes here.
+//!
 //! # Mount Points
 //! Supports Windows-style drive letters (C:, D:, etc.) and
 //! NT device paths (\\Device\\HarddiskVolume1).
 
 pub mod path;
+pub mod canon;
+pub mod watch;
 pub mod vfs;
 pub mod mount;
 pub mod fat32;
@@ -62,12 +73,20 @@ pub mod dfs;
 pub mod clfs;
 pub mod rdbss;
 pub mod efs;
+pub mod p9fs;
+pub mod security;
 
 // Re-export common types
 pub use path::{ParsedPath, PathComponent, MAX_PATH, MAX_COMPONENT};
 pub use vfs::{FsStatus, FileType, FileInfo, DirEntry, FsType, FsOps};
-pub use vfs::{VNode, FileHandle, INVALID_HANDLE};
+pub use vfs::{VNode, FileHandle, INVALID_HANDLE, handle_options};
+pub use vfs::{FsControl, FsExtent, FsRange, FsVolumeData};
 pub use mount::{MountPoint, mount_flags};
+pub use security::{FILE_GENERIC_MAPPING, fs_access_check};
+
+use crate::fsrtl::{self, FltCallbackData, FltOperation, FltPreopCallbackStatus};
+use crate::fsrtl::notify::file_action::*;
+use crate::fsrtl::notify::notify_filter::{FILE_NOTIFY_CHANGE_DIR_NAME, FILE_NOTIFY_CHANGE_FILE_NAME};
 
 /// File system statistics
 #[derive(Debug, Clone, Copy)]
@@ -98,11 +117,91 @@ impl FsStats {
 // High-Level File Operations
 // ============================================================================
 
+/// Canonicalize a path and find the mount point serving it
+///
+/// # Returns
+/// The mount point, the canonical path and the path within the mount
+fn resolve_mount<'a>(
+    path: &str,
+    canonical: &'a mut [u8; MAX_PATH],
+) -> Result<(MountPoint, &'a str, &'a str), FsStatus> {
+    let path = canon::canonicalize_path(path, canonical)?;
+    let (mut mp, remaining) = mount::resolve_path_mount(path).ok_or(FsStatus::NotMounted)?;
+    if mp.is_removable() {
+        volume::verify_mount(mp.drive_letter as char)?;
+        mp = mount::get_mount_point(mp.drive_letter as char).ok_or(FsStatus::NotMounted)?;
+    }
+    Ok((mp, path, remaining))
+}
+
+// ============================================================================
+// Mini-Filter Dispatch
+// ============================================================================
+
+/// Create dispositions reported to mini-filters
+const FILE_OPEN: u32 = 1;
+const FILE_CREATE: u32 = 2;
+
+/// Pending create operation handed from pre- to post-create callbacks
+type FilteredCreate = Option<(FltCallbackData, FltPreopCallbackStatus)>;
+
+/// Run the pre-create callbacks of registered mini-filters
+///
+/// A filter that completes the create fails the open: the VFS cannot
+/// finish an open on a filter's behalf, so completion is always a denial.
+fn filter_pre_create(path: &str, access: u32, disposition: u32) -> Result<FilteredCreate, FsStatus> {
+    if !fsrtl::flt_filters_registered() {
+        return Ok(None);
+    }
+
+    let mut data = FltCallbackData::empty();
+    data.operation = FltOperation::Create;
+    data.set_file_path(path);
+    data.io_params.access_mask = access;
+    data.io_params.disposition = disposition;
+    let process = crate::ps::get_current_process();
+    if !process.is_null() {
+        data.requestor_pid = unsafe { (*process).unique_process_id };
+    }
+
+    let status = fsrtl::flt_invoke_pre_callbacks(&mut data);
+    if status == FltPreopCallbackStatus::Complete {
+        return Err(FsStatus::AccessDenied);
+    }
+    Ok(Some((data, status)))
+}
+
+/// Run the post-create callbacks for a successful open
+///
+/// The new handle is passed in `io_params.param1`. A filter that sets a
+/// failure `io_status` cancels the open and the handle is closed.
+fn filter_post_create(pending: FilteredCreate, handle: u16) -> Result<u16, FsStatus> {
+    let (mut data, pre_status) = match pending {
+        Some(p) => p,
+        None => return Ok(handle),
+    };
+
+    data.io_params.param1 = handle as usize;
+    fsrtl::flt_invoke_post_callbacks(&mut data, pre_status);
+    if data.io_status < 0 {
+        let _ = vfs::vfs_free_handle(handle);
+        return Err(FsStatus::AccessDenied);
+    }
+    Ok(handle)
+}
+
 /// Open a file by path
-pub fn open(path: &str, _mode: u32) -> Result<u16, FsStatus> {
+///
+/// `mode` is the access requested; 0 requests read access.
+pub fn open(path: &str, mode: u32) -> Result<u16, FsStatus> {
     // Resolve mount point
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, full_path, remaining) = resolve_mount(path, &mut canonical)?;
+
+    let access = if mode == 0 { crate::io::file_access::FILE_GENERIC_READ } else { mode };
+    fs_access_check(full_path, access)?;
+
+    let pending = filter_pre_create(full_path, mode, FILE_OPEN)?;
 
     // Lookup through VFS
     let vnode_id = unsafe {
@@ -113,9 +212,40 @@ pub fn open(path: &str, _mode: u32) -> Result<u16, FsStatus> {
     let handle = vfs::vfs_alloc_handle(mp.fs_index, vnode_id)
         .ok_or(FsStatus::TooManyFiles)?;
 
+    filter_post_create(pending, handle)
+}
+
+/// Open a file by path with handle options (`handle_options`)
+///
+/// `FILE_NO_INTERMEDIATE_BUFFERING` requires sector-aligned offsets and
+/// lengths on file systems that support it; `FILE_WRITE_THROUGH` flushes
+/// every write to the media before it returns.
+pub fn open_with_options(path: &str, mode: u32, options: u32) -> Result<u16, FsStatus> {
+    let handle = open(path, mode)?;
+    if let Err(e) = vfs::vfs_set_handle_options(handle, options) {
+        let _ = close(handle);
+        return Err(e);
+    }
     Ok(handle)
 }
 
+/// Replace the options of an open handle
+pub fn set_options(handle: u16, options: u32) -> Result<(), FsStatus> {
+    vfs::vfs_set_handle_options(handle, options)
+}
+
+/// Send a file system control request (FSCTL) for an open file
+pub fn fsctl(handle: u16, request: &mut FsControl) -> Result<(), FsStatus> {
+    vfs::vfs_fsctl(handle, request)
+}
+
+/// Send a file system control request for the volume holding `path`
+pub fn fsctl_volume(path: &str, request: &mut FsControl) -> Result<(), FsStatus> {
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, _, _) = resolve_mount(path, &mut canonical)?;
+    vfs::vfs_fsctl_volume(mp.fs_index, request)
+}
+
 /// Close a file handle
 pub fn close(handle: u16) -> Result<(), FsStatus> {
     vfs::vfs_free_handle(handle)
@@ -160,8 +290,11 @@ pub fn create(path: &str, attrs: u32) -> Result<u16, FsStatus> {
     }
 
     // Get mount point and resolve path
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, full_path, remaining) = resolve_mount(path, &mut canonical)?;
+    fs_access_check(security::parent_path(full_path), security::FILE_ADD_FILE)?;
+
+    let pending = filter_pre_create(full_path, 0, FILE_CREATE)?;
 
     // Get parent directory's vnode
     let parent_vnode = if remaining.is_empty() || !remaining.contains('\\') {
@@ -194,13 +327,15 @@ pub fn create(path: &str, attrs: u32) -> Result<u16, FsStatus> {
     let handle = vfs::vfs_alloc_handle(mp.fs_index, vnode_id)
         .ok_or(FsStatus::TooManyFiles)?;
 
-    Ok(handle)
+    watch::report_change(full_path, FILE_NOTIFY_CHANGE_FILE_NAME, FILE_ACTION_ADDED);
+
+    filter_post_create(pending, handle)
 }
 
 /// Get file information by path
 pub fn stat(path: &str) -> Result<FileInfo, FsStatus> {
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, _, remaining) = resolve_mount(path, &mut canonical)?;
 
     let vnode_id = unsafe {
         vfs::vfs_lookup(mp.fs_index, remaining)?
@@ -224,8 +359,9 @@ pub fn delete(path: &str) -> Result<(), FsStatus> {
     }
 
     // Get mount point and resolve path
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, full_path, remaining) = resolve_mount(path, &mut canonical)?;
+    fs_access_check(full_path, crate::se::standard_rights::DELETE)?;
 
     // Get parent directory's vnode
     let parent_vnode = if remaining.is_empty() || !remaining.contains('\\') {
@@ -255,6 +391,7 @@ pub fn delete(path: &str) -> Result<(), FsStatus> {
     };
 
     if status == FsStatus::Success {
+        watch::report_change(full_path, FILE_NOTIFY_CHANGE_FILE_NAME, FILE_ACTION_REMOVED);
         Ok(())
     } else {
         Err(status)
@@ -269,8 +406,9 @@ pub fn mkdir(path: &str) -> Result<(), FsStatus> {
     }
 
     // Get mount point and resolve path
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, full_path, remaining) = resolve_mount(path, &mut canonical)?;
+    fs_access_check(security::parent_path(full_path), security::FILE_ADD_SUBDIRECTORY)?;
 
     // Get parent directory's vnode
     let parent_vnode = if remaining.is_empty() || !remaining.contains('\\') {
@@ -299,6 +437,7 @@ pub fn mkdir(path: &str) -> Result<(), FsStatus> {
         vfs::vfs_mkdir(mp.fs_index, parent_vnode, dir_name)?;
     }
 
+    watch::report_change(full_path, FILE_NOTIFY_CHANGE_DIR_NAME, FILE_ACTION_ADDED);
     Ok(())
 }
 
@@ -310,8 +449,9 @@ pub fn rmdir(path: &str) -> Result<(), FsStatus> {
     }
 
     // Get mount point and resolve path
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, full_path, remaining) = resolve_mount(path, &mut canonical)?;
+    fs_access_check(full_path, crate::se::standard_rights::DELETE)?;
 
     // Get parent directory's vnode
     let parent_vnode = if remaining.is_empty() || !remaining.contains('\\') {
@@ -341,6 +481,7 @@ pub fn rmdir(path: &str) -> Result<(), FsStatus> {
     };
 
     if status == FsStatus::Success {
+        watch::report_change(full_path, FILE_NOTIFY_CHANGE_DIR_NAME, FILE_ACTION_REMOVED);
         Ok(())
     } else {
         Err(status)
@@ -355,18 +496,21 @@ pub fn rename(old_path: &str, new_path: &str) -> Result<(), FsStatus> {
     }
 
     // Get mount point for old path
-    let (old_mp, old_remaining) = mount::resolve_path_mount(old_path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut old_canonical = [0u8; MAX_PATH];
+    let (old_mp, old_full_path, old_remaining) = resolve_mount(old_path, &mut old_canonical)?;
 
     // Get mount point for new path
-    let (new_mp, new_remaining) = mount::resolve_path_mount(new_path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut new_canonical = [0u8; MAX_PATH];
+    let (new_mp, new_full_path, new_remaining) = resolve_mount(new_path, &mut new_canonical)?;
 
     // Cross-filesystem rename is not supported
     if old_mp.fs_index != new_mp.fs_index {
         return Err(FsStatus::NotSupported);
     }
 
+    fs_access_check(old_full_path, crate::se::standard_rights::DELETE)?;
+    fs_access_check(security::parent_path(new_full_path), security::FILE_ADD_FILE)?;
+
     // Get old parent directory's vnode
     let old_parent_vnode = if old_remaining.is_empty() || !old_remaining.contains('\\') {
         0  // Root directory
@@ -414,6 +558,9 @@ pub fn rename(old_path: &str, new_path: &str) -> Result<(), FsStatus> {
     };
 
     if status == FsStatus::Success {
+        let filter = FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_DIR_NAME;
+        watch::report_change(old_full_path, filter, FILE_ACTION_RENAMED_OLD_NAME);
+        watch::report_change(new_full_path, filter, FILE_ACTION_RENAMED_NEW_NAME);
         Ok(())
     } else {
         Err(status)
@@ -524,8 +671,8 @@ pub fn copy(src_path: &str, dst_path: &str) -> Result<u64, FsStatus> {
 
 /// Read a directory
 pub fn readdir(path: &str, offset: u32) -> Result<DirEntry, FsStatus> {
-    let (mp, remaining) = mount::resolve_path_mount(path)
-        .ok_or(FsStatus::NotMounted)?;
+    let mut canonical = [0u8; MAX_PATH];
+    let (mp, _, remaining) = resolve_mount(path, &mut canonical)?;
 
     let vnode_id = if remaining.is_empty() {
         0  // Root of mount
@@ -574,9 +721,24 @@ pub fn init() {
     // Initialize Encrypting File System
     efs::init();
 
+    // Initialize the mini-filter manager before volumes come online
+    fsrtl::init_fltmgr();
+
     // Initialize volume integration (auto-mounts detected volumes)
     volume::init();
 
+    // Saved registry hives live on the system volume
+    crate::cm::cm_load_hives();
+
+    // Disk write caching policy (fsutil behavior set disablewritecache)
+    volume::load_write_cache_policy();
+
+    // Mount the host shared folder, if QEMU exports one
+    p9fs::init();
+
+    // Restore persistent DOS device aliases (subst /P)
+    canon::load_persistent_aliases();
+
     // Print statistics
     let stats = FsStats::current();
     crate::serial_println!(
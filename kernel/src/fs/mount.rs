@@ -7,6 +7,13 @@
 //!
 //! # Mount Table
 //! Maps drive letters to file system instances and device paths.
+//!
+//! # Drive Letter Links
+//! Acting as the mount manager, mounting a volume creates the symbolic
+//! link `\DosDevices\X:` to its device path, and unmounting deletes it,
+//! so the object namespace (`\GLOBAL??`) always matches the mount table.
+
+extern crate alloc;
 
 use crate::ke::SpinLock;
 use crate::fs::vfs::{FsStatus, FsType};
@@ -54,6 +61,10 @@ pub struct MountPoint {
     pub volume_label: [u8; 16],
     /// Volume serial number
     pub volume_serial: u32,
+    /// Volume number (disk layer) behind the mount
+    pub volume_number: u8,
+    /// Hash of the media's first sectors, to recognize it after a change
+    pub media_fingerprint: u32,
     /// Root vnode ID
     pub root_vnode: u64,
 }
@@ -71,6 +82,8 @@ impl MountPoint {
             device_path_len: 0,
             volume_label: [0; 16],
             volume_serial: 0,
+            volume_number: 0,
+            media_fingerprint: 0,
             root_vnode: 0,
         }
     }
@@ -90,6 +103,11 @@ impl MountPoint {
         (self.flags & mount_flags::MF_BOOT) != 0
     }
 
+    /// Check if removable media
+    pub fn is_removable(&self) -> bool {
+        (self.flags & mount_flags::MF_REMOVABLE) != 0
+    }
+
     /// Get device path as string
     pub fn device_path_str(&self) -> &str {
         core::str::from_utf8(&self.device_path[..self.device_path_len as usize]).unwrap_or("")
@@ -139,6 +157,27 @@ static mut MOUNT_TABLE: [MountPoint; MAX_MOUNT_POINTS] = {
 /// Mount table lock
 static MOUNT_LOCK: SpinLock<()> = SpinLock::new(());
 
+/// Name of a drive letter's symbolic link
+fn drive_link_name(drive: char) -> alloc::string::String {
+    alloc::format!("\\DosDevices\\{}:", drive)
+}
+
+/// Create the drive letter link for a newly mounted volume
+fn create_drive_link(drive: char, device_path: &str) {
+    if device_path.is_empty() {
+        return;
+    }
+    let status = crate::io::io_create_symbolic_link(&drive_link_name(drive), device_path);
+    if status < 0 {
+        crate::serial_println!("[FS] Cannot create {}: link to {}: {:#x}", drive, device_path, status as u32);
+    }
+}
+
+/// Delete the drive letter link of an unmounted volume
+fn delete_drive_link(drive: char) {
+    let _ = crate::io::io_delete_symbolic_link(&drive_link_name(drive));
+}
+
 // ============================================================================
 // Mount Operations
 // ============================================================================
@@ -158,7 +197,7 @@ pub fn mount(
 
     let index = (drive as u8 - b'A') as usize;
 
-    let _guard = MOUNT_LOCK.lock();
+    let guard = MOUNT_LOCK.lock();
 
     unsafe {
         if MOUNT_TABLE[index].active {
@@ -175,8 +214,11 @@ pub fn mount(
         mp.root_vnode = 0;  // Root of file system
 
         crate::serial_println!("[FS] Mounted {}:\\ -> {}", drive, device_path);
-        Ok(())
     }
+    drop(guard);
+
+    create_drive_link(drive, device_path);
+    Ok(())
 }
 
 /// Unmount a file system
@@ -188,7 +230,7 @@ pub fn unmount(drive_letter: char) -> Result<(), FsStatus> {
 
     let index = (drive as u8 - b'A') as usize;
 
-    let _guard = MOUNT_LOCK.lock();
+    let guard = MOUNT_LOCK.lock();
 
     unsafe {
         if !MOUNT_TABLE[index].active {
@@ -204,8 +246,44 @@ pub fn unmount(drive_letter: char) -> Result<(), FsStatus> {
         MOUNT_TABLE[index] = MountPoint::empty();
 
         crate::serial_println!("[FS] Unmounted {}:\\", drive);
-        Ok(())
     }
+    drop(guard);
+
+    delete_drive_link(drive);
+    Ok(())
+}
+
+/// Dismount a file system
+///
+/// Like `unmount`, but a removable volume goes even when it is the system
+/// or boot volume: once its media is gone there is nothing to keep.
+pub fn dismount(drive_letter: char) -> Result<(), FsStatus> {
+    let drive = drive_letter.to_ascii_uppercase();
+    if !drive.is_ascii_uppercase() {
+        return Err(FsStatus::InvalidPath);
+    }
+
+    let index = (drive as u8 - b'A') as usize;
+
+    let guard = MOUNT_LOCK.lock();
+
+    unsafe {
+        let mp = &MOUNT_TABLE[index];
+        if !mp.active {
+            return Err(FsStatus::NotMounted);
+        }
+        if !mp.is_removable() && (mp.is_system() || mp.is_boot()) {
+            return Err(FsStatus::DeviceBusy);
+        }
+
+        MOUNT_TABLE[index] = MountPoint::empty();
+
+        crate::serial_println!("[FS] Dismounted {}:\\", drive);
+    }
+    drop(guard);
+
+    delete_drive_link(drive);
+    Ok(())
 }
 
 /// Get mount point by drive letter
@@ -310,6 +388,14 @@ pub fn list_mounts() -> [Option<(char, FsType)>; MAX_MOUNT_POINTS] {
     result
 }
 
+/// Check whether any mounted volume uses a VFS driver
+pub fn driver_in_use(fs_index: u16) -> bool {
+    let _guard = MOUNT_LOCK.lock();
+    unsafe {
+        MOUNT_TABLE.iter().any(|m| m.active && m.fs_index == fs_index)
+    }
+}
+
 /// Count mounted volumes
 pub fn mount_count() -> u32 {
     let _guard = MOUNT_LOCK.lock();
@@ -360,6 +446,28 @@ pub fn set_volume_serial(drive_letter: char, serial: u32) -> Result<(), FsStatus
     }
 }
 
+/// Record the volume and media fingerprint behind a mount
+pub fn set_volume_media(drive_letter: char, volume_number: u8, fingerprint: u32) -> Result<(), FsStatus> {
+    let drive = drive_letter.to_ascii_uppercase();
+    if !drive.is_ascii_uppercase() {
+        return Err(FsStatus::InvalidPath);
+    }
+
+    let index = (drive as u8 - b'A') as usize;
+
+    let _guard = MOUNT_LOCK.lock();
+
+    unsafe {
+        if !MOUNT_TABLE[index].active {
+            return Err(FsStatus::NotMounted);
+        }
+
+        MOUNT_TABLE[index].volume_number = volume_number;
+        MOUNT_TABLE[index].media_fingerprint = fingerprint;
+        Ok(())
+    }
+}
+
 /// Initialize mount point management
 pub fn init() {
     crate::serial_println!("[FS] Mount point manager initializing...");
@@ -331,6 +331,23 @@ pub fn unmount_volume(mount_index: u16) -> bool {
     false
 }
 
+/// Unmount the volume on a device
+pub fn unmount_device(device_index: u16) -> bool {
+    let _guard = MOUNT_LOCK.lock();
+
+    unsafe {
+        for mount in NTFS_MOUNTS.iter_mut() {
+            if mount.active && mount.device_index == device_index {
+                mount.active = false;
+                NTFS_MOUNT_COUNT.fetch_sub(1, Ordering::Relaxed);
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
 // ============================================================================
 // VFS Operations
 // ============================================================================
@@ -355,6 +372,7 @@ pub fn ntfs_ops() -> FsOps {
         close: Some(ntfs_vfs_close),
         getsize: Some(ntfs_vfs_getsize),
         sync: Some(ntfs_vfs_sync),
+        ..FsOps::empty()
     }
 }
 
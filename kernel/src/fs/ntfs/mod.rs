@@ -58,7 +58,7 @@ pub use attr::{
 };
 pub use file::{NtfsMount, ntfs_ops, ntfs_mount_count, mount_volume, get_mount};
 
-use crate::fs::vfs::{vfs_register_fs, FsType};
+use crate::fs::vfs::{vfs_register_driver, FsDriver, FsStatus, FsType};
 use core::sync::atomic::{AtomicU16, Ordering};
 
 /// NTFS file system driver name
@@ -77,11 +77,38 @@ pub fn vfs_index() -> Option<u16> {
     }
 }
 
+/// VFS recognizer: a valid NTFS boot sector, whatever the partition type
+unsafe fn recognize(_volume_number: u8, _partition: u8, boot_sector: &[u8]) -> bool {
+    boot_sector.get(..512)
+        .and_then(|bytes| <&[u8; 512]>::try_from(bytes).ok())
+        .and_then(NtfsBootSector::from_bytes)
+        .is_some()
+}
+
+/// VFS attach
+unsafe fn attach(_fs_index: u16, volume_number: u8, boot_sector: &[u8]) -> FsStatus {
+    match mount_volume(boot_sector, volume_number as u16) {
+        Some(_) => FsStatus::Success,
+        None => FsStatus::InvalidFileSystem,
+    }
+}
+
+/// VFS detach
+unsafe fn detach(_fs_index: u16, volume_number: u8) -> FsStatus {
+    if file::unmount_device(volume_number as u16) {
+        FsStatus::Success
+    } else {
+        FsStatus::NotMounted
+    }
+}
+
 /// Register NTFS with VFS
 pub fn register() {
     unsafe {
-        let ops = file::ntfs_ops();
-        if let Some(idx) = vfs_register_fs(NTFS_NAME, FsType::Ntfs, ops) {
+        let driver = FsDriver::new(NTFS_NAME, FsType::Ntfs, file::ntfs_ops())
+            .with_recognizer(recognize, attach)
+            .with_detach(detach);
+        if let Some(idx) = vfs_register_driver(driver) {
             NTFS_VFS_INDEX.store(idx, Ordering::Relaxed);
             crate::serial_println!("[FS] NTFS driver registered with VFS (index={})", idx);
         } else {
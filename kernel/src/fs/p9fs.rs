@@ -0,0 +1,916 @@
+//! 9P Shared Folder File System (9P2000.L client)
+//!
+//! Mounts a host directory exported over virtio-9p as a read-only drive
+//! letter, so test binaries and configs in the host workspace can be
+//! used without rebuilding a disk image:
+//!
+//! ```text
+//! qemu ... -fsdev local,id=ws,path=.,security_model=none,readonly=on \
+//!          -device virtio-9p-pci,fsdev=ws,mount_tag=workspace
+//! ```
+//!
+//! # Nodes
+//! Every file the VFS has looked up is a node holding a walked fid. The
+//! VFS node ID is `generation << 16 | index`, so an ID that outlives its
+//! node (evicted to make room) is rejected instead of naming a different
+//! file. The root is always node 0 (ID 0). Reads and directory listings
+//! open a second fid on the node, clunked when the handle is closed.
+//!
+//! # Names
+//! Host file systems are usually case-sensitive; paths here are not. A
+//! walk that fails with ENOENT falls back to a case-insensitive search of
+//! the directory.
+
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+
+use super::path::MAX_COMPONENT;
+use super::vfs::{
+    vfs_register_fs, DirEntry, FileInfo, FileType, FsInfo, FsOps, FsStatus, FsType,
+};
+use crate::drivers::virtio::p9::{p9_transport_rpc, MAX_MESSAGE_SIZE};
+use crate::ke::KMutex;
+
+/// File system driver name
+pub const P9FS_NAME: &str = "9p";
+
+/// Drive letter tried first when mounting at boot
+pub const DEFAULT_DRIVE: char = 'H';
+
+/// Cached nodes (including the root)
+pub const MAX_NODES: usize = 128;
+
+/// Protocol version spoken
+const VERSION: &str = "9P2000.L";
+
+/// Message types (9P2000.L)
+mod msg {
+    pub const RLERROR: u8 = 7;
+    pub const TSTATFS: u8 = 8;
+    pub const TLOPEN: u8 = 12;
+    pub const TGETATTR: u8 = 24;
+    pub const TREADDIR: u8 = 40;
+    pub const TVERSION: u8 = 100;
+    pub const TATTACH: u8 = 104;
+    pub const TWALK: u8 = 110;
+    pub const TREAD: u8 = 116;
+    pub const TCLUNK: u8 = 120;
+}
+
+/// Tag used for Tversion
+const NOTAG: u16 = 0xFFFF;
+
+/// "No fid" for Tattach's afid
+const NOFID: u32 = u32::MAX;
+
+/// Fid used for one-off walks (directory entry sizes)
+const TEMP_FID: u32 = 0xFFFF_0000;
+
+/// Qid type bit for directories
+const QTDIR: u8 = 0x80;
+
+/// Tgetattr mask: mode, nlink, size, times, blocks (P9_GETATTR_BASIC)
+const GETATTR_BASIC: u64 = 0x7FF;
+
+/// Message header (size[4] type[1] tag[2])
+const HEADER_SIZE: usize = 7;
+
+/// Header of Rread/Rreaddir (header + count[4])
+const IO_HEADER_SIZE: usize = HEADER_SIZE + 4;
+
+/// Unix epoch to 1980-01-01 (the VFS time base)
+const EPOCH_1980: u64 = 315_532_800;
+
+/// Linux errno values returned in Rlerror
+mod errno {
+    pub const EPERM: u32 = 1;
+    pub const ENOENT: u32 = 2;
+    pub const EACCES: u32 = 13;
+    pub const ENOTDIR: u32 = 20;
+    pub const EISDIR: u32 = 21;
+    pub const ENAMETOOLONG: u32 = 36;
+}
+
+// ============================================================================
+// Message Encoding
+// ============================================================================
+
+/// T-message being built
+struct Request {
+    buf: &'static mut [u8],
+    len: usize,
+}
+
+impl Request {
+    fn new(buf: &'static mut [u8], kind: u8, tag: u16) -> Self {
+        let mut r = Self { buf, len: 4 };
+        r.u8(kind);
+        r.u16(tag);
+        r
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        let end = (self.len + bytes.len()).min(self.buf.len());
+        self.buf[self.len..end].copy_from_slice(&bytes[..end - self.len]);
+        self.len = end;
+    }
+
+    fn u8(&mut self, v: u8) { self.put(&[v]); }
+    fn u16(&mut self, v: u16) { self.put(&v.to_le_bytes()); }
+    fn u32(&mut self, v: u32) { self.put(&v.to_le_bytes()); }
+    fn u64(&mut self, v: u64) { self.put(&v.to_le_bytes()); }
+
+    fn str(&mut self, s: &str) {
+        self.u16(s.len() as u16);
+        self.put(s.as_bytes());
+    }
+
+    /// Patch in the size and return the message
+    fn finish(self) -> &'static [u8] {
+        let len = self.len;
+        self.buf[..4].copy_from_slice(&(len as u32).to_le_bytes());
+        &self.buf[..len]
+    }
+}
+
+/// R-message being parsed
+struct Reply<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reply<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], FsStatus> {
+        let bytes = self.buf.get(self.pos..self.pos + n).ok_or(FsStatus::IoError)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, FsStatus> { Ok(self.take(1)?[0]) }
+    fn u16(&mut self) -> Result<u16, FsStatus> { Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap())) }
+    fn u32(&mut self) -> Result<u32, FsStatus> { Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+    fn u64(&mut self) -> Result<u64, FsStatus> { Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap())) }
+
+    fn str(&mut self) -> Result<&'a [u8], FsStatus> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+
+    fn qid(&mut self) -> Result<Qid, FsStatus> {
+        Ok(Qid { kind: self.u8()?, version: self.u32()?, path: self.u64()? })
+    }
+}
+
+/// Server's unique file identity
+#[derive(Debug, Clone, Copy, Default)]
+struct Qid {
+    kind: u8,
+    version: u32,
+    path: u64,
+}
+
+static mut TX_BUFFER: [u8; MAX_MESSAGE_SIZE] = [0; MAX_MESSAGE_SIZE];
+static mut RX_BUFFER: [u8; MAX_MESSAGE_SIZE] = [0; MAX_MESSAGE_SIZE];
+
+/// Start a T-message in the transmit buffer
+///
+/// Callers hold P9_LOCK, the only user of the buffers.
+fn request(kind: u8) -> Request {
+    let tag = if kind == msg::TVERSION { NOTAG } else { 0 };
+    Request::new(unsafe { &mut *core::ptr::addr_of_mut!(TX_BUFFER) }, kind, tag)
+}
+
+fn errno_status(code: u32) -> FsStatus {
+    match code {
+        errno::ENOENT => FsStatus::NotFound,
+        errno::EPERM | errno::EACCES => FsStatus::AccessDenied,
+        errno::ENOTDIR => FsStatus::NotDirectory,
+        errno::EISDIR => FsStatus::IsDirectory,
+        errno::ENAMETOOLONG => FsStatus::NameTooLong,
+        _ => FsStatus::IoError,
+    }
+}
+
+/// Send a T-message and return the R-message body
+fn rpc(request: Request) -> Result<Reply<'static>, FsStatus> {
+    let message = request.finish();
+    let kind = message[4];
+    let rx = unsafe { &mut *core::ptr::addr_of_mut!(RX_BUFFER) };
+    let len = p9_transport_rpc(message, rx).map_err(|_| FsStatus::IoError)?;
+    RPCS.fetch_add(1, Ordering::Relaxed);
+
+    let mut reply = Reply { buf: &rx[..len], pos: 0 };
+    let size = reply.u32()? as usize;
+    let rkind = reply.u8()?;
+    let _tag = reply.u16()?;
+    reply.buf = &rx[..size.min(len)];
+
+    if rkind == msg::RLERROR {
+        let code = reply.u32()?;
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+        return Err(errno_status(code));
+    }
+    if rkind != kind + 1 {
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+        return Err(FsStatus::IoError);
+    }
+    Ok(reply)
+}
+
+// ============================================================================
+// Protocol Operations
+// ============================================================================
+
+fn p9_walk(fid: u32, newfid: u32, names: &[&str]) -> Result<Option<Qid>, FsStatus> {
+    let mut r = request(msg::TWALK);
+    r.u32(fid);
+    r.u32(newfid);
+    r.u16(names.len() as u16);
+    for name in names {
+        r.str(name);
+    }
+    let mut reply = rpc(r)?;
+    let count = reply.u16()? as usize;
+    if count < names.len() {
+        // Partial walk: newfid was not created
+        return Err(FsStatus::NotFound);
+    }
+    let mut last = None;
+    for _ in 0..count {
+        last = Some(reply.qid()?);
+    }
+    Ok(last)
+}
+
+fn p9_clunk(fid: u32) {
+    let mut r = request(msg::TCLUNK);
+    r.u32(fid);
+    let _ = rpc(r);
+}
+
+/// Open `fid` read-only
+fn p9_lopen(fid: u32) -> Result<u32, FsStatus> {
+    let mut r = request(msg::TLOPEN);
+    r.u32(fid);
+    r.u32(0); // O_RDONLY
+    let mut reply = rpc(r)?;
+    let _qid = reply.qid()?;
+    reply.u32() // iounit
+}
+
+/// Attributes returned by Tgetattr
+struct Attr {
+    mode: u32,
+    nlink: u64,
+    size: u64,
+    blksize: u64,
+    blocks: u64,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
+}
+
+fn p9_getattr(fid: u32) -> Result<Attr, FsStatus> {
+    let mut r = request(msg::TGETATTR);
+    r.u32(fid);
+    r.u64(GETATTR_BASIC);
+    let mut reply = rpc(r)?;
+    let _valid = reply.u64()?;
+    let _qid = reply.qid()?;
+    let mode = reply.u32()?;
+    let _uid = reply.u32()?;
+    let _gid = reply.u32()?;
+    let nlink = reply.u64()?;
+    let _rdev = reply.u64()?;
+    let size = reply.u64()?;
+    let blksize = reply.u64()?;
+    let blocks = reply.u64()?;
+    let atime = reply.u64()?;
+    let _atime_nsec = reply.u64()?;
+    let mtime = reply.u64()?;
+    let _mtime_nsec = reply.u64()?;
+    let ctime = reply.u64()?;
+    Ok(Attr { mode, nlink, size, blksize, blocks, atime, mtime, ctime })
+}
+
+/// Linux S_IFDIR
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+
+fn attr_to_info(attr: &Attr) -> FileInfo {
+    let directory = attr.mode & S_IFMT == S_IFDIR;
+    let mut attributes = 0;
+    if directory {
+        attributes |= super::vfs::file_attrs::ATTR_DIRECTORY;
+    }
+    // Mounted read-only
+    attributes |= super::vfs::file_attrs::ATTR_READONLY;
+    FileInfo {
+        size: attr.size,
+        file_type: if directory { FileType::Directory } else { FileType::Regular },
+        attributes,
+        created: attr.ctime.saturating_sub(EPOCH_1980),
+        accessed: attr.atime.saturating_sub(EPOCH_1980),
+        modified: attr.mtime.saturating_sub(EPOCH_1980),
+        nlink: attr.nlink as u32,
+        block_size: attr.blksize as u32,
+        blocks: attr.blocks,
+    }
+}
+
+// ============================================================================
+// Node Table
+// ============================================================================
+
+/// A looked-up file
+#[derive(Clone, Copy)]
+struct Node {
+    in_use: bool,
+    generation: u16,
+    /// Parent node index (the root is its own parent)
+    parent: u16,
+    name: [u8; MAX_COMPONENT],
+    name_len: u8,
+    qid: Qid,
+    /// An open fid exists (`open_fid`)
+    open: bool,
+    /// Directory listing cursor: next entry index and its server cookie
+    dir_index: u32,
+    dir_cookie: u64,
+    last_use: u64,
+}
+
+impl Node {
+    const fn empty() -> Self {
+        Self {
+            in_use: false,
+            generation: 0,
+            parent: 0,
+            name: [0; MAX_COMPONENT],
+            name_len: 0,
+            qid: Qid { kind: 0, version: 0, path: 0 },
+            open: false,
+            dir_index: 0,
+            dir_cookie: 0,
+            last_use: 0,
+        }
+    }
+
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("")
+    }
+
+    fn is_directory(&self) -> bool {
+        self.qid.kind & QTDIR != 0
+    }
+}
+
+/// Walked fid of node `index`
+fn walk_fid(index: usize) -> u32 {
+    1 + index as u32 * 2
+}
+
+/// Open fid of node `index`
+fn open_fid(index: usize) -> u32 {
+    2 + index as u32 * 2
+}
+
+fn node_id(index: usize, generation: u16) -> u64 {
+    ((generation as u64) << 16) | index as u64
+}
+
+static mut NODES: [Node; MAX_NODES] = [const { Node::empty() }; MAX_NODES];
+
+/// Serializes all protocol traffic and the node table
+static mut P9_LOCK: KMutex = KMutex::new();
+
+static MOUNTED: AtomicBool = AtomicBool::new(false);
+static MOUNTED_DRIVE: AtomicU16 = AtomicU16::new(0);
+static NEGOTIATED_MSIZE: AtomicU64 = AtomicU64::new(0);
+static USE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+static VFS_INDEX: AtomicU16 = AtomicU16::new(u16::MAX);
+
+static RPCS: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+fn nodes() -> &'static mut [Node; MAX_NODES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(NODES) }
+}
+
+/// Resolve a VFS node ID to a node index
+fn node_index(id: u64) -> Result<usize, FsStatus> {
+    let index = (id & 0xFFFF) as usize;
+    let node = nodes().get_mut(index).filter(|n| n.in_use).ok_or(FsStatus::NotFound)?;
+    if node.generation as u64 != id >> 16 {
+        // Evicted since the VFS looked it up
+        return Err(FsStatus::InvalidHandle);
+    }
+    node.last_use = USE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(index)
+}
+
+/// Drop a node's fids
+fn release_node(index: usize) {
+    let node = &mut nodes()[index];
+    if node.open {
+        p9_clunk(open_fid(index));
+        node.open = false;
+    }
+    p9_clunk(walk_fid(index));
+    node.in_use = false;
+}
+
+/// Find a free node slot, evicting the least recently used leaf if full
+fn alloc_node() -> Option<usize> {
+    if let Some(free) = nodes().iter().position(|n| !n.in_use) {
+        return Some(free);
+    }
+
+    let table = nodes();
+    let victim = (1..MAX_NODES)
+        .filter(|&i| !(1..MAX_NODES).any(|c| table[c].in_use && table[c].parent as usize == i))
+        .min_by_key(|&i| table[i].last_use)?;
+    release_node(victim);
+    EVICTIONS.fetch_add(1, Ordering::Relaxed);
+    Some(victim)
+}
+
+/// Walk from `parent` to `name` into a new node
+fn walk_node(parent: usize, name: &str) -> Result<usize, FsStatus> {
+    if name.len() > MAX_COMPONENT {
+        return Err(FsStatus::NameTooLong);
+    }
+    let index = alloc_node().ok_or(FsStatus::TooManyFiles)?;
+    let qid = p9_walk(walk_fid(parent), walk_fid(index), &[name])?.ok_or(FsStatus::NotFound)?;
+
+    let node = &mut nodes()[index];
+    let generation = node.generation.wrapping_add(1).max(1);
+    *node = Node::empty();
+    node.in_use = true;
+    node.generation = generation;
+    node.parent = parent as u16;
+    node.name[..name.len()].copy_from_slice(name.as_bytes());
+    node.name_len = name.len() as u8;
+    node.qid = qid;
+    node.last_use = USE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(index)
+}
+
+/// Open the node's second fid for reading, once
+fn ensure_open(index: usize) -> Result<(), FsStatus> {
+    if nodes()[index].open {
+        return Ok(());
+    }
+    p9_walk(walk_fid(index), open_fid(index), &[])?;
+    if let Err(e) = p9_lopen(open_fid(index)) {
+        p9_clunk(open_fid(index));
+        return Err(e);
+    }
+    let node = &mut nodes()[index];
+    node.open = true;
+    node.dir_index = 0;
+    node.dir_cookie = 0;
+    Ok(())
+}
+
+/// One directory entry from Rreaddir
+struct RawEntry<'a> {
+    cookie: u64,
+    name: &'a [u8],
+}
+
+/// Read directory entries starting at `cookie`, calling `visit` for each
+/// until it returns true
+///
+/// # Returns
+/// `false` at the end of the directory
+fn for_each_entry(index: usize, mut cookie: u64, visit: &mut dyn FnMut(&RawEntry) -> bool) -> Result<bool, FsStatus> {
+    ensure_open(index)?;
+    loop {
+        let mut r = request(msg::TREADDIR);
+        r.u32(open_fid(index));
+        r.u64(cookie);
+        r.u32(4096);
+        let mut reply = rpc(r)?;
+        let count = reply.u32()? as usize;
+        if count == 0 {
+            return Ok(false);
+        }
+        let end = reply.pos + count;
+        while reply.pos < end {
+            let _qid = reply.qid()?;
+            let offset = reply.u64()?;
+            let _kind = reply.u8()?;
+            let name = reply.str()?;
+            cookie = offset;
+            if visit(&RawEntry { cookie: offset, name }) {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Case-insensitive directory search for `name`
+fn find_name_insensitive(parent: usize, name: &str, out: &mut [u8; MAX_COMPONENT]) -> Result<usize, FsStatus> {
+    let mut found = 0;
+    for_each_entry(parent, 0, &mut |entry| {
+        if entry.name.len() <= MAX_COMPONENT && entry.name.eq_ignore_ascii_case(name.as_bytes()) {
+            out[..entry.name.len()].copy_from_slice(entry.name);
+            found = entry.name.len();
+            true
+        } else {
+            false
+        }
+    })?;
+    if found == 0 {
+        return Err(FsStatus::NotFound);
+    }
+    Ok(found)
+}
+
+// ============================================================================
+// VFS Operations
+// ============================================================================
+
+/// Hold P9_LOCK for the duration of a VFS operation
+struct Locked;
+
+impl Locked {
+    fn acquire() -> Self {
+        unsafe { (*core::ptr::addr_of!(P9_LOCK)).acquire(); }
+        Locked
+    }
+}
+
+impl Drop for Locked {
+    fn drop(&mut self) {
+        unsafe { (*core::ptr::addr_of!(P9_LOCK)).release(); }
+    }
+}
+
+unsafe fn p9fs_mount(_fs_index: u16, _device: *mut u8) -> FsStatus {
+    FsStatus::Success
+}
+
+unsafe fn p9fs_unmount(_fs_index: u16) -> FsStatus {
+    FsStatus::Success
+}
+
+unsafe fn p9fs_statfs(_fs_index: u16) -> FsInfo {
+    let _lock = Locked::acquire();
+    let mut info = FsInfo::empty();
+    info.fs_type = FsType::Plan9;
+
+    let mut r = request(msg::TSTATFS);
+    r.u32(walk_fid(0));
+    if let Ok(mut reply) = rpc(r) {
+        let _type = reply.u32();
+        info.block_size = reply.u32().unwrap_or(512);
+        info.total_blocks = reply.u64().unwrap_or(0);
+        info.free_blocks = reply.u64().unwrap_or(0);
+        let _bavail = reply.u64();
+        info.total_files = reply.u64().unwrap_or(0);
+        info.free_files = reply.u64().unwrap_or(0);
+    }
+    let label = b"HOST";
+    info.label[..label.len()].copy_from_slice(label);
+    info
+}
+
+unsafe fn p9fs_lookup(_fs_index: u16, parent: u64, name: &str) -> Result<u64, FsStatus> {
+    let _lock = Locked::acquire();
+    let parent = node_index(parent)?;
+    let table = nodes();
+
+    if name.is_empty() || name == "." {
+        return Ok(node_id(parent, table[parent].generation));
+    }
+    if name == ".." {
+        let up = table[parent].parent as usize;
+        return Ok(node_id(up, table[up].generation));
+    }
+    if !table[parent].is_directory() {
+        return Err(FsStatus::NotDirectory);
+    }
+
+    // Cached, matched the same way the walk would have
+    if let Some(i) = (1..MAX_NODES).find(|&i| {
+        table[i].in_use && table[i].parent as usize == parent && table[i].name().eq_ignore_ascii_case(name)
+    }) {
+        table[i].last_use = USE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        return Ok(node_id(i, table[i].generation));
+    }
+
+    let index = match walk_node(parent, name) {
+        Err(FsStatus::NotFound) => {
+            let mut actual = [0u8; MAX_COMPONENT];
+            let len = find_name_insensitive(parent, name, &mut actual)?;
+            let actual = core::str::from_utf8(&actual[..len]).map_err(|_| FsStatus::NotFound)?;
+            walk_node(parent, actual)?
+        }
+        other => other?,
+    };
+    Ok(node_id(index, table[index].generation))
+}
+
+unsafe fn p9fs_getattr(_fs_index: u16, node: u64) -> Result<FileInfo, FsStatus> {
+    let _lock = Locked::acquire();
+    let index = node_index(node)?;
+    Ok(attr_to_info(&p9_getattr(walk_fid(index))?))
+}
+
+unsafe fn p9fs_getsize(_fs_index: u16, node: u64) -> Result<u64, FsStatus> {
+    let _lock = Locked::acquire();
+    let index = node_index(node)?;
+    Ok(p9_getattr(walk_fid(index))?.size)
+}
+
+unsafe fn p9fs_read(_fs_index: u16, node: u64, offset: u64, buf: &mut [u8]) -> Result<usize, FsStatus> {
+    let _lock = Locked::acquire();
+    let index = node_index(node)?;
+    if nodes()[index].is_directory() {
+        return Err(FsStatus::IsDirectory);
+    }
+    ensure_open(index)?;
+
+    let msize = NEGOTIATED_MSIZE.load(Ordering::Relaxed) as usize;
+    let chunk = msize.saturating_sub(IO_HEADER_SIZE).max(1);
+    let mut done = 0;
+    while done < buf.len() {
+        let want = (buf.len() - done).min(chunk);
+        let mut r = request(msg::TREAD);
+        r.u32(open_fid(index));
+        r.u64(offset + done as u64);
+        r.u32(want as u32);
+        let mut reply = rpc(r)?;
+        let count = (reply.u32()? as usize).min(want);
+        buf[done..done + count].copy_from_slice(reply.take(count)?);
+        done += count;
+        if count < want {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+unsafe fn p9fs_readdir(_fs_index: u16, dir: u64, offset: u32, entry: &mut DirEntry) -> FsStatus {
+    let _lock = Locked::acquire();
+    let index = match node_index(dir) {
+        Ok(i) => i,
+        Err(e) => return e,
+    };
+    if !nodes()[index].is_directory() {
+        return FsStatus::NotDirectory;
+    }
+    if let Err(e) = ensure_open(index) {
+        return e;
+    }
+
+    // Sequential listings continue from the cursor; anything else rescans
+    let (mut position, cookie) = {
+        let node = &nodes()[index];
+        if node.dir_index == offset && offset != 0 {
+            (node.dir_index, node.dir_cookie)
+        } else {
+            (0, 0)
+        }
+    };
+
+    let mut name = [0u8; MAX_COMPONENT];
+    let mut name_len = 0;
+    let mut next_cookie = 0;
+    let found = for_each_entry(index, cookie, &mut |raw| {
+        if raw.name == b"." || raw.name == b".." {
+            return false;
+        }
+        if position < offset {
+            position += 1;
+            return false;
+        }
+        name_len = raw.name.len().min(MAX_COMPONENT);
+        name[..name_len].copy_from_slice(&raw.name[..name_len]);
+        next_cookie = raw.cookie;
+        true
+    });
+    match found {
+        Ok(true) => {}
+        Ok(false) => return FsStatus::NoMoreEntries,
+        Err(e) => return e,
+    }
+
+    {
+        let node = &mut nodes()[index];
+        node.dir_index = offset + 1;
+        node.dir_cookie = next_cookie;
+    }
+
+    *entry = DirEntry::empty();
+    entry.name[..name_len].copy_from_slice(&name[..name_len]);
+    entry.name_len = name_len as u8;
+    entry.next_offset = offset + 1;
+
+    // Size and type need a walk of their own
+    let entry_name = core::str::from_utf8(&name[..name_len]).unwrap_or("");
+    if p9_walk(walk_fid(index), TEMP_FID, &[entry_name]).is_ok() {
+        if let Ok(attr) = p9_getattr(TEMP_FID) {
+            let info = attr_to_info(&attr);
+            entry.file_type = info.file_type;
+            entry.size = info.size;
+            entry.attributes = info.attributes;
+        }
+        p9_clunk(TEMP_FID);
+    }
+    FsStatus::Success
+}
+
+unsafe fn p9fs_close(_fs_index: u16, node: u64) -> FsStatus {
+    let _lock = Locked::acquire();
+    if let Ok(index) = node_index(node) {
+        if nodes()[index].open {
+            p9_clunk(open_fid(index));
+            nodes()[index].open = false;
+        }
+    }
+    FsStatus::Success
+}
+
+unsafe fn p9fs_sync(_fs_index: u16, _node: u64) -> FsStatus {
+    FsStatus::Success
+}
+
+/// 9P VFS operations (read-only: writes report NotSupported)
+pub fn p9fs_ops() -> FsOps {
+    FsOps {
+        mount: Some(p9fs_mount),
+        unmount: Some(p9fs_unmount),
+        statfs: Some(p9fs_statfs),
+        lookup: Some(p9fs_lookup),
+        readdir: Some(p9fs_readdir),
+        getattr: Some(p9fs_getattr),
+        read: Some(p9fs_read),
+        close: Some(p9fs_close),
+        getsize: Some(p9fs_getsize),
+        sync: Some(p9fs_sync),
+        ..FsOps::empty()
+    }
+}
+
+// ============================================================================
+// Mounting
+// ============================================================================
+
+/// Negotiate the protocol and attach to the share's root
+fn attach() -> Result<(), FsStatus> {
+    let mut r = request(msg::TVERSION);
+    r.u32(MAX_MESSAGE_SIZE as u32);
+    r.str(VERSION);
+    let mut reply = rpc(r)?;
+    let msize = reply.u32()?;
+    if reply.str()? != VERSION.as_bytes() {
+        crate::serial_println!("[9P] Server does not speak {}", VERSION);
+        return Err(FsStatus::NotSupported);
+    }
+    NEGOTIATED_MSIZE.store((msize as u64).min(MAX_MESSAGE_SIZE as u64), Ordering::Relaxed);
+
+    let mut r = request(msg::TATTACH);
+    r.u32(walk_fid(0));
+    r.u32(NOFID);
+    r.str("nostalgia");
+    r.str("");
+    r.u32(0); // n_uname
+    let qid = rpc(r)?.qid()?;
+
+    let root = &mut nodes()[0];
+    *root = Node::empty();
+    root.in_use = true;
+    root.qid = qid;
+    Ok(())
+}
+
+/// Mount the shared folder at `drive`
+pub fn p9fs_mount_share(drive: char) -> Result<(), FsStatus> {
+    if MOUNTED.load(Ordering::Acquire) {
+        return Err(FsStatus::AlreadyExists);
+    }
+    let vfs_index = VFS_INDEX.load(Ordering::Relaxed);
+    if vfs_index == u16::MAX {
+        return Err(FsStatus::NotSupported);
+    }
+    crate::drivers::virtio::p9::init().map_err(|e| {
+        crate::serial_println!("[9P] {}", e);
+        FsStatus::NotFound
+    })?;
+
+    {
+        let _lock = Locked::acquire();
+        attach()?;
+    }
+
+    let tag = crate::drivers::virtio::p9::p9_mount_tag().unwrap_or("");
+    let mut device_path = [0u8; 64];
+    let prefix = b"\\Device\\VirtioFs\\";
+    let len = (prefix.len() + tag.len()).min(device_path.len());
+    device_path[..prefix.len()].copy_from_slice(prefix);
+    device_path[prefix.len()..len].copy_from_slice(&tag.as_bytes()[..len - prefix.len()]);
+    let device_path = core::str::from_utf8(&device_path[..len]).unwrap_or("\\Device\\VirtioFs");
+
+    if let Err(e) = super::mount::mount(
+        drive,
+        FsType::Plan9,
+        vfs_index,
+        device_path,
+        super::mount::mount_flags::MF_READONLY | super::mount::mount_flags::MF_NETWORK,
+    ) {
+        let _lock = Locked::acquire();
+        release_node(0);
+        return Err(e);
+    }
+    let _ = super::mount::set_volume_label(drive, "HOST");
+
+    MOUNTED_DRIVE.store(drive.to_ascii_uppercase() as u16, Ordering::Relaxed);
+    MOUNTED.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Unmount the shared folder and release every fid
+pub fn p9fs_unmount_share() -> Result<(), FsStatus> {
+    if !MOUNTED.load(Ordering::Acquire) {
+        return Err(FsStatus::NotMounted);
+    }
+    let drive = MOUNTED_DRIVE.load(Ordering::Relaxed) as u8 as char;
+    super::mount::unmount(drive)?;
+
+    let _lock = Locked::acquire();
+    for index in (0..MAX_NODES).rev() {
+        if nodes()[index].in_use {
+            release_node(index);
+        }
+    }
+    MOUNTED.store(false, Ordering::Release);
+    Ok(())
+}
+
+/// 9P client statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct P9fsStats {
+    /// Drive letter the share is mounted at
+    pub drive: Option<char>,
+    /// Negotiated maximum message size
+    pub msize: u64,
+    /// Nodes holding a fid
+    pub cached_nodes: usize,
+    /// Nodes with an open fid
+    pub open_nodes: usize,
+    pub rpcs: u64,
+    /// Rlerror replies and protocol errors
+    pub errors: u64,
+    pub evictions: u64,
+}
+
+/// Get client statistics
+pub fn p9fs_stats() -> P9fsStats {
+    let table = nodes();
+    P9fsStats {
+        drive: if MOUNTED.load(Ordering::Acquire) {
+            Some(MOUNTED_DRIVE.load(Ordering::Relaxed) as u8 as char)
+        } else {
+            None
+        },
+        msize: NEGOTIATED_MSIZE.load(Ordering::Relaxed),
+        cached_nodes: table.iter().filter(|n| n.in_use).count(),
+        open_nodes: table.iter().filter(|n| n.in_use && n.open).count(),
+        rpcs: RPCS.load(Ordering::Relaxed),
+        errors: ERRORS.load(Ordering::Relaxed),
+        evictions: EVICTIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Register with the VFS and mount a shared folder if one is attached
+pub fn init() {
+    unsafe {
+        (*core::ptr::addr_of_mut!(P9_LOCK)).init();
+        match vfs_register_fs(P9FS_NAME, FsType::Plan9, p9fs_ops()) {
+            Some(idx) => VFS_INDEX.store(idx, Ordering::Relaxed),
+            None => {
+                crate::serial_println!("[9P] Failed to register with VFS");
+                return;
+            }
+        }
+    }
+
+    // Quiet when QEMU was started without a shared folder
+    if crate::drivers::virtio::p9::init().is_err() {
+        return;
+    }
+    let drive = (DEFAULT_DRIVE..='Z').find(|&d| super::mount::get_mount_point(d).is_none());
+    match drive.map(p9fs_mount_share) {
+        Some(Ok(())) => crate::serial_println!(
+            "[9P] Shared folder '{}' mounted at {}:",
+            crate::drivers::virtio::p9::p9_mount_tag().unwrap_or(""),
+            drive.unwrap_or('?')
+        ),
+        Some(Err(e)) => crate::serial_println!("[9P] Mount failed: {:?}", e),
+        None => crate::serial_println!("[9P] No free drive letter"),
+    }
+}
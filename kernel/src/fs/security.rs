@@ -0,0 +1,99 @@
+//! File Security
+//!
+//! FAT volumes store no security descriptors, so every file and directory
+//! gets a default descriptor from its path, modeled on a fresh Windows
+//! install:
+//!
+//! - `\WINDOWS`, `\Program Files` and `\Documents and Settings`:
+//!   LocalSystem and Administrators full control, Users read and execute
+//! - `\Documents and Settings\<user>`: LocalSystem, Administrators and
+//!   the profile's owner full control
+//! - everything else: LocalSystem and Administrators full control,
+//!   Authenticated Users modify
+//!
+//! Opening a file needs the access requested, creating a file or directory
+//! `FILE_ADD_FILE`/`FILE_ADD_SUBDIRECTORY` on its parent, and deleting or
+//! renaming one DELETE, checked against the token of the calling process.
+
+use crate::io::file_access::*;
+use crate::se::{
+    generic_rights, standard_rights, GenericMapping, SimpleSecurityDescriptor,
+    SID_AUTHENTICATED_USERS, SID_BUILTIN_ADMINISTRATORS, SID_BUILTIN_USERS, SID_LOCAL_SYSTEM,
+};
+use super::vfs::FsStatus;
+
+/// Add a file to a directory
+pub const FILE_ADD_FILE: u32 = FILE_WRITE_DATA;
+/// Add a subdirectory to a directory
+pub const FILE_ADD_SUBDIRECTORY: u32 = FILE_APPEND_DATA;
+
+/// Generic mapping for files
+pub const FILE_GENERIC_MAPPING: GenericMapping = GenericMapping {
+    generic_read: FILE_GENERIC_READ,
+    generic_write: FILE_GENERIC_WRITE,
+    generic_execute: FILE_GENERIC_EXECUTE,
+    generic_all: FILE_ALL_ACCESS,
+};
+
+/// Read and execute rights
+const READ_EXECUTE: u32 = generic_rights::GENERIC_READ | generic_rights::GENERIC_EXECUTE;
+/// Modify rights: everything but WRITE_DAC and WRITE_OWNER
+const MODIFY: u32 = READ_EXECUTE | generic_rights::GENERIC_WRITE | standard_rights::DELETE;
+
+/// Directories only administrators may change
+const SYSTEM_DIRECTORIES: [&str; 3] = ["WINDOWS", "Program Files", "Documents and Settings"];
+/// Directory holding user profiles
+const PROFILES_DIRECTORY: &str = "Documents and Settings";
+
+/// Fill in the default security descriptor of a path
+fn describe_path(path: &str, sd: &mut SimpleSecurityDescriptor) {
+    sd.set_owner(SID_BUILTIN_ADMINISTRATORS);
+    sd.add_access_allowed(SID_LOCAL_SYSTEM, generic_rights::GENERIC_ALL);
+    sd.add_access_allowed(SID_BUILTIN_ADMINISTRATORS, generic_rights::GENERIC_ALL);
+
+    // Skip the drive letter
+    let relative = match path.find(":\\") {
+        Some(pos) => &path[pos + 2..],
+        None => path.trim_start_matches('\\'),
+    };
+    let mut components = relative.split('\\').filter(|c| !c.is_empty());
+    let top = components.next().unwrap_or("");
+
+    if top.eq_ignore_ascii_case(PROFILES_DIRECTORY) {
+        if let Some(profile) = components.next() {
+            if let Some(owner) = crate::se::se_lookup_account_sid(profile) {
+                sd.set_owner(owner);
+                sd.add_access_allowed(owner, generic_rights::GENERIC_ALL);
+            }
+            return;
+        }
+    }
+
+    if SYSTEM_DIRECTORIES.iter().any(|dir| top.eq_ignore_ascii_case(dir)) {
+        sd.add_access_allowed(SID_BUILTIN_USERS, READ_EXECUTE);
+    } else {
+        sd.add_access_allowed(SID_AUTHENTICATED_USERS, MODIFY);
+    }
+}
+
+/// Get the directory containing a path
+pub fn parent_path(path: &str) -> &str {
+    match path.trim_end_matches('\\').rfind('\\') {
+        Some(pos) => &path[..pos],
+        None => "",
+    }
+}
+
+/// Check the calling process's access to a path
+pub fn fs_access_check(path: &str, desired_access: u32) -> Result<(), FsStatus> {
+    let result = unsafe {
+        crate::ps::ps_access_check_object(desired_access, &FILE_GENERIC_MAPPING, |sd| {
+            describe_path(path, sd)
+        })
+    };
+
+    result.map_err(|reason| {
+        crate::serial_println!("[FS] Access {:#x} to {} denied: {:?}", desired_access, path, reason);
+        FsStatus::AccessDenied
+    })
+}
@@ -5,9 +5,26 @@
 //!
 //! # Key Concepts
 //! - **FileSystem**: A file system driver (FAT32, NTFS, etc.)
+//! - **FsDriver**: Registration record: name, recognizer and dispatch table.
+//!   Volume mounting asks each registered recognizer in turn and attaches
+//!   the volume to the first driver that accepts it
 //! - **VNode**: Virtual node representing a file or directory
 //! - **FileHandle**: Open file descriptor
 //! - **DirEntry**: Directory entry for enumeration
+//!
+//! # Handle Options
+//! A handle opened with `FILE_NO_INTERMEDIATE_BUFFERING` reads and writes
+//! through the driver's `read_direct`/`write_direct` operations, which move
+//! whole sectors between the caller's buffer and the disk; the offset and
+//! length must then be sector multiples. `FILE_WRITE_THROUGH` makes every
+//! write call the driver's `sync` before returning, so the data and the
+//! file size are past the drive's write cache when the write completes.
+//!
+//! # File System Controls
+//! `vfs_fsctl` and `vfs_fsctl_volume` pass an `FsControl` request (the
+//! FSCTL_* codes) to the driver's `fsctl` operation, which fills in the
+//! request's output fields. Drivers answer the requests they understand
+//! and return `NotSupported` for the rest.
 
 use core::sync::atomic::{AtomicU32, Ordering};
 use crate::ke::SpinLock;
@@ -75,6 +92,10 @@ pub enum FsStatus {
     DeviceBusy = -21,
     /// Invalid handle
     InvalidHandle = -22,
+    /// Media changed; the volume was verified and must be reopened
+    VerifyRequired = -23,
+    /// No media in the drive
+    NoMedia = -24,
 }
 
 impl FsStatus {
@@ -86,10 +107,10 @@ impl FsStatus {
 /// File type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
-#[derive(Default)]
+#[derive(Default)]
 pub enum FileType {
     /// Regular file
-    #[default]
+    #[default]
     Regular = 0,
     /// Directory
     Directory = 1,
@@ -135,6 +156,16 @@ pub mod open_flags {
     pub const O_DIRECTORY: u32 = 0x10000;
 }
 
+/// Handle options (`FileHandle::handle_flags`), numbered as the NT create options
+pub mod handle_options {
+    /// Writes are flushed to the media before they complete
+    pub const FILE_WRITE_THROUGH: u32 = 0x0002;
+    /// Sector-aligned I/O directly between the caller's buffer and the disk
+    pub const FILE_NO_INTERMEDIATE_BUFFERING: u32 = 0x0008;
+    /// Options a handle can carry
+    pub const VALID_OPTIONS: u32 = FILE_WRITE_THROUGH | FILE_NO_INTERMEDIATE_BUFFERING;
+}
+
 /// Seek origin
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -371,9 +402,9 @@ impl Default for FileHandle {
 /// File system type identifier
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
-#[derive(Default)]
+#[derive(Default)]
 pub enum FsType {
-    #[default]
+    #[default]
     Unknown = 0,
     Fat12 = 1,
     Fat16 = 2,
@@ -383,6 +414,8 @@ pub enum FsType {
     Ext2 = 6,
     Ext4 = 7,
     Iso9660 = 8,
+    /// Host shared folder over 9P
+    Plan9 = 9,
 }
 
 
@@ -423,6 +456,12 @@ pub struct FsOps {
     pub getsize: Option<unsafe fn(fs_index: u16, node_id: u64) -> Result<u64, FsStatus>>,
     /// Sync/flush file data and metadata to disk
     pub sync: Option<unsafe fn(fs_index: u16, node_id: u64) -> FsStatus>,
+    /// Read whole sectors straight into the caller's buffer (non-cached)
+    pub read_direct: Option<unsafe fn(fs_index: u16, node_id: u64, offset: u64, buf: &mut [u8]) -> Result<usize, FsStatus>>,
+    /// Write whole sectors straight from the caller's buffer (non-cached)
+    pub write_direct: Option<unsafe fn(fs_index: u16, node_id: u64, offset: u64, buf: &[u8]) -> Result<usize, FsStatus>>,
+    /// File system control request; node 0 addresses the volume
+    pub fsctl: Option<unsafe fn(fs_index: u16, node_id: u64, request: &mut FsControl) -> FsStatus>,
 }
 
 impl FsOps {
@@ -445,6 +484,9 @@ impl FsOps {
             rename: None,
             getsize: None,
             sync: None,
+            read_direct: None,
+            write_direct: None,
+            fsctl: None,
         }
     }
 }
@@ -455,6 +497,55 @@ impl Default for FsOps {
     }
 }
 
+/// Volume geometry and free space (FSCTL_GET_NTFS_VOLUME_DATA)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsVolumeData {
+    pub bytes_per_sector: u32,
+    pub bytes_per_cluster: u32,
+    pub total_clusters: u64,
+    pub free_clusters: u64,
+}
+
+/// A run of clusters of a file (FSCTL_GET_RETRIEVAL_POINTERS)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsExtent {
+    /// First cluster of the run within the file (VCN)
+    pub vcn: u64,
+    /// First cluster of the run on the volume (LCN)
+    pub lcn: u64,
+    /// Length of the run in clusters
+    pub clusters: u64,
+}
+
+/// A byte range of a file backed by storage (FSCTL_QUERY_ALLOCATED_RANGES)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// File system control request
+///
+/// Volume requests are sent to node 0; the others to an open file. The
+/// driver fills in the output fields and returns the status.
+pub enum FsControl<'a> {
+    /// FSCTL_IS_VOLUME_DIRTY: the volume needs checking
+    IsVolumeDirty { dirty: bool },
+    /// FSCTL_MARK_VOLUME_DIRTY: check the volume at the next mount
+    MarkVolumeDirty,
+    /// FSCTL_GET_NTFS_VOLUME_DATA
+    GetVolumeData { data: FsVolumeData },
+    /// FSCTL_GET_RETRIEVAL_POINTERS: the file's runs from `start_vcn`;
+    /// `more` is set when they did not all fit in `extents`
+    GetRetrievalPointers { start_vcn: u64, extents: &'a mut [FsExtent], count: usize, more: bool },
+    /// FSCTL_QUERY_ALLOCATED_RANGES: the parts of the file holding data
+    QueryAllocatedRanges { ranges: &'a mut [FsRange], count: usize, more: bool },
+    /// Sparse attribute, valid data length and allocation size of the file
+    QueryFileData { sparse: bool, valid_data_length: u64, allocation_size: u64 },
+    /// FSCTL_SET_SPARSE
+    SetSparse { sparse: bool },
+}
+
 /// File system info
 #[derive(Debug, Clone, Copy)]
 pub struct FsInfo {
@@ -506,6 +597,69 @@ impl Default for FsInfo {
     }
 }
 
+/// Volume recognizer
+///
+/// Called with the volume number, its partition type (0 if unknown) and
+/// the first sector of the volume. Returns true if the driver can mount it.
+pub type FsRecognizer = unsafe fn(volume_number: u8, partition_type: u8, boot_sector: &[u8]) -> bool;
+
+/// Attach a recognized volume to the driver
+///
+/// Called with the driver's VFS index before the volume is given a mount
+/// point; the driver sets up its per-volume state here.
+pub type FsAttach = unsafe fn(fs_index: u16, volume_number: u8, boot_sector: &[u8]) -> FsStatus;
+
+/// Detach a volume from the driver
+///
+/// Called when the volume is dismounted, including after its media was
+/// removed; the driver drops its per-volume state without touching the
+/// device.
+pub type FsDetach = unsafe fn(fs_index: u16, volume_number: u8) -> FsStatus;
+
+/// File system driver registration
+pub struct FsDriver {
+    /// Driver name (up to 15 bytes kept)
+    pub name: &'static str,
+    /// File system type
+    pub fs_type: FsType,
+    /// Dispatch table
+    pub ops: FsOps,
+    /// Volume recognizer, `None` for drivers that are mounted explicitly
+    /// (network and pseudo file systems)
+    pub recognize: Option<FsRecognizer>,
+    /// Volume attach callback
+    pub attach: Option<FsAttach>,
+    /// Volume detach callback
+    pub detach: Option<FsDetach>,
+}
+
+impl FsDriver {
+    /// Driver without a recognizer
+    pub const fn new(name: &'static str, fs_type: FsType, ops: FsOps) -> Self {
+        Self {
+            name,
+            fs_type,
+            ops,
+            recognize: None,
+            attach: None,
+            detach: None,
+        }
+    }
+
+    /// Set the recognizer and attach callbacks
+    pub const fn with_recognizer(mut self, recognize: FsRecognizer, attach: FsAttach) -> Self {
+        self.recognize = Some(recognize);
+        self.attach = Some(attach);
+        self
+    }
+
+    /// Set the detach callback
+    pub const fn with_detach(mut self, detach: FsDetach) -> Self {
+        self.detach = Some(detach);
+        self
+    }
+}
+
 /// Registered file system
 #[repr(C)]
 pub struct RegisteredFs {
@@ -517,6 +671,12 @@ pub struct RegisteredFs {
     pub mounted: bool,
     /// Operations
     pub ops: FsOps,
+    /// Volume recognizer
+    pub recognize: Option<FsRecognizer>,
+    /// Volume attach callback
+    pub attach: Option<FsAttach>,
+    /// Volume detach callback
+    pub detach: Option<FsDetach>,
     /// Root vnode ID
     pub root_vnode: u64,
     /// Device pointer
@@ -532,6 +692,9 @@ impl RegisteredFs {
             name: [0; 16],
             mounted: false,
             ops: FsOps::empty(),
+            recognize: None,
+            attach: None,
+            detach: None,
             root_vnode: 0,
             device: core::ptr::null_mut(),
             private: core::ptr::null_mut(),
@@ -586,20 +749,38 @@ static FS_COUNT: AtomicU32 = AtomicU32::new(0);
 // VFS Operations
 // ============================================================================
 
-/// Register a file system
-pub unsafe fn vfs_register_fs(name: &str, fs_type: FsType, ops: FsOps) -> Option<u16> {
+/// Register a file system without a recognizer
+pub unsafe fn vfs_register_fs(name: &'static str, fs_type: FsType, ops: FsOps) -> Option<u16> {
+    vfs_register_driver(FsDriver::new(name, fs_type, ops))
+}
+
+/// Register a file system driver
+///
+/// Returns the driver's VFS index, or None if the name is already taken
+/// or all slots are in use.
+pub unsafe fn vfs_register_driver(driver: FsDriver) -> Option<u16> {
+    if driver.fs_type == FsType::Unknown || driver.name.is_empty() {
+        return None;
+    }
+
     let _guard = VFS_LOCK.lock();
 
+    let name = &driver.name.as_bytes()[..driver.name.len().min(15)];
+    if FILE_SYSTEMS.iter().any(|fs| fs.fs_type != FsType::Unknown && fs.name_str().as_bytes() == name) {
+        return None;
+    }
+
     // Find a free slot
     for i in 0..MAX_FILE_SYSTEMS {
         if FILE_SYSTEMS[i].fs_type == FsType::Unknown {
             let fs = &mut FILE_SYSTEMS[i];
-            fs.fs_type = fs_type;
-            fs.ops = ops;
-
-            let bytes = name.as_bytes();
-            let len = bytes.len().min(15);
-            fs.name[..len].copy_from_slice(&bytes[..len]);
+            *fs = RegisteredFs::empty();
+            fs.fs_type = driver.fs_type;
+            fs.ops = driver.ops;
+            fs.recognize = driver.recognize;
+            fs.attach = driver.attach;
+            fs.detach = driver.detach;
+            fs.name[..name.len()].copy_from_slice(name);
 
             FS_COUNT.fetch_add(1, Ordering::SeqCst);
             return Some(i as u16);
@@ -609,6 +790,54 @@ pub unsafe fn vfs_register_fs(name: &str, fs_type: FsType, ops: FsOps) -> Option
     None
 }
 
+/// Unregister a file system driver
+///
+/// Fails with `DeviceBusy` while a volume is mounted through it.
+pub unsafe fn vfs_unregister_fs(index: u16) -> Result<(), FsStatus> {
+    if vfs_get_fs(index).is_none() {
+        return Err(FsStatus::NotFound);
+    }
+    if crate::fs::mount::driver_in_use(index) {
+        return Err(FsStatus::DeviceBusy);
+    }
+
+    let _guard = VFS_LOCK.lock();
+    FILE_SYSTEMS[index as usize] = RegisteredFs::empty();
+    FS_COUNT.fetch_sub(1, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Find a registered driver by name
+pub fn vfs_find_fs(name: &str) -> Option<u16> {
+    let _guard = VFS_LOCK.lock();
+    unsafe {
+        FILE_SYSTEMS.iter()
+            .position(|fs| fs.fs_type != FsType::Unknown && fs.name_str().eq_ignore_ascii_case(name))
+            .map(|i| i as u16)
+    }
+}
+
+/// Find the driver for a volume
+///
+/// Registered recognizers are asked in slot order (registration order,
+/// unless a driver was unregistered) and the first one that accepts the
+/// volume wins. Recognizers run without the VFS lock held since they
+/// may do I/O.
+pub fn vfs_recognize(volume_number: u8, partition_type: u8, boot_sector: &[u8]) -> Option<u16> {
+    for i in 0..MAX_FILE_SYSTEMS {
+        let recognize = {
+            let _guard = VFS_LOCK.lock();
+            unsafe { FILE_SYSTEMS[i].recognize }
+        };
+        if let Some(recognize) = recognize {
+            if unsafe { recognize(volume_number, partition_type, boot_sector) } {
+                return Some(i as u16);
+            }
+        }
+    }
+    None
+}
+
 /// Get file system by index
 pub unsafe fn vfs_get_fs(index: u16) -> Option<&'static RegisteredFs> {
     if (index as usize) < MAX_FILE_SYSTEMS {
@@ -758,6 +987,7 @@ pub fn vfs_alloc_handle(fs_index: u16, vnode_id: u64) -> Option<u16> {
                 FILE_HANDLES[i].vnode_index = vnode_id as u32;
                 FILE_HANDLES[i].position = 0;
                 FILE_HANDLES[i].flags = fs_index as u32;  // Store fs_index in flags temporarily
+                FILE_HANDLES[i].handle_flags = 0;
                 return Some(i as u16);
             }
         }
@@ -821,7 +1051,12 @@ pub fn vfs_read(handle: u16, buf: &mut [u8]) -> Result<usize, FsStatus> {
         let position = fh.position;
 
         let fs = vfs_get_fs(fs_index).ok_or(FsStatus::NotMounted)?;
-        let read_fn = fs.ops.read.ok_or(FsStatus::NotSupported)?;
+        // File systems without direct I/O keep no data of their own to bypass
+        let read_fn = if fh.handle_flags & handle_options::FILE_NO_INTERMEDIATE_BUFFERING != 0 {
+            fs.ops.read_direct.or(fs.ops.read)
+        } else {
+            fs.ops.read
+        }.ok_or(FsStatus::NotSupported)?;
 
         let bytes_read = read_fn(fs_index, vnode_id, position, buf)?;
         fh.position += bytes_read as u64;
@@ -848,11 +1083,24 @@ pub fn vfs_write(handle: u16, buf: &[u8]) -> Result<usize, FsStatus> {
         let position = fh.position;
 
         let fs = vfs_get_fs(fs_index).ok_or(FsStatus::NotMounted)?;
-        let write_fn = fs.ops.write.ok_or(FsStatus::NotSupported)?;
+        let write_fn = if fh.handle_flags & handle_options::FILE_NO_INTERMEDIATE_BUFFERING != 0 {
+            fs.ops.write_direct.or(fs.ops.write)
+        } else {
+            fs.ops.write
+        }.ok_or(FsStatus::NotSupported)?;
 
         let bytes_written = write_fn(fs_index, vnode_id, position, buf)?;
         fh.position += bytes_written as u64;
 
+        if fh.handle_flags & handle_options::FILE_WRITE_THROUGH != 0 {
+            if let Some(sync_fn) = fs.ops.sync {
+                match sync_fn(fs_index, vnode_id) {
+                    FsStatus::Success => {}
+                    status => return Err(status),
+                }
+            }
+        }
+
         Ok(bytes_written)
     }
 }
@@ -939,6 +1187,68 @@ pub fn vfs_fstat(handle: u16) -> Result<FileInfo, FsStatus> {
     }
 }
 
+/// Send a file system control request for an open file
+pub fn vfs_fsctl(handle: u16, request: &mut FsControl) -> Result<(), FsStatus> {
+    let (fs_index, vnode_id) = {
+        let _guard = VFS_LOCK.lock();
+        let index = handle as usize;
+        unsafe {
+            if index >= MAX_OPEN_FILES || !FILE_HANDLES[index].in_use {
+                return Err(FsStatus::InvalidHandle);
+            }
+            (FILE_HANDLES[index].flags as u16, FILE_HANDLES[index].vnode_index as u64)
+        }
+    };
+    vfs_fsctl_node(fs_index, vnode_id, request)
+}
+
+/// Send a file system control request for a volume
+pub fn vfs_fsctl_volume(fs_index: u16, request: &mut FsControl) -> Result<(), FsStatus> {
+    vfs_fsctl_node(fs_index, 0, request)
+}
+
+fn vfs_fsctl_node(fs_index: u16, node_id: u64, request: &mut FsControl) -> Result<(), FsStatus> {
+    unsafe {
+        let fs = vfs_get_fs(fs_index).ok_or(FsStatus::NotMounted)?;
+        let fsctl_fn = fs.ops.fsctl.ok_or(FsStatus::NotSupported)?;
+        match fsctl_fn(fs_index, node_id, request) {
+            FsStatus::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+}
+
+/// Set a handle's options (`handle_options`), replacing the previous ones
+pub fn vfs_set_handle_options(handle: u16, options: u32) -> Result<(), FsStatus> {
+    if options & !handle_options::VALID_OPTIONS != 0 {
+        return Err(FsStatus::InvalidParameter);
+    }
+
+    let _guard = VFS_LOCK.lock();
+
+    unsafe {
+        let index = handle as usize;
+        if index >= MAX_OPEN_FILES || !FILE_HANDLES[index].in_use {
+            return Err(FsStatus::InvalidHandle);
+        }
+        FILE_HANDLES[index].handle_flags = options;
+    }
+    Ok(())
+}
+
+/// Get a handle's options
+pub fn vfs_handle_options(handle: u16) -> Result<u32, FsStatus> {
+    let _guard = VFS_LOCK.lock();
+
+    unsafe {
+        let index = handle as usize;
+        if index >= MAX_OPEN_FILES || !FILE_HANDLES[index].in_use {
+            return Err(FsStatus::InvalidHandle);
+        }
+        Ok(FILE_HANDLES[index].handle_flags)
+    }
+}
+
 /// Sync/flush file data and metadata to disk by handle
 pub fn vfs_sync(handle: u16) -> Result<(), FsStatus> {
     if handle == INVALID_HANDLE {
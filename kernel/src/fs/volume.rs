@@ -1,26 +1,47 @@
 //! Volume Integration
 //!
 //! Integrates block device volumes with the file system layer.
-//! Provides automatic mounting of detected FAT32 volumes.
+//! Provides automatic mounting of detected volumes.
 //!
 //! # Volume to Mount Flow
 //! 1. Storage subsystem detects physical disks
 //! 2. Disk driver scans MBR for partitions
 //! 3. Volumes are created for each partition
-//! 4. This module mounts FAT32 volumes to drive letters
-
-use crate::io::disk::{
-    Volume, get_volume, partition_type,
-    get_volume_read_callback, get_volume_write_callback,
-};
-use crate::io::block::SECTOR_SIZE;
-use crate::fs::vfs::{FsType, FsStatus};
-use crate::fs::mount::{mount, mount_flags};
+//! 4. Each registered file system recognizer is offered the volume's
+//!    boot sector; the first driver that accepts it attaches the volume
+//! 5. This module mounts recognized volumes to drive letters
+//!
+//! # Removable Media
+//! Mounts on removable devices record a fingerprint of the media. When the
+//! block layer reports a media change, `verify_mount` (the analogue of
+//! IRP_MN_VERIFY_VOLUME) compares the fingerprint: the same media is
+//! simply revalidated, different media is dismounted, the disk rescanned
+//! and the new volume mounted at the same letter, and the caller gets
+//! `VerifyRequired` so it reopens its files.
+//!
+//! # Write Caching
+//! `DisableWriteCache` under the FileSystem control key turns off the
+//! volatile write cache of every writable disk (`fsutil behavior set
+//! disablewritecache 1`). It is applied again at every boot.
+
+use crate::io::disk::{self, Volume, get_volume, partition_type, get_volume_read_callback};
+use crate::io::block::{self, BlockStatus, SECTOR_SIZE};
+use crate::fs::vfs::{self, FsType, FsStatus};
+use crate::fs::mount::{self, mount, mount_flags};
 use crate::fs::fat32::bpb::Fat32BootSector;
 
 /// Boot sector buffer for reading
 static mut BOOT_SECTOR: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
 
+/// Sector buffer for media fingerprints
+static mut FINGERPRINT_SECTOR: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+
+/// Sector of the ISO 9660 primary volume descriptor (block 16)
+const ISO_PVD_SECTOR: u64 = 64;
+
+/// File system behavior settings
+const FILESYSTEM_KEY: &str = "MACHINE\\SYSTEM\\CurrentControlSet\\Control\\FileSystem";
+
 /// Check if a volume contains a FAT32 file system
 pub fn is_fat32_volume(volume_number: u8) -> bool {
     let vol = match get_volume(volume_number) {
@@ -28,58 +49,33 @@ pub fn is_fat32_volume(volume_number: u8) -> bool {
         None => return false,
     };
 
-    // Check partition type first
-    if !partition_type::is_fat(vol.partition_type) {
-        // Could still be FAT if partition type is wrong, check boot sector
-        if vol.partition_type != partition_type::EMPTY {
-            return false;
-        }
+    unsafe {
+        read_boot_sector(volume_number)
+            && crate::fs::fat32::is_fat32_boot_sector(vol.partition_type, &BOOT_SECTOR)
     }
+}
 
-    // Read boot sector and validate
+/// Find the file system driver for a volume
+///
+/// # Returns
+/// The driver's VFS index and file system type
+pub fn recognize_volume(volume_number: u8) -> Option<(u16, FsType)> {
+    let vol = get_volume(volume_number)?;
     unsafe {
         if !read_boot_sector(volume_number) {
-            return false;
-        }
-
-        // Check for FAT32 signature
-        let bs = &*(BOOT_SECTOR.as_ptr() as *const Fat32BootSector);
-
-        // Check jump instruction (0xEB or 0xE9)
-        if bs.jump[0] != 0xEB && bs.jump[0] != 0xE9 {
-            return false;
-        }
-
-        // Check bytes per sector (must be power of 2, 512-4096)
-        let bps = bs.bpb.bytes_per_sector;
-        if !(512..=4096).contains(&bps) || (bps & (bps - 1)) != 0 {
-            return false;
-        }
-
-        // Check sectors per cluster (must be power of 2)
-        let spc = bs.bpb.sectors_per_cluster;
-        if spc == 0 || (spc & (spc - 1)) != 0 {
-            return false;
-        }
-
-        // FAT32 has 0 root entries and non-zero sectors per FAT32
-        if bs.bpb.root_entry_count != 0 {
-            return false;  // FAT12/16
-        }
-
-        if bs.ext_bpb.sectors_per_fat_32 == 0 {
-            return false;  // Not FAT32
-        }
-
-        // Check signature
-        if bs.signature != [0x55, 0xAA] {
-            return false;
+            return None;
         }
-
-        true
+        let index = vfs::vfs_recognize(volume_number, vol.partition_type, &BOOT_SECTOR)?;
+        Some((index, vfs::vfs_get_fs(index)?.fs_type))
     }
 }
 
+/// Name of the driver that recognizes a volume
+fn recognized_name(volume_number: u8) -> Option<&'static str> {
+    let (index, _) = recognize_volume(volume_number)?;
+    unsafe { vfs::vfs_get_fs(index).map(|fs| fs.name_str()) }
+}
+
 /// Read boot sector from volume
 unsafe fn read_boot_sector(volume_number: u8) -> bool {
     let read_fn = match get_volume_read_callback(volume_number) {
@@ -106,18 +102,10 @@ pub fn mount_volume(
     drive_letter: char,
     flags: u32,
 ) -> Result<(), FsStatus> {
-    let vol = get_volume(volume_number).ok_or(FsStatus::NotFound)?;
+    get_volume(volume_number).ok_or(FsStatus::NotFound)?;
 
-    // Determine file system type and get the VFS driver index
-    let (fs_type, vfs_index) = if is_fat32_volume(volume_number) {
-        let idx = crate::fs::fat32::vfs_index().ok_or(FsStatus::NotSupported)?;
-        (FsType::Fat32, idx)
-    } else if vol.partition_type == partition_type::NTFS {
-        // NTFS not yet implemented
-        return Err(FsStatus::NotSupported);
-    } else {
-        return Err(FsStatus::NotSupported);
-    };
+    // Find the driver whose recognizer accepts the volume
+    let (vfs_index, fs_type) = recognize_volume(volume_number).ok_or(FsStatus::NotSupported)?;
 
     // Create device path
     let device_path = format_device_path(volume_number);
@@ -125,20 +113,41 @@ pub fn mount_volume(
         .unwrap_or("\\Device\\HarddiskVolume0")
         .trim_end_matches('\0');
 
-    // If FAT32, register with FAT32 driver first (before VFS mount)
-    // This sets up the mount with the volume_number as identifier
-    if fs_type == FsType::Fat32 {
-        mount_fat32_volume(volume_number)?;
+    // Attach the volume to the driver first (before VFS mount); the
+    // recognizer left the boot sector in BOOT_SECTOR
+    let attach = unsafe { vfs::vfs_get_fs(vfs_index).and_then(|fs| fs.attach) };
+    if let Some(attach) = attach {
+        let status = unsafe { attach(vfs_index, volume_number, &BOOT_SECTOR) };
+        if status != FsStatus::Success {
+            return Err(status);
+        }
     }
 
-    // Mount to VFS with the correct driver index
-    mount(
+    let mut flags = flags;
+    let vol = get_volume(volume_number).ok_or(FsStatus::NotFound)?;
+    if let Some(dev) = block::get_block_device(vol.disk_index) {
+        if dev.is_removable() {
+            flags |= mount_flags::MF_REMOVABLE;
+        }
+        if dev.is_readonly() {
+            flags |= mount_flags::MF_READONLY;
+        }
+    }
+
+    // Mount to VFS with the driver's index
+    if let Err(e) = mount(
         drive_letter,
         fs_type,
-        vfs_index,  // Use the FAT32 driver's VFS index
+        vfs_index,
         path_str,
         flags,
-    )?;
+    ) {
+        if let Some(detach) = unsafe { vfs::vfs_get_fs(vfs_index).and_then(|fs| fs.detach) } {
+            unsafe { detach(vfs_index, volume_number) };
+        }
+        return Err(e);
+    }
+    let _ = mount::set_volume_media(drive_letter, volume_number, media_fingerprint(vol));
 
     // Also register with io/vfs for file browser compatibility
     if fs_type == FsType::Fat32 {
@@ -148,6 +157,35 @@ pub fn mount_volume(
     Ok(())
 }
 
+/// FNV-1a hash of the volume's first sector and, if present, the ISO 9660
+/// primary volume descriptor
+///
+/// Read past the device's verify mark, since it is used to decide whether
+/// the mark can be cleared.
+fn media_fingerprint(vol: &Volume) -> u32 {
+    let mut hash = 0x811C_9DC5u32;
+    for sector in [0, ISO_PVD_SECTOR] {
+        if sector >= vol.total_sectors {
+            break;
+        }
+        unsafe {
+            let status = block::read_sectors_override(
+                vol.disk_index,
+                vol.start_lba + sector,
+                1,
+                &mut FINGERPRINT_SECTOR,
+            );
+            if status != BlockStatus::Success {
+                break;
+            }
+            for &byte in FINGERPRINT_SECTOR.iter() {
+                hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+            }
+        }
+    }
+    hash
+}
+
 /// Format device path for volume
 fn format_device_path(volume_number: u8) -> [u8; 32] {
     let mut path = [0u8; 32];
@@ -166,39 +204,186 @@ fn format_device_path(volume_number: u8) -> [u8; 32] {
     path
 }
 
-/// Mount a FAT32 volume to the FAT32 driver
-fn mount_fat32_volume(volume_number: u8) -> Result<(), FsStatus> {
-    let read_cb = get_volume_read_callback(volume_number)
-        .ok_or(FsStatus::IoError)?;
-    let write_cb = get_volume_write_callback(volume_number)
-        .ok_or(FsStatus::IoError)?;
-
-    // Get the FAT32 VFS driver index - this is what VFS will pass to lookup
-    let vfs_index = crate::fs::fat32::vfs_index()
-        .ok_or(FsStatus::NotSupported)?;
-
-    let status = unsafe {
-        crate::fs::fat32::mount_volume(
-            vfs_index,  // Use FAT32 VFS index, not volume_number
-            volume_number as *mut u8,
-            read_cb,
-            write_cb,
-        )
+/// Unmount a volume
+pub fn unmount_volume(drive_letter: char) -> Result<(), FsStatus> {
+    crate::fs::mount::unmount(drive_letter)
+}
+
+/// Dismount a volume and detach it from its driver
+///
+/// Removable volumes may be dismounted even as the system or boot volume.
+pub fn dismount_volume(drive_letter: char) -> Result<(), FsStatus> {
+    let mp = mount::get_mount_point(drive_letter).ok_or(FsStatus::NotMounted)?;
+    mount::dismount(drive_letter)?;
+
+    if let Some(detach) = unsafe { vfs::vfs_get_fs(mp.fs_index).and_then(|fs| fs.detach) } {
+        unsafe { detach(mp.fs_index, mp.volume_number) };
+    }
+    crate::io::vfs::unmount(drive_letter);
+    Ok(())
+}
+
+/// Verify the volume mounted at a drive letter
+///
+/// Fixed volumes always pass. For removable ones the device is checked
+/// for a media change:
+///
+/// - unchanged, or the same media reinserted: `Ok`
+/// - different media: the old volume is dismounted, the disk rescanned
+///   and its first recognized volume mounted at the same letter; returns
+///   `VerifyRequired` so the caller retries against the new volume
+/// - no media: the volume is dismounted and `NoMedia` returned
+pub fn verify_mount(drive_letter: char) -> Result<(), FsStatus> {
+    let mp = mount::get_mount_point(drive_letter).ok_or(FsStatus::NotMounted)?;
+    if !mp.is_removable() || mp.volume_number == 0 {
+        return Ok(());
+    }
+
+    let vol = match get_volume(mp.volume_number) {
+        Some(v) => *v,
+        None => {
+            let _ = dismount_volume(drive_letter);
+            return Err(FsStatus::NoMedia);
+        }
     };
 
-    if status == FsStatus::Success {
-        Ok(())
-    } else {
-        Err(status)
+    match block::check_media(vol.disk_index) {
+        BlockStatus::Success => return Ok(()),
+        BlockStatus::MediaChanged => {}
+        BlockStatus::NoMedia => {
+            let _ = dismount_volume(drive_letter);
+            disk::remove_disk_volumes(vol.disk_index);
+            block::clear_verify(vol.disk_index);
+            return Err(FsStatus::NoMedia);
+        }
+        _ => return Err(FsStatus::IoError),
+    }
+
+    // Tray opened and closed on the same disc
+    if vol.total_sectors != 0 && media_fingerprint(&vol) == mp.media_fingerprint {
+        block::clear_verify(vol.disk_index);
+        crate::serial_println!("[FS] {}:\\ verified, media unchanged", drive_letter);
+        return Ok(());
+    }
+
+    crate::serial_println!("[FS] {}:\\ media changed, remounting", drive_letter);
+    dismount_volume(drive_letter)?;
+    let flags = mp.flags & !(mount_flags::MF_SYSTEM | mount_flags::MF_BOOT);
+    mount_disk(vol.disk_index, drive_letter, flags)?;
+    Err(FsStatus::VerifyRequired)
+}
+
+/// Rescan a removable disk and mount its first recognized volume
+///
+/// Picks up media inserted into an empty drive as well as replacement
+/// media after a change.
+pub fn mount_disk(disk_index: u8, drive_letter: char, flags: u32) -> Result<(), FsStatus> {
+    let dev = block::get_block_device(disk_index).ok_or(FsStatus::NotFound)?;
+    if !dev.is_removable() {
+        return Err(FsStatus::NotSupported);
+    }
+
+    match block::check_media(disk_index) {
+        BlockStatus::Success | BlockStatus::MediaChanged => {}
+        BlockStatus::NoMedia => {
+            disk::remove_disk_volumes(disk_index);
+            block::clear_verify(disk_index);
+            return Err(FsStatus::NoMedia);
+        }
+        _ => return Err(FsStatus::IoError),
     }
+    block::clear_verify(disk_index);
+    disk::rescan_disk(disk_index);
+
+    let (volumes, count) = disk::disk_volumes(disk_index);
+    if !volumes[..count].iter().any(|&v| mount_volume(v, drive_letter, flags).is_ok()) {
+        return Err(FsStatus::NoMedia);
+    }
+    Ok(())
 }
 
-/// Unmount a volume
-pub fn unmount_volume(drive_letter: char) -> Result<(), FsStatus> {
-    crate::fs::mount::unmount(drive_letter)
+/// Dismount the volume at a drive letter and eject its media
+pub fn eject_drive(drive_letter: char) -> Result<(), FsStatus> {
+    let mp = mount::get_mount_point(drive_letter).ok_or(FsStatus::NotMounted)?;
+    if !mp.is_removable() {
+        return Err(FsStatus::NotSupported);
+    }
+    let vol = get_volume(mp.volume_number).copied().ok_or(FsStatus::NotFound)?;
+
+    dismount_volume(drive_letter)?;
+    eject_disk(vol.disk_index)
+}
+
+/// Eject the media of a removable disk, dismounting its volumes first
+pub fn eject_disk(disk_index: u8) -> Result<(), FsStatus> {
+    let dev = block::get_block_device(disk_index).ok_or(FsStatus::NotFound)?;
+    if !dev.is_removable() {
+        return Err(FsStatus::NotSupported);
+    }
+
+    for (letter, _) in mount::list_mounts().iter().flatten() {
+        let on_disk = mount::get_mount_point(*letter)
+            .and_then(|mp| get_volume(mp.volume_number))
+            .is_some_and(|v| v.disk_index == disk_index);
+        if on_disk {
+            dismount_volume(*letter)?;
+        }
+    }
+
+    let status = block::eject_media(disk_index);
+    disk::remove_disk_volumes(disk_index);
+    block::clear_verify(disk_index);
+    match status {
+        BlockStatus::Success => Ok(()),
+        BlockStatus::NotSupported => Err(FsStatus::NotSupported),
+        BlockStatus::NoMedia => Err(FsStatus::NoMedia),
+        _ => Err(FsStatus::IoError),
+    }
+}
+
+/// Check if the write cache policy turns disk caches off
+pub fn write_cache_disabled() -> bool {
+    unsafe { crate::cm::cm_read_dword(FILESYSTEM_KEY, "DisableWriteCache") }.unwrap_or(0) != 0
 }
 
-/// Auto-mount detected FAT32 volumes
+/// Turn the write cache of every writable disk on or off
+///
+/// Disks without a controllable cache are skipped.
+///
+/// # Returns
+/// The number of disks now in the requested state
+pub fn apply_write_cache_policy(disable: bool) -> u32 {
+    let mut applied = 0u32;
+    for index in 0..block::MAX_BLOCK_DEVICES as u8 {
+        let Some(dev) = block::get_block_device(index) else { continue };
+        if dev.is_readonly() {
+            continue;
+        }
+        if block::set_write_cache(index, !disable) == BlockStatus::Success {
+            applied += 1;
+        }
+    }
+    applied
+}
+
+/// Persist and apply the write cache policy
+pub fn set_write_cache_policy(disable: bool) -> Result<u32, FsStatus> {
+    let status = unsafe { crate::cm::cm_write_dword(FILESYSTEM_KEY, "DisableWriteCache", disable as u32) };
+    if status != crate::cm::CmStatus::Success {
+        return Err(FsStatus::IoError);
+    }
+    Ok(apply_write_cache_policy(disable))
+}
+
+/// Apply the persisted write cache policy at boot
+pub fn load_write_cache_policy() {
+    if write_cache_disabled() {
+        let count = apply_write_cache_policy(true);
+        crate::serial_println!("[FS] Write caching disabled on {} disk(s)", count);
+    }
+}
+
+/// Auto-mount detected volumes that a registered driver recognizes
 /// Assigns drive letters starting from C:
 pub fn auto_mount_volumes() -> u32 {
     let mut mounted = 0u32;
@@ -213,25 +398,27 @@ pub fn auto_mount_volumes() -> u32 {
                 continue;
             }
 
-            // Check if FAT32
-            if is_fat32_volume(vol_num) {
+            if let Some(name) = recognized_name(vol_num) {
                 let drive = next_letter as char;
 
                 // Determine mount flags
                 let mut flags = 0u32;
+                let removable = block::get_block_device(vol.disk_index)
+                    .is_some_and(|dev| dev.is_removable());
                 if vol.bootable {
                     flags |= mount_flags::MF_BOOT;
                 }
-                if mounted == 0 {
+                if mounted == 0 && !removable {
                     flags |= mount_flags::MF_SYSTEM;
                 }
 
                 match mount_volume(vol_num, drive, flags) {
                     Ok(()) => {
                         crate::serial_println!(
-                            "[FS] Mounted Volume {} as {}:\\ (FAT32, {} MB)",
+                            "[FS] Mounted Volume {} as {}:\\ ({}, {} MB)",
                             vol_num,
                             drive,
+                            name,
                             vol.size_mb()
                         );
                         mounted += 1;
@@ -251,7 +438,7 @@ pub fn auto_mount_volumes() -> u32 {
                 }
             } else {
                 crate::serial_println!(
-                    "[FS] Volume {}: {} (not FAT32, skipping)",
+                    "[FS] Volume {}: {} (no file system recognized, skipping)",
                     vol_num,
                     partition_type::name(vol.partition_type)
                 );
@@ -260,7 +447,7 @@ pub fn auto_mount_volumes() -> u32 {
     }
 
     if mounted == 0 {
-        crate::serial_println!("[FS] No FAT32 volumes found to mount");
+        crate::serial_println!("[FS] No volumes found to mount");
     } else {
         crate::serial_println!("[FS] Auto-mounted {} volume(s)", mounted);
     }
@@ -271,7 +458,7 @@ pub fn auto_mount_volumes() -> u32 {
 /// Get volume information for a drive letter
 pub fn get_drive_volume(drive_letter: char) -> Option<Volume> {
     let mp = crate::fs::mount::get_mount_point(drive_letter)?;
-    get_volume(mp.fs_index as u8).copied()
+    get_volume(mp.volume_number).copied()
 }
 
 /// List all mountable volumes
@@ -284,11 +471,8 @@ pub fn list_mountable_volumes() {
                 continue;
             }
 
-            let fs_type = if is_fat32_volume(vol_num) {
-                "FAT32"
-            } else {
-                partition_type::name(vol.partition_type)
-            };
+            let fs_type = recognized_name(vol_num)
+                .unwrap_or_else(|| partition_type::name(vol.partition_type));
 
             crate::serial_println!(
                 "  Volume {}: Disk {} Part {} - {} ({} MB) {}",
@@ -310,7 +494,7 @@ pub fn init() {
     // List detected volumes
     list_mountable_volumes();
 
-    // Auto-mount FAT32 volumes
+    // Auto-mount recognized volumes
     auto_mount_volumes();
 
     crate::serial_println!("[FS] Volume integration initialized");
@@ -0,0 +1,187 @@
+//! Directory Change Watches
+//!
+//! Kernel-side consumers (services, the shell) watch a directory for
+//! changes. The high-level file operations in `fs` report every name
+//! change here; each watch filters and buffers the changes through the
+//! FsRtl notification package and signals its event.
+//!
+//! Paths are matched case-insensitively, so reported names are upper-case.
+
+use super::path::MAX_PATH;
+use super::vfs::FsStatus;
+use crate::fsrtl::notify::{
+    fsrtl_get_next_notification, fsrtl_notify_change_directory, fsrtl_notify_cleanup,
+    fsrtl_notify_full_report_change, NotifyChange, NotifyEntry,
+};
+use crate::ke::{EventType, KEvent};
+use crate::ke::spinlock::SpinLock;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Maximum concurrent watches
+pub const MAX_WATCHES: usize = 8;
+
+/// Directory change watch
+struct Watch {
+    active: bool,
+    notify: NotifyChange,
+    /// Signaled when a change is buffered
+    event: KEvent,
+}
+
+impl Watch {
+    const fn new() -> Self {
+        Self {
+            active: false,
+            notify: NotifyChange::new(),
+            event: KEvent::new(),
+        }
+    }
+}
+
+static mut WATCHES: [Watch; MAX_WATCHES] = [const { Watch::new() }; MAX_WATCHES];
+
+/// Protects WATCHES
+static WATCH_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// Active watch count (lets unwatched operations skip the report)
+static ACTIVE_WATCHES: AtomicU32 = AtomicU32::new(0);
+
+/// Changes reported to at least one watch
+static CHANGES_REPORTED: AtomicU64 = AtomicU64::new(0);
+
+/// Copy `path` upper-cased into `buf`
+fn upcase<'a>(path: &str, buf: &'a mut [u8; MAX_PATH]) -> &'a str {
+    let len = path.len().min(MAX_PATH);
+    buf[..len].copy_from_slice(&path.as_bytes()[..len]);
+    buf[..len].make_ascii_uppercase();
+    core::str::from_utf8(&buf[..len]).unwrap_or("")
+}
+
+/// Start watching a directory
+///
+/// # Arguments
+/// * `path` - Directory to watch
+/// * `watch_subtree` - Also report changes in subdirectories
+/// * `completion_filter` - `FILE_NOTIFY_CHANGE_*` flags
+///
+/// # Returns
+/// The watch ID
+pub fn watch_directory(path: &str, watch_subtree: bool, completion_filter: u32) -> Result<usize, FsStatus> {
+    let mut canonical = [0u8; MAX_PATH];
+    let directory = super::canon::canonicalize_path(path, &mut canonical)?;
+    match super::stat(directory) {
+        Ok(info) if info.file_type == super::FileType::Directory => {}
+        Ok(_) => return Err(FsStatus::NotDirectory),
+        Err(e) => return Err(e),
+    }
+
+    let mut upper = [0u8; MAX_PATH];
+    let directory = upcase(directory, &mut upper);
+
+    let _guard = WATCH_LOCK.lock();
+    unsafe {
+        let watches = &mut *core::ptr::addr_of_mut!(WATCHES);
+        let (id, watch) = watches
+            .iter_mut()
+            .enumerate()
+            .find(|(_, w)| !w.active)
+            .ok_or(FsStatus::TooManyFiles)?;
+
+        fsrtl_notify_change_directory(
+            core::ptr::null_mut(),
+            &mut watch.notify,
+            directory,
+            watch_subtree,
+            completion_filter,
+            0,
+        );
+        watch.event.init(EventType::Synchronization, false);
+        watch.active = true;
+        ACTIVE_WATCHES.fetch_add(1, Ordering::AcqRel);
+        Ok(id)
+    }
+}
+
+/// Stop watching
+pub fn close_watch(id: usize) {
+    let _guard = WATCH_LOCK.lock();
+    unsafe {
+        let watches = &mut *core::ptr::addr_of_mut!(WATCHES);
+        if let Some(watch) = watches.get_mut(id).filter(|w| w.active) {
+            fsrtl_notify_cleanup(&mut watch.notify);
+            watch.active = false;
+            ACTIVE_WATCHES.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Take the next buffered change, if any
+pub fn next_change(id: usize) -> Option<NotifyEntry> {
+    let _guard = WATCH_LOCK.lock();
+    unsafe {
+        let watches = &mut *core::ptr::addr_of_mut!(WATCHES);
+        let watch = watches.get_mut(id).filter(|w| w.active)?;
+        fsrtl_get_next_notification(&mut watch.notify)
+    }
+}
+
+/// Wait until a change is buffered or the timeout expires
+///
+/// # Returns
+/// `true` if the watch was signaled
+pub fn wait_change(id: usize, timeout_ms: u64) -> bool {
+    let event = unsafe {
+        let watches = &mut *core::ptr::addr_of_mut!(WATCHES);
+        match watches.get_mut(id).filter(|w| w.active) {
+            Some(w) => &mut w.event as *mut KEvent,
+            None => return false,
+        }
+    };
+
+    let status = unsafe {
+        crate::ke::wait::ke_wait_for_single_object(
+            &mut (*event).header as *mut _,
+            Some(timeout_ms),
+        )
+    };
+    status == crate::ke::dispatcher::WaitStatus::Object0
+}
+
+/// Report a change to every matching watch
+///
+/// Called by the high-level file operations with the canonical path.
+pub fn report_change(path: &str, filter_match: u32, action: u32) {
+    if ACTIVE_WATCHES.load(Ordering::Acquire) == 0 {
+        return;
+    }
+
+    let mut upper = [0u8; MAX_PATH];
+    let path = upcase(path, &mut upper);
+
+    // Buffer under the lock, signal waiters after dropping it
+    let mut signal = [false; MAX_WATCHES];
+    {
+        let _guard = WATCH_LOCK.lock();
+        unsafe {
+            let watches = &mut *core::ptr::addr_of_mut!(WATCHES);
+            for (i, watch) in watches.iter_mut().enumerate().filter(|(_, w)| w.active) {
+                let before = watch.notify.notification_count;
+                fsrtl_notify_full_report_change(&mut watch.notify, path, filter_match, action);
+                signal[i] = watch.notify.notification_count != before;
+            }
+        }
+    }
+
+    for (i, _) in signal.iter().enumerate().filter(|(_, s)| **s) {
+        CHANGES_REPORTED.fetch_add(1, Ordering::Relaxed);
+        unsafe { (*core::ptr::addr_of!(WATCHES[i])).event.set(); }
+    }
+}
+
+/// Number of active watches and changes delivered to them
+pub fn watch_stats() -> (u32, u64) {
+    (
+        ACTIVE_WATCHES.load(Ordering::Relaxed),
+        CHANGES_REPORTED.load(Ordering::Relaxed),
+    )
+}
@@ -0,0 +1,473 @@
+//! On-Access Scanner Demo Filter
+//!
+//! A sample mini-filter in the style of an anti-virus on-access scanner,
+//! showing how the filter manager callbacks fit together:
+//!
+//! - **Pre-create**: applies the exclusion rules, then denies opens whose
+//!   file name matches a blacklisted pattern or whose cached hash is
+//!   blacklisted
+//! - **Post-create**: hashes the file through the new handle on first open
+//!   (FNV-1a, 64-bit) and caches the result keyed by path and size; a
+//!   freshly computed hash on the blacklist cancels the open
+//!
+//! # Exclusion Rules
+//!
+//! Scanners must never recurse into the I/O they cause or stall the pager:
+//!
+//! - Paging I/O is passed through untouched (no scan, no blacklist check)
+//! - Opens requesting only attribute or synchronize access skip the scan
+//! - Directories and files larger than `MAX_SCAN_SIZE` are not hashed
+//! - The hash is read through the already-opened handle, so the scan
+//!   itself never re-enters the create path
+//!
+//! # Blacklist
+//!
+//! Value names under `BLACKLIST_KEY` are the blacklist entries: either a
+//! file name pattern (`EICAR.COM`, `*.VBS`; patterns containing `\` match
+//! the full path) or `#` followed by a 16-digit hex hash.
+
+use super::fltmgr::{
+    flt_register_filter, flt_start_filtering, flt_unregister_filter,
+    FltCallbackData, FltFilter, FltOperation, FltPostopCallbackStatus,
+    FltPreopCallbackStatus, FltRegistration,
+};
+use super::name::fsrtl_is_name_in_expression;
+use crate::ke::spinlock::SpinLock;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Filter name
+pub const AVSCAN_FILTER_NAME: &str = "AvScanDemo";
+
+/// Altitude inside the FSFilter Anti-Virus range
+pub const AVSCAN_ALTITUDE: u32 = 385100;
+
+/// Registry key holding the blacklist
+pub const BLACKLIST_KEY: &str = "MACHINE\\SYSTEM\\CurrentControlSet\\Services\\AvScan\\Blacklist";
+
+/// Hash cache entries
+pub const MAX_CACHE_ENTRIES: usize = 32;
+
+/// Blacklist entries
+pub const MAX_BLACKLIST_ENTRIES: usize = 32;
+
+/// Largest file the scanner will hash
+pub const MAX_SCAN_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Maximum path kept in a cache entry
+const CACHE_PATH_LEN: usize = 128;
+
+/// Maximum blacklist pattern length
+const PATTERN_LEN: usize = 64;
+
+/// Scan read chunk
+const SCAN_CHUNK: usize = 1024;
+
+/// NTSTATUS reported when an open is blocked
+const STATUS_ACCESS_DENIED: i32 = 0xC0000022u32 as i32;
+
+/// Access rights that touch file data (anything else is metadata only)
+const DATA_ACCESS_MASK: u32 = 0x0000_0001   // FILE_READ_DATA
+    | 0x0000_0002                           // FILE_WRITE_DATA
+    | 0x0000_0004                           // FILE_APPEND_DATA
+    | 0x0000_0020                           // FILE_EXECUTE
+    | 0x0001_0000                           // DELETE
+    | 0xF000_0000;                          // GENERIC_*
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// ============================================================================
+// State
+// ============================================================================
+
+/// Cached file hash
+#[derive(Clone, Copy)]
+pub struct AvScanCacheEntry {
+    pub valid: bool,
+    pub path: [u8; CACHE_PATH_LEN],
+    pub path_len: usize,
+    pub size: u64,
+    pub hash: u64,
+    /// Opens served from the cache
+    pub hits: u32,
+    /// Last use (LRU sequence)
+    last_use: u64,
+}
+
+impl AvScanCacheEntry {
+    pub const fn empty() -> Self {
+        Self {
+            valid: false,
+            path: [0; CACHE_PATH_LEN],
+            path_len: 0,
+            size: 0,
+            hash: 0,
+            hits: 0,
+            last_use: 0,
+        }
+    }
+
+    pub fn path_str(&self) -> &str {
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap_or("")
+    }
+}
+
+/// Blacklist entry
+#[derive(Clone, Copy)]
+enum BlacklistEntry {
+    Empty,
+    /// Upper-cased name pattern
+    Pattern([u8; PATTERN_LEN], usize),
+    /// File hash
+    Hash(u64),
+}
+
+struct AvScanState {
+    filter_id: Option<u32>,
+    cache: [AvScanCacheEntry; MAX_CACHE_ENTRIES],
+    blacklist: [BlacklistEntry; MAX_BLACKLIST_ENTRIES],
+    use_seq: u64,
+}
+
+static AVSCAN_STATE: SpinLock<AvScanState> = SpinLock::new(AvScanState {
+    filter_id: None,
+    cache: [AvScanCacheEntry::empty(); MAX_CACHE_ENTRIES],
+    blacklist: [BlacklistEntry::Empty; MAX_BLACKLIST_ENTRIES],
+    use_seq: 0,
+});
+
+static FILES_SCANNED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SCANNED: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static OPENS_BLOCKED: AtomicU64 = AtomicU64::new(0);
+static PAGING_SKIPPED: AtomicU64 = AtomicU64::new(0);
+static EXCLUDED: AtomicU64 = AtomicU64::new(0);
+static BLACKLIST_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Scanner statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AvScanStats {
+    pub running: bool,
+    pub files_scanned: u64,
+    pub bytes_scanned: u64,
+    pub cache_hits: u64,
+    pub opens_blocked: u64,
+    pub paging_skipped: u64,
+    pub excluded: u64,
+    pub blacklist_entries: u32,
+    pub cached_files: usize,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Last path component
+fn file_name(path: &str) -> &str {
+    match path.rfind('\\') {
+        Some(pos) => &path[pos + 1..],
+        None => path,
+    }
+}
+
+/// Parse a `#xxxxxxxxxxxxxxxx` hash entry
+fn parse_hash(entry: &str) -> Option<u64> {
+    let hex = entry.strip_prefix('#')?;
+    if hex.is_empty() || hex.len() > 16 {
+        return None;
+    }
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Exclusion rules shared by pre- and post-create
+fn is_excluded(data: &FltCallbackData) -> bool {
+    if data.paging_io {
+        PAGING_SKIPPED.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    // Zero is a legacy open with full access; otherwise require data access
+    let access = data.io_params.access_mask;
+    if access != 0 && access & DATA_ACCESS_MASK == 0 {
+        EXCLUDED.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    false
+}
+
+fn is_name_blacklisted(state: &AvScanState, path: &str) -> bool {
+    let name = file_name(path);
+    state.blacklist.iter().any(|entry| match entry {
+        BlacklistEntry::Pattern(pattern, len) => {
+            let pattern = core::str::from_utf8(&pattern[..*len]).unwrap_or("");
+            let target = if pattern.contains('\\') { path } else { name };
+            fsrtl_is_name_in_expression(pattern, target, true)
+        }
+        _ => false,
+    })
+}
+
+fn is_hash_blacklisted(state: &AvScanState, hash: u64) -> bool {
+    state.blacklist.iter().any(|entry| matches!(entry, BlacklistEntry::Hash(h) if *h == hash))
+}
+
+fn find_cached(state: &AvScanState, path: &str) -> Option<usize> {
+    state.cache.iter().position(|e| {
+        e.valid && e.path_str().eq_ignore_ascii_case(path)
+    })
+}
+
+/// Hash a file through an open handle, rewinding it afterwards
+fn hash_file(handle: u16, size: u64) -> Option<u64> {
+    use crate::fs::{self, SeekWhence};
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut chunk = [0u8; SCAN_CHUNK];
+    let mut total = 0u64;
+
+    let result = loop {
+        match fs::read(handle, &mut chunk) {
+            Ok(0) | Err(crate::fs::FsStatus::EndOfFile) => break Some(hash),
+            Ok(n) => {
+                for &b in &chunk[..n] {
+                    hash ^= b as u64;
+                    hash = hash.wrapping_mul(FNV_PRIME);
+                }
+                total += n as u64;
+                if total >= size {
+                    break Some(hash);
+                }
+            }
+            Err(_) => break None,
+        }
+    };
+
+    let _ = fs::seek(handle, 0, SeekWhence::Set);
+    BYTES_SCANNED.fetch_add(total, Ordering::Relaxed);
+    result
+}
+
+// ============================================================================
+// Callbacks
+// ============================================================================
+
+fn avscan_pre_create(
+    data: &mut FltCallbackData,
+    _filter: &FltFilter,
+    _context: usize,
+) -> FltPreopCallbackStatus {
+    if is_excluded(data) {
+        return FltPreopCallbackStatus::Success;
+    }
+
+    let path = data.file_path_str();
+    let state = AVSCAN_STATE.lock();
+    let blocked = is_name_blacklisted(&state, path)
+        || find_cached(&state, path)
+            .is_some_and(|i| is_hash_blacklisted(&state, state.cache[i].hash));
+    drop(state);
+
+    if blocked {
+        crate::serial_println!("[AVSCAN] Blocked open of {} (PID {})", path, data.requestor_pid);
+        OPENS_BLOCKED.fetch_add(1, Ordering::Relaxed);
+        data.io_status = STATUS_ACCESS_DENIED;
+        return FltPreopCallbackStatus::Complete;
+    }
+
+    // Ask for the post-create callback so the file can be hashed
+    FltPreopCallbackStatus::Synchronize
+}
+
+fn avscan_post_create(
+    data: &mut FltCallbackData,
+    _filter: &FltFilter,
+    _context: usize,
+    _status: FltPreopCallbackStatus,
+) -> FltPostopCallbackStatus {
+    if data.io_status < 0 || is_excluded(data) {
+        return FltPostopCallbackStatus::FinishedProcessing;
+    }
+
+    let handle = data.io_params.param1 as u16;
+    let info = match crate::fs::fstat(handle) {
+        Ok(info) => info,
+        Err(_) => return FltPostopCallbackStatus::FinishedProcessing,
+    };
+    if info.file_type != crate::fs::FileType::Regular || info.size > MAX_SCAN_SIZE {
+        EXCLUDED.fetch_add(1, Ordering::Relaxed);
+        return FltPostopCallbackStatus::FinishedProcessing;
+    }
+
+    let path = data.file_path_str();
+
+    // Served from the cache while the size is unchanged
+    {
+        let mut state = AVSCAN_STATE.lock();
+        if let Some(i) = find_cached(&state, path) {
+            if state.cache[i].size == info.size {
+                state.use_seq += 1;
+                let seq = state.use_seq;
+                state.cache[i].hits += 1;
+                state.cache[i].last_use = seq;
+                CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+                return FltPostopCallbackStatus::FinishedProcessing;
+            }
+            state.cache[i].valid = false;
+        }
+    }
+
+    // First open: hash without holding the lock
+    let hash = match hash_file(handle, info.size) {
+        Some(h) => h,
+        None => return FltPostopCallbackStatus::FinishedProcessing,
+    };
+    FILES_SCANNED.fetch_add(1, Ordering::Relaxed);
+
+    let mut state = AVSCAN_STATE.lock();
+    if path.len() <= CACHE_PATH_LEN {
+        let slot = state.cache.iter().position(|e| !e.valid).unwrap_or_else(|| {
+            let mut oldest = 0;
+            for (i, e) in state.cache.iter().enumerate() {
+                if e.last_use < state.cache[oldest].last_use {
+                    oldest = i;
+                }
+            }
+            oldest
+        });
+        state.use_seq += 1;
+        let mut entry = AvScanCacheEntry::empty();
+        entry.valid = true;
+        entry.path[..path.len()].copy_from_slice(path.as_bytes());
+        entry.path_len = path.len();
+        entry.size = info.size;
+        entry.hash = hash;
+        entry.last_use = state.use_seq;
+        state.cache[slot] = entry;
+    }
+    let blocked = is_hash_blacklisted(&state, hash);
+    drop(state);
+
+    if blocked {
+        crate::serial_println!(
+            "[AVSCAN] Blocked open of {} (hash {:016x}, PID {})",
+            path, hash, data.requestor_pid
+        );
+        OPENS_BLOCKED.fetch_add(1, Ordering::Relaxed);
+        data.io_status = STATUS_ACCESS_DENIED;
+    }
+
+    FltPostopCallbackStatus::FinishedProcessing
+}
+
+// ============================================================================
+// Control
+// ============================================================================
+
+/// Reload the blacklist from the registry
+///
+/// # Returns
+/// Number of entries loaded
+pub fn avscan_reload_blacklist() -> usize {
+    use crate::cm;
+
+    let mut blacklist = [BlacklistEntry::Empty; MAX_BLACKLIST_ENTRIES];
+    let mut count = 0;
+
+    if let Ok(key) = unsafe { cm::cm_open_key(BLACKLIST_KEY) } {
+        let mut index = 0;
+        while let Ok(value) = unsafe { cm::cm_enumerate_value(key, index) } {
+            index += 1;
+            if count >= MAX_BLACKLIST_ENTRIES {
+                break;
+            }
+            let name = value.name.as_str();
+            if let Some(hash) = parse_hash(name) {
+                blacklist[count] = BlacklistEntry::Hash(hash);
+                count += 1;
+            } else if !name.is_empty() && name.len() <= PATTERN_LEN {
+                let mut pattern = [0u8; PATTERN_LEN];
+                pattern[..name.len()].copy_from_slice(name.as_bytes());
+                pattern[..name.len()].make_ascii_uppercase();
+                blacklist[count] = BlacklistEntry::Pattern(pattern, name.len());
+                count += 1;
+            }
+        }
+    }
+
+    AVSCAN_STATE.lock().blacklist = blacklist;
+    BLACKLIST_COUNT.store(count as u32, Ordering::Relaxed);
+    count
+}
+
+/// Register the filter and start filtering
+///
+/// # Returns
+/// The filter ID, or `None` if registration failed
+pub fn avscan_start() -> Option<u32> {
+    if let Some(id) = AVSCAN_STATE.lock().filter_id {
+        return Some(id);
+    }
+
+    let entries = avscan_reload_blacklist();
+
+    let mut registration = FltRegistration::empty();
+    registration.set_name(AVSCAN_FILTER_NAME);
+    registration.altitude = AVSCAN_ALTITUDE;
+    registration.add_callback(
+        FltOperation::Create,
+        Some(avscan_pre_create),
+        Some(avscan_post_create),
+    );
+
+    let filter_id = flt_register_filter(&registration)?;
+    AVSCAN_STATE.lock().filter_id = Some(filter_id);
+    flt_start_filtering(filter_id);
+
+    crate::serial_println!("[AVSCAN] Scanner started ({} blacklist entries)", entries);
+    Some(filter_id)
+}
+
+/// Unregister the filter
+pub fn avscan_stop() -> bool {
+    let filter_id = match AVSCAN_STATE.lock().filter_id.take() {
+        Some(id) => id,
+        None => return false,
+    };
+    flt_unregister_filter(filter_id)
+}
+
+/// Drop every cached hash
+pub fn avscan_flush_cache() {
+    let mut state = AVSCAN_STATE.lock();
+    for entry in state.cache.iter_mut() {
+        entry.valid = false;
+    }
+}
+
+/// Snapshot of the valid cache entries (most recently used first)
+pub fn avscan_cache_snapshot() -> ([AvScanCacheEntry; MAX_CACHE_ENTRIES], usize) {
+    let state = AVSCAN_STATE.lock();
+    let mut out = [AvScanCacheEntry::empty(); MAX_CACHE_ENTRIES];
+    let mut count = 0;
+    for entry in state.cache.iter().filter(|e| e.valid) {
+        out[count] = *entry;
+        count += 1;
+    }
+    out[..count].sort_unstable_by_key(|e| core::cmp::Reverse(e.last_use));
+    (out, count)
+}
+
+/// Get scanner statistics
+pub fn avscan_get_stats() -> AvScanStats {
+    let state = AVSCAN_STATE.lock();
+    AvScanStats {
+        running: state.filter_id.is_some(),
+        files_scanned: FILES_SCANNED.load(Ordering::Relaxed),
+        bytes_scanned: BYTES_SCANNED.load(Ordering::Relaxed),
+        cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+        opens_blocked: OPENS_BLOCKED.load(Ordering::Relaxed),
+        paging_skipped: PAGING_SKIPPED.load(Ordering::Relaxed),
+        excluded: EXCLUDED.load(Ordering::Relaxed),
+        blacklist_entries: BLACKLIST_COUNT.load(Ordering::Relaxed),
+        cached_files: state.cache.iter().filter(|e| e.valid).count(),
+    }
+}
@@ -689,6 +689,15 @@ impl FltMgrState {
 
 static FLTMGR_STATE: SpinLock<FltMgrState> = SpinLock::new(FltMgrState::new());
 
+/// Number of registered filters (lets the I/O path skip callback dispatch)
+static REGISTERED_FILTERS: AtomicU32 = AtomicU32::new(0);
+
+/// Check whether any mini-filter is registered
+#[inline]
+pub fn flt_filters_registered() -> bool {
+    REGISTERED_FILTERS.load(Ordering::Acquire) != 0
+}
+
 // ============================================================================
 // Filter Registration API
 // ============================================================================
@@ -713,6 +722,7 @@ pub fn flt_register_filter(registration: &FltRegistration) -> Option<u32> {
     }
 
     if found_slot.is_some() {
+        REGISTERED_FILTERS.fetch_add(1, Ordering::AcqRel);
         crate::serial_println!(
             "[FLTMGR] Registered filter '{}' (ID={}, altitude={})",
             registration.name_str(),
@@ -757,6 +767,7 @@ pub fn flt_unregister_filter(filter_id: u32) -> bool {
         }
 
         state.filters[idx] = FltFilter::empty();
+        REGISTERED_FILTERS.fetch_sub(1, Ordering::AcqRel);
 
         crate::serial_println!("[FLTMGR] Unregistered filter '{}'", name);
         true
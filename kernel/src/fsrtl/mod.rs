@@ -9,6 +9,8 @@
 //! - **Fast I/O**: Predicates for determining fast I/O eligibility
 //! - **Tunneling**: Short name preservation across delete/rename
 //! - **Notifications**: Directory change notifications
+//! - **Filter Manager**: Mini-filter registration and callback dispatch,
+//!   with a sample on-access scanner filter (`avscan`)
 //!
 //! This implementation is NT 5.2 (Windows Server 2003) compatible.
 
@@ -23,6 +25,7 @@ pub mod fastio;
 pub mod stackovf;
 pub mod unc;
 pub mod fltmgr;
+pub mod avscan;
 
 // Re-export key types
 pub use mcb::{
@@ -174,9 +177,16 @@ pub use fltmgr::{
     // Communication ports
     flt_create_communication_port, flt_close_communication_port, flt_send_message,
     // Callback invocation
-    flt_invoke_pre_callbacks, flt_invoke_post_callbacks,
+    flt_invoke_pre_callbacks, flt_invoke_post_callbacks, flt_filters_registered,
     // Statistics
     get_fltmgr_stats, get_filter_snapshots, list_filters,
     // Init
     init as init_fltmgr,
 };
+
+pub use avscan::{
+    AvScanCacheEntry, AvScanStats,
+    AVSCAN_FILTER_NAME, AVSCAN_ALTITUDE, BLACKLIST_KEY as AVSCAN_BLACKLIST_KEY,
+    avscan_start, avscan_stop, avscan_reload_blacklist, avscan_flush_cache,
+    avscan_cache_snapshot, avscan_get_stats,
+};
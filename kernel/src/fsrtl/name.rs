@@ -85,6 +85,9 @@ pub fn fsrtl_are_names_equal(name1: &str, name2: &str, ignore_case: bool) -> boo
 
 /// Check if a name matches an expression with wildcards
 ///
+/// Implemented by `rtl_is_name_in_expression`, which documents the DOS
+/// wildcard rules.
+///
 /// # Arguments
 /// * `expression` - Pattern with wildcards
 /// * `name` - Name to match against
@@ -93,105 +96,7 @@ pub fn fsrtl_are_names_equal(name1: &str, name2: &str, ignore_case: bool) -> boo
 /// # Returns
 /// true if name matches expression
 pub fn fsrtl_is_name_in_expression(expression: &str, name: &str, ignore_case: bool) -> bool {
-    if expression.is_empty() {
-        return name.is_empty();
-    }
-
-    if !fsrtl_does_name_contain_wild_cards(expression) {
-        return fsrtl_are_names_equal(expression, name, ignore_case);
-    }
-
-    // Work with bytes for file system names (ASCII-safe)
-    let expr = expression.as_bytes();
-    let name_bytes = name.as_bytes();
-
-    match_expression(expr, name_bytes, ignore_case)
-}
-
-/// Internal wildcard matching (operates on bytes for efficiency)
-fn match_expression(expr: &[u8], name: &[u8], ignore_case: bool) -> bool {
-    let mut ei = 0; // expression index
-    let mut ni = 0; // name index
-
-    // Stack for backtracking on * matches
-    let mut star_ei = None;
-    let mut star_ni = None;
-
-    while ni < name.len() {
-        if ei < expr.len() {
-            let ec = expr[ei];
-
-            match ec {
-                b'*' | b'<' => {
-                    // Star matches zero or more characters
-                    star_ei = Some(ei);
-                    star_ni = Some(ni);
-                    ei += 1;
-                    continue;
-                }
-                b'?' | b'>' => {
-                    // Question matches exactly one character
-                    // '>' matches one char but not a dot
-                    if ec == b'>' && name[ni] == b'.' {
-                        // Try backtracking
-                        if let (Some(sei), Some(sni)) = (star_ei, star_ni) {
-                            ei = sei + 1;
-                            star_ni = Some(sni + 1);
-                            ni = sni + 1;
-                            continue;
-                        }
-                        return false;
-                    }
-                    ei += 1;
-                    ni += 1;
-                    continue;
-                }
-                b'"' => {
-                    // DOS_DOT matches period or end of name
-                    if name[ni] == b'.' {
-                        ei += 1;
-                        ni += 1;
-                        continue;
-                    }
-                    ei += 1;
-                    continue;
-                }
-                c => {
-                    // Regular character - must match
-                    let matches = if ignore_case {
-                        c.to_ascii_uppercase() == name[ni].to_ascii_uppercase()
-                    } else {
-                        c == name[ni]
-                    };
-
-                    if matches {
-                        ei += 1;
-                        ni += 1;
-                        continue;
-                    }
-                }
-            }
-        }
-
-        // No match - try backtracking on previous *
-        if let (Some(sei), Some(sni)) = (star_ei, star_ni) {
-            ei = sei + 1;
-            star_ni = Some(sni + 1);
-            ni = sni + 1;
-        } else {
-            return false;
-        }
-    }
-
-    // Consume any trailing wildcards
-    while ei < expr.len() {
-        match expr[ei] {
-            b'*' | b'<' => ei += 1,
-            _ => return false,
-        }
-    }
-
-    true
+    crate::rtl::rtl_is_name_in_expression(expression, name, ignore_case)
 }
 
 /// Check if a name is legal for FAT file system
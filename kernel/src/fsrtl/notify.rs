@@ -245,7 +245,13 @@ pub fn fsrtl_notify_full_report_change(
     let target = full_target_name.as_bytes();
     let watched = &notify.full_directory_name[..notify.directory_name_length as usize];
 
-    if !target.starts_with(watched) {
+    if !target.starts_with(watched) || target.len() == watched.len() {
+        return;
+    }
+
+    // The match must end on a component boundary ("C:\\DIR" is not a
+    // parent of "C:\\DIRX")
+    if watched.last() != Some(&b'\\') && target[watched.len()] != b'\\' {
         return;
     }
 
@@ -8,6 +8,7 @@
 //! - RSDT/XSDT (Root/Extended System Description Table)
 //! - MADT/APIC (Multiple APIC Description Table)
 //! - FADT (Fixed ACPI Description Table)
+//! - Any other table by signature via `find_acpi_table`
 //!
 //! ## Usage
 //! ```ignore
@@ -385,6 +386,9 @@ static ACPI_INFO: Mutex<AcpiInfo> = Mutex::new(AcpiInfo {
 
 static ACPI_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+/// Root SDT location saved at init for later table lookups: (address, is_xsdt)
+static ROOT_SDT: Mutex<(u64, bool)> = Mutex::new((0, false));
+
 /// Validate ACPI table checksum
 fn validate_checksum(addr: u64, len: usize) -> bool {
     let mut sum: u8 = 0;
@@ -718,6 +722,8 @@ pub unsafe fn init(rsdp_addr: u64) {
         None => return,
     };
 
+    *ROOT_SDT.lock() = (sdt_addr, use_xsdt);
+
     let mut info = ACPI_INFO.lock();
     info.revision = if use_xsdt { 2 } else { 0 };
 
@@ -745,6 +751,23 @@ pub unsafe fn init(rsdp_addr: u64) {
     );
 }
 
+/// Build a table signature from its four ASCII characters
+pub const fn table_signature(sig: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*sig)
+}
+
+/// Find an ACPI table by signature
+///
+/// Returns the physical address of the table header. Tables beyond the
+/// MADT/FADT (DMAR, TPM2, ...) are located on demand by their drivers.
+pub fn find_acpi_table(signature: u32) -> Option<u64> {
+    let (sdt_addr, use_xsdt) = *ROOT_SDT.lock();
+    if sdt_addr == 0 {
+        return None;
+    }
+    unsafe { find_table(sdt_addr, use_xsdt, signature) }
+}
+
 /// Check if ACPI is initialized
 pub fn is_initialized() -> bool {
     ACPI_INITIALIZED.load(Ordering::SeqCst)
@@ -1166,10 +1189,7 @@ pub fn enable_acpi() -> bool {
                 crate::serial_println!("[ACPI] ACPI mode enabled");
                 return true;
             }
-            // Small delay
-            for _ in 0..1000 {
-                core::hint::spin_loop();
-            }
+            super::timer::ke_stall_execution_processor(1_000);
         }
 
         crate::serial_println!("[ACPI] Failed to enable ACPI mode");
@@ -1357,9 +1377,7 @@ pub unsafe fn reset() -> ! {
         }
 
         // Wait a bit
-        for _ in 0..1000000 {
-            core::hint::spin_loop();
-        }
+        super::timer::ke_stall_execution_processor(100_000);
     }
 
     drop(sleep_info);
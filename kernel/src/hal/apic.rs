@@ -399,6 +399,10 @@ impl LocalApic {
 
 /// Send an IPI to a specific processor (convenience function)
 pub fn send_ipi(dest_apic_id: u8, vector: u8) {
+    // One hypercall instead of trapping on the ICR write
+    if super::hv::hv_send_ipi(dest_apic_id, vector) {
+        return;
+    }
     get().send_fixed_ipi(dest_apic_id, vector);
 }
 
@@ -619,18 +623,13 @@ pub unsafe fn start_ap(apic_id: u8, startup_vector: u8) {
     apic.send_init_ipi(apic_id);
 
     // Wait 10ms (INIT-SIPI delay as per Intel spec)
-    // For now, use a simple delay loop
-    for _ in 0..10_000 {
-        core::hint::spin_loop();
-    }
+    super::timer::ke_stall_execution_processor(10_000);
 
     // Send first SIPI
     apic.send_startup_ipi(apic_id, startup_vector);
 
     // Wait 200μs
-    for _ in 0..1_000 {
-        core::hint::spin_loop();
-    }
+    super::timer::ke_stall_execution_processor(200);
 
     // Send second SIPI (some processors need this)
     apic.send_startup_ipi(apic_id, startup_vector);
@@ -685,9 +684,7 @@ pub unsafe fn start_all_aps() {
             ap_count += 1;
 
             // Wait a bit before starting next AP
-            for _ in 0..50_000 {
-                core::hint::spin_loop();
-            }
+            super::timer::ke_stall_execution_processor(1_000);
         }
     }
 
@@ -14,11 +14,18 @@
 //! - FLUSH CACHE (0xE7): Flush write cache
 //! - READ SECTORS EXT (0x24): 48-bit LBA read
 //! - WRITE SECTORS EXT (0x34): 48-bit LBA write
+//!
+//! # Storage Port
+//! The driver is a storage port miniport (`io::storport`): the class layer
+//! sends SCSI requests, which ATAPI devices take directly through PACKET
+//! (0xA0) and ATA disks get translated to the commands above.
 
 use crate::arch::io::{inb, inw, outb, outw};
-use crate::io::block::{
-    BlockDeviceType, BlockOps, BlockStatus, DiskGeometry,
-    register_block_device, block_flags, SECTOR_SIZE,
+use crate::io::block::{block_flags, smart_cmd, SECTOR_SIZE};
+use crate::io::storport::{
+    self, DataDirection, MiniportOps, SenseData, Srb, SrbStatus,
+    asc, peripheral, sat, scsiop, sense_key,
+    storport_add_target, storport_register_adapter,
 };
 use crate::ke::SpinLock;
 
@@ -62,8 +69,24 @@ pub mod ata_cmd {
     pub const FLUSH_CACHE: u8 = 0xE7;
     pub const FLUSH_CACHE_EXT: u8 = 0xEA;
     pub const SET_FEATURES: u8 = 0xEF;
+    pub const PACKET: u8 = 0xA0;
+}
+
+/// SET FEATURES subcommands
+mod ata_feature {
+    pub const ENABLE_WRITE_CACHE: u8 = 0x02;
+    pub const DISABLE_WRITE_CACHE: u8 = 0x82;
 }
 
+/// ATAPI block size (CD/DVD)
+pub const ATAPI_BLOCK_SIZE: usize = 2048;
+
+/// Sectors per PIO command (one SRB may span several)
+const MAX_PIO_SECTORS: u32 = 128;
+
+/// Largest transfer per SRB
+const MAX_TRANSFER_BYTES: u32 = 64 * 1024;
+
 /// ATA status register bits
 pub mod ata_status {
     pub const ERR: u8 = 0x01;   // Error
@@ -122,6 +145,12 @@ pub struct AtaDevice {
     pub channel: u8,      // 0 = primary, 1 = secondary
     pub drive: u8,        // 0 = master, 1 = slave
     pub lba48: bool,
+    /// Volatile write cache supported
+    pub write_cache: bool,
+    /// Volatile write cache currently enabled
+    pub write_cache_enabled: bool,
+    /// SMART feature set supported and enabled
+    pub smart: bool,
     pub total_sectors: u64,
     pub sector_size: u32,
     pub model: [u8; 48],
@@ -137,6 +166,9 @@ impl AtaDevice {
             channel: 0,
             drive: 0,
             lba48: false,
+            write_cache: false,
+            write_cache_enabled: false,
+            smart: false,
             total_sectors: 0,
             sector_size: SECTOR_SIZE as u32,
             model: [0; 48],
@@ -306,6 +338,13 @@ fn parse_identify_data(data: &[u16; 256], device: &mut AtaDevice) {
     let cmd_set2 = data[83];
     device.lba48 = (cmd_set2 & (1 << 10)) != 0;
 
+    // Words 82/85: Write cache supported / enabled
+    device.write_cache = (data[82] & (1 << 5)) != 0;
+    device.write_cache_enabled = device.write_cache && (data[85] & (1 << 5)) != 0;
+
+    // Words 82/85: SMART supported / enabled
+    device.smart = (data[82] & 1) != 0 && (data[85] & 1) != 0;
+
     // Words 60-61: Total sectors (LBA28)
     let sectors_28 = (data[61] as u64) << 16 | (data[60] as u64);
 
@@ -400,121 +439,152 @@ unsafe fn identify_drive(channel_idx: u8, drive: u8) -> Option<AtaDevice> {
     device.is_atapi = is_atapi;
     parse_identify_data(&data, &mut device);
 
+    if is_atapi {
+        // Packet devices report no capacity in IDENTIFY; the class layer
+        // sizes the media with READ CAPACITY
+        device.sector_size = SECTOR_SIZE as u32;
+        device.total_sectors = 0;
+    }
+
     Some(device)
 }
 
 // ============================================================================
-// Read/Write Operations
+// ATAPI Packet Commands
 // ============================================================================
 
-/// Read sectors using PIO
-unsafe fn ata_read(dev_index: u8, lba: u64, count: u32, buf: *mut u8) -> BlockStatus {
-    // Look up the block device to get controller and device_num
-    let block_dev = match crate::io::block::get_block_device(dev_index) {
-        Some(d) => d,
-        None => return BlockStatus::NotFound,
-    };
-
-    // Compute ATA device index: controller * 2 + device_num
-    let ata_index = (block_dev.controller as usize) * 2 + (block_dev.device_num as usize);
-
-    if ata_index >= MAX_ATA_DEVICES {
-        return BlockStatus::NotFound;
-    }
-
-    let device = &ATA_DEVICES[ata_index];
-    if !device.present {
-        return BlockStatus::NotFound;
-    }
-
+/// Send a packet command, reading up to `len` bytes of data into `buf`
+///
+/// Caller holds ATA_LOCK. Returns the number of bytes transferred, or
+/// `CheckCondition` if the device reported an error (fetch the sense data
+/// with REQUEST SENSE).
+unsafe fn atapi_packet(device: &AtaDevice, packet: &[u8; 12], buf: *mut u8, len: usize) -> Result<usize, SrbStatus> {
     let channel = &IDE_CHANNELS[device.channel as usize];
-    let _guard = ATA_LOCK.lock();
 
-    // Check bounds
-    if lba + count as u64 > device.total_sectors {
-        return BlockStatus::InvalidParameter;
+    select_drive(channel, device.drive);
+    if !wait_bsy(channel.base) {
+        return Err(SrbStatus::Timeout);
     }
 
-    // Select the drive first (just the drive select byte)
-    let drive_byte = 0xE0 | ((device.drive & 1) << 4);
-    outb(channel.base + 6, drive_byte);
+    // PIO transfer, byte count limit for each DRQ block
+    let limit = if len == 0 { ATAPI_BLOCK_SIZE } else { len.min(0xFFFE) };
+    outb(channel.base + 1, 0);
+    outb(channel.base + 4, (limit & 0xFF) as u8);
+    outb(channel.base + 5, (limit >> 8) as u8);
+    outb(channel.base + 7, ata_cmd::PACKET);
     ata_delay(channel.control);
 
-    // Wait for drive ready after selection
-    if !wait_ready(channel.base) {
-        return BlockStatus::NotReady;
+    if !wait_drq(channel.base) {
+        return Err(packet_failure(channel));
     }
-
-    // Select drive with full LBA
-    select_drive_lba(channel, device.drive, lba, device.lba48);
-
-    // Set sector count
-    if device.lba48 {
-        outb(channel.base + 2, ((count >> 8) & 0xFF) as u8);
+    for pair in packet.as_chunks::<2>().0 {
+        outw(channel.base, u16::from_le_bytes(*pair));
     }
-    outb(channel.base + 2, (count & 0xFF) as u8);
-
-    // Send read command
-    let cmd = if device.lba48 { ata_cmd::READ_SECTORS_EXT } else { ata_cmd::READ_SECTORS };
-    outb(channel.base + 7, cmd);
 
-    // Read sectors
-    let mut buf_ptr = buf;
-    for _ in 0..count {
-        // Wait for DRQ
-        if !wait_drq(channel.base) {
-            return BlockStatus::IoError;
+    let mut done = 0usize;
+    loop {
+        ata_delay(channel.control);
+        if !wait_bsy(channel.base) {
+            return Err(SrbStatus::Timeout);
         }
-
-        // Check for errors
         let status = inb(channel.base + 7);
-        if (status & ata_status::ERR) != 0 {
-            return BlockStatus::IoError;
+        if (status & (ata_status::ERR | ata_status::DF)) != 0 {
+            return Err(SrbStatus::CheckCondition);
+        }
+        if (status & ata_status::DRQ) == 0 {
+            break;
         }
 
-        // Read sector data
-        for _ in 0..256 {
+        // Drain the DRQ block, keeping what fits in the buffer
+        let count = inb(channel.base + 4) as usize | (inb(channel.base + 5) as usize) << 8;
+        for i in 0..count.div_ceil(2) {
             let word = inw(channel.base);
-            *buf_ptr = (word & 0xFF) as u8;
-            *buf_ptr.add(1) = (word >> 8) as u8;
-            buf_ptr = buf_ptr.add(2);
+            let at = done + i * 2;
+            if at < len {
+                *buf.add(at) = word as u8;
+            }
+            if at + 1 < len {
+                *buf.add(at + 1) = (word >> 8) as u8;
+            }
         }
+        done += count;
     }
 
-    BlockStatus::Success
+    Ok(done.min(len))
 }
 
-/// Write sectors using PIO
-unsafe fn ata_write(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> BlockStatus {
-    // Look up the block device to get controller and device_num
-    let block_dev = match crate::io::block::get_block_device(dev_index) {
-        Some(d) => d,
-        None => return BlockStatus::NotFound,
-    };
-
-    // Compute ATA device index: controller * 2 + device_num
-    let ata_index = (block_dev.controller as usize) * 2 + (block_dev.device_num as usize);
-
-    if ata_index >= MAX_ATA_DEVICES {
-        return BlockStatus::NotFound;
+/// Status of a packet command that did not reach the data phase
+unsafe fn packet_failure(channel: &IdeChannel) -> SrbStatus {
+    if (inb(channel.base + 7) & (ata_status::ERR | ata_status::DF)) != 0 {
+        SrbStatus::CheckCondition
+    } else {
+        SrbStatus::Timeout
     }
+}
 
-    let device = &ATA_DEVICES[ata_index];
-    if !device.present {
-        return BlockStatus::NotFound;
+/// Pass an SRB through to a packet device, with auto-sense on error
+unsafe fn atapi_start_io(device: &AtaDevice, srb: &mut Srb) -> SrbStatus {
+    if srb.direction == DataDirection::Out {
+        // No writable packet media here
+        return srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_COMMAND, 0);
     }
 
-    if device.is_atapi {
-        return BlockStatus::WriteProtected; // ATAPI is read-only for now
+    let mut packet = [0u8; 12];
+    let len = (srb.cdb_length as usize).min(12);
+    packet[..len].copy_from_slice(&srb.cdb[..len]);
+
+    match atapi_packet(device, &packet, srb.data, srb.data_length as usize) {
+        Ok(n) => {
+            srb.transferred = n as u32;
+            SrbStatus::Success
+        }
+        Err(SrbStatus::CheckCondition) => {
+            let channel = &IDE_CHANNELS[device.channel as usize];
+            // Sense key from the error register, refined by REQUEST SENSE
+            let key = inb(channel.base + 1) >> 4;
+            srb.sense = SenseData { key, asc: 0, ascq: 0 };
+
+            let request_sense = [scsiop::REQUEST_SENSE, 0, 0, 0, 18, 0, 0, 0, 0, 0, 0, 0];
+            let mut sense = [0u8; 18];
+            if let Ok(n) = atapi_packet(device, &request_sense, sense.as_mut_ptr(), sense.len()) {
+                if n >= 14 {
+                    srb.sense = SenseData::from_fixed(&sense);
+                }
+            }
+            SrbStatus::CheckCondition
+        }
+        Err(status) => status,
     }
+}
 
-    let channel = &IDE_CHANNELS[device.channel as usize];
-    let _guard = ATA_LOCK.lock();
+// ============================================================================
+// SCSI to ATA Translation
+// ============================================================================
 
-    // Check bounds
-    if lba + count as u64 > device.total_sectors {
-        return BlockStatus::InvalidParameter;
+/// Status of a failed ATA command, with the error register as sense data
+unsafe fn ata_failure(channel: &IdeChannel, srb: &mut Srb) -> SrbStatus {
+    let status = inb(channel.base + 7);
+    if (status & (ata_status::ERR | ata_status::DF)) == 0 {
+        return SrbStatus::Timeout;
+    }
+
+    let error = inb(channel.base + 1);
+    if (error & (ata_error::MC | ata_error::MCR)) != 0 {
+        srb.check_condition(sense_key::UNIT_ATTENTION, asc::MEDIUM_CHANGED, 0)
+    } else if (error & ata_error::UNC) != 0 {
+        srb.check_condition(sense_key::MEDIUM_ERROR, asc::UNRECOVERED_READ_ERROR, 0)
+    } else if (error & ata_error::IDNF) != 0 {
+        srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::LBA_OUT_OF_RANGE, 0)
+    } else if (error & ata_error::ABRT) != 0 {
+        srb.check_condition(sense_key::ABORTED_COMMAND, asc::NO_ADDITIONAL, 0)
+    } else {
+        srb.check_condition(sense_key::HARDWARE_ERROR, asc::INTERNAL_TARGET_FAILURE, 0)
     }
+}
+
+/// Read or write sectors using PIO (caller holds ATA_LOCK)
+unsafe fn pio_transfer(device: &AtaDevice, srb: &mut Srb, lba: u64, count: u32, buf: *mut u8, write: bool) -> SrbStatus {
+    let channel = &IDE_CHANNELS[device.channel as usize];
 
     // Select the drive first (just the drive select byte)
     let drive_byte = 0xE0 | ((device.drive & 1) << 4);
@@ -523,7 +593,7 @@ unsafe fn ata_write(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> Bloc
 
     // Wait for drive ready after selection
     if !wait_ready(channel.base) {
-        return BlockStatus::NotReady;
+        return srb.check_condition(sense_key::NOT_READY, asc::LUN_NOT_READY, 0);
     }
 
     // Select drive with full LBA
@@ -535,60 +605,52 @@ unsafe fn ata_write(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> Bloc
     }
     outb(channel.base + 2, (count & 0xFF) as u8);
 
-    // Send write command
-    let cmd = if device.lba48 { ata_cmd::WRITE_SECTORS_EXT } else { ata_cmd::WRITE_SECTORS };
+    // Send command
+    let cmd = match (write, device.lba48) {
+        (false, true) => ata_cmd::READ_SECTORS_EXT,
+        (false, false) => ata_cmd::READ_SECTORS,
+        (true, true) => ata_cmd::WRITE_SECTORS_EXT,
+        (true, false) => ata_cmd::WRITE_SECTORS,
+    };
     outb(channel.base + 7, cmd);
 
-    // Write sectors
     let mut buf_ptr = buf;
     for _ in 0..count {
         // Wait for DRQ
         if !wait_drq(channel.base) {
-            return BlockStatus::IoError;
+            return ata_failure(channel, srb);
         }
 
-        // Write sector data
+        // Transfer sector data
         for _ in 0..256 {
-            let word = (*buf_ptr as u16) | ((*buf_ptr.add(1) as u16) << 8);
-            outw(channel.base, word);
+            if write {
+                let word = (*buf_ptr as u16) | ((*buf_ptr.add(1) as u16) << 8);
+                outw(channel.base, word);
+            } else {
+                let word = inw(channel.base);
+                *buf_ptr = (word & 0xFF) as u8;
+                *buf_ptr.add(1) = (word >> 8) as u8;
+            }
             buf_ptr = buf_ptr.add(2);
         }
     }
 
-    // Wait for completion
-    if !wait_bsy(channel.base) {
-        return BlockStatus::IoError;
-    }
-
-    let status = inb(channel.base + 7);
-    if (status & ata_status::ERR) != 0 {
-        return BlockStatus::IoError;
+    if write {
+        // Wait for completion
+        if !wait_bsy(channel.base) {
+            return SrbStatus::Timeout;
+        }
+        if (inb(channel.base + 7) & ata_status::ERR) != 0 {
+            return ata_failure(channel, srb);
+        }
     }
 
-    BlockStatus::Success
+    SrbStatus::Success
 }
 
-/// Flush cache
-unsafe fn ata_flush(dev_index: u8) -> BlockStatus {
-    // Look up the block device to get controller and device_num
-    let block_dev = match crate::io::block::get_block_device(dev_index) {
-        Some(d) => d,
-        None => return BlockStatus::NotFound,
-    };
-
-    let ata_index = (block_dev.controller as usize) * 2 + (block_dev.device_num as usize);
-
-    if ata_index >= MAX_ATA_DEVICES {
-        return BlockStatus::NotFound;
-    }
-
-    let device = &ATA_DEVICES[ata_index];
-    if !device.present {
-        return BlockStatus::NotFound;
-    }
-
+/// Flush the write cache (caller holds ATA_LOCK)
+unsafe fn ata_flush(device: &AtaDevice, srb: &mut Srb) -> SrbStatus {
     let channel = &IDE_CHANNELS[device.channel as usize];
-    let _guard = ATA_LOCK.lock();
 
     // Select drive
     let drive_byte = 0xE0 | ((device.drive & 1) << 4);
@@ -601,123 +663,210 @@ unsafe fn ata_flush(dev_index: u8) -> BlockStatus {
 
     // Wait for completion
     if !wait_bsy(channel.base) {
-        return BlockStatus::Timeout;
+        return SrbStatus::Timeout;
     }
 
-    let status = inb(channel.base + 7);
-    if (status & ata_status::ERR) != 0 {
-        BlockStatus::IoError
+    if (inb(channel.base + 7) & ata_status::ERR) != 0 {
+        ata_failure(channel, srb)
     } else {
-        BlockStatus::Success
+        SrbStatus::Success
     }
 }
 
-/// Get device geometry
-unsafe fn ata_get_geometry(dev_index: u8) -> DiskGeometry {
-    // Look up the block device to get controller and device_num
-    let block_dev = match crate::io::block::get_block_device(dev_index) {
-        Some(d) => d,
-        None => return DiskGeometry::empty(),
-    };
-
-    let ata_index = (block_dev.controller as usize) * 2 + (block_dev.device_num as usize);
+/// Enable or disable the write cache (caller holds ATA_LOCK)
+unsafe fn ata_set_write_cache(device: &AtaDevice, srb: &mut Srb, enable: bool) -> SrbStatus {
+    let channel = &IDE_CHANNELS[device.channel as usize];
 
-    if ata_index >= MAX_ATA_DEVICES {
-        return DiskGeometry::empty();
+    select_drive(channel, device.drive);
+    if !wait_bsy(channel.base) {
+        return SrbStatus::Timeout;
     }
 
-    let device = &ATA_DEVICES[ata_index];
-    if !device.present {
-        return DiskGeometry::empty();
-    }
+    let feature = if enable { ata_feature::ENABLE_WRITE_CACHE } else { ata_feature::DISABLE_WRITE_CACHE };
+    outb(channel.base + 1, feature);
+    outb(channel.base + 7, ata_cmd::SET_FEATURES);
 
-    DiskGeometry {
-        total_sectors: device.total_sectors,
-        sector_size: device.sector_size,
-        cylinders: 0,
-        heads: 0,
-        sectors_per_track: 0,
+    if !wait_bsy(channel.base) {
+        return SrbStatus::Timeout;
+    }
+    if (inb(channel.base + 7) & ata_status::ERR) != 0 {
+        return ata_failure(channel, srb);
     }
-}
 
-/// Check if device is ready
-unsafe fn ata_is_ready(dev_index: u8) -> bool {
-    // Look up the block device to get controller and device_num
-    let block_dev = match crate::io::block::get_block_device(dev_index) {
-        Some(d) => d,
-        None => return false,
-    };
+    let index = device.channel as usize * 2 + device.drive as usize;
+    ATA_DEVICES[index].write_cache_enabled = enable;
+    SrbStatus::Success
+}
 
-    let ata_index = (block_dev.controller as usize) * 2 + (block_dev.device_num as usize);
+/// Read a SMART page with PIO (caller holds ATA_LOCK)
+unsafe fn ata_smart_read(device: &AtaDevice, srb: &mut Srb, feature: u8) -> SrbStatus {
+    let channel = &IDE_CHANNELS[device.channel as usize];
 
-    if ata_index >= MAX_ATA_DEVICES {
-        return false;
+    select_drive(channel, device.drive);
+    if !wait_ready(channel.base) {
+        return srb.check_condition(sense_key::NOT_READY, asc::LUN_NOT_READY, 0);
     }
 
-    let device = &ATA_DEVICES[ata_index];
-    if !device.present {
-        return false;
+    outb(channel.base + 1, feature);
+    outb(channel.base + 2, 1);
+    outb(channel.base + 3, 0);
+    outb(channel.base + 4, sat::SMART_LBA_MID);
+    outb(channel.base + 5, sat::SMART_LBA_HIGH);
+    outb(channel.base + 7, sat::ATA_SMART);
+
+    if !wait_drq(channel.base) {
+        return ata_failure(channel, srb);
     }
+    for i in 0..256 {
+        let word = inw(channel.base);
+        *srb.data.add(i * 2) = (word & 0xFF) as u8;
+        *srb.data.add(i * 2 + 1) = (word >> 8) as u8;
+    }
+    srb.transferred = SECTOR_SIZE as u32;
+    SrbStatus::Success
+}
 
-    let channel = &IDE_CHANNELS[device.channel as usize];
-    let status = inb(channel.base + 7);
-    (status & ata_status::RDY) != 0 && (status & ata_status::BSY) == 0
+/// Execute an ATA PASS-THROUGH(16) on an ATA disk
+///
+/// Only the SMART page reads are passed through.
+unsafe fn sat_pass_through(device: &AtaDevice, srb: &mut Srb) -> SrbStatus {
+    let cdb = srb.cdb;
+    let smart_read = (cdb[1] & 0x1E) == sat::PROTOCOL_PIO_IN
+        && cdb[14] == sat::ATA_SMART
+        && cdb[10] == sat::SMART_LBA_MID
+        && cdb[12] == sat::SMART_LBA_HIGH
+        && matches!(cdb[4], smart_cmd::READ_DATA | smart_cmd::READ_THRESHOLDS);
+    if !smart_read || !device.smart {
+        return srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_CDB_FIELD, 0);
+    }
+    if srb.direction != DataDirection::In || srb.data.is_null() || (srb.data_length as usize) < SECTOR_SIZE {
+        return SrbStatus::InvalidRequest;
+    }
+    ata_smart_read(device, srb, cdb[4])
 }
 
-/// Reset device
-unsafe fn ata_reset(dev_index: u8) -> BlockStatus {
-    // Look up the block device to get controller and device_num
-    let block_dev = match crate::io::block::get_block_device(dev_index) {
-        Some(d) => d,
-        None => return BlockStatus::NotFound,
-    };
+/// Execute an SRB on an ATA disk (SAT)
+unsafe fn sat_start_io(device: &AtaDevice, srb: &mut Srb) -> SrbStatus {
+    let channel = &IDE_CHANNELS[device.channel as usize];
+
+    match srb.opcode() {
+        scsiop::TEST_UNIT_READY => {
+            select_drive(channel, device.drive);
+            if wait_ready(channel.base) {
+                SrbStatus::Success
+            } else {
+                srb.check_condition(sense_key::NOT_READY, asc::LUN_NOT_READY, 0)
+            }
+        }
+        scsiop::INQUIRY => {
+            let model = core::str::from_utf8(&device.model).unwrap_or("").trim_end_matches('\0');
+            let firmware = core::str::from_utf8(&device.firmware).unwrap_or("").trim_end_matches('\0');
+            let data = storport::inquiry_data(peripheral::DIRECT_ACCESS, false, "ATA", model, firmware);
+            srb.complete_with(&data)
+        }
+        scsiop::READ_CAPACITY | scsiop::READ_CAPACITY16 => {
+            storport::complete_read_capacity(srb, device.total_sectors, SECTOR_SIZE as u32)
+        }
+        scsiop::READ10 | scsiop::READ16 | scsiop::WRITE10 | scsiop::WRITE16 => {
+            let (lba, count) = match srb.rw_range() {
+                Some(range) => range,
+                None => return SrbStatus::InvalidRequest,
+            };
+            if lba + count as u64 > device.total_sectors {
+                return srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::LBA_OUT_OF_RANGE, 0);
+            }
+            if count as usize * SECTOR_SIZE > srb.data_length as usize {
+                return SrbStatus::InvalidRequest;
+            }
 
-    let ata_index = (block_dev.controller as usize) * 2 + (block_dev.device_num as usize);
+            let write = matches!(srb.opcode(), scsiop::WRITE10 | scsiop::WRITE16);
+            let mut done = 0u32;
+            while done < count {
+                let sectors = (count - done).min(MAX_PIO_SECTORS);
+                let buf = srb.data.add(done as usize * SECTOR_SIZE);
+                let status = pio_transfer(device, srb, lba + done as u64, sectors, buf, write);
+                if status != SrbStatus::Success {
+                    return status;
+                }
+                done += sectors;
+            }
 
-    if ata_index >= MAX_ATA_DEVICES {
-        return BlockStatus::NotFound;
+            // PIO commands have no FUA form; push the data out of the cache
+            if srb.fua() && device.write_cache_enabled {
+                let status = ata_flush(device, srb);
+                if status != SrbStatus::Success {
+                    return status;
+                }
+            }
+            srb.transferred = count * SECTOR_SIZE as u32;
+            SrbStatus::Success
+        }
+        scsiop::SYNCHRONIZE_CACHE | scsiop::SYNCHRONIZE_CACHE16 => ata_flush(device, srb),
+        scsiop::ATA_PASS_THROUGH16 => sat_pass_through(device, srb),
+        scsiop::MODE_SENSE6 => storport::complete_caching_mode_sense(srb, device.write_cache_enabled),
+        scsiop::MODE_SELECT6 => match storport::caching_mode_select(srb) {
+            Some(enable) if enable == device.write_cache_enabled => SrbStatus::Success,
+            Some(enable) if device.write_cache => ata_set_write_cache(device, srb, enable),
+            _ => srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_CDB_FIELD, 0),
+        },
+        // Fixed disk: nothing to lock or eject
+        scsiop::START_STOP_UNIT | scsiop::MEDIUM_REMOVAL => SrbStatus::Success,
+        _ => srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_COMMAND, 0),
     }
+}
+
+// ============================================================================
+// Miniport
+// ============================================================================
 
-    let device = &ATA_DEVICES[ata_index];
-    if !device.present {
-        return BlockStatus::NotFound;
+/// Execute an SRB; the target is the ATA device index (channel * 2 + drive)
+unsafe fn ata_start_io(_context: usize, srb: &mut Srb) -> SrbStatus {
+    let device = match ATA_DEVICES.get(srb.target as usize) {
+        Some(d) if d.present => *d,
+        _ => return SrbStatus::NoDevice,
+    };
+
+    let _guard = ATA_LOCK.lock();
+
+    if device.is_atapi {
+        atapi_start_io(&device, srb)
+    } else {
+        sat_start_io(&device, srb)
     }
+}
 
-    let channel = &IDE_CHANNELS[device.channel as usize];
+/// Software reset of both channels
+unsafe fn ata_reset_bus(_context: usize) -> bool {
     let _guard = ATA_LOCK.lock();
+    let mut ok = true;
 
-    // Software reset
-    outb(channel.control, 0x04); // Set SRST
-    ata_delay(channel.control);
-    outb(channel.control, 0x00); // Clear SRST
-    ata_delay(channel.control);
+    for channel in IDE_CHANNELS.iter() {
+        outb(channel.control, 0x04 | 0x02); // Set SRST, keep nIEN
+        ata_delay(channel.control);
+        outb(channel.control, 0x02); // Clear SRST
+        ata_delay(channel.control);
 
-    // Wait for reset to complete
-    if !wait_bsy(channel.base) {
-        return BlockStatus::Timeout;
+        // Wait for reset to complete
+        ok &= wait_bsy(channel.base);
     }
 
-    BlockStatus::Success
+    ok
 }
 
 // ============================================================================
 // Initialization
 // ============================================================================
 
-/// Create ATA block operations
-fn ata_ops() -> BlockOps {
-    BlockOps {
-        read: Some(ata_read),
-        write: Some(ata_write),
-        flush: Some(ata_flush),
-        get_geometry: Some(ata_get_geometry),
-        is_ready: Some(ata_is_ready),
-        reset: Some(ata_reset),
+/// ATA miniport entry points
+fn ata_miniport() -> MiniportOps {
+    MiniportOps {
+        start_io: ata_start_io,
+        reset_bus: Some(ata_reset_bus),
     }
 }
 
-/// Detect and initialize ATA devices
-pub fn detect_devices() -> u32 {
+/// Detect ATA devices and hand them to the storage port class layer
+pub fn detect_devices(adapter: u8) -> u32 {
     let mut count = 0u32;
 
     crate::serial_println!("[ATA] Detecting IDE devices...");
@@ -732,52 +881,26 @@ pub fn detect_devices() -> u32 {
                     ATA_DEVICES[dev_idx as usize] = device;
                 }
 
-                // Determine device type and flags
-                let dev_type = if device.is_atapi {
-                    BlockDeviceType::Optical
-                } else {
-                    BlockDeviceType::HardDisk
-                };
-
-                let mut flags = 0u32;
-                if device.lba48 {
-                    flags |= block_flags::LBA48;
-                }
-                if device.is_atapi {
-                    flags |= block_flags::REMOVABLE | block_flags::READONLY;
-                }
+                let flags = if device.lba48 { block_flags::LBA48 } else { 0 };
 
-                // Create geometry
-                let geometry = DiskGeometry {
-                    total_sectors: device.total_sectors,
-                    sector_size: device.sector_size,
-                    cylinders: 0,
-                    heads: 0,
-                    sectors_per_track: 0,
-                };
-
-                // Register with block device layer
-                if let Some(block_idx) = register_block_device(
-                    dev_type,
-                    channel_idx,
-                    drive,
-                    geometry,
-                    ata_ops(),
-                    flags,
-                ) {
+                // Register with the class layer
+                if let Some(block_idx) = storport_add_target(adapter, dev_idx, flags) {
                     let model = core::str::from_utf8(&device.model).unwrap_or("Unknown");
                     let model = model.trim_end_matches('\0').trim();
+                    let size_mb = crate::io::block::get_block_device(block_idx)
+                        .map(|d| d.geometry.size_mb())
+                        .unwrap_or(0);
 
                     crate::serial_println!(
                         "[ATA] {}.{}: {} ({} MB, LBA48: {})",
                         if channel_idx == 0 { "Primary" } else { "Secondary" },
                         if drive == 0 { "Master" } else { "Slave" },
                         model,
-                        geometry.size_mb(),
+                        size_mb,
                         if device.lba48 { "yes" } else { "no" }
                     );
 
-                    // Update block device with model/serial
+                    // IDENTIFY has the full model string and the serial
                     if let Some(bdev) = crate::io::block::get_block_device_mut(block_idx) {
                         bdev.set_model(model);
                         let serial = core::str::from_utf8(&device.serial)
@@ -812,8 +935,16 @@ pub fn init() {
         outb(ide_ports::SECONDARY_CONTROL, 0x02);
     }
 
+    let adapter = match storport_register_adapter("ata", ata_miniport(), 0, MAX_TRANSFER_BYTES) {
+        Some(a) => a,
+        None => {
+            crate::serial_println!("[ATA] Storage port adapter table full");
+            return;
+        }
+    };
+
     // Detect devices
-    let count = detect_devices();
+    let count = detect_devices(adapter);
 
     crate::serial_println!("[ATA] ATA/IDE driver initialized ({} devices)", count);
 }
@@ -401,10 +401,7 @@ fn wait_for_update_complete() {
             break;
         }
         timeout -= 1;
-        // Small delay
-        for _ in 0..100 {
-            core::hint::spin_loop();
-        }
+        super::timer::ke_stall_execution_processor(1);
     }
 }
 
@@ -520,6 +520,133 @@ pub fn display_set_gop(framebuffer: u64, width: u32, height: u32, stride: u32) {
     }
 }
 
+// ============================================================================
+// Boot Display Table
+// ============================================================================
+
+/// GOP instances passed by the bootloader
+pub const MAX_BOOT_DISPLAYS: usize = 4;
+
+/// Modes recorded per GOP instance
+pub const MAX_BOOT_VIDEO_MODES: usize = 64;
+
+/// `BootDisplay::current_mode` when the active mode is not in the list
+pub const BOOT_MODE_UNKNOWN: u32 = u32::MAX;
+
+/// BootInfo display flag: the boot.cfg resolution was applied
+pub const BOOT_DISPLAY_PREFERRED_MODE: u32 = 0x1;
+
+/// EFI_GRAPHICS_PIXEL_FORMAT values
+pub mod gop_pixel_format {
+    pub const RGB: u32 = 0;
+    pub const BGR: u32 = 1;
+    pub const BITMASK: u32 = 2;
+    pub const BLT_ONLY: u32 = 3;
+}
+
+/// One GOP mode
+/// Must match the bootloader's BootVideoMode structure exactly!
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BootVideoMode {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels per scanline
+    pub pixels_per_scanline: u32,
+    /// EFI_GRAPHICS_PIXEL_FORMAT (see `gop_pixel_format`)
+    pub pixel_format: u32,
+}
+
+impl BootVideoMode {
+    /// Whether the mode has a linear framebuffer
+    pub fn is_linear(&self) -> bool {
+        self.pixel_format != gop_pixel_format::BLT_ONLY
+    }
+
+    pub fn pixel_format_name(&self) -> &'static str {
+        match self.pixel_format {
+            gop_pixel_format::RGB => "RGB",
+            gop_pixel_format::BGR => "BGR",
+            gop_pixel_format::BITMASK => "Bitmask",
+            gop_pixel_format::BLT_ONLY => "BltOnly",
+            _ => "?",
+        }
+    }
+}
+
+/// One GOP instance as enumerated by the bootloader
+/// Must match the bootloader's BootDisplay structure exactly!
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootDisplay {
+    /// Physical address of the framebuffer (0 for BLT-only modes)
+    pub framebuffer_addr: u64,
+    /// Framebuffer size in bytes
+    pub framebuffer_size: u64,
+    /// The active mode
+    pub active: BootVideoMode,
+    /// Index into `modes` of the active mode
+    pub current_mode: u32,
+    /// Valid entries in `modes`
+    pub mode_count: u32,
+    pub modes: [BootVideoMode; MAX_BOOT_VIDEO_MODES],
+}
+
+impl BootDisplay {
+    const fn empty() -> Self {
+        Self {
+            framebuffer_addr: 0,
+            framebuffer_size: 0,
+            active: BootVideoMode { width: 0, height: 0, pixels_per_scanline: 0, pixel_format: 0 },
+            current_mode: BOOT_MODE_UNKNOWN,
+            mode_count: 0,
+            modes: [BootVideoMode { width: 0, height: 0, pixels_per_scanline: 0, pixel_format: 0 };
+                MAX_BOOT_VIDEO_MODES],
+        }
+    }
+
+    /// Modes the firmware offered for this display
+    pub fn modes(&self) -> &[BootVideoMode] {
+        &self.modes[..(self.mode_count as usize).min(MAX_BOOT_VIDEO_MODES)]
+    }
+
+    /// Find a linear-framebuffer mode by resolution
+    pub fn find_mode(&self, width: u32, height: u32) -> Option<usize> {
+        self.modes().iter().position(|m| m.width == width && m.height == height && m.is_linear())
+    }
+}
+
+static mut BOOT_DISPLAYS: [BootDisplay; MAX_BOOT_DISPLAYS] = [BootDisplay::empty(); MAX_BOOT_DISPLAYS];
+static BOOT_DISPLAY_COUNT: AtomicU32 = AtomicU32::new(0);
+static BOOT_DISPLAY_FLAGS: AtomicU32 = AtomicU32::new(0);
+
+/// Copy the bootloader's display table into kernel memory
+///
+/// Called once at kernel entry, while the bootloader's data is still
+/// intact and identity mapped.
+pub fn display_capture_boot_displays(table_addr: u64, count: u32, flags: u32) {
+    let count = if table_addr == 0 { 0 } else { (count as usize).min(MAX_BOOT_DISPLAYS) };
+    unsafe {
+        let table = &mut *core::ptr::addr_of_mut!(BOOT_DISPLAYS);
+        for (i, slot) in table.iter_mut().take(count).enumerate() {
+            *slot = core::ptr::read_unaligned((table_addr as *const BootDisplay).add(i));
+        }
+    }
+    BOOT_DISPLAY_COUNT.store(count as u32, Ordering::Release);
+    BOOT_DISPLAY_FLAGS.store(flags, Ordering::Relaxed);
+}
+
+/// Displays enumerated by the bootloader; the first is the boot framebuffer
+pub fn display_boot_displays() -> &'static [BootDisplay] {
+    let count = BOOT_DISPLAY_COUNT.load(Ordering::Acquire) as usize;
+    unsafe { &(&*core::ptr::addr_of!(BOOT_DISPLAYS))[..count] }
+}
+
+/// BOOT_DISPLAY_* flags from the bootloader
+pub fn display_boot_flags() -> u32 {
+    BOOT_DISPLAY_FLAGS.load(Ordering::Relaxed)
+}
+
 // ============================================================================
 // Query Functions
 // ============================================================================
@@ -599,6 +599,11 @@ pub fn hal_put_dma_adapter(adapter: *mut DmaAdapter) {
 
 /// Allocate common buffer (contiguous physical memory for DMA)
 ///
+/// The buffer is always cached: bus-master DMA on x86 snoops the CPU
+/// caches, and the memory manager only hands out cached contiguous memory,
+/// so `cache_enabled` is accepted for HalAllocateCommonBuffer compatibility
+/// only.
+///
 /// Returns (virtual_address, physical_address) or None on failure
 pub fn hal_allocate_common_buffer(
     adapter: *mut DmaAdapter,
@@ -609,28 +614,40 @@ pub fn hal_allocate_common_buffer(
         return None;
     }
 
-    // In a real implementation, this would:
-    // 1. Allocate physically contiguous memory
-    // 2. Map it for DMA access
-    // 3. Return both addresses
+    let desc = unsafe { (*adapter).device_description };
 
-    // For now, simulate allocation
-    // Real implementation would use mm::allocate_contiguous_memory
+    // Devices that can't address 64 bits get memory below 4GB;
+    // ISA-style controllers also can't cross a 64KB boundary.
+    let highest = if desc.dma_width == DmaWidth::Width64Bits {
+        u64::MAX
+    } else {
+        0xFFFF_FFFF
+    };
+    let boundary = if desc.interface_type == InterfaceType::Isa && !desc.dma_64k_boundary {
+        0x10000
+    } else {
+        0
+    };
+    let base = unsafe {
+        crate::mm::mm_allocate_contiguous_memory_specify_cache(
+            length, 0, highest, boundary, crate::mm::MemoryCachingType::MmCached,
+        )?
+    };
 
     unsafe {
         (*adapter).common_buffers_allocated.fetch_add(1, Ordering::Relaxed);
         (*adapter).common_buffer_bytes.fetch_add(length as u64, Ordering::Relaxed);
     }
 
-    // Placeholder - would return actual addresses
-    None
+    // Low memory is identity mapped, so the virtual and logical addresses match
+    Some((base as usize, base))
 }
 
 /// Free common buffer
 pub fn hal_free_common_buffer(
     adapter: *mut DmaAdapter,
     length: usize,
-    _virtual_address: usize,
+    virtual_address: usize,
     _physical_address: u64,
     _cache_enabled: bool,
 ) {
@@ -639,6 +656,10 @@ pub fn hal_free_common_buffer(
     }
 
     unsafe {
+        if !crate::mm::mm_free_contiguous_memory(virtual_address as u64) {
+            return;
+        }
+
         if (*adapter).common_buffers_allocated.load(Ordering::Relaxed) > 0 {
             (*adapter).common_buffers_allocated.fetch_sub(1, Ordering::Relaxed);
         }
@@ -0,0 +1,314 @@
+//! UEFI Runtime Services
+//!
+//! Calls into the firmware's runtime services after boot. The loader maps
+//! every runtime region into a kernel-half window and calls
+//! SetVirtualAddressMap, then passes the virtual address of the runtime
+//! services table in the EFI_RUNTIME boot info tag.
+//!
+//! - **Variables**: GetVariable, SetVariable, GetNextVariableName
+//! - **Reset**: ResetSystem (cold, warm, shutdown)
+//! - **OS indications**: boot to firmware setup on the next restart
+//!
+//! Runtime services are not reentrant, so every call is made under
+//! `EFI_LOCK` with interrupts disabled.
+//!
+//! # NT Functions
+//!
+//! - `HalGetEnvironmentVariableEx` - `efi_get_variable`
+//! - `HalSetEnvironmentVariableEx` - `efi_set_variable`
+//! - `HalEnumerateEnvironmentVariablesEx` - `efi_next_variable_name`
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::ke::SpinLock;
+
+/// EFI_STATUS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EfiStatus(pub u64);
+
+impl EfiStatus {
+    const ERROR_BIT: u64 = 1 << 63;
+
+    pub const SUCCESS: Self = Self(0);
+    pub const INVALID_PARAMETER: Self = Self(Self::ERROR_BIT | 2);
+    pub const UNSUPPORTED: Self = Self(Self::ERROR_BIT | 3);
+    pub const BUFFER_TOO_SMALL: Self = Self(Self::ERROR_BIT | 5);
+    pub const DEVICE_ERROR: Self = Self(Self::ERROR_BIT | 7);
+    pub const WRITE_PROTECTED: Self = Self(Self::ERROR_BIT | 8);
+    pub const OUT_OF_RESOURCES: Self = Self(Self::ERROR_BIT | 9);
+    pub const NOT_FOUND: Self = Self(Self::ERROR_BIT | 14);
+    pub const ACCESS_DENIED: Self = Self(Self::ERROR_BIT | 15);
+    pub const SECURITY_VIOLATION: Self = Self(Self::ERROR_BIT | 26);
+
+    pub fn is_error(self) -> bool {
+        self.0 & Self::ERROR_BIT != 0
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SUCCESS => "EFI_SUCCESS",
+            Self::INVALID_PARAMETER => "EFI_INVALID_PARAMETER",
+            Self::UNSUPPORTED => "EFI_UNSUPPORTED",
+            Self::BUFFER_TOO_SMALL => "EFI_BUFFER_TOO_SMALL",
+            Self::DEVICE_ERROR => "EFI_DEVICE_ERROR",
+            Self::WRITE_PROTECTED => "EFI_WRITE_PROTECTED",
+            Self::OUT_OF_RESOURCES => "EFI_OUT_OF_RESOURCES",
+            Self::NOT_FOUND => "EFI_NOT_FOUND",
+            Self::ACCESS_DENIED => "EFI_ACCESS_DENIED",
+            Self::SECURITY_VIOLATION => "EFI_SECURITY_VIOLATION",
+            _ => "EFI error",
+        }
+    }
+}
+
+/// EFI_GUID
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EfiGuid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl core::fmt::Display for EfiGuid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let d = &self.data4;
+        write!(f, "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+            self.data1, self.data2, self.data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7])
+    }
+}
+
+/// EFI_GLOBAL_VARIABLE (BootOrder, Boot####, OsIndications, ...)
+pub const EFI_GLOBAL_VARIABLE: EfiGuid = EfiGuid {
+    data1: 0x8BE4DF61,
+    data2: 0x93CA,
+    data3: 0x11D2,
+    data4: [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C],
+};
+
+/// Variable attributes
+pub mod attr {
+    pub const NON_VOLATILE: u32 = 0x1;
+    pub const BOOTSERVICE_ACCESS: u32 = 0x2;
+    pub const RUNTIME_ACCESS: u32 = 0x4;
+}
+
+/// OsIndications bit: boot to the firmware user interface
+pub const EFI_OS_INDICATIONS_BOOT_TO_FW_UI: u64 = 0x1;
+
+/// EFI_RESET_TYPE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum EfiResetType {
+    Cold = 0,
+    Warm = 1,
+    Shutdown = 2,
+}
+
+/// Longest variable name handled, in UTF-16 units including the NUL
+pub const EFI_MAX_VARIABLE_NAME: usize = 128;
+
+type GetVariableFn = unsafe extern "efiapi" fn(
+    name: *const u16, guid: *const EfiGuid, attributes: *mut u32,
+    data_size: *mut usize, data: *mut u8,
+) -> u64;
+type GetNextVariableNameFn = unsafe extern "efiapi" fn(
+    name_size: *mut usize, name: *mut u16, guid: *mut EfiGuid,
+) -> u64;
+type SetVariableFn = unsafe extern "efiapi" fn(
+    name: *const u16, guid: *const EfiGuid, attributes: u32,
+    data_size: usize, data: *const u8,
+) -> u64;
+type ResetSystemFn = unsafe extern "efiapi" fn(
+    reset_type: u32, status: u64, data_size: usize, data: *const u8,
+) -> !;
+
+/// EFI_RUNTIME_SERVICES (fields past ResetSystem are not used)
+#[repr(C)]
+struct EfiRuntimeServices {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+    get_time: usize,
+    set_time: usize,
+    get_wakeup_time: usize,
+    set_wakeup_time: usize,
+    set_virtual_address_map: usize,
+    convert_pointer: usize,
+    get_variable: GetVariableFn,
+    get_next_variable_name: GetNextVariableNameFn,
+    set_variable: SetVariableFn,
+    get_next_high_monotonic_count: usize,
+    reset_system: ResetSystemFn,
+}
+
+/// "RUNTSERV"
+const EFI_RUNTIME_SERVICES_SIGNATURE: u64 = 0x56524553544E5552;
+
+/// Runtime services table (0 if unavailable)
+static RUNTIME_SERVICES: AtomicU64 = AtomicU64::new(0);
+
+/// Firmware revision from the runtime services header
+static RUNTIME_REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// Serializes runtime services calls
+static EFI_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// Take over runtime services from the boot info
+pub fn init(boot_info: &crate::BootInfo) {
+    if boot_info.efi_runtime_services == 0 {
+        if boot_info.efi_runtime_status != 0 {
+            crate::serial_println!("[EFI] Runtime services unavailable: SetVirtualAddressMap returned {}",
+                EfiStatus(boot_info.efi_runtime_status).name());
+        } else {
+            crate::serial_println!("[EFI] Runtime services not provided by the loader");
+        }
+        return;
+    }
+
+    let rt = boot_info.efi_runtime_services as *const EfiRuntimeServices;
+    let (signature, revision) = unsafe { ((*rt).signature, (*rt).revision) };
+    if signature != EFI_RUNTIME_SERVICES_SIGNATURE {
+        crate::serial_println!("[EFI] Bad runtime services signature {:#x}", signature);
+        return;
+    }
+
+    RUNTIME_REVISION.store(revision as u64, Ordering::Relaxed);
+    RUNTIME_SERVICES.store(rt as u64, Ordering::Release);
+    crate::serial_println!("[EFI] Runtime services at {:#x}, revision {}.{}",
+        rt as u64, revision >> 16, revision & 0xFFFF);
+}
+
+/// Whether runtime services can be called
+pub fn efi_runtime_available() -> bool {
+    RUNTIME_SERVICES.load(Ordering::Acquire) != 0
+}
+
+/// Runtime services revision (major << 16 | minor), 0 if unavailable
+pub fn efi_runtime_revision() -> u32 {
+    RUNTIME_REVISION.load(Ordering::Relaxed) as u32
+}
+
+/// Run `f` on the runtime services table, serialized with interrupts off
+fn with_runtime<R>(f: impl FnOnce(&EfiRuntimeServices) -> R) -> Result<R, EfiStatus> {
+    let rt = RUNTIME_SERVICES.load(Ordering::Acquire);
+    if rt == 0 {
+        return Err(EfiStatus::UNSUPPORTED);
+    }
+    Ok(crate::arch::without_interrupts(|| {
+        let _guard = EFI_LOCK.lock();
+        f(unsafe { &*(rt as *const EfiRuntimeServices) })
+    }))
+}
+
+fn check(status: u64) -> Result<(), EfiStatus> {
+    let status = EfiStatus(status);
+    if status.is_error() { Err(status) } else { Ok(()) }
+}
+
+/// Encode `name` as NUL-terminated UTF-16
+fn encode_name(name: &str, buf: &mut [u16; EFI_MAX_VARIABLE_NAME]) -> Result<(), EfiStatus> {
+    let mut len = 0;
+    for unit in name.encode_utf16() {
+        if len + 1 >= buf.len() {
+            return Err(EfiStatus::INVALID_PARAMETER);
+        }
+        buf[len] = unit;
+        len += 1;
+    }
+    buf[len] = 0;
+    Ok(())
+}
+
+/// Read a variable into `data`
+///
+/// Returns the attributes and the variable size. If `data` is too small
+/// the error is BUFFER_TOO_SMALL.
+pub fn efi_get_variable(name: &str, guid: &EfiGuid, data: &mut [u8]) -> Result<(u32, usize), EfiStatus> {
+    let mut name16 = [0u16; EFI_MAX_VARIABLE_NAME];
+    encode_name(name, &mut name16)?;
+
+    let mut attributes = 0u32;
+    let mut size = data.len();
+    let status = with_runtime(|rt| unsafe {
+        (rt.get_variable)(name16.as_ptr(), guid, &mut attributes, &mut size, data.as_mut_ptr())
+    })?;
+    check(status)?;
+    Ok((attributes, size))
+}
+
+/// Write a variable; empty `data` deletes it
+pub fn efi_set_variable(name: &str, guid: &EfiGuid, attributes: u32, data: &[u8]) -> Result<(), EfiStatus> {
+    let mut name16 = [0u16; EFI_MAX_VARIABLE_NAME];
+    encode_name(name, &mut name16)?;
+
+    let status = with_runtime(|rt| unsafe {
+        (rt.set_variable)(name16.as_ptr(), guid, attributes, data.len(), data.as_ptr())
+    })?;
+    check(status)
+}
+
+/// Advance a variable enumeration
+///
+/// Start with `name[0] == 0`; each call replaces `name` and `guid` with the
+/// next variable. Returns Ok(false) at the end of the list.
+pub fn efi_next_variable_name(name: &mut [u16; EFI_MAX_VARIABLE_NAME], guid: &mut EfiGuid) -> Result<bool, EfiStatus> {
+    let mut size = core::mem::size_of_val(name);
+    let status = with_runtime(|rt| unsafe {
+        (rt.get_next_variable_name)(&mut size, name.as_mut_ptr(), guid)
+    })?;
+    match check(status) {
+        Ok(()) => Ok(true),
+        Err(EfiStatus::NOT_FOUND) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read a UINT64 variable (OsIndications, OsIndicationsSupported)
+pub fn efi_get_variable_u64(name: &str, guid: &EfiGuid) -> Result<u64, EfiStatus> {
+    let mut data = [0u8; 8];
+    let (_, size) = efi_get_variable(name, guid, &mut data)?;
+    if size != data.len() {
+        return Err(EfiStatus::INVALID_PARAMETER);
+    }
+    Ok(u64::from_le_bytes(data))
+}
+
+/// Ask the firmware to enter its setup UI on the next restart
+pub fn efi_request_firmware_ui() -> Result<(), EfiStatus> {
+    let supported = efi_get_variable_u64("OsIndicationsSupported", &EFI_GLOBAL_VARIABLE)?;
+    if supported & EFI_OS_INDICATIONS_BOOT_TO_FW_UI == 0 {
+        return Err(EfiStatus::UNSUPPORTED);
+    }
+    let current = match efi_get_variable_u64("OsIndications", &EFI_GLOBAL_VARIABLE) {
+        Ok(value) => value,
+        Err(EfiStatus::NOT_FOUND) => 0,
+        Err(e) => return Err(e),
+    };
+    let value = current | EFI_OS_INDICATIONS_BOOT_TO_FW_UI;
+    efi_set_variable(
+        "OsIndications",
+        &EFI_GLOBAL_VARIABLE,
+        attr::NON_VOLATILE | attr::BOOTSERVICE_ACCESS | attr::RUNTIME_ACCESS,
+        &value.to_le_bytes(),
+    )
+}
+
+/// Reset or power off through the firmware
+///
+/// Returns only if runtime services are unavailable, so callers can fall
+/// back to ACPI or the keyboard controller.
+pub fn efi_reset_system(reset_type: EfiResetType) {
+    let rt = RUNTIME_SERVICES.load(Ordering::Acquire);
+    if rt == 0 {
+        return;
+    }
+    crate::serial_println!("[EFI] ResetSystem({:?})", reset_type);
+    crate::arch::disable_interrupts();
+    unsafe {
+        let rt = &*(rt as *const EfiRuntimeServices);
+        (rt.reset_system)(reset_type as u32, 0, 0, core::ptr::null());
+    }
+}
@@ -0,0 +1,587 @@
+//! Hypervisor Detection and Paravirtual Enlightenments
+//!
+//! When the CPUID hypervisor bit is set, leaf 0x40000000 names the
+//! hypervisor. Under KVM and Hyper-V the HAL switches to the paravirtual
+//! interfaces they expose, which avoid trapping on every clock read:
+//!
+//! - **KVM**: kvmclock (pvclock structure updated by the host) and the
+//!   `KVM_HC_SEND_IPI` hypercall, which sends an IPI to up to 128 CPUs
+//!   with one exit instead of one ICR write per target
+//! - **Hyper-V**: the partition reference TSC page (100ns clock computed
+//!   from RDTSC), with the reference counter MSR as a slower fallback.
+//!   The synthetic interrupt controller is detected and reported.
+//!
+//! On bare metal, or under any other hypervisor, nothing changes and the
+//! HAL keeps using the TSC and the local APIC directly.
+//!
+//! kvmclock is registered for the boot processor only. Other processors
+//! read the same structure, which is only valid when the host reports a
+//! stable TSC (`PVCLOCK_TSC_STABLE_BIT`); otherwise kvmclock is used just
+//! to learn the TSC frequency.
+
+use core::ptr;
+use core::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicU8, Ordering};
+use crate::arch::x86_64::percpu::{rdmsr, wrmsr};
+use crate::mm::PAGE_SIZE;
+use super::cpuid::{cpuid, cpuid_is_virtual};
+use super::timer::read_tsc;
+
+// ============================================================================
+// CPUID Leaves
+// ============================================================================
+
+/// Hypervisor vendor leaf (EBX:ECX:EDX = signature, EAX = max leaf)
+const CPUID_HV_VENDOR: u32 = 0x4000_0000;
+
+/// KVM features leaf / Hyper-V interface leaf
+const CPUID_HV_FEATURES: u32 = 0x4000_0001;
+
+/// Hyper-V partition privileges and features
+const CPUID_HYPERV_FEATURES: u32 = 0x4000_0003;
+
+// ============================================================================
+// KVM Interface
+// ============================================================================
+
+/// Legacy kvmclock system time MSR
+const MSR_KVM_SYSTEM_TIME: u32 = 0x12;
+
+/// kvmclock system time MSR (CLOCKSOURCE2)
+const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4b56_4d01;
+
+/// kvmclock via MSR 0x12
+const KVM_FEATURE_CLOCKSOURCE: u32 = 1 << 0;
+
+/// kvmclock via MSR 0x4b564d01
+const KVM_FEATURE_CLOCKSOURCE2: u32 = 1 << 3;
+
+/// KVM_HC_SEND_IPI available
+const KVM_FEATURE_PV_SEND_IPI: u32 = 1 << 11;
+
+/// Host guarantees a stable kvmclock
+const KVM_FEATURE_CLOCKSOURCE_STABLE_BIT: u32 = 1 << 24;
+
+/// pvclock flags: TSC is synchronized across vCPUs
+const PVCLOCK_TSC_STABLE_BIT: u8 = 1 << 0;
+
+/// Hypercall number for paravirtual IPIs
+const KVM_HC_SEND_IPI: u64 = 10;
+
+/// pvclock per-vCPU time information, written by the host
+#[repr(C)]
+struct PvclockVcpuTimeInfo {
+    version: u32,
+    pad0: u32,
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+    flags: u8,
+    pad: [u8; 2],
+}
+
+// ============================================================================
+// Hyper-V Interface
+// ============================================================================
+
+/// Guest OS identity (must be set before using enlightenments)
+const HV_X64_MSR_GUEST_OS_ID: u32 = 0x4000_0000;
+
+/// Partition reference counter (100ns units)
+const HV_X64_MSR_TIME_REF_COUNT: u32 = 0x4000_0020;
+
+/// Reference TSC page location
+const HV_X64_MSR_REFERENCE_TSC: u32 = 0x4000_0021;
+
+/// TSC frequency in Hz
+const HV_X64_MSR_TSC_FREQUENCY: u32 = 0x4000_0022;
+
+/// HV_X64_MSR_TIME_REF_COUNT available
+const HV_MSR_TIME_REF_COUNT_AVAILABLE: u32 = 1 << 1;
+
+/// Synthetic interrupt controller MSRs available
+const HV_MSR_SYNIC_AVAILABLE: u32 = 1 << 2;
+
+/// HV_X64_MSR_REFERENCE_TSC available
+const HV_MSR_REFERENCE_TSC_AVAILABLE: u32 = 1 << 9;
+
+/// HV_X64_MSR_TSC_FREQUENCY available
+const HV_ACCESS_FREQUENCY_MSRS: u32 = 1 << 11;
+
+/// Guest OS ID: open source (bit 63), OS type 0x7F, version 5.2
+const HV_GUEST_OS_ID: u64 = (1 << 63) | (0x7F << 56) | (5 << 40) | (2 << 32);
+
+/// Reference TSC page layout
+#[repr(C)]
+struct HvReferenceTscPage {
+    /// 0 means the page is invalid, fall back to the reference counter
+    tsc_sequence: u32,
+    reserved1: u32,
+    tsc_scale: u64,
+    tsc_offset: i64,
+}
+
+// ============================================================================
+// State
+// ============================================================================
+
+/// Hypervisor vendor
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HypervisorVendor {
+    /// Bare metal (or hypervisor bit clear)
+    None = 0,
+    Kvm = 1,
+    HyperV = 2,
+    VMware = 3,
+    Xen = 4,
+    VirtualBox = 5,
+    /// QEMU TCG (no hardware acceleration)
+    Tcg = 6,
+    /// Hypervisor bit set but signature unrecognized
+    Unknown = 7,
+}
+
+impl HypervisorVendor {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Kvm,
+            2 => Self::HyperV,
+            3 => Self::VMware,
+            4 => Self::Xen,
+            5 => Self::VirtualBox,
+            6 => Self::Tcg,
+            7 => Self::Unknown,
+            _ => Self::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Kvm => "KVM",
+            Self::HyperV => "Hyper-V",
+            Self::VMware => "VMware",
+            Self::Xen => "Xen",
+            Self::VirtualBox => "VirtualBox",
+            Self::Tcg => "QEMU TCG",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Paravirtual clock in use
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PvClockSource {
+    /// None; use the TSC directly
+    None = 0,
+    /// kvmclock, nanoseconds
+    KvmClock = 1,
+    /// Hyper-V reference TSC page, 100ns units
+    HvReferenceTsc = 2,
+    /// Hyper-V reference counter MSR, 100ns units
+    HvReferenceCounter = 3,
+}
+
+impl PvClockSource {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::KvmClock,
+            2 => Self::HvReferenceTsc,
+            3 => Self::HvReferenceCounter,
+            _ => Self::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::KvmClock => "kvmclock",
+            Self::HvReferenceTsc => "Hyper-V reference TSC page",
+            Self::HvReferenceCounter => "Hyper-V reference counter",
+        }
+    }
+
+    /// Counter frequency in Hz
+    pub fn frequency(self) -> u64 {
+        match self {
+            Self::None => 0,
+            Self::KvmClock => 1_000_000_000,
+            Self::HvReferenceTsc | Self::HvReferenceCounter => 10_000_000,
+        }
+    }
+}
+
+static VENDOR: AtomicU8 = AtomicU8::new(HypervisorVendor::None as u8);
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(PvClockSource::None as u8);
+static PV_IPI: AtomicBool = AtomicBool::new(false);
+static SYNIC_AVAILABLE: AtomicBool = AtomicBool::new(false);
+static USE_VMMCALL: AtomicBool = AtomicBool::new(false);
+
+/// Raw feature bits from CPUID 0x40000001 (KVM) / 0x40000003 (Hyper-V)
+static FEATURES: AtomicU64 = AtomicU64::new(0);
+
+/// TSC frequency reported by the hypervisor (0 = not reported)
+static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Physical (identity-mapped) address of the kvmclock / reference TSC page
+static CLOCK_PAGE: AtomicU64 = AtomicU64::new(0);
+
+static PV_IPIS_SENT: AtomicU64 = AtomicU64::new(0);
+static PV_IPI_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Identify the hypervisor from the CPUID vendor signature
+fn detect_vendor() -> (HypervisorVendor, u32) {
+    if !cpuid_is_virtual() {
+        return (HypervisorVendor::None, 0);
+    }
+
+    let (max_leaf, ebx, ecx, edx) = cpuid(CPUID_HV_VENDOR, 0);
+    let mut signature = [0u8; 12];
+    signature[0..4].copy_from_slice(&ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&ecx.to_le_bytes());
+    signature[8..12].copy_from_slice(&edx.to_le_bytes());
+
+    let vendor = match &signature {
+        b"KVMKVMKVM\0\0\0" => HypervisorVendor::Kvm,
+        b"Microsoft Hv" => HypervisorVendor::HyperV,
+        b"VMwareVMware" => HypervisorVendor::VMware,
+        b"XenVMMXenVMM" => HypervisorVendor::Xen,
+        b"VBoxVBoxVBox" => HypervisorVendor::VirtualBox,
+        b"TCGTCGTCGTCG" => HypervisorVendor::Tcg,
+        _ => HypervisorVendor::Unknown,
+    };
+    (vendor, max_leaf)
+}
+
+/// AMD processors use VMMCALL instead of VMCALL
+fn cpu_is_amd() -> bool {
+    let (_, ebx, ecx, edx) = cpuid(0, 0);
+    let mut vendor = [0u8; 12];
+    vendor[0..4].copy_from_slice(&ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&edx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&ecx.to_le_bytes());
+    &vendor == b"AuthenticAMD" || &vendor == b"HygonGenuine"
+}
+
+// ============================================================================
+// kvmclock
+// ============================================================================
+
+/// Read nanoseconds from a pvclock structure
+fn pvclock_read(info: *const PvclockVcpuTimeInfo) -> u64 {
+    unsafe {
+        loop {
+            let version = ptr::read_volatile(ptr::addr_of!((*info).version));
+            if version & 1 != 0 {
+                // Host is updating the structure
+                core::hint::spin_loop();
+                continue;
+            }
+            fence(Ordering::Acquire);
+
+            let tsc_timestamp = ptr::read_volatile(ptr::addr_of!((*info).tsc_timestamp));
+            let system_time = ptr::read_volatile(ptr::addr_of!((*info).system_time));
+            let mul = ptr::read_volatile(ptr::addr_of!((*info).tsc_to_system_mul));
+            let shift = ptr::read_volatile(ptr::addr_of!((*info).tsc_shift));
+
+            let mut delta = read_tsc().wrapping_sub(tsc_timestamp);
+            if shift >= 0 {
+                delta <<= shift;
+            } else {
+                delta >>= -(shift as i32);
+            }
+            let ns = system_time.wrapping_add(((delta as u128 * mul as u128) >> 32) as u64);
+
+            fence(Ordering::Acquire);
+            if ptr::read_volatile(ptr::addr_of!((*info).version)) == version {
+                return ns;
+            }
+        }
+    }
+}
+
+/// TSC frequency implied by the pvclock scale
+fn pvclock_tsc_frequency(info: *const PvclockVcpuTimeInfo) -> u64 {
+    let (mul, shift) = unsafe {
+        (
+            ptr::read_volatile(ptr::addr_of!((*info).tsc_to_system_mul)),
+            ptr::read_volatile(ptr::addr_of!((*info).tsc_shift)),
+        )
+    };
+    if mul == 0 {
+        return 0;
+    }
+    let freq = (1_000_000_000u64 << 32) / mul as u64;
+    if shift < 0 {
+        freq << -(shift as i32)
+    } else {
+        freq >> shift
+    }
+}
+
+unsafe fn init_kvm(max_leaf: u32) {
+    if max_leaf < CPUID_HV_FEATURES {
+        return;
+    }
+    let (features, _, _, _) = cpuid(CPUID_HV_FEATURES, 0);
+    FEATURES.store(features as u64, Ordering::Relaxed);
+
+    let msr = if features & KVM_FEATURE_CLOCKSOURCE2 != 0 {
+        MSR_KVM_SYSTEM_TIME_NEW
+    } else if features & KVM_FEATURE_CLOCKSOURCE != 0 {
+        MSR_KVM_SYSTEM_TIME
+    } else {
+        0
+    };
+
+    if msr != 0 {
+        if let Some(pfn) = crate::mm::mm_allocate_zeroed_page() {
+            let page = (pfn * PAGE_SIZE) as u64;
+            // Bit 0 enables the host updates
+            wrmsr(msr, page | 1);
+            CLOCK_PAGE.store(page, Ordering::Release);
+
+            let info = page as *const PvclockVcpuTimeInfo;
+            // Wait for the first update
+            let mut spins = 0;
+            while ptr::read_volatile(ptr::addr_of!((*info).tsc_to_system_mul)) == 0 && spins < 1_000_000 {
+                core::hint::spin_loop();
+                spins += 1;
+            }
+            TSC_FREQUENCY.store(pvclock_tsc_frequency(info), Ordering::Release);
+
+            let flags = ptr::read_volatile(ptr::addr_of!((*info).flags));
+            let stable = features & KVM_FEATURE_CLOCKSOURCE_STABLE_BIT != 0
+                && flags & PVCLOCK_TSC_STABLE_BIT != 0;
+            if stable || super::acpi::get_processor_count() <= 1 {
+                CLOCK_SOURCE.store(PvClockSource::KvmClock as u8, Ordering::Release);
+            }
+        }
+    }
+
+    if features & KVM_FEATURE_PV_SEND_IPI != 0 {
+        USE_VMMCALL.store(cpu_is_amd(), Ordering::Relaxed);
+        PV_IPI.store(true, Ordering::Release);
+    }
+}
+
+/// Issue a KVM hypercall with four arguments
+unsafe fn kvm_hypercall4(nr: u64, a0: u64, a1: u64, a2: u64, a3: u64) -> i64 {
+    let ret: u64;
+    if USE_VMMCALL.load(Ordering::Relaxed) {
+        core::arch::asm!(
+            "xchg rbx, {a0}",
+            "vmmcall",
+            "xchg rbx, {a0}",
+            a0 = inout(reg) a0 => _,
+            inout("rax") nr => ret,
+            in("rcx") a1,
+            in("rdx") a2,
+            in("rsi") a3,
+            options(nostack)
+        );
+    } else {
+        core::arch::asm!(
+            "xchg rbx, {a0}",
+            "vmcall",
+            "xchg rbx, {a0}",
+            a0 = inout(reg) a0 => _,
+            inout("rax") nr => ret,
+            in("rcx") a1,
+            in("rdx") a2,
+            in("rsi") a3,
+            options(nostack)
+        );
+    }
+    ret as i64
+}
+
+/// Send a fixed IPI through `KVM_HC_SEND_IPI`
+///
+/// # Returns
+/// `false` if PV IPIs are unavailable or the hypercall failed, in which
+/// case the caller should fall back to the local APIC.
+pub fn hv_send_ipi(dest_apic_id: u8, vector: u8) -> bool {
+    if !PV_IPI.load(Ordering::Acquire) {
+        return false;
+    }
+    // Bitmap relative to min APIC ID; ICR low: fixed delivery, physical
+    let ret = unsafe { kvm_hypercall4(KVM_HC_SEND_IPI, 1, 0, dest_apic_id as u64, vector as u64) };
+    if ret < 0 {
+        PV_IPI_FAILURES.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    PV_IPIS_SENT.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+// ============================================================================
+// Hyper-V reference time
+// ============================================================================
+
+/// Read 100ns units from the reference TSC page
+///
+/// # Returns
+/// `None` while the hypervisor has marked the page invalid
+fn hv_reference_tsc_read(page: *const HvReferenceTscPage) -> Option<u64> {
+    unsafe {
+        loop {
+            let sequence = ptr::read_volatile(ptr::addr_of!((*page).tsc_sequence));
+            if sequence == 0 {
+                return None;
+            }
+            fence(Ordering::Acquire);
+
+            let scale = ptr::read_volatile(ptr::addr_of!((*page).tsc_scale));
+            let offset = ptr::read_volatile(ptr::addr_of!((*page).tsc_offset));
+            let tsc = read_tsc();
+            let time = (((tsc as u128 * scale as u128) >> 64) as u64).wrapping_add(offset as u64);
+
+            fence(Ordering::Acquire);
+            if ptr::read_volatile(ptr::addr_of!((*page).tsc_sequence)) == sequence {
+                return Some(time);
+            }
+        }
+    }
+}
+
+unsafe fn init_hyperv(max_leaf: u32) {
+    if max_leaf < CPUID_HYPERV_FEATURES {
+        return;
+    }
+    let (features, _, _, _) = cpuid(CPUID_HYPERV_FEATURES, 0);
+    FEATURES.store(features as u64, Ordering::Relaxed);
+    SYNIC_AVAILABLE.store(features & HV_MSR_SYNIC_AVAILABLE != 0, Ordering::Relaxed);
+
+    wrmsr(HV_X64_MSR_GUEST_OS_ID, HV_GUEST_OS_ID);
+
+    if features & HV_ACCESS_FREQUENCY_MSRS != 0 {
+        TSC_FREQUENCY.store(rdmsr(HV_X64_MSR_TSC_FREQUENCY), Ordering::Release);
+    }
+
+    if features & HV_MSR_REFERENCE_TSC_AVAILABLE != 0 {
+        if let Some(pfn) = crate::mm::mm_allocate_zeroed_page() {
+            let page = (pfn * PAGE_SIZE) as u64;
+            // GPA page number in bits 63:12, bit 0 enables
+            wrmsr(HV_X64_MSR_REFERENCE_TSC, page | 1);
+            CLOCK_PAGE.store(page, Ordering::Release);
+            if hv_reference_tsc_read(page as *const HvReferenceTscPage).is_some() {
+                CLOCK_SOURCE.store(PvClockSource::HvReferenceTsc as u8, Ordering::Release);
+                return;
+            }
+        }
+    }
+
+    if features & HV_MSR_TIME_REF_COUNT_AVAILABLE != 0 {
+        CLOCK_SOURCE.store(PvClockSource::HvReferenceCounter as u8, Ordering::Release);
+    }
+}
+
+// ============================================================================
+// Clock API
+// ============================================================================
+
+/// Read the paravirtual clock
+///
+/// # Returns
+/// Counter value in units of `hv_clock_source().frequency()`, or `None`
+/// when no paravirtual clock is in use
+pub fn hv_read_clock() -> Option<u64> {
+    let page = CLOCK_PAGE.load(Ordering::Acquire);
+    match hv_clock_source() {
+        PvClockSource::None => None,
+        PvClockSource::KvmClock => Some(pvclock_read(page as *const PvclockVcpuTimeInfo)),
+        PvClockSource::HvReferenceTsc => {
+            hv_reference_tsc_read(page as *const HvReferenceTscPage)
+                .or_else(|| Some(unsafe { rdmsr(HV_X64_MSR_TIME_REF_COUNT) }))
+        }
+        PvClockSource::HvReferenceCounter => Some(unsafe { rdmsr(HV_X64_MSR_TIME_REF_COUNT) }),
+    }
+}
+
+/// Paravirtual clock in use
+pub fn hv_clock_source() -> PvClockSource {
+    PvClockSource::from_u8(CLOCK_SOURCE.load(Ordering::Acquire))
+}
+
+/// TSC frequency reported by the hypervisor, if any
+pub fn hv_tsc_frequency() -> Option<u64> {
+    match TSC_FREQUENCY.load(Ordering::Acquire) {
+        0 => None,
+        freq => Some(freq),
+    }
+}
+
+/// Detected hypervisor
+pub fn hv_vendor() -> HypervisorVendor {
+    HypervisorVendor::from_u8(VENDOR.load(Ordering::Acquire))
+}
+
+// ============================================================================
+// Statistics
+// ============================================================================
+
+/// Hypervisor enlightenment status
+#[derive(Debug, Clone, Copy)]
+pub struct HvStatus {
+    pub vendor: HypervisorVendor,
+    /// Raw feature bits of the vendor's feature leaf
+    pub features: u64,
+    pub clock_source: PvClockSource,
+    /// TSC frequency reported by the hypervisor (0 = not reported)
+    pub tsc_frequency: u64,
+    pub pv_ipi: bool,
+    pub pv_ipis_sent: u64,
+    pub pv_ipi_failures: u64,
+    pub synic_available: bool,
+}
+
+/// Get hypervisor enlightenment status
+pub fn hv_get_status() -> HvStatus {
+    HvStatus {
+        vendor: hv_vendor(),
+        features: FEATURES.load(Ordering::Relaxed),
+        clock_source: hv_clock_source(),
+        tsc_frequency: TSC_FREQUENCY.load(Ordering::Relaxed),
+        pv_ipi: PV_IPI.load(Ordering::Relaxed),
+        pv_ipis_sent: PV_IPIS_SENT.load(Ordering::Relaxed),
+        pv_ipi_failures: PV_IPI_FAILURES.load(Ordering::Relaxed),
+        synic_available: SYNIC_AVAILABLE.load(Ordering::Relaxed),
+    }
+}
+
+// ============================================================================
+// Initialization
+// ============================================================================
+
+/// Detect the hypervisor and enable its enlightenments
+///
+/// Must run on the boot processor after the memory manager (the clock
+/// pages are allocated from it) and before timer calibration.
+pub fn init() {
+    let (vendor, max_leaf) = detect_vendor();
+    VENDOR.store(vendor as u8, Ordering::Release);
+
+    unsafe {
+        match vendor {
+            HypervisorVendor::Kvm => init_kvm(max_leaf),
+            HypervisorVendor::HyperV => init_hyperv(max_leaf),
+            _ => {}
+        }
+    }
+
+    if vendor != HypervisorVendor::None {
+        crate::serial_println!(
+            "[HAL] Hypervisor: {}, clock: {}, PV IPI: {}",
+            vendor.name(),
+            hv_clock_source().name(),
+            PV_IPI.load(Ordering::Relaxed)
+        );
+    }
+}
@@ -597,6 +597,8 @@ pub fn hal_dispatch_interrupt(vector: u8) -> bool {
         return false;
     }
 
+    super::replay::replay_note_interrupt(vector);
+
     unsafe {
         // Walk the interrupt chain
         let chain = &VECTOR_CHAINS[chain_idx];
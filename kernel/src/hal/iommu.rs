@@ -0,0 +1,832 @@
+//! I/O Memory Management Unit (Intel VT-d)
+//!
+//! Discovers DMA remapping hardware from the ACPI DMAR table and programs
+//! it so device DMA goes through the IOMMU instead of reaching physical
+//! memory unchecked.
+//!
+//! # Modes
+//!
+//! - **Passthrough**: Devices see host physical addresses unchanged. Used
+//!   by default when the unit supports it (ECAP.PT), so existing drivers
+//!   keep working.
+//! - **Identity**: Without PT support, the shared default domain maps all
+//!   usable RAM 1:1 through second-level page tables.
+//! - **Isolated**: A device moved into its own domain can only reach the
+//!   buffers mapped for it with `iommu_map_dma`.
+//!
+//! # Structures
+//!
+//! - Root table: 256 entries, one per PCI bus
+//! - Context table: 256 entries per bus, one per device/function
+//! - Second-level tables: 4-level, 48-bit address width
+//!
+//! # Usage
+//!
+//! ```ignore
+//! let loc = PciLocation::new(0, 3, 0);
+//! iommu_isolate_device(loc)?;
+//! let iova = iommu_map_dma(loc, buffer_phys, len, true)?;
+//! // ... program device with iova ...
+//! iommu_unmap_dma(loc, iova, len);
+//! ```
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use crate::ke::spinlock::SpinLock;
+use crate::mm::PAGE_SIZE;
+use super::pci::PciLocation;
+
+// ============================================================================
+// DMAR Table
+// ============================================================================
+
+/// DMAR table signature
+const DMAR_SIGNATURE: u32 = super::acpi::table_signature(b"DMAR");
+
+/// DMAR remapping structure types
+const DMAR_TYPE_DRHD: u16 = 0;
+const DMAR_TYPE_RMRR: u16 = 1;
+
+/// DRHD flag: unit covers all devices not listed under another unit
+const DRHD_INCLUDE_PCI_ALL: u8 = 0x01;
+
+/// Device scope types
+const SCOPE_PCI_ENDPOINT: u8 = 1;
+const SCOPE_PCI_BRIDGE: u8 = 2;
+
+/// DMAR table header (follows the common ACPI header)
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct DmarHeader {
+    header: super::acpi::AcpiHeader,
+    host_address_width: u8,
+    flags: u8,
+    reserved: [u8; 10],
+}
+
+/// Remapping structure header
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct DmarEntryHeader {
+    entry_type: u16,
+    length: u16,
+}
+
+/// DMA Remapping Hardware unit Definition
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct DmarDrhd {
+    header: DmarEntryHeader,
+    flags: u8,
+    size: u8,
+    segment: u16,
+    register_base: u64,
+}
+
+/// Reserved Memory Region Reporting
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct DmarRmrr {
+    header: DmarEntryHeader,
+    reserved: u16,
+    segment: u16,
+    base: u64,
+    limit: u64,
+}
+
+/// Device scope entry header
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct DmarDeviceScope {
+    scope_type: u8,
+    length: u8,
+    reserved: u16,
+    enumeration_id: u8,
+    start_bus: u8,
+}
+
+// ============================================================================
+// Register Definitions
+// ============================================================================
+
+mod regs {
+    pub const VER: usize = 0x00;
+    pub const CAP: usize = 0x08;
+    pub const ECAP: usize = 0x10;
+    pub const GCMD: usize = 0x18;
+    pub const GSTS: usize = 0x1C;
+    pub const RTADDR: usize = 0x20;
+    pub const CCMD: usize = 0x28;
+    pub const FSTS: usize = 0x34;
+}
+
+/// Global command/status bits
+const GCMD_TE: u32 = 1 << 31;
+const GCMD_SRTP: u32 = 1 << 30;
+/// One-shot command bits that must not be written back from GSTS
+const GCMD_ONE_SHOT: u32 = GCMD_SRTP | (1 << 29) | (1 << 27) | (1 << 24);
+
+/// Context command: invalidate, global granularity
+const CCMD_ICC: u64 = 1 << 63;
+const CCMD_GLOBAL: u64 = 1 << 61;
+
+/// IOTLB invalidate: invalidate, global granularity, drain reads/writes
+const IOTLB_IVT: u64 = 1 << 63;
+const IOTLB_GLOBAL: u64 = 1 << 60;
+const IOTLB_DRAIN: u64 = (1 << 49) | (1 << 48);
+
+/// ECAP: pass-through translation supported
+const ECAP_PT: u64 = 1 << 6;
+
+/// CAP.SAGAW bit for 4-level (48-bit) second-level tables
+const CAP_SAGAW_48: u64 = 1 << 10;
+
+/// Context entry translation types
+const CONTEXT_TT_TRANSLATED: u64 = 0b00 << 2;
+const CONTEXT_TT_PASSTHROUGH: u64 = 0b10 << 2;
+/// Context entry address width: 48-bit, 4-level
+const CONTEXT_AW_48: u64 = 0b010;
+
+/// Second-level PTE bits
+const SL_READ: u64 = 1 << 0;
+const SL_WRITE: u64 = 1 << 1;
+const SL_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Register poll budget before a command is considered hung
+const POLL_LIMIT: u32 = 1_000_000;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Maximum remapping units tracked
+pub const MAX_IOMMU_UNITS: usize = 8;
+
+/// Maximum per-device domains
+pub const MAX_IOMMU_DOMAINS: usize = 32;
+
+/// Maximum reserved memory regions tracked
+pub const MAX_RMRR: usize = 16;
+
+/// Domain ID of the shared default domain
+pub const DEFAULT_DOMAIN_ID: u16 = 1;
+
+/// How the default domain handles DMA
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IommuMode {
+    /// IOMMU not present or not enabled
+    #[default]
+    Disabled = 0,
+    /// Context entries use pass-through translation
+    Passthrough = 1,
+    /// Default domain identity-maps RAM through page tables
+    Identity = 2,
+}
+
+/// A DMA remapping hardware unit
+#[derive(Debug, Clone, Copy)]
+pub struct IommuUnit {
+    /// Register base (physical == virtual in low memory)
+    pub register_base: u64,
+    /// PCI segment
+    pub segment: u16,
+    /// Covers every device not claimed by another unit
+    pub include_all: bool,
+    /// Architecture version (major << 4 | minor)
+    pub version: u32,
+    /// Capability register
+    pub cap: u64,
+    /// Extended capability register
+    pub ecap: u64,
+    /// Root table physical address
+    pub root_table: u64,
+    /// Translation enabled on this unit
+    pub enabled: bool,
+    /// Buses explicitly scoped to this unit (bitmap)
+    pub scoped_buses: [u64; 4],
+}
+
+impl IommuUnit {
+    const fn empty() -> Self {
+        Self {
+            register_base: 0,
+            segment: 0,
+            include_all: false,
+            version: 0,
+            cap: 0,
+            ecap: 0,
+            root_table: 0,
+            enabled: false,
+            scoped_buses: [0; 4],
+        }
+    }
+
+    /// Check if this unit translates DMA from a device
+    fn covers(&self, loc: PciLocation) -> bool {
+        let bus = loc.bus as usize;
+        self.include_all || (self.scoped_buses[bus / 64] & (1 << (bus % 64))) != 0
+    }
+
+    unsafe fn read32(&self, offset: usize) -> u32 {
+        ptr::read_volatile((self.register_base as usize + offset) as *const u32)
+    }
+
+    unsafe fn write32(&self, offset: usize, value: u32) {
+        ptr::write_volatile((self.register_base as usize + offset) as *mut u32, value)
+    }
+
+    unsafe fn read64(&self, offset: usize) -> u64 {
+        ptr::read_volatile((self.register_base as usize + offset) as *const u64)
+    }
+
+    unsafe fn write64(&self, offset: usize, value: u64) {
+        ptr::write_volatile((self.register_base as usize + offset) as *mut u64, value)
+    }
+
+    /// IOTLB register offset (ECAP.IRO is in 16-byte units)
+    fn iotlb_offset(&self) -> usize {
+        (((self.ecap >> 8) & 0x3FF) as usize) * 16 + 8
+    }
+
+    /// Issue a global command and wait for the status bit to follow
+    unsafe fn global_command(&self, bit: u32, set: bool) -> bool {
+        let status = self.read32(regs::GSTS) & !GCMD_ONE_SHOT;
+        let command = if set { status | bit } else { status & !bit };
+        self.write32(regs::GCMD, command);
+
+        for _ in 0..POLL_LIMIT {
+            if ((self.read32(regs::GSTS) & bit) != 0) == set {
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+
+    /// Globally invalidate the context cache and IOTLB
+    unsafe fn flush_all(&self) -> bool {
+        self.write64(regs::CCMD, CCMD_ICC | CCMD_GLOBAL);
+        let mut ok = false;
+        for _ in 0..POLL_LIMIT {
+            if self.read64(regs::CCMD) & CCMD_ICC == 0 {
+                ok = true;
+                break;
+            }
+            core::hint::spin_loop();
+        }
+
+        let iotlb = self.iotlb_offset();
+        self.write64(iotlb, IOTLB_IVT | IOTLB_GLOBAL | IOTLB_DRAIN);
+        for _ in 0..POLL_LIMIT {
+            if self.read64(iotlb) & IOTLB_IVT == 0 {
+                return ok;
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+}
+
+/// A translation domain
+#[derive(Debug, Clone, Copy)]
+pub struct IommuDomain {
+    /// Domain ID programmed into context entries
+    pub id: u16,
+    /// Device owning the domain (None for the default domain)
+    pub device: Option<PciLocation>,
+    /// Second-level PML4 physical address
+    pub page_table: u64,
+    /// Pages currently mapped
+    pub mapped_pages: u64,
+}
+
+/// Reserved memory region a device keeps accessing (USB legacy, GPU stolen memory)
+#[derive(Debug, Clone, Copy)]
+pub struct IommuRmrr {
+    pub base: u64,
+    pub limit: u64,
+    /// First endpoint the region is scoped to
+    pub device: Option<PciLocation>,
+}
+
+/// IOMMU statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IommuStats {
+    pub mode: IommuMode,
+    pub unit_count: usize,
+    pub domain_count: usize,
+    pub rmrr_count: usize,
+    pub map_calls: u64,
+    pub unmap_calls: u64,
+    pub faults: u32,
+}
+
+// ============================================================================
+// Global State
+// ============================================================================
+
+struct IommuState {
+    units: [IommuUnit; MAX_IOMMU_UNITS],
+    unit_count: usize,
+    domains: [Option<IommuDomain>; MAX_IOMMU_DOMAINS],
+    rmrr: [Option<IommuRmrr>; MAX_RMRR],
+    mode: IommuMode,
+    default_page_table: u64,
+}
+
+static IOMMU_STATE: SpinLock<IommuState> = SpinLock::new(IommuState {
+    units: [IommuUnit::empty(); MAX_IOMMU_UNITS],
+    unit_count: 0,
+    domains: [None; MAX_IOMMU_DOMAINS],
+    rmrr: [None; MAX_RMRR],
+    mode: IommuMode::Disabled,
+    default_page_table: 0,
+});
+
+static IOMMU_INITIALIZED: AtomicBool = AtomicBool::new(false);
+static MAP_CALLS: AtomicU64 = AtomicU64::new(0);
+static UNMAP_CALLS: AtomicU64 = AtomicU64::new(0);
+static FAULT_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// ============================================================================
+// Table Helpers
+// ============================================================================
+
+/// Allocate a zeroed page for IOMMU tables, returning its physical address
+unsafe fn alloc_table_page() -> Option<u64> {
+    let pfn = crate::mm::mm_allocate_zeroed_page()?;
+    Some((pfn * PAGE_SIZE) as u64)
+}
+
+/// Walk (and build) the second-level table down to the 4KB PTE for `iova`
+unsafe fn sl_pte(pml4: u64, iova: u64, create: bool) -> Option<*mut u64> {
+    let mut table = pml4;
+    for level in (1..4).rev() {
+        let index = ((iova >> (12 + 9 * level)) & 0x1FF) as usize;
+        let entry = (table as *mut u64).add(index);
+        let value = ptr::read_volatile(entry);
+        if value & (SL_READ | SL_WRITE) == 0 {
+            if !create {
+                return None;
+            }
+            let next = alloc_table_page()?;
+            ptr::write_volatile(entry, next | SL_READ | SL_WRITE);
+            crate::hal::cache::cache_flush_line(entry as u64);
+            table = next;
+        } else {
+            table = value & SL_ADDR_MASK;
+        }
+    }
+    Some((table as *mut u64).add(((iova >> 12) & 0x1FF) as usize))
+}
+
+/// Map a page range 1:1 in a second-level table
+unsafe fn sl_map_range(pml4: u64, base: u64, len: u64, write: bool) -> Option<u64> {
+    let start = base & !(PAGE_SIZE as u64 - 1);
+    let end = (base + len).div_ceil(PAGE_SIZE as u64) * PAGE_SIZE as u64;
+    let flags = SL_READ | if write { SL_WRITE } else { 0 };
+    let mut pages = 0;
+
+    let mut addr = start;
+    while addr < end {
+        let pte = sl_pte(pml4, addr, true)?;
+        ptr::write_volatile(pte, addr | flags);
+        crate::hal::cache::cache_flush_line(pte as u64);
+        addr += PAGE_SIZE as u64;
+        pages += 1;
+    }
+    Some(pages)
+}
+
+/// Unmap a page range from a second-level table
+unsafe fn sl_unmap_range(pml4: u64, base: u64, len: u64) -> u64 {
+    let start = base & !(PAGE_SIZE as u64 - 1);
+    let end = (base + len).div_ceil(PAGE_SIZE as u64) * PAGE_SIZE as u64;
+    let mut pages = 0;
+
+    let mut addr = start;
+    while addr < end {
+        if let Some(pte) = sl_pte(pml4, addr, false) {
+            if ptr::read_volatile(pte) != 0 {
+                ptr::write_volatile(pte, 0);
+                crate::hal::cache::cache_flush_line(pte as u64);
+                pages += 1;
+            }
+        }
+        addr += PAGE_SIZE as u64;
+    }
+    pages
+}
+
+/// Get (allocating if needed) the context entry for a device on a unit
+unsafe fn context_entry(unit: &IommuUnit, loc: PciLocation) -> Option<*mut u64> {
+    let root = (unit.root_table as *mut u64).add(loc.bus as usize * 2);
+    let mut context_table = ptr::read_volatile(root) & SL_ADDR_MASK;
+    if context_table == 0 {
+        context_table = alloc_table_page()?;
+        ptr::write_volatile(root, context_table | 1);
+        crate::hal::cache::cache_flush_line(root as u64);
+    }
+    let devfn = ((loc.device as usize) << 3) | loc.function as usize;
+    Some((context_table as *mut u64).add(devfn * 2))
+}
+
+/// Program a context entry
+unsafe fn write_context(entry: *mut u64, domain_id: u16, translation: u64, page_table: u64) {
+    // Clear present first so the hardware never sees a torn entry
+    ptr::write_volatile(entry, 0);
+    ptr::write_volatile(entry.add(1), CONTEXT_AW_48 | ((domain_id as u64) << 8));
+    ptr::write_volatile(entry, (page_table & SL_ADDR_MASK) | translation | 1);
+    crate::hal::cache::cache_flush_line(entry as u64);
+}
+
+// ============================================================================
+// DMAR Parsing
+// ============================================================================
+
+/// Record the buses named by a list of device scopes
+unsafe fn parse_scopes(start: u64, end: u64, mut f: impl FnMut(PciLocation, u8)) {
+    let mut addr = start;
+    while addr + core::mem::size_of::<DmarDeviceScope>() as u64 <= end {
+        let scope = ptr::read_unaligned(addr as *const DmarDeviceScope);
+        if scope.length < 6 {
+            break;
+        }
+        // Path entries are (device, function) pairs; the first one is enough
+        // for endpoints on the start bus.
+        if scope.length >= 8 {
+            let device = ptr::read_volatile((addr + 6) as *const u8);
+            let function = ptr::read_volatile((addr + 7) as *const u8);
+            f(PciLocation::new(scope.start_bus, device, function), scope.scope_type);
+        }
+        addr += scope.length as u64;
+    }
+}
+
+/// Parse the DMAR table into units and reserved regions
+unsafe fn parse_dmar(dmar_addr: u64, state: &mut IommuState) {
+    let dmar = ptr::read_unaligned(dmar_addr as *const DmarHeader);
+    let length = dmar.header.length as u64;
+    let mut addr = dmar_addr + core::mem::size_of::<DmarHeader>() as u64;
+    let end = dmar_addr + length;
+
+    crate::serial_println!("[IOMMU] DMAR: host address width {}", dmar.host_address_width as u32 + 1);
+
+    while addr + core::mem::size_of::<DmarEntryHeader>() as u64 <= end {
+        let header = ptr::read_unaligned(addr as *const DmarEntryHeader);
+        if header.length == 0 {
+            break;
+        }
+        let entry_end = addr + header.length as u64;
+
+        match header.entry_type {
+            DMAR_TYPE_DRHD if state.unit_count < MAX_IOMMU_UNITS => {
+                let drhd = ptr::read_unaligned(addr as *const DmarDrhd);
+                let mut unit = IommuUnit::empty();
+                unit.register_base = drhd.register_base;
+                unit.segment = drhd.segment;
+                unit.include_all = drhd.flags & DRHD_INCLUDE_PCI_ALL != 0;
+                parse_scopes(
+                    addr + core::mem::size_of::<DmarDrhd>() as u64,
+                    entry_end,
+                    |loc, scope_type| {
+                        if scope_type == SCOPE_PCI_ENDPOINT || scope_type == SCOPE_PCI_BRIDGE {
+                            let bus = loc.bus as usize;
+                            unit.scoped_buses[bus / 64] |= 1 << (bus % 64);
+                        }
+                    },
+                );
+                state.units[state.unit_count] = unit;
+                state.unit_count += 1;
+            }
+            DMAR_TYPE_RMRR => {
+                let rmrr = ptr::read_unaligned(addr as *const DmarRmrr);
+                let mut device = None;
+                parse_scopes(
+                    addr + core::mem::size_of::<DmarRmrr>() as u64,
+                    entry_end,
+                    |loc, _| {
+                        if device.is_none() {
+                            device = Some(loc);
+                        }
+                    },
+                );
+                if let Some(slot) = state.rmrr.iter_mut().find(|r| r.is_none()) {
+                    *slot = Some(IommuRmrr { base: rmrr.base, limit: rmrr.limit, device });
+                }
+            }
+            _ => {}
+        }
+
+        addr = entry_end;
+    }
+}
+
+// ============================================================================
+// Initialization
+// ============================================================================
+
+/// Bring up one remapping unit with every context pointing at the default domain
+unsafe fn init_unit(unit: &mut IommuUnit, mode: IommuMode, default_table: u64) -> bool {
+    unit.version = unit.read32(regs::VER);
+    unit.cap = unit.read64(regs::CAP);
+    unit.ecap = unit.read64(regs::ECAP);
+
+    if mode == IommuMode::Identity && unit.cap & CAP_SAGAW_48 == 0 {
+        crate::serial_println!("[IOMMU] Unit {:#x}: no 4-level table support", unit.register_base);
+        return false;
+    }
+
+    unit.root_table = match alloc_table_page() {
+        Some(page) => page,
+        None => return false,
+    };
+
+    // Populate context entries for every bus/device/function the unit owns
+    let translation = if mode == IommuMode::Passthrough {
+        CONTEXT_TT_PASSTHROUGH
+    } else {
+        CONTEXT_TT_TRANSLATED
+    };
+    for bus in 0..=255u8 {
+        let probe = PciLocation::new(bus, 0, 0);
+        if !unit.covers(probe) {
+            continue;
+        }
+        for devfn in 0..256usize {
+            let loc = PciLocation::new(bus, (devfn >> 3) as u8, (devfn & 7) as u8);
+            if let Some(entry) = context_entry(unit, loc) {
+                write_context(entry, DEFAULT_DOMAIN_ID, translation, default_table);
+            }
+        }
+    }
+
+    unit.write64(regs::RTADDR, unit.root_table);
+    if !unit.global_command(GCMD_SRTP, true) {
+        crate::serial_println!("[IOMMU] Unit {:#x}: root table pointer timeout", unit.register_base);
+        return false;
+    }
+    unit.flush_all();
+
+    if !unit.global_command(GCMD_TE, true) {
+        crate::serial_println!("[IOMMU] Unit {:#x}: translation enable timeout", unit.register_base);
+        return false;
+    }
+
+    unit.enabled = true;
+    true
+}
+
+/// Initialize the IOMMU from the ACPI DMAR table
+pub fn init() {
+    let dmar_addr = match super::acpi::find_acpi_table(DMAR_SIGNATURE) {
+        Some(addr) => addr,
+        None => {
+            crate::serial_println!("[IOMMU] No DMAR table, DMA remapping disabled");
+            return;
+        }
+    };
+
+    let mut state = IOMMU_STATE.lock();
+
+    unsafe {
+        parse_dmar(dmar_addr, &mut state);
+
+        if state.unit_count == 0 {
+            crate::serial_println!("[IOMMU] DMAR lists no remapping units");
+            return;
+        }
+
+        // Passthrough only if every unit supports it
+        let all_pt = (0..state.unit_count)
+            .all(|i| state.units[i].read64(regs::ECAP) & ECAP_PT != 0);
+        let mode = if all_pt { IommuMode::Passthrough } else { IommuMode::Identity };
+
+        // Identity mode needs a default table covering RAM
+        let mut default_table = 0;
+        if mode == IommuMode::Identity {
+            default_table = match alloc_table_page() {
+                Some(page) => page,
+                None => return,
+            };
+            let ram_top = crate::mm::pfn::mm_get_pfn_database_size() as u64 * PAGE_SIZE as u64;
+            sl_map_range(default_table, 0, ram_top, true);
+            for rmrr in state.rmrr.iter().flatten() {
+                sl_map_range(default_table, rmrr.base, rmrr.limit + 1 - rmrr.base, true);
+            }
+        }
+        state.default_page_table = default_table;
+
+        let mut enabled = 0;
+        for i in 0..state.unit_count {
+            let mut unit = state.units[i];
+            if init_unit(&mut unit, mode, default_table) {
+                enabled += 1;
+            }
+            state.units[i] = unit;
+        }
+
+        if enabled == 0 {
+            crate::serial_println!("[IOMMU] No remapping unit could be enabled");
+            return;
+        }
+
+        state.mode = mode;
+        state.domains[0] = Some(IommuDomain {
+            id: DEFAULT_DOMAIN_ID,
+            device: None,
+            page_table: default_table,
+            mapped_pages: 0,
+        });
+
+        crate::serial_println!(
+            "[IOMMU] {} of {} unit(s) enabled, mode {:?}, {} RMRR region(s)",
+            enabled,
+            state.unit_count,
+            mode,
+            state.rmrr.iter().flatten().count()
+        );
+    }
+
+    IOMMU_INITIALIZED.store(true, Ordering::Release);
+}
+
+// ============================================================================
+// Driver Interface
+// ============================================================================
+
+/// Find the domain index owned by a device
+fn find_device_domain(state: &IommuState, loc: PciLocation) -> Option<usize> {
+    state.domains.iter().position(|d| matches!(d, Some(d) if d.device == Some(loc)))
+}
+
+/// Move a device into its own translation domain
+///
+/// Afterwards the device can only reach memory mapped with `iommu_map_dma`.
+/// Devices with reserved memory regions keep their RMRR ranges mapped.
+pub fn iommu_isolate_device(loc: PciLocation) -> Result<u16, &'static str> {
+    if !IOMMU_INITIALIZED.load(Ordering::Acquire) {
+        return Err("IOMMU not enabled");
+    }
+
+    let mut state = IOMMU_STATE.lock();
+
+    if let Some(index) = find_device_domain(&state, loc) {
+        return Ok(state.domains[index].unwrap().id);
+    }
+
+    let unit_index = (0..state.unit_count)
+        .find(|&i| state.units[i].enabled && state.units[i].covers(loc))
+        .ok_or("device not behind an enabled unit")?;
+    let slot = state.domains.iter().position(|d| d.is_none()).ok_or("no free domains")?;
+
+    unsafe {
+        if state.units[unit_index].cap & CAP_SAGAW_48 == 0 {
+            return Err("unit lacks 4-level table support");
+        }
+
+        let page_table = alloc_table_page().ok_or("out of memory")?;
+        let mut mapped_pages = 0;
+        for rmrr in state.rmrr.iter().flatten() {
+            if rmrr.device == Some(loc) {
+                mapped_pages += sl_map_range(page_table, rmrr.base, rmrr.limit + 1 - rmrr.base, true)
+                    .ok_or("out of memory")?;
+            }
+        }
+
+        let id = DEFAULT_DOMAIN_ID + slot as u16;
+        let unit = state.units[unit_index];
+        let entry = context_entry(&unit, loc).ok_or("out of memory")?;
+        write_context(entry, id, CONTEXT_TT_TRANSLATED, page_table);
+        unit.flush_all();
+
+        state.domains[slot] = Some(IommuDomain {
+            id,
+            device: Some(loc),
+            page_table,
+            mapped_pages,
+        });
+
+        crate::serial_println!(
+            "[IOMMU] {:02x}:{:02x}.{} isolated in domain {}",
+            loc.bus, loc.device, loc.function, id
+        );
+        Ok(id)
+    }
+}
+
+/// Map a DMA buffer for a device
+///
+/// Returns the I/O virtual address the device should use. Devices still in
+/// the default domain get the physical address back unchanged.
+pub fn iommu_map_dma(loc: PciLocation, physical: u64, length: usize, write: bool) -> Option<u64> {
+    MAP_CALLS.fetch_add(1, Ordering::Relaxed);
+
+    if !IOMMU_INITIALIZED.load(Ordering::Acquire) {
+        return Some(physical);
+    }
+
+    let mut state = IOMMU_STATE.lock();
+    let index = match find_device_domain(&state, loc) {
+        Some(index) => index,
+        None => return Some(physical),
+    };
+
+    unsafe {
+        let mut domain = state.domains[index]?;
+        domain.mapped_pages += sl_map_range(domain.page_table, physical, length as u64, write)?;
+        state.domains[index] = Some(domain);
+
+        for i in 0..state.unit_count {
+            if state.units[i].enabled && state.units[i].covers(loc) {
+                state.units[i].flush_all();
+            }
+        }
+    }
+
+    // Mappings are 1:1 so drivers can keep using physical addresses
+    Some(physical)
+}
+
+/// Remove a DMA mapping created by `iommu_map_dma`
+pub fn iommu_unmap_dma(loc: PciLocation, iova: u64, length: usize) {
+    UNMAP_CALLS.fetch_add(1, Ordering::Relaxed);
+
+    if !IOMMU_INITIALIZED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let mut state = IOMMU_STATE.lock();
+    let index = match find_device_domain(&state, loc) {
+        Some(index) => index,
+        None => return,
+    };
+
+    unsafe {
+        if let Some(mut domain) = state.domains[index] {
+            let pages = sl_unmap_range(domain.page_table, iova, length as u64);
+            domain.mapped_pages = domain.mapped_pages.saturating_sub(pages);
+            state.domains[index] = Some(domain);
+        }
+
+        for i in 0..state.unit_count {
+            if state.units[i].enabled && state.units[i].covers(loc) {
+                state.units[i].flush_all();
+            }
+        }
+    }
+}
+
+/// Poll fault status registers, returning the number of units reporting faults
+pub fn iommu_poll_faults() -> u32 {
+    let state = IOMMU_STATE.lock();
+    let mut faulting = 0;
+
+    for unit in state.units[..state.unit_count].iter().filter(|u| u.enabled) {
+        let fsts = unsafe { unit.read32(regs::FSTS) };
+        if fsts & 0xFF != 0 {
+            faulting += 1;
+            crate::serial_println!("[IOMMU] Unit {:#x}: fault status {:#x}", unit.register_base, fsts);
+            // Status bits are write-1-to-clear
+            unsafe { unit.write32(regs::FSTS, fsts & 0x7F) };
+        }
+    }
+
+    FAULT_COUNT.fetch_add(faulting, Ordering::Relaxed);
+    faulting
+}
+
+// ============================================================================
+// Query Functions
+// ============================================================================
+
+/// Check if DMA remapping is active
+pub fn iommu_is_enabled() -> bool {
+    IOMMU_INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Get remapping units
+pub fn iommu_get_units() -> ([IommuUnit; MAX_IOMMU_UNITS], usize) {
+    let state = IOMMU_STATE.lock();
+    (state.units, state.unit_count)
+}
+
+/// Get domains
+pub fn iommu_get_domains() -> [Option<IommuDomain>; MAX_IOMMU_DOMAINS] {
+    IOMMU_STATE.lock().domains
+}
+
+/// Get IOMMU statistics
+pub fn iommu_get_stats() -> IommuStats {
+    let state = IOMMU_STATE.lock();
+    IommuStats {
+        mode: state.mode,
+        unit_count: state.unit_count,
+        domain_count: state.domains.iter().flatten().count(),
+        rmrr_count: state.rmrr.iter().flatten().count(),
+        map_calls: MAP_CALLS.load(Ordering::Relaxed),
+        unmap_calls: UNMAP_CALLS.load(Ordering::Relaxed),
+        faults: FAULT_COUNT.load(Ordering::Relaxed),
+    }
+}
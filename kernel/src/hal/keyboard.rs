@@ -4,8 +4,8 @@
 //! Uses IRQ1 (vector 33) for interrupt-driven input.
 
 use crate::arch::io::{inb, outb};
-use crate::ke::SpinLock;
-use core::sync::atomic::{AtomicBool, Ordering};
+use crate::ke::{EventType, KEvent, SpinLock};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// PS/2 controller ports
 pub mod ps2_ports {
@@ -76,6 +76,15 @@ static KEYBOARD_BUFFER: SpinLock<KeyboardBuffer> = SpinLock::new(KeyboardBuffer:
 /// Scancode buffer (for raw scancodes including key up/down)
 static SCANCODE_BUFFER: SpinLock<KeyboardBuffer> = SpinLock::new(KeyboardBuffer::new());
 
+/// Signaled when characters are added to the keyboard buffer
+static mut INPUT_EVENT: KEvent = KEvent::new();
+
+/// Thread holding modal input (0 = none); other readers see no input
+static MODAL_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+/// Signaled instead of `INPUT_EVENT` while a modal owner exists
+static mut MODAL_INPUT_EVENT: KEvent = KEvent::new();
+
 /// Keyboard state
 static mut SHIFT_PRESSED: bool = false;
 static mut CTRL_PRESSED: bool = false;
@@ -188,8 +197,82 @@ pub fn handle_interrupt() {
     // Read scancode from the PS/2 data port
     let scancode = unsafe { inb(ps2_ports::DATA) };
 
+    // Record it, or drop it while recorded input is being replayed
+    if !super::replay::replay_filter_scancode(scancode) {
+        return;
+    }
+
+    // F8 while the boot screen is up shows the boot messages
+    if scancode == crate::bootscreen::SCANCODE_F8 {
+        crate::bootscreen::request_messages();
+    }
+
     // Handle the scancode
     process_scancode(scancode);
+    signal_input();
+}
+
+/// Feed a scancode through the normal keyboard path as if it had been
+/// read from the controller (used by input replay)
+pub fn inject_scancode(scancode: u8) {
+    process_scancode(scancode);
+    signal_input();
+}
+
+/// Wake a reader if the scancode produced characters
+fn signal_input() {
+    if has_input() {
+        unsafe {
+            if MODAL_OWNER.load(Ordering::Acquire) != 0 {
+                (*core::ptr::addr_of!(MODAL_INPUT_EVENT)).set();
+            } else {
+                (*core::ptr::addr_of!(INPUT_EVENT)).set();
+            }
+        }
+    }
+}
+
+/// Block until input is signaled or the timeout expires
+fn wait_for_input(timeout_ms: Option<u64>) {
+    unsafe {
+        let event = if is_modal_owner() {
+            core::ptr::addr_of_mut!(MODAL_INPUT_EVENT)
+        } else {
+            core::ptr::addr_of_mut!(INPUT_EVENT)
+        };
+        crate::ke::wait::ke_wait_for_single_object(&mut (*event).header as *mut _, timeout_ms);
+    }
+}
+
+fn is_modal_owner() -> bool {
+    let owner = MODAL_OWNER.load(Ordering::Acquire);
+    owner != 0 && owner == crate::ke::prcb::get_current_thread() as usize
+}
+
+/// Check if the current thread may take characters from the buffer
+fn may_read() -> bool {
+    let owner = MODAL_OWNER.load(Ordering::Acquire);
+    owner == 0 || owner == crate::ke::prcb::get_current_thread() as usize
+}
+
+/// Take the keyboard for the current thread (modal prompts)
+///
+/// Until `end_modal_input`, other readers block as if no key had been
+/// pressed. Fails if another thread already holds it.
+pub fn begin_modal_input() -> bool {
+    let thread = crate::ke::prcb::get_current_thread() as usize;
+    if thread == 0 {
+        return false;
+    }
+    MODAL_OWNER.compare_exchange(0, thread, Ordering::AcqRel, Ordering::Acquire).is_ok()
+}
+
+/// Release modal input and hand any buffered keys back to normal readers
+pub fn end_modal_input() {
+    if is_modal_owner() {
+        MODAL_OWNER.store(0, Ordering::Release);
+        signal_input();
+    }
 }
 
 /// Process a scancode
@@ -336,20 +419,36 @@ fn process_scancode(scancode: u8) {
 pub fn read_char() -> u8 {
     loop {
         // Check buffer (filled by interrupt handler)
-        {
-            let mut buf = KEYBOARD_BUFFER.lock();
-            if let Some(c) = buf.pop() {
-                return c;
-            }
+        if let Some(c) = try_read_char() {
+            return c;
+        }
+
+        // Sleep until the interrupt handler adds input
+        wait_for_input(None);
+    }
+}
+
+/// Read a character from the keyboard buffer, giving up after `timeout_ms`
+pub fn read_char_timeout(timeout_ms: u64) -> Option<u8> {
+    let deadline = crate::hal::apic::get_tick_count() + timeout_ms;
+    loop {
+        if let Some(c) = try_read_char() {
+            return Some(c);
         }
 
-        // Yield to other threads while waiting for keyboard input
-        unsafe { crate::ke::scheduler::ki_yield(); }
+        let now = crate::hal::apic::get_tick_count();
+        if now >= deadline {
+            return None;
+        }
+        wait_for_input(Some(deadline - now));
     }
 }
 
 /// Try to read a character from the keyboard buffer (non-blocking)
 pub fn try_read_char() -> Option<u8> {
+    if !may_read() {
+        return None;
+    }
     let mut buf = KEYBOARD_BUFFER.lock();
     buf.pop()
 }
@@ -367,12 +466,22 @@ pub fn has_input() -> bool {
     !buf.is_empty()
 }
 
+/// Check if the keyboard driver has been initialized
+pub fn is_initialized() -> bool {
+    KEYBOARD_INITIALIZED.load(Ordering::Acquire)
+}
+
 /// Get the number of characters in the buffer
 pub fn buffer_len() -> usize {
     let buf = KEYBOARD_BUFFER.lock();
     buf.len()
 }
 
+/// Address and size of the input queue the ISR fills, for pinning
+pub fn buffer_range() -> Option<(u64, usize)> {
+    Some((&KEYBOARD_BUFFER as *const _ as u64, core::mem::size_of_val(&KEYBOARD_BUFFER)))
+}
+
 /// Read a line from the keyboard (blocking, with echo)
 pub fn read_line(buffer: &mut [u8]) -> usize {
     let mut pos = 0;
@@ -421,6 +530,11 @@ pub fn init() {
         return; // Already initialized
     }
 
+    unsafe {
+        (*core::ptr::addr_of_mut!(INPUT_EVENT)).init(EventType::Synchronization, false);
+        (*core::ptr::addr_of_mut!(MODAL_INPUT_EVENT)).init(EventType::Synchronization, false);
+    }
+
     // Wait for keyboard controller to be ready
     unsafe {
         // Disable devices temporarily
@@ -19,6 +19,7 @@
 //! - PIT (8254) for legacy systems
 //! - APIC timer for modern systems
 //! - HPET for high-precision timing
+//! - kvmclock / Hyper-V reference TSC page when running virtualized
 //!
 //! # APIC
 //!
@@ -36,7 +37,10 @@ pub mod cmos;
 pub mod cpuid;
 pub mod display;
 pub mod dma;
+pub mod efi;
+pub mod hv;
 pub mod interrupt;
+pub mod iommu;
 pub mod keyboard;
 pub mod mce;
 pub mod mouse;
@@ -48,7 +52,9 @@ pub mod port;
 pub mod power;
 pub mod ppm;
 pub mod profile;
+pub mod replay;
 pub mod rtc;
+pub mod smbios;
 pub mod timer;
 pub mod tlb;
 
@@ -71,7 +77,7 @@ pub use timer::{
     TIME_INCREMENT, NANOSECONDS_PER_SECOND, NANOSECONDS_PER_TIME_UNIT,
     NT_UNIX_EPOCH_DIFF,
     read_tsc, read_tsc_serialized, is_tsc_invariant,
-    hal_query_performance_counter, hal_query_performance_frequency,
+    hal_query_performance_counter, hal_query_performance_frequency, hal_query_tsc_frequency,
     hal_query_performance_counter_ex, ticks_to_nanoseconds, nanoseconds_to_ticks,
     hal_query_system_time, hal_query_local_time, hal_query_boot_time,
     hal_query_uptime, hal_query_uptime_seconds, hal_query_tick_count,
@@ -81,8 +87,8 @@ pub use timer::{
     hal_enable_timer_interrupt, hal_disable_timer_interrupt,
     hal_is_timer_interrupt_enabled, hal_get_timer_interrupt_count,
     hal_calibrate_timers, hal_get_calibration, hal_is_calibrated,
-    hal_stall_execution, hal_stall_execution_ns, hal_get_timer_stats,
-    hal_is_timer_initialized,
+    hal_stall_execution, hal_stall_execution_ns, ke_stall_execution_processor,
+    hal_get_timer_stats, hal_is_timer_initialized,
 };
 
 // Re-export DMA types
@@ -200,6 +206,8 @@ pub use display::{
     display_bugcheck_screen, display_is_bugcheck_active,
     display_set_gop, display_get_info, display_get_mode, display_is_initialized,
     display_get_stats,
+    BootDisplay, BootVideoMode, MAX_BOOT_DISPLAYS, MAX_BOOT_VIDEO_MODES, BOOT_DISPLAY_PREFERRED_MODE,
+    display_capture_boot_displays, display_boot_displays, display_boot_flags,
     hal_display_string, hal_query_display_parameters, inbv_display_string, inbv_set_text_color,
 };
 
@@ -264,5 +272,12 @@ pub use tlb::{
     tlb_get_stats, ke_flush_single_tb, ke_flush_entire_tb,
 };
 
+// Re-export IOMMU types
+pub use iommu::{
+    IommuMode, IommuUnit, IommuDomain, IommuRmrr, IommuStats,
+    iommu_isolate_device, iommu_map_dma, iommu_unmap_dma, iommu_poll_faults,
+    iommu_is_enabled, iommu_get_units, iommu_get_domains, iommu_get_stats,
+};
+
 // TODO: Future submodules
 // pub mod platform;
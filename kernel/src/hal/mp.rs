@@ -369,10 +369,7 @@ pub fn mp_send_sipi(apic_id: u32, vector: u8) -> bool {
 
 /// Delay in microseconds (busy wait)
 fn delay_us(us: u32) {
-    // Use TSC for delay if available, otherwise busy loop
-    for _ in 0..(us * 100) {
-        core::hint::spin_loop();
-    }
+    super::timer::ke_stall_execution_processor(us);
 }
 
 /// Start a single AP
@@ -64,6 +64,7 @@ pub mod virtio_device {
     pub const CONSOLE: u16 = 0x1043;
     pub const ENTROPY: u16 = 0x1044;
     pub const BALLOON: u16 = 0x1045;
+    pub const NINEP: u16 = 0x1049;
     pub const SCSI: u16 = 0x1048;
     pub const GPU: u16 = 0x1050;
     pub const INPUT: u16 = 0x1052;
@@ -74,6 +75,7 @@ pub mod virtio_legacy {
     pub const NETWORK: u16 = 0x1000;
     pub const BLOCK: u16 = 0x1001;
     pub const BALLOON: u16 = 0x1002;
+    pub const NINEP: u16 = 0x1009;
     pub const CONSOLE: u16 = 0x1003;
     pub const SCSI: u16 = 0x1004;
     pub const ENTROPY: u16 = 0x1005;
@@ -250,6 +252,26 @@ pub fn pci_find_capability(loc: PciLocation, cap_id: u8) -> Option<u8> {
 
 /// Scan for VirtIO network devices
 pub fn find_virtio_net_devices() -> alloc::vec::Vec<PciLocation> {
+    find_virtio_devices(virtio_legacy::NETWORK, virtio_device::NETWORK, "Network")
+}
+
+/// Scan for VirtIO block devices
+pub fn find_virtio_blk_devices() -> alloc::vec::Vec<PciLocation> {
+    find_virtio_devices(virtio_legacy::BLOCK, virtio_device::BLOCK, "Block")
+}
+
+/// Scan for VirtIO memory balloon devices
+pub fn find_virtio_balloon_devices() -> alloc::vec::Vec<PciLocation> {
+    find_virtio_devices(virtio_legacy::BALLOON, virtio_device::BALLOON, "Balloon")
+}
+
+/// Scan for VirtIO 9P (shared folder) devices
+pub fn find_virtio_9p_devices() -> alloc::vec::Vec<PciLocation> {
+    find_virtio_devices(virtio_legacy::NINEP, virtio_device::NINEP, "9P")
+}
+
+/// Scan for VirtIO devices with the given transitional or modern device ID
+fn find_virtio_devices(legacy_id: u16, modern_id: u16, kind: &str) -> alloc::vec::Vec<PciLocation> {
     let mut devices = alloc::vec::Vec::new();
 
     for bus in 0..=255u8 {
@@ -265,12 +287,12 @@ pub fn find_virtio_net_devices() -> alloc::vec::Vec<PciLocation> {
                 if vendor == VIRTIO_VENDOR_ID {
                     let device_id = pci_read_config_u16(loc, config::DEVICE_ID);
 
-                    // Check for network device (legacy or modern)
-                    if device_id == virtio_legacy::NETWORK || device_id == virtio_device::NETWORK {
+                    // Check for the device (legacy or modern)
+                    if device_id == legacy_id || device_id == modern_id {
                         devices.push(loc);
                         crate::serial_println!(
-                            "[PCI] Found VirtIO Network at {:02X}:{:02X}.{} (device_id={:#06X})",
-                            bus, device, function, device_id
+                            "[PCI] Found VirtIO {} at {:02X}:{:02X}.{} (device_id={:#06X})",
+                            kind, bus, device, function, device_id
                         );
                     }
                 }
@@ -289,6 +311,39 @@ pub fn find_virtio_net_devices() -> alloc::vec::Vec<PciLocation> {
     devices
 }
 
+/// Scan for devices of a class, subclass and optionally programming interface
+pub fn find_devices_by_class(class: u8, subclass: u8, prog_if: Option<u8>) -> alloc::vec::Vec<PciLocation> {
+    let mut devices = alloc::vec::Vec::new();
+
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            for function in 0..8u8 {
+                let loc = PciLocation::new(bus, device, function);
+                if pci_read_config_u16(loc, config::VENDOR_ID) == 0xFFFF {
+                    continue;
+                }
+
+                if pci_read_config_u8(loc, config::CLASS_CODE) == class
+                    && pci_read_config_u8(loc, config::SUBCLASS) == subclass
+                    && prog_if.is_none_or(|p| pci_read_config_u8(loc, config::PROG_IF) == p)
+                {
+                    devices.push(loc);
+                }
+
+                // Only check function 0 if not multifunction
+                if function == 0 {
+                    let header_type = pci_read_config_u8(loc, config::HEADER_TYPE);
+                    if (header_type & 0x80) == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    devices
+}
+
 /// Initialize PCI subsystem
 pub fn init() {
     crate::serial_println!("[PCI] PCI subsystem initialized");
@@ -4,6 +4,7 @@
 //! Modern systems use the APIC, but the PIC is still needed for
 //! PS/2 keyboard support.
 
+use core::sync::atomic::{AtomicU16, Ordering};
 use crate::arch::io::{inb, outb};
 
 /// PIC ports
@@ -127,16 +128,50 @@ pub unsafe fn disable() {
     outb(ports::PIC2_DATA, 0xFF);
 }
 
+/// IRQ lines unmasked by device drivers (bit per IRQ)
+///
+/// Kept so the fixed masks written by `init` and `enable_mouse_irq`
+/// do not mask lines drivers enabled earlier.
+static DEVICE_IRQS: AtomicU16 = AtomicU16::new(0);
+
+/// Master/slave mask bits to clear for driver-enabled lines
+fn device_unmask_bits() -> (u8, u8) {
+    let irqs = DEVICE_IRQS.load(Ordering::Acquire);
+    let mut master = irqs as u8;
+    let slave = (irqs >> 8) as u8;
+    if slave != 0 {
+        master |= 1 << irq::CASCADE;
+    }
+    (master, slave)
+}
+
+/// Unmask an IRQ line for a device driver (PCI INTx routed through the PIC)
+///
+/// The line stays unmasked across later PIC reprogramming.
+pub fn enable_device_irq(line: u8) {
+    if line >= 16 {
+        return;
+    }
+    DEVICE_IRQS.fetch_or(1 << line, Ordering::AcqRel);
+    unsafe {
+        clear_mask(line);
+        if line >= 8 {
+            clear_mask(irq::CASCADE);
+        }
+    }
+}
+
 /// Initialize the PIC for keyboard support
 pub fn init() {
+    let (master, slave) = device_unmask_bits();
     unsafe {
         // Remap PIC to vectors 32-47
         remap(32, 40);
 
         // Mask all interrupts except keyboard (IRQ1)
         // We use APIC for timer, so mask IRQ0
-        outb(ports::PIC1_DATA, 0xFD); // 11111101 - only keyboard enabled
-        outb(ports::PIC2_DATA, 0xFF); // All slave interrupts masked
+        outb(ports::PIC1_DATA, 0xFD & !master); // 11111101 - only keyboard enabled
+        outb(ports::PIC2_DATA, !slave); // Slave masked except driver lines
     }
 
     crate::serial_println!("[PIC] 8259 PIC initialized (keyboard IRQ enabled)");
@@ -145,15 +180,16 @@ pub fn init() {
 /// Enable mouse IRQ (IRQ12 on slave PIC)
 /// Must be called after mouse controller is initialized
 pub fn enable_mouse_irq() {
+    let (master, slave) = device_unmask_bits();
     unsafe {
         // Enable IRQ2 (cascade) on master PIC and IRQ1 (keyboard)
         // 0xF9 = 11111001 - bits 1 (keyboard) and 2 (cascade) enabled
-        outb(ports::PIC1_DATA, 0xF9);
+        outb(ports::PIC1_DATA, 0xF9 & !master);
 
         // Enable IRQ12 (mouse) on slave PIC
         // IRQ12 is bit 4 on the slave PIC (IRQ 8-15)
         // 0xEF = 11101111 - bit 4 (IRQ12) enabled
-        outb(ports::PIC2_DATA, 0xEF);
+        outb(ports::PIC2_DATA, 0xEF & !slave);
     }
 
     crate::serial_println!("[PIC] Mouse IRQ12 enabled");
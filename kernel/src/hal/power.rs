@@ -412,20 +412,23 @@ fn power_acpi_shutdown() -> ! {
         }
 
         // Give it time to work
-        for _ in 0..10000000 {
-            core::hint::spin_loop();
-        }
+        super::timer::ke_stall_execution_processor(500_000);
     }
 
-    // If ACPI failed, try keyboard controller
+    // If ACPI failed, ask the firmware
+    super::efi::efi_reset_system(super::efi::EfiResetType::Shutdown);
+
+    // Then try keyboard controller
     power_keyboard_shutdown();
 
     // Final fallback
     power_triple_fault();
 }
 
-/// Restart via keyboard controller
+/// Restart via UEFI ResetSystem, falling back to the keyboard controller
 fn power_restart() -> ! {
+    super::efi::efi_reset_system(super::efi::EfiResetType::Cold);
+
     // Try keyboard controller reset
     #[cfg(target_arch = "x86_64")]
     {
@@ -443,9 +446,7 @@ fn power_restart() -> ! {
     }
 
     // Give it time to work
-    for _ in 0..10000000 {
-        core::hint::spin_loop();
-    }
+    super::timer::ke_stall_execution_processor(500_000);
 
     // If keyboard reset failed, try triple fault
     power_triple_fault();
@@ -597,7 +598,13 @@ pub fn hal_return_to_firmware(action: u32) -> ! {
     match action {
         0 => power_shutdown(false),  // Power off
         1 => power_shutdown(true),   // Restart
-        2 => power_shutdown(true),   // Reboot to firmware (restart for now)
+        2 => {
+            // Reboot to firmware setup
+            if let Err(e) = super::efi::efi_request_firmware_ui() {
+                crate::serial_println!("[Power] Cannot request firmware UI: {}", e.name());
+            }
+            power_shutdown(true)
+        }
         _ => power_shutdown(false),
     }
 }
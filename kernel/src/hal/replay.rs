@@ -0,0 +1,516 @@
+//! Deterministic Record/Replay
+//!
+//! An optional debugging aid for scheduler and input races. In record mode
+//! the HAL logs the nondeterministic inputs the system sees:
+//!
+//! - **Interrupt**: device interrupt arrival order (keyboard, mouse and
+//!   anything dispatched through `hal_dispatch_interrupt`). Clock ticks are
+//!   not logged individually; every record carries the tick it arrived on.
+//! - **Keyboard**: raw PS/2 scancodes
+//! - **PerfCounter** / **SystemTime**: timer values returned to user mode by
+//!   `NtQueryPerformanceCounter` and `NtQuerySystemTime`
+//!
+//! In replay mode the log is fed back: scancodes are injected from the clock
+//! tick at the same tick offset they were recorded at (live keyboard input
+//! is dropped until the recorded input runs out), and the timer services
+//! return the recorded values in order. Device interrupts cannot be
+//! re-delivered, so their live arrival order is compared against the log
+//! and the first divergence is reported instead.
+//!
+//! Records are fixed-size and live in a static ring; recording stops (and
+//! counts the loss) when it fills, since a replay has to start from the
+//! beginning of the log.
+//!
+//! # File Format
+//!
+//! `replay_save` writes a `ReplayFileHeader` followed by `event_count`
+//! `ReplayEvent` records, all little-endian. `replay_load` reads the same
+//! format back.
+
+use crate::ke::SpinLock;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+/// Records held in the log
+pub const REPLAY_CAPACITY: usize = 16384;
+
+/// Log file magic ("NREPLAY\0")
+pub const REPLAY_MAGIC: [u8; 8] = *b"NREPLAY\0";
+
+/// Log file format version
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Record/replay mode
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    Off = 0,
+    Record = 1,
+    Replay = 2,
+}
+
+/// Record kinds
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayEventKind {
+    Interrupt = 1,
+    Keyboard = 2,
+    PerfCounter = 3,
+    SystemTime = 4,
+}
+
+impl ReplayEventKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Interrupt),
+            2 => Some(Self::Keyboard),
+            3 => Some(Self::PerfCounter),
+            4 => Some(Self::SystemTime),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Interrupt => "Interrupt",
+            Self::Keyboard => "Keyboard",
+            Self::PerfCounter => "PerfCounter",
+            Self::SystemTime => "SystemTime",
+        }
+    }
+}
+
+/// One logged input (16 bytes)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayEvent {
+    /// `ReplayEventKind`
+    pub kind: u8,
+    /// Interrupt vector or scancode
+    pub data: u8,
+    pub reserved: u16,
+    /// Clock ticks since recording started
+    pub tick: u32,
+    /// Timer value returned (timer kinds only)
+    pub value: u64,
+}
+
+impl ReplayEvent {
+    pub fn kind(&self) -> Option<ReplayEventKind> {
+        ReplayEventKind::from_u8(self.kind)
+    }
+}
+
+/// Log file header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayFileHeader {
+    pub magic: [u8; 8],
+    pub version: u32,
+    pub header_size: u32,
+    pub event_size: u32,
+    pub event_count: u32,
+    pub tsc_frequency: u64,
+    pub events_lost: u64,
+}
+
+/// First point where a replay stopped matching its log
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayDivergence {
+    /// Log index of the expected record
+    pub index: u32,
+    /// Expected interrupt vector (0 if the log had no more interrupts)
+    pub expected: u8,
+    /// Vector that actually arrived
+    pub actual: u8,
+    /// Ticks into the replay
+    pub tick: u32,
+}
+
+/// Record/replay status
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayStatus {
+    pub mode: ReplayMode,
+    /// Records in the log
+    pub event_count: u32,
+    /// Records dropped because the log was full
+    pub events_lost: u64,
+    /// Replay progress per stream (records consumed)
+    pub keys_replayed: u32,
+    pub timers_replayed: u32,
+    pub interrupts_matched: u32,
+    /// Interrupts that did not match the log
+    pub divergences: u32,
+    pub first_divergence: Option<ReplayDivergence>,
+    /// Ticks since recording or replay started
+    pub elapsed_ticks: u64,
+}
+
+/// Replay cursors, one per stream
+struct ReplayCursors {
+    keyboard: usize,
+    timer: usize,
+    interrupt: usize,
+    keys_replayed: u32,
+    timers_replayed: u32,
+    interrupts_matched: u32,
+    divergences: u32,
+    first_divergence: Option<ReplayDivergence>,
+}
+
+impl ReplayCursors {
+    const fn new() -> Self {
+        Self {
+            keyboard: 0,
+            timer: 0,
+            interrupt: 0,
+            keys_replayed: 0,
+            timers_replayed: 0,
+            interrupts_matched: 0,
+            divergences: 0,
+            first_divergence: None,
+        }
+    }
+}
+
+static MODE: AtomicU8 = AtomicU8::new(ReplayMode::Off as u8);
+static NEXT_EVENT: AtomicU32 = AtomicU32::new(0);
+static EVENTS_LOST: AtomicU64 = AtomicU64::new(0);
+static START_TICK: AtomicU64 = AtomicU64::new(0);
+static CURSORS: SpinLock<ReplayCursors> = SpinLock::new(ReplayCursors::new());
+static mut EVENTS: [ReplayEvent; REPLAY_CAPACITY] = [ReplayEvent {
+    kind: 0,
+    data: 0,
+    reserved: 0,
+    tick: 0,
+    value: 0,
+}; REPLAY_CAPACITY];
+
+/// Current mode
+#[inline]
+pub fn replay_mode() -> ReplayMode {
+    match MODE.load(Ordering::Acquire) {
+        1 => ReplayMode::Record,
+        2 => ReplayMode::Replay,
+        _ => ReplayMode::Off,
+    }
+}
+
+fn elapsed_ticks() -> u64 {
+    super::apic::get_tick_count().saturating_sub(START_TICK.load(Ordering::Relaxed))
+}
+
+fn event_count() -> usize {
+    (NEXT_EVENT.load(Ordering::Acquire) as usize).min(REPLAY_CAPACITY)
+}
+
+fn event_at(index: usize) -> ReplayEvent {
+    unsafe { (*core::ptr::addr_of!(EVENTS))[index] }
+}
+
+fn log_event(kind: ReplayEventKind, data: u8, value: u64) {
+    let index = NEXT_EVENT.fetch_add(1, Ordering::AcqRel) as usize;
+    if index >= REPLAY_CAPACITY {
+        NEXT_EVENT.store(REPLAY_CAPACITY as u32, Ordering::Release);
+        EVENTS_LOST.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let event = ReplayEvent {
+        kind: kind as u8,
+        data,
+        reserved: 0,
+        tick: elapsed_ticks() as u32,
+        value,
+    };
+    unsafe { (*core::ptr::addr_of_mut!(EVENTS))[index] = event };
+}
+
+/// Index of the next record of `kind` at or after `from`
+fn next_of_kind(from: usize, count: usize, kind: ReplayEventKind) -> Option<usize> {
+    (from..count).find(|&i| event_at(i).kind == kind as u8)
+}
+
+fn next_timer(from: usize, count: usize) -> Option<usize> {
+    (from..count).find(|&i| {
+        matches!(event_at(i).kind(), Some(ReplayEventKind::PerfCounter | ReplayEventKind::SystemTime))
+    })
+}
+
+// ============================================================================
+// Mode control
+// ============================================================================
+
+/// Clear the log and start recording
+pub fn replay_start_recording() {
+    MODE.store(ReplayMode::Off as u8, Ordering::Release);
+    NEXT_EVENT.store(0, Ordering::Release);
+    EVENTS_LOST.store(0, Ordering::Relaxed);
+    START_TICK.store(super::apic::get_tick_count(), Ordering::Relaxed);
+    MODE.store(ReplayMode::Record as u8, Ordering::Release);
+    crate::serial_println!("[REPLAY] Recording started");
+}
+
+/// Start feeding the current log back
+///
+/// Returns false if the log is empty.
+pub fn replay_start_replay() -> bool {
+    if event_count() == 0 {
+        return false;
+    }
+    MODE.store(ReplayMode::Off as u8, Ordering::Release);
+    *CURSORS.lock() = ReplayCursors::new();
+    START_TICK.store(super::apic::get_tick_count(), Ordering::Relaxed);
+    MODE.store(ReplayMode::Replay as u8, Ordering::Release);
+    crate::serial_println!("[REPLAY] Replaying {} records", event_count());
+    true
+}
+
+/// Stop recording or replaying; the log is kept
+pub fn replay_stop() {
+    let previous = replay_mode();
+    MODE.store(ReplayMode::Off as u8, Ordering::Release);
+    if previous != ReplayMode::Off {
+        crate::serial_println!("[REPLAY] Stopped ({} records)", event_count());
+    }
+}
+
+/// Current status
+pub fn replay_status() -> ReplayStatus {
+    let mode = replay_mode();
+    let cursors = CURSORS.lock();
+    ReplayStatus {
+        mode,
+        event_count: event_count() as u32,
+        events_lost: EVENTS_LOST.load(Ordering::Relaxed),
+        keys_replayed: cursors.keys_replayed,
+        timers_replayed: cursors.timers_replayed,
+        interrupts_matched: cursors.interrupts_matched,
+        divergences: cursors.divergences,
+        first_divergence: cursors.first_divergence,
+        elapsed_ticks: if mode == ReplayMode::Off { 0 } else { elapsed_ticks() },
+    }
+}
+
+/// Log record by index
+pub fn replay_event(index: usize) -> Option<ReplayEvent> {
+    if index < event_count() {
+        Some(event_at(index))
+    } else {
+        None
+    }
+}
+
+// ============================================================================
+// Hooks
+// ============================================================================
+
+/// Note a device interrupt arriving on `vector`
+///
+/// Called from interrupt context.
+#[inline]
+pub fn replay_note_interrupt(vector: u8) {
+    match replay_mode() {
+        ReplayMode::Off => {}
+        ReplayMode::Record => log_event(ReplayEventKind::Interrupt, vector, 0),
+        ReplayMode::Replay => check_interrupt(vector),
+    }
+}
+
+fn check_interrupt(vector: u8) {
+    let count = event_count();
+    let mut cursors = CURSORS.lock();
+    let expected = next_of_kind(cursors.interrupt, count, ReplayEventKind::Interrupt);
+    match expected {
+        Some(index) if event_at(index).data == vector => {
+            cursors.interrupt = index + 1;
+            cursors.interrupts_matched += 1;
+        }
+        _ => {
+            cursors.divergences += 1;
+            if cursors.first_divergence.is_none() {
+                let divergence = ReplayDivergence {
+                    index: expected.unwrap_or(count) as u32,
+                    expected: expected.map(|i| event_at(i).data).unwrap_or(0),
+                    actual: vector,
+                    tick: elapsed_ticks() as u32,
+                };
+                cursors.first_divergence = Some(divergence);
+                crate::serial_println!(
+                    "[REPLAY] Interrupt order diverged at record {}: expected vector {}, got {}",
+                    divergence.index, divergence.expected, divergence.actual
+                );
+            }
+            // Resync past the expected record so one extra or missing
+            // interrupt does not flag every one after it
+            if let Some(index) = expected {
+                cursors.interrupt = index + 1;
+            }
+        }
+    }
+}
+
+/// Filter a scancode read from the keyboard controller
+///
+/// Returns false if the scancode should be dropped because recorded input
+/// is being replayed.
+#[inline]
+pub fn replay_filter_scancode(scancode: u8) -> bool {
+    match replay_mode() {
+        ReplayMode::Off => true,
+        ReplayMode::Record => {
+            log_event(ReplayEventKind::Keyboard, scancode, 0);
+            true
+        }
+        ReplayMode::Replay => {
+            let cursors = CURSORS.lock();
+            next_of_kind(cursors.keyboard, event_count(), ReplayEventKind::Keyboard).is_none()
+        }
+    }
+}
+
+/// Clock tick hook: inject recorded scancodes that have come due
+///
+/// Called from the clock interrupt.
+#[inline]
+pub fn replay_tick() {
+    if replay_mode() != ReplayMode::Replay {
+        return;
+    }
+    let now = elapsed_ticks();
+    let count = event_count();
+    loop {
+        let scancode = {
+            let mut cursors = CURSORS.lock();
+            match next_of_kind(cursors.keyboard, count, ReplayEventKind::Keyboard) {
+                Some(index) if event_at(index).tick as u64 <= now => {
+                    cursors.keyboard = index + 1;
+                    cursors.keys_replayed += 1;
+                    event_at(index).data
+                }
+                _ => break,
+            }
+        };
+        super::keyboard::inject_scancode(scancode);
+    }
+}
+
+fn timer_value(kind: ReplayEventKind, live: u64) -> u64 {
+    match replay_mode() {
+        ReplayMode::Off => live,
+        ReplayMode::Record => {
+            log_event(kind, 0, live);
+            live
+        }
+        ReplayMode::Replay => {
+            let mut cursors = CURSORS.lock();
+            match next_timer(cursors.timer, event_count()) {
+                Some(index) => {
+                    cursors.timer = index + 1;
+                    cursors.timers_replayed += 1;
+                    let event = event_at(index);
+                    if event.kind == kind as u8 { event.value } else { live }
+                }
+                None => live,
+            }
+        }
+    }
+}
+
+/// Performance counter value to return to user mode
+#[inline]
+pub fn replay_perf_counter(live: u64) -> u64 {
+    timer_value(ReplayEventKind::PerfCounter, live)
+}
+
+/// System time value to return to user mode
+#[inline]
+pub fn replay_system_time(live: u64) -> u64 {
+    timer_value(ReplayEventKind::SystemTime, live)
+}
+
+// ============================================================================
+// Log files
+// ============================================================================
+
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+fn events_bytes(count: usize) -> &'static [u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            core::ptr::addr_of!(EVENTS) as *const u8,
+            count * core::mem::size_of::<ReplayEvent>(),
+        )
+    }
+}
+
+/// Write the log to `path`; returns the number of records written
+pub fn replay_save(path: &str) -> Result<usize, crate::fs::FsStatus> {
+    if replay_mode() == ReplayMode::Record {
+        replay_stop();
+    }
+    let count = event_count();
+    let header = ReplayFileHeader {
+        magic: REPLAY_MAGIC,
+        version: REPLAY_VERSION,
+        header_size: core::mem::size_of::<ReplayFileHeader>() as u32,
+        event_size: core::mem::size_of::<ReplayEvent>() as u32,
+        event_count: count as u32,
+        tsc_frequency: super::timer::hal_query_tsc_frequency(),
+        events_lost: EVENTS_LOST.load(Ordering::Relaxed),
+    };
+
+    let handle = crate::fs::create(path, 0)?;
+    let result = (|| -> Result<usize, crate::fs::FsStatus> {
+        crate::fs::write(handle, as_bytes(&header))?;
+        crate::fs::write(handle, events_bytes(count))?;
+        Ok(count)
+    })();
+    let _ = crate::fs::close(handle);
+    result
+}
+
+/// Replace the log with the one in `path`; returns the number of records
+pub fn replay_load(path: &str) -> Result<usize, crate::fs::FsStatus> {
+    replay_stop();
+    let handle = crate::fs::open(path, 0)?;
+    let result = (|| -> Result<usize, crate::fs::FsStatus> {
+        let mut raw = [0u8; core::mem::size_of::<ReplayFileHeader>()];
+        if crate::fs::read(handle, &mut raw)? != raw.len() {
+            return Err(crate::fs::FsStatus::InvalidParameter);
+        }
+        let header = unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const ReplayFileHeader) };
+        if header.magic != REPLAY_MAGIC
+            || header.version != REPLAY_VERSION
+            || header.header_size as usize != raw.len()
+            || header.event_size as usize != core::mem::size_of::<ReplayEvent>()
+        {
+            return Err(crate::fs::FsStatus::InvalidParameter);
+        }
+
+        let count = (header.event_count as usize).min(REPLAY_CAPACITY);
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(
+                core::ptr::addr_of_mut!(EVENTS) as *mut u8,
+                count * core::mem::size_of::<ReplayEvent>(),
+            )
+        };
+        let mut done = 0;
+        while done < bytes.len() {
+            let n = crate::fs::read(handle, &mut bytes[done..])?;
+            if n == 0 {
+                break;
+            }
+            done += n;
+        }
+        let loaded = done / core::mem::size_of::<ReplayEvent>();
+        NEXT_EVENT.store(loaded as u32, Ordering::Release);
+        EVENTS_LOST.store(header.events_lost, Ordering::Relaxed);
+        Ok(loaded)
+    })();
+    let _ = crate::fs::close(handle);
+    if result.is_err() {
+        NEXT_EVENT.store(0, Ordering::Release);
+    }
+    result
+}
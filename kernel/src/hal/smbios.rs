@@ -0,0 +1,616 @@
+//! SMBIOS / DMI Tables
+//!
+//! Parses the firmware's SMBIOS structure table once at boot. The entry
+//! point comes from the loader (EFI configuration table) or, on legacy
+//! firmware, from a scan of the BIOS area at 0xF0000-0xFFFFF; both the
+//! 64-bit `_SM3_` and the 32-bit `_SM_` entry points are understood.
+//!
+//! The structures callers usually want (BIOS, system, baseboard, chassis,
+//! processors and memory devices) are decoded into `SmbiosInfo`;
+//! `smbios_structures` walks the raw table for everything else.
+//!
+//! After the registry is up, `smbios_store_in_registry` publishes the
+//! results under `HKLM\HARDWARE\DESCRIPTION\System\BIOS` (the values
+//! Windows keeps there) and `...\System\MemoryDevice\<n>`.
+
+extern crate alloc;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Longest string kept per field
+pub const SMBIOS_STRING_LEN: usize = 64;
+
+/// Processors (Type 4) kept
+pub const MAX_SMBIOS_PROCESSORS: usize = 8;
+
+/// Populated memory devices (Type 17) kept
+pub const MAX_SMBIOS_MEMORY_DEVICES: usize = 16;
+
+/// Registry key for the BIOS/system values
+pub const SMBIOS_BIOS_KEY: &str = "MACHINE\\HARDWARE\\DESCRIPTION\\System\\BIOS";
+
+/// Registry key under which memory devices are listed
+pub const SMBIOS_MEMORY_KEY: &str = "MACHINE\\HARDWARE\\DESCRIPTION\\System\\MemoryDevice";
+
+/// Structure types decoded here
+pub mod smbios_type {
+    pub const BIOS: u8 = 0;
+    pub const SYSTEM: u8 = 1;
+    pub const BASEBOARD: u8 = 2;
+    pub const CHASSIS: u8 = 3;
+    pub const PROCESSOR: u8 = 4;
+    pub const MEMORY_ARRAY: u8 = 16;
+    pub const MEMORY_DEVICE: u8 = 17;
+    pub const END_OF_TABLE: u8 = 127;
+}
+
+/// A string copied out of a structure
+#[derive(Clone, Copy)]
+pub struct SmbiosString {
+    bytes: [u8; SMBIOS_STRING_LEN],
+    len: u8,
+}
+
+impl SmbiosString {
+    pub const EMPTY: Self = Self { bytes: [0; SMBIOS_STRING_LEN], len: 0 };
+
+    fn new(text: &str) -> Self {
+        let text = text.trim();
+        let mut s = Self::EMPTY;
+        // Cut on a character boundary
+        let mut len = text.len().min(SMBIOS_STRING_LEN);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        s.bytes[..len].copy_from_slice(&text.as_bytes()[..len]);
+        s.len = len as u8;
+        s
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or("")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl core::fmt::Display for SmbiosString {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(if self.is_empty() { "N/A" } else { self.as_str() })
+    }
+}
+
+/// Where the structure table is
+#[derive(Debug, Clone, Copy)]
+pub struct SmbiosEntry {
+    /// Physical address of the entry point
+    pub entry_addr: u64,
+    /// Physical address of the structure table
+    pub table_addr: u64,
+    /// Table length (maximum size for 3.x entry points)
+    pub table_len: u32,
+    pub major: u8,
+    pub minor: u8,
+    /// Structure count from a 2.x entry point (0 for 3.x)
+    pub structure_count: u16,
+}
+
+/// Processor (Type 4)
+#[derive(Clone, Copy)]
+pub struct SmbiosProcessor {
+    pub socket: SmbiosString,
+    pub manufacturer: SmbiosString,
+    pub version: SmbiosString,
+    pub max_speed_mhz: u16,
+    pub current_speed_mhz: u16,
+    pub cores: u16,
+    pub threads: u16,
+}
+
+/// Populated memory device (Type 17)
+#[derive(Clone, Copy)]
+pub struct SmbiosMemoryDevice {
+    pub locator: SmbiosString,
+    pub bank: SmbiosString,
+    pub manufacturer: SmbiosString,
+    pub serial: SmbiosString,
+    pub part_number: SmbiosString,
+    pub size_mb: u32,
+    /// Maximum speed in MT/s (0 if unknown)
+    pub speed: u16,
+    /// SMBIOS memory type (e.g. 0x1A DDR4)
+    pub memory_type: u8,
+}
+
+/// Decoded system description
+#[derive(Clone, Copy)]
+pub struct SmbiosInfo {
+    pub entry: SmbiosEntry,
+    pub bios_vendor: SmbiosString,
+    pub bios_version: SmbiosString,
+    pub bios_date: SmbiosString,
+    pub system_manufacturer: SmbiosString,
+    pub system_product: SmbiosString,
+    pub system_version: SmbiosString,
+    pub system_serial: SmbiosString,
+    pub system_sku: SmbiosString,
+    pub system_family: SmbiosString,
+    /// Raw UUID bytes as stored in the table
+    pub system_uuid: [u8; 16],
+    pub board_manufacturer: SmbiosString,
+    pub board_product: SmbiosString,
+    pub board_version: SmbiosString,
+    pub board_serial: SmbiosString,
+    pub chassis_manufacturer: SmbiosString,
+    pub chassis_serial: SmbiosString,
+    pub chassis_type: u8,
+    pub processors: [SmbiosProcessor; MAX_SMBIOS_PROCESSORS],
+    pub processor_count: usize,
+    pub memory_devices: [SmbiosMemoryDevice; MAX_SMBIOS_MEMORY_DEVICES],
+    pub memory_device_count: usize,
+    /// Memory device slots, populated or not
+    pub memory_slots: usize,
+    /// Sum of the populated devices
+    pub total_memory_mb: u64,
+    /// Maximum capacity of the memory arrays (Type 16)
+    pub max_memory_kb: u64,
+    /// Structures in the table
+    pub structure_count: usize,
+}
+
+impl SmbiosInfo {
+    const EMPTY_PROCESSOR: SmbiosProcessor = SmbiosProcessor {
+        socket: SmbiosString::EMPTY,
+        manufacturer: SmbiosString::EMPTY,
+        version: SmbiosString::EMPTY,
+        max_speed_mhz: 0,
+        current_speed_mhz: 0,
+        cores: 0,
+        threads: 0,
+    };
+
+    const EMPTY_MEMORY_DEVICE: SmbiosMemoryDevice = SmbiosMemoryDevice {
+        locator: SmbiosString::EMPTY,
+        bank: SmbiosString::EMPTY,
+        manufacturer: SmbiosString::EMPTY,
+        serial: SmbiosString::EMPTY,
+        part_number: SmbiosString::EMPTY,
+        size_mb: 0,
+        speed: 0,
+        memory_type: 0,
+    };
+
+    const EMPTY: Self = Self {
+        entry: SmbiosEntry {
+            entry_addr: 0,
+            table_addr: 0,
+            table_len: 0,
+            major: 0,
+            minor: 0,
+            structure_count: 0,
+        },
+        bios_vendor: SmbiosString::EMPTY,
+        bios_version: SmbiosString::EMPTY,
+        bios_date: SmbiosString::EMPTY,
+        system_manufacturer: SmbiosString::EMPTY,
+        system_product: SmbiosString::EMPTY,
+        system_version: SmbiosString::EMPTY,
+        system_serial: SmbiosString::EMPTY,
+        system_sku: SmbiosString::EMPTY,
+        system_family: SmbiosString::EMPTY,
+        system_uuid: [0; 16],
+        board_manufacturer: SmbiosString::EMPTY,
+        board_product: SmbiosString::EMPTY,
+        board_version: SmbiosString::EMPTY,
+        board_serial: SmbiosString::EMPTY,
+        chassis_manufacturer: SmbiosString::EMPTY,
+        chassis_serial: SmbiosString::EMPTY,
+        chassis_type: 0,
+        processors: [Self::EMPTY_PROCESSOR; MAX_SMBIOS_PROCESSORS],
+        processor_count: 0,
+        memory_devices: [Self::EMPTY_MEMORY_DEVICE; MAX_SMBIOS_MEMORY_DEVICES],
+        memory_device_count: 0,
+        memory_slots: 0,
+        total_memory_mb: 0,
+        max_memory_kb: 0,
+        structure_count: 0,
+    };
+
+    /// Decoded processors
+    pub fn processors(&self) -> &[SmbiosProcessor] {
+        &self.processors[..self.processor_count]
+    }
+
+    /// Populated memory devices
+    pub fn memory_devices(&self) -> &[SmbiosMemoryDevice] {
+        &self.memory_devices[..self.memory_device_count]
+    }
+
+    /// System UUID in the usual text form (SMBIOS 2.6+ byte order)
+    pub fn uuid_string(&self) -> alloc::string::String {
+        let u = &self.system_uuid;
+        alloc::format!(
+            "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            u[3], u[2], u[1], u[0], u[5], u[4], u[7], u[6],
+            u[8], u[9], u[10], u[11], u[12], u[13], u[14], u[15]
+        )
+    }
+}
+
+/// One structure in the table
+#[derive(Clone, Copy)]
+pub struct SmbiosStructure<'a> {
+    pub struct_type: u8,
+    pub handle: u16,
+    /// Formatted area, header included
+    pub formatted: &'a [u8],
+    /// Unformatted string set (without the final NUL)
+    strings: &'a [u8],
+}
+
+impl<'a> SmbiosStructure<'a> {
+    pub fn byte(&self, offset: usize) -> Option<u8> {
+        self.formatted.get(offset).copied()
+    }
+
+    pub fn word(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(self.formatted.get(offset..offset + 2)?.try_into().ok()?))
+    }
+
+    pub fn dword(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(self.formatted.get(offset..offset + 4)?.try_into().ok()?))
+    }
+
+    pub fn qword(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(self.formatted.get(offset..offset + 8)?.try_into().ok()?))
+    }
+
+    /// String number `index` (1-based; 0 means none)
+    pub fn string(&self, index: u8) -> &'a str {
+        if index == 0 {
+            return "";
+        }
+        self.strings
+            .split(|&b| b == 0)
+            .nth(index as usize - 1)
+            .and_then(|s| core::str::from_utf8(s).ok())
+            .unwrap_or("")
+    }
+
+    /// String referenced by the byte at `offset`
+    pub fn string_at(&self, offset: usize) -> SmbiosString {
+        SmbiosString::new(self.byte(offset).map_or("", |index| self.string(index)))
+    }
+}
+
+/// Iterator over the structure table
+pub struct SmbiosIter {
+    table: &'static [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl Iterator for SmbiosIter {
+    type Item = SmbiosStructure<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let table = self.table;
+        let start = self.offset;
+        let header = table.get(start..start + 4)?;
+        let length = header[1] as usize;
+        if length < 4 {
+            self.done = true;
+            return None;
+        }
+        let formatted = table.get(start..start + length)?;
+
+        // Strings end at a double NUL
+        let strings_start = start + length;
+        let rest = table.get(strings_start..)?;
+        let end = rest.windows(2).position(|w| w == [0, 0])?;
+        self.offset = strings_start + end + 2;
+
+        let structure = SmbiosStructure {
+            struct_type: header[0],
+            handle: u16::from_le_bytes([header[2], header[3]]),
+            formatted,
+            strings: &rest[..end],
+        };
+        if structure.struct_type == smbios_type::END_OF_TABLE {
+            self.done = true;
+        }
+        Some(structure)
+    }
+}
+
+static mut SMBIOS_INFO: SmbiosInfo = SmbiosInfo::EMPTY;
+static SMBIOS_PRESENT: AtomicBool = AtomicBool::new(false);
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// Decode the entry point at `addr`
+unsafe fn read_entry(addr: u64) -> Option<SmbiosEntry> {
+    let anchor = core::slice::from_raw_parts(addr as *const u8, 5);
+    if anchor == b"_SM3_" {
+        let length = *((addr + 6) as *const u8) as usize;
+        let bytes = core::slice::from_raw_parts(addr as *const u8, length.max(0x18));
+        if !checksum_ok(&bytes[..length]) {
+            return None;
+        }
+        return Some(SmbiosEntry {
+            entry_addr: addr,
+            table_addr: u64::from_le_bytes(bytes[0x10..0x18].try_into().ok()?),
+            table_len: u32::from_le_bytes(bytes[0x0C..0x10].try_into().ok()?),
+            major: bytes[7],
+            minor: bytes[8],
+            structure_count: 0,
+        });
+    }
+    if &anchor[..4] == b"_SM_" {
+        let length = *((addr + 5) as *const u8) as usize;
+        let bytes = core::slice::from_raw_parts(addr as *const u8, length.max(0x1F));
+        if !checksum_ok(&bytes[..length]) || &bytes[0x10..0x15] != b"_DMI_" {
+            return None;
+        }
+        return Some(SmbiosEntry {
+            entry_addr: addr,
+            table_addr: u32::from_le_bytes(bytes[0x18..0x1C].try_into().ok()?) as u64,
+            table_len: u16::from_le_bytes([bytes[0x16], bytes[0x17]]) as u32,
+            major: bytes[6],
+            minor: bytes[7],
+            structure_count: u16::from_le_bytes([bytes[0x1C], bytes[0x1D]]),
+        });
+    }
+    None
+}
+
+/// Scan the legacy BIOS area, preferring a 3.x entry point
+unsafe fn scan_bios_area() -> Option<SmbiosEntry> {
+    let mut legacy = None;
+    for addr in (0xF0000u64..0x100000).step_by(16) {
+        let anchor = core::slice::from_raw_parts(addr as *const u8, 5);
+        if anchor == b"_SM3_" {
+            if let Some(entry) = read_entry(addr) {
+                return Some(entry);
+            }
+        } else if &anchor[..4] == b"_SM_" && legacy.is_none() {
+            legacy = read_entry(addr);
+        }
+    }
+    legacy
+}
+
+/// Walk the structure table
+pub fn smbios_structures() -> Option<SmbiosIter> {
+    let info = smbios_info()?;
+    let table = unsafe {
+        core::slice::from_raw_parts(info.entry.table_addr as *const u8, info.entry.table_len as usize)
+    };
+    Some(SmbiosIter { table, offset: 0, done: false })
+}
+
+fn decode(info: &mut SmbiosInfo, table: &'static [u8]) {
+    use smbios_type::*;
+
+    let iter = SmbiosIter { table, offset: 0, done: false };
+    for s in iter {
+        info.structure_count += 1;
+        match s.struct_type {
+            BIOS => {
+                info.bios_vendor = s.string_at(0x04);
+                info.bios_version = s.string_at(0x05);
+                info.bios_date = s.string_at(0x08);
+            }
+            SYSTEM => {
+                info.system_manufacturer = s.string_at(0x04);
+                info.system_product = s.string_at(0x05);
+                info.system_version = s.string_at(0x06);
+                info.system_serial = s.string_at(0x07);
+                if let Some(uuid) = s.formatted.get(0x08..0x18) {
+                    info.system_uuid.copy_from_slice(uuid);
+                }
+                info.system_sku = s.string_at(0x19);
+                info.system_family = s.string_at(0x1A);
+            }
+            BASEBOARD if info.board_manufacturer.is_empty() => {
+                info.board_manufacturer = s.string_at(0x04);
+                info.board_product = s.string_at(0x05);
+                info.board_version = s.string_at(0x06);
+                info.board_serial = s.string_at(0x07);
+            }
+            CHASSIS if info.chassis_type == 0 => {
+                info.chassis_manufacturer = s.string_at(0x04);
+                info.chassis_type = s.byte(0x05).unwrap_or(0) & 0x7F;
+                info.chassis_serial = s.string_at(0x07);
+            }
+            PROCESSOR if info.processor_count < MAX_SMBIOS_PROCESSORS => {
+                // Skip unpopulated sockets
+                if s.byte(0x18).unwrap_or(0) & 0x40 == 0 {
+                    continue;
+                }
+                let cores = s.byte(0x23).unwrap_or(0) as u16;
+                let threads = s.byte(0x25).unwrap_or(0) as u16;
+                info.processors[info.processor_count] = SmbiosProcessor {
+                    socket: s.string_at(0x04),
+                    manufacturer: s.string_at(0x07),
+                    version: s.string_at(0x10),
+                    max_speed_mhz: s.word(0x14).unwrap_or(0),
+                    current_speed_mhz: s.word(0x16).unwrap_or(0),
+                    // 0xFF means "see the 3.0 word fields"
+                    cores: if cores == 0xFF { s.word(0x2A).unwrap_or(cores) } else { cores },
+                    threads: if threads == 0xFF { s.word(0x2E).unwrap_or(threads) } else { threads },
+                };
+                info.processor_count += 1;
+            }
+            MEMORY_ARRAY => {
+                let max = s.dword(0x07).unwrap_or(0);
+                info.max_memory_kb += if max == 0x8000_0000 {
+                    s.qword(0x0F).unwrap_or(0) / 1024
+                } else {
+                    max as u64
+                };
+            }
+            MEMORY_DEVICE => {
+                info.memory_slots += 1;
+                let size = s.word(0x0C).unwrap_or(0);
+                let size_mb = match size {
+                    0 | 0xFFFF => continue,
+                    0x7FFF => s.dword(0x1C).unwrap_or(0) & 0x7FFF_FFFF,
+                    _ if size & 0x8000 != 0 => (size & 0x7FFF) as u32 / 1024,
+                    _ => size as u32,
+                };
+                info.total_memory_mb += size_mb as u64;
+                if info.memory_device_count < MAX_SMBIOS_MEMORY_DEVICES {
+                    info.memory_devices[info.memory_device_count] = SmbiosMemoryDevice {
+                        locator: s.string_at(0x10),
+                        bank: s.string_at(0x11),
+                        manufacturer: s.string_at(0x17),
+                        serial: s.string_at(0x18),
+                        part_number: s.string_at(0x1A),
+                        size_mb,
+                        speed: s.word(0x15).unwrap_or(0),
+                        memory_type: s.byte(0x12).unwrap_or(0),
+                    };
+                    info.memory_device_count += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Locate and decode the SMBIOS tables
+pub fn init(boot_info: &crate::BootInfo) {
+    let entry = unsafe {
+        if boot_info.smbios_entry_addr != 0 {
+            read_entry(boot_info.smbios_entry_addr)
+        } else {
+            scan_bios_area()
+        }
+    };
+    let Some(entry) = entry else {
+        crate::serial_println!("[SMBIOS] No entry point found");
+        return;
+    };
+    if entry.table_addr == 0 || entry.table_len == 0 {
+        crate::serial_println!("[SMBIOS] Empty structure table");
+        return;
+    }
+
+    let table = unsafe {
+        core::slice::from_raw_parts(entry.table_addr as *const u8, entry.table_len as usize)
+    };
+    let info = unsafe { &mut *core::ptr::addr_of_mut!(SMBIOS_INFO) };
+    info.entry = entry;
+    decode(info, table);
+    SMBIOS_PRESENT.store(true, Ordering::Release);
+
+    crate::serial_println!(
+        "[SMBIOS] v{}.{}: {} structures, {} {} (BIOS {} {})",
+        entry.major, entry.minor, info.structure_count,
+        info.system_manufacturer, info.system_product,
+        info.bios_vendor, info.bios_version
+    );
+}
+
+/// Decoded SMBIOS information, if tables were found
+pub fn smbios_info() -> Option<&'static SmbiosInfo> {
+    if !SMBIOS_PRESENT.load(Ordering::Acquire) {
+        return None;
+    }
+    // Written once by `init`, read-only afterwards
+    Some(unsafe { &*core::ptr::addr_of!(SMBIOS_INFO) })
+}
+
+/// Chassis type name (Type 3, offset 0x05)
+pub fn chassis_type_name(chassis_type: u8) -> &'static str {
+    match chassis_type {
+        0x01 => "Other",
+        0x03 => "Desktop",
+        0x04 => "Low Profile Desktop",
+        0x06 => "Mini Tower",
+        0x07 => "Tower",
+        0x08 => "Portable",
+        0x09 => "Laptop",
+        0x0A => "Notebook",
+        0x0D => "All in One",
+        0x0E => "Sub Notebook",
+        0x11 => "Main Server Chassis",
+        0x17 => "Rack Mount Chassis",
+        0x1E => "Tablet",
+        0x1F => "Convertible",
+        0x20 => "Detachable",
+        0x23 => "Mini PC",
+        0x24 => "Stick PC",
+        _ => "Unknown",
+    }
+}
+
+/// Memory type name (Type 17, offset 0x12)
+pub fn memory_type_name(memory_type: u8) -> &'static str {
+    match memory_type {
+        0x07 => "RAM",
+        0x0F => "SDRAM",
+        0x12 => "DDR",
+        0x13 => "DDR2",
+        0x18 => "DDR3",
+        0x1A => "DDR4",
+        0x1B => "LPDDR",
+        0x1C => "LPDDR2",
+        0x1D => "LPDDR3",
+        0x1E => "LPDDR4",
+        0x22 => "DDR5",
+        0x23 => "LPDDR5",
+        _ => "Unknown",
+    }
+}
+
+/// Publish the decoded information in the HARDWARE hive
+///
+/// Called once the configuration manager is initialized.
+pub fn smbios_store_in_registry() {
+    use crate::cm::{cm_write_dword, cm_write_string};
+
+    let Some(info) = smbios_info() else { return };
+    let values = [
+        ("BIOSVendor", &info.bios_vendor),
+        ("BIOSVersion", &info.bios_version),
+        ("BIOSReleaseDate", &info.bios_date),
+        ("SystemManufacturer", &info.system_manufacturer),
+        ("SystemProductName", &info.system_product),
+        ("SystemVersion", &info.system_version),
+        ("SystemSerialNumber", &info.system_serial),
+        ("SystemSKU", &info.system_sku),
+        ("SystemFamily", &info.system_family),
+        ("BaseBoardManufacturer", &info.board_manufacturer),
+        ("BaseBoardProduct", &info.board_product),
+        ("BaseBoardVersion", &info.board_version),
+        ("BaseBoardSerialNumber", &info.board_serial),
+    ];
+    unsafe {
+        for (name, value) in values {
+            cm_write_string(SMBIOS_BIOS_KEY, name, value.as_str());
+        }
+        cm_write_string(SMBIOS_BIOS_KEY, "SystemUUID", &info.uuid_string());
+        cm_write_dword(SMBIOS_BIOS_KEY, "SMBIOSMajorVersion", info.entry.major as u32);
+        cm_write_dword(SMBIOS_BIOS_KEY, "SMBIOSMinorVersion", info.entry.minor as u32);
+
+        for (index, device) in info.memory_devices().iter().enumerate() {
+            let key = alloc::format!("{}\\{}", SMBIOS_MEMORY_KEY, index);
+            cm_write_string(&key, "DeviceLocator", device.locator.as_str());
+            cm_write_string(&key, "BankLocator", device.bank.as_str());
+            cm_write_string(&key, "Manufacturer", device.manufacturer.as_str());
+            cm_write_string(&key, "SerialNumber", device.serial.as_str());
+            cm_write_string(&key, "PartNumber", device.part_number.as_str());
+            cm_write_dword(&key, "SizeMB", device.size_mb);
+            cm_write_dword(&key, "Speed", device.speed as u32);
+        }
+    }
+}
@@ -60,6 +60,8 @@ pub enum TimerSource {
     ApicTimer = 3,
     /// 8254 Programmable Interval Timer
     Pit = 4,
+    /// Hypervisor paravirtual clock (kvmclock, Hyper-V reference time)
+    Paravirt = 5,
 }
 
 /// System time epoch
@@ -286,8 +288,15 @@ pub fn get_tsc_frequency_cpuid() -> Option<u64> {
 ///
 /// Returns the current value of the performance counter.
 /// Use with `hal_query_performance_frequency()` to calculate elapsed time.
+/// Under a hypervisor with a paravirtual clock this is the paravirtual
+/// clock rather than the raw TSC.
 #[inline]
 pub fn hal_query_performance_counter() -> u64 {
+    if unsafe { CALIBRATION.timer_source } == TimerSource::Paravirt {
+        if let Some(counter) = super::hv::hv_read_clock() {
+            return counter;
+        }
+    }
     read_tsc()
 }
 
@@ -303,11 +312,20 @@ pub fn hal_query_performance_frequency() -> u64 {
 ///
 /// Returns both the counter value and frequency for atomic access.
 pub fn hal_query_performance_counter_ex() -> (u64, u64) {
-    let counter = read_tsc();
+    let counter = hal_query_performance_counter();
     let freq = unsafe { CALIBRATION.performance_frequency };
     (counter, freq)
 }
 
+/// Query TSC frequency
+///
+/// Returns the frequency of `read_tsc()` in Hz, which differs from the
+/// performance counter frequency when a paravirtual clock is in use.
+#[inline]
+pub fn hal_query_tsc_frequency() -> u64 {
+    unsafe { CALIBRATION.tsc_frequency }
+}
+
 /// Convert performance counter ticks to nanoseconds
 pub fn ticks_to_nanoseconds(ticks: u64) -> u64 {
     let freq = unsafe { CALIBRATION.performance_frequency };
@@ -456,6 +474,20 @@ pub fn hal_calibrate_timers() {
         // Check for invariant TSC
         CALIBRATION.tsc_invariant = is_tsc_invariant();
 
+        // Under a hypervisor, prefer its paravirtual clock and TSC frequency
+        let pv_clock = super::hv::hv_clock_source();
+        if let Some(freq) = super::hv::hv_tsc_frequency().or_else(get_tsc_frequency_cpuid) {
+            if pv_clock != super::hv::PvClockSource::None {
+                CALIBRATION.tsc_frequency = freq;
+                CALIBRATION.performance_frequency = pv_clock.frequency();
+                CALIBRATION.timer_source = TimerSource::Paravirt;
+                CALIBRATION.calibrated = true;
+                crate::serial_println!("[HAL] Performance counter: {} ({} Hz), TSC {} Hz",
+                    pv_clock.name(), pv_clock.frequency(), freq);
+                return;
+            }
+        }
+
         // Try to get TSC frequency from CPUID first
         if let Some(freq) = get_tsc_frequency_cpuid() {
             CALIBRATION.tsc_frequency = freq;
@@ -495,7 +527,7 @@ pub fn hal_is_calibrated() -> bool {
 
 /// Busy-wait for a number of microseconds
 pub fn hal_stall_execution(microseconds: u32) {
-    let freq = unsafe { CALIBRATION.performance_frequency };
+    let freq = unsafe { CALIBRATION.tsc_frequency };
     if freq == 0 {
         // Fallback: simple loop-based delay
         for _ in 0..microseconds * 100 {
@@ -514,7 +546,7 @@ pub fn hal_stall_execution(microseconds: u32) {
 
 /// Busy-wait for a number of nanoseconds
 pub fn hal_stall_execution_ns(nanoseconds: u64) {
-    let freq = unsafe { CALIBRATION.performance_frequency };
+    let freq = unsafe { CALIBRATION.tsc_frequency };
     if freq == 0 {
         return;
     }
@@ -527,6 +559,15 @@ pub fn hal_stall_execution_ns(nanoseconds: u64) {
     }
 }
 
+/// Stall the processor for a number of microseconds (KeStallExecutionProcessor)
+///
+/// For hardware settle times only: the processor does nothing else while
+/// stalled. Waits of a millisecond or more from thread context should
+/// sleep instead (`ki_delay_execution` or an object wait with a timeout).
+pub fn ke_stall_execution_processor(microseconds: u32) {
+    hal_stall_execution(microseconds);
+}
+
 // ============================================================================
 // Timer Statistics
 // ============================================================================
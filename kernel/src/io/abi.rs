@@ -0,0 +1,71 @@
+//! Driver ABI Layout Checks
+//!
+//! Out-of-tree drivers see the I/O manager's objects through the mirrored
+//! definitions in the `wdm` crate. These compile-time assertions fail the
+//! kernel build if a kernel structure changes without the crate following,
+//! or if the kernel's ABI version (`ldr::driver`) moves without the crate's.
+
+use core::mem::{align_of, offset_of, size_of};
+use crate::ke::KEvent;
+use crate::ob::ObjectHeader;
+use super::device::{DeviceObject, DeviceQueue};
+use super::driver::{DriverExtension, DriverObject, IRP_MJ_MAXIMUM_FUNCTION};
+use super::irp::{IoStackLocation, IoStatusBlock, Irp, IrpMajorFunction, IRP_MAX_STACK_SIZE};
+
+macro_rules! assert_same_layout {
+    ($kernel:ty, $wdm:ty) => {
+        const _: () = assert!(size_of::<$kernel>() == size_of::<$wdm>());
+        const _: () = assert!(align_of::<$kernel>() == align_of::<$wdm>());
+    };
+}
+
+macro_rules! assert_same_offset {
+    ($kernel:ty, $wdm:ty, $($field:ident),+) => {
+        $(const _: () = assert!(offset_of!($kernel, $field) == offset_of!($wdm, $field));)+
+    };
+}
+
+// Opaque storage sizes
+const _: () = assert!(size_of::<ObjectHeader>() == wdm::OBJECT_HEADER_SIZE);
+const _: () = assert!(size_of::<DeviceQueue>() == wdm::DEVICE_QUEUE_SIZE);
+const _: () = assert!(size_of::<KEvent>() == wdm::KEVENT_SIZE);
+
+// Table sizes
+const _: () = assert!(IRP_MJ_MAXIMUM_FUNCTION == wdm::IRP_MJ_MAXIMUM_FUNCTION);
+const _: () = assert!(IRP_MAX_STACK_SIZE == wdm::IRP_MAX_STACK_SIZE);
+const _: () = assert!(IrpMajorFunction::Read as usize == wdm::IRP_MJ_READ);
+const _: () = assert!(IrpMajorFunction::DeviceControl as usize == wdm::IRP_MJ_DEVICE_CONTROL);
+const _: () = assert!(IrpMajorFunction::Pnp as usize == wdm::IRP_MJ_PNP);
+
+// ABI version handshake
+const _: () = assert!(crate::ldr::driver::KERNEL_ABI_MAJOR == wdm::WDM_ABI_MAJOR);
+const _: () = assert!(crate::ldr::driver::KERNEL_ABI_MINOR == wdm::WDM_ABI_MINOR);
+const _: () = assert!(crate::ldr::driver::KERNEL_ABI_VERSION.packed() == wdm::WDM_ABI_PACKED);
+assert_same_layout!(crate::ldr::driver::DriverAbiInfo, wdm::DriverAbiInfo);
+assert_same_offset!(crate::ldr::driver::DriverAbiInfo, wdm::DriverAbiInfo,
+    major, minor, driver_version, export_count);
+
+assert_same_layout!(crate::rtl::UnicodeString, wdm::UnicodeString);
+assert_same_layout!(IoStatusBlock, wdm::IoStatusBlock);
+
+assert_same_layout!(DriverObject, wdm::DriverObject);
+assert_same_offset!(DriverObject, wdm::DriverObject,
+    device_object, driver_start, driver_extension, driver_name,
+    driver_init, driver_unload, major_function);
+
+assert_same_layout!(DriverExtension, wdm::DriverExtension);
+assert_same_offset!(DriverExtension, wdm::DriverExtension, add_device, service_key_name);
+
+assert_same_layout!(DeviceObject, wdm::DeviceObject);
+assert_same_offset!(DeviceObject, wdm::DeviceObject,
+    driver_object, next_device, attached_device, flags, device_type,
+    stack_size, dpc, sector_size, device_extension, device_name);
+
+assert_same_layout!(IoStackLocation, wdm::IoStackLocation);
+assert_same_offset!(IoStackLocation, wdm::IoStackLocation,
+    parameters, device_object, completion_routine, completion_context);
+
+assert_same_layout!(Irp, wdm::Irp);
+assert_same_offset!(Irp, wdm::Irp,
+    io_status, current_location, stack_count, user_io_status_block,
+    cancel_routine, user_buffer, system_buffer, mdl_address, tail, stack);
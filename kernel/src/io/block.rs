@@ -24,6 +24,25 @@
 //! │    Driver       │ │    Driver       │ │     Driver      │
 //! └─────────────────┘ └─────────────────┘ └─────────────────┘
 //! ```
+//!
+//! # Removable Media
+//! Drivers for removable devices report a media change with
+//! `BlockStatus::MediaChanged`, either from I/O or from `check_media`.
+//! The device is then marked `VERIFY_VOLUME` (DO_VERIFY_VOLUME) and
+//! further I/O fails with `BlockStatus::VerifyRequired` until the file
+//! system has verified the volume and cleared the flag. Verification
+//! itself reads through `read_sectors_override` (SL_OVERRIDE_VERIFY_VOLUME).
+//!
+//! # Write Caching
+//! Devices with a volatile write cache carry `WRITE_CACHE`. Ordinary
+//! writes may sit in that cache until `flush_device`; `write_sectors_fua`
+//! (forced unit access) returns only once the data is on the media, and
+//! is meant for metadata that must survive a crash. `set_write_cache`
+//! turns the cache off, after which every write is write-through.
+//!
+//! # I/O Priority
+//! Reads and writes are admitted through `iosched` at the issuing thread's
+//! I/O priority, so Low and VeryLow requests yield to foreground I/O.
 
 use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use crate::ke::SpinLock;
@@ -58,6 +77,12 @@ pub enum BlockStatus {
     NotReady = 8,
     /// Bad sector
     BadSector = 9,
+    /// Media was changed since the last access
+    MediaChanged = 10,
+    /// Media changed; the volume must be verified before further I/O
+    VerifyRequired = 11,
+    /// Operation not supported by the device
+    NotSupported = 12,
 }
 
 /// Block device type
@@ -103,6 +128,18 @@ pub mod block_flags {
     pub const PRESENT: u32 = 0x0100;
     /// Device is the boot device
     pub const BOOT: u32 = 0x0200;
+    /// Media changed, volume must be verified (DO_VERIFY_VOLUME)
+    pub const VERIFY_VOLUME: u32 = 0x0400;
+    /// Volatile write cache enabled
+    pub const WRITE_CACHE: u32 = 0x0800;
+}
+
+/// SMART subcommands (ATA SMART feature register)
+pub mod smart_cmd {
+    /// Attribute values
+    pub const READ_DATA: u8 = 0xD0;
+    /// Attribute thresholds
+    pub const READ_THRESHOLDS: u8 = 0xD1;
 }
 
 /// Disk geometry information
@@ -173,6 +210,8 @@ pub struct BlockOps {
     pub read: Option<unsafe fn(dev_index: u8, lba: u64, count: u32, buf: *mut u8) -> BlockStatus>,
     /// Write sectors to device
     pub write: Option<unsafe fn(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> BlockStatus>,
+    /// Write sectors through to the media (forced unit access)
+    pub write_fua: Option<unsafe fn(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> BlockStatus>,
     /// Flush device cache
     pub flush: Option<unsafe fn(dev_index: u8) -> BlockStatus>,
     /// Get device geometry
@@ -181,6 +220,14 @@ pub struct BlockOps {
     pub is_ready: Option<unsafe fn(dev_index: u8) -> bool>,
     /// Reset device
     pub reset: Option<unsafe fn(dev_index: u8) -> BlockStatus>,
+    /// Check for media: Success, NoMedia or MediaChanged
+    pub check_media: Option<unsafe fn(dev_index: u8) -> BlockStatus>,
+    /// Eject removable media
+    pub eject: Option<unsafe fn(dev_index: u8) -> BlockStatus>,
+    /// Enable or disable the volatile write cache
+    pub set_write_cache: Option<unsafe fn(dev_index: u8, enable: bool) -> BlockStatus>,
+    /// Read a 512-byte SMART page (`smart_cmd`)
+    pub smart: Option<unsafe fn(dev_index: u8, command: u8, buf: *mut u8) -> BlockStatus>,
 }
 
 impl BlockOps {
@@ -188,10 +235,15 @@ impl BlockOps {
         Self {
             read: None,
             write: None,
+            write_fua: None,
             flush: None,
             get_geometry: None,
             is_ready: None,
             reset: None,
+            check_media: None,
+            eject: None,
+            set_write_cache: None,
+            smart: None,
         }
     }
 }
@@ -239,6 +291,12 @@ pub struct BlockDevice {
     pub sectors_written: AtomicU64,
     /// Error count
     pub errors: AtomicU32,
+    /// Media changes seen
+    pub media_changes: AtomicU32,
+    /// Cache flushes issued
+    pub flushes: AtomicU64,
+    /// Forced unit access writes
+    pub fua_writes: AtomicU64,
 }
 
 impl BlockDevice {
@@ -261,6 +319,9 @@ impl BlockDevice {
             sectors_read: AtomicU64::new(0),
             sectors_written: AtomicU64::new(0),
             errors: AtomicU32::new(0),
+            media_changes: AtomicU32::new(0),
+            flushes: AtomicU64::new(0),
+            fua_writes: AtomicU64::new(0),
         }
     }
 
@@ -297,6 +358,16 @@ impl BlockDevice {
         (self.flags & block_flags::READONLY) != 0
     }
 
+    /// Check if the device has a volatile write cache enabled
+    pub fn write_cache_enabled(&self) -> bool {
+        (self.flags & block_flags::WRITE_CACHE) != 0
+    }
+
+    /// Check if the volume on the device must be verified
+    pub fn verify_required(&self) -> bool {
+        (self.flags & block_flags::VERIFY_VOLUME) != 0
+    }
+
     /// Set device name
     pub fn set_name(&mut self, name: &str) {
         let bytes = name.as_bytes();
@@ -483,7 +554,21 @@ pub fn device_count() -> u32 {
 // ============================================================================
 
 /// Read sectors from a block device
+///
+/// Fails with `VerifyRequired` after a media change until the volume has
+/// been verified.
 pub fn read_sectors(index: u8, lba: u64, count: u32, buf: &mut [u8]) -> BlockStatus {
+    match get_block_device(index) {
+        Some(dev) if dev.verify_required() => BlockStatus::VerifyRequired,
+        Some(_) => read_sectors_override(index, lba, count, buf),
+        None => BlockStatus::NotFound,
+    }
+}
+
+/// Read sectors even when the device is marked for verification
+///
+/// Used by file systems to verify the volume on the new media.
+pub fn read_sectors_override(index: u8, lba: u64, count: u32, buf: &mut [u8]) -> BlockStatus {
     let dev = match get_block_device(index) {
         Some(d) => d,
         None => return BlockStatus::NotFound,
@@ -506,20 +591,42 @@ pub fn read_sectors(index: u8, lba: u64, count: u32, buf: &mut [u8]) -> BlockSta
         None => return BlockStatus::IoError,
     };
 
+    let _ticket = super::iosched::begin(index);
     let status = unsafe { read_fn(index, lba, count, buf.as_mut_ptr()) };
 
-    if status == BlockStatus::Success {
-        dev.reads.fetch_add(1, Ordering::Relaxed);
-        dev.sectors_read.fetch_add(count as u64, Ordering::Relaxed);
-    } else {
-        dev.errors.fetch_add(1, Ordering::Relaxed);
+    match status {
+        BlockStatus::Success => {
+            dev.reads.fetch_add(1, Ordering::Relaxed);
+            dev.sectors_read.fetch_add(count as u64, Ordering::Relaxed);
+            status
+        }
+        BlockStatus::MediaChanged | BlockStatus::NoMedia => {
+            note_media_change(index);
+            BlockStatus::VerifyRequired
+        }
+        _ => {
+            dev.errors.fetch_add(1, Ordering::Relaxed);
+            status
+        }
     }
-
-    status
 }
 
 /// Write sectors to a block device
+///
+/// The data may still be in the device's write cache on return.
 pub fn write_sectors(index: u8, lba: u64, count: u32, buf: &[u8]) -> BlockStatus {
+    write_sectors_with(index, lba, count, buf, false)
+}
+
+/// Write sectors through to the media (forced unit access)
+///
+/// On a device with a write cache the driver's FUA write is used, or the
+/// write is followed by a cache flush if it has none.
+pub fn write_sectors_fua(index: u8, lba: u64, count: u32, buf: &[u8]) -> BlockStatus {
+    write_sectors_with(index, lba, count, buf, true)
+}
+
+fn write_sectors_with(index: u8, lba: u64, count: u32, buf: &[u8], fua: bool) -> BlockStatus {
     let dev = match get_block_device(index) {
         Some(d) => d,
         None => {
@@ -528,6 +635,10 @@ pub fn write_sectors(index: u8, lba: u64, count: u32, buf: &[u8]) -> BlockStatus
         }
     };
 
+    if dev.verify_required() {
+        return BlockStatus::VerifyRequired;
+    }
+
     // Check write protection
     if dev.is_readonly() {
         crate::serial_println!("[BLOCK] write_sectors: device {} is readonly", index);
@@ -557,11 +668,27 @@ pub fn write_sectors(index: u8, lba: u64, count: u32, buf: &[u8]) -> BlockStatus
         }
     };
 
-    let status = unsafe { write_fn(index, lba, count, buf.as_ptr()) };
+    let _ticket = super::iosched::begin(index);
+
+    // Without a write cache every write already reaches the media
+    let fua = fua && dev.write_cache_enabled();
+    let mut status = match (fua, dev.ops.write_fua) {
+        (true, Some(write_fua)) => unsafe { write_fua(index, lba, count, buf.as_ptr()) },
+        _ => unsafe { write_fn(index, lba, count, buf.as_ptr()) },
+    };
+    if fua && status == BlockStatus::Success {
+        dev.fua_writes.fetch_add(1, Ordering::Relaxed);
+        if dev.ops.write_fua.is_none() {
+            status = flush_device(index);
+        }
+    }
 
     if status == BlockStatus::Success {
         dev.writes.fetch_add(1, Ordering::Relaxed);
         dev.sectors_written.fetch_add(count as u64, Ordering::Relaxed);
+    } else if matches!(status, BlockStatus::MediaChanged | BlockStatus::NoMedia) {
+        note_media_change(index);
+        return BlockStatus::VerifyRequired;
     } else {
         dev.errors.fetch_add(1, Ordering::Relaxed);
         crate::serial_println!("[BLOCK] write_sectors: driver returned {:?}", status);
@@ -571,18 +698,84 @@ pub fn write_sectors(index: u8, lba: u64, count: u32, buf: &[u8]) -> BlockStatus
 }
 
 /// Flush device cache
+///
+/// A barrier: every write completed before the call is on the media when
+/// it returns. Devices without a write cache have nothing to flush.
 pub fn flush_device(index: u8) -> BlockStatus {
     let dev = match get_block_device(index) {
         Some(d) => d,
         None => return BlockStatus::NotFound,
     };
+    if !dev.write_cache_enabled() {
+        return BlockStatus::Success;
+    }
 
+    dev.flushes.fetch_add(1, Ordering::Relaxed);
     match dev.ops.flush {
         Some(f) => unsafe { f(index) },
         None => BlockStatus::Success, // No-op if not supported
     }
 }
 
+/// Flush the write cache of every writable device
+///
+/// # Returns
+/// (devices flushed, devices that failed)
+pub fn flush_all_devices() -> (u32, u32) {
+    let (mut flushed, mut failed) = (0u32, 0u32);
+    for index in 0..MAX_BLOCK_DEVICES as u8 {
+        let Some(dev) = get_block_device(index) else { continue };
+        if dev.is_readonly() {
+            continue;
+        }
+        if flush_device(index) == BlockStatus::Success {
+            flushed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+    (flushed, failed)
+}
+
+/// Enable or disable a device's volatile write cache
+///
+/// The cache is flushed before it is turned off.
+pub fn set_write_cache(index: u8, enable: bool) -> BlockStatus {
+    let dev = match get_block_device(index) {
+        Some(d) => d,
+        None => return BlockStatus::NotFound,
+    };
+    if dev.write_cache_enabled() == enable {
+        return BlockStatus::Success;
+    }
+    let set_fn = match dev.ops.set_write_cache {
+        Some(f) => f,
+        None => return BlockStatus::NotSupported,
+    };
+
+    if !enable {
+        let status = flush_device(index);
+        if status != BlockStatus::Success {
+            return status;
+        }
+    }
+
+    let status = unsafe { set_fn(index, enable) };
+    if status == BlockStatus::Success {
+        let _guard = BLOCK_LOCK.lock();
+        if let Some(dev) = get_block_device_mut(index) {
+            if enable {
+                dev.flags |= block_flags::WRITE_CACHE;
+            } else {
+                dev.flags &= !block_flags::WRITE_CACHE;
+            }
+        }
+        crate::serial_println!("[BLOCK] {}: write cache {}", dev.name_str(),
+            if enable { "enabled" } else { "disabled" });
+    }
+    status
+}
+
 /// Check if device is ready
 pub fn is_device_ready(index: u8) -> bool {
     let dev = match get_block_device(index) {
@@ -609,6 +802,99 @@ pub fn reset_device(index: u8) -> BlockStatus {
     }
 }
 
+// ============================================================================
+// Removable Media
+// ============================================================================
+
+/// Mark a device for verification and pick up the new media's geometry
+fn note_media_change(index: u8) {
+    let _guard = BLOCK_LOCK.lock();
+    let Some(dev) = get_block_device_mut(index) else {
+        return;
+    };
+    if !dev.verify_required() {
+        dev.media_changes.fetch_add(1, Ordering::Relaxed);
+        crate::serial_println!("[BLOCK] {}: media changed, volume must be verified", dev.name_str());
+    }
+    dev.flags |= block_flags::VERIFY_VOLUME;
+    if let Some(get_geometry) = dev.ops.get_geometry {
+        dev.geometry = unsafe { get_geometry(index) };
+    }
+}
+
+/// Check a removable device for a media change
+///
+/// # Returns
+/// `Success` if the media is unchanged, `MediaChanged` (the device is
+/// now marked for verification) or `NoMedia`. Fixed devices always
+/// report `Success`.
+pub fn check_media(index: u8) -> BlockStatus {
+    let dev = match get_block_device(index) {
+        Some(d) => d,
+        None => return BlockStatus::NotFound,
+    };
+    if !dev.is_removable() {
+        return BlockStatus::Success;
+    }
+
+    let status = match dev.ops.check_media {
+        Some(f) => unsafe { f(index) },
+        None => BlockStatus::Success,
+    };
+    match status {
+        BlockStatus::MediaChanged | BlockStatus::NoMedia => note_media_change(index),
+        BlockStatus::Success if dev.verify_required() => return BlockStatus::MediaChanged,
+        _ => {}
+    }
+    status
+}
+
+/// Check whether a device is marked for verification
+pub fn verify_required(index: u8) -> bool {
+    get_block_device(index).is_some_and(|dev| dev.verify_required())
+}
+
+/// Clear the verify mark once the file system has verified the volume
+pub fn clear_verify(index: u8) {
+    let _guard = BLOCK_LOCK.lock();
+    if let Some(dev) = get_block_device_mut(index) {
+        dev.flags &= !block_flags::VERIFY_VOLUME;
+    }
+}
+
+/// Eject the media of a removable device
+pub fn eject_media(index: u8) -> BlockStatus {
+    let dev = match get_block_device(index) {
+        Some(d) => d,
+        None => return BlockStatus::NotFound,
+    };
+    if !dev.is_removable() {
+        return BlockStatus::InvalidParameter;
+    }
+
+    let status = match dev.ops.eject {
+        Some(f) => unsafe { f(index) },
+        None => BlockStatus::NotSupported,
+    };
+    if status == BlockStatus::Success {
+        note_media_change(index);
+    }
+    status
+}
+
+/// Read a SMART page from a device
+pub fn smart_command(index: u8, command: u8, buf: &mut [u8; SECTOR_SIZE]) -> BlockStatus {
+    let dev = match get_block_device(index) {
+        Some(d) => d,
+        None => return BlockStatus::NotFound,
+    };
+
+    match dev.ops.smart {
+        Some(f) => unsafe { f(index, command, buf.as_mut_ptr()) },
+        None => BlockStatus::NotSupported,
+    }
+}
+
 // ============================================================================
 // Convenience Functions for File System
 // ============================================================================
@@ -740,6 +1026,10 @@ pub struct BlockDeviceSnapshot {
     pub sectors_written: u64,
     /// Errors
     pub errors: u32,
+    /// Cache flushes
+    pub flushes: u64,
+    /// Forced unit access writes
+    pub fua_writes: u64,
     /// Is present
     pub present: bool,
     /// Model string (truncated)
@@ -760,6 +1050,8 @@ impl BlockDeviceSnapshot {
             sectors_read: 0,
             sectors_written: 0,
             errors: 0,
+            flushes: 0,
+            fua_writes: 0,
             present: false,
             model: [0u8; 32],
         }
@@ -792,6 +1084,8 @@ pub fn io_get_block_snapshots(max_count: usize) -> ([BlockDeviceSnapshot; 16], u
                 snap.sectors_read = dev.sectors_read.load(Ordering::Relaxed);
                 snap.sectors_written = dev.sectors_written.load(Ordering::Relaxed);
                 snap.errors = dev.errors.load(Ordering::Relaxed);
+                snap.flushes = dev.flushes.load(Ordering::Relaxed);
+                snap.fua_writes = dev.fua_writes.load(Ordering::Relaxed);
                 snap.present = dev.is_present();
                 // Copy model (truncated to 32 bytes)
                 let model_len = dev.model.iter().position(|&b| b == 0).unwrap_or(32).min(32);
@@ -511,6 +511,9 @@ pub unsafe fn io_delete_device(device: *mut DeviceObject) {
         return;
     }
 
+    // Symbolic links to the device go with it
+    crate::ob::ob_delete_owned_symbolic_links(device as usize);
+
     let _guard = DEVICE_POOL_LOCK.lock();
 
     // Remove from driver's device list
@@ -569,6 +572,44 @@ pub unsafe fn io_attach_device(
     top
 }
 
+// ============================================================================
+// Symbolic Links
+// ============================================================================
+
+/// Find an allocated device object by name, ignoring case
+pub fn io_find_device(device_name: &str) -> *mut DeviceObject {
+    let _guard = DEVICE_POOL_LOCK.lock();
+
+    unsafe {
+        for i in 0..MAX_DEVICES {
+            if DEVICE_POOL_BITMAP & (1 << i) == 0 {
+                continue;
+            }
+            let device = &mut DEVICE_POOL[i] as *mut DeviceObject;
+            if (*device).name().eq_ignore_ascii_case(device_name.as_bytes()) {
+                return device;
+            }
+        }
+    }
+
+    ptr::null_mut()
+}
+
+/// Create a symbolic link to a device (IoCreateSymbolicLink)
+///
+/// When `device_name` names a device object the link belongs to it and
+/// is deleted by `io_delete_device`; otherwise it lasts until
+/// `io_delete_symbolic_link`.
+pub fn io_create_symbolic_link(link_name: &str, device_name: &str) -> i32 {
+    let owner = io_find_device(device_name) as usize;
+    crate::ob::ob_create_owned_symbolic_link(link_name, device_name, owner)
+}
+
+/// Delete a symbolic link created by `io_create_symbolic_link`
+pub fn io_delete_symbolic_link(link_name: &str) -> i32 {
+    crate::ob::ob_delete_symbolic_link(link_name)
+}
+
 /// Initialize device subsystem
 pub unsafe fn init_device_system() {
     crate::serial_println!("[IO] Device subsystem initialized ({} devices available)", MAX_DEVICES);
@@ -7,11 +7,16 @@
 //! - Physical disks: \Device\Harddisk0, \Device\Harddisk1, ...
 //! - Partitions: \Device\HarddiskVolume1, \Device\HarddiskVolume2, ...
 //! - Drive letters: C:\, D:\, E:\, ...
+//!
+//! # Removable Media
+//! Removable media without a partition table (CDs, superfloppies) get a
+//! single volume spanning the whole device. After a media change the
+//! disk's volumes are dropped and rescanned with `rescan_disk`.
 
 use crate::ke::SpinLock;
 use super::block::{
     BlockStatus, SECTOR_SIZE,
-    get_block_device, read_sectors, write_sectors, device_count,
+    get_block_device, read_sectors, write_sectors, write_sectors_fua, flush_device, device_count,
 };
 
 /// Maximum partitions per disk
@@ -20,6 +25,9 @@ pub const MAX_PARTITIONS_PER_DISK: usize = 4;
 /// Maximum total volumes
 pub const MAX_VOLUMES: usize = 32;
 
+/// Partition index of a volume spanning unpartitioned media
+pub const WHOLE_MEDIA_PARTITION: u8 = 0xFF;
+
 /// Partition type codes
 pub mod partition_type {
     pub const EMPTY: u8 = 0x00;
@@ -290,11 +298,49 @@ fn read_mbr(disk_index: u8) -> Option<Mbr> {
     }
 }
 
+/// Add a volume covering the whole of unpartitioned removable media
+fn add_whole_media_volume(disk_index: u8) -> u32 {
+    let dev = match get_block_device(disk_index) {
+        Some(d) => d,
+        None => return 0,
+    };
+    if !dev.is_removable() || dev.geometry.total_sectors == 0 {
+        return 0;
+    }
+
+    let _guard = VOLUME_LOCK.lock();
+
+    unsafe {
+        for vol in VOLUMES.iter_mut() {
+            if !vol.active {
+                *vol = Volume::empty();
+                vol.active = true;
+                vol.disk_index = disk_index;
+                vol.partition_index = WHOLE_MEDIA_PARTITION;
+                vol.volume_number = NEXT_VOLUME;
+                NEXT_VOLUME += 1;
+                vol.total_sectors = dev.geometry.total_sectors;
+                vol.sector_size = dev.geometry.sector_size;
+
+                crate::serial_println!(
+                    "[DISK] Volume {}: Disk {} whole media ({} MB)",
+                    vol.volume_number,
+                    disk_index,
+                    vol.size_mb()
+                );
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
 /// Scan partitions on a disk
 fn scan_partitions(disk_index: u8) -> u32 {
     let mbr = match read_mbr(disk_index) {
         Some(m) => m,
-        None => return 0,
+        None => return add_whole_media_volume(disk_index),
     };
 
     let dev = match get_block_device(disk_index) {
@@ -597,6 +643,28 @@ pub fn volume_write(volume_number: u8, offset_sectors: u64, count: u32, buf: &[u
     status
 }
 
+/// Write sectors to volume, bypassing the disk's write cache
+pub fn volume_write_fua(volume_number: u8, offset_sectors: u64, count: u32, buf: &[u8]) -> BlockStatus {
+    let vol = match get_volume(volume_number) {
+        Some(v) => v,
+        None => return BlockStatus::NotFound,
+    };
+
+    if offset_sectors + count as u64 > vol.total_sectors {
+        return BlockStatus::InvalidParameter;
+    }
+
+    write_sectors_fua(vol.disk_index, vol.start_lba + offset_sectors, count, buf)
+}
+
+/// Flush the write cache of the disk holding a volume
+pub fn volume_flush(volume_number: u8) -> BlockStatus {
+    match get_volume(volume_number) {
+        Some(vol) => flush_device(vol.disk_index),
+        None => BlockStatus::NotFound,
+    }
+}
+
 /// Read single sector from volume
 pub fn volume_read_sector(volume_number: u8, offset_sectors: u64, buf: &mut [u8; SECTOR_SIZE]) -> bool {
     volume_read(volume_number, offset_sectors, 1, buf) == BlockStatus::Success
@@ -629,6 +697,24 @@ pub fn get_volume_write_callback(volume_number: u8) -> Option<unsafe fn(*mut u8,
     }
 }
 
+/// Get volume forced unit access write callback for file system
+pub fn get_volume_write_fua_callback(volume_number: u8) -> Option<unsafe fn(*mut u8, u64, &[u8]) -> bool> {
+    if get_volume(volume_number).is_some() {
+        Some(volume_fs_write_fua)
+    } else {
+        None
+    }
+}
+
+/// Get volume cache flush callback for file system
+pub fn get_volume_flush_callback(volume_number: u8) -> Option<unsafe fn(*mut u8) -> bool> {
+    if get_volume(volume_number).is_some() {
+        Some(volume_fs_flush)
+    } else {
+        None
+    }
+}
+
 /// File system read callback
 unsafe fn volume_fs_read(device: *mut u8, sector: u64, buf: &mut [u8]) -> bool {
     let volume_number = device as u8;
@@ -641,6 +727,18 @@ unsafe fn volume_fs_write(device: *mut u8, sector: u64, buf: &[u8]) -> bool {
     volume_write(volume_number, sector, 1, buf) == BlockStatus::Success
 }
 
+/// File system forced unit access write callback
+unsafe fn volume_fs_write_fua(device: *mut u8, sector: u64, buf: &[u8]) -> bool {
+    let volume_number = device as u8;
+    volume_write_fua(volume_number, sector, 1, buf) == BlockStatus::Success
+}
+
+/// File system cache flush callback
+unsafe fn volume_fs_flush(device: *mut u8) -> bool {
+    let volume_number = device as u8;
+    volume_flush(volume_number) == BlockStatus::Success
+}
+
 // ============================================================================
 // Initialization
 // ============================================================================
@@ -665,6 +763,53 @@ pub fn scan_all_disks() -> u32 {
     total_volumes
 }
 
+/// Drop every volume on a disk (media removed or changed)
+///
+/// Returns the number of volumes removed.
+pub fn remove_disk_volumes(disk_index: u8) -> u32 {
+    let _guard = VOLUME_LOCK.lock();
+    let mut count = 0u32;
+
+    unsafe {
+        for vol in VOLUMES.iter_mut() {
+            if vol.active && vol.disk_index == disk_index {
+                crate::serial_println!("[DISK] Volume {} removed (disk {})", vol.volume_number, disk_index);
+                *vol = Volume::empty();
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+/// Rescan a disk after a media change
+///
+/// Existing volumes on the disk are dropped first; the new ones get fresh
+/// volume numbers. Returns the number of volumes found.
+pub fn rescan_disk(disk_index: u8) -> u32 {
+    remove_disk_volumes(disk_index);
+    scan_partitions(disk_index)
+}
+
+/// Volumes on a disk, in table order
+pub fn disk_volumes(disk_index: u8) -> ([u8; MAX_VOLUMES], usize) {
+    let _guard = VOLUME_LOCK.lock();
+    let mut numbers = [0u8; MAX_VOLUMES];
+    let mut count = 0;
+
+    unsafe {
+        for vol in VOLUMES.iter() {
+            if vol.active && vol.disk_index == disk_index {
+                numbers[count] = vol.volume_number;
+                count += 1;
+            }
+        }
+    }
+
+    (numbers, count)
+}
+
 /// Initialize disk subsystem
 pub fn init() {
     crate::serial_println!("[DISK] Disk subsystem initializing...");
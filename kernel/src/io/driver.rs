@@ -13,6 +13,10 @@
 //! Each major function (Create, Read, Write, etc.) has a dispatch
 //! routine in the driver object. The I/O manager calls these routines
 //! when an IRP of that type is sent to a device owned by the driver.
+//!
+//! # ABI
+//! Callback types use the C calling convention so out-of-tree drivers
+//! built against the `wdm` crate can fill in the dispatch table directly.
 
 use core::ptr;
 use crate::ke::SpinLock;
@@ -30,7 +34,7 @@ pub const IRP_MJ_MAXIMUM_FUNCTION: usize = 28;
 ///
 /// Called by the I/O manager to handle an IRP for a device.
 /// Returns NTSTATUS code.
-pub type DriverDispatch = fn(
+pub type DriverDispatch = extern "C" fn(
     device: *mut DeviceObject,
     irp: *mut Irp,
 ) -> i32;
@@ -38,20 +42,22 @@ pub type DriverDispatch = fn(
 /// Driver unload routine type
 ///
 /// Called when the driver is being unloaded.
-pub type DriverUnload = fn(driver: *mut DriverObject);
+pub type DriverUnload = extern "C" fn(driver: *mut DriverObject);
 
 /// Driver initialization routine type
 ///
-/// Called when the driver is loaded.
-pub type DriverInitialize = fn(
+/// Called when the driver is loaded. `abi` describes the running kernel's
+/// driver ABI (see `ldr::driver`).
+pub type DriverInitialize = extern "C" fn(
     driver: *mut DriverObject,
-    registry_path: *const u8,
+    registry_path: *const crate::rtl::UnicodeString,
+    abi: *const crate::ldr::driver::DriverAbiInfo,
 ) -> i32;
 
 /// Driver add device routine type (for PnP)
 ///
 /// Called when a new device is detected.
-pub type DriverAddDevice = fn(
+pub type DriverAddDevice = extern "C" fn(
     driver: *mut DriverObject,
     physical_device: *mut DeviceObject,
 ) -> i32;
@@ -59,7 +65,7 @@ pub type DriverAddDevice = fn(
 /// Driver start I/O routine type
 ///
 /// Called to start I/O on a device (for StartIo-based drivers)
-pub type DriverStartIo = fn(
+pub type DriverStartIo = extern "C" fn(
     device: *mut DeviceObject,
     irp: *mut Irp,
 );
@@ -190,7 +196,7 @@ impl Default for DriverObject {
 }
 
 /// Default dispatch routine - returns not implemented
-fn default_dispatch(_device: *mut DeviceObject, irp: *mut Irp) -> i32 {
+extern "C" fn default_dispatch(_device: *mut DeviceObject, irp: *mut Irp) -> i32 {
     unsafe {
         if !irp.is_null() {
             (*irp).io_status.status = -1073741822; // STATUS_NOT_IMPLEMENTED
@@ -0,0 +1,277 @@
+//! I/O Priority Scheduling
+//!
+//! Every block request runs at the I/O priority of the thread that issued
+//! it: the thread's own priority when it has been moved off Normal, or else
+//! its process's (set with NtSetInformationProcess(ProcessIoPriority) or
+//! `start /low`). IRPs carry the same priority in their flags.
+//!
+//! Normal and higher requests go straight to the driver. Low and VeryLow
+//! requests are background I/O:
+//!
+//! - they wait until the device has seen no foreground I/O for an idle gap
+//! - only one background request is in flight per device
+//! - the wait is capped, so background work still progresses on a disk
+//!   that is never idle
+//!
+//! Requests issued at raised IRQL or without a current thread can't wait
+//! and are dispatched immediately.
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use crate::hal::apic;
+use crate::ke::kpcr::{irql, ke_get_current_irql};
+use super::block::MAX_BLOCK_DEVICES;
+
+/// I/O priority levels (IO_PRIORITY_HINT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum IoPriority {
+    VeryLow = 0,
+    Low = 1,
+    Normal = 2,
+    High = 3,
+    Critical = 4,
+}
+
+impl IoPriority {
+    /// Number of priority levels
+    pub const COUNT: usize = 5;
+
+    /// All levels, lowest first
+    pub const ALL: [IoPriority; Self::COUNT] = [
+        IoPriority::VeryLow,
+        IoPriority::Low,
+        IoPriority::Normal,
+        IoPriority::High,
+        IoPriority::Critical,
+    ];
+
+    /// Convert a raw IO_PRIORITY_HINT value
+    pub fn from_raw(value: u32) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    /// Parse a priority name (case-insensitive)
+    pub fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("verylow") {
+            return Some(IoPriority::VeryLow);
+        }
+        Self::ALL.iter().copied().find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            IoPriority::VeryLow => "very-low",
+            IoPriority::Low => "low",
+            IoPriority::Normal => "normal",
+            IoPriority::High => "high",
+            IoPriority::Critical => "critical",
+        }
+    }
+
+    /// Background priorities are throttled behind foreground I/O
+    pub fn is_background(self) -> bool {
+        self <= IoPriority::Low
+    }
+
+    /// Foreground-idle gap and longest wait for a background request (ms)
+    fn throttle_limits(self) -> (u64, u64) {
+        match self {
+            IoPriority::VeryLow => (VERY_LOW_IDLE_GAP_MS, VERY_LOW_MAX_DELAY_MS),
+            _ => (LOW_IDLE_GAP_MS, LOW_MAX_DELAY_MS),
+        }
+    }
+}
+
+/// Idle gap a Low request waits for after the last foreground I/O
+const LOW_IDLE_GAP_MS: u64 = 10;
+
+/// Idle gap a VeryLow request waits for after the last foreground I/O
+const VERY_LOW_IDLE_GAP_MS: u64 = 50;
+
+/// Longest a Low request is held back
+const LOW_MAX_DELAY_MS: u64 = 500;
+
+/// Longest a VeryLow request is held back
+const VERY_LOW_MAX_DELAY_MS: u64 = 2000;
+
+/// Poll interval while a background request is held back
+const THROTTLE_POLL_MS: u64 = 5;
+
+/// Per-device queue state
+struct DeviceQueue {
+    /// Foreground requests in flight
+    foreground: AtomicU32,
+    /// Background requests in flight
+    background: AtomicU32,
+    /// Tick of the last foreground dispatch or completion
+    last_foreground: AtomicU64,
+}
+
+impl DeviceQueue {
+    const fn new() -> Self {
+        Self {
+            foreground: AtomicU32::new(0),
+            background: AtomicU32::new(0),
+            last_foreground: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Per-priority counters
+struct PriorityCounters {
+    dispatched: AtomicU64,
+    throttled: AtomicU64,
+    delay_ms: AtomicU64,
+}
+
+impl PriorityCounters {
+    const fn new() -> Self {
+        Self {
+            dispatched: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            delay_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+static QUEUES: [DeviceQueue; MAX_BLOCK_DEVICES] = {
+    const INIT: DeviceQueue = DeviceQueue::new();
+    [INIT; MAX_BLOCK_DEVICES]
+};
+
+static COUNTERS: [PriorityCounters; IoPriority::COUNT] = {
+    const INIT: PriorityCounters = PriorityCounters::new();
+    [INIT; IoPriority::COUNT]
+};
+
+/// Per-priority scheduler statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoPriorityStats {
+    /// Requests dispatched at this priority
+    pub dispatched: u64,
+    /// Requests that were held back behind foreground I/O
+    pub throttled: u64,
+    /// Total time requests were held back (ms)
+    pub delay_ms: u64,
+}
+
+/// A request admitted by the scheduler
+///
+/// Dropping the ticket marks the request complete.
+pub struct IoTicket {
+    index: usize,
+    priority: IoPriority,
+}
+
+impl Drop for IoTicket {
+    fn drop(&mut self) {
+        let queue = &QUEUES[self.index];
+        if self.priority.is_background() {
+            queue.background.fetch_sub(1, Ordering::AcqRel);
+        } else {
+            queue.last_foreground.store(apic::get_tick_count(), Ordering::Relaxed);
+            queue.foreground.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Get the I/O priority of the current thread
+///
+/// The thread's own priority wins when it is not Normal; otherwise the
+/// process priority applies. Kernel worker threads use their process's.
+pub fn current_io_priority() -> IoPriority {
+    let thread = unsafe { crate::ke::prcb::get_current_prcb_mut().current_thread };
+    if thread.is_null() {
+        return IoPriority::Normal;
+    }
+
+    if let Some(ethread) = crate::ps::ethread_from_kthread(thread) {
+        let priority = unsafe { (*ethread).io_priority };
+        if priority != IoPriority::Normal as u8 {
+            return IoPriority::from_raw(priority as u32).unwrap_or(IoPriority::Normal);
+        }
+    }
+
+    let process = crate::ps::get_current_process();
+    if process.is_null() {
+        return IoPriority::Normal;
+    }
+    IoPriority::from_raw(unsafe { (*process).io_priority } as u32).unwrap_or(IoPriority::Normal)
+}
+
+/// Admit a request on a block device at the current thread's priority
+pub fn begin(index: u8) -> IoTicket {
+    begin_with_priority(index, current_io_priority())
+}
+
+/// Admit a request on a block device at an explicit priority
+///
+/// Background requests may block here until the device is idle.
+pub fn begin_with_priority(index: u8, priority: IoPriority) -> IoTicket {
+    let index = (index as usize).min(MAX_BLOCK_DEVICES - 1);
+    let queue = &QUEUES[index];
+    let counters = &COUNTERS[priority as usize];
+    counters.dispatched.fetch_add(1, Ordering::Relaxed);
+
+    if !priority.is_background() {
+        queue.foreground.fetch_add(1, Ordering::AcqRel);
+        queue.last_foreground.store(apic::get_tick_count(), Ordering::Relaxed);
+        return IoTicket { index, priority };
+    }
+
+    let (idle_gap, max_delay) = priority.throttle_limits();
+    let can_wait = ke_get_current_irql() == irql::PASSIVE_LEVEL
+        && unsafe { !crate::ke::prcb::get_current_prcb_mut().current_thread.is_null() };
+    let start = apic::get_tick_count();
+    let mut throttled = false;
+
+    loop {
+        let now = apic::get_tick_count();
+        let idle = queue.foreground.load(Ordering::Acquire) == 0
+            && now.saturating_sub(queue.last_foreground.load(Ordering::Relaxed)) >= idle_gap;
+        if idle && queue.background
+            .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            break;
+        }
+
+        // Out of patience: go anyway rather than starve
+        if !can_wait || now.saturating_sub(start) >= max_delay {
+            queue.background.fetch_add(1, Ordering::AcqRel);
+            break;
+        }
+
+        throttled = true;
+        unsafe { crate::ke::wait::ke_delay_execution_alertable(THROTTLE_POLL_MS, false); }
+    }
+
+    if throttled {
+        counters.throttled.fetch_add(1, Ordering::Relaxed);
+        counters.delay_ms.fetch_add(apic::get_tick_count().saturating_sub(start), Ordering::Relaxed);
+    }
+
+    IoTicket { index, priority }
+}
+
+/// Get scheduler statistics for a priority level
+pub fn priority_stats(priority: IoPriority) -> IoPriorityStats {
+    let counters = &COUNTERS[priority as usize];
+    IoPriorityStats {
+        dispatched: counters.dispatched.load(Ordering::Relaxed),
+        throttled: counters.throttled.load(Ordering::Relaxed),
+        delay_ms: counters.delay_ms.load(Ordering::Relaxed),
+    }
+}
+
+/// Get the foreground and background requests in flight on a device
+pub fn device_inflight(index: u8) -> (u32, u32) {
+    match QUEUES.get(index as usize) {
+        Some(queue) => (
+            queue.foreground.load(Ordering::Relaxed),
+            queue.background.load(Ordering::Relaxed),
+        ),
+        None => (0, 0),
+    }
+}
@@ -20,6 +20,7 @@
 use core::ptr;
 use core::sync::atomic::{AtomicU32, Ordering};
 use crate::ke::{list::ListEntry, KEvent, SpinLock};
+use super::iosched::IoPriority;
 
 /// Maximum number of stack locations per IRP
 pub const IRP_MAX_STACK_SIZE: usize = 8;
@@ -54,6 +55,10 @@ pub mod irp_flags {
     pub const IRP_NOCACHE: u32 = 0x1000;
     /// IRP has been completed
     pub const IRP_COMPLETED: u32 = 0x2000;
+    /// I/O priority of the issuing thread (see `Irp::io_priority`)
+    pub const IRP_PRIORITY_MASK: u32 = 0x0007_0000;
+    /// Shift of the I/O priority field
+    pub const IRP_PRIORITY_SHIFT: u32 = 16;
 }
 
 /// Major function codes (IRP types)
@@ -181,7 +186,7 @@ pub mod sl_control {
 }
 
 /// I/O completion routine type
-pub type IoCompletionRoutine = fn(
+pub type IoCompletionRoutine = extern "C" fn(
     device: *mut super::device::DeviceObject,
     irp: *mut Irp,
     context: *mut u8,
@@ -375,7 +380,7 @@ pub struct Irp {
 }
 
 /// Cancel routine type
-pub type CancelRoutine = fn(
+pub type CancelRoutine = extern "C" fn(
     device: *mut super::device::DeviceObject,
     irp: *mut Irp,
 );
@@ -483,12 +488,16 @@ impl Irp {
     }
 
     /// Initialize an IRP
+    ///
+    /// The IRP is issued by the current thread and inherits its I/O priority.
     pub fn init(&mut self, stack_size: i8) {
         self.type_id = 0x0006;
         self.size = core::mem::size_of::<Self>() as u16;
         self.stack_count = stack_size;
         self.current_location = stack_size; // Start past the end
         self.flags = AtomicU32::new(0);
+        self.thread = unsafe { crate::ke::prcb::get_current_prcb_mut().current_thread };
+        self.set_io_priority(super::iosched::current_io_priority());
         self.io_status = IoStatusBlock::new();
         self.cancel = false;
         self.pending_returned = false;
@@ -590,6 +599,20 @@ impl Irp {
     pub fn is_pending(&self) -> bool {
         self.has_flag(irp_flags::IRP_PENDING)
     }
+
+    /// Get the I/O priority the IRP was issued at (IoGetIoPriorityHint)
+    pub fn io_priority(&self) -> IoPriority {
+        let flags = self.flags.load(Ordering::SeqCst);
+        let raw = (flags & irp_flags::IRP_PRIORITY_MASK) >> irp_flags::IRP_PRIORITY_SHIFT;
+        IoPriority::from_raw(raw).unwrap_or(IoPriority::Normal)
+    }
+
+    /// Set the I/O priority of the IRP (IoSetIoPriorityHint)
+    pub fn set_io_priority(&self, priority: IoPriority) {
+        let bits = (priority as u32) << irp_flags::IRP_PRIORITY_SHIFT;
+        self.clear_flag(irp_flags::IRP_PRIORITY_MASK & !bits);
+        self.set_flag(bits);
+    }
 }
 
 impl Default for Irp {
@@ -715,6 +738,8 @@ pub struct IrpSnapshot {
     pub is_cancelled: bool,
     /// Thread ID (if available)
     pub thread_id: u32,
+    /// I/O priority
+    pub io_priority: IoPriority,
 }
 
 /// Get IRP pool statistics
@@ -767,6 +792,7 @@ pub fn io_get_irp_snapshots(max_count: usize) -> ([IrpSnapshot; 32], usize) {
         is_pending: false,
         is_cancelled: false,
         thread_id: 0,
+        io_priority: IoPriority::Normal,
     }; 32];
 
     let max_count = max_count.min(32);
@@ -818,6 +844,7 @@ pub fn io_get_irp_snapshots(max_count: usize) -> ([IrpSnapshot; 32], usize) {
                         is_pending: (flags & irp_flags::IRP_PENDING) != 0,
                         is_cancelled: irp.cancel,
                         thread_id,
+                        io_priority: irp.io_priority(),
                     };
                     count += 1;
                 }
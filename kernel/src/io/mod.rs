@@ -31,13 +31,17 @@ pub mod driver;
 pub mod file;
 pub mod complete;
 pub mod block;
+pub mod iosched;
 pub mod disk;
 pub mod iocp;
 pub mod pipe;
 pub mod ramdisk;
+pub mod storport;
+pub mod smart;
 pub mod pnp;
 pub mod csq;
 pub mod volmgr;
+mod abi;
 pub mod mup;
 pub mod fat32;
 pub mod vfs;
@@ -69,6 +73,9 @@ pub use device::{
     io_create_device,
     io_delete_device,
     io_attach_device,
+    io_find_device,
+    io_create_symbolic_link,
+    io_delete_symbolic_link,
     DevicePoolStats,
     DeviceSnapshot,
     io_get_device_stats,
@@ -159,6 +166,31 @@ pub use disk::{
     MAX_VOLUMES,
 };
 
+pub use storport::{
+    MiniportOps,
+    Srb,
+    SrbStatus,
+    StorPortAdapterStats,
+    storport_register_adapter,
+    storport_add_target,
+    storport_adapter_stats,
+};
+
+pub use iosched::{
+    IoPriority,
+    IoPriorityStats,
+    current_io_priority,
+};
+
+pub use smart::{
+    SmartAttribute,
+    SmartReport,
+    SmartServiceStatus,
+    smart_read,
+    smart_check_all,
+    smart_service_status,
+};
+
 pub use iocp::{
     IoCompletionPort,
     IoCompletionPacket,
@@ -404,6 +436,11 @@ pub fn init_storage() {
     // Initialize ATA/IDE driver (detects disks)
     crate::hal::ata::init();
 
+    // Initialize VirtIO block driver (QEMU virtio disks)
+    if let Err(e) = crate::drivers::virtio::blk::init() {
+        crate::serial_println!("[IO] VirtIO block: {}", e);
+    }
+
     // Initialize RAM disk subsystem
     ramdisk::init();
 
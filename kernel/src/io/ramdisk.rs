@@ -262,10 +262,15 @@ pub fn create_ramdisk_with_size(size: usize) -> Option<u8> {
     let ops = BlockOps {
         read: Some(ramdisk_read),
         write: Some(ramdisk_write),
+        write_fua: None,
         flush: Some(ramdisk_flush),
         get_geometry: Some(ramdisk_get_geometry),
         is_ready: Some(ramdisk_is_ready),
         reset: Some(ramdisk_reset),
+        check_media: None,
+        eject: None,
+        set_write_cache: None,
+        smart: None,
     };
 
     // Register with block layer
@@ -0,0 +1,416 @@
+//! Disk Health Monitoring (SMART)
+//!
+//! Reads the SMART attribute and threshold pages of each disk through the
+//! block layer and decodes them the way `smartctl -A` does. An attribute
+//! whose normalized value has fallen to its threshold is failing; a failing
+//! pre-failure attribute means the drive is expected to fail soon.
+//!
+//! The health service re-checks every disk every `CHECK_INTERVAL_MS` and
+//! writes an event log entry when:
+//!
+//! - an attribute trips its threshold (logged once per trip)
+//! - the reallocated sector count grows
+//! - the temperature rises above `TEMPERATURE_LIMIT`
+//!
+//! Only ATA disks report SMART (via the SAT pass-through of the storage
+//! port layer); other devices answer `NotSupported` and are skipped.
+
+extern crate alloc;
+
+use alloc::format;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::ex::eventlog::{log_error_fmt, log_info_fmt, log_warning_fmt, EventSource};
+use crate::hal::apic;
+use crate::ke::{EventType, KEvent, SpinLock};
+use super::block::{self, smart_cmd, BlockStatus, MAX_BLOCK_DEVICES, SECTOR_SIZE};
+
+/// Attribute entries in a SMART page
+pub const MAX_ATTRIBUTES: usize = 30;
+
+/// Interval between health checks
+pub const CHECK_INTERVAL_MS: u64 = 30 * 60 * 1000;
+
+/// Temperature (Celsius) above which a warning is logged
+pub const TEMPERATURE_LIMIT: u32 = 60;
+
+/// Service thread priority
+const SERVICE_PRIORITY: i8 = 6;
+
+/// Size of one attribute entry
+const ATTRIBUTE_SIZE: usize = 12;
+
+/// Event IDs
+mod event_id {
+    pub const SERVICE_STARTED: u32 = 7100;
+    pub const THRESHOLD_EXCEEDED: u32 = 7101;
+    pub const REALLOCATED_SECTORS: u32 = 7102;
+    pub const TEMPERATURE: u32 = 7103;
+}
+
+/// Well-known attribute IDs
+pub mod attribute_id {
+    pub const REALLOCATED_SECTOR_COUNT: u8 = 5;
+    pub const POWER_ON_HOURS: u8 = 9;
+    pub const AIRFLOW_TEMPERATURE: u8 = 190;
+    pub const TEMPERATURE: u8 = 194;
+}
+
+/// Attribute flag: failure predicts imminent drive failure
+const FLAG_PREFAILURE: u16 = 0x0001;
+
+/// One decoded SMART attribute
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmartAttribute {
+    pub id: u8,
+    pub flags: u16,
+    /// Normalized value (higher is better)
+    pub value: u8,
+    /// Worst normalized value seen
+    pub worst: u8,
+    /// Vendor-specific raw value (48 bits)
+    pub raw: u64,
+    /// Failure threshold for `value` (0 = never fails)
+    pub threshold: u8,
+}
+
+impl SmartAttribute {
+    /// Attribute name, as printed by smartctl
+    pub fn name(&self) -> &'static str {
+        match self.id {
+            1 => "Raw_Read_Error_Rate",
+            2 => "Throughput_Performance",
+            3 => "Spin_Up_Time",
+            4 => "Start_Stop_Count",
+            5 => "Reallocated_Sector_Ct",
+            7 => "Seek_Error_Rate",
+            8 => "Seek_Time_Performance",
+            9 => "Power_On_Hours",
+            10 => "Spin_Retry_Count",
+            11 => "Calibration_Retry_Count",
+            12 => "Power_Cycle_Count",
+            177 => "Wear_Leveling_Count",
+            183 => "Runtime_Bad_Block",
+            184 => "End-to-End_Error",
+            187 => "Reported_Uncorrect",
+            188 => "Command_Timeout",
+            190 => "Airflow_Temperature_Cel",
+            191 => "G-Sense_Error_Rate",
+            192 => "Power-Off_Retract_Count",
+            193 => "Load_Cycle_Count",
+            194 => "Temperature_Celsius",
+            195 => "Hardware_ECC_Recovered",
+            196 => "Reallocated_Event_Count",
+            197 => "Current_Pending_Sector",
+            198 => "Offline_Uncorrectable",
+            199 => "UDMA_CRC_Error_Count",
+            200 => "Multi_Zone_Error_Rate",
+            _ => "Unknown_Attribute",
+        }
+    }
+
+    /// Check if a failure of this attribute predicts drive failure
+    pub fn prefailure(&self) -> bool {
+        (self.flags & FLAG_PREFAILURE) != 0
+    }
+
+    /// Check if the value has fallen to the threshold
+    pub fn failing(&self) -> bool {
+        self.threshold != 0 && self.value <= self.threshold
+    }
+}
+
+/// Decoded SMART data of a disk
+#[derive(Clone, Copy)]
+pub struct SmartReport {
+    pub attributes: [SmartAttribute; MAX_ATTRIBUTES],
+    pub count: usize,
+}
+
+impl SmartReport {
+    /// Valid attributes
+    pub fn attributes(&self) -> &[SmartAttribute] {
+        &self.attributes[..self.count]
+    }
+
+    /// Find an attribute by ID
+    pub fn find(&self, id: u8) -> Option<&SmartAttribute> {
+        self.attributes().iter().find(|a| a.id == id)
+    }
+
+    /// Current temperature in Celsius
+    pub fn temperature(&self) -> Option<u32> {
+        self.find(attribute_id::TEMPERATURE)
+            .or_else(|| self.find(attribute_id::AIRFLOW_TEMPERATURE))
+            .map(|a| (a.raw & 0xFF) as u32)
+    }
+
+    /// Sectors remapped to spares
+    pub fn reallocated_sectors(&self) -> Option<u64> {
+        self.find(attribute_id::REALLOCATED_SECTOR_COUNT).map(|a| a.raw & 0xFFFF_FFFF)
+    }
+
+    /// Power-on time in hours
+    pub fn power_on_hours(&self) -> Option<u64> {
+        self.find(attribute_id::POWER_ON_HOURS).map(|a| a.raw & 0xFFFF_FFFF)
+    }
+
+    /// Overall assessment: no pre-failure attribute is failing
+    pub fn passed(&self) -> bool {
+        !self.attributes().iter().any(|a| a.prefailure() && a.failing())
+    }
+}
+
+/// Check the page checksum (all 512 bytes sum to zero)
+fn checksum_ok(page: &[u8; SECTOR_SIZE]) -> bool {
+    page.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+/// Attribute table of a SMART data or threshold page (from byte 2)
+fn table(page: &[u8; SECTOR_SIZE]) -> &[[u8; ATTRIBUTE_SIZE]] {
+    page[2..2 + MAX_ATTRIBUTES * ATTRIBUTE_SIZE].as_chunks::<ATTRIBUTE_SIZE>().0
+}
+
+/// Read and decode the SMART pages of a disk
+pub fn smart_read(index: u8) -> Result<SmartReport, BlockStatus> {
+    let mut data = [0u8; SECTOR_SIZE];
+    let mut thresholds = [0u8; SECTOR_SIZE];
+
+    let status = block::smart_command(index, smart_cmd::READ_DATA, &mut data);
+    if status != BlockStatus::Success {
+        return Err(status);
+    }
+    let status = block::smart_command(index, smart_cmd::READ_THRESHOLDS, &mut thresholds);
+    if status != BlockStatus::Success {
+        return Err(status);
+    }
+    if !checksum_ok(&data) || !checksum_ok(&thresholds) {
+        return Err(BlockStatus::IoError);
+    }
+
+    let mut report = SmartReport {
+        attributes: [SmartAttribute::default(); MAX_ATTRIBUTES],
+        count: 0,
+    };
+    let limits = table(&thresholds);
+    for entry in table(&data).iter().filter(|e| e[0] != 0) {
+        let mut raw = [0u8; 8];
+        raw[..6].copy_from_slice(&entry[5..11]);
+        let threshold = limits
+            .iter()
+            .find(|t| t[0] == entry[0])
+            .map(|t| t[1])
+            .unwrap_or(0);
+
+        report.attributes[report.count] = SmartAttribute {
+            id: entry[0],
+            flags: u16::from_le_bytes([entry[1], entry[2]]),
+            value: entry[3],
+            worst: entry[4],
+            raw: u64::from_le_bytes(raw),
+            threshold,
+        };
+        report.count += 1;
+    }
+    Ok(report)
+}
+
+// ============================================================================
+// Health Service
+// ============================================================================
+
+/// What the previous check saw on a disk
+#[derive(Clone, Copy)]
+struct DiskHealth {
+    checked: bool,
+    /// Attributes failing at the last check (bit per attribute ID)
+    tripped: [u64; 4],
+    reallocated: u64,
+    over_temperature: bool,
+}
+
+impl DiskHealth {
+    const fn new() -> Self {
+        Self { checked: false, tripped: [0; 4], reallocated: 0, over_temperature: false }
+    }
+
+    fn is_tripped(&self, id: u8) -> bool {
+        (self.tripped[id as usize / 64] & (1 << (id % 64))) != 0
+    }
+
+    fn set_tripped(&mut self, id: u8, tripped: bool) {
+        if tripped {
+            self.tripped[id as usize / 64] |= 1 << (id % 64);
+        } else {
+            self.tripped[id as usize / 64] &= !(1 << (id % 64));
+        }
+    }
+}
+
+static HEALTH: SpinLock<[DiskHealth; MAX_BLOCK_DEVICES]> =
+    SpinLock::new([DiskHealth::new(); MAX_BLOCK_DEVICES]);
+
+/// Service running
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Stop requested
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Wakes the service thread early (stop request)
+static mut WAKE_EVENT: KEvent = KEvent::new();
+
+/// Checks run
+static CHECK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Events logged
+static EVENT_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Tick of the last check
+static LAST_CHECK: AtomicU64 = AtomicU64::new(0);
+
+/// Compare a disk against its previous check, logging what got worse
+///
+/// # Returns
+/// The number of events logged
+fn check_disk(index: u8, report: &SmartReport) -> u32 {
+    let name = match block::get_block_device(index) {
+        Some(dev) => dev.name_str(),
+        None => return 0,
+    };
+    let mut health = HEALTH.lock();
+    let state = &mut health[index as usize];
+    let mut events = 0u32;
+
+    for attr in report.attributes() {
+        let failing = attr.failing();
+        if failing && !state.is_tripped(attr.id) {
+            log_error_fmt(EventSource::Hardware, event_id::THRESHOLD_EXCEEDED, format!(
+                "Disk {}: SMART attribute {} {} has reached its threshold (value {}, threshold {}){}",
+                name, attr.id, attr.name(), attr.value, attr.threshold,
+                if attr.prefailure() { "; drive failure predicted" } else { "" }));
+            events += 1;
+        }
+        state.set_tripped(attr.id, failing);
+    }
+
+    if let Some(reallocated) = report.reallocated_sectors() {
+        if state.checked && reallocated > state.reallocated {
+            log_warning_fmt(EventSource::Hardware, event_id::REALLOCATED_SECTORS, format!(
+                "Disk {}: reallocated sector count increased from {} to {}",
+                name, state.reallocated, reallocated));
+            events += 1;
+        }
+        state.reallocated = reallocated;
+    }
+
+    if let Some(temperature) = report.temperature() {
+        let over = temperature > TEMPERATURE_LIMIT;
+        if over && !state.over_temperature {
+            log_warning_fmt(EventSource::Hardware, event_id::TEMPERATURE, format!(
+                "Disk {}: temperature {} C exceeds {} C", name, temperature, TEMPERATURE_LIMIT));
+            events += 1;
+        }
+        state.over_temperature = over;
+    }
+
+    state.checked = true;
+    events
+}
+
+/// Check every disk that reports SMART
+///
+/// # Returns
+/// The number of events logged
+pub fn smart_check_all() -> u32 {
+    let mut events = 0u32;
+    for index in 0..MAX_BLOCK_DEVICES as u8 {
+        if block::get_block_device(index).is_none() {
+            continue;
+        }
+        if let Ok(report) = smart_read(index) {
+            events += check_disk(index, &report);
+        }
+    }
+    CHECK_COUNT.fetch_add(1, Ordering::Relaxed);
+    EVENT_COUNT.fetch_add(events as u64, Ordering::Relaxed);
+    LAST_CHECK.store(apic::get_tick_count(), Ordering::Relaxed);
+    events
+}
+
+/// Service thread
+fn health_thread() {
+    while !STOP_REQUESTED.load(Ordering::Acquire) {
+        smart_check_all();
+        unsafe {
+            crate::ke::wait::ke_wait_for_single_object(
+                &mut (*core::ptr::addr_of_mut!(WAKE_EVENT)).header as *mut _,
+                Some(CHECK_INTERVAL_MS),
+            );
+        }
+    }
+
+    crate::serial_println!("[SMART] Health service stopped");
+    RUNNING.store(false, Ordering::Release);
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+/// Start the health service
+///
+/// # Returns
+/// `false` if it is already running or the thread could not be created
+pub fn smart_service_start() -> bool {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    STOP_REQUESTED.store(false, Ordering::Release);
+    unsafe {
+        (*core::ptr::addr_of_mut!(WAKE_EVENT)).init(EventType::Synchronization, false);
+        if crate::ke::init::create_thread(SERVICE_PRIORITY, health_thread).is_none() {
+            RUNNING.store(false, Ordering::Release);
+            return false;
+        }
+    }
+    true
+}
+
+/// Ask the service to stop
+pub fn smart_service_stop() -> bool {
+    if !RUNNING.load(Ordering::Acquire) {
+        return false;
+    }
+    STOP_REQUESTED.store(true, Ordering::Release);
+    unsafe { (*core::ptr::addr_of!(WAKE_EVENT)).set() };
+    true
+}
+
+/// Start the service at boot if any disk reports SMART
+pub fn start_at_boot() {
+    let mut page = [0u8; SECTOR_SIZE];
+    let disks = (0..MAX_BLOCK_DEVICES as u8)
+        .filter(|&i| block::smart_command(i, smart_cmd::READ_DATA, &mut page) == BlockStatus::Success)
+        .count();
+    if disks > 0 && smart_service_start() {
+        log_info_fmt(EventSource::Hardware, event_id::SERVICE_STARTED, format!(
+            "SMART health service monitoring {} disk(s)", disks));
+        crate::serial_println!("[SMART] Health service started ({} disk(s))", disks);
+    }
+}
+
+/// Service status
+#[derive(Debug, Clone, Copy)]
+pub struct SmartServiceStatus {
+    pub running: bool,
+    pub checks: u64,
+    pub events: u64,
+    /// Tick of the last check (0 = none yet)
+    pub last_check: u64,
+}
+
+/// Get the service status
+pub fn smart_service_status() -> SmartServiceStatus {
+    SmartServiceStatus {
+        running: RUNNING.load(Ordering::Acquire),
+        checks: CHECK_COUNT.load(Ordering::Relaxed),
+        events: EVENT_COUNT.load(Ordering::Relaxed),
+        last_check: LAST_CHECK.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,1068 @@
+//! Storage Port Layer (storport-lite)
+//!
+//! A common request path for storage drivers. Each adapter driver (the
+//! miniport) implements one `start_io` entry point that executes SCSI
+//! Request Blocks (SRBs): ATAPI and USB mass storage pass the CDB through,
+//! ATA disks and virtio-blk translate it to their own commands.
+//!
+//! The class layer on top turns each target into a block device:
+//!
+//! - INQUIRY and READ CAPACITY size and type the target
+//! - reads and writes become READ/WRITE(10), or (16) past 2 TB, split at
+//!   the adapter's transfer limit
+//! - logical blocks larger than 512 bytes (CD/DVD) are presented as
+//!   512-byte sectors through a bounce buffer
+//! - sense data is decoded to `BlockStatus`; transient conditions (busy,
+//!   becoming ready, power-on UNIT ATTENTION) are retried
+//! - the write cache is read and set through the caching mode page, and
+//!   forced unit access writes set the FUA bit (or are followed by
+//!   SYNCHRONIZE CACHE when the target does not report DPOFUA)
+//! - SMART pages are read with ATA PASS-THROUGH(16), which the ATA
+//!   miniport's SAT layer executes
+//!
+//! ```text
+//! Block layer ──► Class (this module) ──► MiniportOps::start_io
+//!                                          ├── hal::ata (SAT, ATAPI)
+//!                                          └── virtio-blk
+//! ```
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use crate::ke::SpinLock;
+use super::block::{
+    BlockDeviceType, BlockOps, BlockStatus, DiskGeometry,
+    register_block_device, block_flags, MAX_BLOCK_DEVICES, SECTOR_SIZE,
+};
+
+/// Maximum number of adapters
+pub const MAX_ADAPTERS: usize = 8;
+
+/// Largest logical block size supported
+pub const MAX_LOGICAL_BLOCK_SIZE: usize = 4096;
+
+/// Attempts after the first for a retryable failure
+const MAX_RETRIES: u32 = 4;
+
+/// SCSI operation codes
+pub mod scsiop {
+    pub const TEST_UNIT_READY: u8 = 0x00;
+    pub const REQUEST_SENSE: u8 = 0x03;
+    pub const INQUIRY: u8 = 0x12;
+    pub const MODE_SELECT6: u8 = 0x15;
+    pub const MODE_SENSE6: u8 = 0x1A;
+    pub const START_STOP_UNIT: u8 = 0x1B;
+    pub const MEDIUM_REMOVAL: u8 = 0x1E;
+    pub const READ_CAPACITY: u8 = 0x25;
+    pub const READ10: u8 = 0x28;
+    pub const WRITE10: u8 = 0x2A;
+    pub const SYNCHRONIZE_CACHE: u8 = 0x35;
+    pub const READ16: u8 = 0x88;
+    pub const WRITE16: u8 = 0x8A;
+    pub const SYNCHRONIZE_CACHE16: u8 = 0x91;
+    /// SERVICE ACTION IN(16); service action 0x10 is READ CAPACITY(16)
+    pub const READ_CAPACITY16: u8 = 0x9E;
+    pub const ATA_PASS_THROUGH16: u8 = 0x85;
+}
+
+/// SCSI/ATA Translation (SAT) pass-through fields
+pub mod sat {
+    /// Protocol field (CDB byte 1, bits 4:1): PIO data-in
+    pub const PROTOCOL_PIO_IN: u8 = 4 << 1;
+    /// T_DIR (from device), BYT_BLOK (count in blocks), T_LENGTH (count field)
+    pub const FLAGS_PIO_IN: u8 = 0x08 | 0x04 | 0x02;
+    /// ATA SMART command
+    pub const ATA_SMART: u8 = 0xB0;
+    /// SMART signature in LBA mid/high
+    pub const SMART_LBA_MID: u8 = 0x4F;
+    pub const SMART_LBA_HIGH: u8 = 0xC2;
+}
+
+/// Service action of READ CAPACITY(16)
+const SERVICE_ACTION_READ_CAPACITY16: u8 = 0x10;
+
+/// Force unit access bit (byte 1 of READ/WRITE(10) and (16))
+pub const CDB_FUA: u8 = 0x08;
+
+/// Caching mode page
+pub const MODE_PAGE_CACHING: u8 = 0x08;
+
+/// Request for all mode pages
+const MODE_PAGE_ALL: u8 = 0x3F;
+
+/// Write cache enable bit (caching page byte 2)
+const CACHING_WCE: u8 = 0x04;
+
+/// DPO and FUA supported (mode parameter header, device-specific byte)
+const MODE_DPOFUA: u8 = 0x10;
+
+/// Page format bit of MODE SELECT
+const MODE_SELECT_PF: u8 = 0x10;
+
+/// Length of the caching mode page
+const CACHING_PAGE_LEN: usize = 20;
+
+/// SCSI sense keys
+pub mod sense_key {
+    pub const NO_SENSE: u8 = 0x0;
+    pub const RECOVERED_ERROR: u8 = 0x1;
+    pub const NOT_READY: u8 = 0x2;
+    pub const MEDIUM_ERROR: u8 = 0x3;
+    pub const HARDWARE_ERROR: u8 = 0x4;
+    pub const ILLEGAL_REQUEST: u8 = 0x5;
+    pub const UNIT_ATTENTION: u8 = 0x6;
+    pub const DATA_PROTECT: u8 = 0x7;
+    pub const ABORTED_COMMAND: u8 = 0xB;
+}
+
+/// Additional sense codes
+pub mod asc {
+    pub const NO_ADDITIONAL: u8 = 0x00;
+    pub const LUN_NOT_READY: u8 = 0x04;
+    pub const UNRECOVERED_READ_ERROR: u8 = 0x11;
+    pub const INVALID_COMMAND: u8 = 0x20;
+    pub const LBA_OUT_OF_RANGE: u8 = 0x21;
+    pub const INVALID_CDB_FIELD: u8 = 0x24;
+    pub const WRITE_PROTECTED: u8 = 0x27;
+    pub const MEDIUM_CHANGED: u8 = 0x28;
+    pub const POWER_ON_RESET: u8 = 0x29;
+    pub const MEDIUM_NOT_PRESENT: u8 = 0x3A;
+    pub const INTERNAL_TARGET_FAILURE: u8 = 0x44;
+}
+
+/// Additional sense code qualifier for LUN_NOT_READY: becoming ready
+const ASCQ_BECOMING_READY: u8 = 0x01;
+
+/// Peripheral device types (INQUIRY byte 0)
+pub mod peripheral {
+    pub const DIRECT_ACCESS: u8 = 0x00;
+    pub const CDROM: u8 = 0x05;
+}
+
+/// SRB completion status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrbStatus {
+    /// Request completed
+    Success,
+    /// Target reported an error; `Srb::sense` holds the sense data
+    CheckCondition,
+    /// Adapter or target busy
+    Busy,
+    /// Request timed out
+    Timeout,
+    /// No device at the target
+    NoDevice,
+    /// Request malformed or not supported by the adapter
+    InvalidRequest,
+    /// Adapter error
+    Error,
+}
+
+/// SRB data transfer direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDirection {
+    None,
+    In,
+    Out,
+}
+
+/// Decoded fixed-format sense data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenseData {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+}
+
+impl SenseData {
+    /// Parse a REQUEST SENSE response (fixed format)
+    pub fn from_fixed(data: &[u8]) -> Self {
+        if data.len() < 14 {
+            return Self::default();
+        }
+        Self {
+            key: data[2] & 0x0F,
+            asc: data[12],
+            ascq: data[13],
+        }
+    }
+}
+
+/// SCSI Request Block
+pub struct Srb {
+    /// Target ID on the adapter
+    pub target: u8,
+    /// Command descriptor block
+    pub cdb: [u8; 16],
+    /// Valid CDB bytes
+    pub cdb_length: u8,
+    /// Data direction
+    pub direction: DataDirection,
+    /// Data buffer
+    pub data: *mut u8,
+    /// Data buffer length in bytes
+    pub data_length: u32,
+    /// Bytes actually transferred
+    pub transferred: u32,
+    /// Sense data, valid on `CheckCondition`
+    pub sense: SenseData,
+}
+
+impl Srb {
+    /// Build an SRB
+    pub fn new(target: u8, cdb: &[u8], direction: DataDirection, data: *mut u8, data_length: u32) -> Self {
+        let mut srb = Self {
+            target,
+            cdb: [0; 16],
+            cdb_length: cdb.len().min(16) as u8,
+            direction,
+            data,
+            data_length,
+            transferred: 0,
+            sense: SenseData::default(),
+        };
+        srb.cdb[..srb.cdb_length as usize].copy_from_slice(&cdb[..srb.cdb_length as usize]);
+        srb
+    }
+
+    /// Operation code
+    pub fn opcode(&self) -> u8 {
+        self.cdb[0]
+    }
+
+    /// Check if a write must reach the media before completing
+    pub fn fua(&self) -> bool {
+        matches!(self.cdb[0], scsiop::WRITE10 | scsiop::WRITE16) && (self.cdb[1] & CDB_FUA) != 0
+    }
+
+    /// Fail the request with sense data
+    pub fn check_condition(&mut self, key: u8, asc: u8, ascq: u8) -> SrbStatus {
+        self.sense = SenseData { key, asc, ascq };
+        SrbStatus::CheckCondition
+    }
+
+    /// Complete a data-in request with a response built by the miniport
+    pub fn complete_with(&mut self, response: &[u8]) -> SrbStatus {
+        let len = response.len().min(self.data_length as usize);
+        if len > 0 && !self.data.is_null() {
+            unsafe { core::ptr::copy_nonoverlapping(response.as_ptr(), self.data, len) };
+        }
+        self.transferred = len as u32;
+        SrbStatus::Success
+    }
+
+    /// Starting LBA and block count of a READ/WRITE(10) or (16)
+    pub fn rw_range(&self) -> Option<(u64, u32)> {
+        let c = &self.cdb;
+        match c[0] {
+            scsiop::READ10 | scsiop::WRITE10 => Some((
+                u32::from_be_bytes([c[2], c[3], c[4], c[5]]) as u64,
+                u16::from_be_bytes([c[7], c[8]]) as u32,
+            )),
+            scsiop::READ16 | scsiop::WRITE16 => Some((
+                u64::from_be_bytes([c[2], c[3], c[4], c[5], c[6], c[7], c[8], c[9]]),
+                u32::from_be_bytes([c[10], c[11], c[12], c[13]]),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Build a standard INQUIRY response
+pub fn inquiry_data(device_type: u8, removable: bool, vendor: &str, product: &str, revision: &str) -> [u8; 36] {
+    let mut data = [b' '; 36];
+    data[0] = device_type;
+    data[1] = if removable { 0x80 } else { 0 };
+    data[2] = 0x05; // SPC-3
+    data[3] = 0x02; // Response data format
+    data[4] = 31;   // Additional length
+    data[5] = 0;
+    data[6] = 0;
+    data[7] = 0;
+    for (field, text) in [(8..16, vendor), (16..32, product), (32..36, revision)] {
+        let bytes = text.trim().as_bytes();
+        let len = bytes.len().min(field.len());
+        data[field.start..field.start + len].copy_from_slice(&bytes[..len]);
+    }
+    data
+}
+
+/// Answer READ CAPACITY(10) or (16) for a target of `blocks` logical blocks
+pub fn complete_read_capacity(srb: &mut Srb, blocks: u64, block_size: u32) -> SrbStatus {
+    let last_lba = blocks.saturating_sub(1);
+    if srb.opcode() == scsiop::READ_CAPACITY {
+        let mut data = [0u8; 8];
+        data[..4].copy_from_slice(&(last_lba.min(0xFFFF_FFFF) as u32).to_be_bytes());
+        data[4..].copy_from_slice(&block_size.to_be_bytes());
+        srb.complete_with(&data)
+    } else {
+        let mut data = [0u8; 32];
+        data[..8].copy_from_slice(&last_lba.to_be_bytes());
+        data[8..12].copy_from_slice(&block_size.to_be_bytes());
+        srb.complete_with(&data)
+    }
+}
+
+/// Answer MODE SENSE(6) for the caching page
+///
+/// Miniports honor FUA themselves (natively or with a flush), so DPOFUA
+/// is always reported.
+pub fn complete_caching_mode_sense(srb: &mut Srb, write_cache: bool) -> SrbStatus {
+    let page = srb.cdb[2] & 0x3F;
+    if page != MODE_PAGE_CACHING && page != MODE_PAGE_ALL {
+        return srb.check_condition(sense_key::ILLEGAL_REQUEST, asc::INVALID_CDB_FIELD, 0);
+    }
+
+    let mut data = [0u8; 4 + CACHING_PAGE_LEN];
+    data[0] = (data.len() - 1) as u8;
+    data[2] = MODE_DPOFUA;
+    data[4] = MODE_PAGE_CACHING;
+    data[5] = (CACHING_PAGE_LEN - 2) as u8;
+    data[6] = if write_cache { CACHING_WCE } else { 0 };
+    srb.complete_with(&data)
+}
+
+/// Parse the write cache setting from a MODE SELECT(6) caching page
+pub fn caching_mode_select(srb: &Srb) -> Option<bool> {
+    if srb.data.is_null() {
+        return None;
+    }
+    let list = unsafe { core::slice::from_raw_parts(srb.data, srb.data_length as usize) };
+    let page = list.get(4 + *list.get(3)? as usize..)?;
+    if page.len() < 3 || page[0] & 0x3F != MODE_PAGE_CACHING {
+        return None;
+    }
+    Some(page[2] & CACHING_WCE != 0)
+}
+
+/// Decode sense data
+///
+/// # Returns
+/// The block status to report and whether the request should be retried.
+/// UNIT ATTENTION for a medium change is reported as `MediaChanged` on
+/// removable targets, so the volume gets verified; on fixed targets it is
+/// retried like a power-on reset.
+pub fn decode_sense(sense: &SenseData, removable: bool) -> (BlockStatus, bool) {
+    match sense.key {
+        sense_key::NO_SENSE | sense_key::RECOVERED_ERROR => (BlockStatus::Success, false),
+        sense_key::NOT_READY => match (sense.asc, sense.ascq) {
+            (asc::MEDIUM_NOT_PRESENT, _) => (BlockStatus::NoMedia, false),
+            (asc::LUN_NOT_READY, ASCQ_BECOMING_READY) => (BlockStatus::NotReady, true),
+            _ => (BlockStatus::NotReady, false),
+        },
+        sense_key::MEDIUM_ERROR => (BlockStatus::BadSector, true),
+        sense_key::HARDWARE_ERROR => (BlockStatus::IoError, true),
+        sense_key::ILLEGAL_REQUEST => (BlockStatus::InvalidParameter, false),
+        sense_key::UNIT_ATTENTION if removable && sense.asc == asc::MEDIUM_CHANGED => {
+            (BlockStatus::MediaChanged, false)
+        }
+        sense_key::UNIT_ATTENTION => (BlockStatus::IoError, true),
+        sense_key::DATA_PROTECT => (BlockStatus::WriteProtected, false),
+        sense_key::ABORTED_COMMAND => (BlockStatus::IoError, true),
+        _ => (BlockStatus::IoError, false),
+    }
+}
+
+// ============================================================================
+// Adapters
+// ============================================================================
+
+/// Miniport entry points
+#[derive(Clone, Copy)]
+pub struct MiniportOps {
+    /// Execute an SRB synchronously, filling `sense` on `CheckCondition`
+    pub start_io: unsafe fn(context: usize, srb: &mut Srb) -> SrbStatus,
+    /// Reset the bus after repeated timeouts
+    pub reset_bus: Option<unsafe fn(context: usize) -> bool>,
+}
+
+/// Registered adapter
+struct Adapter {
+    active: bool,
+    name: [u8; 16],
+    ops: Option<MiniportOps>,
+    context: usize,
+    /// Largest transfer per SRB in bytes
+    max_transfer: u32,
+    requests: AtomicU64,
+    retries: AtomicU32,
+    sense_errors: AtomicU32,
+    resets: AtomicU32,
+}
+
+impl Adapter {
+    const fn empty() -> Self {
+        Self {
+            active: false,
+            name: [0; 16],
+            ops: None,
+            context: 0,
+            max_transfer: 0,
+            requests: AtomicU64::new(0),
+            retries: AtomicU32::new(0),
+            sense_errors: AtomicU32::new(0),
+            resets: AtomicU32::new(0),
+        }
+    }
+}
+
+/// Adapter table
+static mut ADAPTERS: [Adapter; MAX_ADAPTERS] = {
+    const INIT: Adapter = Adapter::empty();
+    [INIT; MAX_ADAPTERS]
+};
+
+/// Adapter table lock
+static ADAPTER_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// Register an adapter
+///
+/// # Arguments
+/// * `name` - Adapter name for diagnostics
+/// * `ops` - Miniport entry points
+/// * `context` - Passed back to the miniport on every call
+/// * `max_transfer` - Largest data transfer per SRB in bytes
+pub fn storport_register_adapter(name: &str, ops: MiniportOps, context: usize, max_transfer: u32) -> Option<u8> {
+    let _guard = ADAPTER_LOCK.lock();
+
+    unsafe {
+        for (i, adapter) in ADAPTERS.iter_mut().enumerate() {
+            if !adapter.active {
+                *adapter = Adapter::empty();
+                adapter.active = true;
+                adapter.ops = Some(ops);
+                adapter.context = context;
+                adapter.max_transfer = max_transfer.max(SECTOR_SIZE as u32);
+                let len = name.len().min(15);
+                adapter.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+                crate::serial_println!("[STORPORT] Adapter {}: {} (max transfer {} KB)",
+                    i, name, adapter.max_transfer / 1024);
+                return Some(i as u8);
+            }
+        }
+    }
+
+    None
+}
+
+// ============================================================================
+// Class Layer
+// ============================================================================
+
+/// A target presented as a block device
+#[derive(Clone, Copy)]
+struct StorUnit {
+    active: bool,
+    adapter: u8,
+    target: u8,
+    removable: bool,
+    /// Logical block size in bytes
+    block_size: u32,
+    /// Capacity in logical blocks (0 = no media)
+    blocks: u64,
+    /// Target honors the FUA bit
+    dpofua: bool,
+}
+
+impl StorUnit {
+    const fn empty() -> Self {
+        Self {
+            active: false,
+            adapter: 0,
+            target: 0,
+            removable: false,
+            block_size: SECTOR_SIZE as u32,
+            blocks: 0,
+            dpofua: false,
+        }
+    }
+
+    /// 512-byte sectors per logical block
+    fn sectors_per_block(&self) -> u64 {
+        (self.block_size as usize / SECTOR_SIZE) as u64
+    }
+
+    /// Geometry presented to the block layer
+    fn geometry(&self) -> DiskGeometry {
+        DiskGeometry {
+            total_sectors: self.blocks * self.sectors_per_block(),
+            sector_size: SECTOR_SIZE as u32,
+            cylinders: 0,
+            heads: 0,
+            sectors_per_track: 0,
+        }
+    }
+}
+
+/// Units, indexed by block device index
+static mut UNITS: [StorUnit; MAX_BLOCK_DEVICES] = [StorUnit::empty(); MAX_BLOCK_DEVICES];
+
+/// Bounce buffer for partial logical blocks
+static mut BOUNCE: [u8; MAX_LOGICAL_BLOCK_SIZE] = [0; MAX_LOGICAL_BLOCK_SIZE];
+
+/// Bounce buffer lock
+static BOUNCE_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// Look up the unit behind a block device
+unsafe fn unit(dev_index: u8) -> Option<&'static mut StorUnit> {
+    let unit = (*core::ptr::addr_of_mut!(UNITS)).get_mut(dev_index as usize)?;
+    if unit.active { Some(unit) } else { None }
+}
+
+/// Send a CDB to a unit, retrying transient failures
+///
+/// # Returns
+/// Bytes transferred
+unsafe fn execute(
+    unit: &StorUnit,
+    cdb: &[u8],
+    direction: DataDirection,
+    data: *mut u8,
+    data_length: u32,
+) -> Result<u32, BlockStatus> {
+    let adapter = &ADAPTERS[unit.adapter as usize];
+    let ops = adapter.ops.ok_or(BlockStatus::NotFound)?;
+    let mut attempt = 0;
+
+    loop {
+        let mut srb = Srb::new(unit.target, cdb, direction, data, data_length);
+        adapter.requests.fetch_add(1, Ordering::Relaxed);
+
+        let status = (ops.start_io)(adapter.context, &mut srb);
+        let (result, retry) = match status {
+            SrbStatus::Success => return Ok(srb.transferred),
+            SrbStatus::CheckCondition => decode_sense(&srb.sense, unit.removable),
+            SrbStatus::Busy => (BlockStatus::Busy, true),
+            SrbStatus::Timeout => (BlockStatus::Timeout, true),
+            SrbStatus::Error => (BlockStatus::IoError, true),
+            SrbStatus::NoDevice => (BlockStatus::NotFound, false),
+            SrbStatus::InvalidRequest => (BlockStatus::InvalidParameter, false),
+        };
+        if result == BlockStatus::Success {
+            // Recovered error: the data is good
+            return Ok(srb.transferred);
+        }
+        if !retry || attempt >= MAX_RETRIES {
+            if status == SrbStatus::CheckCondition {
+                adapter.sense_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            return Err(result);
+        }
+
+        attempt += 1;
+        adapter.retries.fetch_add(1, Ordering::Relaxed);
+        if status == SrbStatus::Timeout && attempt == MAX_RETRIES / 2 {
+            if let Some(reset_bus) = ops.reset_bus {
+                adapter.resets.fetch_add(1, Ordering::Relaxed);
+                reset_bus(adapter.context);
+            }
+        }
+    }
+}
+
+/// Size the media with READ CAPACITY, falling back to (16) past 2 TB
+unsafe fn read_capacity(unit: &mut StorUnit) -> BlockStatus {
+    let mut data = [0u8; 32];
+    let cdb = [scsiop::READ_CAPACITY, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    if let Err(status) = execute(unit, &cdb, DataDirection::In, data.as_mut_ptr(), 8) {
+        unit.blocks = 0;
+        return status;
+    }
+    let mut last_lba = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u64;
+    let mut block_size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+
+    if last_lba == 0xFFFF_FFFF {
+        let mut cdb = [0u8; 16];
+        cdb[0] = scsiop::READ_CAPACITY16;
+        cdb[1] = SERVICE_ACTION_READ_CAPACITY16;
+        cdb[13] = data.len() as u8;
+        if let Err(status) = execute(unit, &cdb, DataDirection::In, data.as_mut_ptr(), 32) {
+            unit.blocks = 0;
+            return status;
+        }
+        last_lba = u64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+        block_size = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    }
+
+    // Some drives report 0 or 2352 for audio media; only data blocks are read
+    if block_size == 0 || block_size % SECTOR_SIZE as u32 != 0
+        || block_size as usize > MAX_LOGICAL_BLOCK_SIZE
+    {
+        block_size = if unit.removable { 2048 } else { SECTOR_SIZE as u32 };
+    }
+    unit.block_size = block_size;
+    unit.blocks = last_lba + 1;
+    BlockStatus::Success
+}
+
+/// Pick up the new media's capacity after a media change report
+unsafe fn media_changed(unit: &mut StorUnit) {
+    let _ = read_capacity(unit);
+}
+
+/// Transfer whole logical blocks, split at the adapter limit
+///
+/// With `fua`, writes carry the FUA bit.
+unsafe fn transfer_blocks(unit: &mut StorUnit, block: u64, blocks: u64, buf: *mut u8, write: bool, fua: bool) -> BlockStatus {
+    let max_transfer = ADAPTERS[unit.adapter as usize].max_transfer as u64;
+    let per_request = (max_transfer / unit.block_size as u64).max(1);
+    let mut done = 0u64;
+
+    while done < blocks {
+        let lba = block + done;
+        let count = (blocks - done).min(per_request);
+        let use16 = lba + count > 0xFFFF_FFFF;
+        let count = if use16 { count } else { count.min(0xFFFF) };
+
+        let mut cdb = [0u8; 16];
+        let cdb_len = if use16 {
+            cdb[0] = if write { scsiop::WRITE16 } else { scsiop::READ16 };
+            cdb[2..10].copy_from_slice(&lba.to_be_bytes());
+            cdb[10..14].copy_from_slice(&(count as u32).to_be_bytes());
+            16
+        } else {
+            cdb[0] = if write { scsiop::WRITE10 } else { scsiop::READ10 };
+            cdb[2..6].copy_from_slice(&(lba as u32).to_be_bytes());
+            cdb[7..9].copy_from_slice(&(count as u16).to_be_bytes());
+            10
+        };
+        if write && fua {
+            cdb[1] |= CDB_FUA;
+        }
+
+        let bytes = count * unit.block_size as u64;
+        let data = buf.add((done * unit.block_size as u64) as usize);
+        let direction = if write { DataDirection::Out } else { DataDirection::In };
+        match execute(unit, &cdb[..cdb_len], direction, data, bytes as u32) {
+            Ok(n) if n as u64 == bytes => {}
+            Ok(_) => return BlockStatus::IoError,
+            Err(status) => {
+                match status {
+                    BlockStatus::MediaChanged => media_changed(unit),
+                    BlockStatus::NoMedia => unit.blocks = 0,
+                    _ => {}
+                }
+                return status;
+            }
+        }
+        done += count;
+    }
+
+    BlockStatus::Success
+}
+
+/// Transfer 512-byte sectors, going through the bounce buffer for
+/// partial logical blocks (read-modify-write when writing)
+unsafe fn transfer_sectors(unit: &mut StorUnit, lba: u64, count: u32, buf: *mut u8, write: bool, fua: bool) -> BlockStatus {
+    let spb = unit.sectors_per_block();
+    if spb == 1 {
+        return transfer_blocks(unit, lba, count as u64, buf, write, fua);
+    }
+
+    let end = lba + count as u64;
+    let mut sector = lba;
+    while sector < end {
+        let data = buf.add(((sector - lba) as usize) * SECTOR_SIZE);
+        let offset = sector % spb;
+
+        if offset == 0 && end - sector >= spb {
+            let blocks = (end - sector) / spb;
+            let status = transfer_blocks(unit, sector / spb, blocks, data, write, fua);
+            if status != BlockStatus::Success {
+                return status;
+            }
+            sector += blocks * spb;
+            continue;
+        }
+
+        let n = (spb - offset).min(end - sector);
+        let bytes = n as usize * SECTOR_SIZE;
+        let _guard = BOUNCE_LOCK.lock();
+        let bounce = core::ptr::addr_of_mut!(BOUNCE) as *mut u8;
+        let at = bounce.add(offset as usize * SECTOR_SIZE);
+
+        let status = transfer_blocks(unit, sector / spb, 1, bounce, false, false);
+        if status != BlockStatus::Success {
+            return status;
+        }
+        if write {
+            core::ptr::copy_nonoverlapping(data, at, bytes);
+            let status = transfer_blocks(unit, sector / spb, 1, bounce, true, fua);
+            if status != BlockStatus::Success {
+                return status;
+            }
+        } else {
+            core::ptr::copy_nonoverlapping(at, data, bytes);
+        }
+        sector += n;
+    }
+
+    BlockStatus::Success
+}
+
+// ============================================================================
+// Block Operations
+// ============================================================================
+
+unsafe fn class_read(dev_index: u8, lba: u64, count: u32, buf: *mut u8) -> BlockStatus {
+    match unit(dev_index) {
+        Some(unit) => transfer_sectors(unit, lba, count, buf, false, false),
+        None => BlockStatus::NotFound,
+    }
+}
+
+unsafe fn class_write(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> BlockStatus {
+    match unit(dev_index) {
+        Some(unit) => transfer_sectors(unit, lba, count, buf as *mut u8, true, false),
+        None => BlockStatus::NotFound,
+    }
+}
+
+unsafe fn class_write_fua(dev_index: u8, lba: u64, count: u32, buf: *const u8) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    if unit.dpofua {
+        return transfer_sectors(unit, lba, count, buf as *mut u8, true, true);
+    }
+    match transfer_sectors(unit, lba, count, buf as *mut u8, true, false) {
+        BlockStatus::Success => class_flush(dev_index),
+        status => status,
+    }
+}
+
+unsafe fn class_flush(dev_index: u8) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    let cdb = [scsiop::SYNCHRONIZE_CACHE, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    match execute(unit, &cdb, DataDirection::None, core::ptr::null_mut(), 0) {
+        // No cache, or a read-only drive that does not implement the command
+        Ok(_) | Err(BlockStatus::InvalidParameter) => BlockStatus::Success,
+        Err(status) => status,
+    }
+}
+
+/// Read the caching mode page
+///
+/// # Returns
+/// The page and whether the target reports DPOFUA
+unsafe fn read_caching_page(unit: &StorUnit) -> Result<([u8; CACHING_PAGE_LEN], bool), BlockStatus> {
+    let mut data = [0u8; 4 + 8 + CACHING_PAGE_LEN];
+    // DBD: no block descriptors
+    let cdb = [scsiop::MODE_SENSE6, 0x08, MODE_PAGE_CACHING, 0, data.len() as u8, 0];
+    let n = execute(unit, &cdb, DataDirection::In, data.as_mut_ptr(), data.len() as u32)? as usize;
+
+    let start = 4 + data[3] as usize;
+    if n < start + 3 || data[start] & 0x3F != MODE_PAGE_CACHING {
+        return Err(BlockStatus::IoError);
+    }
+    let mut page = [0u8; CACHING_PAGE_LEN];
+    let len = (n - start).min(CACHING_PAGE_LEN);
+    page[..len].copy_from_slice(&data[start..start + len]);
+    Ok((page, data[2] & MODE_DPOFUA != 0))
+}
+
+unsafe fn class_set_write_cache(dev_index: u8, enable: bool) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    let (mut page, _) = match read_caching_page(unit) {
+        Ok(p) => p,
+        Err(status) => return status,
+    };
+
+    let mut list = [0u8; 4 + CACHING_PAGE_LEN];
+    page[0] &= 0x3F; // Clear PS
+    if enable {
+        page[2] |= CACHING_WCE;
+    } else {
+        page[2] &= !CACHING_WCE;
+    }
+    list[4..].copy_from_slice(&page);
+    let cdb = [scsiop::MODE_SELECT6, MODE_SELECT_PF, 0, 0, list.len() as u8, 0];
+    match execute(unit, &cdb, DataDirection::Out, list.as_mut_ptr(), list.len() as u32) {
+        Ok(_) => BlockStatus::Success,
+        Err(status) => status,
+    }
+}
+
+unsafe fn class_smart(dev_index: u8, command: u8, buf: *mut u8) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    let cdb = [
+        scsiop::ATA_PASS_THROUGH16, sat::PROTOCOL_PIO_IN, sat::FLAGS_PIO_IN,
+        0, command,                 // Features
+        0, 1,                       // Count
+        0, 0,                       // LBA low
+        0, sat::SMART_LBA_MID,
+        0, sat::SMART_LBA_HIGH,
+        0,                          // Device
+        sat::ATA_SMART, 0,
+    ];
+    match execute(unit, &cdb, DataDirection::In, buf, SECTOR_SIZE as u32) {
+        Ok(n) if n == SECTOR_SIZE as u32 => BlockStatus::Success,
+        Ok(_) => BlockStatus::IoError,
+        // Not an ATA device behind a SAT layer, or SMART disabled
+        Err(BlockStatus::InvalidParameter) => BlockStatus::NotSupported,
+        Err(status) => status,
+    }
+}
+
+unsafe fn class_get_geometry(dev_index: u8) -> DiskGeometry {
+    match unit(dev_index) {
+        Some(unit) => unit.geometry(),
+        None => DiskGeometry::empty(),
+    }
+}
+
+unsafe fn class_is_ready(dev_index: u8) -> bool {
+    let Some(unit) = unit(dev_index) else {
+        return false;
+    };
+    let Some(ops) = ADAPTERS[unit.adapter as usize].ops else {
+        return false;
+    };
+    let cdb = [scsiop::TEST_UNIT_READY, 0, 0, 0, 0, 0];
+    let mut srb = Srb::new(unit.target, &cdb, DataDirection::None, core::ptr::null_mut(), 0);
+    (ops.start_io)(ADAPTERS[unit.adapter as usize].context, &mut srb) == SrbStatus::Success
+}
+
+unsafe fn class_reset(dev_index: u8) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    let adapter = &ADAPTERS[unit.adapter as usize];
+    match adapter.ops.and_then(|ops| ops.reset_bus) {
+        Some(reset_bus) => {
+            adapter.resets.fetch_add(1, Ordering::Relaxed);
+            if reset_bus(adapter.context) { BlockStatus::Success } else { BlockStatus::Timeout }
+        }
+        None => BlockStatus::Success,
+    }
+}
+
+unsafe fn class_check_media(dev_index: u8) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    if !unit.removable {
+        return BlockStatus::Success;
+    }
+
+    let cdb = [scsiop::TEST_UNIT_READY, 0, 0, 0, 0, 0];
+    match execute(unit, &cdb, DataDirection::None, core::ptr::null_mut(), 0) {
+        // Media in a drive that was empty, without a UNIT ATTENTION
+        Ok(_) if unit.blocks == 0 => {
+            media_changed(unit);
+            BlockStatus::MediaChanged
+        }
+        Ok(_) => BlockStatus::Success,
+        Err(BlockStatus::MediaChanged) => {
+            media_changed(unit);
+            BlockStatus::MediaChanged
+        }
+        Err(BlockStatus::NoMedia) => {
+            unit.blocks = 0;
+            BlockStatus::NoMedia
+        }
+        Err(status) => status,
+    }
+}
+
+unsafe fn class_eject(dev_index: u8) -> BlockStatus {
+    let Some(unit) = unit(dev_index) else {
+        return BlockStatus::NotFound;
+    };
+    if !unit.removable {
+        return BlockStatus::NotSupported;
+    }
+
+    // Allow removal, then START STOP UNIT with LoEj=1 Start=0
+    let allow = [scsiop::MEDIUM_REMOVAL, 0, 0, 0, 0, 0];
+    let _ = execute(unit, &allow, DataDirection::None, core::ptr::null_mut(), 0);
+    let eject = [scsiop::START_STOP_UNIT, 0, 0, 0, 0x02, 0];
+    match execute(unit, &eject, DataDirection::None, core::ptr::null_mut(), 0) {
+        Ok(_) => {
+            unit.blocks = 0;
+            BlockStatus::Success
+        }
+        Err(status) => status,
+    }
+}
+
+/// Block operations of class-layer devices
+fn class_ops() -> BlockOps {
+    BlockOps {
+        read: Some(class_read),
+        write: Some(class_write),
+        write_fua: Some(class_write_fua),
+        flush: Some(class_flush),
+        get_geometry: Some(class_get_geometry),
+        is_ready: Some(class_is_ready),
+        reset: Some(class_reset),
+        check_media: Some(class_check_media),
+        eject: Some(class_eject),
+        set_write_cache: Some(class_set_write_cache),
+        smart: Some(class_smart),
+    }
+}
+
+/// Trim an INQUIRY text field
+fn inquiry_field(field: &[u8]) -> &str {
+    core::str::from_utf8(field).unwrap_or("").trim()
+}
+
+/// Probe a target and register it as a block device
+///
+/// The target is typed and sized with INQUIRY and READ CAPACITY. A
+/// removable drive without media is still registered, with no sectors.
+///
+/// # Arguments
+/// * `adapter` - Adapter index
+/// * `target` - Target ID on the adapter
+/// * `flags` - Extra block flags (LBA48, DMA, READONLY)
+///
+/// # Returns
+/// The block device index
+pub fn storport_add_target(adapter: u8, target: u8, flags: u32) -> Option<u8> {
+    if adapter as usize >= MAX_ADAPTERS || unsafe { !ADAPTERS[adapter as usize].active } {
+        return None;
+    }
+
+    let mut unit = StorUnit {
+        active: true,
+        adapter,
+        target,
+        ..StorUnit::empty()
+    };
+
+    let mut inquiry = [0u8; 36];
+    let cdb = [scsiop::INQUIRY, 0, 0, 0, inquiry.len() as u8, 0];
+    unsafe { execute(&unit, &cdb, DataDirection::In, inquiry.as_mut_ptr(), 36) }.ok()?;
+
+    unit.removable = inquiry[1] & 0x80 != 0;
+    let mut flags = flags;
+    let device_type = match inquiry[0] & 0x1F {
+        peripheral::DIRECT_ACCESS => BlockDeviceType::HardDisk,
+        peripheral::CDROM => {
+            unit.removable = true;
+            flags |= block_flags::READONLY;
+            BlockDeviceType::Optical
+        }
+        other => {
+            crate::serial_println!("[STORPORT] Target {}.{}: device type {:#x} not supported",
+                adapter, target, other);
+            return None;
+        }
+    };
+    if unit.removable {
+        flags |= block_flags::REMOVABLE;
+    }
+
+    unsafe {
+        // Clear the power-on UNIT ATTENTION before sizing the media
+        let tur = [scsiop::TEST_UNIT_READY, 0, 0, 0, 0, 0];
+        for _ in 0..3 {
+            match execute(&unit, &tur, DataDirection::None, core::ptr::null_mut(), 0) {
+                Err(BlockStatus::MediaChanged) => continue,
+                _ => break,
+            }
+        }
+        if read_capacity(&mut unit) != BlockStatus::Success && !unit.removable {
+            crate::serial_println!("[STORPORT] Target {}.{}: READ CAPACITY failed", adapter, target);
+            return None;
+        }
+
+        // Without a caching page, assume a write cache: flushing one that
+        // is not there costs little, losing writes in one that is costs data
+        if (flags & block_flags::READONLY) == 0 {
+            let write_cache = match read_caching_page(&unit) {
+                Ok((page, dpofua)) => {
+                    unit.dpofua = dpofua;
+                    page[2] & CACHING_WCE != 0
+                }
+                Err(_) => true,
+            };
+            if write_cache {
+                flags |= block_flags::WRITE_CACHE;
+            }
+        }
+    }
+
+    let index = register_block_device(device_type, adapter, target, unit.geometry(), class_ops(), flags)?;
+    unsafe {
+        UNITS[index as usize] = unit;
+    }
+
+    if let Some(bdev) = super::block::get_block_device_mut(index) {
+        let vendor = inquiry_field(&inquiry[8..16]);
+        let product = inquiry_field(&inquiry[16..32]);
+        let mut model = [0u8; 48];
+        let mut len = 0;
+        for part in [vendor, product] {
+            if part.is_empty() || len + part.len() + 1 > model.len() {
+                continue;
+            }
+            if len > 0 {
+                model[len] = b' ';
+                len += 1;
+            }
+            model[len..len + part.len()].copy_from_slice(part.as_bytes());
+            len += part.len();
+        }
+        bdev.set_model(core::str::from_utf8(&model[..len]).unwrap_or(""));
+    }
+
+    crate::serial_println!("[STORPORT] Target {}.{}: {} block size {}, {} blocks{}{}",
+        adapter, target,
+        if device_type == BlockDeviceType::Optical { "CD-ROM" } else { "disk" },
+        unit.block_size, unit.blocks,
+        if unit.removable { " (removable)" } else { "" },
+        if (flags & block_flags::WRITE_CACHE) != 0 { ", write cache" } else { "" });
+
+    Some(index)
+}
+
+/// Adapter statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StorPortAdapterStats {
+    pub name: [u8; 16],
+    pub max_transfer: u32,
+    pub requests: u64,
+    pub retries: u32,
+    pub sense_errors: u32,
+    pub resets: u32,
+}
+
+impl StorPortAdapterStats {
+    /// Get adapter name as string
+    pub fn name_str(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(16);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+}
+
+/// Get statistics for all registered adapters
+pub fn storport_adapter_stats() -> ([StorPortAdapterStats; MAX_ADAPTERS], usize) {
+    let mut stats = [StorPortAdapterStats::default(); MAX_ADAPTERS];
+    let mut count = 0;
+    let _guard = ADAPTER_LOCK.lock();
+
+    unsafe {
+        for adapter in ADAPTERS.iter() {
+            if adapter.active {
+                stats[count] = StorPortAdapterStats {
+                    name: adapter.name,
+                    max_transfer: adapter.max_transfer,
+                    requests: adapter.requests.load(Ordering::Relaxed),
+                    retries: adapter.retries.load(Ordering::Relaxed),
+                    sense_errors: adapter.sense_errors.load(Ordering::Relaxed),
+                    resets: adapter.resets.load(Ordering::Relaxed),
+                };
+                count += 1;
+            }
+        }
+    }
+
+    (stats, count)
+}
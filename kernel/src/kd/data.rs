@@ -288,9 +288,9 @@ impl KdDebuggerData {
             mm_resident_available_pages: 0,
             pool_track_table: 0,
             non_paged_pool_descriptor: 0,
-            mm_highest_user_address: 0x7FFFFFFEFFFF,
-            mm_system_range_start: 0xFFFF800000000000,
-            mm_user_probe_address: 0x7FFFFFFEFFFF,
+            mm_highest_user_address: crate::mm::MM_HIGHEST_USER_ADDRESS,
+            mm_system_range_start: crate::mm::MM_SYSTEM_RANGE_START,
+            mm_user_probe_address: crate::mm::MM_USER_PROBE_ADDRESS,
             kd_print_circular_buffer: 0,
             kd_print_circular_buffer_end: 0,
             kd_print_write_pointer: 0,
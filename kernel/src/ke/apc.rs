@@ -12,6 +12,18 @@
 //! - **User APC**: Executes in user mode when thread returns from kernel
 //!   in an alertable wait state
 //!
+//! # Regions
+//!
+//! - **Critical region** (`ke_enter_critical_region`): normal kernel APCs
+//!   are held back; special kernel APCs still run. Required around
+//!   ERESOURCE ownership so a suspend APC cannot stop a lock holder.
+//! - **Guarded region** (`ke_enter_guarded_region`): all kernel APCs are
+//!   held back, for code that would deadlock against a special APC such
+//!   as I/O completion.
+//!
+//! Both nest. Leaving the outermost region delivers whatever was queued
+//! meanwhile, if the thread is at PASSIVE_LEVEL.
+//!
 //! # NT Compatibility
 //! Equivalent to NT's KAPC / KeInitializeApc / KeInsertQueueApc
 
@@ -457,31 +469,43 @@ pub unsafe fn ki_deliver_apc(mode: ApcMode) {
     }
 }
 
-/// Deliver all pending kernel APCs
+/// Deliver all pending kernel APCs the thread's regions allow
+///
+/// Special APCs sit at the head of the queue, so delivery stops at the
+/// first normal APC that cannot run yet.
 unsafe fn deliver_kernel_apcs(thread: *mut KThread) {
     let apc_state = &mut (*thread).apc_state;
     let queue = &mut apc_state.apc_list_head[ApcMode::KernelMode as usize];
 
-    // Mark that we're delivering kernel APCs
-    apc_state.kernel_apc_in_progress = true;
+    // Guarded region: nothing runs
+    if (*thread).special_apc_disable != 0 {
+        apc_state.kernel_apc_pending = !queue.is_empty();
+        return;
+    }
 
     while !queue.is_empty() {
         let entry = queue.flink;
         let apc = containing_record!(entry, KApc, apc_list_entry);
 
-        // Check if this is a special APC or if we can deliver normal APCs
-        // (Normal kernel APCs can only be delivered when not in critical region)
-        if !(*apc).is_special() && (*thread).special_apc_disable != 0 {
+        if (*apc).is_special() {
+            crate::serial_println!("[APC] Delivering special kernel APC to thread {}", (*thread).thread_id);
+            (*apc).deliver();
+            continue;
+        }
+
+        // Normal APCs wait for the critical region to end and never nest
+        if (*thread).kernel_apc_disable != 0 || apc_state.kernel_apc_in_progress {
             break;
         }
 
         crate::serial_println!("[APC] Delivering kernel APC to thread {}", (*thread).thread_id);
+        apc_state.kernel_apc_in_progress = true;
         (*apc).deliver();
+        apc_state.kernel_apc_in_progress = false;
     }
 
     // Update pending flag
     apc_state.kernel_apc_pending = !queue.is_empty();
-    apc_state.kernel_apc_in_progress = false;
 }
 
 /// Deliver pending user APCs
@@ -531,6 +555,144 @@ pub unsafe fn ki_rundown_apcs(thread: *mut KThread) {
     apc_state.user_apc_pending = false;
 }
 
+// ============================================================================
+// Critical and Guarded Regions
+// ============================================================================
+
+fn current_thread() -> *mut KThread {
+    super::prcb::get_current_prcb().current_thread
+}
+
+/// Deliver kernel APCs queued while the current thread's regions held them
+///
+/// Runs only at PASSIVE_LEVEL; at a higher IRQL the APCs stay pending
+/// until the next delivery point.
+pub fn ki_check_for_kernel_apc_delivery() {
+    use super::kpcr::{irql, ke_get_current_irql, ke_raise_irql, ke_lower_irql};
+
+    let thread = current_thread();
+    if thread.is_null() || ke_get_current_irql() != irql::PASSIVE_LEVEL {
+        return;
+    }
+
+    unsafe {
+        if !(*thread).apc_state.kernel_apc_pending {
+            return;
+        }
+        let old_irql = ke_raise_irql(irql::APC_LEVEL);
+        deliver_kernel_apcs(thread);
+        ke_lower_irql(old_irql);
+    }
+}
+
+/// Enter a critical region: hold back normal kernel APCs
+///
+/// Equivalent to KeEnterCriticalRegion.
+pub fn ke_enter_critical_region() {
+    let thread = current_thread();
+    if !thread.is_null() {
+        unsafe { (*thread).kernel_apc_disable -= 1; }
+    }
+}
+
+/// Leave a critical region, delivering held APCs if it was the outermost
+///
+/// Equivalent to KeLeaveCriticalRegion.
+pub fn ke_leave_critical_region() {
+    let thread = current_thread();
+    if thread.is_null() {
+        return;
+    }
+
+    unsafe {
+        debug_assert!((*thread).kernel_apc_disable < 0, "ke_leave_critical_region without enter");
+        (*thread).kernel_apc_disable += 1;
+        if (*thread).kernel_apc_disable == 0 && (*thread).special_apc_disable == 0 {
+            ki_check_for_kernel_apc_delivery();
+        }
+    }
+}
+
+/// Enter a guarded region: hold back all kernel APCs
+///
+/// Equivalent to KeEnterGuardedRegion.
+pub fn ke_enter_guarded_region() {
+    let thread = current_thread();
+    if !thread.is_null() {
+        unsafe { (*thread).special_apc_disable -= 1; }
+    }
+}
+
+/// Leave a guarded region, delivering held APCs if it was the outermost
+///
+/// Equivalent to KeLeaveGuardedRegion.
+pub fn ke_leave_guarded_region() {
+    let thread = current_thread();
+    if thread.is_null() {
+        return;
+    }
+
+    unsafe {
+        debug_assert!((*thread).special_apc_disable < 0, "ke_leave_guarded_region without enter");
+        (*thread).special_apc_disable += 1;
+        if (*thread).special_apc_disable == 0 {
+            ki_check_for_kernel_apc_delivery();
+        }
+    }
+}
+
+/// True if the current thread is in a critical or guarded region
+///
+/// Equivalent to KeAreApcsDisabled.
+pub fn ke_are_apcs_disabled() -> bool {
+    let thread = current_thread();
+    !thread.is_null() && unsafe { (*thread).kernel_apc_disable != 0 || (*thread).special_apc_disable != 0 }
+}
+
+/// True if no kernel APC can currently run on this thread
+///
+/// Equivalent to KeAreAllApcsDisabled: a guarded region, or IRQL at or
+/// above APC_LEVEL.
+pub fn ke_are_all_apcs_disabled() -> bool {
+    use super::kpcr::{irql, ke_get_current_irql};
+
+    let thread = current_thread();
+    ke_get_current_irql() >= irql::APC_LEVEL
+        || (!thread.is_null() && unsafe { (*thread).special_apc_disable != 0 })
+}
+
+/// RAII critical region
+pub struct CriticalRegionGuard(());
+
+impl CriticalRegionGuard {
+    pub fn new() -> Self {
+        ke_enter_critical_region();
+        Self(())
+    }
+}
+
+impl Drop for CriticalRegionGuard {
+    fn drop(&mut self) {
+        ke_leave_critical_region();
+    }
+}
+
+/// RAII guarded region
+pub struct GuardedRegionGuard(());
+
+impl GuardedRegionGuard {
+    pub fn new() -> Self {
+        ke_enter_guarded_region();
+        Self(())
+    }
+}
+
+impl Drop for GuardedRegionGuard {
+    fn drop(&mut self) {
+        ke_leave_guarded_region();
+    }
+}
+
 /// Check if current thread has pending APCs
 pub fn ki_check_apc_pending() -> bool {
     unsafe {
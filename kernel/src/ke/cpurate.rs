@@ -0,0 +1,84 @@
+//! CPU Rate Control
+//!
+//! Hard-caps the processor time a process may use. A process with a CPU
+//! rate limit of N percent gets a budget of N% of all processors' clock
+//! ticks per `CPU_RATE_WINDOW_MS` window. Every clock tick its threads run
+//! is charged against the budget; once it is spent, the running thread is
+//! preempted and none of the process's threads are selected again until
+//! the window ends.
+//!
+//! A window that overran its budget (ticks charged on other processors
+//! in the same tick) carries the overrun into the next one, so the limit
+//! holds over any longer interval.
+//!
+//! The Idle and System processes are never limited.
+
+use core::sync::atomic::Ordering;
+use super::process::KProcess;
+use super::thread::KThread;
+
+/// Length of one CPU rate window
+pub const CPU_RATE_WINDOW_MS: u64 = 100;
+
+/// Check whether a process has a CPU rate limit
+#[inline]
+fn is_limited(process: &KProcess) -> bool {
+    process.cpu_rate_limit > 0 && process.cpu_rate_limit < 100 && !process.is_system_critical()
+}
+
+/// Clock ticks a limited process may run per window
+fn window_budget(process: &KProcess) -> u64 {
+    let cpus = super::prcb::get_active_cpu_count().max(1) as u64;
+    (CPU_RATE_WINDOW_MS * cpus * process.cpu_rate_limit as u64 / 100).max(1)
+}
+
+/// Set a process's CPU rate limit
+///
+/// `percent` of 0 or 100 and above removes the limit.
+pub fn ke_set_cpu_rate_limit(process: &mut KProcess, percent: u32) {
+    process.cpu_rate_limit = if percent >= 100 { 0 } else { percent as u8 };
+    process.cpu_rate_window_start.store(crate::hal::apic::get_tick_count(), Ordering::Relaxed);
+    process.cpu_rate_window_ticks.store(0, Ordering::Relaxed);
+    process.cpu_rate_throttled_until.store(0, Ordering::Relaxed);
+}
+
+/// Charge one clock tick to a process's CPU rate budget
+///
+/// Returns true if the budget is now spent and the running thread should
+/// be preempted.
+///
+/// # Safety
+/// Must be called from the clock interrupt
+pub unsafe fn ki_charge_cpu_rate(process: *mut KProcess, now: u64) -> bool {
+    if process.is_null() || !is_limited(&*process) {
+        return false;
+    }
+    let process = &*process;
+    let budget = window_budget(process);
+
+    let start = process.cpu_rate_window_start.load(Ordering::Relaxed);
+    if now.saturating_sub(start) >= CPU_RATE_WINDOW_MS {
+        let used = process.cpu_rate_window_ticks.load(Ordering::Relaxed);
+        process.cpu_rate_window_start.store(now, Ordering::Relaxed);
+        process.cpu_rate_window_ticks.store(used.saturating_sub(budget).min(budget), Ordering::Relaxed);
+    }
+
+    let used = process.cpu_rate_window_ticks.fetch_add(1, Ordering::Relaxed) + 1;
+    if used < budget {
+        return false;
+    }
+
+    let window_end = process.cpu_rate_window_start.load(Ordering::Relaxed) + CPU_RATE_WINDOW_MS;
+    process.cpu_rate_throttled_until.store(window_end, Ordering::Relaxed);
+    true
+}
+
+/// Check whether a thread's process has spent its CPU rate budget
+///
+/// # Safety
+/// Thread must be valid
+#[inline]
+pub unsafe fn ki_is_rate_throttled(thread: *mut KThread, now: u64) -> bool {
+    let process = (*thread).process;
+    !process.is_null() && (*process).cpu_rate_throttled_until.load(Ordering::Relaxed) > now
+}
@@ -0,0 +1,559 @@
+//! Dispatcher Deadlock Detector
+//!
+//! Builds the wait-for graph of blocked threads and looks for sets of
+//! threads that can never wake up. Only mutexes record an owner, so edges
+//! run from a thread blocked on a mutex to the thread owning it; events,
+//! semaphores and timers can be signaled by anyone and never form edges.
+//!
+//! # Detection
+//! A thread with an untimed wait is stuck when:
+//! - **WaitAny**: every object it waits on is a mutex owned by a stuck thread
+//! - **WaitAll**: at least one of them is
+//!
+//! Starting from all untimed waiters, threads that can make progress are
+//! removed until nothing changes; what remains is deadlocked. A cycle is
+//! reported only when the same waits (same threads, same wait start ticks)
+//! were stuck on the previous pass too, since the graph is sampled without
+//! stopping the other processors.
+//!
+//! # Policy
+//! - `Disabled`: scans still run on request, nothing is reported
+//! - `Log`: cycle, owners, object names and stacks go to the serial log
+//!   and the event log (default)
+//! - `Bugcheck`: log, then DRIVER_VERIFIER_DETECTED_VIOLATION (0xC4) with
+//!   parameter 1 = 0x1001, as the NT deadlock verifier does
+//!
+//! Dispatcher objects have no names of their own; owners of long-lived
+//! mutexes can attach one with `ke_set_object_name` for the report.
+
+extern crate alloc;
+
+use alloc::format;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use crate::ex::eventlog::{kernel_events, log_error_fmt, EventSource};
+use super::dispatcher::{DispatcherHeader, DispatcherType, WaitType};
+use super::mutex::KMutex;
+use super::spinlock::SpinLock;
+use super::thread::{constants, KThread, ThreadState, THREAD_POOL, THREAD_POOL_BITMAP};
+
+/// Interval between background scans
+pub const DEADLOCK_SCAN_INTERVAL_MS: u64 = 5000;
+
+/// Wait objects recorded per blocked thread
+pub const MAX_RECORDED_OBJECTS: usize = 4;
+
+/// Threads a scan can see: the kernel thread pool plus process threads
+pub const MAX_SCANNED_THREADS: usize = constants::MAX_THREADS + crate::ps::MAX_THREADS;
+
+/// Cycle members kept in a report
+pub const MAX_REPORTED_CYCLE: usize = 8;
+
+/// Stack frames captured per thread in a report
+const REPORT_STACK_FRAMES: usize = 8;
+
+/// Named objects
+const MAX_OBJECT_NAMES: usize = 32;
+
+/// NT deadlock verifier bugcheck
+const DRIVER_VERIFIER_DETECTED_VIOLATION: u32 = 0xC4;
+const VERIFIER_DEADLOCK_DETECTED: u64 = 0x1001;
+
+/// What to do when a deadlock is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DeadlockPolicy {
+    Disabled = 0,
+    Log = 1,
+    Bugcheck = 2,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(DeadlockPolicy::Log as u8);
+static MONITOR_RUNNING: AtomicBool = AtomicBool::new(false);
+static SCAN_COUNT: AtomicU64 = AtomicU64::new(0);
+static DEADLOCK_COUNT: AtomicU64 = AtomicU64::new(0);
+static LAST_DEADLOCK_TICK: AtomicU64 = AtomicU64::new(0);
+
+// ============================================================================
+// Object Names
+// ============================================================================
+
+static OBJECT_NAMES: SpinLock<[(usize, &'static str); MAX_OBJECT_NAMES]> =
+    SpinLock::new([(0, ""); MAX_OBJECT_NAMES]);
+
+/// Name a dispatcher object for deadlock and wait chain reports
+///
+/// An empty name removes the entry. Returns false when the table is full.
+pub fn ke_set_object_name(object: *const DispatcherHeader, name: &'static str) -> bool {
+    let object = object as usize;
+    let mut names = OBJECT_NAMES.lock();
+    if let Some(entry) = names.iter_mut().find(|e| e.0 == object) {
+        if name.is_empty() {
+            *entry = (0, "");
+        } else {
+            entry.1 = name;
+        }
+        return true;
+    }
+    if name.is_empty() {
+        return true;
+    }
+    match names.iter_mut().find(|e| e.0 == 0) {
+        Some(entry) => {
+            *entry = (object, name);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Name attached to a dispatcher object, if any
+pub fn ke_object_name(object: usize) -> Option<&'static str> {
+    OBJECT_NAMES.lock().iter().find(|e| e.0 == object && object != 0).map(|e| e.1)
+}
+
+/// Short name of a dispatcher object type
+pub fn dispatcher_type_name(object_type: DispatcherType) -> &'static str {
+    match object_type {
+        DispatcherType::Event => "Event",
+        DispatcherType::Mutex => "Mutant",
+        DispatcherType::Semaphore => "Semaphore",
+        DispatcherType::Timer => "Timer",
+        DispatcherType::Thread => "Thread",
+        DispatcherType::Process => "Process",
+        DispatcherType::Queue => "Queue",
+        DispatcherType::IoCompletion => "IoCompletion",
+    }
+}
+
+// ============================================================================
+// Wait Snapshots
+// ============================================================================
+
+/// One object a blocked thread waits on
+#[derive(Debug, Clone, Copy)]
+pub struct WaitObjectInfo {
+    pub object: usize,
+    pub object_type: DispatcherType,
+    pub signal_state: i32,
+    /// Owning thread (mutexes only), or 0
+    pub owner: usize,
+    pub owner_id: u32,
+}
+
+impl WaitObjectInfo {
+    const fn empty() -> Self {
+        Self { object: 0, object_type: DispatcherType::Event, signal_state: 0, owner: 0, owner_id: 0 }
+    }
+}
+
+/// A thread blocked in a dispatcher wait
+#[derive(Debug, Clone, Copy)]
+pub struct BlockedThread {
+    pub thread: usize,
+    pub thread_id: u32,
+    pub wait_type: WaitType,
+    pub wait_reason: u8,
+    pub alertable: bool,
+    /// The wait has a timeout
+    pub timed: bool,
+    pub wait_start_tick: u64,
+    /// Objects waited on; only the first MAX_RECORDED_OBJECTS are recorded
+    pub object_count: usize,
+    pub objects: [WaitObjectInfo; MAX_RECORDED_OBJECTS],
+}
+
+impl BlockedThread {
+    pub const fn empty() -> Self {
+        Self {
+            thread: 0,
+            thread_id: 0,
+            wait_type: WaitType::WaitAny,
+            wait_reason: 0,
+            alertable: false,
+            timed: false,
+            wait_start_tick: 0,
+            object_count: 0,
+            objects: [WaitObjectInfo::empty(); MAX_RECORDED_OBJECTS],
+        }
+    }
+
+    /// Recorded objects
+    pub fn recorded(&self) -> &[WaitObjectInfo] {
+        &self.objects[..self.object_count.min(MAX_RECORDED_OBJECTS)]
+    }
+}
+
+/// Visit every allocated thread
+unsafe fn for_each_thread(mut visit: impl FnMut(*mut KThread)) {
+    for i in 0..constants::MAX_THREADS {
+        if THREAD_POOL_BITMAP & (1 << i) != 0 {
+            visit(ptr::addr_of_mut!(THREAD_POOL[i]));
+        }
+    }
+
+    let (ethreads, count) = crate::ps::ps_get_ethread_list();
+    for &ethread in ethreads.iter().take(count) {
+        if !ethread.is_null() {
+            visit((*ethread).get_tcb_mut());
+        }
+    }
+}
+
+unsafe fn describe_object(object: *mut DispatcherHeader) -> WaitObjectInfo {
+    let header = &*object;
+    let mut info = WaitObjectInfo {
+        object: object as usize,
+        object_type: header.object_type,
+        signal_state: header.signal_state(),
+        owner: 0,
+        owner_id: 0,
+    };
+    if header.object_type == DispatcherType::Mutex {
+        let owner = (*(object as *mut KMutex)).owner();
+        if !owner.is_null() {
+            info.owner = owner as usize;
+            info.owner_id = (*owner).thread_id;
+        }
+    }
+    info
+}
+
+/// Snapshot every thread blocked in a dispatcher wait
+///
+/// Returns the number of entries written. The snapshot is taken without
+/// stopping other processors, so a thread may already have woken.
+pub fn ke_snapshot_blocked_threads(out: &mut [BlockedThread]) -> usize {
+    let mut count = 0;
+    unsafe {
+        for_each_thread(|thread| {
+            let t = &*thread;
+            if count >= out.len() || t.state != ThreadState::Waiting
+                || t.wait_block_list.is_null() || t.wait_count == 0 {
+                return;
+            }
+
+            let mut entry = BlockedThread {
+                thread: thread as usize,
+                thread_id: t.thread_id,
+                wait_type: t.wait_type,
+                wait_reason: t.wait_reason,
+                alertable: t.alertable,
+                timed: t.wait_timed,
+                wait_start_tick: t.wait_start_tick,
+                object_count: t.wait_count as usize,
+                objects: [WaitObjectInfo::empty(); MAX_RECORDED_OBJECTS],
+            };
+            for i in 0..entry.object_count.min(MAX_RECORDED_OBJECTS) {
+                let object = (*t.wait_block_list.add(i)).object;
+                if !object.is_null() {
+                    entry.objects[i] = describe_object(object);
+                }
+            }
+
+            out[count] = entry;
+            count += 1;
+        });
+    }
+    count
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Index of the blocked thread owning `object`, if it is stuck
+fn stuck_owner(threads: &[BlockedThread], stuck: &[bool], object: &WaitObjectInfo, waiter: usize) -> Option<usize> {
+    if object.object_type != DispatcherType::Mutex || object.owner == 0 || object.owner == waiter {
+        return None;
+    }
+    threads.iter().position(|t| t.thread == object.owner).filter(|&j| stuck[j])
+}
+
+/// Mark the threads that can never wake up
+fn find_stuck(threads: &[BlockedThread], stuck: &mut [bool]) {
+    for (i, t) in threads.iter().enumerate() {
+        stuck[i] = !t.timed && t.object_count <= MAX_RECORDED_OBJECTS;
+    }
+
+    loop {
+        let mut changed = false;
+        for (i, t) in threads.iter().enumerate() {
+            if !stuck[i] {
+                continue;
+            }
+            let mut blocked = t.recorded().iter().map(|o| stuck_owner(threads, stuck, o, t.thread).is_some());
+            let still_stuck = match t.wait_type {
+                WaitType::WaitAny => blocked.all(|b| b),
+                WaitType::WaitAll => blocked.any(|b| b),
+            };
+            if !still_stuck {
+                stuck[i] = false;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Follow wait-for edges from a stuck thread until one repeats
+///
+/// Writes the cycle's thread indices to `cycle` and returns its length.
+fn extract_cycle(threads: &[BlockedThread], stuck: &[bool], start: usize, cycle: &mut [usize]) -> usize {
+    let mut path = [usize::MAX; MAX_SCANNED_THREADS];
+    let mut length = 0;
+    let mut current = start;
+
+    loop {
+        if let Some(pos) = path[..length].iter().position(|&p| p == current) {
+            let cycle_length = length - pos;
+            cycle[..cycle_length].copy_from_slice(&path[pos..length]);
+            return cycle_length;
+        }
+        if length == path.len() {
+            return 0;
+        }
+        path[length] = current;
+        length += 1;
+
+        let t = &threads[current];
+        match t.recorded().iter().find_map(|o| stuck_owner(threads, stuck, o, t.thread)) {
+            Some(next) => current = next,
+            None => return 0,
+        }
+    }
+}
+
+/// Detector state; the snapshot lives here rather than on the caller's stack
+struct DetectorState {
+    threads: [BlockedThread; MAX_SCANNED_THREADS],
+    /// Waits seen stuck on the previous pass
+    previous: [(usize, u64); MAX_SCANNED_THREADS],
+    previous_count: usize,
+    /// Waits already reported
+    reported: [(usize, u64); MAX_SCANNED_THREADS],
+}
+
+static DETECTOR: SpinLock<DetectorState> = SpinLock::new(DetectorState {
+    threads: [BlockedThread::empty(); MAX_SCANNED_THREADS],
+    previous: [(0, 0); MAX_SCANNED_THREADS],
+    previous_count: 0,
+    reported: [(0, 0); MAX_SCANNED_THREADS],
+});
+
+/// A detected deadlock
+#[derive(Debug, Clone, Copy)]
+pub struct DeadlockReport {
+    /// Threads in the cycle, in wait-for order (the first MAX_REPORTED_CYCLE)
+    pub cycle: [BlockedThread; MAX_REPORTED_CYCLE],
+    pub cycle_length: usize,
+    /// Stuck threads outside the cycle (waiting behind it)
+    pub blocked_behind: usize,
+}
+
+impl DeadlockReport {
+    /// Recorded cycle members
+    pub fn members(&self) -> &[BlockedThread] {
+        &self.cycle[..self.cycle_length.min(MAX_REPORTED_CYCLE)]
+    }
+}
+
+/// Run one detection pass
+///
+/// Returns the first newly confirmed cycle, after reporting it according
+/// to the policy.
+pub fn ke_scan_for_deadlocks() -> Option<DeadlockReport> {
+    SCAN_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    let mut guard = DETECTOR.lock();
+    let state = &mut *guard;
+    let count = ke_snapshot_blocked_threads(&mut state.threads);
+    let threads = &state.threads[..count];
+
+    let mut stuck = [false; MAX_SCANNED_THREADS];
+    find_stuck(threads, &mut stuck[..count]);
+    let stuck = &stuck[..count];
+
+    let confirmed = |t: &BlockedThread| {
+        state.previous[..state.previous_count].contains(&(t.thread, t.wait_start_tick))
+    };
+    let reported = |t: &BlockedThread| state.reported.contains(&(t.thread, t.wait_start_tick));
+
+    let mut result = None;
+    let mut cycle = [0usize; MAX_SCANNED_THREADS];
+    for start in 0..count {
+        if !stuck[start] {
+            continue;
+        }
+        let length = extract_cycle(threads, stuck, start, &mut cycle);
+        let members = &cycle[..length];
+        if length == 0
+            || !members.iter().all(|&i| confirmed(&threads[i]))
+            || members.iter().all(|&i| reported(&threads[i])) {
+            continue;
+        }
+
+        let mut report = DeadlockReport {
+            cycle: [BlockedThread::empty(); MAX_REPORTED_CYCLE],
+            cycle_length: length,
+            blocked_behind: stuck.iter().filter(|&&s| s).count() - length,
+        };
+        for (slot, &i) in members.iter().take(MAX_REPORTED_CYCLE).enumerate() {
+            report.cycle[slot] = threads[i];
+        }
+        result = Some((report, length));
+        break;
+    }
+
+    // Report each wait once
+    if let Some((_, length)) = result {
+        for &i in &cycle[..length] {
+            let key = (state.threads[i].thread, state.threads[i].wait_start_tick);
+            let slot = state.reported.iter().position(|r| r.0 == key.0)
+                .or_else(|| state.reported.iter().position(|r| r.0 == 0))
+                .unwrap_or(0);
+            state.reported[slot] = key;
+        }
+    }
+
+    // Remember this pass
+    state.previous_count = 0;
+    for i in 0..count {
+        if stuck[i] {
+            state.previous[state.previous_count] = (state.threads[i].thread, state.threads[i].wait_start_tick);
+            state.previous_count += 1;
+        }
+    }
+    drop(guard);
+
+    let result = result.map(|(report, _)| report);
+    if let Some(report) = &result {
+        DEADLOCK_COUNT.fetch_add(1, Ordering::Relaxed);
+        LAST_DEADLOCK_TICK.store(crate::hal::apic::get_tick_count(), Ordering::Relaxed);
+        report_deadlock(report);
+    }
+    result
+}
+
+/// Describe an object as `Mutant "name" @0x...`
+fn object_label(object: &WaitObjectInfo) -> alloc::string::String {
+    match ke_object_name(object.object) {
+        Some(name) => format!("{} \"{}\" @{:#x}", dispatcher_type_name(object.object_type), name, object.object),
+        None => format!("{} @{:#x}", dispatcher_type_name(object.object_type), object.object),
+    }
+}
+
+fn report_deadlock(report: &DeadlockReport) {
+    let policy = ke_deadlock_policy();
+    if policy == DeadlockPolicy::Disabled {
+        return;
+    }
+
+    let now = crate::hal::apic::get_tick_count();
+    let cycle = report.members();
+
+    crate::serial_println!("[KE] DEADLOCK: {} threads in a wait cycle ({} more blocked behind it)",
+        report.cycle_length, report.blocked_behind);
+    let mut summary = alloc::string::String::new();
+    for t in cycle {
+        let object = t.recorded().iter().find(|o| o.owner != 0 && o.owner != t.thread);
+        let (label, owner) = match object {
+            Some(o) => (object_label(o), o.owner_id),
+            None => (alloc::string::String::from("?"), 0),
+        };
+        crate::serial_println!("[KE]   Thread {} waiting {} ms on {} owned by thread {}",
+            t.thread_id, now.saturating_sub(t.wait_start_tick), label, owner);
+
+        let mut frames = [0u64; REPORT_STACK_FRAMES];
+        let depth = unsafe { (*(t.thread as *const KThread)).capture_stack(&mut frames) };
+        for (n, frame) in frames[..depth].iter().enumerate() {
+            crate::serial_println!("[KE]     #{} {:#018x}", n, frame);
+        }
+
+        if !summary.is_empty() {
+            summary.push_str(", ");
+        }
+        summary.push_str(&format!("thread {} -> {} (owner {})", t.thread_id, label, owner));
+    }
+
+    log_error_fmt(EventSource::Kernel, kernel_events::DEADLOCK_DETECTED, format!(
+        "Deadlock among {} threads: {}", report.cycle_length, summary));
+
+    if policy == DeadlockPolicy::Bugcheck {
+        let first = &cycle[0];
+        let object = first.recorded().first().map(|o| o.object).unwrap_or(0);
+        let second = cycle.get(1).map(|t| t.thread_id).unwrap_or(0);
+        super::bugcheck::ke_bugcheck_ex(
+            DRIVER_VERIFIER_DETECTED_VIOLATION,
+            VERIFIER_DEADLOCK_DETECTED,
+            object as u64,
+            first.thread_id as u64,
+            second as u64,
+        );
+    }
+}
+
+// ============================================================================
+// Policy, Statistics and Monitor
+// ============================================================================
+
+/// Current reporting policy
+pub fn ke_deadlock_policy() -> DeadlockPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        0 => DeadlockPolicy::Disabled,
+        2 => DeadlockPolicy::Bugcheck,
+        _ => DeadlockPolicy::Log,
+    }
+}
+
+/// Set the reporting policy
+pub fn ke_set_deadlock_policy(policy: DeadlockPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Detector statistics
+#[derive(Debug, Clone, Copy)]
+pub struct DeadlockStats {
+    pub monitor_running: bool,
+    pub scans: u64,
+    pub deadlocks: u64,
+    /// Tick of the last detection (0 = none)
+    pub last_deadlock_tick: u64,
+    pub policy: DeadlockPolicy,
+}
+
+/// Get detector statistics
+pub fn ke_get_deadlock_stats() -> DeadlockStats {
+    DeadlockStats {
+        monitor_running: MONITOR_RUNNING.load(Ordering::Acquire),
+        scans: SCAN_COUNT.load(Ordering::Relaxed),
+        deadlocks: DEADLOCK_COUNT.load(Ordering::Relaxed),
+        last_deadlock_tick: LAST_DEADLOCK_TICK.load(Ordering::Relaxed),
+        policy: ke_deadlock_policy(),
+    }
+}
+
+/// Start the background deadlock scan
+pub fn ke_start_deadlock_monitor() -> Result<(), &'static str> {
+    if MONITOR_RUNNING.swap(true, Ordering::AcqRel) {
+        return Err("Deadlock monitor already running");
+    }
+
+    let spawned = crate::ex::task::ex_spawn_task("deadlock", async {
+        loop {
+            crate::ex::task::ex_task_sleep(DEADLOCK_SCAN_INTERVAL_MS).await;
+            if ke_deadlock_policy() != DeadlockPolicy::Disabled {
+                ke_scan_for_deadlocks();
+            }
+        }
+    });
+    if let Err(e) = spawned {
+        MONITOR_RUNNING.store(false, Ordering::Release);
+        return Err(e);
+    }
+
+    crate::serial_println!("[KE] Deadlock monitor started ({} ms interval)", DEADLOCK_SCAN_INTERVAL_MS);
+    Ok(())
+}
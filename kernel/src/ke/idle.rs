@@ -48,14 +48,15 @@ pub unsafe fn init_idle_thread(cpu_id: usize) {
     IDLE_THREADS[cpu_id].base_priority = 0;
     IDLE_THREADS[cpu_id].quantum = constants::THREAD_QUANTUM;
     IDLE_THREADS[cpu_id].state = ThreadState::Running; // Starts as running
-    IDLE_THREADS[cpu_id].process = process::get_system_process_mut();
+    IDLE_THREADS[cpu_id].process = process::get_idle_process_mut();
     IDLE_THREADS[cpu_id].stack_base = stack_base;
     IDLE_THREADS[cpu_id].stack_limit = IDLE_STACKS[cpu_id].data.as_mut_ptr();
     IDLE_THREADS[cpu_id].wait_list_entry.init_head();
     IDLE_THREADS[cpu_id].thread_list_entry.init_head();
 
     // Initialize APC state
-    IDLE_THREADS[cpu_id].apc_state.init(process::get_system_process_mut());
+    IDLE_THREADS[cpu_id].apc_state.init(process::get_idle_process_mut());
+    (*crate::ps::get_idle_process()).increment_thread_count();
     IDLE_THREADS[cpu_id].special_apc_disable = 0;
     IDLE_THREADS[cpu_id].kernel_apc_disable = 0;
     IDLE_THREADS[cpu_id].alertable = false;
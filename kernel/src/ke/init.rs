@@ -3,14 +3,15 @@
 //! This module provides initialization routines for the kernel executive,
 //! including the scheduler, PRCB, KPCR, threads, and timer.
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use crate::hal::apic;
 use crate::arch::x86_64::idt::vector;
 use super::prcb;
 use super::kpcr;
 use super::process;
 use super::idle;
-use super::thread::{KThread, constants};
+use super::thread::{KThread, ThreadState, constants};
+use super::SpinLock;
 use super::timer;
 
 /// Timer frequency in Hz (1000 = 1ms tick)
@@ -27,8 +28,15 @@ struct AlignedStack {
 static mut THREAD_STACK_POOL: [AlignedStack; constants::MAX_THREADS] =
     [AlignedStack { data: [0; constants::THREAD_STACK_SIZE] }; constants::MAX_THREADS];
 
-/// Next available stack index
-static NEXT_STACK: AtomicU32 = AtomicU32::new(1); // 0 is reserved for idle
+/// Stack slots in use (bit 0 is reserved for idle)
+static STACK_BITMAP: AtomicU32 = AtomicU32::new(1);
+
+/// Tick at which the thread owning each stack slot exited (0 = not exited)
+static EXIT_TICKS: [AtomicU64; constants::MAX_THREADS] =
+    [const { AtomicU64::new(0) }; constants::MAX_THREADS];
+
+/// Serializes thread creation and reaping
+static THREAD_CREATE_LOCK: SpinLock<()> = SpinLock::new(());
 
 /// Initialize the kernel executive (phase 1)
 ///
@@ -76,6 +84,8 @@ pub unsafe fn init() {
 
 /// Create and start a kernel thread
 ///
+/// The entry point must not return; threads that finish call `exit_thread`.
+///
 /// # Arguments
 /// * `priority` - Thread priority (0-31)
 /// * `entry` - Thread entry point function
@@ -86,17 +96,25 @@ pub unsafe fn create_thread(
     priority: i8,
     entry: fn(),
 ) -> Option<*mut KThread> {
-    use super::thread::allocate_thread;
+    use super::thread::{allocate_thread, free_thread};
     use crate::arch::x86_64::context::setup_initial_context;
 
+    let _guard = THREAD_CREATE_LOCK.lock();
+
+    // Recycle threads that have exited since the last creation
+    reap_exited_threads();
+
     // Allocate a thread from the pool
     let thread = allocate_thread()?;
 
-    // Get next stack index
-    let stack_index = NEXT_STACK.fetch_add(1, Ordering::SeqCst) as usize;
-    if stack_index >= constants::MAX_THREADS {
-        return None;
-    }
+    // Claim a free stack slot
+    let stack_index = match allocate_stack_slot() {
+        Some(i) => i,
+        None => {
+            free_thread(thread);
+            return None;
+        }
+    };
 
     let stack_base = THREAD_STACK_POOL[stack_index].data.as_mut_ptr()
         .add(constants::THREAD_STACK_SIZE);
@@ -123,6 +141,9 @@ pub unsafe fn create_thread(
     // Set up initial context to jump to entry point
     setup_initial_context(thread, stack_base, entry);
 
+    // Kernel worker threads belong to the System process
+    (*crate::ps::get_system_process()).increment_thread_count();
+
     // Ready the thread
     super::scheduler::ki_ready_thread(thread);
 
@@ -131,6 +152,86 @@ pub unsafe fn create_thread(
     Some(thread)
 }
 
+/// Terminate the current thread
+///
+/// The thread is switched away from and never rescheduled. Its pool entry
+/// and stack are recycled by a later `create_thread`, once the thread has
+/// been off its stack for at least a tick.
+///
+/// # Safety
+/// Must be called from a thread created by `create_thread`
+pub unsafe fn exit_thread() -> ! {
+    crate::arch::x86_64::disable_interrupts();
+
+    let prcb = prcb::get_current_prcb_mut();
+    let thread = prcb.current_thread;
+    if !thread.is_null() && thread != prcb.idle_thread {
+        let slot = (*thread).thread_id as usize;
+        if slot < constants::MAX_THREADS {
+            EXIT_TICKS[slot].store(apic::get_tick_count() + 1, Ordering::Release);
+        }
+        (*thread).state = ThreadState::Terminated;
+        let process = (*thread).process as *mut crate::ps::EProcess;
+        if !process.is_null() {
+            (*process).decrement_thread_count();
+        }
+        prcb.next_thread = core::ptr::null_mut();
+        super::scheduler::ki_dispatch_interrupt();
+    }
+
+    // Not reached unless there was nothing to switch to
+    loop {
+        crate::arch::x86_64::halt();
+    }
+}
+
+/// Claim the lowest free stack slot
+fn allocate_stack_slot() -> Option<usize> {
+    loop {
+        let bitmap = STACK_BITMAP.load(Ordering::Acquire);
+        let slot = (!bitmap).trailing_zeros() as usize;
+        if slot >= constants::MAX_THREADS {
+            return None;
+        }
+        if STACK_BITMAP
+            .compare_exchange(bitmap, bitmap | (1 << slot), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            return Some(slot);
+        }
+    }
+}
+
+/// Return exited threads and their stacks to the pools
+///
+/// # Safety
+/// Caller must hold THREAD_CREATE_LOCK
+unsafe fn reap_exited_threads() {
+    use super::thread::{free_thread, THREAD_POOL, THREAD_POOL_BITMAP};
+
+    let now = apic::get_tick_count();
+    for i in 0..constants::MAX_THREADS {
+        if THREAD_POOL_BITMAP & (1 << i) == 0 {
+            continue;
+        }
+        let thread = core::ptr::addr_of_mut!(THREAD_POOL[i]);
+        if (*thread).state != ThreadState::Terminated {
+            continue;
+        }
+        let slot = (*thread).thread_id as usize;
+        if slot == 0 || slot >= constants::MAX_THREADS {
+            continue;
+        }
+        let exited = EXIT_TICKS[slot].load(Ordering::Acquire);
+        if exited == 0 || now <= exited {
+            continue;
+        }
+        EXIT_TICKS[slot].store(0, Ordering::Release);
+        free_thread(thread);
+        STACK_BITMAP.fetch_and(!(1 << slot), Ordering::AcqRel);
+    }
+}
+
 /// Start the scheduler
 ///
 /// This enables interrupts and begins normal scheduling.
@@ -169,6 +270,7 @@ pub unsafe fn start_scheduler() {
 
 use super::timer::{KTimer, TimerType};
 use super::dpc::KDpc;
+use super::scheduler;
 
 // ============================================================================
 // Timer Test - Kernel Timer Demonstration
@@ -266,26 +368,18 @@ fn oneshot_timer_worker() {
         }
         crate::serial_println!("[OneShot] Set timer #{} at ticks={}", iteration, set_time);
 
-        // Wait for timer to expire by polling
-        loop {
-            if unsafe { ONESHOT_TIMER.is_signaled() } {
-                let expire_time = apic::get_tick_count();
-                let elapsed = expire_time - set_time;
-                unsafe { ONESHOT_EXPIRATIONS += 1; }
-                crate::serial_println!("[OneShot] Timer #{} expired! elapsed={}ms, total={}",
-                    iteration, elapsed, unsafe { ONESHOT_EXPIRATIONS });
-                break;
-            }
-            // Small delay
-            for _ in 0..1000 {
-                core::hint::spin_loop();
-            }
+        // Wait for timer to expire
+        unsafe {
+            wait::ke_wait_for_single_object(&ONESHOT_TIMER.header as *const _ as *mut DispatcherHeader, None);
         }
+        let expire_time = apic::get_tick_count();
+        let elapsed = expire_time - set_time;
+        unsafe { ONESHOT_EXPIRATIONS += 1; }
+        crate::serial_println!("[OneShot] Timer #{} expired! elapsed={}ms, total={}",
+            iteration, elapsed, unsafe { ONESHOT_EXPIRATIONS });
 
         // Pause before next timer
-        for _ in 0..200000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(200); }
     }
 }
 
@@ -301,24 +395,21 @@ fn periodic_timer_worker() {
 
     loop {
         // Wait for timer to become signaled
-        if unsafe { PERIODIC_TIMER.is_signaled() } {
-            let now = apic::get_tick_count();
-            let interval = now - unsafe { LAST_PERIODIC_TIME };
-            unsafe {
-                PERIODIC_EXPIRATIONS += 1;
-                LAST_PERIODIC_TIME = now;
-                // Clear the signal to acknowledge this expiration
-                // The timer will become signaled again on next period
-                PERIODIC_TIMER.clear_signal();
-            }
-            crate::serial_println!("[Periodic] Tick #{} at ticks={}, interval={}ms",
-                unsafe { PERIODIC_EXPIRATIONS }, now, interval);
+        unsafe {
+            wait::ke_wait_for_single_object(&PERIODIC_TIMER.header as *const _ as *mut DispatcherHeader, None);
         }
 
-        // Small delay
-        for _ in 0..5000 {
-            core::hint::spin_loop();
+        let now = apic::get_tick_count();
+        let interval = now - unsafe { LAST_PERIODIC_TIME };
+        unsafe {
+            PERIODIC_EXPIRATIONS += 1;
+            LAST_PERIODIC_TIME = now;
+            // Clear the signal to acknowledge this expiration
+            // The timer will become signaled again on next period
+            PERIODIC_TIMER.clear_signal();
         }
+        crate::serial_println!("[Periodic] Tick #{} at ticks={}, interval={}ms",
+            unsafe { PERIODIC_EXPIRATIONS }, now, interval);
     }
 }
 
@@ -343,15 +434,11 @@ fn dpc_timer_worker() {
                 break;
             }
             // Small delay
-            for _ in 0..5000 {
-                core::hint::spin_loop();
-            }
+            unsafe { scheduler::ki_delay_execution(10); }
         }
 
         // Pause before next timer
-        for _ in 0..300000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(300); }
     }
 }
 
@@ -382,9 +469,7 @@ fn timer_monitor_thread() {
         }
 
         // Small delay
-        for _ in 0..5000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(100); }
     }
 }
 
@@ -550,10 +635,8 @@ fn apc_test_thread() {
             crate::serial_println!("[APC-Test] Stats: iteration={}, APCs delivered={}", iteration, count);
         }
 
-        // Small delay
-        for _ in 0..10000 {
-            core::hint::spin_loop();
-        }
+        // 10ms delay per iteration
+        unsafe { scheduler::ki_delay_execution(10); }
     }
 }
 
@@ -656,9 +739,7 @@ fn wait_any_thread() {
         }
 
         // Small delay before next wait
-        for _ in 0..50000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(50); }
     }
 }
 
@@ -702,9 +783,7 @@ fn wait_all_thread() {
         }
 
         // Small delay before next wait
-        for _ in 0..100000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(100); }
     }
 }
 
@@ -764,9 +843,7 @@ fn signal_thread() {
         }
 
         // 10ms delay per iteration
-        for _ in 0..10000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(10); }
     }
 }
 
@@ -807,9 +884,7 @@ fn wait_single_thread() {
         }
 
         // Small delay before next wait
-        for _ in 0..20000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(20); }
     }
 }
 
@@ -839,9 +914,7 @@ fn wait_monitor_thread() {
         }
 
         // Small delay
-        for _ in 0..10000 {
-            core::hint::spin_loop();
-        }
+        unsafe { scheduler::ki_delay_execution(100); }
     }
 }
 
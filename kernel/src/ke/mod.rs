@@ -9,6 +9,7 @@
 //! - **Spinlocks**: Low-level synchronization primitives (including queued spinlocks)
 //! - **Wait/Unwait**: Multi-object wait support
 //! - **IPI**: Inter-processor interrupt for SMP communication
+//! - **Deadlock Detector**: Wait-for graph cycle detection over mutex waits
 //!
 //! # IRQL (Interrupt Request Level)
 //!
@@ -81,9 +82,15 @@ pub mod profile;
 // Balance set manager
 pub mod balance;
 
+// CPU rate control
+pub mod cpurate;
+
 // Performance counters
 pub mod perfctr;
 
+// Deadlock detection
+pub mod deadlock;
+
 // Re-export key types
 pub use list::ListEntry;
 pub use thread::{KThread, ThreadState};
@@ -146,6 +153,12 @@ pub use dpc::{KDpc, DpcRoutine, DpcImportance};
 
 // Re-export APC types
 pub use apc::{KApc, KApcState, ApcMode, ApcEnvironment, KernelRoutine, NormalRoutine, RundownRoutine};
+pub use apc::{
+    ke_enter_critical_region, ke_leave_critical_region,
+    ke_enter_guarded_region, ke_leave_guarded_region,
+    ke_are_apcs_disabled, ke_are_all_apcs_disabled,
+    CriticalRegionGuard, GuardedRegionGuard,
+};
 
 // Re-export timer types
 pub use timer::{KTimer, TimerType};
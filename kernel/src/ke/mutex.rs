@@ -201,6 +201,13 @@ impl KMutex {
         // Add to mutex's wait list
         self.header.wait_list().insert_tail(&mut wait_block.wait_list_entry);
 
+        // Record the wait for deadlock detection
+        (*thread).wait_block_list = &mut wait_block;
+        (*thread).wait_type = WaitType::WaitAny;
+        (*thread).wait_count = 1;
+        (*thread).wait_start_tick = crate::hal::apic::get_tick_count();
+        (*thread).wait_timed = false;
+
         // Set thread state to waiting
         (*thread).state = ThreadState::Waiting;
 
@@ -9,6 +9,16 @@
 //! Full NT EPROCESS would be built on top of this.
 
 use super::list::ListEntry;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Process ID of the Idle process (owns the per-processor idle threads)
+pub const IDLE_PROCESS_ID: u32 = 0;
+
+/// Process ID of the System process (owns kernel worker threads)
+pub const SYSTEM_PROCESS_ID: u32 = 4;
+
+/// Length of one clock tick in 100ns units
+pub const CLOCK_TICK_100NS: u64 = 10_000;
 
 /// Process states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -56,6 +66,24 @@ pub struct KProcess {
 
     /// Primary token (security context for process)
     pub token: *mut u8,
+
+    /// Time spent in kernel mode by all threads (100ns units)
+    pub kernel_time: AtomicU64,
+
+    /// Time spent in user mode by all threads (100ns units)
+    pub user_time: AtomicU64,
+
+    /// CPU rate limit in percent of total processor time (0 = unlimited)
+    pub cpu_rate_limit: u8,
+
+    /// Tick at which the current CPU rate window started
+    pub cpu_rate_window_start: AtomicU64,
+
+    /// Clock ticks charged in the current CPU rate window
+    pub cpu_rate_window_ticks: AtomicU64,
+
+    /// Threads are not scheduled before this tick (rate budget exhausted)
+    pub cpu_rate_throttled_until: AtomicU64,
 }
 
 impl KProcess {
@@ -71,6 +99,12 @@ impl KProcess {
             directory_table_base: 0,
             process_id: 0,
             token: core::ptr::null_mut(),
+            kernel_time: AtomicU64::new(0),
+            user_time: AtomicU64::new(0),
+            cpu_rate_limit: 0,
+            cpu_rate_window_start: AtomicU64::new(0),
+            cpu_rate_window_ticks: AtomicU64::new(0),
+            cpu_rate_throttled_until: AtomicU64::new(0),
         }
     }
 
@@ -83,6 +117,40 @@ impl KProcess {
         self.state = ProcessState::Initialized;
         self.active_threads = 0;
         self.token = core::ptr::null_mut();
+        self.kernel_time.store(0, Ordering::Relaxed);
+        self.user_time.store(0, Ordering::Relaxed);
+        self.affinity = u64::MAX;
+        self.cpu_rate_limit = 0;
+        self.cpu_rate_window_start.store(0, Ordering::Relaxed);
+        self.cpu_rate_window_ticks.store(0, Ordering::Relaxed);
+        self.cpu_rate_throttled_until.store(0, Ordering::Relaxed);
+    }
+
+    /// Charge run time to the process (called from the clock interrupt)
+    #[inline]
+    pub fn charge_time(&self, user_mode: bool, time_100ns: u64) {
+        if user_mode {
+            self.user_time.fetch_add(time_100ns, Ordering::Relaxed);
+        } else {
+            self.kernel_time.fetch_add(time_100ns, Ordering::Relaxed);
+        }
+    }
+
+    /// Total kernel mode time (100ns units)
+    pub fn kernel_time(&self) -> u64 {
+        self.kernel_time.load(Ordering::Relaxed)
+    }
+
+    /// Total user mode time (100ns units)
+    pub fn user_time(&self) -> u64 {
+        self.user_time.load(Ordering::Relaxed)
+    }
+
+    /// Check whether this is the Idle or System process
+    ///
+    /// Neither can be suspended or terminated.
+    pub fn is_system_critical(&self) -> bool {
+        core::ptr::eq(self, get_idle_process()) || core::ptr::eq(self, get_system_process())
     }
 
     /// Set the process token
@@ -102,25 +170,29 @@ impl Default for KProcess {
     }
 }
 
-/// The system process (process 0)
-///
-/// This is a static process that owns all kernel threads.
-/// Kernel threads don't have their own address space - they use
-/// the kernel's page tables.
-static mut SYSTEM_PROCESS: KProcess = KProcess::new();
+// The Idle (PID 0) and System (PID 4) processes are static EPROCESSes owned
+// by the process manager; their KPROCESS is the embedded PCB, so kernel
+// threads resolve to a real EPROCESS via PsGetCurrentProcess.
 
-/// Initialize the system process
+/// Initialize the Idle and System processes' kernel state
+///
+/// The process manager fills in the rest of their EPROCESS in phase 1.
 ///
 /// # Safety
 /// Must be called exactly once during kernel initialization
 pub unsafe fn init_system_process() {
-    SYSTEM_PROCESS.init(0, 8, 0);
-    SYSTEM_PROCESS.state = ProcessState::Ready;
+    let system = get_system_process_mut();
+    (*system).init(SYSTEM_PROCESS_ID, 8, 0);
+    (*system).state = ProcessState::Ready;
+
+    let idle = get_idle_process_mut();
+    (*idle).init(IDLE_PROCESS_ID, 0, 0);
+    (*idle).state = ProcessState::Ready;
 }
 
 /// Get a reference to the system process
 pub fn get_system_process() -> &'static KProcess {
-    unsafe { &SYSTEM_PROCESS }
+    unsafe { &*get_system_process_mut() }
 }
 
 /// Get a mutable pointer to the system process
@@ -128,5 +200,18 @@ pub fn get_system_process() -> &'static KProcess {
 /// # Safety
 /// Caller must ensure proper synchronization
 pub unsafe fn get_system_process_mut() -> *mut KProcess {
-    &mut SYSTEM_PROCESS as *mut KProcess
+    crate::ps::eprocess::get_system_process() as *mut KProcess
+}
+
+/// Get a reference to the idle process
+pub fn get_idle_process() -> &'static KProcess {
+    unsafe { &*get_idle_process_mut() }
+}
+
+/// Get a mutable pointer to the idle process
+///
+/// # Safety
+/// Caller must ensure proper synchronization
+pub unsafe fn get_idle_process_mut() -> *mut KProcess {
+    crate::ps::eprocess::get_idle_process() as *mut KProcess
 }
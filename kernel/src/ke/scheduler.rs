@@ -4,6 +4,7 @@
 //! - O(1) thread selection using ready summary bitmap
 //! - Quantum-based preemption
 //! - Priority boost/decay for dynamic priority threads
+//! - Per-process CPU rate caps (see `cpurate`)
 //!
 //! Priority levels:
 //! - 0-15: Dynamic (variable) priority threads
@@ -13,6 +14,7 @@ use core::ptr;
 use super::thread::{KThread, ThreadState, constants};
 use super::prcb::{KPrcb, get_current_prcb_mut};
 use super::apc::{ApcMode, ki_deliver_apc};
+use super::cpurate;
 use crate::containing_record;
 
 /// Insert a thread into the ready queue
@@ -26,6 +28,12 @@ pub unsafe fn ki_ready_thread(thread: *mut KThread) {
     let prcb = get_current_prcb_mut();
     let priority = (*thread).priority as usize;
 
+    // Requeues of preempted/yielding threads are covered by CSwitch
+    let previous_state = (*thread).state;
+    if previous_state != ThreadState::Running && previous_state != ThreadState::Ready {
+        crate::etw::etw_trace_ready_thread(prcb.current_thread, thread, previous_state);
+    }
+
     // Set thread state to Ready
     (*thread).state = ThreadState::Ready;
 
@@ -41,17 +49,24 @@ pub unsafe fn ki_ready_thread(thread: *mut KThread) {
 ///
 /// Removes and returns the first thread from the highest priority non-empty queue.
 /// With SMP support, this enforces affinity - only selects threads that can run
-/// on this CPU.
+/// on this CPU. Threads of a process that has spent its CPU rate budget are
+/// skipped until the rate window ends.
 ///
 /// # Safety
 /// Must be called with interrupts disabled
 pub unsafe fn ki_select_ready_thread(prcb: &mut KPrcb) -> Option<*mut KThread> {
     let cpu_mask = 1u64 << prcb.number;
+    let now = crate::hal::apic::get_tick_count();
 
-    // Try each priority level from highest to lowest
-    let mut priority = prcb.find_highest_ready_priority()?;
+    // Try each priority level from highest to lowest. Queues holding only
+    // threads that can't run here stay marked ready for other processors
+    // and for when the rate window ends.
+    let mut summary = prcb.ready_summary;
+
+    while summary != 0 {
+        let priority = (31 - summary.leading_zeros()) as usize;
+        summary &= !(1 << priority);
 
-    loop {
         let queue = &mut prcb.ready_queues[priority];
 
         // Scan this priority's queue for a thread with compatible affinity
@@ -61,7 +76,7 @@ pub unsafe fn ki_select_ready_thread(prcb: &mut KPrcb) -> Option<*mut KThread> {
             let next_entry = (*entry).flink;
 
             // Check if this thread can run on this CPU
-            if (*thread).affinity & cpu_mask != 0 {
+            if (*thread).affinity & cpu_mask != 0 && !cpurate::ki_is_rate_throttled(thread, now) {
                 // Found a compatible thread - remove it from queue
                 (*entry).remove_entry();
 
@@ -76,18 +91,9 @@ pub unsafe fn ki_select_ready_thread(prcb: &mut KPrcb) -> Option<*mut KThread> {
             entry = next_entry;
         }
 
-        // No compatible threads at this priority, try next priority
-        if priority == 0 {
-            break; // Checked all priorities
-        }
-
-        // Clear the ready bit for this priority since we didn't find anything
-        prcb.clear_ready_bit(priority);
-
-        // Find next priority
-        match prcb.find_highest_ready_priority() {
-            Some(p) if p < priority => priority = p,
-            _ => break,
+        // Nothing left at this priority at all
+        if queue.is_empty() {
+            prcb.clear_ready_bit(priority);
         }
     }
 
@@ -138,15 +144,63 @@ pub unsafe fn ki_quantum_end() {
         (*current).quantum = constants::THREAD_QUANTUM;
 
         // For non-realtime threads, decay priority
+        let old_priority = (*current).priority;
         if !(*current).is_realtime() && (*current).priority > (*current).base_priority {
             (*current).priority -= 1;
         }
+        crate::etw::etw_trace_quantum_end(current, old_priority);
 
         // Request dispatch
         ki_dispatch_interrupt();
     }
 }
 
+/// Charge the current clock tick to the running thread and its process
+///
+/// Equivalent to KiUpdateRunTime. Ticks spent in a processor's idle thread
+/// go to the Idle process and are counted as idle time, which is what
+/// Idle% in `top` is derived from.
+///
+/// # Safety
+/// Must be called from the clock interrupt
+pub unsafe fn ki_update_run_time(user_mode: bool) {
+    use super::process::CLOCK_TICK_100NS;
+    use super::perfctr;
+
+    let prcb = get_current_prcb_mut();
+    let current = prcb.current_thread;
+
+    if current.is_null() {
+        return;
+    }
+
+    if user_mode {
+        (*current).user_time = (*current).user_time.wrapping_add(1);
+    } else {
+        (*current).kernel_time = (*current).kernel_time.wrapping_add(1);
+    }
+
+    let process = (*current).process;
+    if !process.is_null() {
+        (*process).charge_time(user_mode, CLOCK_TICK_100NS);
+    }
+
+    // CPU rate budget spent: end the quantum on this tick
+    if current != prcb.idle_thread
+        && cpurate::ki_charge_cpu_rate(process, crate::hal::apic::get_tick_count())
+    {
+        (*current).quantum = 0;
+    }
+
+    if current == prcb.idle_thread {
+        perfctr::add_idle_time(CLOCK_TICK_100NS);
+    } else if user_mode {
+        perfctr::add_user_time(CLOCK_TICK_100NS);
+    } else {
+        perfctr::add_kernel_time(CLOCK_TICK_100NS);
+    }
+}
+
 /// Try to steal work from another CPU's ready queue
 ///
 /// This implements work stealing for load balancing. When a CPU has no local
@@ -164,6 +218,7 @@ pub unsafe fn ki_try_steal_thread(prcb: &mut KPrcb) -> Option<*mut KThread> {
 
     let current_cpu = prcb.number as usize;
     let cpu_mask = 1u64 << current_cpu;
+    let now = crate::hal::apic::get_tick_count();
 
     // Find the busiest CPU (most ready threads)
     let mut busiest_cpu = None;
@@ -197,7 +252,9 @@ pub unsafe fn ki_try_steal_thread(prcb: &mut KPrcb) -> Option<*mut KThread> {
                     let next_entry = (*entry).flink;
 
                     // Check if this thread can run on our CPU
-                    if (*thread).affinity & cpu_mask != 0 {
+                    if (*thread).affinity & cpu_mask != 0
+                        && !cpurate::ki_is_rate_throttled(thread, now)
+                    {
                         // Found a compatible thread - steal it!
                         (*entry).remove_entry();
 
@@ -279,6 +336,7 @@ unsafe fn ki_swap_context_internal() {
 
     // Clear next thread
     prcb.next_thread = ptr::null_mut();
+    let quantum_end = prcb.quantum_end;
 
     // Update states - but don't put idle thread back on ready queue
     if !old_thread.is_null()
@@ -291,6 +349,8 @@ unsafe fn ki_swap_context_internal() {
             }
         }
 
+    crate::etw::etw_trace_cswitch(old_thread, new_thread, prcb.idle_thread, quantum_end);
+
     (*new_thread).state = ThreadState::Running;
     prcb.current_thread = new_thread;
     prcb.context_switches += 1;
@@ -361,8 +421,17 @@ pub unsafe fn ki_boost_priority(thread: *mut KThread, boost: i8) {
         return;
     }
 
+    let old_priority = (*thread).priority;
     let new_priority = ((*thread).base_priority + boost).min(constants::LOW_REALTIME_PRIORITY - 1);
     (*thread).priority = new_priority;
+
+    crate::etw::etw_trace_priority(
+        crate::etw::SchedEventType::PriorityBoost,
+        get_current_prcb_mut().current_thread,
+        thread,
+        old_priority,
+        boost as u32,
+    );
 }
 
 /// Set a thread's base priority
@@ -374,6 +443,14 @@ pub unsafe fn ke_set_priority(thread: *mut KThread, priority: i8) {
     (*thread).base_priority = priority;
     (*thread).priority = priority;
 
+    crate::etw::etw_trace_priority(
+        crate::etw::SchedEventType::PriorityChange,
+        get_current_prcb_mut().current_thread,
+        thread,
+        old_priority,
+        0,
+    );
+
     // If thread is ready and priority changed, may need to requeue
     if (*thread).state == ThreadState::Ready && priority != old_priority {
         ki_unready_thread(thread);
@@ -391,53 +468,18 @@ pub unsafe fn ke_set_priority(thread: *mut KThread, priority: i8) {
 
 /// Delay execution for the specified number of milliseconds
 ///
-/// Puts the current thread to sleep for approximately the specified time.
-///
-/// # Safety
-/// Must be called from thread context
-/// Delay execution for the specified number of milliseconds
-///
-/// This is the proper timer-based implementation that puts the thread to sleep
-/// and wakes it when the timer expires, rather than busy-waiting.
+/// Puts the thread to sleep on a timer and wakes it when the timer
+/// expires, rather than busy-waiting.
 ///
 /// Equivalent to KeDelayExecutionThread
 pub unsafe fn ki_delay_execution(delay_ms: u64) {
-    use super::timer::KTimer;
-    use super::wait::ke_wait_for_single_object;
-    use super::dispatcher::DispatcherHeader;
-
     if delay_ms == 0 {
         // Zero delay just yields
         ki_yield();
         return;
     }
 
-    // Get the current thread
-    let prcb = super::prcb::get_current_prcb_mut();
-    let thread = prcb.current_thread;
-
-    if thread.is_null() {
-        return;
-    }
-
-    // Use a static timer for sleep operations
-    // In a full implementation, each thread would have its own wait timer
-    static SLEEP_TIMER: KTimer = KTimer::new();
-
-    // Initialize and set the timer
-    SLEEP_TIMER.init();
-    SLEEP_TIMER.set(delay_ms as u32, 0, None);
-
-    // Wait for the timer to expire
-    // The wait infrastructure handles putting the thread to sleep
-    // and waking it when the timer fires
-    let _ = ke_wait_for_single_object(
-        &SLEEP_TIMER.header as *const DispatcherHeader as *mut DispatcherHeader,
-        Some(delay_ms),
-    );
-
-    // Cancel timer in case wait returned early (e.g., due to APC)
-    SLEEP_TIMER.cancel();
+    super::wait::ke_delay_execution_alertable(delay_ms, false);
 }
 
 /// List all active threads
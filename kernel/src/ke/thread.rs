@@ -161,11 +161,12 @@ pub struct KThread {
     /// APC state (contains kernel and user APC queues)
     pub apc_state: KApcState,
 
-    /// Special APC disable count
-    /// When non-zero, normal kernel APCs cannot be delivered
+    /// Guarded region depth, counted down from 0
+    /// When non-zero, no kernel APCs are delivered
     pub special_apc_disable: i16,
 
-    /// APC disable count (kernel APCs disabled when non-zero)
+    /// Critical region depth, counted down from 0
+    /// When non-zero, normal kernel APCs are not delivered
     pub kernel_apc_disable: i16,
 
     /// Whether thread is in an alertable wait
@@ -190,6 +191,13 @@ pub struct KThread {
     /// Wait reason code
     pub wait_reason: u8,
 
+    // Time accounting
+    /// Clock ticks spent running in kernel mode
+    pub kernel_time: u32,
+
+    /// Clock ticks spent running in user mode
+    pub user_time: u32,
+
     /// Whether user APC is pending
     pub user_apc_pending: bool,
 
@@ -217,6 +225,12 @@ pub struct KThread {
     pub copy_on_open: bool,
     /// Effective-only flag for impersonation
     pub effective_only: bool,
+
+    // Wait metadata (deadlock detection and wait chain inspection)
+    /// Tick (ms) at which the current wait began
+    pub wait_start_tick: u64,
+    /// The current wait has a timeout and ends on its own
+    pub wait_timed: bool,
 }
 
 impl KThread {
@@ -250,6 +264,8 @@ impl KThread {
             wait_type: WaitType::WaitAny,
             wait_count: 0,
             wait_reason: 0,
+            kernel_time: 0,
+            user_time: 0,
             user_apc_pending: false,
             queue: ptr::null_mut(),
             queue_list_entry: ListEntry::new(),
@@ -261,6 +277,8 @@ impl KThread {
             impersonating: false,
             copy_on_open: false,
             effective_only: false,
+            wait_start_tick: 0,
+            wait_timed: false,
         }
     }
 
@@ -353,6 +371,11 @@ impl KThread {
         self.process = process;
         self.state = ThreadState::Initialized;
 
+        // New threads start with the process's default affinity
+        if !process.is_null() {
+            self.affinity = (*process).affinity;
+        }
+
         // Initialize APC state
         self.apc_state.init(process);
         self.special_apc_disable = 0;
@@ -375,6 +398,47 @@ impl KThread {
     pub fn is_realtime(&self) -> bool {
         self.priority >= constants::LOW_REALTIME_PRIORITY
     }
+
+    /// Capture the return addresses of a thread that is not running
+    ///
+    /// Starts from the frame `ki_swap_context` saved on its kernel stack
+    /// (flags, r15-r12, rbp, rbx, return address) and follows the RBP
+    /// chain while it stays inside the thread's stack. Returns the number
+    /// of frames written.
+    ///
+    /// # Safety
+    /// The thread must be switched out (waiting or ready), not running.
+    pub unsafe fn capture_stack(&self, frames: &mut [u64]) -> usize {
+        let limit = self.stack_limit as u64;
+        let base = self.stack_base as u64;
+        let in_stack = |addr: u64, len: u64| addr >= limit && addr + len <= base && addr % 8 == 0;
+
+        let saved = self.kernel_stack as u64;
+        if frames.is_empty() || limit == 0 || !in_stack(saved, 8 * 8) {
+            return 0;
+        }
+
+        let saved = saved as *const u64;
+        frames[0] = *saved.add(7);
+        let mut count = 1;
+        let mut rbp = *saved.add(5);
+
+        while count < frames.len() && in_stack(rbp, 16) {
+            let ret = *((rbp + 8) as *const u64);
+            if ret == 0 {
+                break;
+            }
+            frames[count] = ret;
+            count += 1;
+
+            let next = *(rbp as *const u64);
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+        count
+    }
 }
 
 impl Default for KThread {
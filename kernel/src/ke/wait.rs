@@ -310,6 +310,8 @@ unsafe fn wait_for_objects_blocking(
     (*thread).wait_block_list = wait_blocks.as_mut_ptr();
     (*thread).wait_type = wait_type;
     (*thread).wait_count = count as u8;
+    (*thread).wait_start_tick = crate::hal::apic::get_tick_count();
+    (*thread).wait_timed = using_timeout;
 
     // Initialize wait blocks and insert into object wait lists
     for (i, &object) in objects.iter().enumerate() {
@@ -713,6 +715,8 @@ unsafe fn wait_for_objects_blocking_alertable(
     let timer = KTimer::new();
     timer.init();
     let has_timeout = timeout_ms.is_some() && timeout_ms != Some(TIMEOUT_INFINITE);
+    (*thread).wait_start_tick = crate::hal::apic::get_tick_count();
+    (*thread).wait_timed = has_timeout;
 
     if has_timeout {
         let ms = timeout_ms.unwrap();
@@ -784,6 +788,11 @@ unsafe fn wait_for_objects_blocking_alertable(
 
 /// Simple delay with alertable support
 ///
+/// The thread blocks on a timer and the processor is free for other
+/// threads (or the idle loop) until it expires. User APCs pending when the
+/// delay starts end it at once; ones queued during the delay are delivered
+/// when it ends.
+///
 /// # Arguments
 /// * `milliseconds` - Time to delay in milliseconds
 /// * `alertable` - If true, APCs can interrupt the delay
@@ -818,10 +827,9 @@ pub unsafe fn ke_delay_execution_alertable(milliseconds: u64, alertable: bool) -
     // Set alertable flag during wait
     let old_alertable = (*thread).alertable;
     (*thread).alertable = alertable;
-    (*thread).state = ThreadState::Waiting;
 
-    // Yield until timer fires or we're alerted
-    scheduler::ki_yield();
+    // Block until the timer fires
+    ke_wait_for_single_object(&timer.header as *const _ as *mut DispatcherHeader, None);
 
     // Restore alertable flag
     (*thread).alertable = old_alertable;
@@ -0,0 +1,72 @@
+//! AHCI Controller Tests
+//!
+//! Checks every AHCI SATA controller on the PCI bus against what the
+//! AHCI specification requires of its configuration space: the class
+//! triple, an interrupt pin, and the HBA register block (ABAR, BAR5)
+//! decoded as 32-bit non-prefetchable memory.
+//!
+//! Needs an AHCI controller (requires-ahci); under QEMU add e.g.
+//! `-device ahci,id=ahci` or use `-machine q35`.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use crate::hal::pci::{self, config, PciBar, PciLocation};
+use super::{KTest, KTestContext, KTestRequirement, KTestSuite};
+
+pub static AHCI_SUITE: KTestSuite = KTestSuite {
+    name: "ahci",
+    description: "AHCI controllers follow the spec",
+    requires: &[KTestRequirement::Ahci],
+    tests: &[
+        KTest { name: "controller-class", run: test_controller_class },
+        KTest { name: "abar", run: test_abar },
+    ],
+};
+
+/// Mass storage controller
+const CLASS_MASS_STORAGE: u8 = 0x01;
+/// SATA controller
+const SUBCLASS_SATA: u8 = 0x06;
+/// AHCI 1.0 programming interface
+const PROG_IF_AHCI: u8 = 0x01;
+
+/// BAR holding the HBA registers (ABAR)
+const ABAR_INDEX: u8 = 5;
+
+/// Find every AHCI controller on the PCI bus
+pub fn find_ahci_controllers() -> Vec<PciLocation> {
+    pci::find_devices_by_class(CLASS_MASS_STORAGE, SUBCLASS_SATA, Some(PROG_IF_AHCI))
+}
+
+fn test_controller_class(ctx: &mut KTestContext) {
+    for loc in find_ahci_controllers() {
+        let class = pci::pci_read_config_u8(loc, config::CLASS_CODE);
+        let subclass = pci::pci_read_config_u8(loc, config::SUBCLASS);
+        let prog_if = pci::pci_read_config_u8(loc, config::PROG_IF);
+        ctx.check((class, subclass, prog_if) == (CLASS_MASS_STORAGE, SUBCLASS_SATA, PROG_IF_AHCI),
+            format_args!("{:?}: class {:02x}:{:02x}:{:02x}", loc, class, subclass, prog_if));
+
+        let vendor = pci::pci_read_config_u16(loc, config::VENDOR_ID);
+        ctx.check(vendor != 0 && vendor != 0xFFFF, format_args!("{:?}: vendor {:#06x}", loc, vendor));
+
+        let pin = pci::pci_read_config_u8(loc, config::INTERRUPT_PIN);
+        ctx.check((1..=4).contains(&pin), format_args!("{:?}: interrupt pin {}", loc, pin));
+    }
+}
+
+fn test_abar(ctx: &mut KTestContext) {
+    for loc in find_ahci_controllers() {
+        match pci::pci_get_bar(loc, ABAR_INDEX) {
+            Some(PciBar::Memory { address, prefetchable, is_64bit, .. }) => {
+                ctx.check(address != 0, format_args!("{:?}: ABAR not assigned", loc));
+                ctx.check(!prefetchable, format_args!("{:?}: ABAR prefetchable", loc));
+                ctx.check(!is_64bit, format_args!("{:?}: ABAR is 64-bit", loc));
+            }
+            Some(PciBar::Io { port }) => {
+                ctx.check(false, format_args!("{:?}: ABAR is I/O port {:#x}", loc, port));
+            }
+            None => ctx.check(false, format_args!("{:?}: no ABAR", loc)),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! On-Access Scanner Tests
+//!
+//! Drives the avscan demo filter through the filter manager the way the
+//! create path does: a name added to the registry blacklist must be
+//! denied after a reload, and allowed again once it is removed.
+//!
+//! The blacklist entry used here is removed before the test returns, and
+//! the scanner is stopped again if the test had to start it.
+
+use crate::cm;
+use crate::fsrtl::{self, FltCallbackData, FltOperation, FltPreopCallbackStatus, AVSCAN_BLACKLIST_KEY};
+use super::{KTest, KTestContext, KTestSuite};
+
+pub static AVSCAN_SUITE: KTestSuite = KTestSuite {
+    name: "avscan",
+    description: "On-access scanner blacklist",
+    requires: &[],
+    tests: &[
+        KTest { name: "registry-key", run: test_registry_key },
+        KTest { name: "blacklist-denies", run: test_blacklist_denies },
+    ],
+};
+
+/// Blacklist pattern no real file is expected to match
+const TEST_PATTERN: &str = "KTESTAVS.BLK";
+
+/// A path the pattern matches, and one it does not
+const BLOCKED_PATH: &str = "C:\\TEMP\\KTESTAVS.BLK";
+const ALLOWED_PATH: &str = "C:\\TEMP\\KTESTAVS.TXT";
+
+const FILE_READ_DATA: u32 = 0x0000_0001;
+const STATUS_ACCESS_DENIED: i32 = 0xC0000022u32 as i32;
+
+/// Send a create for `path` through the registered pre-create callbacks
+fn pre_create(path: &str) -> (FltPreopCallbackStatus, i32) {
+    let mut data = FltCallbackData::empty();
+    data.operation = FltOperation::Create;
+    data.io_params.access_mask = FILE_READ_DATA;
+    data.set_file_path(path);
+    let status = fsrtl::flt_invoke_pre_callbacks(&mut data);
+    (status, data.io_status)
+}
+
+fn test_registry_key(ctx: &mut KTestContext) {
+    // The blacklist key must resolve under a registry root the cm accepts
+    let status = unsafe { cm::cm_write_string(AVSCAN_BLACKLIST_KEY, TEST_PATTERN, TEST_PATTERN) };
+    ctx.check(status == cm::CmStatus::Success,
+        format_args!("cannot write {}: {:?}", AVSCAN_BLACKLIST_KEY, status));
+    match unsafe { cm::cm_open_key(AVSCAN_BLACKLIST_KEY) } {
+        Ok(key) => {
+            let status = unsafe { cm::cm_delete_value(key, TEST_PATTERN) };
+            ctx.check(status == cm::CmStatus::Success, format_args!("cannot delete test entry: {:?}", status));
+        }
+        Err(e) => ctx.check(false, format_args!("cannot open {}: {:?}", AVSCAN_BLACKLIST_KEY, e)),
+    }
+}
+
+fn test_blacklist_denies(ctx: &mut KTestContext) {
+    let was_running = fsrtl::avscan_get_stats().running;
+    if fsrtl::avscan_start().is_none() {
+        ctx.check(false, format_args!("cannot start the scanner"));
+        return;
+    }
+
+    let before = fsrtl::avscan_reload_blacklist();
+    let status = unsafe { cm::cm_write_string(AVSCAN_BLACKLIST_KEY, TEST_PATTERN, TEST_PATTERN) };
+    ctx.check(status == cm::CmStatus::Success, format_args!("cannot add blacklist entry: {:?}", status));
+
+    let after = fsrtl::avscan_reload_blacklist();
+    ctx.check(after == before + 1, format_args!("reload found {} entries, expected {}", after, before + 1));
+
+    let (status, io_status) = pre_create(BLOCKED_PATH);
+    ctx.check(status == FltPreopCallbackStatus::Complete && io_status == STATUS_ACCESS_DENIED,
+        format_args!("{} not denied: {:?}, {:#x}", BLOCKED_PATH, status, io_status));
+
+    let (_, io_status) = pre_create(ALLOWED_PATH);
+    ctx.check(io_status != STATUS_ACCESS_DENIED, format_args!("{} denied", ALLOWED_PATH));
+
+    // Removing the entry lifts the block on the next reload
+    if let Ok(key) = unsafe { cm::cm_open_key(AVSCAN_BLACKLIST_KEY) } {
+        let _ = unsafe { cm::cm_delete_value(key, TEST_PATTERN) };
+    }
+    let restored = fsrtl::avscan_reload_blacklist();
+    ctx.check(restored == before, format_args!("reload after removal found {} entries", restored));
+
+    let (_, io_status) = pre_create(BLOCKED_PATH);
+    ctx.check(io_status != STATUS_ACCESS_DENIED, format_args!("{} still denied after removal", BLOCKED_PATH));
+
+    if !was_running {
+        fsrtl::avscan_stop();
+    }
+}
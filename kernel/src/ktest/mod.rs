@@ -0,0 +1,312 @@
+//! Kernel Self-Tests (ktest)
+//!
+//! The kernel cannot run under `cargo test`, so checks that need a live
+//! kernel (real page tables, the syscall table, initialized subsystems)
+//! are kept here as named suites and run on demand by the `ktest` shell
+//! command.
+//!
+//! A test is a function that makes any number of checks through its
+//! `KTestContext`; it passes when none of them failed. Failed checks are
+//! reported as they happen through the caller's output sink.
+//!
+//! # Suites
+//!
+//! - **syscall**: Every system service called with system-space and
+//!   non-canonical pointers must fail with STATUS_ACCESS_VIOLATION
+//! - **pattern**: rtl DOS wildcard and regex matching
+//! - **avscan**: Registry blacklist entries deny opens after a reload
+//! - **net**: Network adapters are sane and transmit frames (requires-net)
+//! - **ahci**: AHCI controllers are configured as the spec demands
+//!   (requires-ahci)
+//!
+//! Add a suite by listing it in `KTEST_SUITES`.
+//!
+//! # Requirements
+//!
+//! A suite that exercises a device driver lists what it needs in
+//! `requires`. When a requirement is not met on this machine the whole
+//! suite is reported as skipped rather than failed, so one set of suites
+//! runs on every QEMU configuration.
+//!
+//! # Unattended Runs
+//!
+//! Booting with `ktest=<suite[,suite...]|all>` on the kernel command line
+//! runs the named suites on a kernel thread once the system is up,
+//! streams machine-readable results to COM2 (see `results`) and then
+//! writes the verdict to the QEMU `isa-debug-exit` port, so a test host
+//! gets the results and an exit status without a person at the console.
+
+extern crate alloc;
+
+pub mod ahci;
+pub mod avscan;
+pub mod net;
+pub mod pattern;
+pub mod results;
+pub mod syscall;
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Failed checks reported per test; the rest are only counted
+const MAX_REPORTED_FAILURES: u32 = 16;
+
+/// State of the running test
+pub struct KTestContext {
+    suite: &'static str,
+    test: &'static str,
+    checks: u32,
+    failures: u32,
+    report: fn(&str),
+}
+
+impl KTestContext {
+    /// Record a check; reports `what` when it failed
+    pub fn check(&mut self, ok: bool, what: fmt::Arguments) {
+        self.checks += 1;
+        if !ok {
+            self.failures += 1;
+            if self.failures <= MAX_REPORTED_FAILURES {
+                let message = format!("{}", what);
+                results::ktest_result_failure(self.suite, self.test, &message);
+                (self.report)(&format!("    FAIL: {}", message));
+            } else if self.failures == MAX_REPORTED_FAILURES + 1 {
+                (self.report)("    (further failures not shown)");
+            }
+        }
+    }
+}
+
+/// A single test
+pub struct KTest {
+    pub name: &'static str,
+    pub run: fn(&mut KTestContext),
+}
+
+/// Hardware a suite needs before it can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KTestRequirement {
+    /// An AHCI SATA controller (PCI class 01, subclass 06, interface 01)
+    Ahci,
+    /// A network adapter other than the loopback device
+    Net,
+}
+
+impl KTestRequirement {
+    pub fn name(self) -> &'static str {
+        match self {
+            KTestRequirement::Ahci => "requires-ahci",
+            KTestRequirement::Net => "requires-net",
+        }
+    }
+
+    /// What is missing when the requirement is not met
+    pub fn absent_reason(self) -> &'static str {
+        match self {
+            KTestRequirement::Ahci => "no AHCI controller",
+            KTestRequirement::Net => "no network adapter",
+        }
+    }
+
+    /// Check whether this machine has the hardware
+    pub fn is_met(self) -> bool {
+        match self {
+            KTestRequirement::Ahci => !ahci::find_ahci_controllers().is_empty(),
+            KTestRequirement::Net => net::hardware_device_count() != 0,
+        }
+    }
+}
+
+/// A named group of tests
+pub struct KTestSuite {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Hardware the suite needs; it is skipped if any is absent
+    pub requires: &'static [KTestRequirement],
+    pub tests: &'static [KTest],
+}
+
+/// All registered suites
+pub static KTEST_SUITES: &[&KTestSuite] = &[
+    &syscall::SYSCALL_SUITE,
+    &pattern::PATTERN_SUITE,
+    &avscan::AVSCAN_SUITE,
+    &net::NET_SUITE,
+    &ahci::AHCI_SUITE,
+];
+
+/// Outcome of a suite run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KTestSummary {
+    pub tests: u32,
+    pub tests_failed: u32,
+    /// Tests not run because a requirement was not met
+    pub tests_skipped: u32,
+    pub checks: u32,
+    pub checks_failed: u32,
+}
+
+impl KTestSummary {
+    pub fn passed(&self) -> bool {
+        self.tests_failed == 0
+    }
+
+    /// Fold another summary into this one
+    pub fn add(&mut self, other: &KTestSummary) {
+        self.tests += other.tests;
+        self.tests_failed += other.tests_failed;
+        self.tests_skipped += other.tests_skipped;
+        self.checks += other.checks;
+        self.checks_failed += other.checks_failed;
+    }
+}
+
+/// Find a suite by name, ignoring case
+pub fn ktest_find_suite(name: &str) -> Option<&'static KTestSuite> {
+    KTEST_SUITES.iter().copied().find(|s| s.name.eq_ignore_ascii_case(name))
+}
+
+/// The first requirement of `suite` this machine does not meet
+pub fn ktest_missing_requirement(suite: &KTestSuite) -> Option<KTestRequirement> {
+    suite.requires.iter().copied().find(|r| !r.is_met())
+}
+
+/// Run every test of a suite, reporting each result through `report`
+///
+/// A suite with an unmet requirement is skipped: none of its tests run
+/// and they are all counted in `tests_skipped`.
+pub fn ktest_run_suite(suite: &KTestSuite, report: fn(&str)) -> KTestSummary {
+    let mut summary = KTestSummary::default();
+    results::ktest_result_suite(suite);
+
+    if let Some(missing) = ktest_missing_requirement(suite) {
+        summary.tests_skipped = suite.tests.len() as u32;
+        results::ktest_result_skip(suite, missing);
+        report(&format!("  SKIP: {} ({})", missing.name(), missing.absent_reason()));
+        crate::serial_println!("[KTEST] {}: skipped, {}", suite.name, missing.absent_reason());
+        results::ktest_result_summary(suite.name, &summary);
+        return summary;
+    }
+
+    for test in suite.tests {
+        let mut ctx = KTestContext {
+            suite: suite.name,
+            test: test.name,
+            checks: 0,
+            failures: 0,
+            report,
+        };
+        (test.run)(&mut ctx);
+        results::ktest_result_test(suite.name, test.name, ctx.checks, ctx.failures);
+
+        summary.tests += 1;
+        summary.checks += ctx.checks;
+        summary.checks_failed += ctx.failures;
+        if ctx.failures != 0 {
+            summary.tests_failed += 1;
+        }
+        report(&format!(
+            "  {:<24} {} ({} checks, {} failed)",
+            test.name,
+            if ctx.failures == 0 { "PASS" } else { "FAIL" },
+            ctx.checks,
+            ctx.failures,
+        ));
+    }
+
+    crate::serial_println!(
+        "[KTEST] {}: {}/{} tests passed, {} checks",
+        suite.name,
+        summary.tests - summary.tests_failed,
+        summary.tests,
+        summary.checks,
+    );
+    results::ktest_result_summary(suite.name, &summary);
+    summary
+}
+
+/// Resolve a comma-separated suite list, or `all`
+///
+/// Returns the name that matched nothing on failure.
+pub fn ktest_select_suites(list: &str) -> Result<Vec<&'static KTestSuite>, &str> {
+    if list.eq_ignore_ascii_case("all") {
+        return Ok(KTEST_SUITES.to_vec());
+    }
+    let mut suites = Vec::new();
+    for name in list.split(',').filter(|n| !n.is_empty()) {
+        suites.push(ktest_find_suite(name).ok_or(name)?);
+    }
+    Ok(suites)
+}
+
+/// Run `suites` in order and emit the overall verdict
+pub fn ktest_run_suites(suites: &[&KTestSuite], report: fn(&str)) -> KTestSummary {
+    let mut total = KTestSummary::default();
+    for suite in suites {
+        report(&format!("Running {} ({})", suite.name, suite.description));
+        total.add(&ktest_run_suite(suite, report));
+    }
+    results::ktest_result_summary("all", &total);
+    results::ktest_result_done(&total);
+    total
+}
+
+/// QEMU isa-debug-exit port (`-device isa-debug-exit,iobase=0xf4,iosize=0x04`)
+///
+/// QEMU exits with status `(value << 1) | 1`: 1 for a pass, 3 for a fail.
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+
+/// Priority of the unattended runner thread
+const BOOT_RUN_PRIORITY: i8 = 8;
+
+/// Give drivers and DHCP time to settle before an unattended run
+const BOOT_RUN_SETTLE_MS: u64 = 3000;
+
+/// The `ktest=` boot option, if given
+fn boot_suite_list() -> Option<&'static str> {
+    crate::bootinfo::boot_cmdline().split_whitespace().find_map(|opt| {
+        let (key, value) = opt.trim_start_matches(['/', '-']).split_once('=')?;
+        key.eq_ignore_ascii_case("ktest").then_some(value)
+    })
+}
+
+/// Start an unattended run if the command line asks for one
+pub fn ktest_start_at_boot() {
+    let Some(list) = boot_suite_list() else {
+        return;
+    };
+    if let Err(name) = ktest_select_suites(list) {
+        crate::serial_println!("[KTEST] Boot run: no suite named '{}'", name);
+        return;
+    }
+    if unsafe { crate::ke::init::create_thread(BOOT_RUN_PRIORITY, boot_run_thread) }.is_none() {
+        crate::serial_println!("[KTEST] Boot run: cannot create thread");
+        return;
+    }
+    crate::serial_println!("[KTEST] Boot run of '{}' scheduled", list);
+}
+
+fn serial_report(line: &str) {
+    crate::serial_println!("[KTEST] {}", line.trim_start());
+}
+
+/// Unattended runner thread
+fn boot_run_thread() {
+    unsafe { crate::ke::wait::ke_delay_execution_alertable(BOOT_RUN_SETTLE_MS, false) };
+
+    // Validated when the thread was started
+    let suites = boot_suite_list().and_then(|list| ktest_select_suites(list).ok()).unwrap_or_default();
+    let total = ktest_run_suites(&suites, serial_report);
+    crate::serial_println!(
+        "[KTEST] Boot run {}: {}/{} tests passed, {} skipped",
+        if total.passed() { "PASSED" } else { "FAILED" },
+        total.tests - total.tests_failed,
+        total.tests,
+        total.tests_skipped,
+    );
+
+    // Ends the VM when the exit device is present; a no-op otherwise
+    crate::hal::port::write_port_u32(DEBUG_EXIT_PORT, if total.passed() { 0 } else { 1 });
+    unsafe { crate::ke::init::exit_thread() }
+}
@@ -0,0 +1,80 @@
+//! Network Adapter Tests
+//!
+//! End-to-end checks of every registered network adapter other than the
+//! loopback device: the driver reported a usable configuration, and a
+//! frame handed to the stack leaves through the driver's transmit path
+//! and is counted. The frame sent is an ARP probe (sender address
+//! 0.0.0.0), which nothing on the segment answers or records.
+//!
+//! Needs a NIC (requires-net); under QEMU add e.g.
+//! `-nic user,model=virtio-net-pci`.
+
+use crate::net::{self, arp, ip::Ipv4Address, loopback::LOOPBACK_MAC};
+use super::{KTest, KTestContext, KTestRequirement, KTestSuite};
+
+pub static NET_SUITE: KTestSuite = KTestSuite {
+    name: "net",
+    description: "Network adapters configure and transmit",
+    requires: &[KTestRequirement::Net],
+    tests: &[
+        KTest { name: "adapter-config", run: test_adapter_config },
+        KTest { name: "transmit", run: test_transmit },
+    ],
+};
+
+/// Smallest MTU an IPv4 host must support
+const MIN_IPV4_MTU: u32 = 576;
+
+/// Indexes of the registered adapters that are real hardware
+fn hardware_devices() -> impl Iterator<Item = usize> {
+    (0..net::get_device_count())
+        .filter(|&i| net::get_device(i).is_some_and(|d| d.info.mac_address != LOOPBACK_MAC))
+}
+
+/// Number of network adapters other than loopback
+pub fn hardware_device_count() -> usize {
+    hardware_devices().count()
+}
+
+fn test_adapter_config(ctx: &mut KTestContext) {
+    for index in hardware_devices() {
+        let Some(device) = net::get_device(index) else {
+            continue;
+        };
+        let info = &device.info;
+        let caps = &info.capabilities;
+
+        ctx.check(!info.name.is_empty(), format_args!("adapter {} has no name", index));
+        ctx.check(info.mac_address.is_unicast() && info.mac_address != net::ethernet::MacAddress::ZERO,
+            format_args!("{}: bad MAC {:?}", info.name, info.mac_address));
+        ctx.check(caps.mtu >= MIN_IPV4_MTU, format_args!("{}: MTU {} too small", info.name, caps.mtu));
+        ctx.check(caps.max_frame_size as usize >= caps.mtu as usize + net::ethernet::ETHERNET_HEADER_SIZE,
+            format_args!("{}: frame size {} below MTU {}", info.name, caps.max_frame_size, caps.mtu));
+        ctx.check(device.tx_callback.is_some(), format_args!("{}: no transmit callback", info.name));
+        ctx.check(device.is_ready(), format_args!("{}: not ready ({:?})", info.name, device.state()));
+    }
+}
+
+fn test_transmit(ctx: &mut KTestContext) {
+    for index in hardware_devices() {
+        let Some(device) = net::get_device(index) else {
+            continue;
+        };
+        let mac = device.info.mac_address;
+        let packets = device.stats.tx_packets;
+        let errors = device.stats.tx_errors;
+        let target = device.ip_address.unwrap_or(Ipv4Address::ANY);
+
+        let sent = arp::send_arp_request(index, mac, Ipv4Address::ANY, target);
+        ctx.check(sent.is_ok(), format_args!("{}: transmit failed: {:?}", device.info.name, sent));
+
+        let Some(device) = net::get_device(index) else {
+            ctx.check(false, format_args!("adapter {} vanished", index));
+            continue;
+        };
+        ctx.check(device.stats.tx_packets == packets + 1,
+            format_args!("{}: tx_packets {} -> {}", device.info.name, packets, device.stats.tx_packets));
+        ctx.check(device.stats.tx_errors == errors,
+            format_args!("{}: tx_errors {} -> {}", device.info.name, errors, device.stats.tx_errors));
+    }
+}
@@ -0,0 +1,245 @@
+//! Pattern Matching Tests
+//!
+//! Table-driven checks of the rtl DOS wildcard matcher against the
+//! FsRtlIsNameInExpression rules, and of the regex subset: syntax,
+//! anchoring, leftmost-longest match positions and compile errors.
+
+extern crate alloc;
+
+use alloc::string::String;
+use crate::rtl::pattern::{rtl_does_name_contain_wildcards, rtl_is_name_in_expression, RegexError, RtlRegex};
+use super::{KTest, KTestContext, KTestSuite};
+
+pub static PATTERN_SUITE: KTestSuite = KTestSuite {
+    name: "pattern",
+    description: "rtl wildcard and regex matching",
+    requires: &[],
+    tests: &[
+        KTest { name: "wildcard-basic", run: test_wildcard_basic },
+        KTest { name: "wildcard-dos", run: test_wildcard_dos },
+        KTest { name: "wildcard-case", run: test_wildcard_case },
+        KTest { name: "wildcard-long-names", run: test_wildcard_long_names },
+        KTest { name: "regex-syntax", run: test_regex_syntax },
+        KTest { name: "regex-anchors", run: test_regex_anchors },
+        KTest { name: "regex-find", run: test_regex_find },
+        KTest { name: "regex-errors", run: test_regex_errors },
+    ],
+};
+
+/// (expression, name, expected) with case folded
+const WILDCARD_BASIC: &[(&str, &str, bool)] = &[
+    ("", "", true),
+    ("", "a", false),
+    ("*", "", true),
+    ("*", "anything.at.all", true),
+    ("?", "", false),
+    ("?", "a", true),
+    ("?", "ab", false),
+    ("??", "ab", true),
+    ("a*", "abc", true),
+    ("*c", "abc", true),
+    ("a*c", "ac", true),
+    ("a*c", "abxc", true),
+    ("a*c", "abxcd", false),
+    ("*.txt", "readme.txt", true),
+    ("*.txt", "readme.txt.bak", false),
+    ("*.*", "readme.txt", true),
+    ("*.*", "readme", false),
+    ("*a*b*c*", "xaybzc", true),
+    ("*a*b*c*", "xaybz", false),
+    ("file?.log", "file1.log", true),
+    ("file?.log", "file10.log", false),
+    ("?.txt", "\u{e9}.txt", true),
+    ("readme.txt", "readme.txt", true),
+    ("readme.txt", "readme.tx", false),
+];
+
+/// DOS_STAR (<), DOS_QM (>) and DOS_DOT (")
+const WILDCARD_DOS: &[(&str, &str, bool)] = &[
+    ("<", "abc", true),
+    ("<", "a.b", false),
+    ("<.txt", "a.txt", true),
+    ("<.txt", "a.b.txt", true),
+    ("<.txt", "atxt", false),
+    ("<.*", "a.b", true),
+    ("<.<", "a.b.c", true),
+    ("<\"*", "readme", true),
+    ("<\"*", "readme.txt", true),
+    (">", "a", true),
+    (">", "", true),
+    (">>>", "ab", true),
+    (">>>", "abcd", false),
+    (">>>.txt", "a.txt", true),
+    (">>>.txt", "abc.txt", true),
+    (">>>.txt", "abcd.txt", false),
+    ("a>.b", "a.b", true),
+    ("foo\"", "foo", true),
+    ("foo\"", "foo.", true),
+    ("foo\"", "foox", false),
+    ("foo\"txt", "foo.txt", true),
+    ("foo\"txt", "footxt", false),
+];
+
+fn check_wildcards(ctx: &mut KTestContext, table: &[(&str, &str, bool)]) {
+    for &(expression, name, expected) in table {
+        let matched = rtl_is_name_in_expression(expression, name, true);
+        ctx.check(matched == expected, format_args!("'{}' vs '{}': got {}", expression, name, matched));
+    }
+}
+
+fn test_wildcard_basic(ctx: &mut KTestContext) {
+    check_wildcards(ctx, WILDCARD_BASIC);
+    ctx.check(rtl_does_name_contain_wildcards("a*"), format_args!("'a*' has no wildcards"));
+    ctx.check(rtl_does_name_contain_wildcards("a<b"), format_args!("'a<b' has no wildcards"));
+    ctx.check(!rtl_does_name_contain_wildcards("a.b"), format_args!("'a.b' has wildcards"));
+}
+
+fn test_wildcard_dos(ctx: &mut KTestContext) {
+    check_wildcards(ctx, WILDCARD_DOS);
+}
+
+fn test_wildcard_case(ctx: &mut KTestContext) {
+    ctx.check(rtl_is_name_in_expression("*.TXT", "a.txt", true), format_args!("case not folded"));
+    ctx.check(!rtl_is_name_in_expression("*.TXT", "a.txt", false), format_args!("case folded when exact"));
+    ctx.check(rtl_is_name_in_expression("README", "readme", true), format_args!("literal case not folded"));
+    ctx.check(!rtl_is_name_in_expression("README", "readme", false), format_args!("literal case folded"));
+    ctx.check(crate::fsrtl::fsrtl_is_name_in_expression("*.sys", "DISK.SYS", true),
+        format_args!("fsrtl disagrees with rtl"));
+}
+
+fn test_wildcard_long_names(ctx: &mut KTestContext) {
+    // Past the inline position set, onto the heap
+    let mut name = String::new();
+    for _ in 0..700 {
+        name.push('a');
+    }
+    name.push('z');
+    ctx.check(rtl_is_name_in_expression("*z", &name, true), format_args!("long name: *z"));
+    ctx.check(rtl_is_name_in_expression("a*a?", &name, true), format_args!("long name: a*a?"));
+    ctx.check(!rtl_is_name_in_expression("*y", &name, true), format_args!("long name: *y"));
+
+    // Many stars over a near miss must still finish promptly
+    ctx.check(!rtl_is_name_in_expression("*a*a*a*a*a*a*a*b", &name[..200], true),
+        format_args!("star-heavy near miss matched"));
+}
+
+/// (pattern, text, expected) with case folded
+const REGEX_MATCHES: &[(&str, &str, bool)] = &[
+    ("abc", "xxabcxx", true),
+    ("abc", "ab", false),
+    ("a.c", "abc", true),
+    ("a.c", "ac", false),
+    ("ab*c", "ac", true),
+    ("ab*c", "abbbc", true),
+    ("ab+c", "ac", false),
+    ("ab+c", "abbc", true),
+    ("colou?r", "color", true),
+    ("colou?r", "colour", true),
+    ("[abc]x", "bx", true),
+    ("[abc]x", "dx", false),
+    ("[a-f]+", "zzz", false),
+    ("[a-f]+", "zzcafe", true),
+    ("[^0-9]", "123", false),
+    ("[^0-9]", "12a", true),
+    ("[]x]", "]", true),
+    ("[x-]", "-", true),
+    ("\\d+", "abc123", true),
+    ("\\d", "abc", false),
+    ("\\D", "123", false),
+    ("\\w+\\s\\w+", "hello world", true),
+    ("\\S", "   ", false),
+    ("[\\d_]", "_", true),
+    ("a\\.b", "a.b", true),
+    ("a\\.b", "axb", false),
+    ("a\\*", "a*", true),
+    ("cat|dog", "hotdog", true),
+    ("cat|dog", "bird", false),
+    ("ERROR", "an error occurred", true),
+    ("a^b", "a^b", true),
+    ("a$b", "a$b", true),
+    (".*", "", true),
+];
+
+fn test_regex_syntax(ctx: &mut KTestContext) {
+    for &(pattern, text, expected) in REGEX_MATCHES {
+        match RtlRegex::compile(pattern, true) {
+            Ok(regex) => {
+                let matched = regex.is_match(text);
+                ctx.check(matched == expected, format_args!("/{}/ vs '{}': got {}", pattern, text, matched));
+            }
+            Err(e) => ctx.check(false, format_args!("/{}/ did not compile: {}", pattern, e)),
+        }
+    }
+
+    let exact = RtlRegex::compile("Error", false).unwrap();
+    ctx.check(!exact.is_match("error"), format_args!("case folded without ignore_case"));
+    let class = RtlRegex::compile("[A-Z]", true).unwrap();
+    ctx.check(class.is_match("q"), format_args!("class case not folded"));
+}
+
+fn test_regex_anchors(ctx: &mut KTestContext) {
+    const ANCHORED: &[(&str, &str, bool)] = &[
+        ("^abc", "abcdef", true),
+        ("^abc", "xabc", false),
+        ("def$", "abcdef", true),
+        ("def$", "defx", false),
+        ("^abc$", "abc", true),
+        ("^abc$", "abcc", false),
+        ("^$", "", true),
+        ("^$", "x", false),
+        ("^a|b$", "xxb", true),
+        ("^a|b$", "xax", false),
+        ("^\\d+$", "2024", true),
+        ("^\\d+$", "20x4", false),
+    ];
+    for &(pattern, text, expected) in ANCHORED {
+        let matched = RtlRegex::compile(pattern, false).map(|r| r.is_match(text));
+        ctx.check(matched == Ok(expected), format_args!("/{}/ vs '{}': got {:?}", pattern, text, matched));
+    }
+}
+
+fn test_regex_find(ctx: &mut KTestContext) {
+    const FINDS: &[(&str, &str, Option<(usize, usize)>)] = &[
+        ("b+", "aabbbcc", Some((2, 5))),
+        ("x*", "abc", Some((0, 0))),
+        ("c$", "abcabc", Some((5, 6))),
+        ("a.*c", "xabcabcx", Some((1, 7))),
+        ("a|ab", "xab", Some((1, 3))),
+        ("\\d\\d", "a1b22c", Some((3, 5))),
+        ("cat|at", "scat", Some((1, 4))),
+        ("z", "abc", None),
+        ("\u{e9}+", "caf\u{e9}\u{e9}", Some((3, 7))),
+    ];
+    for &(pattern, text, expected) in FINDS {
+        let found = RtlRegex::compile(pattern, false).map(|r| r.find(text));
+        ctx.check(found == Ok(expected), format_args!("/{}/ in '{}': got {:?}", pattern, text, found));
+    }
+
+    // Several stars over a near miss must not blow up
+    let regex = RtlRegex::compile("a*a*a*a*a*b", false).unwrap();
+    let text = "a".repeat(4096);
+    ctx.check(!regex.is_match(&text), format_args!("star-heavy near miss matched"));
+}
+
+fn test_regex_errors(ctx: &mut KTestContext) {
+    const ERRORS: &[(&str, RegexError)] = &[
+        ("[abc", RegexError::UnterminatedClass),
+        ("[z-a]", RegexError::InvalidRange),
+        ("abc\\", RegexError::TrailingBackslash),
+        ("*a", RegexError::NothingToRepeat),
+        ("a**", RegexError::NothingToRepeat),
+        ("a*?", RegexError::NothingToRepeat),
+        ("a|+b", RegexError::NothingToRepeat),
+        ("^*", RegexError::NothingToRepeat),
+        ("(ab)", RegexError::Unsupported('(')),
+        ("a{2}", RegexError::Unsupported('{')),
+    ];
+    for &(pattern, expected) in ERRORS {
+        let result = RtlRegex::compile(pattern, false).map(|_| ());
+        ctx.check(result == Err(expected), format_args!("/{}/: got {:?}", pattern, result));
+    }
+
+    let too_long = "a".repeat(crate::rtl::pattern::MAX_REGEX_NODES + 1);
+    ctx.check(RtlRegex::compile(&too_long, false).map(|_| ()) == Err(RegexError::TooComplex),
+        format_args!("oversized expression compiled"));
+}
@@ -0,0 +1,183 @@
+//! Machine-Readable Test Results
+//!
+//! Every suite run also streams its results as JSON lines to a serial
+//! port reserved for them (COM2), so a host capturing that port - QEMU
+//! `-serial file:...` as the second serial device - can parse the outcome
+//! without scraping the debug log on COM1.
+//!
+//! The port is brought up on first use; if no UART answers there the
+//! records are dropped and runs behave exactly as before.
+//!
+//! # Records
+//!
+//! ```text
+//! {"type":"suite","suite":"net","tests":2,"requires":["requires-net"]}
+//! {"type":"test","suite":"net","test":"transmit","result":"pass","checks":3,"failed":0}
+//! {"type":"failure","suite":"net","test":"transmit","message":"..."}
+//! {"type":"skip","suite":"ahci","requirement":"requires-ahci","reason":"no AHCI controller"}
+//! {"type":"summary","suite":"net","tests":2,"failed":0,"skipped":0,"checks":9,"checks_failed":0}
+//! {"type":"done","result":"pass","tests":15,"failed":0,"skipped":2}
+//! ```
+//!
+//! The summary for a whole run uses the suite name `all`, and `done` is
+//! always the last record of an unattended run.
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, Ordering};
+use super::{KTestRequirement, KTestSuite, KTestSummary};
+
+/// Serial port the records are written to
+pub const KTEST_RESULT_PORT: u8 = 2;
+
+const PORT_UNKNOWN: u8 = 0;
+const PORT_READY: u8 = 1;
+const PORT_ABSENT: u8 = 2;
+
+static PORT_STATE: AtomicU8 = AtomicU8::new(PORT_UNKNOWN);
+
+/// Check whether results are being written, probing the port once
+pub fn ktest_results_enabled() -> bool {
+    match PORT_STATE.load(Ordering::Acquire) {
+        PORT_READY => true,
+        PORT_ABSENT => false,
+        _ => {
+            let ready = crate::drivers::serial::init_port(KTEST_RESULT_PORT).is_ok();
+            PORT_STATE.store(if ready { PORT_READY } else { PORT_ABSENT }, Ordering::Release);
+            if ready {
+                crate::serial_println!("[KTEST] Results on COM{}", KTEST_RESULT_PORT);
+            }
+            ready
+        }
+    }
+}
+
+/// Append `s` as a JSON string literal
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Start a record of the given type
+fn begin(kind: &str) -> String {
+    let mut record = String::from("{\"type\":");
+    push_json_string(&mut record, kind);
+    record
+}
+
+/// Add a string field
+fn field_str(record: &mut String, name: &str, value: &str) {
+    let _ = write!(record, ",\"{}\":", name);
+    push_json_string(record, value);
+}
+
+/// Add a numeric field
+fn field_u32(record: &mut String, name: &str, value: u32) {
+    let _ = write!(record, ",\"{}\":{}", name, value);
+}
+
+/// Close a record and write it out as one line
+fn emit(mut record: String) {
+    record.push_str("}\n");
+    let _ = crate::drivers::serial::write(KTEST_RESULT_PORT, record.as_bytes());
+}
+
+/// A suite is about to run
+pub fn ktest_result_suite(suite: &KTestSuite) {
+    if !ktest_results_enabled() {
+        return;
+    }
+    let mut record = begin("suite");
+    field_str(&mut record, "suite", suite.name);
+    field_u32(&mut record, "tests", suite.tests.len() as u32);
+    record.push_str(",\"requires\":[");
+    for (i, requirement) in suite.requires.iter().enumerate() {
+        if i != 0 {
+            record.push(',');
+        }
+        push_json_string(&mut record, requirement.name());
+    }
+    record.push(']');
+    emit(record);
+}
+
+/// A test finished
+pub fn ktest_result_test(suite: &str, test: &str, checks: u32, failures: u32) {
+    if !ktest_results_enabled() {
+        return;
+    }
+    let mut record = begin("test");
+    field_str(&mut record, "suite", suite);
+    field_str(&mut record, "test", test);
+    field_str(&mut record, "result", if failures == 0 { "pass" } else { "fail" });
+    field_u32(&mut record, "checks", checks);
+    field_u32(&mut record, "failed", failures);
+    emit(record);
+}
+
+/// A check failed
+pub fn ktest_result_failure(suite: &str, test: &str, message: &str) {
+    if !ktest_results_enabled() {
+        return;
+    }
+    let mut record = begin("failure");
+    field_str(&mut record, "suite", suite);
+    field_str(&mut record, "test", test);
+    field_str(&mut record, "message", message);
+    emit(record);
+}
+
+/// A suite was skipped for want of hardware
+pub fn ktest_result_skip(suite: &KTestSuite, missing: KTestRequirement) {
+    if !ktest_results_enabled() {
+        return;
+    }
+    let mut record = begin("skip");
+    field_str(&mut record, "suite", suite.name);
+    field_str(&mut record, "requirement", missing.name());
+    field_str(&mut record, "reason", missing.absent_reason());
+    emit(record);
+}
+
+/// Totals for a suite, or for a whole run as `all`
+pub fn ktest_result_summary(suite: &str, summary: &KTestSummary) {
+    if !ktest_results_enabled() {
+        return;
+    }
+    let mut record = begin("summary");
+    field_str(&mut record, "suite", suite);
+    field_u32(&mut record, "tests", summary.tests);
+    field_u32(&mut record, "failed", summary.tests_failed);
+    field_u32(&mut record, "skipped", summary.tests_skipped);
+    field_u32(&mut record, "checks", summary.checks);
+    field_u32(&mut record, "checks_failed", summary.checks_failed);
+    emit(record);
+}
+
+/// The run is over
+pub fn ktest_result_done(total: &KTestSummary) {
+    if !ktest_results_enabled() {
+        return;
+    }
+    let mut record = begin("done");
+    field_str(&mut record, "result", if total.passed() { "pass" } else { "fail" });
+    field_u32(&mut record, "tests", total.tests);
+    field_u32(&mut record, "failed", total.tests_failed);
+    field_u32(&mut record, "skipped", total.tests_skipped);
+    emit(record);
+}
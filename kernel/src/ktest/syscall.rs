@@ -0,0 +1,189 @@
+//! System Service Pointer Tests
+//!
+//! Calls every system service that takes pointers with hostile values in
+//! each pointer argument: the first address past the user range, the
+//! non-canonical hole, system space and the kernel image. Each call must
+//! fail with STATUS_ACCESS_VIOLATION, which the dispatcher returns before
+//! the handler runs, so a failing check means a handler was reached with
+//! a system-space pointer (or the kernel faulted trying).
+//!
+//! Only the pointer arguments are made hostile; the rest are zero, so no
+//! handler runs with arguments that could harm the running system.
+
+use crate::arch::x86_64::syscall::{
+    status::STATUS_ACCESS_VIOLATION,
+    syscall_check_pointers, syscall_declared_without_pointers, syscall_is_registered,
+    syscall_name, syscall_pointer_mask,
+    SyscallNumber, MAX_SYSCALLS,
+};
+use crate::mm::{KERNEL_SPACE_START, MM_HIGHEST_USER_ADDRESS, MM_USER_PROBE_ADDRESS};
+use super::{KTest, KTestContext, KTestSuite};
+
+extern "C" {
+    fn syscall_dispatcher(
+        num: usize, a1: usize, a2: usize, a3: usize,
+        a4: usize, a5: usize, a6: usize,
+    ) -> isize;
+}
+
+pub static SYSCALL_SUITE: KTestSuite = KTestSuite {
+    name: "syscall",
+    description: "System services reject system-space pointers",
+    requires: &[],
+    tests: &[
+        KTest { name: "user-range-boundary", run: test_user_range_boundary },
+        KTest { name: "pointer-table", run: test_pointer_table },
+        KTest { name: "pointer-table-coverage", run: test_pointer_table_coverage },
+        KTest { name: "by-value-arguments", run: test_by_value_arguments },
+        KTest { name: "kernel-pointers", run: test_kernel_pointers },
+        KTest { name: "all-pointers-hostile", run: test_all_pointers_hostile },
+    ],
+};
+
+/// Fixed hostile pointer values
+const HOSTILE_POINTERS: &[u64] = &[
+    MM_USER_PROBE_ADDRESS,          // first byte past the user range
+    MM_HIGHEST_USER_ADDRESS + 1,    // inside the guard region
+    0x0000_8000_0000_0000,          // first non-canonical address
+    0x8000_0000_0000_0000,          // middle of the non-canonical hole
+    KERNEL_SPACE_START,             // start of system space
+    0xFFFF_FFFF_8000_0000,          // kernel image base
+    0xFFFF_FFFF_FFFF_F000,          // last page
+    u64::MAX,
+];
+
+/// Hostile pointers: the fixed set plus an address in the loaded kernel
+fn hostile_pointers() -> impl Iterator<Item = usize> {
+    let kernel_data = &SYSCALL_SUITE as *const KTestSuite as u64;
+    HOSTILE_POINTERS.iter().copied().chain(core::iter::once(kernel_data)).map(|p| p as usize)
+}
+
+fn dispatch(num: usize, args: &[usize; 6]) -> isize {
+    unsafe { syscall_dispatcher(num, args[0], args[1], args[2], args[3], args[4], args[5]) }
+}
+
+/// The probing layer agrees on where user space ends
+fn test_user_range_boundary(ctx: &mut KTestContext) {
+    use crate::ex::probe::probe_for_read;
+    use crate::mm::address::is_valid_user_range;
+
+    let highest = MM_HIGHEST_USER_ADDRESS;
+    ctx.check(is_valid_user_range(highest, 1), format_args!("last user byte {:#x} rejected", highest));
+    ctx.check(!is_valid_user_range(highest, 2), format_args!("range crossing {:#x} accepted", highest));
+    ctx.check(!is_valid_user_range(MM_USER_PROBE_ADDRESS, 1), format_args!("probe address accepted"));
+    ctx.check(!is_valid_user_range(KERNEL_SPACE_START, 1), format_args!("system space accepted"));
+    ctx.check(!is_valid_user_range(u64::MAX, 2), format_args!("wrapping range accepted"));
+
+    ctx.check(probe_for_read(highest as usize, 1, 1).is_ok(),
+        format_args!("ProbeForRead rejected {:#x}", highest));
+    ctx.check(probe_for_read(MM_USER_PROBE_ADDRESS as usize, 1, 1).is_err(),
+        format_args!("ProbeForRead accepted the probe address"));
+    ctx.check(probe_for_read(usize::MAX - 10, 100, 1).is_err(),
+        format_args!("ProbeForRead accepted a wrapping range"));
+
+    // Buffers are probed over their length, not just at their start
+    let read_file = SyscallNumber::NtReadFile as usize;
+    let last_page = (MM_USER_PROBE_ADDRESS - 0x1000) as usize;
+    ctx.check(syscall_check_pointers(read_file, &[0, last_page, 0x1000, 0x10000, 0, 0]).is_none(),
+        format_args!("NtReadFile rejected a buffer ending at the user boundary"));
+    ctx.check(syscall_check_pointers(read_file, &[0, last_page, 0x1001, 0x10000, 0, 0]) == Some(2),
+        format_args!("NtReadFile accepted a buffer crossing the user boundary"));
+    ctx.check(syscall_check_pointers(read_file, &[0, highest as usize, 16, 0, 0, 0]) == Some(2),
+        format_args!("NtReadFile accepted a buffer starting at {:#x}", highest));
+    ctx.check(syscall_check_pointers(read_file, &[0, MM_USER_PROBE_ADDRESS as usize, 0, 0, 0, 0]) == Some(2),
+        format_args!("NtReadFile accepted an empty buffer at the probe address"));
+    ctx.check(syscall_check_pointers(read_file, &[0, 0x10000, usize::MAX, 0, 0, 0]) == Some(2),
+        format_args!("NtReadFile accepted a wrapping buffer"));
+    ctx.check(syscall_check_pointers(read_file, &[0, 0, 0, MM_USER_PROBE_ADDRESS as usize, 0, 0]) == Some(4),
+        format_args!("NtReadFile accepted the probe address as argument 4"));
+}
+
+/// Every syscall with pointer arguments is registered, with at most six
+fn test_pointer_table(ctx: &mut KTestContext) {
+    for num in 0..MAX_SYSCALLS {
+        let mask = syscall_pointer_mask(num);
+        if mask == 0 {
+            continue;
+        }
+        ctx.check(syscall_is_registered(num),
+            format_args!("{} ({}) has pointer arguments but no handler", syscall_name(num), num));
+        ctx.check(mask & 0xC0 == 0,
+            format_args!("{} ({}) lists arguments past the sixth", syscall_name(num), num));
+    }
+}
+
+/// Every registered service says whether it takes pointers, exactly once
+fn test_pointer_table_coverage(ctx: &mut KTestContext) {
+    for num in (0..MAX_SYSCALLS).filter(|&n| syscall_is_registered(n)) {
+        let has_pointers = syscall_pointer_mask(num) != 0;
+        let without_pointers = syscall_declared_without_pointers(num);
+        ctx.check(has_pointers || without_pointers,
+            format_args!("{} ({}) is in neither SYSCALL_POINTER_ARGS nor SYSCALL_NO_POINTER_ARGS",
+                syscall_name(num), num));
+        ctx.check(!(has_pointers && without_pointers),
+            format_args!("{} ({}) is in both pointer tables", syscall_name(num), num));
+    }
+}
+
+/// Arguments that are not pointers are passed through unchecked
+fn test_by_value_arguments(ctx: &mut KTestContext) {
+    // Relative timeout by value (negative 100ns units)
+    let relative = (-10_000i64) as usize;
+    ctx.check(syscall_check_pointers(SyscallNumber::NtWaitForDebugEvent as usize, &[0, 0, relative, 0, 0, 0]).is_none(),
+        format_args!("NtWaitForDebugEvent rejected a timeout value"));
+
+    // Completion port and section handles are object addresses
+    let object = KERNEL_SPACE_START as usize;
+    ctx.check(syscall_check_pointers(SyscallNumber::NtSetIoCompletion as usize, &[object, 0, 0, 0, 0, 0]).is_none(),
+        format_args!("NtSetIoCompletion rejected a completion port handle"));
+    ctx.check(syscall_check_pointers(SyscallNumber::NtQuerySection as usize, &[object, 0, 0, 0, 0, 0]).is_none(),
+        format_args!("NtQuerySection rejected a section handle"));
+
+    // NtCurrentProcess pseudo-handle
+    ctx.check(syscall_check_pointers(SyscallNumber::NtQueryInformationProcess as usize, &[usize::MAX, 0, 0, 0, 0, 0]).is_none(),
+        format_args!("NtQueryInformationProcess rejected NtCurrentProcess()"));
+}
+
+/// Each pointer argument, one at a time, with every hostile value
+fn test_kernel_pointers(ctx: &mut KTestContext) {
+    for num in 0..MAX_SYSCALLS {
+        let mask = syscall_pointer_mask(num);
+        if mask == 0 || !syscall_is_registered(num) {
+            continue;
+        }
+        for arg in (0..6).filter(|i| mask & (1 << i) != 0) {
+            for pointer in hostile_pointers() {
+                let mut args = [0usize; 6];
+                args[arg] = pointer;
+                let result = dispatch(num, &args);
+                ctx.check(result == STATUS_ACCESS_VIOLATION, format_args!(
+                    "{} argument {} = {:#x} returned {:#x}",
+                    syscall_name(num), arg + 1, pointer, result as u32,
+                ));
+            }
+        }
+    }
+}
+
+/// All pointer arguments hostile at once
+fn test_all_pointers_hostile(ctx: &mut KTestContext) {
+    for num in 0..MAX_SYSCALLS {
+        let mask = syscall_pointer_mask(num);
+        if mask == 0 || !syscall_is_registered(num) {
+            continue;
+        }
+        for pointer in hostile_pointers() {
+            let mut args = [0usize; 6];
+            for (i, arg) in args.iter_mut().enumerate() {
+                if mask & (1 << i) != 0 {
+                    *arg = pointer;
+                }
+            }
+            let result = dispatch(num, &args);
+            ctx.check(result == STATUS_ACCESS_VIOLATION, format_args!(
+                "{} with all pointers {:#x} returned {:#x}",
+                syscall_name(num), pointer, result as u32,
+            ));
+        }
+    }
+}
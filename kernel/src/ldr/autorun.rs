@@ -0,0 +1,456 @@
+//! Developer Auto-Run Service
+//!
+//! Shortens the host build → guest test loop: any executable dropped into
+//! `C:\AUTORUN\` is loaded and started automatically, with its standard
+//! output and error redirected to `C:\AUTORUN\<NAME>.LOG`.
+//!
+//! The service watches the directory through a directory change watch.
+//! A file is only run once its size has stayed unchanged for
+//! `SETTLE_MS`, so executables still being copied in are not loaded
+//! half-written. Executables already present when the service starts are
+//! run as well, which covers images placed on the disk before boot.
+//!
+//! The service starts at boot when `C:\AUTORUN\` exists; `autorun start`
+//! starts it by hand.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use crate::fs::{self, FileType, SeekWhence};
+use crate::fsrtl::notify::file_action::{FILE_ACTION_ADDED, FILE_ACTION_RENAMED_NEW_NAME};
+use crate::fsrtl::notify::notify_filter::FILE_NOTIFY_CHANGE_FILE_NAME;
+use crate::hal::apic;
+use crate::ke::spinlock::SpinLock;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Watched directory
+pub const AUTORUN_DIR: &str = "C:\\AUTORUN";
+
+/// Time a dropped file's size must stay unchanged before it is run
+pub const SETTLE_MS: u64 = 500;
+
+/// Largest executable the service will load
+pub const MAX_EXE_SIZE: usize = 0x100000;
+
+/// Files waiting to settle
+const MAX_PENDING: usize = 8;
+
+/// Runs whose output is still being logged
+const MAX_RUNS: usize = 4;
+
+/// Watch poll interval
+const POLL_MS: u64 = 250;
+
+/// Service thread priority
+const SERVICE_PRIORITY: i8 = 8;
+
+/// 8.3 file name
+#[derive(Clone, Copy)]
+struct ShortName {
+    bytes: [u8; 12],
+    len: usize,
+}
+
+impl ShortName {
+    const fn empty() -> Self {
+        Self { bytes: [0; 12], len: 0 }
+    }
+
+    fn from(name: &str) -> Option<Self> {
+        if name.is_empty() || name.len() > 12 || name.contains('\\') {
+            return None;
+        }
+        let mut short = Self::empty();
+        short.bytes[..name.len()].copy_from_slice(name.as_bytes());
+        short.bytes[..name.len()].make_ascii_uppercase();
+        short.len = name.len();
+        Some(short)
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+
+    /// Name with the extension replaced by `.LOG`
+    fn log_name(&self) -> String {
+        let name = self.as_str();
+        let stem = name.rfind('.').map_or(name, |dot| &name[..dot]);
+        format!("{}.LOG", stem)
+    }
+}
+
+/// Dropped file waiting for its size to settle
+#[derive(Clone, Copy)]
+struct Pending {
+    name: ShortName,
+    size: u64,
+    /// Tick of the last observed size change
+    changed_at: u64,
+}
+
+/// Started executable
+#[derive(Clone, Copy)]
+pub struct AutorunRun {
+    name: ShortName,
+    pub pid: u32,
+    pub started_at: u64,
+    /// Log file handle (open while the process runs)
+    log: Option<u16>,
+    thread: *mut crate::ps::EThread,
+    process: *mut crate::ps::EProcess,
+}
+
+impl AutorunRun {
+    pub fn name_str(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn is_logging(&self) -> bool {
+        self.log.is_some()
+    }
+}
+
+// Raw pointers only touched by the service thread and under the lock
+unsafe impl Send for AutorunRun {}
+
+struct AutorunState {
+    pending: [Option<Pending>; MAX_PENDING],
+    runs: [Option<AutorunRun>; MAX_RUNS],
+}
+
+static AUTORUN_STATE: SpinLock<AutorunState> = SpinLock::new(AutorunState {
+    pending: [None; MAX_PENDING],
+    runs: [None; MAX_RUNS],
+});
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static STARTED_COUNT: AtomicU64 = AtomicU64::new(0);
+static FAILED_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Executable buffer (the loader copies sections out of it)
+static mut EXE_BUFFER: [u8; MAX_EXE_SIZE] = [0; MAX_EXE_SIZE];
+
+fn full_path(name: &str) -> String {
+    format!("{}\\{}", AUTORUN_DIR, name)
+}
+
+fn is_executable(name: &str) -> bool {
+    name.len() > 4 && name[name.len() - 4..].eq_ignore_ascii_case(".EXE")
+}
+
+/// Write a line to a log handle
+fn log_line(handle: u16, line: &str) {
+    let _ = fs::write(handle, line.as_bytes());
+    let _ = fs::write(handle, b"\r\n");
+}
+
+/// Create (or replace) the log file for a run
+fn create_log(name: &ShortName) -> Option<u16> {
+    let path = full_path(&name.log_name());
+    let _ = fs::delete(&path);
+    fs::create(&path, 0).ok()
+}
+
+/// Queue a dropped file until its size settles
+fn queue(name: &str) {
+    let Some(name) = ShortName::from(name) else { return };
+    if !is_executable(name.as_str()) {
+        return;
+    }
+
+    let now = apic::get_tick_count();
+    let mut state = AUTORUN_STATE.lock();
+    if state.pending.iter().flatten().any(|p| p.name.as_str() == name.as_str()) {
+        return;
+    }
+    if let Some(slot) = state.pending.iter_mut().find(|p| p.is_none()) {
+        *slot = Some(Pending { name, size: u64::MAX, changed_at: now });
+    } else {
+        crate::serial_println!("[AUTORUN] Queue full, dropping {}", name.as_str());
+    }
+}
+
+/// Run pending files whose size has settled
+fn run_settled() {
+    let now = apic::get_tick_count();
+    let pending = AUTORUN_STATE.lock().pending;
+
+    // Current sizes, taken without holding the lock (None = file gone)
+    let mut sizes = [None; MAX_PENDING];
+    for (i, p) in pending.iter().enumerate() {
+        if let Some(p) = p {
+            sizes[i] = match fs::stat(&full_path(p.name.as_str())) {
+                Ok(info) if info.file_type == FileType::Regular => Some(info.size),
+                _ => None,
+            };
+        }
+    }
+
+    let mut ready = [None; MAX_PENDING];
+    {
+        let mut state = AUTORUN_STATE.lock();
+        for (i, slot) in state.pending.iter_mut().enumerate() {
+            let Some(p) = slot else { continue };
+            match sizes[i] {
+                // Removed or renamed before it settled
+                None => *slot = None,
+                Some(size) if size != p.size => {
+                    p.size = size;
+                    p.changed_at = now;
+                }
+                Some(size) if size > 0 && now - p.changed_at >= SETTLE_MS => {
+                    ready[i] = Some(p.name);
+                    *slot = None;
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    for name in ready.iter().flatten() {
+        run(name);
+    }
+}
+
+/// Load and start one executable
+fn run(name: &ShortName) {
+    let path = full_path(name.as_str());
+    crate::serial_println!("[AUTORUN] Running {}", path.as_str());
+
+    // A re-dropped image replaces its previous run and log
+    finish_run(|r| r.name.as_str() == name.as_str());
+
+    let log = create_log(name);
+    if let Some(h) = log {
+        log_line(h, &format!("autorun: {} at tick {}", path.as_str(), apic::get_tick_count()));
+    }
+
+    let result = load(&path, name);
+    let (process, thread) = match result {
+        Ok(pt) => pt,
+        Err(msg) => {
+            crate::serial_println!("[AUTORUN] {}: {}", name.as_str(), msg);
+            FAILED_COUNT.fetch_add(1, Ordering::Relaxed);
+            if let Some(h) = log {
+                log_line(h, &format!("autorun: failed: {}", msg));
+                let _ = fs::close(h);
+            }
+            return;
+        }
+    };
+
+    let pid = unsafe { (*process).unique_process_id };
+    if let Some(h) = log {
+        log_line(h, &format!("autorun: started PID {}", pid));
+        if !crate::arch::x86_64::syscall::set_stdio_redirect(pid, Some(h)) {
+            log_line(h, "autorun: output redirection table full, output goes to serial");
+        }
+    }
+
+    let record = AutorunRun {
+        name: *name,
+        pid,
+        started_at: apic::get_tick_count(),
+        log,
+        thread,
+        process,
+    };
+
+    // With every slot taken, the oldest run stops being logged
+    let evicted = {
+        let mut state = AUTORUN_STATE.lock();
+        let (slot, evicted) = match state.runs.iter().position(|r| r.is_none()) {
+            Some(i) => (i, None),
+            None => {
+                let oldest = (0..MAX_RUNS)
+                    .min_by_key(|&i| state.runs[i].map_or(0, |r| r.started_at))
+                    .unwrap_or(0);
+                (oldest, state.runs[oldest].take())
+            }
+        };
+        state.runs[slot] = Some(record);
+        evicted
+    };
+    if let Some(old) = evicted {
+        close_run(&old);
+    }
+
+    STARTED_COUNT.fetch_add(1, Ordering::Relaxed);
+    unsafe { crate::ps::ps_start_user_thread(thread) };
+}
+
+/// Read, load and create the process for an executable
+fn load(
+    path: &str,
+    name: &ShortName,
+) -> Result<(*mut crate::ps::EProcess, *mut crate::ps::EThread), &'static str> {
+    let handle = fs::open(path, 0).map_err(|_| "cannot open file")?;
+    let size = fs::fstat(handle).map(|i| i.size as usize).unwrap_or(0);
+    if size > MAX_EXE_SIZE {
+        let _ = fs::close(handle);
+        return Err("image too large");
+    }
+
+    let buffer = unsafe { &mut *core::ptr::addr_of_mut!(EXE_BUFFER) };
+    let _ = fs::seek(handle, 0, SeekWhence::Set);
+    let mut read = 0;
+    while read < size {
+        match fs::read(handle, &mut buffer[read..size]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(_) => break,
+        }
+    }
+    let _ = fs::close(handle);
+    if read < 64 {
+        return Err("short read");
+    }
+
+    unsafe {
+        let info = super::parse_pe(buffer.as_ptr()).map_err(|_| "not a valid PE image")?;
+        if !info.is_64bit {
+            return Err("32-bit images are not supported");
+        }
+        let loaded = super::load_executable(buffer.as_ptr(), read, name.as_str().as_bytes())
+            .map_err(|_| "loader rejected the image")?;
+        if loaded.process.is_null() || loaded.thread.is_null() {
+            return Err("process creation failed");
+        }
+        Ok((loaded.process, loaded.thread))
+    }
+}
+
+/// Close a run's log and drop its redirection
+fn close_run(run: &AutorunRun) {
+    if let Some(h) = run.log {
+        crate::arch::x86_64::syscall::set_stdio_redirect(run.pid, None);
+        log_line(h, &format!("autorun: PID {} finished", run.pid));
+        let _ = fs::close(h);
+    }
+}
+
+/// Remove and close every run matching `predicate`
+fn finish_run(predicate: impl Fn(&AutorunRun) -> bool) {
+    let mut finished = [None; MAX_RUNS];
+    {
+        let mut state = AUTORUN_STATE.lock();
+        for (i, slot) in state.runs.iter_mut().enumerate() {
+            if slot.as_ref().is_some_and(&predicate) {
+                finished[i] = slot.take();
+            }
+        }
+    }
+    for run in finished.iter().flatten() {
+        close_run(run);
+    }
+}
+
+/// Close the logs of processes that have exited
+fn reap_runs() {
+    finish_run(|r| unsafe {
+        (*r.process).is_exiting()
+            || (*(*r.thread).get_tcb()).state == crate::ke::thread::ThreadState::Terminated
+    });
+}
+
+/// Service thread
+fn autorun_thread() {
+    let watch = match fs::watch::watch_directory(AUTORUN_DIR, false, FILE_NOTIFY_CHANGE_FILE_NAME) {
+        Ok(w) => w,
+        Err(e) => {
+            crate::serial_println!("[AUTORUN] Cannot watch {}: {:?}", AUTORUN_DIR, e);
+            RUNNING.store(false, Ordering::Release);
+            unsafe { crate::ke::init::exit_thread() }
+        }
+    };
+    crate::serial_println!("[AUTORUN] Watching {}", AUTORUN_DIR);
+
+    // Executables already in place (copied onto the disk before boot)
+    let mut offset = 0;
+    while let Ok(entry) = fs::readdir(AUTORUN_DIR, offset) {
+        offset = entry.next_offset;
+        if entry.file_type == FileType::Regular {
+            queue(entry.name_str());
+        }
+    }
+
+    while !STOP_REQUESTED.load(Ordering::Acquire) {
+        fs::watch::wait_change(watch, POLL_MS);
+        while let Some(change) = fs::watch::next_change(watch) {
+            if change.action == FILE_ACTION_ADDED || change.action == FILE_ACTION_RENAMED_NEW_NAME {
+                let name = core::str::from_utf8(&change.name[..change.name_length as usize]).unwrap_or("");
+                queue(name);
+            }
+        }
+        run_settled();
+        reap_runs();
+    }
+
+    fs::watch::close_watch(watch);
+    finish_run(|_| true);
+    AUTORUN_STATE.lock().pending = [None; MAX_PENDING];
+    crate::serial_println!("[AUTORUN] Service stopped");
+    RUNNING.store(false, Ordering::Release);
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+// ============================================================================
+// Control
+// ============================================================================
+
+/// Start the service
+///
+/// # Returns
+/// `false` if it is already running or the thread could not be created
+pub fn autorun_start() -> bool {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    STOP_REQUESTED.store(false, Ordering::Release);
+    if unsafe { crate::ke::init::create_thread(SERVICE_PRIORITY, autorun_thread) }.is_none() {
+        RUNNING.store(false, Ordering::Release);
+        return false;
+    }
+    true
+}
+
+/// Ask the service to stop (it exits within one poll interval)
+pub fn autorun_stop() -> bool {
+    if !RUNNING.load(Ordering::Acquire) {
+        return false;
+    }
+    STOP_REQUESTED.store(true, Ordering::Release);
+    true
+}
+
+/// Start the service at boot when the auto-run directory exists
+pub fn start_at_boot() {
+    if let Ok(info) = fs::stat(AUTORUN_DIR) {
+        if info.file_type == FileType::Directory && autorun_start() {
+            crate::serial_println!("[AUTORUN] Service started for {}", AUTORUN_DIR);
+        }
+    }
+}
+
+/// Service status
+pub struct AutorunStatus {
+    pub running: bool,
+    pub started: u64,
+    pub failed: u64,
+    pub pending: usize,
+    pub runs: [Option<AutorunRun>; MAX_RUNS],
+}
+
+/// Get the service status
+pub fn autorun_status() -> AutorunStatus {
+    let state = AUTORUN_STATE.lock();
+    AutorunStatus {
+        running: RUNNING.load(Ordering::Acquire),
+        started: STARTED_COUNT.load(Ordering::Relaxed),
+        failed: FAILED_COUNT.load(Ordering::Relaxed),
+        pending: state.pending.iter().flatten().count(),
+        runs: state.runs,
+    }
+}
@@ -0,0 +1,444 @@
+//! Kernel-Mode Driver Loading
+//!
+//! Loads PE drivers (IMAGE_SUBSYSTEM_NATIVE), binds their imports to the
+//! kernel export table and calls DriverEntry.
+//!
+//! # ABI Versioning
+//!
+//! The driver-facing surface carries a `major.minor` version:
+//!
+//! - **major** changes when a mirrored structure changes layout or an
+//!   export changes signature. Drivers built for another major are refused.
+//! - **minor** changes when exports are added. A driver built for a newer
+//!   minor than the kernel provides is refused.
+//!
+//! Drivers declare the version they were built against by exporting a
+//! `DriverAbiVersion` u32 (`major << 16 | minor`; `wdm::declare_abi_version!`
+//! does this). Images without it are treated as 1.0. Every export records
+//! the version that introduced it, and an import newer than the driver's
+//! declared version fails the load rather than binding silently.
+//!
+//! DriverEntry receives a `DriverAbiInfo` as a third argument so drivers
+//! can adapt to the running kernel.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::ke::SpinLock;
+use super::{PeError, LoadedImage};
+
+// ============================================================================
+// ABI Version
+// ============================================================================
+
+/// Current kernel ABI major version
+pub const KERNEL_ABI_MAJOR: u16 = 1;
+
+/// Current kernel ABI minor version
+pub const KERNEL_ABI_MINOR: u16 = 5;
+
+/// Export name drivers use to declare their ABI version
+pub const ABI_VERSION_EXPORT: &str = "DriverAbiVersion";
+
+/// ABI version (major.minor)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AbiVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl AbiVersion {
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Unpack from the `major << 16 | minor` export format
+    pub const fn from_packed(value: u32) -> Self {
+        Self { major: (value >> 16) as u16, minor: value as u16 }
+    }
+
+    pub const fn packed(&self) -> u32 {
+        ((self.major as u32) << 16) | self.minor as u32
+    }
+
+    /// Check if a driver built against `self` can run on `kernel`
+    pub fn is_compatible_with(&self, kernel: AbiVersion) -> bool {
+        self.major == kernel.major && self.minor <= kernel.minor
+    }
+}
+
+impl core::fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Running kernel's ABI version
+pub const KERNEL_ABI_VERSION: AbiVersion = AbiVersion::new(KERNEL_ABI_MAJOR, KERNEL_ABI_MINOR);
+
+/// Version assumed for drivers that don't declare one
+pub const LEGACY_ABI_VERSION: AbiVersion = AbiVersion::new(1, 0);
+
+/// Version handshake passed to DriverEntry
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DriverAbiInfo {
+    /// Size of this structure
+    pub size: u32,
+    /// Kernel ABI major version
+    pub major: u16,
+    /// Kernel ABI minor version
+    pub minor: u16,
+    /// Version the driver declared (or 1.0)
+    pub driver_version: u32,
+    /// Number of kernel exports available
+    pub export_count: u32,
+}
+
+/// Exports introduced after 1.0, with the version that added them
+pub const EXPORT_VERSIONS: &[(&str, AbiVersion)] = &[
+    // 1.1: contiguous cache-typed allocation, raise-to-DPC spinlocks
+    ("MmAllocateContiguousMemorySpecifyCache", AbiVersion::new(1, 1)),
+    ("MmFreeContiguousMemorySpecifyCache", AbiVersion::new(1, 1)),
+    ("KeAcquireSpinLockRaiseToDpc", AbiVersion::new(1, 1)),
+    ("KeReleaseSpinLock", AbiVersion::new(1, 1)),
+    // 1.2: calibrated microsecond stalls
+    ("KeStallExecutionProcessor", AbiVersion::new(1, 2)),
+    // 1.3: hard error popups
+    ("IoRaiseInformationalHardError", AbiVersion::new(1, 3)),
+    // 1.4: device symbolic links
+    ("IoCreateSymbolicLink", AbiVersion::new(1, 4)),
+    ("IoDeleteSymbolicLink", AbiVersion::new(1, 4)),
+];
+
+/// Version that added the C runtime exports (`rtl::kmcrt::KMCRT_EXPORTS`)
+pub const KMCRT_ABI_VERSION: AbiVersion = AbiVersion::new(1, 5);
+
+/// Version that introduced a kernel export
+///
+/// Exports not listed in `EXPORT_VERSIONS` or `KMCRT_EXPORTS` are part of
+/// the original 1.0 surface.
+pub fn export_since(func_name: &str) -> AbiVersion {
+    if crate::rtl::kmcrt::kmcrt_export(func_name).is_some() {
+        return KMCRT_ABI_VERSION;
+    }
+    EXPORT_VERSIONS
+        .iter()
+        .find(|(name, _)| *name == func_name)
+        .map(|&(_, since)| since)
+        .unwrap_or(LEGACY_ABI_VERSION)
+}
+
+// ============================================================================
+// Loaded Driver Tracking
+// ============================================================================
+
+/// Maximum loaded PE drivers
+pub const MAX_LOADED_DRIVERS: usize = 16;
+
+/// Driver name length
+const DRIVER_NAME_LEN: usize = 32;
+
+/// A loaded PE driver
+#[derive(Clone, Copy)]
+pub struct LoadedDriver {
+    pub name: [u8; DRIVER_NAME_LEN],
+    pub name_len: usize,
+    pub image: LoadedImage,
+    pub abi: AbiVersion,
+    pub driver_object: *mut crate::io::DriverObject,
+}
+
+impl LoadedDriver {
+    pub fn name_str(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+}
+
+// Safety: driver table access is serialized by DRIVER_LOAD_LOCK
+unsafe impl Send for LoadedDriver {}
+
+static mut LOADED_DRIVERS: [Option<LoadedDriver>; MAX_LOADED_DRIVERS] = [None; MAX_LOADED_DRIVERS];
+
+/// Serializes driver loads (import binding uses the static below)
+static DRIVER_LOAD_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// ABI version of the driver whose imports are being bound
+static BINDING_ABI: AtomicU32 = AtomicU32::new(0);
+
+/// Imports refused for being newer than the driver's declared version
+static BINDING_REJECTS: AtomicU32 = AtomicU32::new(0);
+
+/// Import that stopped the last bind (function, DLL), for the error popup
+static UNRESOLVED_IMPORT: SpinLock<Option<(String, String)>> = SpinLock::new(None);
+
+/// Driver load failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverLoadError {
+    /// Image is malformed
+    Image(PeError),
+    /// Image is not a native-subsystem driver
+    NotADriver,
+    /// Driver built for an incompatible ABI
+    AbiMismatch(AbiVersion),
+    /// Driver imports something the kernel doesn't export (or not at its version)
+    UnresolvedImport,
+    /// Driver table or image memory exhausted
+    NoResources,
+    /// DriverEntry returned a failure status
+    InitFailed(i32),
+}
+
+impl DriverLoadError {
+    /// NTSTATUS equivalent
+    pub fn status(&self) -> i32 {
+        match self {
+            Self::Image(_) => 0xC000007Bu32 as i32,          // STATUS_INVALID_IMAGE_FORMAT
+            Self::NotADriver => 0xC000007Bu32 as i32,        // STATUS_INVALID_IMAGE_FORMAT
+            Self::AbiMismatch(_) => 0xC0000059u32 as i32,    // STATUS_REVISION_MISMATCH
+            Self::UnresolvedImport => 0xC0000139u32 as i32,  // STATUS_ENTRYPOINT_NOT_FOUND
+            Self::NoResources => 0xC000009Au32 as i32,       // STATUS_INSUFFICIENT_RESOURCES
+            Self::InitFailed(status) => *status,
+        }
+    }
+}
+
+// ============================================================================
+// Import Binding
+// ============================================================================
+
+/// Import resolver that enforces since-version metadata
+fn versioned_import_resolver(dll_name: &str, func_name: &str, _ordinal: u16) -> Option<u64> {
+    let addr = versioned_import(dll_name, func_name);
+    if addr.is_none() {
+        *UNRESOLVED_IMPORT.lock() = Some((String::from(func_name), String::from(dll_name)));
+    }
+    addr
+}
+
+fn versioned_import(dll_name: &str, func_name: &str) -> Option<u64> {
+    let addr = super::resolve_kernel_export(dll_name, func_name)?;
+
+    let driver_abi = AbiVersion::from_packed(BINDING_ABI.load(Ordering::Acquire));
+    let since = export_since(func_name);
+    if since > driver_abi {
+        crate::serial_println!(
+            "[LDR] {} was added in ABI {}, driver declares {}",
+            func_name, since, driver_abi
+        );
+        BINDING_REJECTS.fetch_add(1, Ordering::Relaxed);
+        return None;
+    }
+
+    Some(addr)
+}
+
+/// Read the ABI version a driver image declares
+unsafe fn declared_abi_version(image_base: *const u8) -> Option<AbiVersion> {
+    let addr = super::find_export_by_name(image_base, ABI_VERSION_EXPORT)?;
+    Some(AbiVersion::from_packed(core::ptr::read_unaligned(addr as *const u32)))
+}
+
+// ============================================================================
+// Load / Unload
+// ============================================================================
+
+/// Load a PE driver and call its DriverEntry
+///
+/// # Safety
+/// `file_base` must point to a complete PE file image.
+pub unsafe fn load_driver(
+    file_base: *const u8,
+    name: &[u8],
+) -> Result<*mut crate::io::DriverObject, DriverLoadError> {
+    let pe_info = super::parse_pe(file_base).map_err(DriverLoadError::Image)?;
+
+    // No size is passed in; trust the headers for the extent and let the
+    // verifier check everything inside it
+    let file_size = super::verify::pe_file_extent(file_base).ok_or(DriverLoadError::Image(PeError::InvalidSection))?;
+    super::verify::ldr_verify_image(file_base, file_size, name).map_err(DriverLoadError::Image)?;
+
+    if !pe_info.is_driver() || !pe_info.is_64bit {
+        return Err(DriverLoadError::NotADriver);
+    }
+
+    let guard = DRIVER_LOAD_LOCK.lock();
+
+    let slot = (0..MAX_LOADED_DRIVERS)
+        .find(|&i| LOADED_DRIVERS[i].is_none())
+        .ok_or(DriverLoadError::NoResources)?;
+
+    // Image memory: physically contiguous so it is also identity-mapped
+    let image_size = pe_info.size_of_image as usize;
+    let base = crate::mm::mm_allocate_contiguous_memory(image_size, u64::MAX)
+        .ok_or(DriverLoadError::NoResources)?;
+    let load_base = base as *mut u8;
+
+    let result = bind_and_start(file_base, load_base, &pe_info, name);
+    match result {
+        Ok((driver, abi)) => {
+            let mut entry = LoadedDriver {
+                name: [0; DRIVER_NAME_LEN],
+                name_len: name.len().min(DRIVER_NAME_LEN),
+                image: LoadedImage {
+                    base,
+                    size: pe_info.size_of_image,
+                    entry_point: base + pe_info.entry_point_rva as u64,
+                    pe_info,
+                },
+                abi,
+                driver_object: driver,
+            };
+            entry.name[..entry.name_len].copy_from_slice(&name[..entry.name_len]);
+            LOADED_DRIVERS[slot] = Some(entry);
+
+            crate::serial_println!(
+                "[LDR] Driver '{}' loaded at {:#x} (ABI {})",
+                entry.name_str(), base, abi
+            );
+            Ok(driver)
+        }
+        Err(e) => {
+            crate::mm::mm_free_contiguous_memory(base);
+            crate::serial_println!("[LDR] Driver load failed: {:?}", e);
+            drop(guard);
+            report_load_failure(name, e);
+            Err(e)
+        }
+    }
+}
+
+/// Tell the user why a driver did not load
+fn report_load_failure(name: &[u8], error: DriverLoadError) {
+    use crate::ex::{ex_raise_hard_error_with_strings, HardErrorResponseOption};
+
+    let name = core::str::from_utf8(name).unwrap_or("driver");
+    match error {
+        DriverLoadError::UnresolvedImport => {
+            let (function, dll) = UNRESOLVED_IMPORT.lock().take()
+                .unwrap_or_else(|| (String::from("?"), String::from("?")));
+            ex_raise_hard_error_with_strings(error.status(), &[&function, &dll], HardErrorResponseOption::Ok);
+        }
+        DriverLoadError::Image(_) | DriverLoadError::NotADriver | DriverLoadError::AbiMismatch(_) => {
+            ex_raise_hard_error_with_strings(error.status(), &[name], HardErrorResponseOption::Ok);
+        }
+        DriverLoadError::NoResources | DriverLoadError::InitFailed(_) => {
+            let status = format!("0x{:08X}", error.status() as u32);
+            ex_raise_hard_error_with_strings(
+                0xC000026Cu32 as i32, // STATUS_DRIVER_UNABLE_TO_LOAD
+                &[name, &status],
+                HardErrorResponseOption::Ok,
+            );
+        }
+    }
+}
+
+/// Map the image, check its ABI, bind imports and run DriverEntry
+unsafe fn bind_and_start(
+    file_base: *const u8,
+    load_base: *mut u8,
+    pe_info: &super::PeInfo,
+    name: &[u8],
+) -> Result<(*mut crate::io::DriverObject, AbiVersion), DriverLoadError> {
+    super::copy_sections(file_base, load_base, pe_info).map_err(DriverLoadError::Image)?;
+
+    // Measure the image before relocation, so the digest does not depend
+    // on the load address
+    crate::drivers::tpm::tpm_measure_driver(
+        core::str::from_utf8(name).unwrap_or("driver"),
+        core::slice::from_raw_parts(load_base, pe_info.size_of_image as usize),
+    );
+
+    let actual_base = load_base as u64;
+    if actual_base != pe_info.image_base {
+        if !pe_info.has_relocations {
+            return Err(DriverLoadError::Image(PeError::NotRelocatable));
+        }
+        super::process_relocations(load_base, pe_info.image_base, actual_base)
+            .map_err(DriverLoadError::Image)?;
+    }
+
+    let abi = declared_abi_version(load_base).unwrap_or(LEGACY_ABI_VERSION);
+    if !abi.is_compatible_with(KERNEL_ABI_VERSION) {
+        crate::serial_println!(
+            "[LDR] Driver built for ABI {}, kernel provides {}",
+            abi, KERNEL_ABI_VERSION
+        );
+        return Err(DriverLoadError::AbiMismatch(abi));
+    }
+
+    BINDING_ABI.store(abi.packed(), Ordering::Release);
+    super::process_imports(load_base, versioned_import_resolver)
+        .map_err(|_| DriverLoadError::UnresolvedImport)?;
+
+    if pe_info.entry_point_rva == 0 {
+        return Err(DriverLoadError::Image(PeError::InvalidOptionalHeader));
+    }
+
+    let driver = crate::io::io_create_driver(name);
+    if driver.is_null() {
+        return Err(DriverLoadError::NoResources);
+    }
+
+    let entry: crate::io::DriverInitialize =
+        core::mem::transmute(actual_base + pe_info.entry_point_rva as u64);
+    (*driver).driver_start = load_base;
+    (*driver).driver_size = pe_info.size_of_image;
+    (*driver).driver_init = Some(entry);
+
+    let abi_info = DriverAbiInfo {
+        size: core::mem::size_of::<DriverAbiInfo>() as u32,
+        major: KERNEL_ABI_MAJOR,
+        minor: KERNEL_ABI_MINOR,
+        driver_version: abi.packed(),
+        export_count: super::get_kernel_export_count() as u32,
+    };
+
+    let status = entry(driver, core::ptr::null(), &abi_info);
+    if status < 0 {
+        // DriverEntry failed: don't call an unload routine it may have set
+        (*driver).driver_unload = None;
+        crate::io::io_delete_driver(driver);
+        return Err(DriverLoadError::InitFailed(status));
+    }
+
+    Ok((driver, abi))
+}
+
+/// Unload a PE driver by name
+pub unsafe fn unload_driver(name: &str) -> bool {
+    let _guard = DRIVER_LOAD_LOCK.lock();
+
+    for slot in 0..MAX_LOADED_DRIVERS {
+        if let Some(entry) = LOADED_DRIVERS[slot] {
+            if entry.name_str().eq_ignore_ascii_case(name) {
+                crate::io::io_delete_driver(entry.driver_object);
+                crate::mm::mm_free_contiguous_memory(entry.image.base);
+                LOADED_DRIVERS[slot] = None;
+                crate::serial_println!("[LDR] Driver '{}' unloaded", name);
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+// ============================================================================
+// Query Functions
+// ============================================================================
+
+/// Snapshot of loaded PE drivers
+pub fn get_loaded_drivers() -> ([Option<LoadedDriver>; MAX_LOADED_DRIVERS], usize) {
+    let _guard = DRIVER_LOAD_LOCK.lock();
+    let drivers = unsafe { LOADED_DRIVERS };
+    let count = drivers.iter().flatten().count();
+    (drivers, count)
+}
+
+/// Number of imports refused by since-version checks
+pub fn get_abi_reject_count() -> u32 {
+    BINDING_REJECTS.load(Ordering::Relaxed)
+}
@@ -5,6 +5,7 @@
 //! - Loading executables and DLLs into memory
 //! - Processing relocations
 //! - Resolving imports
+//! - Verifying image structure before anything is mapped
 //!
 //! # Architecture
 //!
@@ -41,10 +42,15 @@
 //! ```
 
 pub mod pe;
+pub mod driver;
+pub mod autorun;
+pub mod verify;
 
 // Re-export PE types
 pub use pe::*;
 
+extern crate alloc;
+
 use core::ptr;
 use crate::ke::SpinLock;
 
@@ -134,6 +140,8 @@ pub enum PeError {
     OutOfMemory,
     /// Image not relocatable
     NotRelocatable,
+    /// Image failed pre-load verification
+    VerificationFailed,
 }
 
 /// Parsed PE information
@@ -1155,9 +1163,12 @@ pub struct LoadResult {
 /// - The caller must ensure the file is safe to execute
 pub unsafe fn load_executable(
     file_base: *const u8,
-    _file_size: usize,
+    file_size: usize,
     name: &[u8],
 ) -> Result<LoadResult, PeError> {
+    // Refuse images the loader would trip over
+    verify::ldr_verify_image(file_base, file_size, name)?;
+
     // Parse PE headers
     let pe_info = parse_pe(file_base)?;
 
@@ -1242,6 +1253,9 @@ pub unsafe fn load_executable(
         return Err(PeError::OutOfMemory);
     }
 
+    // Executables are launched from the interactive shell
+    (*process).session_id = crate::ob::SESSION_CONSOLE;
+
     // Set up process image information
     // Note: This uses kernel addresses for now
     (*process).section_object = file_base as *mut u8;
@@ -1262,6 +1276,13 @@ pub unsafe fn load_executable(
         }
     }
 
+    crate::mm::pin::mm_pin_image_if_configured(
+        (*process).unique_process_id,
+        name,
+        actual_base,
+        image_size,
+    );
+
     let loaded = LoadedImage {
         base: actual_base,
         size: pe_info.size_of_image,
@@ -1298,7 +1319,7 @@ pub unsafe fn load_executable(
 pub unsafe fn load_executable_to_address_space(
     process: *mut crate::ps::EProcess,
     file_base: *const u8,
-    _file_size: usize,
+    file_size: usize,
 ) -> Result<(u64, u64, u32), PeError> {
     use crate::mm::{MmAddressSpace, pte_flags, mm_map_user_page, PAGE_SIZE};
 
@@ -1307,6 +1328,8 @@ pub unsafe fn load_executable_to_address_space(
         return Err(PeError::OutOfMemory);
     }
 
+    verify::ldr_verify_image(file_base, file_size, b"image")?;
+
     // Get the process's address space
     let aspace = (*process).address_space as *mut MmAddressSpace;
     if aspace.is_null() {
@@ -1617,9 +1640,11 @@ unsafe fn process_relocations_in_place(
 pub unsafe fn load_dll(
     process: *mut crate::ps::EProcess,
     file_base: *const u8,
-    _file_size: usize,
+    file_size: usize,
     name: &[u8],
 ) -> Result<LoadedImage, PeError> {
+    verify::ldr_verify_image(file_base, file_size, name)?;
+
     // Parse PE headers
     let pe_info = parse_pe(file_base)?;
 
@@ -1688,6 +1713,9 @@ pub unsafe fn load_dll(
         }
     }
 
+    let process_id = if process.is_null() { 0 } else { (*process).unique_process_id };
+    crate::mm::pin::mm_pin_image_if_configured(process_id, name, actual_base, image_size);
+
     let loaded = LoadedImage {
         base: actual_base,
         size: pe_info.size_of_image,
@@ -1709,6 +1737,7 @@ pub unsafe fn unload_dll(image: &LoadedImage) -> Result<(), PeError> {
 
     // Find and free the DLL buffer
     if let Some(idx) = find_dll_buffer_index(base_ptr) {
+        crate::mm::pin::mm_unpin_range(image.base, image.size as usize);
         free_dll_buffer(idx);
         crate::serial_println!("[LDR] DLL unloaded from {:#x}", image.base);
         Ok(())
@@ -1881,6 +1910,8 @@ fn resolve_ntoskrnl_export(func_name: &str) -> Option<u64> {
         "MmUnmapIoSpace" => Some(unsafe { core::mem::transmute(mm_unmap_io_space as *const () as usize) }),
         "MmAllocateContiguousMemory" => Some(unsafe { core::mem::transmute(mm_allocate_contiguous as *const () as usize) }),
         "MmFreeContiguousMemory" => Some(unsafe { core::mem::transmute(mm_free_contiguous as *const () as usize) }),
+        "MmAllocateContiguousMemorySpecifyCache" => Some(unsafe { core::mem::transmute(mm_allocate_contiguous_specify_cache as *const () as usize) }),
+        "MmFreeContiguousMemorySpecifyCache" => Some(unsafe { core::mem::transmute(mm_free_contiguous_specify_cache as *const () as usize) }),
 
         // Executive Pool
         "ExAllocatePool" => Some(unsafe { core::mem::transmute(ex_allocate_pool as *const () as usize) }),
@@ -1892,6 +1923,8 @@ fn resolve_ntoskrnl_export(func_name: &str) -> Option<u64> {
         "KeInitializeSpinLock" => Some(unsafe { core::mem::transmute(ke_initialize_spinlock as *const () as usize) }),
         "KeAcquireSpinLockAtDpcLevel" => Some(unsafe { core::mem::transmute(ke_acquire_spinlock_dpc as *const () as usize) }),
         "KeReleaseSpinLockFromDpcLevel" => Some(unsafe { core::mem::transmute(ke_release_spinlock_dpc as *const () as usize) }),
+        "KeAcquireSpinLockRaiseToDpc" => Some(unsafe { core::mem::transmute(ke_acquire_spinlock_raise_to_dpc as *const () as usize) }),
+        "KeReleaseSpinLock" => Some(unsafe { core::mem::transmute(ke_release_spinlock as *const () as usize) }),
         "KeInitializeEvent" => Some(unsafe { core::mem::transmute(ke_initialize_event as *const () as usize) }),
         "KeSetEvent" => Some(unsafe { core::mem::transmute(ke_set_event as *const () as usize) }),
         "KeResetEvent" => Some(unsafe { core::mem::transmute(ke_reset_event as *const () as usize) }),
@@ -1901,6 +1934,7 @@ fn resolve_ntoskrnl_export(func_name: &str) -> Option<u64> {
         "KeLowerIrql" => Some(unsafe { core::mem::transmute(ke_lower_irql as *const () as usize) }),
         "KeQuerySystemTime" => Some(unsafe { core::mem::transmute(ke_query_system_time as *const () as usize) }),
         "KeDelayExecutionThread" => Some(unsafe { core::mem::transmute(ke_delay_execution as *const () as usize) }),
+        "KeStallExecutionProcessor" => Some(unsafe { core::mem::transmute(ke_stall_execution_processor as *const () as usize) }),
 
         // I/O Manager
         "IoCreateDevice" => Some(unsafe { core::mem::transmute(io_create_device as *const () as usize) }),
@@ -1912,6 +1946,9 @@ fn resolve_ntoskrnl_export(func_name: &str) -> Option<u64> {
         "IoCallDriver" => Some(unsafe { core::mem::transmute(io_call_driver as *const () as usize) }),
         "IofCompleteRequest" => Some(unsafe { core::mem::transmute(iof_complete_request as *const () as usize) }),
         "IofCallDriver" => Some(unsafe { core::mem::transmute(iof_call_driver as *const () as usize) }),
+        "IoRaiseInformationalHardError" => Some(unsafe { core::mem::transmute(io_raise_informational_hard_error as *const () as usize) }),
+        "IoCreateSymbolicLink" => Some(unsafe { core::mem::transmute(io_create_symbolic_link as *const () as usize) }),
+        "IoDeleteSymbolicLink" => Some(unsafe { core::mem::transmute(io_delete_symbolic_link as *const () as usize) }),
 
         // Runtime Library
         "RtlCopyMemory" => Some(unsafe { core::mem::transmute(rtl_copy_memory as *const () as usize) }),
@@ -1944,6 +1981,11 @@ fn resolve_ntoskrnl_export(func_name: &str) -> Option<u64> {
         return addr.map(|f| f as usize as u64);
     }
 
+    // C runtime routines and intrinsics
+    if let Some(addr) = crate::rtl::kmcrt::kmcrt_export(func_name) {
+        return Some(addr);
+    }
+
     crate::serial_println!("[LDR] Unresolved ntoskrnl export: {}", func_name);
     None
 }
@@ -1972,8 +2014,8 @@ fn resolve_hal_export(func_name: &str) -> Option<u64> {
 
 /// Get the count of available kernel exports
 pub fn get_kernel_export_count() -> usize {
-    // Count of entries in resolve_ntoskrnl_export match + resolve_hal_export
-    52 + 8
+    // Count of entries in resolve_ntoskrnl_export match + resolve_hal_export + kmcrt
+    54 + 8 + crate::rtl::kmcrt::KMCRT_EXPORTS.len()
 }
 
 /// Create a kernel-mode import resolver
@@ -2016,17 +2058,43 @@ unsafe extern "C" fn mm_unmap_io_space(_virtual_addr: u64, _size: usize) {
     // No-op for now
 }
 
-unsafe extern "C" fn mm_allocate_contiguous(size: usize) -> u64 {
-    // Allocate from nonpaged pool (contiguous is just pool allocation for now)
-    crate::mm::pool::ex_allocate_pool_with_tag(
-        crate::mm::pool::PoolType::NonPagedPool,
+unsafe extern "C" fn mm_allocate_contiguous(size: usize, highest_acceptable: u64) -> u64 {
+    crate::mm::mm_allocate_contiguous_memory(size, highest_acceptable).unwrap_or(0)
+}
+
+unsafe extern "C" fn mm_free_contiguous(base: u64) {
+    crate::mm::mm_free_contiguous_memory(base);
+}
+
+/// MmAllocateContiguousMemorySpecifyCache export
+///
+/// Only MmCached (1) is supported; any other cache type returns 0.
+unsafe extern "C" fn mm_allocate_contiguous_specify_cache(
+    size: usize,
+    lowest_acceptable: u64,
+    highest_acceptable: u64,
+    boundary_multiple: u64,
+    cache_type: u32,
+) -> u64 {
+    let cache_type = match cache_type {
+        0 => crate::mm::MemoryCachingType::MmNonCached,
+        2 => crate::mm::MemoryCachingType::MmWriteCombined,
+        3 => crate::mm::MemoryCachingType::MmHardwareCoherentCached,
+        4 => crate::mm::MemoryCachingType::MmNonCachedUnordered,
+        5 => crate::mm::MemoryCachingType::MmFrameBufferCached,
+        _ => crate::mm::MemoryCachingType::MmCached,
+    };
+    crate::mm::mm_allocate_contiguous_memory_specify_cache(
         size,
-        u32::from_le_bytes(*b"Cont"),
-    ) as u64
+        lowest_acceptable,
+        highest_acceptable,
+        boundary_multiple,
+        cache_type,
+    ).unwrap_or(0)
 }
 
-unsafe extern "C" fn mm_free_contiguous(base: u64, _size: usize) {
-    crate::mm::pool::ex_free_pool_with_tag(base as *mut u8, u32::from_le_bytes(*b"Cont"));
+unsafe extern "C" fn mm_free_contiguous_specify_cache(base: u64, _size: usize, _cache_type: u32) {
+    crate::mm::mm_free_contiguous_memory(base);
 }
 
 // Executive Pool stubs
@@ -2075,6 +2143,17 @@ unsafe extern "C" fn ke_release_spinlock_dpc(lock: *mut u64) {
     core::sync::atomic::AtomicU64::from_ptr(lock).store(0, core::sync::atomic::Ordering::Release);
 }
 
+unsafe extern "C" fn ke_acquire_spinlock_raise_to_dpc(lock: *mut u64) -> u8 {
+    let old_irql = crate::ke::kpcr::ke_raise_irql(crate::ke::kpcr::irql::DISPATCH_LEVEL);
+    ke_acquire_spinlock_dpc(lock);
+    old_irql
+}
+
+unsafe extern "C" fn ke_release_spinlock(lock: *mut u64, old_irql: u8) {
+    ke_release_spinlock_dpc(lock);
+    crate::ke::kpcr::ke_lower_irql(old_irql);
+}
+
 unsafe extern "C" fn ke_initialize_event(event: *mut u64, event_type: u32, state: bool) {
     let _ = (event, event_type, state);
     // TODO: Initialize dispatcher object
@@ -2113,22 +2192,115 @@ unsafe extern "C" fn ke_query_system_time(time: *mut i64) {
     *time = crate::rtl::rtl_get_system_time();
 }
 
-unsafe extern "C" fn ke_delay_execution(_alertable: bool, interval: *const i64) -> i32 {
-    let _ = interval;
-    // TODO: Actual delay
-    0
+unsafe extern "C" fn ke_delay_execution(alertable: bool, interval: *const i64) -> i32 {
+    if interval.is_null() {
+        return 0xC000000Du32 as i32; // STATUS_INVALID_PARAMETER
+    }
+    crate::ex::ke_delay_execution_thread(0, alertable, *interval)
+}
+
+unsafe extern "C" fn ke_stall_execution_processor(microseconds: u32) {
+    crate::hal::timer::ke_stall_execution_processor(microseconds);
 }
 
 // I/O Manager stubs
+unsafe extern "C" fn io_raise_informational_hard_error(status: i32, string: u64, _thread: u64) -> u8 {
+    // Shown without waiting; `string` (a UNICODE_STRING) fills in %1
+    let params = [string as usize];
+    let count = if string != 0 { 1 } else { 0 };
+    let result = crate::ex::ex_raise_hard_error(
+        status,
+        count,
+        count,
+        &params[..count as usize],
+        crate::ex::HardErrorResponseOption::OkNoWait,
+    );
+    result.is_ok() as u8
+}
+
+/// Read a driver's UNICODE_STRING argument
+unsafe fn unicode_string_arg(string: u64) -> Option<alloc::string::String> {
+    if string == 0 {
+        return None;
+    }
+    let us = &*(string as *const crate::rtl::UnicodeString);
+    Some(alloc::string::String::from_utf16_lossy(us.as_slice()))
+}
+
+unsafe extern "C" fn io_create_symbolic_link(link_name: u64, device_name: u64) -> i32 {
+    match (unicode_string_arg(link_name), unicode_string_arg(device_name)) {
+        (Some(link), Some(device)) => crate::io::io_create_symbolic_link(&link, &device),
+        _ => -1073741811, // STATUS_INVALID_PARAMETER
+    }
+}
+
+unsafe extern "C" fn io_delete_symbolic_link(link_name: u64) -> i32 {
+    match unicode_string_arg(link_name) {
+        Some(link) => crate::io::io_delete_symbolic_link(&link),
+        None => -1073741811, // STATUS_INVALID_PARAMETER
+    }
+}
+
 unsafe extern "C" fn io_create_device(
-    _driver: u64, _ext_size: u32, _name: u64, _type: u32, _chars: u32, _exclusive: bool, _device: *mut u64
+    driver: u64, ext_size: u32, name: u64, device_type: u32, chars: u32, exclusive: bool, device: *mut u64
 ) -> i32 {
-    // TODO: Create device object
+    if driver == 0 || device.is_null() {
+        return -1073741811; // STATUS_INVALID_PARAMETER
+    }
+
+    // Narrow the UNICODE_STRING name to the I/O manager's byte names
+    let mut name_buf = [0u8; crate::io::device::DEVICE_NAME_LENGTH];
+    let mut name_len = 0;
+    if name != 0 {
+        let us = &*(name as *const crate::rtl::UnicodeString);
+        if !us.buffer.is_null() {
+            let chars_in = (us.length as usize / 2).min(name_buf.len() - 1);
+            for (i, slot) in name_buf.iter_mut().enumerate().take(chars_in) {
+                *slot = *us.buffer.add(i) as u8;
+            }
+            name_len = chars_in;
+        }
+    }
+    let device_name = if name_len > 0 { Some(&name_buf[..name_len]) } else { None };
+
+    let dev = crate::io::io_create_device(
+        driver as *mut crate::io::DriverObject, device_type, device_name, chars,
+    );
+    if dev.is_null() {
+        return -1073741670; // STATUS_INSUFFICIENT_RESOURCES
+    }
+
+    if ext_size > 0 {
+        let ext = crate::mm::pool::ex_allocate_pool_with_tag(
+            crate::mm::pool::PoolType::NonPagedPool, ext_size as usize, u32::from_le_bytes(*b"DevX"),
+        );
+        if ext.is_null() {
+            crate::io::io_delete_device(dev);
+            return -1073741670; // STATUS_INSUFFICIENT_RESOURCES
+        }
+        ptr::write_bytes(ext, 0, ext_size as usize);
+        (*dev).device_extension = ext;
+        (*dev).device_extension_size = ext_size;
+    }
+
+    if exclusive {
+        (*dev).flags.fetch_or(crate::io::device_flags::DO_EXCLUSIVE, core::sync::atomic::Ordering::SeqCst);
+    }
+
+    *device = dev as u64;
     0
 }
 
-unsafe extern "C" fn io_delete_device(_device: u64) {
-    // TODO: Delete device
+unsafe extern "C" fn io_delete_device(device: u64) {
+    if device == 0 {
+        return;
+    }
+    let dev = device as *mut crate::io::DeviceObject;
+    if !(*dev).device_extension.is_null() {
+        crate::mm::pool::ex_free_pool_with_tag((*dev).device_extension, u32::from_le_bytes(*b"DevX"));
+        (*dev).device_extension = ptr::null_mut();
+    }
+    crate::io::io_delete_device(dev);
 }
 
 unsafe extern "C" fn io_attach_device(_source: u64, _target_name: u64, _target: *mut u64) -> i32 {
@@ -2150,14 +2322,13 @@ unsafe extern "C" fn io_get_current_irp_stack(irp: u64) -> u64 {
 }
 
 unsafe extern "C" fn io_complete_request(irp: u64, priority_boost: i8) {
-    let _ = (irp, priority_boost);
-    // TODO: Complete IRP
+    if irp != 0 {
+        crate::io::io_complete_request(irp as *mut crate::io::Irp, priority_boost);
+    }
 }
 
 unsafe extern "C" fn io_call_driver(device: u64, irp: u64) -> i32 {
-    let _ = (device, irp);
-    // TODO: Call driver
-    0
+    crate::io::io_call_driver(device as *mut crate::io::DeviceObject, irp as *mut crate::io::Irp)
 }
 
 unsafe extern "C" fn iof_complete_request(irp: u64, priority_boost: i8) {
@@ -2237,11 +2408,11 @@ unsafe extern "C" fn rtl_compare_unicode_string(s1: *const crate::rtl::UnicodeSt
 }
 
 // Debug stubs
-unsafe extern "C" fn dbg_print(format: *const u8) -> i32 {
-    // Simple implementation - just print the format string
-    // Note: Full variadic support would require c_variadic feature
-    let s = cstr_to_str(format);
-    crate::serial_println!("[DBG] {}", s);
+unsafe extern "C" fn dbg_print(format: *const u8, args: ...) -> i32 {
+    let mut buffer = [0u8; 512];
+    let len = crate::rtl::kmcrt::kmcrt_vsnprintf(buffer.as_mut_ptr(), buffer.len(), format, args);
+    let text = alloc::string::String::from_utf8_lossy(&buffer[..(len.max(0) as usize).min(buffer.len() - 1)]);
+    crate::serial_println!("[DBG] {}", text.trim_end_matches('\n'));
     0
 }
 
@@ -0,0 +1,1114 @@
+//! PE Image Verification
+//!
+//! Deep structural checks on a PE image before it is loaded: header
+//! sanity, section alignment and overlap, import/export table bounds,
+//! base relocation blocks and the entry point. Every problem the loader
+//! would otherwise hit at runtime (a wild copy, an unterminated thunk
+//! array, a relocation past the image) is reported as an error; oddities
+//! the loader tolerates are reported as warnings.
+//!
+//! All reads are bounds-checked against the supplied buffer, so the
+//! checker is safe to run on untrusted images.
+//!
+//! # Layouts
+//! - **File**: the image as stored on disk; RVAs are mapped to file
+//!   offsets through the section table.
+//! - **Mapped**: the image as laid out in memory by the loader; RVAs are
+//!   offsets from the base.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use super::pe::*;
+use super::{PeError, PeInfo};
+
+/// Thunks walked per imported DLL before the array counts as unterminated
+const MAX_THUNKS_PER_DLL: usize = 4096;
+
+/// Import descriptors walked before the table counts as unterminated
+const MAX_IMPORT_DESCRIPTORS: usize = 1024;
+
+/// Longest DLL or function name accepted
+const MAX_NAME_LEN: usize = 256;
+
+/// Issues kept per report
+const MAX_ISSUES: usize = 64;
+
+/// Image layout of the buffer being verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeLayout {
+    File,
+    Mapped,
+}
+
+/// Issue severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeIssueSeverity {
+    /// Tolerated by the loader
+    Warning,
+    /// The loader would fault or misbehave
+    Error,
+}
+
+/// Area of the image an issue belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeCheck {
+    Headers,
+    Alignment,
+    Sections,
+    Imports,
+    Exports,
+    Relocations,
+    EntryPoint,
+}
+
+impl PeCheck {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PeCheck::Headers => "headers",
+            PeCheck::Alignment => "alignment",
+            PeCheck::Sections => "sections",
+            PeCheck::Imports => "imports",
+            PeCheck::Exports => "exports",
+            PeCheck::Relocations => "relocations",
+            PeCheck::EntryPoint => "entry point",
+        }
+    }
+}
+
+/// A single verification finding
+#[derive(Debug, Clone)]
+pub struct PeIssue {
+    pub severity: PeIssueSeverity,
+    pub check: PeCheck,
+    pub message: String,
+}
+
+/// Result of verifying an image
+#[derive(Debug, Clone, Default)]
+pub struct PeVerifyReport {
+    /// Parsed headers, if they were readable
+    pub info: Option<PeInfo>,
+    pub issues: Vec<PeIssue>,
+    /// Issues not kept because the report was full
+    pub suppressed: usize,
+    pub sections: usize,
+    pub import_dlls: usize,
+    pub import_functions: usize,
+    pub exports: usize,
+    pub relocation_blocks: usize,
+    pub relocations: usize,
+}
+
+impl PeVerifyReport {
+    /// Number of errors found
+    pub fn errors(&self) -> usize {
+        self.issues.iter().filter(|i| i.severity == PeIssueSeverity::Error).count()
+    }
+
+    /// Number of warnings found
+    pub fn warnings(&self) -> usize {
+        self.issues.iter().filter(|i| i.severity == PeIssueSeverity::Warning).count()
+    }
+
+    /// True if the loader can map the image safely
+    pub fn is_loadable(&self) -> bool {
+        self.info.is_some() && self.errors() == 0
+    }
+
+    fn push(&mut self, severity: PeIssueSeverity, check: PeCheck, message: String) {
+        if self.issues.len() < MAX_ISSUES {
+            self.issues.push(PeIssue { severity, check, message });
+        } else {
+            self.suppressed += 1;
+        }
+    }
+
+    fn error(&mut self, check: PeCheck, message: String) {
+        self.push(PeIssueSeverity::Error, check, message);
+    }
+
+    fn warning(&mut self, check: PeCheck, message: String) {
+        self.push(PeIssueSeverity::Warning, check, message);
+    }
+}
+
+/// Bounds-checked view of an image buffer
+struct ImageView<'a> {
+    data: &'a [u8],
+    layout: PeLayout,
+    size_of_image: u32,
+    size_of_headers: u32,
+    sections: Vec<ImageSectionHeader>,
+    directories: [ImageDataDirectory; IMAGE_NUMBEROF_DIRECTORY_ENTRIES],
+}
+
+impl ImageView<'_> {
+    /// Read a `T` at a buffer offset
+    fn read<T: Copy>(&self, offset: usize) -> Option<T> {
+        let end = offset.checked_add(core::mem::size_of::<T>())?;
+        if end > self.data.len() {
+            return None;
+        }
+        // SAFETY: the range was checked above; T is plain old data
+        Some(unsafe { core::ptr::read_unaligned(self.data.as_ptr().add(offset) as *const T) })
+    }
+
+    /// Map `len` bytes at `rva` to a buffer offset, if all of them are present
+    fn rva_to_offset(&self, rva: u32, len: u32) -> Option<usize> {
+        let end = rva.checked_add(len)?;
+        if end > self.size_of_image {
+            return None;
+        }
+
+        let offset = match self.layout {
+            PeLayout::Mapped => rva as usize,
+            PeLayout::File if end <= self.size_of_headers => rva as usize,
+            PeLayout::File => {
+                let section = self.sections.iter().find(|s| {
+                    let start = s.virtual_address;
+                    rva >= start && end <= start.saturating_add(s.size_of_raw_data)
+                })?;
+                (section.pointer_to_raw_data + (rva - section.virtual_address)) as usize
+            }
+        };
+
+        if offset + len as usize > self.data.len() {
+            return None;
+        }
+        Some(offset)
+    }
+
+    fn read_rva<T: Copy>(&self, rva: u32) -> Option<T> {
+        self.read(self.rva_to_offset(rva, core::mem::size_of::<T>() as u32)?)
+    }
+
+    /// Length of the NUL-terminated string at `rva`, if it ends in bounds
+    fn cstr_len(&self, rva: u32) -> Option<usize> {
+        let offset = self.rva_to_offset(rva, 1)?;
+        let limit = (self.data.len() - offset).min(MAX_NAME_LEN);
+        let bytes = &self.data[offset..offset + limit];
+        let len = bytes.iter().position(|&b| b == 0)?;
+        // Strings must also stay inside one RVA range
+        self.rva_to_offset(rva, len as u32 + 1)?;
+        Some(len)
+    }
+
+    fn cstr(&self, rva: u32) -> Option<&str> {
+        let len = self.cstr_len(rva)?;
+        let offset = self.rva_to_offset(rva, 1)?;
+        core::str::from_utf8(&self.data[offset..offset + len]).ok()
+    }
+
+    /// Section containing `rva`, by virtual extent
+    fn section_at(&self, rva: u32) -> Option<&ImageSectionHeader> {
+        self.sections.iter().find(|s| {
+            let start = s.virtual_address;
+            rva >= start && rva < start.saturating_add(virtual_extent(s))
+        })
+    }
+}
+
+/// Bytes a section occupies in memory
+fn virtual_extent(section: &ImageSectionHeader) -> u32 {
+    if section.virtual_size != 0 { section.virtual_size } else { section.size_of_raw_data }
+}
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    if alignment == 0 { value } else { value.saturating_add(alignment - 1) & !(alignment - 1) }
+}
+
+/// Verify a PE image held in `data`
+pub fn pe_verify(data: &[u8], layout: PeLayout) -> PeVerifyReport {
+    let mut report = PeVerifyReport::default();
+
+    let view = match verify_headers(data, layout, &mut report) {
+        Some(view) => view,
+        None => return report,
+    };
+
+    verify_sections(&view, &mut report);
+    verify_directories(&view, &mut report);
+    verify_imports(&view, &mut report);
+    verify_exports(&view, &mut report);
+    verify_relocations(&view, &mut report);
+    verify_entry_point(&view, &mut report);
+
+    report
+}
+
+/// Check the DOS, NT and optional headers and build the image view
+fn verify_headers<'a>(data: &'a [u8], layout: PeLayout, report: &mut PeVerifyReport) -> Option<ImageView<'a>> {
+    let mut view = ImageView { data, layout, size_of_image: u32::MAX, size_of_headers: 0,
+        sections: Vec::new(), directories: [ImageDataDirectory::default(); IMAGE_NUMBEROF_DIRECTORY_ENTRIES] };
+
+    let dos = match view.read::<ImageDosHeader>(0) {
+        Some(dos) if dos.is_valid() => dos,
+        Some(_) => {
+            report.error(PeCheck::Headers, String::from("missing MZ signature"));
+            return None;
+        }
+        None => {
+            report.error(PeCheck::Headers, String::from("image shorter than a DOS header"));
+            return None;
+        }
+    };
+
+    let pe_offset = dos.pe_offset();
+    if view.read::<u32>(pe_offset) != Some(IMAGE_NT_SIGNATURE) {
+        report.error(PeCheck::Headers, format!("no PE signature at e_lfanew {:#x}", pe_offset));
+        return None;
+    }
+
+    let file_header_offset = pe_offset + 4;
+    let file_header: ImageFileHeader = match view.read(file_header_offset) {
+        Some(h) => h,
+        None => {
+            report.error(PeCheck::Headers, String::from("truncated COFF file header"));
+            return None;
+        }
+    };
+
+    let optional_offset = file_header_offset + core::mem::size_of::<ImageFileHeader>();
+    let optional_size = file_header.size_of_optional_header as usize;
+    let magic = view.read::<u16>(optional_offset);
+    let (min_size, is_64bit) = match magic {
+        Some(IMAGE_NT_OPTIONAL_HDR64_MAGIC) => (core::mem::size_of::<ImageOptionalHeader64>(), true),
+        Some(IMAGE_NT_OPTIONAL_HDR32_MAGIC) => (core::mem::size_of::<ImageOptionalHeader32>(), false),
+        _ => {
+            report.error(PeCheck::Headers, String::from("unknown optional header magic"));
+            return None;
+        }
+    };
+    let directory_table_size = IMAGE_NUMBEROF_DIRECTORY_ENTRIES * core::mem::size_of::<ImageDataDirectory>();
+    if optional_size < min_size - directory_table_size {
+        report.error(PeCheck::Headers, format!("optional header size {:#x} is too small", optional_size));
+        return None;
+    }
+    if optional_offset + optional_size > data.len() {
+        report.error(PeCheck::Headers, String::from("truncated optional header"));
+        return None;
+    }
+
+    // parse_pe reads through raw pointers; only call it once the
+    // headers are known to be inside the buffer
+    let info = match unsafe { super::parse_pe(data.as_ptr()) } {
+        Ok(info) => info,
+        Err(e) => {
+            report.error(PeCheck::Headers, format!("headers rejected by the loader: {:?}", e));
+            return None;
+        }
+    };
+    if info.is_64bit != is_64bit {
+        report.error(PeCheck::Headers, String::from("optional header magic does not match the machine type"));
+    }
+    report.info = Some(info);
+
+    // Data directories: bounded by both NumberOfRvaAndSizes and the
+    // declared optional header size
+    let directory_offset = optional_offset + min_size - directory_table_size;
+    let declared = view.read::<u32>(directory_offset - 4).unwrap_or(0) as usize;
+    let fits = (optional_size - (min_size - directory_table_size)) / core::mem::size_of::<ImageDataDirectory>();
+    if declared > fits {
+        report.warning(PeCheck::Headers, format!(
+            "{} data directories declared, only {} fit in the optional header", declared, fits));
+    }
+    for index in 0..declared.min(fits).min(IMAGE_NUMBEROF_DIRECTORY_ENTRIES) {
+        if let Some(dir) = view.read(directory_offset + index * core::mem::size_of::<ImageDataDirectory>()) {
+            view.directories[index] = dir;
+        }
+    }
+
+    view.size_of_image = info.size_of_image;
+    view.size_of_headers = info.size_of_headers;
+
+    // Alignment fields
+    let section_alignment = info.section_alignment;
+    let file_alignment = info.file_alignment;
+    if section_alignment == 0 || !section_alignment.is_power_of_two() {
+        report.error(PeCheck::Alignment, format!("section alignment {:#x} is not a power of two", section_alignment));
+    }
+    if file_alignment == 0 || !file_alignment.is_power_of_two() {
+        report.error(PeCheck::Alignment, format!("file alignment {:#x} is not a power of two", file_alignment));
+    } else if !(0x200..=0x10000).contains(&file_alignment) {
+        report.warning(PeCheck::Alignment, format!("file alignment {:#x} is outside 0x200-0x10000", file_alignment));
+    }
+    if file_alignment > section_alignment {
+        report.warning(PeCheck::Alignment, format!(
+            "file alignment {:#x} exceeds section alignment {:#x}", file_alignment, section_alignment));
+    }
+    if section_alignment.is_power_of_two() && info.size_of_image % section_alignment != 0 {
+        report.warning(PeCheck::Alignment, format!(
+            "image size {:#x} is not a multiple of the section alignment", info.size_of_image));
+    }
+    if file_alignment.is_power_of_two() && info.size_of_headers % file_alignment != 0 {
+        report.warning(PeCheck::Alignment, format!(
+            "header size {:#x} is not a multiple of the file alignment", info.size_of_headers));
+    }
+
+    // The loader copies size_of_headers bytes verbatim
+    if info.size_of_headers > info.size_of_image {
+        report.error(PeCheck::Headers, format!(
+            "header size {:#x} exceeds image size {:#x}", info.size_of_headers, info.size_of_image));
+    }
+    if info.size_of_headers as usize > data.len() {
+        report.error(PeCheck::Headers, format!(
+            "header size {:#x} runs past the end of the image buffer ({:#x})", info.size_of_headers, data.len()));
+    }
+
+    // Section table
+    let table_offset = optional_offset + optional_size;
+    let count = file_header.number_of_sections as usize;
+    let table_end = table_offset + count * core::mem::size_of::<ImageSectionHeader>();
+    if table_end > data.len() {
+        report.error(PeCheck::Sections, format!("section table of {} entries is truncated", count));
+        return None;
+    }
+    if table_end > info.size_of_headers as usize {
+        report.warning(PeCheck::Sections, String::from("section table extends past the declared header size"));
+    }
+    if count == 0 {
+        report.warning(PeCheck::Sections, String::from("image has no sections"));
+    }
+
+    for i in 0..count {
+        if let Some(section) = view.read::<ImageSectionHeader>(table_offset + i * core::mem::size_of::<ImageSectionHeader>()) {
+            view.sections.push(section);
+        }
+    }
+
+    Some(view)
+}
+
+/// Check section placement, alignment and overlap
+fn verify_sections(view: &ImageView, report: &mut PeVerifyReport) {
+    let info = match report.info {
+        Some(info) => info,
+        None => return,
+    };
+    let section_alignment = info.section_alignment;
+    let file_alignment = info.file_alignment;
+    let headers_end = align_up(info.size_of_headers, section_alignment);
+
+    report.sections = view.sections.len();
+
+    let mut previous: Option<(&ImageSectionHeader, u32)> = None;
+    for section in &view.sections {
+        let name = section.name_str();
+        let va = section.virtual_address;
+        let extent = virtual_extent(section);
+        let raw_offset = section.pointer_to_raw_data;
+        let raw_size = section.size_of_raw_data;
+
+        if section_alignment.is_power_of_two() && va % section_alignment != 0 {
+            report.error(PeCheck::Alignment, format!(
+                "section {} at RVA {:#x} is not aligned to {:#x}", name, va, section_alignment));
+        }
+        if raw_size != 0 && file_alignment.is_power_of_two() && raw_offset % file_alignment != 0 {
+            report.warning(PeCheck::Alignment, format!(
+                "section {} raw data at {:#x} is not aligned to {:#x}", name, raw_offset, file_alignment));
+        }
+
+        if va < headers_end {
+            report.error(PeCheck::Sections, format!("section {} at RVA {:#x} overlaps the headers", name, va));
+        }
+        match va.checked_add(extent) {
+            Some(end) if end <= info.size_of_image => {}
+            _ => report.error(PeCheck::Sections, format!(
+                "section {} ({:#x}+{:#x}) extends past the image size {:#x}", name, va, extent, info.size_of_image)),
+        }
+
+        if view.layout == PeLayout::File && raw_size != 0 {
+            let in_file = (raw_offset as usize).checked_add(raw_size as usize)
+                .is_some_and(|end| end <= view.data.len());
+            if !in_file {
+                report.error(PeCheck::Sections, format!(
+                    "section {} raw data ({:#x}+{:#x}) runs past the end of the file", name, raw_offset, raw_size));
+            }
+        }
+
+        if let Some((prev, prev_end)) = previous {
+            let prev_va = prev.virtual_address;
+            if va < prev_va {
+                report.warning(PeCheck::Sections, format!(
+                    "section {} is out of order (RVA {:#x} after {:#x})", name, va, prev_va));
+            } else if va < prev_end {
+                report.error(PeCheck::Sections, format!(
+                    "section {} at RVA {:#x} overlaps {} (ends at {:#x})", name, va, prev.name_str(), prev_end));
+            }
+        }
+        previous = Some((section, va.saturating_add(align_up(extent, section_alignment))));
+    }
+
+    // Overlapping raw data is legal but almost always a packer or a bug
+    for (i, a) in view.sections.iter().enumerate() {
+        for b in &view.sections[i + 1..] {
+            let (a_start, a_size) = (a.pointer_to_raw_data, a.size_of_raw_data);
+            let (b_start, b_size) = (b.pointer_to_raw_data, b.size_of_raw_data);
+            if a_size != 0 && b_size != 0
+                && a_start < b_start.saturating_add(b_size) && b_start < a_start.saturating_add(a_size)
+            {
+                report.warning(PeCheck::Sections, format!(
+                    "sections {} and {} share raw file data", a.name_str(), b.name_str()));
+            }
+        }
+    }
+}
+
+fn directory(view: &ImageView, index: usize) -> Option<ImageDataDirectory> {
+    Some(view.directories[index]).filter(|d| d.is_present())
+}
+
+/// Check that every present data directory lies inside the image
+fn verify_directories(view: &ImageView, report: &mut PeVerifyReport) {
+    use directory_entry::*;
+
+    const NAMES: [&str; IMAGE_NUMBEROF_DIRECTORY_ENTRIES] = [
+        "export", "import", "resource", "exception", "security", "base relocation",
+        "debug", "architecture", "global pointer", "TLS", "load config",
+        "bound import", "IAT", "delay import", "COM descriptor", "reserved",
+    ];
+
+    for (index, name) in NAMES.iter().enumerate() {
+        // The security directory holds a file offset, not an RVA
+        if index == IMAGE_DIRECTORY_ENTRY_SECURITY {
+            continue;
+        }
+        if let Some(dir) = directory(view, index) {
+            let (rva, size) = (dir.virtual_address, dir.size);
+            let check = match index {
+                IMAGE_DIRECTORY_ENTRY_IMPORT => PeCheck::Imports,
+                IMAGE_DIRECTORY_ENTRY_EXPORT => PeCheck::Exports,
+                IMAGE_DIRECTORY_ENTRY_BASERELOC => PeCheck::Relocations,
+                _ => PeCheck::Headers,
+            };
+            if rva.checked_add(size).is_none_or(|end| end > view.size_of_image) {
+                report.error(check, format!(
+                    "{} directory ({:#x}+{:#x}) extends past the image", name, rva, size));
+            } else if view.rva_to_offset(rva, size).is_none() {
+                report.error(check, format!(
+                    "{} directory ({:#x}+{:#x}) is not backed by image data", name, rva, size));
+            }
+        }
+    }
+}
+
+/// Walk the import descriptors and their thunk arrays
+fn verify_imports(view: &ImageView, report: &mut PeVerifyReport) {
+    let dir = match directory(view, directory_entry::IMAGE_DIRECTORY_ENTRY_IMPORT) {
+        Some(dir) => dir,
+        None => return,
+    };
+    let is_64bit = report.info.is_some_and(|i| i.is_64bit);
+    let thunk_size = if is_64bit { 8 } else { 4 };
+    let descriptor_size = core::mem::size_of::<ImageImportDescriptor>() as u32;
+
+    for index in 0..MAX_IMPORT_DESCRIPTORS as u32 {
+        let rva = dir.virtual_address + index * descriptor_size;
+        let desc: ImageImportDescriptor = match view.read_rva(rva) {
+            Some(desc) => desc,
+            None => {
+                report.error(PeCheck::Imports, format!("import descriptor {} at RVA {:#x} is out of bounds", index, rva));
+                return;
+            }
+        };
+        if desc.is_null() {
+            return;
+        }
+        report.import_dlls += 1;
+
+        let dll = match view.cstr(desc.name) {
+            Some(name) => name,
+            None => {
+                report.error(PeCheck::Imports, format!(
+                    "import descriptor {} has an invalid DLL name RVA {:#x}", index, { desc.name }));
+                "?"
+            }
+        };
+
+        // The loader walks the lookup table, falling back to the IAT
+        let table = if desc.original_first_thunk != 0 { desc.original_first_thunk } else { desc.first_thunk };
+        if desc.first_thunk == 0 || view.rva_to_offset(desc.first_thunk, thunk_size).is_none() {
+            report.error(PeCheck::Imports, format!("imports from {}: IAT RVA {:#x} is out of bounds", dll, { desc.first_thunk }));
+            continue;
+        }
+
+        let mut terminated = false;
+        for slot in 0..MAX_THUNKS_PER_DLL as u32 {
+            let thunk_rva = table + slot * thunk_size;
+            let thunk = if is_64bit {
+                view.read_rva::<u64>(thunk_rva)
+            } else {
+                view.read_rva::<u32>(thunk_rva).map(|t| t as u64)
+            };
+            let thunk = match thunk {
+                Some(t) => t,
+                None => {
+                    report.error(PeCheck::Imports, format!(
+                        "imports from {}: thunk {} at RVA {:#x} is out of bounds", dll, slot, thunk_rva));
+                    terminated = true;
+                    break;
+                }
+            };
+            if thunk == 0 {
+                terminated = true;
+                break;
+            }
+            report.import_functions += 1;
+
+            let by_ordinal = if is_64bit { thunk & IMAGE_ORDINAL_FLAG64 != 0 } else { thunk & IMAGE_ORDINAL_FLAG32 as u64 != 0 };
+            if by_ordinal {
+                continue;
+            }
+            // IMAGE_IMPORT_BY_NAME: u16 hint, then the name
+            let hint_rva = thunk as u32;
+            if thunk > u32::MAX as u64 || view.cstr_len(hint_rva.wrapping_add(2)).is_none() {
+                report.error(PeCheck::Imports, format!(
+                    "imports from {}: thunk {} names RVA {:#x}, which is not a valid string", dll, slot, thunk));
+            }
+        }
+        if !terminated {
+            report.error(PeCheck::Imports, format!(
+                "imports from {}: thunk array is not terminated within {} entries", dll, MAX_THUNKS_PER_DLL));
+        }
+    }
+
+    report.error(PeCheck::Imports, format!(
+        "import descriptor table is not terminated within {} entries", MAX_IMPORT_DESCRIPTORS));
+}
+
+/// Check the export directory and its three tables
+fn verify_exports(view: &ImageView, report: &mut PeVerifyReport) {
+    let dir = match directory(view, directory_entry::IMAGE_DIRECTORY_ENTRY_EXPORT) {
+        Some(dir) => dir,
+        None => return,
+    };
+    let exports: ImageExportDirectory = match view.read_rva(dir.virtual_address) {
+        Some(e) => e,
+        None => {
+            report.error(PeCheck::Exports, String::from("export directory is truncated"));
+            return;
+        }
+    };
+    let dir_start = dir.virtual_address;
+    let dir_end = dir_start.saturating_add(dir.size);
+
+    if view.cstr(exports.name).is_none() {
+        report.warning(PeCheck::Exports, format!("export DLL name RVA {:#x} is invalid", { exports.name }));
+    }
+
+    let functions = exports.number_of_functions;
+    let names = exports.number_of_names;
+    let tables = [
+        ("address", exports.address_of_functions, functions.checked_mul(4)),
+        ("name pointer", exports.address_of_names, names.checked_mul(4)),
+        ("ordinal", exports.address_of_name_ordinals, names.checked_mul(2)),
+    ];
+    for (table, rva, size) in tables {
+        let ok = size.is_some_and(|size| size == 0 || view.rva_to_offset(rva, size).is_some());
+        if !ok {
+            report.error(PeCheck::Exports, format!(
+                "export {} table at RVA {:#x} is out of bounds", table, rva));
+            return;
+        }
+    }
+    report.exports = functions as usize;
+
+    let mut bad_names = 0;
+    let mut bad_ordinals = 0;
+    for i in 0..names {
+        let name_rva = view.read_rva::<u32>(exports.address_of_names + i * 4).unwrap_or(0);
+        if view.cstr_len(name_rva).is_none() {
+            bad_names += 1;
+        }
+        let ordinal = view.read_rva::<u16>(exports.address_of_name_ordinals + i * 2).unwrap_or(u16::MAX);
+        if ordinal as u32 >= functions {
+            bad_ordinals += 1;
+        }
+    }
+    if bad_names > 0 {
+        report.error(PeCheck::Exports, format!("{} export name(s) point outside the image", bad_names));
+    }
+    if bad_ordinals > 0 {
+        report.error(PeCheck::Exports, format!("{} export name(s) map to an ordinal past the address table", bad_ordinals));
+    }
+
+    let mut bad_functions = 0;
+    let mut bad_forwarders = 0;
+    for i in 0..functions {
+        let rva = view.read_rva::<u32>(exports.address_of_functions + i * 4).unwrap_or(0);
+        if rva == 0 {
+            continue;
+        }
+        if rva >= dir_start && rva < dir_end {
+            // Forwarder string ("DLL.Function")
+            if view.cstr(rva).is_none_or(|s| !s.contains('.')) {
+                bad_forwarders += 1;
+            }
+        } else if rva >= view.size_of_image {
+            bad_functions += 1;
+        }
+    }
+    if bad_functions > 0 {
+        report.error(PeCheck::Exports, format!("{} exported function(s) lie outside the image", bad_functions));
+    }
+    if bad_forwarders > 0 {
+        report.error(PeCheck::Exports, format!("{} export forwarder(s) are malformed", bad_forwarders));
+    }
+}
+
+/// Walk the base relocation blocks the way the loader will
+fn verify_relocations(view: &ImageView, report: &mut PeVerifyReport) {
+    use relocation_type::*;
+
+    let info = match report.info {
+        Some(info) => info,
+        None => return,
+    };
+    let dir = match directory(view, directory_entry::IMAGE_DIRECTORY_ENTRY_BASERELOC) {
+        Some(dir) => dir,
+        None => {
+            if !info.has_relocations {
+                report.warning(PeCheck::Relocations, String::from(
+                    "relocations are stripped; the image only loads at its preferred base"));
+            }
+            return;
+        }
+    };
+
+    let mut offset = 0u32;
+    let mut bad_types = 0;
+    let mut bad_targets = 0;
+    while offset + 8 <= dir.size {
+        let block: ImageBaseRelocation = match view.read_rva(dir.virtual_address + offset) {
+            Some(b) => b,
+            None => break,
+        };
+        let (page, size) = (block.virtual_address, block.size_of_block);
+        if size < 8 || size % 2 != 0 || offset + size > dir.size {
+            report.error(PeCheck::Relocations, format!(
+                "relocation block at offset {:#x} has invalid size {:#x}", offset, size));
+            return;
+        }
+        if page >= info.size_of_image {
+            report.error(PeCheck::Relocations, format!(
+                "relocation block at offset {:#x} targets page {:#x} outside the image", offset, page));
+        }
+        report.relocation_blocks += 1;
+
+        for i in 0..block.entry_count() as u32 {
+            let entry = view.read_rva::<u16>(dir.virtual_address + offset + 8 + i * 2).unwrap_or(0);
+            let width = match reloc_type(entry) {
+                IMAGE_REL_BASED_ABSOLUTE => continue,
+                IMAGE_REL_BASED_HIGH | IMAGE_REL_BASED_LOW => 2,
+                IMAGE_REL_BASED_HIGHLOW => 4,
+                IMAGE_REL_BASED_DIR64 => 8,
+                _ => {
+                    bad_types += 1;
+                    continue;
+                }
+            };
+            report.relocations += 1;
+            let target = page as u64 + reloc_offset(entry) as u64;
+            if target + width > info.size_of_image as u64 {
+                bad_targets += 1;
+            }
+        }
+
+        offset += size;
+    }
+
+    if bad_types > 0 {
+        report.error(PeCheck::Relocations, format!("{} relocation(s) use a type the loader does not support", bad_types));
+    }
+    if bad_targets > 0 {
+        report.error(PeCheck::Relocations, format!("{} relocation(s) patch bytes past the end of the image", bad_targets));
+    }
+}
+
+/// Check that the entry point lands in executable code
+fn verify_entry_point(view: &ImageView, report: &mut PeVerifyReport) {
+    let info = match report.info {
+        Some(info) => info,
+        None => return,
+    };
+    let entry = info.entry_point_rva;
+
+    if entry == 0 {
+        if !info.is_dll {
+            report.error(PeCheck::EntryPoint, String::from("executable has no entry point"));
+        }
+        return;
+    }
+    if entry >= info.size_of_image {
+        report.error(PeCheck::EntryPoint, format!(
+            "entry point {:#x} is past the image size {:#x}", entry, info.size_of_image));
+        return;
+    }
+
+    match view.section_at(entry) {
+        None => report.error(PeCheck::EntryPoint, format!("entry point {:#x} is not inside any section", entry)),
+        Some(section) if !section.is_executable() && !section.is_code() => {
+            report.error(PeCheck::EntryPoint, format!(
+                "entry point {:#x} is in non-executable section {}", entry, section.name_str()));
+        }
+        Some(section) if entry - section.virtual_address >= section.size_of_raw_data => {
+            report.error(PeCheck::EntryPoint, format!(
+                "entry point {:#x} is in the zero-filled tail of section {}", entry, section.name_str()));
+        }
+        Some(_) => {}
+    }
+}
+
+// ============================================================================
+// Loader Gate
+// ============================================================================
+
+/// File extent described by the headers: the end of the furthest section
+///
+/// # Safety
+/// `file_base` must point to PE headers that `parse_pe` accepts.
+pub unsafe fn pe_file_extent(file_base: *const u8) -> Option<usize> {
+    let info = super::parse_pe(file_base).ok()?;
+    let sections = super::get_section_headers(file_base)?;
+    let end = sections.iter()
+        .map(|s| s.pointer_to_raw_data as usize + s.size_of_raw_data as usize)
+        .max()
+        .unwrap_or(0);
+    Some(end.max(info.size_of_headers as usize))
+}
+
+/// Verify a file image before loading it
+///
+/// Logs every finding and refuses the image if any error was found.
+///
+/// # Safety
+/// `file_base` must be readable for `file_size` bytes.
+pub unsafe fn ldr_verify_image(file_base: *const u8, file_size: usize, name: &[u8]) -> Result<(), PeError> {
+    let data = core::slice::from_raw_parts(file_base, file_size);
+    let report = pe_verify(data, PeLayout::File);
+    let name = core::str::from_utf8(name).unwrap_or("image");
+
+    for issue in &report.issues {
+        crate::serial_println!(
+            "[LDR] {}: {} {}: {}",
+            name,
+            if issue.severity == PeIssueSeverity::Error { "error" } else { "warning" },
+            issue.check.name(),
+            issue.message
+        );
+    }
+
+    if report.is_loadable() {
+        Ok(())
+    } else {
+        crate::serial_println!("[LDR] {}: rejected by image verification ({} error(s))", name, report.errors());
+        Err(PeError::VerificationFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use section_characteristics::*;
+
+    const TEXT: u32 = IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ;
+    const RDATA: u32 = IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ;
+
+    const OPTIONAL_HEADER: usize = 0x58;
+    const SECTION_TABLE: usize = OPTIONAL_HEADER + 0xF0;
+
+    /// A minimal AMD64 executable in file layout: headers, .text at RVA
+    /// 0x1000 (file 0x200) and .rdata at RVA 0x2000 (file 0x400)
+    struct TestImage(Vec<u8>);
+
+    impl TestImage {
+        fn new() -> Self {
+            let mut image = TestImage(vec![0; 0x600]);
+            image.put_u16(0, IMAGE_DOS_SIGNATURE);
+            image.put_u32(0x3C, 0x40);
+            image.put_u32(0x40, IMAGE_NT_SIGNATURE);
+            image.put_u16(0x44, machine_type::IMAGE_FILE_MACHINE_AMD64);
+            image.put_u16(0x46, 2);
+            image.put_u16(0x54, 0xF0);
+            image.put_u16(0x56, file_characteristics::IMAGE_FILE_EXECUTABLE_IMAGE);
+
+            image.put_u16(OPTIONAL_HEADER, IMAGE_NT_OPTIONAL_HDR64_MAGIC);
+            image.set_entry_point(0x1000);
+            image.put_u32(OPTIONAL_HEADER + 24, 0x4000_0000);
+            image.put_u32(OPTIONAL_HEADER + 32, 0x1000);
+            image.put_u32(OPTIONAL_HEADER + 36, 0x200);
+            image.put_u32(OPTIONAL_HEADER + 56, 0x3000);
+            image.put_u32(OPTIONAL_HEADER + 60, 0x200);
+            image.put_u32(OPTIONAL_HEADER + 108, IMAGE_NUMBEROF_DIRECTORY_ENTRIES as u32);
+
+            image.set_section(0, b".text", 0x1000, 0x100, 0x200, 0x200, TEXT);
+            image.set_section(1, b".rdata", 0x2000, 0x200, 0x400, 0x200, RDATA);
+            image
+        }
+
+        fn put_u16(&mut self, offset: usize, value: u16) {
+            self.0[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        fn put_u32(&mut self, offset: usize, value: u32) {
+            self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        fn put_u64(&mut self, offset: usize, value: u64) {
+            self.0[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+
+        /// File offset of an RVA inside .rdata
+        fn rdata(rva: u32) -> usize {
+            (rva - 0x2000 + 0x400) as usize
+        }
+
+        fn put_str(&mut self, rva: u32, s: &[u8]) {
+            let offset = Self::rdata(rva);
+            self.0[offset..offset + s.len()].copy_from_slice(s);
+        }
+
+        fn set_entry_point(&mut self, rva: u32) {
+            self.put_u32(OPTIONAL_HEADER + 16, rva);
+        }
+
+        fn set_directory(&mut self, index: usize, rva: u32, size: u32) {
+            let offset = OPTIONAL_HEADER + 112 + index * 8;
+            self.put_u32(offset, rva);
+            self.put_u32(offset + 4, size);
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn set_section(&mut self, index: usize, name: &[u8], va: u32, size: u32, raw: u32, raw_size: u32, flags: u32) {
+            let offset = SECTION_TABLE + index * 40;
+            self.0[offset..offset + 8].fill(0);
+            self.0[offset..offset + name.len()].copy_from_slice(name);
+            self.put_u32(offset + 8, size);
+            self.put_u32(offset + 12, va);
+            self.put_u32(offset + 16, raw_size);
+            self.put_u32(offset + 20, raw);
+            self.put_u32(offset + 36, flags);
+        }
+
+        /// The image as the loader lays it out in memory
+        fn mapped(&self) -> Vec<u8> {
+            let mut mapped = vec![0; 0x3000];
+            mapped[..0x200].copy_from_slice(&self.0[..0x200]);
+            mapped[0x1000..0x1200].copy_from_slice(&self.0[0x200..0x400]);
+            mapped[0x2000..0x2200].copy_from_slice(&self.0[0x400..0x600]);
+            mapped
+        }
+
+        fn verify(&self) -> PeVerifyReport {
+            pe_verify(&self.0, PeLayout::File)
+        }
+    }
+
+    fn has_error(report: &PeVerifyReport, check: PeCheck, text: &str) -> bool {
+        report.issues.iter().any(|i| {
+            i.severity == PeIssueSeverity::Error && i.check == check && i.message.contains(text)
+        })
+    }
+
+    #[test]
+    fn test_valid_image() {
+        let image = TestImage::new();
+        for report in [image.verify(), pe_verify(&image.mapped(), PeLayout::Mapped)] {
+            assert!(report.is_loadable(), "{:?}", report.issues);
+            assert_eq!(report.errors(), 0);
+            assert_eq!(report.warnings(), 0);
+            assert_eq!(report.sections, 2);
+        }
+    }
+
+    #[test]
+    fn test_bad_headers() {
+        let report = pe_verify(&[0; 16], PeLayout::File);
+        assert!(has_error(&report, PeCheck::Headers, "shorter than a DOS header"));
+        assert!(!report.is_loadable());
+
+        let mut image = TestImage::new();
+        image.put_u16(0, 0);
+        assert!(has_error(&image.verify(), PeCheck::Headers, "MZ"));
+
+        let mut image = TestImage::new();
+        image.put_u32(0x3C, 0x5F0);
+        assert!(has_error(&image.verify(), PeCheck::Headers, "no PE signature"));
+
+        let mut image = TestImage::new();
+        image.put_u16(OPTIONAL_HEADER, 0x1234);
+        assert!(has_error(&image.verify(), PeCheck::Headers, "magic"));
+
+        let report = pe_verify(&TestImage::new().0[..0x100], PeLayout::File);
+        assert!(has_error(&report, PeCheck::Headers, "truncated optional header"));
+        assert!(report.info.is_none());
+    }
+
+    #[test]
+    fn test_alignment() {
+        let mut image = TestImage::new();
+        image.put_u32(OPTIONAL_HEADER + 32, 0x1800);
+        assert!(has_error(&image.verify(), PeCheck::Alignment, "section alignment"));
+
+        let mut image = TestImage::new();
+        image.set_section(1, b".rdata", 0x2100, 0x100, 0x400, 0x200, RDATA);
+        assert!(has_error(&image.verify(), PeCheck::Alignment, "not aligned"));
+    }
+
+    #[test]
+    fn test_section_placement() {
+        let mut image = TestImage::new();
+        image.set_section(1, b".rdata", 0x1000, 0x200, 0x400, 0x200, RDATA);
+        assert!(has_error(&image.verify(), PeCheck::Sections, "overlaps .text"));
+
+        let mut image = TestImage::new();
+        image.set_section(1, b".rdata", 0x2000, 0x2000, 0x400, 0x200, RDATA);
+        assert!(has_error(&image.verify(), PeCheck::Sections, "past the image size"));
+
+        let mut image = TestImage::new();
+        image.set_section(1, b".rdata", 0x2000, 0x200, 0x400, 0x400, RDATA);
+        assert!(has_error(&image.verify(), PeCheck::Sections, "past the end of the file"));
+
+        let mut image = TestImage::new();
+        image.set_section(0, b".text", 0, 0x100, 0x200, 0x200, TEXT);
+        assert!(has_error(&image.verify(), PeCheck::Sections, "overlaps the headers"));
+    }
+
+    #[test]
+    fn test_entry_point() {
+        let mut image = TestImage::new();
+        image.set_entry_point(0);
+        assert!(has_error(&image.verify(), PeCheck::EntryPoint, "no entry point"));
+
+        image.set_entry_point(0x2010);
+        assert!(has_error(&image.verify(), PeCheck::EntryPoint, "non-executable"));
+
+        image.set_entry_point(0x5000);
+        assert!(has_error(&image.verify(), PeCheck::EntryPoint, "past the image size"));
+
+        image.set_entry_point(0x1000);
+        image.set_section(0, b".text", 0x1000, 0x400, 0x200, 0x200, TEXT);
+        image.set_entry_point(0x1300);
+        assert!(has_error(&image.verify(), PeCheck::EntryPoint, "zero-filled tail"));
+    }
+
+    #[test]
+    fn test_relocations() {
+        let mut image = TestImage::new();
+        image.set_directory(directory_entry::IMAGE_DIRECTORY_ENTRY_BASERELOC, 0x2000, 12);
+        image.put_u32(TestImage::rdata(0x2000), 0x1000);
+        image.put_u32(TestImage::rdata(0x2004), 12);
+        image.put_u16(TestImage::rdata(0x2008), (relocation_type::IMAGE_REL_BASED_DIR64 << 12) | 0x10);
+        let report = image.verify();
+        assert!(report.is_loadable(), "{:?}", report.issues);
+        assert_eq!((report.relocation_blocks, report.relocations), (1, 1));
+
+        // Patches the last bytes of the image and beyond
+        image.put_u32(TestImage::rdata(0x2000), 0x2000);
+        image.put_u16(TestImage::rdata(0x2008), (relocation_type::IMAGE_REL_BASED_DIR64 << 12) | 0xFFC);
+        assert!(has_error(&image.verify(), PeCheck::Relocations, "past the end of the image"));
+
+        image.put_u16(TestImage::rdata(0x2008), (relocation_type::IMAGE_REL_BASED_THUMB_MOV32 << 12) | 0x10);
+        assert!(has_error(&image.verify(), PeCheck::Relocations, "type the loader does not support"));
+
+        image.put_u32(TestImage::rdata(0x2004), 6);
+        assert!(has_error(&image.verify(), PeCheck::Relocations, "invalid size"));
+    }
+
+    #[test]
+    fn test_stripped_relocations() {
+        let mut image = TestImage::new();
+        image.put_u16(0x56, file_characteristics::IMAGE_FILE_EXECUTABLE_IMAGE
+            | file_characteristics::IMAGE_FILE_RELOCS_STRIPPED);
+        let report = image.verify();
+        assert!(report.is_loadable());
+        assert_eq!(report.warnings(), 1);
+        assert_eq!(report.issues[0].check, PeCheck::Relocations);
+    }
+
+    /// Import one function by name from K.DLL
+    fn with_imports() -> TestImage {
+        let mut image = TestImage::new();
+        image.set_directory(directory_entry::IMAGE_DIRECTORY_ENTRY_IMPORT, 0x2100, 40);
+        image.put_u32(TestImage::rdata(0x2100), 0x2140);
+        image.put_u32(TestImage::rdata(0x210C), 0x2170);
+        image.put_u32(TestImage::rdata(0x2110), 0x2150);
+        image.put_u64(TestImage::rdata(0x2140), 0x2160);
+        image.put_u64(TestImage::rdata(0x2150), 0x2160);
+        image.put_str(0x2162, b"Foo\0");
+        image.put_str(0x2170, b"K.DLL\0");
+        image
+    }
+
+    #[test]
+    fn test_imports() {
+        let report = with_imports().verify();
+        assert!(report.is_loadable(), "{:?}", report.issues);
+        assert_eq!((report.import_dlls, report.import_functions), (1, 1));
+
+        let mut image = with_imports();
+        image.put_u64(TestImage::rdata(0x2140), 0x9000);
+        assert!(has_error(&image.verify(), PeCheck::Imports, "not a valid string"));
+
+        let mut image = with_imports();
+        image.put_u32(TestImage::rdata(0x210C), 0x8000);
+        assert!(has_error(&image.verify(), PeCheck::Imports, "invalid DLL name"));
+
+        let mut image = with_imports();
+        image.put_u32(TestImage::rdata(0x2110), 0);
+        assert!(has_error(&image.verify(), PeCheck::Imports, "IAT RVA"));
+
+        let mut image = with_imports();
+        image.set_directory(directory_entry::IMAGE_DIRECTORY_ENTRY_IMPORT, 0x2F00, 0x200);
+        assert!(has_error(&image.verify(), PeCheck::Imports, "extends past the image"));
+    }
+
+    #[test]
+    fn test_exports() {
+        let mut image = TestImage::new();
+        image.set_directory(directory_entry::IMAGE_DIRECTORY_ENTRY_EXPORT, 0x2100, 0x80);
+        image.put_u32(TestImage::rdata(0x2100 + 12), 0x2170);
+        image.put_u32(TestImage::rdata(0x2100 + 20), 1);
+        image.put_u32(TestImage::rdata(0x2100 + 24), 1);
+        image.put_u32(TestImage::rdata(0x2100 + 28), 0x2140);
+        image.put_u32(TestImage::rdata(0x2100 + 32), 0x2148);
+        image.put_u32(TestImage::rdata(0x2100 + 36), 0x2150);
+        image.put_u32(TestImage::rdata(0x2140), 0x1000);
+        image.put_u32(TestImage::rdata(0x2148), 0x2160);
+        image.put_str(0x2160, b"Foo\0");
+        image.put_str(0x2170, b"K.DLL\0");
+        let report = image.verify();
+        assert!(report.is_loadable(), "{:?}", report.issues);
+        assert_eq!(report.exports, 1);
+
+        image.put_u16(TestImage::rdata(0x2150), 5);
+        assert!(has_error(&image.verify(), PeCheck::Exports, "ordinal past the address table"));
+        image.put_u16(TestImage::rdata(0x2150), 0);
+
+        // A function RVA inside the export directory is a forwarder
+        image.put_u32(TestImage::rdata(0x2140), 0x2160);
+        assert!(has_error(&image.verify(), PeCheck::Exports, "forwarder"));
+        image.put_str(0x2160, b"K.Foo\0");
+        assert!(image.verify().is_loadable());
+
+        image.put_u32(TestImage::rdata(0x2100 + 28), 0x8000);
+        assert!(has_error(&image.verify(), PeCheck::Exports, "address table"));
+    }
+
+    #[test]
+    fn test_issue_limit() {
+        let mut image = TestImage::new();
+        image.put_u16(0x46, 12);
+        for index in 0..12 {
+            image.set_section(index, b".dup", 0x1800, 0x100, 0x200, 0x200, TEXT);
+        }
+        let report = image.verify();
+        assert_eq!(report.issues.len(), MAX_ISSUES);
+        assert!(report.suppressed > 0);
+        assert!(!report.is_loadable());
+    }
+}
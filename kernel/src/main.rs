@@ -63,6 +63,7 @@
 
 // Subsystem modules
 pub mod arch;
+pub mod bootinfo;
 pub mod arb;
 pub mod cc;
 pub mod cm;
@@ -93,12 +94,14 @@ pub mod svc;
 pub mod verifier;
 pub mod wmi;
 pub mod kd;
+pub mod ktest;
 pub mod rpc;
 pub mod vdm;
 pub mod csr;
 pub mod win32k;
 pub mod subsys;
 
+mod bootscreen;
 mod framebuffer;
 mod serial;
 
@@ -106,7 +109,9 @@ use core::panic::PanicInfo;
 use core::sync::atomic::{AtomicPtr, Ordering};
 
 /// Boot information passed from the bootloader
-/// Must match the bootloader's BootInfo structure exactly!
+///
+/// Parsed from the loader's handoff by the `bootinfo` module; the handoff
+/// itself is a versioned tag list, not this structure.
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct BootInfo {
@@ -138,6 +143,33 @@ pub struct BootInfo {
     pub pml4_physical_addr: u64,
     /// ACPI RSDP address (if found)
     pub rsdp_addr: u64,
+    /// Number of GOP instances in the display table
+    pub display_count: u32,
+    /// BOOT_DISPLAY_* flags
+    pub display_flags: u32,
+    /// Address of the display table (`display_count` BootDisplay entries)
+    pub display_table_addr: u64,
+    /// Physical address of the initial ramdisk (0 if none)
+    pub initrd_addr: u64,
+    /// Initial ramdisk size in bytes
+    pub initrd_size: u64,
+    /// Virtual address of the EFI system table (0 if runtime services are unavailable)
+    pub efi_system_table: u64,
+    /// Virtual address of the EFI runtime services table
+    pub efi_runtime_services: u64,
+    /// EFI status of the loader's SetVirtualAddressMap call
+    pub efi_runtime_status: u64,
+    /// Physical address of the TCG event log copy (0 if none)
+    pub tpm_event_log_addr: u64,
+    pub tpm_event_log_size: u64,
+    /// Physical address of the EFI_TCG2_FINAL_EVENTS_TABLE
+    pub tpm_final_events_addr: u64,
+    /// bootinfo::TPM_EVENT_LOG_* flags
+    pub tpm_event_log_flags: u64,
+    /// Physical address of the SMBIOS entry point (0: scan the BIOS area)
+    pub smbios_entry_addr: u64,
+    /// 3 for a 64-bit entry point, 2 for a 32-bit one
+    pub smbios_entry_version: u64,
 }
 
 impl BootInfo {
@@ -201,25 +233,46 @@ static mut BOOT_INFO_COPY: BootInfo = BootInfo {
     kernel_size: 0,
     pml4_physical_addr: 0,
     rsdp_addr: 0,
+    display_count: 0,
+    display_flags: 0,
+    display_table_addr: 0,
+    initrd_addr: 0,
+    initrd_size: 0,
+    efi_system_table: 0,
+    efi_runtime_services: 0,
+    efi_runtime_status: 0,
+    tpm_event_log_addr: 0,
+    tpm_event_log_size: 0,
+    tpm_final_events_addr: 0,
+    tpm_event_log_flags: 0,
+    smbios_entry_addr: 0,
+    smbios_entry_version: 0,
 };
 
 /// Kernel entry point - called by bootloader
 ///
-/// The bootloader passes a pointer to BootInfo in RDI (System V ABI).
+/// The bootloader passes a pointer to its boot info handoff in RDI (System V ABI).
 /// This function performs Phase 0 initialization and then starts Phase 1.
 #[no_mangle]
-pub extern "C" fn kernel_main(boot_info_ptr: *const BootInfo) -> ! {
+pub extern "C" fn kernel_main(boot_info_ptr: *const u8) -> ! {
+    // Catch faults from here until Phase 0 loads the real IDT; without this
+    // an early crash is a silent reboot or hang.
+    unsafe { arch::early::install(); }
+
     // CRITICAL: Copy boot_info BEFORE zeroing .bss!
     // The bootloader may have placed boot_info in our .bss region.
-    // We must save it first, then zero .bss, then restore it.
-    let saved_boot_info = unsafe { core::ptr::read_volatile(boot_info_ptr) };
+    // We must save it first, then zero .bss, then parse it.
+    let raw_boot_info = unsafe { bootinfo::RawBootInfo::capture(boot_info_ptr) };
 
     // Zero .bss before anything else
     // This must happen before any statics (including Mutex) are used.
     unsafe { zero_bss(); }
 
-    // Now store the saved boot_info in our static (which is in .data, not .bss)
-    unsafe { BOOT_INFO_COPY = saved_boot_info; }
+    // Now parse the saved boot_info into our static (which is in .data, not .bss)
+    let parse_result = bootinfo::parse(&raw_boot_info);
+    if let Ok(parsed) = parse_result {
+        unsafe { BOOT_INFO_COPY = parsed; }
+    }
 
     // Absolute first thing: write directly to serial (no mutex, no formatting)
     serial::early_puts(b"K\n");  // Just output "K" to prove we're alive
@@ -233,21 +286,30 @@ pub extern "C" fn kernel_main(boot_info_ptr: *const BootInfo) -> ! {
     // Validate boot info
     let boot_info = unsafe { &BOOT_INFO_COPY };
     serial_println!("Boot info ptr: {:#x}", boot_info_ptr as u64);
-    serial_println!("Boot info magic: {:#x}", boot_info.magic);
 
-    if !boot_info.is_valid() {
-        serial_println!("FATAL: Invalid boot info magic!");
+    if let Err(e) = parse_result {
+        serial_println!("FATAL: Invalid boot info: {:?}", e);
         // Can't do much without valid boot info - just halt
         loop {
             arch::halt();
         }
     }
-    serial_println!("Boot info validated OK");
+    let protocol = bootinfo::boot_protocol_info();
+    serial_println!("Boot info validated OK (protocol v{}, {} tags, {} unknown)",
+        protocol.version, protocol.tag_count, protocol.unknown_tags);
+
+    // Copy the bootloader's display table before its memory is reused
+    hal::display::display_capture_boot_displays(
+        boot_info.display_table_addr,
+        boot_info.display_count,
+        boot_info.display_flags,
+    );
 
     // Initialize framebuffer for early output
     serial_println!("Initializing framebuffer...");
     framebuffer::init(boot_info);
     serial_println!("Framebuffer initialized");
+    bootscreen::init();
 
     // Print welcome message
     kprintln!("========================================");
@@ -280,6 +342,7 @@ pub extern "C" fn kernel_main(boot_info_ptr: *const BootInfo) -> ! {
     kprintln!("[SMP] Active CPUs: {}", ke::prcb::get_active_cpu_count());
     serial_println!("[SMP] Active CPUs: {}", ke::prcb::get_active_cpu_count());
 
+    bootscreen::finish();
     kprintln!("");
     kprintln!("Kernel initialization complete!");
     kprintln!("Entering idle loop...");
@@ -311,6 +374,18 @@ fn phase0_init(boot_info: &BootInfo) {
     if boot_info.rsdp_addr != 0 {
         kprintln!("    RSDP: {:#x}", boot_info.rsdp_addr);
     }
+    if boot_info.display_count > 1 {
+        kprintln!("    Displays: {}", boot_info.display_count);
+    }
+    if boot_info.initrd_size != 0 {
+        kprintln!("    Initrd: {} KB @ {:#x}",
+            boot_info.initrd_size / 1024,
+            boot_info.initrd_addr);
+    }
+    let cmdline = bootinfo::boot_cmdline();
+    if !cmdline.is_empty() {
+        kprintln!("    Command line: {}", cmdline);
+    }
 
     // Initialize architecture-specific components
     kprintln!("  Initializing GDT...");
@@ -339,6 +414,7 @@ fn phase1_init(boot_info: &BootInfo) {
     unsafe {
         mm::init(boot_info);
     }
+    framebuffer::enable_double_buffer();
     kprintln!("  Memory manager initialized");
 
     // Initialize ACPI (hardware discovery)
@@ -354,6 +430,38 @@ fn phase1_init(boot_info: &BootInfo) {
         kprintln!("  ACPI not available");
     }
 
+    // Take over UEFI runtime services (variables, ResetSystem)
+    hal::efi::init(boot_info);
+    if hal::efi::efi_runtime_available() {
+        kprintln!("  UEFI runtime services available");
+    }
+
+    // SMBIOS/DMI system description
+    hal::smbios::init(boot_info);
+
+    // TPM and the firmware's measured boot log
+    drivers::tpm::init(boot_info);
+    if let Some(info) = drivers::tpm::tpm_info() {
+        kprintln!("  TPM 2.0 ({}, {})", info.interface, info.manufacturer_str());
+    }
+
+    // Initialize IOMMU (DMA remapping)
+    hal::iommu::init();
+    if hal::iommu::iommu_is_enabled() {
+        kprintln!("  IOMMU enabled ({:?})", hal::iommu::iommu_get_stats().mode);
+    }
+
+    // Initialize HAL interrupt objects (drivers connect ISRs during storage init)
+    hal::interrupt::init();
+
+    // Detect the hypervisor (paravirtual clocks), then calibrate timers
+    hal::hv::init();
+    if hal::hv::hv_vendor() != hal::hv::HypervisorVendor::None {
+        kprintln!("  Hypervisor: {} (clock: {})",
+            hal::hv::hv_vendor().name(), hal::hv::hv_clock_source().name());
+    }
+    hal::timer::init();
+
     // Initialize RTC (real-time clock)
     kprintln!("  Initializing RTC...");
     hal::rtc::init();
@@ -369,6 +477,11 @@ fn phase1_init(boot_info: &BootInfo) {
     perf::init();
     kprintln!("  Performance monitoring initialized");
 
+    // Initialize WMI, before drivers register their data blocks
+    kprintln!("  Initializing WMI...");
+    wmi::wmi_initialize();
+    kprintln!("  WMI initialized");
+
     // Initialize resource arbiter subsystem
     kprintln!("  Initializing resource arbiter...");
     arb::init();
@@ -423,6 +536,7 @@ fn phase1_init(boot_info: &BootInfo) {
     unsafe {
         cm::init();
     }
+    hal::smbios::smbios_store_in_registry();
     kprintln!("  Configuration manager initialized");
 
     // Initialize File System
@@ -596,6 +710,39 @@ fn phase1_init(boot_info: &BootInfo) {
         }
     }
 
+    // Cooperative memory balloon (QEMU -device virtio-balloon)
+    if let Err(e) = drivers::virtio::balloon::init() {
+        serial_println!("[BALLOON] {}", e);
+    }
+
+    // Developer auto-run service (only when C:\AUTORUN exists)
+    ldr::autorun::start_at_boot();
+
+    // Disk health monitoring (ATA SMART)
+    io::smart::start_at_boot();
+
+    // Dispatcher deadlock detector
+    if let Err(e) = ke::deadlock::ke_start_deadlock_monitor() {
+        serial_println!("[KE] {}", e);
+    }
+
+    // Keep the shell, keyboard and console paths resident
+    mm::pin::mm_pin_init();
+
+    // Pool and heap fragmentation monitor
+    if let Err(e) = mm::defrag::mm_start_fragmentation_monitor() {
+        serial_println!("[MM] Fragmentation monitor: {}", e);
+    }
+
+    // Registry lazy flusher
+    cm::cm_start_lazy_flusher();
+
+    // Remote syslog sink (only when a collector is configured)
+    net::syslog::sink_start_at_boot();
+
+    // Unattended self-tests (only with ktest= on the command line)
+    ktest::ktest_start_at_boot();
+
     // Start the scheduler (enables interrupts)
     kprintln!("  Starting scheduler...");
     unsafe {
@@ -608,11 +755,18 @@ fn phase1_init(boot_info: &BootInfo) {
 fn shell_thread_entry() {
     serial_println!("[SHELL] Shell thread entry starting...");
 
-    // Wait a moment for other threads to start and print their messages
-    for _ in 0..500 {
-        unsafe { core::arch::asm!("pause"); }
+    // The shell runs in its own process in the interactive console session
+    unsafe {
+        let process = ps::ps_create_process(ps::get_system_process(), b"cmd.exe", 8);
+        if !process.is_null() {
+            (*process).session_id = ob::SESSION_CONSOLE;
+            ps::ps_attach_current_thread(process);
+        }
     }
 
+    // Wait a moment for other threads to start and print their messages
+    ex::delay::ex_sleep(10);
+
     serial_println!("[SHELL] Checking Win32k status...");
 
     // Check if graphical shell is enabled (Win32k initialized)
@@ -1914,7 +2068,8 @@ fn test_syscall() {
     };
     serial_println!("[SYSCALL-TEST] NtGetCurrentThreadId returned: {}", tid);
 
-    // Test NtDebugPrint (syscall 52)
+    // Test NtDebugPrint (syscall 52): the message lives in kernel .rodata,
+    // so the dispatcher must refuse it before the handler runs
     let msg = b"Hello from syscall!\n";
     let result = unsafe {
         extern "C" {
@@ -1925,7 +2080,12 @@ fn test_syscall() {
         }
         syscall_dispatcher(52, msg.as_ptr() as usize, msg.len(), 0, 0, 0, 0)
     };
-    serial_println!("[SYSCALL-TEST] NtDebugPrint returned: {}", result);
+    serial_println!("[SYSCALL-TEST] NtDebugPrint of a kernel buffer returned: {:#x}", result);
+    let kernel_buffer_rejected =
+        result == arch::x86_64::syscall::status::STATUS_ACCESS_VIOLATION;
+    if !kernel_buffer_rejected {
+        serial_println!("[SYSCALL-TEST] FAILED: kernel buffer accepted, expected STATUS_ACCESS_VIOLATION");
+    }
 
     // Test invalid syscall
     let invalid = unsafe {
@@ -1940,16 +2100,20 @@ fn test_syscall() {
     serial_println!("[SYSCALL-TEST] Invalid syscall 999 returned: {}", invalid);
 
     // Test suspend/resume syscalls
-    test_suspend_resume_syscalls();
+    let suspend_passed = test_suspend_resume_syscalls();
 
-    serial_println!("[SYSCALL-TEST] All syscall tests passed!");
+    if kernel_buffer_rejected && suspend_passed {
+        serial_println!("[SYSCALL-TEST] All syscall tests passed!");
+    } else {
+        serial_println!("[SYSCALL-TEST] Syscall tests FAILED");
+    }
 
     // Test user mode page tables and execution
     test_user_mode();
 }
 
-/// Test suspend/resume syscalls
-fn test_suspend_resume_syscalls() {
+/// Test suspend/resume syscalls; returns whether every check passed
+fn test_suspend_resume_syscalls() -> bool {
     serial_println!("[SUSPEND-TEST] Testing suspend/resume syscalls...");
 
     // Syscall numbers
@@ -2006,8 +2170,15 @@ fn test_suspend_resume_syscalls() {
     };
     serial_println!("[SUSPEND-TEST] NtResumeProcess(invalid) = {:#x}", result as u32);
 
-    // Test with valid process handle (system process, PID 0)
-    // Process handle base is 0x5000, so handle for PID 0 = 0x5000
+    // The System process (PID 4) must refuse suspension. NtOpenProcess
+    // cannot be used to get a handle from here: its CLIENT_ID and handle
+    // pointers would be on the kernel stack, which the dispatcher refuses.
+    const NT_OPEN_PROCESS: usize = 90;
+    let client_id = arch::x86_64::syscall::ClientIdForProcess {
+        unique_process: ke::process::SYSTEM_PROCESS_ID as u64,
+        unique_thread: 0,
+    };
+    let mut handle: usize = 0;
     let result = unsafe {
         extern "C" {
             fn syscall_dispatcher(
@@ -2015,23 +2186,73 @@ fn test_suspend_resume_syscalls() {
                 a4: usize, a5: usize, a6: usize,
             ) -> isize;
         }
-        syscall_dispatcher(NT_SUSPEND_PROCESS, 0x5000, 0, 0, 0, 0, 0)
+        syscall_dispatcher(
+            NT_OPEN_PROCESS,
+            &mut handle as *mut usize as usize,
+            0x0800, // PROCESS_SUSPEND_RESUME
+            0,
+            &client_id as *const _ as usize,
+            0, 0,
+        )
     };
-    serial_println!("[SUSPEND-TEST] NtSuspendProcess(system) = {:#x}", result as u32);
-
-    // Resume it
-    let result = unsafe {
-        extern "C" {
-            fn syscall_dispatcher(
-                num: usize, a1: usize, a2: usize, a3: usize,
-                a4: usize, a5: usize, a6: usize,
-            ) -> isize;
+    serial_println!("[SUSPEND-TEST] NtOpenProcess(kernel buffers) = {:#x} (expected 0xc0000005)", result as u32);
+    let mut passed = result == arch::x86_64::syscall::status::STATUS_ACCESS_VIOLATION;
+
+    // Name the System process by its 0x5000 + pid handle instead
+    use arch::x86_64::syscall::status::STATUS_ACCESS_DENIED;
+    match unsafe { arch::x86_64::syscall::kernel_process_handle(ke::process::SYSTEM_PROCESS_ID) } {
+        Some(handle) => {
+            let result = unsafe {
+                extern "C" {
+                    fn syscall_dispatcher(
+                        num: usize, a1: usize, a2: usize, a3: usize,
+                        a4: usize, a5: usize, a6: usize,
+                    ) -> isize;
+                }
+                syscall_dispatcher(NT_SUSPEND_PROCESS, handle, 0, 0, 0, 0, 0)
+            };
+            serial_println!("[SUSPEND-TEST] NtSuspendProcess(System) = {:#x} (expected 0xc0000022)", result as u32);
+            passed &= result == STATUS_ACCESS_DENIED;
         }
-        syscall_dispatcher(NT_RESUME_PROCESS, 0x5000, 0, 0, 0, 0, 0)
+        None => {
+            serial_println!("[SUSPEND-TEST] No handle for the System process");
+            passed = false;
+        }
+    }
+
+    // Likewise a System thread; this one is never started, so a broken
+    // guard cannot stall anything
+    fn never_runs(_: *mut u8) {}
+    let thread = unsafe { ps::ps_create_system_thread(never_runs, core::ptr::null_mut(), 8) };
+    let handle = if thread.is_null() {
+        None
+    } else {
+        unsafe { arch::x86_64::syscall::kernel_thread_handle((*thread).cid.unique_thread) }
     };
-    serial_println!("[SUSPEND-TEST] NtResumeProcess(system) = {:#x}", result as u32);
+    match handle {
+        Some(handle) => {
+            let result = unsafe {
+                extern "C" {
+                    fn syscall_dispatcher(
+                        num: usize, a1: usize, a2: usize, a3: usize,
+                        a4: usize, a5: usize, a6: usize,
+                    ) -> isize;
+                }
+                syscall_dispatcher(NT_SUSPEND_THREAD, handle, 0, 0, 0, 0, 0)
+            };
+            serial_println!("[SUSPEND-TEST] NtSuspendThread(System) = {:#x} (expected 0xc0000022)", result as u32);
+            passed &= result == STATUS_ACCESS_DENIED;
+            unsafe { arch::x86_64::syscall::kernel_close_thread_handle(handle) };
+        }
+        None => {
+            serial_println!("[SUSPEND-TEST] Cannot create a System thread to test against");
+            passed = false;
+        }
+    }
+    unsafe { ps::ps_delete_unstarted_thread(thread) };
 
-    serial_println!("[SUSPEND-TEST] Suspend/resume tests complete!");
+    serial_println!("[SUSPEND-TEST] Suspend/resume tests {}", if passed { "complete!" } else { "FAILED" });
+    passed
 }
 
 /// Test user mode page tables and execution
@@ -2085,6 +2306,7 @@ fn idle_loop() -> ! {
 /// Panic handler
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    bootscreen::show_messages();
     kprintln!("");
     kprintln!("!!! KERNEL PANIC !!!");
     kprintln!("{}", info);
@@ -2100,5 +2322,9 @@ fn panic(info: &PanicInfo) -> ! {
 /// Allocation error handler
 #[alloc_error_handler]
 fn alloc_error(layout: core::alloc::Layout) -> ! {
-    panic!("Allocation failed: {:?}", layout);
+    let heap = mm::mm_get_heap_stats();
+    panic!(
+        "Allocation failed: {:?} (kernel heap: {} of {} KB in use, largest free block {} bytes)",
+        layout, heap.bytes_in_use / 1024, heap.total_size / 1024, heap.largest_free_block
+    );
 }
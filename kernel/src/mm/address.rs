@@ -8,7 +8,8 @@
 //! # Address Space Layout (x86_64)
 //!
 //! ```text
-//! 0x0000_0000_0000_0000 - 0x0000_7FFF_FFFF_FFFF: User space (128TB)
+//! 0x0000_0000_0001_0000 - 0x0000_7FFF_FFFE_FFFF: User space (MmHighestUserAddress)
+//! 0x0000_7FFF_FFFF_0000 - 0x0000_7FFF_FFFF_FFFF: No-access guard (MmUserProbeAddress)
 //! 0x0000_8000_0000_0000 - 0xFFFF_7FFF_FFFF_FFFF: Non-canonical hole
 //! 0xFFFF_8000_0000_0000 - 0xFFFF_FFFF_FFFF_FFFF: Kernel space (128TB)
 //! ```
@@ -29,7 +30,22 @@ pub const MAX_ADDRESS_SPACES: usize = 64;
 /// User space start address
 pub const USER_SPACE_START: u64 = 0x0000_0000_0001_0000; // 64KB
 /// User space end address
-pub const USER_SPACE_END: u64 = 0x0000_7FFF_FFFF_FFFF;   // 128TB - 1
+pub const USER_SPACE_END: u64 = MM_HIGHEST_USER_ADDRESS;
+
+/// Highest address a user-mode caller can pass (MmHighestUserAddress)
+///
+/// Every pointer a system service takes from user mode must be at or
+/// below this address; anything above is the guard, the non-canonical
+/// hole or system space.
+pub const MM_HIGHEST_USER_ADDRESS: u64 = 0x0000_7FFF_FFFE_FFFF;
+/// First address past the user range (MmUserProbeAddress)
+///
+/// The 64KB from here to the non-canonical hole is never mapped, so a
+/// check of just the start of a small structure cannot be used to reach
+/// past the user range.
+pub const MM_USER_PROBE_ADDRESS: u64 = 0x0000_7FFF_FFFF_0000;
+/// Start of system space (MmSystemRangeStart)
+pub const MM_SYSTEM_RANGE_START: u64 = KERNEL_SPACE_START;
 /// Kernel space start address
 pub const KERNEL_SPACE_START: u64 = 0xFFFF_8000_0000_0000;
 /// Kernel space end address
@@ -682,8 +698,8 @@ pub unsafe fn mm_access_fault(
     // Hard fault - need to allocate a physical page
     aspace_ref.working_set.hard_fault_count.fetch_add(1, Ordering::Relaxed);
 
-    // Allocate a physical page
-    let pfn = match super::pfn::mm_allocate_zeroed_page() {
+    // Allocate a physical page, colored by virtual page number
+    let pfn = match super::pfn::mm_allocate_page_for_va(page_addr) {
         Some(p) => p,
         None => {
             // Try to trim working set to free pages
@@ -700,7 +716,7 @@ pub unsafe fn mm_access_fault(
                         aspace_ref.working_set.remove_page(victim_addr);
 
                         // Try allocation again
-                        match super::pfn::mm_allocate_zeroed_page() {
+                        match super::pfn::mm_allocate_page_for_va(page_addr) {
                             Some(p) => p,
                             None => return false, // Still no memory
                         }
@@ -979,8 +995,8 @@ pub unsafe fn mm_map_user_page(
         return None;
     }
 
-    // Allocate a physical page
-    let pfn = super::pfn::mm_allocate_zeroed_page()?;
+    // Allocate a physical page, colored by virtual page number
+    let pfn = super::pfn::mm_allocate_page_for_va(virt_addr)?;
     let phys_addr = (pfn * super::pfn::PAGE_SIZE) as u64;
 
     // Map the page in the process's address space
@@ -0,0 +1,259 @@
+//! Allocator Health and Compaction
+//!
+//! Fragmentation metrics for the executive pool and the kernel heap, a
+//! compaction pass over movable heap structures, and a background monitor
+//! that alerts when fragmentation crosses a threshold.
+//!
+//! # Metrics
+//! - **Pool**: size-class slabs cannot fragment externally, so the figure
+//!   is internal waste: the share of allocated bytes lost to rounding
+//!   requests up to their size class.
+//! - **Heap**: external fragmentation, the share of free bytes outside the
+//!   largest free block.
+//!
+//! # Compaction
+//! Heap blocks cannot be moved behind their owners' backs. Structures that
+//! keep their storage behind a lock are listed in `MOVABLE_STRUCTURES`;
+//! compaction asks each to release its spare capacity, which reallocates
+//! the storage into the lowest free block that fits. Nothing outside the
+//! owner's lock points into that storage, so the move is safe.
+//!
+//! # Monitor
+//! A green task samples both allocators every `MONITOR_INTERVAL_MS`. A
+//! crossing above a threshold logs a warning once and, for the heap, runs
+//! a compaction pass; falling back below logs an informational event.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use crate::ex::eventlog::{log_info_fmt, log_warning_fmt, memory_events, EventSource};
+use super::heap::mm_get_heap_stats;
+use super::pool::{mm_get_pool_class_count, mm_get_pool_class_stats, mm_get_pool_stats};
+
+/// Interval between monitor samples
+pub const MONITOR_INTERVAL_MS: u64 = 30 * 1000;
+
+/// Default pool fragmentation alert threshold (percent)
+pub const DEFAULT_POOL_THRESHOLD: u32 = 50;
+
+/// Default heap fragmentation alert threshold (percent)
+pub const DEFAULT_HEAP_THRESHOLD: u32 = 40;
+
+/// Pool block header size, not usable by the caller
+const POOL_HEADER_SIZE: usize = 16;
+
+/// A heap structure that can give back spare capacity
+pub struct MovableStructure {
+    pub name: &'static str,
+    /// Shrink the structure under its own lock; returns bytes released
+    pub compact: fn() -> usize,
+}
+
+/// Structures visited by a compaction pass
+pub static MOVABLE_STRUCTURES: &[MovableStructure] = &[
+    MovableStructure { name: "ob-symlinks", compact: crate::ob::symlink::ob_compact_symbolic_links },
+    MovableStructure { name: "vdm-pending-interrupts", compact: crate::vdm::interrupt::vdm_compact_pending_queue },
+    MovableStructure { name: "gdi-stretch-modes", compact: crate::win32k::gdi::dib::compact_stretch_modes },
+    MovableStructure { name: "gdi-icm-state", compact: crate::win32k::gdi::icm::compact_icm_state },
+];
+
+static POOL_THRESHOLD: AtomicU32 = AtomicU32::new(DEFAULT_POOL_THRESHOLD);
+static HEAP_THRESHOLD: AtomicU32 = AtomicU32::new(DEFAULT_HEAP_THRESHOLD);
+
+static MONITOR_RUNNING: AtomicBool = AtomicBool::new(false);
+static POOL_ALERT: AtomicBool = AtomicBool::new(false);
+static HEAP_ALERT: AtomicBool = AtomicBool::new(false);
+static SAMPLE_COUNT: AtomicU64 = AtomicU64::new(0);
+static ALERT_COUNT: AtomicU64 = AtomicU64::new(0);
+static COMPACTION_COUNT: AtomicU64 = AtomicU64::new(0);
+static BYTES_RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+// ============================================================================
+// Metrics
+// ============================================================================
+
+/// Fragmentation of both allocators
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FragmentationStats {
+    /// Largest pool request that can currently succeed
+    pub pool_largest_free: usize,
+    /// Percent of allocated pool bytes lost to size-class rounding
+    pub pool_fragmentation: u32,
+    /// Largest heap request that can currently succeed
+    pub heap_largest_free: usize,
+    /// Percent of free heap bytes outside the largest free block
+    pub heap_fragmentation: u32,
+    pub heap_free_blocks: usize,
+}
+
+fn percent(part: usize, whole: usize) -> u32 {
+    if whole == 0 { 0 } else { (part as u64 * 100 / whole as u64) as u32 }
+}
+
+/// Sample pool and heap fragmentation
+pub fn mm_get_fragmentation() -> FragmentationStats {
+    let pool = mm_get_pool_stats();
+    let pool_largest_free = (0..mm_get_pool_class_count())
+        .filter_map(mm_get_pool_class_stats)
+        .filter(|c| c.free_blocks > 0)
+        .map(|c| c.block_size - POOL_HEADER_SIZE)
+        .max()
+        .unwrap_or(0);
+
+    let heap = mm_get_heap_stats();
+
+    FragmentationStats {
+        pool_largest_free,
+        pool_fragmentation: percent(pool.bytes_allocated.saturating_sub(pool.bytes_requested), pool.bytes_allocated),
+        heap_largest_free: heap.largest_free_block,
+        heap_fragmentation: percent(heap.bytes_free - heap.largest_free_block, heap.bytes_free),
+        heap_free_blocks: heap.free_blocks,
+    }
+}
+
+/// Alert thresholds as (pool, heap) percentages
+pub fn mm_fragmentation_thresholds() -> (u32, u32) {
+    (POOL_THRESHOLD.load(Ordering::Relaxed), HEAP_THRESHOLD.load(Ordering::Relaxed))
+}
+
+/// Set the alert thresholds (percent, 1-100)
+pub fn mm_set_fragmentation_thresholds(pool: u32, heap: u32) -> bool {
+    if !(1..=100).contains(&pool) || !(1..=100).contains(&heap) {
+        return false;
+    }
+    POOL_THRESHOLD.store(pool, Ordering::Relaxed);
+    HEAP_THRESHOLD.store(heap, Ordering::Relaxed);
+    true
+}
+
+// ============================================================================
+// Compaction
+// ============================================================================
+
+/// Release a vector's spare capacity; returns the bytes given back
+pub fn mm_shrink_vec<T>(vec: &mut Vec<T>) -> usize {
+    let spare = vec.capacity() - vec.len();
+    vec.shrink_to_fit();
+    spare * core::mem::size_of::<T>()
+}
+
+/// Outcome of a compaction pass
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompactionResult {
+    pub structures: usize,
+    pub bytes_released: usize,
+    pub largest_free_before: usize,
+    pub largest_free_after: usize,
+    pub fragmentation_before: u32,
+    pub fragmentation_after: u32,
+}
+
+/// Compact every movable heap structure
+pub fn mm_compact_heap() -> CompactionResult {
+    let before = mm_get_fragmentation();
+
+    let mut bytes_released = 0;
+    for structure in MOVABLE_STRUCTURES {
+        bytes_released += (structure.compact)();
+    }
+
+    let after = mm_get_fragmentation();
+    COMPACTION_COUNT.fetch_add(1, Ordering::Relaxed);
+    BYTES_RELEASED.fetch_add(bytes_released, Ordering::Relaxed);
+
+    CompactionResult {
+        structures: MOVABLE_STRUCTURES.len(),
+        bytes_released,
+        largest_free_before: before.heap_largest_free,
+        largest_free_after: after.heap_largest_free,
+        fragmentation_before: before.heap_fragmentation,
+        fragmentation_after: after.heap_fragmentation,
+    }
+}
+
+// ============================================================================
+// Monitor
+// ============================================================================
+
+/// Monitor status
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefragStatus {
+    pub running: bool,
+    pub samples: u64,
+    pub alerts: u64,
+    pub compactions: u64,
+    pub bytes_released: usize,
+    pub pool_alert: bool,
+    pub heap_alert: bool,
+}
+
+/// Get the monitor status
+pub fn mm_get_defrag_status() -> DefragStatus {
+    DefragStatus {
+        running: MONITOR_RUNNING.load(Ordering::Acquire),
+        samples: SAMPLE_COUNT.load(Ordering::Relaxed),
+        alerts: ALERT_COUNT.load(Ordering::Relaxed),
+        compactions: COMPACTION_COUNT.load(Ordering::Relaxed),
+        bytes_released: BYTES_RELEASED.load(Ordering::Relaxed),
+        pool_alert: POOL_ALERT.load(Ordering::Relaxed),
+        heap_alert: HEAP_ALERT.load(Ordering::Relaxed),
+    }
+}
+
+/// Track one allocator's alert state; returns true on an upward crossing
+fn update_alert(alert: &AtomicBool, name: &str, value: u32, threshold: u32, largest: usize) -> bool {
+    let above = value >= threshold;
+    if alert.swap(above, Ordering::Relaxed) == above {
+        return false;
+    }
+
+    if above {
+        ALERT_COUNT.fetch_add(1, Ordering::Relaxed);
+        log_warning_fmt(EventSource::Memory, memory_events::FRAGMENTATION_HIGH, format!(
+            "{} fragmentation {}% reached the {}% threshold (largest free block {} bytes)",
+            name, value, threshold, largest));
+        crate::serial_println!("[MM] {} fragmentation {}% (threshold {}%)", name, value, threshold);
+    } else {
+        log_info_fmt(EventSource::Memory, memory_events::FRAGMENTATION_NORMAL, format!(
+            "{} fragmentation back to {}% (threshold {}%)", name, value, threshold));
+    }
+    above
+}
+
+/// Take one sample, alerting and compacting as needed
+pub fn mm_defrag_sample() {
+    SAMPLE_COUNT.fetch_add(1, Ordering::Relaxed);
+    let stats = mm_get_fragmentation();
+    let (pool_threshold, heap_threshold) = mm_fragmentation_thresholds();
+
+    update_alert(&POOL_ALERT, "Pool", stats.pool_fragmentation, pool_threshold, stats.pool_largest_free);
+    if update_alert(&HEAP_ALERT, "Heap", stats.heap_fragmentation, heap_threshold, stats.heap_largest_free) {
+        let result = mm_compact_heap();
+        log_info_fmt(EventSource::Memory, memory_events::HEAP_COMPACTED, format!(
+            "Heap compaction released {} bytes; fragmentation {}% -> {}%",
+            result.bytes_released, result.fragmentation_before, result.fragmentation_after));
+    }
+}
+
+/// Start the background fragmentation monitor
+pub fn mm_start_fragmentation_monitor() -> Result<(), &'static str> {
+    if MONITOR_RUNNING.swap(true, Ordering::AcqRel) {
+        return Err("Monitor already running");
+    }
+
+    let spawned = crate::ex::task::ex_spawn_task("mmdefrag", async {
+        loop {
+            crate::ex::task::ex_task_sleep(MONITOR_INTERVAL_MS).await;
+            mm_defrag_sample();
+        }
+    });
+    if let Err(e) = spawned {
+        MONITOR_RUNNING.store(false, Ordering::Release);
+        return Err(e);
+    }
+
+    crate::serial_println!("[MM] Fragmentation monitor started ({} s interval)", MONITOR_INTERVAL_MS / 1000);
+    Ok(())
+}
@@ -0,0 +1,267 @@
+//! Kernel Heap
+//!
+//! Backs the Rust global allocator (`alloc::vec::Vec`, `alloc::string::String`,
+//! `alloc::boxed::Box`, ...) with a dedicated arena, kept apart from the
+//! executive pools so that runaway collection growth cannot starve
+//! `ExAllocatePoolWithTag` callers, and vice versa.
+//!
+//! # Implementation
+//! A first-fit free list, sorted by address, over a static arena. Free
+//! blocks hold their size and the address of the next free block; freed
+//! blocks are merged with their neighbours. Allocations carry no header:
+//! `dealloc` receives the layout, which is rounded the same way again.
+//!
+//! # Exhaustion
+//! When no free block fits, the allocator returns null and counts the
+//! failure. Infallible collection methods then end in the allocation
+//! error handler; callers that can recover use `try_reserve` and friends,
+//! which see the null and return `Err` instead.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use crate::ke::SpinLock;
+
+/// Size of the heap arena (8MB)
+pub const KERNEL_HEAP_SIZE: usize = 8 * 1024 * 1024;
+
+/// Allocation granularity; also the size of a free block header
+const HEAP_GRANULE: usize = 16;
+
+/// Aligned heap arena
+#[repr(C, align(4096))]
+struct HeapArena {
+    data: [u8; KERNEL_HEAP_SIZE],
+}
+
+/// Heap arena storage
+static mut HEAP_ARENA: HeapArena = HeapArena { data: [0; KERNEL_HEAP_SIZE] };
+
+/// Header written at the start of every free block
+#[repr(C)]
+struct FreeBlock {
+    /// Size of this block in bytes (multiple of HEAP_GRANULE)
+    size: usize,
+    /// Address of the next free block, or 0
+    next: usize,
+}
+
+/// Heap state, protected by HEAP_LOCK
+struct HeapState {
+    /// The free list has been built
+    initialized: bool,
+    /// Address of the first free block, or 0
+    free_head: usize,
+    bytes_in_use: usize,
+    peak_in_use: usize,
+    allocation_count: u64,
+    free_count: u64,
+    failed_count: u64,
+    /// Size of the most recent failed request
+    last_failed_size: usize,
+}
+
+static HEAP_LOCK: SpinLock<HeapState> = SpinLock::new(HeapState {
+    initialized: false,
+    free_head: 0,
+    bytes_in_use: 0,
+    peak_in_use: 0,
+    allocation_count: 0,
+    free_count: 0,
+    failed_count: 0,
+    last_failed_size: 0,
+});
+
+/// Round a request up to the allocation granularity
+fn block_size(layout: &Layout) -> usize {
+    layout.size().max(1).div_ceil(HEAP_GRANULE) * HEAP_GRANULE
+}
+
+fn arena_base() -> usize {
+    unsafe { core::ptr::addr_of!(HEAP_ARENA.data) as usize }
+}
+
+impl HeapState {
+    /// Build the free list on first use, so allocations work before `mm::init`
+    unsafe fn ensure_initialized(&mut self) {
+        if !self.initialized {
+            let block = arena_base() as *mut FreeBlock;
+            (*block).size = KERNEL_HEAP_SIZE;
+            (*block).next = 0;
+            self.free_head = block as usize;
+            self.initialized = true;
+        }
+    }
+
+    /// Carve `size` bytes aligned to `align` out of the first block that fits
+    unsafe fn allocate(&mut self, size: usize, align: usize) -> *mut u8 {
+        self.ensure_initialized();
+
+        let mut prev: usize = 0;
+        let mut current = self.free_head;
+
+        while current != 0 {
+            let block = current as *mut FreeBlock;
+            let block_size = (*block).size;
+            let next = (*block).next;
+
+            let start = (current + align - 1) & !(align - 1);
+            let front = start - current;
+            if front + size <= block_size {
+                let back = block_size - front - size;
+
+                // Unlink, then give back the unused front and tail
+                let mut link = next;
+                if back > 0 {
+                    let tail = (start + size) as *mut FreeBlock;
+                    (*tail).size = back;
+                    (*tail).next = link;
+                    link = tail as usize;
+                }
+                if front > 0 {
+                    (*block).size = front;
+                    (*block).next = link;
+                    link = current;
+                }
+                if prev == 0 {
+                    self.free_head = link;
+                } else {
+                    (*(prev as *mut FreeBlock)).next = link;
+                }
+
+                self.bytes_in_use += size;
+                self.peak_in_use = self.peak_in_use.max(self.bytes_in_use);
+                self.allocation_count += 1;
+                return start as *mut u8;
+            }
+
+            prev = current;
+            current = next;
+        }
+
+        self.failed_count += 1;
+        self.last_failed_size = size;
+        ptr::null_mut()
+    }
+
+    /// Return a block to the free list, merging it with its neighbours
+    unsafe fn free(&mut self, addr: usize, size: usize) {
+        let mut prev: usize = 0;
+        let mut next = self.free_head;
+        while next != 0 && next < addr {
+            prev = next;
+            next = (*(next as *mut FreeBlock)).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        (*block).size = size;
+        (*block).next = next;
+
+        if next != 0 && addr + size == next {
+            let following = next as *mut FreeBlock;
+            (*block).size += (*following).size;
+            (*block).next = (*following).next;
+        }
+
+        if prev == 0 {
+            self.free_head = addr;
+        } else {
+            let preceding = prev as *mut FreeBlock;
+            if prev + (*preceding).size == addr {
+                (*preceding).size += (*block).size;
+                (*preceding).next = (*block).next;
+            } else {
+                (*preceding).next = addr;
+            }
+        }
+
+        self.bytes_in_use -= size;
+        self.free_count += 1;
+    }
+}
+
+// ============================================================================
+// Global Allocator
+// ============================================================================
+
+struct KernelHeapAllocator;
+
+unsafe impl GlobalAlloc for KernelHeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = block_size(&layout);
+        let align = layout.align().max(HEAP_GRANULE);
+        HEAP_LOCK.lock().allocate(size, align)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let addr = ptr as usize;
+        let base = arena_base();
+        if addr < base || addr >= base + KERNEL_HEAP_SIZE {
+            crate::serial_println!("[MM] Heap free of foreign pointer {:#x}", addr);
+            return;
+        }
+        HEAP_LOCK.lock().free(addr, block_size(&layout));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelHeapAllocator = KernelHeapAllocator;
+
+// ============================================================================
+// Heap Statistics
+// ============================================================================
+
+/// Kernel heap statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    pub total_size: usize,
+    pub bytes_in_use: usize,
+    pub bytes_free: usize,
+    pub peak_in_use: usize,
+    pub allocation_count: u64,
+    pub free_count: u64,
+    pub failed_count: u64,
+    pub last_failed_size: usize,
+    /// Number of free blocks
+    pub free_blocks: usize,
+    /// Largest single allocation that can currently succeed
+    pub largest_free_block: usize,
+}
+
+/// Get kernel heap statistics
+pub fn mm_get_heap_stats() -> HeapStats {
+    let mut state = HEAP_LOCK.lock();
+    unsafe { state.ensure_initialized(); }
+
+    let mut free_blocks = 0;
+    let mut largest_free_block = 0;
+    let mut current = state.free_head;
+    while current != 0 {
+        let block = unsafe { &*(current as *const FreeBlock) };
+        free_blocks += 1;
+        largest_free_block = largest_free_block.max(block.size);
+        current = block.next;
+    }
+
+    HeapStats {
+        total_size: KERNEL_HEAP_SIZE,
+        bytes_in_use: state.bytes_in_use,
+        bytes_free: KERNEL_HEAP_SIZE - state.bytes_in_use,
+        peak_in_use: state.peak_in_use,
+        allocation_count: state.allocation_count,
+        free_count: state.free_count,
+        failed_count: state.failed_count,
+        last_failed_size: state.last_failed_size,
+        free_blocks,
+        largest_free_block,
+    }
+}
+
+/// Initialize the kernel heap
+pub fn init() {
+    let stats = mm_get_heap_stats();
+    crate::serial_println!(
+        "[MM] Kernel heap initialized ({} KB arena, {} KB in use)",
+        stats.total_size / 1024,
+        stats.bytes_in_use / 1024
+    );
+}
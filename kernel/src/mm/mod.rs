@@ -8,10 +8,13 @@
 //! - **Working Sets**: Pages currently in memory per process
 //! - **Section Objects**: Shared memory and file mapping
 //! - **Pool Allocator**: Paged and NonPaged pools
+//! - **Kernel Heap**: Arena behind the Rust global allocator
+//! - **Defrag**: Pool/heap fragmentation metrics, compaction and alerts
+//! - **Pinning**: Hot paths and configured images kept resident
 //!
 //! # Address Space Layout (x86_64)
 //!
-//! - User space: 0x0000_0000_0000_0000 - 0x0000_7FFF_FFFF_FFFF
+//! - User space: 0x0000_0000_0001_0000 - 0x0000_7FFF_FFFE_FFFF (`MM_HIGHEST_USER_ADDRESS`)
 //! - Kernel space: 0xFFFF_8000_0000_0000 - 0xFFFF_FFFF_FFFF_FFFF
 //!
 //! # Key Structures
@@ -25,6 +28,8 @@ pub mod pfn;
 pub mod pte;
 pub mod vad;
 pub mod pool;
+pub mod heap;
+pub mod defrag;
 pub mod address;
 pub mod physical;
 pub mod user;
@@ -35,6 +40,7 @@ pub mod ws;
 pub mod wrtwatch;
 pub mod awe;
 pub mod lockvm;
+pub mod pin;
 
 // Re-export PFN types
 pub use pfn::{
@@ -46,13 +52,28 @@ pub use pfn::{
     LARGE_PAGE_SIZE,
     LARGE_PAGE_SHIFT,
     pfn_flags,
+    MM_MAXIMUM_COLORS,
+    PageColorStats,
     mm_allocate_page,
+    mm_allocate_page_by_color,
+    mm_allocate_page_for_va,
     mm_allocate_zeroed_page,
     mm_free_page,
+    mm_allocate_contiguous_run,
     mm_pfn_entry,
     mm_get_stats,
     mm_init_pfn_database,
+    mm_init_pfn_ranges,
     mm_init_pfn_simple,
+    mm_set_page_colors,
+    mm_get_page_colors,
+    mm_get_page_color_stats,
+    MemoryCondition,
+    mm_low_memory_event,
+    mm_high_memory_event,
+    mm_memory_condition,
+    mm_memory_thresholds,
+    mm_low_memory_transitions,
 };
 
 // Re-export PTE types
@@ -124,6 +145,30 @@ pub use pool::{
     mm_get_pool_free_count,
 };
 
+// Re-export kernel heap types
+pub use heap::{
+    HeapStats,
+    mm_get_heap_stats,
+};
+
+// Re-export allocator health types
+pub use defrag::{
+    FragmentationStats,
+    CompactionResult,
+    mm_get_fragmentation,
+    mm_compact_heap,
+};
+
+// Re-export pinning types
+pub use pin::{
+    PinnedRange,
+    PinSource,
+    mm_pin_range,
+    mm_unpin,
+    mm_is_address_pinned,
+    mm_pinned_ranges,
+};
+
 // Re-export address space types
 pub use address::{
     MmAddressSpace,
@@ -134,6 +179,9 @@ pub use address::{
     MAX_ADDRESS_SPACES,
     USER_SPACE_START,
     USER_SPACE_END,
+    MM_HIGHEST_USER_ADDRESS,
+    MM_USER_PROBE_ADDRESS,
+    MM_SYSTEM_RANGE_START,
     KERNEL_SPACE_START,
     KERNEL_SPACE_END,
     DEFAULT_STACK_SIZE,
@@ -167,6 +215,13 @@ pub use physical::{
     mm_free_physical_page,
     mm_alloc_contiguous_pages,
     mm_free_contiguous_pages,
+    MmContiguousAllocation,
+    MAX_CONTIGUOUS_ALLOCATIONS,
+    mm_allocate_contiguous_memory,
+    mm_allocate_contiguous_memory_specify_cache,
+    mm_allocate_contiguous_memory_aligned,
+    mm_free_contiguous_memory,
+    mm_get_contiguous_allocations,
     mm_get_physical_stats,
     mm_get_region_count,
     mm_get_region,
@@ -175,6 +230,8 @@ pub use physical::{
     mm_alloc_large_page,
     mm_free_large_page,
     mm_parse_memory_map,
+    MemoryMapSanitation,
+    mm_get_memory_map_sanitation,
     mm_lock_pages,
     mm_unlock_pages as mm_unlock_physical_pages,
 };
@@ -358,17 +415,26 @@ pub use lockvm::{
 /// Initialize the Memory Manager
 ///
 /// This initializes all memory management subsystems:
-/// 1. PFN database
-/// 2. PTE subsystem
-/// 3. VAD subsystem
-/// 4. Pool allocator
-/// 5. Address space management
-/// 6. Physical memory management
+/// 1. Physical memory management (parses and sanitizes the memory map)
+/// 2. PFN database
+/// 3. PTE subsystem
+/// 4. VAD subsystem
+/// 5. Pool allocator
+/// 6. Address space management
 pub unsafe fn init(boot_info: &crate::BootInfo) {
     crate::serial_println!("[MM] Initializing Memory Manager...");
 
-    // Initialize PFN database
-    pfn::init();
+    // Initialize physical memory management (parses memory map, reclaims
+    // boot services memory, reserves runtime services regions)
+    physical::init(boot_info);
+
+    // Initialize PFN database from the sanitized map
+    if physical::mm_populate_pfn_database() == 0 {
+        pfn::init();
+    }
+
+    // Bin free pages by cache color
+    pfn::mm_configure_page_colors();
 
     // Initialize PTE subsystem
     pte::init();
@@ -379,12 +445,12 @@ pub unsafe fn init(boot_info: &crate::BootInfo) {
     // Initialize pool allocator
     pool::init();
 
+    // Initialize kernel heap
+    heap::init();
+
     // Initialize address space management
     address::init();
 
-    // Initialize physical memory management (parses memory map)
-    physical::init(boot_info);
-
     // Initialize section subsystem
     section::init();
 
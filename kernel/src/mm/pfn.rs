@@ -17,8 +17,8 @@
 //! - Bad: Hardware error, unusable
 
 use core::ptr;
-use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use crate::ke::SpinLock;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use crate::ke::{EventType, KEvent, SpinLock};
 
 /// Page size (4KB)
 pub const PAGE_SIZE: usize = 4096;
@@ -188,38 +188,79 @@ static ZEROED_PAGES: AtomicU32 = AtomicU32::new(0);
 /// Number of active pages
 static ACTIVE_PAGES: AtomicU32 = AtomicU32::new(0);
 
-/// Free page list head (index into PFN database)
-static mut FREE_LIST_HEAD: u32 = u32::MAX;
+/// Maximum number of page colors supported by the free lists
+///
+/// Physical pages are binned by the cache sets they map to, so that
+/// consecutive virtual pages can be backed by pages that don't compete for
+/// the same L2 sets (NT: MmSecondaryColors).
+pub const MM_MAXIMUM_COLORS: usize = 64;
+
+/// Default color count used until cache geometry is known
+pub const MM_DEFAULT_COLORS: usize = 16;
+
+/// Number of page colors currently in use (power of two)
+static PAGE_COLORS: AtomicU32 = AtomicU32::new(MM_DEFAULT_COLORS as u32);
+
+/// Next color handed out to allocations without a color preference
+static NEXT_PAGE_COLOR: AtomicU32 = AtomicU32::new(0);
+
+/// Free page list heads, one per color (index into PFN database)
+static mut FREE_LIST_HEADS: [u32; MM_MAXIMUM_COLORS] = [u32::MAX; MM_MAXIMUM_COLORS];
+
+/// Zeroed page list heads, one per color
+static mut ZEROED_LIST_HEADS: [u32; MM_MAXIMUM_COLORS] = [u32::MAX; MM_MAXIMUM_COLORS];
 
-/// Zeroed page list head
-static mut ZEROED_LIST_HEAD: u32 = u32::MAX;
+/// Free + zeroed pages available per color
+static mut PAGES_BY_COLOR: [u32; MM_MAXIMUM_COLORS] = [0; MM_MAXIMUM_COLORS];
+
+/// Allocations satisfied with the requested color
+static COLOR_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// Allocations that had to fall back to a different color
+static COLOR_MISSES: AtomicU64 = AtomicU64::new(0);
 
 // ============================================================================
 // Page List Operations
 // ============================================================================
 
-/// Insert a page at the head of the free list
+/// Get the color mask for the current color count
+#[inline]
+fn color_mask() -> usize {
+    PAGE_COLORS.load(Ordering::Relaxed) as usize - 1
+}
+
+/// Get the cache color of a physical page
+#[inline]
+pub fn mi_page_color(pfn_index: usize) -> usize {
+    pfn_index & color_mask()
+}
+
+/// Insert a page at the head of its color's free list
 unsafe fn insert_free_page(pfn_index: u32) {
+    let color = mi_page_color(pfn_index as usize);
     let pfn = &mut PFN_DATABASE[pfn_index as usize];
     pfn.state = MmPageState::Free;
-    pfn.flink = FREE_LIST_HEAD;
+    pfn.color = color as u8;
+    pfn.flink = FREE_LIST_HEADS[color];
     pfn.blink = u32::MAX;
 
-    if FREE_LIST_HEAD != u32::MAX {
-        PFN_DATABASE[FREE_LIST_HEAD as usize].blink = pfn_index;
+    if FREE_LIST_HEADS[color] != u32::MAX {
+        PFN_DATABASE[FREE_LIST_HEADS[color] as usize].blink = pfn_index;
     }
-    FREE_LIST_HEAD = pfn_index;
+    FREE_LIST_HEADS[color] = pfn_index;
+    PAGES_BY_COLOR[color] += 1;
     FREE_PAGES.fetch_add(1, Ordering::SeqCst);
 }
 
-/// Remove a page from the free list
+/// Remove a page from its color's free list
 unsafe fn remove_free_page(pfn_index: u32) {
     let pfn = &mut PFN_DATABASE[pfn_index as usize];
+    let color = pfn.color as usize;
 
     if pfn.blink != u32::MAX {
         PFN_DATABASE[pfn.blink as usize].flink = pfn.flink;
     } else {
-        FREE_LIST_HEAD = pfn.flink;
+        FREE_LIST_HEADS[color] = pfn.flink;
     }
 
     if pfn.flink != u32::MAX {
@@ -228,31 +269,36 @@ unsafe fn remove_free_page(pfn_index: u32) {
 
     pfn.flink = u32::MAX;
     pfn.blink = u32::MAX;
+    PAGES_BY_COLOR[color] -= 1;
     FREE_PAGES.fetch_sub(1, Ordering::SeqCst);
 }
 
-/// Insert a page at the head of the zeroed list
+/// Insert a page at the head of its color's zeroed list
 unsafe fn insert_zeroed_page(pfn_index: u32) {
+    let color = mi_page_color(pfn_index as usize);
     let pfn = &mut PFN_DATABASE[pfn_index as usize];
     pfn.state = MmPageState::Zeroed;
-    pfn.flink = ZEROED_LIST_HEAD;
+    pfn.color = color as u8;
+    pfn.flink = ZEROED_LIST_HEADS[color];
     pfn.blink = u32::MAX;
 
-    if ZEROED_LIST_HEAD != u32::MAX {
-        PFN_DATABASE[ZEROED_LIST_HEAD as usize].blink = pfn_index;
+    if ZEROED_LIST_HEADS[color] != u32::MAX {
+        PFN_DATABASE[ZEROED_LIST_HEADS[color] as usize].blink = pfn_index;
     }
-    ZEROED_LIST_HEAD = pfn_index;
+    ZEROED_LIST_HEADS[color] = pfn_index;
+    PAGES_BY_COLOR[color] += 1;
     ZEROED_PAGES.fetch_add(1, Ordering::SeqCst);
 }
 
-/// Remove a page from the zeroed list
+/// Remove a page from its color's zeroed list
 unsafe fn remove_zeroed_page(pfn_index: u32) {
     let pfn = &mut PFN_DATABASE[pfn_index as usize];
+    let color = pfn.color as usize;
 
     if pfn.blink != u32::MAX {
         PFN_DATABASE[pfn.blink as usize].flink = pfn.flink;
     } else {
-        ZEROED_LIST_HEAD = pfn.flink;
+        ZEROED_LIST_HEADS[color] = pfn.flink;
     }
 
     if pfn.flink != u32::MAX {
@@ -261,9 +307,29 @@ unsafe fn remove_zeroed_page(pfn_index: u32) {
 
     pfn.flink = u32::MAX;
     pfn.blink = u32::MAX;
+    PAGES_BY_COLOR[color] -= 1;
     ZEROED_PAGES.fetch_sub(1, Ordering::SeqCst);
 }
 
+/// Take a page of exactly the given color, preferring zeroed pages
+///
+/// Returns the page index and whether it still needs to be zeroed.
+unsafe fn take_page_of_color(color: usize) -> Option<(usize, bool)> {
+    if ZEROED_LIST_HEADS[color] != u32::MAX {
+        let pfn_index = ZEROED_LIST_HEADS[color];
+        remove_zeroed_page(pfn_index);
+        return Some((pfn_index as usize, false));
+    }
+
+    if FREE_LIST_HEADS[color] != u32::MAX {
+        let pfn_index = FREE_LIST_HEADS[color];
+        remove_free_page(pfn_index);
+        return Some((pfn_index as usize, true));
+    }
+
+    None
+}
+
 // ============================================================================
 // Public Interface
 // ============================================================================
@@ -277,41 +343,169 @@ pub unsafe fn mm_pfn_entry(pfn_index: usize) -> Option<&'static mut MmPfn> {
     }
 }
 
+/// Allocate a physical page of a preferred cache color
+///
+/// Searches the requested color first, then the remaining colors in
+/// order, so the allocation only fails when no free page exists at all.
+/// Returns the physical page number, or None if no pages available.
+pub unsafe fn mm_allocate_page_by_color(color: usize) -> Option<usize> {
+    let page = mi_allocate_page_by_color(color);
+    mi_update_memory_condition();
+    page
+}
+
+unsafe fn mi_allocate_page_by_color(color: usize) -> Option<usize> {
+    let _guard = PFN_LOCK.lock();
+
+    let colors = PAGE_COLORS.load(Ordering::Relaxed) as usize;
+    let wanted = color & (colors - 1);
+
+    for i in 0..colors {
+        let c = (wanted + i) & (colors - 1);
+        if let Some((pfn_index, needs_zero)) = take_page_of_color(c) {
+            if i == 0 {
+                COLOR_HITS.fetch_add(1, Ordering::Relaxed);
+            } else {
+                COLOR_MISSES.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let pfn = &mut PFN_DATABASE[pfn_index];
+            pfn.state = MmPageState::Active;
+            pfn.reference_count.store(1, Ordering::SeqCst);
+            ACTIVE_PAGES.fetch_add(1, Ordering::SeqCst);
+
+            if needs_zero {
+                // Zero the page
+                let page_addr = pfn_index * PAGE_SIZE;
+                let page_ptr = page_addr as *mut u8;
+                core::ptr::write_bytes(page_ptr, 0, PAGE_SIZE);
+            }
+
+            return Some(pfn_index);
+        }
+    }
+
+    None
+}
+
+/// Allocate a physical page backing a virtual address
+///
+/// The color is derived from the virtual page number, so a virtually
+/// contiguous buffer is spread evenly across the cache.
+pub unsafe fn mm_allocate_page_for_va(virtual_address: u64) -> Option<usize> {
+    mm_allocate_page_by_color((virtual_address >> PAGE_SHIFT) as usize)
+}
+
 /// Allocate a physical page
 ///
+/// Colors are handed out round-robin so unrelated allocations don't pile
+/// up on the same cache sets.
 /// Returns the physical page number, or None if no pages available.
 pub unsafe fn mm_allocate_page() -> Option<usize> {
+    let color = NEXT_PAGE_COLOR.fetch_add(1, Ordering::Relaxed) as usize;
+    mm_allocate_page_by_color(color)
+}
+
+/// Allocate a run of physically contiguous pages
+///
+/// Scans the PFN database for `page_count` consecutive free pages that
+/// satisfy these constraints:
+/// - the whole run lies within `lowest_pfn..=highest_pfn`
+/// - the first page is a multiple of `alignment_pages` (0 or 1 = any)
+/// - the run does not cross a multiple of `boundary_pages` (0 = none)
+///
+/// The search runs from the top of the acceptable range downward so low
+/// memory stays available for callers with tighter address limits.
+/// Pages are returned active with a reference count of one, zeroed.
+pub unsafe fn mm_allocate_contiguous_run(
+    page_count: usize,
+    lowest_pfn: usize,
+    highest_pfn: usize,
+    alignment_pages: usize,
+    boundary_pages: usize,
+) -> Option<usize> {
+    let start = mi_allocate_contiguous_run(page_count, lowest_pfn, highest_pfn, alignment_pages, boundary_pages);
+    mi_update_memory_condition();
+    start
+}
+
+unsafe fn mi_allocate_contiguous_run(
+    page_count: usize,
+    lowest_pfn: usize,
+    highest_pfn: usize,
+    alignment_pages: usize,
+    boundary_pages: usize,
+) -> Option<usize> {
+    if page_count == 0 {
+        return None;
+    }
+
     let _guard = PFN_LOCK.lock();
 
-    // Try zeroed list first
-    if ZEROED_LIST_HEAD != u32::MAX {
-        let pfn_index = ZEROED_LIST_HEAD as usize;
-        remove_zeroed_page(pfn_index as u32);
-        let pfn = &mut PFN_DATABASE[pfn_index];
-        pfn.state = MmPageState::Active;
-        pfn.reference_count.store(1, Ordering::SeqCst);
-        ACTIVE_PAGES.fetch_add(1, Ordering::SeqCst);
-        return Some(pfn_index);
+    let limit = highest_pfn.min(PFN_DATABASE.len() - 1);
+    if limit < lowest_pfn || limit - lowest_pfn + 1 < page_count {
+        return None;
+    }
+    if boundary_pages != 0 && page_count > boundary_pages {
+        return None;
     }
 
-    // Try free list
-    if FREE_LIST_HEAD != u32::MAX {
-        let pfn_index = FREE_LIST_HEAD as usize;
-        remove_free_page(pfn_index as u32);
-        let pfn = &mut PFN_DATABASE[pfn_index];
+    let alignment = alignment_pages.max(1);
+    let mut start = (limit + 1 - page_count) / alignment * alignment;
+
+    loop {
+        if start < lowest_pfn {
+            return None;
+        }
+
+        let end = start + page_count - 1;
+        let crosses = boundary_pages != 0 && start / boundary_pages != end / boundary_pages;
+
+        if !crosses {
+            // Find the highest page in the run that is not free
+            let mut blocker = None;
+            for index in (start..=end).rev() {
+                if !PFN_DATABASE[index].is_free() {
+                    blocker = Some(index);
+                    break;
+                }
+            }
+
+            match blocker {
+                None => break,
+                Some(index) => {
+                    // Any run overlapping the blocker is unusable
+                    if index < page_count {
+                        return None;
+                    }
+                    start = (index - page_count) / alignment * alignment;
+                    continue;
+                }
+            }
+        }
+
+        if start < alignment {
+            return None;
+        }
+        start -= alignment;
+    }
+
+    for index in start..start + page_count {
+        let needs_zero = PFN_DATABASE[index].state == MmPageState::Free;
+        if needs_zero {
+            remove_free_page(index as u32);
+            core::ptr::write_bytes((index * PAGE_SIZE) as *mut u8, 0, PAGE_SIZE);
+        } else {
+            remove_zeroed_page(index as u32);
+        }
+
+        let pfn = &mut PFN_DATABASE[index];
         pfn.state = MmPageState::Active;
         pfn.reference_count.store(1, Ordering::SeqCst);
         ACTIVE_PAGES.fetch_add(1, Ordering::SeqCst);
-
-        // Zero the page
-        let page_addr = pfn_index * PAGE_SIZE;
-        let page_ptr = page_addr as *mut u8;
-        core::ptr::write_bytes(page_ptr, 0, PAGE_SIZE);
-
-        return Some(pfn_index);
     }
 
-    None
+    Some(start)
 }
 
 /// Allocate a zeroed physical page
@@ -321,6 +515,11 @@ pub unsafe fn mm_allocate_zeroed_page() -> Option<usize> {
 
 /// Free a physical page
 pub unsafe fn mm_free_page(pfn_index: usize) {
+    mi_free_page(pfn_index);
+    mi_update_memory_condition();
+}
+
+unsafe fn mi_free_page(pfn_index: usize) {
     if pfn_index >= PFN_DATABASE.len() {
         return;
     }
@@ -447,12 +646,40 @@ pub unsafe fn mm_init_pfn_database(
     }
 
     TOTAL_PAGES = pages_added;
+    mi_init_memory_condition();
 
     crate::serial_println!("[MM] PFN database initialized");
     crate::serial_println!("[MM]   {} pages ({} MB) available",
         pages_added, (pages_added * PAGE_SIZE) / (1024 * 1024));
 }
 
+/// Initialize the PFN database from sanitized free ranges
+///
+/// `ranges` yields (first page, page count) pairs that are known to be
+/// free; the physical memory manager builds them from the memory map.
+/// Returns the number of pages added.
+pub unsafe fn mm_init_pfn_ranges(ranges: impl Iterator<Item = (usize, usize)>) -> usize {
+    let mut pages_added = 0usize;
+
+    for (start_page, num_pages) in ranges {
+        // Skip first 1MB for safety (real-mode structures, AP trampoline)
+        let first = start_page.max(256);
+        let last = (start_page + num_pages).min(PFN_DATABASE.len());
+        for page in first..last {
+            insert_free_page(page as u32);
+            pages_added += 1;
+        }
+    }
+
+    TOTAL_PAGES = pages_added;
+    mi_init_memory_condition();
+
+    crate::serial_println!("[MM] PFN database initialized from memory map");
+    crate::serial_println!("[MM]   {} pages ({} KB) available",
+        pages_added, (pages_added * PAGE_SIZE) / 1024);
+    pages_added
+}
+
 /// Simple initialization for testing (marks some pages as free)
 pub unsafe fn mm_init_pfn_simple(start_page: usize, num_pages: usize) {
     let end_page = (start_page + num_pages).min(PFN_DATABASE.len());
@@ -462,16 +689,239 @@ pub unsafe fn mm_init_pfn_simple(start_page: usize, num_pages: usize) {
     }
 
     TOTAL_PAGES = end_page - start_page;
+    mi_init_memory_condition();
 
     crate::serial_println!("[MM] PFN database initialized (simple)");
     crate::serial_println!("[MM]   {} pages ({} KB) available",
         TOTAL_PAGES, (TOTAL_PAGES * PAGE_SIZE) / 1024);
 }
 
-/// Initialize PFN subsystem
+/// Set the number of page colors and rebuild the free lists
+///
+/// The count is rounded down to a power of two and clamped to
+/// `MM_MAXIMUM_COLORS`. Pages already on the free/zeroed lists are
+/// re-binned under the new color mask.
+pub fn mm_set_page_colors(count: usize) {
+    let mut colors = count.clamp(1, MM_MAXIMUM_COLORS);
+    colors = 1 << (usize::BITS - 1 - colors.leading_zeros());
+
+    let _guard = PFN_LOCK.lock();
+
+    unsafe {
+        // Unlink everything, then reinsert with the new mask
+        let mut free_pages = [u32::MAX; MM_MAXIMUM_COLORS];
+        let mut zeroed_pages = [u32::MAX; MM_MAXIMUM_COLORS];
+        free_pages.copy_from_slice(&FREE_LIST_HEADS);
+        zeroed_pages.copy_from_slice(&ZEROED_LIST_HEADS);
+
+        FREE_LIST_HEADS = [u32::MAX; MM_MAXIMUM_COLORS];
+        ZEROED_LIST_HEADS = [u32::MAX; MM_MAXIMUM_COLORS];
+        PAGES_BY_COLOR = [0; MM_MAXIMUM_COLORS];
+        FREE_PAGES.store(0, Ordering::SeqCst);
+        ZEROED_PAGES.store(0, Ordering::SeqCst);
+
+        PAGE_COLORS.store(colors as u32, Ordering::SeqCst);
+
+        for head in free_pages.iter() {
+            let mut index = *head;
+            while index != u32::MAX {
+                let next = PFN_DATABASE[index as usize].flink;
+                insert_free_page(index);
+                index = next;
+            }
+        }
+        for head in zeroed_pages.iter() {
+            let mut index = *head;
+            while index != u32::MAX {
+                let next = PFN_DATABASE[index as usize].flink;
+                insert_zeroed_page(index);
+                index = next;
+            }
+        }
+    }
+}
+
+/// Derive the page color count from the L2 cache geometry
+///
+/// One color per page-sized slice of a cache way: pages whose index
+/// differs by a multiple of the color count compete for the same sets.
+pub fn mm_configure_page_colors() {
+    if !crate::hal::cache::cache_is_initialized() {
+        crate::hal::cache::init();
+    }
+
+    let colors = match crate::hal::cache::cache_get_info(2) {
+        Some(info) if info.ways > 0 && !info.fully_assoc => {
+            let way_size = info.size as usize / info.ways as usize;
+            (way_size / PAGE_SIZE).max(1)
+        }
+        _ => MM_DEFAULT_COLORS,
+    };
+
+    mm_set_page_colors(colors);
+
+    crate::serial_println!("[MM] Page coloring: {} colors", mm_get_page_colors());
+}
+
+/// Get the number of page colors in use
+pub fn mm_get_page_colors() -> usize {
+    PAGE_COLORS.load(Ordering::Relaxed) as usize
+}
+
+/// Page color statistics
+#[derive(Debug, Clone, Copy)]
+pub struct PageColorStats {
+    /// Number of colors in use
+    pub colors: usize,
+    /// Free + zeroed pages available per color
+    pub pages_by_color: [u32; MM_MAXIMUM_COLORS],
+    /// Allocations satisfied with the requested color
+    pub hits: u64,
+    /// Allocations that fell back to another color
+    pub misses: u64,
+}
+
+/// Get page color statistics
+pub fn mm_get_page_color_stats() -> PageColorStats {
+    let _guard = PFN_LOCK.lock();
+
+    PageColorStats {
+        colors: PAGE_COLORS.load(Ordering::Relaxed) as usize,
+        pages_by_color: unsafe { PAGES_BY_COLOR },
+        hits: COLOR_HITS.load(Ordering::Relaxed),
+        misses: COLOR_MISSES.load(Ordering::Relaxed),
+    }
+}
+
+// ============================================================================
+// Memory Condition Events
+// ============================================================================
+
+/// Memory condition reported by the low/high memory events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MemoryCondition {
+    Normal = 0,
+    /// Available pages at or below the low threshold
+    Low = 1,
+    /// Available pages at or above the high threshold
+    High = 2,
+}
+
+/// Signaled while available memory is low (NT: MmLowMemoryEvent)
+static mut LOW_MEMORY_EVENT: KEvent = KEvent::new();
+
+/// Signaled while available memory is plentiful (NT: MmHighMemoryEvent)
+static mut HIGH_MEMORY_EVENT: KEvent = KEvent::new();
+
+/// Events have been initialized
+static MEMORY_EVENTS_READY: AtomicBool = AtomicBool::new(false);
+
+/// Available page thresholds
+static LOW_MEMORY_THRESHOLD: AtomicU32 = AtomicU32::new(0);
+static HIGH_MEMORY_THRESHOLD: AtomicU32 = AtomicU32::new(0);
+
+/// Current MemoryCondition
+static MEMORY_CONDITION: AtomicU8 = AtomicU8::new(MemoryCondition::Normal as u8);
+
+/// Transitions into the low condition
+static LOW_MEMORY_TRANSITIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Compute thresholds from the page count and arm the events
+unsafe fn mi_init_memory_condition() {
+    let total = TOTAL_PAGES as u32;
+    LOW_MEMORY_THRESHOLD.store((total / 16).max(32), Ordering::Relaxed);
+    HIGH_MEMORY_THRESHOLD.store(total / 4, Ordering::Relaxed);
+
+    (*ptr::addr_of_mut!(LOW_MEMORY_EVENT)).init(EventType::Notification, false);
+    (*ptr::addr_of_mut!(HIGH_MEMORY_EVENT)).init(EventType::Notification, false);
+    MEMORY_CONDITION.store(MemoryCondition::Normal as u8, Ordering::Relaxed);
+    MEMORY_EVENTS_READY.store(true, Ordering::Release);
+
+    mi_update_memory_condition();
+}
+
+/// Re-evaluate available memory and signal/reset the condition events
+///
+/// Called after the PFN lock is dropped; the events are only touched on
+/// a transition so the common path is a couple of atomic loads.
+fn mi_update_memory_condition() {
+    if !MEMORY_EVENTS_READY.load(Ordering::Acquire) {
+        return;
+    }
+
+    let available = FREE_PAGES.load(Ordering::Relaxed) + ZEROED_PAGES.load(Ordering::Relaxed);
+    let condition = if available <= LOW_MEMORY_THRESHOLD.load(Ordering::Relaxed) {
+        MemoryCondition::Low
+    } else if available >= HIGH_MEMORY_THRESHOLD.load(Ordering::Relaxed) {
+        MemoryCondition::High
+    } else {
+        MemoryCondition::Normal
+    };
+
+    let previous = MEMORY_CONDITION.swap(condition as u8, Ordering::AcqRel);
+    if previous == condition as u8 {
+        return;
+    }
+
+    unsafe {
+        let low = &*ptr::addr_of!(LOW_MEMORY_EVENT);
+        let high = &*ptr::addr_of!(HIGH_MEMORY_EVENT);
+        match condition {
+            MemoryCondition::Low => {
+                LOW_MEMORY_TRANSITIONS.fetch_add(1, Ordering::Relaxed);
+                high.reset();
+                low.set();
+            }
+            MemoryCondition::High => {
+                low.reset();
+                high.set();
+            }
+            MemoryCondition::Normal => {
+                low.reset();
+                high.reset();
+            }
+        }
+    }
+}
+
+/// Notification event signaled while available memory is low
+pub fn mm_low_memory_event() -> *mut KEvent {
+    ptr::addr_of_mut!(LOW_MEMORY_EVENT)
+}
+
+/// Notification event signaled while available memory is plentiful
+pub fn mm_high_memory_event() -> *mut KEvent {
+    ptr::addr_of_mut!(HIGH_MEMORY_EVENT)
+}
+
+/// Current memory condition
+pub fn mm_memory_condition() -> MemoryCondition {
+    match MEMORY_CONDITION.load(Ordering::Acquire) {
+        1 => MemoryCondition::Low,
+        2 => MemoryCondition::High,
+        _ => MemoryCondition::Normal,
+    }
+}
+
+/// Low and high available-page thresholds
+pub fn mm_memory_thresholds() -> (u32, u32) {
+    (
+        LOW_MEMORY_THRESHOLD.load(Ordering::Relaxed),
+        HIGH_MEMORY_THRESHOLD.load(Ordering::Relaxed),
+    )
+}
+
+/// Number of times available memory dropped to the low threshold
+pub fn mm_low_memory_transitions() -> u64 {
+    LOW_MEMORY_TRANSITIONS.load(Ordering::Relaxed)
+}
+
+/// Initialize PFN subsystem without a memory map
+///
+/// Fallback used when the bootloader passed no usable memory map;
+/// normally `physical::mm_populate_pfn_database` fills the database.
 pub fn init() {
-    // For now, just mark some pages as available
-    // In a real implementation, we'd parse the memory map
     unsafe {
         // Mark pages 256-1024 as free (1MB - 4MB region)
         // This avoids the first 1MB which has BIOS/boot stuff
@@ -8,11 +8,23 @@
 //! - Contiguous allocation for DMA
 //! - Memory region tracking
 //! - Large page support
+//!
+//! # Memory Map Sanitation
+//!
+//! The UEFI memory map is sorted, overlaps are trimmed and adjacent ranges
+//! of the same type are merged. Boot services code/data is reclaimed as
+//! conventional memory once nothing the kernel still uses (the current
+//! stack, descriptor tables, memory map, RSDP, initrd) lies inside it.
+//! Runtime services regions are never handed to the PFN database, so the
+//! firmware's runtime code and data stay intact.
 
 use core::sync::atomic::{AtomicU64, Ordering};
+use crate::ke::SpinLock;
+use super::mdl::MemoryCachingType;
 use super::pfn::{
     mm_allocate_page, mm_allocate_zeroed_page, mm_free_page,
-    mm_pfn_entry, mm_get_stats,
+    mm_allocate_contiguous_run,
+    mm_pfn_entry, mm_get_stats, mm_init_pfn_ranges,
     PAGE_SIZE, LARGE_PAGE_SIZE,
 };
 
@@ -100,8 +112,8 @@ impl MmMemoryRegion {
     }
 }
 
-/// Maximum number of memory regions
-const MAX_MEMORY_REGIONS: usize = 64;
+/// Maximum number of memory regions (firmware maps often exceed 100 entries before merging)
+const MAX_MEMORY_REGIONS: usize = 256;
 
 /// Memory regions
 static mut MEMORY_REGIONS: [MmMemoryRegion; MAX_MEMORY_REGIONS] = [MmMemoryRegion {
@@ -120,6 +132,35 @@ static TOTAL_PHYSICAL_MEMORY: AtomicU64 = AtomicU64::new(0);
 /// Usable physical memory
 static USABLE_PHYSICAL_MEMORY: AtomicU64 = AtomicU64::new(0);
 
+/// Memory map sanitation results
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryMapSanitation {
+    /// Entries in the firmware map
+    pub original_entries: usize,
+    /// Entries dropped because the region table was full
+    pub dropped_entries: usize,
+    /// Entries merged into a neighbour
+    pub merged_entries: usize,
+    /// Entries trimmed because they overlapped the previous one
+    pub overlaps_trimmed: usize,
+    /// Boot services memory reclaimed as conventional
+    pub reclaimed_bytes: u64,
+    /// Boot services memory kept because something still references it
+    pub retained_bytes: u64,
+    /// Runtime services code/data reserved for the firmware
+    pub runtime_bytes: u64,
+}
+
+static mut SANITATION: MemoryMapSanitation = MemoryMapSanitation {
+    original_entries: 0,
+    dropped_entries: 0,
+    merged_entries: 0,
+    overlaps_trimmed: 0,
+    reclaimed_bytes: 0,
+    retained_bytes: 0,
+    runtime_bytes: 0,
+};
+
 // ============================================================================
 // Physical Page Allocation
 // ============================================================================
@@ -149,9 +190,6 @@ pub unsafe fn mm_free_physical_page(physical_address: u64) {
 /// This is needed for DMA operations that require contiguous memory.
 /// Returns None if contiguous allocation is not possible.
 pub unsafe fn mm_alloc_contiguous_pages(page_count: usize) -> Option<u64> {
-    // Simple implementation: try to find contiguous free pages
-    // In a real implementation, this would use a more sophisticated allocator
-
     if page_count == 0 {
         return None;
     }
@@ -160,9 +198,8 @@ pub unsafe fn mm_alloc_contiguous_pages(page_count: usize) -> Option<u64> {
         return mm_alloc_physical_page();
     }
 
-    // For now, we don't support multi-page contiguous allocation
-    // This would require a buddy allocator or similar
-    None
+    let pfn = mm_allocate_contiguous_run(page_count, 0, usize::MAX, 1, 0)?;
+    Some(pfn as u64 * PAGE_SIZE as u64)
 }
 
 /// Free contiguous physical pages
@@ -173,6 +210,160 @@ pub unsafe fn mm_free_contiguous_pages(physical_address: u64, page_count: usize)
     }
 }
 
+// ============================================================================
+// Contiguous Memory (MmAllocateContiguousMemory*)
+// ============================================================================
+
+/// Maximum number of outstanding contiguous allocations
+pub const MAX_CONTIGUOUS_ALLOCATIONS: usize = 64;
+
+/// Outstanding contiguous allocation
+///
+/// MmFreeContiguousMemory only receives the base address, so the size
+/// and caching type of each allocation are remembered here.
+#[derive(Debug, Clone, Copy)]
+pub struct MmContiguousAllocation {
+    /// Base address (physical == virtual, memory is identity mapped)
+    pub base: u64,
+    /// Number of pages
+    pub page_count: usize,
+}
+
+static CONTIGUOUS_LOCK: SpinLock<()> = SpinLock::new(());
+
+static mut CONTIGUOUS_ALLOCATIONS: [Option<MmContiguousAllocation>; MAX_CONTIGUOUS_ALLOCATIONS] =
+    [None; MAX_CONTIGUOUS_ALLOCATIONS];
+
+/// Allocate physically contiguous memory with address constraints
+///
+/// Searches the PFN database for a run of pages that:
+/// - lies between `lowest_acceptable` and `highest_acceptable` (inclusive)
+/// - does not cross a multiple of `boundary_multiple` (0 = no boundary)
+/// - starts on an `alignment` byte boundary (rounded up to a page)
+///
+/// Only `MmCached` is supported. Low memory is identity mapped write-back
+/// with large pages, which cannot be given per-page cache attributes, so
+/// any other `cache_type` is refused rather than handed out cached.
+///
+/// Returns the base address of the zeroed block, or None.
+pub unsafe fn mm_allocate_contiguous_memory_aligned(
+    number_of_bytes: usize,
+    lowest_acceptable: u64,
+    highest_acceptable: u64,
+    boundary_multiple: u64,
+    alignment: usize,
+    cache_type: MemoryCachingType,
+) -> Option<u64> {
+    if number_of_bytes == 0 || cache_type != MemoryCachingType::MmCached {
+        return None;
+    }
+
+    let page_count = number_of_bytes.div_ceil(PAGE_SIZE);
+    let lowest_pfn = lowest_acceptable.div_ceil(PAGE_SIZE as u64) as usize;
+    let highest_pfn = if highest_acceptable == u64::MAX {
+        usize::MAX
+    } else {
+        // The last page must end at or below the highest acceptable byte
+        ((highest_acceptable + 1) / PAGE_SIZE as u64) as usize
+    };
+    if highest_pfn == 0 {
+        return None;
+    }
+    let boundary_pages = (boundary_multiple / PAGE_SIZE as u64) as usize;
+    if boundary_multiple != 0 && (boundary_pages == 0 || !boundary_multiple.is_power_of_two()) {
+        return None;
+    }
+    let alignment_pages = alignment.div_ceil(PAGE_SIZE).max(1);
+
+    let _guard = CONTIGUOUS_LOCK.lock();
+
+    let slot = CONTIGUOUS_ALLOCATIONS.iter().position(|a| a.is_none())?;
+
+    let pfn = mm_allocate_contiguous_run(
+        page_count,
+        lowest_pfn,
+        highest_pfn - 1,
+        alignment_pages,
+        boundary_pages,
+    )?;
+    let base = pfn as u64 * PAGE_SIZE as u64;
+
+    CONTIGUOUS_ALLOCATIONS[slot] = Some(MmContiguousAllocation {
+        base,
+        page_count,
+    });
+
+    Some(base)
+}
+
+/// Allocate physically contiguous memory (MmAllocateContiguousMemorySpecifyCache)
+///
+/// Only `MmCached` memory can be allocated; see
+/// `mm_allocate_contiguous_memory_aligned`.
+pub unsafe fn mm_allocate_contiguous_memory_specify_cache(
+    number_of_bytes: usize,
+    lowest_acceptable: u64,
+    highest_acceptable: u64,
+    boundary_multiple: u64,
+    cache_type: MemoryCachingType,
+) -> Option<u64> {
+    mm_allocate_contiguous_memory_aligned(
+        number_of_bytes,
+        lowest_acceptable,
+        highest_acceptable,
+        boundary_multiple,
+        PAGE_SIZE,
+        cache_type,
+    )
+}
+
+/// Allocate cached contiguous memory (MmAllocateContiguousMemory)
+pub unsafe fn mm_allocate_contiguous_memory(
+    number_of_bytes: usize,
+    highest_acceptable: u64,
+) -> Option<u64> {
+    mm_allocate_contiguous_memory_specify_cache(
+        number_of_bytes,
+        0,
+        highest_acceptable,
+        0,
+        MemoryCachingType::MmCached,
+    )
+}
+
+/// Free memory from mm_allocate_contiguous_memory* (MmFreeContiguousMemory)
+///
+/// Returns false if `base` is not the start of an outstanding allocation.
+pub unsafe fn mm_free_contiguous_memory(base: u64) -> bool {
+    let allocation = {
+        let _guard = CONTIGUOUS_LOCK.lock();
+        let slot = CONTIGUOUS_ALLOCATIONS
+            .iter()
+            .position(|a| matches!(a, Some(a) if a.base == base));
+        match slot {
+            Some(i) => CONTIGUOUS_ALLOCATIONS[i].take(),
+            None => None,
+        }
+    };
+
+    match allocation {
+        Some(a) => {
+            mm_free_contiguous_pages(a.base, a.page_count);
+            true
+        }
+        None => {
+            crate::serial_println!("[MM] Free of unknown contiguous block {:#x}", base);
+            false
+        }
+    }
+}
+
+/// Get a snapshot of outstanding contiguous allocations
+pub fn mm_get_contiguous_allocations() -> [Option<MmContiguousAllocation>; MAX_CONTIGUOUS_ALLOCATIONS] {
+    let _guard = CONTIGUOUS_LOCK.lock();
+    unsafe { CONTIGUOUS_ALLOCATIONS }
+}
+
 // ============================================================================
 // Physical Memory Information
 // ============================================================================
@@ -277,10 +468,9 @@ pub fn mm_get_physical_memory_type(physical_address: u64) -> Option<MmMemoryType
 /// Large pages can improve TLB efficiency for large allocations.
 pub unsafe fn mm_alloc_large_page() -> Option<u64> {
     // Large pages need 512 contiguous 4KB pages aligned to 2MB
-    // This is expensive - would need a buddy allocator
-
-    // For now, not supported
-    None
+    let pages = LARGE_PAGE_SIZE / PAGE_SIZE;
+    let pfn = mm_allocate_contiguous_run(pages, 0, usize::MAX, pages, 0)?;
+    Some(pfn as u64 * PAGE_SIZE as u64)
 }
 
 /// Free a large page
@@ -315,13 +505,13 @@ pub unsafe fn mm_parse_memory_map(
         attribute: u64,
     }
 
-    let mut total = 0u64;
-    let mut usable = 0u64;
     let mut region_idx = 0usize;
+    let mut dropped = 0usize;
 
     for i in 0..memory_map_entries {
         if region_idx >= MAX_MEMORY_REGIONS {
-            break;
+            dropped += 1;
+            continue;
         }
 
         let entry_addr = memory_map_addr + (i * memory_map_entry_size);
@@ -346,11 +536,8 @@ pub unsafe fn mm_parse_memory_map(
             _ => MmMemoryType::Reserved,
         };
 
-        let size = entry.number_of_pages * PAGE_SIZE as u64;
-        total += size;
-
-        if memory_type.is_usable() {
-            usable += size;
+        if entry.number_of_pages == 0 {
+            continue;
         }
 
         MEMORY_REGIONS[region_idx] = MmMemoryRegion {
@@ -364,10 +551,141 @@ pub unsafe fn mm_parse_memory_map(
     }
 
     MEMORY_REGION_COUNT = region_idx;
+    SANITATION = MemoryMapSanitation {
+        original_entries: memory_map_entries as usize,
+        dropped_entries: dropped,
+        ..MemoryMapSanitation::default()
+    };
+    update_totals();
+}
+
+/// Recompute total and usable memory from the region table
+unsafe fn update_totals() {
+    let mut total = 0u64;
+    let mut usable = 0u64;
+    for region in &MEMORY_REGIONS[..MEMORY_REGION_COUNT] {
+        total += region.size();
+        if region.memory_type.is_usable() {
+            usable += region.size();
+        }
+    }
     TOTAL_PHYSICAL_MEMORY.store(total, Ordering::SeqCst);
     USABLE_PHYSICAL_MEMORY.store(usable, Ordering::SeqCst);
 }
 
+/// Physical addresses the kernel may still be using from boot time
+fn boot_references(boot_info: &crate::BootInfo) -> [(u64, u64); 6] {
+    use x86_64::instructions::tables::{sgdt, sidt};
+
+    let rsp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+    let gdtr = sgdt();
+    let idtr = sidt();
+    let map_size = boot_info.memory_map_entries * boot_info.memory_map_entry_size;
+
+    // (start, length); the stack entry covers the page below RSP as well
+    [
+        (rsp.saturating_sub(PAGE_SIZE as u64), 2 * PAGE_SIZE as u64),
+        (gdtr.base.as_u64(), gdtr.limit as u64 + 1),
+        (idtr.base.as_u64(), idtr.limit as u64 + 1),
+        (boot_info.memory_map_addr, map_size),
+        (boot_info.rsdp_addr, if boot_info.rsdp_addr != 0 { 36 } else { 0 }),
+        (boot_info.initrd_addr, boot_info.initrd_size),
+    ]
+}
+
+/// Sort, trim and merge the region table, reclaiming boot services memory
+///
+/// Must run after everything handed over by the bootloader has been
+/// consumed or copied (boot info, display table).
+pub unsafe fn mm_sanitize_memory_map(boot_info: &crate::BootInfo) {
+    let references = boot_references(boot_info);
+    let regions = &mut MEMORY_REGIONS[..MEMORY_REGION_COUNT];
+    let mut stats = SANITATION;
+
+    regions.sort_unstable_by_key(|r| r.physical_start);
+
+    // Reclaim boot services memory nothing refers to any more
+    for region in regions.iter_mut() {
+        match region.memory_type {
+            MmMemoryType::BootServicesCode | MmMemoryType::BootServicesData => {
+                let (start, end) = (region.physical_start, region.physical_end());
+                let referenced = references.iter().any(|&(addr, len)| {
+                    len != 0 && addr < end && addr.saturating_add(len) > start
+                });
+                if referenced {
+                    stats.retained_bytes += region.size();
+                } else {
+                    region.memory_type = MmMemoryType::Conventional;
+                    stats.reclaimed_bytes += region.size();
+                }
+            }
+            MmMemoryType::RuntimeServicesCode | MmMemoryType::RuntimeServicesData => {
+                stats.runtime_bytes += region.size();
+            }
+            _ => {}
+        }
+    }
+
+    // Trim overlaps and merge adjacent ranges of the same type
+    let mut out = 0usize;
+    for i in 0..regions.len() {
+        let mut region = regions[i];
+        if out > 0 {
+            let prev_end = regions[out - 1].physical_end();
+            if region.physical_start < prev_end {
+                // Keep the earlier entry; drop the overlapping pages of this one
+                stats.overlaps_trimmed += 1;
+                let overlap = (prev_end - region.physical_start) / PAGE_SIZE as u64;
+                if overlap >= region.page_count {
+                    continue;
+                }
+                region.physical_start = prev_end;
+                region.page_count -= overlap;
+            }
+            let prev = &mut regions[out - 1];
+            if prev.physical_end() == region.physical_start
+                && prev.memory_type == region.memory_type
+                && prev.attributes == region.attributes
+            {
+                prev.page_count += region.page_count;
+                stats.merged_entries += 1;
+                continue;
+            }
+        }
+        regions[out] = region;
+        out += 1;
+    }
+
+    MEMORY_REGION_COUNT = out;
+    SANITATION = stats;
+    update_totals();
+}
+
+/// Memory map sanitation results
+pub fn mm_get_memory_map_sanitation() -> MemoryMapSanitation {
+    unsafe { SANITATION }
+}
+
+/// Hand conventional memory from the sanitized map to the PFN database
+///
+/// Runtime services, ACPI, loader and retained boot services regions are
+/// left out. Returns the number of pages added (0 if there is no map).
+pub unsafe fn mm_populate_pfn_database() -> usize {
+    if MEMORY_REGION_COUNT == 0 {
+        return 0;
+    }
+    let regions = &MEMORY_REGIONS[..MEMORY_REGION_COUNT];
+    mm_init_pfn_ranges(
+        regions
+            .iter()
+            .filter(|r| r.memory_type == MmMemoryType::Conventional)
+            .map(|r| ((r.physical_start / PAGE_SIZE as u64) as usize, r.page_count as usize)),
+    )
+}
+
 // ============================================================================
 // Page Locking
 // ============================================================================
@@ -408,13 +726,22 @@ pub unsafe fn init(boot_info: &crate::BootInfo) {
             boot_info.memory_map_entries,
             boot_info.memory_map_entry_size,
         );
+        mm_sanitize_memory_map(boot_info);
     }
 
     let total = TOTAL_PHYSICAL_MEMORY.load(Ordering::SeqCst);
     let usable = USABLE_PHYSICAL_MEMORY.load(Ordering::SeqCst);
+    let stats = SANITATION;
 
     crate::serial_println!("[MM] Physical memory initialized");
     crate::serial_println!("[MM]   Total: {} MB", total / (1024 * 1024));
     crate::serial_println!("[MM]   Usable: {} MB", usable / (1024 * 1024));
-    crate::serial_println!("[MM]   {} memory regions", unsafe { MEMORY_REGION_COUNT });
+    crate::serial_println!("[MM]   {} memory regions ({} firmware entries, {} merged, {} overlaps trimmed)",
+        unsafe { MEMORY_REGION_COUNT }, stats.original_entries,
+        stats.merged_entries, stats.overlaps_trimmed);
+    crate::serial_println!("[MM]   Boot services: {} KB reclaimed, {} KB retained; runtime services: {} KB reserved",
+        stats.reclaimed_bytes / 1024, stats.retained_bytes / 1024, stats.runtime_bytes / 1024);
+    if stats.dropped_entries != 0 {
+        crate::serial_println!("[MM]   WARNING: {} memory map entries dropped", stats.dropped_entries);
+    }
 }
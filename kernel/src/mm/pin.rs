@@ -0,0 +1,340 @@
+//! Pinned Memory
+//!
+//! Keeps interactive hot paths resident no matter how hard the memory
+//! manager is squeezed. A pinned range is locked with MAP_SYSTEM through
+//! the virtual memory lock table and recorded here; working set trimming
+//! and the available-page estimate both skip any page inside a pinned
+//! range, so the shell keeps responding while the system pages.
+//!
+//! # Sources
+//! - **Builtin**: the hot paths in `BUILTIN_HOT_PATHS` - the kernel image
+//!   (shell, keyboard ISR and console render code), the shell state, the
+//!   keyboard input queue and the console back buffer
+//! - **Registry**: images named in `PinnedImages`, pinned by the loader
+//!   as soon as they are mapped
+//! - **Manual**: ranges added from the shell with `mm pin add`
+//!
+//! # Registry
+//! Values under `PIN_KEY`:
+//! - `PinHotPaths` (DWORD, default 1): pin the builtin hot paths at boot
+//! - `PinnedImages` (REG_SZ): image names separated by `;` or `,`,
+//!   e.g. `cmd.exe;kernel32.dll`
+//!
+//! Pins are matched by address alone, so a pinned user image also keeps
+//! the same addresses resident in other processes' working sets. That
+//! errs on the side of residency, which is the point.
+
+extern crate alloc;
+
+use alloc::format;
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::ke::SpinLock;
+use super::lockvm::{nt_lock_virtual_memory, nt_unlock_virtual_memory, MAP_SYSTEM};
+use super::pfn::{PAGE_SIZE, PAGE_SHIFT};
+
+/// Registry key holding the pinning configuration
+pub const PIN_KEY: &str = "MACHINE\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Memory Management";
+
+/// Maximum number of pinned ranges
+pub const MAX_PINNED_RANGES: usize = 32;
+
+/// Maximum length of a pin name
+pub const PIN_NAME_LENGTH: usize = 32;
+
+/// Where a pin came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinSource {
+    Builtin,
+    Registry,
+    Manual,
+}
+
+impl PinSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            PinSource::Builtin => "builtin",
+            PinSource::Registry => "registry",
+            PinSource::Manual => "manual",
+        }
+    }
+}
+
+/// A pinned range of virtual memory
+#[derive(Debug, Clone, Copy)]
+pub struct PinnedRange {
+    name: [u8; PIN_NAME_LENGTH],
+    name_len: u8,
+    /// Owning process, 0 for system memory
+    pub process_id: u32,
+    /// Page-aligned start
+    pub start: u64,
+    /// Page-aligned size in bytes
+    pub size: u64,
+    pub source: PinSource,
+}
+
+impl PinnedRange {
+    const fn empty() -> Self {
+        Self {
+            name: [0; PIN_NAME_LENGTH],
+            name_len: 0,
+            process_id: 0,
+            start: 0,
+            size: 0,
+            source: PinSource::Manual,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("?")
+    }
+
+    pub fn pages(&self) -> u64 {
+        self.size >> PAGE_SHIFT
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr - self.start < self.size
+    }
+}
+
+struct PinTable {
+    ranges: [PinnedRange; MAX_PINNED_RANGES],
+    count: usize,
+}
+
+static PIN_TABLE: SpinLock<PinTable> = SpinLock::new(PinTable {
+    ranges: [PinnedRange::empty(); MAX_PINNED_RANGES],
+    count: 0,
+});
+
+/// Pages spared by working set trimming because they were pinned
+static TRIMS_SKIPPED: AtomicU32 = AtomicU32::new(0);
+
+/// A hot path pinned at boot
+pub struct HotPath {
+    pub name: &'static str,
+    /// Current extent, or `None` if it does not exist on this machine
+    pub range: fn() -> Option<(u64, usize)>,
+}
+
+/// Hot paths pinned by `mm_pin_init` when `PinHotPaths` is set
+pub static BUILTIN_HOT_PATHS: &[HotPath] = &[
+    HotPath { name: "kernel-image", range: kernel_image_range },
+    HotPath { name: "shell-state", range: crate::shell::state_range },
+    HotPath { name: "keyboard-buffer", range: crate::hal::keyboard::buffer_range },
+    HotPath { name: "console-back-buffer", range: crate::framebuffer::back_buffer_range },
+];
+
+/// The loaded kernel image; every kernel code path lives here
+fn kernel_image_range() -> Option<(u64, usize)> {
+    extern "C" {
+        static __kernel_start: u8;
+        static __kernel_end: u8;
+    }
+    let start = &raw const __kernel_start as u64;
+    let end = &raw const __kernel_end as u64;
+    Some((start, (end - start) as usize))
+}
+
+/// Pin `size` bytes at `start` under `name`
+///
+/// The range is widened to whole pages, locked in system memory and
+/// touched so every page is resident before this returns. Pinning an
+/// already pinned name fails.
+pub fn mm_pin_range(
+    name: &str,
+    process_id: u32,
+    start: u64,
+    size: usize,
+    source: PinSource,
+) -> Result<(), &'static str> {
+    if name.is_empty() || name.len() > PIN_NAME_LENGTH {
+        return Err("Invalid pin name");
+    }
+    if size == 0 {
+        return Err("Empty range");
+    }
+    if mm_find_pin(name).is_some() {
+        return Err("Name already pinned");
+    }
+
+    let mut base = start;
+    let mut length = size as u64;
+    let status = unsafe { nt_lock_virtual_memory(process_id, &mut base, &mut length, MAP_SYSTEM) };
+    if status != 0 {
+        return Err("Lock quota exceeded");
+    }
+
+    let added = {
+        let mut table = PIN_TABLE.lock();
+        if table.count < MAX_PINNED_RANGES {
+            let mut range = PinnedRange::empty();
+            range.name[..name.len()].copy_from_slice(name.as_bytes());
+            range.name_len = name.len() as u8;
+            range.process_id = process_id;
+            range.start = base;
+            range.size = length;
+            range.source = source;
+            let index = table.count;
+            table.ranges[index] = range;
+            table.count += 1;
+            true
+        } else {
+            false
+        }
+    };
+    if !added {
+        unsafe { nt_unlock_virtual_memory(process_id, &mut base, &mut length, MAP_SYSTEM) };
+        return Err("Pin table full");
+    }
+
+    // Fault everything in now rather than on the first keystroke
+    let mut page = base;
+    while page < base + length {
+        unsafe { core::ptr::read_volatile(page as *const u8) };
+        page += PAGE_SIZE as u64;
+    }
+
+    crate::serial_println!("[MM] Pinned {} ({} pages at {:#x}, {})",
+        name, length >> PAGE_SHIFT, base, source.name());
+    Ok(())
+}
+
+/// Remove the pin called `name`
+pub fn mm_unpin(name: &str) -> Result<(), &'static str> {
+    let removed = {
+        let mut table = PIN_TABLE.lock();
+        let count = table.count;
+        let index = table.ranges[..count]
+            .iter()
+            .position(|r| r.name().eq_ignore_ascii_case(name))
+            .ok_or("Not pinned")?;
+        let range = table.ranges[index];
+        table.ranges.copy_within(index + 1..count, index);
+        table.count -= 1;
+        range
+    };
+
+    release(&removed);
+    Ok(())
+}
+
+/// Remove every pin starting inside `[start, start + size)`
+///
+/// Used when an image is unloaded. Returns how many pins were removed.
+pub fn mm_unpin_range(start: u64, size: usize) -> usize {
+    let mut removed = [PinnedRange::empty(); MAX_PINNED_RANGES];
+    let mut removed_count = 0;
+    {
+        let mut table = PIN_TABLE.lock();
+        let mut index = 0;
+        while index < table.count {
+            let range = table.ranges[index];
+            if range.start >= start && range.start - start < size as u64 {
+                removed[removed_count] = range;
+                removed_count += 1;
+                let count = table.count;
+                table.ranges.copy_within(index + 1..count, index);
+                table.count -= 1;
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    for range in &removed[..removed_count] {
+        release(range);
+    }
+    removed_count
+}
+
+/// Drop the memory lock behind a pin already taken out of the table
+fn release(range: &PinnedRange) {
+    let mut base = range.start;
+    let mut length = range.size;
+    unsafe { nt_unlock_virtual_memory(range.process_id, &mut base, &mut length, MAP_SYSTEM) };
+    crate::serial_println!("[MM] Unpinned {}", range.name());
+}
+
+/// Find a pin by name
+pub fn mm_find_pin(name: &str) -> Option<PinnedRange> {
+    let table = PIN_TABLE.lock();
+    table.ranges[..table.count].iter().find(|r| r.name().eq_ignore_ascii_case(name)).copied()
+}
+
+/// Check whether `addr` lies in a pinned range
+pub fn mm_is_address_pinned(addr: u64) -> bool {
+    let table = PIN_TABLE.lock();
+    table.ranges[..table.count].iter().any(|r| r.contains(addr))
+}
+
+/// Copy out the pinned ranges; returns the array and how many are valid
+pub fn mm_pinned_ranges() -> ([PinnedRange; MAX_PINNED_RANGES], usize) {
+    let table = PIN_TABLE.lock();
+    (table.ranges, table.count)
+}
+
+/// Record a page that trimming left alone because it was pinned
+pub(crate) fn mi_note_pinned_trim_skip() {
+    TRIMS_SKIPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Pages working set trimming has skipped because they were pinned
+pub fn mm_pinned_trim_skips() -> u32 {
+    TRIMS_SKIPPED.load(Ordering::Relaxed)
+}
+
+/// Check whether `PinnedImages` names `image`
+///
+/// Only the file name part of `image` is compared, without regard to case.
+pub fn mm_is_image_pin_configured(image: &str) -> bool {
+    let file_name = image.rsplit(['\\', '/']).next().unwrap_or(image);
+    let Some(list) = (unsafe { crate::cm::cm_read_string(PIN_KEY, "PinnedImages") }) else {
+        return false;
+    };
+    list.split([';', ',']).any(|entry| entry.trim().eq_ignore_ascii_case(file_name))
+}
+
+/// Pin a freshly mapped image if the registry asks for it
+///
+/// Called by the loader; failures are logged and the load carries on.
+pub fn mm_pin_image_if_configured(process_id: u32, image: &[u8], base: u64, size: usize) {
+    let Ok(image) = core::str::from_utf8(image) else {
+        return;
+    };
+    if !mm_is_image_pin_configured(image) {
+        return;
+    }
+
+    let file_name = image.rsplit(['\\', '/']).next().unwrap_or(image);
+    // One pin per process the image is mapped into
+    let name = format!("{}:{}", file_name, process_id);
+    let mut end = name.len().min(PIN_NAME_LENGTH);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    let name = &name[..end];
+
+    if let Err(e) = mm_pin_range(name, process_id, base, size, PinSource::Registry) {
+        crate::serial_println!("[MM] Cannot pin {}: {}", name, e);
+    }
+}
+
+/// Pin the builtin hot paths unless `PinHotPaths` is 0
+pub fn mm_pin_init() {
+    let enabled = unsafe { crate::cm::cm_read_dword(PIN_KEY, "PinHotPaths") }.unwrap_or(1) != 0;
+    if !enabled {
+        crate::serial_println!("[MM] Hot path pinning disabled by registry");
+        return;
+    }
+
+    for hot_path in BUILTIN_HOT_PATHS {
+        let Some((start, size)) = (hot_path.range)() else {
+            continue;
+        };
+        if let Err(e) = mm_pin_range(hot_path.name, 0, start, size, PinSource::Builtin) {
+            crate::serial_println!("[MM] Cannot pin {}: {}", hot_path.name, e);
+        }
+    }
+}
@@ -78,8 +78,8 @@ struct PoolHeader {
     pool_type: PoolType,
     /// Flags
     flags: u8,
-    /// Reserved
-    _reserved: u16,
+    /// Bytes requested by the caller (at most 16KB, so it fits)
+    requested: u16,
 }
 
 impl PoolHeader {
@@ -91,7 +91,7 @@ impl PoolHeader {
             tag,
             pool_type,
             flags: 0,
-            _reserved: 0,
+            requested: 0,
         }
     }
 }
@@ -229,6 +229,7 @@ static POOL_LOCK: SpinLock<()> = SpinLock::new(());
 static POOL_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
 static POOL_FREES: AtomicUsize = AtomicUsize::new(0);
 static POOL_BYTES_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static POOL_BYTES_REQUESTED: AtomicUsize = AtomicUsize::new(0);
 
 // ============================================================================
 // Pool API
@@ -284,10 +285,12 @@ pub unsafe fn ex_allocate_pool_with_tag(
     let header = block_ptr as *mut PoolHeader;
     *header = PoolHeader::new(arena.block_size as u32, tag, pool_type);
     (*header).flags = pool_flags::ALLOCATED;
+    (*header).requested = size as u16;
 
     // Update stats
     POOL_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
     POOL_BYTES_ALLOCATED.fetch_add(arena.block_size, Ordering::Relaxed);
+    POOL_BYTES_REQUESTED.fetch_add(size, Ordering::Relaxed);
 
     // Return pointer after header
     block_ptr.add(PoolHeader::SIZE)
@@ -361,6 +364,7 @@ pub unsafe fn ex_free_pool_with_tag(ptr: *mut u8, tag: PoolTag) {
         // Update stats
         POOL_FREES.fetch_add(1, Ordering::Relaxed);
         POOL_BYTES_ALLOCATED.fetch_sub(block_size, Ordering::Relaxed);
+        POOL_BYTES_REQUESTED.fetch_sub((*header_ptr).requested as usize, Ordering::Relaxed);
 
         // Clear header
         (*header_ptr).flags = 0;
@@ -390,6 +394,8 @@ fn get_arena_offset(class_idx: usize) -> usize {
 pub struct PoolStats {
     pub total_size: usize,
     pub bytes_allocated: usize,
+    /// Bytes callers asked for; the rest of `bytes_allocated` is size-class rounding
+    pub bytes_requested: usize,
     pub bytes_free: usize,
     pub allocation_count: usize,
     pub free_count: usize,
@@ -401,6 +407,7 @@ pub fn mm_get_pool_stats() -> PoolStats {
     PoolStats {
         total_size: POOL_HEAP_SIZE,
         bytes_allocated,
+        bytes_requested: POOL_BYTES_REQUESTED.load(Ordering::Relaxed),
         bytes_free: POOL_HEAP_SIZE.saturating_sub(bytes_allocated),
         allocation_count: POOL_ALLOCATIONS.load(Ordering::Relaxed),
         free_count: POOL_FREES.load(Ordering::Relaxed),
@@ -463,44 +470,6 @@ pub fn mm_get_size_classes() -> &'static [usize] {
     &SIZE_CLASSES
 }
 
-// ============================================================================
-// Global Allocator (optional)
-// ============================================================================
-
-// Global allocator for heap allocation support
-mod global_allocator {
-    use super::*;
-    use core::alloc::{GlobalAlloc, Layout};
-
-    struct PoolAllocator;
-
-    unsafe impl GlobalAlloc for PoolAllocator {
-        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-            ex_allocate_pool_with_tag(
-                PoolType::NonPagedPool,
-                layout.size(),
-                pool_tags::TAG_GENERIC,
-            )
-        }
-
-        unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-            ex_free_pool(ptr);
-        }
-
-        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-            let new_ptr = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
-            if !new_ptr.is_null() {
-                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
-                self.dealloc(ptr, layout);
-            }
-            new_ptr
-        }
-    }
-
-    #[global_allocator]
-    static ALLOCATOR: PoolAllocator = PoolAllocator;
-}
-
 // ============================================================================
 // Initialization
 // ============================================================================
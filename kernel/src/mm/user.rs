@@ -234,28 +234,28 @@ pub unsafe fn init_user_page_tables() {
     USER_PD.entries[stack_pd_idx] = user_pt_stack_phys | pte_flags::PRESENT | pte_flags::WRITABLE | pte_flags::USER;
 
     // CRITICAL: Also identity-map the kernel's physical address range!
-    // The kernel is running from the identity-mapped low address (e.g., 0xda00000),
-    // not from the high virtual address (0xffffffff80000000).
+    // The kernel is running from its identity-mapped physical alias, not
+    // from the higher-half virtual address the loader relocated it to.
     // After CR3 switch, we need to continue executing from the low address.
-    // Add 2MB huge pages for the kernel's physical area.
     //
-    // We need to identity-map:
-    // 1. PD[0..1] - Low kernel statics (stack at 0x7c7d8, etc.)
-    //    Note: PD[2] = user code at 0x400000, PD[3] = user stack at 0x7FC000
-    //    These are already set up as page tables, don't overwrite!
-    // 2. PD[104..120] - Kernel code/data at ~0xda00000
-    //
-    // Map PD[0..1] as 2MB identity pages (kernel statics area)
-    for pd_idx in 0..2 {
+    // Map the rest of the first 1GB as 2MB identity pages (low kernel
+    // statics and stack, a kernel loaded below 1GB). PD[2] = user code at
+    // 0x400000 and PD[3] = user stack at 0x7FC000 are already set up as
+    // page tables, don't overwrite!
+    for pd_idx in 0..ENTRIES_PER_TABLE {
+        if pd_idx == code_pd_idx || pd_idx == stack_pd_idx {
+            continue;
+        }
         let phys_addr = (pd_idx as u64) << 21; // 2MB aligned physical address
         USER_PD.entries[pd_idx] = phys_addr | pte_flags::PRESENT | pte_flags::WRITABLE | pte_flags::HUGE_PAGE;
     }
 
-    // Map PD[4..127] as 2MB identity pages (skip user code/stack at 2,3)
-    // This covers 0x800000 to 0xFFFFFFF (8MB to 256MB)
-    for pd_idx in 4..128 {
-        let phys_addr = (pd_idx as u64) << 21; // 2MB aligned physical address
-        USER_PD.entries[pd_idx] = phys_addr | pte_flags::PRESENT | pte_flags::WRITABLE | pte_flags::HUGE_PAGE;
+    // A kernel loaded above 4GB gets 1GB identity pages for its image
+    let (kernel_phys_start, kernel_phys_end) = kernel_physical_range();
+    let first_gb = (kernel_phys_start >> 30).max(4) as usize;
+    let last_gb = ((kernel_phys_end - 1) >> 30) as usize;
+    for gb in first_gb..=last_gb.min(ENTRIES_PER_TABLE - 1) {
+        USER_PDPT.entries[gb] = ((gb as u64) << 30) | pte_flags::PRESENT | pte_flags::WRITABLE | pte_flags::HUGE_PAGE;
     }
 
     // Map code pages (USER_RWX - executable, readable, writable, user accessible)
@@ -280,6 +280,21 @@ pub unsafe fn init_user_page_tables() {
     crate::serial_println!("[MM-USER] User page tables initialized (CR3={:#x})", user_pml4_phys);
 }
 
+/// Physical range of the kernel image, including the headroom the loader
+/// maps past the end of BSS
+unsafe fn kernel_physical_range() -> (u64, u64) {
+    extern "C" {
+        static __kernel_start: u8;
+        static __kernel_end: u8;
+    }
+    /// Matches the loader's KERNEL_MAP_HEADROOM
+    const KERNEL_MAP_HEADROOM: u64 = 0x200_0000;
+
+    let start = virt_to_phys(&raw const __kernel_start as u64);
+    let size = &raw const __kernel_end as u64 - &raw const __kernel_start as u64;
+    (start, start + size + KERNEL_MAP_HEADROOM)
+}
+
 /// Physical address of user PML4 (cached after init)
 static mut USER_PML4_PHYS: u64 = 0;
 
@@ -421,7 +421,8 @@ pub unsafe fn mi_remove_wsle(
 
 /// Trim the working set to a target size
 ///
-/// Returns the number of pages trimmed.
+/// Locked and pinned pages are never trimmed. Returns the number of
+/// pages trimmed.
 pub unsafe fn mi_trim_working_set(
     ws_info: *mut MmSupport,
     pages_to_trim: u32,
@@ -449,7 +450,11 @@ pub unsafe fn mi_trim_working_set(
 
         let wsle = &mut wsl.wsle[index as usize];
 
-        if wsle.valid && !wsle.locked && wsle.age >= trim_age {
+        let trimmable = wsle.valid && !wsle.locked && wsle.age >= trim_age;
+        if trimmable && super::pin::mm_is_address_pinned(wsle.virtual_address) {
+            // Pinned hot path: leave it resident
+            super::pin::mi_note_pinned_trim_skip();
+        } else if trimmable {
             // This page is old enough to trim
             // In a real implementation, we would:
             // 1. Write the page to swap if dirty
@@ -527,7 +532,7 @@ pub unsafe fn mi_estimate_available(ws_info: *mut MmSupport) -> u32 {
 
     for i in 0..MAX_WSLE_PER_PROCESS {
         let wsle = &wsl.wsle[i];
-        if wsle.valid && !wsle.locked {
+        if wsle.valid && !wsle.locked && !super::pin::mm_is_address_pinned(wsle.virtual_address) {
             // Pages with high age are considered available
             if wsle.age >= 3 {
                 available += 1;
@@ -148,9 +148,7 @@ pub fn request_daytime(
             return Err("Daytime timeout");
         }
 
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 }
 
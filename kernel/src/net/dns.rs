@@ -452,7 +452,7 @@ pub fn resolve(device_index: usize, hostname: &str) -> Option<Ipv4Address> {
             return None;
         }
 
-        core::hint::spin_loop();
+        crate::ex::ex_sleep(1);
     }
 }
 
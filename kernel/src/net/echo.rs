@@ -197,9 +197,7 @@ pub fn send_echo(
         }
 
         // Small delay
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 }
 
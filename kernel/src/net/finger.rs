@@ -119,9 +119,7 @@ pub fn finger_query(
             return Err("Finger timeout");
         }
 
-        for _ in 0..100 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 
     let _ = tcp::socket_close(socket);
@@ -401,9 +401,7 @@ impl FtpSession {
             }
 
             // Small delay
-            for _ in 0..100 {
-                core::hint::spin_loop();
-            }
+            crate::ex::ex_sleep(1);
         }
 
         let _ = tcp::socket_close(data_socket);
@@ -572,9 +570,7 @@ impl FtpSession {
             }
 
             polls += 1;
-            for _ in 0..100 {
-                core::hint::spin_loop();
-            }
+            crate::ex::ex_sleep(1);
         }
 
         if total_read == 0 {
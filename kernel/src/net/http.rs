@@ -97,9 +97,7 @@ impl HttpClient {
                     crate::drivers::virtio::net::poll();
                     polls += 1;
                     // Small delay
-                    for _ in 0..1000 {
-                        core::hint::spin_loop();
-                    }
+                    crate::ex::ex_sleep(1);
                 }
                 Some(tcp::TcpState::Closed) | None => {
                     self.socket = None;
@@ -149,9 +147,7 @@ impl HttpClient {
                 Ok(_) => {
                     // No data available
                     polls += 1;
-                    for _ in 0..100 {
-                        core::hint::spin_loop();
-                    }
+                    crate::ex::ex_sleep(1);
                 }
                 Err(_) => {
                     // Check if connection closed
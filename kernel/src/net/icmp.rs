@@ -180,6 +180,7 @@ pub fn handle_icmp_packet(
 
             unsafe {
                 super::NETWORK_STATS.icmp_echo_requests += 1;
+                super::PROTOCOL_STATS.icmp.echo_requests_received += 1;
             }
 
             // Send Echo Reply
@@ -193,6 +194,9 @@ pub fn handle_icmp_packet(
             );
         }
         IcmpType::EchoReply => {
+            unsafe {
+                super::PROTOCOL_STATS.icmp.echo_replies_received += 1;
+            }
             crate::serial_println!(
                 "[ICMP] Echo Reply from {:?} (id={}, seq={})",
                 ip_header.source_addr,
@@ -201,6 +205,9 @@ pub fn handle_icmp_packet(
             );
         }
         IcmpType::DestUnreachable => {
+            unsafe {
+                super::PROTOCOL_STATS.icmp.dest_unreachable_received += 1;
+            }
             crate::serial_println!(
                 "[ICMP] Destination Unreachable from {:?} (code={})",
                 ip_header.source_addr,
@@ -208,6 +215,9 @@ pub fn handle_icmp_packet(
             );
         }
         IcmpType::TimeExceeded => {
+            unsafe {
+                super::PROTOCOL_STATS.icmp.time_exceeded_received += 1;
+            }
             crate::serial_println!(
                 "[ICMP] Time Exceeded from {:?} (code={})",
                 ip_header.source_addr,
@@ -271,6 +281,9 @@ fn send_echo_reply(
         if device.transmit(&frame).is_ok() {
             unsafe {
                 super::NETWORK_STATS.icmp_echo_replies += 1;
+                super::PROTOCOL_STATS.ip.sent += 1;
+                super::PROTOCOL_STATS.icmp.messages_sent += 1;
+                super::PROTOCOL_STATS.icmp.echo_replies_sent += 1;
             }
             super::record_tx_packet(frame.len());
         }
@@ -320,6 +333,11 @@ pub fn send_icmp_echo_request(
     if let Some(device) = super::get_device_mut(device_index) {
         device.transmit(&frame)?;
         super::record_tx_packet(frame.len());
+        unsafe {
+            super::PROTOCOL_STATS.ip.sent += 1;
+            super::PROTOCOL_STATS.icmp.messages_sent += 1;
+            super::PROTOCOL_STATS.icmp.echo_requests_sent += 1;
+        }
     }
 
     Ok(())
@@ -376,6 +394,11 @@ pub fn send_icmp_echo_with_ttl(
     if let Some(device) = super::get_device_mut(device_index) {
         device.transmit(&frame)?;
         super::record_tx_packet(frame.len());
+        unsafe {
+            super::PROTOCOL_STATS.ip.sent += 1;
+            super::PROTOCOL_STATS.icmp.messages_sent += 1;
+            super::PROTOCOL_STATS.icmp.echo_requests_sent += 1;
+        }
     }
 
     Ok(())
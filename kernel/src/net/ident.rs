@@ -138,9 +138,7 @@ pub fn ident_query(
             return Err("Ident timeout");
         }
 
-        for _ in 0..100 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 
     let _ = tcp::socket_close(socket);
@@ -100,6 +100,19 @@ impl Ipv4Address {
     pub fn as_bytes(&self) -> &[u8; 4] {
         &self.0
     }
+
+    /// Parse dotted-decimal notation ("192.168.1.10")
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut octets = [0u8; 4];
+        let mut parts = s.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Ipv4Address(octets))
+    }
 }
 
 impl fmt::Debug for Ipv4Address {
@@ -108,6 +108,114 @@ static mut NETWORK_STATS: NetworkStats = NetworkStats {
     icmp_echo_replies: 0,
 };
 
+/// IP layer counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IpStats {
+    /// Datagrams received from the link layer
+    pub received: u64,
+    /// Datagrams discarded for a malformed header
+    pub header_errors: u64,
+    /// Datagrams for a protocol with no handler
+    pub unknown_protocols: u64,
+    /// Datagrams handed to a transport protocol
+    pub delivered: u64,
+    /// Datagrams sent
+    pub sent: u64,
+}
+
+/// ICMP counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IcmpStats {
+    pub messages_received: u64,
+    /// Messages discarded as malformed
+    pub input_errors: u64,
+    pub echo_requests_received: u64,
+    pub echo_replies_received: u64,
+    pub dest_unreachable_received: u64,
+    pub time_exceeded_received: u64,
+    pub messages_sent: u64,
+    pub echo_requests_sent: u64,
+    pub echo_replies_sent: u64,
+}
+
+/// TCP counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpStats {
+    /// Connections opened with a SYN from this host
+    pub active_opens: u64,
+    /// Connections accepted on a listening socket
+    pub passive_opens: u64,
+    /// Connection attempts that failed to send their SYN
+    pub failed_attempts: u64,
+    pub resets_received: u64,
+    pub resets_sent: u64,
+    pub segments_received: u64,
+    pub segments_sent: u64,
+    /// Segments discarded as malformed
+    pub input_errors: u64,
+    /// Connections currently established (filled in by `get_protocol_stats`)
+    pub current_established: u64,
+}
+
+/// UDP counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UdpStats {
+    pub datagrams_received: u64,
+    /// Datagrams for a port with no bound socket
+    pub no_port: u64,
+    /// Datagrams dropped as malformed or for a full receive queue
+    pub receive_errors: u64,
+    pub datagrams_sent: u64,
+}
+
+/// Per-protocol statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtocolStats {
+    pub ip: IpStats,
+    pub icmp: IcmpStats,
+    pub tcp: TcpStats,
+    pub udp: UdpStats,
+}
+
+/// Global per-protocol statistics
+static mut PROTOCOL_STATS: ProtocolStats = ProtocolStats {
+    ip: IpStats {
+        received: 0,
+        header_errors: 0,
+        unknown_protocols: 0,
+        delivered: 0,
+        sent: 0,
+    },
+    icmp: IcmpStats {
+        messages_received: 0,
+        input_errors: 0,
+        echo_requests_received: 0,
+        echo_replies_received: 0,
+        dest_unreachable_received: 0,
+        time_exceeded_received: 0,
+        messages_sent: 0,
+        echo_requests_sent: 0,
+        echo_replies_sent: 0,
+    },
+    tcp: TcpStats {
+        active_opens: 0,
+        passive_opens: 0,
+        failed_attempts: 0,
+        resets_received: 0,
+        resets_sent: 0,
+        segments_received: 0,
+        segments_sent: 0,
+        input_errors: 0,
+        current_established: 0,
+    },
+    udp: UdpStats {
+        datagrams_received: 0,
+        no_port: 0,
+        receive_errors: 0,
+        datagrams_sent: 0,
+    },
+};
+
 /// Network initialized flag
 static NETWORK_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
@@ -239,6 +347,55 @@ pub fn get_stats() -> NetworkStats {
     unsafe { NETWORK_STATS }
 }
 
+/// Get per-protocol statistics
+pub fn get_protocol_stats() -> ProtocolStats {
+    let mut stats = unsafe { PROTOCOL_STATS };
+    stats.tcp.current_established = tcp::enumerate_connections()
+        .iter()
+        .filter(|c| matches!(c.state, tcp::TcpState::Established | tcp::TcpState::CloseWait))
+        .count() as u64;
+    stats
+}
+
+/// Interface snapshot for status display
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub index: usize,
+    pub name: alloc::string::String,
+    pub mac_address: MacAddress,
+    pub state: NetworkDeviceState,
+    /// Link speed in Mbps
+    pub link_speed: u32,
+    pub mtu: u32,
+    pub promiscuous: bool,
+    pub ip_address: Option<Ipv4Address>,
+    pub subnet_mask: Option<Ipv4Address>,
+    pub gateway: Option<Ipv4Address>,
+    pub stats: device::DeviceStatistics,
+}
+
+/// Enumerate registered network interfaces
+pub fn enumerate_interfaces() -> Vec<InterfaceInfo> {
+    let _guard = DEVICE_LOCK.lock();
+    let devices = unsafe { NETWORK_DEVICES.as_ref() };
+
+    devices.map(|devices| {
+        devices.iter().enumerate().map(|(index, device)| InterfaceInfo {
+            index,
+            name: device.info.name.clone(),
+            mac_address: device.info.mac_address,
+            state: device.state(),
+            link_speed: device.info.capabilities.link_speed,
+            mtu: device.info.capabilities.mtu,
+            promiscuous: device.is_promiscuous(),
+            ip_address: device.ip_address,
+            subnet_mask: device.subnet_mask,
+            gateway: device.gateway,
+            stats: device.stats,
+        }).collect()
+    }).unwrap_or_default()
+}
+
 /// Update received packet statistics
 pub fn record_rx_packet(bytes: usize) {
     unsafe {
@@ -283,18 +440,32 @@ pub fn handle_rx_packet(device_index: usize, packet: &[u8]) {
         Some(h) => h,
         None => {
             record_rx_error();
+            if let Some(device) = get_device_mut(device_index) {
+                device.record_rx_error();
+            }
             return;
         }
     };
 
+    if let Some(device) = get_device_mut(device_index) {
+        device.record_rx(packet.len());
+    }
+
     // Get payload (after Ethernet header)
     let payload = &packet[ethernet::ETHERNET_HEADER_SIZE..];
 
     // Handle based on EtherType
     match eth_header.ether_type {
         EtherType::Ipv4 => {
+            unsafe {
+                PROTOCOL_STATS.ip.received += 1;
+            }
             if let Some(ip_header) = ip::parse_ipv4_header(payload) {
                 handle_ip_packet(device_index, &eth_header, &ip_header, payload);
+            } else {
+                unsafe {
+                    PROTOCOL_STATS.ip.header_errors += 1;
+                }
             }
         }
         EtherType::Arp => {
@@ -321,28 +492,46 @@ fn handle_ip_packet(
 ) {
     let ip_header_len = (ip_header.version_ihl & 0x0F) as usize * 4;
     if data.len() < ip_header_len {
+        unsafe {
+            PROTOCOL_STATS.ip.header_errors += 1;
+        }
         return;
     }
 
     let ip_payload = &data[ip_header_len..];
 
     match ip_header.protocol {
-        IpProtocol::Icmp => {
+        IpProtocol::Icmp => unsafe {
+            PROTOCOL_STATS.ip.delivered += 1;
+            PROTOCOL_STATS.icmp.messages_received += 1;
             if let Some(icmp) = icmp::parse_icmp_packet(ip_payload) {
                 icmp::handle_icmp_packet(device_index, eth_header, ip_header, &icmp, ip_payload);
+            } else {
+                PROTOCOL_STATS.icmp.input_errors += 1;
             }
         }
-        IpProtocol::Tcp => {
+        IpProtocol::Tcp => unsafe {
+            PROTOCOL_STATS.ip.delivered += 1;
+            PROTOCOL_STATS.tcp.segments_received += 1;
             if let Some(tcp_header) = tcp::parse_tcp_header(ip_payload) {
                 tcp::handle_tcp_packet(device_index, ip_header, &tcp_header, ip_payload);
+            } else {
+                PROTOCOL_STATS.tcp.input_errors += 1;
             }
         }
-        IpProtocol::Udp => {
+        IpProtocol::Udp => unsafe {
+            PROTOCOL_STATS.ip.delivered += 1;
+            PROTOCOL_STATS.udp.datagrams_received += 1;
             if let Some(udp_header) = udp::parse_udp_packet(ip_payload) {
                 udp::handle_udp_packet(device_index, ip_header, &udp_header, ip_payload);
+            } else {
+                PROTOCOL_STATS.udp.receive_errors += 1;
             }
         }
         _ => {
+            unsafe {
+                PROTOCOL_STATS.ip.unknown_protocols += 1;
+            }
             crate::serial_println!(
                 "[NET] IP protocol {} from {:?}",
                 ip_header.protocol as u8,
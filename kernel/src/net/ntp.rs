@@ -343,9 +343,7 @@ fn ntp_request(socket: usize, device_index: usize, server_ip: Ipv4Address) -> Re
         }
 
         polls += 1;
-        for _ in 0..100 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 }
 
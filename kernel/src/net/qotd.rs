@@ -149,9 +149,7 @@ pub fn request_quote(
             return Err("QOTD timeout");
         }
 
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 }
 
@@ -592,9 +592,7 @@ pub fn get(
         }
 
         // Small delay to avoid busy waiting
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 }
 
@@ -3,11 +3,21 @@
 //! RFC 5424 - The Syslog Protocol
 //! RFC 3164 - BSD Syslog Protocol (legacy)
 //! Send log messages to remote syslog servers.
+//!
+//! # Remote Log Sink
+//! The sink streams kernel diagnostics to a collector so that long
+//! unattended runs keep their logs even if the serial console is lost.
+//! Event log records (or, optionally, every console line) are queued
+//! from any context and sent with RFC 5424 framing by a green task once
+//! a device has a route to the collector; records raised during early
+//! boot go out as soon as networking comes up. Each datagram carries a
+//! `[meta sequenceId]` element so gaps are visible on the collector.
 
 extern crate alloc;
 
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicU32, AtomicBool, Ordering};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicBool, AtomicUsize, Ordering};
 use super::udp;
 use super::ip::Ipv4Address;
 use crate::ke::SpinLock;
@@ -95,6 +105,21 @@ pub enum Severity {
 }
 
 impl Severity {
+    /// Severity from its numeric level (0-7)
+    pub fn from_level(level: u32) -> Option<Self> {
+        Some(match level {
+            0 => Severity::Emergency,
+            1 => Severity::Alert,
+            2 => Severity::Critical,
+            3 => Severity::Error,
+            4 => Severity::Warning,
+            5 => Severity::Notice,
+            6 => Severity::Info,
+            7 => Severity::Debug,
+            _ => return None,
+        })
+    }
+
     /// Get severity name
     pub fn name(&self) -> &'static str {
         match self {
@@ -219,11 +244,15 @@ fn format_bsd_message(
 }
 
 /// Format RFC 5424 syslog message
+///
+/// `msg_id` and `structured_data` are written verbatim; pass "-" for NILVALUE.
 fn format_rfc5424_message(
     facility: Facility,
     severity: Severity,
     hostname: &[u8],
     app_name: &[u8],
+    msg_id: &[u8],
+    structured_data: &[u8],
     message: &str,
 ) -> Vec<u8> {
     let mut buf = Vec::with_capacity(MAX_MESSAGE_SIZE);
@@ -234,9 +263,13 @@ fn format_rfc5424_message(
     write_u8_decimal(&mut buf, pri);
     buf.extend_from_slice(b">1 ");
 
-    // Timestamp (NILVALUE for now)
-    buf.push(b'-');
-    buf.push(b' ');
+    // Timestamp from the RTC, which runs on UTC
+    let dt = crate::hal::rtc::get_datetime();
+    let _ = write!(
+        ByteWriter(&mut buf),
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z ",
+        dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+    );
 
     // Hostname
     buf.extend_from_slice(hostname);
@@ -250,12 +283,12 @@ fn format_rfc5424_message(
     buf.push(b'-');
     buf.push(b' ');
 
-    // Msgid (NILVALUE)
-    buf.push(b'-');
+    // Msgid
+    buf.extend_from_slice(msg_id);
     buf.push(b' ');
 
-    // Structured data (NILVALUE)
-    buf.push(b'-');
+    // Structured data
+    buf.extend_from_slice(structured_data);
     buf.push(b' ');
 
     // Message (UTF-8 BOM optional, we skip it)
@@ -267,6 +300,16 @@ fn format_rfc5424_message(
     buf
 }
 
+/// `fmt::Write` adapter for a byte buffer
+struct ByteWriter<'a>(&'a mut Vec<u8>);
+
+impl Write for ByteWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
 /// Write u8 as decimal string
 fn write_u8_decimal(buf: &mut Vec<u8>, val: u8) {
     if val >= 100 {
@@ -340,7 +383,7 @@ pub fn send_with_facility(
     let app_name = &config.app_name[..config.app_name_len];
 
     let packet = if config.use_rfc5424 {
-        format_rfc5424_message(facility, severity, hostname, app_name, message)
+        format_rfc5424_message(facility, severity, hostname, app_name, b"-", b"-", message)
     } else {
         format_bsd_message(facility, severity, hostname, app_name, message)
     };
@@ -436,6 +479,412 @@ pub fn set_server(device_index: usize, server_ip: Ipv4Address, port: u16) {
     crate::serial_println!("[SYSLOG] Server configured: {:?}:{}", server_ip, port);
 }
 
+// ============================================================================
+// Remote Log Sink
+// ============================================================================
+
+/// Registry key holding the boot-time sink configuration
+const SINK_KEY: &str = "MACHINE\\SYSTEM\\CurrentControlSet\\Services\\Syslog\\Parameters";
+
+/// Records held while the network is down
+const SINK_QUEUE_DEPTH: usize = 64;
+
+/// Longest message text kept per record
+const SINK_TEXT_SIZE: usize = 240;
+
+/// Interval between forwarder passes
+pub const SINK_FLUSH_INTERVAL_MS: u64 = 250;
+
+/// Remote sink configuration
+#[derive(Debug, Clone, Copy)]
+pub struct SinkConfig {
+    pub server_ip: Ipv4Address,
+    pub server_port: u16,
+    /// Least severe level forwarded
+    pub min_severity: Severity,
+    /// Forward every console line instead of event log records only
+    pub forward_console: bool,
+}
+
+/// A queued log record
+#[derive(Clone, Copy)]
+struct SinkRecord {
+    severity: Severity,
+    /// RFC 5424 MSGID: the event source, or "console"
+    msg_id: &'static str,
+    len: usize,
+    text: [u8; SINK_TEXT_SIZE],
+}
+
+const EMPTY_RECORD: SinkRecord = SinkRecord {
+    severity: Severity::Debug,
+    msg_id: "-",
+    len: 0,
+    text: [0; SINK_TEXT_SIZE],
+};
+
+/// Ring of records awaiting the forwarder
+struct SinkQueue {
+    records: [SinkRecord; SINK_QUEUE_DEPTH],
+    head: usize,
+    count: usize,
+}
+
+static SINK_QUEUE: SpinLock<SinkQueue> = SpinLock::new(SinkQueue {
+    records: [EMPTY_RECORD; SINK_QUEUE_DEPTH],
+    head: 0,
+    count: 0,
+});
+
+static SINK_CONFIG: SpinLock<Option<SinkConfig>> = SpinLock::new(None);
+static SINK_ENABLED: AtomicBool = AtomicBool::new(false);
+static SINK_CONSOLE: AtomicBool = AtomicBool::new(false);
+static SINK_MIN_SEVERITY: AtomicU32 = AtomicU32::new(Severity::Info as u32);
+static SINK_TASK_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Set while the forwarder sends, so its own network logging is not queued
+static SINK_FORWARDING: AtomicBool = AtomicBool::new(false);
+/// UDP socket owned by the forwarder (usize::MAX = none)
+static SINK_SOCKET: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+static SINK_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+static SINK_QUEUED: AtomicU64 = AtomicU64::new(0);
+static SINK_FORWARDED: AtomicU64 = AtomicU64::new(0);
+static SINK_DROPPED: AtomicU64 = AtomicU64::new(0);
+static SINK_SEND_ERRORS: AtomicU64 = AtomicU64::new(0);
+/// Drops not yet reported to the collector
+static SINK_UNREPORTED_DROPS: AtomicU64 = AtomicU64::new(0);
+
+/// Formats into a fixed buffer, dropping whatever does not fit
+struct TruncatingWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    full: bool,
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            let n = ch.len_utf8();
+            if self.full || self.len + n > self.buf.len() {
+                self.full = true;
+                break;
+            }
+            ch.encode_utf8(&mut self.buf[self.len..]);
+            self.len += n;
+        }
+        Ok(())
+    }
+}
+
+/// Map an event log type to a syslog severity
+fn event_severity(event_type: crate::ex::eventlog::EventType) -> Severity {
+    use crate::ex::eventlog::EventType;
+
+    match event_type {
+        EventType::Error | EventType::FailureAudit => Severity::Error,
+        EventType::Warning => Severity::Warning,
+        EventType::SuccessAudit => Severity::Notice,
+        EventType::Information => Severity::Info,
+    }
+}
+
+/// Queue a record if the sink is running and the severity passes its filter
+fn sink_enqueue(severity: Severity, msg_id: &'static str, args: fmt::Arguments) {
+    if !SINK_ENABLED.load(Ordering::Relaxed) || SINK_FORWARDING.load(Ordering::Relaxed) {
+        return;
+    }
+    if severity as u32 > SINK_MIN_SEVERITY.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut text = [0u8; SINK_TEXT_SIZE];
+    let mut writer = TruncatingWriter { buf: &mut text, len: 0, full: false };
+    let _ = writer.write_fmt(args);
+    let written = writer.len;
+    let len = text[..written].trim_ascii_end().len();
+    if len == 0 {
+        return;
+    }
+
+    // Records may be raised from interrupt context; never spin here
+    let queued = SINK_QUEUE.try_lock().is_some_and(|mut queue| {
+        if queue.count == SINK_QUEUE_DEPTH {
+            return false;
+        }
+        let slot = (queue.head + queue.count) % SINK_QUEUE_DEPTH;
+        queue.records[slot] = SinkRecord { severity, msg_id, len, text };
+        queue.count += 1;
+        true
+    });
+
+    if queued {
+        SINK_QUEUED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        SINK_DROPPED.fetch_add(1, Ordering::Relaxed);
+        SINK_UNREPORTED_DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Offer an event log record to the sink
+///
+/// Skipped when console lines are forwarded, since the event log echoes
+/// every record to the console.
+pub fn sink_submit_event(
+    event_type: crate::ex::eventlog::EventType,
+    source: &'static str,
+    event_id: u32,
+    description: &str,
+) {
+    if SINK_CONSOLE.load(Ordering::Relaxed) {
+        return;
+    }
+    sink_enqueue(event_severity(event_type), source, format_args!("Event {}: {}", event_id, description));
+}
+
+/// Offer a console line to the sink
+pub fn sink_submit_console(args: fmt::Arguments) {
+    if SINK_CONSOLE.load(Ordering::Relaxed) {
+        sink_enqueue(Severity::Info, "console", args);
+    }
+}
+
+/// Pick the device to reach `server` through and the next-hop address
+fn sink_route(server: Ipv4Address) -> Option<(usize, Ipv4Address)> {
+    let mut fallback = None;
+    for index in 0..super::get_device_count() {
+        let device = match super::get_device(index) {
+            Some(d) if d.is_ready() => d,
+            _ => continue,
+        };
+        let (ip, mask) = match (device.ip_address, device.subnet_mask) {
+            (Some(ip), Some(mask)) => (ip, mask),
+            _ => continue,
+        };
+        if ip.same_subnet(&server, &mask) {
+            return Some((index, server));
+        }
+        if fallback.is_none() && !ip.is_loopback() {
+            fallback = device.gateway.map(|gateway| (index, gateway));
+        }
+    }
+    fallback
+}
+
+/// Send one record to the collector
+fn sink_send(config: &SinkConfig, device_index: usize, socket: usize, record: &SinkRecord) -> Result<(), &'static str> {
+    let (hostname, hostname_len) = {
+        let _guard = SYSLOG_LOCK.lock();
+        match unsafe { SYSLOG_CONFIG.as_ref() } {
+            Some(c) => (c.hostname, c.hostname_len),
+            None => {
+                let defaults = SyslogConfig::default();
+                (defaults.hostname, defaults.hostname_len)
+            }
+        }
+    };
+
+    let mut structured_data = Vec::with_capacity(40);
+    let sequence = SINK_SEQUENCE.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+    let _ = write!(ByteWriter(&mut structured_data), "[meta sequenceId=\"{}\"]", sequence);
+
+    let text = core::str::from_utf8(&record.text[..record.len]).unwrap_or("<invalid UTF-8>");
+    let packet = format_rfc5424_message(
+        Facility::Kern,
+        record.severity,
+        &hostname[..hostname_len],
+        b"kernel",
+        record.msg_id.as_bytes(),
+        &structured_data,
+        text,
+    );
+
+    udp::socket_sendto(socket, device_index, config.server_ip, config.server_port, &packet)?;
+    BYTES_SENT.fetch_add(packet.len() as u32, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Forward queued records once the network can reach the collector
+///
+/// Returns the number of records sent. Records stay queued while there is
+/// no route or the next hop is not in the ARP cache; the forwarder asks
+/// for the address instead of blocking on resolution.
+pub fn sink_flush() -> usize {
+    if !SINK_ENABLED.load(Ordering::Acquire) {
+        return 0;
+    }
+    let config = match *SINK_CONFIG.lock() {
+        Some(config) => config,
+        None => return 0,
+    };
+    let (device_index, next_hop) = match sink_route(config.server_ip) {
+        Some(route) => route,
+        None => return 0,
+    };
+
+    SINK_FORWARDING.store(true, Ordering::Release);
+    let sent = sink_forward(&config, device_index, next_hop);
+    SINK_FORWARDING.store(false, Ordering::Release);
+    sent
+}
+
+fn sink_forward(config: &SinkConfig, device_index: usize, next_hop: Ipv4Address) -> usize {
+    if super::arp::arp_cache_lookup(next_hop).is_none() {
+        if let Some(device) = super::get_device(device_index) {
+            if let Some(ip) = device.ip_address {
+                let _ = super::arp::send_arp_request(device_index, device.info.mac_address, ip, next_hop);
+            }
+        }
+        return 0;
+    }
+
+    let mut socket = SINK_SOCKET.load(Ordering::Acquire);
+    if socket == usize::MAX {
+        socket = match udp::socket_create() {
+            Some(s) => s,
+            None => return 0,
+        };
+        SINK_SOCKET.store(socket, Ordering::Release);
+    }
+
+    let mut sent = 0;
+
+    let dropped = SINK_UNREPORTED_DROPS.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let mut notice = EMPTY_RECORD;
+        let mut writer = TruncatingWriter { buf: &mut notice.text, len: 0, full: false };
+        let _ = write!(writer, "{} log record(s) dropped while the sink queue was full", dropped);
+        notice.len = writer.len;
+        notice.severity = Severity::Warning;
+        notice.msg_id = "syslog";
+        if sink_send(config, device_index, socket, &notice).is_ok() {
+            sent += 1;
+        }
+    }
+
+    loop {
+        let record = {
+            let mut queue = SINK_QUEUE.lock();
+            if queue.count == 0 {
+                break;
+            }
+            let record = queue.records[queue.head];
+            queue.head = (queue.head + 1) % SINK_QUEUE_DEPTH;
+            queue.count -= 1;
+            record
+        };
+
+        if sink_send(config, device_index, socket, &record).is_ok() {
+            SINK_FORWARDED.fetch_add(1, Ordering::Relaxed);
+            sent += 1;
+        } else {
+            SINK_SEND_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    sent
+}
+
+/// Start forwarding log records to a syslog collector
+pub fn sink_start(config: SinkConfig) -> Result<(), &'static str> {
+    if config.server_ip == Ipv4Address::ANY {
+        return Err("No syslog server given");
+    }
+
+    *SINK_CONFIG.lock() = Some(config);
+    SINK_MIN_SEVERITY.store(config.min_severity as u32, Ordering::Relaxed);
+    SINK_CONSOLE.store(config.forward_console, Ordering::Relaxed);
+    SINK_ENABLED.store(true, Ordering::Release);
+
+    if !SINK_TASK_RUNNING.swap(true, Ordering::AcqRel) {
+        let spawned = crate::ex::task::ex_spawn_task("syslogsink", async {
+            loop {
+                crate::ex::task::ex_task_sleep(SINK_FLUSH_INTERVAL_MS).await;
+                sink_flush();
+            }
+        });
+        if let Err(e) = spawned {
+            SINK_TASK_RUNNING.store(false, Ordering::Release);
+            SINK_ENABLED.store(false, Ordering::Release);
+            return Err(e);
+        }
+    }
+
+    crate::serial_println!(
+        "[SYSLOG] Remote sink forwarding to {}:{} ({} and above{})",
+        config.server_ip,
+        config.server_port,
+        config.min_severity.name(),
+        if config.forward_console { ", console" } else { "" }
+    );
+    Ok(())
+}
+
+/// Stop forwarding and discard queued records
+pub fn sink_stop() {
+    SINK_ENABLED.store(false, Ordering::Release);
+    let mut queue = SINK_QUEUE.lock();
+    queue.head = 0;
+    queue.count = 0;
+}
+
+/// Start the sink from the registry, if a server is configured there
+///
+/// Values under `SINK_KEY`: `RemoteServer` (dotted IPv4, required),
+/// `RemotePort`, `MinimumSeverity` (0-7) and `ForwardConsole`.
+pub fn sink_start_at_boot() {
+    let server = match unsafe { crate::cm::cm_read_string(SINK_KEY, "RemoteServer") } {
+        Some(s) => s,
+        None => return,
+    };
+    let server_ip = match Ipv4Address::parse(server.trim()) {
+        Some(ip) => ip,
+        None => {
+            crate::serial_println!("[SYSLOG] Ignoring invalid RemoteServer '{}'", server);
+            return;
+        }
+    };
+
+    let read = |name| unsafe { crate::cm::cm_read_dword(SINK_KEY, name) };
+    let config = SinkConfig {
+        server_ip,
+        server_port: read("RemotePort").and_then(|p| u16::try_from(p).ok()).unwrap_or(SYSLOG_PORT),
+        min_severity: read("MinimumSeverity").and_then(Severity::from_level).unwrap_or(Severity::Info),
+        forward_console: read("ForwardConsole").unwrap_or(0) != 0,
+    };
+    if let Err(e) = sink_start(config) {
+        crate::serial_println!("[SYSLOG] Remote sink: {}", e);
+    }
+}
+
+/// Remote sink status
+#[derive(Debug, Clone, Copy)]
+pub struct SinkStatus {
+    pub enabled: bool,
+    pub config: Option<SinkConfig>,
+    /// Device and next hop in use, if the collector is reachable
+    pub route: Option<(usize, Ipv4Address)>,
+    pub queued: u64,
+    pub pending: usize,
+    pub forwarded: u64,
+    pub dropped: u64,
+    pub send_errors: u64,
+}
+
+/// Get the remote sink status
+pub fn sink_status() -> SinkStatus {
+    let config = *SINK_CONFIG.lock();
+    SinkStatus {
+        enabled: SINK_ENABLED.load(Ordering::Acquire),
+        config,
+        route: config.and_then(|c| sink_route(c.server_ip)),
+        queued: SINK_QUEUED.load(Ordering::Relaxed),
+        pending: SINK_QUEUE.lock().count,
+        forwarded: SINK_FORWARDED.load(Ordering::Relaxed),
+        dropped: SINK_DROPPED.load(Ordering::Relaxed),
+        send_errors: SINK_SEND_ERRORS.load(Ordering::Relaxed),
+    }
+}
+
 /// Initialize syslog module
 pub fn init() {
     unsafe {
@@ -504,7 +504,15 @@ pub fn socket_connect(
         &[],
     );
 
-    send_tcp_segment(device_index, src_ip, remote_ip, &segment)?;
+    if let Err(e) = send_tcp_segment(device_index, src_ip, remote_ip, &segment) {
+        unsafe {
+            super::PROTOCOL_STATS.tcp.failed_attempts += 1;
+        }
+        return Err(e);
+    }
+    unsafe {
+        super::PROTOCOL_STATS.tcp.active_opens += 1;
+    }
     crate::serial_println!("[TCP] SYN sent to {:?}:{}", remote_ip, remote_port);
 
     Ok(())
@@ -735,6 +743,10 @@ fn send_tcp_segment(
     if let Some(device_mut) = super::get_device_mut(device_index) {
         device_mut.transmit(&frame)?;
         super::record_tx_packet(frame.len());
+        unsafe {
+            super::PROTOCOL_STATS.ip.sent += 1;
+            super::PROTOCOL_STATS.tcp.segments_sent += 1;
+        }
         Ok(())
     } else {
         Err("Device not found")
@@ -818,6 +830,9 @@ fn handle_tcp_for_socket(
     // Handle RST
     if tcp_header.is_rst() {
         crate::serial_println!("[TCP] RST received, closing connection");
+        unsafe {
+            super::PROTOCOL_STATS.tcp.resets_received += 1;
+        }
         tcb.state = TcpState::Closed;
         return;
     }
@@ -827,6 +842,9 @@ fn handle_tcp_for_socket(
             // Expecting SYN
             if tcp_header.is_syn() && !tcp_header.is_ack() {
                 crate::serial_println!("[TCP] SYN received on listening socket");
+                unsafe {
+                    super::PROTOCOL_STATS.tcp.passive_opens += 1;
+                }
 
                 // Generate ISN and send SYN-ACK
                 tcb.iss = generate_isn();
@@ -1057,7 +1075,11 @@ fn send_rst(
         0,
         &[],
     );
-    let _ = send_tcp_segment(device_index, src_ip, dst_ip, &segment);
+    if send_tcp_segment(device_index, src_ip, dst_ip, &segment).is_ok() {
+        unsafe {
+            super::PROTOCOL_STATS.tcp.resets_sent += 1;
+        }
+    }
 }
 
 /// Get TCP socket statistics
@@ -1103,6 +1125,7 @@ pub fn get_socket_info(socket: TcpSocket) -> Option<(TcpState, u16, u16, Ipv4Add
 pub struct TcpConnectionInfo {
     pub socket_id: usize,
     pub state: TcpState,
+    pub local_ip: Ipv4Address,
     pub local_port: u16,
     pub remote_port: u16,
     pub remote_ip: Ipv4Address,
@@ -1119,9 +1142,18 @@ pub fn enumerate_connections() -> alloc::vec::Vec<TcpConnectionInfo> {
             for (i, socket) in sockets.iter().enumerate() {
                 let tcb = socket.lock();
                 if tcb.is_open && tcb.state != TcpState::Closed {
+                    // Listeners accept on any address
+                    let local_ip = if tcb.state == TcpState::Listen {
+                        Ipv4Address::ANY
+                    } else {
+                        super::get_device(tcb.device_index)
+                            .and_then(|d| d.ip_address)
+                            .unwrap_or(Ipv4Address::ANY)
+                    };
                     connections.push(TcpConnectionInfo {
                         socket_id: i,
                         state: tcb.state,
+                        local_ip,
                         local_port: tcb.local_port,
                         remote_port: tcb.remote_port,
                         remote_ip: tcb.remote_ip,
@@ -292,9 +292,7 @@ pub fn get(
             }
 
             polls += 1;
-            for _ in 0..100 {
-                core::hint::spin_loop();
-            }
+            crate::ex::ex_sleep(1);
         }
 
         if !received {
@@ -174,9 +174,7 @@ pub fn request_time(
             return Err("TIME timeout");
         }
 
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 }
 
@@ -351,6 +351,10 @@ pub fn socket_sendto(
     if let Some(device) = super::get_device_mut(device_index) {
         device.transmit(&frame)?;
         super::record_tx_packet(frame.len());
+        unsafe {
+            super::PROTOCOL_STATS.ip.sent += 1;
+            super::PROTOCOL_STATS.udp.datagrams_sent += 1;
+        }
         Ok(data.len())
     } else {
         Err("Device not found")
@@ -416,6 +420,7 @@ pub fn handle_udp_packet(
                 // Check if queue has space
                 if socket.rx_count >= MAX_RX_QUEUE {
                     crate::serial_println!("[UDP] Socket receive queue full, dropping packet");
+                    super::PROTOCOL_STATS.udp.receive_errors += 1;
                     return;
                 }
 
@@ -442,6 +447,7 @@ pub fn handle_udp_packet(
         }
 
         crate::serial_println!("[UDP] No socket bound to port {}", udp.dst_port);
+        super::PROTOCOL_STATS.udp.no_port += 1;
     }
 }
 
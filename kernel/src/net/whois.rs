@@ -82,9 +82,7 @@ pub fn whois_query(
             return Err("WHOIS timeout");
         }
 
-        for _ in 0..100 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(1);
     }
 
     let _ = tcp::socket_close(socket);
@@ -99,6 +99,20 @@ impl ObjectDirectory {
         }
     }
 
+    /// Object body pointer used for directory entries and lookups
+    ///
+    /// The header is the first field, so the body begins right after it.
+    #[inline]
+    pub fn object_body(&self) -> *mut u8 {
+        self.header.body()
+    }
+
+    /// Recover the directory from an object body pointer
+    #[inline]
+    pub unsafe fn from_object(object: *mut u8) -> *mut ObjectDirectory {
+        ObjectHeader::from_body(object) as *mut ObjectDirectory
+    }
+
     /// Set directory name
     unsafe fn set_name(&mut self, name: &[u8], parent: *mut ObjectDirectory) {
         // Allocate name info (for now, use static storage - need proper allocator)
@@ -298,7 +312,7 @@ pub fn directory_parse_procedure(
     }
 
     unsafe {
-        let dir = object as *mut ObjectDirectory;
+        let dir = ObjectDirectory::from_object(object);
 
         // Find the first component of the remaining name
         let mut component_end = remaining_name.len();
@@ -393,26 +407,17 @@ pub unsafe fn init_namespace() {
     // Initialize ObjectTypes directory
     OBJECT_TYPES_DIRECTORY.init(Some(b"ObjectTypes"), &mut ROOT_DIRECTORY);
     OBJECT_TYPES_DIRECTORY.header.set_flag(flags::OB_FLAG_PERMANENT);
-    ROOT_DIRECTORY.insert(
-        &mut OBJECT_TYPES_DIRECTORY as *mut _ as *mut u8,
-        b"ObjectTypes",
-    );
+    ROOT_DIRECTORY.insert(OBJECT_TYPES_DIRECTORY.object_body(), b"ObjectTypes");
 
     // Initialize BaseNamedObjects directory
     BASE_NAMED_OBJECTS.init(Some(b"BaseNamedObjects"), &mut ROOT_DIRECTORY);
     BASE_NAMED_OBJECTS.header.set_flag(flags::OB_FLAG_PERMANENT);
-    ROOT_DIRECTORY.insert(
-        &mut BASE_NAMED_OBJECTS as *mut _ as *mut u8,
-        b"BaseNamedObjects",
-    );
+    ROOT_DIRECTORY.insert(BASE_NAMED_OBJECTS.object_body(), b"BaseNamedObjects");
 
     // Initialize Device directory
     DEVICE_DIRECTORY.init(Some(b"Device"), &mut ROOT_DIRECTORY);
     DEVICE_DIRECTORY.header.set_flag(flags::OB_FLAG_PERMANENT);
-    ROOT_DIRECTORY.insert(
-        &mut DEVICE_DIRECTORY as *mut _ as *mut u8,
-        b"Device",
-    );
+    ROOT_DIRECTORY.insert(DEVICE_DIRECTORY.object_body(), b"Device");
 
     crate::serial_println!("[OB] Namespace initialized");
     crate::serial_println!("[OB]   \\ObjectTypes");
@@ -436,15 +441,11 @@ pub unsafe fn ob_lookup_object(path: &[u8]) -> *mut u8 {
     let path = &path[1..];
 
     if path.is_empty() {
-        return &mut ROOT_DIRECTORY as *mut _ as *mut u8;
+        return ROOT_DIRECTORY.object_body();
     }
 
     let mut found: *mut u8 = ptr::null_mut();
-    let status = directory_parse_procedure(
-        &mut ROOT_DIRECTORY as *mut _ as *mut u8,
-        path,
-        &mut found,
-    );
+    let status = directory_parse_procedure(ROOT_DIRECTORY.object_body(), path, &mut found);
 
     if status == 0 {
         found
@@ -495,7 +496,8 @@ pub struct DirectoryStats {
 pub fn ob_get_directory_stats() -> DirectoryStats {
     unsafe {
         DirectoryStats {
-            directory_count: 4, // Root, ObjectTypes, BaseNamedObjects, Device
+            // Root, ObjectTypes, BaseNamedObjects, Device, plus session directories
+            directory_count: 4 + super::session::ob_session_directory_count(),
             root_entry_count: ROOT_DIRECTORY.count(),
             object_types_count: OBJECT_TYPES_DIRECTORY.count(),
             base_named_count: BASE_NAMED_OBJECTS.count(),
@@ -21,6 +21,7 @@
 //! - `\Device` - Device objects
 //! - `\DosDevices` - Drive letters (C:, etc.)
 //! - `\Global??` - Per-session devices
+//! - `\Sessions\N` - Per-session named objects and window stations
 //!
 //! # Key Structures
 //!
@@ -34,6 +35,7 @@ pub mod directory;
 pub mod handle;
 pub mod header;
 pub mod object_type;
+pub mod session;
 pub mod symlink;
 
 // Re-exports for convenience
@@ -62,9 +64,18 @@ pub use directory::{
 pub use symlink::{
     ObjectSymbolicLink, symlink_flags, symlink_access,
     ob_create_symbolic_link, ob_create_symbolic_link_ex, ob_create_dos_device_link,
+    ob_create_owned_symbolic_link, ob_delete_owned_symbolic_links, ob_list_directory_links,
     ob_delete_symbolic_link, ob_query_symbolic_link, ob_parse_symbolic_link,
     ob_resolve_symbolic_links, ob_is_symbolic_link, ob_list_symbolic_links,
     ob_get_symlink_stats, obp_symlink_init,
+    ob_define_dos_device, ob_delete_dos_device, ob_query_dos_device, ob_list_dos_devices,
+};
+pub use session::{
+    Session, WindowStation, SessionSnapshot, winsta_flags,
+    SESSION_SERVICES, SESSION_CONSOLE, MAX_SESSIONS,
+    ob_create_session, ob_get_session, ob_current_session_id,
+    ob_get_session_named_objects, ob_resolve_session_name,
+    ob_lookup_window_station, ob_session_process_count, ob_get_session_snapshots,
 };
 
 /// Initialize the Object Manager
@@ -86,5 +97,8 @@ pub unsafe fn init() {
     // Initialize symbolic links
     symlink::obp_symlink_init();
 
+    // Create the services and console sessions
+    session::init_sessions();
+
     crate::serial_println!("[OB] Object Manager initialized");
 }
@@ -19,6 +19,8 @@
 //! - File
 //! - Section
 //! - Key (registry)
+//! - Session
+//! - WindowStation
 
 use core::sync::atomic::{AtomicU32, Ordering};
 use core::ptr;
@@ -44,6 +46,8 @@ pub mod type_index {
     pub const TYPE_TOKEN: u8 = 13;
     pub const TYPE_DEVICE: u8 = 14;
     pub const TYPE_DRIVER: u8 = 15;
+    pub const TYPE_SESSION: u8 = 16;
+    pub const TYPE_WINDOW_STATION: u8 = 17;
 }
 
 /// Open procedure - called when a handle is created
@@ -469,6 +473,42 @@ pub unsafe fn init_object_types() {
         ObjectTypeCallbacks::new(),
     );
 
+    // Initialize Session type
+    let session_type_info = ObjectTypeInfo {
+        object_body_size: core::mem::size_of::<super::session::Session>() as u32,
+        default_quota: 0,
+        valid_access_mask: 0x000F0003, // SESSION_ALL_ACCESS
+        pool_type: 0,
+        maintain_handle_count: false,
+        allow_naming: false,
+        security_required: false,
+    };
+
+    create_object_type(
+        b"Session",
+        type_index::TYPE_SESSION,
+        session_type_info,
+        ObjectTypeCallbacks::new(),
+    );
+
+    // Initialize WindowStation type
+    let winsta_type_info = ObjectTypeInfo {
+        object_body_size: core::mem::size_of::<super::session::WindowStation>() as u32,
+        default_quota: 0,
+        valid_access_mask: 0x000F037F, // WINSTA_ALL_ACCESS
+        pool_type: 0,
+        maintain_handle_count: true,
+        allow_naming: true,
+        security_required: false,
+    };
+
+    create_object_type(
+        b"WindowStation",
+        type_index::TYPE_WINDOW_STATION,
+        winsta_type_info,
+        ObjectTypeCallbacks::new(),
+    );
+
     crate::serial_println!("[OB] Object types initialized");
 }
 
@@ -0,0 +1,484 @@
+//! Sessions and Window Stations
+//!
+//! A session groups the processes of one logon with their own slice of the
+//! object namespace, so named objects (and later the GUI) of different
+//! logons cannot collide:
+//!
+//! - Session 0 hosts the Idle/System processes and services
+//! - Session 1 is the interactive console running the shell
+//!
+//! # Namespace Layout
+//! - `\Sessions\N` - Session root directory
+//! - `\Sessions\N\BaseNamedObjects` - Named objects of session N (N >= 1);
+//!   session 0 uses the global `\BaseNamedObjects`, as on NT
+//! - `\Sessions\N\Windows\WindowStations` - Window stations of session N
+//!
+//! # Name Resolution
+//! Object names prefixed with `Global\` always resolve in session 0's
+//! namespace. `Local\` and unprefixed names resolve in the caller's session.
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use super::directory::{ObjectDirectory, get_root_directory, get_base_named_objects};
+use super::header::{ObjectHeader, ObjectNameInfo, flags};
+use super::object_type::{type_index, get_object_type_mut};
+use crate::ke::SpinLock;
+
+/// Session used by services and the system processes
+pub const SESSION_SERVICES: u32 = 0;
+
+/// Interactive console session
+pub const SESSION_CONSOLE: u32 = 1;
+
+/// Maximum number of sessions
+pub const MAX_SESSIONS: usize = 4;
+
+/// Maximum window stations per session
+pub const MAX_SESSION_WINDOW_STATIONS: usize = 2;
+
+/// Interactive window station name
+pub const INTERACTIVE_WINDOW_STATION: &[u8] = b"WinSta0";
+
+/// Non-interactive window station for services running as LocalSystem
+pub const SERVICE_WINDOW_STATION: &[u8] = b"Service-0x0-3e7$";
+
+/// Window station flags
+pub mod winsta_flags {
+    /// Window station can display user interface
+    pub const WSF_VISIBLE: u32 = 0x0001;
+    /// Window station has no input or display devices
+    pub const WSF_NOIO: u32 = 0x0004;
+}
+
+const STATUS_SUCCESS: i32 = 0;
+const STATUS_INVALID_PARAMETER: i32 = 0xC000000Du32 as i32;
+const STATUS_OBJECT_NAME_COLLISION: i32 = 0xC0000035u32 as i32;
+const STATUS_INSUFFICIENT_RESOURCES: i32 = 0xC000009Au32 as i32;
+
+// ============================================================================
+// Window Station Object
+// ============================================================================
+
+/// Window station object
+///
+/// Groundwork for win32k: holds the clipboard, atom table and desktops of
+/// a session once the GUI is session-aware.
+#[repr(C)]
+pub struct WindowStation {
+    /// Object header
+    pub header: ObjectHeader,
+    /// Object name storage
+    name_info: ObjectNameInfo,
+    /// Owning session
+    pub session_id: u32,
+    /// Window station flags (WSF_*)
+    pub flags: u32,
+    /// Slot in use
+    in_use: bool,
+}
+
+impl WindowStation {
+    const fn new() -> Self {
+        Self {
+            header: ObjectHeader::new(),
+            name_info: ObjectNameInfo::new(),
+            session_id: 0,
+            flags: 0,
+            in_use: false,
+        }
+    }
+
+    /// Window station name
+    pub fn name(&self) -> &[u8] {
+        self.name_info.name_slice()
+    }
+
+    /// Object body pointer used for directory entries
+    #[inline]
+    pub fn object_body(&self) -> *mut u8 {
+        self.header.body()
+    }
+
+    /// Recover the window station from an object body pointer
+    #[inline]
+    pub unsafe fn from_object(object: *mut u8) -> *mut WindowStation {
+        ObjectHeader::from_body(object) as *mut WindowStation
+    }
+
+    unsafe fn init(&mut self, name: &[u8], session_id: u32, flags: u32, parent: *mut ObjectDirectory) {
+        if let Some(winsta_type) = get_object_type_mut(type_index::TYPE_WINDOW_STATION) {
+            self.header.init(winsta_type);
+            winsta_type.increment_object_count();
+        }
+
+        self.name_info.set_name(name);
+        self.name_info.directory = parent;
+        self.header.name_info = &mut self.name_info;
+        self.header.set_flag(flags::OB_FLAG_NAMED);
+        self.header.set_flag(flags::OB_FLAG_PERMANENT);
+
+        self.session_id = session_id;
+        self.flags = flags;
+        self.in_use = true;
+    }
+}
+
+// ============================================================================
+// Session Object
+// ============================================================================
+
+/// Session object
+#[repr(C)]
+pub struct Session {
+    /// Object header
+    pub header: ObjectHeader,
+    /// Session ID
+    pub session_id: u32,
+    /// Session name (e.g. "Console")
+    name: [u8; 16],
+    name_length: usize,
+    /// Session has been created
+    active: AtomicBool,
+    /// Tick count at creation
+    pub create_time: u64,
+    /// \Sessions\N
+    root: ObjectDirectory,
+    /// \Sessions\N\BaseNamedObjects (unused for session 0)
+    base_named_objects: ObjectDirectory,
+    /// \Sessions\N\Windows
+    windows: ObjectDirectory,
+    /// \Sessions\N\Windows\WindowStations
+    window_stations_directory: ObjectDirectory,
+    /// Window station objects
+    window_stations: [WindowStation; MAX_SESSION_WINDOW_STATIONS],
+}
+
+// Safety: Session creation is serialized by SESSION_LOCK
+unsafe impl Sync for Session {}
+unsafe impl Send for Session {}
+
+impl Session {
+    const fn new() -> Self {
+        Self {
+            header: ObjectHeader::new(),
+            session_id: 0,
+            name: [0; 16],
+            name_length: 0,
+            active: AtomicBool::new(false),
+            create_time: 0,
+            root: ObjectDirectory::new(),
+            base_named_objects: ObjectDirectory::new(),
+            windows: ObjectDirectory::new(),
+            window_stations_directory: ObjectDirectory::new(),
+            window_stations: [const { WindowStation::new() }; MAX_SESSION_WINDOW_STATIONS],
+        }
+    }
+
+    /// Check whether the session exists
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Session name
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_length]
+    }
+
+    /// Directory holding this session's named objects
+    pub fn named_objects_directory(&mut self) -> *mut ObjectDirectory {
+        if self.session_id == SESSION_SERVICES {
+            get_base_named_objects()
+        } else {
+            &mut self.base_named_objects
+        }
+    }
+
+    /// \Sessions\N\Windows\WindowStations directory
+    pub fn window_stations_directory(&mut self) -> *mut ObjectDirectory {
+        &mut self.window_stations_directory
+    }
+
+    /// Number of window stations in the session
+    pub fn window_station_count(&self) -> u32 {
+        self.window_stations.iter().filter(|w| w.in_use).count() as u32
+    }
+
+    /// Create a window station in this session
+    unsafe fn create_window_station(&mut self, name: &[u8], winsta_flags: u32) -> i32 {
+        let parent: *mut ObjectDirectory = &mut self.window_stations_directory;
+        let session_id = self.session_id;
+
+        let slot = match self.window_stations.iter_mut().find(|w| !w.in_use) {
+            Some(w) => w,
+            None => return STATUS_INSUFFICIENT_RESOURCES,
+        };
+
+        slot.init(name, session_id, winsta_flags, parent);
+        if !(*parent).insert(slot.object_body(), name) {
+            slot.in_use = false;
+            return STATUS_OBJECT_NAME_COLLISION;
+        }
+        STATUS_SUCCESS
+    }
+}
+
+// ============================================================================
+// Session Table
+// ============================================================================
+
+/// \Sessions directory
+static mut SESSIONS_DIRECTORY: ObjectDirectory = ObjectDirectory::new();
+
+/// Session objects, indexed by session ID
+static mut SESSION_TABLE: [Session; MAX_SESSIONS] = [const { Session::new() }; MAX_SESSIONS];
+
+/// Serializes session creation
+static SESSION_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// Create a session and its namespace directories
+///
+/// # Returns
+/// NTSTATUS (0 = success)
+///
+/// # Safety
+/// The object namespace must be initialized
+pub unsafe fn ob_create_session(session_id: u32, name: &[u8]) -> i32 {
+    if session_id as usize >= MAX_SESSIONS || name.is_empty() {
+        return STATUS_INVALID_PARAMETER;
+    }
+
+    let _guard = SESSION_LOCK.lock();
+    let session = &mut SESSION_TABLE[session_id as usize];
+    if session.is_active() {
+        return STATUS_OBJECT_NAME_COLLISION;
+    }
+
+    if let Some(session_type) = get_object_type_mut(type_index::TYPE_SESSION) {
+        session.header.init(session_type);
+        session.header.set_flag(flags::OB_FLAG_PERMANENT);
+        session_type.increment_object_count();
+    }
+
+    session.session_id = session_id;
+    let len = name.len().min(session.name.len());
+    session.name[..len].copy_from_slice(&name[..len]);
+    session.name_length = len;
+    session.create_time = crate::hal::apic::get_tick_count();
+
+    // \Sessions\N
+    let id_name = [b'0' + session_id as u8];
+    session.root.init(Some(&id_name), &mut SESSIONS_DIRECTORY);
+    session.root.header.set_flag(flags::OB_FLAG_PERMANENT);
+    SESSIONS_DIRECTORY.insert(session.root.object_body(), &id_name);
+
+    // \Sessions\N\BaseNamedObjects
+    if session_id != SESSION_SERVICES {
+        session.base_named_objects.init(Some(b"BaseNamedObjects"), &mut session.root);
+        session.base_named_objects.header.set_flag(flags::OB_FLAG_PERMANENT);
+        session.root.insert(session.base_named_objects.object_body(), b"BaseNamedObjects");
+    }
+
+    // \Sessions\N\Windows\WindowStations
+    session.windows.init(Some(b"Windows"), &mut session.root);
+    session.windows.header.set_flag(flags::OB_FLAG_PERMANENT);
+    session.root.insert(session.windows.object_body(), b"Windows");
+
+    session.window_stations_directory.init(Some(b"WindowStations"), &mut session.windows);
+    session.window_stations_directory.header.set_flag(flags::OB_FLAG_PERMANENT);
+    session.windows.insert(session.window_stations_directory.object_body(), b"WindowStations");
+
+    let status = if session_id == SESSION_SERVICES {
+        session.create_window_station(SERVICE_WINDOW_STATION, winsta_flags::WSF_NOIO)
+    } else {
+        session.create_window_station(INTERACTIVE_WINDOW_STATION, winsta_flags::WSF_VISIBLE)
+    };
+    if status != STATUS_SUCCESS {
+        crate::serial_println!("[OB] Session {}: window station creation failed ({:#x})",
+            session_id, status as u32);
+    }
+
+    session.active.store(true, Ordering::Release);
+
+    crate::serial_println!("[OB] Session {} '{}' created",
+        session_id, core::str::from_utf8(session.name()).unwrap_or("?"));
+
+    STATUS_SUCCESS
+}
+
+/// Get a session by ID
+///
+/// # Returns
+/// Pointer to the session, or null if it does not exist
+pub fn ob_get_session(session_id: u32) -> *mut Session {
+    if session_id as usize >= MAX_SESSIONS {
+        return ptr::null_mut();
+    }
+    unsafe {
+        let session = &mut SESSION_TABLE[session_id as usize];
+        if session.is_active() {
+            session as *mut Session
+        } else {
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Session of the current process (session 0 for kernel threads)
+pub fn ob_current_session_id() -> u32 {
+    let process = crate::ps::get_current_process();
+    if process.is_null() {
+        SESSION_SERVICES
+    } else {
+        unsafe { (*process).session_id }
+    }
+}
+
+/// Directory holding a session's named objects
+///
+/// Falls back to the global \BaseNamedObjects for unknown sessions.
+pub fn ob_get_session_named_objects(session_id: u32) -> *mut ObjectDirectory {
+    let session = ob_get_session(session_id);
+    if session.is_null() {
+        get_base_named_objects()
+    } else {
+        unsafe { (*session).named_objects_directory() }
+    }
+}
+
+/// Resolve a named-object name to the session whose namespace it lives in
+///
+/// Strips a `Global\` or `Local\` prefix and returns the remaining name.
+pub fn ob_resolve_session_name(name: &[u8]) -> (u32, &[u8]) {
+    const GLOBAL_PREFIX: &[u8] = b"Global\\";
+    const LOCAL_PREFIX: &[u8] = b"Local\\";
+
+    if name.len() >= GLOBAL_PREFIX.len()
+        && name[..GLOBAL_PREFIX.len()].eq_ignore_ascii_case(GLOBAL_PREFIX)
+    {
+        return (SESSION_SERVICES, &name[GLOBAL_PREFIX.len()..]);
+    }
+
+    let session_id = ob_current_session_id();
+    if name.len() >= LOCAL_PREFIX.len()
+        && name[..LOCAL_PREFIX.len()].eq_ignore_ascii_case(LOCAL_PREFIX)
+    {
+        return (session_id, &name[LOCAL_PREFIX.len()..]);
+    }
+    (session_id, name)
+}
+
+/// Look up a window station by name within a session
+pub fn ob_lookup_window_station(session_id: u32, name: &[u8]) -> *mut WindowStation {
+    let session = ob_get_session(session_id);
+    if session.is_null() {
+        return ptr::null_mut();
+    }
+    unsafe {
+        let object = (*(*session).window_stations_directory()).lookup(name);
+        if object.is_null() {
+            ptr::null_mut()
+        } else {
+            WindowStation::from_object(object)
+        }
+    }
+}
+
+/// Number of processes belonging to a session
+pub fn ob_session_process_count(session_id: u32) -> u32 {
+    let mut count = 0;
+    unsafe {
+        let list_head = crate::ps::get_active_process_list();
+        let mut entry = (*list_head).flink;
+        while entry != list_head {
+            let process = crate::containing_record!(entry, crate::ps::EProcess, active_process_links);
+            if (*process).session_id == session_id {
+                count += 1;
+            }
+            entry = (*entry).flink;
+        }
+    }
+    count
+}
+
+/// Number of namespace directories created for sessions
+pub fn ob_session_directory_count() -> u32 {
+    unsafe {
+        if SESSIONS_DIRECTORY.header.object_type.is_null() {
+            return 0;
+        }
+        let mut count = 1; // \Sessions
+        for session in SESSION_TABLE.iter().filter(|s| s.is_active()) {
+            // N, Windows, WindowStations and (except session 0) BaseNamedObjects
+            count += if session.session_id == SESSION_SERVICES { 3 } else { 4 };
+        }
+        count
+    }
+}
+
+// ============================================================================
+// Session Inspection
+// ============================================================================
+
+/// Snapshot of a session for debugging
+#[derive(Debug, Clone, Copy)]
+pub struct SessionSnapshot {
+    /// Session ID
+    pub session_id: u32,
+    /// Session name
+    pub name: [u8; 16],
+    /// Name length
+    pub name_length: u8,
+    /// Processes in the session
+    pub process_count: u32,
+    /// Window stations in the session
+    pub window_station_count: u32,
+    /// Tick count at creation
+    pub create_time: u64,
+}
+
+/// Get snapshots of all active sessions
+pub fn ob_get_session_snapshots() -> ([SessionSnapshot; MAX_SESSIONS], usize) {
+    let mut snapshots = [SessionSnapshot {
+        session_id: 0,
+        name: [0; 16],
+        name_length: 0,
+        process_count: 0,
+        window_station_count: 0,
+        create_time: 0,
+    }; MAX_SESSIONS];
+    let mut count = 0;
+
+    unsafe {
+        for session in SESSION_TABLE.iter().filter(|s| s.is_active()) {
+            let name = session.name();
+            let snap = &mut snapshots[count];
+            snap.session_id = session.session_id;
+            snap.name[..name.len()].copy_from_slice(name);
+            snap.name_length = name.len() as u8;
+            snap.process_count = ob_session_process_count(session.session_id);
+            snap.window_station_count = session.window_station_count();
+            snap.create_time = session.create_time;
+            count += 1;
+        }
+    }
+
+    (snapshots, count)
+}
+
+/// Initialize \Sessions with the services and console sessions
+///
+/// # Safety
+/// Must be called once after the namespace is initialized
+pub unsafe fn init_sessions() {
+    SESSIONS_DIRECTORY.init(Some(b"Sessions"), get_root_directory());
+    SESSIONS_DIRECTORY.header.set_flag(flags::OB_FLAG_PERMANENT);
+    (*get_root_directory()).insert(SESSIONS_DIRECTORY.object_body(), b"Sessions");
+
+    ob_create_session(SESSION_SERVICES, b"Services");
+    ob_create_session(SESSION_CONSOLE, b"Console");
+
+    crate::serial_println!("[OB]   \\Sessions\\0\\Windows\\WindowStations\\Service-0x0-3e7$");
+    crate::serial_println!("[OB]   \\Sessions\\1\\BaseNamedObjects");
+    crate::serial_println!("[OB]   \\Sessions\\1\\Windows\\WindowStations\\WinSta0");
+}
@@ -59,6 +59,8 @@ pub struct ObjectSymbolicLink {
     pub flags: AtomicU32,
     /// Reference count
     pub reference_count: AtomicU32,
+    /// Device object the link belongs to (0 if none); deleted with it
+    pub owner: usize,
 }
 
 impl ObjectSymbolicLink {
@@ -73,6 +75,7 @@ impl ObjectSymbolicLink {
             dos_device_drive_index: 0,
             flags: AtomicU32::new(0),
             reference_count: AtomicU32::new(1),
+            owner: 0,
         }
     }
 
@@ -140,6 +143,17 @@ struct SymlinkEntry {
 /// Global symbolic link table
 static SYMLINK_TABLE: RwLock<Vec<SymlinkEntry>> = RwLock::new(Vec::new());
 
+/// Release spare capacity of the table and its names (heap compaction)
+pub fn ob_compact_symbolic_links() -> usize {
+    let mut table = SYMLINK_TABLE.write();
+    let mut released = 0;
+    for entry in table.iter_mut() {
+        released += entry.name.capacity() - entry.name.len();
+        entry.name.shrink_to_fit();
+    }
+    released + crate::mm::defrag::mm_shrink_vec(&mut table)
+}
+
 /// Statistics
 static SYMLINK_COUNT: AtomicU32 = AtomicU32::new(0);
 static SYMLINK_LOOKUPS: AtomicU32 = AtomicU32::new(0);
@@ -164,32 +178,66 @@ pub fn ob_create_symbolic_link_ex(name: &str, target: &str, flags: u32) -> i32 {
         name
     };
 
-    // Check if link already exists
-    {
-        let table = SYMLINK_TABLE.read();
-        for entry in table.iter() {
-            if entry.name.eq_ignore_ascii_case(normalized_name) {
-                return -1073741790; // STATUS_OBJECT_NAME_COLLISION
-            }
-        }
-    }
-
     // Create the new link
     let link = ObjectSymbolicLink::new(target);
     link.flags.store(flags, Ordering::Relaxed);
 
-    // Add to table
-    {
-        let mut table = SYMLINK_TABLE.write();
-        table.push(SymlinkEntry {
-            name: String::from(normalized_name),
-            link,
-        });
+    insert_link(normalized_name, link)
+}
+
+/// Create a symbolic link that belongs to a device object
+///
+/// The link is removed by `ob_delete_owned_symbolic_links` when the
+/// owner goes away, so it cannot outlive the device it names.
+pub fn ob_create_owned_symbolic_link(name: &str, target: &str, owner: usize) -> i32 {
+    if name.is_empty() || target.is_empty() {
+        return -1073741811; // STATUS_INVALID_PARAMETER
     }
 
-    SYMLINK_COUNT.fetch_add(1, Ordering::Relaxed);
+    let mut link = ObjectSymbolicLink::new(target);
+    link.flags.store(symlink_flags::KERNEL_CREATED, Ordering::Relaxed);
+    link.owner = owner;
+
+    insert_link(name.trim_start_matches('\\'), link)
+}
+
+/// Delete every symbolic link owned by a device object
+///
+/// Returns the number of links removed.
+pub fn ob_delete_owned_symbolic_links(owner: usize) -> u32 {
+    if owner == 0 {
+        return 0;
+    }
+
+    let mut table = SYMLINK_TABLE.write();
+    let before = table.len();
+    table.retain(|entry| {
+        if entry.link.owner != owner {
+            return true;
+        }
+        crate::serial_println!("[OB] Deleted symbolic link: \\{} (owner deleted)", entry.name);
+        false
+    });
+    let removed = (before - table.len()) as u32;
+    SYMLINK_COUNT.fetch_sub(removed, Ordering::Relaxed);
+    removed
+}
+
+/// Add a link to the table unless the name is already taken
+fn insert_link(normalized_name: &str, link: ObjectSymbolicLink) -> i32 {
+    let mut table = SYMLINK_TABLE.write();
+    if table.iter().any(|entry| entry.name.eq_ignore_ascii_case(normalized_name)) {
+        return -1073741790; // STATUS_OBJECT_NAME_COLLISION
+    }
+
+    crate::serial_println!("[OB] Created symbolic link: \\{} -> {}", normalized_name, link.link_target);
 
-    crate::serial_println!("[OB] Created symbolic link: \\{} -> {}", normalized_name, target);
+    table.push(SymlinkEntry {
+        name: String::from(normalized_name),
+        link,
+    });
+
+    SYMLINK_COUNT.fetch_add(1, Ordering::Relaxed);
 
     0 // STATUS_SUCCESS
 }
@@ -202,40 +250,10 @@ pub fn ob_create_dos_device_link(drive_letter: char, target: &str) -> i32 {
 
     let name = alloc::format!("DosDevices\\{}:", drive_letter.to_ascii_uppercase());
 
-    // Normalize the name
-    let normalized_name = if name.starts_with('\\') {
-        &name[1..]
-    } else {
-        &name
-    };
-
-    // Check if link already exists
-    {
-        let table = SYMLINK_TABLE.read();
-        for entry in table.iter() {
-            if entry.name.eq_ignore_ascii_case(normalized_name) {
-                return -1073741790; // STATUS_OBJECT_NAME_COLLISION
-            }
-        }
-    }
-
     // Create the new DOS device link
     let link = ObjectSymbolicLink::new_dos_device(drive_letter, target);
 
-    // Add to table
-    {
-        let mut table = SYMLINK_TABLE.write();
-        table.push(SymlinkEntry {
-            name: String::from(normalized_name),
-            link,
-        });
-    }
-
-    SYMLINK_COUNT.fetch_add(1, Ordering::Relaxed);
-
-    crate::serial_println!("[OB] Created DOS device link: \\{} -> {}", normalized_name, target);
-
-    0 // STATUS_SUCCESS
+    insert_link(&name, link)
 }
 
 /// Delete a symbolic link
@@ -408,6 +426,121 @@ pub fn ob_list_symbolic_links() -> Vec<(String, String)> {
     result
 }
 
+/// List the symbolic links directly inside a namespace directory
+///
+/// Returns (leaf name, target) pairs sorted by name. A directory that is
+/// itself a link (`\GLOBAL??` -> `\DosDevices`) is followed, and links
+/// filed under either name are listed.
+pub fn ob_list_directory_links(directory: &str) -> Vec<(String, String)> {
+    let named = String::from(directory.trim_matches('\\'));
+    let resolved = ob_resolve_symbolic_links(directory, 8);
+    let resolved = String::from(resolved.trim_matches('\\'));
+
+    let table = SYMLINK_TABLE.read();
+    let mut result: Vec<(String, String)> = Vec::new();
+
+    for entry in table.iter() {
+        let leaf = [&named, &resolved].iter().find_map(|dir| {
+            let rest = entry.name.get(dir.len()..)?;
+            let matches = !dir.is_empty()
+                && entry.name[..dir.len()].eq_ignore_ascii_case(dir)
+                && rest.starts_with('\\');
+            matches.then(|| &rest[1..])
+        });
+        match leaf {
+            Some(leaf) if !leaf.is_empty() && !leaf.contains('\\')
+                && !result.iter().any(|(name, _)| name.eq_ignore_ascii_case(leaf)) => {
+                result.push((String::from(leaf), entry.link.link_target.clone()));
+            }
+            _ => {}
+        }
+    }
+
+    result.sort_by_key(|a| a.0.to_ascii_uppercase());
+    result
+}
+
+// ============================================================================
+// DOS Device Aliases
+// ============================================================================
+
+/// Number of DOS device aliases, so path canonicalization can skip lookups
+static DOS_ALIAS_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Symlink table name of a DOS device alias
+///
+/// Session aliases live in `\Sessions\N\DosDevices`, global ones in `\GLOBAL??`.
+fn dos_device_link_name(session_id: Option<u32>, drive_letter: char) -> String {
+    let letter = drive_letter.to_ascii_uppercase();
+    match session_id {
+        Some(id) => alloc::format!("Sessions\\{}\\DosDevices\\{}:", id, letter),
+        None => alloc::format!("GLOBAL??\\{}:", letter),
+    }
+}
+
+/// Define a DOS device alias (e.g. `X:` -> `C:\PROJECTS`)
+///
+/// A session alias is only visible to processes in that session and
+/// shadows a global alias with the same drive letter.
+pub fn ob_define_dos_device(session_id: Option<u32>, drive_letter: char, target: &str) -> i32 {
+    if !drive_letter.is_ascii_alphabetic() || target.is_empty() {
+        return -1073741811; // STATUS_INVALID_PARAMETER
+    }
+
+    let name = dos_device_link_name(session_id, drive_letter);
+    let link = ObjectSymbolicLink::new_dos_device(drive_letter, target);
+    let status = insert_link(&name, link);
+    if status == 0 {
+        DOS_ALIAS_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    status
+}
+
+/// Remove a DOS device alias
+pub fn ob_delete_dos_device(session_id: Option<u32>, drive_letter: char) -> i32 {
+    let status = ob_delete_symbolic_link(&dos_device_link_name(session_id, drive_letter));
+    if status == 0 {
+        DOS_ALIAS_COUNT.fetch_sub(1, Ordering::Relaxed);
+    }
+    status
+}
+
+/// Look up the alias of a drive letter as seen by the current session
+pub fn ob_query_dos_device(drive_letter: char) -> Option<String> {
+    if DOS_ALIAS_COUNT.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let session_id = super::session::ob_current_session_id();
+    ob_query_symbolic_link(&dos_device_link_name(Some(session_id), drive_letter))
+        .or_else(|| ob_query_symbolic_link(&dos_device_link_name(None, drive_letter)))
+}
+
+/// List DOS device aliases as (drive letter, target, session or None if global)
+pub fn ob_list_dos_devices() -> Vec<(char, String, Option<u32>)> {
+    let table = SYMLINK_TABLE.read();
+    let mut result = Vec::new();
+
+    for entry in table.iter() {
+        if !entry.link.is_dos_device() || entry.link.dos_device_drive_index == 0 {
+            continue;
+        }
+        let letter = (b'A' + entry.link.dos_device_drive_index - 1) as char;
+        let session_id = if entry.name.len() >= 8 && entry.name[..8].eq_ignore_ascii_case("GLOBAL??") {
+            None
+        } else if let Some(rest) = entry.name.strip_prefix("Sessions\\") {
+            match rest.split('\\').next().and_then(|id| id.parse().ok()) {
+                Some(id) => Some(id),
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+        result.push((letter, entry.link.link_target.clone(), session_id));
+    }
+
+    result
+}
+
 /// Initialize symbolic link subsystem
 pub fn obp_symlink_init() {
     // Create standard symbolic links
@@ -0,0 +1,512 @@
+//! Benchmark Suite
+//!
+//! Named, reproducible workloads timed with the performance counter (TSC),
+//! reporting throughput and latency percentiles so performance changes
+//! between builds can be measured from inside the OS. Driven by the
+//! `bench` shell command.
+//!
+//! Every workload uses fixed sizes, a fixed random seed and a fixed
+//! operation count, so two runs on the same machine do the same work.
+//!
+//! # Workloads
+//!
+//! - `seqwrite` / `seqread`: 4 KB sequential file I/O through the VFS
+//! - `rand4k`: Random 4 KB reads and writes (70/30) within a file
+//! - `diskread`: Random 4 KB raw sector reads from a block device, below
+//!   the file system and cache (compares IDE with virtio-blk)
+//! - `cc`: Cache manager copy-write/copy-read path on a private cache map
+//! - `syscall`: Syscall dispatcher round trip (NtGetCurrentProcessId)
+//! - `cswitch`: Context-switch latency, ping-ponging with a partner thread
+//! - `pool`: Pool allocate/free of 256-byte blocks
+
+extern crate alloc;
+
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::fs;
+use crate::hal::timer::{hal_query_tsc_frequency, read_tsc_serialized};
+
+/// Size of each I/O operation
+pub const BENCH_IO_SIZE: usize = 4096;
+
+/// Size of the file used by the I/O workloads
+pub const BENCH_FILE_SIZE: u64 = 256 * 1024;
+
+/// Default operation count per workload
+pub const BENCH_DEFAULT_OPS: u32 = 1000;
+
+/// Maximum operation count per workload
+pub const BENCH_MAX_OPS: u32 = 1_000_000;
+
+/// Seed for workloads that use random offsets
+const BENCH_SEED: u32 = 0xBE7C_0001;
+
+/// Latency samples kept per run; longer runs keep every Nth operation
+const MAX_SAMPLES: usize = 4096;
+
+/// Priority of the cswitch partner; matches the shell so yields alternate
+const PARTNER_PRIORITY: i8 = 12;
+
+/// Pool tag for benchmark allocations ('Bnch')
+const BENCH_POOL_TAG: u32 = u32::from_le_bytes(*b"Bnch");
+
+/// Benchmark workloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchWorkload {
+    SeqWrite,
+    SeqRead,
+    Random4k,
+    DiskRead,
+    CacheCopy,
+    Syscall,
+    ContextSwitch,
+    PoolAlloc,
+}
+
+/// Workload names, in `bench all` order
+pub const BENCH_WORKLOADS: &[(&str, BenchWorkload, &str)] = &[
+    ("seqwrite", BenchWorkload::SeqWrite, "Sequential 4K file writes"),
+    ("seqread", BenchWorkload::SeqRead, "Sequential 4K file reads"),
+    ("rand4k", BenchWorkload::Random4k, "Random 4K file reads/writes (70/30)"),
+    ("diskread", BenchWorkload::DiskRead, "Random 4K raw block device reads"),
+    ("cc", BenchWorkload::CacheCopy, "Cache manager copy write/read"),
+    ("syscall", BenchWorkload::Syscall, "Syscall dispatcher round trip"),
+    ("cswitch", BenchWorkload::ContextSwitch, "Context switch (thread ping-pong)"),
+    ("pool", BenchWorkload::PoolAlloc, "Pool alloc/free (256 bytes)"),
+];
+
+impl BenchWorkload {
+    /// Look up a workload by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        BENCH_WORKLOADS
+            .iter()
+            .find(|(n, _, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, w, _)| *w)
+    }
+
+    /// Workload name
+    pub fn name(&self) -> &'static str {
+        BENCH_WORKLOADS
+            .iter()
+            .find(|(_, w, _)| w == self)
+            .map(|(n, _, _)| *n)
+            .unwrap_or("?")
+    }
+}
+
+/// Result of one benchmark run
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    pub workload: BenchWorkload,
+    /// Operations completed
+    pub ops: u32,
+    /// Bytes transferred (I/O workloads)
+    pub bytes: u64,
+    /// Total elapsed time in nanoseconds
+    pub elapsed_ns: u64,
+    /// Latency percentiles in nanoseconds
+    pub min_ns: u64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub max_ns: u64,
+}
+
+impl BenchResult {
+    /// Operations per second
+    pub fn ops_per_sec(&self) -> u64 {
+        if self.elapsed_ns == 0 {
+            return 0;
+        }
+        (self.ops as u128 * 1_000_000_000 / self.elapsed_ns as u128) as u64
+    }
+
+    /// Throughput in KB/s (I/O workloads)
+    pub fn kb_per_sec(&self) -> u64 {
+        if self.elapsed_ns == 0 {
+            return 0;
+        }
+        (self.bytes as u128 * 1_000_000_000 / 1024 / self.elapsed_ns as u128) as u64
+    }
+}
+
+/// Benchmark error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchError {
+    /// Performance counter frequency unknown
+    NotCalibrated,
+    /// Operation count out of range
+    InvalidOps,
+    /// Another benchmark is running
+    Busy,
+    /// File system operation failed
+    Fs(fs::FsStatus),
+    /// Cache map could not be created
+    NoCacheMap,
+    /// Pool allocation failed
+    NoMemory,
+    /// Partner thread could not be created or stopped responding
+    Thread,
+    /// Block device not found or too small
+    NoDevice,
+    /// Block device read failed
+    Block(crate::io::block::BlockStatus),
+}
+
+// ============================================================================
+// Sampling
+// ============================================================================
+
+/// Latency samples for the run in progress (TSC ticks)
+struct Samples {
+    data: [u64; MAX_SAMPLES],
+    count: usize,
+    /// Record every `stride`-th operation
+    stride: u32,
+    seen: u32,
+}
+
+impl Samples {
+    const fn new() -> Self {
+        Self { data: [0; MAX_SAMPLES], count: 0, stride: 1, seen: 0 }
+    }
+
+    fn reset(&mut self, ops: u32) {
+        self.count = 0;
+        self.seen = 0;
+        self.stride = ops.div_ceil(MAX_SAMPLES as u32).max(1);
+    }
+
+    fn record(&mut self, ticks: u64) {
+        if self.seen.is_multiple_of(self.stride) && self.count < MAX_SAMPLES {
+            self.data[self.count] = ticks;
+            self.count += 1;
+        }
+        self.seen += 1;
+    }
+}
+
+/// Owned by whichever run holds RUNNING
+static mut SAMPLES: Samples = Samples::new();
+
+/// Set while a benchmark runs; guards SAMPLES and IO_BUFFER
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Scratch buffer for I/O workloads
+static mut IO_BUFFER: [u8; BENCH_IO_SIZE] = [0; BENCH_IO_SIZE];
+
+fn ticks_to_ns(ticks: u64, freq: u64) -> u64 {
+    (ticks as u128 * 1_000_000_000 / freq as u128) as u64
+}
+
+/// Time one operation and record its latency
+#[inline]
+fn timed<T>(samples: &mut Samples, op: impl FnOnce() -> T) -> T {
+    let start = read_tsc_serialized();
+    let result = op();
+    samples.record(read_tsc_serialized().wrapping_sub(start));
+    result
+}
+
+// ============================================================================
+// Workloads
+// ============================================================================
+
+fn bench_file_path(dir: &str) -> alloc::string::String {
+    alloc::format!("{}\\BENCH.DAT", dir)
+}
+
+/// Create the benchmark file filled to BENCH_FILE_SIZE (untimed)
+fn prepare_file(path: &str, buf: &mut [u8; BENCH_IO_SIZE]) -> Result<u16, BenchError> {
+    let handle = fs::create(path, 0).map_err(BenchError::Fs)?;
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    for _ in 0..(BENCH_FILE_SIZE / BENCH_IO_SIZE as u64) {
+        if let Err(e) = fs::write(handle, &buf[..]) {
+            let _ = fs::close(handle);
+            return Err(BenchError::Fs(e));
+        }
+    }
+    Ok(handle)
+}
+
+/// Run the file workloads; returns bytes transferred
+fn run_file(
+    workload: BenchWorkload,
+    ops: u32,
+    dir: &str,
+    samples: &mut Samples,
+    buf: &mut [u8; BENCH_IO_SIZE],
+) -> Result<u64, BenchError> {
+    let path = bench_file_path(dir);
+    let handle = prepare_file(&path, buf)?;
+    let blocks = BENCH_FILE_SIZE / BENCH_IO_SIZE as u64;
+    let mut seed = BENCH_SEED;
+    let mut bytes = 0u64;
+    let mut result = Ok(());
+
+    let _ = fs::seek(handle, 0, fs::SeekWhence::Set);
+    for i in 0..ops as u64 {
+        let (offset, write) = match workload {
+            BenchWorkload::SeqWrite => ((i % blocks) * BENCH_IO_SIZE as u64, true),
+            BenchWorkload::SeqRead => ((i % blocks) * BENCH_IO_SIZE as u64, false),
+            _ => {
+                let block = crate::rtl::rtl_random(&mut seed) as u64 % blocks;
+                (block * BENCH_IO_SIZE as u64, crate::rtl::rtl_random(&mut seed) % 10 < 3)
+            }
+        };
+
+        let r = timed(samples, || {
+            // Sequential workloads only seek when wrapping around
+            if workload == BenchWorkload::Random4k || offset == 0 {
+                fs::seek(handle, offset as i64, fs::SeekWhence::Set)?;
+            }
+            if write {
+                fs::write(handle, &buf[..])
+            } else {
+                fs::read(handle, &mut buf[..])
+            }
+        });
+
+        match r {
+            Ok(n) => bytes += n as u64,
+            Err(e) => {
+                result = Err(BenchError::Fs(e));
+                break;
+            }
+        }
+    }
+
+    let _ = fs::close(handle);
+    let _ = fs::delete(&path);
+    result.map(|_| bytes)
+}
+
+/// Random raw reads from a block device; returns bytes read
+///
+/// `device` is a block device name (`hda`, `vda`); empty selects the
+/// first device.
+fn run_disk(ops: u32, device: &str, samples: &mut Samples, buf: &mut [u8; BENCH_IO_SIZE]) -> Result<u64, BenchError> {
+    use crate::io::block;
+
+    let index = if device.is_empty() {
+        (0..block::MAX_BLOCK_DEVICES as u8).find(|&i| block::get_block_device(i).is_some())
+    } else {
+        block::find_block_device(device)
+    }
+    .ok_or(BenchError::NoDevice)?;
+    let dev = block::get_block_device(index).ok_or(BenchError::NoDevice)?;
+
+    let sector_size = dev.geometry.sector_size.max(1) as usize;
+    let sectors = (BENCH_IO_SIZE / sector_size) as u32;
+    let blocks = dev.geometry.total_sectors / sectors as u64;
+    if sectors == 0 || blocks == 0 {
+        return Err(BenchError::NoDevice);
+    }
+
+    let mut seed = BENCH_SEED;
+    let mut bytes = 0u64;
+    for _ in 0..ops {
+        let lba = (crate::rtl::rtl_random(&mut seed) as u64 % blocks) * sectors as u64;
+        let status = timed(samples, || block::read_sectors(index, lba, sectors, &mut buf[..]));
+        if status != block::BlockStatus::Success {
+            return Err(BenchError::Block(status));
+        }
+        bytes += BENCH_IO_SIZE as u64;
+    }
+    Ok(bytes)
+}
+
+/// Cache manager copy path on a private cache map; returns bytes copied
+fn run_cache(ops: u32, samples: &mut Samples, buf: &mut [u8; BENCH_IO_SIZE]) -> Result<u64, BenchError> {
+    // The cache map only uses the file object as an identity
+    static FILE_OBJECT_TOKEN: u8 = 0;
+
+    let map = unsafe {
+        crate::cc::cc_initialize_cache_map(
+            &FILE_OBJECT_TOKEN as *const u8 as *mut u8,
+            BENCH_FILE_SIZE,
+        )
+    };
+    if map.is_null() {
+        return Err(BenchError::NoCacheMap);
+    }
+
+    let blocks = BENCH_FILE_SIZE / BENCH_IO_SIZE as u64;
+    let mut bytes = 0u64;
+
+    for i in 0..ops as u64 {
+        let offset = (i % blocks) * BENCH_IO_SIZE as u64;
+        let ok = timed(samples, || unsafe {
+            crate::cc::cc_copy_write(map, offset, buf.as_ptr(), BENCH_IO_SIZE as u32)
+                && crate::cc::cc_copy_read(map, offset, buf.as_mut_ptr(), BENCH_IO_SIZE as u32)
+        });
+        if ok {
+            bytes += 2 * BENCH_IO_SIZE as u64;
+        }
+    }
+
+    unsafe { crate::cc::cc_uninitialize_cache_map(map) };
+    Ok(bytes)
+}
+
+fn run_syscall(ops: u32, samples: &mut Samples) {
+    extern "C" {
+        fn syscall_dispatcher(
+            num: usize, a1: usize, a2: usize, a3: usize,
+            a4: usize, a5: usize, a6: usize,
+        ) -> isize;
+    }
+    const NT_GET_CURRENT_PROCESS_ID: usize = 3;
+
+    for _ in 0..ops {
+        timed(samples, || unsafe {
+            core::hint::black_box(syscall_dispatcher(NT_GET_CURRENT_PROCESS_ID, 0, 0, 0, 0, 0, 0))
+        });
+    }
+}
+
+/// Ping-pong state: 1 = partner's turn, 0 = benchmark's turn
+static PING_TURN: AtomicU32 = AtomicU32::new(0);
+static PING_STOP: AtomicBool = AtomicBool::new(false);
+static PING_PARTNER_DONE: AtomicBool = AtomicBool::new(false);
+
+fn ping_partner() {
+    while !PING_STOP.load(Ordering::Acquire) {
+        if PING_TURN.load(Ordering::Acquire) == 1 {
+            PING_TURN.store(0, Ordering::Release);
+        }
+        unsafe { crate::ke::scheduler::ki_yield() };
+    }
+    PING_PARTNER_DONE.store(true, Ordering::Release);
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+/// Context switch ping-pong; each sample is one round trip (two switches)
+fn run_cswitch(ops: u32, samples: &mut Samples) -> Result<(), BenchError> {
+    PING_TURN.store(0, Ordering::Release);
+    PING_STOP.store(false, Ordering::Release);
+    PING_PARTNER_DONE.store(false, Ordering::Release);
+
+    if unsafe { crate::ke::init::create_thread(PARTNER_PRIORITY, ping_partner) }.is_none() {
+        return Err(BenchError::Thread);
+    }
+
+    let freq = hal_query_tsc_frequency();
+    let mut result = Ok(());
+
+    for _ in 0..ops {
+        let start = read_tsc_serialized();
+        PING_TURN.store(1, Ordering::Release);
+        while PING_TURN.load(Ordering::Acquire) == 1 {
+            unsafe { crate::ke::scheduler::ki_yield() };
+            // One second without an answer means the partner is not running
+            if read_tsc_serialized().wrapping_sub(start) > freq {
+                result = Err(BenchError::Thread);
+                break;
+            }
+        }
+        if result.is_err() {
+            break;
+        }
+        samples.record(read_tsc_serialized().wrapping_sub(start));
+    }
+
+    PING_STOP.store(true, Ordering::Release);
+    let start = read_tsc_serialized();
+    while !PING_PARTNER_DONE.load(Ordering::Acquire) && read_tsc_serialized().wrapping_sub(start) < freq {
+        unsafe { crate::ke::scheduler::ki_yield() };
+    }
+
+    result
+}
+
+fn run_pool(ops: u32, samples: &mut Samples) -> Result<(), BenchError> {
+    use crate::mm::{ex_allocate_pool_with_tag, ex_free_pool_with_tag, PoolType};
+
+    for _ in 0..ops {
+        let ok = timed(samples, || unsafe {
+            let p = ex_allocate_pool_with_tag(PoolType::NonPagedPool, 256, BENCH_POOL_TAG);
+            if p.is_null() {
+                return false;
+            }
+            ex_free_pool_with_tag(p, BENCH_POOL_TAG);
+            true
+        });
+        if !ok {
+            return Err(BenchError::NoMemory);
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Entry Point
+// ============================================================================
+
+/// Run one workload for `ops` operations
+///
+/// `dir` is where the file workloads create their scratch file; for
+/// `diskread` it names the block device. Must be called from a thread
+/// that can yield (e.g. the shell).
+pub fn bench_run(workload: BenchWorkload, ops: u32, dir: &str) -> Result<BenchResult, BenchError> {
+    let freq = hal_query_tsc_frequency();
+    if freq == 0 {
+        return Err(BenchError::NotCalibrated);
+    }
+    if ops == 0 || ops > BENCH_MAX_OPS {
+        return Err(BenchError::InvalidOps);
+    }
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Err(BenchError::Busy);
+    }
+
+    // SAFETY: RUNNING gives this run exclusive use of the statics
+    let samples = unsafe { &mut *addr_of_mut!(SAMPLES) };
+    let buf = unsafe { &mut *addr_of_mut!(IO_BUFFER) };
+    samples.reset(ops);
+
+    let start = read_tsc_serialized();
+    let outcome = match workload {
+        BenchWorkload::SeqWrite | BenchWorkload::SeqRead | BenchWorkload::Random4k => {
+            run_file(workload, ops, dir, samples, buf)
+        }
+        BenchWorkload::DiskRead => run_disk(ops, dir, samples, buf),
+        BenchWorkload::CacheCopy => run_cache(ops, samples, buf),
+        BenchWorkload::Syscall => {
+            run_syscall(ops, samples);
+            Ok(0)
+        }
+        BenchWorkload::ContextSwitch => run_cswitch(ops, samples).map(|_| 0),
+        BenchWorkload::PoolAlloc => run_pool(ops, samples).map(|_| 0),
+    };
+    let elapsed = read_tsc_serialized().wrapping_sub(start);
+
+    let result = outcome.map(|bytes| {
+        let count = samples.count;
+        let data = &mut samples.data[..count];
+        data.sort_unstable();
+        let pct = |p: usize| -> u64 {
+            if count == 0 {
+                0
+            } else {
+                ticks_to_ns(data[((count - 1) * p) / 100], freq)
+            }
+        };
+        BenchResult {
+            workload,
+            ops,
+            bytes,
+            elapsed_ns: ticks_to_ns(elapsed, freq),
+            min_ns: pct(0),
+            p50_ns: pct(50),
+            p90_ns: pct(90),
+            p99_ns: pct(99),
+            max_ns: pct(100),
+        }
+    });
+
+    RUNNING.store(false, Ordering::Release);
+    result
+}
@@ -8,9 +8,12 @@
 
 extern crate alloc;
 
+pub mod bench;
 pub mod counters;
 pub mod hooks;
 pub mod profile;
+pub mod stress;
+pub mod syscall;
 
 use core::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use crate::ke::SpinLock;
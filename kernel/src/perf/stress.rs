@@ -0,0 +1,539 @@
+//! Stress Testing
+//!
+//! Hammers the file system, pool allocator and thread lifecycle from
+//! several concurrent worker threads for a fixed duration, checking
+//! invariants as it goes. Intended to shake out races in the scheduler
+//! and VFS; driven by the `stress` shell command.
+//!
+//! # Workers
+//!
+//! - **fs**: Creates, rewrites, renames, verifies and deletes a small set
+//!   of files in its own directory, tracking what each file should contain
+//! - **pool**: Allocates and frees random-sized pool blocks, filling each
+//!   with a pattern and verifying it is intact on free
+//! - **threads**: Repeatedly creates short-lived threads and waits for
+//!   each to run and exit
+//!
+//! Each worker cleans up after itself before exiting, so a clean run also
+//! checks that nothing was leaked (files, pool blocks, threads).
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use crate::fs;
+use crate::hal::apic;
+use crate::ke::SpinLock;
+use crate::mm::{ex_allocate_pool_with_tag, ex_free_pool_with_tag, PoolType};
+
+/// Pool tag for stress allocations ('Strs')
+pub const STRESS_POOL_TAG: u32 = u32::from_le_bytes(*b"Strs");
+
+/// Maximum workers of each kind
+pub const MAX_WORKERS_PER_KIND: u32 = 8;
+
+/// Files tracked per fs worker
+const FILES_PER_WORKER: usize = 8;
+
+/// Pool blocks held per pool worker
+const BLOCKS_PER_WORKER: usize = 32;
+
+/// Largest pool block a worker allocates
+const MAX_BLOCK_SIZE: u32 = 2048;
+
+/// Worker priority; matches the shell so the two round-robin while the
+/// shell waits for the run to finish
+const WORKER_PRIORITY: i8 = 12;
+
+/// Ticks (ms) to wait past the deadline for workers to finish cleanup
+const FINISH_GRACE_TICKS: u64 = 10_000;
+
+// ============================================================================
+// Configuration and Statistics
+// ============================================================================
+
+/// Stress run configuration
+#[derive(Debug, Clone, Copy)]
+pub struct StressConfig {
+    /// File system workers
+    pub fs_workers: u32,
+    /// Pool workers
+    pub pool_workers: u32,
+    /// Thread create/exit workers
+    pub thread_workers: u32,
+    /// Run duration in milliseconds
+    pub duration_ms: u64,
+    /// Random seed (workers derive their own seeds from it)
+    pub seed: u32,
+}
+
+impl StressConfig {
+    pub const fn new() -> Self {
+        Self {
+            fs_workers: 2,
+            pool_workers: 2,
+            thread_workers: 1,
+            duration_ms: 10_000,
+            seed: 0x5EED_0001,
+        }
+    }
+
+    fn total_workers(&self) -> u32 {
+        self.fs_workers + self.pool_workers + self.thread_workers
+    }
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Live counters, updated by the workers
+struct StressCounters {
+    fs_ops: AtomicU64,
+    fs_errors: AtomicU64,
+    pool_allocs: AtomicU64,
+    pool_frees: AtomicU64,
+    pool_failures: AtomicU64,
+    threads_created: AtomicU64,
+    threads_exited: AtomicU64,
+    thread_failures: AtomicU64,
+    invariant_failures: AtomicU64,
+}
+
+impl StressCounters {
+    const fn new() -> Self {
+        Self {
+            fs_ops: AtomicU64::new(0),
+            fs_errors: AtomicU64::new(0),
+            pool_allocs: AtomicU64::new(0),
+            pool_frees: AtomicU64::new(0),
+            pool_failures: AtomicU64::new(0),
+            threads_created: AtomicU64::new(0),
+            threads_exited: AtomicU64::new(0),
+            thread_failures: AtomicU64::new(0),
+            invariant_failures: AtomicU64::new(0),
+        }
+    }
+
+    fn reset(&self) {
+        for c in [
+            &self.fs_ops, &self.fs_errors, &self.pool_allocs, &self.pool_frees,
+            &self.pool_failures, &self.threads_created, &self.threads_exited,
+            &self.thread_failures, &self.invariant_failures,
+        ] {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Results of a stress run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StressReport {
+    pub config: StressConfig,
+    /// Actual run time in milliseconds
+    pub elapsed_ms: u64,
+    /// Workers that started
+    pub workers_started: u32,
+    /// Workers that finished cleanup before the grace period ran out
+    pub workers_finished: u32,
+    pub fs_ops: u64,
+    pub fs_errors: u64,
+    pub pool_allocs: u64,
+    pub pool_frees: u64,
+    pub pool_failures: u64,
+    pub threads_created: u64,
+    pub threads_exited: u64,
+    pub thread_failures: u64,
+    /// Invariant violations (data corruption, leaks, lost threads)
+    pub invariant_failures: u64,
+}
+
+impl StressReport {
+    /// True if every worker finished and no invariant was violated
+    pub fn passed(&self) -> bool {
+        self.invariant_failures == 0 && self.workers_finished == self.workers_started
+    }
+}
+
+static COUNTERS: StressCounters = StressCounters::new();
+
+/// Set while a run is in progress
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Set to end a run early
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Tick at which workers stop issuing new operations
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+/// Next worker index to hand out
+static NEXT_WORKER: AtomicU32 = AtomicU32::new(0);
+
+/// Workers that have finished
+static FINISHED: AtomicU32 = AtomicU32::new(0);
+
+/// Configuration of the current run
+static CONFIG: SpinLock<StressConfig> = SpinLock::new(StressConfig::new());
+
+/// Base directory for fs workers
+static BASE_DIR: SpinLock<String> = SpinLock::new(String::new());
+
+/// Report of the last completed run
+static LAST_REPORT: SpinLock<Option<StressReport>> = SpinLock::new(None);
+
+fn invariant_failed(what: &str, worker: u32) {
+    COUNTERS.invariant_failures.fetch_add(1, Ordering::Relaxed);
+    crate::serial_println!("[STRESS] worker {}: invariant violated: {}", worker, what);
+}
+
+fn should_stop() -> bool {
+    STOP.load(Ordering::Relaxed) || apic::get_tick_count() >= DEADLINE.load(Ordering::Relaxed)
+}
+
+fn yield_now() {
+    unsafe { crate::ke::scheduler::ki_yield() };
+}
+
+// ============================================================================
+// Workers
+// ============================================================================
+
+/// Common worker entry: claim an index and dispatch on its kind
+fn worker_entry() {
+    let index = NEXT_WORKER.fetch_add(1, Ordering::AcqRel);
+    let config = *CONFIG.lock();
+    let mut seed = config.seed ^ index.wrapping_mul(0x9E37_79B9);
+
+    if index < config.fs_workers {
+        fs_worker(index, &mut seed);
+    } else if index < config.fs_workers + config.pool_workers {
+        pool_worker(index, &mut seed);
+    } else {
+        thread_worker(index);
+    }
+
+    FINISHED.fetch_add(1, Ordering::AcqRel);
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+/// What an fs worker believes one of its files contains
+#[derive(Clone, Copy)]
+struct TrackedFile {
+    exists: bool,
+    /// Which of the two names the file currently has
+    renamed: bool,
+    /// Generation stamped into the contents
+    generation: u32,
+}
+
+fn file_path(dir: &str, slot: usize, renamed: bool) -> String {
+    format!("{}\\{}{}.DAT", dir, if renamed { "R" } else { "F" }, slot)
+}
+
+/// Contents written for a file: a header plus a length derived from it
+fn file_contents(worker: u32, slot: usize, generation: u32) -> String {
+    let mut s = format!("W{} S{} G{}:", worker, slot, generation);
+    let extra = (generation as usize * 37 + slot * 11) % 200;
+    for i in 0..extra {
+        s.push((b'a' + (i % 26) as u8) as char);
+    }
+    s
+}
+
+/// Read a file into a string (bounded)
+fn read_small_file(path: &str) -> Result<String, fs::FsStatus> {
+    let handle = fs::open(path, 0)?;
+    let mut data = String::new();
+    let mut buf = [0u8; 256];
+    let result = loop {
+        match fs::read(handle, &mut buf) {
+            Ok(0) | Err(fs::FsStatus::EndOfFile) => break Ok(()),
+            Ok(n) => {
+                if data.len() + n > 4096 {
+                    break Err(fs::FsStatus::InvalidParameter);
+                }
+                data.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    let _ = fs::close(handle);
+    result.map(|_| data)
+}
+
+fn write_file(path: &str, contents: &str) -> Result<(), fs::FsStatus> {
+    let handle = fs::create(path, 0)?;
+    let result = fs::write(handle, contents.as_bytes()).map(|_| ());
+    let _ = fs::close(handle);
+    result
+}
+
+fn fs_op(result: Result<(), fs::FsStatus>) -> bool {
+    COUNTERS.fs_ops.fetch_add(1, Ordering::Relaxed);
+    if result.is_err() {
+        COUNTERS.fs_errors.fetch_add(1, Ordering::Relaxed);
+        false
+    } else {
+        true
+    }
+}
+
+fn fs_worker(worker: u32, seed: &mut u32) {
+    let dir = format!("{}\\W{}", BASE_DIR.lock().as_str(), worker);
+    let _ = fs::mkdir(&dir);
+
+    let mut files = [TrackedFile { exists: false, renamed: false, generation: 0 }; FILES_PER_WORKER];
+
+    while !should_stop() {
+        let slot = (crate::rtl::rtl_random(seed) as usize) % FILES_PER_WORKER;
+        let op = crate::rtl::rtl_random(seed) % 4;
+        let f = files[slot];
+        let path = file_path(&dir, slot, f.renamed);
+
+        match op {
+            // Create or overwrite with a new generation
+            0 => {
+                let generation = f.generation + 1;
+                if fs_op(write_file(&path, &file_contents(worker, slot, generation))) {
+                    files[slot] = TrackedFile { exists: true, renamed: f.renamed, generation };
+                }
+            }
+            // Read back and verify
+            1 if f.exists => {
+                COUNTERS.fs_ops.fetch_add(1, Ordering::Relaxed);
+                match read_small_file(&path) {
+                    Ok(data) => {
+                        if data != file_contents(worker, slot, f.generation) {
+                            invariant_failed("file contents do not match last write", worker);
+                        }
+                    }
+                    Err(_) => invariant_failed("tracked file could not be read", worker),
+                }
+            }
+            // Rename between the two names
+            2 if f.exists => {
+                let new_path = file_path(&dir, slot, !f.renamed);
+                if fs_op(fs::rename(&path, &new_path)) {
+                    files[slot].renamed = !f.renamed;
+                    if fs::stat(&path).is_ok() {
+                        invariant_failed("old name still exists after rename", worker);
+                    }
+                }
+            }
+            // Delete
+            3 if f.exists && fs_op(fs::delete(&path)) => {
+                files[slot].exists = false;
+                if fs::stat(&path).is_ok() {
+                    invariant_failed("file still exists after delete", worker);
+                }
+            }
+            _ => {}
+        }
+
+        yield_now();
+    }
+
+    // Directory listing must agree with what we tracked
+    let expected = files.iter().filter(|f| f.exists).count() as u32;
+    let mut listed = 0u32;
+    while fs::readdir(&dir, listed).is_ok() {
+        listed += 1;
+    }
+    if listed != expected {
+        invariant_failed("directory entry count does not match tracked files", worker);
+    }
+
+    for (slot, f) in files.iter().enumerate() {
+        if f.exists {
+            let _ = fs::delete(&file_path(&dir, slot, f.renamed));
+        }
+    }
+    if fs::rmdir(&dir).is_err() {
+        invariant_failed("worker directory not empty after cleanup", worker);
+    }
+}
+
+/// A pool block held by a pool worker
+#[derive(Clone, Copy)]
+struct HeldBlock {
+    ptr: *mut u8,
+    size: usize,
+    fill: u8,
+}
+
+fn verify_and_free(block: &HeldBlock, worker: u32) {
+    let data = unsafe { core::slice::from_raw_parts(block.ptr, block.size) };
+    if data.iter().any(|&b| b != block.fill) {
+        invariant_failed("pool block overwritten while allocated", worker);
+    }
+    unsafe { ex_free_pool_with_tag(block.ptr, STRESS_POOL_TAG) };
+    COUNTERS.pool_frees.fetch_add(1, Ordering::Relaxed);
+}
+
+fn pool_worker(worker: u32, seed: &mut u32) {
+    let mut blocks: [Option<HeldBlock>; BLOCKS_PER_WORKER] = [None; BLOCKS_PER_WORKER];
+
+    while !should_stop() {
+        let slot = (crate::rtl::rtl_random(seed) as usize) % BLOCKS_PER_WORKER;
+
+        match blocks[slot].take() {
+            Some(block) => verify_and_free(&block, worker),
+            None => {
+                let size = 1 + (crate::rtl::rtl_random(seed) % MAX_BLOCK_SIZE) as usize;
+                let ptr = unsafe { ex_allocate_pool_with_tag(PoolType::NonPagedPool, size, STRESS_POOL_TAG) };
+                if ptr.is_null() {
+                    COUNTERS.pool_failures.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    let fill = (worker as u8).wrapping_mul(31).wrapping_add(slot as u8) | 1;
+                    unsafe { core::ptr::write_bytes(ptr, fill, size) };
+                    COUNTERS.pool_allocs.fetch_add(1, Ordering::Relaxed);
+                    blocks[slot] = Some(HeldBlock { ptr, size, fill });
+                }
+            }
+        }
+
+        yield_now();
+    }
+
+    for block in blocks.iter().flatten() {
+        verify_and_free(block, worker);
+    }
+}
+
+/// Entry point for the short-lived threads created by thread workers
+fn child_entry() {
+    COUNTERS.threads_exited.fetch_add(1, Ordering::AcqRel);
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+fn thread_worker(worker: u32) {
+    while !should_stop() {
+        let before = COUNTERS.threads_exited.load(Ordering::Acquire);
+
+        let created = unsafe { crate::ke::init::create_thread(WORKER_PRIORITY, child_entry) };
+        if created.is_none() {
+            COUNTERS.thread_failures.fetch_add(1, Ordering::Relaxed);
+            yield_now();
+            continue;
+        }
+        COUNTERS.threads_created.fetch_add(1, Ordering::Relaxed);
+
+        // Wait for the child to run; other thread workers' children may
+        // also bump the counter, which is fine for progress purposes
+        let give_up = apic::get_tick_count() + 1000;
+        while COUNTERS.threads_exited.load(Ordering::Acquire) == before {
+            if apic::get_tick_count() > give_up {
+                invariant_failed("created thread never ran", worker);
+                break;
+            }
+            yield_now();
+        }
+    }
+}
+
+// ============================================================================
+// Control
+// ============================================================================
+
+/// Run a stress test and wait for it to finish
+///
+/// Must be called from a thread that can yield (e.g. the shell).
+pub fn stress_run(config: StressConfig, base_dir: &str) -> Result<StressReport, &'static str> {
+    if config.total_workers() == 0 {
+        return Err("no workers requested");
+    }
+    if config.fs_workers > MAX_WORKERS_PER_KIND
+        || config.pool_workers > MAX_WORKERS_PER_KIND
+        || config.thread_workers > MAX_WORKERS_PER_KIND
+    {
+        return Err("too many workers");
+    }
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Err("a stress run is already in progress");
+    }
+
+    COUNTERS.reset();
+    STOP.store(false, Ordering::Release);
+    NEXT_WORKER.store(0, Ordering::Release);
+    FINISHED.store(0, Ordering::Release);
+    *CONFIG.lock() = config;
+    *BASE_DIR.lock() = String::from(base_dir);
+
+    if config.fs_workers > 0 {
+        let _ = fs::mkdir(base_dir);
+    }
+
+    crate::serial_println!("[STRESS] Starting: fs={} pool={} threads={} for {} ms",
+        config.fs_workers, config.pool_workers, config.thread_workers, config.duration_ms);
+
+    let start = apic::get_tick_count();
+    DEADLINE.store(start + config.duration_ms, Ordering::Release);
+
+    let mut started = 0u32;
+    for _ in 0..config.total_workers() {
+        if unsafe { crate::ke::init::create_thread(WORKER_PRIORITY, worker_entry) }.is_none() {
+            break;
+        }
+        started += 1;
+    }
+
+    if started < config.total_workers() {
+        // Stop the ones that did start; they would take the wrong roles
+        STOP.store(true, Ordering::Release);
+    }
+
+    let give_up = start + config.duration_ms + FINISH_GRACE_TICKS;
+    while FINISHED.load(Ordering::Acquire) < started && apic::get_tick_count() < give_up {
+        yield_now();
+    }
+
+    let finished = FINISHED.load(Ordering::Acquire);
+    let created = COUNTERS.threads_created.load(Ordering::Acquire);
+    let exited = COUNTERS.threads_exited.load(Ordering::Acquire);
+    if created != exited {
+        invariant_failed("created threads did not all exit", 0);
+    }
+    let allocs = COUNTERS.pool_allocs.load(Ordering::Acquire);
+    let frees = COUNTERS.pool_frees.load(Ordering::Acquire);
+    if finished == started && allocs != frees {
+        invariant_failed("pool blocks leaked", 0);
+    }
+    if config.fs_workers > 0 && finished == started && fs::rmdir(base_dir).is_err() {
+        invariant_failed("base directory not empty after run", 0);
+    }
+
+    let report = StressReport {
+        config,
+        elapsed_ms: apic::get_tick_count() - start,
+        workers_started: started,
+        workers_finished: finished,
+        fs_ops: COUNTERS.fs_ops.load(Ordering::Relaxed),
+        fs_errors: COUNTERS.fs_errors.load(Ordering::Relaxed),
+        pool_allocs: allocs,
+        pool_frees: frees,
+        pool_failures: COUNTERS.pool_failures.load(Ordering::Relaxed),
+        threads_created: created,
+        threads_exited: exited,
+        thread_failures: COUNTERS.thread_failures.load(Ordering::Relaxed),
+        invariant_failures: COUNTERS.invariant_failures.load(Ordering::Relaxed),
+    };
+
+    crate::serial_println!("[STRESS] Finished in {} ms: {} invariant failures",
+        report.elapsed_ms, report.invariant_failures);
+
+    *LAST_REPORT.lock() = Some(report);
+    RUNNING.store(false, Ordering::Release);
+
+    if started == 0 {
+        return Err("could not create worker threads");
+    }
+    Ok(report)
+}
+
+/// Get the report of the last completed run
+pub fn stress_last_report() -> Option<StressReport> {
+    *LAST_REPORT.lock()
+}
@@ -0,0 +1,289 @@
+//! System Call Statistics
+//!
+//! Per-syscall invocation counts and latency histograms, recorded by the
+//! syscall dispatcher. Each processor updates its own slot so the hot
+//! path is a TSC read and a few uncontended relaxed atomic adds.
+//!
+//! Latencies are bucketed by powers of two of TSC ticks; bucket `i`
+//! holds calls that took `[2^(i + SYSCALL_HIST_SHIFT), 2^(i + 1 + SHIFT))`
+//! ticks, with the first and last buckets open-ended.
+//!
+//! Exposed through NtQuerySystemInformation:
+//! - SystemCallCountInformation (6): NT-compatible per-syscall counts
+//! - SystemSyscallLatencyInformation (250): histograms, Nostalgia extension
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use crate::arch::x86_64::syscall::MAX_SYSCALLS;
+
+/// Number of latency buckets per syscall
+pub const SYSCALL_HIST_BUCKETS: usize = 16;
+
+/// log2 of the upper bound (in TSC ticks) of the first bucket
+pub const SYSCALL_HIST_SHIFT: u32 = 6;
+
+/// Processors with their own statistics slot; higher-numbered CPUs share
+pub const SYSCALL_STAT_CPUS: usize = 16;
+
+/// NtQuerySystemInformation class for latency histograms
+pub const SYSTEM_SYSCALL_LATENCY_INFORMATION: u32 = 250;
+
+/// Statistics for one syscall on one processor
+struct SyscallSlot {
+    count: AtomicU64,
+    total_ticks: AtomicU64,
+    max_ticks: AtomicU64,
+    buckets: [AtomicU32; SYSCALL_HIST_BUCKETS],
+}
+
+impl SyscallSlot {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total_ticks: AtomicU64::new(0),
+            max_ticks: AtomicU64::new(0),
+            buckets: [const { AtomicU32::new(0) }; SYSCALL_HIST_BUCKETS],
+        }
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.total_ticks.store(0, Ordering::Relaxed);
+        self.max_ticks.store(0, Ordering::Relaxed);
+        for b in self.buckets.iter() {
+            b.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[repr(align(64))]
+struct CpuSyscallStats {
+    slots: [SyscallSlot; MAX_SYSCALLS],
+}
+
+impl CpuSyscallStats {
+    const fn new() -> Self {
+        Self { slots: [const { SyscallSlot::new() }; MAX_SYSCALLS] }
+    }
+}
+
+static STATS: [CpuSyscallStats; SYSCALL_STAT_CPUS] =
+    [const { CpuSyscallStats::new() }; SYSCALL_STAT_CPUS];
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Aggregated statistics for one syscall
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallStat {
+    pub number: u32,
+    pub count: u64,
+    pub total_ticks: u64,
+    pub max_ticks: u64,
+    pub buckets: [u32; SYSCALL_HIST_BUCKETS],
+}
+
+impl SyscallStat {
+    const fn empty(number: u32) -> Self {
+        Self { number, count: 0, total_ticks: 0, max_ticks: 0, buckets: [0; SYSCALL_HIST_BUCKETS] }
+    }
+
+    /// Approximate latency percentile in ticks (upper bound of its bucket)
+    pub fn percentile_ticks(&self, pct: u64) -> u64 {
+        let total: u64 = self.buckets.iter().map(|&b| b as u64).sum();
+        if total == 0 {
+            return 0;
+        }
+        let target = (total * pct).div_ceil(100).max(1);
+        let mut seen = 0u64;
+        for (i, &b) in self.buckets.iter().enumerate() {
+            seen += b as u64;
+            if seen >= target {
+                return if i == SYSCALL_HIST_BUCKETS - 1 {
+                    self.max_ticks
+                } else {
+                    bucket_upper_ticks(i)
+                };
+            }
+        }
+        self.max_ticks
+    }
+}
+
+/// Upper bound (exclusive) of a bucket in TSC ticks
+pub fn bucket_upper_ticks(bucket: usize) -> u64 {
+    1u64 << (bucket as u32 + 1 + SYSCALL_HIST_SHIFT)
+}
+
+/// Convert TSC ticks to nanoseconds
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    let freq = crate::hal::timer::hal_query_tsc_frequency().max(1);
+    ((ticks as u128 * 1_000_000_000) / freq as u128) as u64
+}
+
+#[inline]
+fn bucket_for(ticks: u64) -> usize {
+    let log = 63 - (ticks | 1).leading_zeros();
+    (log.saturating_sub(SYSCALL_HIST_SHIFT) as usize).min(SYSCALL_HIST_BUCKETS - 1)
+}
+
+/// Check whether statistics collection is enabled
+#[inline]
+pub fn syscall_stats_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Enable or disable statistics collection
+pub fn syscall_stats_set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Record one completed syscall (called by the dispatcher)
+#[inline]
+pub fn syscall_stats_record(number: usize, ticks: u64) {
+    if number >= MAX_SYSCALLS {
+        return;
+    }
+    let cpu = crate::ke::prcb::ke_get_current_processor_number() as usize % SYSCALL_STAT_CPUS;
+    let slot = &STATS[cpu].slots[number];
+
+    slot.count.fetch_add(1, Ordering::Relaxed);
+    slot.total_ticks.fetch_add(ticks, Ordering::Relaxed);
+    slot.max_ticks.fetch_max(ticks, Ordering::Relaxed);
+    slot.buckets[bucket_for(ticks)].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Clear all statistics
+pub fn syscall_stats_reset() {
+    for cpu in STATS.iter() {
+        for slot in cpu.slots.iter() {
+            slot.reset();
+        }
+    }
+}
+
+/// Statistics for one syscall, summed over all processors
+pub fn syscall_stats_get(number: usize) -> SyscallStat {
+    let mut stat = SyscallStat::empty(number as u32);
+    if number >= MAX_SYSCALLS {
+        return stat;
+    }
+    for cpu in STATS.iter() {
+        let slot = &cpu.slots[number];
+        stat.count += slot.count.load(Ordering::Relaxed);
+        stat.total_ticks += slot.total_ticks.load(Ordering::Relaxed);
+        stat.max_ticks = stat.max_ticks.max(slot.max_ticks.load(Ordering::Relaxed));
+        for (i, b) in slot.buckets.iter().enumerate() {
+            stat.buckets[i] += b.load(Ordering::Relaxed);
+        }
+    }
+    stat
+}
+
+/// Total calls made on one processor slot
+pub fn syscall_stats_cpu_total(cpu: usize) -> u64 {
+    if cpu >= SYSCALL_STAT_CPUS {
+        return 0;
+    }
+    STATS[cpu].slots.iter().map(|s| s.count.load(Ordering::Relaxed)).sum()
+}
+
+// ============================================================================
+// NtQuerySystemInformation Support
+// ============================================================================
+
+/// SYSTEM_CALL_COUNT_INFORMATION header; followed by one u32 per service
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemCallCountInformation {
+    /// Total size of the returned data
+    pub length: u32,
+    /// Number of service tables (always 1)
+    pub number_of_tables: u32,
+}
+
+/// Header for SystemSyscallLatencyInformation; followed by `entry_count`
+/// `SyscallLatencyEntry` records for syscalls that have been called
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemSyscallLatencyInformation {
+    pub entry_count: u32,
+    pub bucket_count: u32,
+    pub bucket_shift: u32,
+    pub reserved: u32,
+    /// TSC frequency in Hz, for converting ticks to time
+    pub tsc_frequency: u64,
+}
+
+/// Per-syscall latency record
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallLatencyEntry {
+    pub number: u32,
+    pub reserved: u32,
+    pub count: u64,
+    pub total_ticks: u64,
+    pub max_ticks: u64,
+    pub buckets: [u32; SYSCALL_HIST_BUCKETS],
+}
+
+/// Fill a SystemCallCountInformation buffer
+///
+/// Returns the required length; writes only if `buffer.len()` suffices.
+pub fn syscall_stats_query_counts(buffer: &mut [u8]) -> usize {
+    let header = core::mem::size_of::<SystemCallCountInformation>();
+    let required = header + MAX_SYSCALLS * 4;
+    if buffer.len() < required {
+        return required;
+    }
+
+    let info = SystemCallCountInformation { length: required as u32, number_of_tables: 1 };
+    unsafe { core::ptr::write_unaligned(buffer.as_mut_ptr() as *mut SystemCallCountInformation, info) };
+
+    for n in 0..MAX_SYSCALLS {
+        let count = syscall_stats_get(n).count.min(u32::MAX as u64) as u32;
+        let off = header + n * 4;
+        buffer[off..off + 4].copy_from_slice(&count.to_le_bytes());
+    }
+    required
+}
+
+/// Fill a SystemSyscallLatencyInformation buffer
+///
+/// Returns the required length; writes only if `buffer.len()` suffices.
+pub fn syscall_stats_query_latency(buffer: &mut [u8]) -> usize {
+    let header = core::mem::size_of::<SystemSyscallLatencyInformation>();
+    let entry_size = core::mem::size_of::<SyscallLatencyEntry>();
+
+    let used = (0..MAX_SYSCALLS).filter(|&n| syscall_stats_get(n).count > 0).count();
+    let required = header + used * entry_size;
+    if buffer.len() < required {
+        return required;
+    }
+
+    let info = SystemSyscallLatencyInformation {
+        entry_count: used as u32,
+        bucket_count: SYSCALL_HIST_BUCKETS as u32,
+        bucket_shift: SYSCALL_HIST_SHIFT,
+        reserved: 0,
+        tsc_frequency: crate::hal::timer::hal_query_tsc_frequency(),
+    };
+    unsafe { core::ptr::write_unaligned(buffer.as_mut_ptr() as *mut SystemSyscallLatencyInformation, info) };
+
+    let mut off = header;
+    for n in 0..MAX_SYSCALLS {
+        let stat = syscall_stats_get(n);
+        if stat.count == 0 || off + entry_size > buffer.len() {
+            continue;
+        }
+        let entry = SyscallLatencyEntry {
+            number: stat.number,
+            reserved: 0,
+            count: stat.count,
+            total_ticks: stat.total_ticks,
+            max_ticks: stat.max_ticks,
+            buckets: stat.buckets,
+        };
+        unsafe { core::ptr::write_unaligned(buffer.as_mut_ptr().add(off) as *mut SyscallLatencyEntry, entry) };
+        off += entry_size;
+    }
+    required
+}
@@ -80,6 +80,8 @@ pub use shutdown::{
     get_shutdown_work_count,
     set_shutdown_reason,
     get_shutdown_reason,
+    ShutdownProgress,
+    po_flush_for_shutdown,
 };
 
 // Re-export power request types
@@ -531,6 +533,12 @@ pub fn set_system_power_state(state: SystemPowerState) -> Result<(), i32> {
         _ => {}
     }
 
+    // Services stop and dirty state reaches the disk before devices power down
+    if state == SystemPowerState::Shutdown {
+        crate::svc::database::stop_all_services();
+        po_flush_for_shutdown(console_progress);
+    }
+
     // Notify all registered devices to transition to appropriate D-state
     notify_devices_of_system_power_change(state);
 
@@ -546,9 +554,6 @@ pub fn set_system_power_state(state: SystemPowerState) -> Result<(), i32> {
             SYSTEM_POWER_STATE.store(state as u8, Ordering::SeqCst);
             crate::serial_println!("[PO] System shutdown initiated");
 
-            // Stop all services gracefully
-            crate::svc::database::stop_all_services();
-
             // Perform actual shutdown via ACPI
             unsafe {
                 crate::hal::acpi::shutdown();
@@ -699,15 +704,26 @@ pub fn is_action_in_progress() -> bool {
     (PO_FLAGS.load(Ordering::SeqCst) & po_flags::ACTION_IN_PROGRESS) != 0
 }
 
+/// Report shutdown progress on the boot console
+pub fn console_progress(line: &str) {
+    crate::kprintln!("{}", line);
+}
+
 /// Initiate system shutdown
 ///
 /// This function initiates a graceful system shutdown. It will:
 /// 1. Stop all running services
-/// 2. Notify all devices to enter D3 (off) state
-/// 3. Perform ACPI S5 shutdown
+/// 2. Flush the registry, file cache, volumes and disk caches
+/// 3. Notify all devices to enter D3 (off) state
+/// 4. Perform ACPI S5 shutdown
 ///
 /// This function does not return on success.
 pub fn shutdown() -> ! {
+    shutdown_with_progress(console_progress)
+}
+
+/// Initiate system shutdown, reporting flush progress to `progress`
+pub fn shutdown_with_progress(progress: ShutdownProgress) -> ! {
     crate::serial_println!("[PO] Initiating system shutdown...");
 
     // Mark action in progress
@@ -724,6 +740,9 @@ pub fn shutdown() -> ! {
     let stopped = crate::svc::database::stop_all_services();
     crate::serial_println!("[PO] Stopped {} services", stopped);
 
+    // Write back the registry and caches before anything powers down
+    po_flush_for_shutdown(progress);
+
     // Notify all devices to enter D3
     notify_devices_of_system_power_change(SystemPowerState::Shutdown);
 
@@ -737,11 +756,17 @@ pub fn shutdown() -> ! {
 ///
 /// This function initiates a system restart. It will:
 /// 1. Stop all running services
-/// 2. Notify all devices
-/// 3. Perform ACPI reset
+/// 2. Flush the registry, file cache, volumes and disk caches
+/// 3. Notify all devices
+/// 4. Perform ACPI reset
 ///
 /// This function does not return on success.
 pub fn restart() -> ! {
+    restart_with_progress(console_progress)
+}
+
+/// Initiate system restart, reporting flush progress to `progress`
+pub fn restart_with_progress(progress: ShutdownProgress) -> ! {
     crate::serial_println!("[PO] Initiating system restart...");
 
     // Mark action in progress
@@ -751,9 +776,15 @@ pub fn restart() -> ! {
     let stopped = crate::svc::database::stop_all_services();
     crate::serial_println!("[PO] Stopped {} services", stopped);
 
+    // Write back the registry and caches before anything powers down
+    po_flush_for_shutdown(progress);
+
     // Notify all devices to enter D3
     notify_devices_of_system_power_change(SystemPowerState::Shutdown);
 
+    // Prefer the firmware's reset; falls through if runtime services are unavailable
+    crate::hal::efi::efi_reset_system(crate::hal::efi::EfiResetType::Cold);
+
     // Perform ACPI reset
     unsafe {
         crate::hal::acpi::reset()
@@ -12,8 +12,9 @@
 //! 3. Registered shutdown workers are executed
 //! 4. System waits for threads registered for shutdown wait
 //! 5. Services are stopped
-//! 6. Devices are powered down
-//! 7. ACPI S5 or reset is performed
+//! 6. Dirty state is flushed (registry, file cache, volumes, disk caches)
+//! 7. Devices are powered down
+//! 8. ACPI S5 or reset is performed
 //!
 //! # NT Functions
 //!
@@ -25,6 +26,9 @@ extern crate alloc;
 
 use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use crate::ke::spinlock::SpinLock;
+use crate::ke::{EventType, KEvent};
+use alloc::format;
+use alloc::string::String;
 use alloc::vec::Vec;
 
 /// Maximum number of threads that can wait for shutdown
@@ -378,6 +382,164 @@ fn wait_for_shutdown_threads() -> u32 {
     waited
 }
 
+// ============================================================================
+// Shutdown Flush
+// ============================================================================
+
+/// Progress sink for the shutdown flush (one line per call)
+pub type ShutdownProgress = fn(&str);
+
+/// A flush phase: its description, time budget and routine
+///
+/// The routine returns whether it succeeded and a one-line result.
+struct FlushPhase {
+    description: &'static str,
+    timeout_ms: u64,
+    run: fn() -> (bool, String),
+}
+
+/// Flush phases, in dependency order: each one pushes data into the
+/// layer flushed by the next.
+static FLUSH_PHASES: [FlushPhase; 4] = [
+    FlushPhase { description: "Saving registry hives", timeout_ms: 15_000, run: flush_registry },
+    FlushPhase { description: "Writing cached file data", timeout_ms: 10_000, run: flush_file_cache },
+    FlushPhase { description: "Closing file systems", timeout_ms: 10_000, run: flush_file_systems },
+    FlushPhase { description: "Flushing disk caches", timeout_ms: 5_000, run: flush_disk_caches },
+];
+
+/// Flush worker priority
+const FLUSH_WORKER_PRIORITY: i8 = 12;
+
+static FLUSH_STARTED: AtomicBool = AtomicBool::new(false);
+/// Phases the worker has finished
+static FLUSH_PHASES_DONE: AtomicU32 = AtomicU32::new(0);
+/// Set when the caller gives up; the worker starts no further phase
+static FLUSH_ABANDONED: AtomicBool = AtomicBool::new(false);
+static FLUSH_RESULTS: SpinLock<Vec<(bool, String)>> = SpinLock::new(Vec::new());
+static mut FLUSH_PHASE_EVENT: KEvent = KEvent::new();
+
+fn flush_registry() -> (bool, String) {
+    let summary = crate::cm::cm_shutdown_flush();
+    match summary.error {
+        None => (true, format!("{} hives written", summary.written)),
+        Some(e) => (false, format!("{} hives written, {} failed ({:?})", summary.written, summary.failed, e)),
+    }
+}
+
+fn flush_file_cache() -> (bool, String) {
+    unsafe { crate::cc::cc_flush_all() };
+    (true, String::from("done"))
+}
+
+fn flush_file_systems() -> (bool, String) {
+    let (clean, failed) = unsafe { crate::fs::fat32::fat32_flush_volumes() };
+    if failed == 0 {
+        (true, format!("{} volumes marked clean", clean))
+    } else {
+        (false, format!("{} volumes marked clean, {} left dirty", clean, failed))
+    }
+}
+
+fn flush_disk_caches() -> (bool, String) {
+    let (flushed, failed) = crate::io::block::flush_all_devices();
+    if failed == 0 {
+        (true, format!("{} devices flushed", flushed))
+    } else {
+        (false, format!("{} devices flushed, {} failed", flushed, failed))
+    }
+}
+
+/// Worker thread running the phases in order
+fn flush_worker() {
+    for phase in FLUSH_PHASES.iter() {
+        if FLUSH_ABANDONED.load(Ordering::Acquire) {
+            break;
+        }
+        let result = (phase.run)();
+        FLUSH_RESULTS.lock().push(result);
+        FLUSH_PHASES_DONE.fetch_add(1, Ordering::AcqRel);
+        unsafe { (*core::ptr::addr_of!(FLUSH_PHASE_EVENT)).set() };
+    }
+    unsafe { crate::ke::init::exit_thread() }
+}
+
+/// Check if the caller can block on the worker
+fn can_wait_for_worker() -> bool {
+    !crate::ke::prcb::get_current_thread().is_null() && crate::arch::x86_64::interrupts_enabled()
+}
+
+/// Flush all dirty state to disk before power-off
+///
+/// Runs the registry, file cache, file system and disk cache flushes in
+/// that order, reporting each phase to `progress`. A phase that overruns
+/// its time budget ends the sequence: the later phases are skipped, so a
+/// volume whose data may be incomplete is left marked dirty rather than
+/// clean. Later calls return the first call's result.
+///
+/// # Returns
+/// `true` if every phase completed and succeeded
+pub fn po_flush_for_shutdown(progress: ShutdownProgress) -> bool {
+    if FLUSH_STARTED.swap(true, Ordering::AcqRel) {
+        let results = FLUSH_RESULTS.lock();
+        return results.len() == FLUSH_PHASES.len() && results.iter().all(|r| r.0);
+    }
+
+    let total = FLUSH_PHASES.len();
+    let threaded = can_wait_for_worker() && unsafe {
+        (*core::ptr::addr_of_mut!(FLUSH_PHASE_EVENT)).init(EventType::Synchronization, false);
+        crate::ke::init::create_thread(FLUSH_WORKER_PRIORITY, flush_worker).is_some()
+    };
+
+    let mut all_ok = true;
+    for (i, phase) in FLUSH_PHASES.iter().enumerate() {
+        let line = format!("[{}/{}] {}...", i + 1, total, phase.description);
+        crate::serial_println!("[PO] {}", line);
+        progress(&line);
+
+        let start = crate::hal::apic::get_tick_count();
+        if threaded {
+            let deadline = start + phase.timeout_ms;
+            while FLUSH_PHASES_DONE.load(Ordering::Acquire) <= i as u32 {
+                let now = crate::hal::apic::get_tick_count();
+                if now >= deadline {
+                    break;
+                }
+                unsafe {
+                    crate::ke::wait::ke_wait_for_single_object(
+                        &mut (*core::ptr::addr_of_mut!(FLUSH_PHASE_EVENT)).header as *mut _,
+                        Some(deadline - now),
+                    );
+                }
+            }
+        } else {
+            let result = (phase.run)();
+            FLUSH_RESULTS.lock().push(result);
+            FLUSH_PHASES_DONE.fetch_add(1, Ordering::AcqRel);
+        }
+
+        let result = FLUSH_RESULTS.lock().get(i).cloned();
+        let elapsed = crate::hal::apic::get_tick_count().saturating_sub(start);
+        let line = match result {
+            Some((ok, detail)) => {
+                all_ok &= ok;
+                format!("      {} ({} ms){}", detail, elapsed, if ok { "" } else { " - FAILED" })
+            }
+            None => {
+                FLUSH_ABANDONED.store(true, Ordering::Release);
+                format!("      timed out after {} s; skipping remaining steps", phase.timeout_ms / 1000)
+            }
+        };
+        crate::serial_println!("[PO] {}", line.trim_start());
+        progress(&line);
+
+        if FLUSH_ABANDONED.load(Ordering::Acquire) {
+            return false;
+        }
+    }
+
+    all_ok
+}
+
 // ============================================================================
 // Shutdown Statistics
 // ============================================================================
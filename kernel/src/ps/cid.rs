@@ -153,7 +153,7 @@ pub unsafe fn ps_free_process_id(pid: u32) {
     let _guard = CID_LOCK.lock();
 
     let index = pid as usize;
-    if index > 0 && index < MAX_PROCESSES {
+    if !super::eprocess::ps_is_system_process_id(pid) && index < MAX_PROCESSES {
         PROCESS_TABLE[index] = CidTableEntry::new();
     }
 }
@@ -229,16 +229,20 @@ pub unsafe fn ps_lookup_thread_by_id(tid: u32) -> *mut u8 {
 /// # Safety
 /// Must be called once during kernel initialization
 pub unsafe fn init_cid_table() {
-    // Entry 0 is reserved for the System process (PID 0)
-    // It will be set up when the system process is created
+    // Entries 0 and 4 are reserved for the Idle and System processes
+    // They will be set up when those processes are initialized
     crate::serial_println!("[PS] CID table initialized");
 }
 
-/// Register the system process (PID 0)
-pub unsafe fn ps_register_system_process(process: *mut u8) {
+/// Register the Idle (PID 0) and System (PID 4) processes
+pub unsafe fn ps_register_system_process(idle: *mut u8, system: *mut u8) {
+    use crate::ke::process::{IDLE_PROCESS_ID, SYSTEM_PROCESS_ID};
+
     let _guard = CID_LOCK.lock();
-    PROCESS_TABLE[0].object = process;
-    PROCESS_TABLE[0].entry_type = CidEntryType::Process;
+    PROCESS_TABLE[IDLE_PROCESS_ID as usize].object = idle;
+    PROCESS_TABLE[IDLE_PROCESS_ID as usize].entry_type = CidEntryType::Process;
+    PROCESS_TABLE[SYSTEM_PROCESS_ID as usize].object = system;
+    PROCESS_TABLE[SYSTEM_PROCESS_ID as usize].entry_type = CidEntryType::Process;
 }
 
 // ============================================================================
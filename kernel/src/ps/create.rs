@@ -17,7 +17,7 @@ use crate::ke::{
     SpinLock,
 };
 use crate::arch::x86_64::context::{setup_initial_context, setup_user_thread_context_with_teb};
-use super::cid::{ps_allocate_process_id, ps_allocate_thread_id};
+use super::cid::{ps_allocate_process_id, ps_allocate_thread_id, ps_free_thread_id};
 use super::eprocess::{EProcess, allocate_process, process_flags};
 use super::ethread::{EThread, allocate_thread, thread_flags};
 use super::peb::{allocate_peb, init_peb, allocate_peb_ldr_data, init_peb_ldr_data};
@@ -101,7 +101,7 @@ pub unsafe fn ps_create_process(
     // Allocate a process ID
     let pid = ps_allocate_process_id(process as *mut u8);
     if pid == 0 && !parent.is_null() {
-        // PID 0 is reserved for system process
+        // PID 0 means the table is full (0 is the Idle process)
         super::eprocess::free_process(process);
         return ptr::null_mut();
     }
@@ -116,9 +116,23 @@ pub unsafe fn ps_create_process(
     // Initialize the process
     (*process).init(pid, parent_pid, name, base_priority);
 
+    // Processes join their parent's session
+    if !parent.is_null() {
+        (*process).session_id = (*parent).session_id;
+    }
+
+    // Own copy of the parent's token, and with it the parent's integrity level
+    super::security::ps_assign_primary_token(process, parent);
+
+    // Per-image scheduling defaults (IFEO PerfOptions)
+    super::perfopts::ps_apply_image_policy(process);
+
     // Add to active process list
     let list_head = super::eprocess::get_active_process_list();
-    (*list_head).insert_tail(&mut (*process).active_process_links);
+    {
+        let _guard = super::eprocess::ps_lock_process_list();
+        (*list_head).insert_tail(&mut (*process).active_process_links);
+    }
 
     crate::serial_println!("[PS] Created process {} '{}'", pid,
         core::str::from_utf8_unchecked((*process).image_name()));
@@ -143,6 +157,35 @@ pub unsafe fn ps_create_system_process(name: &[u8]) -> *mut EProcess {
     process
 }
 
+/// Move the current kernel thread into another process
+///
+/// Used at boot to give the shell thread its own process in the console
+/// session. The thread must not own any resources of its old process.
+pub unsafe fn ps_attach_current_thread(process: *mut EProcess) {
+    if process.is_null() {
+        return;
+    }
+
+    crate::arch::x86_64::without_interrupts(|| {
+        let thread = crate::ke::prcb::get_current_prcb_mut().current_thread;
+        if thread.is_null() {
+            return;
+        }
+
+        let old = (*thread).process as *mut EProcess;
+        if old == process {
+            return;
+        }
+        if !old.is_null() {
+            (*old).decrement_thread_count();
+        }
+
+        (*thread).process = &mut (*process).pcb;
+        (*thread).apc_state.process = &mut (*process).pcb;
+        (*process).increment_thread_count();
+    });
+}
+
 // ============================================================================
 // Thread Creation
 // ============================================================================
@@ -221,7 +264,10 @@ pub unsafe fn ps_create_thread(
     );
 
     // Add thread to process's thread list
-    (*process).thread_list_head.insert_tail(&mut (*thread).thread_list_entry);
+    {
+        let _guard = super::eprocess::ps_lock_process_list();
+        (*process).thread_list_head.insert_tail(&mut (*thread).thread_list_entry);
+    }
     (*process).increment_thread_count();
 
     crate::serial_println!("[PS] Created thread {} in process {}",
@@ -275,6 +321,37 @@ pub unsafe fn ps_start_thread(thread: *mut EThread) {
     ki_ready_thread((*thread).get_tcb_mut());
 }
 
+/// Delete a thread that was created but never started
+///
+/// Undoes `ps_create_thread`: unlinks the thread from its process and
+/// returns its ID, stack and ETHREAD to their pools.
+///
+/// # Safety
+/// The thread must come from `ps_create_thread` and must never have been
+/// readied.
+pub unsafe fn ps_delete_unstarted_thread(thread: *mut EThread) {
+    if thread.is_null() {
+        return;
+    }
+
+    let process = (*thread).thread_process;
+    {
+        let _guard = super::eprocess::ps_lock_process_list();
+        (*thread).thread_list_entry.remove_entry();
+    }
+    if !process.is_null() {
+        (*process).decrement_thread_count();
+    }
+    ps_free_thread_id((*thread).cid.unique_thread);
+
+    // The stack base is the top of its pool slot
+    let pool = core::ptr::addr_of!(PS_STACK_POOL) as usize;
+    let offset = (*thread).tcb.stack_base as usize - pool;
+    free_stack(offset / constants::THREAD_STACK_SIZE - 1);
+
+    super::ethread::free_thread(thread);
+}
+
 /// Create and start a system thread in one call
 ///
 /// This is a convenience function that creates a thread and immediately
@@ -416,7 +493,10 @@ pub unsafe fn ps_create_user_thread_ex(
     );
 
     // Add thread to process's thread list
-    (*process).thread_list_head.insert_tail(&mut (*thread).thread_list_entry);
+    {
+        let _guard = super::eprocess::ps_lock_process_list();
+        (*process).thread_list_head.insert_tail(&mut (*thread).thread_list_entry);
+    }
     (*process).increment_thread_count();
 
     crate::serial_println!("[PS] Created user-mode thread {} in process {} (entry={:#x}, TEB={:p})",
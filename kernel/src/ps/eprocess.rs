@@ -16,7 +16,8 @@
 
 use core::ptr;
 use core::sync::atomic::{AtomicU32, Ordering};
-use crate::ke::{KProcess, ProcessState, list::ListEntry, SpinLock};
+use crate::ke::{KProcess, ProcessState, list::ListEntry, SpinLock, SpinLockGuard};
+use crate::ke::process::{IDLE_PROCESS_ID, SYSTEM_PROCESS_ID};
 use crate::ob::HandleTable;
 use crate::se::Token;
 use super::cid::ClientId;
@@ -148,11 +149,7 @@ pub struct EProcess {
     /// Other transfer count (bytes)
     pub other_transfer_count: u64,
 
-    // Time accounting
-    /// Kernel mode execution time (100ns units)
-    pub kernel_time: u64,
-    /// User mode execution time (100ns units)
-    pub user_time: u64,
+    // Time accounting (kernel/user time is kept in the PCB)
     /// CPU cycle time counter
     pub cycle_time: u64,
 
@@ -224,8 +221,6 @@ impl EProcess {
             write_transfer_count: 0,
             other_transfer_count: 0,
             // Time accounting
-            kernel_time: 0,
-            user_time: 0,
             cycle_time: 0,
             // Priority
             priority_class: 0x20, // NORMAL_PRIORITY_CLASS
@@ -276,6 +271,9 @@ impl EProcess {
         self.active_threads = AtomicU32::new(0);
         self.thread_count = AtomicU32::new(0);
 
+        // Scheduling defaults; per-image PerfOptions may override them
+        self.io_priority = 2; // Normal
+
         // Mark as initialized
         self.flags.store(process_flags::PS_PROCESS_FLAGS_INITIALIZED, Ordering::Release);
     }
@@ -382,13 +380,19 @@ static mut PROCESS_POOL: [EProcess; MAX_PROCESSES] = {
 /// Process pool bitmap
 static mut PROCESS_POOL_BITMAP: u64 = 0;
 
-/// System process (PID 0)
+/// Idle process (PID 0)
+static mut IDLE_PROCESS: EProcess = EProcess::new();
+
+/// System process (PID 4)
 static mut SYSTEM_PROCESS: EProcess = EProcess::new();
 
 /// Active process list head
 static mut ACTIVE_PROCESS_LIST: ListEntry = ListEntry::new();
 
 /// Process list lock
+///
+/// Also guards every process's thread list and loader module lists, so a
+/// snapshot taken under it sees all three in one consistent state.
 static PROCESS_LIST_LOCK: SpinLock<()> = SpinLock::new(());
 
 /// Allocate a process from the pool
@@ -425,29 +429,70 @@ pub fn get_system_process() -> *mut EProcess {
     unsafe { &mut SYSTEM_PROCESS as *mut EProcess }
 }
 
-/// Initialize the system process
+/// Get the idle process
+pub fn get_idle_process() -> *mut EProcess {
+    unsafe { &mut IDLE_PROCESS as *mut EProcess }
+}
+
+/// Check whether a process ID names the Idle or System process
+pub fn ps_is_system_process_id(pid: u32) -> bool {
+    pid == IDLE_PROCESS_ID || pid == SYSTEM_PROCESS_ID
+}
+
+/// Initialize the Idle and System processes
+///
+/// Their PCBs were already used by the kernel since phase 0 (idle and
+/// worker threads point at them); this fills in the executive parts and
+/// recounts the threads that exist by now.
 ///
 /// # Safety
 /// Must be called once during kernel initialization
 pub unsafe fn init_system_process() {
-    SYSTEM_PROCESS.init(0, 0, b"System", 8);
+    IDLE_PROCESS.init(IDLE_PROCESS_ID, IDLE_PROCESS_ID, b"Idle", 0);
+    IDLE_PROCESS.set_flag(process_flags::PS_PROCESS_FLAGS_SYSTEM);
+    IDLE_PROCESS.pcb.state = ProcessState::Ready;
+
+    SYSTEM_PROCESS.init(SYSTEM_PROCESS_ID, IDLE_PROCESS_ID, b"System", 8);
     SYSTEM_PROCESS.set_flag(process_flags::PS_PROCESS_FLAGS_SYSTEM);
     SYSTEM_PROCESS.pcb.state = ProcessState::Ready;
 
+    // One idle thread per started processor
+    for cpu in 0..crate::ke::prcb::MAX_CPUS {
+        if let Some(prcb) = crate::ke::prcb::get_prcb(cpu) {
+            if !prcb.idle_thread.is_null() {
+                IDLE_PROCESS.increment_thread_count();
+            }
+        }
+    }
+
     // Initialize active process list
     ACTIVE_PROCESS_LIST.init_head();
 
-    // Add system process to active list
+    // Add both to the active list, Idle first as on NT
     let _guard = PROCESS_LIST_LOCK.lock();
+    ACTIVE_PROCESS_LIST.insert_tail(&mut IDLE_PROCESS.active_process_links);
     ACTIVE_PROCESS_LIST.insert_tail(&mut SYSTEM_PROCESS.active_process_links);
 
     // Register in CID table
-    super::cid::ps_register_system_process(&mut SYSTEM_PROCESS as *mut _ as *mut u8);
-
-    crate::serial_println!("[PS] System process initialized (PID 0)");
+    super::cid::ps_register_system_process(
+        &mut IDLE_PROCESS as *mut _ as *mut u8,
+        &mut SYSTEM_PROCESS as *mut _ as *mut u8,
+    );
+
+    crate::serial_println!("[PS] Idle process initialized (PID {}, {} threads)",
+        IDLE_PROCESS_ID, IDLE_PROCESS.thread_count());
+    crate::serial_println!("[PS] System process initialized (PID {})", SYSTEM_PROCESS_ID);
 }
 
 /// Get the active process list head
 pub unsafe fn get_active_process_list() -> *mut ListEntry {
     &mut ACTIVE_PROCESS_LIST as *mut ListEntry
 }
+
+/// Acquire the process list lock
+///
+/// Hold it while linking processes, threads or loader entries into their
+/// lists, and while walking those lists for a snapshot.
+pub fn ps_lock_process_list() -> SpinLockGuard<'static, ()> {
+    PROCESS_LIST_LOCK.lock()
+}
@@ -423,6 +423,20 @@ pub unsafe fn get_thread_by_index(index: usize) -> Option<*mut EThread> {
     None
 }
 
+/// Get the ETHREAD containing a KTHREAD
+///
+/// Kernel worker threads are bare KTHREADs from the scheduler's pool and
+/// have no executive thread; those return `None`.
+pub fn ethread_from_kthread(thread: *mut KThread) -> Option<*mut EThread> {
+    let base = core::ptr::addr_of!(THREAD_POOL) as usize;
+    let end = base + MAX_THREADS * core::mem::size_of::<EThread>();
+    let addr = thread as usize;
+    if addr < base || addr >= end || !(addr - base).is_multiple_of(core::mem::size_of::<EThread>()) {
+        return None;
+    }
+    Some(thread as *mut EThread)
+}
+
 /// Get list of all allocated threads
 ///
 /// Returns an array of pointers to KThread (up to MAX_THREADS) and the count
@@ -8,6 +8,8 @@
 //! - **Thread Creation**: Stack setup, context initialization
 //! - **Client ID Table**: Process/thread ID management
 //! - **Job Objects**: Process grouping and limits
+//! - **Process Security**: Primary tokens and integrity levels
+//! - **Snapshots**: Consistent process/thread/module lists for toolhelp
 //!
 //! # Process Structure
 //!
@@ -41,6 +43,9 @@ pub mod job;
 pub mod peb;
 pub mod teb;
 pub mod quota;
+pub mod perfopts;
+pub mod security;
+pub mod snapshot;
 
 // Re-exports for convenience
 pub use cid::{
@@ -55,21 +60,22 @@ pub use cid::{
 
 pub use eprocess::{
     EProcess, process_flags, PS_IMAGE_NAME_LENGTH,
-    allocate_process, free_process, get_system_process,
+    allocate_process, free_process, get_system_process, get_idle_process,
+    ps_is_system_process_id,
     get_active_process_list,
 };
 
 pub use ethread::{
     EThread, thread_flags,
-    allocate_thread, free_thread, get_thread_by_index,
+    allocate_thread, free_thread, get_thread_by_index, ethread_from_kthread,
     ps_get_thread_list, ps_get_ethread_list,
 };
 
 pub use create::{
     PsThreadStartRoutine,
-    ps_create_process, ps_create_system_process,
+    ps_create_process, ps_create_system_process, ps_attach_current_thread,
     ps_create_thread, ps_create_system_thread,
-    ps_start_thread, ps_create_and_start_system_thread,
+    ps_start_thread, ps_create_and_start_system_thread, ps_delete_unstarted_thread,
     // User-mode thread/process creation
     ps_create_user_thread, ps_create_user_thread_ex,
     ps_create_user_process, ps_create_user_process_ex,
@@ -87,6 +93,17 @@ pub use job::{
     get_job_stats, ps_get_job_snapshots, job_limit_flags_name,
 };
 
+pub use perfopts::{
+    ImagePolicy, IFEO_KEY,
+    query_image_policy, set_image_policy_value, ps_apply_image_policy,
+};
+
+pub use security::{
+    ps_get_process_token, ps_assign_primary_token, ps_set_primary_token,
+    ps_get_process_integrity_level, ps_set_process_integrity_level,
+    ps_access_check_object, ps_access_check_process,
+};
+
 pub use peb::{
     Peb, PebLdrData, LdrDataTableEntry,
     RtlUserProcessParameters, UnicodeString,
@@ -133,6 +150,12 @@ pub use quota::{
     get_quota_stats, get_quota_block_snapshots, get_quota_block_count,
 };
 
+pub use snapshot::{
+    ProcessSnapshot, SnapshotProcessEntry, SnapshotThreadEntry, SnapshotModuleEntry,
+    SystemProcessSnapshotInformation, SYSTEM_PROCESS_SNAPSHOT_INFORMATION,
+    ps_take_snapshot, ps_query_snapshot,
+};
+
 /// Initialize the Process Manager
 ///
 /// # Safety
@@ -143,7 +166,7 @@ pub unsafe fn init() {
     // Initialize the CID table
     cid::init_cid_table();
 
-    // Initialize the system process (PID 0)
+    // Initialize the Idle (PID 0) and System (PID 4) processes
     eprocess::init_system_process();
 
     // Initialize quota management
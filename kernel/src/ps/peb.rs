@@ -1003,6 +1003,7 @@ pub unsafe fn init_ldr_entry(
 pub unsafe fn add_ldr_entry_to_lists(ldr: *mut PebLdrData, entry: *mut LdrDataTableEntry) {
     let ldr = &mut *ldr;
     let entry = &mut *entry;
+    let _guard = super::eprocess::ps_lock_process_list();
 
     // Add to load order list (at tail)
     insert_tail_list64(
@@ -0,0 +1,130 @@
+//! Per-Image Performance Options
+//!
+//! Scheduling defaults applied when a process is created, read from the
+//! image's PerfOptions key under Image File Execution Options:
+//!
+//! ```text
+//! MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\
+//!     Image File Execution Options\<image>\PerfOptions
+//! ```
+//!
+//! Values (all DWORD, all optional):
+//!
+//! - `CpuRateLimit`: hard cap in percent of total processor time (1-99)
+//! - `AffinityMask`: default processor affinity for the process's threads
+//! - `IoPriority`: default I/O priority (0 very low, 1 low, 2 normal);
+//!   as on NT, PerfOptions can only lower it
+//!
+//! The image name is matched case-insensitively against the key name.
+//! This is how background services are constrained without changing them.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use crate::cm::{cm_read_dword, cm_write_dword, CmStatus};
+use super::eprocess::EProcess;
+
+/// Image File Execution Options root key
+pub const IFEO_KEY: &str =
+    "MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Image File Execution Options";
+
+/// Value names under PerfOptions
+pub mod perf_value {
+    pub const CPU_RATE_LIMIT: &str = "CpuRateLimit";
+    pub const AFFINITY_MASK: &str = "AffinityMask";
+    pub const IO_PRIORITY: &str = "IoPriority";
+}
+
+/// Scheduling policy configured for an image
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImagePolicy {
+    /// CPU cap in percent
+    pub cpu_rate_limit: Option<u32>,
+    /// Default affinity mask
+    pub affinity_mask: Option<u64>,
+    /// Default I/O priority
+    pub io_priority: Option<u32>,
+}
+
+impl ImagePolicy {
+    /// Check whether any option is set
+    pub fn is_empty(&self) -> bool {
+        self.cpu_rate_limit.is_none() && self.affinity_mask.is_none() && self.io_priority.is_none()
+    }
+}
+
+/// Get the PerfOptions key path of an image
+pub fn perf_options_key(image: &str) -> String {
+    format!("{}\\{}\\PerfOptions", IFEO_KEY, image)
+}
+
+/// Read the policy configured for an image
+pub fn query_image_policy(image: &str) -> ImagePolicy {
+    let key = perf_options_key(image);
+    unsafe {
+        ImagePolicy {
+            cpu_rate_limit: cm_read_dword(&key, perf_value::CPU_RATE_LIMIT),
+            affinity_mask: cm_read_dword(&key, perf_value::AFFINITY_MASK).map(|m| m as u64),
+            io_priority: cm_read_dword(&key, perf_value::IO_PRIORITY),
+        }
+    }
+}
+
+/// Write one PerfOptions value for an image
+pub fn set_image_policy_value(image: &str, name: &str, value: u32) -> CmStatus {
+    unsafe { cm_write_dword(&perf_options_key(image), name, value) }
+}
+
+/// Apply the image's policy to a newly created process
+///
+/// Invalid values are ignored with a serial warning rather than failing
+/// process creation.
+///
+/// # Safety
+/// Process must be valid and have no running threads yet
+pub unsafe fn ps_apply_image_policy(process: *mut EProcess) {
+    if process.is_null() {
+        return;
+    }
+
+    let image = match core::str::from_utf8((*process).image_name()) {
+        Ok(name) if !name.is_empty() => name,
+        _ => return,
+    };
+    let policy = query_image_policy(image);
+    if policy.is_empty() {
+        return;
+    }
+
+    if let Some(percent) = policy.cpu_rate_limit {
+        if (1..100).contains(&percent) {
+            crate::ke::cpurate::ke_set_cpu_rate_limit(&mut (*process).pcb, percent);
+        } else {
+            crate::serial_println!("[PS] {}: ignoring CpuRateLimit {}", image, percent);
+        }
+    }
+
+    if let Some(mask) = policy.affinity_mask {
+        let mask = mask & crate::ke::ke_get_active_processors();
+        if mask != 0 {
+            (*process).pcb.affinity = mask;
+        } else {
+            crate::serial_println!("[PS] {}: AffinityMask has no active processors", image);
+        }
+    }
+
+    if let Some(priority) = policy.io_priority {
+        if priority <= crate::io::IoPriority::Normal as u32 {
+            (*process).io_priority = priority as u8;
+        } else {
+            crate::serial_println!("[PS] {}: ignoring IoPriority {}", image, priority);
+        }
+    }
+
+    crate::serial_println!("[PS] Applied PerfOptions to {} (cpu {}%, affinity {:#x}, io {})",
+        image,
+        (*process).pcb.cpu_rate_limit,
+        (*process).pcb.affinity,
+        (*process).io_priority);
+}
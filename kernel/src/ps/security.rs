@@ -0,0 +1,151 @@
+//! Process Security
+//!
+//! Every process created by `ps_create_process` gets its own primary token,
+//! a copy of its parent's. The Idle and System processes have none and
+//! run with the system token.
+//!
+//! A process therefore starts at its parent's integrity level. It can be
+//! lowered before the process runs, never raised. The process object
+//! itself is labeled with its token's level and no-write-up/no-read-up,
+//! so a lower process can't open a higher one for writing or reading.
+//!
+//! RUNAS replaces a new process's token with a logon token before the
+//! process runs. Files and registry keys are checked against the token of
+//! the process making the request.
+
+use crate::se::{
+    se_access_check, se_allocate_security_descriptor, se_free_security_descriptor,
+    se_duplicate_token, se_free_token, se_get_system_token,
+    AccessCheckResult, GenericMapping, IntegrityLevel, MandatoryLabel, SimpleSecurityDescriptor,
+    Token, TokenType, SID_LOCAL_SYSTEM,
+};
+use super::eprocess::EProcess;
+
+/// Get the primary token a process runs with
+///
+/// # Safety
+/// Process must be valid or null
+pub unsafe fn ps_get_process_token(process: *mut EProcess) -> *mut Token {
+    if process.is_null() || (*process).token.is_null() {
+        se_get_system_token()
+    } else {
+        (*process).token
+    }
+}
+
+/// Give a new process a copy of its parent's primary token
+///
+/// If the token pool is exhausted the parent's token is shared instead;
+/// such a process can't have its integrity level changed.
+///
+/// # Safety
+/// Process must be valid and have no running threads yet
+pub unsafe fn ps_assign_primary_token(process: *mut EProcess, parent: *mut EProcess) {
+    let source = ps_get_process_token(parent);
+    let mut token = se_duplicate_token(source, TokenType::Primary);
+    if token.is_null() {
+        (*source).add_ref();
+        token = source;
+    }
+    (*process).token = token;
+    (*process).pcb.set_token(token as *mut u8);
+}
+
+/// Replace the primary token of a new process
+///
+/// The process takes over the caller's reference to `token`.
+///
+/// # Safety
+/// Process must be valid and have no running threads yet; token must be
+/// a valid primary token
+pub unsafe fn ps_set_primary_token(process: *mut EProcess, token: *mut Token) {
+    let old = (*process).token;
+    (*process).token = token;
+    (*process).pcb.set_token(token as *mut u8);
+
+    if !old.is_null() && old != se_get_system_token() && (*old).release() == 1 {
+        se_free_token(old);
+    }
+}
+
+/// Get the integrity level a process runs at
+///
+/// # Safety
+/// Process must be valid or null
+pub unsafe fn ps_get_process_integrity_level(process: *mut EProcess) -> IntegrityLevel {
+    (*ps_get_process_token(process)).integrity_level()
+}
+
+/// Lower the integrity level of a process
+///
+/// # Returns
+/// `false` if `level` is above the current level or the token is shared
+///
+/// # Safety
+/// Process must be valid
+pub unsafe fn ps_set_process_integrity_level(process: *mut EProcess, level: IntegrityLevel) -> bool {
+    let token = (*process).token;
+    if token.is_null() || (*token).reference_count.load(core::sync::atomic::Ordering::Acquire) > 1 {
+        return false;
+    }
+    if level > (*token).integrity_level() {
+        return false;
+    }
+    let _guard = (*token).token_lock.lock();
+    (*token).set_integrity_level(level)
+}
+
+/// Check the current process's access to an object
+///
+/// `describe` fills in the object's security descriptor. Every default
+/// descriptor grants LocalSystem full control, so its checks are skipped.
+///
+/// # Safety
+/// Must be called from a thread with a valid current process or none
+pub unsafe fn ps_access_check_object(
+    desired_access: u32,
+    generic_mapping: &GenericMapping,
+    describe: impl FnOnce(&mut SimpleSecurityDescriptor),
+) -> Result<(), AccessCheckResult> {
+    let token = ps_get_process_token(super::get_current_process());
+    if (*token).is_user(&SID_LOCAL_SYSTEM) {
+        return Ok(());
+    }
+
+    let sd = se_allocate_security_descriptor();
+    if sd.is_null() {
+        return Err(AccessCheckResult::DeniedNoSD);
+    }
+    describe(&mut *sd);
+
+    let result = se_access_check(&*token, &*sd, desired_access, generic_mapping);
+    se_free_security_descriptor(sd);
+    result.map(|_| ())
+}
+
+/// Check the current process's access to a process object
+///
+/// Process objects carry no DACL; the check enforces their mandatory label.
+///
+/// # Safety
+/// Target must be valid
+pub unsafe fn ps_access_check_process(
+    target: *mut EProcess,
+    desired_access: u32,
+    generic_mapping: &GenericMapping,
+) -> Result<u32, AccessCheckResult> {
+    let token = ps_get_process_token(super::get_current_process());
+    let target_token = ps_get_process_token(target);
+
+    // Security descriptors are too large for a kernel stack
+    let sd = se_allocate_security_descriptor();
+    if sd.is_null() {
+        return Err(AccessCheckResult::DeniedNoSD);
+    }
+    (*sd).set_owner((*target_token).user);
+    (*sd).set_label(MandatoryLabel::for_process((*target_token).integrity_level()));
+
+    let result = se_access_check(&*token, &*sd, desired_access, generic_mapping);
+    se_free_security_descriptor(sd);
+    result
+}
@@ -0,0 +1,273 @@
+//! Process Snapshots
+//!
+//! Point-in-time copies of the process, thread and module lists for
+//! toolhelp-style callers. Everything is gathered under a single
+//! acquisition of the process list lock, which also guards the thread
+//! and loader module lists, so a snapshot never shows a thread whose
+//! process is missing or a process caught half-way through creation.
+//!
+//! The lock is held only while copying into kernel memory; the caller's
+//! buffer is written afterwards.
+//!
+//! Exposed through NtQuerySystemInformation:
+//! - SystemProcessSnapshotInformation (251): Nostalgia extension
+//!
+//! # Buffer Layout
+//!
+//! ```text
+//! SystemProcessSnapshotInformation  header, offsets relative to buffer start
+//! SnapshotProcessEntry[process_count]
+//! SnapshotThreadEntry[thread_count]
+//! SnapshotModuleEntry[module_count]
+//! ```
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use crate::ke::list::ListEntry;
+use super::eprocess::{EProcess, PS_IMAGE_NAME_LENGTH, get_active_process_list, ps_lock_process_list};
+use super::ethread::EThread;
+use super::peb::{LdrDataTableEntry, MAX_LDR_ENTRIES};
+use super::{MAX_PROCESSES, MAX_THREADS};
+
+/// NtQuerySystemInformation class for process snapshots
+pub const SYSTEM_PROCESS_SNAPSHOT_INFORMATION: u32 = 251;
+
+/// Header for SystemProcessSnapshotInformation
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemProcessSnapshotInformation {
+    /// Total size of the returned data
+    pub length: u32,
+    pub process_count: u32,
+    pub thread_count: u32,
+    pub module_count: u32,
+    pub process_offset: u32,
+    pub thread_offset: u32,
+    pub module_offset: u32,
+    pub reserved: u32,
+    /// Tick count when the snapshot was taken
+    pub snapshot_time: u64,
+}
+
+/// One process in a snapshot
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SnapshotProcessEntry {
+    pub process_id: u32,
+    pub parent_process_id: u32,
+    pub session_id: u32,
+    pub thread_count: u32,
+    pub handle_count: u32,
+    pub base_priority: i32,
+    pub priority_class: u32,
+    pub flags: u32,
+    pub create_time: u64,
+    /// 0 while the process is running
+    pub exit_time: u64,
+    /// Kernel time in 100ns units
+    pub kernel_time: u64,
+    /// User time in 100ns units
+    pub user_time: u64,
+    pub working_set_size: u64,
+    pub virtual_size: u64,
+    pub peb: u64,
+    /// Image name, NUL-padded
+    pub image_name: [u8; PS_IMAGE_NAME_LENGTH],
+}
+
+/// One thread in a snapshot
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SnapshotThreadEntry {
+    pub thread_id: u32,
+    pub process_id: u32,
+    /// KTHREAD state (ThreadState)
+    pub state: u8,
+    pub wait_reason: u8,
+    pub priority: i8,
+    pub base_priority: i8,
+    pub flags: u32,
+    pub create_time: u64,
+    /// Clock ticks in kernel mode
+    pub kernel_time: u32,
+    /// Clock ticks in user mode
+    pub user_time: u32,
+    pub start_address: u64,
+    pub win32_start_address: u64,
+    pub teb: u64,
+}
+
+/// One loaded module in a snapshot
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SnapshotModuleEntry {
+    pub process_id: u32,
+    pub size_of_image: u32,
+    pub base: u64,
+    pub entry_point: u64,
+    /// LDRP_* flags
+    pub flags: u32,
+    /// 0xFFFF for the pinned main executable
+    pub load_count: u16,
+    pub tls_index: u16,
+}
+
+/// A consistent copy of the process, thread and module lists
+#[derive(Debug, Clone, Default)]
+pub struct ProcessSnapshot {
+    pub snapshot_time: u64,
+    pub processes: Vec<SnapshotProcessEntry>,
+    pub threads: Vec<SnapshotThreadEntry>,
+    pub modules: Vec<SnapshotModuleEntry>,
+}
+
+/// Take a snapshot of every process, its threads and its loaded modules
+///
+/// Returns `None` if the snapshot storage could not be allocated.
+pub fn ps_take_snapshot() -> Option<ProcessSnapshot> {
+    // Allocate up front: the heap must not be touched with the lock held
+    let mut snapshot = ProcessSnapshot::default();
+    snapshot.processes.try_reserve_exact(MAX_PROCESSES).ok()?;
+    snapshot.threads.try_reserve_exact(MAX_THREADS).ok()?;
+    snapshot.modules.try_reserve_exact(MAX_LDR_ENTRIES).ok()?;
+
+    let _guard = ps_lock_process_list();
+    snapshot.snapshot_time = crate::hal::apic::get_tick_count();
+
+    unsafe {
+        let head = get_active_process_list();
+        let mut entry = (*head).flink;
+        while entry != head && snapshot.processes.len() < MAX_PROCESSES {
+            let process = crate::containing_record!(entry, EProcess, active_process_links);
+            snapshot_process(&mut snapshot, &*process);
+            entry = (*entry).flink;
+        }
+    }
+
+    Some(snapshot)
+}
+
+/// Copy one process and its lists; the process list lock must be held
+unsafe fn snapshot_process(snapshot: &mut ProcessSnapshot, process: &EProcess) {
+    let pid = process.unique_process_id;
+
+    snapshot.processes.push(SnapshotProcessEntry {
+        process_id: pid,
+        parent_process_id: process.inherited_from_unique_process_id,
+        session_id: process.session_id,
+        thread_count: process.thread_count(),
+        handle_count: process.handle_count,
+        base_priority: process.pcb.base_priority as i32,
+        priority_class: process.priority_class as u32,
+        flags: process.flags.load(core::sync::atomic::Ordering::Relaxed),
+        create_time: process.create_time,
+        exit_time: process.exit_time,
+        kernel_time: process.pcb.kernel_time(),
+        user_time: process.pcb.user_time(),
+        working_set_size: process.working_set_size,
+        virtual_size: process.virtual_size,
+        peb: process.peb as u64,
+        image_name: process.image_file_name,
+    });
+
+    let head = &process.thread_list_head as *const ListEntry as *mut ListEntry;
+    let mut entry = (*head).flink;
+    while !entry.is_null() && entry != head && snapshot.threads.len() < MAX_THREADS {
+        let thread = &*crate::containing_record!(entry, EThread, thread_list_entry);
+        snapshot.threads.push(SnapshotThreadEntry {
+            thread_id: thread.cid.unique_thread,
+            process_id: pid,
+            state: thread.tcb.state as u8,
+            wait_reason: thread.tcb.wait_reason,
+            priority: thread.tcb.priority,
+            base_priority: thread.tcb.base_priority,
+            flags: thread.flags.load(core::sync::atomic::Ordering::Relaxed),
+            create_time: thread.create_time,
+            kernel_time: thread.tcb.kernel_time,
+            user_time: thread.tcb.user_time,
+            start_address: thread.start_address as u64,
+            win32_start_address: thread.win32_start_address as u64,
+            teb: thread.teb as u64,
+        });
+        entry = (*entry).flink;
+    }
+
+    // The PEB and its loader data live in kernel pools
+    if process.peb.is_null() || (*process.peb).ldr.is_null() {
+        return;
+    }
+    let ldr = &*(*process.peb).ldr;
+    let head = &ldr.in_load_order_module_list as *const _ as u64;
+    let mut link = ldr.in_load_order_module_list.flink;
+    while link != 0 && link != head && snapshot.modules.len() < MAX_LDR_ENTRIES {
+        // in_load_order_links is the first field of the entry
+        let module = &*(link as *const LdrDataTableEntry);
+        snapshot.modules.push(SnapshotModuleEntry {
+            process_id: pid,
+            size_of_image: module.size_of_image,
+            base: module.dll_base as u64,
+            entry_point: module.entry_point as u64,
+            flags: module.flags,
+            load_count: module.load_count,
+            tls_index: module.tls_index,
+        });
+        link = module.in_load_order_links.flink;
+    }
+}
+
+/// Size of a snapshot serialized by `ps_query_snapshot`
+pub fn ps_snapshot_size(snapshot: &ProcessSnapshot) -> usize {
+    core::mem::size_of::<SystemProcessSnapshotInformation>()
+        + snapshot.processes.len() * core::mem::size_of::<SnapshotProcessEntry>()
+        + snapshot.threads.len() * core::mem::size_of::<SnapshotThreadEntry>()
+        + snapshot.modules.len() * core::mem::size_of::<SnapshotModuleEntry>()
+}
+
+/// Write `items` to `buffer` at `offset`; returns the offset after them
+fn write_entries<T: Copy>(buffer: &mut [u8], offset: usize, items: &[T]) -> usize {
+    let size = core::mem::size_of::<T>();
+    for (i, item) in items.iter().enumerate() {
+        unsafe {
+            core::ptr::write_unaligned(buffer.as_mut_ptr().add(offset + i * size) as *mut T, *item);
+        }
+    }
+    offset + core::mem::size_of_val(items)
+}
+
+/// Fill a SystemProcessSnapshotInformation buffer
+///
+/// Returns the required length, writing only if `buffer.len()` suffices,
+/// or `None` if no snapshot could be taken. Processes may be created
+/// between a sizing call and the real one, so callers should retry with
+/// the returned size on a length mismatch.
+pub fn ps_query_snapshot(buffer: &mut [u8]) -> Option<usize> {
+    let snapshot = ps_take_snapshot()?;
+    let required = ps_snapshot_size(&snapshot);
+    if buffer.len() < required {
+        return Some(required);
+    }
+
+    let header = core::mem::size_of::<SystemProcessSnapshotInformation>();
+    let process_offset = header;
+    let thread_offset = process_offset + snapshot.processes.len() * core::mem::size_of::<SnapshotProcessEntry>();
+    let module_offset = thread_offset + snapshot.threads.len() * core::mem::size_of::<SnapshotThreadEntry>();
+
+    let info = SystemProcessSnapshotInformation {
+        length: required as u32,
+        process_count: snapshot.processes.len() as u32,
+        thread_count: snapshot.threads.len() as u32,
+        module_count: snapshot.modules.len() as u32,
+        process_offset: process_offset as u32,
+        thread_offset: thread_offset as u32,
+        module_offset: module_offset as u32,
+        reserved: 0,
+        snapshot_time: snapshot.snapshot_time,
+    };
+    unsafe { core::ptr::write_unaligned(buffer.as_mut_ptr() as *mut SystemProcessSnapshotInformation, info) };
+
+    write_entries(buffer, process_offset, &snapshot.processes);
+    write_entries(buffer, thread_offset, &snapshot.threads);
+    write_entries(buffer, module_offset, &snapshot.modules);
+    Some(required)
+}
@@ -821,16 +821,13 @@ pub fn rpc_binding_from_string_binding(
     let binding_id = state.next_binding_id;
 
     // Simple parsing - find protocol sequence
-    let parts: Vec<&str> = string_binding.splitn(2, ':').collect();
-    if parts.len() < 2 {
-        return Err(RpcStatus::InvalidBinding);
-    }
+    let (protseq, addr_ep) = string_binding.split_once(':')
+        .ok_or(RpcStatus::InvalidBinding)?;
 
-    let protocol_seq = RpcProtocolSequence::from_str(parts[0])
+    let protocol_seq = RpcProtocolSequence::from_str(protseq)
         .ok_or(RpcStatus::NoProtseqs)?;
 
     // Parse network address and endpoint
-    let addr_ep = parts[1];
     let (network_addr, endpoint) = if let Some(bracket_pos) = addr_ep.find('[') {
         let addr = &addr_ep[..bracket_pos];
         let ep = addr_ep[bracket_pos + 1..].trim_end_matches(']');
@@ -984,9 +981,15 @@ pub struct RpcInterfaceSnapshot {
 }
 
 /// Get interface snapshots
+///
+/// Returns an empty list when the kernel heap cannot hold the snapshots.
 pub fn rpc_get_interface_snapshots() -> Vec<RpcInterfaceSnapshot> {
-    let state = RPC_STATE.lock();
     let mut snapshots = Vec::new();
+    if snapshots.try_reserve_exact(MAX_RPC_INTERFACES).is_err() {
+        return snapshots;
+    }
+
+    let state = RPC_STATE.lock();
 
     for iface in state.interfaces.iter() {
         if iface.active {
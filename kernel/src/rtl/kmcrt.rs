@@ -0,0 +1,1113 @@
+//! Kernel-Mode C Runtime (kmcrt)
+//!
+//! The subset of the C runtime that NT's ntoskrnl exports to drivers:
+//! memory and string routines, the `snprintf` family and the compiler
+//! intrinsics MSVC-style sources call (`_InterlockedCompareExchange`,
+//! `_BitScanForward`, ...). With these resolved by the loader, existing
+//! C driver sources can be ported and linked against this kernel without
+//! carrying their own CRT.
+//!
+//! The routines are exported under their C names from `KMCRT_EXPORTS`;
+//! the Rust functions carry a `kmcrt_` prefix so they never collide with
+//! the `memcpy`/`memset` the compiler links into the kernel itself.
+//!
+//! # printf Subset
+//!
+//! Conversions: `%d %i %u %x %X %o %p %c %s %%`, plus the NT extensions
+//! `%S`/`%ls`/`%ws` (wide string), `%C`/`%lc`/`%wc` (wide character) and
+//! `%Z`/`%wZ` (ANSI_STRING/UNICODE_STRING pointer). Flags `- + 0 #` and
+//! space, `*` width and precision, and the length modifiers `hh h l ll z
+//! j t I I32 I64` are understood. As in NT sources, `long` is 32 bits;
+//! use `ll` or `I64` for 64-bit values. Floating point and `%n` are not
+//! supported and are copied through unexpanded.
+
+use core::ffi::VaList;
+use core::sync::atomic::{AtomicI32, AtomicI64, AtomicPtr, Ordering};
+
+/// A C runtime routine exported to drivers
+pub struct KmcrtExport {
+    pub name: &'static str,
+    pub address: *const (),
+}
+
+// Safety: the addresses are of functions, which are immutable
+unsafe impl Sync for KmcrtExport {}
+
+macro_rules! exports {
+    ($($name:literal => $function:ident),* $(,)?) => {
+        &[$(KmcrtExport { name: $name, address: $function as *const () }),*]
+    };
+}
+
+/// Every exported routine, by C name
+pub static KMCRT_EXPORTS: &[KmcrtExport] = exports! {
+    // Memory
+    "memcpy" => kmcrt_memcpy,
+    "memmove" => kmcrt_memmove,
+    "memset" => kmcrt_memset,
+    "memcmp" => kmcrt_memcmp,
+    "memchr" => kmcrt_memchr,
+    // Narrow strings
+    "strlen" => kmcrt_strlen,
+    "strnlen" => kmcrt_strnlen,
+    "strcmp" => kmcrt_strcmp,
+    "strncmp" => kmcrt_strncmp,
+    "_stricmp" => kmcrt_stricmp,
+    "_strnicmp" => kmcrt_strnicmp,
+    "strcpy" => kmcrt_strcpy,
+    "strncpy" => kmcrt_strncpy,
+    "strcat" => kmcrt_strcat,
+    "strchr" => kmcrt_strchr,
+    "strrchr" => kmcrt_strrchr,
+    "strstr" => kmcrt_strstr,
+    // Wide strings
+    "wcslen" => kmcrt_wcslen,
+    "wcsnlen" => kmcrt_wcsnlen,
+    "wcscmp" => kmcrt_wcscmp,
+    "wcsncmp" => kmcrt_wcsncmp,
+    "_wcsicmp" => kmcrt_wcsicmp,
+    "_wcsnicmp" => kmcrt_wcsnicmp,
+    "wcscpy" => kmcrt_wcscpy,
+    "wcsncpy" => kmcrt_wcsncpy,
+    "wcscat" => kmcrt_wcscat,
+    "wcschr" => kmcrt_wcschr,
+    "wcsrchr" => kmcrt_wcsrchr,
+    // Characters and numbers
+    "tolower" => kmcrt_tolower,
+    "toupper" => kmcrt_toupper,
+    "towlower" => kmcrt_towlower,
+    "towupper" => kmcrt_towupper,
+    "atoi" => kmcrt_atoi,
+    "strtoul" => kmcrt_strtoul,
+    // Formatting
+    "snprintf" => kmcrt_snprintf,
+    "vsnprintf" => kmcrt_vsnprintf,
+    "_snprintf" => kmcrt_snprintf_ms,
+    "_vsnprintf" => kmcrt_vsnprintf_ms,
+    // Interlocked intrinsics
+    "_InterlockedCompareExchange" => kmcrt_interlocked_compare_exchange,
+    "_InterlockedCompareExchange64" => kmcrt_interlocked_compare_exchange64,
+    "_InterlockedCompareExchangePointer" => kmcrt_interlocked_compare_exchange_pointer,
+    "_InterlockedExchange" => kmcrt_interlocked_exchange,
+    "_InterlockedExchange64" => kmcrt_interlocked_exchange64,
+    "_InterlockedExchangePointer" => kmcrt_interlocked_exchange_pointer,
+    "_InterlockedExchangeAdd" => kmcrt_interlocked_exchange_add,
+    "_InterlockedExchangeAdd64" => kmcrt_interlocked_exchange_add64,
+    "_InterlockedIncrement" => kmcrt_interlocked_increment,
+    "_InterlockedDecrement" => kmcrt_interlocked_decrement,
+    "_InterlockedIncrement64" => kmcrt_interlocked_increment64,
+    "_InterlockedDecrement64" => kmcrt_interlocked_decrement64,
+    "_InterlockedAnd" => kmcrt_interlocked_and,
+    "_InterlockedOr" => kmcrt_interlocked_or,
+    "_InterlockedXor" => kmcrt_interlocked_xor,
+    // Bit intrinsics
+    "_BitScanForward" => kmcrt_bit_scan_forward,
+    "_BitScanReverse" => kmcrt_bit_scan_reverse,
+    "_BitScanForward64" => kmcrt_bit_scan_forward64,
+    "_BitScanReverse64" => kmcrt_bit_scan_reverse64,
+    "_byteswap_ushort" => kmcrt_byteswap_ushort,
+    "_byteswap_ulong" => kmcrt_byteswap_ulong,
+    "_byteswap_uint64" => kmcrt_byteswap_uint64,
+    "_rotl" => kmcrt_rotl,
+    "_rotr" => kmcrt_rotr,
+};
+
+/// Address of a C runtime export
+pub fn kmcrt_export(name: &str) -> Option<u64> {
+    KMCRT_EXPORTS
+        .iter()
+        .find(|export| export.name == name)
+        .map(|export| export.address as u64)
+}
+
+// ============================================================================
+// Memory
+// ============================================================================
+
+pub unsafe extern "C" fn kmcrt_memcpy(dest: *mut u8, src: *const u8, count: usize) -> *mut u8 {
+    core::ptr::copy_nonoverlapping(src, dest, count);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_memmove(dest: *mut u8, src: *const u8, count: usize) -> *mut u8 {
+    core::ptr::copy(src, dest, count);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_memset(dest: *mut u8, value: i32, count: usize) -> *mut u8 {
+    core::ptr::write_bytes(dest, value as u8, count);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_memcmp(s1: *const u8, s2: *const u8, count: usize) -> i32 {
+    for i in 0..count {
+        let (a, b) = (*s1.add(i), *s2.add(i));
+        if a != b {
+            return a as i32 - b as i32;
+        }
+    }
+    0
+}
+
+pub unsafe extern "C" fn kmcrt_memchr(s: *const u8, value: i32, count: usize) -> *const u8 {
+    for i in 0..count {
+        if *s.add(i) == value as u8 {
+            return s.add(i);
+        }
+    }
+    core::ptr::null()
+}
+
+// ============================================================================
+// Narrow Strings
+// ============================================================================
+
+pub unsafe extern "C" fn kmcrt_strlen(s: *const u8) -> usize {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+pub unsafe extern "C" fn kmcrt_strnlen(s: *const u8, max: usize) -> usize {
+    let mut len = 0;
+    while len < max && *s.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+/// Compare up to `count` characters, optionally folding ASCII case
+unsafe fn compare<T: Copy + Into<u32>>(s1: *const T, s2: *const T, count: usize, fold: bool) -> i32 {
+    let fold_case = |c: u32| if fold && (b'A' as u32..=b'Z' as u32).contains(&c) { c + 32 } else { c };
+    for i in 0..count {
+        let a = fold_case((*s1.add(i)).into());
+        let b = fold_case((*s2.add(i)).into());
+        if a != b {
+            return if a < b { -1 } else { 1 };
+        }
+        if a == 0 {
+            break;
+        }
+    }
+    0
+}
+
+pub unsafe extern "C" fn kmcrt_strcmp(s1: *const u8, s2: *const u8) -> i32 {
+    compare(s1, s2, usize::MAX, false)
+}
+
+pub unsafe extern "C" fn kmcrt_strncmp(s1: *const u8, s2: *const u8, count: usize) -> i32 {
+    compare(s1, s2, count, false)
+}
+
+pub unsafe extern "C" fn kmcrt_stricmp(s1: *const u8, s2: *const u8) -> i32 {
+    compare(s1, s2, usize::MAX, true)
+}
+
+pub unsafe extern "C" fn kmcrt_strnicmp(s1: *const u8, s2: *const u8, count: usize) -> i32 {
+    compare(s1, s2, count, true)
+}
+
+pub unsafe extern "C" fn kmcrt_strcpy(dest: *mut u8, src: *const u8) -> *mut u8 {
+    core::ptr::copy(src, dest, kmcrt_strlen(src) + 1);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_strncpy(dest: *mut u8, src: *const u8, count: usize) -> *mut u8 {
+    let len = kmcrt_strnlen(src, count);
+    core::ptr::copy(src, dest, len);
+    core::ptr::write_bytes(dest.add(len), 0, count - len);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_strcat(dest: *mut u8, src: *const u8) -> *mut u8 {
+    kmcrt_strcpy(dest.add(kmcrt_strlen(dest)), src);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_strchr(s: *const u8, value: i32) -> *const u8 {
+    let len = kmcrt_strlen(s);
+    kmcrt_memchr(s, value, len + 1)
+}
+
+pub unsafe extern "C" fn kmcrt_strrchr(s: *const u8, value: i32) -> *const u8 {
+    let mut i = kmcrt_strlen(s) + 1;
+    while i > 0 {
+        i -= 1;
+        if *s.add(i) == value as u8 {
+            return s.add(i);
+        }
+    }
+    core::ptr::null()
+}
+
+pub unsafe extern "C" fn kmcrt_strstr(haystack: *const u8, needle: *const u8) -> *const u8 {
+    let needle_len = kmcrt_strlen(needle);
+    let mut p = haystack;
+    loop {
+        if kmcrt_strncmp(p, needle, needle_len) == 0 {
+            return p;
+        }
+        if *p == 0 {
+            return core::ptr::null();
+        }
+        p = p.add(1);
+    }
+}
+
+// ============================================================================
+// Wide Strings
+// ============================================================================
+
+pub unsafe extern "C" fn kmcrt_wcslen(s: *const u16) -> usize {
+    kmcrt_wcsnlen(s, usize::MAX)
+}
+
+pub unsafe extern "C" fn kmcrt_wcsnlen(s: *const u16, max: usize) -> usize {
+    let mut len = 0;
+    while len < max && *s.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+pub unsafe extern "C" fn kmcrt_wcscmp(s1: *const u16, s2: *const u16) -> i32 {
+    compare(s1, s2, usize::MAX, false)
+}
+
+pub unsafe extern "C" fn kmcrt_wcsncmp(s1: *const u16, s2: *const u16, count: usize) -> i32 {
+    compare(s1, s2, count, false)
+}
+
+pub unsafe extern "C" fn kmcrt_wcsicmp(s1: *const u16, s2: *const u16) -> i32 {
+    compare(s1, s2, usize::MAX, true)
+}
+
+pub unsafe extern "C" fn kmcrt_wcsnicmp(s1: *const u16, s2: *const u16, count: usize) -> i32 {
+    compare(s1, s2, count, true)
+}
+
+pub unsafe extern "C" fn kmcrt_wcscpy(dest: *mut u16, src: *const u16) -> *mut u16 {
+    core::ptr::copy(src, dest, kmcrt_wcslen(src) + 1);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_wcsncpy(dest: *mut u16, src: *const u16, count: usize) -> *mut u16 {
+    let len = kmcrt_wcsnlen(src, count);
+    core::ptr::copy(src, dest, len);
+    core::ptr::write_bytes(dest.add(len), 0, count - len);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_wcscat(dest: *mut u16, src: *const u16) -> *mut u16 {
+    kmcrt_wcscpy(dest.add(kmcrt_wcslen(dest)), src);
+    dest
+}
+
+pub unsafe extern "C" fn kmcrt_wcschr(s: *const u16, value: u16) -> *const u16 {
+    let mut p = s;
+    loop {
+        if *p == value {
+            return p;
+        }
+        if *p == 0 {
+            return core::ptr::null();
+        }
+        p = p.add(1);
+    }
+}
+
+pub unsafe extern "C" fn kmcrt_wcsrchr(s: *const u16, value: u16) -> *const u16 {
+    let mut i = kmcrt_wcslen(s) + 1;
+    while i > 0 {
+        i -= 1;
+        if *s.add(i) == value {
+            return s.add(i);
+        }
+    }
+    core::ptr::null()
+}
+
+// ============================================================================
+// Characters and Numbers
+// ============================================================================
+
+pub extern "C" fn kmcrt_tolower(c: i32) -> i32 {
+    if (b'A' as i32..=b'Z' as i32).contains(&c) { c + 32 } else { c }
+}
+
+pub extern "C" fn kmcrt_toupper(c: i32) -> i32 {
+    if (b'a' as i32..=b'z' as i32).contains(&c) { c - 32 } else { c }
+}
+
+pub extern "C" fn kmcrt_towlower(c: u16) -> u16 {
+    kmcrt_tolower(c as i32) as u16
+}
+
+pub extern "C" fn kmcrt_towupper(c: u16) -> u16 {
+    kmcrt_toupper(c as i32) as u16
+}
+
+pub unsafe extern "C" fn kmcrt_atoi(s: *const u8) -> i32 {
+    let mut p = s;
+    while matches!(*p, b' ' | b'\t' | b'\n' | b'\r') {
+        p = p.add(1);
+    }
+    let negative = *p == b'-';
+    if *p == b'-' || *p == b'+' {
+        p = p.add(1);
+    }
+    let mut value: i32 = 0;
+    while (*p).is_ascii_digit() {
+        value = value.wrapping_mul(10).wrapping_add((*p - b'0') as i32);
+        p = p.add(1);
+    }
+    if negative { value.wrapping_neg() } else { value }
+}
+
+/// Parse an unsigned number; `base` 0 takes the base from a `0x`/`0` prefix
+pub unsafe extern "C" fn kmcrt_strtoul(s: *const u8, end: *mut *const u8, base: i32) -> u32 {
+    let mut p = s;
+    while matches!(*p, b' ' | b'\t' | b'\n' | b'\r') {
+        p = p.add(1);
+    }
+    let negative = *p == b'-';
+    if *p == b'-' || *p == b'+' {
+        p = p.add(1);
+    }
+    let hex_prefix = *p == b'0' && (*p.add(1) | 0x20) == b'x' && (*p.add(2)).is_ascii_hexdigit();
+    let mut base = base as u32;
+    if (base == 0 || base == 16) && hex_prefix {
+        p = p.add(2);
+        base = 16;
+    } else if base == 0 {
+        base = if *p == b'0' { 8 } else { 10 };
+    }
+
+    let start = p;
+    let mut value: u32 = 0;
+    while let Some(digit) = (*p as char).to_digit(base.clamp(2, 36)) {
+        value = value.saturating_mul(base).saturating_add(digit);
+        p = p.add(1);
+    }
+    if !end.is_null() {
+        *end = if p == start { s } else { p };
+    }
+    if negative { value.wrapping_neg() } else { value }
+}
+
+// ============================================================================
+// Formatting
+// ============================================================================
+
+/// Bounded output buffer; counts what would have been written
+struct Output {
+    buffer: *mut u8,
+    capacity: usize,
+    written: usize,
+}
+
+impl Output {
+    fn push(&mut self, byte: u8) {
+        if self.written < self.capacity {
+            unsafe { *self.buffer.add(self.written) = byte };
+        }
+        self.written += 1;
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.push(b);
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut utf8 = [0u8; 4];
+        self.push_bytes(c.encode_utf8(&mut utf8).as_bytes());
+    }
+
+    fn pad(&mut self, byte: u8, count: usize) {
+        for _ in 0..count {
+            self.push(byte);
+        }
+    }
+}
+
+/// A parsed conversion specification
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// 64-bit argument (ll, I64, z, j, t, I)
+    wide_int: bool,
+    /// 8 or 16-bit argument (hh, h)
+    short: u8,
+    /// Wide character or string (l, w)
+    wide_char: bool,
+}
+
+/// Read a string argument as bytes, up to `limit` characters
+unsafe fn narrow_arg<'a>(s: *const u8, limit: usize) -> &'a [u8] {
+    if s.is_null() {
+        return b"(null)";
+    }
+    core::slice::from_raw_parts(s, kmcrt_strnlen(s, limit))
+}
+
+/// Write a UTF-16 string, up to `limit` characters
+unsafe fn push_wide(out: &mut Output, s: *const u16, len: usize, spec: &Spec) {
+    let limit = spec.precision.unwrap_or(usize::MAX).min(len);
+    let units = if s.is_null() { &[] } else { core::slice::from_raw_parts(s, limit) };
+    let count = char::decode_utf16(units.iter().copied()).count();
+    let pad = spec.width.saturating_sub(count);
+    if !spec.left {
+        out.pad(b' ', pad);
+    }
+    for c in char::decode_utf16(units.iter().copied()) {
+        out.push_char(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+    }
+    if spec.left {
+        out.pad(b' ', pad);
+    }
+}
+
+fn push_text(out: &mut Output, text: &[u8], spec: &Spec) {
+    let text = &text[..spec.precision.unwrap_or(usize::MAX).min(text.len())];
+    let pad = spec.width.saturating_sub(text.len());
+    if !spec.left {
+        out.pad(b' ', pad);
+    }
+    out.push_bytes(text);
+    if spec.left {
+        out.pad(b' ', pad);
+    }
+}
+
+fn push_number(out: &mut Output, magnitude: u64, negative: bool, radix: u64, upper: bool, spec: &Spec) {
+    let digits_table = if upper { b"0123456789ABCDEF" } else { b"0123456789abcdef" };
+    let mut digits = [0u8; 24];
+    let mut len = 0;
+    let mut n = magnitude;
+    while n != 0 {
+        digits[digits.len() - 1 - len] = digits_table[(n % radix) as usize];
+        n /= radix;
+        len += 1;
+    }
+    let digits = &digits[digits.len() - len..];
+
+    // Precision is the minimum digit count; "%.0d" of zero prints nothing
+    let precision = spec.precision.unwrap_or(1);
+    let mut zeros = precision.saturating_sub(digits.len());
+    let prefix: &[u8] = match radix {
+        _ if negative => b"-",
+        10 if spec.plus => b"+",
+        10 if spec.space => b" ",
+        16 if spec.alternate && magnitude != 0 => if upper { b"0X" } else { b"0x" },
+        8 if spec.alternate && zeros == 0 && magnitude != 0 => b"0",
+        _ => b"",
+    };
+
+    let mut length = prefix.len() + zeros + digits.len();
+    if spec.zero && !spec.left && spec.precision.is_none() && length < spec.width {
+        zeros += spec.width - length;
+        length = spec.width;
+    }
+    let pad = spec.width.saturating_sub(length);
+    if !spec.left {
+        out.pad(b' ', pad);
+    }
+    out.push_bytes(prefix);
+    out.pad(b'0', zeros);
+    out.push_bytes(digits);
+    if spec.left {
+        out.pad(b' ', pad);
+    }
+}
+
+/// Read an unsigned integer argument of the spec's size
+unsafe fn unsigned_arg(args: &mut VaList, spec: &Spec) -> u64 {
+    let value = if spec.wide_int { args.next_arg::<u64>() } else { args.next_arg::<u32>() as u64 };
+    match spec.short {
+        8 => value as u8 as u64,
+        16 => value as u16 as u64,
+        _ => value,
+    }
+}
+
+/// Expand a printf format into `out`
+unsafe fn expand(out: &mut Output, fmt: *const u8, args: &mut VaList) {
+    if fmt.is_null() {
+        return;
+    }
+    let mut p = fmt;
+
+    while *p != 0 {
+        if *p != b'%' {
+            out.push(*p);
+            p = p.add(1);
+            continue;
+        }
+        let start = p;
+        p = p.add(1);
+
+        let mut spec = Spec::default();
+        loop {
+            match *p {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'0' => spec.zero = true,
+                b'#' => spec.alternate = true,
+                _ => break,
+            }
+            p = p.add(1);
+        }
+
+        if *p == b'*' {
+            let width = args.next_arg::<i32>();
+            spec.left |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+            p = p.add(1);
+        } else {
+            while (*p).is_ascii_digit() {
+                spec.width = spec.width * 10 + (*p - b'0') as usize;
+                p = p.add(1);
+            }
+        }
+
+        if *p == b'.' {
+            p = p.add(1);
+            let mut precision = 0usize;
+            if *p == b'*' {
+                // A negative precision is taken as omitted
+                let value = args.next_arg::<i32>();
+                spec.precision = usize::try_from(value).ok();
+                p = p.add(1);
+            } else {
+                while (*p).is_ascii_digit() {
+                    precision = precision * 10 + (*p - b'0') as usize;
+                    p = p.add(1);
+                }
+                spec.precision = Some(precision);
+            }
+        }
+
+        // Length modifiers
+        loop {
+            match *p {
+                b'h' => spec.short = if spec.short == 16 { 8 } else { 16 },
+                b'l' if *p.add(1) == b'l' => {
+                    spec.wide_int = true;
+                    p = p.add(1);
+                }
+                b'l' | b'w' => spec.wide_char = true,
+                b'z' | b'j' | b't' => spec.wide_int = true,
+                b'I' if *p.add(1) == b'6' && *p.add(2) == b'4' => {
+                    spec.wide_int = true;
+                    p = p.add(2);
+                }
+                b'I' if *p.add(1) == b'3' && *p.add(2) == b'2' => p = p.add(2),
+                b'I' => spec.wide_int = true,
+                _ => break,
+            }
+            p = p.add(1);
+        }
+
+        let conversion = *p;
+        if conversion == 0 {
+            out.push_bytes(narrow_arg(start, usize::MAX));
+            break;
+        }
+        p = p.add(1);
+
+        match conversion {
+            b'd' | b'i' => {
+                let value = if spec.wide_int { args.next_arg::<i64>() } else { args.next_arg::<i32>() as i64 };
+                let value = match spec.short {
+                    8 => value as i8 as i64,
+                    16 => value as i16 as i64,
+                    _ => value,
+                };
+                push_number(out, value.unsigned_abs(), value < 0, 10, false, &spec);
+            }
+            b'u' => push_number(out, unsigned_arg(args, &spec), false, 10, false, &spec),
+            b'x' => push_number(out, unsigned_arg(args, &spec), false, 16, false, &spec),
+            b'X' => push_number(out, unsigned_arg(args, &spec), false, 16, true, &spec),
+            b'o' => push_number(out, unsigned_arg(args, &spec), false, 8, false, &spec),
+            b'p' => {
+                let pointer = args.next_arg::<usize>() as u64;
+                let spec = Spec { precision: Some(16), ..spec };
+                push_number(out, pointer, false, 16, true, &spec);
+            }
+            b'c' if !spec.wide_char => {
+                let c = [args.next_arg::<i32>() as u8];
+                push_text(out, &c, &Spec { precision: None, ..spec });
+            }
+            b'c' | b'C' => {
+                let c = args.next_arg::<u32>() as u16;
+                push_wide(out, &c, 1, &Spec { precision: None, ..spec });
+            }
+            b's' if !spec.wide_char => {
+                let s = args.next_arg::<usize>() as *const u8;
+                push_text(out, narrow_arg(s, spec.precision.unwrap_or(usize::MAX)), &spec);
+            }
+            b's' | b'S' => {
+                let s = args.next_arg::<usize>() as *const u16;
+                if s.is_null() {
+                    push_text(out, b"(null)", &spec);
+                } else {
+                    push_wide(out, s, kmcrt_wcsnlen(s, spec.precision.unwrap_or(usize::MAX)), &spec);
+                }
+            }
+            b'Z' => {
+                // ANSI_STRING, or UNICODE_STRING with `w`
+                let string = args.next_arg::<usize>();
+                if string == 0 {
+                    push_text(out, b"(null)", &spec);
+                } else if spec.wide_char {
+                    let us = &*(string as *const super::UnicodeString);
+                    push_wide(out, us.buffer, us.length as usize / 2, &spec);
+                } else {
+                    let ansi = &*(string as *const super::AnsiString);
+                    push_text(out, ansi.as_slice(), &spec);
+                }
+            }
+            b'%' => out.push(b'%'),
+            _ => {
+                // Unsupported conversion: copy it through
+                let len = p as usize - start as usize;
+                out.push_bytes(core::slice::from_raw_parts(start, len));
+            }
+        }
+    }
+}
+
+/// Format into `buffer` (C99 semantics)
+///
+/// Always NUL-terminates when `count` is nonzero. Returns the length the
+/// full output would have, so a result of `count` or more means it was
+/// truncated.
+pub unsafe extern "C" fn kmcrt_vsnprintf(buffer: *mut u8, count: usize, fmt: *const u8, mut args: VaList) -> i32 {
+    let mut out = Output { buffer, capacity: count.saturating_sub(1), written: 0 };
+    expand(&mut out, fmt, &mut args);
+    if count > 0 {
+        *buffer.add(out.written.min(count - 1)) = 0;
+    }
+    out.written as i32
+}
+
+pub unsafe extern "C" fn kmcrt_snprintf(buffer: *mut u8, count: usize, fmt: *const u8, args: ...) -> i32 {
+    kmcrt_vsnprintf(buffer, count, fmt, args)
+}
+
+/// Format into `buffer` (Microsoft `_vsnprintf` semantics)
+///
+/// Returns -1 when the output does not fit, in which case the buffer is
+/// full and not NUL-terminated.
+pub unsafe extern "C" fn kmcrt_vsnprintf_ms(buffer: *mut u8, count: usize, fmt: *const u8, mut args: VaList) -> i32 {
+    let mut out = Output { buffer, capacity: count, written: 0 };
+    expand(&mut out, fmt, &mut args);
+    if out.written > count {
+        return -1;
+    }
+    if out.written < count {
+        *buffer.add(out.written) = 0;
+    }
+    out.written as i32
+}
+
+pub unsafe extern "C" fn kmcrt_snprintf_ms(buffer: *mut u8, count: usize, fmt: *const u8, args: ...) -> i32 {
+    kmcrt_vsnprintf_ms(buffer, count, fmt, args)
+}
+
+// ============================================================================
+// Interlocked Intrinsics
+// ============================================================================
+
+pub unsafe extern "C" fn kmcrt_interlocked_compare_exchange(dest: *mut i32, exchange: i32, comparand: i32) -> i32 {
+    match AtomicI32::from_ptr(dest).compare_exchange(comparand, exchange, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(v) | Err(v) => v,
+    }
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_compare_exchange64(dest: *mut i64, exchange: i64, comparand: i64) -> i64 {
+    match AtomicI64::from_ptr(dest).compare_exchange(comparand, exchange, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(v) | Err(v) => v,
+    }
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_compare_exchange_pointer(
+    dest: *mut *mut u8,
+    exchange: *mut u8,
+    comparand: *mut u8,
+) -> *mut u8 {
+    match AtomicPtr::from_ptr(dest).compare_exchange(comparand, exchange, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(v) | Err(v) => v,
+    }
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_exchange(dest: *mut i32, value: i32) -> i32 {
+    AtomicI32::from_ptr(dest).swap(value, Ordering::SeqCst)
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_exchange64(dest: *mut i64, value: i64) -> i64 {
+    AtomicI64::from_ptr(dest).swap(value, Ordering::SeqCst)
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_exchange_pointer(dest: *mut *mut u8, value: *mut u8) -> *mut u8 {
+    AtomicPtr::from_ptr(dest).swap(value, Ordering::SeqCst)
+}
+
+/// Returns the original value
+pub unsafe extern "C" fn kmcrt_interlocked_exchange_add(dest: *mut i32, value: i32) -> i32 {
+    AtomicI32::from_ptr(dest).fetch_add(value, Ordering::SeqCst)
+}
+
+/// Returns the original value
+pub unsafe extern "C" fn kmcrt_interlocked_exchange_add64(dest: *mut i64, value: i64) -> i64 {
+    AtomicI64::from_ptr(dest).fetch_add(value, Ordering::SeqCst)
+}
+
+/// Returns the new value
+pub unsafe extern "C" fn kmcrt_interlocked_increment(dest: *mut i32) -> i32 {
+    AtomicI32::from_ptr(dest).fetch_add(1, Ordering::SeqCst).wrapping_add(1)
+}
+
+/// Returns the new value
+pub unsafe extern "C" fn kmcrt_interlocked_decrement(dest: *mut i32) -> i32 {
+    AtomicI32::from_ptr(dest).fetch_sub(1, Ordering::SeqCst).wrapping_sub(1)
+}
+
+/// Returns the new value
+pub unsafe extern "C" fn kmcrt_interlocked_increment64(dest: *mut i64) -> i64 {
+    AtomicI64::from_ptr(dest).fetch_add(1, Ordering::SeqCst).wrapping_add(1)
+}
+
+/// Returns the new value
+pub unsafe extern "C" fn kmcrt_interlocked_decrement64(dest: *mut i64) -> i64 {
+    AtomicI64::from_ptr(dest).fetch_sub(1, Ordering::SeqCst).wrapping_sub(1)
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_and(dest: *mut i32, value: i32) -> i32 {
+    AtomicI32::from_ptr(dest).fetch_and(value, Ordering::SeqCst)
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_or(dest: *mut i32, value: i32) -> i32 {
+    AtomicI32::from_ptr(dest).fetch_or(value, Ordering::SeqCst)
+}
+
+pub unsafe extern "C" fn kmcrt_interlocked_xor(dest: *mut i32, value: i32) -> i32 {
+    AtomicI32::from_ptr(dest).fetch_xor(value, Ordering::SeqCst)
+}
+
+// ============================================================================
+// Bit Intrinsics
+// ============================================================================
+
+/// Index of the lowest set bit; returns 0 (index unset) for a zero mask
+pub unsafe extern "C" fn kmcrt_bit_scan_forward(index: *mut u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    *index = mask.trailing_zeros();
+    1
+}
+
+/// Index of the highest set bit; returns 0 (index unset) for a zero mask
+pub unsafe extern "C" fn kmcrt_bit_scan_reverse(index: *mut u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    *index = 31 - mask.leading_zeros();
+    1
+}
+
+pub unsafe extern "C" fn kmcrt_bit_scan_forward64(index: *mut u32, mask: u64) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    *index = mask.trailing_zeros();
+    1
+}
+
+pub unsafe extern "C" fn kmcrt_bit_scan_reverse64(index: *mut u32, mask: u64) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    *index = 63 - mask.leading_zeros();
+    1
+}
+
+pub extern "C" fn kmcrt_byteswap_ushort(value: u16) -> u16 {
+    value.swap_bytes()
+}
+
+pub extern "C" fn kmcrt_byteswap_ulong(value: u32) -> u32 {
+    value.swap_bytes()
+}
+
+pub extern "C" fn kmcrt_byteswap_uint64(value: u64) -> u64 {
+    value.swap_bytes()
+}
+
+pub extern "C" fn kmcrt_rotl(value: u32, shift: i32) -> u32 {
+    value.rotate_left(shift as u32 & 31)
+}
+
+pub extern "C" fn kmcrt_rotr(value: u32, shift: i32) -> u32 {
+    value.rotate_right(shift as u32 & 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Format with `kmcrt_snprintf` into a 64-byte buffer and check the
+    /// output, its length and its terminator
+    macro_rules! assert_format {
+        ($expected:expr, $fmt:literal $(, $arg:expr)*) => {{
+            let mut buffer = [0xAAu8; 64];
+            let fmt = concat!($fmt, "\0").as_ptr();
+            let written = unsafe { kmcrt_snprintf(buffer.as_mut_ptr(), buffer.len(), fmt $(, $arg)*) };
+            let expected: &[u8] = $expected;
+            assert_eq!(&buffer[..expected.len()], expected);
+            assert_eq!(buffer[expected.len()], 0);
+            assert_eq!(written, expected.len() as i32);
+        }};
+    }
+
+    fn wide(s: &str) -> [u16; 16] {
+        let mut units = [0u16; 16];
+        for (unit, c) in units.iter_mut().zip(s.encode_utf16()) {
+            *unit = c;
+        }
+        units
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut buffer = *b"abcdef";
+        unsafe {
+            kmcrt_memmove(buffer.as_mut_ptr().add(2), buffer.as_ptr(), 3);
+            assert_eq!(&buffer, b"ababcf");
+            kmcrt_memset(buffer.as_mut_ptr(), b'x' as i32, 2);
+            assert_eq!(&buffer, b"xxabcf");
+            assert_eq!(kmcrt_memcmp(b"abc".as_ptr(), b"abd".as_ptr(), 3), -1);
+            assert_eq!(kmcrt_memcmp(b"abc".as_ptr(), b"abd".as_ptr(), 2), 0);
+            assert_eq!(kmcrt_memchr(buffer.as_ptr(), b'c' as i32, 6), buffer.as_ptr().add(4));
+            assert!(kmcrt_memchr(buffer.as_ptr(), b'z' as i32, 6).is_null());
+        }
+    }
+
+    #[test]
+    fn test_narrow_compare() {
+        unsafe {
+            assert_eq!(kmcrt_strlen(c"hello".as_ptr().cast()), 5);
+            assert_eq!(kmcrt_strnlen(c"hello".as_ptr().cast(), 3), 3);
+            assert_eq!(kmcrt_strcmp(c"abc".as_ptr().cast(), c"abc".as_ptr().cast()), 0);
+            assert!(kmcrt_strcmp(c"abc".as_ptr().cast(), c"abd".as_ptr().cast()) < 0);
+            assert!(kmcrt_strcmp(c"ab".as_ptr().cast(), c"abc".as_ptr().cast()) < 0);
+            assert_eq!(kmcrt_strncmp(c"abcx".as_ptr().cast(), c"abcy".as_ptr().cast(), 3), 0);
+            assert_eq!(kmcrt_stricmp(c"HeLLo".as_ptr().cast(), c"hello".as_ptr().cast()), 0);
+            assert!(kmcrt_stricmp(c"a".as_ptr().cast(), c"B".as_ptr().cast()) < 0);
+            assert_eq!(kmcrt_strnicmp(c"ABx".as_ptr().cast(), c"aby".as_ptr().cast(), 2), 0);
+        }
+    }
+
+    #[test]
+    fn test_narrow_copy_and_search() {
+        let mut buffer = [0xFFu8; 16];
+        unsafe {
+            kmcrt_strcpy(buffer.as_mut_ptr(), c"foo".as_ptr().cast());
+            kmcrt_strcat(buffer.as_mut_ptr(), c"bar".as_ptr().cast());
+            assert_eq!(&buffer[..7], b"foobar\0");
+
+            kmcrt_strncpy(buffer.as_mut_ptr(), c"ab".as_ptr().cast(), 5);
+            assert_eq!(&buffer[..6], b"ab\0\0\0r");
+
+            let s: *const u8 = c"a/b/c".as_ptr().cast();
+            assert_eq!(kmcrt_strchr(s, b'/' as i32), s.add(1));
+            assert_eq!(kmcrt_strrchr(s, b'/' as i32), s.add(3));
+            assert_eq!(kmcrt_strchr(s, 0), s.add(5));
+            assert!(kmcrt_strchr(s, b'x' as i32).is_null());
+            assert_eq!(kmcrt_strstr(s, c"b/c".as_ptr().cast()), s.add(2));
+            assert_eq!(kmcrt_strstr(s, c"".as_ptr().cast()), s);
+            assert!(kmcrt_strstr(s, c"c/".as_ptr().cast()).is_null());
+        }
+    }
+
+    #[test]
+    fn test_wide() {
+        let hello = wide("Hello");
+        let mut buffer = [0xFFFFu16; 16];
+        unsafe {
+            assert_eq!(kmcrt_wcslen(hello.as_ptr()), 5);
+            assert_eq!(kmcrt_wcsicmp(hello.as_ptr(), wide("hELLO").as_ptr()), 0);
+            assert!(kmcrt_wcscmp(hello.as_ptr(), wide("Help").as_ptr()) < 0);
+            assert_eq!(kmcrt_wcsnicmp(hello.as_ptr(), wide("HELP").as_ptr(), 3), 0);
+
+            kmcrt_wcscpy(buffer.as_mut_ptr(), hello.as_ptr());
+            kmcrt_wcscat(buffer.as_mut_ptr(), wide("!").as_ptr());
+            assert_eq!(&buffer[..7], &wide("Hello!")[..7]);
+            assert_eq!(kmcrt_wcschr(hello.as_ptr(), b'l' as u16), hello.as_ptr().add(2));
+            assert_eq!(kmcrt_wcsrchr(hello.as_ptr(), b'l' as u16), hello.as_ptr().add(3));
+            assert!(kmcrt_wcschr(hello.as_ptr(), b'z' as u16).is_null());
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let mut end = core::ptr::null();
+        unsafe {
+            assert_eq!(kmcrt_atoi(c"  -42abc".as_ptr().cast()), -42);
+            assert_eq!(kmcrt_atoi(c"+7".as_ptr().cast()), 7);
+            assert_eq!(kmcrt_atoi(c"x".as_ptr().cast()), 0);
+
+            let s: *const u8 = c"0x1fz".as_ptr().cast();
+            assert_eq!(kmcrt_strtoul(s, &mut end, 0), 0x1f);
+            assert_eq!(end, s.add(4));
+            assert_eq!(kmcrt_strtoul(c"017".as_ptr().cast(), &mut end, 0), 0o17);
+            assert_eq!(kmcrt_strtoul(c"ff".as_ptr().cast(), &mut end, 16), 0xff);
+            assert_eq!(kmcrt_strtoul(c"99999999999".as_ptr().cast(), &mut end, 10), u32::MAX);
+
+            // No digits: `end` is the start of the string
+            let s: *const u8 = c"  zz".as_ptr().cast();
+            assert_eq!(kmcrt_strtoul(s, &mut end, 10), 0);
+            assert_eq!(end, s);
+        }
+        assert_eq!(kmcrt_toupper(b'a' as i32), b'A' as i32);
+        assert_eq!(kmcrt_tolower(b'[' as i32), b'[' as i32);
+        assert_eq!(kmcrt_towlower(0xC9), 0xC9);
+    }
+
+    #[test]
+    fn test_format_integers() {
+        assert_format!(b"42 -7 ff FF 17", "%d %i %x %X %o", 42i32, -7i32, 255u32, 255u32, 15u32);
+        assert_format!(b"[  -42][-42  ][-0042]", "[%5d][%-5d][%05d]", -42i32, -42i32, -42i32);
+        assert_format!(b"+5| 5|0x1f|017", "%+d|% d|%#x|%#o", 5i32, 5i32, 31u32, 15u32);
+        assert_format!(b"00042|   0042|", "%.5d|%7.4d|%.0d", 42i32, 42i32, 0i32);
+        assert_format!(b"4294967295", "%u", u32::MAX);
+        assert_format!(b"-1 ffffffffff", "%I64d %llx", -1i64, 0xFF_FFFF_FFFFu64);
+        assert_format!(b"-1 255 65535", "%hhd %hhu %hu", 0xFFi32, 0x1FFu32, 0x1FFFFu32);
+        assert_format!(b"[    7]", "[%*d]", 5i32, 7i32);
+        assert_format!(b"[7    ]", "[%*d]", -5i32, 7i32);
+    }
+
+    #[test]
+    fn test_format_long_is_32_bits() {
+        assert_format!(b"ffffffff", "%lx", 0xFFFF_FFFFu32);
+        assert_format!(b"123456789abc", "%Ix", 0x1234_5678_9ABCusize);
+    }
+
+    #[test]
+    fn test_format_strings() {
+        let s: *const u8 = c"text".as_ptr().cast();
+        assert_format!(b"[text][  text][te  ]", "[%s][%6s][%-4.2s]", s, s, s);
+        assert_format!(b"(null)", "%s", core::ptr::null::<u8>());
+        assert_format!(b"x%", "%c%%", b'x' as i32);
+        assert_format!(b"[tex]", "[%.*s]", 3i32, s);
+
+        let w = wide("Wide\u{e9}");
+        assert_format!("Wide\u{e9}|Wi".as_bytes(), "%S|%.2ls", w.as_ptr(), w.as_ptr());
+        assert_format!("\u{e9}".as_bytes(), "%C", 0xE9u32);
+    }
+
+    #[test]
+    fn test_format_counted_strings() {
+        let mut text = *b"ansi!";
+        let ansi = super::super::AnsiString { length: 4, maximum_length: 5, buffer: text.as_mut_ptr() };
+        let mut units = wide("unicode");
+        let unicode = super::super::UnicodeString { length: 6, maximum_length: 32, buffer: units.as_mut_ptr() };
+        assert_format!(b"ansi|uni", "%Z|%wZ", &ansi as *const _, &unicode as *const _);
+    }
+
+    #[test]
+    fn test_format_pointer_and_unsupported() {
+        assert_format!(b"00000000DEADBEEF", "%p", 0xDEAD_BEEFusize);
+        assert_format!(b"%f %n", "%f %n");
+        assert_format!(b"end %", "end %");
+    }
+
+    #[test]
+    fn test_snprintf_truncation() {
+        let mut buffer = [0xAAu8; 8];
+        let written = unsafe { kmcrt_snprintf(buffer.as_mut_ptr(), 4, c"%d".as_ptr().cast(), 123456i32) };
+        assert_eq!(written, 6);
+        assert_eq!(&buffer[..5], b"123\0\xAA");
+        let written = unsafe { kmcrt_snprintf(buffer.as_mut_ptr(), 0, c"abc".as_ptr().cast()) };
+        assert_eq!(written, 3);
+        assert_eq!(buffer[0], b'1');
+    }
+
+    #[test]
+    fn test_snprintf_ms_truncation() {
+        let mut buffer = [0xAAu8; 8];
+        let written = unsafe { kmcrt_snprintf_ms(buffer.as_mut_ptr(), 4, c"%d".as_ptr().cast(), 123456i32) };
+        assert_eq!(written, -1);
+        assert_eq!(&buffer[..5], b"1234\xAA");
+        // Exactly full: no room for the terminator, but not an error
+        let written = unsafe { kmcrt_snprintf_ms(buffer.as_mut_ptr(), 4, c"abcd".as_ptr().cast()) };
+        assert_eq!(written, 4);
+        assert_eq!(&buffer[..5], b"abcd\xAA");
+        let written = unsafe { kmcrt_snprintf_ms(buffer.as_mut_ptr(), 8, c"ab".as_ptr().cast()) };
+        assert_eq!(written, 2);
+        assert_eq!(&buffer[..3], b"ab\0");
+    }
+
+    #[test]
+    fn test_interlocked() {
+        let mut value = 5i32;
+        let mut value64 = 5i64;
+        unsafe {
+            assert_eq!(kmcrt_interlocked_compare_exchange(&mut value, 9, 4), 5);
+            assert_eq!(value, 5);
+            assert_eq!(kmcrt_interlocked_compare_exchange(&mut value, 9, 5), 5);
+            assert_eq!(value, 9);
+            assert_eq!(kmcrt_interlocked_exchange_add(&mut value, 1), 9);
+            assert_eq!(kmcrt_interlocked_increment(&mut value), 11);
+            assert_eq!(kmcrt_interlocked_decrement(&mut value), 10);
+            assert_eq!(kmcrt_interlocked_and(&mut value, 0b0110), 10);
+            assert_eq!(value, 0b0010);
+            assert_eq!(kmcrt_interlocked_or(&mut value, 0b1000), 0b0010);
+            assert_eq!(kmcrt_interlocked_xor(&mut value, 0b1010), 0b1010);
+            assert_eq!(value, 0);
+
+            assert_eq!(kmcrt_interlocked_exchange64(&mut value64, i64::MAX), 5);
+            assert_eq!(kmcrt_interlocked_increment64(&mut value64), i64::MIN);
+            assert_eq!(kmcrt_interlocked_compare_exchange64(&mut value64, 1, i64::MIN), i64::MIN);
+            assert_eq!(kmcrt_interlocked_decrement64(&mut value64), 0);
+        }
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut index = 99u32;
+        unsafe {
+            assert_eq!(kmcrt_bit_scan_forward(&mut index, 0), 0);
+            assert_eq!(index, 99);
+            assert_eq!(kmcrt_bit_scan_forward(&mut index, 0b1000_1000), 1);
+            assert_eq!(index, 3);
+            assert_eq!(kmcrt_bit_scan_reverse(&mut index, 0b1000_1000), 1);
+            assert_eq!(index, 7);
+            assert_eq!(kmcrt_bit_scan_reverse64(&mut index, 1 << 40), 1);
+            assert_eq!(index, 40);
+        }
+        assert_eq!(kmcrt_byteswap_ulong(0x1122_3344), 0x4433_2211);
+        assert_eq!(kmcrt_rotl(0x8000_0001, 1), 0x0000_0003);
+        assert_eq!(kmcrt_rotr(0x0000_0003, 33), 0x8000_0001);
+    }
+
+    #[test]
+    fn test_exports() {
+        assert_eq!(kmcrt_export("memcpy"), Some(kmcrt_memcpy as *const () as u64));
+        assert!(kmcrt_export("_vsnprintf").is_some());
+        assert!(kmcrt_export("printf").is_none());
+    }
+}
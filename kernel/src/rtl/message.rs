@@ -0,0 +1,680 @@
+//! Message Tables
+//!
+//! Message-compiler (mc) compatible message tables and the routines that
+//! look up and format their text.
+//!
+//! # RT_MESSAGETABLE layout
+//!
+//! ```text
+//! MESSAGE_RESOURCE_DATA
+//!     NumberOfBlocks: u32
+//!     Blocks[NumberOfBlocks]
+//!         LowId: u32, HighId: u32, OffsetToEntries: u32
+//!
+//! MESSAGE_RESOURCE_ENTRY (one per id in LowId..=HighId)
+//!     Length: u16          // whole entry, padded to 4 bytes
+//!     Flags: u16           // MESSAGE_RESOURCE_UNICODE => UTF-16 text
+//!     Text: [u8]           // NUL-terminated
+//! ```
+//!
+//! The kernel carries three tables of its own (system/network errors,
+//! NTSTATUS texts and event log descriptions), built on first use. Other
+//! modules carry theirs as resource type 11, name 1 in their PE image.
+//!
+//! # Insert sequences (RtlFormatMessage)
+//!
+//! - `%1`..`%99` - argument, optionally with a printf spec: `%2!08X!`
+//! - `%n` - line break, `%r` - bare CR, `%t` - tab
+//! - `%0` - end of message, no trailing line break
+//! - `%%`, `%.`, `%!`, `% ` - the literal character
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::ke::SpinLock;
+
+/// Entry text is UTF-16 rather than ANSI
+pub const MESSAGE_RESOURCE_UNICODE: u16 = 0x0001;
+
+/// Resource type of a message table
+pub const RT_MESSAGETABLE: u32 = 11;
+
+/// Resource name mc gives the message table
+pub const MESSAGE_TABLE_RESOURCE_NAME: u32 = 1;
+
+/// Maximum-width value that joins source lines without wrapping
+pub const FORMAT_MESSAGE_MAX_WIDTH_MASK: u32 = 0xFF;
+
+/// Highest insert number
+pub const MAXIMUM_MESSAGE_INSERT: usize = 99;
+
+const STATUS_INVALID_PARAMETER: i32 = 0xC000000Du32 as i32;
+
+/// An insertion argument for `rtl_format_message`
+#[derive(Debug, Clone, Copy)]
+pub enum MessageArgument<'a> {
+    Str(&'a str),
+    Number(u64),
+}
+
+// ============================================================================
+// Table Lookup
+// ============================================================================
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Find message `id` in a MESSAGE_RESOURCE_DATA image
+///
+/// Returns the text with its terminating NULs removed; mc-built tables
+/// keep the trailing line break of each message.
+pub fn rtl_find_message(data: &[u8], id: u32) -> Option<String> {
+    let blocks = read_u32(data, 0)? as usize;
+    for block in 0..blocks {
+        let base = 4 + block * 12;
+        let low = read_u32(data, base)?;
+        let high = read_u32(data, base + 4)?;
+        if id < low || id > high {
+            continue;
+        }
+
+        let mut offset = read_u32(data, base + 8)? as usize;
+        for _ in low..id {
+            let length = read_u16(data, offset)? as usize;
+            if length < 4 {
+                return None;
+            }
+            offset += length;
+        }
+
+        let length = read_u16(data, offset)? as usize;
+        let flags = read_u16(data, offset + 2)?;
+        let text = data.get(offset + 4..offset + length.max(4))?;
+        return Some(if flags & MESSAGE_RESOURCE_UNICODE != 0 {
+            let units: Vec<u16> = text
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|&c| u16::from_le_bytes(c))
+                .take_while(|&u| u != 0)
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            text.iter()
+                .take_while(|&&b| b != 0)
+                .map(|&b| b as char)
+                .collect()
+        });
+    }
+    None
+}
+
+/// Build a MESSAGE_RESOURCE_DATA image from `(id, text)` pairs
+///
+/// Consecutive ids share a block. ASCII text is stored as ANSI entries,
+/// anything else as UTF-16, as mc does.
+pub fn rtl_build_message_table(messages: &[(u32, &str)]) -> Vec<u8> {
+    let mut sorted: Vec<(u32, &str)> = messages.to_vec();
+    sorted.sort_by_key(|m| m.0);
+    sorted.dedup_by_key(|m| m.0);
+
+    // Group runs of consecutive ids
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (i, &(id, _)) in sorted.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if sorted[range.1].0 + 1 == id => range.1 = i,
+            _ => ranges.push((i, i)),
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut offsets = Vec::with_capacity(ranges.len());
+    let header = 4 + ranges.len() * 12;
+    for &(first, last) in &ranges {
+        offsets.push(header + entries.len());
+        for &(_, text) in &sorted[first..=last] {
+            let (flags, mut bytes) = if text.is_ascii() {
+                let mut bytes = Vec::from(text.as_bytes());
+                bytes.push(0);
+                (0, bytes)
+            } else {
+                let mut bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                bytes.extend_from_slice(&[0, 0]);
+                (MESSAGE_RESOURCE_UNICODE, bytes)
+            };
+            while bytes.len() % 4 != 0 {
+                bytes.push(0);
+            }
+            entries.extend_from_slice(&((bytes.len() + 4) as u16).to_le_bytes());
+            entries.extend_from_slice(&flags.to_le_bytes());
+            entries.extend_from_slice(&bytes);
+        }
+    }
+
+    let mut data = Vec::with_capacity(header + entries.len());
+    data.extend_from_slice(&(ranges.len() as u32).to_le_bytes());
+    for (&(first, last), &offset) in ranges.iter().zip(&offsets) {
+        data.extend_from_slice(&sorted[first].0.to_le_bytes());
+        data.extend_from_slice(&sorted[last].0.to_le_bytes());
+        data.extend_from_slice(&(offset as u32).to_le_bytes());
+    }
+    data.extend_from_slice(&entries);
+    data
+}
+
+// ============================================================================
+// PE Resources
+// ============================================================================
+
+/// Select the entry with integer id `id` from a resource directory
+///
+/// `None` as the id takes the first entry (any language).
+fn resource_directory_entry(section: &[u8], directory: usize, id: Option<u32>) -> Option<u32> {
+    let named = read_u16(section, directory + 12)? as usize;
+    let ids = read_u16(section, directory + 14)? as usize;
+    let first = directory + 16;
+    let mut fallback = None;
+    for i in named..named + ids {
+        let name = read_u32(section, first + i * 8)?;
+        let target = read_u32(section, first + i * 8 + 4)?;
+        if Some(name) == id {
+            return Some(target);
+        }
+        fallback.get_or_insert(target);
+    }
+    if id.is_none() { fallback } else { None }
+}
+
+/// Locate the message table resource of a mapped PE image
+///
+/// Picks `language` when the image has it, otherwise its first language.
+///
+/// # Safety
+/// `base` must be the base of a mapped PE image.
+pub unsafe fn rtl_find_message_resource(base: *const u8, language: u16) -> Option<&'static [u8]> {
+    use super::image::rtl_image_directory_entry_to_data;
+    use crate::ldr::pe::directory_entry::IMAGE_DIRECTORY_ENTRY_RESOURCE;
+
+    const SUBDIRECTORY: u32 = 0x8000_0000;
+
+    let mut size = 0u32;
+    let section = rtl_image_directory_entry_to_data(base, IMAGE_DIRECTORY_ENTRY_RESOURCE, &mut size);
+    if section.is_null() {
+        return None;
+    }
+    let section = core::slice::from_raw_parts(section, size as usize);
+
+    // Type -> name -> language -> data entry
+    let by_type = resource_directory_entry(section, 0, Some(RT_MESSAGETABLE))?;
+    if by_type & SUBDIRECTORY == 0 {
+        return None;
+    }
+    let by_name = resource_directory_entry(
+        section, (by_type & !SUBDIRECTORY) as usize, Some(MESSAGE_TABLE_RESOURCE_NAME))?;
+    if by_name & SUBDIRECTORY == 0 {
+        return None;
+    }
+    let languages = (by_name & !SUBDIRECTORY) as usize;
+    let data_entry = resource_directory_entry(section, languages, Some(language as u32))
+        .or_else(|| resource_directory_entry(section, languages, None))?;
+    if data_entry & SUBDIRECTORY != 0 {
+        return None;
+    }
+
+    let rva = read_u32(section, data_entry as usize)?;
+    let length = read_u32(section, data_entry as usize + 4)?;
+    Some(core::slice::from_raw_parts(base.add(rva as usize), length as usize))
+}
+
+/// Find message `id` in the message table of a mapped PE image
+///
+/// # Safety
+/// `base` must be the base of a mapped PE image.
+pub unsafe fn rtl_find_message_in_image(base: *const u8, id: u32, language: u16) -> Option<String> {
+    rtl_find_message(rtl_find_message_resource(base, language)?, id)
+}
+
+// ============================================================================
+// Formatting
+// ============================================================================
+
+/// Format one insert according to its printf spec
+fn format_insert(spec: &str, argument: MessageArgument) -> String {
+    let mut rest = spec;
+    let left = rest.starts_with('-');
+    rest = rest.trim_start_matches('-');
+    let zero = rest.starts_with('0');
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let width: usize = rest[..digits].parse().unwrap_or(0);
+    rest = &rest[digits..];
+    let wide = rest.starts_with("I64") || rest.starts_with("ll");
+    let conversion = rest.trim_start_matches(['l', 'h', 'w', 'I', '6', '4', '3', '2']).chars().next().unwrap_or('s');
+
+    let text = match argument {
+        MessageArgument::Str(s) => String::from(s),
+        MessageArgument::Number(n) => {
+            let n = if wide { n } else { n & 0xFFFF_FFFF };
+            match conversion {
+                'x' => alloc::format!("{:x}", n),
+                'X' => alloc::format!("{:X}", n),
+                'p' => alloc::format!("{:016X}", n),
+                'd' | 'i' if wide => alloc::format!("{}", n as i64),
+                'd' | 'i' => alloc::format!("{}", n as u32 as i32),
+                'c' => char::from_u32(n as u32).map(String::from).unwrap_or_default(),
+                _ => alloc::format!("{}", n),
+            }
+        }
+    };
+
+    let pad = width.saturating_sub(text.chars().count());
+    if pad == 0 {
+        return text;
+    }
+    let numeric = matches!(argument, MessageArgument::Number(_)) && conversion != 's';
+    let mut padded = String::with_capacity(text.len() + pad);
+    if left {
+        padded.push_str(&text);
+        padded.extend(core::iter::repeat_n(' ', pad));
+    } else if zero && numeric {
+        let (sign, digits) = text.split_at(if text.starts_with('-') { 1 } else { 0 });
+        padded.push_str(sign);
+        padded.extend(core::iter::repeat_n('0', pad));
+        padded.push_str(digits);
+    } else {
+        padded.extend(core::iter::repeat_n(' ', pad));
+        padded.push_str(&text);
+    }
+    padded
+}
+
+/// Break every line of `text` at spaces so none exceeds `width` columns
+fn wrap_lines(text: &str, width: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split("\r\n").enumerate() {
+        if i > 0 {
+            out.push_str("\r\n");
+        }
+        let mut column = 0;
+        for word in line.split(' ').filter(|w| !w.is_empty()) {
+            let length = word.chars().count();
+            if column > 0 && column + 1 + length > width {
+                out.push_str("\r\n");
+                column = 0;
+            } else if column > 0 {
+                out.push(' ');
+                column += 1;
+            }
+            out.push_str(word);
+            column += length;
+        }
+    }
+    out
+}
+
+/// Expand a message's insert sequences (RtlFormatMessage)
+///
+/// With `ignore_inserts` the `%1`..`%99` inserts are copied through
+/// unexpanded. A `maximum_width` of 0 keeps the message's own line
+/// breaks; any other value turns them into spaces and, below
+/// `FORMAT_MESSAGE_MAX_WIDTH_MASK`, wraps lines at that width.
+///
+/// Fails with STATUS_INVALID_PARAMETER when an insert has no argument.
+pub fn rtl_format_message(
+    message: &str,
+    arguments: &[MessageArgument],
+    ignore_inserts: bool,
+    maximum_width: u32,
+) -> Result<String, i32> {
+    let mut out = String::with_capacity(message.len());
+    let mut chars = message.char_indices().peekable();
+
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '%' => {}
+            '\r' | '\n' if maximum_width != 0 => {
+                if c == '\r' && chars.peek().map(|&(_, d)| d) == Some('\n') {
+                    chars.next();
+                }
+                if !out.ends_with(' ') && !out.is_empty() {
+                    out.push(' ');
+                }
+                continue;
+            }
+            '\n' if !out.ends_with('\r') => {
+                out.push_str("\r\n");
+                continue;
+            }
+            _ => {
+                out.push(c);
+                continue;
+            }
+        }
+
+        let Some(&(start, next)) = chars.peek() else {
+            out.push('%');
+            break;
+        };
+        match next {
+            '1'..='9' => {
+                let mut end = start;
+                let mut index = 0usize;
+                while let Some(&(i, d)) = chars.peek() {
+                    match d.to_digit(10) {
+                        Some(v) if index * 10 + (v as usize) <= MAXIMUM_MESSAGE_INSERT => {
+                            index = index * 10 + v as usize;
+                            end = i + 1;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+
+                // Optional !printf-spec!
+                let mut spec = "s";
+                if let Some(&(bang, '!')) = chars.peek() {
+                    if let Some(close) = message[bang + 1..].find('!') {
+                        spec = &message[bang + 1..bang + 1 + close];
+                        end = bang + close + 2;
+                        while chars.peek().is_some_and(|&(i, _)| i < end) {
+                            chars.next();
+                        }
+                    }
+                }
+
+                if ignore_inserts {
+                    out.push('%');
+                    out.push_str(&message[start..end]);
+                    continue;
+                }
+                let argument = *arguments.get(index - 1).ok_or(STATUS_INVALID_PARAMETER)?;
+                out.push_str(&format_insert(spec, argument));
+            }
+            'n' => {
+                chars.next();
+                out.push_str("\r\n");
+            }
+            'r' => {
+                chars.next();
+                out.push('\r');
+            }
+            't' => {
+                chars.next();
+                out.push('\t');
+            }
+            '0' => return Ok(finish_lines(out, maximum_width)),
+            other => {
+                chars.next();
+                out.push(other);
+            }
+        }
+    }
+
+    Ok(finish_lines(out, maximum_width))
+}
+
+fn finish_lines(out: String, maximum_width: u32) -> String {
+    if maximum_width != 0 && maximum_width < FORMAT_MESSAGE_MAX_WIDTH_MASK {
+        wrap_lines(&out, maximum_width as usize)
+    } else {
+        out
+    }
+}
+
+// ============================================================================
+// Kernel Message Tables
+// ============================================================================
+
+/// The message tables built into the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum KernelMessageTable {
+    /// Win32 and network (NERR) error texts, as netmsg/kernel32
+    System = 0,
+    /// NTSTATUS texts, `{Caption}` first line, as ntdll
+    NtStatus = 1,
+    /// Event log descriptions, by event ID
+    Event = 2,
+}
+
+impl KernelMessageTable {
+    fn messages(self) -> &'static [(u32, &'static str)] {
+        match self {
+            KernelMessageTable::System => SYSTEM_MESSAGES,
+            KernelMessageTable::NtStatus => NTSTATUS_MESSAGES,
+            KernelMessageTable::Event => EVENT_MESSAGES,
+        }
+    }
+}
+
+const SYSTEM_MESSAGES: &[(u32, &str)] = &[
+    (0, "The operation completed successfully."),
+    (1, "Incorrect function."),
+    (2, "The system cannot find the file specified."),
+    (3, "The system cannot find the path specified."),
+    (4, "The system cannot open the file."),
+    (5, "Access is denied."),
+    (6, "The handle is invalid."),
+    (8, "Not enough storage is available to process this command."),
+    (13, "The data is invalid."),
+    (15, "The system cannot find the drive specified."),
+    (19, "The media is write protected."),
+    (21, "The device is not ready."),
+    (32, "The process cannot access the file because it is being used by another process."),
+    (50, "The request is not supported."),
+    (53, "The network path was not found."),
+    (64, "The specified network name is no longer available."),
+    (65, "Network access is denied."),
+    (67, "The network name cannot be found."),
+    (80, "The file exists."),
+    (86, "The specified network password is not correct."),
+    (87, "The parameter is incorrect."),
+    (112, "There is not enough space on the disk."),
+    (123, "The filename, directory name, or volume label syntax is incorrect."),
+    (1060, "The specified service does not exist as an installed service."),
+    (1326, "Logon failure: unknown user name or bad password."),
+    (1331, "Logon failure: account currently disabled."),
+    (2102, "The workstation driver is not installed."),
+    (2114, "The Server service is not started."),
+    (2182, "The requested service has already been started."),
+    (2184, "The service has not been started."),
+    (2185, "The service name is invalid."),
+    (2221, "The user name could not be found."),
+    (2224, "The account already exists."),
+    (2250, "The network connection could not be found."),
+    (2310, "This shared resource does not exist."),
+];
+
+const NTSTATUS_MESSAGES: &[(u32, &str)] = &[
+    (0xC0000005, "{Application Error}\r\n\
+        The instruction at %1 referenced memory at %2. The memory could not be accessed."),
+    (0xC0000017, "{Out of Memory}\r\n\
+        There is not enough memory to complete this operation."),
+    (0xC0000022, "{Access Denied}\r\n\
+        A process has requested access to an object, but has not been granted those access rights."),
+    (0xC0000059, "{Revision Mismatch}\r\n\
+        %1 was built for a kernel interface this system does not provide."),
+    (0xC000007B, "{Bad Image}\r\n\
+        %1 is either not designed to run on this system or contains an error."),
+    (0xC000009A, "{Insufficient Resources}\r\n\
+        Insufficient system resources exist to complete the requested operation."),
+    (0xC0000102, "{Corrupt File}\r\n\
+        The file or directory %1 is corrupt and unreadable. Please run the chkdsk utility."),
+    (0xC0000135, "{Unable To Locate Component}\r\n\
+        This application has failed to start because %1 was not found."),
+    (0xC0000139, "{Entry Point Not Found}\r\n\
+        The procedure entry point %1 could not be located in the dynamic link library %2."),
+    (0xC0000142, "{DLL Initialization Failed}\r\n\
+        Initialization of the dynamic link library %1 failed. The process is terminating abnormally."),
+    (0xC0000185, "{I/O Error}\r\n\
+        The I/O device reported an I/O error."),
+    (0xC0000221, "{Bad Image Checksum}\r\n\
+        The image %1 is possibly corrupt. The header checksum does not match the computed checksum."),
+    (0xC000026C, "{Unable to Load Device Driver}\r\n\
+        %1 device driver could not be loaded. Error Status was %2."),
+];
+
+const EVENT_MESSAGES: &[(u32, &str)] = &[
+    (26, "Application popup: %1 : %2"),
+    (702, "The file system structure on volume %1 was not shut down cleanly. Run chkdsk to check the volume."),
+];
+
+/// Built tables, by `KernelMessageTable`
+static KERNEL_TABLES: SpinLock<[Option<&'static [u8]>; 3]> = SpinLock::new([None; 3]);
+
+/// The MESSAGE_RESOURCE_DATA image of a kernel table
+pub fn rtl_kernel_message_table(table: KernelMessageTable) -> &'static [u8] {
+    let mut tables = KERNEL_TABLES.lock();
+    tables[table as usize]
+        .get_or_insert_with(|| rtl_build_message_table(table.messages()).leak())
+}
+
+/// Look up message `id` in a kernel table
+pub fn rtl_get_kernel_message(table: KernelMessageTable, id: u32) -> Option<String> {
+    rtl_find_message(rtl_kernel_message_table(table), id)
+}
+
+/// Look up and format message `id` from a kernel table, keeping its line breaks
+pub fn rtl_format_kernel_message(
+    table: KernelMessageTable,
+    id: u32,
+    arguments: &[MessageArgument],
+) -> Option<String> {
+    let message = rtl_get_kernel_message(table, id)?;
+    rtl_format_message(&message, arguments, false, 0).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(message: &str, arguments: &[MessageArgument]) -> Result<String, i32> {
+        rtl_format_message(message, arguments, false, 0)
+    }
+
+    #[test]
+    fn test_find_ansi() {
+        let table = rtl_build_message_table(&[(1, "One"), (2, "Two"), (3, "Three")]);
+        assert_eq!(rtl_find_message(&table, 1).as_deref(), Some("One"));
+        assert_eq!(rtl_find_message(&table, 3).as_deref(), Some("Three"));
+        assert_eq!(rtl_find_message(&table, 4), None);
+        assert_eq!(rtl_find_message(&table, 0), None);
+    }
+
+    #[test]
+    fn test_find_unicode() {
+        let table = rtl_build_message_table(&[(7, "Caf\u{e9}"), (8, "plain")]);
+        assert_eq!(rtl_find_message(&table, 7).as_deref(), Some("Caf\u{e9}"));
+        assert_eq!(rtl_find_message(&table, 8).as_deref(), Some("plain"));
+    }
+
+    #[test]
+    fn test_build_blocks() {
+        let table = rtl_build_message_table(&[(10, "b"), (1, "a"), (11, "c"), (1, "dup")]);
+        // Ids 1 and 10..=11 make two blocks
+        assert_eq!(read_u32(&table, 0), Some(2));
+        assert_eq!((read_u32(&table, 4), read_u32(&table, 8)), (Some(1), Some(1)));
+        assert_eq!((read_u32(&table, 16), read_u32(&table, 20)), (Some(10), Some(11)));
+        assert_eq!(rtl_find_message(&table, 1).as_deref(), Some("a"));
+        assert_eq!(rtl_find_message(&table, 11).as_deref(), Some("c"));
+        assert_eq!(rtl_find_message(&table, 5), None);
+    }
+
+    #[test]
+    fn test_build_entry_padding() {
+        let table = rtl_build_message_table(&[(1, "abcd")]);
+        // Header, one block, then "abcd\0" padded to 8 behind a 4 byte entry header
+        assert_eq!(table.len(), 4 + 12 + 12);
+        assert_eq!(read_u16(&table, 16), Some(12));
+        assert_eq!(read_u16(&table, 18), Some(0));
+    }
+
+    #[test]
+    fn test_find_truncated() {
+        let table = rtl_build_message_table(&[(1, "One"), (2, "Two")]);
+        assert_eq!(rtl_find_message(&table[..table.len() - 4], 2), None);
+        assert_eq!(rtl_find_message(&table[..10], 1), None);
+        assert_eq!(rtl_find_message(&[], 1), None);
+    }
+
+    #[test]
+    fn test_find_bad_length() {
+        let mut table = rtl_build_message_table(&[(1, "One"), (2, "Two")]);
+        table[16] = 0;
+        table[17] = 0;
+        assert_eq!(rtl_find_message(&table, 2), None);
+    }
+
+    #[test]
+    fn test_format_inserts() {
+        let arguments = [MessageArgument::Str("disk"), MessageArgument::Number(42)];
+        assert_eq!(format("%1 has %2 errors", &arguments).unwrap(), "disk has 42 errors");
+        assert_eq!(format("%2!08X!", &arguments).unwrap(), "0000002A");
+        assert_eq!(format("%2!-4d!|", &arguments).unwrap(), "42  |");
+        assert_eq!(format("%1!6s!", &arguments).unwrap(), "  disk");
+        assert_eq!(format("%3", &arguments), Err(STATUS_INVALID_PARAMETER));
+    }
+
+    #[test]
+    fn test_format_numbers() {
+        let negative = [MessageArgument::Number(-5i64 as u64)];
+        assert_eq!(format("%1!d!", &negative).unwrap(), "-5");
+        assert_eq!(format("%1!05d!", &negative).unwrap(), "-0005");
+        assert_eq!(format("%1!I64d!", &negative).unwrap(), "-5");
+        assert_eq!(format("%1!x!", &[MessageArgument::Number(0x1_0000_00ff)]).unwrap(), "ff");
+        assert_eq!(format("%1!c!", &[MessageArgument::Number(0x41)]).unwrap(), "A");
+    }
+
+    #[test]
+    fn test_format_escapes() {
+        assert_eq!(format("a%nb%tc%rd", &[]).unwrap(), "a\r\nb\tc\rd");
+        assert_eq!(format("100%% done%.%!% ", &[]).unwrap(), "100% done.! ");
+        assert_eq!(format("stop%0here", &[]).unwrap(), "stop");
+        assert_eq!(format("line\n", &[]).unwrap(), "line\r\n");
+        assert_eq!(format("trailing %", &[]).unwrap(), "trailing %");
+    }
+
+    #[test]
+    fn test_format_ignore_inserts() {
+        let out = rtl_format_message("%1 and %2!x!%n", &[], true, 0).unwrap();
+        assert_eq!(out, "%1 and %2!x!\r\n");
+    }
+
+    #[test]
+    fn test_format_insert_limit() {
+        let arguments: Vec<MessageArgument> = (0..100).map(MessageArgument::Number).collect();
+        assert_eq!(format("%99", &arguments).unwrap(), "98");
+        // Only two digits belong to the insert
+        assert_eq!(format("%100", &arguments).unwrap(), "90");
+    }
+
+    #[test]
+    fn test_format_width() {
+        let message = "one two\r\nthree four five";
+        let out = rtl_format_message(message, &[], false, FORMAT_MESSAGE_MAX_WIDTH_MASK).unwrap();
+        assert_eq!(out, "one two three four five");
+        let out = rtl_format_message(message, &[], false, 9).unwrap();
+        assert_eq!(out, "one two\r\nthree\r\nfour five");
+    }
+
+    #[test]
+    fn test_kernel_tables() {
+        assert_eq!(rtl_get_kernel_message(KernelMessageTable::System, 5).as_deref(),
+            Some("Access is denied."));
+        let text = rtl_get_kernel_message(KernelMessageTable::NtStatus, 0xC0000022).unwrap();
+        assert!(text.starts_with("{Access Denied}\r\n"));
+        let text = rtl_format_kernel_message(KernelMessageTable::Event, 26,
+            &[MessageArgument::Str("app"), MessageArgument::Str("boom")]);
+        assert_eq!(text.as_deref(), Some("Application popup: app : boom"));
+        assert_eq!(rtl_get_kernel_message(KernelMessageTable::Event, 1), None);
+    }
+}
@@ -8,6 +8,9 @@
 //! - **AVL Trees**: Self-balancing binary trees (for VAD)
 //! - **Splay Trees**: Self-adjusting binary trees
 //! - **Heap**: User-mode heap management
+//! - **Message tables**: mc-compatible tables and RtlFormatMessage
+//! - **Patterns**: DOS wildcard (FsRtlIsNameInExpression) and regex matching
+//! - **kmcrt**: C runtime routines and intrinsics exported to drivers
 //!
 //! # UNICODE_STRING
 //!
@@ -44,8 +47,11 @@ pub mod heap;
 pub mod format;
 pub mod hex;
 pub mod image;
+pub mod kmcrt;
 pub mod memory;
+pub mod message;
 pub mod nls;
+pub mod pattern;
 pub mod random;
 pub mod string;
 pub mod time;
@@ -72,6 +78,8 @@ pub use heap::*;
 pub use hex::{encode as hex_encode, decode as hex_decode};
 pub use image::*;
 pub use memory::*;
+pub use message::*;
+pub use pattern::*;
 pub use random::*;
 pub use string::*;
 pub use time::*;
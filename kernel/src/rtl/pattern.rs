@@ -0,0 +1,670 @@
+//! Pattern Matching
+//!
+//! Name and text matching shared by directory enumeration, the shell and
+//! the event log and registry tools.
+//!
+//! # DOS Wildcards
+//!
+//! `rtl_is_name_in_expression` follows FsRtlIsNameInExpression:
+//! - `*` matches zero or more characters
+//! - `?` matches exactly one character
+//! - `<` (DOS_STAR) matches zero or more characters, but never the last
+//!   period in the name
+//! - `>` (DOS_QM) matches one character, or nothing at a period or at the
+//!   end of the name
+//! - `"` (DOS_DOT) matches a period, or nothing at the end of the name
+//!
+//! Win32 rewrites patterns such as `*.*` into these forms before calling
+//! down, so a raw `*.*` here only matches names with a period, as on NT.
+//! Matching is a single pass over the expression tracking every reachable
+//! position in the name, so it never backtracks.
+//!
+//! # Regular Expressions
+//!
+//! `RtlRegex` is a small regex subset for searching text:
+//! - literals, `.` (any character) and `\` escapes of metacharacters
+//! - classes `[abc]`, `[a-z]`, `[^...]` and `\d \w \s` (`\D \W \S` negated)
+//! - `*`, `+` and `?` after a single atom
+//! - `^` and `$` anchors at the start and end of a branch
+//! - `|` alternation at the top level
+//!
+//! Groups, counted repetition and lazy quantifiers are not supported.
+//! Matches are leftmost-longest. Every state of the expression is tracked
+//! at once, so matching takes time proportional to the text length times
+//! the expression length, whatever the input.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Name positions tracked on the stack; longer names use the heap
+const INLINE_POSITION_WORDS: usize = 8;
+
+/// Maximum atoms in a compiled expression
+pub const MAX_REGEX_NODES: usize = 128;
+
+/// Check if a name contains DOS wildcard characters
+pub fn rtl_does_name_contain_wildcards(name: &str) -> bool {
+    name.bytes().any(|c| matches!(c, b'*' | b'?' | b'<' | b'>' | b'"'))
+}
+
+/// Compare two characters, folding ASCII case if asked
+#[inline]
+fn chars_equal(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && a.eq_ignore_ascii_case(&b))
+}
+
+/// Set of byte positions in a name
+struct PositionSet<'a> {
+    words: &'a mut [u64],
+}
+
+impl PositionSet<'_> {
+    #[inline]
+    fn insert(&mut self, pos: usize) {
+        self.words[pos / 64] |= 1 << (pos % 64);
+    }
+
+    #[inline]
+    fn contains(&self, pos: usize) -> bool {
+        self.words[pos / 64] & (1 << (pos % 64)) != 0
+    }
+
+    fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Lowest position in the set
+    fn first(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .position(|&w| w != 0)
+            .map(|i| i * 64 + self.words[i].trailing_zeros() as usize)
+    }
+}
+
+/// Check if `name` matches a DOS wildcard `expression`
+///
+/// Compatible with FsRtlIsNameInExpression; see the module documentation
+/// for the wildcard rules. Case is folded for ASCII only.
+pub fn rtl_is_name_in_expression(expression: &str, name: &str, ignore_case: bool) -> bool {
+    if expression.is_empty() {
+        return name.is_empty();
+    }
+    if !rtl_does_name_contain_wildcards(expression) {
+        return expression.len() == name.len()
+            && expression.chars().zip(name.chars()).all(|(a, b)| chars_equal(a, b, ignore_case));
+    }
+
+    let words = (name.len() + 1).div_ceil(64);
+    let mut inline = [0u64; 2 * INLINE_POSITION_WORDS];
+    let mut heap: Vec<u64>;
+    let storage: &mut [u64] = if words <= INLINE_POSITION_WORDS {
+        &mut inline[..2 * words]
+    } else {
+        heap = vec![0u64; 2 * words];
+        &mut heap
+    };
+    let (current, next) = storage.split_at_mut(words);
+    let mut current = PositionSet { words: current };
+    let mut next = PositionSet { words: next };
+    current.insert(0);
+
+    let bytes = name.as_bytes();
+    let end = name.len();
+    let last_dot = name.rfind('.');
+    let char_at = |pos: usize| name[pos..].chars().next();
+
+    for token in expression.chars() {
+        next.clear();
+        match token {
+            '*' => {
+                // Everything from the first reachable position on
+                if let Some(first) = current.first() {
+                    for pos in first..=end {
+                        if name.is_char_boundary(pos) {
+                            next.insert(pos);
+                        }
+                    }
+                }
+            }
+            '<' => {
+                // Up to the last period, or anywhere once past it
+                for pos in 0..=end {
+                    if !current.contains(pos) {
+                        continue;
+                    }
+                    let limit = match last_dot {
+                        Some(dot) if pos <= dot => dot,
+                        _ => end,
+                    };
+                    for reach in pos..=limit {
+                        if name.is_char_boundary(reach) {
+                            next.insert(reach);
+                        }
+                    }
+                }
+            }
+            '>' => {
+                for pos in 0..=end {
+                    if !current.contains(pos) {
+                        continue;
+                    }
+                    match char_at(pos) {
+                        None | Some('.') => next.insert(pos),
+                        Some(c) => next.insert(pos + c.len_utf8()),
+                    }
+                }
+            }
+            '"' => {
+                for pos in 0..=end {
+                    if !current.contains(pos) {
+                        continue;
+                    }
+                    if pos == end {
+                        next.insert(pos);
+                    } else if bytes[pos] == b'.' {
+                        next.insert(pos + 1);
+                    }
+                }
+            }
+            '?' => {
+                for pos in 0..end {
+                    if current.contains(pos) {
+                        if let Some(c) = char_at(pos) {
+                            next.insert(pos + c.len_utf8());
+                        }
+                    }
+                }
+            }
+            literal => {
+                for pos in 0..end {
+                    if current.contains(pos) {
+                        if let Some(c) = char_at(pos) {
+                            if chars_equal(literal, c, ignore_case) {
+                                next.insert(pos + c.len_utf8());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if next.is_empty() {
+            return false;
+        }
+        core::mem::swap(&mut current, &mut next);
+    }
+
+    current.contains(end)
+}
+
+/// Reasons a regular expression does not compile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegexError {
+    /// `[` without a closing `]`
+    UnterminatedClass,
+    /// A class range whose end sorts before its start
+    InvalidRange,
+    /// `\` at the end of the pattern
+    TrailingBackslash,
+    /// A quantifier with nothing (or another quantifier) before it
+    NothingToRepeat,
+    /// Syntax outside the supported subset
+    Unsupported(char),
+    /// More than `MAX_REGEX_NODES` atoms
+    TooComplex,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexError::UnterminatedClass => write!(f, "missing ]"),
+            RegexError::InvalidRange => write!(f, "invalid class range"),
+            RegexError::TrailingBackslash => write!(f, "trailing \\"),
+            RegexError::NothingToRepeat => write!(f, "nothing to repeat"),
+            RegexError::Unsupported(c) => write!(f, "'{}' is not supported", c),
+            RegexError::TooComplex => write!(f, "expression too complex"),
+        }
+    }
+}
+
+/// What a single atom matches
+#[derive(Debug, Clone, Copy)]
+enum Atom {
+    Char(char),
+    Any,
+    /// `len` ranges from `start` in the class range table
+    Class { start: u16, len: u16, negated: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repeat {
+    One,
+    ZeroOrOne,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    atom: Atom,
+    repeat: Repeat,
+}
+
+/// One top-level alternative
+#[derive(Debug, Clone, Copy)]
+struct Branch {
+    start: usize,
+    end: usize,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+const DIGIT_RANGES: &[(char, char)] = &[('0', '9')];
+const WORD_RANGES: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE_RANGES: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+/// Add `index` and the states reachable from it without input to `set`
+///
+/// A state keeps the earliest start that reaches it.
+fn add_state(nodes: &[Node], set: &mut [usize], mut index: usize, start: usize) {
+    loop {
+        if set[index] <= start {
+            return;
+        }
+        set[index] = start;
+        if index == nodes.len() || !matches!(nodes[index].repeat, Repeat::ZeroOrOne | Repeat::ZeroOrMore) {
+            return;
+        }
+        index += 1;
+    }
+}
+
+/// A compiled regular expression
+#[derive(Debug, Clone)]
+pub struct RtlRegex {
+    nodes: Vec<Node>,
+    ranges: Vec<(char, char)>,
+    branches: Vec<Branch>,
+    ignore_case: bool,
+}
+
+impl RtlRegex {
+    /// Compile `pattern`; `ignore_case` folds ASCII case when matching
+    pub fn compile(pattern: &str, ignore_case: bool) -> Result<Self, RegexError> {
+        let mut regex = RtlRegex {
+            nodes: Vec::new(),
+            ranges: Vec::new(),
+            branches: Vec::new(),
+            ignore_case,
+        };
+
+        let mut chars = pattern.chars().peekable();
+        let mut branch = Branch { start: 0, end: 0, anchored_start: false, anchored_end: false };
+        let mut at_branch_start = true;
+
+        while let Some(c) = chars.next() {
+            let atom = match c {
+                '|' => {
+                    branch.end = regex.nodes.len();
+                    regex.branches.push(branch);
+                    branch = Branch { start: branch.end, end: 0, anchored_start: false, anchored_end: false };
+                    at_branch_start = true;
+                    continue;
+                }
+                '^' if at_branch_start => {
+                    branch.anchored_start = true;
+                    at_branch_start = false;
+                    continue;
+                }
+                '$' if matches!(chars.peek(), None | Some(&'|')) => {
+                    branch.anchored_end = true;
+                    continue;
+                }
+                '*' | '+' | '?' => {
+                    let repeatable = regex.nodes.len() > branch.start;
+                    let last = match regex.nodes.last_mut() {
+                        Some(node) if repeatable && node.repeat == Repeat::One => node,
+                        _ => return Err(RegexError::NothingToRepeat),
+                    };
+                    last.repeat = match c {
+                        '*' => Repeat::ZeroOrMore,
+                        '+' => Repeat::OneOrMore,
+                        _ => Repeat::ZeroOrOne,
+                    };
+                    continue;
+                }
+                '(' | ')' | '{' => return Err(RegexError::Unsupported(c)),
+                '.' => Atom::Any,
+                '[' => regex.parse_class(&mut chars)?,
+                '\\' => {
+                    let escaped = chars.next().ok_or(RegexError::TrailingBackslash)?;
+                    regex.parse_escape(escaped)
+                }
+                literal => Atom::Char(literal),
+            };
+
+            at_branch_start = false;
+            if regex.nodes.len() >= MAX_REGEX_NODES {
+                return Err(RegexError::TooComplex);
+            }
+            regex.nodes.push(Node { atom, repeat: Repeat::One });
+        }
+
+        branch.end = regex.nodes.len();
+        regex.branches.push(branch);
+        Ok(regex)
+    }
+
+    /// Add ranges to the class table; returns the class atom
+    fn push_class(&mut self, ranges: &[(char, char)], negated: bool) -> Atom {
+        let start = self.ranges.len() as u16;
+        self.ranges.extend_from_slice(ranges);
+        Atom::Class { start, len: ranges.len() as u16, negated }
+    }
+
+    fn parse_escape(&mut self, escaped: char) -> Atom {
+        match escaped {
+            'd' => self.push_class(DIGIT_RANGES, false),
+            'D' => self.push_class(DIGIT_RANGES, true),
+            'w' => self.push_class(WORD_RANGES, false),
+            'W' => self.push_class(WORD_RANGES, true),
+            's' => self.push_class(SPACE_RANGES, false),
+            'S' => self.push_class(SPACE_RANGES, true),
+            't' => Atom::Char('\t'),
+            'n' => Atom::Char('\n'),
+            'r' => Atom::Char('\r'),
+            other => Atom::Char(other),
+        }
+    }
+
+    /// Parse a class after its opening `[`
+    fn parse_class(&mut self, chars: &mut core::iter::Peekable<core::str::Chars>) -> Result<Atom, RegexError> {
+        let negated = chars.next_if_eq(&'^').is_some();
+        let start = self.ranges.len();
+        let mut first = true;
+
+        loop {
+            let c = chars.next().ok_or(RegexError::UnterminatedClass)?;
+            let low = match c {
+                // A leading ] is a literal
+                ']' if !first => break,
+                '\\' => {
+                    let escaped = chars.next().ok_or(RegexError::TrailingBackslash)?;
+                    let builtin = match escaped {
+                        'd' => Some(DIGIT_RANGES),
+                        'w' => Some(WORD_RANGES),
+                        's' => Some(SPACE_RANGES),
+                        _ => None,
+                    };
+                    if let Some(ranges) = builtin {
+                        self.ranges.extend_from_slice(ranges);
+                        first = false;
+                        continue;
+                    }
+                    match escaped {
+                        't' => '\t',
+                        'n' => '\n',
+                        'r' => '\r',
+                        other => other,
+                    }
+                }
+                other => other,
+            };
+            first = false;
+
+            // A - before the closing ] is a literal
+            let is_range = chars.peek() == Some(&'-') && {
+                let mut lookahead = chars.clone();
+                lookahead.next();
+                !matches!(lookahead.peek(), None | Some(&']'))
+            };
+            if !is_range {
+                self.ranges.push((low, low));
+                continue;
+            }
+            chars.next();
+            let high = match chars.next() {
+                Some('\\') => chars.next().ok_or(RegexError::TrailingBackslash)?,
+                Some(high) => high,
+                None => return Err(RegexError::UnterminatedClass),
+            };
+            if high < low {
+                return Err(RegexError::InvalidRange);
+            }
+            self.ranges.push((low, high));
+        }
+
+        Ok(Atom::Class {
+            start: start as u16,
+            len: (self.ranges.len() - start) as u16,
+            negated,
+        })
+    }
+
+    /// Check if `c` is one of a class's ranges
+    fn class_contains(&self, start: u16, len: u16, c: char) -> bool {
+        let ranges = &self.ranges[start as usize..(start + len) as usize];
+        let in_ranges = |c: char| ranges.iter().any(|&(low, high)| c >= low && c <= high);
+        in_ranges(c)
+            || (self.ignore_case && (in_ranges(c.to_ascii_lowercase()) || in_ranges(c.to_ascii_uppercase())))
+    }
+
+    /// Check if one atom matches `c`
+    #[inline]
+    fn atom_matches(&self, atom: Atom, c: char) -> bool {
+        match atom {
+            Atom::Char(expected) => chars_equal(expected, c, self.ignore_case),
+            Atom::Any => true,
+            Atom::Class { start, len, negated } => self.class_contains(start, len, c) != negated,
+        }
+    }
+
+    /// Run one branch over `text`; returns its leftmost-longest match
+    ///
+    /// Every state of the branch is tracked at once, each with the
+    /// earliest start position that reaches it, so nothing backtracks.
+    fn match_branch(&self, branch: &Branch, text: &str) -> Option<(usize, usize)> {
+        let nodes = &self.nodes[branch.start..branch.end];
+        let accept = nodes.len();
+        let mut current = vec![usize::MAX; accept + 1];
+        let mut next = vec![usize::MAX; accept + 1];
+        let mut best: Option<(usize, usize)> = None;
+
+        for (pos, c) in text.char_indices().map(|(i, c)| (i, Some(c))).chain(core::iter::once((text.len(), None))) {
+            if best.is_none() && (!branch.anchored_start || pos == 0) {
+                add_state(nodes, &mut current, 0, pos);
+            }
+
+            let start = current[accept];
+            if start != usize::MAX && (!branch.anchored_end || pos == text.len()) {
+                // Starts only grow, so a later match is never further left
+                if best.is_none_or(|(best_start, _)| start <= best_start) {
+                    best = Some((start, pos));
+                }
+            }
+
+            let Some(c) = c else {
+                break;
+            };
+            next.fill(usize::MAX);
+            for (index, node) in nodes.iter().enumerate() {
+                let start = current[index];
+                if start == usize::MAX || best.is_some_and(|(best_start, _)| start > best_start) {
+                    continue;
+                }
+                if !self.atom_matches(node.atom, c) {
+                    continue;
+                }
+                if matches!(node.repeat, Repeat::ZeroOrMore | Repeat::OneOrMore) {
+                    add_state(nodes, &mut next, index, start);
+                }
+                add_state(nodes, &mut next, index + 1, start);
+            }
+            core::mem::swap(&mut current, &mut next);
+
+            let alive = current.iter().any(|&start| start != usize::MAX);
+            if !alive && (best.is_some() || branch.anchored_start) {
+                break;
+            }
+        }
+
+        best
+    }
+
+    /// Find the leftmost match in `text`; returns its byte range
+    ///
+    /// Of the matches starting there, the longest wins.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for branch in &self.branches {
+            if let Some((start, end)) = self.match_branch(branch, text) {
+                let better = match best {
+                    None => true,
+                    Some((best_start, best_end)) => start < best_start || (start == best_start && end > best_end),
+                };
+                if better {
+                    best = Some((start, end));
+                }
+            }
+        }
+        best
+    }
+
+    /// Check if the expression matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(expression: &str, name: &str) -> bool {
+        rtl_is_name_in_expression(expression, name, true)
+    }
+
+    #[test]
+    fn test_star() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "any.name.at.all"));
+        assert!(matches("a*c", "ac"));
+        assert!(matches("a*c", "abbbc"));
+        assert!(!matches("a*c", "abcd"));
+        assert!(matches("*.txt", "readme.txt"));
+        assert!(!matches("*.txt", "readme.txt.bak"));
+        assert!(!matches("*.*", "readme"));
+    }
+
+    #[test]
+    fn test_question_mark() {
+        assert!(matches("?", "a"));
+        assert!(!matches("?", ""));
+        assert!(!matches("?", "ab"));
+        assert!(matches("file?.log", "file1.log"));
+        assert!(!matches("file?.log", "file10.log"));
+    }
+
+    #[test]
+    fn test_dos_star() {
+        assert!(matches("<", "abc"));
+        assert!(!matches("<", "a.b"));
+        assert!(matches("<.txt", "a.b.txt"));
+        assert!(!matches("<.txt", "atxt"));
+        assert!(matches("<.<", "a.b.c"));
+    }
+
+    #[test]
+    fn test_dos_qm() {
+        assert!(matches(">", ""));
+        assert!(matches(">>>", "ab"));
+        assert!(!matches(">>>", "abcd"));
+        assert!(matches(">>>.txt", "a.txt"));
+        assert!(!matches(">>>.txt", "abcd.txt"));
+        assert!(matches("a>.b", "a.b"));
+    }
+
+    #[test]
+    fn test_dos_dot() {
+        assert!(matches("foo\"", "foo"));
+        assert!(matches("foo\"", "foo."));
+        assert!(!matches("foo\"", "foox"));
+        assert!(matches("foo\"txt", "foo.txt"));
+        assert!(matches("<\"*", "readme"));
+    }
+
+    #[test]
+    fn test_case() {
+        assert!(rtl_is_name_in_expression("*.TXT", "a.txt", true));
+        assert!(!rtl_is_name_in_expression("*.TXT", "a.txt", false));
+    }
+
+    #[test]
+    fn test_non_ascii_names() {
+        assert!(matches("?.txt", "\u{e9}.txt"));
+        assert!(matches("caf?", "caf\u{e9}"));
+        assert!(matches("*\u{e9}", "caf\u{e9}"));
+        assert!(!matches("??.txt", "\u{e9}.txt"));
+        assert!(matches("\u{441}*", "\u{441}\u{43b}\u{43e}\u{432}\u{43e}"));
+    }
+
+    #[test]
+    fn test_long_names() {
+        let mut name = "a".repeat(700);
+        name.push('z');
+        assert!(matches("*z", &name));
+        assert!(!matches("*y", &name));
+        assert!(!matches("*a*a*a*a*a*a*b", &name[..200]));
+    }
+
+    #[test]
+    fn test_contains_wildcards() {
+        assert!(rtl_does_name_contain_wildcards("a*"));
+        assert!(rtl_does_name_contain_wildcards("a<b"));
+        assert!(rtl_does_name_contain_wildcards("a\"b"));
+        assert!(!rtl_does_name_contain_wildcards("a.b"));
+    }
+
+    #[test]
+    fn test_regex_match() {
+        let regex = RtlRegex::compile("^ab+c$", false).unwrap();
+        assert!(regex.is_match("abbc"));
+        assert!(!regex.is_match("ac"));
+        assert!(RtlRegex::compile("\\d+", false).unwrap().is_match("x42"));
+        assert!(RtlRegex::compile("cat|dog", false).unwrap().is_match("hotdog"));
+        assert!(RtlRegex::compile("ERROR", true).unwrap().is_match("an error"));
+    }
+
+    #[test]
+    fn test_regex_find() {
+        assert_eq!(RtlRegex::compile("b+", false).unwrap().find("aabbbcc"), Some((2, 5)));
+        assert_eq!(RtlRegex::compile("a|ab", false).unwrap().find("xab"), Some((1, 3)));
+        assert_eq!(RtlRegex::compile("\u{e9}+", false).unwrap().find("caf\u{e9}\u{e9}"), Some((3, 7)));
+        assert_eq!(RtlRegex::compile("z", false).unwrap().find("abc"), None);
+    }
+
+    #[test]
+    fn test_regex_errors() {
+        assert_eq!(RtlRegex::compile("[abc", false).err(), Some(RegexError::UnterminatedClass));
+        assert_eq!(RtlRegex::compile("[z-a]", false).err(), Some(RegexError::InvalidRange));
+        assert_eq!(RtlRegex::compile("abc\\", false).err(), Some(RegexError::TrailingBackslash));
+        assert_eq!(RtlRegex::compile("*a", false).err(), Some(RegexError::NothingToRepeat));
+        assert_eq!(RtlRegex::compile("(ab)", false).err(), Some(RegexError::Unsupported('(')));
+    }
+
+    #[test]
+    fn test_regex_node_limit() {
+        let at_limit = "a".repeat(MAX_REGEX_NODES);
+        assert!(RtlRegex::compile(&at_limit, false).is_ok());
+        let over_limit = "a".repeat(MAX_REGEX_NODES + 1);
+        assert_eq!(RtlRegex::compile(&over_limit, false).err(), Some(RegexError::TooComplex));
+    }
+}
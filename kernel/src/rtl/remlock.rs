@@ -74,9 +74,9 @@ impl RemoveEvent {
     }
 
     pub fn wait(&self) {
-        // Busy-wait implementation (in a real kernel, this would be a proper wait)
+        // Removal runs at passive level, so sleep between polls
         while !self.signaled.load(Ordering::Acquire) {
-            core::hint::spin_loop();
+            crate::ex::ex_sleep(1);
         }
     }
 
@@ -5,6 +5,8 @@
 //! to perform a requested operation on an object.
 //!
 //! # Access Check Algorithm
+//! 0. Mandatory integrity check: if the token's integrity level is below
+//!    the object's label, deny the rights the label's policy withholds
 //! 1. If no DACL, grant all access
 //! 2. If empty DACL, deny all access
 //! 3. Process ACEs in order:
@@ -21,11 +23,13 @@
 //! - SeBackupPrivilege grants read access for backup
 //! - SeRestorePrivilege grants write access for restore
 //! - SeTakeOwnershipPrivilege grants WRITE_OWNER
+//! - No privilege overrides the mandatory integrity check
 
 use super::token::Token;
 use super::descriptor::SimpleSecurityDescriptor;
 use super::acl::{AceType, SimpleAce, generic_rights, standard_rights, special_rights};
 use super::privilege::privilege_luids;
+use super::integrity::se_mandatory_withheld_access;
 
 /// Access check result
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -38,6 +42,8 @@ pub enum AccessCheckResult {
     DeniedNoSD,
     /// Privilege required
     PrivilegeRequired,
+    /// Access denied - withheld by the object's mandatory label
+    MandatoryPolicy,
 }
 
 /// Generic mapping - maps generic rights to specific rights
@@ -122,6 +128,15 @@ pub fn se_access_check(
     let maximum_allowed = (remaining & special_rights::MAXIMUM_ALLOWED) != 0;
     remaining &= !special_rights::MAXIMUM_ALLOWED;
 
+    // Mandatory integrity check - nothing below may grant what the label withholds
+    let withheld = se_mandatory_withheld_access(token, &sd.label, generic_mapping);
+    if (remaining & withheld) != 0 {
+        if !maximum_allowed {
+            return Err(AccessCheckResult::MandatoryPolicy);
+        }
+        remaining &= !withheld;
+    }
+
     // Handle ACCESS_SYSTEM_SECURITY - requires SeSecurityPrivilege
     if (remaining & special_rights::ACCESS_SYSTEM_SECURITY) != 0 {
         if token.is_privilege_enabled(privilege_luids::SE_SECURITY_LUID) {
@@ -207,7 +222,7 @@ pub fn se_access_check(
 
     // For maximum allowed, return what we got
     if maximum_allowed {
-        return Ok(granted);
+        return Ok(granted & !withheld);
     }
 
     // Check if all requested rights were granted
@@ -5,6 +5,7 @@
 //! - Group SID: Primary group of the object
 //! - DACL: Discretionary ACL - who can access the object
 //! - SACL: System ACL - auditing information
+//! - Mandatory label: Integrity level and policy (simple format only)
 //!
 //! # Formats
 //! - Self-relative: All data in one contiguous block (for storage/transmission)
@@ -13,6 +14,7 @@
 use core::ptr;
 use super::sid::Sid;
 use super::acl::{Acl, SimpleAcl};
+use super::integrity::MandatoryLabel;
 use crate::ke::SpinLock;
 
 /// Security descriptor revision
@@ -192,6 +194,8 @@ pub struct SimpleSecurityDescriptor {
     pub group_present: bool,
     /// DACL (inline, simplified)
     pub dacl: SimpleAcl,
+    /// Mandatory label (Medium, no-write-up unless set)
+    pub label: MandatoryLabel,
 }
 
 impl SimpleSecurityDescriptor {
@@ -204,6 +208,7 @@ impl SimpleSecurityDescriptor {
             group: Sid::new(),
             group_present: false,
             dacl: SimpleAcl::new(),
+            label: MandatoryLabel::DEFAULT,
         }
     }
 
@@ -226,6 +231,11 @@ impl SimpleSecurityDescriptor {
         self.control &= !sd_control::SE_GROUP_DEFAULTED;
     }
 
+    /// Set the mandatory label
+    pub fn set_label(&mut self, label: MandatoryLabel) {
+        self.label = label;
+    }
+
     /// Set the DACL as present
     pub fn set_dacl_present(&mut self, present: bool) {
         if present {
@@ -0,0 +1,209 @@
+//! Mandatory Integrity Control
+//!
+//! Every token carries an integrity level, stored as a mandatory label
+//! group SID (S-1-16-RID) with `SE_GROUP_INTEGRITY` set, and every security
+//! descriptor carries a mandatory label: a level plus a policy. The access
+//! check applies the label before the DACL. When the token's level is below
+//! the object's, the label's policy withholds:
+//!
+//! - write rights (`NO_WRITE_UP`, the default policy)
+//! - read rights (`NO_READ_UP`)
+//! - execute rights (`NO_EXECUTE_UP`)
+//!
+//! No DACL entry or privilege grants a right the label withholds. Objects
+//! without an explicit label are treated as Medium, `NO_WRITE_UP`.
+//!
+//! # Levels
+//! - Untrusted (S-1-16-0): anonymous logons
+//! - Low (S-1-16-4096): sandboxed processes
+//! - Medium (S-1-16-8192): ordinary users
+//! - High (S-1-16-12288): elevated administrators
+//! - System (S-1-16-16384): LocalSystem and services
+
+use super::access::GenericMapping;
+use super::acl::standard_rights;
+use super::sid::{identifier_authority, well_known_rids, Sid, SID_REVISION};
+use super::sid::{SID_LOCAL_SERVICE, SID_LOCAL_SYSTEM, SID_NETWORK_SERVICE};
+use super::token::Token;
+
+/// Mandatory label policy flags (SYSTEM_MANDATORY_LABEL_ACE mask)
+pub mod label_policy {
+    /// Lower levels may not write the object
+    pub const SYSTEM_MANDATORY_LABEL_NO_WRITE_UP: u32 = 0x1;
+    /// Lower levels may not read the object
+    pub const SYSTEM_MANDATORY_LABEL_NO_READ_UP: u32 = 0x2;
+    /// Lower levels may not execute the object
+    pub const SYSTEM_MANDATORY_LABEL_NO_EXECUTE_UP: u32 = 0x4;
+    /// All valid policy flags
+    pub const SYSTEM_MANDATORY_LABEL_VALID_MASK: u32 = 0x7;
+}
+
+/// Token mandatory policy flags (TOKEN_MANDATORY_POLICY)
+pub mod token_policy {
+    /// The token is exempt from mandatory write checks
+    pub const TOKEN_MANDATORY_POLICY_OFF: u32 = 0x0;
+    /// The token is subject to `NO_WRITE_UP`
+    pub const TOKEN_MANDATORY_POLICY_NO_WRITE_UP: u32 = 0x1;
+}
+
+/// Integrity level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum IntegrityLevel {
+    Untrusted = well_known_rids::SECURITY_MANDATORY_UNTRUSTED_RID,
+    Low = well_known_rids::SECURITY_MANDATORY_LOW_RID,
+    Medium = well_known_rids::SECURITY_MANDATORY_MEDIUM_RID,
+    High = well_known_rids::SECURITY_MANDATORY_HIGH_RID,
+    System = well_known_rids::SECURITY_MANDATORY_SYSTEM_RID,
+}
+
+impl IntegrityLevel {
+    /// All levels, lowest first
+    pub const ALL: [IntegrityLevel; 5] = [
+        IntegrityLevel::Untrusted,
+        IntegrityLevel::Low,
+        IntegrityLevel::Medium,
+        IntegrityLevel::High,
+        IntegrityLevel::System,
+    ];
+
+    /// Convert a mandatory label RID
+    ///
+    /// RIDs between levels (e.g. Medium Plus, 0x2100) count as the level
+    /// below them.
+    pub fn from_rid(rid: u32) -> Self {
+        Self::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|level| level.rid() <= rid)
+            .unwrap_or(IntegrityLevel::Untrusted)
+    }
+
+    /// Mandatory label RID
+    pub fn rid(self) -> u32 {
+        self as u32
+    }
+
+    /// Mandatory label SID (S-1-16-RID)
+    pub const fn sid(self) -> Sid {
+        let mut sub_authority = [0; super::sid::SID_MAX_SUB_AUTHORITIES];
+        sub_authority[0] = self as u32;
+        Sid {
+            revision: SID_REVISION,
+            sub_authority_count: 1,
+            identifier_authority: identifier_authority::SECURITY_MANDATORY_LABEL_AUTHORITY,
+            sub_authority,
+        }
+    }
+
+    /// Get the level named by a mandatory label SID
+    pub fn from_sid(sid: &Sid) -> Option<Self> {
+        if sid.identifier_authority != identifier_authority::SECURITY_MANDATORY_LABEL_AUTHORITY
+            || sid.sub_authority_count != 1
+        {
+            return None;
+        }
+        Some(Self::from_rid(sid.sub_authority[0]))
+    }
+
+    /// Default level of a new token for a user
+    ///
+    /// The service accounts run at System; everyone else at Medium.
+    pub fn default_for_user(user: &Sid) -> Self {
+        if user.equal(&SID_LOCAL_SYSTEM) || user.equal(&SID_LOCAL_SERVICE) || user.equal(&SID_NETWORK_SERVICE) {
+            IntegrityLevel::System
+        } else {
+            IntegrityLevel::Medium
+        }
+    }
+
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            IntegrityLevel::Untrusted => "Untrusted",
+            IntegrityLevel::Low => "Low",
+            IntegrityLevel::Medium => "Medium",
+            IntegrityLevel::High => "High",
+            IntegrityLevel::System => "System",
+        }
+    }
+
+    /// Parse a level name (case-insensitive)
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Mandatory label of an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MandatoryLabel {
+    /// Integrity level of the object
+    pub level: IntegrityLevel,
+    /// Policy flags (`label_policy`)
+    pub policy: u32,
+}
+
+impl MandatoryLabel {
+    /// Implicit label of objects that have none
+    pub const DEFAULT: Self = Self::new(
+        IntegrityLevel::Medium,
+        label_policy::SYSTEM_MANDATORY_LABEL_NO_WRITE_UP,
+    );
+
+    pub const fn new(level: IntegrityLevel, policy: u32) -> Self {
+        Self {
+            level,
+            policy: policy & label_policy::SYSTEM_MANDATORY_LABEL_VALID_MASK,
+        }
+    }
+
+    /// Label of a process object running with a token at `level`
+    ///
+    /// Lower processes may neither write nor read it.
+    pub const fn for_process(level: IntegrityLevel) -> Self {
+        Self::new(
+            level,
+            label_policy::SYSTEM_MANDATORY_LABEL_NO_WRITE_UP | label_policy::SYSTEM_MANDATORY_LABEL_NO_READ_UP,
+        )
+    }
+}
+
+impl Default for MandatoryLabel {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Get the access rights a mandatory label withholds from a token
+///
+/// The result is in mapped (specific and standard) rights. READ_CONTROL
+/// and SYNCHRONIZE are never withheld, so a lower process can still read
+/// an object's label and wait on it.
+pub fn se_mandatory_withheld_access(
+    token: &Token,
+    label: &MandatoryLabel,
+    generic_mapping: &GenericMapping,
+) -> u32 {
+    if token.integrity_level() >= label.level {
+        return 0;
+    }
+
+    let mut withheld = 0;
+    if (label.policy & label_policy::SYSTEM_MANDATORY_LABEL_NO_WRITE_UP) != 0
+        && (token.mandatory_policy & token_policy::TOKEN_MANDATORY_POLICY_NO_WRITE_UP) != 0
+    {
+        withheld |= generic_mapping.generic_write
+            | standard_rights::DELETE
+            | standard_rights::WRITE_DAC
+            | standard_rights::WRITE_OWNER;
+    }
+    if (label.policy & label_policy::SYSTEM_MANDATORY_LABEL_NO_READ_UP) != 0 {
+        withheld |= generic_mapping.generic_read;
+    }
+    if (label.policy & label_policy::SYSTEM_MANDATORY_LABEL_NO_EXECUTE_UP) != 0 {
+        withheld |= generic_mapping.generic_execute;
+    }
+
+    withheld & !(standard_rights::READ_CONTROL | standard_rights::SYNCHRONIZE)
+}
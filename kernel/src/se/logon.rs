@@ -0,0 +1,221 @@
+//! Interactive Logon
+//!
+//! Authenticates a local account against the SAM and builds the primary
+//! token it runs with, as LsaLogonUser does for an MSV1_0 interactive
+//! logon. The token holds:
+//!
+//! - user: S-1-5-21-<machine>-<RID>
+//! - groups: the account's primary domain group, Everyone, every local
+//!   group (alias) the account belongs to, Interactive, Authenticated
+//!   Users and the mandatory label
+//! - privileges: those XP grants the Users group, plus the administrative
+//!   ones for members of Administrators (all but SeChangeNotify,
+//!   SeImpersonate and SeCreateGlobal disabled until enabled)
+//!
+//! Administrators get an elevated token at High integrity; everyone else
+//! a standard token at Medium.
+
+extern crate alloc;
+
+use alloc::format;
+use super::audit::{se_audit_logon_failure, se_audit_logon_success};
+use super::integrity::IntegrityLevel;
+use super::ntlm::ntlm_compute_nt_hash;
+use super::privilege::{privilege_attributes, privilege_luids, Luid};
+use super::sam::{
+    sam_enumerate_aliases, sam_enumerate_users, sam_validate_password,
+    user_account_control, Rid, SamError, UserAccount,
+};
+use super::sid::{identifier_authority, sid_attributes, well_known_rids, Sid};
+use super::sid::{SID_AUTHENTICATED_USERS, SID_BUILTIN_ADMINISTRATORS, SID_INTERACTIVE, SID_WORLD};
+use super::token::{se_create_token, Token, TokenElevationType, TokenSource, TokenType};
+
+/// Logon type of an interactive logon (LOGON32_LOGON_INTERACTIVE)
+pub const LOGON32_LOGON_INTERACTIVE: u32 = 2;
+
+/// Sub-authorities of the machine's account domain
+/// (S-1-5-21-1004336348-1177238915-682003330)
+const ACCOUNT_DOMAIN: [u32; 4] = [21, 1004336348, 1177238915, 682003330];
+
+/// Logon failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogonError {
+    /// Unknown user name or bad password
+    LogonFailure,
+    /// The account is disabled
+    AccountDisabled,
+    /// The account is locked out
+    AccountLocked,
+    /// The token pool is exhausted
+    NoResources,
+}
+
+impl LogonError {
+    /// Win32 error code
+    pub fn win32_error(self) -> u32 {
+        match self {
+            LogonError::LogonFailure => 1326,
+            LogonError::AccountDisabled => 1331,
+            LogonError::AccountLocked => 1909,
+            LogonError::NoResources => 1450,
+        }
+    }
+
+    /// Win32 error message
+    pub fn message(self) -> &'static str {
+        match self {
+            LogonError::LogonFailure => "Logon failure: unknown user name or bad password.",
+            LogonError::AccountDisabled => "Logon failure: account currently disabled.",
+            LogonError::AccountLocked => "The referenced account is currently locked out and may not be logged on to.",
+            LogonError::NoResources => "Insufficient system resources exist to complete the requested service.",
+        }
+    }
+}
+
+/// Get the SID of a local account
+pub fn se_account_sid(rid: Rid) -> Sid {
+    let mut sub_authorities = [0u32; 5];
+    sub_authorities[..4].copy_from_slice(&ACCOUNT_DOMAIN);
+    sub_authorities[4] = rid;
+    Sid::create(identifier_authority::SECURITY_NT_AUTHORITY, &sub_authorities).unwrap_or_default()
+}
+
+/// Get the SID of a builtin local group
+fn builtin_alias_sid(rid: Rid) -> Sid {
+    Sid::create(
+        identifier_authority::SECURITY_NT_AUTHORITY,
+        &[well_known_rids::SECURITY_BUILTIN_DOMAIN_RID, rid],
+    )
+    .unwrap_or_default()
+}
+
+/// Find a local account by name (case-insensitive)
+pub fn se_lookup_account(name: &str) -> Option<UserAccount> {
+    sam_enumerate_users()
+        .into_iter()
+        .find(|user| user.get_username().eq_ignore_ascii_case(name.as_bytes()))
+}
+
+/// Get the SID of a local account by name
+pub fn se_lookup_account_sid(name: &str) -> Option<Sid> {
+    se_lookup_account(name).map(|user| se_account_sid(user.rid))
+}
+
+/// Check an account's password
+fn authenticate(user: &UserAccount, password: &[u8]) -> Result<(), LogonError> {
+    let password_optional =
+        (user.user_account_control & user_account_control::UF_PASSWORD_NOT_REQUIRED) != 0;
+    if password.is_empty() && password_optional {
+        if user.is_disabled() {
+            return Err(LogonError::AccountDisabled);
+        }
+        if user.is_locked() {
+            return Err(LogonError::AccountLocked);
+        }
+        return Ok(());
+    }
+
+    match sam_validate_password(user.rid, &ntlm_compute_nt_hash(password)) {
+        Ok(true) => Ok(()),
+        Ok(false) | Err(SamError::UserNotFound) => Err(LogonError::LogonFailure),
+        Err(SamError::AccountDisabled) => Err(LogonError::AccountDisabled),
+        Err(SamError::AccountLocked) => Err(LogonError::AccountLocked),
+        Err(_) => Err(LogonError::LogonFailure),
+    }
+}
+
+/// Build the primary token of an authenticated account
+unsafe fn build_token(user: &UserAccount) -> Result<*mut Token, LogonError> {
+    let token = se_create_token(se_account_sid(user.rid), TokenType::Primary);
+    if token.is_null() {
+        return Err(LogonError::NoResources);
+    }
+    let token = &mut *token;
+
+    let enabled = sid_attributes::SE_GROUP_MANDATORY
+        | sid_attributes::SE_GROUP_ENABLED_BY_DEFAULT
+        | sid_attributes::SE_GROUP_ENABLED;
+
+    token.primary_group_index = token.group_count as i8;
+    token.add_group(se_account_sid(user.primary_group_rid), enabled);
+    token.add_group(SID_WORLD, enabled);
+    for alias in sam_enumerate_aliases() {
+        if alias.has_member(user.rid) {
+            token.add_group(builtin_alias_sid(alias.rid), enabled);
+        }
+    }
+    token.add_group(SID_INTERACTIVE, enabled);
+    token.add_group(SID_AUTHENTICATED_USERS, enabled);
+
+    let is_admin = token.has_group(&SID_BUILTIN_ADMINISTRATORS);
+
+    use privilege_luids::*;
+    let on = privilege_attributes::SE_PRIVILEGE_ENABLED | privilege_attributes::SE_PRIVILEGE_ENABLED_BY_DEFAULT;
+    token.add_privilege(SE_CHANGE_NOTIFY_LUID, on);
+    token.add_privilege(SE_SHUTDOWN_LUID, 0);
+    token.add_privilege(SE_UNDOCK_LUID, 0);
+    if is_admin {
+        token.add_privilege(SE_IMPERSONATE_LUID, on);
+        token.add_privilege(SE_CREATE_GLOBAL_LUID, on);
+        for luid in [
+            SE_SECURITY_LUID,
+            SE_BACKUP_LUID,
+            SE_RESTORE_LUID,
+            SE_SYSTEMTIME_LUID,
+            SE_TAKE_OWNERSHIP_LUID,
+            SE_DEBUG_LUID,
+            SE_SYSTEM_ENVIRONMENT_LUID,
+            SE_SYSTEM_PROFILE_LUID,
+            SE_PROF_SINGLE_PROCESS_LUID,
+            SE_INC_BASE_PRIORITY_LUID,
+            SE_LOAD_DRIVER_LUID,
+            SE_CREATE_PAGEFILE_LUID,
+            SE_INCREASE_QUOTA_LUID,
+            SE_MANAGE_VOLUME_LUID,
+        ] {
+            token.add_privilege(luid, 0);
+        }
+    }
+
+    if is_admin {
+        token.set_integrity_level(IntegrityLevel::High);
+        token.elevation_type = TokenElevationType::Full;
+        token.is_elevated = true;
+    } else {
+        token.set_integrity_level(IntegrityLevel::Medium);
+    }
+
+    let logon_id = crate::ex::luid::ex_allocate_locally_unique_id();
+    token.authentication_id = Luid::new(logon_id.low_part, logon_id.high_part as i32);
+    token.token_source = TokenSource::with_name(b"Advapi  ");
+
+    Ok(token as *mut Token)
+}
+
+/// Log a local account on interactively
+///
+/// # Returns
+/// A new primary token with a reference for the caller
+///
+/// # Safety
+/// SAM and the token pool must be initialized
+pub unsafe fn se_logon_user(name: &str, password: &[u8]) -> Result<*mut Token, LogonError> {
+    let result = match se_lookup_account(name) {
+        Some(user) => authenticate(&user, password).and_then(|()| build_token(&user)),
+        None => Err(LogonError::LogonFailure),
+    };
+
+    match result {
+        Ok(token) => {
+            let sid = format!("{}", (*token).user);
+            se_audit_logon_success(&sid, LOGON32_LOGON_INTERACTIVE, "Advapi");
+            crate::serial_println!("[SE] Logon: {} ({}), integrity {}",
+                name, sid, (*token).integrity_level().name());
+        }
+        Err(error) => {
+            se_audit_logon_failure(name, error.win32_error());
+            crate::serial_println!("[SE] Logon failed for {}: {:?}", name, error);
+        }
+    }
+    result
+}
@@ -7,7 +7,9 @@
 //! - **Access Control Lists (ACLs)**: Permission lists
 //! - **Access Checks**: Permission verification
 //! - **Privileges**: Special capabilities (SeDebugPrivilege, etc.)
+//! - **Integrity Levels**: Mandatory labels checked before the DACL
 //! - **Impersonation**: Thread-level security context switching
+//! - **Logon**: Authenticating local accounts and building their tokens
 //!
 //! # Security Descriptor
 //!
@@ -16,6 +18,7 @@
 //! - Group SID
 //! - DACL (Discretionary ACL) - who can access
 //! - SACL (System ACL) - auditing
+//! - Mandatory label - integrity level and no-write-up/no-read-up policy
 //!
 //! # Key Structures
 //!
@@ -29,6 +32,7 @@ pub mod privilege;
 pub mod acl;
 pub mod descriptor;
 pub mod token;
+pub mod integrity;
 pub mod access;
 pub mod audit;
 pub mod ksecdd;
@@ -36,6 +40,7 @@ pub mod schannel;
 pub mod lsa;
 pub mod sam;
 pub mod ntlm;
+pub mod logon;
 pub mod gpo;
 
 // Re-export SID types
@@ -56,6 +61,7 @@ pub use sid::{
     SID_BUILTIN_ADMINISTRATORS,
     SID_BUILTIN_USERS,
     SID_AUTHENTICATED_USERS,
+    SID_INTERACTIVE,
     // Functions
     se_allocate_sid,
     se_free_sid,
@@ -123,6 +129,7 @@ pub use token::{
     MAX_TOKENS,
     se_create_token,
     se_free_token,
+    se_duplicate_token,
     se_create_system_token,
     se_get_system_token,
     get_token_stats,
@@ -131,6 +138,25 @@ pub use token::{
     impersonation_level_name,
 };
 
+// Re-export integrity types
+pub use integrity::{
+    IntegrityLevel,
+    MandatoryLabel,
+    label_policy,
+    token_policy,
+    se_mandatory_withheld_access,
+};
+
+// Re-export logon types
+pub use logon::{
+    LogonError,
+    LOGON32_LOGON_INTERACTIVE,
+    se_logon_user,
+    se_account_sid,
+    se_lookup_account,
+    se_lookup_account_sid,
+};
+
 // Re-export access check types
 pub use access::{
     AccessCheckResult,
@@ -713,6 +713,10 @@ fn create_builtin_aliases(state: &mut SamState) {
         alias.comment[..comment_len].copy_from_slice(&comment[..comment_len]);
         alias.comment_len = comment_len;
 
+        // Add Administrator to Administrators
+        alias.members[0] = well_known_rids::DOMAIN_USER_RID_ADMIN;
+        alias.member_count = 1;
+
         state.alias_count += 1;
         state.domain.alias_count += 1;
     }
@@ -750,6 +754,10 @@ fn create_builtin_aliases(state: &mut SamState) {
         alias.comment[..comment.len()].copy_from_slice(comment);
         alias.comment_len = comment.len();
 
+        // Add Guest to Guests
+        alias.members[0] = well_known_rids::DOMAIN_USER_RID_GUEST;
+        alias.member_count = 1;
+
         state.alias_count += 1;
         state.domain.alias_count += 1;
     }
@@ -887,6 +895,14 @@ pub fn sam_delete_user(rid: Rid) -> Result<(), SamError> {
             state.users[i].in_use = false;
             state.users[i] = UserAccount::empty();
 
+            // Drop the account from every local group
+            for alias in state.aliases.iter_mut().filter(|a| a.in_use) {
+                if let Some(j) = alias.members[..alias.member_count].iter().position(|&m| m == rid) {
+                    alias.members.copy_within(j + 1..alias.member_count, j);
+                    alias.member_count -= 1;
+                }
+            }
+
             if state.user_count > 0 {
                 state.user_count -= 1;
             }
@@ -1234,6 +1250,33 @@ pub fn sam_add_member_to_alias(alias_rid: Rid, member_rid: Rid) -> Result<(), Sa
     Err(SamError::AliasNotFound)
 }
 
+/// Remove member from alias
+pub fn sam_remove_member_from_alias(alias_rid: Rid, member_rid: Rid) -> Result<(), SamError> {
+    let mut state = SAM_STATE.lock();
+
+    if !state.initialized {
+        return Err(SamError::NotInitialized);
+    }
+
+    for i in 0..MAX_ALIASES {
+        if state.aliases[i].in_use && state.aliases[i].rid == alias_rid {
+            for j in 0..state.aliases[i].member_count {
+                if state.aliases[i].members[j] == member_rid {
+                    // Shift remaining members
+                    for k in j..state.aliases[i].member_count - 1 {
+                        state.aliases[i].members[k] = state.aliases[i].members[k + 1];
+                    }
+                    state.aliases[i].member_count -= 1;
+                    return Ok(());
+                }
+            }
+            return Err(SamError::MemberNotFound);
+        }
+    }
+
+    Err(SamError::AliasNotFound)
+}
+
 /// Enumerate aliases
 pub fn sam_enumerate_aliases() -> Vec<AliasAccount> {
     let state = SAM_STATE.lock();
@@ -14,6 +14,7 @@
 //! - S-1-5-19: Local Service
 //! - S-1-5-20: Network Service
 //! - S-1-5-32-544: Administrators
+//! - S-1-16-8192: Medium Mandatory Level
 
 use core::ptr;
 
@@ -37,6 +38,8 @@ pub mod identifier_authority {
     pub const SECURITY_NON_UNIQUE_AUTHORITY: [u8; 6] = [0, 0, 0, 0, 0, 4];
     /// NT authority (most common)
     pub const SECURITY_NT_AUTHORITY: [u8; 6] = [0, 0, 0, 0, 0, 5];
+    /// Mandatory label authority (integrity levels)
+    pub const SECURITY_MANDATORY_LABEL_AUTHORITY: [u8; 6] = [0, 0, 0, 0, 0, 16];
 }
 
 /// Well-known relative identifiers (RIDs)
@@ -77,6 +80,13 @@ pub mod well_known_rids {
     pub const DOMAIN_ALIAS_RID_USERS: u32 = 545;
     pub const DOMAIN_ALIAS_RID_GUESTS: u32 = 546;
     pub const DOMAIN_ALIAS_RID_POWER_USERS: u32 = 547;
+
+    /// Mandatory label RIDs (integrity levels)
+    pub const SECURITY_MANDATORY_UNTRUSTED_RID: u32 = 0x0000;
+    pub const SECURITY_MANDATORY_LOW_RID: u32 = 0x1000;
+    pub const SECURITY_MANDATORY_MEDIUM_RID: u32 = 0x2000;
+    pub const SECURITY_MANDATORY_HIGH_RID: u32 = 0x3000;
+    pub const SECURITY_MANDATORY_SYSTEM_RID: u32 = 0x4000;
 }
 
 /// Security Identifier (SID)
@@ -193,6 +203,20 @@ impl PartialEq for Sid {
 
 impl Eq for Sid {}
 
+impl core::fmt::Display for Sid {
+    /// Format as an SDDL SID string (S-1-5-32-544)
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let authority = self.identifier_authority
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        write!(f, "S-{}-{}", self.revision, authority)?;
+        for i in 0..(self.sub_authority_count as usize).min(SID_MAX_SUB_AUTHORITIES) {
+            write!(f, "-{}", self.sub_authority[i])?;
+        }
+        Ok(())
+    }
+}
+
 // ============================================================================
 // Well-Known SIDs
 // ============================================================================
@@ -253,6 +277,14 @@ pub const SID_BUILTIN_USERS: Sid = Sid {
     sub_authority: [well_known_rids::SECURITY_BUILTIN_DOMAIN_RID, well_known_rids::DOMAIN_ALIAS_RID_USERS, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
 };
 
+/// Interactive SID (S-1-5-4)
+pub const SID_INTERACTIVE: Sid = Sid {
+    revision: SID_REVISION,
+    sub_authority_count: 1,
+    identifier_authority: identifier_authority::SECURITY_NT_AUTHORITY,
+    sub_authority: [well_known_rids::SECURITY_INTERACTIVE_RID, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+};
+
 /// Authenticated Users SID (S-1-5-11)
 pub const SID_AUTHENTICATED_USERS: Sid = Sid {
     revision: SID_REVISION,
@@ -325,6 +357,11 @@ impl SidAndAttributes {
     pub fn is_deny_only(&self) -> bool {
         (self.attributes & sid_attributes::SE_GROUP_USE_FOR_DENY_ONLY) != 0
     }
+
+    /// Check if this is the token's integrity level
+    pub fn is_integrity(&self) -> bool {
+        (self.attributes & sid_attributes::SE_GROUP_INTEGRITY) != 0
+    }
 }
 
 impl Default for SidAndAttributes {
@@ -6,6 +6,7 @@
 //! - Group SIDs: Group memberships
 //! - Privileges: Special rights (SeDebugPrivilege, etc.)
 //! - Default DACL: Applied to new objects
+//! - Integrity level: Mandatory label group SID (see `integrity`)
 //! - Token type: Primary (process) or Impersonation (thread)
 //!
 //! # Token Types
@@ -25,6 +26,7 @@ use crate::ob::ObjectHeader;
 use super::sid::{Sid, SidAndAttributes, SID_LOCAL_SYSTEM, SID_BUILTIN_ADMINISTRATORS, sid_attributes};
 use super::privilege::{Luid, LuidAndAttributes, PrivilegeSet, SE_MAX_PRIVILEGES, privilege_attributes};
 use super::acl::SimpleAcl;
+use super::integrity::{token_policy, IntegrityLevel};
 
 /// Maximum number of groups in a token
 pub const TOKEN_MAX_GROUPS: usize = 32;
@@ -228,6 +230,9 @@ pub struct Token {
 
     /// Origin LUID (logon session that created this token)
     pub origin_luid: Luid,
+
+    /// Mandatory policy (`token_policy` flags)
+    pub mandatory_policy: u32,
 }
 
 impl Token {
@@ -257,14 +262,18 @@ impl Token {
             elevation_type: TokenElevationType::Default,
             is_elevated: false,
             origin_luid: Luid::new(0, 0),
+            mandatory_policy: token_policy::TOKEN_MANDATORY_POLICY_NO_WRITE_UP,
         }
     }
 
     /// Initialize the token
+    ///
+    /// The token starts at the default integrity level for its user.
     pub fn init(&mut self, user: Sid, token_type: TokenType) {
         self.user = user;
         self.token_type = token_type;
         self.reference_count.store(1, Ordering::SeqCst);
+        self.set_integrity_level(IntegrityLevel::default_for_user(&user));
     }
 
     /// Add a group to the token
@@ -361,6 +370,37 @@ impl Token {
         self.is_user(sid) || self.has_group(sid)
     }
 
+    /// Find the group holding the integrity level
+    fn integrity_group_index(&self) -> Option<usize> {
+        (0..self.group_count as usize).find(|&i| self.groups[i].is_integrity())
+    }
+
+    /// Get the token's integrity level
+    ///
+    /// A token without a mandatory label is Untrusted.
+    pub fn integrity_level(&self) -> IntegrityLevel {
+        self.integrity_group_index()
+            .and_then(|i| IntegrityLevel::from_sid(&self.group_sids[i]))
+            .unwrap_or(IntegrityLevel::Untrusted)
+    }
+
+    /// Set the token's integrity level
+    ///
+    /// Callers are responsible for only ever lowering the level of a token
+    /// that is in use.
+    pub fn set_integrity_level(&mut self, level: IntegrityLevel) -> bool {
+        match self.integrity_group_index() {
+            Some(i) => {
+                self.group_sids[i] = level.sid();
+                true
+            }
+            None => self.add_group(
+                level.sid(),
+                sid_attributes::SE_GROUP_INTEGRITY | sid_attributes::SE_GROUP_INTEGRITY_ENABLED,
+            ),
+        }
+    }
+
     /// Get token statistics
     pub fn get_statistics(&self) -> TokenStatistics {
         TokenStatistics {
@@ -459,6 +499,54 @@ pub unsafe fn se_free_token(token: *mut Token) {
     }
 }
 
+/// Duplicate a token
+///
+/// The copy has its own token ID and the same identity, groups, privileges,
+/// default DACL and integrity level as the source. Used to give each new
+/// process its own primary token.
+///
+/// # Safety
+/// Source must be a valid token
+pub unsafe fn se_duplicate_token(source: *const Token, token_type: TokenType) -> *mut Token {
+    if source.is_null() {
+        return ptr::null_mut();
+    }
+    let source = &*source;
+    let token = se_create_token(source.user, token_type);
+    if token.is_null() {
+        return token;
+    }
+    let dup = &mut *token;
+
+    // Groups point into the token's own SID storage, so re-add them
+    dup.group_count = 0;
+    for i in 0..source.group_count as usize {
+        dup.add_group(source.group_sids[i], source.groups[i].attributes);
+    }
+
+    dup.authentication_id = source.authentication_id;
+    dup.expiration_time = source.expiration_time;
+    dup.impersonation_level = source.impersonation_level;
+    dup.token_source = source.token_source;
+    dup.flags.store(source.flags.load(Ordering::Relaxed), Ordering::Relaxed);
+    dup.owner_index = source.owner_index;
+    dup.primary_group_index = source.primary_group_index;
+    dup.privileges.privilege_count = source.privileges.privilege_count;
+    dup.privileges.control = source.privileges.control;
+    dup.privileges.privilege = source.privileges.privilege;
+    dup.default_dacl.revision = source.default_dacl.revision;
+    dup.default_dacl.ace_count = source.default_dacl.ace_count;
+    dup.default_dacl.aces = source.default_dacl.aces;
+    dup.session_id = source.session_id;
+    dup.restricted_sid_count = source.restricted_sid_count;
+    dup.elevation_type = source.elevation_type;
+    dup.is_elevated = source.is_elevated;
+    dup.origin_luid = source.origin_luid;
+    dup.mandatory_policy = source.mandatory_policy;
+
+    token
+}
+
 /// Create a system token with full privileges
 pub unsafe fn se_create_system_token() -> *mut Token {
     let token = se_create_token(SID_LOCAL_SYSTEM, TokenType::Primary);
@@ -606,6 +694,8 @@ pub struct TokenSnapshot {
     pub ref_count: u32,
     /// Session ID
     pub session_id: u32,
+    /// Integrity level
+    pub integrity_level: IntegrityLevel,
 }
 
 impl TokenSnapshot {
@@ -621,6 +711,7 @@ impl TokenSnapshot {
             privilege_count: 0,
             ref_count: 0,
             session_id: 0,
+            integrity_level: IntegrityLevel::Untrusted,
         }
     }
 }
@@ -682,6 +773,7 @@ pub fn se_get_token_snapshots(max_count: usize) -> ([TokenSnapshot; 32], usize)
                 snap.privilege_count = token.privileges.privilege_count;
                 snap.ref_count = token.reference_count.load(Ordering::Relaxed);
                 snap.session_id = token.session_id;
+                snap.integrity_level = token.integrity_level();
 
                 count += 1;
             }
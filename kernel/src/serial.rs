@@ -42,6 +42,9 @@ static WRITER: Mutex<SerialWriter> = Mutex::new(SerialWriter);
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
     WRITER.lock().write_fmt(args).unwrap();
+
+    // Mirror the line to the remote syslog sink when it forwards the console
+    crate::net::syslog::sink_submit_console(args);
 }
 
 /// Print macro for serial output
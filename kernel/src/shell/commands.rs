@@ -98,13 +98,30 @@ pub fn cmd_help(args: &[&str]) {
         outln!("    copy [s] [d]   Copy file");
         outln!("    ren [old] [new] Rename file");
         outln!("    touch [file]   Create empty file");
+        outln!("    dd if= of=     Block copy between files and raw disks (bs, count)");
+        outln!("    avscan <cmd>   On-access scanner demo filter (start, cache, block)");
+        outln!("    autorun <cmd>  Run executables dropped in C:\\AUTORUN (start, stop)");
+        outln!("    hostfs [cmd]   Host shared folder over 9P (mount, unmount)");
+        outln!("    mount [X: n]   List, mount or dismount (/D) volumes");
+        outln!("    eject X:       Dismount and eject removable media");
         outln!("");
         outln!("  System:");
         outln!("    sysinfo        Comprehensive system overview");
         outln!("    mem            Show memory usage");
+        outln!("    balloon        VirtIO memory balloon status");
+        outln!("    smart [disk]   Disk health (SMART attributes, check, service)");
         outln!("    time           Show system time");
         outln!("    ps <cmd>       Process subsystem (list, proc, thread)");
         outln!("    history        Show command history");
+        outln!("    script <cmd>   Run BASIC test scripts (run, eval)");
+        outln!("    stress <cmd>   Concurrent FS/pool/thread stress test (run, last)");
+        outln!("    bench <cmd>    Benchmark workloads (list, run)");
+        outln!("    ktest <suite>  Kernel self-tests (list, run, all)");
+        outln!("    syscallstat    Per-syscall call counts and latency histograms");
+        outln!("    trace <cmd>    Scheduler tracing (start, stop, cswitch, dump)");
+        outln!("    replay <cmd>   Record/replay interrupts and input (record, play, save, load)");
+        outln!("    top [secs]     Per-process CPU usage including Idle");
+        outln!("    watch <cmd>    Rerun a command every few seconds (-n secs)");
         outln!("    reboot         Restart the system");
         outln!("");
         outln!("  Hardware/Power:");
@@ -112,6 +129,8 @@ pub fn cmd_help(args: &[&str]) {
         outln!("    acpi [tables]  Scan ACPI tables (RSDP, RSDT/XSDT)");
         outln!("    pci [scan]     Scan PCI devices");
         outln!("    power [cmd]    Power management (acpi, throttle, sleep, policy)");
+        outln!("    efivar [cmd]   UEFI variables (list, get, set, bootorder, fwsetup)");
+        outln!("    tpm [cmd]      TPM 2.0 (status, pcrs, log, verify, extend, quote)");
         outln!("    shutdown       Shut down the system (ACPI S5)");
         outln!("    reboot         Restart the system (ACPI reset)");
         outln!("");
@@ -211,66 +230,11 @@ fn eq_ignore_case(a: &str, b: &str) -> bool {
 }
 
 /// Check if a filename matches a wildcard pattern (case-insensitive)
-/// Supports:
+/// Supports the DOS wildcards of `rtl_is_name_in_expression`:
 ///   * - matches any sequence of characters (including empty)
 ///   ? - matches exactly one character
 fn wildcard_match(pattern: &str, name: &str) -> bool {
-    let pat = pattern.as_bytes();
-    let txt = name.as_bytes();
-    wildcard_match_bytes(pat, txt)
-}
-
-/// Recursive wildcard matching on byte slices
-fn wildcard_match_bytes(pattern: &[u8], text: &[u8]) -> bool {
-    let mut p = 0;
-    let mut t = 0;
-    let mut star_p: Option<usize> = None;
-    let mut star_t: Option<usize> = None;
-
-    while t < text.len() {
-        if p < pattern.len() {
-            let pc = pattern[p];
-            let tc = text[t];
-
-            if pc == b'*' {
-                // Star: remember position and try to match zero chars first
-                star_p = Some(p);
-                star_t = Some(t);
-                p += 1;
-                continue;
-            } else if pc == b'?' {
-                // Question mark: match any single character
-                p += 1;
-                t += 1;
-                continue;
-            } else {
-                // Regular character: case-insensitive compare
-                if pc.to_ascii_lowercase() == tc.to_ascii_lowercase() {
-                    p += 1;
-                    t += 1;
-                    continue;
-                }
-            }
-        }
-
-        // No match at current position - try to use a previous star
-        if let (Some(sp), Some(st)) = (star_p, star_t) {
-            // Backtrack: advance star_t and retry
-            p = sp + 1;
-            star_t = Some(st + 1);
-            t = st + 1;
-        } else {
-            // No star to backtrack to - no match
-            return false;
-        }
-    }
-
-    // Consume any trailing stars in pattern
-    while p < pattern.len() && pattern[p] == b'*' {
-        p += 1;
-    }
-
-    p == pattern.len()
+    crate::rtl::rtl_is_name_in_expression(pattern, name, true)
 }
 
 /// Check if a string contains wildcard characters
@@ -941,12 +905,370 @@ pub fn cmd_mem() {
     outln!("    Free:      {} KB ({} pages)", stats.free_pages * 4, stats.free_pages);
     outln!("    Zeroed:    {} KB ({} pages)", stats.zeroed_pages * 4, stats.zeroed_pages);
     outln!("    Active:    {} KB ({} pages)", stats.active_pages * 4, stats.active_pages);
+    let map = crate::mm::mm_get_memory_map_sanitation();
+    if map.original_entries != 0 {
+        outln!("    Reclaimed: {} KB from boot services ({} KB still in use)",
+            map.reclaimed_bytes / 1024, map.retained_bytes / 1024);
+        outln!("    Runtime:   {} KB reserved for firmware", map.runtime_bytes / 1024);
+    }
     outln!("");
     outln!("  Memory Totals:");
     outln!("    Total:     {} bytes", stats.total_bytes());
     outln!("    Free:      {} bytes", stats.free_bytes());
     outln!("    Used:      {} bytes", stats.used_bytes());
     outln!("");
+
+    let frag = crate::mm::mm_get_fragmentation();
+    let pool = crate::mm::mm_get_pool_stats();
+    outln!("  Executive Pool:");
+    outln!("    Size:      {} KB", pool.total_size / 1024);
+    outln!("    Used:      {} KB ({} allocations, {} frees)",
+        pool.bytes_allocated / 1024, pool.allocation_count, pool.free_count);
+    outln!("    Fragmentation: {}% (largest free {} bytes)", frag.pool_fragmentation, frag.pool_largest_free);
+    outln!("");
+
+    let heap = crate::mm::mm_get_heap_stats();
+    outln!("  Kernel Heap:");
+    outln!("    Size:      {} KB", heap.total_size / 1024);
+    outln!("    Used:      {} KB (peak {} KB)", heap.bytes_in_use / 1024, heap.peak_in_use / 1024);
+    outln!("    Free:      {} KB in {} blocks (largest {} KB)",
+        heap.bytes_free / 1024, heap.free_blocks, heap.largest_free_block / 1024);
+    outln!("    Allocs:    {} ({} frees)", heap.allocation_count, heap.free_count);
+    outln!("    Fragmentation: {}%", frag.heap_fragmentation);
+    if heap.failed_count != 0 {
+        outln!("    Failed:    {} (last request {} bytes)", heap.failed_count, heap.last_failed_size);
+    }
+    outln!("");
+
+    let (low, high) = crate::mm::mm_memory_thresholds();
+    outln!("  Memory Condition:");
+    outln!("    Current:   {:?} (low <= {} pages, high >= {} pages)",
+        crate::mm::mm_memory_condition(), low, high);
+    outln!("    Low events: {}", crate::mm::mm_low_memory_transitions());
+    let balloon = crate::drivers::virtio::balloon::virtio_balloon_stats();
+    if balloon.ready {
+        outln!("    Balloon:   {} KB held by host", balloon.actual_pages * 4);
+    }
+    outln!("");
+}
+
+/// HOSTFS command - host shared folder over virtio-9p
+pub fn cmd_hostfs(args: &[&str]) {
+    use crate::fs::p9fs;
+
+    let sub = args.first().copied().unwrap_or("");
+    if eq_ignore_case(sub, "mount") {
+        let drive = args.get(1)
+            .and_then(|a| a.chars().next())
+            .unwrap_or(p9fs::DEFAULT_DRIVE);
+        match p9fs::p9fs_mount_share(drive) {
+            Ok(()) => outln!("Shared folder mounted at {}:", drive.to_ascii_uppercase()),
+            Err(e) => outln!("Mount failed: {:?}", e),
+        }
+        return;
+    }
+    if eq_ignore_case(sub, "unmount") {
+        match p9fs::p9fs_unmount_share() {
+            Ok(()) => outln!("Shared folder unmounted"),
+            Err(e) => outln!("Unmount failed: {:?}", e),
+        }
+        return;
+    }
+    if !sub.is_empty() {
+        outln!("Usage: hostfs [mount [X:] | unmount]");
+        return;
+    }
+
+    let transport = crate::drivers::virtio::p9::virtio_9p_stats();
+    let stats = p9fs::p9fs_stats();
+    outln!("Host Shared Folder (9P2000.L)");
+    outln!("=============================");
+    if !transport.ready {
+        outln!("No virtio-9p device (run QEMU with SHARE=<dir>)");
+        return;
+    }
+    outln!("Mount tag:     {}", crate::drivers::virtio::p9::p9_mount_tag().unwrap_or(""));
+    match stats.drive {
+        Some(d) => outln!("Drive:         {}: (read-only)", d),
+        None => outln!("Drive:         not mounted"),
+    }
+    outln!("msize:         {}", stats.msize);
+    match transport.irq {
+        Some(irq) => outln!("Completion:    interrupt (IRQ {})", irq),
+        None => outln!("Completion:    polled"),
+    }
+    outln!("Nodes cached:  {} ({} open)", stats.cached_nodes, stats.open_nodes);
+    outln!("Evictions:     {}", stats.evictions);
+    outln!("Requests:      {}", stats.rpcs);
+    outln!("Bytes out/in:  {} / {}", transport.bytes_sent, transport.bytes_received);
+    outln!("Errors:        {}", stats.errors);
+    outln!("Timeouts:      {}", transport.timeouts);
+}
+
+/// Parse a drive argument ("E" or "E:")
+fn parse_drive_arg(arg: &str) -> Option<char> {
+    let bytes = arg.as_bytes();
+    let valid = match bytes.len() {
+        1 => true,
+        2 => bytes[1] == b':',
+        _ => false,
+    };
+    if valid && bytes[0].is_ascii_alphabetic() {
+        Some(bytes[0].to_ascii_uppercase() as char)
+    } else {
+        None
+    }
+}
+
+/// MOUNT command - list, mount and dismount volumes
+pub fn cmd_mount(args: &[&str]) {
+    use crate::fs::{mount, volume};
+
+    if args.contains(&"/?") || args.len() > 2 {
+        outln!("Mounts and dismounts volumes.");
+        outln!("");
+        outln!("MOUNT");
+        outln!("MOUNT drive: volume");
+        outln!("MOUNT drive: /R disk");
+        outln!("MOUNT drive: /D");
+        outln!("");
+        outln!("  volume   Volume number to mount (see VOLUMES LIST)");
+        outln!("  /R disk  Rescan removable disk and mount its media");
+        outln!("  /D       Dismount the volume");
+        return;
+    }
+
+    if args.is_empty() {
+        outln!("Drive  Type    Volume  Flags");
+        for (letter, fs_type) in mount::list_mounts().iter().flatten() {
+            let Some(mp) = mount::get_mount_point(*letter) else {
+                continue;
+            };
+            outln!("{}:     {:<7} {:<7} {}{}{}{}",
+                letter,
+                alloc::format!("{:?}", fs_type),
+                mp.volume_number,
+                if mp.is_system() { "system " } else { "" },
+                if mp.is_boot() { "boot " } else { "" },
+                if mp.is_removable() { "removable " } else { "" },
+                if mp.is_readonly() { "readonly" } else { "" });
+        }
+        return;
+    }
+
+    let Some(drive) = parse_drive_arg(args[0]) else {
+        outln!("Invalid drive - {}", args[0]);
+        return;
+    };
+    let Some(arg) = args.get(1) else {
+        outln!("Missing volume number or /D");
+        return;
+    };
+
+    let result = if eq_ignore_case(arg, "/D") {
+        volume::dismount_volume(drive)
+    } else if let Some(disk) = arg.strip_prefix("/R").or_else(|| arg.strip_prefix("/r")) {
+        match disk.trim_start_matches(':').parse::<u8>() {
+            Ok(disk) => volume::mount_disk(disk, drive, 0),
+            Err(_) => {
+                outln!("Invalid disk - {}", arg);
+                return;
+            }
+        }
+    } else {
+        match arg.parse::<u8>() {
+            Ok(vol) => volume::mount_volume(vol, drive, 0),
+            Err(_) => {
+                outln!("Invalid volume - {}", arg);
+                return;
+            }
+        }
+    };
+
+    match result {
+        Ok(()) if eq_ignore_case(arg, "/D") => outln!("{}: dismounted", drive),
+        Ok(()) => outln!("{}: mounted", drive),
+        Err(e) => outln!("Mount failed: {:?}", e),
+    }
+}
+
+/// EJECT command - dismount and eject removable media
+pub fn cmd_eject(args: &[&str]) {
+    use crate::fs::volume;
+
+    if args.len() != 1 || args[0] == "/?" {
+        outln!("Dismounts a removable volume and ejects its media.");
+        outln!("");
+        outln!("EJECT drive:");
+        outln!("EJECT disk");
+        return;
+    }
+
+    let result = match args[0].parse::<u8>() {
+        Ok(disk) => volume::eject_disk(disk),
+        Err(_) => match parse_drive_arg(args[0]) {
+            Some(drive) => volume::eject_drive(drive),
+            None => {
+                outln!("Invalid drive - {}", args[0]);
+                return;
+            }
+        },
+    };
+
+    match result {
+        Ok(()) => outln!("Media ejected"),
+        Err(e) => outln!("Eject failed: {:?}", e),
+    }
+}
+
+/// SMART command - disk health (SMART attributes)
+pub fn cmd_smart(args: &[&str]) {
+    use crate::io::block::{self, MAX_BLOCK_DEVICES};
+    use crate::io::smart;
+
+    if args.first().is_some_and(|a| *a == "/?" || eq_ignore_ascii_case(a, "help")) {
+        outln!("Shows SMART health data of ATA disks.");
+        outln!("");
+        outln!("SMART                   Health summary of every disk");
+        outln!("SMART disk              Attributes of one disk (index or name)");
+        outln!("SMART check             Run a health check now, logging threshold trips");
+        outln!("SMART service [start|stop]");
+        outln!("                        Periodic health-check service");
+        return;
+    }
+
+    let Some(&arg) = args.first() else {
+        outln!("Disk  Model                     Health  Temp  Realloc  Power-On");
+        let mut found = false;
+        for index in 0..MAX_BLOCK_DEVICES as u8 {
+            let Some(dev) = block::get_block_device(index) else { continue };
+            let Ok(report) = smart::smart_read(index) else { continue };
+            let temp = report.temperature().map(|t| alloc::format!("{} C", t)).unwrap_or_else(|| "-".into());
+            let realloc = report.reallocated_sectors().map(|r| alloc::format!("{}", r)).unwrap_or_else(|| "-".into());
+            let hours = report.power_on_hours().map(|h| alloc::format!("{} h", h)).unwrap_or_else(|| "-".into());
+            outln!("{:<5} {:<25} {:<7} {:>4} {:>8} {:>9}",
+                dev.name_str(), dev.model_str(), if report.passed() { "PASSED" } else { "FAILED" },
+                temp, realloc, hours);
+            found = true;
+        }
+        if !found {
+            outln!("No disks report SMART data");
+        }
+        return;
+    };
+
+    if eq_ignore_ascii_case(arg, "check") {
+        let events = smart::smart_check_all();
+        outln!("Health check complete, {} event(s) logged", events);
+        return;
+    }
+
+    if eq_ignore_ascii_case(arg, "service") {
+        match args.get(1) {
+            Some(a) if eq_ignore_ascii_case(a, "start") => {
+                if smart::smart_service_start() {
+                    outln!("SMART health service started");
+                } else {
+                    outln!("SMART health service is already running");
+                }
+            }
+            Some(a) if eq_ignore_ascii_case(a, "stop") => {
+                if smart::smart_service_stop() {
+                    outln!("SMART health service stopping");
+                } else {
+                    outln!("SMART health service is not running");
+                }
+            }
+            _ => {
+                let status = smart::smart_service_status();
+                outln!("State:       {}", if status.running { "running" } else { "stopped" });
+                outln!("Interval:    {} min", smart::CHECK_INTERVAL_MS / 60_000);
+                outln!("Checks:      {}", status.checks);
+                outln!("Events:      {}", status.events);
+                if status.checks > 0 {
+                    outln!("Last check:  tick {}", status.last_check);
+                }
+            }
+        }
+        return;
+    }
+
+    let index = match arg.parse::<u8>().ok().or_else(|| block::find_block_device(arg)) {
+        Some(i) => i,
+        None => {
+            outln!("No such disk - {}", arg);
+            return;
+        }
+    };
+    let Some(dev) = block::get_block_device(index) else {
+        outln!("No such disk - {}", arg);
+        return;
+    };
+    let report = match smart::smart_read(index) {
+        Ok(r) => r,
+        Err(crate::io::block::BlockStatus::NotSupported) => {
+            outln!("{}: SMART not supported", dev.name_str());
+            return;
+        }
+        Err(e) => {
+            outln!("{}: cannot read SMART data: {:?}", dev.name_str(), e);
+            return;
+        }
+    };
+
+    outln!("SMART data for {} ({})", dev.name_str(), dev.model_str());
+    outln!("Overall health: {}", if report.passed() { "PASSED" } else { "FAILED" });
+    if let Some(t) = report.temperature() {
+        outln!("Temperature:    {} C", t);
+    }
+    if let Some(r) = report.reallocated_sectors() {
+        outln!("Reallocated:    {} sectors", r);
+    }
+    if let Some(h) = report.power_on_hours() {
+        outln!("Power-on time:  {} hours", h);
+    }
+    outln!("");
+    outln!("ID# ATTRIBUTE_NAME           FLAG   VALUE WORST THRESH TYPE     RAW_VALUE     WHEN_FAILED");
+    for attr in report.attributes() {
+        let failed = if !attr.failing() {
+            "-"
+        } else if attr.value == attr.worst {
+            "FAILING_NOW"
+        } else {
+            "In_the_past"
+        };
+        outln!("{:>3} {:<24} {:#06x} {:03}   {:03}   {:03}    {:<8} {:<13} {}",
+            attr.id, attr.name(), attr.flags, attr.value, attr.worst, attr.threshold,
+            if attr.prefailure() { "Pre-fail" } else { "Old_age" }, attr.raw, failed);
+    }
+}
+
+/// BALLOON command - show virtio-balloon driver state
+pub fn cmd_balloon(_args: &[&str]) {
+    use crate::drivers::virtio::balloon::balloon_features;
+
+    let stats = crate::drivers::virtio::balloon::virtio_balloon_stats();
+
+    outln!("VirtIO Memory Balloon");
+    outln!("=====================");
+    if !stats.ready {
+        outln!("No virtio-balloon device (run QEMU with -device virtio-balloon)");
+        return;
+    }
+    outln!("Target:        {} pages ({} KB)", stats.target_pages, stats.target_pages * 4);
+    outln!("Held by host:  {} pages ({} KB)", stats.actual_pages, stats.actual_pages * 4);
+    outln!("Features:      {:#010x}{}{}", stats.features,
+        if stats.features & balloon_features::MUST_TELL_HOST != 0 { " must-tell-host" } else { "" },
+        if stats.features & balloon_features::DEFLATE_ON_OOM != 0 { " deflate-on-oom" } else { "" });
+    match stats.irq {
+        Some(irq) => outln!("Notification:  interrupt (IRQ {})", irq),
+        None => outln!("Notification:  polled"),
+    }
+    outln!("Inflated:      {} pages", stats.pages_inflated);
+    outln!("Deflated:      {} pages", stats.pages_deflated);
+    outln!("OOM deflates:  {}", stats.oom_deflations);
+    outln!("Config events: {}", stats.config_changes);
+    outln!("Errors:        {}", stats.errors);
 }
 
 /// Show system time (tick count)
@@ -1031,8 +1353,8 @@ pub fn cmd_ps(args: &[&str]) {
     } else if eq_ignore_case(cmd, "list") {
         outln!("Active Processes");
         outln!("");
-        outln!("{:<6} {:<6} {:<8} {:<16}", "PID", "PPID", "Threads", "Name");
-        outln!("----------------------------------------------");
+        outln!("{:<6} {:<6} {:<8} {:<16} {:>14}", "PID", "PPID", "Threads", "Name", "CPU Time");
+        outln!("-------------------------------------------------------------");
 
         unsafe {
             let list_head = ps::get_active_process_list();
@@ -1049,8 +1371,10 @@ pub fn cmd_ps(args: &[&str]) {
                     let thread_count = (*process).thread_count();
                     let name = (*process).image_name();
                     let name_str = core::str::from_utf8(name).unwrap_or("?");
+                    let cpu_time = (*process).pcb.kernel_time() + (*process).pcb.user_time();
 
-                    outln!("{:<6} {:<6} {:<8} {:<16}", pid, ppid, thread_count, name_str);
+                    outln!("{:<6} {:<6} {:<8} {:<16} {:>14}", pid, ppid, thread_count, name_str,
+                        format_cpu_time(cpu_time).as_str());
 
                     entry = (*entry).flink;
                     count += 1;
@@ -1097,6 +1421,14 @@ pub fn cmd_ps(args: &[&str]) {
             outln!("Threads:     {}", (*process).thread_count());
             outln!("System:      {}", if (*process).is_system() { "Yes" } else { "No" });
             outln!("Exiting:     {}", if (*process).is_exiting() { "Yes" } else { "No" });
+            outln!("Affinity:    {:#x}", (*process).pcb.affinity);
+            match (*process).pcb.cpu_rate_limit {
+                0 => outln!("CPU limit:   None"),
+                pct => outln!("CPU limit:   {}% (window {} ms)", pct, crate::ke::cpurate::CPU_RATE_WINDOW_MS),
+            }
+            let io_priority = crate::io::IoPriority::from_raw((*process).io_priority as u32);
+            outln!("I/O prio:    {}", io_priority.map(|p| p.name()).unwrap_or("?"));
+            outln!("Integrity:   {}", ps::ps_get_process_integrity_level(process).name());
         }
     } else if eq_ignore_case(cmd, "thread") {
         if args.len() < 2 {
@@ -1143,10 +1475,15 @@ pub fn cmd_ps(args: &[&str]) {
 /// Reboot the system
 pub fn cmd_reboot() {
     outln!("Rebooting...");
-    outln!("Stopping services and resetting...");
+    outln!("Stopping services and flushing to disk...");
 
-    // restart() never returns - it will reset the system
-    crate::po::restart();
+    // restart_with_progress() never returns - it will reset the system
+    crate::po::restart_with_progress(shell_progress);
+}
+
+/// Shutdown progress sink for the shell console
+fn shell_progress(line: &str) {
+    outln!("{}", line);
 }
 
 /// Test suspend/resume syscalls
@@ -1628,10 +1965,10 @@ pub fn cmd_power(args: &[&str]) {
 /// Shutdown the system
 pub fn cmd_shutdown() {
     outln!("Initiating system shutdown...");
-    outln!("Stopping services and powering off...");
+    outln!("Stopping services and flushing to disk...");
 
-    // shutdown() never returns - it will power off the system
-    crate::po::shutdown();
+    // shutdown_with_progress() never returns - it will power off the system
+    crate::po::shutdown_with_progress(shell_progress);
 }
 
 /// VEH (Vectored Exception Handler) information and testing
@@ -2191,6 +2528,8 @@ pub fn cmd_net(args: &[&str]) {
         outln!("      HELP | HELPMSG | LOCALGROUP | NAME | PAUSE | PRINT | SEND |");
         outln!("      SESSION | SHARE | START | STATISTICS | STOP | TIME | USE |");
         outln!("      USER | VIEW ]");
+        outln!("");
+        outln!("NET [ STATS | CONNECTIONS | INTERFACES ] shows protocol stack status.");
         return;
     }
 
@@ -2251,71 +2590,111 @@ pub fn cmd_net(args: &[&str]) {
             outln!("The {} service could not be continued.", args[1]);
         }
     } else if eq_ignore_case(cmd, "user") {
-        // User account management
-        if args.len() < 2 {
+        use crate::se::sam::{self, well_known_rids};
+
+        // User account management against the SAM
+        let delete = args.iter().any(|a| eq_ignore_case(a, "/delete"));
+        let add = args.iter().any(|a| eq_ignore_case(a, "/add"));
+        let operands: alloc::vec::Vec<&str> = args[1..].iter().copied().filter(|a| !a.starts_with('/')).collect();
+
+        if operands.is_empty() && !add && !delete {
             outln!("");
-            outln!("User accounts for \\\\NOSTALGOS");
+            outln!("User accounts for \\\\{}", get_hostname());
             outln!("");
             outln!("-------------------------------------------------------------------------------");
-            outln!("Administrator            Guest                    SYSTEM");
-            outln!("The command completed successfully.");
-        } else if eq_ignore_case(args[1], "/add") {
-            if args.len() < 3 {
-                outln!("The syntax of this command is:");
-                outln!("NET USER username [password | *] /ADD [options]");
-            } else {
-                outln!("The command completed successfully.");
+            for (i, user) in sam::sam_enumerate_users().iter().enumerate() {
+                out!("{:<25}", core::str::from_utf8(user.get_username()).unwrap_or("?"));
+                if i % 3 == 2 {
+                    outln!("");
+                }
             }
-        } else if eq_ignore_case(args[1], "/delete") {
-            if args.len() < 3 {
-                outln!("The syntax of this command is:");
+            outln!("");
+            outln!("The command completed successfully.");
+            return;
+        }
+
+        if operands.is_empty() {
+            outln!("The syntax of this command is:");
+            if delete {
                 outln!("NET USER username /DELETE");
             } else {
-                outln!("The command completed successfully.");
+                outln!("NET USER username [password | *] /ADD");
             }
-        } else {
-            use crate::hal::rtc::get_datetime;
-            use crate::se::get_token_stats;
+            return;
+        }
 
-            // Show user info using real security subsystem data
-            let username = args[1];
-            let dt = get_datetime();
-            let token_stats = get_token_stats();
-            let am_pm = if dt.hour < 12 { "AM" } else { "PM" };
-            let hour_12 = if dt.hour == 0 { 12 } else if dt.hour > 12 { dt.hour - 12 } else { dt.hour };
-
-            outln!("User name                    {}", username);
-            outln!("Full Name                    ");
-            outln!("Comment                      Built-in account");
-            outln!("User's comment               ");
-            outln!("Country code                 000 (System Default)");
-            outln!("Account active               Yes");
+        let username = operands[0];
+        let password = operands.get(1).copied();
+
+        // "*" prompts for the password
+        let mut prompted = [0u8; 64];
+        let password: Option<&[u8]> = match password {
+            Some("*") => {
+                out!("Type a password for the user: ");
+                let len = read_password(&mut prompted);
+                Some(&prompted[..len])
+            }
+            Some(p) => Some(p.as_bytes()),
+            None => None,
+        };
+
+        if add {
+            let result = sam::sam_create_user(username.as_bytes()).and_then(|rid| {
+                if let Some(password) = password {
+                    sam::sam_set_user_password(rid, &crate::se::ntlm::ntlm_compute_nt_hash(password))?;
+                }
+                sam::sam_add_member_to_alias(well_known_rids::DOMAIN_ALIAS_RID_USERS, rid)
+            });
+            match result {
+                Ok(()) => outln!("The command completed successfully."),
+                Err(sam::SamError::UserExists) => outln!("The account already exists."),
+                Err(e) => outln!("System error {:#x} has occurred.", e as u32),
+            }
+            return;
+        }
+
+        let account = match crate::se::se_lookup_account(username) {
+            Some(account) => account,
+            None => {
+                outln!("The user name could not be found.");
+                return;
+            }
+        };
+
+        if delete {
+            match sam::sam_delete_user(account.rid) {
+                Ok(()) => outln!("The command completed successfully."),
+                Err(e) => outln!("System error {:#x} has occurred.", e as u32),
+            }
+        } else if let Some(password) = password {
+            match sam::sam_set_user_password(account.rid, &crate::se::ntlm::ntlm_compute_nt_hash(password)) {
+                Ok(()) => outln!("The command completed successfully."),
+                Err(e) => outln!("System error {:#x} has occurred.", e as u32),
+            }
+        } else {
+            use sam::user_account_control::UF_PASSWORD_NOT_REQUIRED;
+
+            let yes_no = |b: bool| if b { "Yes" } else { "No" };
+            outln!("User name                    {}", core::str::from_utf8(account.get_username()).unwrap_or("?"));
+            outln!("Full Name                    {}", core::str::from_utf8(account.get_full_name()).unwrap_or(""));
+            outln!("Comment                      {}",
+                core::str::from_utf8(&account.comment[..account.comment_len]).unwrap_or(""));
+            outln!("User's SID                   {}", crate::se::se_account_sid(account.rid));
+            outln!("Account active               {}", yes_no(!account.is_disabled()));
+            outln!("Account locked               {}", yes_no(account.is_locked()));
             outln!("Account expires              Never");
             outln!("");
-            outln!("Password last set            {:02}/{:02}/{:04} {}:{:02}:{:02} {}",
-                   dt.month, dt.day, dt.year, hour_12, dt.minute, dt.second, am_pm);
-            outln!("Password expires             Never");
-            outln!("Password changeable          {:02}/{:02}/{:04} {}:{:02}:{:02} {}",
-                   dt.month, dt.day, dt.year, hour_12, dt.minute, dt.second, am_pm);
-            outln!("Password required            Yes");
-            outln!("User may change password     Yes");
+            outln!("Password required            {}",
+                yes_no((account.user_account_control & UF_PASSWORD_NOT_REQUIRED) == 0));
+            outln!("Logon count                  {}", account.logon_count);
             outln!("");
-            outln!("Workstations allowed         All");
-            outln!("Logon script                 ");
-            outln!("User profile                 ");
-            outln!("Home directory               ");
-            outln!("Last logon                   {:02}/{:02}/{:04} {}:{:02}:{:02} {}",
-                   dt.month, dt.day, dt.year, hour_12, dt.minute, dt.second, am_pm);
-            outln!("");
-            outln!("Logon hours allowed          All");
-            outln!("");
-            // Use security subsystem to determine group membership
-            // Primary tokens are typically system/admin tokens
-            if token_stats.primary_tokens > 0 {
-                outln!("Local Group Memberships      *Administrators");
-            } else {
-                outln!("Local Group Memberships      *Users");
+            out!("Local Group Memberships      ");
+            for alias in sam::sam_enumerate_aliases() {
+                if alias.has_member(account.rid) {
+                    out!("*{:<20}", core::str::from_utf8(alias.get_name()).unwrap_or("?"));
+                }
             }
+            outln!("");
             outln!("Global Group memberships     *None");
             outln!("The command completed successfully.");
         }
@@ -2384,32 +2763,191 @@ pub fn cmd_net(args: &[&str]) {
             outln!("The command completed successfully.");
         }
     } else if eq_ignore_case(cmd, "localgroup") {
-        // Local group management
+        use crate::se::sam;
+
+        // Local group (alias) management against the SAM
+        let aliases = sam::sam_enumerate_aliases();
         if args.len() < 2 {
             outln!("");
-            outln!("Aliases for \\\\NOSTALGOS");
+            outln!("Aliases for \\\\{}", get_hostname());
             outln!("");
             outln!("-------------------------------------------------------------------------------");
-            outln!("*Administrators");
-            outln!("*Backup Operators");
-            outln!("*Guests");
-            outln!("*Network Configuration Operators");
-            outln!("*Power Users");
-            outln!("*Remote Desktop Users");
-            outln!("*Replicator");
-            outln!("*Users");
+            for alias in &aliases {
+                outln!("*{}", core::str::from_utf8(alias.get_name()).unwrap_or("?"));
+            }
             outln!("The command completed successfully.");
-        } else {
-            // Show group members
-            outln!("Alias name     {}", args[1]);
-            outln!("Comment        ");
-            outln!("");
-            outln!("Members");
-            outln!("");
-            outln!("-------------------------------------------------------------------------------");
-            outln!("Administrator");
+            return;
+        }
+
+        let alias = match aliases.iter().find(|a| a.get_name().eq_ignore_ascii_case(args[1].as_bytes())) {
+            Some(alias) => alias,
+            None => {
+                outln!("The specified local group does not exist.");
+                return;
+            }
+        };
+
+        let add = args.iter().any(|a| eq_ignore_case(a, "/add"));
+        let delete = args.iter().any(|a| eq_ignore_case(a, "/delete"));
+        if add || delete {
+            for name in args[2..].iter().filter(|a| !a.starts_with('/')) {
+                let account = match crate::se::se_lookup_account(name) {
+                    Some(account) => account,
+                    None => {
+                        outln!("There is no such global user or group: {}.", name);
+                        return;
+                    }
+                };
+                let result = if add {
+                    sam::sam_add_member_to_alias(alias.rid, account.rid)
+                } else {
+                    sam::sam_remove_member_from_alias(alias.rid, account.rid)
+                };
+                match result {
+                    Ok(()) => {}
+                    Err(sam::SamError::MemberInAlias) => {
+                        outln!("The specified account name is already a member of the group.");
+                        return;
+                    }
+                    Err(sam::SamError::MemberNotFound) => {
+                        outln!("The specified account name is not a member of the group.");
+                        return;
+                    }
+                    Err(e) => {
+                        outln!("System error {:#x} has occurred.", e as u32);
+                        return;
+                    }
+                }
+            }
             outln!("The command completed successfully.");
+            return;
         }
+
+        // Show group members
+        outln!("Alias name     {}", core::str::from_utf8(alias.get_name()).unwrap_or("?"));
+        outln!("Comment        {}", core::str::from_utf8(&alias.comment[..alias.comment_len]).unwrap_or(""));
+        outln!("");
+        outln!("Members");
+        outln!("");
+        outln!("-------------------------------------------------------------------------------");
+        for &rid in &alias.members[..alias.member_count] {
+            if let Ok(user) = sam::sam_get_user_by_rid(rid) {
+                outln!("{}", core::str::from_utf8(user.get_username()).unwrap_or("?"));
+            }
+        }
+        outln!("The command completed successfully.");
+    } else if eq_ignore_case(cmd, "stats") {
+        let link = crate::net::get_stats();
+        let stats = crate::net::get_protocol_stats();
+
+        outln!("");
+        outln!("Link Statistics");
+        outln!("  Frames Received            {:>12}    Frames Sent          {:>12}", link.packets_received, link.packets_transmitted);
+        outln!("  Bytes Received             {:>12}    Bytes Sent           {:>12}", link.bytes_received, link.bytes_transmitted);
+        outln!("  Receive Errors             {:>12}    Transmit Errors      {:>12}", link.receive_errors, link.transmit_errors);
+        outln!("  ARP Requests Sent          {:>12}    ARP Replies Received {:>12}", link.arp_requests, link.arp_replies);
+        outln!("");
+        outln!("IPv4 Statistics");
+        outln!("  Datagrams Received         {:>12}", stats.ip.received);
+        outln!("  Received Header Errors     {:>12}", stats.ip.header_errors);
+        outln!("  Unknown Protocols Received {:>12}", stats.ip.unknown_protocols);
+        outln!("  Datagrams Delivered        {:>12}", stats.ip.delivered);
+        outln!("  Datagrams Sent             {:>12}", stats.ip.sent);
+        outln!("");
+        outln!("ICMPv4 Statistics            {:>12}    {:>12}", "Received", "Sent");
+        outln!("  Messages                   {:>12}    {:>12}", stats.icmp.messages_received, stats.icmp.messages_sent);
+        outln!("  Errors                     {:>12}    {:>12}", stats.icmp.input_errors, 0);
+        outln!("  Destination Unreachable    {:>12}    {:>12}", stats.icmp.dest_unreachable_received, 0);
+        outln!("  Time Exceeded              {:>12}    {:>12}", stats.icmp.time_exceeded_received, 0);
+        outln!("  Echo Requests              {:>12}    {:>12}", stats.icmp.echo_requests_received, stats.icmp.echo_requests_sent);
+        outln!("  Echo Replies               {:>12}    {:>12}", stats.icmp.echo_replies_received, stats.icmp.echo_replies_sent);
+        outln!("");
+        outln!("TCP Statistics");
+        outln!("  Active Opens               {:>12}", stats.tcp.active_opens);
+        outln!("  Passive Opens              {:>12}", stats.tcp.passive_opens);
+        outln!("  Failed Connection Attempts {:>12}", stats.tcp.failed_attempts);
+        outln!("  Resets Received            {:>12}", stats.tcp.resets_received);
+        outln!("  Resets Sent                {:>12}", stats.tcp.resets_sent);
+        outln!("  Current Connections        {:>12}", stats.tcp.current_established);
+        outln!("  Segments Received          {:>12}", stats.tcp.segments_received);
+        outln!("  Segments Sent              {:>12}", stats.tcp.segments_sent);
+        outln!("  Segments With Errors       {:>12}", stats.tcp.input_errors);
+        outln!("");
+        outln!("UDP Statistics");
+        outln!("  Datagrams Received         {:>12}", stats.udp.datagrams_received);
+        outln!("  No Ports                   {:>12}", stats.udp.no_port);
+        outln!("  Receive Errors             {:>12}", stats.udp.receive_errors);
+        outln!("  Datagrams Sent             {:>12}", stats.udp.datagrams_sent);
+        outln!("");
+    } else if eq_ignore_case(cmd, "connections") {
+        use crate::net::{tcp, udp};
+
+        let connections = tcp::enumerate_connections();
+        let endpoints = udp::enumerate_endpoints();
+
+        outln!("");
+        outln!("  Proto  Local Address          Foreign Address        State            Recv-Q  Send-Q");
+        for conn in &connections {
+            let local = alloc::format!("{}:{}", conn.local_ip, conn.local_port);
+            let remote = if conn.remote_port == 0 {
+                alloc::format!("*:*")
+            } else {
+                alloc::format!("{}:{}", conn.remote_ip, conn.remote_port)
+            };
+            outln!("  TCP    {:<22} {:<22} {:<16} {:>6}  {:>6}",
+                local, remote, tcp_state_name(conn.state), conn.rx_queue, conn.tx_queue);
+        }
+        for ep in &endpoints {
+            let local = alloc::format!("{}:{}", ep.local_ip, ep.local_port);
+            outln!("  UDP    {:<22} {:<22} {:<16} {:>6}  {:>6}", local, "*:*", "", ep.rx_queue, 0);
+        }
+        outln!("");
+        outln!("{} TCP connection(s), {} UDP endpoint(s)", connections.len(), endpoints.len());
+    } else if eq_ignore_case(cmd, "interfaces") {
+        use crate::net::NetworkDeviceState;
+
+        let interfaces = crate::net::enumerate_interfaces();
+        if interfaces.is_empty() {
+            outln!("No network interfaces are registered.");
+            return;
+        }
+
+        for iface in &interfaces {
+            let link = match iface.state {
+                NetworkDeviceState::Connected => "Up",
+                NetworkDeviceState::Disconnected => "Down (no carrier)",
+                NetworkDeviceState::NotInitialized => "Not initialized",
+                NetworkDeviceState::Initializing => "Initializing",
+                NetworkDeviceState::Error => "Error",
+                NetworkDeviceState::Resetting => "Resetting",
+                NetworkDeviceState::Removing => "Removing",
+            };
+
+            outln!("");
+            outln!("Interface {}: {}", iface.index, iface.name);
+            outln!("  Link State         {}", link);
+            outln!("  Physical Address   {}", iface.mac_address);
+            if iface.link_speed >= 1000 && iface.link_speed % 1000 == 0 {
+                outln!("  Speed              {} Gbps", iface.link_speed / 1000);
+            } else {
+                outln!("  Speed              {} Mbps", iface.link_speed);
+            }
+            outln!("  MTU                {}", iface.mtu);
+            outln!("  Promiscuous        {}", if iface.promiscuous { "Yes" } else { "No" });
+            match (iface.ip_address, iface.subnet_mask) {
+                (Some(ip), Some(mask)) => outln!("  IPv4 Address       {} / {}", ip, mask),
+                (Some(ip), None) => outln!("  IPv4 Address       {}", ip),
+                _ => outln!("  IPv4 Address       (not configured)"),
+            }
+            if let Some(gateway) = iface.gateway {
+                outln!("  Default Gateway    {}", gateway);
+            }
+            outln!("  Received           {} packets, {} bytes, {} errors, {} dropped",
+                iface.stats.rx_packets, iface.stats.rx_bytes, iface.stats.rx_errors, iface.stats.rx_dropped);
+            outln!("  Sent               {} packets, {} bytes, {} errors, {} dropped",
+                iface.stats.tx_packets, iface.stats.tx_bytes, iface.stats.tx_errors, iface.stats.tx_dropped);
+        }
+        outln!("");
     } else if eq_ignore_case(cmd, "statistics") {
         use crate::net::get_stats;
         use crate::hal::rtc::get_datetime;
@@ -2567,16 +3105,16 @@ pub fn cmd_net(args: &[&str]) {
             outln!("The syntax of this command is:");
             outln!("NET HELPMSG message#");
         } else {
+            use crate::rtl::message::{rtl_get_kernel_message, KernelMessageTable};
+
             let code = args[1];
-            match code {
-                "0" => outln!("The operation completed successfully."),
-                "5" => outln!("Access is denied."),
-                "53" => outln!("The network path was not found."),
-                "64" => outln!("The specified network name is no longer available."),
-                "67" => outln!("The network name cannot be found."),
-                "1326" => outln!("Logon failure: unknown user name or bad password."),
-                "2221" => outln!("The user name could not be found."),
-                _ => outln!("An error occurred for which no help message is available: {}", code),
+            match code.parse::<u32>().ok().and_then(|n| rtl_get_kernel_message(KernelMessageTable::System, n)) {
+                Some(text) => {
+                    outln!("");
+                    outln!("{}", text.trim_end());
+                    outln!("");
+                }
+                None => outln!("{} is not a valid Windows network message number.", code),
             }
         }
     } else if eq_ignore_case(cmd, "help") {
@@ -2620,6 +3158,8 @@ pub fn cmd_pe(args: &[&str]) {
         outln!("  sections <address> List sections");
         outln!("  imports <address>  List imports");
         outln!("  exports <address>  List exports");
+        outln!("  verify <file | address> [size] [mapped]");
+        outln!("                     Check the image the way the loader will");
         outln!("  kernel             Analyze kernel image");
         return;
     }
@@ -2676,11 +3216,128 @@ pub fn cmd_pe(args: &[&str]) {
             return;
         }
         show_pe_exports(addr);
+    } else if eq_ignore_case(cmd, "verify") {
+        if args.len() < 2 {
+            outln!("Usage: pe verify <file | address> [size] [mapped]");
+            return;
+        }
+        verify_pe(&args[1..]);
     } else {
         outln!("Unknown pe command: {}", cmd);
     }
 }
 
+/// Largest file `pe verify` reads into memory
+const PE_VERIFY_MAX_FILE: usize = 16 * 1024 * 1024;
+
+/// Run the loader's image verifier on a file or a memory range
+fn verify_pe(args: &[&str]) {
+    use crate::ldr::verify::{pe_verify, pe_file_extent, PeCheck, PeIssueSeverity, PeLayout};
+
+    let target = args[0];
+    let mapped = args.iter().skip(1).any(|a| eq_ignore_case(a, "mapped"));
+    let address = parse_hex_address(target);
+    let is_path = address == 0 || target.contains('.') || target.contains('\\') || target.contains(':');
+
+    let file;
+    let data: &[u8] = if is_path {
+        let path = alloc::string::String::from(resolve_path(target));
+        file = match read_whole_file(&path, PE_VERIFY_MAX_FILE) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                outln!("Cannot read {}: {:?}", path, e);
+                return;
+            }
+        };
+        outln!("Verifying {} ({} bytes, {} layout)", path, file.len(), if mapped { "mapped" } else { "file" });
+        &file
+    } else {
+        let size = match args.get(1).filter(|a| !eq_ignore_case(a, "mapped")) {
+            Some(s) => parse_hex_address(s) as usize,
+            None if mapped => unsafe { crate::ldr::parse_pe(address as *const u8) }
+                .map(|info| info.size_of_image as usize).unwrap_or(0),
+            None => unsafe { pe_file_extent(address as *const u8) }.unwrap_or(0),
+        };
+        if size == 0 {
+            outln!("Cannot determine the image size at {:#x}; pass it explicitly", address);
+            return;
+        }
+        outln!("Verifying image at {:#x} ({:#x} bytes, {} layout)", address, size, if mapped { "mapped" } else { "file" });
+        unsafe { core::slice::from_raw_parts(address as *const u8, size) }
+    };
+
+    let report = pe_verify(data, if mapped { PeLayout::Mapped } else { PeLayout::File });
+    outln!("");
+
+    if let Some(info) = report.info {
+        outln!("  {} {}, image size {:#x}, entry {:#x}",
+            if info.is_64bit { "PE32+" } else { "PE32" },
+            if info.is_dll { "DLL" } else { "executable" },
+            info.size_of_image, info.entry_point_rva);
+        outln!("  {} sections, {} imported DLL(s) / {} function(s), {} export(s), {} relocation(s) in {} block(s)",
+            report.sections, report.import_dlls, report.import_functions,
+            report.exports, report.relocations, report.relocation_blocks);
+        outln!("");
+    }
+
+    let checks = [
+        PeCheck::Headers, PeCheck::Alignment, PeCheck::Sections, PeCheck::Imports,
+        PeCheck::Exports, PeCheck::Relocations, PeCheck::EntryPoint,
+    ];
+    for check in checks {
+        let mut issues = report.issues.iter().filter(|i| i.check == check).peekable();
+        if issues.peek().is_none() {
+            if report.info.is_some() {
+                outln!("  [ok]   {}", check.name());
+            }
+            continue;
+        }
+        for issue in issues {
+            let tag = if issue.severity == PeIssueSeverity::Error { "[ERR] " } else { "[warn]" };
+            outln!("  {} {}: {}", tag, check.name(), issue.message);
+        }
+    }
+    if report.suppressed > 0 {
+        outln!("  ... {} more issue(s) not shown", report.suppressed);
+    }
+
+    outln!("");
+    outln!("{} error(s), {} warning(s) - image {} loadable",
+        report.errors(), report.warnings(), if report.is_loadable() { "is" } else { "is NOT" });
+}
+
+/// Read a whole file into memory, refusing files larger than `limit`
+fn read_whole_file(path: &str, limit: usize) -> Result<alloc::vec::Vec<u8>, fs::FsStatus> {
+    let handle = fs::open(path, 0)?;
+    let size = match fs::fstat(handle) {
+        Ok(info) => info.size as usize,
+        Err(e) => {
+            let _ = fs::close(handle);
+            return Err(e);
+        }
+    };
+    if size > limit {
+        let _ = fs::close(handle);
+        return Err(fs::FsStatus::InvalidParameter);
+    }
+
+    let mut data = alloc::vec![0u8; size];
+    let mut filled = 0;
+    while filled < size {
+        match fs::read(handle, &mut data[filled..]) {
+            Ok(0) | Err(fs::FsStatus::EndOfFile) => break,
+            Ok(n) => filled += n,
+            Err(e) => {
+                let _ = fs::close(handle);
+                return Err(e);
+            }
+        }
+    }
+    let _ = fs::close(handle);
+    data.truncate(filled);
+    Ok(data)
+}
+
 /// Parse a hex address from string
 fn parse_hex_address(s: &str) -> u64 {
     let s = s.trim();
@@ -3060,6 +3717,10 @@ pub fn cmd_ldr(args: &[&str]) {
         outln!("  load <addr>        Load PE executable at address");
         outln!("  dll <pid> <addr>   Load DLL into process");
         outln!("  parse <addr>       Parse PE at address (don't load)");
+        outln!("  driver <addr> [n]  Load kernel-mode driver at address");
+        outln!("  drivers            List loaded PE drivers");
+        outln!("  unload <name>      Unload a PE driver");
+        outln!("  abi                Show driver ABI version and exports");
         return;
     }
 
@@ -3200,7 +3861,7 @@ pub fn cmd_ldr(args: &[&str]) {
                     outln!("  Entry:   {:#x}", info.entry_point_rva);
                     outln!("");
 
-                    match ldr::load_dll(process, base, info.size_of_image as usize, name) {
+                    match ldr::load_dll(process, base, ldr::verify::pe_file_extent(base).unwrap_or(0), name) {
                         Ok(loaded) => {
                             outln!("DLL loaded successfully!");
                             outln!("  Base:    {:#x}", loaded.base);
@@ -3217,6 +3878,70 @@ pub fn cmd_ldr(args: &[&str]) {
                 }
             }
         }
+    } else if eq_ignore_case(cmd, "driver") {
+        if args.len() < 2 {
+            outln!("Usage: ldr driver <address> [name]");
+            outln!("");
+            outln!("Loads a kernel-mode driver (.sys) from the given memory address");
+            outln!("and calls its DriverEntry.");
+            return;
+        }
+
+        let addr = parse_hex_address(args[1]);
+        if addr == 0 {
+            outln!("Invalid address: {}", args[1]);
+            return;
+        }
+        let name = if args.len() > 2 { args[2].as_bytes() } else { b"driver.sys" };
+
+        match unsafe { ldr::driver::load_driver(addr as *const u8, name) } {
+            Ok(driver) => {
+                outln!("Driver loaded (DriverObject {:p})", driver);
+            }
+            Err(e) => {
+                outln!("Load failed: {:?} (status {:#010x})", e, e.status() as u32);
+            }
+        }
+    } else if eq_ignore_case(cmd, "drivers") {
+        let (drivers, count) = ldr::driver::get_loaded_drivers();
+        if count == 0 {
+            outln!("No PE drivers loaded");
+            return;
+        }
+
+        outln!("{:<20} {:<18} {:<10} ABI", "Name", "Base", "Size");
+        outln!("------------------------------------------------------------");
+        for drv in drivers.iter().flatten() {
+            outln!("{:<20} {:#016x} {:#010x} {}",
+                drv.name_str(), drv.image.base, drv.image.size, drv.abi);
+        }
+        outln!("");
+        outln!("Total: {} driver(s)", count);
+    } else if eq_ignore_case(cmd, "unload") {
+        if args.len() < 2 {
+            outln!("Usage: ldr unload <name>");
+            return;
+        }
+
+        if unsafe { ldr::driver::unload_driver(args[1]) } {
+            outln!("Driver '{}' unloaded", args[1]);
+        } else {
+            outln!("Driver '{}' not loaded", args[1]);
+        }
+    } else if eq_ignore_case(cmd, "abi") {
+        outln!("Driver ABI:");
+        outln!("  Kernel version:   {}", ldr::driver::KERNEL_ABI_VERSION);
+        outln!("  Legacy default:   {}", ldr::driver::LEGACY_ABI_VERSION);
+        outln!("  Kernel exports:   {}", ldr::get_kernel_export_count());
+        outln!("  Rejected imports: {}", ldr::driver::get_abi_reject_count());
+        outln!("");
+        outln!("Exports added after 1.0:");
+        for (name, since) in ldr::driver::EXPORT_VERSIONS {
+            outln!("  {:<40} since {}", name, since);
+        }
+        outln!("  {:<40} since {}",
+            alloc::format!("C runtime ({} routines)", crate::rtl::kmcrt::KMCRT_EXPORTS.len()),
+            ldr::driver::KMCRT_ABI_VERSION);
     } else if eq_ignore_case(cmd, "load") {
         if args.len() < 2 {
             outln!("Usage: ldr load <address>");
@@ -3247,7 +3972,7 @@ pub fn cmd_ldr(args: &[&str]) {
                     outln!("");
 
                     // Try to load it
-                    match ldr::load_executable(base, info.size_of_image as usize, b"loaded.exe") {
+                    match ldr::load_executable(base, ldr::verify::pe_file_extent(base).unwrap_or(0), b"loaded.exe") {
                         Ok(result) => {
                             outln!("Loaded successfully!");
                             outln!("  Process: PID {}", (*result.process).process_id());
@@ -3895,6 +4620,7 @@ pub fn cmd_ob(args: &[&str]) {
         outln!("  info               Show OB statistics");
         outln!("  types              List registered object types");
         outln!("  dir [path]         List directory contents");
+        outln!("  list <directory>   List symbolic links in a directory");
         outln!("  handles            Show system handle table");
         outln!("  symlink            Symbolic link operations:");
         outln!("    symlink list     List all symbolic links");
@@ -3958,9 +4684,28 @@ pub fn cmd_ob(args: &[&str]) {
             } else if path == "\\BaseNamedObjects" || path == "/BaseNamedObjects" {
                 ob::get_base_named_objects()
             } else {
-                outln!("Unknown directory: {}", path);
-                outln!("Known directories: \\, \\ObjectTypes, \\Device, \\BaseNamedObjects");
-                return;
+                // Walk the namespace for anything else (e.g. \Sessions\1)
+                let mut full = [0u8; 128];
+                let mut len = 0;
+                if !path.starts_with('\\') && !path.starts_with('/') {
+                    full[0] = b'\\';
+                    len = 1;
+                }
+                for &c in path.as_bytes().iter().take(full.len() - len) {
+                    full[len] = if c == b'/' { b'\\' } else { c };
+                    len += 1;
+                }
+
+                let object = ob::ob_lookup_object(&full[..len]);
+                let is_directory = !object.is_null()
+                    && (*ob::ObjectHeader::from_body(object)).get_type()
+                        .is_some_and(|t| t.type_index == ob::type_index::TYPE_DIRECTORY);
+                if !is_directory {
+                    outln!("Unknown directory: {}", path);
+                    outln!("Known directories: \\, \\ObjectTypes, \\Device, \\BaseNamedObjects, \\Sessions");
+                    return;
+                }
+                ob::ObjectDirectory::from_object(object)
             };
 
             if dir.is_null() {
@@ -4005,6 +4750,29 @@ pub fn cmd_ob(args: &[&str]) {
                 outln!("Total: {} entries", count);
             }
         }
+    } else if eq_ignore_case(cmd, "list") {
+        if args.len() < 2 {
+            outln!("Usage: ob list <directory>");
+            outln!("Example: ob list \\GLOBAL??");
+            return;
+        }
+        let directory = args[1];
+        let links = ob::ob_list_directory_links(directory);
+
+        outln!("Directory: {}", directory);
+        outln!("");
+        outln!("{:<24} {:<14} {}", "Name", "Type", "Target");
+        outln!("------------------------------------------------------------");
+        for (name, target) in links.iter() {
+            outln!("{:<24} {:<14} {}", name, "SymbolicLink", target);
+        }
+
+        if links.is_empty() {
+            outln!("  (No symbolic links)");
+        } else {
+            outln!("");
+            outln!("Total: {} symbolic links", links.len());
+        }
     } else if eq_ignore_case(cmd, "handles") {
         outln!("System Handle Table");
         outln!("");
@@ -4129,6 +4897,7 @@ pub fn cmd_ex(args: &[&str]) {
         outln!("Commands:");
         outln!("  info               Show executive information");
         outln!("  worker             Show work queue status");
+        outln!("  tasks              Show green tasks");
         outln!("  callback           Show registered callbacks");
         outln!("  luid               Show LUID allocator status");
         outln!("  lookaside          Show lookaside list info");
@@ -4146,6 +4915,7 @@ pub fn cmd_ex(args: &[&str]) {
         outln!("  Fast Mutexes:    Efficient kernel mutexes");
         outln!("  Lookaside Lists: Fixed-size allocators");
         outln!("  Worker Threads:  Deferred work execution");
+        outln!("  Green Tasks:     Cooperative driver tasks");
         outln!("  Callbacks:       Notification callbacks");
         outln!("  Rundown:         Safe resource cleanup");
         outln!("  Keyed Events:    Synchronization primitives");
@@ -4168,6 +4938,37 @@ pub fn cmd_ex(args: &[&str]) {
         outln!("{:<25} {:<10}", "HyperCriticalWorkQueue", hyper);
         outln!("");
         outln!("Total pending: {}", critical + delayed + hyper);
+    } else if eq_ignore_case(cmd, "tasks") {
+        let stats = ex::ex_get_task_stats();
+        outln!("Green Tasks");
+        outln!("");
+        outln!("Executor threads: {}", stats.threads);
+        outln!("Live:             {} ({} ready)", stats.live, stats.ready);
+        outln!("Spawned:          {}", stats.spawned);
+        outln!("Completed:        {}", stats.completed);
+        outln!("Cancelled:        {}", stats.cancelled);
+        outln!("Polls:            {}", stats.polls);
+
+        let tasks = ex::ex_get_task_snapshots();
+        if !tasks.is_empty() {
+            outln!("");
+            outln!("{:<10} {:<20} {:<8} {:>8} {:>10} {:>10}", "Id", "Name", "State", "Polls", "Age(ms)", "Wake(ms)");
+            outln!("----------------------------------------------------------------------");
+            for task in tasks.iter() {
+                let state = match task.state {
+                    ex::TaskState::Ready => "Ready",
+                    ex::TaskState::Running => "Running",
+                    ex::TaskState::Waiting => "Waiting",
+                    ex::TaskState::Free => "Free",
+                };
+                let wake = match task.wake_in_ms {
+                    Some(ms) => alloc::format!("{}", ms),
+                    None => alloc::string::String::from("-"),
+                };
+                outln!("{:#010x} {:<20} {:<8} {:>8} {:>10} {:>10}",
+                    task.id.as_u32(), task.name, state, task.polls, task.age_ms, wake);
+            }
+        }
     } else if eq_ignore_case(cmd, "callback") {
         outln!("Executive Callback Objects");
         outln!("");
@@ -4340,6 +5141,8 @@ pub fn cmd_ke(args: &[&str]) {
         outln!("  timer              Show timer information");
         outln!("  prcb               Show processor control block");
         outln!("  balance            Balance set manager status");
+        outln!("  deadlock [scan|policy off|log|bugcheck]  Deadlock detector");
+        outln!("  waits [tid]        Blocked threads and their wait chains");
         return;
     }
 
@@ -4478,11 +5281,141 @@ pub fn cmd_ke(args: &[&str]) {
         outln!("  Periodic Interval:     {} (100ns units)", ke::PERIODIC_INTERVAL);
         outln!("  Ready Without Running: {} ticks", ke::READY_WITHOUT_RUNNING);
         outln!("  Stack Scan Period:     {}", ke::STACK_SCAN_PERIOD);
+    } else if eq_ignore_case(cmd, "deadlock") {
+        use crate::ke::deadlock::{self, DeadlockPolicy};
+
+        let sub = args.get(1).copied().unwrap_or("");
+        if eq_ignore_case(sub, "policy") {
+            let policy = match args.get(2).copied().unwrap_or("") {
+                p if eq_ignore_case(p, "off") => DeadlockPolicy::Disabled,
+                p if eq_ignore_case(p, "log") => DeadlockPolicy::Log,
+                p if eq_ignore_case(p, "bugcheck") => DeadlockPolicy::Bugcheck,
+                _ => {
+                    outln!("Usage: ke deadlock policy off|log|bugcheck");
+                    return;
+                }
+            };
+            deadlock::ke_set_deadlock_policy(policy);
+            outln!("Deadlock policy: {:?}", policy);
+        } else if eq_ignore_case(sub, "scan") {
+            // A cycle must be seen on two passes before it is reported
+            let found = deadlock::ke_scan_for_deadlocks().or_else(deadlock::ke_scan_for_deadlocks);
+            match found {
+                Some(report) => {
+                    outln!("Deadlock: {} threads in a cycle, {} blocked behind it",
+                        report.cycle_length, report.blocked_behind);
+                    for t in report.members() {
+                        for o in t.recorded().iter().filter(|o| o.owner != 0 && o.owner != t.thread) {
+                            outln!("  Thread {} waits on {} @{:#x} owned by thread {}",
+                                t.thread_id, deadlock::dispatcher_type_name(o.object_type), o.object, o.owner_id);
+                        }
+                    }
+                }
+                None => outln!("No new deadlocks found"),
+            }
+        } else {
+            let stats = deadlock::ke_get_deadlock_stats();
+            outln!("Deadlock Detector");
+            outln!("");
+            outln!("  Monitor:        {} ({} ms interval)",
+                if stats.monitor_running { "running" } else { "stopped" }, deadlock::DEADLOCK_SCAN_INTERVAL_MS);
+            outln!("  Policy:         {:?}", stats.policy);
+            outln!("  Scans:          {}", stats.scans);
+            outln!("  Deadlocks:      {}", stats.deadlocks);
+            if stats.last_deadlock_tick != 0 {
+                outln!("  Last detected:  {} ms ago",
+                    crate::hal::apic::get_tick_count().saturating_sub(stats.last_deadlock_tick));
+            }
+        }
+    } else if eq_ignore_case(cmd, "waits") {
+        show_wait_chains(args.get(1).and_then(|v| v.parse::<u32>().ok()));
     } else {
         outln!("Unknown ke command: {}", cmd);
     }
 }
 
+/// Show blocked threads, what they wait on, and the owners they wait behind
+fn show_wait_chains(only_tid: Option<u32>) {
+    use crate::ke::deadlock::{self, BlockedThread, MAX_SCANNED_THREADS};
+    use crate::ke::WaitType;
+
+    let mut threads = alloc::vec![BlockedThread::empty(); MAX_SCANNED_THREADS];
+    let count = deadlock::ke_snapshot_blocked_threads(&mut threads);
+    threads.truncate(count);
+    let now = crate::hal::apic::get_tick_count();
+
+    outln!("Blocked Threads");
+    outln!("");
+    if count == 0 {
+        outln!("No threads are blocked in a dispatcher wait");
+        return;
+    }
+
+    let mut shown = 0;
+    for t in threads.iter().filter(|t| only_tid.is_none_or(|tid| t.thread_id == tid)) {
+        shown += 1;
+        outln!("Thread {}  {} {} for {} ms{}{}",
+            t.thread_id,
+            wait_reason_name(t.wait_reason),
+            if t.wait_type == WaitType::WaitAll { "WaitAll" } else { "WaitAny" },
+            now.saturating_sub(t.wait_start_tick),
+            if t.timed { ", with timeout" } else { "" },
+            if t.alertable { ", alertable" } else { "" });
+
+        for o in t.recorded() {
+            let kind = deadlock::dispatcher_type_name(o.object_type);
+            let name = deadlock::ke_object_name(o.object).unwrap_or("");
+            if o.owner != 0 {
+                outln!("    {:<10} {:<16} @{:#x}  owner thread {}", kind, name, o.object, o.owner_id);
+            } else {
+                outln!("    {:<10} {:<16} @{:#x}  signal state {}", kind, name, o.object, o.signal_state);
+            }
+        }
+        if t.object_count > t.recorded().len() {
+            outln!("    ... {} more objects", t.object_count - t.recorded().len());
+        }
+
+        // Follow mutex owners while they are blocked too
+        let mut chain = [0u32; MAX_SCANNED_THREADS];
+        let mut length = 0;
+        let mut current = t;
+        let end = loop {
+            chain[length] = current.thread_id;
+            length += 1;
+            let next = current.recorded().iter().find(|o| o.owner != 0 && o.owner != current.thread);
+            let Some(object) = next else { break "" };
+            match threads.iter().find(|b| b.thread == object.owner) {
+                Some(owner) if chain[..length].contains(&owner.thread_id) => {
+                    chain[length] = owner.thread_id;
+                    length += 1;
+                    break "  ** cycle: deadlock **";
+                }
+                Some(owner) if length < MAX_SCANNED_THREADS - 1 => current = owner,
+                Some(_) => break "  ...",
+                None => {
+                    chain[length] = object.owner_id;
+                    length += 1;
+                    break "  (owner not waiting)";
+                }
+            }
+        };
+        if length > 1 {
+            out!("  Chain:");
+            for (i, tid) in chain[..length].iter().enumerate() {
+                out!("{}{}", if i == 0 { " " } else { " -> " }, tid);
+            }
+            outln!("{}", end);
+        }
+        outln!("");
+    }
+
+    if shown == 0 {
+        outln!("Thread {} is not blocked in a dispatcher wait", only_tid.unwrap_or(0));
+    } else {
+        outln!("{} blocked thread(s)", shown);
+    }
+}
+
 // ============================================================================
 // Memory Manager (MM) Command
 // ============================================================================
@@ -4500,10 +5433,19 @@ pub fn cmd_mm(args: &[&str]) {
         outln!("  info               Show memory manager information");
         outln!("  stats              Show memory statistics");
         outln!("  pool               Show pool allocator status");
+        outln!("  frag               Show pool/heap fragmentation and alerts");
+        outln!("  frag threshold <pool%> <heap%>  Set fragmentation alert thresholds");
+        outln!("  compact            Compact movable heap structures");
         outln!("  physical           Show physical memory info");
         outln!("  vad                Show VAD statistics");
         outln!("  section            Show section statistics");
         outln!("  ws                 Show working set statistics");
+        outln!("  colors             Show free pages per cache color");
+        outln!("  colortest [pages]  Compare same-color vs colored page sweeps");
+        outln!("  contig             List contiguous memory allocations");
+        outln!("  pin [list]         List pinned hot paths and images");
+        outln!("  pin add <name> <address> <size>  Pin a range");
+        outln!("  pin remove <name>  Remove a pin");
         return;
     }
 
@@ -4557,6 +5499,43 @@ pub fn cmd_mm(args: &[&str]) {
         outln!("  Bytes free:        {} bytes", pool_stats.bytes_free);
         outln!("  Allocation count:  {}", pool_stats.allocation_count);
         outln!("  Free count:        {}", pool_stats.free_count);
+    } else if eq_ignore_case(cmd, "frag") {
+        if args.len() > 1 && eq_ignore_case(args[1], "threshold") {
+            let pool = args.get(2).and_then(|v| v.trim_end_matches('%').parse::<u32>().ok());
+            let heap = args.get(3).and_then(|v| v.trim_end_matches('%').parse::<u32>().ok());
+            match (pool, heap) {
+                (Some(pool), Some(heap)) if mm::defrag::mm_set_fragmentation_thresholds(pool, heap) => {
+                    outln!("Alert thresholds: pool {}%, heap {}%", pool, heap);
+                }
+                _ => outln!("Usage: mm frag threshold <pool%> <heap%>  (1-100)"),
+            }
+            return;
+        }
+
+        let frag = mm::mm_get_fragmentation();
+        let (pool_threshold, heap_threshold) = mm::defrag::mm_fragmentation_thresholds();
+        let status = mm::defrag::mm_get_defrag_status();
+        outln!("Allocator Fragmentation");
+        outln!("");
+        outln!("Executive Pool (size-class rounding):");
+        outln!("  Fragmentation:     {}% (alert at {}%){}", frag.pool_fragmentation, pool_threshold,
+            if status.pool_alert { "  ** ALERT **" } else { "" });
+        outln!("  Largest free:      {} bytes", frag.pool_largest_free);
+        outln!("");
+        outln!("Kernel Heap (free space outside the largest block):");
+        outln!("  Fragmentation:     {}% (alert at {}%){}", frag.heap_fragmentation, heap_threshold,
+            if status.heap_alert { "  ** ALERT **" } else { "" });
+        outln!("  Largest free:      {} bytes", frag.heap_largest_free);
+        outln!("  Free blocks:       {}", frag.heap_free_blocks);
+        outln!("");
+        outln!("Monitor:             {} ({} samples, {} alerts)",
+            if status.running { "running" } else { "stopped" }, status.samples, status.alerts);
+        outln!("Compactions:         {} ({} bytes released)", status.compactions, status.bytes_released);
+    } else if eq_ignore_case(cmd, "compact") {
+        let result = mm::mm_compact_heap();
+        outln!("Compacted {} movable structures, released {} bytes", result.structures, result.bytes_released);
+        outln!("  Heap fragmentation: {}% -> {}%", result.fragmentation_before, result.fragmentation_after);
+        outln!("  Largest free block: {} -> {} bytes", result.largest_free_before, result.largest_free_after);
     } else if eq_ignore_case(cmd, "physical") {
         outln!("Physical Memory Information");
         outln!("");
@@ -4615,16 +5594,177 @@ pub fn cmd_mm(args: &[&str]) {
         outln!("Trimming:");
         outln!("  Trim operations:              {}", ws_stats.trim_count);
         outln!("  Total pages trimmed:          {}", ws_stats.total_pages_trimmed);
+        outln!("  Pinned pages skipped:         {}", mm::pin::mm_pinned_trim_skips());
         outln!("");
         outln!("Constants:");
         outln!("  Max entries/process:          {}", mm::MAX_WSLE_PER_PROCESS);
         outln!("  Default minimum WS:           {} pages", mm::DEFAULT_MINIMUM_WORKING_SET_SIZE);
         outln!("  Default maximum WS:           {} pages", mm::DEFAULT_MAXIMUM_WORKING_SET_SIZE);
+    } else if eq_ignore_case(cmd, "colors") {
+        let stats = mm::mm_get_page_color_stats();
+        outln!("Page Color Statistics");
+        outln!("");
+        outln!("Colors in use:     {}", stats.colors);
+        outln!("Color hits:        {}", stats.hits);
+        outln!("Color fallbacks:   {}", stats.misses);
+        outln!("");
+        outln!("Free pages by color:");
+        for row in (0..stats.colors).step_by(8) {
+            out!(" {:>3}:", row);
+            for color in row..(row + 8).min(stats.colors) {
+                out!(" {:>6}", stats.pages_by_color[color]);
+            }
+            outln!("");
+        }
+    } else if eq_ignore_case(cmd, "contig") {
+        outln!("Contiguous Memory Allocations");
+        outln!("");
+        outln!("{:<18} {:>8}", "Base", "Pages");
+        outln!("---------------------------");
+        let mut count = 0;
+        for alloc in mm::mm_get_contiguous_allocations().iter().flatten() {
+            outln!("{:#018x} {:>8}", alloc.base, alloc.page_count);
+            count += 1;
+        }
+        outln!("");
+        outln!("{} of {} slots in use", count, mm::MAX_CONTIGUOUS_ALLOCATIONS);
+    } else if eq_ignore_case(cmd, "colortest") {
+        mm_color_test(args.get(1).and_then(|s| s.parse::<usize>().ok()).unwrap_or(32));
+    } else if eq_ignore_case(cmd, "pin") {
+        mm_pin_command(&args[1..]);
     } else {
         outln!("Unknown mm command: {}", cmd);
     }
 }
 
+/// `mm pin` subcommands
+fn mm_pin_command(args: &[&str]) {
+    use crate::mm::pin;
+
+    let sub = args.first().copied().unwrap_or("list");
+    if eq_ignore_case(sub, "list") {
+        let (ranges, count) = pin::mm_pinned_ranges();
+        outln!("Pinned Memory");
+        outln!("");
+        outln!("{:<24} {:>6} {:<18} {:>8} {:<8}", "Name", "PID", "Start", "Pages", "Source");
+        outln!("------------------------------------------------------------------");
+        let mut total = 0;
+        for range in &ranges[..count] {
+            outln!("{:<24} {:>6} {:#018x} {:>8} {:<8}",
+                range.name(), range.process_id, range.start, range.pages(), range.source.name());
+            total += range.pages();
+        }
+        outln!("");
+        outln!("{} of {} pins, {} pages ({} KB)", count, pin::MAX_PINNED_RANGES, total, total * 4);
+        outln!("Trims skipped:  {} pages", pin::mm_pinned_trim_skips());
+        outln!("");
+        match unsafe { crate::cm::cm_read_string(pin::PIN_KEY, "PinnedImages") } {
+            Some(images) => outln!("PinnedImages:   {}", images),
+            None => outln!("PinnedImages:   (not set)"),
+        }
+    } else if eq_ignore_case(sub, "add") {
+        if args.len() < 4 {
+            outln!("Usage: mm pin add <name> <address> <size>");
+            return;
+        }
+        let address = parse_hex_address(args[2]);
+        let size = parse_hex_or_dec(args[3]).unwrap_or(0) as usize;
+        if address == 0 || size == 0 {
+            outln!("Invalid address or size");
+            return;
+        }
+        match pin::mm_pin_range(args[1], 0, address, size, pin::PinSource::Manual) {
+            Ok(()) => outln!("Pinned {}", args[1]),
+            Err(e) => outln!("Cannot pin {}: {}", args[1], e),
+        }
+    } else if eq_ignore_case(sub, "remove") {
+        if args.len() < 2 {
+            outln!("Usage: mm pin remove <name>");
+            return;
+        }
+        match pin::mm_unpin(args[1]) {
+            Ok(()) => outln!("Unpinned {}", args[1]),
+            Err(e) => outln!("Cannot unpin {}: {}", args[1], e),
+        }
+    } else {
+        outln!("Usage: mm pin [list | add <name> <address> <size> | remove <name>]");
+    }
+}
+
+/// Measure cache behaviour of same-color vs round-robin colored pages
+///
+/// Allocates two buffers of `pages` physical pages: one where every page
+/// has the same color, one with consecutive colors, then times repeated
+/// sweeps over each. With coloring the second sweep should stay in cache.
+fn mm_color_test(pages: usize) {
+    use crate::mm;
+
+    const MAX_TEST_PAGES: usize = 256;
+    const PASSES: usize = 16;
+
+    let pages = pages.clamp(1, MAX_TEST_PAGES);
+    let line = crate::hal::cache::cache_get_line_size().max(1);
+    let colors = mm::mm_get_page_colors();
+
+    let mut same = [0usize; MAX_TEST_PAGES];
+    let mut spread = [0usize; MAX_TEST_PAGES];
+    let mut same_count = 0;
+    let mut spread_count = 0;
+
+    unsafe {
+        for i in 0..pages {
+            match mm::mm_allocate_page_by_color(0) {
+                Some(pfn) => { same[i] = pfn; same_count += 1; }
+                None => break,
+            }
+            match mm::mm_allocate_page_by_color(i) {
+                Some(pfn) => { spread[i] = pfn; spread_count += 1; }
+                None => break,
+            }
+        }
+    }
+
+    let sweep = |set: &[usize]| -> u64 {
+        let start = crate::hal::timer::read_tsc();
+        for _ in 0..PASSES {
+            for &pfn in set {
+                let base = pfn * mm::PAGE_SIZE;
+                let mut off = 0;
+                while off < mm::PAGE_SIZE {
+                    unsafe { core::ptr::read_volatile((base + off) as *const u8); }
+                    off += line;
+                }
+            }
+        }
+        crate::hal::timer::read_tsc() - start
+    };
+
+    let n = same_count.min(spread_count);
+    if n > 0 {
+        // Warm up both sets once so the first sweep isn't penalized
+        sweep(&same[..n]);
+        sweep(&spread[..n]);
+        let same_cycles = sweep(&same[..n]);
+        let spread_cycles = sweep(&spread[..n]);
+
+        outln!("Page Color Test ({} pages, {} colors, {} passes)", n, colors, PASSES);
+        outln!("");
+        outln!("  Same color:      {} cycles", same_cycles);
+        outln!("  Colored:         {} cycles", spread_cycles);
+        if let Some(ratio) = (same_cycles * 100).checked_div(spread_cycles) {
+            outln!("  Ratio:           {}.{:02}x", ratio / 100, ratio % 100);
+        }
+    } else {
+        outln!("Page color test: out of physical pages");
+    }
+
+    unsafe {
+        for &pfn in same[..same_count].iter().chain(spread[..spread_count].iter()) {
+            mm::mm_free_page(pfn);
+        }
+    }
+}
+
 // ============================================================================
 // I/O Manager (IO) Command
 // ============================================================================
@@ -4787,6 +5927,8 @@ pub fn cmd_hal(args: &[&str]) {
         outln!("  time               Show RTC date/time");
         outln!("  apic               Show APIC status");
         outln!("  tick               Show system tick count");
+        outln!("  iommu              Show DMA remapping units and domains");
+        outln!("  display [n]        Show displays and modes passed by the bootloader");
         return;
     }
 
@@ -4855,6 +5997,72 @@ pub fn cmd_hal(args: &[&str]) {
             let ticks_per_sec = ticks / uptime;
             outln!("Ticks/second:   ~{}", ticks_per_sec);
         }
+    } else if eq_ignore_case(cmd, "iommu") {
+        outln!("I/O Memory Management Unit");
+        outln!("");
+
+        let stats = hal::iommu_get_stats();
+        if !hal::iommu_is_enabled() {
+            outln!("DMA remapping not enabled ({} unit(s) found)", stats.unit_count);
+            return;
+        }
+
+        outln!("Mode:           {:?}", stats.mode);
+        outln!("RMRR regions:   {}", stats.rmrr_count);
+        outln!("Map calls:      {}", stats.map_calls);
+        outln!("Unmap calls:    {}", stats.unmap_calls);
+        outln!("Faults:         {}", hal::iommu_poll_faults() + stats.faults);
+        outln!("");
+
+        let (units, count) = hal::iommu_get_units();
+        outln!("Units:");
+        for unit in units.iter().take(count) {
+            outln!("  {:#010x}  seg {}  ver {}.{}  {}{}",
+                unit.register_base, unit.segment,
+                (unit.version >> 4) & 0xF, unit.version & 0xF,
+                if unit.enabled { "enabled" } else { "disabled" },
+                if unit.include_all { "  (all devices)" } else { "" });
+        }
+        outln!("");
+
+        outln!("Domains:");
+        for domain in hal::iommu_get_domains().iter().flatten() {
+            match domain.device {
+                Some(loc) => outln!("  {:>3}  {:02x}:{:02x}.{}  {} page(s)",
+                    domain.id, loc.bus, loc.device, loc.function, domain.mapped_pages),
+                None => outln!("  {:>3}  default", domain.id),
+            }
+        }
+    } else if eq_ignore_case(cmd, "display") {
+        let displays = hal::display_boot_displays();
+        if displays.is_empty() {
+            outln!("No GOP displays passed by the bootloader (headless boot)");
+            return;
+        }
+        let only = args.get(1).and_then(|s| parse_number(s));
+
+        outln!("Boot Displays ({})", displays.len());
+        if hal::display_boot_flags() & hal::BOOT_DISPLAY_PREFERRED_MODE != 0 {
+            outln!("Primary mode set from boot.cfg");
+        }
+        for (n, display) in displays.iter().enumerate() {
+            if only.is_some_and(|i| i != n) {
+                continue;
+            }
+            outln!("");
+            outln!("Display {}{}:", n, if n == 0 { " (primary)" } else { "" });
+            outln!("  Framebuffer:  {:#x} ({} KB)",
+                display.framebuffer_addr, display.framebuffer_size / 1024);
+            outln!("  Active mode:  {}x{} {} (stride {} px)",
+                display.active.width, display.active.height,
+                display.active.pixel_format_name(), display.active.pixels_per_scanline);
+            outln!("  Modes:");
+            for (i, mode) in display.modes().iter().enumerate() {
+                outln!("  {} {:>3}  {:>5}x{:<5} {:<8} stride {}",
+                    if i as u32 == display.current_mode { "*" } else { " " },
+                    i, mode.width, mode.height, mode.pixel_format_name(), mode.pixels_per_scanline);
+            }
+        }
     } else {
         outln!("Unknown hal command: {}", cmd);
     }
@@ -6168,6 +7376,8 @@ pub fn cmd_timer(args: &[&str]) {
         outln!("Current tick count: {}", apic::get_tick_count());
     } else if eq_ignore_case(args[0], "pit") {
         show_pit_status();
+    } else if eq_ignore_case(args[0], "hv") {
+        show_hypervisor_status();
     } else if eq_ignore_case(args[0], "help") {
         outln!("Timer Diagnostics");
         outln!("");
@@ -6180,6 +7390,7 @@ pub fn cmd_timer(args: &[&str]) {
         outln!("  active     Show active kernel timers");
         outln!("  tick       Show current tick count");
         outln!("  pit        Show PIT (8254) status");
+        outln!("  hv         Show hypervisor paravirtual clock and IPI status");
         outln!("  help       Show this help");
     } else {
         outln!("Unknown timer command: {}", args[0]);
@@ -6236,6 +7447,36 @@ fn show_timer_status() {
     outln!("APIC ID:         {}", lapic.id());
 }
 
+/// Show hypervisor enlightenments
+fn show_hypervisor_status() {
+    use crate::hal::{hv, timer};
+
+    let status = hv::hv_get_status();
+    outln!("Hypervisor Enlightenments");
+    outln!("");
+    outln!("Hypervisor:      {}", status.vendor.name());
+    if status.vendor == hv::HypervisorVendor::None {
+        outln!("Running on bare metal; using TSC and local APIC directly");
+        return;
+    }
+    outln!("Feature Bits:    {:#010x}", status.features);
+    outln!("Clock Source:    {}", status.clock_source.name());
+    if let Some(counter) = hv::hv_read_clock() {
+        outln!("Clock Value:     {} ({} Hz)", counter, status.clock_source.frequency());
+    }
+    if status.tsc_frequency != 0 {
+        outln!("TSC Frequency:   {} Hz (from hypervisor)", status.tsc_frequency);
+    }
+    outln!("Perf Counter:    {:?}, {} Hz", timer::hal_get_calibration().timer_source,
+        timer::hal_query_performance_frequency());
+    outln!("PV IPI:          {}", if status.pv_ipi { "enabled" } else { "not available" });
+    if status.pv_ipi {
+        outln!("  Sent:          {}", status.pv_ipis_sent);
+        outln!("  Failed:        {}", status.pv_ipi_failures);
+    }
+    outln!("SynIC:           {}", if status.synic_available { "available" } else { "not available" });
+}
+
 /// Show APIC timer details
 fn show_apic_timer_status() {
     use crate::hal::apic;
@@ -9758,8 +10999,13 @@ struct SmbiosHeader {
 
 /// Find SMBIOS entry point
 fn find_smbios_entry() -> Option<(u64, u8, u8, u32, u16)> {
+    // Prefer the tables found at boot (EFI configuration table)
+    if let Some(info) = crate::hal::smbios::smbios_info() {
+        let e = info.entry;
+        return Some((e.table_addr, e.major, e.minor, e.table_len, e.structure_count));
+    }
+
     // Scan for SMBIOS entry in F0000-FFFFF range
-    // In UEFI systems, may need to use EFI System Table instead
     unsafe {
         // First try SMBIOS 3.0 (_SM3_)
         let mut addr = 0xF0000u64;
@@ -12347,7 +13593,8 @@ fn show_apc_stats() {
         outln!("");
         outln!("Thread Flags:");
         outln!("  Alertable:              {}", if thread.alertable { "Yes" } else { "No" });
-        outln!("  Special APC Disable:    {}", thread.special_apc_disable);
+        outln!("  Critical Region Depth:  {}", -(thread.kernel_apc_disable as i32));
+        outln!("  Guarded Region Depth:   {}", -(thread.special_apc_disable as i32));
     }
 }
 
@@ -12367,6 +13614,7 @@ fn show_apc_pending() {
         outln!("  - Returning from kernel to user mode");
         outln!("  - Thread enters alertable wait state");
         outln!("  - IRQL drops to PASSIVE_LEVEL");
+        outln!("  - Thread leaves its critical/guarded region");
     } else {
         outln!("No APCs pending");
     }
@@ -12388,6 +13636,8 @@ pub fn cmd_sched(args: &[&str]) {
         outln!("  sched stats        Show scheduler statistics");
         outln!("  sched ready        Show ready queue summary");
         outln!("  sched current      Show current thread info");
+        outln!("  sched policy <image> [cpurate <pct> | affinity <mask> | iopriority <0-2>]");
+        outln!("                     Show or set an image's PerfOptions");
         outln!("  sched help         Show this help");
         return;
     }
@@ -12398,6 +13648,8 @@ pub fn cmd_sched(args: &[&str]) {
         show_sched_ready();
     } else if eq_ignore_case(subcmd, "current") {
         show_sched_current();
+    } else if eq_ignore_case(subcmd, "policy") {
+        sched_policy(&args[1..]);
     } else {
         outln!("Unknown sched command: {}", subcmd);
         outln!("Use 'sched help' for usage");
@@ -12525,6 +13777,66 @@ fn show_sched_current() {
     }
 }
 
+fn sched_policy(args: &[&str]) {
+    use crate::ps::perfopts::{perf_value, perf_options_key};
+    use crate::ps::{query_image_policy, set_image_policy_value};
+
+    if args.is_empty() {
+        outln!("Usage: sched policy <image> [cpurate <pct> | affinity <mask> | iopriority <0-2>]");
+        return;
+    }
+    let image = args[0];
+
+    if args.len() >= 3 {
+        let (name, value) = if eq_ignore_case(args[1], "cpurate") {
+            (perf_value::CPU_RATE_LIMIT, args[2].parse::<u32>().ok().filter(|p| *p <= 100))
+        } else if eq_ignore_case(args[1], "affinity") {
+            let hex = args[2].trim_start_matches("0x").trim_start_matches("0X");
+            (perf_value::AFFINITY_MASK, u32::from_str_radix(hex, 16).ok())
+        } else if eq_ignore_case(args[1], "iopriority") {
+            (perf_value::IO_PRIORITY, args[2].parse::<u32>().ok().filter(|p| *p <= 2))
+        } else {
+            outln!("Unknown option: {}", args[1]);
+            return;
+        };
+
+        let value = match value {
+            Some(v) => v,
+            None => {
+                outln!("Invalid value for {}: {}", name, args[2]);
+                return;
+            }
+        };
+
+        match set_image_policy_value(image, name, value) {
+            crate::cm::CmStatus::Success => {
+                outln!("{} set to {} for {}", name, value, image);
+                outln!("Applies to processes started from now on.");
+            }
+            status => outln!("Failed to write {}: {:?}", name, status),
+        }
+        return;
+    }
+
+    let policy = query_image_policy(image);
+    outln!("PerfOptions for {}", image);
+    outln!("  Key:           {}", perf_options_key(image).as_str());
+    if policy.is_empty() {
+        outln!("  (none)");
+        return;
+    }
+    if let Some(pct) = policy.cpu_rate_limit {
+        outln!("  CpuRateLimit:  {}%", pct);
+    }
+    if let Some(mask) = policy.affinity_mask {
+        outln!("  AffinityMask:  {:#x}", mask);
+    }
+    if let Some(prio) = policy.io_priority {
+        let name = crate::io::IoPriority::from_raw(prio).map(|p| p.name()).unwrap_or("?");
+        outln!("  IoPriority:    {} ({})", prio, name);
+    }
+}
+
 // ============================================================================
 // Wait Block Viewer Command
 // ============================================================================
@@ -12792,6 +14104,8 @@ pub fn cmd_ioq(args: &[&str]) {
         show_irp_list();
     } else if eq_ignore_ascii_case(cmd, "pending") {
         show_pending_irps();
+    } else if eq_ignore_ascii_case(cmd, "sched") {
+        show_io_sched();
     } else {
         outln!("Unknown subcommand: {}", args[0]);
         show_ioq_help();
@@ -12807,9 +14121,43 @@ fn show_ioq_help() {
     outln!("  stats     - Show IRP pool statistics");
     outln!("  list      - List allocated IRPs");
     outln!("  pending   - Show pending IRPs only");
+    outln!("  sched     - Show I/O priority scheduler statistics");
     outln!("  help      - Show this help message");
 }
 
+fn show_io_sched() {
+    use crate::io::{block, current_io_priority, iosched, IoPriority};
+
+    outln!("I/O Priority Scheduler");
+    outln!("======================");
+    outln!("");
+    outln!("Current I/O priority: {}", current_io_priority().name());
+    outln!("");
+    outln!("{:<10} {:>12} {:>12} {:>12}", "Priority", "Dispatched", "Throttled", "Delay (ms)");
+    outln!("--------------------------------------------------");
+    for priority in IoPriority::ALL.iter().rev() {
+        let stats = iosched::priority_stats(*priority);
+        outln!("{:<10} {:>12} {:>12} {:>12}",
+            priority.name(), stats.dispatched, stats.throttled, stats.delay_ms);
+    }
+
+    let mut header = true;
+    for index in 0..block::MAX_BLOCK_DEVICES as u8 {
+        let dev = match block::get_block_device(index) {
+            Some(dev) => dev,
+            None => continue,
+        };
+        if header {
+            outln!("");
+            outln!("{:<8} {:>12} {:>12}", "Device", "Foreground", "Background");
+            outln!("--------------------------------");
+            header = false;
+        }
+        let (foreground, background) = iosched::device_inflight(index);
+        outln!("{:<8} {:>12} {:>12}", dev.name_str(), foreground, background);
+    }
+}
+
 fn show_irp_stats() {
     use crate::io::{io_get_irp_stats, IrpPoolStats};
 
@@ -12847,22 +14195,23 @@ fn show_irp_list() {
         return;
     }
 
-    outln!("{:<18} {:<14} {:<8} {:<8} {:<8}",
-        "Address", "MajorFunc", "Stack", "Pending", "TID");
-    outln!("------------------------------------------------------------");
+    outln!("{:<18} {:<14} {:<8} {:<8} {:<8} {:<8}",
+        "Address", "MajorFunc", "Stack", "Pending", "TID", "IoPri");
+    outln!("---------------------------------------------------------------------");
 
     for i in 0..count {
         let irp = &snapshots[i];
         let func_name = irp_major_function_name(irp.major_function);
         let pending = if irp.is_pending { "Yes" } else { "No" };
 
-        outln!("{:#018x} {:<14} {}/{:<5} {:<8} {:<8}",
+        outln!("{:#018x} {:<14} {}/{:<5} {:<8} {:<8} {:<8}",
             irp.address,
             func_name,
             irp.current_location,
             irp.stack_count,
             pending,
-            irp.thread_id
+            irp.thread_id,
+            irp.io_priority.name()
         );
     }
 
@@ -13528,6 +14877,8 @@ pub fn cmd_blocks(args: &[&str]) {
         show_block_stats();
     } else if eq_ignore_ascii_case(subcmd, "list") {
         show_block_list();
+    } else if eq_ignore_ascii_case(subcmd, "adapters") {
+        show_storport_adapters();
     } else if eq_ignore_ascii_case(subcmd, "detail") {
         if args.len() < 2 {
             outln!("Usage: blocks detail <index>");
@@ -13546,6 +14897,7 @@ pub fn cmd_blocks(args: &[&str]) {
         outln!("Subcommands:");
         outln!("  stats         - Show block device statistics (default)");
         outln!("  list          - List all registered block devices");
+        outln!("  adapters      - Show storage port adapters and retry counts");
         outln!("  detail <idx>  - Show detailed info for device at index");
         outln!("");
         outln!("Examples:");
@@ -13575,6 +14927,25 @@ fn show_block_stats() {
     outln!("Total Errors:      {}", stats.total_errors);
 }
 
+fn show_storport_adapters() {
+    use crate::io::storport_adapter_stats;
+
+    let (adapters, count) = storport_adapter_stats();
+
+    outln!("Storage Port Adapters");
+    outln!("=====================");
+    outln!("");
+    if count == 0 {
+        outln!("No adapters registered");
+        return;
+    }
+    outln!("Idx  Name        MaxXfer  Requests  Retries  Sense  Resets");
+    for (i, a) in adapters[..count].iter().enumerate() {
+        outln!("{:<4} {:<11} {:>5} KB {:>9} {:>8} {:>6} {:>7}",
+            i, a.name_str(), a.max_transfer / 1024, a.requests, a.retries, a.sense_errors, a.resets);
+    }
+}
+
 fn show_block_list() {
     use crate::io::{io_get_block_snapshots, block_device_type_name};
 
@@ -13666,12 +15037,17 @@ fn show_block_detail(index: u8) {
     if (dev.flags & block_flags::BOOT) != 0 {
         outln!("  - Boot Device");
     }
+    if (dev.flags & block_flags::WRITE_CACHE) != 0 {
+        outln!("  - Write Cache Enabled");
+    }
     outln!("");
     outln!("I/O Statistics:");
     outln!("  Read Operations:    {}", dev.reads);
     outln!("  Write Operations:   {}", dev.writes);
     outln!("  Sectors Read:       {}", dev.sectors_read);
     outln!("  Sectors Written:    {}", dev.sectors_written);
+    outln!("  Cache Flushes:      {}", dev.flushes);
+    outln!("  FUA Writes:         {}", dev.fua_writes);
     outln!("  Errors:             {}", dev.errors);
 }
 
@@ -15182,6 +16558,8 @@ pub fn cmd_disk(args: &[&str]) {
         show_disk_gpt();
     } else if eq_ignore_ascii_case(subcmd, "geometry") {
         show_disk_geometry();
+    } else if eq_ignore_ascii_case(subcmd, "virtio") {
+        show_virtio_blk();
     } else if eq_ignore_ascii_case(subcmd, "help") || subcmd == "-h" || subcmd == "--help" {
         outln!("disk - Disk/Partition Viewer");
         outln!("");
@@ -15193,6 +16571,7 @@ pub fn cmd_disk(args: &[&str]) {
         outln!("  mbr        - Show MBR information");
         outln!("  gpt        - Show GPT information");
         outln!("  geometry   - Show disk geometry");
+        outln!("  virtio     - Show virtio-blk driver status");
         outln!("");
         outln!("FSTUB provides partition table support for MBR and GPT");
         outln!("disks, including extended partitions and GPT attributes.");
@@ -15202,6 +16581,32 @@ pub fn cmd_disk(args: &[&str]) {
     }
 }
 
+fn show_virtio_blk() {
+    let stats = crate::drivers::virtio::blk::virtio_blk_stats();
+
+    outln!("VirtIO Block Driver");
+    outln!("===================");
+    if !stats.ready {
+        outln!("No virtio-blk device (run QEMU with -drive if=virtio)");
+        return;
+    }
+    let name = crate::io::block::get_block_device(stats.block_index)
+        .map(|d| d.name_str())
+        .unwrap_or("?");
+    outln!("Device:        {} (block index {})", name, stats.block_index);
+    outln!("Capacity:      {} sectors ({} MB)", stats.capacity, stats.capacity / 2048);
+    outln!("Features:      {:#010x}", stats.features);
+    match stats.irq {
+        Some(irq) => outln!("Completion:    interrupt (IRQ {})", irq),
+        None => outln!("Completion:    polled"),
+    }
+    outln!("Requests:      {}", stats.requests);
+    outln!("Bytes:         {}", stats.bytes);
+    outln!("Interrupts:    {}", stats.interrupts);
+    outln!("Polled:        {}", stats.polled_completions);
+    outln!("Errors:        {}", stats.errors);
+}
+
 fn show_disk_info() {
     use crate::fstub;
 
@@ -15503,11 +16908,29 @@ pub fn cmd_reg(args: &[&str]) {
         show_reg_hives();
     } else if eq_ignore_ascii_case(subcmd, "query") {
         if args.len() < 2 {
-            outln!("Usage: reg query <path>");
+            outln!("Usage: reg query <path> [/v <name-pattern>] [/f <regex>]");
             outln!("Example: reg query SYSTEM\\CurrentControlSet");
             return;
         }
-        show_reg_query(args[1]);
+
+        let mut names = None;
+        let mut data = None;
+        let mut i = 2;
+        while i + 1 < args.len() {
+            if eq_ignore_ascii_case(args[i], "/v") {
+                names = Some(args[i + 1]);
+            } else if eq_ignore_ascii_case(args[i], "/f") {
+                match crate::rtl::RtlRegex::compile(args[i + 1].trim_matches('"'), true) {
+                    Ok(regex) => data = Some(regex),
+                    Err(e) => {
+                        outln!("Invalid expression: {}", e);
+                        return;
+                    }
+                }
+            }
+            i += 2;
+        }
+        show_reg_query(args[1], names, data.as_ref());
     } else if eq_ignore_ascii_case(subcmd, "enum") {
         if args.len() < 2 {
             outln!("Usage: reg enum <path>");
@@ -15522,6 +16945,8 @@ pub fn cmd_reg(args: &[&str]) {
             return;
         }
         show_reg_info(args[1]);
+    } else if eq_ignore_ascii_case(subcmd, "flush") {
+        reg_flush(args.get(1).is_some_and(|a| eq_ignore_ascii_case(a, "/all")));
     } else if eq_ignore_ascii_case(subcmd, "help") || subcmd == "-h" || subcmd == "--help" {
         outln!("reg - Registry Browser");
         outln!("");
@@ -15530,8 +16955,11 @@ pub fn cmd_reg(args: &[&str]) {
         outln!("Subcommands:");
         outln!("  hives         - List loaded registry hives (default)");
         outln!("  query <path>  - Query a registry key");
+        outln!("      /v <pattern>  Only values whose name matches (wildcards)");
+        outln!("      /f <regex>    Only values whose data matches");
         outln!("  enum <path>   - Enumerate subkeys and values");
         outln!("  info <path>   - Show detailed key information");
+        outln!("  flush [/all]  - Write dirty (or all) hives to disk");
         outln!("");
         outln!("Path Examples:");
         outln!("  SYSTEM");
@@ -15544,7 +16972,25 @@ pub fn cmd_reg(args: &[&str]) {
         outln!("  reg enum SYSTEM         - Enumerate SYSTEM subkeys");
     } else {
         // Treat as a path query
-        show_reg_query(subcmd);
+        show_reg_query(subcmd, None, None);
+    }
+}
+
+fn reg_flush(all: bool) {
+    use crate::cm;
+
+    let summary = cm::cm_flush_hives(all);
+    match summary.error {
+        None => outln!("{} hives written", summary.written),
+        Some(e) => outln!("{} hives written, {} failed: {:?}", summary.written, summary.failed, e),
+    }
+
+    let status = cm::cm_flush_status();
+    outln!("Lazy flusher: {} (every {} s), {} hives loaded at boot",
+        if status.lazy_flusher_running { "running" } else { "stopped" },
+        cm::LAZY_FLUSH_INTERVAL_MS / 1000, status.hives_loaded);
+    if let Some(path) = unsafe { cm::cm_get_hive(cm::hive_indices::HIVE_SYSTEM) }.and_then(cm::cm_hive_file_path) {
+        outln!("SYSTEM hive file: {}", path);
     }
 }
 
@@ -15587,7 +17033,11 @@ fn show_reg_hives() {
     outln!("Total: {} hives loaded", hive_count);
 }
 
-fn show_reg_query(path: &str) {
+/// Show a key's information and values
+///
+/// `names` filters value names with DOS wildcards; `data` filters on the
+/// displayed value data.
+fn show_reg_query(path: &str, names: Option<&str>, data: Option<&crate::rtl::RtlRegex>) {
     use crate::cm;
 
     outln!("Registry Query: {}", path);
@@ -15618,6 +17068,9 @@ fn show_reg_query(path: &str) {
                                 } else {
                                     value.name.as_str()
                                 };
+                                if names.is_some_and(|pattern| !crate::rtl::rtl_is_name_in_expression(pattern, name, true)) {
+                                    continue;
+                                }
                                 let type_str = match value.value_type {
                                     cm::RegType::Sz => "REG_SZ",
                                     cm::RegType::Dword => "REG_DWORD",
@@ -15636,14 +17089,23 @@ fn show_reg_query(path: &str) {
                                     _ => "",
                                 };
 
-                                if !display.is_empty() {
-                                    outln!("  {} ({}) = \"{}\"", name, type_str, display);
+                                let text = if !display.is_empty() {
+                                    alloc::string::String::from(display)
                                 } else if let Some(dw) = value.get_dword() {
-                                    outln!("  {} ({}) = {:#x}", name, type_str, dw);
+                                    alloc::format!("{:#x}", dw)
                                 } else if let Some(qw) = value.get_qword() {
-                                    outln!("  {} ({}) = {:#x}", name, type_str, qw);
+                                    alloc::format!("{:#x}", qw)
+                                } else {
+                                    alloc::format!("<{} bytes>", value.data.size)
+                                };
+                                if data.is_some_and(|regex| !regex.is_match(&text)) {
+                                    continue;
+                                }
+
+                                if !display.is_empty() {
+                                    outln!("  {} ({}) = \"{}\"", name, type_str, text);
                                 } else {
-                                    outln!("  {} ({}) = <{} bytes>", name, type_str, value.data.size);
+                                    outln!("  {} ({}) = {}", name, type_str, text);
                                 }
                             }
                         }
@@ -17375,6 +18837,8 @@ pub fn cmd_netinfo(args: &[&str]) {
             outln!("  send <msg>           Send test message");
             outln!("  facility <name>      Set default facility");
             outln!("  stats                Show syslog statistics");
+            outln!("  sink [start <ip> [port] [level] [console] | stop]");
+            outln!("                       Stream kernel logs to a collector");
             return;
         }
 
@@ -17440,6 +18904,49 @@ pub fn cmd_netinfo(args: &[&str]) {
 
             net::syslog::set_server(device_idx, server_ip, port);
             outln!("Syslog server set to {:?}:{}", server_ip, port);
+        } else if eq_ignore_case(args[1], "sink") {
+            use net::syslog::{SinkConfig, Severity};
+
+            if args.len() > 2 && eq_ignore_case(args[2], "start") {
+                let server_ip = match args.get(3).and_then(|s| net::Ipv4Address::parse(s)) {
+                    Some(ip) => ip,
+                    None => {
+                        outln!("Usage: netinfo syslog sink start <ip> [port] [level 0-7] [console]");
+                        return;
+                    }
+                };
+                let numbers: alloc::vec::Vec<u32> = args[4..].iter().filter_map(|a| a.parse().ok()).collect();
+                let config = SinkConfig {
+                    server_ip,
+                    server_port: numbers.first().map(|&p| p as u16).unwrap_or(net::syslog::SYSLOG_PORT),
+                    min_severity: numbers.get(1).and_then(|&l| Severity::from_level(l)).unwrap_or(Severity::Info),
+                    forward_console: args[4..].iter().any(|a| eq_ignore_case(a, "console")),
+                };
+                match net::syslog::sink_start(config) {
+                    Ok(()) => outln!("Forwarding logs to {}:{}", config.server_ip, config.server_port),
+                    Err(e) => outln!("Failed to start sink: {}", e),
+                }
+            } else if args.len() > 2 && eq_ignore_case(args[2], "stop") {
+                net::syslog::sink_stop();
+                outln!("Remote log sink stopped");
+            } else {
+                let status = net::syslog::sink_status();
+                outln!("Remote Log Sink:");
+                outln!("  State: {}", if status.enabled { "forwarding" } else { "stopped" });
+                if let Some(config) = status.config {
+                    outln!("  Collector: {}:{}", config.server_ip, config.server_port);
+                    outln!("  Minimum severity: {}", config.min_severity.name());
+                    outln!("  Source: {}", if config.forward_console { "console lines" } else { "event log" });
+                }
+                match status.route {
+                    Some((device, next_hop)) => outln!("  Route: device {} via {}", device, next_hop),
+                    None => outln!("  Route: none (waiting for network)"),
+                }
+                outln!("  Records queued: {} ({} pending)", status.queued, status.pending);
+                outln!("  Records forwarded: {}", status.forwarded);
+                outln!("  Records dropped: {}", status.dropped);
+                outln!("  Send errors: {}", status.send_errors);
+            }
         } else if eq_ignore_case(args[1], "send") {
             if args.len() < 3 {
                 outln!("Usage: netinfo syslog send <message>");
@@ -17896,6 +19403,7 @@ pub fn cmd_eventlog(args: &[&str]) {
         outln!("  list [n]     Show last n events (default 10)");
         outln!("  errors       Show recent error events");
         outln!("  warnings     Show recent warning events");
+        outln!("  find <source> [regex]  Show events from matching sources");
         outln!("  stats        Show event log statistics");
         outln!("  clear        Clear event log");
         outln!("  test         Generate test events");
@@ -17931,7 +19439,7 @@ pub fn cmd_eventlog(args: &[&str]) {
                 event.source.name(),
                 event.event_type.name(),
                 event.event_id,
-                event.message
+                event.description()
             );
         }
     } else if eq_ignore_case(args[0], "errors") {
@@ -17948,7 +19456,7 @@ pub fn cmd_eventlog(args: &[&str]) {
                 "[{}] #{}: {}",
                 event.source.name(),
                 event.event_id,
-                event.message
+                event.description()
             );
         }
     } else if eq_ignore_case(args[0], "warnings") {
@@ -17965,7 +19473,39 @@ pub fn cmd_eventlog(args: &[&str]) {
                 "[{}] #{}: {}",
                 event.source.name(),
                 event.event_id,
-                event.message
+                event.description()
+            );
+        }
+    } else if eq_ignore_case(args[0], "find") {
+        let Some(&source) = args.get(1) else {
+            outln!("Usage: eventlog find <source-pattern> [regex]");
+            outln!("Example: eventlog find M* \"fragment|compact\"");
+            return;
+        };
+        let regex = match args.get(2).map(|text| crate::rtl::RtlRegex::compile(text.trim_matches('"'), true)) {
+            Some(Ok(regex)) => Some(regex),
+            Some(Err(e)) => {
+                outln!("Invalid expression: {}", e);
+                return;
+            }
+            None => None,
+        };
+
+        let events = eventlog::get_events_matching(source, regex.as_ref(), 50);
+        if events.is_empty() {
+            outln!("No matching events in log");
+            return;
+        }
+
+        outln!("Matching Events ({}):", events.len());
+        outln!("");
+        for event in events {
+            outln!(
+                "[{}] [{}] #{}: {}",
+                event.source.name(),
+                event.event_type.name(),
+                event.event_id,
+                event.description()
             );
         }
     } else if eq_ignore_case(args[0], "clear") {
@@ -18515,20 +20055,7 @@ pub fn cmd_netstat(args: &[&str]) {
 
         let tcp_connections = tcp::enumerate_connections();
         for conn in &tcp_connections {
-            // Format state
-            let state_str = match conn.state {
-                tcp::TcpState::Closed => "CLOSED",
-                tcp::TcpState::Listen => "LISTENING",
-                tcp::TcpState::SynSent => "SYN_SENT",
-                tcp::TcpState::SynReceived => "SYN_RECEIVED",
-                tcp::TcpState::Established => "ESTABLISHED",
-                tcp::TcpState::FinWait1 => "FIN_WAIT_1",
-                tcp::TcpState::FinWait2 => "FIN_WAIT_2",
-                tcp::TcpState::CloseWait => "CLOSE_WAIT",
-                tcp::TcpState::Closing => "CLOSING",
-                tcp::TcpState::LastAck => "LAST_ACK",
-                tcp::TcpState::TimeWait => "TIME_WAIT",
-            };
+            let state_str = tcp_state_name(conn.state);
 
             // Skip non-established if not -a
             if !show_all && conn.state != tcp::TcpState::Established &&
@@ -18537,7 +20064,7 @@ pub fn cmd_netstat(args: &[&str]) {
             }
 
             // Format addresses
-            let local_addr = alloc::format!("0.0.0.0:{}", conn.local_port);
+            let local_addr = alloc::format!("{}:{}", conn.local_ip, conn.local_port);
             let remote_addr = if conn.remote_port == 0 {
                 alloc::format!("*:*")
             } else {
@@ -18585,6 +20112,25 @@ pub fn cmd_netstat(args: &[&str]) {
     outln!("");
 }
 
+/// Netstat-style name of a TCP state
+fn tcp_state_name(state: crate::net::tcp::TcpState) -> &'static str {
+    use crate::net::tcp::TcpState;
+
+    match state {
+        TcpState::Closed => "CLOSED",
+        TcpState::Listen => "LISTENING",
+        TcpState::SynSent => "SYN_SENT",
+        TcpState::SynReceived => "SYN_RECEIVED",
+        TcpState::Established => "ESTABLISHED",
+        TcpState::FinWait1 => "FIN_WAIT_1",
+        TcpState::FinWait2 => "FIN_WAIT_2",
+        TcpState::CloseWait => "CLOSE_WAIT",
+        TcpState::Closing => "CLOSING",
+        TcpState::LastAck => "LAST_ACK",
+        TcpState::TimeWait => "TIME_WAIT",
+    }
+}
+
 /// Route table display command
 pub fn cmd_route(args: &[&str]) {
     use crate::net;
@@ -18832,9 +20378,7 @@ pub fn cmd_tracert(args: &[&str]) {
                     // Check for reply in the pending buffer
                     // Note: In a real implementation, we'd need to hook into
                     // the ICMP handler to capture Time Exceeded responses
-                    for _ in 0..1000 {
-                        core::hint::spin_loop();
-                    }
+                    crate::ex::ex_sleep(1);
 
                     // For now, simulate timeout since we can't easily capture
                     // intermediate router responses without more infrastructure
@@ -18851,9 +20395,7 @@ pub fn cmd_tracert(args: &[&str]) {
         }
 
         // Small delay between probes
-        for _ in 0..10000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(10);
     }
 
     outln!("");
@@ -19052,9 +20594,7 @@ pub fn cmd_ping(args: &[&str]) {
 
                 // Simple delay to simulate waiting
                 // Note: Real implementation would need reply capture infrastructure
-                for _ in 0..100000 {
-                    core::hint::spin_loop();
-                }
+                crate::ex::ex_sleep(1);
 
                 let rtt = (crate::hal::apic::get_tick_count() - start) / 1000; // Convert to ms
 
@@ -19083,12 +20623,7 @@ pub fn cmd_ping(args: &[&str]) {
 
         // Delay between pings (1 second)
         if seq + 1 < count {
-            let delay_end = crate::hal::apic::get_tick_count() + 1000 * 1000;
-            while crate::hal::apic::get_tick_count() < delay_end {
-                for _ in 0..1000 {
-                    core::hint::spin_loop();
-                }
-            }
+            crate::ex::ex_sleep(1000);
         }
     }
 
@@ -19160,20 +20695,59 @@ pub fn cmd_systeminfo(_args: &[&str]) {
     use crate::ke::prcb::get_active_cpu_count;
     use crate::hal::acpi::get_processor_count;
 
+    let mut version = crate::rtl::OsVersionInfoEx::new();
+    crate::rtl::rtl_get_version(&mut version);
+    let smbios = crate::hal::smbios::smbios_info();
+
     outln!("");
     outln!("Host Name:                 {}", get_hostname());
     outln!("OS Name:                   Nostalgia OS");
-    outln!("OS Version:                5.2.3790 Build 3790");
+    outln!("OS Version:                {}.{}.{} Build {}",
+        version.major_version, version.minor_version, version.build_number, version.build_number);
     outln!("OS Manufacturer:           Nostalgia Project");
     outln!("OS Configuration:          Standalone Server");
     outln!("OS Build Type:             Multiprocessor Free");
+    if let Some(info) = smbios {
+        outln!("System Manufacturer:       {}", info.system_manufacturer);
+        outln!("System Model:              {}", info.system_product);
+    }
+    outln!("System Type:               x64-based PC");
 
     // Get real processor count from ACPI and active CPU count from prcb
     let total_procs = get_processor_count();
     let active_procs = get_active_cpu_count();
+    let brand = crate::hal::cpuid::cpuid_get_brand_string();
+    let brand = core::str::from_utf8(brand).unwrap_or("").trim_matches(|c: char| c == '\0' || c == ' ');
+    let model = crate::hal::cpuid::cpuid_get_model();
+    let vendor = core::str::from_utf8(crate::hal::cpuid::cpuid_get_vendor_string()).unwrap_or("");
+    let mhz = crate::hal::timer::hal_query_tsc_frequency() / 1_000_000;
     outln!("Processor(s):              {} Processor(s) Installed ({} active).", total_procs, active_procs);
     for i in 0..active_procs {
-        outln!("                           [{:02}]: x86_64 Family Compatible Processor", i + 1);
+        if brand.is_empty() {
+            outln!("                           [{:02}]: Intel64 Family {} Model {} Stepping {} {} ~{} Mhz",
+                i + 1, model.family, model.model, model.stepping, vendor, mhz);
+        } else {
+            outln!("                           [{:02}]: {} ~{} Mhz", i + 1, brand, mhz);
+        }
+    }
+    if let Some(info) = smbios {
+        outln!("BIOS Version:              {} {}, {}", info.bios_vendor, info.bios_version, info.bios_date);
+        outln!("BaseBoard:                 {} {}", info.board_manufacturer, info.board_product);
+        if !info.system_serial.is_empty() {
+            outln!("Serial Number:             {}", info.system_serial);
+        }
+        outln!("System UUID:               {}", info.uuid_string());
+        if info.memory_device_count > 0 {
+            outln!("Memory Device(s):          {} of {} Slot(s) Populated, {} MB.",
+                info.memory_device_count, info.memory_slots, info.total_memory_mb);
+            for (i, device) in info.memory_devices().iter().enumerate() {
+                outln!("                           [{:02}]: {} {} MB {} {} MT/s",
+                    i + 1, device.locator, device.size_mb,
+                    crate::hal::smbios::memory_type_name(device.memory_type), device.speed);
+            }
+        }
+    } else {
+        outln!("BIOS Version:              N/A (no SMBIOS tables)");
     }
 
     // Memory info using mm_get_stats
@@ -19283,8 +20857,8 @@ pub fn cmd_tasklist(args: &[&str]) {
                         thread_count, handle_count, ppid, priority_str);
 
                     // Show time accounting if available
-                    let kernel_ms = (*process).kernel_time / 10_000; // 100ns to ms
-                    let user_ms = (*process).user_time / 10_000;
+                    let kernel_ms = (*process).pcb.kernel_time() / 10_000; // 100ns to ms
+                    let user_ms = (*process).pcb.user_time() / 10_000;
                     if kernel_ms > 0 || user_ms > 0 {
                         outln!("  CPU Time: {}ms kernel, {}ms user", kernel_ms, user_ms);
                     }
@@ -19346,8 +20920,26 @@ pub fn set_username(name: &str) {
 }
 
 /// Whoami command - display current user
-pub fn cmd_whoami(_args: &[&str]) {
+pub fn cmd_whoami(args: &[&str]) {
     outln!("{}\\{}", get_hostname(), get_username());
+
+    let token = unsafe { &*crate::ps::ps_get_process_token(crate::ps::get_current_process()) };
+
+    if args.iter().any(|a| eq_ignore_case(a, "/user")) {
+        outln!("");
+        outln!("User SID  {}", token.user);
+    }
+
+    if args.iter().any(|a| eq_ignore_case(a, "/groups")) {
+        outln!("");
+        for sid in &token.group_sids[..token.group_count as usize] {
+            if crate::se::IntegrityLevel::from_sid(sid).is_none() {
+                outln!("Group     {}", sid);
+            }
+        }
+        let level = token.integrity_level();
+        outln!("Mandatory Label\\{} Mandatory Level  S-1-16-{}", level.name(), level.rid());
+    }
 }
 
 /// Calculate day of week (Zeller's formula, 0=Sun...6=Sat)
@@ -20664,12 +22256,13 @@ pub fn cmd_find(args: &[&str]) {
     if args.len() < 2 {
         outln!("Searches for a text string in a file.");
         outln!("");
-        outln!("FIND [/I] [/V] [/C] [/N] \"string\" filename");
+        outln!("FIND [/I] [/V] [/C] [/N] [/R] \"string\" filename");
         outln!("");
         outln!("  /V   Displays lines NOT containing the string");
         outln!("  /C   Displays only the count of matching lines");
         outln!("  /I   Ignores case");
         outln!("  /N   Displays line numbers");
+        outln!("  /R   Treats the string as a regular expression");
         outln!("  string   Text string to find (in quotes)");
         return;
     }
@@ -20679,12 +22272,15 @@ pub fn cmd_find(args: &[&str]) {
     let mut count_only = false;
     let mut ignore_case = false;
     let mut show_numbers = false;
+    let mut use_regex = false;
     let mut arg_start = 0;
 
     for (i, arg) in args.iter().enumerate() {
         if arg.starts_with('/') || arg.starts_with('-') {
             let opt = arg.to_ascii_uppercase();
-            if opt == "/V" || opt == "-V" {
+            if opt == "/R" || opt == "-R" {
+                use_regex = true;
+            } else if opt == "/V" || opt == "-V" {
                 invert = true;
             } else if opt == "/C" || opt == "-C" {
                 count_only = true;
@@ -20710,6 +22306,18 @@ pub fn cmd_find(args: &[&str]) {
         search_str = &search_str[1..search_str.len() - 1];
     }
 
+    let regex = if use_regex {
+        match crate::rtl::RtlRegex::compile(search_str, ignore_case) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                outln!("FIND: Invalid expression: {}", e);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
     let filename = args[arg_start + 1];
     let path = resolve_path(filename);
 
@@ -20729,6 +22337,11 @@ pub fn cmd_find(args: &[&str]) {
     } else {
         alloc::string::String::from(search_str)
     };
+    let line_contains = |line: &str| match &regex {
+        Some(regex) => regex.is_match(line),
+        None if ignore_case => line.to_ascii_lowercase().contains(&search_pattern),
+        None => line.contains(&search_pattern),
+    };
 
     let mut buf = [0u8; 4096];
     let mut line_buf = alloc::vec::Vec::new();
@@ -20742,13 +22355,7 @@ pub fn cmd_find(args: &[&str]) {
                 for &b in &buf[..n] {
                     if b == b'\n' {
                         if let Ok(line) = core::str::from_utf8(&line_buf) {
-                            let search_line = if ignore_case {
-                                line.to_ascii_lowercase()
-                            } else {
-                                alloc::string::String::from(line)
-                            };
-
-                            let contains = search_line.contains(&search_pattern);
+                            let contains = line_contains(line);
                             let matches = if invert { !contains } else { contains };
 
                             if matches {
@@ -20776,13 +22383,7 @@ pub fn cmd_find(args: &[&str]) {
     // Process last line
     if !line_buf.is_empty() {
         if let Ok(line) = core::str::from_utf8(&line_buf) {
-            let search_line = if ignore_case {
-                line.to_ascii_lowercase()
-            } else {
-                alloc::string::String::from(line)
-            };
-
-            let contains = search_line.contains(&search_pattern);
+            let contains = line_contains(line);
             let matches = if invert { !contains } else { contains };
 
             if matches {
@@ -21882,64 +23483,222 @@ pub fn cmd_popd(_args: &[&str]) {
     }
 }
 
-/// Drive substitution table (simple simulation)
-static mut SUBST_TABLE: [([u8; 64], usize); 26] = [([0u8; 64], 0); 26];
-
-/// SUBST command - substitute a drive letter for a path
+/// SUBST command - associate a drive letter with a path
+///
+/// Aliases are DOS device links in the console session; `/P` makes a
+/// global alias and records it in the registry so it survives a reboot.
 pub fn cmd_subst(args: &[&str]) {
+    use crate::fs::canon::DOS_DEVICES_KEY;
+    use crate::ob;
+
+    if args.contains(&"/?") || args.len() == 1 || args.len() > 3 {
+        outln!("Associates a path with a drive letter.");
+        outln!("");
+        outln!("SUBST [drive1: [drive2:]path [/P]]");
+        outln!("SUBST drive1: /D");
+        outln!("");
+        outln!("  drive1:        Specifies a virtual drive to which to assign a path.");
+        outln!("  [drive2:]path  Specifies a physical drive and path to assign.");
+        outln!("  /P             Makes the substitution global and persistent.");
+        outln!("  /D             Deletes a substituted (virtual) drive.");
+        outln!("");
+        outln!("Type SUBST with no parameters to display current virtual drives.");
+        return;
+    }
+
+    let session = ob::ob_current_session_id();
+
     if args.is_empty() {
-        // Display current substitutions
-        outln!("Current drive substitutions:");
-        let mut count = 0;
+        for (letter, target, owner) in ob::ob_list_dos_devices() {
+            if owner.is_none() || owner == Some(session) {
+                let scope = if owner.is_none() { "  (global)" } else { "" };
+                outln!("{}:\\: => {}{}", letter, target.as_str(), scope);
+            }
+        }
+        return;
+    }
+
+    let drive = args[0].as_bytes();
+    if drive.len() != 2 || drive[1] != b':' || !drive[0].is_ascii_alphabetic() {
+        outln!("Invalid parameter - {}", args[0]);
+        return;
+    }
+    let letter = drive[0].to_ascii_uppercase() as char;
+    let value_name = alloc::format!("{}:", letter);
+
+    if eq_ignore_case(args[1], "/D") {
+        if ob::ob_delete_dos_device(Some(session), letter) == 0 {
+            return;
+        }
+        if ob::ob_delete_dos_device(None, letter) != 0 {
+            outln!("Invalid parameter - {}", value_name.as_str());
+            return;
+        }
         unsafe {
-            for (i, (path, len)) in SUBST_TABLE.iter().enumerate() {
-                if *len > 0 {
-                    let letter = (b'A' + i as u8) as char;
-                    if let Ok(p) = core::str::from_utf8(&path[..*len]) {
-                        outln!("{}:\\  =>  {}", letter, p);
-                        count += 1;
-                    }
-                }
+            if let Ok(key) = crate::cm::cm_open_key(DOS_DEVICES_KEY) {
+                crate::cm::cm_delete_value(key, &value_name);
             }
         }
-        if count == 0 {
-            outln!("  (none)");
+        return;
+    }
+
+    let persistent = match args.get(2) {
+        Some(flag) if eq_ignore_case(flag, "/P") => true,
+        Some(flag) => {
+            outln!("Invalid parameter - {}", flag);
+            return;
         }
+        None => false,
+    };
+
+    if crate::fs::mount::get_mount_point(letter).is_some() {
+        outln!("Invalid parameter - {}", value_name.as_str());
+        return;
+    }
+    if ob::ob_query_dos_device(letter).is_some() {
+        outln!("Drive already SUBSTed");
         return;
     }
 
-    // Parse: SUBST drive: path  or  SUBST drive: /D
-    if args.len() >= 2 {
-        let drive = args[0];
-        let second = args[1];
+    // Store the target in canonical form so it no longer depends on the cwd
+    let mut canonical = [0u8; crate::fs::MAX_PATH];
+    let target = match crate::fs::canon::canonicalize_path(resolve_path(args[1]), &mut canonical) {
+        Ok(t) => alloc::string::String::from(t),
+        Err(_) => {
+            outln!("Invalid parameter - {}", args[1]);
+            return;
+        }
+    };
+    match crate::fs::stat(&target) {
+        Ok(info) if info.file_type == crate::fs::FileType::Directory => {}
+        _ => {
+            outln!("Path not found - {}", target.as_str());
+            return;
+        }
+    }
+
+    let owner = if persistent { None } else { Some(session) };
+    if ob::ob_define_dos_device(owner, letter, &target) != 0 {
+        outln!("Drive already SUBSTed");
+        return;
+    }
+
+    if persistent {
+        let status = unsafe { crate::cm::cm_write_string(DOS_DEVICES_KEY, &value_name, &target) };
+        if status != crate::cm::CmStatus::Success {
+            outln!("Warning: substitution could not be saved to the registry");
+        }
+    }
+}
+
+/// AUTORUN command - control the developer auto-run service
+pub fn cmd_autorun(args: &[&str]) {
+    use crate::ldr::autorun;
 
-        // Get drive letter
-        let letter = drive.chars().next().unwrap_or('?').to_ascii_uppercase();
-        if letter < 'A' || letter > 'Z' {
-            outln!("SUBST: Invalid drive letter");
+    let sub = args.first().copied().unwrap_or("status");
+
+    if eq_ignore_case(sub, "start") {
+        if crate::fs::stat(autorun::AUTORUN_DIR).is_err() && crate::fs::mkdir(autorun::AUTORUN_DIR).is_err() {
+            outln!("Cannot create {}", autorun::AUTORUN_DIR);
             return;
         }
-        let idx = (letter as u8 - b'A') as usize;
+        if autorun::autorun_start() {
+            outln!("Auto-run service started, watching {}", autorun::AUTORUN_DIR);
+        } else {
+            outln!("Auto-run service is already running");
+        }
+    } else if eq_ignore_case(sub, "stop") {
+        if autorun::autorun_stop() {
+            outln!("Auto-run service stopping");
+        } else {
+            outln!("Auto-run service is not running");
+        }
+    } else if eq_ignore_case(sub, "status") {
+        let status = autorun::autorun_status();
+        outln!("Auto-run service: {}", if status.running { "running" } else { "stopped" });
+        outln!("  Directory: {}", autorun::AUTORUN_DIR);
+        outln!("  Started:   {}  Failed: {}  Pending: {}", status.started, status.failed, status.pending);
+        let now = crate::hal::apic::get_tick_count();
+        for run in status.runs.iter().flatten() {
+            outln!("  {:<12}  PID {:<5}  {:>6} ms  {}",
+                run.name_str(), run.pid, now - run.started_at,
+                if run.is_logging() { "logging" } else { "no log" });
+        }
+    } else {
+        outln!("Usage: autorun [status | start | stop]");
+    }
+}
 
-        if second == "/D" || second == "/d" || second == "-d" || second == "-D" {
-            // Delete substitution
-            unsafe {
-                SUBST_TABLE[idx].1 = 0;
-            }
-            outln!("Drive {}:\\ substitution deleted", letter);
+/// AVSCAN command - control the on-access scanner demo mini-filter
+pub fn cmd_avscan(args: &[&str]) {
+    use crate::fsrtl;
+
+    let sub = args.first().copied().unwrap_or("status");
+
+    if eq_ignore_case(sub, "start") {
+        match fsrtl::avscan_start() {
+            Some(id) => outln!("{} started (filter {}, altitude {})",
+                fsrtl::AVSCAN_FILTER_NAME, id, fsrtl::AVSCAN_ALTITUDE),
+            None => outln!("Failed to register {}", fsrtl::AVSCAN_FILTER_NAME),
+        }
+    } else if eq_ignore_case(sub, "stop") {
+        if fsrtl::avscan_stop() {
+            outln!("{} stopped", fsrtl::AVSCAN_FILTER_NAME);
         } else {
-            // Add substitution
-            let path = resolve_path(second);
-            unsafe {
-                let len = path.len().min(63);
-                SUBST_TABLE[idx].0[..len].copy_from_slice(&path.as_bytes()[..len]);
-                SUBST_TABLE[idx].1 = len;
+            outln!("{} is not running", fsrtl::AVSCAN_FILTER_NAME);
+        }
+    } else if eq_ignore_case(sub, "reload") {
+        outln!("Loaded {} blacklist entries", fsrtl::avscan_reload_blacklist());
+    } else if eq_ignore_case(sub, "flush") {
+        fsrtl::avscan_flush_cache();
+        outln!("Hash cache flushed");
+    } else if eq_ignore_case(sub, "cache") {
+        let (entries, count) = fsrtl::avscan_cache_snapshot();
+        if count == 0 {
+            outln!("Hash cache is empty");
+            return;
+        }
+        outln!("{:<16}  {:>10}  {:>5}  Path", "Hash", "Size", "Hits");
+        for entry in &entries[..count] {
+            outln!("{:016x}  {:>10}  {:>5}  {}", entry.hash, entry.size, entry.hits, entry.path_str());
+        }
+    } else if eq_ignore_case(sub, "block") || eq_ignore_case(sub, "unblock") {
+        let entry = match args.get(1) {
+            Some(e) => *e,
+            None => {
+                outln!("Usage: avscan {} <pattern | #hash>", sub);
+                return;
             }
-            outln!("{}:\\ => {}", letter, path);
+        };
+        let ok = unsafe {
+            if eq_ignore_case(sub, "block") {
+                crate::cm::cm_write_string(fsrtl::AVSCAN_BLACKLIST_KEY, entry, entry)
+                    == crate::cm::CmStatus::Success
+            } else {
+                match crate::cm::cm_open_key(fsrtl::AVSCAN_BLACKLIST_KEY) {
+                    Ok(key) => crate::cm::cm_delete_value(key, entry) == crate::cm::CmStatus::Success,
+                    Err(_) => false,
+                }
+            }
+        };
+        if !ok {
+            outln!("Failed to update blacklist entry {}", entry);
+            return;
         }
+        outln!("Blacklist now has {} entries", fsrtl::avscan_reload_blacklist());
+    } else if eq_ignore_case(sub, "status") {
+        let stats = fsrtl::avscan_get_stats();
+        outln!("{} ({})", fsrtl::AVSCAN_FILTER_NAME, if stats.running { "running" } else { "stopped" });
+        outln!("  Files scanned:    {} ({} bytes)", stats.files_scanned, stats.bytes_scanned);
+        outln!("  Cache hits:       {} ({} files cached)", stats.cache_hits, stats.cached_files);
+        outln!("  Opens blocked:    {}", stats.opens_blocked);
+        outln!("  Paging I/O skips: {}", stats.paging_skipped);
+        outln!("  Excluded opens:   {}", stats.excluded);
+        outln!("  Blacklist:        {} entries", stats.blacklist_entries);
     } else {
-        outln!("SUBST drive: path");
-        outln!("SUBST drive: /D");
+        outln!("Usage: avscan [status | start | stop | cache | flush | reload]");
+        outln!("       avscan block <pattern | #hash>");
+        outln!("       avscan unblock <pattern | #hash>");
     }
 }
 
@@ -22647,7 +24406,7 @@ pub fn cmd_start(args: &[&str]) {
         outln!("  /MAX        Start maximized");
         outln!("  /WAIT       Wait for application to terminate");
         outln!("  /B          Start without creating new window");
-        outln!("  /LOW        Start with IDLE priority class");
+        outln!("  /LOW        Start with IDLE priority class and low I/O priority");
         outln!("  /NORMAL     Start with NORMAL priority class");
         outln!("  /HIGH       Start with HIGH priority class");
         outln!("  /REALTIME   Start with REALTIME priority class");
@@ -22665,6 +24424,7 @@ pub fn cmd_start(args: &[&str]) {
     let mut wait_for_exit = false;
     let mut no_window = false;
     let mut priority = "NORMAL";
+    let mut io_priority = crate::io::IoPriority::Normal;
     let mut minimized = false;
     let mut maximized = false;
     let mut program_args: alloc::vec::Vec<&str> = alloc::vec::Vec::new();
@@ -22704,7 +24464,9 @@ pub fn cmd_start(args: &[&str]) {
             // Use original environment - noted
             i += 1;
         } else if upper == "/LOW" || upper == "-LOW" {
+            // Background task: yield the disk to interactive work too
             priority = "IDLE";
+            io_priority = crate::io::IoPriority::Low;
             i += 1;
         } else if upper == "/NORMAL" || upper == "-NORMAL" {
             priority = "NORMAL";
@@ -22745,6 +24507,7 @@ pub fn cmd_start(args: &[&str]) {
         outln!("  Working dir:   {}", d);
     }
     outln!("  Priority:      {}", priority);
+    outln!("  I/O priority:  {}", io_priority.name());
     outln!("  Window state:  {}", if minimized { "Minimized" } else if maximized { "Maximized" } else if no_window { "Hidden" } else { "Normal" });
     outln!("  Wait for exit: {}", if wait_for_exit { "Yes" } else { "No" });
     outln!("");
@@ -22763,7 +24526,8 @@ pub fn cmd_start(args: &[&str]) {
     outln!("  Request time: {} ticks", start_ticks);
 
     log_info(EventSource::Process, 2001, &alloc::format!(
-        "START: Launched '{}' with PID {:04X}, priority {}", program, simulated_pid, priority
+        "START: Launched '{}' with PID {:04X}, priority {}, I/O priority {}",
+        program, simulated_pid, priority, io_priority.name()
     ));
 
     if wait_for_exit {
@@ -22887,8 +24651,8 @@ pub fn cmd_choice(args: &[&str]) {
         let mut last_displayed = timeout_secs;
 
         while crate::hal::apic::get_tick_count() < end_tick {
-            // Check for keyboard input using HAL keyboard buffer
-            if let Some(byte) = crate::hal::keyboard::try_read_char() {
+            // Wait up to 100ms for a key so the countdown keeps updating
+            if let Some(byte) = crate::hal::keyboard::read_char_timeout(100) {
                 let c = byte as char;
                 // Check if character is in choices list
                 let found = if case_sensitive {
@@ -22912,11 +24676,6 @@ pub fn cmd_choice(args: &[&str]) {
                     choices, remaining);
                 last_displayed = remaining;
             }
-
-            // Small delay to prevent busy spinning
-            for _ in 0..1000 {
-                core::hint::spin_loop();
-            }
         }
 
         if let Some(c) = selected_char {
@@ -22954,7 +24713,7 @@ pub fn cmd_choice(args: &[&str]) {
         let mut selected_char: Option<char> = None;
 
         while attempts < max_attempts {
-            if let Some(byte) = crate::hal::keyboard::try_read_char() {
+            if let Some(byte) = crate::hal::keyboard::read_char_timeout(1) {
                 let c = byte as char;
                 // Check if character is in choices list
                 let found = if case_sensitive {
@@ -22969,9 +24728,6 @@ pub fn cmd_choice(args: &[&str]) {
                 }
             }
             attempts += 1;
-            for _ in 0..100 {
-                core::hint::spin_loop();
-            }
         }
 
         if let Some(c) = selected_char {
@@ -23049,10 +24805,8 @@ pub fn cmd_timeout(args: &[&str]) {
             crate::serial_print!("\rWaiting for {} seconds...  ", remaining);
             last_displayed = remaining;
         }
-        // Small busy wait
-        for _ in 0..10000 {
-            core::hint::spin_loop();
-        }
+        // Sleep until the next countdown update
+        crate::ex::ex_sleep(100);
     }
 
     outln!("\rTimeout complete.              ");
@@ -23084,21 +24838,14 @@ pub fn cmd_pause(args: &[&str]) {
     let timeout_ticks = 5000u64; // 5 second timeout for serial mode
     let end_tick = start_tick + timeout_ticks;
 
-    while crate::hal::apic::get_tick_count() < end_tick {
-        // Check for keyboard input using try_read_char (non-blocking)
-        if let Some(byte) = crate::hal::keyboard::try_read_char() {
-            key_pressed = true;
-            // Echo printable characters
-            let c = byte as char;
-            if c.is_ascii_graphic() || c == ' ' {
-                crate::serial_print!("{}", c);
-            }
-            break;
-        }
-
-        // Small delay
-        for _ in 0..500 {
-            core::hint::spin_loop();
+    // Sleep until a key arrives or the timeout expires
+    let remaining = end_tick.saturating_sub(crate::hal::apic::get_tick_count());
+    if let Some(byte) = crate::hal::keyboard::read_char_timeout(remaining) {
+        key_pressed = true;
+        // Echo printable characters
+        let c = byte as char;
+        if c.is_ascii_graphic() || c == ' ' {
+            crate::serial_print!("{}", c);
         }
     }
 
@@ -23677,6 +25424,12 @@ pub fn cmd_taskkill(args: &[&str]) {
             return;
         }
 
+        if crate::ps::ps_is_system_process_id(p) {
+            outln!("ERROR: The process with PID {} could not be terminated.", p);
+            outln!("Reason: This is critical system process.");
+            return;
+        }
+
         // Try to terminate by PID
         unsafe {
             let proc_ptr = crate::ps::ps_lookup_process_by_id(p);
@@ -23725,7 +25478,12 @@ pub fn cmd_taskkill(args: &[&str]) {
                     let proc_name = core::str::from_utf8(name_bytes).unwrap_or("unknown");
                     if eq_ignore_case(proc_name, name) {
                         let pid = (*proc).unique_process_id;
-                        outln!("SUCCESS: Sent termination signal to \"{}\" (PID: {})", proc_name, pid);
+                        if crate::ps::ps_is_system_process_id(pid) {
+                            outln!("ERROR: The process \"{}\" with PID {} could not be terminated.", proc_name, pid);
+                            outln!("Reason: This is critical system process.");
+                        } else {
+                            outln!("SUCCESS: Sent termination signal to \"{}\" (PID: {})", proc_name, pid);
+                        }
                         found = true;
                     }
                 }
@@ -24000,30 +25758,20 @@ pub fn cmd_gpupdate(args: &[&str]) {
     if target_computer {
         outln!("  Processing Computer policy...");
         // Simulate work
-        for _ in 0..500000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(50);
         outln!("    Applying Security Settings...");
-        for _ in 0..300000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(30);
         outln!("    Applying Administrative Templates...");
-        for _ in 0..200000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(20);
         outln!("  Computer Policy update has completed successfully.");
         log_info(EventSource::Security, 4020, "GPUPDATE: Computer policy refreshed");
     }
 
     if target_user {
         outln!("  Processing User policy...");
-        for _ in 0..400000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(40);
         outln!("    Applying User Rights Assignment...");
-        for _ in 0..200000 {
-            core::hint::spin_loop();
-        }
+        crate::ex::ex_sleep(20);
         outln!("  User Policy update has completed successfully.");
         log_info(EventSource::Security, 4021, "GPUPDATE: User policy refreshed");
     }
@@ -25369,61 +27117,114 @@ pub fn cmd_setx(args: &[&str]) {
 // RUNAS Command - Run As Different User
 // ============================================================================
 
+/// A shell session started by RUNAS
+struct RunasSession {
+    /// Process the shell ran in before
+    previous_process: *mut crate::ps::EProcess,
+    /// Process of the session
+    process: *mut crate::ps::EProcess,
+    /// User name before
+    previous_user: [u8; 32],
+    previous_user_len: usize,
+}
+
+/// Active RUNAS sessions, innermost last
+static mut RUNAS_SESSIONS: alloc::vec::Vec<RunasSession> = alloc::vec::Vec::new();
+
+/// Read a line from the keyboard without echoing it
+///
+/// Returns the number of bytes read into `buf`; input past its end is
+/// dropped.
+fn read_password(buf: &mut [u8]) -> usize {
+    let mut len = 0usize;
+    loop {
+        match crate::hal::keyboard::read_char() {
+            b'\r' | b'\n' => break,
+            0x08 | 0x7F => len = len.saturating_sub(1),
+            c if c >= 0x20 && len < buf.len() => {
+                buf[len] = c;
+                len += 1;
+            }
+            _ => {}
+        }
+    }
+    outln!("");
+    len
+}
+
+/// End the innermost RUNAS session
+///
+/// Moves the shell back to the process and user it ran as before.
+///
+/// # Returns
+/// `false` if no RUNAS session is active
+pub fn end_runas_session() -> bool {
+    use crate::ps::eprocess::process_flags;
+
+    let session = match unsafe { (*addr_of_mut!(RUNAS_SESSIONS)).pop() } {
+        Some(session) => session,
+        None => return false,
+    };
+
+    unsafe {
+        let token = crate::ps::ps_get_process_token(session.process);
+        crate::se::se_audit_logoff(
+            &alloc::format!("{}", (*token).user),
+            (*token).authentication_id.low_part as u64,
+        );
+
+        crate::ps::ps_attach_current_thread(session.previous_process);
+        (*session.process).exit_time = crate::hal::apic::get_tick_count();
+        (*session.process).set_flag(process_flags::PS_PROCESS_FLAGS_EXITING | process_flags::PS_PROCESS_FLAGS_DEAD);
+    }
+
+    let previous_user = core::str::from_utf8(&session.previous_user[..session.previous_user_len])
+        .unwrap_or("Administrator");
+    set_username(previous_user);
+    set_env_var("USERNAME", previous_user);
+    true
+}
+
 /// RUNAS command - run as different user
+///
+/// Logs the account on and moves the shell into a new cmd.exe process
+/// running with its token; EXIT returns to the previous session.
 pub fn cmd_runas(args: &[&str]) {
-    use crate::se::get_token_stats;
-    
-    use crate::ps::get_cid_stats;
     use crate::ex::eventlog::{log_info, log_warning, EventSource};
-    use crate::hal::apic::get_tick_count;
 
     if args.is_empty() || eq_ignore_case(args[0], "/?") || eq_ignore_case(args[0], "help") {
         outln!("Allows a user to run specific tools and programs with different");
         outln!("permissions than the user's current logon provides.");
         outln!("");
-        outln!("RUNAS [/profile] [/env] [/netonly] /user:user program");
+        outln!("RUNAS [/profile] [/env] /user:user program");
         outln!("");
         outln!("  /profile       Load the user's profile (default)");
         outln!("  /noprofile     Do not load the user's profile");
         outln!("  /env           Use current environment instead of user's");
-        outln!("  /netonly       Use credentials for remote access only");
-        outln!("  /savecred      Use credentials previously saved by user");
-        outln!("  /smartcard     Use credentials from a smart card");
-        outln!("  /trustlevel:   Run with specified trust level (e.g., 0x20000)");
-        outln!("  /user:user     User@Domain or Domain\\User format");
-        outln!("  program        Program to run");
+        outln!("  /user:user     User or .\\User or COMPUTER\\User");
+        outln!("  program        Program to run (cmd)");
+        outln!("");
+        outln!("EXIT returns to the previous user.");
         outln!("");
         outln!("Examples:");
         outln!("  runas /user:Administrator cmd");
-        outln!("  runas /user:DOMAIN\\admin \"mmc %windir%\\system32\\dsa.msc\"");
+        outln!("  runas /noprofile /user:bob cmd");
         return;
     }
 
     // Parse options
     let mut user = "";
     let mut program_args: alloc::vec::Vec<&str> = alloc::vec::Vec::new();
-    let mut load_profile = true;
-    let mut use_current_env = false;
-    let mut netonly = false;
-    let mut savecred = false;
-    let mut smartcard = false;
 
     for arg in args {
         let upper = arg.to_ascii_uppercase();
         if upper.starts_with("/USER:") {
             user = &arg[6..];
-        } else if upper == "/NOPROFILE" {
-            load_profile = false;
-        } else if upper == "/PROFILE" {
-            load_profile = true;
-        } else if upper == "/ENV" {
-            use_current_env = true;
-        } else if upper == "/NETONLY" {
-            netonly = true;
-        } else if upper == "/SAVECRED" {
-            savecred = true;
-        } else if upper == "/SMARTCARD" {
-            smartcard = true;
+        } else if upper == "/NOPROFILE" || upper == "/PROFILE" || upper == "/ENV" {
+            // Profiles and environments are shared by every shell session
+        } else if upper == "/NETONLY" || upper == "/SAVECRED" || upper == "/SMARTCARD" {
+            outln!("RUNAS ERROR: {} is not supported.", arg);
+            return;
         } else if !arg.starts_with('/') {
             program_args.push(arg);
         }
@@ -25443,63 +27244,89 @@ pub fn cmd_runas(args: &[&str]) {
 
     let program = program_args.join(" ");
 
-    // Parse user into domain\user or user@domain format
-    let (domain, username) = if user.contains('\\') {
-        let parts: alloc::vec::Vec<&str> = user.splitn(2, '\\').collect();
-        (parts[0], *parts.get(1).unwrap_or(&""))
-    } else if user.contains('@') {
-        let parts: alloc::vec::Vec<&str> = user.splitn(2, '@').collect();
-        (*parts.get(1).unwrap_or(&""), parts[0])
-    } else {
-        (".", user)
+    // Only local accounts: user, .\user or COMPUTER\user
+    let username = match user.split_once('\\') {
+        Some((domain, name)) if domain == "." || eq_ignore_case(domain, get_hostname()) => name,
+        Some(_) => {
+            outln!("RUNAS ERROR: Unable to run - {}", program);
+            outln!("1311: There are currently no logon servers available to service the logon request.");
+            return;
+        }
+        None => user,
     };
 
-    let token_stats = get_token_stats();
-    let cid_stats = get_cid_stats();
-    let start_ticks = get_tick_count();
+    if !eq_ignore_case(program_args[0], "cmd") && !eq_ignore_case(program_args[0], "cmd.exe") {
+        outln!("RUNAS ERROR: Unable to run - {}", program);
+        outln!("Only cmd can be run as another user.");
+        return;
+    }
 
-    outln!("");
-    outln!("RUNAS: Credential request");
-    outln!("  User:       {}", user);
-    outln!("  Domain:     {}", if domain == "." { "(local)" } else { domain });
-    outln!("  Username:   {}", username);
-    outln!("");
+    out!("Enter the password for {}: ", user);
+    let mut password = [0u8; 64];
+    let password_len = read_password(&mut password);
 
-    if smartcard {
-        outln!("Attempting smart card logon...");
-    } else if savecred {
-        outln!("Checking credential manager for saved credentials...");
-    } else {
-        outln!("Enter the password for {}:", user);
-        outln!("[Password input simulated]");
+    outln!("Attempting to start {} as user \"{}\\{}\" ...", program, get_hostname(), username);
+
+    let token = match unsafe { crate::se::se_logon_user(username, &password[..password_len]) } {
+        Ok(token) => token,
+        Err(e) => {
+            outln!("RUNAS ERROR: Unable to run - {}", program);
+            outln!("{}: {}", e.win32_error(), e.message());
+            log_warning(EventSource::Security, 4033, &alloc::format!(
+                "RUNAS: Logon failed for '{}' ({})", username, e.win32_error()
+            ));
+            return;
+        }
+    };
+    password.fill(0);
+
+    // The account's name as stored, not as typed
+    let account = crate::se::se_lookup_account(username);
+    let account_name = account
+        .as_ref()
+        .and_then(|a| core::str::from_utf8(a.get_username()).ok())
+        .unwrap_or(username);
+
+    let current = crate::ps::get_current_process();
+    let process = unsafe { crate::ps::ps_create_process(current, b"cmd.exe", 8) };
+    if process.is_null() {
+        unsafe { crate::se::se_free_token(token) };
+        outln!("RUNAS ERROR: Unable to run - {}", program);
+        outln!("1450: Insufficient system resources exist to complete the requested service.");
+        return;
     }
 
-    outln!("");
-    outln!("Attempting to start {} as user \"{}\"...", program, user);
-    outln!("");
+    let mut previous_user = [0u8; 32];
+    let previous = get_username().as_bytes();
+    previous_user[..previous.len()].copy_from_slice(previous);
 
-    // Show security context information
-    outln!("Security context:");
-    outln!("  Current tokens:     {}", token_stats.allocated_tokens);
-    outln!("  Primary tokens:     {}", token_stats.primary_tokens);
-    outln!("  Active processes:   {}", cid_stats.active_processes);
-    outln!("");
+    let pid = unsafe {
+        if !current.is_null() {
+            (*process).session_id = (*current).session_id;
+        }
+        crate::ps::ps_set_primary_token(process, token);
 
-    outln!("Impersonation request:");
-    outln!("  Load profile:       {}", if load_profile { "Yes" } else { "No" });
-    outln!("  Environment:        {}", if use_current_env { "Current" } else { "User's" });
-    outln!("  Network only:       {}", if netonly { "Yes" } else { "No" });
-    outln!("");
+        (*addr_of_mut!(RUNAS_SESSIONS)).push(RunasSession {
+            previous_process: current,
+            process,
+            previous_user,
+            previous_user_len: previous.len(),
+        });
+        crate::ps::ps_attach_current_thread(process);
+        (*process).unique_process_id
+    };
 
-    // Simulate process creation
-    let simulated_pid = 0x2000 + ((start_ticks & 0xFFF) as u32);
-    outln!("Process created with impersonation:");
-    outln!("  PID:        {:04X}", simulated_pid);
-    outln!("  Command:    {}", program);
-    outln!("  Token type: Impersonation");
+    set_username(account_name);
+    set_env_var("USERNAME", account_name);
+
+    outln!("");
+    outln!("Running as {}\\{} ({} integrity), PID {}. Type EXIT to return to {}.",
+        get_hostname(), account_name,
+        unsafe { (*token).integrity_level().name() }, pid,
+        core::str::from_utf8(&previous_user[..previous.len()]).unwrap_or(""));
 
     log_info(EventSource::Security, 4032, &alloc::format!(
-        "RUNAS: Started '{}' as user '{}' (PID {:04X})", program, user, simulated_pid
+        "RUNAS: Started '{}' as user '{}' (PID {})", program, account_name, pid
     ));
 }
 
@@ -25723,12 +27550,25 @@ pub fn cmd_wmic(args: &[&str]) {
         outln!("  USERACCOUNT     User accounts");
         outln!("  WMISTATS        WMI subsystem statistics");
         outln!("");
+        outln!("Driver data blocks: WMIC [PATH] <class> [WHERE <instance>] <verb>");
+        outln!("  (classes are listed by WMIC WMISTATS)");
+        outln!("");
         outln!("Verbs: GET, LIST, CALL, SET");
         outln!("");
         outln!("Examples:");
         outln!("  wmic cpu get name,numberofcores");
         outln!("  wmic os get caption,version");
         outln!("  wmic process list brief");
+        outln!("  wmic virtioblk_statistics get requests,bytes");
+        outln!("  wmic path virtioblk_statistics where vda set errors=0");
+        return;
+    }
+
+    if eq_ignore_case(args[0], "PATH") {
+        match args.get(1) {
+            Some(class) => wmic_class(class, &args[2..]),
+            None => outln!("Invalid GET Expression."),
+        }
         return;
     }
 
@@ -26025,12 +27865,166 @@ pub fn cmd_wmic(args: &[&str]) {
             }
         }
 
+        let blocks = crate::wmi::wmi_enumerate_blocks();
+        if !blocks.is_empty() {
+            outln!("");
+            outln!("Data Blocks:");
+            outln!("  GUID                                    Provider  Instances  Class");
+            for block in &blocks {
+                outln!(
+                    "  {}  {:>8}  {:>9}  {}",
+                    block.guid,
+                    block.provider_id,
+                    block.instance_count,
+                    block.layout.map(|l| l.class_name).unwrap_or("-")
+                );
+            }
+        }
+
+    } else if crate::wmi::wmi_find_class(args[0]).is_some() {
+        wmic_class(args[0], &args[1..]);
     } else {
         outln!("Alias not found: {}", alias);
         outln!("Use 'wmic' for list of aliases.");
     }
 }
 
+/// WMIC on a driver-registered data block: `<class> [WHERE <instance>] <verb>`
+///
+/// The instance is an index or an instance name; without one, GET and
+/// LIST cover every instance and SET changes instance 0.
+fn wmic_class(class: &str, args: &[&str]) {
+    use crate::wmi::{wmi_find_class, wmi_instance_name, wmi_query_instance, wmi_set_item};
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    let Some(info) = wmi_find_class(class) else {
+        outln!("ERROR:");
+        outln!("Description = Invalid class");
+        return;
+    };
+    let Some(layout) = info.layout else { return };
+
+    let mut args = args;
+    let mut instances: Vec<u32> = (0..info.instance_count).collect();
+    if args.len() >= 2 && eq_ignore_case(args[0], "WHERE") {
+        let wanted = args[1].trim_matches('"');
+        let index = wanted.parse::<u32>().ok().or_else(|| {
+            (0..info.instance_count).find(|&i| eq_ignore_case(&wmi_instance_name(&info.guid, i), wanted))
+        });
+        match index {
+            Some(i) if i < info.instance_count => instances = alloc::vec![i],
+            _ => {
+                outln!("No Instance(s) Available.");
+                return;
+            }
+        }
+        args = &args[2..];
+    }
+
+    let verb = args.first().copied().unwrap_or("LIST");
+    if eq_ignore_case(verb, "GET") {
+        // Properties may be split by commas, spaces or both
+        let joined = args[1..].join(",");
+        let wanted: Vec<&str> = joined.split(',').filter(|p| !p.is_empty()).collect();
+        let mut columns = Vec::new();
+        for name in &wanted {
+            match layout.field(name) {
+                Some(field) => columns.push(field),
+                None => {
+                    outln!("Invalid query");
+                    return;
+                }
+            }
+        }
+        if columns.is_empty() {
+            columns = layout.fields.iter().collect();
+        }
+
+        let mut rows = Vec::new();
+        for &i in &instances {
+            match wmi_query_instance(layout.class_name, i) {
+                Ok(values) => rows.push(
+                    columns
+                        .iter()
+                        .map(|c| {
+                            values
+                                .iter()
+                                .find(|(f, _)| f.name == c.name)
+                                .map(|(_, v)| alloc::format!("{}", v))
+                                .unwrap_or_default()
+                        })
+                        .collect::<Vec<String>>(),
+                ),
+                Err(e) => {
+                    outln!("ERROR: {}", e.description());
+                    return;
+                }
+            }
+        }
+
+        let widths: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(c, f)| rows.iter().map(|r| r[c].len()).max().unwrap_or(0).max(f.name.len()))
+            .collect();
+        let mut header = String::new();
+        for (f, w) in columns.iter().zip(&widths) {
+            header.push_str(&alloc::format!("{:<w$}  ", f.name, w = *w));
+        }
+        outln!("{}", header.trim_end());
+        for row in &rows {
+            let mut line = String::new();
+            for (value, w) in row.iter().zip(&widths) {
+                line.push_str(&alloc::format!("{:<w$}  ", value, w = *w));
+            }
+            outln!("{}", line.trim_end());
+        }
+    } else if eq_ignore_case(verb, "LIST") {
+        for &i in &instances {
+            match wmi_query_instance(layout.class_name, i) {
+                Ok(values) => {
+                    outln!("");
+                    outln!("InstanceName={}", wmi_instance_name(&info.guid, i));
+                    for (field, value) in values {
+                        outln!("{}={}", field.name, value);
+                    }
+                }
+                Err(e) => {
+                    outln!("ERROR: {}", e.description());
+                    return;
+                }
+            }
+        }
+        outln!("");
+    } else if eq_ignore_case(verb, "SET") {
+        if args.len() < 2 {
+            outln!("Invalid SET Expression.");
+            return;
+        }
+        let joined = args[1..].join(" ");
+        for assignment in joined.split(',') {
+            let Some((property, value)) = assignment.split_once('=') else {
+                outln!("Invalid SET Expression.");
+                return;
+            };
+            let instance = instances.first().copied().unwrap_or(0);
+            let name = wmi_instance_name(&info.guid, instance);
+            outln!("Updating property(s) of '{}.InstanceName=\"{}\"'", layout.class_name, name);
+            match wmi_set_item(layout.class_name, instance, property.trim(), value.trim()) {
+                Ok(()) => outln!("Property(s) update successful."),
+                Err(e) => {
+                    outln!("ERROR:");
+                    outln!("Description = {}", e.description());
+                    return;
+                }
+            }
+        }
+    } else {
+        outln!("Invalid verb. {} supports GET, LIST and SET.", layout.class_name);
+    }
+}
+
 // ============================================================================
 // DRIVERQUERY Command - List Installed Drivers
 // ============================================================================
@@ -26518,10 +28512,15 @@ pub fn cmd_fsutil(args: &[&str]) {
         outln!("  fsutil fsinfo volumeinfo C:  Volume information");
         outln!("  fsutil fsinfo ntfsinfo C:    NTFS-specific information");
         outln!("  fsutil fsinfo statistics C:  FS statistics");
-        outln!("  fsutil dirty query C:        Query dirty bit");
-        outln!("  fsutil volume diskfree C:    Disk free space");
+        outln!("  fsutil dirty query|set C:    Query or set the dirty bit");
+        outln!("  fsutil volume diskfree C:    Free space and cluster size");
+        outln!("  fsutil file queryextents <file> [vcn]");
+        outln!("  fsutil file queryallocranges <file>");
+        outln!("  fsutil file queryvaliddata <file>");
+        outln!("  fsutil sparse queryflag|setflag <file>");
         outln!("  fsutil file queryfilenamebyid");
         outln!("  fsutil behavior query        Query FS behavior options");
+        outln!("  fsutil behavior set disablewritecache 0|1");
         outln!("  fsutil usn                   USN journal commands");
         return;
     }
@@ -26575,6 +28574,7 @@ pub fn cmd_fsutil(args: &[&str]) {
                     FsType::Ext2 => "ext2",
                     FsType::Ext4 => "ext4",
                     FsType::Iso9660 => "CDFS",
+                    FsType::Plan9 => "9P",
                     FsType::Unknown => "RAW",
                 });
                 outln!("Device Path         : {}", mp.device_path_str());
@@ -26656,16 +28656,33 @@ pub fn cmd_fsutil(args: &[&str]) {
         }
 
     } else if subcmd == "dirty" {
-        if args.len() > 2 {
+        if args.len() > 2 && (args[1].eq_ignore_ascii_case("query") || args[1].eq_ignore_ascii_case("set")) {
             let drive = args[2].chars().next().unwrap_or('C').to_ascii_uppercase();
+            let root = alloc::format!("{}:\\", drive);
 
-            if let Some(_mp) = get_mount_point(drive) {
-                outln!("Volume - {}:\\ is NOT Dirty", drive);
-            } else {
+            if get_mount_point(drive).is_none() {
                 outln!("Volume - {}:\\ is not mounted", drive);
+            } else if args[1].eq_ignore_ascii_case("set") {
+                match fs::fsctl_volume(&root, &mut fs::FsControl::MarkVolumeDirty) {
+                    Ok(()) => {
+                        outln!("Volume - {}:\\ is now marked as dirty", drive);
+                        log_info(EventSource::FileSystem, 6003, &alloc::format!("FSUTIL: {}: marked dirty", drive));
+                    }
+                    Err(e) => outln!("Error: {:?}", e),
+                }
+            } else {
+                let mut request = fs::FsControl::IsVolumeDirty { dirty: false };
+                match fs::fsctl_volume(&root, &mut request) {
+                    Ok(()) => {
+                        let fs::FsControl::IsVolumeDirty { dirty } = request else { unreachable!() };
+                        outln!("Volume - {}:\\ is {}Dirty", drive, if dirty { "" } else { "NOT " });
+                    }
+                    Err(e) => outln!("Error: {:?}", e),
+                }
             }
         } else {
             outln!("Usage: fsutil dirty query <drive>");
+            outln!("       fsutil dirty set <drive>");
         }
 
     } else if subcmd == "volume" && args.len() > 1 {
@@ -26678,40 +28695,24 @@ pub fn cmd_fsutil(args: &[&str]) {
                 'C'
             };
 
-            let vol_stats = get_volume_stats();
-            let (snapshots, count) = io_get_volume_snapshots(8);
-
-            // Try to find the volume for this drive
-            let mut vol_size_bytes: u64 = 0;
-            let mut vol_free_bytes: u64 = 0;
-
-            for i in 0..count {
-                // First volume is C:, second is D:, etc.
-                let vol_letter = ((b'C' + i as u8) as char).to_ascii_uppercase();
-                if vol_letter == drive {
-                    let snap = &snapshots[i];
-                    vol_size_bytes = snap.size_mb as u64 * 1024 * 1024;
-                    // Assume 50% free for demonstration
-                    vol_free_bytes = vol_size_bytes / 2;
-                    break;
+            let mut request = fs::FsControl::GetVolumeData { data: fs::FsVolumeData::default() };
+            match fs::fsctl_volume(&alloc::format!("{}:\\", drive), &mut request) {
+                Ok(()) => {
+                    let fs::FsControl::GetVolumeData { data } = request else { unreachable!() };
+                    let cluster = data.bytes_per_cluster as u64;
+                    outln!("Total free bytes        : {:>16} ({})",
+                        data.free_clusters * cluster, format_size(data.free_clusters * cluster));
+                    outln!("Total bytes             : {:>16} ({})",
+                        data.total_clusters * cluster, format_size(data.total_clusters * cluster));
+                    outln!("Total quota free bytes  : {:>16}", data.free_clusters * cluster);
+                    outln!("Bytes per sector        : {:>16}", data.bytes_per_sector);
+                    outln!("Bytes per cluster       : {:>16}", data.bytes_per_cluster);
+                    outln!("Total clusters          : {:>16}", data.total_clusters);
+                    outln!("Free clusters           : {:>16}", data.free_clusters);
                 }
+                Err(e) => outln!("Error: {}: {:?}", drive, e),
             }
 
-            if vol_size_bytes == 0 {
-                // Fallback to total stats
-                vol_size_bytes = vol_stats.total_size_mb as u64 * 1024 * 1024;
-                vol_free_bytes = vol_size_bytes * 3 / 4;  // 75% free
-            }
-
-            outln!("");
-            outln!("Total # of free bytes        : {}", vol_free_bytes);
-            outln!("Total # of bytes             : {}", vol_size_bytes);
-            outln!("Total # of avail free bytes  : {}", vol_free_bytes);
-            outln!("");
-            outln!("Volume Statistics:");
-            outln!("  Active volumes: {}", vol_stats.active_volumes);
-            outln!("  Total size: {} MB", vol_stats.total_size_mb);
-
             log_info(EventSource::FileSystem, 6001, &alloc::format!("FSUTIL: Disk free query for {}:", drive));
         }
 
@@ -26727,6 +28728,30 @@ pub fn cmd_fsutil(args: &[&str]) {
             outln!("  memoryusage           = 1");
             outln!("  disablecompression    = 0");
             outln!("  disableencryption     = 0");
+            outln!("  disablewritecache     = {}", crate::fs::volume::write_cache_disabled() as u32);
+        } else if args.len() > 3 && args[1].eq_ignore_ascii_case("set")
+            && args[2].eq_ignore_ascii_case("disablewritecache")
+        {
+            let disable = match args[3] {
+                "0" => false,
+                "1" => true,
+                _ => {
+                    outln!("Usage: fsutil behavior set disablewritecache 0|1");
+                    return;
+                }
+            };
+            match crate::fs::volume::set_write_cache_policy(disable) {
+                Ok(count) => {
+                    outln!("Write caching {} on {} disk(s)",
+                        if disable { "disabled" } else { "enabled" }, count);
+                    log_info(EventSource::FileSystem, 6002, &alloc::format!(
+                        "FSUTIL: disablewritecache set to {}", disable as u32));
+                }
+                Err(e) => outln!("Error: cannot store setting: {:?}", e),
+            }
+        } else {
+            outln!("Usage: fsutil behavior query");
+            outln!("       fsutil behavior set disablewritecache 0|1");
         }
 
     } else if subcmd == "usn" {
@@ -26755,17 +28780,118 @@ pub fn cmd_fsutil(args: &[&str]) {
             } else if cmd2 == "layout" {
                 outln!("File layout information:");
                 outln!("  (requires valid file path)");
+            } else if cmd2 == "queryextents" && args.len() > 2 {
+                let start_vcn = args.get(3).and_then(|v| parse_number(v)).unwrap_or(0) as u64;
+                let mut extents = [fs::FsExtent::default(); 64];
+                let mut request = fs::FsControl::GetRetrievalPointers {
+                    start_vcn,
+                    extents: &mut extents,
+                    count: 0,
+                    more: false,
+                };
+                match fsutil_file_control(args[2], &mut request) {
+                    Ok(()) => {
+                        let fs::FsControl::GetRetrievalPointers { extents, count, more, .. } = request else { unreachable!() };
+                        if count == 0 {
+                            outln!("File has no allocated extents");
+                        }
+                        for extent in &extents[..count] {
+                            outln!("VCN: {:#x}  Clusters: {:#x}  LCN: {:#x}", extent.vcn, extent.clusters, extent.lcn);
+                        }
+                        if more {
+                            outln!("(more extents follow; rerun with a higher starting VCN)");
+                        }
+                    }
+                    Err(e) => outln!("Error: {}: {:?}", args[2], e),
+                }
+            } else if cmd2 == "queryallocranges" && args.len() > 2 {
+                let mut ranges = [fs::FsRange::default(); 64];
+                let mut request = fs::FsControl::QueryAllocatedRanges {
+                    ranges: &mut ranges,
+                    count: 0,
+                    more: false,
+                };
+                match fsutil_file_control(args[2], &mut request) {
+                    Ok(()) => {
+                        let fs::FsControl::QueryAllocatedRanges { ranges, count, more } = request else { unreachable!() };
+                        if count == 0 {
+                            outln!("File has no allocated ranges");
+                        }
+                        for range in &ranges[..count] {
+                            outln!("Offset: {:#x}  Length: {:#x}", range.offset, range.length);
+                        }
+                        if more {
+                            outln!("(more ranges not shown)");
+                        }
+                    }
+                    Err(e) => outln!("Error: {}: {:?}", args[2], e),
+                }
+            } else if cmd2 == "queryvaliddata" && args.len() > 2 {
+                let mut request = fs::FsControl::QueryFileData { sparse: false, valid_data_length: 0, allocation_size: 0 };
+                match fsutil_file_control(args[2], &mut request) {
+                    Ok(()) => {
+                        let fs::FsControl::QueryFileData { valid_data_length, allocation_size, .. } = request else { unreachable!() };
+                        outln!("Valid Data Length is {:#x}", valid_data_length);
+                        outln!("Allocation Size is   {:#x}", allocation_size);
+                    }
+                    Err(e) => outln!("Error: {}: {:?}", args[2], e),
+                }
+            } else {
+                outln!("Usage: fsutil file queryextents <file> [vcn]");
+                outln!("       fsutil file queryallocranges <file>");
+                outln!("       fsutil file queryvaliddata <file>");
+            }
+        } else {
+            outln!("Usage: fsutil file [queryextents|queryallocranges|queryvaliddata|layout|...]");
+        }
+
+    } else if subcmd == "sparse" {
+        if args.len() > 2 {
+            let cmd2 = args[1].to_ascii_lowercase();
+            if cmd2 == "queryflag" {
+                let mut request = fs::FsControl::QueryFileData { sparse: false, valid_data_length: 0, allocation_size: 0 };
+                match fsutil_file_control(args[2], &mut request) {
+                    Ok(()) => {
+                        let fs::FsControl::QueryFileData { sparse, .. } = request else { unreachable!() };
+                        if sparse {
+                            outln!("This file is set as sparse");
+                        } else {
+                            outln!("This file is NOT set as sparse");
+                        }
+                    }
+                    Err(e) => outln!("Error: {}: {:?}", args[2], e),
+                }
+            } else if cmd2 == "setflag" {
+                let mut request = fs::FsControl::SetSparse { sparse: true };
+                match fsutil_file_control(args[2], &mut request) {
+                    Ok(()) => outln!("Sparse flag set on {}", args[2]),
+                    Err(fs::FsStatus::NotSupported) => {
+                        outln!("Error: the file system does not support sparse files")
+                    }
+                    Err(e) => outln!("Error: {}: {:?}", args[2], e),
+                }
+            } else {
+                outln!("Usage: fsutil sparse [queryflag|setflag] <file>");
             }
         } else {
-            outln!("Usage: fsutil file [queryfilenamebyid|layout|...]");
+            outln!("Usage: fsutil sparse [queryflag|setflag] <file>");
         }
 
     } else {
         outln!("Invalid parameter: {}", subcmd);
-        outln!("Valid commands: fsinfo, dirty, volume, behavior, usn, file");
+        outln!("Valid commands: fsinfo, dirty, volume, behavior, usn, file, sparse");
     }
 }
 
+/// Open `path` and send it a file system control request
+fn fsutil_file_control(path: &str, request: &mut fs::FsControl) -> Result<(), fs::FsStatus> {
+    let path = alloc::string::String::from(resolve_path(path));
+    let handle = fs::open(&path, 0)?;
+    let result = fs::fsctl(handle, request);
+    let _ = fs::close(handle);
+    result
+}
+
 // ============================================================================
 // LOGOFF Command - Log Off User
 // ============================================================================
@@ -27902,6 +30028,7 @@ pub fn cmd_convert(args: &[&str]) {
             FsType::Ext2 => "ext2",
             FsType::Ext4 => "ext4",
             FsType::Iso9660 => "ISO9660",
+            FsType::Plan9 => "9P",
             FsType::Unknown => "Unknown",
         };
 
@@ -28478,10 +30605,11 @@ pub fn cmd_eventtriggers(args: &[&str]) {
                     EventType::FailureAudit => "AUDIT_FAIL",
                 };
                 // Truncate message if too long
-                let msg = if event.message.len() > 40 {
-                    format!("{}...", &event.message[..37])
+                let description = event.description();
+                let msg = if description.len() > 40 {
+                    format!("{}...", &description[..37])
                 } else {
-                    event.message.clone()
+                    description
                 };
                 outln!("{:>10} {:11} {:12} {}", i + 1, type_str, event.source.name(), msg);
             }
@@ -29470,7 +31598,7 @@ pub fn cmd_tracerpt(args: &[&str]) {
         outln!("Recent Events:");
         outln!("-----------------------------------");
         for event in events.iter() {
-            outln!("  [{}] {}: {}", event.event_type.name(), event.source.name(), event.message);
+            outln!("  [{}] {}: {}", event.event_type.name(), event.source.name(), event.description());
         }
         outln!("");
     }
@@ -29506,9 +31634,18 @@ pub fn cmd_query(args: &[&str]) {
 
         let cid_stats = get_cid_stats();
         let dt = get_datetime();
+        let current = crate::ob::ob_current_session_id();
+        let (sessions, count) = crate::ob::ob_get_session_snapshots();
 
-        outln!(" SESSIONNAME       USERNAME                 ID  STATE   TYPE        DEVICE");
-        outln!(" console           Administrator             0  Active  wdcon");
+        outln!(" SESSIONNAME       USERNAME                 ID  STATE   PROCS  WINSTA");
+        for s in &sessions[..count] {
+            let name = core::str::from_utf8(&s.name[..s.name_length as usize]).unwrap_or("?");
+            let user = if s.session_id == crate::ob::SESSION_SERVICES { "SYSTEM" } else { "Administrator" };
+            outln!("{}{:<17} {:<22} {:>3}  Active  {:>5}  {:>6}",
+                   if s.session_id == current { ">" } else { " " },
+                   name.to_ascii_lowercase().as_str(), user, s.session_id,
+                   s.process_count, s.window_station_count);
+        }
         outln!("");
         outln!("Active processes: {}", cid_stats.active_processes);
         outln!("Active threads:   {}", cid_stats.active_threads);
@@ -32578,7 +34715,10 @@ pub fn cmd_harderr(args: &[&str]) {
         outln!("  status       - Show hard error status (default)");
         outln!("  log          - Show error log");
         outln!("  clear        - Clear error log");
-        outln!("  raise <code> - Raise test error (hex)");
+        outln!("  raise <code> [option] [text...]");
+        outln!("               - Raise a test error; option is ok, okcancel,");
+        outln!("                 yesno, yesnocancel, retrycancel, ari or nowait;");
+        outln!("                 text fills the message's %1..%5");
         outln!("  enable       - Enable console error handler");
         outln!("");
         outln!("Hard errors are critical system errors that may require");
@@ -32650,8 +34790,9 @@ fn raise_test_error(args: &[&str]) {
     use crate::ex;
 
     if args.is_empty() {
-        outln!("Usage: harderr raise <status>");
+        outln!("Usage: harderr raise <status> [option] [text...]");
         outln!("  <status> in hex, e.g., 0xC0000001");
+        outln!("Example: harderr raise 0xC0000135 okcancel FOO.DLL");
         outln!("");
         outln!("Common status codes:");
         outln!("  0xC0000001  STATUS_UNSUCCESSFUL");
@@ -32676,24 +34817,22 @@ fn raise_test_error(args: &[&str]) {
         }
     };
 
-    outln!("Raising hard error 0x{:08X}...", status as u32);
+    use ex::HardErrorResponseOption as Opt;
+    let (options, strings) = match args.get(1).map(|s| s.to_ascii_lowercase()).as_deref() {
+        Some("ok") => (Opt::Ok, &args[2..]),
+        Some("okcancel") => (Opt::OkCancel, &args[2..]),
+        Some("yesno") => (Opt::YesNo, &args[2..]),
+        Some("yesnocancel") => (Opt::YesNoCancel, &args[2..]),
+        Some("retrycancel") => (Opt::RetryCancel, &args[2..]),
+        Some("ari") => (Opt::AbortRetryIgnore, &args[2..]),
+        Some("nowait") => (Opt::OkNoWait, &args[2..]),
+        _ => (Opt::Ok, &args[1..]),
+    };
 
-    let response = ex::ex_raise_hard_error(
-        status,
-        0,
-        0,
-        &[],
-        ex::HardErrorResponseOption::Ok,
-    );
+    outln!("Raising hard error 0x{:08X}...", status as u32);
 
-    match response {
-        Ok(r) => {
-            outln!("Response: {:?}", r);
-        }
-        Err(e) => {
-            outln!("Error: 0x{:08X}", e as u32);
-        }
-    }
+    let response = ex::ex_raise_hard_error_with_strings(status, strings, options);
+    outln!("Response: {:?}", response);
 }
 
 fn enable_error_handler() {
@@ -33208,6 +35347,10 @@ pub fn cmd_etw(args: &[&str]) {
             let drop_rate = (stats.events_dropped * 100) / (stats.total_events + stats.events_dropped);
             outln!("  Drop Rate:      {}%", drop_rate);
         }
+        outln!("");
+        outln!("Scheduler Trace (NT Kernel Logger):");
+        outln!("  Events:        {}", stats.sched_events);
+        outln!("  Overwritten:   {}", stats.sched_events_lost);
 
     } else if eq_ignore_case(cmd, "info") {
         outln!("ETW Subsystem Information");
@@ -33238,6 +35381,249 @@ pub fn cmd_etw(args: &[&str]) {
     }
 }
 
+/// Kernel logger trace control (scheduler events)
+pub fn cmd_trace(args: &[&str]) {
+    use crate::etw::{self, KernelTraceFlag};
+
+    if args.is_empty() || eq_ignore_case(args[0], "help") {
+        outln!("Usage: trace <command>");
+        outln!("");
+        outln!("Commands:");
+        outln!("  start [cswitch|dispatcher|all]  Start scheduler tracing (default: all)");
+        outln!("  stop                            Stop tracing, keeping events");
+        outln!("  status                          Show trace state");
+        outln!("  show [n]                        Show the last n events (default 20)");
+        outln!("  cswitch [n]                     Top n threads by context switches");
+        outln!("  dump <file>                     Write events in binary dump format");
+        return;
+    }
+
+    let cmd = args[0];
+    let ns = |ticks: u64| crate::perf::syscall::ticks_to_ns(ticks);
+
+    if eq_ignore_case(cmd, "start") {
+        let which = args.get(1).copied().unwrap_or("all");
+        let flags = if eq_ignore_case(which, "cswitch") {
+            KernelTraceFlag::ContextSwitch as u32
+        } else if eq_ignore_case(which, "dispatcher") {
+            KernelTraceFlag::Dispatcher as u32
+        } else if eq_ignore_case(which, "all") {
+            etw::SCHED_TRACE_FLAGS_MASK
+        } else {
+            outln!("Unknown event group: {} (cswitch, dispatcher, all)", which);
+            return;
+        };
+        etw::sched_trace_start(flags);
+        outln!("Scheduler tracing started (flags {:#010x}, {} event ring)",
+            flags, etw::SCHED_TRACE_CAPACITY);
+
+    } else if eq_ignore_case(cmd, "stop") {
+        etw::sched_trace_stop();
+        outln!("Scheduler tracing stopped ({} events held)", etw::sched_trace_event_count());
+
+    } else if eq_ignore_case(cmd, "status") {
+        let flags = etw::sched_trace_flags();
+        outln!("Scheduler Trace Status");
+        outln!("");
+        outln!("  Tracing:      {}", if flags != 0 { "ACTIVE" } else { "STOPPED" });
+        outln!("  CSwitch:      {}", if flags & KernelTraceFlag::ContextSwitch as u32 != 0 { "on" } else { "off" });
+        outln!("  Dispatcher:   {}", if flags & KernelTraceFlag::Dispatcher as u32 != 0 { "on" } else { "off" });
+        outln!("  Events:       {}", etw::sched_trace_total_events());
+        outln!("  Held:         {}", etw::sched_trace_event_count());
+        outln!("  Overwritten:  {}", etw::sched_trace_lost_events());
+
+    } else if eq_ignore_case(cmd, "show") {
+        let max = args.get(1).and_then(|s| parse_number(s)).unwrap_or(20);
+        let count = etw::sched_trace_event_count();
+        let start = count.saturating_sub(max);
+        if count == 0 {
+            outln!("No scheduler events. Use 'trace start' to begin tracing.");
+            return;
+        }
+
+        let base = etw::sched_trace_event(start).map(|e| e.timestamp).unwrap_or(0);
+        outln!("{:>12} {:>3} {:<15} {:>5} {:>5} {:>4} {:>4} {}",
+            "TIME(ns)", "CPU", "EVENT", "OLD", "NEW", "OPRI", "NPRI", "DETAIL");
+        outln!("{}", "-".repeat(78).as_str());
+        for n in start..count {
+            let Some(e) = etw::sched_trace_event(n) else { break };
+            let detail = match e.event_type {
+                36 if e.old_state == crate::ke::thread::ThreadState::Waiting as u8 => alloc::format!(
+                    "old Waiting ({})", etw::wait_reason_name(e.wait_reason)),
+                36 => alloc::format!("old {}{}", etw::thread_state_name(e.old_state),
+                    if e.flags & etw::SCHED_FLAG_QUANTUM_END != 0 { ", quantum end" } else { "" }),
+                50 => alloc::format!("from {}", etw::thread_state_name(e.old_state)),
+                61 => alloc::format!("boost +{}", e.data),
+                _ => alloc::string::String::new(),
+            };
+            outln!("{:>12} {:>3} {:<15} {:>5} {:>5} {:>4} {:>4} {}",
+                ns(e.timestamp.wrapping_sub(base)), e.cpu, etw::sched_event_name(e.event_type),
+                e.old_thread_id, e.new_thread_id, e.old_priority, e.new_priority, detail.as_str());
+        }
+
+    } else if eq_ignore_case(cmd, "cswitch") {
+        let limit = args.get(1).and_then(|s| parse_number(s)).unwrap_or(10);
+        let mut threads = alloc::vec::Vec::new();
+        let summary = etw::sched_trace_summarize(&mut threads);
+
+        if summary.context_switches == 0 {
+            outln!("No context switch events. Use 'trace start cswitch' to begin tracing.");
+            return;
+        }
+
+        outln!("Context Switch Summary");
+        outln!("");
+        outln!("  Events:            {} over {} ms", summary.events, ns(summary.span_ticks) / 1_000_000);
+        outln!("  Context switches:  {}", summary.context_switches);
+        outln!("  Ready threads:     {}", summary.ready_threads);
+        outln!("  Quantum ends:      {}", summary.quantum_ends);
+        outln!("  Priority boosts:   {}", summary.priority_boosts);
+        outln!("  Priority changes:  {}", summary.priority_changes);
+        if summary.threads_dropped > 0 {
+            outln!("  (events for {} threads beyond the first {} not counted)",
+                summary.threads_dropped, etw::SCHED_SUMMARY_MAX_THREADS);
+        }
+        outln!("");
+
+        threads.sort_unstable_by_key(|t| core::cmp::Reverse(t.switches));
+        outln!("{:>5} {:>9} {:>9} {:>7} {:>7} {:>12} {:>12}  {}",
+            "TID", "SWITCHES", "PREEMPTED", "QEND", "WAITS", "AVG RUN(us)", "TOTAL(us)", "LAST WAIT");
+        outln!("{}", "-".repeat(90).as_str());
+        for t in threads.iter().take(limit) {
+            outln!("{:>5} {:>9} {:>9} {:>7} {:>7} {:>12} {:>12}  {}",
+                t.thread_id, t.switches, t.preemptions, t.quantum_ends, t.waits,
+                ns(t.avg_run_ticks()) / 1000, ns(t.run_ticks) / 1000,
+                if t.waits > 0 { etw::wait_reason_name(t.last_wait_reason) } else { "-" });
+        }
+
+    } else if eq_ignore_case(cmd, "dump") {
+        let Some(file) = args.get(1) else {
+            outln!("Usage: trace dump <file>");
+            return;
+        };
+        let path = alloc::string::String::from(resolve_path(file));
+        match etw::sched_trace_dump(&path) {
+            Ok(n) => outln!("Wrote {} events to {}", n, path.as_str()),
+            Err(e) => outln!("Failed to write {}: {:?}", path.as_str(), e),
+        }
+
+    } else {
+        outln!("Unknown trace command: {}", cmd);
+        outln!("Use 'trace help' for usage information");
+    }
+}
+
+/// Deterministic record/replay of interrupts, timer values and keyboard input
+pub fn cmd_replay(args: &[&str]) {
+    use crate::hal::replay::{self, ReplayMode};
+
+    if args.is_empty() || eq_ignore_case(args[0], "help") {
+        outln!("Usage: replay <command>");
+        outln!("");
+        outln!("Commands:");
+        outln!("  record          Clear the log and start recording");
+        outln!("  stop            Stop recording or replaying, keeping the log");
+        outln!("  play            Replay the log (keyboard input, timer values)");
+        outln!("  status          Show record/replay state");
+        outln!("  show [n]        Show the first n log records (default 20)");
+        outln!("  save <file>     Write the log to a file");
+        outln!("  load <file>     Load a log written by 'replay save'");
+        return;
+    }
+
+    let cmd = args[0];
+
+    if eq_ignore_case(cmd, "record") {
+        replay::replay_start_recording();
+        outln!("Recording interrupts, timer values and keyboard input ({} record log)",
+            replay::REPLAY_CAPACITY);
+
+    } else if eq_ignore_case(cmd, "stop") {
+        replay::replay_stop();
+        outln!("Stopped ({} records held)", replay::replay_status().event_count);
+
+    } else if eq_ignore_case(cmd, "play") {
+        if replay::replay_start_replay() {
+            outln!("Replaying {} records; live keyboard input is ignored until the recorded input runs out",
+                replay::replay_status().event_count);
+        } else {
+            outln!("Log is empty. Use 'replay record' or 'replay load <file>' first.");
+        }
+
+    } else if eq_ignore_case(cmd, "status") {
+        let status = replay::replay_status();
+        outln!("Record/Replay Status");
+        outln!("");
+        outln!("  Mode:          {}", match status.mode {
+            ReplayMode::Off => "off",
+            ReplayMode::Record => "RECORDING",
+            ReplayMode::Replay => "REPLAYING",
+        });
+        outln!("  Records:       {}", status.event_count);
+        outln!("  Dropped:       {}", status.events_lost);
+        if status.mode != ReplayMode::Off {
+            outln!("  Elapsed:       {} ticks", status.elapsed_ticks);
+        }
+        if status.mode == ReplayMode::Replay {
+            outln!("  Keys fed:      {}", status.keys_replayed);
+            outln!("  Timer values:  {}", status.timers_replayed);
+            outln!("  IRQs matched:  {}", status.interrupts_matched);
+            outln!("  Divergences:   {}", status.divergences);
+            if let Some(d) = status.first_divergence {
+                outln!("  First:         record {} at tick {}: expected vector {}, got {}",
+                    d.index, d.tick, d.expected, d.actual);
+            }
+        }
+
+    } else if eq_ignore_case(cmd, "show") {
+        let max = args.get(1).and_then(|s| parse_number(s)).unwrap_or(20);
+        let count = replay::replay_status().event_count as usize;
+        if count == 0 {
+            outln!("Log is empty. Use 'replay record' to begin recording.");
+            return;
+        }
+        outln!("{:>6} {:>8} {:<12} {}", "INDEX", "TICK", "KIND", "DATA");
+        outln!("{}", "-".repeat(48).as_str());
+        for n in 0..count.min(max) {
+            let Some(e) = replay::replay_event(n) else { break };
+            match e.kind() {
+                Some(kind @ replay::ReplayEventKind::Interrupt) =>
+                    outln!("{:>6} {:>8} {:<12} vector {}", n, e.tick, kind.name(), e.data),
+                Some(kind @ replay::ReplayEventKind::Keyboard) =>
+                    outln!("{:>6} {:>8} {:<12} scancode {:#04x}", n, e.tick, kind.name(), e.data),
+                Some(kind) => outln!("{:>6} {:>8} {:<12} {}", n, e.tick, kind.name(), e.value),
+                None => outln!("{:>6} {:>8} {:<12}", n, e.tick, "?"),
+            }
+        }
+
+    } else if eq_ignore_case(cmd, "save") {
+        let Some(file) = args.get(1) else {
+            outln!("Usage: replay save <file>");
+            return;
+        };
+        let path = alloc::string::String::from(resolve_path(file));
+        match replay::replay_save(&path) {
+            Ok(n) => outln!("Wrote {} records to {}", n, path.as_str()),
+            Err(e) => outln!("Failed to write {}: {:?}", path.as_str(), e),
+        }
+
+    } else if eq_ignore_case(cmd, "load") {
+        let Some(file) = args.get(1) else {
+            outln!("Usage: replay load <file>");
+            return;
+        };
+        let path = alloc::string::String::from(resolve_path(file));
+        match replay::replay_load(&path) {
+            Ok(n) => outln!("Loaded {} records from {}", n, path.as_str()),
+            Err(e) => outln!("Failed to load {}: {:?}", path.as_str(), e),
+        }
+
+    } else {
+        outln!("Unknown replay command: {}", cmd);
+        outln!("Use 'replay help' for usage information");
+    }
+}
+
 // =============================================================================
 // PnP (Plug and Play) Command
 // =============================================================================
@@ -33772,6 +36158,123 @@ pub fn cmd_strace(args: &[&str]) {
     }
 }
 
+pub fn cmd_syscallstat(args: &[&str]) {
+    use crate::arch::x86_64::syscall::{syscall_name, MAX_SYSCALLS};
+    use crate::perf::syscall::{self as stats, ticks_to_ns};
+
+    let sub = args.first().copied().unwrap_or("top");
+
+    if eq_ignore_case(sub, "help") {
+        outln!("Usage: syscallstat [command]");
+        outln!("");
+        outln!("Commands:");
+        outln!("  top [n]      Hottest syscalls by call count (default)");
+        outln!("  time [n]     Syscalls by total time spent");
+        outln!("  hist <num>   Latency histogram for one syscall");
+        outln!("  cpu          Calls per processor");
+        outln!("  reset        Clear all counters");
+        outln!("  on | off     Enable or disable collection");
+        return;
+    }
+
+    if eq_ignore_case(sub, "top") || eq_ignore_case(sub, "time") {
+        let limit = match args.get(1) {
+            Some(s) => parse_number(s).unwrap_or(20),
+            None => 20,
+        };
+
+        let mut list: alloc::vec::Vec<stats::SyscallStat> = alloc::vec::Vec::with_capacity(MAX_SYSCALLS);
+        for n in 0..MAX_SYSCALLS {
+            let s = stats::syscall_stats_get(n);
+            if s.count > 0 {
+                list.push(s);
+            }
+        }
+        if eq_ignore_case(sub, "time") {
+            list.sort_unstable_by_key(|s| core::cmp::Reverse(s.total_ticks));
+        } else {
+            list.sort_unstable_by_key(|s| core::cmp::Reverse(s.count));
+        }
+
+        outln!("Syscall Statistics (collection {})",
+            if stats::syscall_stats_enabled() { "on" } else { "off" });
+        outln!("");
+        if list.is_empty() {
+            outln!("No syscalls recorded.");
+            return;
+        }
+
+        outln!("{:<5} {:<32} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "NUM", "NAME", "CALLS", "avg(ns)", "p50(ns)", "p99(ns)", "max(ns)");
+        outln!("{}", "-".repeat(93).as_str());
+        for s in list.iter().take(limit) {
+            outln!("{:<5} {:<32} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                s.number, syscall_name(s.number as usize), s.count,
+                ticks_to_ns(s.total_ticks / s.count),
+                ticks_to_ns(s.percentile_ticks(50)),
+                ticks_to_ns(s.percentile_ticks(99)),
+                ticks_to_ns(s.max_ticks));
+        }
+        let total: u64 = list.iter().map(|s| s.count).sum();
+        outln!("");
+        outln!("{} distinct syscalls, {} calls", list.len(), total);
+
+    } else if eq_ignore_case(sub, "hist") {
+        let num = match args.get(1).and_then(|s| parse_number(s)) {
+            Some(n) if n < MAX_SYSCALLS => n,
+            _ => {
+                outln!("Usage: syscallstat hist <num> (0-{})", MAX_SYSCALLS - 1);
+                return;
+            }
+        };
+        let s = stats::syscall_stats_get(num);
+        outln!("{} ({}): {} calls", syscall_name(num), num, s.count);
+        if s.count == 0 {
+            return;
+        }
+        outln!("");
+
+        let peak = s.buckets.iter().copied().max().unwrap_or(1).max(1) as u64;
+        for (i, &b) in s.buckets.iter().enumerate() {
+            if b == 0 {
+                continue;
+            }
+            let upper = if i == stats::SYSCALL_HIST_BUCKETS - 1 {
+                alloc::string::String::from("inf")
+            } else {
+                alloc::format!("{}", ticks_to_ns(stats::bucket_upper_ticks(i)))
+            };
+            let bar = (b as u64 * 40).div_ceil(peak) as usize;
+            outln!("  < {:>10} ns {:>10} {}", upper.as_str(), b, "#".repeat(bar).as_str());
+        }
+
+    } else if eq_ignore_case(sub, "cpu") {
+        outln!("{:<5} {:>12}", "CPU", "CALLS");
+        for cpu in 0..stats::SYSCALL_STAT_CPUS {
+            let total = stats::syscall_stats_cpu_total(cpu);
+            if total > 0 {
+                outln!("{:<5} {:>12}", cpu, total);
+            }
+        }
+
+    } else if eq_ignore_case(sub, "reset") {
+        stats::syscall_stats_reset();
+        outln!("Syscall statistics cleared");
+
+    } else if eq_ignore_case(sub, "on") {
+        stats::syscall_stats_set_enabled(true);
+        outln!("Syscall statistics enabled");
+
+    } else if eq_ignore_case(sub, "off") {
+        stats::syscall_stats_set_enabled(false);
+        outln!("Syscall statistics disabled");
+
+    } else {
+        outln!("Unknown syscallstat command: {}", sub);
+        outln!("Use 'syscallstat help' for usage information");
+    }
+}
+
 // ============================================================================
 // DBGK (Kernel Debugger) Command
 // ============================================================================
@@ -33871,3 +36374,1280 @@ pub fn cmd_dbgk(args: &[&str]) {
         outln!("Use 'dbgk' for usage information");
     }
 }
+
+/// Script interpreter command
+pub fn cmd_script(args: &[&str]) {
+    if args.is_empty() || eq_ignore_case(args[0], "help") {
+        outln!("Usage: script <command> [args]");
+        outln!("");
+        outln!("Commands:");
+        outln!("  run <file>         Run a BASIC script file");
+        outln!("  eval <statements>  Run statements given on the command line");
+        outln!("  help               Show this help");
+        outln!("");
+        outln!("Example (C:\\TESTS\\FS.BAS):");
+        outln!("  10 MKDIR \"C:\\T\"");
+        outln!("  20 FOR I = 1 TO 1000");
+        outln!("  30   FCREATE \"C:\\T\\F\" + STR$(I), \"data\" + STR$(I)");
+        outln!("  40   ASSERT ERR = 0, \"create failed\"");
+        outln!("  50 NEXT I");
+        outln!("  60 ASSERT FCOUNT(\"C:\\T\") = 1000");
+        outln!("");
+        outln!("Bindings: FCREATE FAPPEND FDELETE FRENAME FCOPY MKDIR RMDIR,");
+        outln!("  FEXISTS FSIZE FREAD$ FCOUNT, REGSET REGDEL REG$ REGDW,");
+        outln!("  PROCS THREADS PID PNAME$, RND TICKS ERR");
+        return;
+    }
+
+    let outcome = if eq_ignore_case(args[0], "run") {
+        if args.len() < 2 {
+            outln!("Usage: script run <file>");
+            return;
+        }
+        let path = alloc::string::String::from(resolve_path(args[1]));
+        match super::script::run_file(&path) {
+            Ok(o) => o,
+            Err(e) => {
+                outln!("script: cannot read {}: {:?}", path, e);
+                return;
+            }
+        }
+    } else if eq_ignore_case(args[0], "eval") {
+        if args.len() < 2 {
+            outln!("Usage: script eval <statements>");
+            return;
+        }
+        let source = args[1..].join(" ");
+        super::script::run_source(&source)
+    } else {
+        outln!("Unknown script command: {}", args[0]);
+        return;
+    };
+
+    match outcome.error {
+        Some((msg, line)) => {
+            outln!("script: error at line {}: {}", line, msg);
+        }
+        None => {
+            if outcome.asserts > 0 {
+                outln!("script: ok ({} statements, {} asserts passed)", outcome.steps, outcome.asserts);
+            }
+        }
+    }
+}
+
+/// Stress test command
+pub fn cmd_stress(args: &[&str]) {
+    use crate::perf::stress::{self, StressConfig, StressReport};
+
+    fn show_report(r: &StressReport) {
+        outln!("Stress Run ({} ms, {}/{} workers finished)", r.elapsed_ms, r.workers_finished, r.workers_started);
+        outln!("");
+        outln!("  Config:   fs={} pool={} threads={} seed={:#x}",
+            r.config.fs_workers, r.config.pool_workers, r.config.thread_workers, r.config.seed);
+        outln!("  FS:       {} ops, {} errors", r.fs_ops, r.fs_errors);
+        outln!("  Pool:     {} allocs, {} frees, {} failures", r.pool_allocs, r.pool_frees, r.pool_failures);
+        outln!("  Threads:  {} created, {} exited, {} create failures",
+            r.threads_created, r.threads_exited, r.thread_failures);
+        outln!("  Invariant failures: {}", r.invariant_failures);
+        outln!("");
+        outln!("Result: {}", if r.passed() { "PASS" } else { "FAIL" });
+    }
+
+    if args.is_empty() || eq_ignore_case(args[0], "help") {
+        outln!("Usage: stress <command> [options]");
+        outln!("");
+        outln!("Commands:");
+        outln!("  run [options]   Run workers for a duration and report");
+        outln!("  last            Show the report of the last run");
+        outln!("");
+        outln!("Options (for run):");
+        outln!("  fs=<n>          File system workers (default 2)");
+        outln!("  pool=<n>        Pool alloc/free workers (default 2)");
+        outln!("  threads=<n>     Thread create/exit workers (default 1)");
+        outln!("  time=<secs>     Duration in seconds (default 10)");
+        outln!("  seed=<n>        Random seed");
+        outln!("  dir=<path>      Base directory for fs workers (default C:\\STRESS)");
+        outln!("");
+        outln!("Maximum {} workers of each kind.", stress::MAX_WORKERS_PER_KIND);
+        return;
+    }
+
+    if eq_ignore_case(args[0], "last") {
+        match stress::stress_last_report() {
+            Some(r) => show_report(&r),
+            None => outln!("No stress run has completed."),
+        }
+    } else if eq_ignore_case(args[0], "run") {
+        let mut config = StressConfig::new();
+        let mut dir = alloc::string::String::from("C:\\STRESS");
+
+        for opt in &args[1..] {
+            let (key, value) = match opt.split_once('=') {
+                Some(kv) => kv,
+                None => {
+                    outln!("Invalid option: {} (expected key=value)", opt);
+                    return;
+                }
+            };
+            if eq_ignore_case(key, "dir") {
+                dir = alloc::string::String::from(resolve_path(value));
+                continue;
+            }
+            let n = match parse_number(value) {
+                Some(n) => n,
+                None => {
+                    outln!("Invalid number: {}", value);
+                    return;
+                }
+            };
+            if eq_ignore_case(key, "fs") {
+                config.fs_workers = n as u32;
+            } else if eq_ignore_case(key, "pool") {
+                config.pool_workers = n as u32;
+            } else if eq_ignore_case(key, "threads") {
+                config.thread_workers = n as u32;
+            } else if eq_ignore_case(key, "time") {
+                config.duration_ms = n as u64 * 1000;
+            } else if eq_ignore_case(key, "seed") {
+                config.seed = n as u32;
+            } else {
+                outln!("Unknown option: {}", key);
+                return;
+            }
+        }
+
+        outln!("Running stress test for {} s...", config.duration_ms / 1000);
+        match stress::stress_run(config, &dir) {
+            Ok(r) => show_report(&r),
+            Err(e) => outln!("stress: {}", e),
+        }
+    } else {
+        outln!("Unknown stress command: {}", args[0]);
+    }
+}
+
+/// Kernel self-test command
+pub fn cmd_ktest(args: &[&str]) {
+    use crate::ktest::{self, results, KTestSummary, KTEST_SUITES};
+
+    fn show_summary(name: &str, s: &KTestSummary) {
+        outln!("");
+        outln!("{}: {}/{} tests passed, {} skipped, {} of {} checks failed -> {}",
+            name, s.tests - s.tests_failed, s.tests, s.tests_skipped, s.checks_failed, s.checks,
+            if s.passed() { "PASS" } else { "FAIL" });
+    }
+
+    if args.is_empty() || eq_ignore_case(args[0], "help") {
+        outln!("Usage: ktest <command>");
+        outln!("");
+        outln!("Commands:");
+        outln!("  list [suite]          List test suites, or the tests of one");
+        outln!("  run <suite[,suite]>   Run the named suites");
+        outln!("  all                   Run every suite");
+        outln!("");
+        outln!("Suites whose hardware is absent are skipped. Results are also");
+        outln!("written as JSON lines to COM{} when that port exists.", results::KTEST_RESULT_PORT);
+        outln!("Boot with ktest=<suite[,suite]|all> to run unattended.");
+        return;
+    }
+
+    if eq_ignore_case(args[0], "list") {
+        if let Some(name) = args.get(1) {
+            let Some(suite) = ktest::ktest_find_suite(name) else {
+                outln!("ktest: no suite named '{}'", name);
+                return;
+            };
+            outln!("{} - {}", suite.name, suite.description);
+            for requirement in suite.requires {
+                outln!("  {:<16} {}", requirement.name(),
+                    if requirement.is_met() { "present" } else { requirement.absent_reason() });
+            }
+            outln!("");
+            for test in suite.tests {
+                outln!("  {}", test.name);
+            }
+            return;
+        }
+
+        outln!("Test Suites");
+        outln!("");
+        outln!("  {:<10} {:>5}  {:<16} {:<8} {}", "Suite", "Tests", "Requires", "Status", "Description");
+        for suite in KTEST_SUITES {
+            let requires = suite.requires.iter().map(|r| r.name()).collect::<alloc::vec::Vec<_>>().join(",");
+            let status = if ktest::ktest_missing_requirement(suite).is_some() { "skip" } else { "ready" };
+            outln!("  {:<10} {:>5}  {:<16} {:<8} {}",
+                suite.name, suite.tests.len(), if requires.is_empty() { "-" } else { &requires },
+                status, suite.description);
+        }
+        outln!("");
+        outln!("Results port: COM{} ({})", results::KTEST_RESULT_PORT,
+            if results::ktest_results_enabled() { "present" } else { "absent" });
+    } else if eq_ignore_case(args[0], "run") {
+        let Some(list) = args.get(1) else {
+            outln!("Usage: ktest run <suite[,suite]>");
+            return;
+        };
+        match ktest::ktest_select_suites(list) {
+            Ok(suites) => {
+                let summary = ktest::ktest_run_suites(&suites, shell_progress);
+                show_summary(list, &summary);
+            }
+            Err(name) => outln!("ktest: no suite named '{}'", name),
+        }
+    } else if eq_ignore_case(args[0], "all") {
+        let total = ktest::ktest_run_suites(KTEST_SUITES, shell_progress);
+        show_summary("All suites", &total);
+    } else {
+        outln!("Unknown ktest command: {}", args[0]);
+    }
+}
+
+/// Benchmark command
+pub fn cmd_bench(args: &[&str]) {
+    use crate::perf::bench::{self, BenchWorkload, BENCH_WORKLOADS};
+
+    if args.is_empty() || eq_ignore_case(args[0], "help") {
+        outln!("Usage: bench <command> [args]");
+        outln!("");
+        outln!("Commands:");
+        outln!("  list                       List workloads");
+        outln!("  run <name|all> [ops] [dir] Run workload(s) for <ops> operations");
+        outln!("");
+        outln!("Default ops: {}. File workloads use a scratch file in [dir]", bench::BENCH_DEFAULT_OPS);
+        outln!("(default: current directory). Latencies are per operation.");
+        outln!("For diskread, [dir] names the block device (e.g. hda, vda).");
+        return;
+    }
+
+    if eq_ignore_case(args[0], "list") {
+        outln!("Benchmark Workloads");
+        outln!("");
+        for (name, _, desc) in BENCH_WORKLOADS {
+            outln!("  {:<10} {}", name, desc);
+        }
+    } else if eq_ignore_case(args[0], "run") {
+        if args.len() < 2 {
+            outln!("Usage: bench run <name|all> [ops] [dir]");
+            return;
+        }
+
+        let ops = match args.get(2) {
+            Some(s) => match parse_number(s) {
+                Some(n) if n > 0 && n <= bench::BENCH_MAX_OPS as usize => n as u32,
+                _ => {
+                    outln!("Invalid operation count: {} (1-{})", s, bench::BENCH_MAX_OPS);
+                    return;
+                }
+            },
+            None => bench::BENCH_DEFAULT_OPS,
+        };
+        let dir = alloc::string::String::from(match args.get(3) {
+            Some(d) => resolve_path(d),
+            None => get_current_dir(),
+        });
+        let dir = dir.trim_end_matches('\\');
+
+        let mut selected: alloc::vec::Vec<BenchWorkload> = alloc::vec::Vec::new();
+        if eq_ignore_case(args[1], "all") {
+            selected.extend(BENCH_WORKLOADS.iter().map(|(_, w, _)| *w));
+        } else {
+            match BenchWorkload::from_name(args[1]) {
+                Some(w) => selected.push(w),
+                None => {
+                    outln!("Unknown workload: {} (see 'bench list')", args[1]);
+                    return;
+                }
+            }
+        }
+
+        outln!("{:<10} {:>8} {:>10} {:>9} {:>9} {:>9} {:>9} {:>10}",
+            "Workload", "Ops", "Ops/sec", "p50(ns)", "p90(ns)", "p99(ns)", "max(ns)", "KB/s");
+        outln!("{}", "-".repeat(82).as_str());
+        for w in selected {
+            // diskread takes a block device name rather than a directory
+            let target = if w == BenchWorkload::DiskRead {
+                args.get(3).copied().unwrap_or("")
+            } else {
+                dir
+            };
+            match bench::bench_run(w, ops, target) {
+                Ok(r) => {
+                    if r.bytes > 0 {
+                        outln!("{:<10} {:>8} {:>10} {:>9} {:>9} {:>9} {:>9} {:>10}",
+                            w.name(), r.ops, r.ops_per_sec(), r.p50_ns, r.p90_ns, r.p99_ns, r.max_ns, r.kb_per_sec());
+                    } else {
+                        outln!("{:<10} {:>8} {:>10} {:>9} {:>9} {:>9} {:>9} {:>10}",
+                            w.name(), r.ops, r.ops_per_sec(), r.p50_ns, r.p90_ns, r.p99_ns, r.max_ns, "-");
+                    }
+                }
+                Err(e) => outln!("{:<10} failed: {:?}", w.name(), e),
+            }
+        }
+    } else {
+        outln!("Unknown bench command: {}", args[0]);
+    }
+}
+
+// ============================================================================
+// Dd - Block copy between files and raw devices
+// ============================================================================
+
+/// Largest block size `dd` accepts; the buffer comes from the kernel
+/// heap, so stay well inside the arena
+const DD_MAX_BLOCK_SIZE: usize = crate::mm::heap::KERNEL_HEAP_SIZE / 4;
+
+/// Interval between progress lines (ms)
+const DD_PROGRESS_MS: u64 = 1000;
+
+/// One side of a `dd` copy
+enum DdEndpoint {
+    /// File opened through the file system
+    File(u16),
+    /// Raw disk (`\\.\PhysicalDriveN`), positioned by sector; `through`
+    /// writes with forced unit access
+    Disk { index: u8, lba: u64, sector_size: usize, total_sectors: u64, through: bool },
+    /// `/dev/zero`: reads return zeros
+    Zero,
+    /// `/dev/null` or `NUL`: writes are discarded, reads end at once
+    Null,
+}
+
+impl DdEndpoint {
+    /// Open `name` for reading, or for writing when `write` is set
+    ///
+    /// `options` are file handle options (`fs::handle_options`).
+    fn open(name: &str, write: bool, truncate: bool, options: u32) -> Result<Self, alloc::string::String> {
+        use crate::io::block;
+
+        if eq_ignore_case(name, "/dev/zero") {
+            return Ok(DdEndpoint::Zero);
+        }
+        if eq_ignore_case(name, "/dev/null") || eq_ignore_case(name, "NUL") {
+            return Ok(DdEndpoint::Null);
+        }
+
+        if name.len() > 4 && name[..4].eq_ignore_ascii_case("\\\\.\\") {
+            let device = &name[4..];
+            let index = if device.len() > 13 && device[..13].eq_ignore_ascii_case("PhysicalDrive") {
+                device[13..].parse::<u8>().ok()
+            } else {
+                block::find_block_device(device)
+            };
+            let dev = index.and_then(block::get_block_device)
+                .ok_or_else(|| alloc::format!("{}: no such device", name))?;
+            if write && dev.is_readonly() {
+                return Err(alloc::format!("{}: device is write-protected", name));
+            }
+            return Ok(DdEndpoint::Disk {
+                index: dev.index,
+                lba: 0,
+                sector_size: dev.geometry.sector_size as usize,
+                total_sectors: dev.geometry.total_sectors,
+                through: options & fs::handle_options::FILE_WRITE_THROUGH != 0,
+            });
+        }
+
+        let path = alloc::string::String::from(resolve_path(name));
+        let handle = if !write {
+            fs::open(&path, 0)
+        } else if truncate {
+            fs::create(&path, 0)
+        } else {
+            match fs::open(&path, crate::io::file_access::FILE_GENERIC_WRITE) {
+                Err(fs::FsStatus::NotFound) => fs::create(&path, 0),
+                other => other,
+            }
+        };
+        let handle = handle.map_err(|e| alloc::format!("{}: {:?}", path, e))?;
+        if let Err(e) = fs::set_options(handle, options) {
+            let _ = fs::close(handle);
+            return Err(alloc::format!("{}: {:?}", path, e));
+        }
+        Ok(DdEndpoint::File(handle))
+    }
+
+    fn sector_size(&self) -> Option<usize> {
+        match self {
+            DdEndpoint::Disk { sector_size, .. } => Some(*sector_size),
+            _ => None,
+        }
+    }
+
+    /// Move forward `bytes` before the copy starts (skip= / seek=)
+    fn advance(&mut self, bytes: u64) -> Result<(), alloc::string::String> {
+        match self {
+            DdEndpoint::File(handle) => fs::seek(*handle, bytes as i64, fs::SeekWhence::Set)
+                .map(|_| ())
+                .map_err(|e| alloc::format!("seek failed: {:?}", e)),
+            DdEndpoint::Disk { lba, sector_size, total_sectors, .. } => {
+                *lba = bytes / *sector_size as u64;
+                if *lba > *total_sectors {
+                    return Err(alloc::format!("offset {} is past the end of the device", bytes));
+                }
+                Ok(())
+            }
+            DdEndpoint::Zero | DdEndpoint::Null => Ok(()),
+        }
+    }
+
+    /// Read up to `buf.len()` bytes; 0 at the end of the input
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, alloc::string::String> {
+        use crate::io::block::{self, BlockStatus};
+
+        match self {
+            DdEndpoint::File(handle) => fs::read(*handle, buf).map_err(|e| alloc::format!("read failed: {:?}", e)),
+            DdEndpoint::Disk { index, lba, sector_size, total_sectors, .. } => {
+                let sectors = ((buf.len() / *sector_size) as u64).min(*total_sectors - *lba);
+                if sectors == 0 {
+                    return Ok(0);
+                }
+                let bytes = sectors as usize * *sector_size;
+                match block::read_sectors(*index, *lba, sectors as u32, &mut buf[..bytes]) {
+                    BlockStatus::Success => {
+                        *lba += sectors;
+                        Ok(bytes)
+                    }
+                    status => Err(alloc::format!("read failed at sector {}: {:?}", lba, status)),
+                }
+            }
+            DdEndpoint::Zero => {
+                buf.fill(0);
+                Ok(buf.len())
+            }
+            DdEndpoint::Null => Ok(0),
+        }
+    }
+
+    /// Write all of `data`; disk writes must be whole sectors
+    fn write(&mut self, data: &[u8]) -> Result<usize, alloc::string::String> {
+        use crate::io::block::{self, BlockStatus};
+
+        match self {
+            DdEndpoint::File(handle) => match fs::write(*handle, data) {
+                Ok(n) if n == data.len() => Ok(n),
+                Ok(n) => Err(alloc::format!("short write ({} of {} bytes)", n, data.len())),
+                Err(e) => Err(alloc::format!("write failed: {:?}", e)),
+            },
+            DdEndpoint::Disk { index, lba, sector_size, total_sectors, through } => {
+                let sectors = (data.len() / *sector_size) as u64;
+                if *lba + sectors > *total_sectors {
+                    return Err(alloc::format!("end of device reached at sector {}", lba));
+                }
+                let status = if *through {
+                    block::write_sectors_fua(*index, *lba, sectors as u32, data)
+                } else {
+                    block::write_sectors(*index, *lba, sectors as u32, data)
+                };
+                match status {
+                    BlockStatus::Success => {
+                        *lba += sectors;
+                        Ok(data.len())
+                    }
+                    status => Err(alloc::format!("write failed at sector {}: {:?}", lba, status)),
+                }
+            }
+            DdEndpoint::Zero | DdEndpoint::Null => Ok(data.len()),
+        }
+    }
+
+    /// Push written data to the media (conv=fsync)
+    fn flush(&mut self) -> Result<(), alloc::string::String> {
+        use crate::io::block::{self, BlockStatus};
+
+        match self {
+            DdEndpoint::File(handle) => fs::sync(*handle).map_err(|e| alloc::format!("sync failed: {:?}", e)),
+            DdEndpoint::Disk { index, .. } => match block::flush_device(*index) {
+                BlockStatus::Success => Ok(()),
+                status => Err(alloc::format!("flush failed: {:?}", status)),
+            },
+            DdEndpoint::Zero | DdEndpoint::Null => Ok(()),
+        }
+    }
+
+    /// Leave non-cached I/O for a short last block, which need not be sector-sized
+    fn end_direct(&mut self) {
+        if let DdEndpoint::File(handle) = self {
+            if let Ok(options) = fs::vfs::vfs_handle_options(*handle) {
+                let _ = fs::set_options(*handle, options & !fs::handle_options::FILE_NO_INTERMEDIATE_BUFFERING);
+            }
+        }
+    }
+
+    fn close(self) {
+        if let DdEndpoint::File(handle) = self {
+            let _ = fs::close(handle);
+        }
+    }
+}
+
+/// Parse a dd size: a number with an optional k, M or G (binary) suffix
+fn dd_parse_size(s: &str) -> Option<u64> {
+    let (digits, multiplier) = match s.as_bytes().last()? {
+        b'k' | b'K' => (&s[..s.len() - 1], 1024),
+        b'm' | b'M' => (&s[..s.len() - 1], 1024 * 1024),
+        b'g' | b'G' => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Bytes per second as text
+fn dd_rate(bytes: u64, elapsed_ms: u64) -> alloc::string::String {
+    let per_sec = bytes * 1000 / elapsed_ms.max(1);
+    if per_sec >= 1024 * 1024 {
+        alloc::format!("{}.{} MB/s", per_sec / (1024 * 1024), per_sec % (1024 * 1024) * 10 / (1024 * 1024))
+    } else {
+        alloc::format!("{} KB/s", per_sec / 1024)
+    }
+}
+
+/// Copy blocks between files and raw devices
+pub fn cmd_dd(args: &[&str]) {
+    if args.is_empty() || args[0] == "/?" || eq_ignore_case(args[0], "help") {
+        outln!("Usage: dd if=<input> of=<output> [bs=N] [count=N] [skip=N] [seek=N]");
+        outln!("          [conv=...] [iflag=...] [oflag=...]");
+        outln!("");
+        outln!("Copies <count> blocks of <bs> bytes (default 512) from input to output.");
+        outln!("Sizes take k, M or G suffixes.");
+        outln!("");
+        outln!("  if=, of=   A file, a raw disk (\\\\.\\PhysicalDrive0, \\\\.\\hda),");
+        outln!("             /dev/zero (input) or /dev/null (NUL)");
+        outln!("  skip=N     Skip N input blocks first");
+        outln!("  seek=N     Skip N output blocks first (implies conv=notrunc)");
+        outln!("  conv=      notrunc  Do not truncate the output file");
+        outln!("             fsync    Flush the output to the media before finishing");
+        outln!("  iflag=direct        Read files without intermediate buffering");
+        outln!("  oflag=direct,sync   Write files without intermediate buffering and/or");
+        outln!("                      write through to the media (disks: forced unit access)");
+        outln!("");
+        outln!("Raw disks are read and written in whole sectors, so bs must be a");
+        outln!("multiple of the sector size; a short last block is padded with zeros.");
+        outln!("");
+        outln!("Example: dd if=\\\\.\\PhysicalDrive0 of=/dev/null bs=1M count=64");
+        return;
+    }
+
+    let mut input_name = None;
+    let mut output_name = None;
+    let mut block_size = 512u64;
+    let mut count = None;
+    let mut skip = 0u64;
+    let mut seek = 0u64;
+    let mut notrunc = false;
+    let mut fsync = false;
+    let mut input_options = 0u32;
+    let mut output_options = 0u32;
+
+    for arg in args {
+        let Some((key, value)) = arg.split_once('=') else {
+            outln!("dd: unrecognized operand '{}'", arg);
+            return;
+        };
+        let number = || dd_parse_size(value);
+        let parsed = if eq_ignore_case(key, "if") {
+            input_name = Some(value);
+            Some(())
+        } else if eq_ignore_case(key, "of") {
+            output_name = Some(value);
+            Some(())
+        } else if eq_ignore_case(key, "bs") {
+            number().map(|n| block_size = n)
+        } else if eq_ignore_case(key, "count") {
+            number().map(|n| count = Some(n))
+        } else if eq_ignore_case(key, "skip") {
+            number().map(|n| skip = n)
+        } else if eq_ignore_case(key, "seek") {
+            number().map(|n| seek = n)
+        } else if eq_ignore_case(key, "conv") {
+            for conv in value.split(',') {
+                if eq_ignore_case(conv, "notrunc") {
+                    notrunc = true;
+                } else if eq_ignore_case(conv, "fsync") {
+                    fsync = true;
+                } else {
+                    outln!("dd: unknown conversion '{}'", conv);
+                    return;
+                }
+            }
+            Some(())
+        } else if eq_ignore_case(key, "iflag") || eq_ignore_case(key, "oflag") {
+            let output = eq_ignore_case(key, "oflag");
+            for flag in value.split(',') {
+                let option = if eq_ignore_case(flag, "direct") {
+                    fs::handle_options::FILE_NO_INTERMEDIATE_BUFFERING
+                } else if output && (eq_ignore_case(flag, "sync") || eq_ignore_case(flag, "dsync")) {
+                    fs::handle_options::FILE_WRITE_THROUGH
+                } else {
+                    outln!("dd: unknown {} '{}'", key, flag);
+                    return;
+                };
+                if output {
+                    output_options |= option;
+                } else {
+                    input_options |= option;
+                }
+            }
+            Some(())
+        } else {
+            outln!("dd: unrecognized operand '{}'", arg);
+            return;
+        };
+        if parsed.is_none() {
+            outln!("dd: invalid number '{}'", value);
+            return;
+        }
+    }
+
+    let (Some(input_name), Some(output_name)) = (input_name, output_name) else {
+        outln!("dd: both if= and of= are required");
+        return;
+    };
+    if block_size == 0 || block_size > DD_MAX_BLOCK_SIZE as u64 {
+        outln!("dd: bs must be between 1 and {}", DD_MAX_BLOCK_SIZE);
+        return;
+    }
+    if eq_ignore_case(input_name, "/dev/zero") && count.is_none() {
+        outln!("dd: count= is required when reading /dev/zero");
+        return;
+    }
+
+    // Byte offsets must fit a signed file position
+    let offset = |blocks: u64| blocks.checked_mul(block_size).filter(|&b| b <= i64::MAX as u64);
+    let Some(skip_bytes) = offset(skip) else {
+        outln!("dd: skip={} is too large for bs={}", skip, block_size);
+        return;
+    };
+    let Some(seek_bytes) = offset(seek) else {
+        outln!("dd: seek={} is too large for bs={}", seek, block_size);
+        return;
+    };
+
+    let mut input = match DdEndpoint::open(input_name, false, false, input_options) {
+        Ok(e) => e,
+        Err(e) => {
+            outln!("dd: {}", e);
+            return;
+        }
+    };
+    let mut output = match DdEndpoint::open(output_name, true, !notrunc && seek == 0, output_options) {
+        Ok(e) => e,
+        Err(e) => {
+            outln!("dd: {}", e);
+            input.close();
+            return;
+        }
+    };
+
+    let result = dd_copy(&mut input, &mut output, block_size as usize, count, skip_bytes, seek_bytes, fsync);
+    input.close();
+    output.close();
+
+    let stats = match result {
+        Ok(stats) => stats,
+        Err((stats, e)) => {
+            outln!("dd: {}", e);
+            stats
+        }
+    };
+    let elapsed = stats.elapsed_ms.max(1);
+    outln!("{}+{} records in", stats.full_in, stats.partial_in);
+    outln!("{}+{} records out", stats.full_out, stats.partial_out);
+    outln!("{} bytes ({}) copied, {}.{:03} s, {}",
+        stats.bytes, format_size(stats.bytes), elapsed / 1000, elapsed % 1000, dd_rate(stats.bytes, elapsed));
+}
+
+/// Records and bytes moved by `dd`
+#[derive(Default)]
+struct DdStats {
+    full_in: u64,
+    partial_in: u64,
+    full_out: u64,
+    partial_out: u64,
+    bytes: u64,
+    elapsed_ms: u64,
+}
+
+fn dd_copy(
+    input: &mut DdEndpoint,
+    output: &mut DdEndpoint,
+    block_size: usize,
+    count: Option<u64>,
+    skip_bytes: u64,
+    seek_bytes: u64,
+    fsync: bool,
+) -> Result<DdStats, (DdStats, alloc::string::String)> {
+    let mut stats = DdStats::default();
+
+    for side in [input.sector_size(), output.sector_size()].into_iter().flatten() {
+        if !block_size.is_multiple_of(side) {
+            return Err((stats, alloc::format!("bs must be a multiple of the {}-byte sector size", side)));
+        }
+    }
+    if let Err(e) = input.advance(skip_bytes) {
+        return Err((stats, e));
+    }
+    if let Err(e) = output.advance(seek_bytes) {
+        return Err((stats, e));
+    }
+
+    let mut buffer = alloc::vec::Vec::new();
+    if buffer.try_reserve_exact(block_size).is_err() {
+        return Err((stats, alloc::format!("bs too large: cannot allocate {} bytes", block_size)));
+    }
+    buffer.resize(block_size, 0u8);
+    let start = crate::hal::apic::get_tick_count();
+    let mut next_progress = start + DD_PROGRESS_MS;
+    let mut progress_shown = false;
+
+    let result = loop {
+        if count.is_some_and(|c| stats.full_in + stats.partial_in >= c) {
+            break Ok(());
+        }
+
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) => break Err(e),
+        };
+        if read == block_size {
+            stats.full_in += 1;
+        } else {
+            stats.partial_in += 1;
+            output.end_direct();
+        }
+
+        // Raw disks take whole sectors: pad a short block with zeros
+        let length = match output.sector_size() {
+            Some(sector) if !read.is_multiple_of(sector) => {
+                let padded = read.next_multiple_of(sector);
+                buffer[read..padded].fill(0);
+                padded
+            }
+            _ => read,
+        };
+        match output.write(&buffer[..length]) {
+            Ok(written) => {
+                if written == block_size {
+                    stats.full_out += 1;
+                } else {
+                    stats.partial_out += 1;
+                }
+                stats.bytes += written as u64;
+            }
+            Err(e) => break Err(e),
+        }
+
+        let now = crate::hal::apic::get_tick_count();
+        if now >= next_progress {
+            next_progress = now + DD_PROGRESS_MS;
+            progress_shown = true;
+            out!("\r{} bytes ({}) copied, {} s, {}   ",
+                stats.bytes, format_size(stats.bytes), (now - start) / 1000, dd_rate(stats.bytes, now - start));
+        }
+    };
+
+    let result = result.and_then(|()| if fsync { output.flush() } else { Ok(()) });
+    stats.elapsed_ms = crate::hal::apic::get_tick_count() - start;
+    if progress_shown {
+        outln!("");
+    }
+    match result {
+        Ok(()) => Ok(stats),
+        Err(e) => Err((stats, e)),
+    }
+}
+
+// ============================================================================
+// Top - Per-process CPU usage
+// ============================================================================
+
+/// Maximum processes sampled by `top`
+const TOP_MAX_PROCESSES: usize = 64;
+
+#[derive(Clone, Copy)]
+struct TopSample {
+    pid: u32,
+    threads: u32,
+    time: u64,
+    name: [u8; 16],
+}
+
+/// Snapshot accumulated run time of every active process
+fn top_snapshot(out: &mut [TopSample; TOP_MAX_PROCESSES]) -> usize {
+    use crate::ps;
+
+    let mut count = 0;
+    unsafe {
+        let list_head = ps::get_active_process_list();
+        let mut entry = (*list_head).flink;
+        while entry != list_head && count < TOP_MAX_PROCESSES {
+            let process = crate::containing_record!(entry, ps::EProcess, active_process_links);
+            let image = (*process).image_name();
+            let mut name = [0u8; 16];
+            let n = image.len().min(16);
+            name[..n].copy_from_slice(&image[..n]);
+
+            out[count] = TopSample {
+                pid: (*process).process_id(),
+                threads: (*process).thread_count(),
+                time: (*process).pcb.kernel_time() + (*process).pcb.user_time(),
+                name,
+            };
+            count += 1;
+            entry = (*entry).flink;
+        }
+    }
+    count
+}
+
+/// Format 100ns units as h:mm:ss.cc
+fn format_cpu_time(time_100ns: u64) -> alloc::string::String {
+    let centis = time_100ns / 100_000;
+    let secs = centis / 100;
+    alloc::format!("{}:{:02}:{:02}.{:02}", secs / 3600, (secs / 60) % 60, secs % 60, centis % 100)
+}
+
+/// Show per-process CPU usage over a sampling interval
+pub fn cmd_top(args: &[&str]) {
+    use crate::hal::apic::get_tick_count;
+
+    if !args.is_empty() && (eq_ignore_case(args[0], "help") || args[0] == "/?") {
+        outln!("Usage: top [seconds]");
+        outln!("");
+        outln!("Samples process run time over an interval (default 1s) and");
+        outln!("shows CPU usage per process. Idle (PID 0) is the time the");
+        outln!("processors spent in their idle threads.");
+        return;
+    }
+
+    let seconds = match args.first() {
+        Some(s) => match parse_number(s) {
+            Some(n) if (1..=60).contains(&n) => n as u64,
+            _ => {
+                outln!("Invalid interval: {} (1-60 seconds)", s);
+                return;
+            }
+        },
+        None => 1,
+    };
+
+    let empty = TopSample { pid: 0, threads: 0, time: 0, name: [0; 16] };
+    let mut before = [empty; TOP_MAX_PROCESSES];
+    let mut after = [empty; TOP_MAX_PROCESSES];
+
+    let n_before = top_snapshot(&mut before);
+    let start = get_tick_count();
+    while get_tick_count().wrapping_sub(start) < seconds * 1000 {
+        unsafe { crate::ke::scheduler::ki_yield(); }
+    }
+    let elapsed = get_tick_count().wrapping_sub(start);
+    let n_after = top_snapshot(&mut after);
+
+    // Run time charged to each process during the interval
+    let mut deltas = [0u64; TOP_MAX_PROCESSES];
+    for (i, s) in after[..n_after].iter().enumerate() {
+        let prev = before[..n_before].iter().find(|b| b.pid == s.pid).map(|b| b.time).unwrap_or(0);
+        deltas[i] = s.time.saturating_sub(prev);
+    }
+    let total: u64 = deltas[..n_after].iter().sum();
+    let idle = after[..n_after].iter().zip(deltas.iter())
+        .find(|(s, _)| s.pid == crate::ke::process::IDLE_PROCESS_ID)
+        .map(|(_, &d)| d)
+        .unwrap_or(0);
+
+    let pct = |d: u64| -> (u64, u64) {
+        if total == 0 {
+            return (0, 0);
+        }
+        let tenths = (d * 1000 + total / 2) / total;
+        (tenths / 10, tenths % 10)
+    };
+
+    let (idle_whole, idle_frac) = pct(idle);
+    let (busy_whole, busy_frac) = pct(total - idle);
+    outln!("Interval: {} ms, CPUs: {}, Processes: {}",
+        elapsed, crate::ke::prcb::get_active_cpu_count(), n_after);
+    outln!("CPU: {}.{}% busy, {}.{}% idle", busy_whole, busy_frac, idle_whole, idle_frac);
+    outln!("");
+    outln!("{:<6} {:<16} {:>7} {:>6} {:>14}", "PID", "Name", "Threads", "CPU%", "CPU Time");
+    outln!("--------------------------------------------------------");
+
+    let mut order: alloc::vec::Vec<usize> = (0..n_after).collect();
+    order.sort_unstable_by_key(|&i| core::cmp::Reverse(deltas[i]));
+    for i in order {
+        let s = &after[i];
+        let len = s.name.iter().position(|&c| c == 0).unwrap_or(16);
+        let name = core::str::from_utf8(&s.name[..len]).unwrap_or("?");
+        let (whole, frac) = pct(deltas[i]);
+        outln!("{:<6} {:<16} {:>7} {:>3}.{:01}% {:>14}",
+            s.pid, name, s.threads, whole, frac, format_cpu_time(s.time).as_str());
+    }
+}
+
+/// Parse a GUID in registry format (8-4-4-4-12 hex digits)
+fn parse_efi_guid(text: &str) -> Option<crate::hal::efi::EfiGuid> {
+    let text = text.trim_start_matches('{').trim_end_matches('}');
+    let parts: [&str; 5] = {
+        let mut it = text.split('-');
+        [it.next()?, it.next()?, it.next()?, it.next()?, it.next()?]
+    };
+    if parts[0].len() != 8 || parts[1].len() != 4 || parts[2].len() != 4
+        || parts[3].len() != 4 || parts[4].len() != 12
+    {
+        return None;
+    }
+    let tail = u64::from_str_radix(parts[4], 16).ok()?.to_be_bytes();
+    let clock = u16::from_str_radix(parts[3], 16).ok()?.to_be_bytes();
+    Some(crate::hal::efi::EfiGuid {
+        data1: u32::from_str_radix(parts[0], 16).ok()?,
+        data2: u16::from_str_radix(parts[1], 16).ok()?,
+        data3: u16::from_str_radix(parts[2], 16).ok()?,
+        data4: [clock[0], clock[1], tail[2], tail[3], tail[4], tail[5], tail[6], tail[7]],
+    })
+}
+
+/// UEFI variables through runtime services
+pub fn cmd_efivar(args: &[&str]) {
+    use crate::hal::efi::{self, EfiGuid, EFI_GLOBAL_VARIABLE, EFI_MAX_VARIABLE_NAME};
+    use alloc::string::String;
+
+    if args.first().is_some_and(|a| eq_ignore_case(a, "help")) {
+        outln!("Usage: efivar [command]");
+        outln!("");
+        outln!("Commands:");
+        outln!("  list                 List all variables (default)");
+        outln!("  get <name> [guid]    Dump a variable (default: EFI global GUID)");
+        outln!("  set <name> <text>    Store text in a non-volatile global variable");
+        outln!("  delete <name> [guid] Delete a variable");
+        outln!("  bootorder            Show BootOrder and the Boot#### entries");
+        outln!("  fwsetup              Enter firmware setup on the next reboot");
+        return;
+    }
+    if !efi::efi_runtime_available() {
+        outln!("UEFI runtime services are not available");
+        return;
+    }
+
+    let cmd = args.first().copied().unwrap_or("list");
+    let guid_arg = |index: usize| -> Option<EfiGuid> {
+        match args.get(index) {
+            Some(text) => {
+                let guid = parse_efi_guid(text);
+                if guid.is_none() {
+                    outln!("Bad GUID: {}", text);
+                }
+                guid
+            }
+            None => Some(EFI_GLOBAL_VARIABLE),
+        }
+    };
+
+    if eq_ignore_case(cmd, "list") {
+        let mut name = [0u16; EFI_MAX_VARIABLE_NAME];
+        let mut guid = EFI_GLOBAL_VARIABLE;
+        let mut count = 0;
+        loop {
+            match efi::efi_next_variable_name(&mut name, &mut guid) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    outln!("GetNextVariableName failed: {}", e.name());
+                    break;
+                }
+            }
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            let mut text = String::new();
+            for c in char::decode_utf16(name[..len].iter().copied()) {
+                text.push(c.unwrap_or('?'));
+            }
+            outln!("  {} {}", guid, text);
+            count += 1;
+        }
+        outln!("{} variables", count);
+
+    } else if eq_ignore_case(cmd, "get") {
+        let Some(name) = args.get(1) else {
+            outln!("Usage: efivar get <name> [guid]");
+            return;
+        };
+        let Some(guid) = guid_arg(2) else { return };
+        let mut data = [0u8; 512];
+        match efi::efi_get_variable(name, &guid, &mut data) {
+            Ok((attributes, size)) => {
+                outln!("{} ({} bytes, attributes {:#x})", name, size, attributes);
+                for (i, row) in data[..size].chunks(16).enumerate() {
+                    let mut line = String::new();
+                    for byte in row {
+                        line.push_str(&alloc::format!("{:02x} ", byte));
+                    }
+                    outln!("  {:04x}: {}", i * 16, line);
+                }
+            }
+            Err(e) => outln!("{}: {}", name, e.name()),
+        }
+
+    } else if eq_ignore_case(cmd, "set") {
+        if args.len() < 3 {
+            outln!("Usage: efivar set <name> <text>");
+            return;
+        }
+        let attributes = efi::attr::NON_VOLATILE | efi::attr::BOOTSERVICE_ACCESS | efi::attr::RUNTIME_ACCESS;
+        match efi::efi_set_variable(args[1], &EFI_GLOBAL_VARIABLE, attributes, args[2].as_bytes()) {
+            Ok(()) => outln!("{} set ({} bytes)", args[1], args[2].len()),
+            Err(e) => outln!("{}: {}", args[1], e.name()),
+        }
+
+    } else if eq_ignore_case(cmd, "delete") {
+        let Some(name) = args.get(1) else {
+            outln!("Usage: efivar delete <name> [guid]");
+            return;
+        };
+        let Some(guid) = guid_arg(2) else { return };
+        match efi::efi_set_variable(name, &guid, 0, &[]) {
+            Ok(()) => outln!("{} deleted", name),
+            Err(e) => outln!("{}: {}", name, e.name()),
+        }
+
+    } else if eq_ignore_case(cmd, "bootorder") {
+        let mut order = [0u8; 128];
+        let size = match efi::efi_get_variable("BootOrder", &EFI_GLOBAL_VARIABLE, &mut order) {
+            Ok((_, size)) => size,
+            Err(e) => {
+                outln!("BootOrder: {}", e.name());
+                return;
+            }
+        };
+        let mut current_bytes = [0u8; 2];
+        let current = efi::efi_get_variable("BootCurrent", &EFI_GLOBAL_VARIABLE, &mut current_bytes)
+            .ok()
+            .map(|_| u16::from_le_bytes(current_bytes));
+
+        outln!("Boot order:");
+        for entry in order[..size].as_chunks::<2>().0 {
+            let number = u16::from_le_bytes(*entry);
+            let var = alloc::format!("Boot{:04X}", number);
+            let mut option = [0u8; 512];
+            // EFI_LOAD_OPTION: Attributes (u32), FilePathListLength (u16), Description (UTF-16)
+            let description = match efi::efi_get_variable(&var, &EFI_GLOBAL_VARIABLE, &mut option) {
+                Ok((_, len)) if len > 6 => {
+                    let units = option[6..len]
+                        .as_chunks::<2>()
+                        .0
+                        .iter()
+                        .map(|c| u16::from_le_bytes(*c))
+                        .take_while(|&c| c != 0);
+                    char::decode_utf16(units).map(|c| c.unwrap_or('?')).collect::<String>()
+                }
+                Ok(_) => String::from("(malformed)"),
+                Err(e) => String::from(e.name()),
+            };
+            let active = u32::from_le_bytes([option[0], option[1], option[2], option[3]]) & 1 != 0;
+            outln!("  {}{} {}{}",
+                if current == Some(number) { "*" } else { " " },
+                var, description,
+                if active { "" } else { " (inactive)" });
+        }
+
+    } else if eq_ignore_case(cmd, "fwsetup") {
+        match efi::efi_request_firmware_ui() {
+            Ok(()) => outln!("Firmware setup will open on the next reboot"),
+            Err(e) => outln!("Cannot request firmware setup: {}", e.name()),
+        }
+
+    } else {
+        outln!("Unknown efivar command: {}", cmd);
+    }
+}
+
+/// Parse a PCR list ("0,1,7" or "0-7") into a mask
+fn parse_pcr_mask(text: &str) -> Option<u32> {
+    let mut mask = 0u32;
+    for part in text.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((a, b)) => (a.parse::<u32>().ok()?, b.parse::<u32>().ok()?),
+            None => {
+                let pcr = part.parse::<u32>().ok()?;
+                (pcr, pcr)
+            }
+        };
+        if first > last || last >= crate::drivers::tpm::TPM_PCR_COUNT {
+            return None;
+        }
+        for pcr in first..=last {
+            mask |= 1 << pcr;
+        }
+    }
+    Some(mask)
+}
+
+fn hex_string(bytes: &[u8]) -> alloc::string::String {
+    use core::fmt::Write;
+    let mut text = alloc::string::String::new();
+    for byte in bytes {
+        let _ = write!(text, "{:02x}", byte);
+    }
+    text
+}
+
+/// TPM 2.0 status, PCRs, event log and quotes
+pub fn cmd_tpm(args: &[&str]) {
+    use crate::drivers::tpm::{self, alg, eventlog};
+
+    if args.first().is_some_and(|a| eq_ignore_case(a, "help")) {
+        outln!("Usage: tpm [command]");
+        outln!("");
+        outln!("Commands:");
+        outln!("  status               Interface, manufacturer and PCR banks (default)");
+        outln!("  pcrs [sha1|sha256]   Read PCRs 0-23");
+        outln!("  log [pcr]            Firmware event log (measured boot)");
+        outln!("  verify               Replay the event log against the SHA-256 PCRs");
+        outln!("  measurements         Measurements made by the kernel");
+        outln!("  extend <pcr> <text>  Extend a PCR with the hash of text");
+        outln!("  quote <pcrs> [nonce] Signed quote over PCRs (e.g. 0-7 or 0,2,4)");
+        return;
+    }
+
+    let cmd = args.first().copied().unwrap_or("status");
+
+    if eq_ignore_case(cmd, "status") {
+        match tpm::tpm_info() {
+            Some(info) => {
+                outln!("TPM 2.0");
+                outln!("  Interface:     {}{}", info.interface,
+                    if info.start_method != 0 { alloc::format!(" (ACPI start method {})", info.start_method) }
+                    else { alloc::string::String::from(" (probed)") });
+                if info.did_vid != 0 {
+                    outln!("  Vendor/device: {:04x}:{:04x}", info.did_vid & 0xFFFF, info.did_vid >> 16);
+                }
+                outln!("  Manufacturer:  {}", info.manufacturer_str());
+                outln!("  Firmware:      {}.{}.{}.{}",
+                    info.firmware_version >> 48, (info.firmware_version >> 32) & 0xFFFF,
+                    (info.firmware_version >> 16) & 0xFFFF, info.firmware_version & 0xFFFF);
+                let mut banks = alloc::string::String::new();
+                for &bank in info.banks() {
+                    banks.push_str(tpm::alg_name(bank));
+                    banks.push(' ');
+                }
+                outln!("  PCR banks:     {}", banks);
+            }
+            None => outln!("No TPM 2.0 found"),
+        }
+        match eventlog::event_log() {
+            Some(log) => outln!("  Event log:     {} bytes, {} events{}", log.size(), log.event_count(),
+                if log.is_truncated() { " (truncated)" } else { "" }),
+            None => outln!("  Event log:     not provided by the loader"),
+        }
+
+    } else if eq_ignore_case(cmd, "pcrs") {
+        let bank = match args.get(1) {
+            None => alg::SHA256,
+            Some(name) if eq_ignore_case(name, "sha256") => alg::SHA256,
+            Some(name) if eq_ignore_case(name, "sha1") => alg::SHA1,
+            Some(name) => {
+                outln!("Unknown bank: {}", name);
+                return;
+            }
+        };
+        let mut digest = [0u8; 64];
+        for pcr in 0..tpm::TPM_PCR_COUNT {
+            match tpm::tpm_pcr_read(pcr, bank, &mut digest) {
+                Ok(len) => outln!("  PCR{:<2} {}", pcr, hex_string(&digest[..len])),
+                Err(e) => {
+                    outln!("PCR read failed: {}", e);
+                    return;
+                }
+            }
+        }
+
+    } else if eq_ignore_case(cmd, "log") {
+        let Some(log) = eventlog::event_log() else {
+            outln!("No event log was handed over by the loader");
+            return;
+        };
+        let filter = args.get(1).and_then(|a| a.parse::<u32>().ok());
+        for event in log.events().filter(|e| filter.is_none_or(|pcr| e.pcr == pcr)) {
+            let digest = event.digest(alg::SHA256).or_else(|| event.digest(alg::SHA1)).unwrap_or(&[]);
+            let shown = &digest[..digest.len().min(8)];
+            outln!("  PCR{:<2} {:<30} {}.. ({} bytes)",
+                event.pcr, eventlog::event_type_name(event.event_type),
+                hex_string(shown), event.data.len());
+        }
+
+    } else if eq_ignore_case(cmd, "verify") {
+        let Some(log) = eventlog::event_log() else {
+            outln!("No event log was handed over by the loader");
+            return;
+        };
+        let mut actual = [0u8; 64];
+        let mut mismatches = 0;
+        for pcr in 0..tpm::TPM_PCR_COUNT {
+            if !log.events().any(|e| e.pcr == pcr) {
+                continue;
+            }
+            let Some(expected) = log.replay_sha256(pcr) else {
+                outln!("The event log has no SHA-256 bank");
+                return;
+            };
+            match tpm::tpm_pcr_read(pcr, alg::SHA256, &mut actual) {
+                Ok(len) if actual[..len] == expected => outln!("  PCR{:<2} ok", pcr),
+                Ok(_) => {
+                    outln!("  PCR{:<2} MISMATCH (log {}..)", pcr, hex_string(&expected[..8]));
+                    mismatches += 1;
+                }
+                Err(e) => {
+                    outln!("PCR read failed: {}", e);
+                    return;
+                }
+            }
+        }
+        outln!("{} mismatch(es); events after the loader's GetEventLog (final events table) are not replayed",
+            mismatches);
+
+    } else if eq_ignore_case(cmd, "measurements") {
+        let (entries, count, dropped) = tpm::tpm_measurements();
+        for m in &entries[..count] {
+            outln!("  PCR{:<2} {} {}{}", m.pcr, hex_string(&m.digest[..8]), m.description_str(),
+                if m.extended { "" } else { " (not extended)" });
+        }
+        outln!("{} measurement(s){}", count,
+            if dropped > 0 { alloc::format!(", {} dropped", dropped) } else { alloc::string::String::new() });
+
+    } else if eq_ignore_case(cmd, "extend") {
+        let (Some(pcr), Some(text)) = (args.get(1).and_then(|a| a.parse::<u32>().ok()), args.get(2)) else {
+            outln!("Usage: tpm extend <pcr> <text>");
+            return;
+        };
+        match tpm::tpm_pcr_extend(pcr, text.as_bytes()) {
+            Ok(digest) => outln!("PCR{} extended with {}", pcr, hex_string(&digest)),
+            Err(e) => outln!("Extend failed: {}", e),
+        }
+
+    } else if eq_ignore_case(cmd, "quote") {
+        let Some(mask) = args.get(1).and_then(|a| parse_pcr_mask(a)) else {
+            outln!("Usage: tpm quote <pcrs> [nonce]");
+            return;
+        };
+        let nonce = args.get(2).map_or(&b"nostalgia"[..], |n| n.as_bytes());
+        let mut attest = [0u8; 512];
+        let mut signature = [0u8; 512];
+        match tpm::tpm_quote(mask, nonce, &mut attest, &mut signature) {
+            Ok((attest_len, signature_len)) => {
+                outln!("Quote over PCR mask {:#08x} ({} byte attestation, {} byte signature)",
+                    mask, attest_len, signature_len);
+                outln!("Attest:");
+                for row in attest[..attest_len].chunks(32) {
+                    outln!("  {}", hex_string(row));
+                }
+                outln!("Signature:");
+                for row in signature[..signature_len].chunks(32) {
+                    outln!("  {}", hex_string(row));
+                }
+            }
+            Err(e) => outln!("Quote failed: {}", e),
+        }
+
+    } else {
+        outln!("Unknown tpm command: {}", cmd);
+    }
+}
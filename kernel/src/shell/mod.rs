@@ -17,6 +17,7 @@ use crate::fs;
 use core::ptr::addr_of_mut;
 
 mod commands;
+mod script;
 
 /// Output redirection state
 static mut REDIRECT_ACTIVE: bool = false;
@@ -146,27 +147,27 @@ const HISTORY_SIZE: usize = 32;
 
 /// List of available commands for tab completion
 const COMMANDS: &[&str] = &[
-    "acpi", "apic", "apcq", "arbiter", "arp", "assoc", "at", "attrib",
-    "blocks", "bootcfg", "bt",
+    "acpi", "apic", "apcq", "arbiter", "arp", "assoc", "at", "attrib", "autorun", "avscan",
+    "balloon", "bench", "blocks", "bootcfg", "bt",
     "cacls", "cache", "call", "callback", "cat", "cc", "cd", "change", "chcp", "chkdsk", "choice", "cid", "cipher", "clear", "clip", "cls", "color", "comp", "compact", "convert", "copy", "cp", "cpufeatures", "cpuinfo",
-    "date", "daytime", "debug", "defrag", "del", "desc", "descriptor", "devdrv", "dir", "discard", "disk", "diskpart", "dmi", "doskey", "dpcq", "driverquery", "dump", "echo", "echoserv", "endlocal", "erase", "eventcreate", "eventlog", "eventtriggers", "ex", "exception", "exit", "expand", "extrac32",
+    "date", "daytime", "dd", "debug", "defrag", "del", "desc", "descriptor", "devdrv", "dir", "discard", "disk", "diskpart", "dmi", "doskey", "dpcq", "driverquery", "dump", "echo", "echoserv", "efivar", "eject", "endlocal", "erase", "eventcreate", "eventlog", "eventtriggers", "ex", "exception", "exit", "expand", "extrac32",
     "fc", "files", "find", "findstr", "finger", "for", "format", "fsutil", "ftype",
     "getmac", "goto", "gpresult", "gpupdate",
-    "hal", "handles", "head", "heap", "help", "history", "hostname", "hpet",
+    "hal", "handles", "head", "heap", "help", "history", "hostfs", "hostname", "hpet",
     "icacls", "ident", "if", "int", "io", "iocp", "ioq", "ipconfig", "irql", "irqstat",
-    "job", "ke", "keyedev",
+    "job", "ke", "keyedev", "ktest",
     "label", "ldr", "logman", "logoff", "lookaside", "ls", "luid",
-    "makecab", "md", "mem", "memmap", "memory", "mkdir", "mm", "mode", "more", "msg", "msr", "mv",
+    "makecab", "md", "mem", "memmap", "memory", "mkdir", "mm", "mode", "more", "mount", "msg", "msr", "mv",
     "nbtstat", "net", "netinfo", "netserv", "netsh", "netstat", "nslookup", "ntbackup", "ntfs",
     "ob", "obdir", "openfiles",
     "pagetable", "partition", "path", "pathping", "pause", "pci", "pe", "peb", "perfmon", "pfn", "ping", "pipes", "po", "pool", "pooltag", "popd", "port", "power", "powercfg", "prcb", "prncnfg", "prndrvr", "prnjobs", "prnmngr", "prnport", "prnqctl", "prefetch", "print", "prompt", "ps", "pushd", "pwd",
     "qotd", "query", "quit",
-    "ramdisk", "rd", "reboot", "recover", "reg", "regsvr32", "relog", "ren", "rename", "replace", "reset", "resume", "rm", "rmdir", "robocopy", "route", "rtl", "runas", "rundll32",
-    "sc", "sched", "schtasks", "se", "secedit", "section", "services", "set", "setlocal", "setx", "shutdown", "smbios", "sort", "stack", "start", "subst", "suspend", "sysinfo", "systeminfo",
-    "tail", "taskkill", "tasklist", "tasks", "teb", "time", "timeout", "timer", "timerq", "timeserv", "title", "touch", "tracerpt", "tracert", "tree", "type", "typeperf",
+    "ramdisk", "rd", "reboot", "recover", "reg", "regsvr32", "relog", "ren", "rename", "replace", "replay", "reset", "resume", "rm", "rmdir", "robocopy", "route", "rtl", "runas", "rundll32",
+    "sc", "sched", "schtasks", "script", "se", "secedit", "section", "services", "set", "setlocal", "setx", "shutdown", "smart", "smbios", "sort", "stack", "start", "stress", "subst", "suspend", "syscallstat", "sysinfo", "systeminfo",
+    "tail", "taskkill", "tasklist", "tasks", "teb", "time", "timeout", "timer", "timerq", "timeserv", "title", "top", "tpm", "touch", "trace", "tracerpt", "tracert", "tree", "type", "typeperf",
     "userproc", "usertest",
     "vad", "veh", "ver", "verifier", "verify", "version", "volumes",
-    "w32tm", "waitq", "wc", "where", "whois", "whoami", "wmic", "worker", "wset", "xcopy",
+    "w32tm", "waitq", "watch", "wc", "where", "whois", "whoami", "wmic", "worker", "wset", "xcopy",
 ];
 
 /// Current working directory
@@ -957,15 +958,17 @@ impl Shell {
 
     /// Execute the current command
     fn execute_command(&mut self) {
-        // Get command string
-        let cmd_str = match core::str::from_utf8(&self.cmd_buf[..self.cmd_len]) {
-            Ok(s) => s.trim(),
-            Err(_) => {
-                serial_println!("Invalid UTF-8 in command");
-                return;
-            }
-        };
+        // Copy the line out so a command (watch) can run others through the shell
+        let buf = self.cmd_buf;
+        match core::str::from_utf8(&buf[..self.cmd_len]) {
+            Ok(s) => self.run_line(s),
+            Err(_) => serial_println!("Invalid UTF-8 in command"),
+        }
+    }
 
+    /// Execute one command line
+    fn run_line(&mut self, line: &str) {
+        let cmd_str = line.trim();
         if cmd_str.is_empty() {
             return;
         }
@@ -1005,8 +1008,11 @@ impl Shell {
         } else if eq_ignore_case(cmd, "clear") || eq_ignore_case(cmd, "cls") {
             commands::cmd_clear();
         } else if eq_ignore_case(cmd, "exit") || eq_ignore_case(cmd, "quit") {
-            serial_println!("Goodbye!");
-            self.running = false;
+            // Leaving a RUNAS session returns to the previous user
+            if !commands::end_runas_session() {
+                serial_println!("Goodbye!");
+                self.running = false;
+            }
         // File system commands
         } else if eq_ignore_case(cmd, "dir") || eq_ignore_case(cmd, "ls") {
             commands::cmd_ls(&args[1..argc]);
@@ -1024,6 +1030,8 @@ impl Shell {
             commands::cmd_del(&args[1..argc]);
         } else if eq_ignore_case(cmd, "copy") || eq_ignore_case(cmd, "cp") {
             commands::cmd_copy(&args[1..argc]);
+        } else if eq_ignore_case(cmd, "dd") {
+            commands::cmd_dd(&args[1..argc]);
         } else if eq_ignore_case(cmd, "ren") || eq_ignore_case(cmd, "rename") || eq_ignore_case(cmd, "mv") {
             commands::cmd_rename(&args[1..argc]);
         } else if eq_ignore_case(cmd, "touch") {
@@ -1688,9 +1696,63 @@ impl Shell {
         // STRACE - System call tracing
         } else if eq_ignore_case(cmd, "strace") {
             commands::cmd_strace(&args[1..argc]);
+        // TRACE - Kernel logger scheduler tracing
+        } else if eq_ignore_case(cmd, "trace") {
+            commands::cmd_trace(&args[1..argc]);
+        // SYSCALLSTAT - Per-syscall counters and latency histograms
+        } else if eq_ignore_case(cmd, "syscallstat") {
+            commands::cmd_syscallstat(&args[1..argc]);
+        // TOP - Per-process CPU usage
+        } else if eq_ignore_case(cmd, "top") {
+            commands::cmd_top(&args[1..argc]);
+        // AUTORUN - Developer auto-run service
+        } else if eq_ignore_case(cmd, "autorun") {
+            commands::cmd_autorun(&args[1..argc]);
+        // AVSCAN - On-access scanner demo filter
+        } else if eq_ignore_case(cmd, "avscan") {
+            commands::cmd_avscan(&args[1..argc]);
+        // HOSTFS - Host shared folder (virtio-9p)
+        } else if eq_ignore_case(cmd, "hostfs") {
+            commands::cmd_hostfs(&args[1..argc]);
+        // MOUNT - Mount and dismount volumes
+        } else if eq_ignore_case(cmd, "mount") {
+            commands::cmd_mount(&args[1..argc]);
+        // EJECT - Eject removable media
+        } else if eq_ignore_case(cmd, "eject") {
+            commands::cmd_eject(&args[1..argc]);
+        // REPLAY - Interrupt/input record and replay
+        } else if eq_ignore_case(cmd, "replay") {
+            commands::cmd_replay(&args[1..argc]);
+        // EFIVAR - UEFI variables through runtime services
+        } else if eq_ignore_case(cmd, "efivar") {
+            commands::cmd_efivar(&args[1..argc]);
+        // TPM - TPM 2.0 and measured boot
+        } else if eq_ignore_case(cmd, "tpm") {
+            commands::cmd_tpm(&args[1..argc]);
+        // BALLOON - VirtIO memory balloon
+        } else if eq_ignore_case(cmd, "balloon") {
+            commands::cmd_balloon(&args[1..argc]);
+        // SMART - Disk health monitoring
+        } else if eq_ignore_case(cmd, "smart") {
+            commands::cmd_smart(&args[1..argc]);
         // DBGK - Kernel debugger subsystem
         } else if eq_ignore_case(cmd, "dbgk") {
             commands::cmd_dbgk(&args[1..argc]);
+        // Script interpreter
+        } else if eq_ignore_case(cmd, "script") {
+            commands::cmd_script(&args[1..argc]);
+        // Benchmarks
+        } else if eq_ignore_case(cmd, "bench") {
+            commands::cmd_bench(&args[1..argc]);
+        // Stress test
+        } else if eq_ignore_case(cmd, "stress") {
+            commands::cmd_stress(&args[1..argc]);
+        // Kernel self-tests
+        } else if eq_ignore_case(cmd, "ktest") {
+            commands::cmd_ktest(&args[1..argc]);
+        // Repeat a command
+        } else if eq_ignore_case(cmd, "watch") {
+            self.watch(&args[1..argc]);
         } else {
             serial_println!("'{}' is not recognized as a command.", args[0]);
             serial_println!("Type 'help' for available commands.");
@@ -1701,6 +1763,71 @@ impl Shell {
             let _ = end_redirect();
         }
     }
+
+    /// Clear the screen and rerun a command every few seconds until a key is pressed
+    fn watch(&mut self, args: &[&str]) {
+        let mut interval_secs = 2u64;
+        let mut args = args;
+        if args.len() >= 2 && (args[0] == "-n" || eq_ignore_case(args[0], "/n")) {
+            match args[1].parse::<u64>() {
+                Ok(secs) if secs > 0 => interval_secs = secs,
+                _ => {
+                    serial_println!("watch: invalid interval '{}'", args[1]);
+                    return;
+                }
+            }
+            args = &args[2..];
+        }
+
+        if args.is_empty() || args[0] == "/?" || eq_ignore_case(args[0], "help") {
+            serial_println!("Usage: watch [-n seconds] <command> [args]");
+            serial_println!("");
+            serial_println!("Clears the screen and runs the command every interval");
+            serial_println!("(default 2 seconds) until a key is pressed.");
+            serial_println!("");
+            serial_println!("Example: watch -n 1 cc stats");
+            return;
+        }
+        if eq_ignore_case(args[0], "watch") {
+            serial_println!("watch: cannot watch another watch");
+            return;
+        }
+
+        // Rebuild the command line from its arguments
+        let mut line = [0u8; MAX_CMD_LEN];
+        let mut len = 0;
+        for arg in args {
+            if len + arg.len() + 1 > MAX_CMD_LEN {
+                break;
+            }
+            if len > 0 {
+                line[len] = b' ';
+                len += 1;
+            }
+            line[len..len + arg.len()].copy_from_slice(arg.as_bytes());
+            len += arg.len();
+        }
+        let line = core::str::from_utf8(&line[..len]).unwrap_or("");
+
+        let interval_ms = interval_secs * 1000;
+        loop {
+            let started = crate::hal::apic::get_tick_count();
+            commands::cmd_clear();
+            serial_println!("Every {}s: {}    (press any key to stop)", interval_secs, line);
+            serial_println!("");
+            self.run_line(line);
+            if !self.running {
+                return;
+            }
+
+            // Sleep out the rest of the interval, waking early on a key
+            let elapsed = crate::hal::apic::get_tick_count().saturating_sub(started);
+            let remaining = interval_ms.saturating_sub(elapsed).max(1);
+            if keyboard::read_char_timeout(remaining).is_some() {
+                break;
+            }
+        }
+    }
 }
 
 /// Parse command string for output redirection
@@ -1815,6 +1942,11 @@ pub fn set_current_dir(path: &str) {
 /// Global shell instance
 static mut SHELL: Shell = Shell::new();
 
+/// Address and size of the shell state, for pinning
+pub fn state_range() -> Option<(u64, usize)> {
+    Some((addr_of_mut!(SHELL) as u64, core::mem::size_of::<Shell>()))
+}
+
 /// Initialize and run the shell
 pub fn run() {
     unsafe {
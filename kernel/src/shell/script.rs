@@ -0,0 +1,1081 @@
+//! Shell Script Interpreter
+//!
+//! A tiny BASIC dialect for scripting regression scenarios from the shell
+//! (`script run C:\TESTS\FS.BAS`) without rebuilding the kernel.
+//!
+//! # Language
+//!
+//! - Optional line numbers, used as `GOTO`/`GOSUB` targets
+//! - Statements separated by `:`; `REM` or `'` comments out the rest of a line
+//! - `LET`, `PRINT`, `IF..THEN..ELSE`, `FOR..TO..STEP`/`NEXT`, `GOTO`,
+//!   `GOSUB`/`RETURN`, `END`, `ASSERT cond [, msg]`
+//! - Integers (i64) and strings; names ending in `$` default to `""`
+//! - Operators: `+ - * / MOD`, `= <> < > <= >=`, `AND OR NOT`
+//!
+//! # Bindings
+//!
+//! - **File system**: `FCREATE p [,data]`, `FAPPEND p, data`, `FDELETE p`,
+//!   `FRENAME a, b`, `FCOPY a, b`, `MKDIR p`, `RMDIR p`;
+//!   `FEXISTS(p)`, `FSIZE(p)`, `FREAD$(p)`, `FCOUNT(dir)`
+//! - **Registry**: `REGSET key, name, value`, `REGDEL key`;
+//!   `REG$(key, name)`, `REGDW(key, name)`
+//! - **Processes**: `PROCS`, `THREADS`, `PID`, `PNAME$(pid)`
+//! - **Misc**: `RND(n)`, `TICKS`, `ERR`, `LEN`, `STR$`, `VAL`, `CHR$`,
+//!   `LEFT$`, `RIGHT$`, `MID$`, `ABS`
+//!
+//! File system and registry statements do not abort on failure; they set
+//! `ERR` to the status code (0 on success) for the script to check.
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::fs;
+use super::{shell_write, shell_writeln};
+
+/// Maximum script file size
+///
+/// Heap allocations come from pool size classes (16 KB at most), so
+/// script buffers, line tables and strings are bounded well below that.
+pub const MAX_SCRIPT_SIZE: usize = 12 * 1024;
+
+/// Maximum script lines
+const MAX_SCRIPT_LINES: usize = 256;
+
+/// Maximum distinct variables
+const MAX_VARIABLES: usize = 128;
+
+/// Maximum string length
+const MAX_STRING_LEN: usize = 4096;
+
+/// Statements executed before a script is considered runaway
+pub const MAX_SCRIPT_STEPS: u64 = 10_000_000;
+
+/// Maximum FOR/GOSUB nesting depth
+const MAX_STACK_DEPTH: usize = 64;
+
+type ScriptResult<T> = Result<T, &'static str>;
+
+// ============================================================================
+// Tokens and Values
+// ============================================================================
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Num(i64),
+    Str(String),
+    /// Identifier or keyword, upper-cased
+    Ident(String),
+    Op(&'static str),
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    fn as_int(&self) -> ScriptResult<i64> {
+        match self {
+            Value::Int(n) => Ok(*n),
+            Value::Str(_) => Err("type mismatch (expected number)"),
+        }
+    }
+
+    fn into_string(self) -> ScriptResult<String> {
+        match self {
+            Value::Str(s) => Ok(s),
+            Value::Int(_) => Err("type mismatch (expected string)"),
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Int(n) => *n != 0,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            Value::Int(n) => format!("{}", n),
+            Value::Str(s) => s.clone(),
+        }
+    }
+}
+
+fn bool_value(b: bool) -> Value {
+    Value::Int(if b { 1 } else { 0 })
+}
+
+/// A tokenized source line
+struct Line {
+    /// BASIC line number, if the line had one
+    number: Option<i64>,
+    /// Source line (1-based) for error messages
+    source_line: usize,
+    toks: Vec<Tok>,
+}
+
+/// Split one source line into tokens
+fn tokenize(text: &str) -> ScriptResult<Vec<Tok>> {
+    let bytes = text.as_bytes();
+    let mut toks = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b' ' || c == b'\t' {
+            i += 1;
+        } else if c == b'\'' {
+            toks.push(Tok::Ident(String::from("REM")));
+            break;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            let n = text[start..i].parse::<i64>().map_err(|_| "number too large")?;
+            toks.push(Tok::Num(n));
+        } else if c == b'"' {
+            let start = i + 1;
+            i = start;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += 1;
+            }
+            if i >= bytes.len() {
+                return Err("unterminated string");
+            }
+            toks.push(Tok::Str(String::from(&text[start..i])));
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            if i < bytes.len() && bytes[i] == b'$' {
+                i += 1;
+            }
+            let word = text[start..i].to_ascii_uppercase();
+            let is_rem = word == "REM";
+            toks.push(Tok::Ident(word));
+            if is_rem {
+                break;
+            }
+        } else {
+            let op = match (c, bytes.get(i + 1)) {
+                (b'<', Some(b'>')) => Some("<>"),
+                (b'<', Some(b'=')) => Some("<="),
+                (b'>', Some(b'=')) => Some(">="),
+                _ => None,
+            };
+            if let Some(op) = op {
+                toks.push(Tok::Op(op));
+                i += 2;
+                continue;
+            }
+            let op = match c {
+                b'+' => "+", b'-' => "-", b'*' => "*", b'/' => "/",
+                b'(' => "(", b')' => ")", b',' => ",", b';' => ";",
+                b':' => ":", b'=' => "=", b'<' => "<", b'>' => ">",
+                _ => return Err("unexpected character"),
+            };
+            toks.push(Tok::Op(op));
+            i += 1;
+        }
+    }
+
+    Ok(toks)
+}
+
+// ============================================================================
+// Program State
+// ============================================================================
+
+/// Position of a statement: (line index, token index)
+type Pc = (usize, usize);
+
+struct ForFrame {
+    var: String,
+    limit: i64,
+    step: i64,
+    body: Pc,
+}
+
+/// Control flow after a statement
+enum Flow {
+    /// Continue at the next statement
+    Next,
+    /// Jump to a position
+    Jump(Pc),
+    /// Skip the rest of the current line
+    SkipLine,
+    /// Stop the program
+    End,
+}
+
+/// Result of running a script
+pub struct ScriptOutcome {
+    /// Statements executed
+    pub steps: u64,
+    /// ASSERT statements that passed
+    pub asserts: u32,
+    /// Error message and source line, if the script failed
+    pub error: Option<(String, usize)>,
+}
+
+struct Interp<'a> {
+    lines: &'a [Line],
+    vars: Vec<(String, Value)>,
+    for_stack: Vec<ForFrame>,
+    gosub_stack: Vec<Pc>,
+    last_err: i64,
+    steps: u64,
+    asserts: u32,
+    /// Line index being executed, for error reporting
+    line: usize,
+}
+
+/// Token cursor over a single line
+struct Cursor<'a> {
+    toks: &'a [Tok],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<&'a Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Tok> {
+        let t = self.toks.get(self.pos);
+        if t.is_some() {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn at_end_of_statement(&self) -> bool {
+        matches!(self.peek(), None | Some(Tok::Op(":")))
+            || self.peek_keyword("ELSE")
+    }
+
+    fn peek_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Op(o)) if *o == op)
+    }
+
+    fn peek_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Ident(w)) if w == kw)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if self.peek_op(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        if self.peek_keyword(kw) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> ScriptResult<()> {
+        if self.eat_op(op) { Ok(()) } else { Err("syntax error") }
+    }
+
+    fn expect_ident(&mut self) -> ScriptResult<&'a str> {
+        match self.next() {
+            Some(Tok::Ident(w)) => Ok(w.as_str()),
+            _ => Err("expected name"),
+        }
+    }
+}
+
+/// Functions callable from expressions; zero-argument ones need no parens
+const FUNCTIONS: &[&str] = &[
+    "RND", "ABS", "LEN", "STR$", "VAL", "CHR$", "LEFT$", "RIGHT$", "MID$",
+    "TICKS", "ERR",
+    "FEXISTS", "FSIZE", "FREAD$", "FCOUNT",
+    "REG$", "REGDW",
+    "PROCS", "THREADS", "PID", "PNAME$",
+];
+
+impl<'a> Interp<'a> {
+    fn new(lines: &'a [Line]) -> Self {
+        Self {
+            lines,
+            vars: Vec::new(),
+            for_stack: Vec::new(),
+            gosub_stack: Vec::new(),
+            last_err: 0,
+            steps: 0,
+            asserts: 0,
+            line: 0,
+        }
+    }
+
+    fn get_var(&self, name: &str) -> Value {
+        for (n, v) in self.vars.iter() {
+            if n == name {
+                return v.clone();
+            }
+        }
+        if name.ends_with('$') {
+            Value::Str(String::new())
+        } else {
+            Value::Int(0)
+        }
+    }
+
+    fn set_var(&mut self, name: &str, value: Value) -> ScriptResult<()> {
+        for (n, v) in self.vars.iter_mut() {
+            if n == name {
+                *v = value;
+                return Ok(());
+            }
+        }
+        if self.vars.len() >= MAX_VARIABLES {
+            return Err("too many variables");
+        }
+        self.vars.push((String::from(name), value));
+        Ok(())
+    }
+
+    fn find_line(&self, number: i64) -> ScriptResult<usize> {
+        self.lines
+            .iter()
+            .position(|l| l.number == Some(number))
+            .ok_or("undefined line number")
+    }
+
+    /// Run from the first line until END, an error, or the last line
+    fn run(&mut self) -> ScriptResult<()> {
+        let mut pc: Pc = (0, 0);
+
+        while pc.0 < self.lines.len() {
+            let line = &self.lines[pc.0];
+            let mut cur = Cursor { toks: &line.toks, pos: pc.1 };
+
+            // Skip statement separators and empty lines
+            if cur.eat_op(":") {
+                pc.1 = cur.pos;
+                continue;
+            }
+            if cur.peek().is_none() {
+                pc = (pc.0 + 1, 0);
+                continue;
+            }
+            // A stray ELSE reached after a taken THEN branch ends the line
+            if cur.peek_keyword("ELSE") {
+                pc = (pc.0 + 1, 0);
+                continue;
+            }
+
+            self.line = pc.0;
+            self.steps += 1;
+            if self.steps > MAX_SCRIPT_STEPS {
+                return Err("step limit exceeded");
+            }
+
+            match self.statement(&mut cur, pc.0)? {
+                Flow::Next => {
+                    if !cur.at_end_of_statement() {
+                        return Err("syntax error");
+                    }
+                    pc.1 = cur.pos;
+                }
+                Flow::Jump(target) => pc = target,
+                Flow::SkipLine => pc = (pc.0 + 1, 0),
+                Flow::End => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Execute one statement starting at the cursor
+    fn statement(&mut self, cur: &mut Cursor<'a>, line_idx: usize) -> ScriptResult<Flow> {
+        let word = match cur.peek() {
+            Some(Tok::Ident(w)) => w.as_str(),
+            _ => return Err("syntax error"),
+        };
+        cur.pos += 1;
+
+        match word {
+            "REM" => Ok(Flow::SkipLine),
+            "END" | "STOP" => Ok(Flow::End),
+            "LET" => {
+                let name = cur.expect_ident()?;
+                self.assign(cur, name)
+            }
+            "PRINT" => self.stmt_print(cur),
+            "IF" => self.stmt_if(cur, line_idx),
+            "FOR" => self.stmt_for(cur, line_idx),
+            "NEXT" => self.stmt_next(cur),
+            "GOTO" => {
+                let target = self.expr(cur)?.as_int()?;
+                Ok(Flow::Jump((self.find_line(target)?, 0)))
+            }
+            "GOSUB" => {
+                let target = self.expr(cur)?.as_int()?;
+                if self.gosub_stack.len() >= MAX_STACK_DEPTH {
+                    return Err("GOSUB nesting too deep");
+                }
+                self.gosub_stack.push((line_idx, cur.pos));
+                Ok(Flow::Jump((self.find_line(target)?, 0)))
+            }
+            "RETURN" => {
+                let ret = self.gosub_stack.pop().ok_or("RETURN without GOSUB")?;
+                Ok(Flow::Jump(ret))
+            }
+            "ASSERT" => {
+                let ok = self.expr(cur)?.is_true();
+                let msg = if cur.eat_op(",") {
+                    Some(self.expr(cur)?.display())
+                } else {
+                    None
+                };
+                if !ok {
+                    if let Some(m) = msg {
+                        shell_writeln(&format!("ASSERT: {}", m));
+                    }
+                    return Err("assertion failed");
+                }
+                self.asserts += 1;
+                Ok(Flow::Next)
+            }
+            "FCREATE" | "FAPPEND" => {
+                let path = self.path_arg(cur)?;
+                let data = if cur.eat_op(",") {
+                    self.expr(cur)?.display()
+                } else {
+                    String::new()
+                };
+                self.last_err = fs_write_file(&path, data.as_bytes(), word == "FAPPEND");
+                Ok(Flow::Next)
+            }
+            "FDELETE" | "MKDIR" | "RMDIR" => {
+                let path = self.path_arg(cur)?;
+                let result = match word {
+                    "FDELETE" => fs::delete(&path),
+                    "MKDIR" => fs::mkdir(&path),
+                    _ => fs::rmdir(&path),
+                };
+                self.last_err = fs_status(result);
+                Ok(Flow::Next)
+            }
+            "FRENAME" | "FCOPY" => {
+                let from = self.path_arg(cur)?;
+                cur.expect_op(",")?;
+                let to = self.path_arg(cur)?;
+                self.last_err = if word == "FRENAME" {
+                    fs_status(fs::rename(&from, &to))
+                } else {
+                    fs_status(fs::copy(&from, &to).map(|_| ()))
+                };
+                Ok(Flow::Next)
+            }
+            "REGSET" => {
+                let key = self.expr(cur)?.into_string()?;
+                cur.expect_op(",")?;
+                let name = self.expr(cur)?.into_string()?;
+                cur.expect_op(",")?;
+                let status = unsafe {
+                    match self.expr(cur)? {
+                        Value::Int(n) => crate::cm::cm_write_dword(&key, &name, n as u32),
+                        Value::Str(s) => crate::cm::cm_write_string(&key, &name, &s),
+                    }
+                };
+                self.last_err = status as i64;
+                Ok(Flow::Next)
+            }
+            "REGDEL" => {
+                let key = self.expr(cur)?.into_string()?;
+                self.last_err = unsafe { crate::cm::cm_delete_key(&key) } as i64;
+                Ok(Flow::Next)
+            }
+            _ => {
+                if cur.peek_op("=") {
+                    self.assign(cur, word)
+                } else {
+                    Err("unknown statement")
+                }
+            }
+        }
+    }
+
+    fn assign(&mut self, cur: &mut Cursor<'a>, name: &str) -> ScriptResult<Flow> {
+        if FUNCTIONS.contains(&name) {
+            return Err("cannot assign to a function");
+        }
+        cur.expect_op("=")?;
+        let value = self.expr(cur)?;
+        self.set_var(name, value)?;
+        Ok(Flow::Next)
+    }
+
+    fn stmt_print(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Flow> {
+        let mut out = String::new();
+        let mut newline = true;
+
+        while !cur.at_end_of_statement() {
+            newline = true;
+            if cur.eat_op(";") {
+                newline = false;
+                continue;
+            }
+            if cur.eat_op(",") {
+                out.push('\t');
+                newline = false;
+                continue;
+            }
+            out.push_str(&self.expr(cur)?.display());
+        }
+
+        if newline {
+            shell_writeln(&out);
+        } else {
+            shell_write(&out);
+        }
+        Ok(Flow::Next)
+    }
+
+    fn stmt_if(&mut self, cur: &mut Cursor<'a>, line_idx: usize) -> ScriptResult<Flow> {
+        let cond = self.expr(cur)?.is_true();
+        if !cur.eat_keyword("THEN") {
+            return Err("IF without THEN");
+        }
+
+        if cond {
+            // THEN <line number> is shorthand for THEN GOTO
+            if let Some(Tok::Num(n)) = cur.peek() {
+                return Ok(Flow::Jump((self.find_line(*n)?, 0)));
+            }
+            return Ok(Flow::Jump((line_idx, cur.pos)));
+        }
+
+        // Condition false: continue after ELSE, or skip the line
+        while let Some(tok) = cur.next() {
+            if matches!(tok, Tok::Ident(w) if w == "ELSE") {
+                if let Some(Tok::Num(n)) = cur.peek() {
+                    return Ok(Flow::Jump((self.find_line(*n)?, 0)));
+                }
+                return Ok(Flow::Jump((line_idx, cur.pos)));
+            }
+        }
+        Ok(Flow::SkipLine)
+    }
+
+    fn stmt_for(&mut self, cur: &mut Cursor<'a>, line_idx: usize) -> ScriptResult<Flow> {
+        let var = String::from(cur.expect_ident()?);
+        cur.expect_op("=")?;
+        let start = self.expr(cur)?.as_int()?;
+        if !cur.eat_keyword("TO") {
+            return Err("FOR without TO");
+        }
+        let limit = self.expr(cur)?.as_int()?;
+        let step = if cur.eat_keyword("STEP") {
+            self.expr(cur)?.as_int()?
+        } else {
+            1
+        };
+        if step == 0 {
+            return Err("FOR STEP of zero");
+        }
+
+        self.set_var(&var, Value::Int(start))?;
+
+        let done = if step > 0 { start > limit } else { start < limit };
+        if done {
+            return Ok(Flow::Jump(self.find_next(line_idx, cur.pos)?));
+        }
+
+        // Re-entering a loop that is already on the stack restarts it
+        self.for_stack.retain(|f| f.var != var);
+        if self.for_stack.len() >= MAX_STACK_DEPTH {
+            return Err("FOR nesting too deep");
+        }
+        self.for_stack.push(ForFrame { var, limit, step, body: (line_idx, cur.pos) });
+        Ok(Flow::Next)
+    }
+
+    /// Find the position after the NEXT matching a FOR at `from`
+    fn find_next(&self, line_idx: usize, pos: usize) -> ScriptResult<Pc> {
+        let mut depth = 0usize;
+        let mut at = (line_idx, pos);
+
+        while at.0 < self.lines.len() {
+            let toks = &self.lines[at.0].toks;
+            while at.1 < toks.len() {
+                if let Tok::Ident(w) = &toks[at.1] {
+                    if w == "FOR" {
+                        depth += 1;
+                    } else if w == "NEXT" {
+                        if depth == 0 {
+                            // Skip the optional loop variable
+                            let mut end = at.1 + 1;
+                            if let Some(Tok::Ident(_)) = toks.get(end) {
+                                end += 1;
+                            }
+                            return Ok((at.0, end));
+                        }
+                        depth -= 1;
+                    } else if w == "REM" {
+                        break;
+                    }
+                }
+                at.1 += 1;
+            }
+            at = (at.0 + 1, 0);
+        }
+
+        Err("FOR without NEXT")
+    }
+
+    fn stmt_next(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Flow> {
+        let named = match cur.peek() {
+            Some(Tok::Ident(w)) if w != "ELSE" => {
+                cur.pos += 1;
+                Some(w.as_str())
+            }
+            _ => None,
+        };
+
+        // NEXT X also closes any inner loops left open
+        if let Some(name) = named {
+            while let Some(top) = self.for_stack.last() {
+                if top.var == name {
+                    break;
+                }
+                self.for_stack.pop();
+            }
+        }
+
+        let frame = self.for_stack.last().ok_or("NEXT without FOR")?;
+        let var = frame.var.clone();
+        let (limit, step, body) = (frame.limit, frame.step, frame.body);
+
+        // A step past the end of the integer range is past any limit too
+        let Some(value) = self.get_var(&var).as_int()?.checked_add(step) else {
+            self.for_stack.pop();
+            return Ok(Flow::Next);
+        };
+        self.set_var(&var, Value::Int(value))?;
+
+        let done = if step > 0 { value > limit } else { value < limit };
+        if done {
+            self.for_stack.pop();
+            Ok(Flow::Next)
+        } else {
+            Ok(Flow::Jump(body))
+        }
+    }
+
+    /// Evaluate a string expression and resolve it as a shell path
+    fn path_arg(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<String> {
+        let raw = self.expr(cur)?.into_string()?;
+        Ok(String::from(super::commands::resolve_path(&raw)))
+    }
+
+    // ------------------------------------------------------------------------
+    // Expressions
+    // ------------------------------------------------------------------------
+
+    fn expr(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        let mut left = self.and_expr(cur)?;
+        while cur.eat_keyword("OR") {
+            let right = self.and_expr(cur)?;
+            left = bool_value(left.is_true() || right.is_true());
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        let mut left = self.not_expr(cur)?;
+        while cur.eat_keyword("AND") {
+            let right = self.not_expr(cur)?;
+            left = bool_value(left.is_true() && right.is_true());
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        if cur.eat_keyword("NOT") {
+            let v = self.not_expr(cur)?;
+            return Ok(bool_value(!v.is_true()));
+        }
+        self.compare(cur)
+    }
+
+    fn compare(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        let left = self.additive(cur)?;
+        let op = match cur.peek() {
+            Some(Tok::Op(op)) if matches!(*op, "=" | "<>" | "<" | ">" | "<=" | ">=") => *op,
+            _ => return Ok(left),
+        };
+        cur.pos += 1;
+        let right = self.additive(cur)?;
+
+        let ord = match (&left, &right) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            _ => return Err("type mismatch in comparison"),
+        };
+        let result = match op {
+            "=" => ord.is_eq(),
+            "<>" => ord.is_ne(),
+            "<" => ord.is_lt(),
+            ">" => ord.is_gt(),
+            "<=" => ord.is_le(),
+            _ => ord.is_ge(),
+        };
+        Ok(bool_value(result))
+    }
+
+    fn additive(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        let mut left = self.term(cur)?;
+        loop {
+            if cur.eat_op("+") {
+                let right = self.term(cur)?;
+                left = match (left, right) {
+                    (Value::Int(a), Value::Int(b)) => Value::Int(a.wrapping_add(b)),
+                    (a, b) => {
+                        // String concatenation; numbers are formatted
+                        let mut s = a.display();
+                        s.push_str(&b.display());
+                        if s.len() > MAX_STRING_LEN {
+                            return Err("string too long");
+                        }
+                        Value::Str(s)
+                    }
+                };
+            } else if cur.eat_op("-") {
+                let right = self.term(cur)?.as_int()?;
+                left = Value::Int(left.as_int()?.wrapping_sub(right));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn term(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        let mut left = self.unary(cur)?;
+        loop {
+            let op = if cur.eat_op("*") {
+                "*"
+            } else if cur.eat_op("/") {
+                "/"
+            } else if cur.eat_keyword("MOD") {
+                "MOD"
+            } else {
+                return Ok(left);
+            };
+            let a = left.as_int()?;
+            let b = self.unary(cur)?.as_int()?;
+            left = Value::Int(match op {
+                "*" => a.wrapping_mul(b),
+                _ if b == 0 => return Err("division by zero"),
+                "/" => a.wrapping_div(b),
+                _ => a.wrapping_rem(b),
+            });
+        }
+    }
+
+    fn unary(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        if cur.eat_op("-") {
+            let v = self.unary(cur)?.as_int()?;
+            return Ok(Value::Int(v.wrapping_neg()));
+        }
+        self.primary(cur)
+    }
+
+    fn primary(&mut self, cur: &mut Cursor<'a>) -> ScriptResult<Value> {
+        match cur.next() {
+            Some(Tok::Num(n)) => Ok(Value::Int(*n)),
+            Some(Tok::Str(s)) => Ok(Value::Str(s.clone())),
+            Some(Tok::Op("(")) => {
+                let v = self.expr(cur)?;
+                cur.expect_op(")")?;
+                Ok(v)
+            }
+            Some(Tok::Ident(name)) => {
+                if FUNCTIONS.contains(&name.as_str()) {
+                    let mut args = Vec::new();
+                    if cur.eat_op("(") {
+                        if !cur.eat_op(")") {
+                            loop {
+                                args.push(self.expr(cur)?);
+                                if cur.eat_op(")") {
+                                    break;
+                                }
+                                cur.expect_op(",")?;
+                            }
+                        }
+                    }
+                    self.call(name, args)
+                } else {
+                    Ok(self.get_var(name))
+                }
+            }
+            _ => Err("syntax error in expression"),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> ScriptResult<Value> {
+        let mut args = args.into_iter();
+        let mut arg = || args.next().ok_or("missing argument");
+
+        let v = match name {
+            "RND" => {
+                let n = arg()?.as_int()?;
+                if n <= 0 {
+                    return Err("RND range must be positive");
+                }
+                Value::Int(crate::rtl::kernel_random_bounded(n.min(u32::MAX as i64) as u32) as i64)
+            }
+            "ABS" => Value::Int(arg()?.as_int()?.wrapping_abs()),
+            "LEN" => Value::Int(arg()?.into_string()?.len() as i64),
+            "STR$" => Value::Str(format!("{}", arg()?.as_int()?)),
+            "VAL" => Value::Int(arg()?.into_string()?.trim().parse::<i64>().unwrap_or(0)),
+            "CHR$" => {
+                let c = arg()?.as_int()?;
+                Value::Str(String::from(char::from((c & 0x7F) as u8)))
+            }
+            "LEFT$" | "RIGHT$" => {
+                let s = arg()?.into_string()?;
+                let n = (arg()?.as_int()?.max(0) as usize).min(s.len());
+                let b = s.as_bytes();
+                let part = if name == "LEFT$" { &b[..n] } else { &b[b.len() - n..] };
+                Value::Str(String::from_utf8_lossy(part).into_owned())
+            }
+            "MID$" => {
+                // MID$(s, start[, len]) with a 1-based start
+                let s = arg()?.into_string()?;
+                let start = ((arg()?.as_int()?.max(1) - 1) as usize).min(s.len());
+                let len = match arg() {
+                    Ok(v) => v.as_int()?.max(0) as usize,
+                    Err(_) => s.len(),
+                };
+                let end = start.saturating_add(len).min(s.len());
+                Value::Str(String::from_utf8_lossy(&s.as_bytes()[start..end]).into_owned())
+            }
+            "TICKS" => Value::Int(crate::hal::timer::ke_query_tick_count() as i64),
+            "ERR" => Value::Int(self.last_err),
+            "FEXISTS" => {
+                let path = String::from(super::commands::resolve_path(&arg()?.into_string()?));
+                bool_value(fs::stat(&path).is_ok())
+            }
+            "FSIZE" => {
+                let path = String::from(super::commands::resolve_path(&arg()?.into_string()?));
+                Value::Int(fs::stat(&path).map(|i| i.size as i64).unwrap_or(-1))
+            }
+            "FREAD$" => {
+                let path = String::from(super::commands::resolve_path(&arg()?.into_string()?));
+                match read_file(&path, MAX_SCRIPT_SIZE) {
+                    Ok(data) => {
+                        self.last_err = 0;
+                        Value::Str(String::from_utf8_lossy(&data).into_owned())
+                    }
+                    Err(e) => {
+                        self.last_err = e as i64;
+                        Value::Str(String::new())
+                    }
+                }
+            }
+            "FCOUNT" => {
+                let path = String::from(super::commands::resolve_path(&arg()?.into_string()?));
+                let mut count = 0u32;
+                while fs::readdir(&path, count).is_ok() {
+                    count += 1;
+                }
+                Value::Int(count as i64)
+            }
+            "REG$" => {
+                let key = arg()?.into_string()?;
+                let value = arg()?.into_string()?;
+                let s = unsafe { crate::cm::cm_read_string(&key, &value) };
+                Value::Str(String::from(s.unwrap_or("")))
+            }
+            "REGDW" => {
+                let key = arg()?.into_string()?;
+                let value = arg()?.into_string()?;
+                let d = unsafe { crate::cm::cm_read_dword(&key, &value) };
+                Value::Int(d.map(|d| d as i64).unwrap_or(-1))
+            }
+            "PROCS" | "THREADS" => {
+                let (procs, threads) = process_counts();
+                Value::Int(if name == "PROCS" { procs } else { threads } as i64)
+            }
+            "PID" => {
+                let process = crate::ps::get_current_process();
+                let pid = if process.is_null() { 0 } else { unsafe { (*process).process_id() } };
+                Value::Int(pid as i64)
+            }
+            "PNAME$" => {
+                let pid = arg()?.as_int()?;
+                let process = unsafe { crate::ps::ps_lookup_process_by_id(pid as u32) }
+                    as *mut crate::ps::EProcess;
+                if process.is_null() {
+                    Value::Str(String::new())
+                } else {
+                    let name = unsafe { (*process).image_name() };
+                    Value::Str(String::from_utf8_lossy(name).into_owned())
+                }
+            }
+            _ => return Err("unknown function"),
+        };
+
+        Ok(v)
+    }
+}
+
+// ============================================================================
+// Bindings
+// ============================================================================
+
+fn fs_status(result: Result<(), fs::FsStatus>) -> i64 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e as i64,
+    }
+}
+
+/// Create (or append to) a file and write `data`
+fn fs_write_file(path: &str, data: &[u8], append: bool) -> i64 {
+    let handle = if append {
+        match fs::open(path, 0) {
+            Ok(h) => {
+                if let Err(e) = fs::seek(h, 0, fs::SeekWhence::End) {
+                    let _ = fs::close(h);
+                    return e as i64;
+                }
+                h
+            }
+            Err(fs::FsStatus::NotFound) => match fs::create(path, 0) {
+                Ok(h) => h,
+                Err(e) => return e as i64,
+            },
+            Err(e) => return e as i64,
+        }
+    } else {
+        match fs::create(path, 0) {
+            Ok(h) => h,
+            Err(e) => return e as i64,
+        }
+    };
+
+    let mut status = 0;
+    if !data.is_empty() {
+        if let Err(e) = fs::write(handle, data) {
+            status = e as i64;
+        }
+    }
+    let _ = fs::close(handle);
+    status
+}
+
+/// Read a whole file, up to `limit` bytes
+fn read_file(path: &str, limit: usize) -> Result<Vec<u8>, fs::FsStatus> {
+    let handle = fs::open(path, 0)?;
+    let mut data = Vec::with_capacity(limit);
+    let mut chunk = [0u8; 512];
+
+    loop {
+        match fs::read(handle, &mut chunk) {
+            Ok(0) | Err(fs::FsStatus::EndOfFile) => break,
+            Ok(n) => {
+                if data.len() + n > limit {
+                    let _ = fs::close(handle);
+                    return Err(fs::FsStatus::InvalidParameter);
+                }
+                data.extend_from_slice(&chunk[..n]);
+            }
+            Err(e) => {
+                let _ = fs::close(handle);
+                return Err(e);
+            }
+        }
+    }
+
+    let _ = fs::close(handle);
+    Ok(data)
+}
+
+/// Count active processes and their threads
+fn process_counts() -> (u32, u32) {
+    let mut procs = 0;
+    let mut threads = 0;
+    unsafe {
+        let head = crate::ps::get_active_process_list();
+        let mut entry = (*head).flink;
+        while entry != head && procs < crate::ps::MAX_PROCESSES as u32 {
+            let process = crate::containing_record!(entry, crate::ps::EProcess, active_process_links);
+            procs += 1;
+            threads += (*process).thread_count();
+            entry = (*entry).flink;
+        }
+    }
+    (procs, threads)
+}
+
+// ============================================================================
+// Entry Points
+// ============================================================================
+
+/// Tokenize a script into lines
+fn parse_program(source: &str) -> Result<Vec<Line>, (String, usize)> {
+    let mut lines = Vec::new();
+
+    for (i, text) in source.lines().enumerate() {
+        let mut toks = tokenize(text.trim()).map_err(|e| (String::from(e), i + 1))?;
+        if toks.is_empty() {
+            continue;
+        }
+        let number = match toks.first() {
+            Some(Tok::Num(n)) => Some(*n),
+            _ => None,
+        };
+        if number.is_some() {
+            toks.remove(0);
+        }
+        if lines.len() >= MAX_SCRIPT_LINES {
+            return Err((String::from("script too long"), i + 1));
+        }
+        lines.push(Line { number, source_line: i + 1, toks });
+    }
+
+    Ok(lines)
+}
+
+/// Run script source text
+pub fn run_source(source: &str) -> ScriptOutcome {
+    let lines = match parse_program(source) {
+        Ok(l) => l,
+        Err(e) => return ScriptOutcome { steps: 0, asserts: 0, error: Some(e) },
+    };
+
+    let mut interp = Interp::new(&lines);
+    let result = interp.run();
+    let error = result.err().map(|msg| {
+        let line = lines.get(interp.line).map(|l| l.source_line).unwrap_or(0);
+        (String::from(msg), line)
+    });
+
+    ScriptOutcome { steps: interp.steps, asserts: interp.asserts, error }
+}
+
+/// Run a script file from the file system
+pub fn run_file(path: &str) -> Result<ScriptOutcome, fs::FsStatus> {
+    let data = read_file(path, MAX_SCRIPT_SIZE)?;
+    let source = core::str::from_utf8(&data).map_err(|_| fs::FsStatus::InvalidParameter)?;
+    Ok(run_source(source))
+}
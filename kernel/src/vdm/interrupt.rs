@@ -205,6 +205,11 @@ struct PendingInterrupt {
 static PENDING_QUEUE: SpinLock<alloc::vec::Vec<PendingInterrupt>> =
     SpinLock::new(alloc::vec::Vec::new());
 
+/// Release the pending queue's spare capacity (heap compaction)
+pub fn vdm_compact_pending_queue() -> usize {
+    crate::mm::defrag::mm_shrink_vec(&mut PENDING_QUEUE.lock())
+}
+
 // ============================================================================
 // Interrupt Functions
 // ============================================================================
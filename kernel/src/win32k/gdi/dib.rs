@@ -385,6 +385,11 @@ impl DibTable {
 
 static DC_STRETCH_MODE: SpinLock<Vec<(GdiHandle, u32)>> = SpinLock::new(Vec::new());
 
+/// Release the stretch mode table's spare capacity (heap compaction)
+pub fn compact_stretch_modes() -> usize {
+    crate::mm::defrag::mm_shrink_vec(&mut DC_STRETCH_MODE.lock())
+}
+
 // ============================================================================
 // DIB API
 // ============================================================================
@@ -292,6 +292,11 @@ struct DcIcmEntry {
 
 static DC_ICM_STATE: SpinLock<Vec<DcIcmEntry>> = SpinLock::new(Vec::new());
 
+/// Release the ICM state table's spare capacity (heap compaction)
+pub fn compact_icm_state() -> usize {
+    crate::mm::defrag::mm_shrink_vec(&mut DC_ICM_STATE.lock())
+}
+
 // ============================================================================
 // ICM API
 // ============================================================================
@@ -158,9 +158,7 @@ pub fn run() {
         crate::serial_println!("[EXPLORER] Restart #{} requested, restarting...", count + 1);
 
         // Small delay before restart
-        for _ in 0..100 {
-            unsafe { core::arch::asm!("pause"); }
-        }
+        crate::ex::ex_sleep(10);
 
         // Restart
         if start() {
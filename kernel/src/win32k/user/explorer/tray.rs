@@ -188,33 +188,37 @@ pub fn message_loop() {
     // Draw initial cursor
     cursor::draw_cursor();
 
-    // Track time for periodic updates
-    let mut tick_count: u64 = 0;
+    // Track time for periodic updates (milliseconds)
     let mut last_clock_update: u64 = 0;
-    const CLOCK_UPDATE_INTERVAL: u64 = 5000;
+    const CLOCK_UPDATE_INTERVAL: u64 = 1000;
+    // Sleep between polls when there is no input (~60 Hz)
+    const IDLE_POLL_INTERVAL: u32 = 16;
 
     loop {
-        tick_count = tick_count.wrapping_add(1);
+        let mut busy = false;
 
         // Process keyboard events (raw scancodes)
         if let Some(scancode) = keyboard::try_read_scancode() {
             process_keyboard_input(scancode);
+            busy = true;
         }
 
         // Process mouse events
         if let Some(event) = mouse::poll_event() {
             process_mouse_event(event);
+            busy = true;
         }
 
         // Periodic clock update
+        let tick_count = crate::hal::apic::get_tick_count();
         if tick_count - last_clock_update >= CLOCK_UPDATE_INTERVAL {
             last_clock_update = tick_count;
             traynot::update_clock();
         }
 
-        // Small delay to prevent busy-waiting
-        for _ in 0..100 {
-            core::hint::spin_loop();
+        // Drain queued input at full speed, otherwise sleep until the next poll
+        if !busy {
+            crate::ex::ex_sleep(IDLE_POLL_INTERVAL);
         }
     }
 }
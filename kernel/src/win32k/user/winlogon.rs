@@ -401,8 +401,12 @@ pub fn shutdown(reboot: bool) -> ! {
 
     drop(session);
 
-    // Call HAL to perform actual shutdown/restart
-    crate::hal::power::power_shutdown(reboot)
+    // Stop services and flush to disk before the power-off
+    if reboot {
+        crate::po::restart()
+    } else {
+        crate::po::shutdown()
+    }
 }
 
 // ============================================================================
@@ -3,7 +3,7 @@
 //! Defines structures for WMI data blocks that drivers use to expose
 //! management information.
 
-use super::WmiError;
+use super::{WmiBlockLayout, WmiError};
 use crate::etw::Guid;
 use alloc::boxed::Box;
 use alloc::string::String;
@@ -248,6 +248,8 @@ pub struct WmiDataBlock {
     pub set_callback: Option<WmiSetCallback>,
     /// Method callback
     pub method_callback: Option<WmiMethodCallback>,
+    /// Class name and field layout, for queries by name
+    pub layout: Option<&'static WmiBlockLayout>,
 }
 
 impl WmiDataBlock {
@@ -264,6 +266,7 @@ impl WmiDataBlock {
             query_callback: None,
             set_callback: None,
             method_callback: None,
+            layout: None,
         }
     }
 
@@ -297,6 +300,13 @@ impl WmiDataBlock {
         self
     }
 
+    /// Set class name and field layout (and the fixed block size)
+    pub fn with_layout(mut self, layout: &'static WmiBlockLayout) -> Self {
+        self.layout = Some(layout);
+        self.data_block_size = layout.size() as u32;
+        self
+    }
+
     /// Set query callback
     pub fn with_query<F>(mut self, callback: F) -> Self
     where
@@ -334,6 +344,7 @@ impl core::fmt::Debug for WmiDataBlock {
             .field("provider_id", &self.provider_id)
             .field("flags", &self.flags)
             .field("instance_count", &self.instance_count)
+            .field("class", &self.layout.map(|l| l.class_name))
             .finish()
     }
 }
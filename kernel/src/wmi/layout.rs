@@ -0,0 +1,401 @@
+//! WMI Data Block Layouts
+//!
+//! A layout names a data block's class and describes each field's name,
+//! type and offset, the way a driver's MOF describes it to the WMI
+//! service. With a layout attached, a block can be queried and set by
+//! class and property name (`wmi_query_instance`, `wmi_set_item`)
+//! without a private IOCTL or decoder per driver.
+//!
+//! ```ignore
+//! static LAYOUT: WmiBlockLayout = WmiBlockLayout::new("MyDriver_Stats", "My statistics", &[
+//!     WmiField::new("Requests", WmiFieldType::Uint64, 0).writable(),
+//!     WmiField::new("Ready", WmiFieldType::Boolean, 8),
+//! ]);
+//! ```
+
+use super::WmiError;
+use alloc::string::String;
+
+extern crate alloc;
+
+/// Type of a data block field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmiFieldType {
+    Boolean,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    /// NUL-padded ASCII of the given size
+    String(u16),
+}
+
+impl WmiFieldType {
+    /// Bytes the field occupies in the block
+    pub const fn size(self) -> usize {
+        match self {
+            WmiFieldType::Boolean | WmiFieldType::Uint8 => 1,
+            WmiFieldType::Uint16 => 2,
+            WmiFieldType::Uint32 | WmiFieldType::Sint32 => 4,
+            WmiFieldType::Uint64 | WmiFieldType::Sint64 => 8,
+            WmiFieldType::String(size) => size as usize,
+        }
+    }
+
+    /// MOF type name
+    pub fn name(self) -> &'static str {
+        match self {
+            WmiFieldType::Boolean => "boolean",
+            WmiFieldType::Uint8 => "uint8",
+            WmiFieldType::Uint16 => "uint16",
+            WmiFieldType::Uint32 => "uint32",
+            WmiFieldType::Uint64 => "uint64",
+            WmiFieldType::Sint32 => "sint32",
+            WmiFieldType::Sint64 => "sint64",
+            WmiFieldType::String(_) => "string",
+        }
+    }
+}
+
+/// A decoded field value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WmiValue {
+    Boolean(bool),
+    Unsigned(u64),
+    Signed(i64),
+    String(String),
+}
+
+impl core::fmt::Display for WmiValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WmiValue::Boolean(b) => f.write_str(if *b { "TRUE" } else { "FALSE" }),
+            WmiValue::Unsigned(n) => write!(f, "{}", n),
+            WmiValue::Signed(n) => write!(f, "{}", n),
+            WmiValue::String(s) => f.write_str(s),
+        }
+    }
+}
+
+impl From<bool> for WmiValue {
+    fn from(value: bool) -> Self {
+        WmiValue::Boolean(value)
+    }
+}
+
+impl From<u64> for WmiValue {
+    fn from(value: u64) -> Self {
+        WmiValue::Unsigned(value)
+    }
+}
+
+impl From<u32> for WmiValue {
+    fn from(value: u32) -> Self {
+        WmiValue::Unsigned(value as u64)
+    }
+}
+
+impl From<u8> for WmiValue {
+    fn from(value: u8) -> Self {
+        WmiValue::Unsigned(value as u64)
+    }
+}
+
+impl From<i64> for WmiValue {
+    fn from(value: i64) -> Self {
+        WmiValue::Signed(value)
+    }
+}
+
+impl From<&str> for WmiValue {
+    fn from(value: &str) -> Self {
+        WmiValue::String(String::from(value))
+    }
+}
+
+/// One field of a data block
+#[derive(Debug, Clone, Copy)]
+pub struct WmiField {
+    /// Property name
+    pub name: &'static str,
+    pub field_type: WmiFieldType,
+    /// Byte offset in the block
+    pub offset: u32,
+    /// Field may be changed through `wmi_set_item`
+    pub writable: bool,
+}
+
+impl WmiField {
+    pub const fn new(name: &'static str, field_type: WmiFieldType, offset: u32) -> Self {
+        Self { name, field_type, offset, writable: false }
+    }
+
+    /// Mark the field writable
+    pub const fn writable(mut self) -> Self {
+        self.writable = true;
+        self
+    }
+
+    fn bytes<'a>(&self, buffer: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.offset as usize;
+        buffer.get(start..start + self.field_type.size())
+    }
+
+    fn bytes_mut<'a>(&self, buffer: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let start = self.offset as usize;
+        buffer.get_mut(start..start + self.field_type.size())
+    }
+
+    /// Decode the field from a block buffer
+    pub fn read(&self, buffer: &[u8]) -> Option<WmiValue> {
+        let bytes = self.bytes(buffer)?;
+        let mut raw = [0u8; 8];
+        if !matches!(self.field_type, WmiFieldType::String(_)) {
+            raw[..bytes.len()].copy_from_slice(bytes);
+        }
+        let raw = u64::from_le_bytes(raw);
+        Some(match self.field_type {
+            WmiFieldType::Boolean => WmiValue::Boolean(raw != 0),
+            WmiFieldType::Sint32 => WmiValue::Signed(raw as u32 as i32 as i64),
+            WmiFieldType::Sint64 => WmiValue::Signed(raw as i64),
+            WmiFieldType::String(_) => WmiValue::String(
+                bytes.iter().take_while(|&&b| b != 0).map(|&b| b as char).collect(),
+            ),
+            _ => WmiValue::Unsigned(raw),
+        })
+    }
+
+    /// Encode a value into the field of a block buffer
+    pub fn write(&self, buffer: &mut [u8], value: &WmiValue) -> Result<(), WmiError> {
+        let field_type = self.field_type;
+        let bytes = self.bytes_mut(buffer).ok_or(WmiError::BufferTooSmall)?;
+        let raw = match (field_type, value) {
+            (WmiFieldType::String(_), WmiValue::String(s)) => {
+                if s.len() > bytes.len() {
+                    return Err(WmiError::InvalidParameter);
+                }
+                bytes.fill(0);
+                bytes[..s.len()].copy_from_slice(s.as_bytes());
+                return Ok(());
+            }
+            (WmiFieldType::String(_), _) => return Err(WmiError::InvalidParameter),
+            (_, WmiValue::Boolean(b)) => *b as u64,
+            (_, WmiValue::Unsigned(n)) => *n,
+            (_, WmiValue::Signed(n)) => *n as u64,
+            (_, WmiValue::String(_)) => return Err(WmiError::InvalidParameter),
+        };
+
+        // Reject values the field cannot hold
+        let size = bytes.len();
+        let fits = match (field_type, value) {
+            (WmiFieldType::Boolean, _) => raw <= 1,
+            (WmiFieldType::Sint32, WmiValue::Signed(n)) => i32::try_from(*n).is_ok(),
+            (WmiFieldType::Sint32, _) => raw <= i32::MAX as u64,
+            (WmiFieldType::Sint64, WmiValue::Unsigned(n)) => *n <= i64::MAX as u64,
+            (WmiFieldType::Sint64, _) => true,
+            (_, WmiValue::Signed(n)) if *n < 0 => false,
+            _ => size == 8 || raw >> (size * 8) == 0,
+        };
+        if !fits {
+            return Err(WmiError::InvalidParameter);
+        }
+        bytes.copy_from_slice(&raw.to_le_bytes()[..size]);
+        Ok(())
+    }
+
+    /// Parse command-line text as a value of this field's type
+    ///
+    /// Numbers may be decimal or `0x` hex; booleans are TRUE/FALSE or 1/0.
+    pub fn parse(&self, text: &str) -> Result<WmiValue, WmiError> {
+        let text = text.trim().trim_matches('"');
+        let unsigned = || match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => text.parse::<u64>().ok(),
+        };
+        match self.field_type {
+            WmiFieldType::Boolean => match text {
+                t if t.eq_ignore_ascii_case("true") || t == "1" => Ok(WmiValue::Boolean(true)),
+                t if t.eq_ignore_ascii_case("false") || t == "0" => Ok(WmiValue::Boolean(false)),
+                _ => Err(WmiError::InvalidParameter),
+            },
+            WmiFieldType::Sint32 | WmiFieldType::Sint64 => text
+                .parse::<i64>()
+                .ok()
+                .or_else(|| unsigned().map(|n| n as i64))
+                .map(WmiValue::Signed)
+                .ok_or(WmiError::InvalidParameter),
+            WmiFieldType::String(_) => Ok(WmiValue::String(String::from(text))),
+            _ => unsigned().map(WmiValue::Unsigned).ok_or(WmiError::InvalidParameter),
+        }
+    }
+}
+
+/// Class name, description and fields of a data block
+#[derive(Debug, Clone, Copy)]
+pub struct WmiBlockLayout {
+    /// Class name the block is queried by
+    pub class_name: &'static str,
+    pub description: &'static str,
+    pub fields: &'static [WmiField],
+}
+
+impl WmiBlockLayout {
+    pub const fn new(
+        class_name: &'static str,
+        description: &'static str,
+        fields: &'static [WmiField],
+    ) -> Self {
+        Self { class_name, description, fields }
+    }
+
+    /// Size of one instance's block
+    pub fn size(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|f| f.offset as usize + f.field_type.size())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Look up a field by property name, ignoring case
+    pub fn field(&self, name: &str) -> Option<&'static WmiField> {
+        self.fields.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Store a value into a named field; for use by query callbacks
+    pub fn put(&self, buffer: &mut [u8], name: &str, value: impl Into<WmiValue>) -> Result<(), WmiError> {
+        self.field(name)
+            .ok_or(WmiError::InvalidParameter)?
+            .write(buffer, &value.into())
+    }
+
+    /// Read a named field; for use by set callbacks
+    pub fn get(&self, buffer: &[u8], name: &str) -> Option<WmiValue> {
+        self.field(name)?.read(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FIELDS: [WmiField; 5] = [
+        WmiField::new("Requests", WmiFieldType::Uint64, 0).writable(),
+        WmiField::new("Ready", WmiFieldType::Boolean, 8),
+        WmiField::new("Level", WmiFieldType::Uint16, 10).writable(),
+        WmiField::new("Delta", WmiFieldType::Sint32, 12),
+        WmiField::new("Name", WmiFieldType::String(8), 16),
+    ];
+
+    static LAYOUT: WmiBlockLayout = WmiBlockLayout::new("Test_Stats", "Test statistics", &FIELDS);
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(WmiFieldType::Boolean.size(), 1);
+        assert_eq!(WmiFieldType::Sint32.size(), 4);
+        assert_eq!(WmiFieldType::Uint64.size(), 8);
+        assert_eq!(WmiFieldType::String(12).size(), 12);
+        assert_eq!(LAYOUT.size(), 24);
+        assert_eq!(WmiBlockLayout::new("Empty", "", &[]).size(), 0);
+    }
+
+    #[test]
+    fn test_field_lookup() {
+        assert_eq!(LAYOUT.field("requests").map(|f| f.offset), Some(0));
+        assert_eq!(LAYOUT.field("NAME").map(|f| f.offset), Some(16));
+        assert!(LAYOUT.field("Missing").is_none());
+        assert!(LAYOUT.field("Requests").unwrap().writable);
+        assert!(!LAYOUT.field("Ready").unwrap().writable);
+    }
+
+    #[test]
+    fn test_put_get() {
+        let mut buffer = [0u8; 24];
+        LAYOUT.put(&mut buffer, "Requests", 0x1122_3344_5566_7788u64).unwrap();
+        LAYOUT.put(&mut buffer, "Ready", true).unwrap();
+        LAYOUT.put(&mut buffer, "Level", 0x1234u32).unwrap();
+        LAYOUT.put(&mut buffer, "Delta", -2i64).unwrap();
+        LAYOUT.put(&mut buffer, "Name", "disk0").unwrap();
+
+        assert_eq!(&buffer[..8], &0x1122_3344_5566_7788u64.to_le_bytes());
+        assert_eq!(&buffer[8..12], &[1, 0, 0x34, 0x12]);
+        assert_eq!(&buffer[12..16], &(-2i32).to_le_bytes());
+        assert_eq!(&buffer[16..24], b"disk0\0\0\0");
+
+        assert_eq!(LAYOUT.get(&buffer, "Requests"), Some(WmiValue::Unsigned(0x1122_3344_5566_7788)));
+        assert_eq!(LAYOUT.get(&buffer, "Ready"), Some(WmiValue::Boolean(true)));
+        assert_eq!(LAYOUT.get(&buffer, "Level"), Some(WmiValue::Unsigned(0x1234)));
+        assert_eq!(LAYOUT.get(&buffer, "Delta"), Some(WmiValue::Signed(-2)));
+        assert_eq!(LAYOUT.get(&buffer, "Name"), Some(WmiValue::from("disk0")));
+        assert_eq!(LAYOUT.get(&buffer, "Missing"), None);
+    }
+
+    #[test]
+    fn test_string_overwrite() {
+        let mut buffer = [0u8; 24];
+        LAYOUT.put(&mut buffer, "Name", "longname").unwrap();
+        LAYOUT.put(&mut buffer, "Name", "ab").unwrap();
+        assert_eq!(&buffer[16..24], b"ab\0\0\0\0\0\0");
+        assert_eq!(LAYOUT.put(&mut buffer, "Name", "too long!"), Err(WmiError::InvalidParameter));
+        assert_eq!(LAYOUT.put(&mut buffer, "Name", 5u32), Err(WmiError::InvalidParameter));
+        assert_eq!(LAYOUT.put(&mut buffer, "Level", "5"), Err(WmiError::InvalidParameter));
+    }
+
+    #[test]
+    fn test_range_checks() {
+        let mut buffer = [0u8; 24];
+        assert_eq!(LAYOUT.put(&mut buffer, "Level", 0x1_0000u32), Err(WmiError::InvalidParameter));
+        assert_eq!(LAYOUT.put(&mut buffer, "Level", -1i64), Err(WmiError::InvalidParameter));
+        assert_eq!(LAYOUT.put(&mut buffer, "Ready", 2u8), Err(WmiError::InvalidParameter));
+        assert_eq!(LAYOUT.put(&mut buffer, "Delta", i32::MAX as i64 + 1), Err(WmiError::InvalidParameter));
+        assert_eq!(LAYOUT.put(&mut buffer, "Delta", 0x8000_0000u32), Err(WmiError::InvalidParameter));
+        assert!(LAYOUT.put(&mut buffer, "Delta", i32::MIN as i64).is_ok());
+        assert!(LAYOUT.put(&mut buffer, "Requests", u64::MAX).is_ok());
+        assert_eq!(buffer, {
+            let mut expected = [0u8; 24];
+            expected[..8].fill(0xFF);
+            expected[12..16].copy_from_slice(&i32::MIN.to_le_bytes());
+            expected
+        });
+
+        let sint64 = WmiField::new("Big", WmiFieldType::Sint64, 0);
+        assert_eq!(sint64.write(&mut buffer, &WmiValue::Unsigned(u64::MAX)), Err(WmiError::InvalidParameter));
+        assert!(sint64.write(&mut buffer, &WmiValue::Signed(i64::MIN)).is_ok());
+        assert_eq!(sint64.read(&buffer), Some(WmiValue::Signed(i64::MIN)));
+    }
+
+    #[test]
+    fn test_short_buffer() {
+        let mut buffer = [0u8; 12];
+        assert_eq!(LAYOUT.put(&mut buffer, "Delta", 1i64), Err(WmiError::BufferTooSmall));
+        assert_eq!(LAYOUT.get(&buffer, "Name"), None);
+        assert!(LAYOUT.put(&mut buffer, "Level", 1u8).is_ok());
+    }
+
+    #[test]
+    fn test_parse() {
+        let field = |name| LAYOUT.field(name).unwrap();
+        assert_eq!(field("Requests").parse("42"), Ok(WmiValue::Unsigned(42)));
+        assert_eq!(field("Requests").parse(" 0x1F "), Ok(WmiValue::Unsigned(0x1F)));
+        assert_eq!(field("Requests").parse("-1"), Err(WmiError::InvalidParameter));
+        assert_eq!(field("Ready").parse("TRUE"), Ok(WmiValue::Boolean(true)));
+        assert_eq!(field("Ready").parse("0"), Ok(WmiValue::Boolean(false)));
+        assert_eq!(field("Ready").parse("yes"), Err(WmiError::InvalidParameter));
+        assert_eq!(field("Delta").parse("-7"), Ok(WmiValue::Signed(-7)));
+        assert_eq!(field("Delta").parse("0x10"), Ok(WmiValue::Signed(16)));
+        assert_eq!(field("Name").parse("\"disk 1\""), Ok(WmiValue::from("disk 1")));
+    }
+
+    #[test]
+    fn test_display() {
+        use alloc::string::ToString;
+        assert_eq!(WmiValue::Boolean(false).to_string(), "FALSE");
+        assert_eq!(WmiValue::Signed(-3).to_string(), "-3");
+        assert_eq!(WmiValue::Unsigned(9).to_string(), "9");
+        assert_eq!(WmiValue::from("x").to_string(), "x");
+        assert_eq!(WmiFieldType::String(4).name(), "string");
+        assert_eq!(WmiFieldType::Sint64.name(), "sint64");
+    }
+}
@@ -21,21 +21,29 @@
 //!                         └─────────────────────────────┘
 //! ```
 //!
+//! Blocks registered with a `WmiBlockLayout` are also reachable by class
+//! name: `wmi_query_instance` decodes an instance into named properties
+//! and `wmi_set_item` changes one, which is what `wmic <class>` uses.
+//!
 //! Based on Windows Server 2003 base/ntos/wmi/
 
 pub mod data;
 pub mod irp;
+pub mod layout;
 pub mod provider;
 
 pub use data::*;
 pub use irp::*;
+pub use layout::*;
 pub use provider::*;
 
 use crate::etw::Guid;
 use crate::ke::SpinLock;
 use alloc::collections::BTreeMap;
 use alloc::collections::VecDeque;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 
@@ -324,6 +332,110 @@ pub fn wmi_execute_method(
     }
 }
 
+// ============================================================================
+// Queries by Class Name
+// ============================================================================
+
+/// A registered data block, as listed by `wmi_enumerate_blocks`
+#[derive(Debug, Clone)]
+pub struct WmiBlockInfo {
+    pub guid: Guid,
+    pub provider_id: u32,
+    pub flags: WmiDataBlockFlags,
+    pub instance_count: u32,
+    /// Class name and fields, if the block was registered with a layout
+    pub layout: Option<&'static WmiBlockLayout>,
+}
+
+/// List every registered data block
+pub fn wmi_enumerate_blocks() -> Vec<WmiBlockInfo> {
+    if !WMI_INITIALIZED.load(Ordering::SeqCst) {
+        return Vec::new();
+    }
+
+    let state = get_wmi_state();
+    let blocks = state.data_blocks.lock();
+    blocks
+        .values()
+        .map(|b| WmiBlockInfo {
+            guid: b.guid,
+            provider_id: b.provider_id,
+            flags: b.flags,
+            instance_count: b.instance_count,
+            layout: b.layout,
+        })
+        .collect()
+}
+
+/// Find a data block by class name, ignoring case
+pub fn wmi_find_class(class_name: &str) -> Option<WmiBlockInfo> {
+    wmi_enumerate_blocks()
+        .into_iter()
+        .find(|b| b.layout.is_some_and(|l| l.class_name.eq_ignore_ascii_case(class_name)))
+}
+
+/// Name of an instance: its static name, else `<base><index>`, else the index
+pub fn wmi_instance_name(guid: &Guid, instance_index: u32) -> String {
+    if WMI_INITIALIZED.load(Ordering::SeqCst) {
+        let state = get_wmi_state();
+        let blocks = state.data_blocks.lock();
+        if let Some(block) = blocks.get(guid) {
+            if let Some(name) = block.instance_names.get(instance_index as usize) {
+                return name.clone();
+            }
+            if !block.instance_base_name.is_empty() {
+                return alloc::format!("{}{}", block.instance_base_name, instance_index);
+            }
+        }
+    }
+    alloc::format!("{}", instance_index)
+}
+
+/// Query one instance of a class and decode it into named properties
+pub fn wmi_query_instance(
+    class_name: &str,
+    instance_index: u32,
+) -> Result<Vec<(&'static WmiField, WmiValue)>, WmiError> {
+    let info = wmi_find_class(class_name).ok_or(WmiError::GuidNotFound)?;
+    let layout = info.layout.ok_or(WmiError::NotSupported)?;
+
+    let mut buffer = vec![0u8; layout.size()];
+    let length = wmi_query_data_block(&info.guid, instance_index, &mut buffer)?;
+    buffer.truncate(length);
+
+    Ok(layout
+        .fields
+        .iter()
+        .filter_map(|field| field.read(&buffer).map(|value| (field, value)))
+        .collect())
+}
+
+/// Change one property of a class instance
+///
+/// The block is queried, the property patched and the whole block handed
+/// to the provider's set callback, so providers see a complete instance.
+pub fn wmi_set_item(
+    class_name: &str,
+    instance_index: u32,
+    property: &str,
+    value: &str,
+) -> Result<(), WmiError> {
+    let info = wmi_find_class(class_name).ok_or(WmiError::GuidNotFound)?;
+    let layout = info.layout.ok_or(WmiError::NotSupported)?;
+    let field = layout.field(property).ok_or(WmiError::InvalidParameter)?;
+    if !field.writable {
+        return Err(WmiError::ReadOnly);
+    }
+
+    let mut buffer = vec![0u8; layout.size()];
+    let length = wmi_query_data_block(&info.guid, instance_index, &mut buffer)?;
+    if length < layout.size() {
+        return Err(WmiError::BufferTooSmall);
+    }
+    field.write(&mut buffer, &field.parse(value)?)?;
+    wmi_set_data_block(&info.guid, instance_index, &buffer)
+}
+
 /// Register a WMI provider
 pub fn wmi_register_provider(
     name: &str,
@@ -350,8 +462,8 @@ pub fn wmi_register_provider(
         provider_guids.insert(provider_id, Vec::new());
     }
 
-    // Track device to provider mapping
-    {
+    // Track device to provider mapping (drivers without a device object pass 0)
+    if device_object != 0 {
         let mut devices = state.registered_devices.lock();
         devices.insert(device_object, provider_id);
     }
@@ -434,6 +546,22 @@ pub enum WmiError {
     InsufficientResources,
 }
 
+impl WmiError {
+    /// Description for command-line tools
+    pub fn description(&self) -> &'static str {
+        match self {
+            WmiError::GuidNotFound => "Invalid class",
+            WmiError::InvalidInstance => "Not found",
+            WmiError::BufferTooSmall => "Buffer too small",
+            WmiError::NotSupported => "Not supported",
+            WmiError::ReadOnly => "Property is read-only",
+            WmiError::InvalidParameter => "Invalid parameter",
+            WmiError::ProviderNotFound => "Provider not found",
+            WmiError::InsufficientResources => "Insufficient resources",
+        }
+    }
+}
+
 /// WMI statistics
 #[derive(Debug, Default, Clone)]
 pub struct WmiStatistics {
@@ -0,0 +1,66 @@
+#!/bin/bash
+# Run Nostalgia OS in QEMU with legacy BIOS firmware, booted by GRUB via Multiboot2
+
+set -e
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+cd "$SCRIPT_DIR"
+
+TARGET_DIR="target"
+ISO_DIR="$TARGET_DIR/iso"
+ISO="$TARGET_DIR/nostalgia-bios.iso"
+
+if ! command -v grub-mkrescue > /dev/null; then
+    echo "grub-mkrescue not found. Install with: sudo apt install grub-pc-bin grub-common xorriso mtools"
+    exit 1
+fi
+
+# Build first
+./build.sh
+
+# Create the ISO tree (kernel ELF, optional initrd, GRUB config)
+echo "Setting up GRUB ISO..."
+rm -rf "$ISO_DIR"
+mkdir -p "$ISO_DIR/boot/grub"
+cp "$TARGET_DIR/x86_64-unknown-none/release/kernel" "$ISO_DIR/boot/kernel"
+
+# Kernel command line and initrd (e.g. CMDLINE="..." INITRD=initrd.img)
+MODULE_LINE=""
+if [ -n "$INITRD" ]; then
+    cp "$INITRD" "$ISO_DIR/boot/initrd"
+    MODULE_LINE="    module2 /boot/initrd initrd"
+fi
+
+cat > "$ISO_DIR/boot/grub/grub.cfg" << EOF
+set timeout=0
+set default=0
+
+menuentry "Nostalgia OS" {
+    multiboot2 /boot/kernel $CMDLINE
+$MODULE_LINE
+    boot
+}
+EOF
+
+grub-mkrescue -o "$ISO" "$ISO_DIR" 2> /dev/null
+
+echo ""
+echo "Starting QEMU..."
+echo ""
+
+# The CD-ROM is the secondary slave so disk.img keeps the IDE slot run-qemu.sh uses
+QEMU_ARGS=(
+    -machine pc,accel=tcg
+    -m 256M
+    -drive file="$ISO",format=raw,if=ide,index=3,media=cdrom
+    -boot d
+    -serial stdio
+    -no-reboot
+)
+
+if [ -f "disk.img" ]; then
+    echo "Adding test disk: disk.img as IDE primary master"
+    QEMU_ARGS+=(-drive file=disk.img,format=raw,if=ide,index=2,media=disk)
+fi
+
+qemu-system-x86_64 "${QEMU_ARGS[@]}" "$@"
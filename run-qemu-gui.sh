@@ -34,6 +34,15 @@ cp "$TARGET_DIR/x86_64-unknown-uefi/release/uefi-loader.efi" \
 cp "$TARGET_DIR/x86_64-unknown-none/release/kernel" \
    "$ESP_DIR/EFI/nostalgia/kernel.bin"
 
+# Loader settings (e.g. RESOLUTION=1280x1024 or RESOLUTION=max, CMDLINE="...")
+rm -f "$ESP_DIR/EFI/nostalgia/boot.cfg"
+if [ -n "$RESOLUTION" ]; then
+    echo "resolution=$RESOLUTION" >> "$ESP_DIR/EFI/nostalgia/boot.cfg"
+fi
+if [ -n "$CMDLINE" ]; then
+    echo "cmdline=$CMDLINE" >> "$ESP_DIR/EFI/nostalgia/boot.cfg"
+fi
+
 # Create a fresh copy of OVMF_VARS (for UEFI variable storage)
 if [ ! -f "$OVMF_VARS_COPY" ]; then
     echo "Creating OVMF variables file..."
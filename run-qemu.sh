@@ -42,6 +42,20 @@ cp "$TARGET_DIR/x86_64-unknown-uefi/release/uefi-loader.efi" \
 cp "$TARGET_DIR/x86_64-unknown-none/release/kernel" \
    "$ESP_DIR/EFI/nostalgia/kernel.bin"
 
+# Unattended self-tests (KTEST=all or KTEST=suite[,suite])
+if [ -n "$KTEST" ]; then
+    CMDLINE="${CMDLINE:+$CMDLINE }ktest=$KTEST"
+fi
+
+# Loader settings (e.g. RESOLUTION=1280x1024 or RESOLUTION=max, CMDLINE="...")
+rm -f "$ESP_DIR/EFI/nostalgia/boot.cfg"
+if [ -n "$RESOLUTION" ]; then
+    echo "resolution=$RESOLUTION" >> "$ESP_DIR/EFI/nostalgia/boot.cfg"
+fi
+if [ -n "$CMDLINE" ]; then
+    echo "cmdline=$CMDLINE" >> "$ESP_DIR/EFI/nostalgia/boot.cfg"
+fi
+
 echo ""
 echo "Starting QEMU..."
 echo ""
@@ -56,11 +70,58 @@ QEMU_ARGS=(
     -no-reboot
 )
 
-# Add test disk image if it exists (as IDE primary master on legacy ISA IDE)
+# Add test disk image if it exists (as IDE primary master on legacy ISA IDE,
+# or as a virtio-blk disk with DISK_IF=virtio)
 if [ -f "disk.img" ]; then
-    echo "Adding test disk: disk.img as IDE primary master"
-    # Use ISA IDE controller at standard ports 0x1F0/0x170
-    QEMU_ARGS+=(-drive file=disk.img,format=raw,if=ide,index=2,media=disk)
+    if [ "${DISK_IF:-ide}" = "virtio" ]; then
+        echo "Adding test disk: disk.img as virtio-blk"
+        QEMU_ARGS+=(
+            -drive file=disk.img,format=raw,if=none,id=vdisk
+            -device virtio-blk-pci,drive=vdisk
+        )
+    else
+        echo "Adding test disk: disk.img as IDE primary master"
+        # Use ISA IDE controller at standard ports 0x1F0/0x170
+        QEMU_ARGS+=(-drive file=disk.img,format=raw,if=ide,index=2,media=disk)
+    fi
+fi
+
+# Host shared folder over virtio-9p, mounted read-only as H:
+if [ -n "$SHARE" ]; then
+    echo "Sharing $SHARE over virtio-9p"
+    QEMU_ARGS+=(
+        -fsdev local,id=share,path="$SHARE",security_model=none,readonly=on
+        -device virtio-9p-pci,fsdev=share,mount_tag=workspace
+    )
+fi
+
+# Memory balloon (resize from the QEMU monitor with 'balloon <MB>')
+if [ -n "$BALLOON" ]; then
+    echo "Adding virtio-balloon device"
+    QEMU_ARGS+=(-device virtio-balloon-pci,deflate-on-oom=on)
+fi
+
+# Network adapter for the net tests
+if [ -n "$NET" ]; then
+    echo "Adding virtio-net adapter (user networking)"
+    QEMU_ARGS+=(-nic user,model=virtio-net-pci)
+fi
+
+# AHCI controller for the ahci tests
+if [ -n "$AHCI" ]; then
+    echo "Adding AHCI controller"
+    QEMU_ARGS+=(-device ahci,id=ahci)
+fi
+
+# ktest results as JSON lines on COM2, verdict through isa-debug-exit
+KTEST_RESULTS="${KTEST_RESULTS:-ktest-results.jsonl}"
+if [ -n "$KTEST" ]; then
+    echo "Running ktest suites: $KTEST (results in $KTEST_RESULTS)"
+    QEMU_ARGS+=(
+        -serial file:"$KTEST_RESULTS"
+        -device isa-debug-exit,iobase=0xf4,iosize=0x04
+        -display none
+    )
 fi
 
 # Handle different OVMF configurations
@@ -80,4 +141,17 @@ else
 fi
 
 # Run QEMU
+if [ -z "$KTEST" ]; then
+    qemu-system-x86_64 "${QEMU_ARGS[@]}" "$@"
+    exit
+fi
+
+# The kernel writes 0 (pass) or 1 (fail), so QEMU exits with 1 or 3
+set +e
 qemu-system-x86_64 "${QEMU_ARGS[@]}" "$@"
+STATUS=$?
+case $STATUS in
+    1) echo "ktest: PASS"; exit 0 ;;
+    3) echo "ktest: FAIL"; exit 1 ;;
+    *) echo "ktest: no verdict (QEMU exit status $STATUS)"; exit 2 ;;
+esac
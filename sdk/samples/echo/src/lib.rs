@@ -0,0 +1,196 @@
+//! Echo Sample Driver
+//!
+//! Minimal WDM-lite driver built against the `wdm` crate. It creates
+//! `\Device\Echo`; data written to the device is kept in the device
+//! extension and handed back by the next read.
+//!
+//! # Requests
+//!
+//! - `IRP_MJ_CREATE` / `IRP_MJ_CLOSE`: Always succeed
+//! - `IRP_MJ_WRITE`: Replace the stored data (truncated to `ECHO_CAPACITY`)
+//! - `IRP_MJ_READ`: Return the stored data
+//! - `IRP_MJ_DEVICE_CONTROL`: `IOCTL_ECHO_RESET`, `IOCTL_ECHO_QUERY_LENGTH`
+
+#![no_std]
+
+use core::ptr;
+use wdm::*;
+
+/// Bytes the device can hold
+pub const ECHO_CAPACITY: usize = 4096;
+
+/// Discard the stored data
+pub const IOCTL_ECHO_RESET: u32 = ctl_code(0x8000, 0x800);
+
+/// Return the stored length as a u32
+pub const IOCTL_ECHO_QUERY_LENGTH: u32 = ctl_code(0x8000, 0x801);
+
+/// CTL_CODE(device_type, function, METHOD_BUFFERED, FILE_ANY_ACCESS)
+const fn ctl_code(device_type: u32, function: u32) -> u32 {
+    (device_type << 16) | (function << 2)
+}
+
+/// Widen an ASCII literal to UTF-16 at compile time
+const fn utf16<const N: usize>(s: &[u8; N]) -> [u16; N] {
+    let mut out = [0u16; N];
+    let mut i = 0;
+    while i < N {
+        out[i] = s[i] as u16;
+        i += 1;
+    }
+    out
+}
+
+wdm::declare_abi_version!();
+
+static DEVICE_NAME_BUFFER: [u16; 12] = utf16(b"\\Device\\Echo");
+
+/// Per-device state
+#[repr(C)]
+struct EchoExtension {
+    lock: KSpinLock,
+    length: usize,
+    data: [u8; ECHO_CAPACITY],
+}
+
+/// Driver entry point
+///
+/// # Safety
+/// Called once by the I/O manager with a valid driver object.
+#[no_mangle]
+pub unsafe extern "C" fn DriverEntry(
+    driver: *mut DriverObject,
+    _registry_path: *const UnicodeString,
+    abi: *const DriverAbiInfo,
+) -> NtStatus {
+    if abi.is_null() || !(*abi).supports(WDM_ABI_MAJOR, WDM_ABI_MINOR) {
+        return STATUS_REVISION_MISMATCH;
+    }
+
+    let name = UnicodeString::from_static(&DEVICE_NAME_BUFFER);
+    let mut device: *mut DeviceObject = ptr::null_mut();
+
+    let status = ffi::IoCreateDevice(
+        driver,
+        core::mem::size_of::<EchoExtension>() as u32,
+        &name,
+        device_type::FILE_DEVICE_UNKNOWN,
+        0,
+        false,
+        &mut device,
+    );
+    if !nt_success(status) {
+        return status;
+    }
+
+    let ext = (*device).extension::<EchoExtension>();
+    ptr::write(ext, EchoExtension {
+        lock: KSpinLock::new(),
+        length: 0,
+        data: [0; ECHO_CAPACITY],
+    });
+    (*device).flags.fetch_or(device_flags::DO_BUFFERED_IO, core::sync::atomic::Ordering::Relaxed);
+
+    (*driver).major_function[IRP_MJ_CREATE] = Some(echo_create_close);
+    (*driver).major_function[IRP_MJ_CLOSE] = Some(echo_create_close);
+    (*driver).major_function[IRP_MJ_READ] = Some(echo_read);
+    (*driver).major_function[IRP_MJ_WRITE] = Some(echo_write);
+    (*driver).major_function[IRP_MJ_DEVICE_CONTROL] = Some(echo_device_control);
+    (*driver).driver_unload = Some(echo_unload);
+
+    ffi::DbgPrint(c"echo: loaded\n".as_ptr().cast());
+    STATUS_SUCCESS
+}
+
+extern "C" fn echo_unload(driver: *mut DriverObject) {
+    unsafe {
+        let device = (*driver).device_object;
+        if !device.is_null() {
+            ffi::IoDeleteDevice(device);
+        }
+        ffi::DbgPrint(c"echo: unloaded\n".as_ptr().cast());
+    }
+}
+
+extern "C" fn echo_create_close(_device: *mut DeviceObject, irp: *mut Irp) -> NtStatus {
+    unsafe { (*irp).complete(STATUS_SUCCESS, 0) }
+}
+
+extern "C" fn echo_write(device: *mut DeviceObject, irp: *mut Irp) -> NtStatus {
+    unsafe {
+        let irp = &mut *irp;
+        let length = match irp.current_stack_location() {
+            Some(stack) => stack.parameters.write.length as usize,
+            None => return irp.complete(STATUS_INVALID_PARAMETER, 0),
+        };
+        if length > 0 && irp.system_buffer.is_null() {
+            return irp.complete(STATUS_INVALID_PARAMETER, 0);
+        }
+
+        let ext = &mut *(*device).extension::<EchoExtension>();
+        let copied = length.min(ECHO_CAPACITY);
+        {
+            let _guard = ext.lock.acquire();
+            ptr::copy_nonoverlapping(irp.system_buffer, ext.data.as_mut_ptr(), copied);
+            ext.length = copied;
+        }
+
+        irp.complete(STATUS_SUCCESS, copied)
+    }
+}
+
+extern "C" fn echo_read(device: *mut DeviceObject, irp: *mut Irp) -> NtStatus {
+    unsafe {
+        let irp = &mut *irp;
+        let length = match irp.current_stack_location() {
+            Some(stack) => stack.parameters.read.length as usize,
+            None => return irp.complete(STATUS_INVALID_PARAMETER, 0),
+        };
+        if length > 0 && irp.system_buffer.is_null() {
+            return irp.complete(STATUS_INVALID_PARAMETER, 0);
+        }
+
+        let ext = &mut *(*device).extension::<EchoExtension>();
+        let copied = {
+            let _guard = ext.lock.acquire();
+            let copied = length.min(ext.length);
+            ptr::copy_nonoverlapping(ext.data.as_ptr(), irp.system_buffer, copied);
+            copied
+        };
+
+        irp.complete(STATUS_SUCCESS, copied)
+    }
+}
+
+extern "C" fn echo_device_control(device: *mut DeviceObject, irp: *mut Irp) -> NtStatus {
+    unsafe {
+        let irp = &mut *irp;
+        let params = match irp.current_stack_location() {
+            Some(stack) => stack.parameters.device_io_control,
+            None => return irp.complete(STATUS_INVALID_PARAMETER, 0),
+        };
+        let ext = &mut *(*device).extension::<EchoExtension>();
+
+        match params.io_control_code {
+            IOCTL_ECHO_RESET => {
+                {
+                    let _guard = ext.lock.acquire();
+                    ext.length = 0;
+                }
+                irp.complete(STATUS_SUCCESS, 0)
+            }
+            IOCTL_ECHO_QUERY_LENGTH => {
+                if (params.output_buffer_length as usize) < core::mem::size_of::<u32>() {
+                    return irp.complete(STATUS_BUFFER_TOO_SMALL, 0);
+                }
+                let length = {
+                    let _guard = ext.lock.acquire();
+                    ext.length as u32
+                };
+                ptr::write_unaligned(irp.system_buffer as *mut u32, length);
+                irp.complete(STATUS_SUCCESS, core::mem::size_of::<u32>())
+            }
+            _ => irp.complete(STATUS_INVALID_DEVICE_REQUEST, 0),
+        }
+    }
+}
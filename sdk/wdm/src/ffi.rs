@@ -0,0 +1,72 @@
+//! Kernel Imports
+//!
+//! Raw declarations of the routines the kernel exports to drivers. Names
+//! match the kernel's `ntoskrnl.exe` export table (`ldr::resolve_kernel_export`),
+//! and signatures match the kernel-side implementations one for one.
+//!
+//! Prefer the wrappers in `pool`, `sync` and `Irp` where they exist.
+
+#![allow(non_snake_case)]
+
+use crate::irp::{Irp, IoStackLocation};
+use crate::object::{DeviceObject, DriverObject, UnicodeString};
+use crate::status::NtStatus;
+
+extern "C" {
+    // Executive pool
+    pub fn ExAllocatePool(pool_type: u32, size: usize) -> *mut u8;
+    pub fn ExAllocatePoolWithTag(pool_type: u32, size: usize, tag: u32) -> *mut u8;
+    pub fn ExFreePool(ptr: *mut u8);
+    pub fn ExFreePoolWithTag(ptr: *mut u8, tag: u32);
+
+    // Synchronization
+    pub fn KeInitializeSpinLock(lock: *mut u64);
+    pub fn KeAcquireSpinLockRaiseToDpc(lock: *mut u64) -> u8;
+    pub fn KeReleaseSpinLock(lock: *mut u64, old_irql: u8);
+    pub fn KeAcquireSpinLockAtDpcLevel(lock: *mut u64);
+    pub fn KeReleaseSpinLockFromDpcLevel(lock: *mut u64);
+    pub fn KeGetCurrentIrql() -> u8;
+    pub fn KeRaiseIrql(new_irql: u8, old_irql: *mut u8);
+    pub fn KeLowerIrql(new_irql: u8);
+
+    // Time
+    pub fn KeQuerySystemTime(time: *mut i64);
+    pub fn KeDelayExecutionThread(alertable: bool, interval: *const i64) -> NtStatus;
+    pub fn KeStallExecutionProcessor(microseconds: u32);
+
+    // I/O manager
+    pub fn IoCreateDevice(
+        driver: *mut DriverObject,
+        extension_size: u32,
+        device_name: *const UnicodeString,
+        device_type: u32,
+        characteristics: u32,
+        exclusive: bool,
+        device: *mut *mut DeviceObject,
+    ) -> NtStatus;
+    pub fn IoDeleteDevice(device: *mut DeviceObject);
+    pub fn IoGetCurrentIrpStackLocation(irp: *mut Irp) -> *mut IoStackLocation;
+    pub fn IoCompleteRequest(irp: *mut Irp, priority_boost: i8);
+    pub fn IoCallDriver(device: *mut DeviceObject, irp: *mut Irp) -> NtStatus;
+    pub fn IoRaiseInformationalHardError(
+        status: NtStatus,
+        string: *const UnicodeString,
+        thread: *mut u8,
+    ) -> u8;
+    pub fn IoCreateSymbolicLink(
+        symbolic_link_name: *const UnicodeString,
+        device_name: *const UnicodeString,
+    ) -> NtStatus;
+    pub fn IoDeleteSymbolicLink(symbolic_link_name: *const UnicodeString) -> NtStatus;
+
+    // Memory manager
+    pub fn MmGetPhysicalAddress(virtual_address: u64) -> u64;
+
+    // Runtime library
+    pub fn RtlCopyMemory(dest: *mut u8, src: *const u8, len: usize);
+    pub fn RtlZeroMemory(dest: *mut u8, len: usize);
+    pub fn RtlCompareMemory(s1: *const u8, s2: *const u8, len: usize) -> usize;
+
+    // Debug
+    pub fn DbgPrint(message: *const u8) -> i32;
+}
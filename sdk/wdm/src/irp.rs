@@ -0,0 +1,213 @@
+//! I/O Request Packets
+//!
+//! Mirrors of the kernel's `io::Irp` and `io::IoStackLocation`. Stack
+//! locations are embedded in the IRP (up to `IRP_MAX_STACK_SIZE`), and
+//! `current_location` is 1-based like the kernel's.
+
+use core::sync::atomic::AtomicU32;
+use crate::object::DeviceObject;
+use crate::status::NtStatus;
+
+/// Maximum number of stack locations per IRP
+pub const IRP_MAX_STACK_SIZE: usize = 8;
+
+/// Major function codes
+pub const IRP_MJ_CREATE: usize = 0;
+pub const IRP_MJ_CLOSE: usize = 2;
+pub const IRP_MJ_READ: usize = 3;
+pub const IRP_MJ_WRITE: usize = 4;
+pub const IRP_MJ_FLUSH_BUFFERS: usize = 9;
+pub const IRP_MJ_DEVICE_CONTROL: usize = 14;
+pub const IRP_MJ_INTERNAL_DEVICE_CONTROL: usize = 15;
+pub const IRP_MJ_SHUTDOWN: usize = 16;
+pub const IRP_MJ_CLEANUP: usize = 18;
+pub const IRP_MJ_POWER: usize = 22;
+pub const IRP_MJ_PNP: usize = 27;
+
+/// Priority boost for IoCompleteRequest when no thread boost is wanted
+pub const IO_NO_INCREMENT: i8 = 0;
+
+/// I/O completion routine
+pub type IoCompletionRoutine = extern "C" fn(
+    device: *mut DeviceObject,
+    irp: *mut Irp,
+    context: *mut u8,
+) -> NtStatus;
+
+/// Cancel routine
+pub type CancelRoutine = extern "C" fn(
+    device: *mut DeviceObject,
+    irp: *mut Irp,
+);
+
+/// I/O Status Block (IO_STATUS_BLOCK)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoStatusBlock {
+    /// Status code
+    pub status: NtStatus,
+    /// Information (bytes transferred, etc.)
+    pub information: usize,
+}
+
+/// Create operation parameters
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CreateParameters {
+    pub security_context: *mut u8,
+    pub options: u32,
+    pub file_attributes: u16,
+    pub share_access: u16,
+    pub ea_length: u32,
+}
+
+/// Read/Write operation parameters
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ReadWriteParameters {
+    /// Length of transfer
+    pub length: u32,
+    /// Key for byte-range locks
+    pub key: u32,
+    /// Byte offset
+    pub byte_offset: u64,
+}
+
+/// Device I/O control parameters
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DeviceIoControlParameters {
+    pub output_buffer_length: u32,
+    pub input_buffer_length: u32,
+    pub io_control_code: u32,
+    /// Type3 input buffer (for METHOD_NEITHER)
+    pub type3_input_buffer: *mut u8,
+}
+
+/// Parameters for different IRP major functions
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union IoStackParameters {
+    pub create: CreateParameters,
+    pub read: ReadWriteParameters,
+    pub write: ReadWriteParameters,
+    pub device_io_control: DeviceIoControlParameters,
+    /// Raw parameter bytes (query/set information and others)
+    pub others: [u8; 32],
+}
+
+/// I/O Stack Location (IO_STACK_LOCATION)
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IoStackLocation {
+    /// Major function code (`IRP_MJ_*`)
+    pub major_function: u8,
+    /// Minor function code
+    pub minor_function: u8,
+    /// Flags
+    pub flags: u8,
+    /// Control flags
+    pub control: u8,
+    /// Parameters (depends on major function)
+    pub parameters: IoStackParameters,
+    /// Device object this stack location is for
+    pub device_object: *mut DeviceObject,
+    /// File object (opaque)
+    pub file_object: *mut u8,
+    /// Completion routine
+    pub completion_routine: Option<IoCompletionRoutine>,
+    /// Context for completion routine
+    pub completion_context: *mut u8,
+}
+
+/// IRP tail (completion tracking)
+#[repr(C)]
+pub struct IrpTail {
+    /// File object (opaque)
+    pub file_object: *mut u8,
+    /// Completion key for I/O completion port
+    pub completion_key: *mut u8,
+    /// Scratch space owned by the current driver
+    pub driver_context: [*mut u8; 4],
+}
+
+/// I/O Request Packet (IRP)
+#[repr(C)]
+pub struct Irp {
+    /// Type identifier (IO_TYPE_IRP)
+    pub type_id: u16,
+    /// Size of structure
+    pub size: u16,
+    /// IRP flags
+    pub flags: AtomicU32,
+    /// List entry for driver queues (Flink, Blink)
+    pub list_entry: [*mut u8; 2],
+    /// I/O status block
+    pub io_status: IoStatusBlock,
+    /// Requestor mode (0 = kernel, 1 = user)
+    pub requestor_mode: u8,
+    /// Pending returned from driver
+    pub pending_returned: bool,
+    /// Current stack location index (1-based)
+    pub current_location: i8,
+    /// Total stack locations
+    pub stack_count: i8,
+    /// Cancel flag
+    pub cancel: bool,
+    /// Cancel IRQL
+    pub cancel_irql: u8,
+    /// APC environment
+    pub apc_environment: u8,
+    /// Allocation flags
+    pub allocation_flags: u8,
+    /// User I/O status block pointer
+    pub user_io_status_block: *mut IoStatusBlock,
+    /// User event to signal on completion (opaque)
+    pub user_event: *mut u8,
+    overlay_async: [*mut u8; 2],
+    /// Cancel routine
+    pub cancel_routine: Option<CancelRoutine>,
+    /// User buffer
+    pub user_buffer: *mut u8,
+    /// System buffer (for buffered I/O)
+    pub system_buffer: *mut u8,
+    /// MDL for direct I/O
+    pub mdl_address: *mut u8,
+    /// Thread that initiated the IRP (opaque)
+    pub thread: *mut u8,
+    /// Tail overlay
+    pub tail: IrpTail,
+    /// Stack locations
+    pub stack: [IoStackLocation; IRP_MAX_STACK_SIZE],
+}
+
+impl Irp {
+    /// Get the current stack location (IoGetCurrentIrpStackLocation)
+    pub fn current_stack_location(&mut self) -> Option<&mut IoStackLocation> {
+        let idx = self.current_location as usize;
+        if idx > 0 && idx <= self.stack_count as usize && idx <= IRP_MAX_STACK_SIZE {
+            Some(&mut self.stack[idx - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Set the final status and information for this IRP
+    pub fn set_status(&mut self, status: NtStatus, information: usize) {
+        self.io_status.status = status;
+        self.io_status.information = information;
+    }
+
+    /// Record a status and hand the IRP back to the I/O manager
+    ///
+    /// Returns `status` so dispatch routines can end with
+    /// `return (*irp).complete(STATUS_SUCCESS, n);`.
+    ///
+    /// # Safety
+    /// The IRP must not be touched again after this call.
+    pub unsafe fn complete(&mut self, status: NtStatus, information: usize) -> NtStatus {
+        self.set_status(status, information);
+        crate::ffi::IoCompleteRequest(self, crate::IO_NO_INCREMENT);
+        status
+    }
+}
@@ -0,0 +1,75 @@
+//! WDM-lite: Driver Support Library
+//!
+//! The driver-facing subset of the Nostalgia OS kernel, packaged so drivers
+//! can be built outside the kernel tree. Everything here mirrors a kernel
+//! definition exactly; the kernel checks the layouts at compile time
+//! (see `kernel/src/io/abi.rs`) so the two cannot drift apart silently.
+//!
+//! Drivers declare the ABI version they were built against with
+//! `declare_abi_version!`; the loader refuses images built for an
+//! incompatible version (see `version`).
+//!
+//! # Contents
+//!
+//! - **Objects**: `DriverObject`, `DeviceObject`, `Irp`, `IoStackLocation`
+//! - **Entry points**: `DriverInitialize` (DriverEntry), dispatch, unload
+//! - **Imports**: Pool, spinlock, IRQL and I/O manager routines resolved
+//!   from the kernel's `ntoskrnl.exe` export table at load time
+//! - **Status codes**: The NTSTATUS values drivers commonly return
+//!
+//! # Writing a Driver
+//!
+//! ```ignore
+//! #![no_std]
+//! use wdm::*;
+//!
+//! wdm::declare_abi_version!();
+//!
+//! #[no_mangle]
+//! pub extern "C" fn DriverEntry(
+//!     driver: *mut DriverObject,
+//!     _path: *const UnicodeString,
+//!     _abi: *const DriverAbiInfo,
+//! ) -> NtStatus {
+//!     unsafe {
+//!         (*driver).major_function[IRP_MJ_CREATE] = Some(dispatch_create);
+//!         (*driver).driver_unload = Some(unload);
+//!     }
+//!     STATUS_SUCCESS
+//! }
+//! ```
+//!
+//! All routines use the C calling convention of the kernel target
+//! (x86_64-unknown-none); drivers must be built for the same target.
+
+#![no_std]
+
+pub mod status;
+pub mod object;
+pub mod irp;
+pub mod ffi;
+pub mod pool;
+pub mod sync;
+pub mod version;
+
+pub use status::*;
+pub use object::{
+    DriverObject, DriverExtension, DeviceObject, UnicodeString,
+    DriverInitialize, DriverDispatch, DriverUnload, DriverAddDevice, DriverStartIo,
+    IRP_MJ_MAXIMUM_FUNCTION, DRIVER_NAME_LENGTH, DEVICE_NAME_LENGTH,
+    OBJECT_HEADER_SIZE, DEVICE_QUEUE_SIZE, KEVENT_SIZE,
+    device_type, device_flags,
+};
+pub use irp::{
+    Irp, IoStackLocation, IoStackParameters, IoStatusBlock, IrpTail,
+    ReadWriteParameters, DeviceIoControlParameters, CreateParameters,
+    IoCompletionRoutine, CancelRoutine, IRP_MAX_STACK_SIZE,
+    IRP_MJ_CREATE, IRP_MJ_CLOSE, IRP_MJ_READ, IRP_MJ_WRITE,
+    IRP_MJ_FLUSH_BUFFERS, IRP_MJ_DEVICE_CONTROL, IRP_MJ_INTERNAL_DEVICE_CONTROL,
+    IRP_MJ_SHUTDOWN, IRP_MJ_CLEANUP, IRP_MJ_POWER, IRP_MJ_PNP,
+    IO_NO_INCREMENT,
+};
+pub use pool::{PoolType, PoolBuffer};
+pub use sync::{KSpinLock, Irql, PASSIVE_LEVEL, APC_LEVEL, DISPATCH_LEVEL};
+pub use version::{DriverAbiInfo, WDM_ABI_MAJOR, WDM_ABI_MINOR, WDM_ABI_PACKED};
+
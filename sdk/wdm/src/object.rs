@@ -0,0 +1,231 @@
+//! Driver and Device Objects
+//!
+//! Mirrors of the kernel's `io::DriverObject` and `io::DeviceObject`.
+//! Fields a driver has no business touching (object header, device queue,
+//! device lock) are kept as opaque storage of the right size so every
+//! public field sits at the same offset as in the kernel.
+
+use core::sync::atomic::{AtomicI32, AtomicU32};
+use crate::irp::Irp;
+use crate::status::NtStatus;
+use crate::version::DriverAbiInfo;
+
+/// Size of the kernel's object header preceding every object body
+pub const OBJECT_HEADER_SIZE: usize = 56;
+
+/// Size of the kernel's per-device IRP queue
+pub const DEVICE_QUEUE_SIZE: usize = 24;
+
+/// Size of a kernel event (dispatcher header + type)
+pub const KEVENT_SIZE: usize = 32;
+
+/// Maximum driver name length
+pub const DRIVER_NAME_LENGTH: usize = 64;
+
+/// Maximum device name length
+pub const DEVICE_NAME_LENGTH: usize = 64;
+
+/// Number of dispatch routines (one per major function)
+pub const IRP_MJ_MAXIMUM_FUNCTION: usize = 28;
+
+/// Counted UTF-16 string (UNICODE_STRING)
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UnicodeString {
+    /// Current length in bytes (not characters)
+    pub length: u16,
+    /// Maximum length in bytes (buffer capacity)
+    pub maximum_length: u16,
+    /// Pointer to UTF-16 buffer
+    pub buffer: *mut u16,
+}
+
+impl UnicodeString {
+    /// Wrap a static UTF-16 buffer (without terminator)
+    pub const fn from_static(buffer: &'static [u16]) -> Self {
+        Self {
+            length: (buffer.len() * 2) as u16,
+            maximum_length: (buffer.len() * 2) as u16,
+            buffer: buffer.as_ptr() as *mut u16,
+        }
+    }
+}
+
+/// Driver initialization routine (DriverEntry)
+///
+/// `abi` describes the running kernel; see `version`.
+pub type DriverInitialize = extern "C" fn(
+    driver: *mut DriverObject,
+    registry_path: *const UnicodeString,
+    abi: *const DriverAbiInfo,
+) -> NtStatus;
+
+/// Driver dispatch routine
+pub type DriverDispatch = extern "C" fn(
+    device: *mut DeviceObject,
+    irp: *mut Irp,
+) -> NtStatus;
+
+/// Driver unload routine
+pub type DriverUnload = extern "C" fn(driver: *mut DriverObject);
+
+/// Driver add device routine (for PnP)
+pub type DriverAddDevice = extern "C" fn(
+    driver: *mut DriverObject,
+    physical_device: *mut DeviceObject,
+) -> NtStatus;
+
+/// Driver start I/O routine
+pub type DriverStartIo = extern "C" fn(
+    device: *mut DeviceObject,
+    irp: *mut Irp,
+);
+
+/// Driver Object (DRIVER_OBJECT)
+#[repr(C)]
+pub struct DriverObject {
+    header: [u64; OBJECT_HEADER_SIZE / 8],
+
+    /// Type identifier (IO_TYPE_DRIVER)
+    pub type_id: u16,
+    /// Size of structure
+    pub size: u16,
+    /// First device object in driver's device list
+    pub device_object: *mut DeviceObject,
+    /// Driver flags
+    pub flags: u32,
+    /// Base of driver image
+    pub driver_start: *mut u8,
+    /// Driver image size
+    pub driver_size: u32,
+    /// Driver section
+    pub driver_section: *mut u8,
+    /// Driver extension
+    pub driver_extension: *mut DriverExtension,
+    /// Driver name
+    pub driver_name: [u8; DRIVER_NAME_LENGTH],
+    /// Driver name length
+    pub driver_name_length: u8,
+    /// Hardware database (registry path for hardware info)
+    pub hardware_database: *const u8,
+    /// Fast I/O dispatch table (opaque to WDM-lite drivers)
+    pub fast_io_dispatch: *mut u8,
+    /// Driver initialization routine
+    pub driver_init: Option<DriverInitialize>,
+    /// Driver start I/O routine
+    pub driver_start_io: Option<DriverStartIo>,
+    /// Driver unload routine
+    pub driver_unload: Option<DriverUnload>,
+    /// Major function dispatch table
+    pub major_function: [Option<DriverDispatch>; IRP_MJ_MAXIMUM_FUNCTION],
+}
+
+impl DriverObject {
+    /// Get driver name
+    pub fn name(&self) -> &[u8] {
+        &self.driver_name[..(self.driver_name_length as usize).min(DRIVER_NAME_LENGTH)]
+    }
+}
+
+/// Driver Extension (DRIVER_EXTENSION)
+#[repr(C)]
+pub struct DriverExtension {
+    /// Back pointer to driver object
+    pub driver_object: *mut DriverObject,
+    /// Add device routine (for PnP drivers)
+    pub add_device: Option<DriverAddDevice>,
+    /// Count of unclaimed devices
+    pub count: u32,
+    /// Service key name (registry)
+    pub service_key_name: [u8; 64],
+}
+
+/// Device Object (DEVICE_OBJECT)
+#[repr(C)]
+pub struct DeviceObject {
+    header: [u64; OBJECT_HEADER_SIZE / 8],
+
+    /// Type identifier (IO_TYPE_DEVICE)
+    pub type_id: u16,
+    /// Size of structure
+    pub size: u16,
+    /// Reference count
+    pub reference_count: AtomicI32,
+    /// Owning driver
+    pub driver_object: *mut DriverObject,
+    /// Next device in driver's device list
+    pub next_device: *mut DeviceObject,
+    /// Device attached above this one
+    pub attached_device: *mut DeviceObject,
+    /// Current IRP being processed
+    pub current_irp: *mut Irp,
+    /// Device timer
+    pub timer: *mut u8,
+    /// Device flags (`device_flags`)
+    pub flags: AtomicU32,
+    /// Device characteristics
+    pub characteristics: u32,
+    /// Device type (`device_type`)
+    pub device_type: u32,
+    /// Number of devices in the stack
+    pub stack_size: u8,
+    /// Alignment requirement
+    pub alignment_requirement: u32,
+    device_queue: [u64; DEVICE_QUEUE_SIZE / 8],
+    /// DPC for device
+    pub dpc: *mut u8,
+    /// Active thread count
+    pub active_thread_count: AtomicU32,
+    /// Security descriptor
+    pub security_descriptor: *mut u8,
+    device_lock: [u64; KEVENT_SIZE / 8],
+    /// Sector size (for disk devices)
+    pub sector_size: u16,
+    /// Spare
+    pub spare1: u16,
+    /// Device extension (driver-specific data)
+    pub device_extension: *mut u8,
+    /// Device extension size
+    pub device_extension_size: u32,
+    /// Device name
+    pub device_name: [u8; DEVICE_NAME_LENGTH],
+    /// Device name length
+    pub device_name_length: u8,
+}
+
+impl DeviceObject {
+    /// Get the device extension as a typed pointer
+    ///
+    /// # Safety
+    /// The extension must have been created with room for a `T`.
+    pub unsafe fn extension<T>(&self) -> *mut T {
+        self.device_extension as *mut T
+    }
+
+    /// Get device name
+    pub fn name(&self) -> &[u8] {
+        &self.device_name[..(self.device_name_length as usize).min(DEVICE_NAME_LENGTH)]
+    }
+}
+
+/// Device type codes
+pub mod device_type {
+    pub const FILE_DEVICE_DISK: u32 = 0x00000007;
+    pub const FILE_DEVICE_KEYBOARD: u32 = 0x0000000B;
+    pub const FILE_DEVICE_MOUSE: u32 = 0x0000000F;
+    pub const FILE_DEVICE_NETWORK: u32 = 0x00000012;
+    pub const FILE_DEVICE_NULL: u32 = 0x00000015;
+    pub const FILE_DEVICE_SERIAL_PORT: u32 = 0x0000001B;
+    pub const FILE_DEVICE_UNKNOWN: u32 = 0x00000022;
+    pub const FILE_DEVICE_VIRTUAL_DISK: u32 = 0x00000024;
+}
+
+/// Device flags
+pub mod device_flags {
+    pub const DO_BUFFERED_IO: u32 = 0x00000004;
+    pub const DO_EXCLUSIVE: u32 = 0x00000008;
+    pub const DO_DIRECT_IO: u32 = 0x00000010;
+    pub const DO_DEVICE_HAS_NAME: u32 = 0x00000040;
+    pub const DO_DEVICE_INITIALIZING: u32 = 0x00000080;
+}
+
@@ -0,0 +1,80 @@
+//! Pool Allocation
+//!
+//! Thin wrappers over ExAllocatePoolWithTag/ExFreePoolWithTag.
+
+use crate::ffi;
+
+/// Pool type
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolType {
+    /// Always resident; usable at any IRQL
+    NonPagedPool = 0,
+    /// May be paged out; PASSIVE_LEVEL/APC_LEVEL only
+    PagedPool = 1,
+}
+
+/// Allocate tagged pool memory, returning null on failure
+///
+/// # Safety
+/// Paged pool must not be requested at DISPATCH_LEVEL or above.
+pub unsafe fn allocate(pool_type: PoolType, size: usize, tag: u32) -> *mut u8 {
+    ffi::ExAllocatePoolWithTag(pool_type as u32, size, tag)
+}
+
+/// Free tagged pool memory
+///
+/// # Safety
+/// `ptr` must come from `allocate` with the same tag.
+pub unsafe fn free(ptr: *mut u8, tag: u32) {
+    if !ptr.is_null() {
+        ffi::ExFreePoolWithTag(ptr, tag);
+    }
+}
+
+/// Owned pool allocation, freed on drop
+pub struct PoolBuffer {
+    ptr: *mut u8,
+    len: usize,
+    tag: u32,
+}
+
+impl PoolBuffer {
+    /// Allocate a zeroed buffer
+    pub fn new(pool_type: PoolType, len: usize, tag: u32) -> Option<Self> {
+        unsafe {
+            let ptr = allocate(pool_type, len, tag);
+            if ptr.is_null() {
+                return None;
+            }
+            ffi::RtlZeroMemory(ptr, len);
+            Some(Self { ptr, len, tag })
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for PoolBuffer {
+    fn drop(&mut self) {
+        unsafe { free(self.ptr, self.tag) };
+    }
+}
@@ -0,0 +1,28 @@
+//! NTSTATUS Codes
+//!
+//! Status values returned by dispatch routines and kernel imports.
+//! Negative values are errors; zero and positive values are success or
+//! informational.
+
+/// NTSTATUS
+pub type NtStatus = i32;
+
+pub const STATUS_SUCCESS: NtStatus = 0x0000_0000;
+pub const STATUS_PENDING: NtStatus = 0x0000_0103;
+pub const STATUS_BUFFER_OVERFLOW: NtStatus = 0x8000_0005_u32 as i32;
+pub const STATUS_UNSUCCESSFUL: NtStatus = 0xC000_0001_u32 as i32;
+pub const STATUS_NOT_IMPLEMENTED: NtStatus = 0xC000_0002_u32 as i32;
+pub const STATUS_INVALID_PARAMETER: NtStatus = 0xC000_000D_u32 as i32;
+pub const STATUS_NO_SUCH_DEVICE: NtStatus = 0xC000_000E_u32 as i32;
+pub const STATUS_INVALID_DEVICE_REQUEST: NtStatus = 0xC000_0010_u32 as i32;
+pub const STATUS_INSUFFICIENT_RESOURCES: NtStatus = 0xC000_009A_u32 as i32;
+pub const STATUS_BUFFER_TOO_SMALL: NtStatus = 0xC000_0023_u32 as i32;
+pub const STATUS_REVISION_MISMATCH: NtStatus = 0xC000_0059_u32 as i32;
+pub const STATUS_OBJECT_NAME_COLLISION: NtStatus = 0xC000_0035_u32 as i32;
+pub const STATUS_CANCELLED: NtStatus = 0xC000_0120_u32 as i32;
+
+/// Check if a status indicates success (NT_SUCCESS)
+#[inline]
+pub const fn nt_success(status: NtStatus) -> bool {
+    status >= 0
+}
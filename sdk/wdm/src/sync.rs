@@ -0,0 +1,61 @@
+//! Synchronization
+//!
+//! IRQL queries and the executive spinlock (KSPIN_LOCK).
+
+use core::cell::UnsafeCell;
+use crate::ffi;
+
+/// Interrupt request level
+pub type Irql = u8;
+
+pub const PASSIVE_LEVEL: Irql = 0;
+pub const APC_LEVEL: Irql = 1;
+pub const DISPATCH_LEVEL: Irql = 2;
+
+/// Get the current processor's IRQL
+pub fn current_irql() -> Irql {
+    unsafe { ffi::KeGetCurrentIrql() }
+}
+
+/// Executive spinlock
+///
+/// Acquiring raises to DISPATCH_LEVEL; the guard restores the previous
+/// IRQL when dropped.
+#[repr(transparent)]
+pub struct KSpinLock {
+    lock: UnsafeCell<u64>,
+}
+
+// Safety: all access goes through the kernel's interlocked routines
+unsafe impl Sync for KSpinLock {}
+unsafe impl Send for KSpinLock {}
+
+impl KSpinLock {
+    pub const fn new() -> Self {
+        Self { lock: UnsafeCell::new(0) }
+    }
+
+    /// Acquire, raising IRQL to DISPATCH_LEVEL
+    pub fn acquire(&self) -> KSpinLockGuard<'_> {
+        let old_irql = unsafe { ffi::KeAcquireSpinLockRaiseToDpc(self.lock.get()) };
+        KSpinLockGuard { lock: self, old_irql }
+    }
+}
+
+impl Default for KSpinLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Held spinlock; releases and lowers IRQL on drop
+pub struct KSpinLockGuard<'a> {
+    lock: &'a KSpinLock,
+    old_irql: Irql,
+}
+
+impl Drop for KSpinLockGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { ffi::KeReleaseSpinLock(self.lock.lock.get(), self.old_irql) };
+    }
+}
@@ -0,0 +1,64 @@
+//! ABI Versioning
+//!
+//! The kernel's driver ABI is versioned `major.minor`. A major bump means
+//! a mirrored structure or export signature changed; a minor bump means
+//! exports were added. The loader accepts a driver when the majors match
+//! and the driver's minor is not newer than the kernel's.
+//!
+//! Version history:
+//!
+//! - **1.0**: Initial export surface
+//! - **1.1**: MmAllocateContiguousMemorySpecifyCache,
+//!   MmFreeContiguousMemorySpecifyCache, KeAcquireSpinLockRaiseToDpc,
+//!   KeReleaseSpinLock; DriverEntry receives `DriverAbiInfo`
+//! - **1.2**: KeStallExecutionProcessor
+//! - **1.3**: IoRaiseInformationalHardError
+//! - **1.4**: IoCreateSymbolicLink, IoDeleteSymbolicLink
+//! - **1.5**: Kernel-mode C runtime: mem*, str*, wcs*, snprintf family,
+//!   _Interlocked* and bit intrinsics; DbgPrint formats its arguments
+
+/// ABI major version this crate targets
+pub const WDM_ABI_MAJOR: u16 = 1;
+
+/// ABI minor version this crate targets
+pub const WDM_ABI_MINOR: u16 = 5;
+
+/// Version in the `DriverAbiVersion` export format (`major << 16 | minor`)
+pub const WDM_ABI_PACKED: u32 = ((WDM_ABI_MAJOR as u32) << 16) | WDM_ABI_MINOR as u32;
+
+/// Version handshake passed to DriverEntry
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DriverAbiInfo {
+    /// Size of this structure
+    pub size: u32,
+    /// Kernel ABI major version
+    pub major: u16,
+    /// Kernel ABI minor version
+    pub minor: u16,
+    /// Version the driver declared (packed)
+    pub driver_version: u32,
+    /// Number of kernel exports available
+    pub export_count: u32,
+}
+
+impl DriverAbiInfo {
+    /// Check if the running kernel provides at least `major.minor`
+    pub fn supports(&self, major: u16, minor: u16) -> bool {
+        self.major == major && self.minor >= minor
+    }
+}
+
+/// Export the ABI version this driver was built against
+///
+/// Expands to the `DriverAbiVersion` data export the loader checks before
+/// binding imports. Use once at the crate root of a driver.
+#[macro_export]
+macro_rules! declare_abi_version {
+    () => {
+        #[no_mangle]
+        #[used]
+        #[allow(non_upper_case_globals)]
+        pub static DriverAbiVersion: u32 = $crate::WDM_ABI_PACKED;
+    };
+}